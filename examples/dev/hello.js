@@ -0,0 +1,17 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+// Example function for the local dev emulator:
+// r3e-faas dev --function 1=./examples/dev/hello.js --events ./examples/dev/events.json
+
+import { oracle } from "r3e";
+
+export default async function (event) {
+  console.log("event:", JSON.stringify(event));
+
+  // Answered by the simulated oracle
+  const price = await oracle.getNeoPrice("USD", [], "dev");
+  console.log("NEO price:", JSON.stringify(price));
+
+  return { ok: true };
+}
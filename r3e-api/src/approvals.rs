@@ -0,0 +1,77 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+//! Executors of the admin operations the API runs after offline approval.
+
+use std::sync::Arc;
+
+use axum::async_trait;
+use r3e_built_in_services::pricing::PricingServiceTrait;
+use r3e_built_in_services::quota::{PlanTier, QuotaServiceTrait};
+use r3e_secrets::approval::OperationExecutor;
+use serde::Deserialize;
+use serde_json::Value;
+use uuid::Uuid;
+
+/// Kind of the operation moving a user to another plan tier
+pub const SET_PLAN_OPERATION: &str = "quota.set_plan";
+
+/// Parameters of a plan change
+#[derive(Debug, Deserialize)]
+struct SetPlanParams {
+    /// User ID
+    user_id: Uuid,
+
+    /// Plan tier
+    tier: PlanTier,
+}
+
+/// Moves a user to another plan tier, overriding their quotas and the plan they are billed on
+pub struct SetPlanExecutor {
+    /// Quota service
+    quota_service: Arc<dyn QuotaServiceTrait>,
+
+    /// Pricing service
+    pricing_service: Arc<dyn PricingServiceTrait>,
+}
+
+impl SetPlanExecutor {
+    /// Create a new plan change executor
+    pub fn new(
+        quota_service: Arc<dyn QuotaServiceTrait>,
+        pricing_service: Arc<dyn PricingServiceTrait>,
+    ) -> Self {
+        Self {
+            quota_service,
+            pricing_service,
+        }
+    }
+}
+
+#[async_trait]
+impl OperationExecutor for SetPlanExecutor {
+    fn validate(&self, params: &Value) -> Result<(), String> {
+        SetPlanParams::deserialize(params)
+            .map(|_| ())
+            .map_err(|e| e.to_string())
+    }
+
+    async fn execute(&self, params: &Value) -> Result<Value, String> {
+        let params = SetPlanParams::deserialize(params).map_err(|e| e.to_string())?;
+        let tenant = params.user_id.to_string();
+
+        self.pricing_service
+            .assign_plan(&tenant, params.tier)
+            .await
+            .map_err(|e| e.to_string())?;
+        self.quota_service
+            .set_plan(&tenant, params.tier)
+            .map_err(|e| e.to_string())?;
+        let usage = self
+            .quota_service
+            .get_usage(&tenant)
+            .map_err(|e| e.to_string())?;
+
+        serde_json::to_value(usage).map_err(|e| e.to_string())
+    }
+}
@@ -0,0 +1,51 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+use axum::extract::Request;
+use axum::http::{header, HeaderMap, HeaderValue};
+use axum::middleware::Next;
+use axum::response::Response;
+use r3e_secrets::audit::AuditContext;
+use tracing::Instrument;
+use uuid::Uuid;
+
+/// Request ID header, taken from the request or generated, and echoed in the response
+pub const REQUEST_ID_HEADER: &str = "X-Request-Id";
+
+/// Audit context of a request from its headers.
+///
+/// The caller is identified later by the `Auth` extractor, so the user ID is left unset.
+pub fn context_from_headers(headers: &HeaderMap) -> AuditContext {
+    let header_value = |name: &str| {
+        headers
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string)
+    };
+
+    let request_id = header_value(REQUEST_ID_HEADER)
+        .filter(|id| !id.is_empty() && id.len() <= 128)
+        .unwrap_or_else(|| Uuid::new_v4().to_string());
+
+    AuditContext {
+        request_id: Some(request_id),
+        user_id: None,
+        source_ip: header_value("X-Forwarded-For")
+            .and_then(|v| v.split(',').next().map(|ip| ip.trim().to_string())),
+        user_agent: header_value(header::USER_AGENT.as_str()),
+    }
+}
+
+/// Middleware running the handler within the request's audit context, so audit events it
+/// records carry the request ID, source IP and user agent
+pub async fn audit_context(request: Request, next: Next) -> Response {
+    let context = context_from_headers(request.headers());
+    let request_id = context.request_id.clone().unwrap_or_default();
+    let span = tracing::info_span!("request", request_id = %request_id);
+
+    let mut response = context.scope(next.run(request)).instrument(span).await;
+    if let Ok(value) = HeaderValue::from_str(&request_id) {
+        response.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+    response
+}
@@ -12,7 +12,7 @@ use axum::{
     response::{IntoResponse, Response},
     Json,
 };
-use chrono::{DateTime, Duration, Utc};
+use chrono::{DateTime, Utc};
 use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
 use serde::{Deserialize, Serialize};
 use sqlx::PgPool;
@@ -20,7 +20,9 @@ use uuid::Uuid;
 
 use crate::config::Config;
 use crate::error::ApiError;
+use crate::models::session::Session;
 use crate::models::user::{User, UserRole};
+use crate::sessions::{DeviceInfo, RevocationReason, SessionStore};
 
 /// JWT claims
 #[derive(Debug, Serialize, Deserialize)]
@@ -39,6 +41,9 @@ pub struct Claims {
 
     /// Expiration
     pub exp: i64,
+
+    /// JWT ID, the session the token was issued for
+    pub jti: String,
 }
 
 /// Authentication service
@@ -51,18 +56,27 @@ pub struct AuthService {
 
     /// JWT expiration in seconds
     jwt_expiration: u64,
+
+    /// Login sessions
+    sessions: SessionStore,
 }
 
 impl AuthService {
     /// Create a new authentication service
     pub fn new(db: PgPool, config: &Config) -> Self {
         Self {
+            sessions: SessionStore::new(db.clone()),
             db,
             jwt_secret: config.jwt_secret.clone(),
             jwt_expiration: config.jwt_expiration,
         }
     }
 
+    /// Login sessions
+    pub fn sessions(&self) -> &SessionStore {
+        &self.sessions
+    }
+
     /// Hash a password
     pub fn hash_password(&self, password: &str) -> Result<String, ApiError> {
         let salt = SaltString::generate(&mut OsRng);
@@ -85,17 +99,15 @@ impl AuthService {
             .is_ok())
     }
 
-    /// Generate a JWT token
-    pub fn generate_token(&self, user: &User) -> Result<String, ApiError> {
-        let now = Utc::now();
-        let expiration = now + Duration::seconds(self.jwt_expiration as i64);
-
+    /// Generate a JWT token for a session, expiring with it
+    pub fn generate_token(&self, user: &User, session: &Session) -> Result<String, ApiError> {
         let claims = Claims {
             sub: user.id.to_string(),
             username: user.username.clone(),
             role: format!("{:?}", user.role).to_lowercase(),
-            iat: now.timestamp(),
-            exp: expiration.timestamp(),
+            iat: session.created_at.timestamp(),
+            exp: session.expires_at.timestamp(),
+            jti: session.id.to_string(),
         };
 
         encode(
@@ -278,6 +290,19 @@ impl AuthService {
         .await
         .map_err(|e| ApiError::Database(format!("Failed to update user: {}", e)))?;
 
+        // Tokens issued with the old password stop working
+        if password.is_some() {
+            let revoked = self
+                .sessions
+                .revoke_all(id, RevocationReason::PasswordChanged, None)
+                .await?;
+            log::info!(
+                "Revoked {} sessions of user {} after a password change",
+                revoked,
+                id
+            );
+        }
+
         Ok(user)
     }
 
@@ -296,11 +321,30 @@ impl AuthService {
         Ok(())
     }
 
-    /// Login a user
+    /// Replace the API key of a user, the previous key stops working right away
+    pub async fn create_api_key(&self, id: Uuid) -> Result<String, ApiError> {
+        let api_key = format!("r3e_{}", Uuid::new_v4().to_string().replace("-", ""));
+
+        let updated = sqlx::query("UPDATE users SET api_key = $1, updated_at = $2 WHERE id = $3")
+            .bind(&api_key)
+            .bind(Utc::now())
+            .bind(id)
+            .execute(&self.db)
+            .await
+            .map_err(|e| ApiError::Database(format!("Failed to create API key: {}", e)))?;
+        if updated.rows_affected() == 0 {
+            return Err(ApiError::NotFound(format!("User not found: {}", id)));
+        }
+
+        Ok(api_key)
+    }
+
+    /// Login a user, starting a session on the device
     pub async fn login(
         &self,
         username_or_email: &str,
         password: &str,
+        device: &DeviceInfo,
     ) -> Result<(User, String), ApiError> {
         // Get the user
         let user = self
@@ -312,11 +356,29 @@ impl AuthService {
             return Err(ApiError::Authentication("Invalid password".to_string()));
         }
 
-        // Generate a token
-        let token = self.generate_token(&user)?;
+        // Start a session and generate its token
+        let token = self.start_session(&user, device).await?;
 
         Ok((user, token))
     }
+
+    /// Start a session of an authenticated user on the device, returning its token
+    pub async fn start_session(
+        &self,
+        user: &User,
+        device: &DeviceInfo,
+    ) -> Result<String, ApiError> {
+        let session = self
+            .sessions
+            .create(
+                user.id,
+                device,
+                std::time::Duration::from_secs(self.jwt_expiration),
+            )
+            .await?;
+
+        self.generate_token(user, &session)
+    }
 }
 
 /// Authentication extractor
@@ -326,6 +388,9 @@ pub struct Auth {
 
     /// Claims
     pub claims: Claims,
+
+    /// Session of the token
+    pub session_id: Uuid,
 }
 
 #[async_trait]
@@ -373,6 +438,22 @@ where
             .verify_token(token)
             .map_err(|e| e.into_response())?;
 
+        // Deny tokens of revoked sessions
+        let session_id = Uuid::parse_str(&claims.jti).map_err(|_| {
+            ApiError::Authentication("Invalid session ID".to_string()).into_response()
+        })?;
+        let active = auth_service
+            .sessions()
+            .touch(session_id)
+            .await
+            .map_err(|e| e.into_response())?;
+        if !active {
+            return Err(
+                ApiError::Authentication("Session has expired or was revoked".to_string())
+                    .into_response(),
+            );
+        }
+
         // Get the user
         let user_id = Uuid::parse_str(&claims.sub)
             .map_err(|_| ApiError::Authentication("Invalid user ID".to_string()).into_response())?;
@@ -382,7 +463,11 @@ where
             .await
             .map_err(|e| e.into_response())?;
 
-        Ok(Self { user, claims })
+        Ok(Self {
+            user,
+            claims,
+            session_id,
+        })
     }
 }
 
@@ -0,0 +1,489 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+//! Authorization rules shared by the REST routes and the GraphQL resolvers, so both surfaces
+//! allow exactly the same actions.
+
+use r3e_built_in_services::balance::AdmissionDecision;
+use r3e_built_in_services::billing::{BillingError, BillingServiceTrait};
+use r3e_deno::sandbox::PolicyStage;
+use uuid::Uuid;
+
+use crate::auth::Auth;
+use crate::error::ApiError;
+use crate::models::domain::DomainMapping;
+use crate::models::function::{Function, FunctionStatus};
+use crate::models::service::{Service, ServiceVisibility};
+use crate::models::user::UserRole;
+use crate::service::ApiService;
+
+/// Whether the user is an admin
+pub fn is_admin(auth: &Auth) -> bool {
+    auth.user.role == UserRole::Admin
+}
+
+/// Users manage their own account, admins any account
+pub fn authorize_user(auth: &Auth, user_id: Uuid, action: &str) -> Result<(), ApiError> {
+    if !is_admin(auth) && auth.user.id != user_id {
+        return Err(ApiError::Authorization(format!(
+            "You are not authorized to {} this user",
+            action
+        )));
+    }
+    Ok(())
+}
+
+/// Only admins change roles
+pub fn authorize_role_change(auth: &Auth, role: Option<UserRole>) -> Result<(), ApiError> {
+    if !is_admin(auth) && role.is_some() {
+        return Err(ApiError::Authorization(
+            "You are not authorized to change your role".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Only the owner of a service views, updates or deletes it
+pub fn authorize_service(auth: &Auth, service: &Service, action: &str) -> Result<(), ApiError> {
+    if service.user_id != auth.user.id {
+        return Err(ApiError::Authorization(format!(
+            "You are not authorized to {} this service",
+            action
+        )));
+    }
+    Ok(())
+}
+
+/// The owner of a service, or anyone for a public service, generates a client for it
+pub fn authorize_service_client(auth: &Auth, service: &Service) -> Result<(), ApiError> {
+    if service.user_id != auth.user.id && service.visibility != ServiceVisibility::Public {
+        return Err(ApiError::Authorization(
+            "You are not authorized to generate a client for this service".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Only the owner of a service adds functions to it
+pub async fn authorize_create_function(
+    api_service: &ApiService,
+    auth: &Auth,
+    service_id: Uuid,
+) -> Result<Service, ApiError> {
+    let service = api_service.service_service.get_service(service_id).await?;
+    if service.user_id != auth.user.id {
+        return Err(ApiError::Authorization(
+            "You are not authorized to create functions for this service".to_string(),
+        ));
+    }
+    Ok(service)
+}
+
+/// Only the owner of a function views, updates or deletes it, or changes its trigger
+pub fn authorize_function(auth: &Auth, function: &Function, action: &str) -> Result<(), ApiError> {
+    if function.user_id != auth.user.id {
+        return Err(ApiError::Authorization(format!(
+            "You are not authorized to {} this function",
+            action
+        )));
+    }
+    Ok(())
+}
+
+/// Only the owner of a domain mapping views, verifies or deletes it
+pub fn authorize_domain(
+    auth: &Auth,
+    mapping: &DomainMapping,
+    action: &str,
+) -> Result<(), ApiError> {
+    if mapping.user_id != auth.user.id {
+        return Err(ApiError::Authorization(format!(
+            "You are not authorized to {} this domain mapping",
+            action
+        )));
+    }
+    Ok(())
+}
+
+/// The owner of a function and admins manage its sandbox permissions
+pub fn authorize_function_permissions(auth: &Auth, function: &Function) -> Result<(), ApiError> {
+    if function.user_id != auth.user.id && !is_admin(auth) {
+        return Err(ApiError::Authorization(
+            "You are not authorized to manage the permissions of this function".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Check that the user owns the function or the function's service is public
+pub async fn authorize_invoke(
+    api_service: &ApiService,
+    auth: &Auth,
+    function: &Function,
+) -> Result<(), ApiError> {
+    let service = api_service
+        .service_service
+        .get_service(function.service_id)
+        .await?;
+
+    if function.user_id != auth.user.id && service.visibility != ServiceVisibility::Public {
+        return Err(ApiError::Authorization(
+            "You are not authorized to invoke this function".to_string(),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Check that the function is active, that the user may invoke it, that its owner is not
+/// suspended, that no admin policy denies the invocation and that an invocation cannot exceed
+/// the caller's or platform cost limit
+pub async fn admit_invocation(
+    api_service: &ApiService,
+    auth: &Auth,
+    function: &Function,
+    requested_max_cost: Option<f64>,
+) -> Result<(), ApiError> {
+    check_active(function)?;
+
+    // Check if the user owns the function or the function's service is public
+    authorize_invoke(api_service, auth, function).await?;
+
+    admit_execution(api_service, function, auth.user.id, 1, requested_max_cost).await
+}
+
+/// Check that the function is active, that the user may invoke it, that its owner is not
+/// suspended, that no admin policy denies the invocations and that the `count` invocations of a
+/// batch cannot together exceed the caller's cost limit, nor each the platform cost limit
+pub async fn admit_batch_invocation(
+    api_service: &ApiService,
+    auth: &Auth,
+    function: &Function,
+    count: u32,
+    requested_max_cost: Option<f64>,
+) -> Result<(), ApiError> {
+    check_active(function)?;
+
+    // Check if the user owns the function or the function's service is public
+    authorize_invoke(api_service, auth, function).await?;
+
+    admit_execution(
+        api_service,
+        function,
+        auth.user.id,
+        count,
+        requested_max_cost,
+    )
+    .await
+}
+
+/// Check that the function is active, that its owner is not suspended, that no admin policy
+/// denies the invocation and that an invocation by an anonymous caller, quoted to the owner,
+/// cannot exceed the platform cost limit
+pub async fn admit_anonymous_invocation(
+    api_service: &ApiService,
+    function: &Function,
+) -> Result<(), ApiError> {
+    check_active(function)?;
+
+    admit_execution(api_service, function, function.user_id, 1, None).await
+}
+
+/// Check that the user owns the function, that it is active, that its owner is not suspended
+/// and that no admin policy denies its invocation. Replays are not billed, so the plan's rate
+/// limit and cost limits do not apply.
+pub async fn admit_replay(
+    api_service: &ApiService,
+    auth: &Auth,
+    function: &Function,
+) -> Result<(), ApiError> {
+    authorize_function(auth, function, "replay events into")?;
+    check_active(function)?;
+
+    check_suspended(api_service.billing_service.as_ref(), function.user_id).await?;
+    check_policies(api_service, function, auth.user.id).await
+}
+
+fn check_active(function: &Function) -> Result<(), ApiError> {
+    if function.status != FunctionStatus::Active {
+        return Err(ApiError::Validation("Function is not active".to_string()));
+    }
+    Ok(())
+}
+
+async fn admit_execution(
+    api_service: &ApiService,
+    function: &Function,
+    caller: Uuid,
+    count: u32,
+    requested_max_cost: Option<f64>,
+) -> Result<(), ApiError> {
+    check_suspended(api_service.billing_service.as_ref(), function.user_id).await?;
+    check_policies(api_service, function, caller).await?;
+    check_balance(api_service, function).await?;
+
+    // The owner's plan bills each invocation, so its rate limit and included invocations apply
+    // to every one of them
+    api_service
+        .pricing_service
+        .admit_invocation(&function.user_id.to_string(), count)
+        .await?;
+
+    // Reject the invocations if their worst-case cost can exceed the caller's limit, or the
+    // platform limit of each invocation
+    let platform_max_cost = api_service
+        .config
+        .max_invocation_cost
+        .map(|max_cost| max_cost * f64::from(count));
+    let max_cost = match (requested_max_cost, platform_max_cost) {
+        (Some(requested), Some(platform)) => Some(requested.min(platform)),
+        (requested, platform) => requested.or(platform),
+    };
+    if let Some(max_cost) = max_cost {
+        let quote = api_service
+            .cost_estimator
+            .guard(function, caller, count, max_cost)
+            .await?;
+        log::debug!(
+            "Invocation of {} quoted at {} GAS, at most {} GAS",
+            function.id,
+            quote.expected_cost,
+            quote.max_cost
+        );
+    }
+
+    Ok(())
+}
+
+/// Functions of tenants suspended for overdue invoices do not run until they pay. An owner
+/// whose standing cannot be checked is not admitted either.
+async fn check_suspended(billing: &dyn BillingServiceTrait, owner: Uuid) -> Result<(), ApiError> {
+    match billing.check_execution(&owner.to_string()).await {
+        Ok(()) => Ok(()),
+        Err(err @ BillingError::Suspended(_)) => Err(err.into()),
+        Err(err) => {
+            log::error!("Failed to check billing of {}: {}", owner, err);
+            Err(ApiError::Service(
+                "Failed to check the billing status of the function owner".to_string(),
+            ))
+        }
+    }
+}
+
+/// Owners whose GAS balance is below the hard limit must deposit before their functions run;
+/// below the soft limit they run, and the owner is alerted
+async fn check_balance(api_service: &ApiService, function: &Function) -> Result<(), ApiError> {
+    let owner = function.user_id.to_string();
+    if let AdmissionDecision::LowBalance { balance } =
+        api_service.balance_admission.admit(&owner).await?
+    {
+        log::warn!(
+            "Invoking {} of {} with a low GAS balance of {}",
+            function.id,
+            owner,
+            balance
+        );
+    }
+    Ok(())
+}
+
+/// Admin policies of the invoke stage, decided on the function, its service and the caller
+async fn check_policies(
+    api_service: &ApiService,
+    function: &Function,
+    caller: Uuid,
+) -> Result<(), ApiError> {
+    let tags: Option<serde_json::Value> =
+        sqlx::query_scalar("SELECT tags FROM services WHERE id = $1")
+            .bind(function.service_id)
+            .fetch_optional(&api_service.db)
+            .await
+            .map_err(|e| ApiError::Database(format!("Failed to get service tags: {}", e)))?;
+
+    let attributes = serde_json::json!({
+        "tenant": function.user_id.to_string(),
+        "caller": caller.to_string(),
+        "function": function.id.to_string(),
+        "function_name": function.name,
+        "service": function.service_id.to_string(),
+        "tags": tags.unwrap_or_default(),
+        "security_level": function.security_level,
+        "runtime": function.runtime,
+        "trigger_type": function.trigger_type,
+    });
+    let serde_json::Value::Object(attributes) = attributes else {
+        unreachable!("attributes are an object");
+    };
+
+    let decision = api_service
+        .policy_engine
+        .evaluate(PolicyStage::Invoke, attributes);
+    match decision.reason {
+        Some(reason) if !decision.allowed => Err(ApiError::Authorization(reason)),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::Claims;
+    use crate::models::service::{ServiceStatus, ServiceType};
+    use crate::models::user::User;
+    use axum::async_trait;
+    use chrono::Utc;
+    use r3e_built_in_services::billing::{
+        BillingAccount, DetectedPayment, Invoice, InvoicePayment,
+    };
+
+    /// Billing service answering `check_execution` with a fixed outcome
+    struct MockBilling(fn(&str) -> Result<(), BillingError>);
+
+    #[async_trait]
+    impl BillingServiceTrait for MockBilling {
+        async fn get_account(&self, _tenant: &str) -> Result<BillingAccount, BillingError> {
+            unimplemented!()
+        }
+
+        async fn add_payer(
+            &self,
+            _tenant: &str,
+            _address: &str,
+        ) -> Result<BillingAccount, BillingError> {
+            unimplemented!()
+        }
+
+        async fn list_invoices(&self, _tenant: &str) -> Result<Vec<Invoice>, BillingError> {
+            unimplemented!()
+        }
+
+        async fn get_invoice(&self, _invoice_id: &str) -> Result<Invoice, BillingError> {
+            unimplemented!()
+        }
+
+        async fn invoice_tenant(
+            &self,
+            _tenant: &str,
+            _now: u64,
+        ) -> Result<Option<Invoice>, BillingError> {
+            unimplemented!()
+        }
+
+        async fn run_billing_cycle(&self, _now: u64) -> Result<Vec<Invoice>, BillingError> {
+            unimplemented!()
+        }
+
+        async fn record_payment(
+            &self,
+            _payment: DetectedPayment,
+        ) -> Result<Option<InvoicePayment>, BillingError> {
+            unimplemented!()
+        }
+
+        async fn check_execution(&self, tenant: &str) -> Result<(), BillingError> {
+            (self.0)(tenant)
+        }
+    }
+
+    fn auth(role: UserRole) -> Auth {
+        let user = User {
+            id: Uuid::new_v4(),
+            username: "alice".to_string(),
+            email: "alice@example.com".to_string(),
+            password_hash: String::new(),
+            role,
+            api_key: None,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        };
+        Auth {
+            claims: Claims {
+                sub: user.id.to_string(),
+                username: user.username.clone(),
+                role: "developer".to_string(),
+                iat: 0,
+                exp: 0,
+                jti: Uuid::new_v4().to_string(),
+            },
+            user,
+            session_id: Uuid::new_v4(),
+        }
+    }
+
+    fn service(owner: Uuid, visibility: ServiceVisibility) -> Service {
+        Service {
+            id: Uuid::new_v4(),
+            user_id: owner,
+            name: "prices".to_string(),
+            description: None,
+            service_type: ServiceType::Standard,
+            config: serde_json::json!({}),
+            status: ServiceStatus::Active,
+            visibility,
+            version: "1.0.0".to_string(),
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_authorize_user() {
+        let developer = auth(UserRole::Developer);
+        let admin = auth(UserRole::Admin);
+        let other = Uuid::new_v4();
+
+        assert!(authorize_user(&developer, developer.user.id, "update").is_ok());
+        assert!(matches!(
+            authorize_user(&developer, other, "update"),
+            Err(ApiError::Authorization(_))
+        ));
+        assert!(authorize_user(&admin, other, "update").is_ok());
+
+        assert!(authorize_role_change(&developer, None).is_ok());
+        assert!(authorize_role_change(&developer, Some(UserRole::Admin)).is_err());
+        assert!(authorize_role_change(&admin, Some(UserRole::Viewer)).is_ok());
+    }
+
+    #[test]
+    fn test_authorize_service() {
+        let owner = auth(UserRole::Developer);
+        let other = auth(UserRole::Developer);
+        // Admins do not manage services of other users
+        let admin = auth(UserRole::Admin);
+
+        let private = service(owner.user.id, ServiceVisibility::Private);
+        assert!(authorize_service(&owner, &private, "update").is_ok());
+        for auth in [&other, &admin] {
+            assert!(authorize_service(auth, &private, "update").is_err());
+            assert!(authorize_service_client(auth, &private).is_err());
+        }
+
+        // Anyone generates a client for a public service, only the owner manages it
+        let public = service(owner.user.id, ServiceVisibility::Public);
+        assert!(authorize_service_client(&other, &public).is_ok());
+        assert!(authorize_service(&other, &public, "delete").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_check_suspended() {
+        let owner = Uuid::new_v4();
+
+        let active = MockBilling(|_| Ok(()));
+        assert!(check_suspended(&active, owner).await.is_ok());
+
+        let suspended = MockBilling(|tenant| Err(BillingError::Suspended(tenant.to_string())));
+        assert!(matches!(
+            check_suspended(&suspended, owner).await,
+            Err(ApiError::PaymentRequired(_))
+        ));
+
+        // Owners whose standing cannot be checked are not admitted
+        let unavailable = MockBilling(|_| Err(BillingError::Storage("unavailable".to_string())));
+        match check_suspended(&unavailable, owner).await {
+            Err(ApiError::Service(message)) => {
+                assert!(!message.contains("unavailable"), "{}", message)
+            }
+            other => panic!("billing error admitted: {:?}", other),
+        }
+    }
+}
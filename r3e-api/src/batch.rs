@@ -0,0 +1,155 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+//! Fan-out of batch invocations across the workers.
+
+use std::future::Future;
+use std::time::Instant;
+
+use futures::stream::{self, StreamExt};
+
+use crate::error::ApiError;
+use crate::models::function::{BatchItemResult, FunctionInvocationResponse};
+
+/// Invoke the function once per input, with at most `parallelism` invocations running at once.
+///
+/// The results are in input order. An item whose invocation fails, or runs but returns an error,
+/// carries the error without failing the other items.
+pub async fn run_batch<'a, F, Fut>(
+    inputs: &'a [serde_json::Value],
+    parallelism: usize,
+    invoke: F,
+) -> Vec<BatchItemResult>
+where
+    F: Fn(&'a serde_json::Value) -> Fut,
+    Fut: Future<Output = Result<FunctionInvocationResponse, ApiError>>,
+{
+    stream::iter(inputs.iter().enumerate())
+        .map(|(index, input)| {
+            let invocation = invoke(input);
+            async move {
+                let item_start = Instant::now();
+                let (invocation, error) = match invocation.await {
+                    Ok(invocation) => {
+                        let error = invocation.error.clone();
+                        (Some(invocation), error)
+                    }
+                    Err(e) => (None, Some(e.to_string())),
+                };
+
+                BatchItemResult {
+                    index,
+                    invocation,
+                    error,
+                    duration_ms: item_start.elapsed().as_millis() as u64,
+                }
+            }
+        })
+        .buffered(parallelism.max(1))
+        .collect()
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+    use uuid::Uuid;
+
+    fn response(result: serde_json::Value, error: Option<&str>) -> FunctionInvocationResponse {
+        FunctionInvocationResponse {
+            invocation_id: Uuid::new_v4(),
+            function_id: Uuid::nil(),
+            result,
+            execution_time_ms: 1,
+            status: match error {
+                Some(_) => "failed".to_string(),
+                None => "completed".to_string(),
+            },
+            error: error.map(str::to_string),
+            error_code: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_bounded_parallelism() {
+        let running = AtomicUsize::new(0);
+        let peak = AtomicUsize::new(0);
+        let inputs: Vec<_> = (0..10).map(|i| serde_json::json!(i)).collect();
+
+        let items = run_batch(&inputs, 3, |input| {
+            let (running, peak) = (&running, &peak);
+            async move {
+                let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                peak.fetch_max(now, Ordering::SeqCst);
+                tokio::time::sleep(Duration::from_millis(10)).await;
+                running.fetch_sub(1, Ordering::SeqCst);
+                Ok(response(input.clone(), None))
+            }
+        })
+        .await;
+
+        assert_eq!(items.len(), 10);
+        assert_eq!(peak.load(Ordering::SeqCst), 3);
+
+        // A parallelism of 0 still runs the batch, one input at a time
+        peak.store(0, Ordering::SeqCst);
+        run_batch(&inputs[..2], 0, |input| {
+            let (running, peak) = (&running, &peak);
+            async move {
+                let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                peak.fetch_max(now, Ordering::SeqCst);
+                running.fetch_sub(1, Ordering::SeqCst);
+                Ok(response(input.clone(), None))
+            }
+        })
+        .await;
+        assert_eq!(peak.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_result_order() {
+        // Later inputs finish first, the results still follow the inputs
+        let inputs: Vec<_> = (0..5).map(|i| serde_json::json!(i)).collect();
+        let items = run_batch(&inputs, 5, |input| async move {
+            let i = input.as_u64().unwrap();
+            tokio::time::sleep(Duration::from_millis(50 - i * 10)).await;
+            Ok(response(serde_json::json!(i * 2), None))
+        })
+        .await;
+
+        for (i, item) in items.iter().enumerate() {
+            assert_eq!(item.index, i);
+            let invocation = item.invocation.as_ref().unwrap();
+            assert_eq!(invocation.result, serde_json::json!(i * 2));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_item_errors() {
+        let inputs = vec![
+            serde_json::json!("ok"),
+            serde_json::json!("throws"),
+            serde_json::json!("unavailable"),
+        ];
+        let items = run_batch(&inputs, 2, |input| async move {
+            match input.as_str().unwrap() {
+                "ok" => Ok(response(serde_json::json!(true), None)),
+                "throws" => Ok(response(serde_json::Value::Null, Some("TypeError"))),
+                _ => Err(ApiError::Service("no worker available".to_string())),
+            }
+        })
+        .await;
+
+        // The function ran and succeeded
+        assert!(items[0].invocation.is_some() && items[0].error.is_none());
+        // The function ran and threw
+        assert!(items[1].invocation.is_some());
+        assert_eq!(items[1].error.as_deref(), Some("TypeError"));
+        // The function did not run
+        assert!(items[2].invocation.is_none());
+        let error = items[2].error.as_deref().unwrap();
+        assert!(error.contains("no worker available"), "{}", error);
+    }
+}
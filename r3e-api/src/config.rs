@@ -1,6 +1,13 @@
 // Copyright @ 2023 - 2024, R3E Network
 // All Rights Reserved
 
+use crate::models::user::UserRole;
+use crate::oidc::{parse_role, OidcConfig};
+use jsonwebtoken::Algorithm;
+use r3e_built_in_services::balance::AdmissionConfig;
+use r3e_core::mtls::MtlsConfig;
+use r3e_secrets::aws_kms::AwsKmsConfig;
+use r3e_secrets::hashicorp::VaultConfig;
 use serde::{Deserialize, Serialize};
 use std::env;
 
@@ -27,6 +34,192 @@ pub struct Config {
 
     /// TEE service URL
     pub tee_service_url: Option<String>,
+
+    /// Path of a JSON file with the measurements the platform's enclaves are expected to report,
+    /// no attestation is trusted without one
+    pub tee_measurements_path: Option<String>,
+
+    /// Hex encoded master key encrypting function secrets
+    pub secrets_master_key: Option<String>,
+
+    /// Path of the function secrets database, shared with the endpoints
+    pub secrets_db_path: String,
+
+    /// Whether function secrets are stored in the Vault KV engine instead of the secrets
+    /// database
+    pub secrets_in_vault: bool,
+
+    /// Path of the user balances database
+    pub balance_db_path: String,
+
+    /// GAS balance below which invocations run, but alert the tenant that it is low
+    pub balance_soft_limit: u64,
+
+    /// GAS balance below which invocations are rejected as payment required
+    pub balance_hard_limit: u64,
+
+    /// Platform-wide maximum worst-case cost of one invocation (in GAS)
+    pub max_invocation_cost: Option<f64>,
+
+    /// Maximum number of inputs of a batch invocation
+    pub max_batch_size: usize,
+
+    /// Maximum number of invocations of a batch running at once
+    pub max_batch_parallelism: usize,
+
+    /// Path of the hash-chained audit log
+    pub audit_log_path: String,
+
+    /// How long idempotency keys and their responses are kept (in seconds)
+    pub idempotency_ttl: u64,
+
+    /// Path of the quota database
+    pub quota_db_path: String,
+
+    /// Path of a JSON file with the quota limits per plan tier, the limits of the plans apply
+    /// without one
+    pub quota_config_path: Option<String>,
+
+    /// Path of a JSON file with the priced plans, defaults apply without one
+    pub plan_config_path: Option<String>,
+
+    /// Path of the billing database
+    pub billing_db_path: String,
+
+    /// Path of a JSON file with the deposit addresses and payment terms of invoices
+    pub billing_config_path: Option<String>,
+
+    /// Backend of the locks keeping the schedulers on a single node: `postgres`, `redis` or
+    /// `rocksdb`, the last one only for single node deployments
+    pub lock_backend: String,
+
+    /// Path of the lock database of the `rocksdb` backend
+    pub lock_db_path: String,
+
+    /// URL of the Redis server of the `redis` backend
+    pub redis_url: Option<String>,
+
+    /// Path of the notification channels and deliveries database
+    pub notification_db_path: String,
+
+    /// Path of a JSON file with the SMTP server, Telegram bot and throttling of notifications,
+    /// only webhook channels deliver without one
+    pub notification_config_path: Option<String>,
+
+    /// Path of the alert rules database
+    pub alert_rule_db_path: String,
+
+    /// Path of the workflow database
+    pub workflow_db_path: String,
+
+    /// Directory of the functions' permission grants, shared with the workers
+    pub permission_grants_path: String,
+
+    /// Directory of the admin policies, shared with the workers
+    pub policy_path: String,
+
+    /// Path of the usage analytics database
+    pub analytics_db_path: String,
+
+    /// Path of the function logs database
+    pub log_db_path: String,
+
+    /// Default maximum age of function logs in seconds
+    pub log_max_age_secs: Option<u64>,
+
+    /// Default maximum size of the logs of a function in bytes
+    pub log_max_bytes: Option<u64>,
+
+    /// Path of the index of the chain events delivered to triggers
+    pub event_db_path: String,
+
+    /// Events a replay invokes its function with per second
+    pub replay_rate_per_sec: u32,
+
+    /// Most events a single replay may invoke its function with
+    pub max_replay_events: u64,
+
+    /// How long the heap reports of invocations out of memory are kept (in seconds)
+    pub heap_report_ttl: u64,
+
+    /// Largest heap snapshot kept with a heap report in bytes
+    pub max_heap_snapshot_size: usize,
+
+    /// Path of a JSON file with the blob store keeping uploaded bundles, in memory without one
+    pub blob_store_config_path: Option<String>,
+
+    /// Size of the parts of chunked uploads in bytes
+    pub upload_part_size: u64,
+
+    /// Largest bundle a chunked upload accepts in bytes
+    pub max_upload_size: u64,
+
+    /// How long an upload is kept after its last part or completion (in seconds)
+    pub upload_ttl: u64,
+
+    /// Uploads a user may have in progress at once
+    pub max_open_uploads: u32,
+
+    /// Hosts of the platform itself, never routed as custom domains
+    pub platform_hosts: Vec<String>,
+
+    /// Whether the `X-Forwarded-Host` set by the proxy in front of the API is trusted
+    pub domain_trust_forwarded_host: bool,
+
+    /// DNS-over-HTTPS resolver looking up the records verifying custom domains
+    pub domain_dns_resolver_url: String,
+
+    /// File of the keys signing API responses
+    pub response_signing_key_path: String,
+
+    /// Paths whose responses are signed on request, `*` matching a path segment
+    pub response_signing_paths: Vec<String>,
+
+    /// How long a rotated response signing key stays published (in seconds)
+    pub response_signing_grace_period: u64,
+
+    /// Hex encoded P-256 public keys approving admin operations offline
+    pub admin_approver_keys: Vec<String>,
+
+    /// Admin operations database path
+    pub operation_db_path: String,
+
+    /// Time an admin operation can be approved in (in seconds)
+    pub operation_ttl: u64,
+
+    /// Requests handled at once before new ones are queued
+    pub load_shed_max_concurrency: usize,
+
+    /// Requests waiting for a slot before new ones are shed
+    pub load_shed_max_queue: usize,
+
+    /// Share of the queue requests other than invocations may take (in percent)
+    pub load_shed_list_queue_share: u8,
+
+    /// Time a request waits for a slot before it is shed (in milliseconds)
+    pub load_shed_queue_timeout_ms: u64,
+
+    /// GraphQL query budgets per role, e.g. `viewer=8:250,developer=10:1000,admin=15:5000`
+    /// giving the maximum depth and complexity
+    pub graphql_query_budgets: Option<String>,
+
+    /// Complexity from which GraphQL queries are logged and counted as expensive
+    pub graphql_expensive_complexity: usize,
+
+    /// HashiCorp Vault holding tenant KEKs in its transit engine
+    pub vault: Option<VaultConfig>,
+
+    /// AWS KMS holding tenant KEKs
+    pub aws_kms: Option<AwsKmsConfig>,
+
+    /// OpenID Connect provider users can log in with
+    pub oidc: Option<OidcConfig>,
+
+    /// Port of the internal listener workers and services call over mutual TLS
+    pub internal_port: u16,
+
+    /// Certificates of the internal listener, which is not started without them
+    pub mtls: Option<MtlsConfig>,
 }
 
 impl Config {
@@ -57,6 +250,350 @@ impl Config {
             oracle_service_url: env::var("ORACLE_SERVICE_URL").ok(),
 
             tee_service_url: env::var("TEE_SERVICE_URL").ok(),
+
+            tee_measurements_path: env::var("TEE_MEASUREMENTS_PATH").ok(),
+
+            secrets_master_key: env::var("SECRETS_MASTER_KEY").ok(),
+
+            secrets_db_path: env::var("SECRETS_DB_PATH")
+                .unwrap_or_else(|_| "./data/secrets".to_string()),
+
+            secrets_in_vault: env::var("SECRETS_BACKEND").as_deref() == Ok("vault"),
+
+            balance_db_path: env::var("BALANCE_DB_PATH")
+                .unwrap_or_else(|_| "./data/balances".to_string()),
+
+            balance_soft_limit: env::var("BALANCE_SOFT_LIMIT")
+                .ok()
+                .and_then(|limit| limit.parse().ok())
+                .unwrap_or(AdmissionConfig::default().soft_limit),
+
+            balance_hard_limit: env::var("BALANCE_HARD_LIMIT")
+                .ok()
+                .and_then(|limit| limit.parse().ok())
+                .unwrap_or(AdmissionConfig::default().hard_limit),
+
+            max_invocation_cost: env::var("MAX_INVOCATION_COST")
+                .ok()
+                .and_then(|cost| cost.parse().ok()),
+
+            max_batch_size: env::var("MAX_BATCH_SIZE")
+                .unwrap_or_else(|_| "100".to_string())
+                .parse()
+                .unwrap_or(100),
+
+            max_batch_parallelism: env::var("MAX_BATCH_PARALLELISM")
+                .unwrap_or_else(|_| "8".to_string())
+                .parse()
+                .unwrap_or(8),
+
+            audit_log_path: env::var("AUDIT_LOG_PATH")
+                .unwrap_or_else(|_| "./data/audit".to_string()),
+
+            idempotency_ttl: env::var("IDEMPOTENCY_TTL")
+                .unwrap_or_else(|_| "86400".to_string())
+                .parse()
+                .unwrap_or(86400),
+
+            quota_db_path: env::var("QUOTA_DB_PATH").unwrap_or_else(|_| "./data/quota".to_string()),
+
+            quota_config_path: env::var("QUOTA_CONFIG_PATH").ok(),
+
+            plan_config_path: env::var("PLAN_CONFIG_PATH").ok(),
+
+            billing_db_path: env::var("BILLING_DB_PATH")
+                .unwrap_or_else(|_| "./data/billing".to_string()),
+
+            billing_config_path: env::var("BILLING_CONFIG_PATH").ok(),
+
+            lock_backend: env::var("LOCK_BACKEND").unwrap_or_else(|_| "postgres".to_string()),
+
+            lock_db_path: env::var("LOCK_DB_PATH").unwrap_or_else(|_| "./data/locks".to_string()),
+
+            redis_url: env::var("REDIS_URL").ok(),
+
+            notification_db_path: env::var("NOTIFICATION_DB_PATH")
+                .unwrap_or_else(|_| "./data/notifications".to_string()),
+
+            notification_config_path: env::var("NOTIFICATION_CONFIG_PATH").ok(),
+
+            alert_rule_db_path: env::var("ALERT_RULE_DB_PATH")
+                .unwrap_or_else(|_| "./data/alert_rules".to_string()),
+
+            workflow_db_path: env::var("WORKFLOW_DB_PATH")
+                .unwrap_or_else(|_| "./data/workflows".to_string()),
+
+            permission_grants_path: env::var("PERMISSION_GRANTS_PATH")
+                .unwrap_or_else(|_| "./data/permissions".to_string()),
+
+            policy_path: env::var("POLICY_PATH").unwrap_or_else(|_| "./data/policies".to_string()),
+
+            analytics_db_path: env::var("ANALYTICS_DB_PATH")
+                .unwrap_or_else(|_| "./data/analytics".to_string()),
+
+            log_db_path: env::var("LOG_DB_PATH").unwrap_or_else(|_| "./data/logs".to_string()),
+
+            log_max_age_secs: env::var("LOG_MAX_AGE_SECS")
+                .unwrap_or_else(|_| "604800".to_string())
+                .parse()
+                .ok(),
+
+            log_max_bytes: env::var("LOG_MAX_BYTES")
+                .unwrap_or_else(|_| "10485760".to_string())
+                .parse()
+                .ok(),
+
+            event_db_path: env::var("EVENT_DB_PATH")
+                .unwrap_or_else(|_| "./data/events".to_string()),
+
+            replay_rate_per_sec: env::var("REPLAY_RATE_PER_SEC")
+                .unwrap_or_else(|_| "10".to_string())
+                .parse()
+                .unwrap_or(10),
+
+            max_replay_events: env::var("MAX_REPLAY_EVENTS")
+                .unwrap_or_else(|_| "10000".to_string())
+                .parse()
+                .unwrap_or(10000),
+
+            heap_report_ttl: env::var("HEAP_REPORT_TTL")
+                .unwrap_or_else(|_| "604800".to_string())
+                .parse()
+                .unwrap_or(604800),
+
+            max_heap_snapshot_size: env::var("MAX_HEAP_SNAPSHOT_SIZE")
+                .unwrap_or_else(|_| "268435456".to_string())
+                .parse()
+                .unwrap_or(268435456),
+
+            blob_store_config_path: env::var("BLOB_STORE_CONFIG_PATH").ok(),
+
+            upload_part_size: env::var("UPLOAD_PART_SIZE")
+                .unwrap_or_else(|_| "5242880".to_string())
+                .parse()
+                .unwrap_or(5242880),
+
+            max_upload_size: env::var("MAX_UPLOAD_SIZE")
+                .unwrap_or_else(|_| "67108864".to_string())
+                .parse()
+                .unwrap_or(67108864),
+
+            upload_ttl: env::var("UPLOAD_TTL")
+                .unwrap_or_else(|_| "86400".to_string())
+                .parse()
+                .unwrap_or(86400),
+
+            max_open_uploads: env::var("MAX_OPEN_UPLOADS")
+                .unwrap_or_else(|_| "10".to_string())
+                .parse()
+                .unwrap_or(10),
+
+            platform_hosts: env::var("PLATFORM_HOSTS")
+                .unwrap_or_default()
+                .split(',')
+                .map(|host| host.trim().to_ascii_lowercase())
+                .filter(|host| !host.is_empty())
+                .collect(),
+
+            domain_trust_forwarded_host: env::var("DOMAIN_TRUST_FORWARDED_HOST")
+                .map(|v| v == "true" || v == "1")
+                .unwrap_or(false),
+
+            domain_dns_resolver_url: env::var("DOMAIN_DNS_RESOLVER_URL")
+                .unwrap_or_else(|_| "https://cloudflare-dns.com/dns-query".to_string()),
+
+            response_signing_key_path: env::var("RESPONSE_SIGNING_KEY_PATH")
+                .unwrap_or_else(|_| "./data/response-signing-keys.json".to_string()),
+
+            response_signing_paths: env::var("RESPONSE_SIGNING_PATHS")
+                .unwrap_or_else(|_| {
+                    "/functions/*/invoke,/services/*/functions/*/invoke".to_string()
+                })
+                .split(',')
+                .map(|path| path.trim().to_string())
+                .filter(|path| !path.is_empty())
+                .collect(),
+
+            response_signing_grace_period: env::var("RESPONSE_SIGNING_GRACE_PERIOD")
+                .unwrap_or_else(|_| "2592000".to_string())
+                .parse()
+                .unwrap_or(2592000),
+
+            admin_approver_keys: env::var("ADMIN_APPROVER_KEYS")
+                .unwrap_or_default()
+                .split(',')
+                .map(|key| key.trim().to_string())
+                .filter(|key| !key.is_empty())
+                .collect(),
+
+            operation_db_path: env::var("OPERATION_DB_PATH")
+                .unwrap_or_else(|_| "./data/operations".to_string()),
+
+            operation_ttl: env::var("OPERATION_TTL")
+                .unwrap_or_else(|_| "86400".to_string())
+                .parse()
+                .unwrap_or(86400),
+
+            load_shed_max_concurrency: env::var("LOAD_SHED_MAX_CONCURRENCY")
+                .unwrap_or_else(|_| "512".to_string())
+                .parse()
+                .unwrap_or(512),
+
+            load_shed_max_queue: env::var("LOAD_SHED_MAX_QUEUE")
+                .unwrap_or_else(|_| "1024".to_string())
+                .parse()
+                .unwrap_or(1024),
+
+            load_shed_list_queue_share: env::var("LOAD_SHED_LIST_QUEUE_SHARE")
+                .unwrap_or_else(|_| "50".to_string())
+                .parse::<u8>()
+                .map(|share| share.min(100))
+                .unwrap_or(50),
+
+            load_shed_queue_timeout_ms: env::var("LOAD_SHED_QUEUE_TIMEOUT_MS")
+                .unwrap_or_else(|_| "2000".to_string())
+                .parse()
+                .unwrap_or(2000),
+
+            graphql_query_budgets: env::var("GRAPHQL_QUERY_BUDGETS").ok(),
+
+            graphql_expensive_complexity: env::var("GRAPHQL_EXPENSIVE_COMPLEXITY")
+                .unwrap_or_else(|_| "500".to_string())
+                .parse()
+                .unwrap_or(500),
+
+            vault: vault_from_env(),
+
+            aws_kms: aws_kms_from_env(),
+
+            oidc: oidc_from_env(),
+
+            internal_port: env::var("INTERNAL_API_PORT")
+                .unwrap_or_else(|_| "3443".to_string())
+                .parse()
+                .unwrap_or(3443),
+
+            mtls: mtls_from_env(),
         }
     }
 }
+
+/// HashiCorp Vault configuration, if `VAULT_ADDR` and `VAULT_TOKEN` are set
+pub fn vault_from_env() -> Option<VaultConfig> {
+    let mut config = VaultConfig::new(
+        &env::var("VAULT_ADDR").ok()?,
+        &env::var("VAULT_TOKEN").ok()?,
+    );
+    config.namespace = env::var("VAULT_NAMESPACE").ok();
+    if let Ok(mount) = env::var("VAULT_TRANSIT_MOUNT") {
+        config.transit_mount = mount;
+    }
+    if let Ok(mount) = env::var("VAULT_KV_MOUNT") {
+        config.kv_mount = mount;
+    }
+    if let Ok(prefix) = env::var("VAULT_KV_PREFIX") {
+        config.kv_prefix = prefix;
+    }
+    let secs = |name: &str, default: u64| {
+        env::var(name)
+            .ok()
+            .and_then(|secs| secs.parse().ok())
+            .unwrap_or(default)
+    };
+    config.cache_ttl_secs = secs("VAULT_CACHE_TTL", config.cache_ttl_secs);
+    config.stale_ttl_secs = secs("VAULT_STALE_TTL", config.stale_ttl_secs);
+    config.token_increment_secs = secs("VAULT_TOKEN_INCREMENT", config.token_increment_secs);
+    Some(config)
+}
+
+/// AWS KMS configuration, if `AWS_KMS_REGION` and the AWS credentials are set
+pub fn aws_kms_from_env() -> Option<AwsKmsConfig> {
+    Some(AwsKmsConfig {
+        region: env::var("AWS_KMS_REGION").ok()?,
+        endpoint: env::var("AWS_KMS_ENDPOINT").ok(),
+        access_key_id: env::var("AWS_ACCESS_KEY_ID").ok()?,
+        secret_access_key: env::var("AWS_SECRET_ACCESS_KEY").ok()?,
+        session_token: env::var("AWS_SESSION_TOKEN").ok(),
+        key_id: env::var("AWS_KMS_KEY_ID").ok(),
+        timeout_secs: 10,
+    })
+}
+
+/// OpenID Connect configuration, if `OIDC_ENABLED` is set and the provider is configured
+pub fn oidc_from_env() -> Option<OidcConfig> {
+    if !env::var("OIDC_ENABLED")
+        .map(|v| v == "true" || v == "1")
+        .unwrap_or(false)
+    {
+        return None;
+    }
+
+    let role_mapping =
+        match OidcConfig::parse_role_mapping(&env::var("OIDC_ROLE_MAPPING").unwrap_or_default()) {
+            Ok(mapping) => mapping,
+            Err(e) => {
+                log::error!("OIDC login disabled: {}", e);
+                return None;
+            }
+        };
+
+    let id_token_algorithm = match env::var("OIDC_ID_TOKEN_ALG") {
+        Ok(alg) => match alg.parse::<Algorithm>() {
+            Ok(alg) => alg,
+            Err(e) => {
+                log::error!(
+                    "OIDC login disabled: invalid OIDC_ID_TOKEN_ALG {}: {}",
+                    alg,
+                    e
+                );
+                return None;
+            }
+        },
+        Err(_) => Algorithm::RS256,
+    };
+
+    Some(OidcConfig {
+        issuer: env::var("OIDC_ISSUER").ok()?,
+        client_id: env::var("OIDC_CLIENT_ID").ok()?,
+        client_secret: env::var("OIDC_CLIENT_SECRET").ok(),
+        redirect_url: env::var("OIDC_REDIRECT_URL").ok()?,
+        scopes: env::var("OIDC_SCOPES")
+            .unwrap_or_else(|_| "openid,email,profile".to_string())
+            .split(',')
+            .map(|scope| scope.trim().to_string())
+            .filter(|scope| !scope.is_empty())
+            .collect(),
+        role_claim: env::var("OIDC_ROLE_CLAIM").unwrap_or_else(|_| "groups".to_string()),
+        role_mapping,
+        default_role: env::var("OIDC_DEFAULT_ROLE")
+            .ok()
+            .and_then(|role| parse_role(&role).ok())
+            .unwrap_or(UserRole::Viewer),
+        sync_roles: env::var("OIDC_SYNC_ROLES")
+            .map(|v| v == "true" || v == "1")
+            .unwrap_or(true),
+        id_token_algorithm,
+    })
+}
+
+/// Mutual TLS configuration, if `MTLS_CERT_PATH`, `MTLS_KEY_PATH` and `MTLS_CA_PATH` are set
+pub fn mtls_from_env() -> Option<MtlsConfig> {
+    let list = |name: &str, default: &str| -> Vec<String> {
+        env::var(name)
+            .unwrap_or_else(|_| default.to_string())
+            .split(',')
+            .map(|item| item.trim().to_string())
+            .filter(|item| !item.is_empty())
+            .collect()
+    };
+
+    Some(MtlsConfig {
+        cert_path: env::var("MTLS_CERT_PATH").ok()?.into(),
+        key_path: env::var("MTLS_KEY_PATH").ok()?.into(),
+        ca_path: env::var("MTLS_CA_PATH").ok()?.into(),
+        ca_key_path: env::var("MTLS_CA_KEY_PATH").ok().map(Into::into),
+        identity: env::var("MTLS_IDENTITY").ok(),
+        dns_names: list("MTLS_DNS_NAMES", "localhost"),
+        allowed_peers: list("MTLS_ALLOWED_PEERS", ""),
+    })
+}
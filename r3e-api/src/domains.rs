@@ -0,0 +1,731 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+//! Custom domains of HTTP-triggered functions.
+//!
+//! A user maps a domain and path prefix to a function and proves control of the domain with
+//! a TXT record. The platform runs behind a TLS terminating proxy: requests for verified
+//! domains are routed by their `Host` header, and the proxy asks [`DomainStore::is_verified`]
+//! before obtaining an ACME certificate for a domain on demand.
+
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+
+use axum::body::{to_bytes, Body};
+use axum::extract::{FromRequestParts, Request, State};
+use axum::http::{header, HeaderMap, HeaderName, HeaderValue, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use chrono::Utc;
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::auth::Auth;
+use crate::authz::{admit_anonymous_invocation, admit_invocation};
+use crate::error::ApiError;
+use crate::models::domain::DomainMapping;
+use crate::models::function::TriggerType;
+use crate::service::ApiService;
+
+/// Label prepended to a domain to name the TXT record verifying it
+pub const VERIFICATION_LABEL: &str = "_r3e-verify";
+
+/// Largest request body passed to a function served on a custom domain
+const MAX_BODY_SIZE: usize = 6 * 1024 * 1024;
+
+/// How long the verified mappings of a domain are cached
+const CACHE_TTL: Duration = Duration::from_secs(30);
+
+/// Name of the TXT record verifying a domain
+pub fn verification_record(domain: &str) -> String {
+    format!("{}.{}", VERIFICATION_LABEL, domain)
+}
+
+/// Lowercase a domain name and check that it is a valid hostname with at least two labels
+pub fn normalize_domain(domain: &str) -> Result<String, ApiError> {
+    let domain = domain.trim().trim_end_matches('.').to_ascii_lowercase();
+    let invalid = || ApiError::Validation(format!("Invalid domain: {}", domain));
+
+    if domain.len() > 253 || !domain.contains('.') {
+        return Err(invalid());
+    }
+    for label in domain.split('.') {
+        if label.is_empty()
+            || label.len() > 63
+            || label.starts_with('-')
+            || label.ends_with('-')
+            || !label
+                .bytes()
+                .all(|b| b.is_ascii_alphanumeric() || b == b'-')
+        {
+            return Err(invalid());
+        }
+    }
+    // A numeric top-level label would make it an IP address
+    if domain
+        .rsplit('.')
+        .next()
+        .is_some_and(|tld| tld.bytes().all(|b| b.is_ascii_digit()))
+    {
+        return Err(invalid());
+    }
+
+    Ok(domain)
+}
+
+/// Normalize a path prefix to start with `/` and have no trailing `/`, `/` for the root
+pub fn normalize_path_prefix(prefix: Option<&str>) -> Result<String, ApiError> {
+    let prefix = prefix.unwrap_or("/").trim().trim_end_matches('/');
+    if prefix.is_empty() {
+        return Ok("/".to_string());
+    }
+    if !prefix.starts_with('/')
+        || prefix.contains("//")
+        || prefix
+            .split('/')
+            .any(|segment| segment == "." || segment == "..")
+        || !prefix
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b"/-._~".contains(&b))
+    {
+        return Err(ApiError::Validation(format!(
+            "Invalid path prefix: {}",
+            prefix
+        )));
+    }
+
+    Ok(prefix.to_string())
+}
+
+/// Mapping with the longest path prefix matching the path at a segment boundary, and the
+/// remainder of the path the function sees
+pub fn match_path<'a>(
+    mappings: &'a [DomainMapping],
+    path: &str,
+) -> Option<(&'a DomainMapping, String)> {
+    mappings
+        .iter()
+        .filter_map(|mapping| {
+            let prefix = mapping.path_prefix.as_str();
+            let rest = if prefix == "/" {
+                path
+            } else {
+                let rest = path.strip_prefix(prefix)?;
+                if !rest.is_empty() && !rest.starts_with('/') {
+                    return None;
+                }
+                rest
+            };
+            let rest = if rest.is_empty() { "/" } else { rest };
+            Some((mapping, rest.to_string()))
+        })
+        .max_by_key(|(mapping, _)| mapping.path_prefix.len())
+}
+
+/// Host a request was sent to, without its port
+///
+/// `X-Forwarded-Host` is only honored behind a proxy that sets it, clients could send it
+/// to reach any function otherwise.
+pub fn request_host(headers: &HeaderMap, trust_forwarded_host: bool) -> Option<String> {
+    let forwarded = trust_forwarded_host
+        .then(|| headers.get("X-Forwarded-Host"))
+        .flatten();
+    let host = forwarded
+        .or_else(|| headers.get(header::HOST))?
+        .to_str()
+        .ok()?
+        .split(',')
+        .next()?
+        .trim();
+    // Strip the port, IPv6 literals are never custom domains
+    if host.starts_with('[') {
+        return None;
+    }
+    let host = host.split(':').next()?;
+
+    Some(host.trim_end_matches('.').to_ascii_lowercase())
+}
+
+/// TXT record values in a DNS-over-HTTPS JSON answer
+pub fn parse_txt_answer(answer: &Value) -> Vec<String> {
+    answer
+        .get("Answer")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter(|record| record.get("type").and_then(Value::as_u64) == Some(16))
+        .filter_map(|record| record.get("data").and_then(Value::as_str))
+        // Long values are split into quoted strings, e.g. `"abc" "def"`
+        .map(|data| {
+            data.split("\" \"")
+                .map(|part| part.trim_matches('"'))
+                .collect::<String>()
+        })
+        .collect()
+}
+
+/// Custom domain mappings, with a short-lived cache of the verified mappings per domain
+#[derive(Clone)]
+pub struct DomainStore {
+    /// Database pool
+    db: PgPool,
+
+    /// DNS-over-HTTPS resolver queried for verification records
+    resolver_url: String,
+
+    /// HTTP client of the resolver
+    client: reqwest::Client,
+
+    /// Verified mappings per domain and when they were loaded
+    cache: Arc<RwLock<HashMap<String, (Instant, Arc<Vec<DomainMapping>>)>>>,
+}
+
+impl DomainStore {
+    /// Create a new domain store
+    pub fn new(db: PgPool, resolver_url: impl Into<String>) -> Self {
+        Self {
+            db,
+            resolver_url: resolver_url.into(),
+            client: reqwest::Client::new(),
+            cache: Arc::new(RwLock::new(HashMap::new())),
+        }
+    }
+
+    /// Map a domain and path prefix to a function.
+    ///
+    /// Domains another user verified cannot be mapped. Further mappings of a domain the user
+    /// already verified are verified right away.
+    pub async fn create(
+        &self,
+        user_id: Uuid,
+        domain: &str,
+        path_prefix: Option<&str>,
+        function_id: Uuid,
+    ) -> Result<DomainMapping, ApiError> {
+        let domain = normalize_domain(domain)?;
+        let path_prefix = normalize_path_prefix(path_prefix)?;
+
+        let owners: Vec<(Uuid, String)> = sqlx::query_as(
+            "SELECT user_id, path_prefix FROM custom_domains WHERE domain = $1 AND verified",
+        )
+        .bind(&domain)
+        .fetch_all(&self.db)
+        .await
+        .map_err(|e| ApiError::Database(format!("Failed to get domain: {}", e)))?;
+        if owners.iter().any(|(owner, _)| *owner != user_id) {
+            return Err(ApiError::Conflict(format!(
+                "Domain {} is verified by another user",
+                domain
+            )));
+        }
+        if owners.iter().any(|(_, prefix)| *prefix == path_prefix) {
+            return Err(ApiError::Conflict(format!(
+                "Path {} of {} is already mapped",
+                path_prefix, domain
+            )));
+        }
+
+        let verified = !owners.is_empty();
+        let now = Utc::now();
+        let mapping = DomainMapping {
+            id: Uuid::new_v4(),
+            user_id,
+            domain,
+            path_prefix,
+            function_id,
+            verification_token: Uuid::new_v4().simple().to_string(),
+            verified,
+            created_at: now,
+            verified_at: verified.then_some(now),
+        };
+
+        sqlx::query(
+            "INSERT INTO custom_domains \
+             (id, user_id, domain, path_prefix, function_id, verification_token, verified, created_at, verified_at) \
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)",
+        )
+        .bind(mapping.id)
+        .bind(mapping.user_id)
+        .bind(&mapping.domain)
+        .bind(&mapping.path_prefix)
+        .bind(mapping.function_id)
+        .bind(&mapping.verification_token)
+        .bind(mapping.verified)
+        .bind(mapping.created_at)
+        .bind(mapping.verified_at)
+        .execute(&self.db)
+        .await
+        .map_err(|e| ApiError::Database(format!("Failed to create domain mapping: {}", e)))?;
+
+        self.invalidate(&mapping.domain);
+        Ok(mapping)
+    }
+
+    /// Get a mapping
+    pub async fn get(&self, id: Uuid) -> Result<DomainMapping, ApiError> {
+        sqlx::query_as::<_, DomainMapping>("SELECT * FROM custom_domains WHERE id = $1")
+            .bind(id)
+            .fetch_optional(&self.db)
+            .await
+            .map_err(|e| ApiError::Database(format!("Failed to get domain mapping: {}", e)))?
+            .ok_or_else(|| ApiError::NotFound(format!("Domain mapping not found: {}", id)))
+    }
+
+    /// List the mappings of a user
+    pub async fn list(&self, user_id: Uuid) -> Result<Vec<DomainMapping>, ApiError> {
+        sqlx::query_as::<_, DomainMapping>(
+            "SELECT * FROM custom_domains WHERE user_id = $1 ORDER BY domain, path_prefix",
+        )
+        .bind(user_id)
+        .fetch_all(&self.db)
+        .await
+        .map_err(|e| ApiError::Database(format!("Failed to list domain mappings: {}", e)))
+    }
+
+    /// Delete a mapping
+    pub async fn delete(&self, id: Uuid) -> Result<(), ApiError> {
+        let domain: Option<String> =
+            sqlx::query_scalar("DELETE FROM custom_domains WHERE id = $1 RETURNING domain")
+                .bind(id)
+                .fetch_optional(&self.db)
+                .await
+                .map_err(|e| {
+                    ApiError::Database(format!("Failed to delete domain mapping: {}", e))
+                })?;
+
+        let domain = domain
+            .ok_or_else(|| ApiError::NotFound(format!("Domain mapping not found: {}", id)))?;
+        self.invalidate(&domain);
+        Ok(())
+    }
+
+    /// Verify a mapping by looking up the token in the domain's TXT record
+    pub async fn verify(&self, id: Uuid) -> Result<DomainMapping, ApiError> {
+        let mut mapping = self.get(id).await?;
+        if mapping.verified {
+            return Ok(mapping);
+        }
+
+        let records = self
+            .lookup_txt(&verification_record(&mapping.domain))
+            .await?;
+        if !records.contains(&mapping.verification_token) {
+            return Err(ApiError::PreconditionFailed(format!(
+                "TXT record {} does not hold the verification token",
+                verification_record(&mapping.domain)
+            )));
+        }
+
+        let other_owner: Option<Uuid> = sqlx::query_scalar(
+            "SELECT user_id FROM custom_domains WHERE domain = $1 AND verified AND user_id <> $2 LIMIT 1",
+        )
+        .bind(&mapping.domain)
+        .bind(mapping.user_id)
+        .fetch_optional(&self.db)
+        .await
+        .map_err(|e| ApiError::Database(format!("Failed to get domain: {}", e)))?;
+        if other_owner.is_some() {
+            return Err(ApiError::Conflict(format!(
+                "Domain {} is verified by another user",
+                mapping.domain
+            )));
+        }
+
+        // The unique index on verified mappings rejects a path verified concurrently
+        let now = Utc::now();
+        sqlx::query("UPDATE custom_domains SET verified = TRUE, verified_at = $2 WHERE id = $1")
+            .bind(id)
+            .bind(now)
+            .execute(&self.db)
+            .await
+            .map_err(|e| match e {
+                sqlx::Error::Database(db) if db.is_unique_violation() => {
+                    ApiError::Conflict(format!("Domain {} is already verified", mapping.domain))
+                }
+                e => ApiError::Database(format!("Failed to verify domain mapping: {}", e)),
+            })?;
+
+        self.invalidate(&mapping.domain);
+        mapping.verified = true;
+        mapping.verified_at = Some(now);
+        Ok(mapping)
+    }
+
+    /// Whether a domain has a verified mapping, so a certificate may be issued for it
+    pub async fn is_verified(&self, domain: &str) -> Result<bool, ApiError> {
+        let Ok(domain) = normalize_domain(domain) else {
+            return Ok(false);
+        };
+        Ok(!self.verified_mappings(&domain).await?.is_empty())
+    }
+
+    /// Verified mappings of a domain
+    pub async fn verified_mappings(
+        &self,
+        domain: &str,
+    ) -> Result<Arc<Vec<DomainMapping>>, ApiError> {
+        if let Some((loaded, mappings)) = self.cache.read().unwrap().get(domain) {
+            if loaded.elapsed() < CACHE_TTL {
+                return Ok(mappings.clone());
+            }
+        }
+
+        let mappings = Arc::new(
+            sqlx::query_as::<_, DomainMapping>(
+                "SELECT * FROM custom_domains WHERE domain = $1 AND verified",
+            )
+            .bind(domain)
+            .fetch_all(&self.db)
+            .await
+            .map_err(|e| ApiError::Database(format!("Failed to get domain mappings: {}", e)))?,
+        );
+
+        let mut cache = self.cache.write().unwrap();
+        cache.retain(|_, (loaded, _)| loaded.elapsed() < CACHE_TTL);
+        cache.insert(domain.to_string(), (Instant::now(), mappings.clone()));
+        Ok(mappings)
+    }
+
+    fn invalidate(&self, domain: &str) {
+        self.cache.write().unwrap().remove(domain);
+    }
+
+    async fn lookup_txt(&self, name: &str) -> Result<Vec<String>, ApiError> {
+        let answer: Value = self
+            .client
+            .get(&self.resolver_url)
+            .query(&[("name", name), ("type", "TXT")])
+            .header(header::ACCEPT, "application/dns-json")
+            .timeout(Duration::from_secs(10))
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| ApiError::ExternalService(format!("Failed to resolve {}: {}", name, e)))?
+            .json()
+            .await
+            .map_err(|e| ApiError::ExternalService(format!("Failed to resolve {}: {}", name, e)))?;
+
+        Ok(parse_txt_answer(&answer))
+    }
+}
+
+/// HTTP trigger settings of a function
+#[derive(Debug, Default, Deserialize)]
+struct HttpTriggerConfig {
+    /// Methods the function accepts, any when empty
+    #[serde(default)]
+    methods: Vec<String>,
+
+    /// Whether callers must authenticate
+    #[serde(default)]
+    auth_required: bool,
+}
+
+/// Middleware serving requests for verified custom domains with their mapped function.
+///
+/// Requests for the platform's own hosts, and for domains without verified mappings, pass
+/// through to the API.
+pub async fn route_custom_domain(
+    State(api_service): State<Arc<ApiService>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let config = &api_service.config;
+    let Some(host) = request_host(request.headers(), config.domain_trust_forwarded_host) else {
+        return next.run(request).await;
+    };
+    if host == "localhost"
+        || host.parse::<std::net::Ipv4Addr>().is_ok()
+        || config
+            .platform_hosts
+            .iter()
+            .any(|platform| *platform == host)
+    {
+        return next.run(request).await;
+    }
+
+    let mappings = match api_service.domain_store.verified_mappings(&host).await {
+        Ok(mappings) if mappings.is_empty() => return next.run(request).await,
+        Ok(mappings) => mappings,
+        Err(e) => return e.into_response(),
+    };
+    let Some((mapping, path)) = match_path(&mappings, request.uri().path()) else {
+        return ApiError::NotFound(format!("No function is mapped to this path of {}", host))
+            .into_response();
+    };
+
+    invoke_mapped(&api_service, mapping, path, request)
+        .await
+        .unwrap_or_else(IntoResponse::into_response)
+}
+
+/// Invoke the function of a mapping with the HTTP request as its input
+async fn invoke_mapped(
+    api_service: &Arc<ApiService>,
+    mapping: &DomainMapping,
+    path: String,
+    request: Request,
+) -> Result<Response, ApiError> {
+    let function = api_service
+        .function_service
+        .get_function(mapping.function_id)
+        .await?;
+    if function.trigger_type != TriggerType::Http {
+        return Err(ApiError::NotFound(
+            "The mapped function is not HTTP-triggered".to_string(),
+        ));
+    }
+    let trigger: HttpTriggerConfig =
+        serde_json::from_value(function.trigger_config.clone()).unwrap_or_default();
+
+    let (mut parts, body) = request.into_parts();
+    if !trigger.methods.is_empty()
+        && !trigger
+            .methods
+            .iter()
+            .any(|method| method.eq_ignore_ascii_case(parts.method.as_str()))
+    {
+        return Ok(StatusCode::METHOD_NOT_ALLOWED.into_response());
+    }
+
+    if trigger.auth_required {
+        let auth = Auth::from_request_parts(&mut parts, api_service)
+            .await
+            .map_err(|_| ApiError::Authentication("Authentication required".to_string()))?;
+        admit_invocation(api_service, &auth, &function, None).await?;
+    } else {
+        admit_anonymous_invocation(api_service, &function).await?;
+    }
+
+    let body = to_bytes(body, MAX_BODY_SIZE)
+        .await
+        .map_err(|e| ApiError::Validation(format!("Failed to read request body: {}", e)))?;
+    let input = http_input(
+        &parts.method,
+        &path,
+        parts.uri.query(),
+        &parts.headers,
+        &body,
+    );
+
+    let response = api_service
+        .function_service
+        .invoke_function(function.id, &input)
+        .await?;
+
+    Ok(http_response(response.result))
+}
+
+/// Invocation input describing an HTTP request
+pub fn http_input(
+    method: &axum::http::Method,
+    path: &str,
+    query: Option<&str>,
+    headers: &HeaderMap,
+    body: &[u8],
+) -> Value {
+    let query: Map<String, Value> = query
+        .map(|query| {
+            url::form_urlencoded::parse(query.as_bytes())
+                .map(|(key, value)| (key.into_owned(), Value::String(value.into_owned())))
+                .collect()
+        })
+        .unwrap_or_default();
+    // Credentials of the platform are not forwarded to user code
+    let headers: Map<String, Value> = headers
+        .iter()
+        .filter(|(name, _)| *name != header::AUTHORIZATION && *name != header::COOKIE)
+        .filter_map(|(name, value)| {
+            Some((
+                name.to_string(),
+                Value::String(value.to_str().ok()?.to_string()),
+            ))
+        })
+        .collect();
+    let body = if body.is_empty() {
+        Value::Null
+    } else {
+        serde_json::from_slice(body)
+            .unwrap_or_else(|_| Value::String(String::from_utf8_lossy(body).into_owned()))
+    };
+
+    json!({
+        "method": method.as_str(),
+        "path": path,
+        "query": query,
+        "headers": headers,
+        "body": body,
+    })
+}
+
+/// HTTP response of a function result.
+///
+/// A result of the form `{statusCode, headers, body}` sets the response status and headers,
+/// any other result is returned as a JSON body.
+pub fn http_response(result: Value) -> Response {
+    let Some(status) = result
+        .get("statusCode")
+        .and_then(Value::as_u64)
+        .and_then(|status| StatusCode::from_u16(status as u16).ok())
+    else {
+        return axum::Json(result).into_response();
+    };
+
+    let mut response = match result.get("body") {
+        None | Some(Value::Null) => Response::new(Body::empty()),
+        Some(Value::String(body)) => Response::new(Body::from(body.clone())),
+        Some(body) => axum::Json(body.clone()).into_response(),
+    };
+    *response.status_mut() = status;
+    if let Some(headers) = result.get("headers").and_then(Value::as_object) {
+        for (name, value) in headers {
+            if let (Ok(name), Some(Ok(value))) = (
+                HeaderName::try_from(name.as_str()),
+                value.as_str().map(HeaderValue::from_str),
+            ) {
+                response.headers_mut().insert(name, value);
+            }
+        }
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mapping(path_prefix: &str) -> DomainMapping {
+        DomainMapping {
+            id: Uuid::new_v4(),
+            user_id: Uuid::nil(),
+            domain: "api.example.com".to_string(),
+            path_prefix: path_prefix.to_string(),
+            function_id: Uuid::new_v4(),
+            verification_token: String::new(),
+            verified: true,
+            created_at: Utc::now(),
+            verified_at: None,
+        }
+    }
+
+    #[test]
+    fn test_normalize_domain() {
+        assert_eq!(
+            normalize_domain(" API.Example.com. ").unwrap(),
+            "api.example.com"
+        );
+        assert!(normalize_domain("localhost").is_err());
+        assert!(normalize_domain("10.0.0.1").is_err());
+        assert!(normalize_domain("-bad.example.com").is_err());
+        assert!(normalize_domain("bad..example.com").is_err());
+        assert!(normalize_domain("under_score.example.com").is_err());
+    }
+
+    #[test]
+    fn test_normalize_path_prefix() {
+        assert_eq!(normalize_path_prefix(None).unwrap(), "/");
+        assert_eq!(normalize_path_prefix(Some("/")).unwrap(), "/");
+        assert_eq!(normalize_path_prefix(Some("/v1/")).unwrap(), "/v1");
+        assert!(normalize_path_prefix(Some("v1")).is_err());
+        assert!(normalize_path_prefix(Some("/v1/../admin")).is_err());
+        assert!(normalize_path_prefix(Some("/a//b")).is_err());
+    }
+
+    #[test]
+    fn test_match_path() {
+        let mappings = vec![mapping("/"), mapping("/v1"), mapping("/v1/orders")];
+
+        let (matched, rest) = match_path(&mappings, "/v1/orders/42").unwrap();
+        assert_eq!(matched.path_prefix, "/v1/orders");
+        assert_eq!(rest, "/42");
+
+        let (matched, rest) = match_path(&mappings, "/v1").unwrap();
+        assert_eq!(matched.path_prefix, "/v1");
+        assert_eq!(rest, "/");
+
+        // Prefixes match whole segments only
+        let (matched, rest) = match_path(&mappings, "/v10").unwrap();
+        assert_eq!(matched.path_prefix, "/");
+        assert_eq!(rest, "/v10");
+
+        assert!(match_path(&mappings[1..], "/other").is_none());
+    }
+
+    #[test]
+    fn test_request_host() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::HOST,
+            HeaderValue::from_static("Fn.Example.com:8443"),
+        );
+        headers.insert(
+            "X-Forwarded-Host",
+            HeaderValue::from_static("other.example.com"),
+        );
+
+        assert_eq!(
+            request_host(&headers, false).as_deref(),
+            Some("fn.example.com")
+        );
+        assert_eq!(
+            request_host(&headers, true).as_deref(),
+            Some("other.example.com")
+        );
+
+        headers.insert(header::HOST, HeaderValue::from_static("[::1]:3000"));
+        assert_eq!(request_host(&headers, false), None);
+    }
+
+    #[test]
+    fn test_parse_txt_answer() {
+        let answer = json!({
+            "Status": 0,
+            "Answer": [
+                { "name": "_r3e-verify.example.com.", "type": 5, "data": "alias.example.com." },
+                { "name": "_r3e-verify.example.com.", "type": 16, "data": "\"abc\" \"def\"" },
+                { "name": "_r3e-verify.example.com.", "type": 16, "data": "\"token\"" },
+            ],
+        });
+
+        assert_eq!(parse_txt_answer(&answer), vec!["abcdef", "token"]);
+        assert!(parse_txt_answer(&json!({ "Status": 3 })).is_empty());
+    }
+
+    #[test]
+    fn test_http_input_drops_credentials() {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::AUTHORIZATION, HeaderValue::from_static("Bearer x"));
+        headers.insert(header::CONTENT_TYPE, HeaderValue::from_static("text/plain"));
+
+        let input = http_input(
+            &axum::http::Method::POST,
+            "/orders",
+            Some("id=42&q=a%20b"),
+            &headers,
+            b"hello",
+        );
+        assert_eq!(input["method"], "POST");
+        assert_eq!(input["query"]["q"], "a b");
+        assert_eq!(input["headers"]["content-type"], "text/plain");
+        assert!(input["headers"].get("authorization").is_none());
+        assert_eq!(input["body"], "hello");
+    }
+
+    #[test]
+    fn test_http_response() {
+        let response = http_response(json!({
+            "statusCode": 201,
+            "headers": { "x-order": "42" },
+            "body": "created",
+        }));
+        assert_eq!(response.status(), StatusCode::CREATED);
+        assert_eq!(response.headers()["x-order"], "42");
+
+        let response = http_response(json!({ "price": 1 }));
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "application/json");
+    }
+}
@@ -0,0 +1,236 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+//! Encryption at rest of tenant function code with per-tenant keys.
+//!
+//! The code of tenants enrolled for encryption at rest is stored as an envelope sealed with
+//! their KEK, with the KEK identifier in `functions.code_key_id`. KEK identifiers are kept in the
+//! `tenant_keys` table, shared with the endpoints service sealing the tenants' secrets.
+
+use std::sync::Arc;
+
+use axum::async_trait;
+use r3e_secrets::aws_kms::{AwsKmsConfig, AwsKmsWrapper, AWS_KMS_PROVIDER};
+use r3e_secrets::envelope::{
+    Envelope, EnvelopeError, EnvelopeService, KeyWrapper, LocalKeyWrapper, TenantKey,
+    TenantKeyStore, LOCAL_PROVIDER,
+};
+use r3e_secrets::hashicorp::{TransitKeyWrapper, VaultClient, VAULT_PROVIDER};
+use r3e_tee::key_management::KeyManagementService;
+use r3e_tee::types::{KeyType, KeyUsage};
+use serde::Serialize;
+use sqlx::PgPool;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::error::ApiError;
+
+/// Provider of KEKs held by the TEE key management service
+pub const TEE_PROVIDER: &str = "tee";
+
+/// Tenant key store over the `tenant_keys` table
+pub struct PgTenantKeyStore {
+    /// Database pool
+    db: PgPool,
+}
+
+impl PgTenantKeyStore {
+    /// Create a new tenant key store
+    pub fn new(db: PgPool) -> Self {
+        Self { db }
+    }
+}
+
+#[async_trait]
+impl TenantKeyStore for PgTenantKeyStore {
+    async fn get(&self, tenant: &str) -> Result<Option<TenantKey>, EnvelopeError> {
+        let row = sqlx::query_as::<_, (String, String, serde_json::Value, i64)>(
+            "SELECT provider, kek_id, retired_kek_ids, created_at FROM tenant_keys WHERE tenant = $1",
+        )
+        .bind(tenant)
+        .fetch_optional(&self.db)
+        .await
+        .map_err(|e| EnvelopeError::Storage(format!("Failed to get tenant key: {}", e)))?;
+
+        row.map(|(provider, kek_id, retired_kek_ids, created_at)| {
+            Ok(TenantKey {
+                tenant: tenant.to_string(),
+                provider,
+                kek_id,
+                retired_kek_ids: serde_json::from_value(retired_kek_ids)
+                    .map_err(|e| EnvelopeError::Storage(e.to_string()))?,
+                created_at: created_at as u64,
+            })
+        })
+        .transpose()
+    }
+
+    async fn put(&self, key: &TenantKey) -> Result<(), EnvelopeError> {
+        sqlx::query(
+            r#"
+            INSERT INTO tenant_keys (tenant, provider, kek_id, retired_kek_ids, created_at)
+            VALUES ($1, $2, $3, $4, $5)
+            ON CONFLICT (tenant) DO UPDATE SET
+                provider = EXCLUDED.provider, kek_id = EXCLUDED.kek_id,
+                retired_kek_ids = EXCLUDED.retired_kek_ids, created_at = EXCLUDED.created_at
+            "#,
+        )
+        .bind(&key.tenant)
+        .bind(&key.provider)
+        .bind(&key.kek_id)
+        .bind(serde_json::json!(key.retired_kek_ids))
+        .bind(key.created_at as i64)
+        .execute(&self.db)
+        .await
+        .map_err(|e| EnvelopeError::Storage(format!("Failed to store tenant key: {}", e)))?;
+
+        Ok(())
+    }
+}
+
+/// KEKs generated in the TEE key management service, never leaving it
+pub struct TeeKeyWrapper {
+    /// Key management service
+    kms: Arc<dyn KeyManagementService>,
+}
+
+impl TeeKeyWrapper {
+    /// Create a new TEE key wrapper
+    pub fn new(kms: Arc<dyn KeyManagementService>) -> Self {
+        Self { kms }
+    }
+}
+
+#[async_trait]
+impl KeyWrapper for TeeKeyWrapper {
+    async fn create_kek(&self, _tenant: &str) -> Result<String, EnvelopeError> {
+        let metadata = self
+            .kms
+            .generate_key(
+                KeyType::Symmetric,
+                vec![KeyUsage::Encryption, KeyUsage::Decryption],
+                "AES",
+                256,
+                false,
+            )
+            .await
+            .map_err(|e| EnvelopeError::Provider(e.to_string()))?;
+        Ok(metadata.id)
+    }
+
+    async fn wrap(&self, kek_id: &str, dek: &[u8]) -> Result<Vec<u8>, EnvelopeError> {
+        self.kms
+            .encrypt(kek_id, dek, None)
+            .await
+            .map_err(|e| EnvelopeError::Provider(e.to_string()))
+    }
+
+    async fn unwrap(&self, kek_id: &str, wrapped: &[u8]) -> Result<Vec<u8>, EnvelopeError> {
+        self.kms
+            .decrypt(kek_id, wrapped, None)
+            .await
+            .map_err(|e| EnvelopeError::Provider(e.to_string()))
+    }
+}
+
+/// Envelope encryption with tenant keys in the database, KEKs derived from the master key and,
+/// if given, KEKs held by the TEE key management service
+pub fn envelope_service(
+    db: PgPool,
+    master_key: [u8; 32],
+    kms: Option<Arc<dyn KeyManagementService>>,
+) -> EnvelopeService {
+    let mut service = EnvelopeService::new(Arc::new(PgTenantKeyStore::new(db)))
+        .with_wrapper(LOCAL_PROVIDER, Arc::new(LocalKeyWrapper::new(master_key)));
+    if let Some(kms) = kms {
+        service = service.with_wrapper(TEE_PROVIDER, Arc::new(TeeKeyWrapper::new(kms)));
+    }
+    service
+}
+
+/// Register the KEK providers of the external key stores configured: `vault` keeps KEKs in
+/// the Vault transit engine, `aws-kms` in AWS KMS
+pub fn with_key_stores(
+    mut service: EnvelopeService,
+    vault: Option<Arc<VaultClient>>,
+    aws_kms: Option<&AwsKmsConfig>,
+) -> Result<EnvelopeService, EnvelopeError> {
+    if let Some(vault) = vault {
+        service = service.with_wrapper(VAULT_PROVIDER, Arc::new(TransitKeyWrapper::new(vault)));
+    }
+    if let Some(aws_kms) = aws_kms {
+        service = service.with_wrapper(
+            AWS_KMS_PROVIDER,
+            Arc::new(AwsKmsWrapper::new(aws_kms.clone())?),
+        );
+    }
+    Ok(service)
+}
+
+/// Outcome of re-keying the code of a tenant's functions
+#[derive(Debug, Default, Serialize, ToSchema)]
+pub struct RekeyReport {
+    /// Functions whose plain code was sealed
+    pub sealed: usize,
+
+    /// Functions whose data key was re-wrapped with the current KEK
+    pub rekeyed: usize,
+
+    /// Functions already sealed with the current KEK
+    pub unchanged: usize,
+}
+
+/// Seal the code of a tenant's functions with the tenant's current KEK: plain code is sealed,
+/// data keys wrapped by retired KEKs are re-wrapped
+pub async fn rekey_function_code(
+    db: &PgPool,
+    envelope: &EnvelopeService,
+    user_id: Uuid,
+) -> Result<RekeyReport, ApiError> {
+    let tenant = user_id.to_string();
+    let functions =
+        sqlx::query_as::<_, (Uuid, String)>("SELECT id, code FROM functions WHERE user_id = $1")
+            .bind(user_id)
+            .fetch_all(db)
+            .await
+            .map_err(|e| ApiError::Database(format!("Failed to list functions: {}", e)))?;
+
+    let mut report = RekeyReport::default();
+    for (id, code) in functions {
+        let sealed = if Envelope::is_encoded(&code) {
+            let mut sealed = Envelope::decode(&code)?;
+            match envelope.rekey(&tenant, &sealed.key).await? {
+                Some(key) => sealed.key = key,
+                None => {
+                    report.unchanged += 1;
+                    continue;
+                }
+            }
+            report.rekeyed += 1;
+            sealed
+        } else {
+            report.sealed += 1;
+            envelope
+                .seal(&tenant, code.as_bytes())
+                .await?
+                .ok_or_else(|| ApiError::Validation(format!("User {} is not enrolled", user_id)))?
+        };
+
+        sqlx::query("UPDATE functions SET code = $1, code_key_id = $2 WHERE id = $3")
+            .bind(sealed.encode())
+            .bind(&sealed.key.kek_id)
+            .bind(id)
+            .execute(db)
+            .await
+            .map_err(|e| ApiError::Database(format!("Failed to re-key function {}: {}", id, e)))?;
+    }
+
+    log::info!(
+        "Re-keyed the code of user {}: {} sealed, {} re-keyed, {} unchanged",
+        user_id,
+        report.sealed,
+        report.rekeyed,
+        report.unchanged
+    );
+    Ok(report)
+}
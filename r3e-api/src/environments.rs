@@ -0,0 +1,545 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+//! Environments functions are promoted through.
+//!
+//! Every user has an ordered chain of environments, `dev`, `staging` and `prod` unless they
+//! set up their own. Each environment holds the configuration and secret bindings of the
+//! functions running in it. Releasing a function to an environment records its current code as
+//! a numbered version; promoting copies the version released to one environment to a later
+//! one, so what runs in `prod` is exactly what was tested in `staging`. Invocations name the
+//! environment they run in and get its version, configuration and secrets.
+
+use std::collections::HashMap;
+
+use chrono::Utc;
+use sqlx::{FromRow, PgPool};
+use uuid::Uuid;
+
+use crate::error::ApiError;
+use crate::models::environment::{
+    CreateEnvironmentRequest, Environment, FunctionRelease, UpdateEnvironmentRequest,
+};
+
+/// Environments every user starts with, in promotion order
+pub const DEFAULT_ENVIRONMENTS: [&str; 3] = ["dev", "staging", "prod"];
+
+/// Most environments a user may have
+const MAX_ENVIRONMENTS: i64 = 16;
+
+/// Released version of a function, with its code as stored
+#[derive(Debug, Clone, FromRow)]
+pub struct FunctionVersion {
+    /// Version number, from 1
+    pub version: i32,
+
+    /// Code, sealed if the owner is enrolled for encryption at rest
+    pub code: String,
+
+    /// Identifier of the tenant KEK the code is sealed with
+    pub code_key_id: Option<String>,
+
+    /// Code hash
+    pub hash: String,
+
+    /// JSON Schema the invocation input must conform to
+    pub input_schema: Option<serde_json::Value>,
+
+    /// JSON Schema the function output must conform to
+    pub output_schema: Option<serde_json::Value>,
+}
+
+/// Environments of users and the versions of functions released to them
+#[derive(Clone)]
+pub struct EnvironmentStore {
+    /// Database pool
+    db: PgPool,
+}
+
+impl EnvironmentStore {
+    /// Create a new environment store
+    pub fn new(db: PgPool) -> Self {
+        Self { db }
+    }
+
+    /// Environments of a user in promotion order, creating the default ones for a new user
+    pub async fn list(&self, user_id: Uuid) -> Result<Vec<Environment>, ApiError> {
+        self.ensure_defaults(user_id).await?;
+
+        sqlx::query_as::<_, Environment>(
+            "SELECT * FROM environments WHERE user_id = $1 ORDER BY position, name",
+        )
+        .bind(user_id)
+        .fetch_all(&self.db)
+        .await
+        .map_err(|e| ApiError::Database(format!("Failed to list environments: {}", e)))
+    }
+
+    /// Get an environment of a user by name
+    pub async fn get(&self, user_id: Uuid, name: &str) -> Result<Environment, ApiError> {
+        self.ensure_defaults(user_id).await?;
+
+        sqlx::query_as::<_, Environment>(
+            "SELECT * FROM environments WHERE user_id = $1 AND name = $2",
+        )
+        .bind(user_id)
+        .bind(name)
+        .fetch_optional(&self.db)
+        .await
+        .map_err(|e| ApiError::Database(format!("Failed to get environment: {}", e)))?
+        .ok_or_else(|| ApiError::NotFound(format!("Environment not found: {}", name)))
+    }
+
+    /// Create an environment, after the last one unless a position is given
+    pub async fn create(
+        &self,
+        user_id: Uuid,
+        request: &CreateEnvironmentRequest,
+    ) -> Result<Environment, ApiError> {
+        check_name(&request.name)?;
+        check_bindings(&request.config, &request.secrets)?;
+        let environments = self.list(user_id).await?;
+        if environments.len() as i64 >= MAX_ENVIRONMENTS {
+            return Err(ApiError::QuotaExceeded(format!(
+                "At most {} environments can be created",
+                MAX_ENVIRONMENTS
+            )));
+        }
+        let position = request.position.unwrap_or_else(|| {
+            environments
+                .last()
+                .map_or(0, |environment| environment.position + 1)
+        });
+
+        let now = Utc::now();
+        sqlx::query_as::<_, Environment>(
+            "INSERT INTO environments \
+             (id, user_id, name, position, config, secrets, created_at, updated_at) \
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $7) RETURNING *",
+        )
+        .bind(Uuid::new_v4())
+        .bind(user_id)
+        .bind(&request.name)
+        .bind(position)
+        .bind(sqlx::types::Json(&request.config))
+        .bind(sqlx::types::Json(&request.secrets))
+        .bind(now)
+        .fetch_one(&self.db)
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::Database(db) if db.is_unique_violation() => {
+                ApiError::Conflict(format!("Environment {} already exists", request.name))
+            }
+            e => ApiError::Database(format!("Failed to create environment: {}", e)),
+        })
+    }
+
+    /// Update the position, configuration or secret bindings of an environment
+    pub async fn update(
+        &self,
+        user_id: Uuid,
+        name: &str,
+        request: &UpdateEnvironmentRequest,
+    ) -> Result<Environment, ApiError> {
+        let environment = self.get(user_id, name).await?;
+        let config = request.config.as_ref().unwrap_or(&environment.config);
+        let secrets = request.secrets.as_ref().unwrap_or(&environment.secrets);
+        check_bindings(config, secrets)?;
+
+        sqlx::query_as::<_, Environment>(
+            "UPDATE environments SET position = $2, config = $3, secrets = $4, updated_at = $5 \
+             WHERE id = $1 RETURNING *",
+        )
+        .bind(environment.id)
+        .bind(request.position.unwrap_or(environment.position))
+        .bind(sqlx::types::Json(config))
+        .bind(sqlx::types::Json(secrets))
+        .bind(Utc::now())
+        .fetch_one(&self.db)
+        .await
+        .map_err(|e| ApiError::Database(format!("Failed to update environment: {}", e)))
+    }
+
+    /// Delete an environment, with the releases of functions to it
+    pub async fn delete(&self, user_id: Uuid, name: &str) -> Result<(), ApiError> {
+        let deleted = sqlx::query("DELETE FROM environments WHERE user_id = $1 AND name = $2")
+            .bind(user_id)
+            .bind(name)
+            .execute(&self.db)
+            .await
+            .map_err(|e| ApiError::Database(format!("Failed to delete environment: {}", e)))?;
+        if deleted.rows_affected() == 0 {
+            return Err(ApiError::NotFound(format!(
+                "Environment not found: {}",
+                name
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// Versions of a function released to each environment, in promotion order
+    pub async fn releases(&self, function_id: Uuid) -> Result<Vec<FunctionRelease>, ApiError> {
+        sqlx::query_as::<_, FunctionRelease>(
+            "SELECT r.function_id, e.name AS environment, r.version, v.hash, r.promoted_from, \
+             r.released_by, r.released_at \
+             FROM function_releases r \
+             JOIN environments e ON e.id = r.environment_id \
+             JOIN function_versions v ON v.function_id = r.function_id AND v.version = r.version \
+             WHERE r.function_id = $1 ORDER BY e.position, e.name",
+        )
+        .bind(function_id)
+        .fetch_all(&self.db)
+        .await
+        .map_err(|e| ApiError::Database(format!("Failed to list releases: {}", e)))
+    }
+
+    /// Release the current code of a function to an environment of its owner.
+    ///
+    /// The code is recorded as a new version unless it is the latest version already.
+    pub async fn release(
+        &self,
+        owner: Uuid,
+        function_id: Uuid,
+        environment: &str,
+        released_by: Uuid,
+    ) -> Result<FunctionRelease, ApiError> {
+        let environment = self.get(owner, environment).await?;
+
+        let mut tx = self
+            .db
+            .begin()
+            .await
+            .map_err(|e| ApiError::Database(format!("Failed to start transaction: {}", e)))?;
+
+        // Lock the function so concurrent releases agree on the version numbers
+        let current: FunctionVersion = sqlx::query_as(
+            "SELECT 0 AS version, code, code_key_id, hash, input_schema, output_schema \
+             FROM functions WHERE id = $1 FOR UPDATE",
+        )
+        .bind(function_id)
+        .fetch_optional(&mut *tx)
+        .await
+        .map_err(|e| ApiError::Database(format!("Failed to get function: {}", e)))?
+        .ok_or_else(|| ApiError::NotFound(format!("Function not found: {}", function_id)))?;
+        let latest: Option<FunctionVersion> = sqlx::query_as(
+            "SELECT version, code, code_key_id, hash, input_schema, output_schema \
+             FROM function_versions WHERE function_id = $1 ORDER BY version DESC LIMIT 1",
+        )
+        .bind(function_id)
+        .fetch_optional(&mut *tx)
+        .await
+        .map_err(|e| ApiError::Database(format!("Failed to get function version: {}", e)))?;
+
+        let version = match latest {
+            Some(latest) if is_same_version(&latest, &current) => latest.version,
+            latest => {
+                let version = latest.map_or(1, |latest| latest.version + 1);
+                sqlx::query(
+                    "INSERT INTO function_versions \
+                     (function_id, version, code, code_key_id, hash, input_schema, output_schema, created_at) \
+                     VALUES ($1, $2, $3, $4, $5, $6, $7, $8)",
+                )
+                .bind(function_id)
+                .bind(version)
+                .bind(&current.code)
+                .bind(&current.code_key_id)
+                .bind(&current.hash)
+                .bind(&current.input_schema)
+                .bind(&current.output_schema)
+                .bind(Utc::now())
+                .execute(&mut *tx)
+                .await
+                .map_err(|e| {
+                    ApiError::Database(format!("Failed to record function version: {}", e))
+                })?;
+                version
+            }
+        };
+
+        upsert_release(
+            &mut tx,
+            function_id,
+            &environment,
+            version,
+            None,
+            released_by,
+        )
+        .await?;
+        tx.commit()
+            .await
+            .map_err(|e| ApiError::Database(format!("Failed to release function: {}", e)))?;
+
+        Ok(FunctionRelease {
+            function_id,
+            environment: environment.name,
+            version,
+            hash: current.hash,
+            promoted_from: None,
+            released_by,
+            released_at: Utc::now(),
+        })
+    }
+
+    /// Promote the version of a function released to `from` to the later environment `to`.
+    ///
+    /// If `version` is given it must be the version released to `from`, so a version
+    /// released after the caller tested it is not promoted by mistake.
+    pub async fn promote(
+        &self,
+        owner: Uuid,
+        function_id: Uuid,
+        from: &str,
+        to: &str,
+        version: Option<i32>,
+        released_by: Uuid,
+    ) -> Result<FunctionRelease, ApiError> {
+        let source = self.get(owner, from).await?;
+        let target = self.get(owner, to).await?;
+        if target.position <= source.position {
+            return Err(ApiError::Validation(format!(
+                "Versions are promoted to later environments, {} does not come after {}",
+                to, from
+            )));
+        }
+
+        let mut tx = self
+            .db
+            .begin()
+            .await
+            .map_err(|e| ApiError::Database(format!("Failed to start transaction: {}", e)))?;
+        let (released, hash): (i32, String) = sqlx::query_as(
+            "SELECT r.version, v.hash FROM function_releases r \
+             JOIN function_versions v ON v.function_id = r.function_id AND v.version = r.version \
+             WHERE r.function_id = $1 AND r.environment_id = $2 FOR UPDATE OF r",
+        )
+        .bind(function_id)
+        .bind(source.id)
+        .fetch_optional(&mut *tx)
+        .await
+        .map_err(|e| ApiError::Database(format!("Failed to get release: {}", e)))?
+        .ok_or_else(|| {
+            ApiError::NotFound(format!(
+                "Function {} has no version released to {}",
+                function_id, from
+            ))
+        })?;
+        if let Some(version) = version.filter(|version| *version != released) {
+            return Err(ApiError::Conflict(format!(
+                "Version {} is released to {}, not version {}",
+                released, from, version
+            )));
+        }
+
+        upsert_release(
+            &mut tx,
+            function_id,
+            &target,
+            released,
+            Some(&source.name),
+            released_by,
+        )
+        .await?;
+        tx.commit()
+            .await
+            .map_err(|e| ApiError::Database(format!("Failed to promote function: {}", e)))?;
+
+        Ok(FunctionRelease {
+            function_id,
+            environment: target.name,
+            version: released,
+            hash,
+            promoted_from: Some(source.name),
+            released_by,
+            released_at: Utc::now(),
+        })
+    }
+
+    /// Environment of the owner of a function and the version released to it, what an
+    /// invocation in that environment runs
+    pub async fn resolve(
+        &self,
+        owner: Uuid,
+        function_id: Uuid,
+        environment: &str,
+    ) -> Result<(Environment, FunctionVersion), ApiError> {
+        let environment = self.get(owner, environment).await?;
+        let version = sqlx::query_as::<_, FunctionVersion>(
+            "SELECT v.version, v.code, v.code_key_id, v.hash, v.input_schema, v.output_schema \
+             FROM function_releases r \
+             JOIN function_versions v ON v.function_id = r.function_id AND v.version = r.version \
+             WHERE r.function_id = $1 AND r.environment_id = $2",
+        )
+        .bind(function_id)
+        .bind(environment.id)
+        .fetch_optional(&self.db)
+        .await
+        .map_err(|e| ApiError::Database(format!("Failed to get release: {}", e)))?
+        .ok_or_else(|| {
+            ApiError::NotFound(format!(
+                "Function {} has no version released to {}",
+                function_id, environment.name
+            ))
+        })?;
+
+        Ok((environment, version))
+    }
+
+    /// Released version of a function by number
+    pub async fn version(
+        &self,
+        function_id: Uuid,
+        version: i32,
+    ) -> Result<FunctionVersion, ApiError> {
+        sqlx::query_as::<_, FunctionVersion>(
+            "SELECT version, code, code_key_id, hash, input_schema, output_schema \
+             FROM function_versions WHERE function_id = $1 AND version = $2",
+        )
+        .bind(function_id)
+        .bind(version)
+        .fetch_optional(&self.db)
+        .await
+        .map_err(|e| ApiError::Database(format!("Failed to get function version: {}", e)))?
+        .ok_or_else(|| {
+            ApiError::NotFound(format!(
+                "Function {} has no version {}",
+                function_id, version
+            ))
+        })
+    }
+
+    /// Create the default environments of a user without any
+    async fn ensure_defaults(&self, user_id: Uuid) -> Result<(), ApiError> {
+        let exists: bool =
+            sqlx::query_scalar("SELECT EXISTS (SELECT 1 FROM environments WHERE user_id = $1)")
+                .bind(user_id)
+                .fetch_one(&self.db)
+                .await
+                .map_err(|e| ApiError::Database(format!("Failed to get environments: {}", e)))?;
+        if exists {
+            return Ok(());
+        }
+
+        let now = Utc::now();
+        for (position, name) in DEFAULT_ENVIRONMENTS.iter().enumerate() {
+            sqlx::query(
+                "INSERT INTO environments \
+                 (id, user_id, name, position, config, secrets, created_at, updated_at) \
+                 VALUES ($1, $2, $3, $4, '{}', '{}', $5, $5) \
+                 ON CONFLICT (user_id, name) DO NOTHING",
+            )
+            .bind(Uuid::new_v4())
+            .bind(user_id)
+            .bind(name)
+            .bind(position as i32)
+            .bind(now)
+            .execute(&self.db)
+            .await
+            .map_err(|e| ApiError::Database(format!("Failed to create environment: {}", e)))?;
+        }
+        Ok(())
+    }
+}
+
+/// Set the version of a function an environment runs
+async fn upsert_release(
+    tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    function_id: Uuid,
+    environment: &Environment,
+    version: i32,
+    promoted_from: Option<&str>,
+    released_by: Uuid,
+) -> Result<(), ApiError> {
+    sqlx::query(
+        "INSERT INTO function_releases \
+         (function_id, environment_id, version, promoted_from, released_by, released_at) \
+         VALUES ($1, $2, $3, $4, $5, $6) \
+         ON CONFLICT (function_id, environment_id) DO UPDATE \
+         SET version = $3, promoted_from = $4, released_by = $5, released_at = $6",
+    )
+    .bind(function_id)
+    .bind(environment.id)
+    .bind(version)
+    .bind(promoted_from)
+    .bind(released_by)
+    .bind(Utc::now())
+    .execute(&mut **tx)
+    .await
+    .map_err(|e| ApiError::Database(format!("Failed to store release: {}", e)))?;
+    Ok(())
+}
+
+/// Whether the current code and schemas of a function are those of a version
+fn is_same_version(version: &FunctionVersion, current: &FunctionVersion) -> bool {
+    version.hash == current.hash
+        && version.input_schema == current.input_schema
+        && version.output_schema == current.output_schema
+}
+
+/// Environment names are lowercase letters, digits and dashes, starting with a letter
+pub fn check_name(name: &str) -> Result<(), ApiError> {
+    let valid = name.len() <= 32
+        && name.starts_with(|c: char| c.is_ascii_lowercase())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-');
+    if !valid {
+        return Err(ApiError::Validation(format!(
+            "Invalid environment name: {}",
+            name
+        )));
+    }
+    Ok(())
+}
+
+/// Configuration keys and secret bindings are environment variable names
+fn check_bindings(
+    config: &HashMap<String, String>,
+    secrets: &HashMap<String, String>,
+) -> Result<(), ApiError> {
+    for key in config.keys().chain(secrets.keys()) {
+        let valid = key.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+            && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+        if !valid {
+            return Err(ApiError::Validation(format!(
+                "Invalid configuration key: {}",
+                key
+            )));
+        }
+    }
+    if let Some(key) = config.keys().find(|key| secrets.contains_key(*key)) {
+        return Err(ApiError::Validation(format!(
+            "{} is both configured and bound to a secret",
+            key
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_name() {
+        for name in DEFAULT_ENVIRONMENTS {
+            assert!(check_name(name).is_ok());
+        }
+        assert!(check_name("qa-2").is_ok());
+        assert!(check_name("Prod").is_err());
+        assert!(check_name("2prod").is_err());
+        assert!(check_name("").is_err());
+        assert!(check_name(&"a".repeat(33)).is_err());
+    }
+
+    #[test]
+    fn test_check_bindings() {
+        let config = HashMap::from([("API_URL".to_string(), "https://dev".to_string())]);
+        let secrets = HashMap::from([("API_KEY".to_string(), "dev-api-key".to_string())]);
+        assert!(check_bindings(&config, &secrets).is_ok());
+
+        let invalid = HashMap::from([("api-url".to_string(), String::new())]);
+        assert!(check_bindings(&invalid, &secrets).is_err());
+        assert!(check_bindings(&config, &config).is_err());
+    }
+}
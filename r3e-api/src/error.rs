@@ -6,7 +6,7 @@ use axum::{
     response::{IntoResponse, Response},
     Json,
 };
-use serde::{Deserialize, Serialize};
+use r3e_core::{CodedError, ErrorBody, ErrorCode};
 use thiserror::Error;
 
 /// API error types
@@ -24,6 +24,12 @@ pub enum ApiError {
     #[error("not found: {0}")]
     NotFound(String),
 
+    #[error("conflict: {0}")]
+    Conflict(String),
+
+    #[error("precondition failed: {0}")]
+    PreconditionFailed(String),
+
     #[error("database error: {0}")]
     Database(String),
 
@@ -35,33 +41,278 @@ pub enum ApiError {
 
     #[error("external service error: {0}")]
     ExternalService(String),
+
+    #[error("quota exceeded: {0}")]
+    QuotaExceeded(String),
+
+    #[error("payment required: {0}")]
+    PaymentRequired(String),
+
+    /// Error carrying its own code, e.g. a function failure reported by a worker
+    #[error("{}", .0.message)]
+    Coded(ErrorBody),
 }
 
-/// API error response
-#[derive(Debug, Serialize, Deserialize)]
-pub struct ErrorResponse {
-    pub status: String,
-    pub message: String,
+/// API error response, `{code, message, details}`
+pub type ErrorResponse = ErrorBody;
+
+impl CodedError for ApiError {
+    fn code(&self) -> ErrorCode {
+        match self {
+            ApiError::Authentication(_) => ErrorCode::Unauthenticated,
+            ApiError::Authorization(_) => ErrorCode::Forbidden,
+            ApiError::Validation(_) => ErrorCode::Validation,
+            ApiError::NotFound(_) => ErrorCode::NotFound,
+            ApiError::Conflict(_) => ErrorCode::Conflict,
+            ApiError::PreconditionFailed(_) => ErrorCode::PreconditionFailed,
+            ApiError::Database(_) => ErrorCode::Storage,
+            ApiError::Service(_) | ApiError::Server(_) => ErrorCode::Internal,
+            ApiError::ExternalService(_) => ErrorCode::Upstream,
+            ApiError::QuotaExceeded(_) => ErrorCode::QuotaExceeded,
+            ApiError::PaymentRequired(_) => ErrorCode::PaymentRequired,
+            ApiError::Coded(body) => body.code,
+        }
+    }
+
+    fn to_body(&self) -> ErrorBody {
+        match self {
+            ApiError::Coded(body) => body.clone(),
+            ApiError::Authentication(message)
+            | ApiError::Authorization(message)
+            | ApiError::Validation(message)
+            | ApiError::NotFound(message)
+            | ApiError::Conflict(message)
+            | ApiError::PreconditionFailed(message)
+            | ApiError::Database(message)
+            | ApiError::Service(message)
+            | ApiError::Server(message)
+            | ApiError::ExternalService(message)
+            | ApiError::QuotaExceeded(message)
+            | ApiError::PaymentRequired(message) => ErrorBody::new(self.code(), message.clone()),
+        }
+    }
 }
 
 impl IntoResponse for ApiError {
     fn into_response(self) -> Response {
-        let (status, error_message) = match self {
-            ApiError::Authentication(message) => (StatusCode::UNAUTHORIZED, message),
-            ApiError::Authorization(message) => (StatusCode::FORBIDDEN, message),
-            ApiError::Validation(message) => (StatusCode::BAD_REQUEST, message),
-            ApiError::NotFound(message) => (StatusCode::NOT_FOUND, message),
-            ApiError::Database(message) => (StatusCode::INTERNAL_SERVER_ERROR, message),
-            ApiError::Service(message) => (StatusCode::INTERNAL_SERVER_ERROR, message),
-            ApiError::Server(message) => (StatusCode::INTERNAL_SERVER_ERROR, message),
-            ApiError::ExternalService(message) => (StatusCode::BAD_GATEWAY, message),
-        };
-
-        let body = Json(ErrorResponse {
-            status: status.to_string(),
-            message: error_message,
-        });
-
-        (status, body).into_response()
+        let body = self.to_body();
+        let status = StatusCode::from_u16(body.code.http_status())
+            .unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+
+        (status, Json(body)).into_response()
+    }
+}
+
+impl From<ErrorBody> for ApiError {
+    fn from(body: ErrorBody) -> Self {
+        ApiError::Coded(body)
+    }
+}
+
+impl From<r3e_core::quota::QuotaError> for ApiError {
+    fn from(err: r3e_core::quota::QuotaError) -> Self {
+        match err {
+            r3e_core::quota::QuotaError::Exceeded { .. } => {
+                ApiError::QuotaExceeded(err.to_string())
+            }
+            r3e_core::quota::QuotaError::Unavailable(_) => ApiError::Coded(err.to_body()),
+        }
+    }
+}
+
+impl From<r3e_secrets::approval::ApprovalError> for ApiError {
+    fn from(err: r3e_secrets::approval::ApprovalError) -> Self {
+        use r3e_secrets::approval::ApprovalError;
+
+        match err {
+            ApprovalError::NotFound(msg) => ApiError::NotFound(msg),
+            ApprovalError::UnknownKind(_) | ApprovalError::InvalidParams(_) => {
+                ApiError::Validation(err.to_string())
+            }
+            ApprovalError::InvalidState(..) | ApprovalError::Expired(_) => {
+                ApiError::Conflict(err.to_string())
+            }
+            ApprovalError::InvalidSignature(_) => ApiError::Authorization(err.to_string()),
+            ApprovalError::InvalidKey(_) | ApprovalError::Storage(_) | ApprovalError::Audit(_) => {
+                ApiError::Service(err.to_string())
+            }
+        }
+    }
+}
+
+impl From<r3e_secrets::envelope::EnvelopeError> for ApiError {
+    fn from(err: r3e_secrets::envelope::EnvelopeError) -> Self {
+        use r3e_secrets::envelope::EnvelopeError;
+
+        match err {
+            EnvelopeError::NotEnrolled(_) | EnvelopeError::UnknownProvider(_) => {
+                ApiError::Validation(err.to_string())
+            }
+            EnvelopeError::Malformed(_)
+            | EnvelopeError::Crypto(_)
+            | EnvelopeError::Provider(_)
+            | EnvelopeError::Storage(_) => ApiError::Service(err.to_string()),
+        }
+    }
+}
+
+impl From<r3e_deno::sandbox::GrantError> for ApiError {
+    fn from(err: r3e_deno::sandbox::GrantError) -> Self {
+        use r3e_deno::sandbox::GrantError;
+
+        match err {
+            GrantError::NotFound(msg) => ApiError::NotFound(msg),
+            GrantError::Invalid(msg) => ApiError::Validation(msg),
+            GrantError::Storage(_) => ApiError::Service(err.to_string()),
+        }
+    }
+}
+
+impl From<r3e_deno::sandbox::PolicyError> for ApiError {
+    fn from(err: r3e_deno::sandbox::PolicyError) -> Self {
+        use r3e_deno::sandbox::PolicyError;
+
+        match err {
+            PolicyError::NotFound(msg) => ApiError::NotFound(msg),
+            PolicyError::Invalid(msg) => ApiError::Validation(msg),
+            PolicyError::Storage(_) => ApiError::Service(err.to_string()),
+        }
+    }
+}
+
+impl From<r3e_store::AnalyticsError> for ApiError {
+    fn from(err: r3e_store::AnalyticsError) -> Self {
+        use r3e_store::AnalyticsError;
+
+        match err {
+            AnalyticsError::InvalidQuery(msg) => ApiError::Validation(msg),
+            AnalyticsError::Storage(_) => ApiError::Service(err.to_string()),
+        }
+    }
+}
+
+impl From<r3e_store::LogError> for ApiError {
+    fn from(err: r3e_store::LogError) -> Self {
+        use r3e_store::LogError;
+
+        match err {
+            LogError::Invalid(msg) => ApiError::Validation(msg),
+            LogError::Storage(_) => ApiError::Service(err.to_string()),
+        }
+    }
+}
+
+impl From<r3e_store::EventError> for ApiError {
+    fn from(err: r3e_store::EventError) -> Self {
+        use r3e_store::EventError;
+
+        match err {
+            EventError::Invalid(msg) => ApiError::Validation(msg),
+            EventError::Storage(_) => ApiError::Service(err.to_string()),
+        }
+    }
+}
+
+impl From<r3e_store::BlobError> for ApiError {
+    fn from(err: r3e_store::BlobError) -> Self {
+        use r3e_store::BlobError;
+
+        match err {
+            BlobError::NotFound(msg) => ApiError::NotFound(msg),
+            BlobError::TooLarge { .. } => ApiError::Validation(err.to_string()),
+            BlobError::DigestMismatch { .. } | BlobError::Backend(_) => {
+                ApiError::Service(err.to_string())
+            }
+        }
+    }
+}
+
+impl From<r3e_built_in_services::workflow::WorkflowError> for ApiError {
+    fn from(err: r3e_built_in_services::workflow::WorkflowError) -> Self {
+        use r3e_built_in_services::workflow::WorkflowError;
+
+        match err {
+            WorkflowError::NotFound(msg) => ApiError::NotFound(msg),
+            WorkflowError::InvalidInput(msg) | WorkflowError::InvalidDefinition(msg) => {
+                ApiError::Validation(msg)
+            }
+            WorkflowError::Storage(_) | WorkflowError::Step(_) => {
+                ApiError::Service(err.to_string())
+            }
+        }
+    }
+}
+
+impl From<r3e_built_in_services::pricing::PricingError> for ApiError {
+    fn from(err: r3e_built_in_services::pricing::PricingError) -> Self {
+        use r3e_built_in_services::pricing::PricingError;
+
+        match err {
+            PricingError::NotFound(msg) => ApiError::NotFound(msg),
+            PricingError::InvalidInput(msg) | PricingError::CostLimitExceeded(msg) => {
+                ApiError::Validation(msg)
+            }
+            PricingError::Unauthorized(msg) => ApiError::Authorization(msg),
+            PricingError::InsufficientFunds(msg) => ApiError::PaymentRequired(msg),
+            PricingError::PlanLimitExceeded(msg) => ApiError::QuotaExceeded(msg),
+            PricingError::RateLimited(msg) => {
+                ApiError::Coded(ErrorBody::new(ErrorCode::RateLimited, msg))
+            }
+            PricingError::Storage(_) => ApiError::Service(err.to_string()),
+        }
+    }
+}
+
+impl From<r3e_built_in_services::notifications::NotificationError> for ApiError {
+    fn from(err: r3e_built_in_services::notifications::NotificationError) -> Self {
+        use r3e_built_in_services::notifications::NotificationError;
+
+        match err {
+            NotificationError::NotFound(msg) => ApiError::NotFound(msg),
+            NotificationError::InvalidInput(msg) => ApiError::Validation(msg),
+            NotificationError::Storage(_) | NotificationError::Delivery(_) => {
+                ApiError::Service(err.to_string())
+            }
+        }
+    }
+}
+
+impl From<r3e_built_in_services::alert_rules::AlertRuleError> for ApiError {
+    fn from(err: r3e_built_in_services::alert_rules::AlertRuleError) -> Self {
+        use r3e_built_in_services::alert_rules::AlertRuleError;
+
+        match err {
+            AlertRuleError::NotFound(msg) => ApiError::NotFound(msg),
+            AlertRuleError::InvalidInput(msg) => ApiError::Validation(msg),
+            AlertRuleError::Storage(_) | AlertRuleError::Analytics(_) => {
+                ApiError::Service(err.to_string())
+            }
+        }
+    }
+}
+
+impl From<r3e_built_in_services::billing::BillingError> for ApiError {
+    fn from(err: r3e_built_in_services::billing::BillingError) -> Self {
+        use r3e_built_in_services::billing::BillingError;
+
+        match err {
+            BillingError::NotFound(msg) => ApiError::NotFound(msg),
+            BillingError::InvalidInput(msg) => ApiError::Validation(msg),
+            BillingError::Suspended(_) => ApiError::PaymentRequired(err.to_string()),
+            BillingError::Storage(_) | BillingError::Pricing(_) => {
+                ApiError::Service(err.to_string())
+            }
+        }
+    }
+}
+
+impl From<r3e_built_in_services::balance::AdmissionError> for ApiError {
+    fn from(err: r3e_built_in_services::balance::AdmissionError) -> Self {
+        use r3e_built_in_services::balance::AdmissionError;
+
+        match err {
+            AdmissionError::PaymentRequired { .. } => ApiError::PaymentRequired(err.to_string()),
+            AdmissionError::Balance(_) => ApiError::Service(err.to_string()),
+        }
     }
 }
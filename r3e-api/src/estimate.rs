@@ -0,0 +1,285 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+
+use r3e_built_in_services::pricing::{
+    CostQuote, ExecutionMetrics, PricingError, PricingServiceTrait, QuoteRequest, ResourceLimits,
+};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::error::ApiError;
+use crate::models::function::{Function, SecurityLevel};
+
+/// Neo N3 PolicyContract script hash
+const POLICY_CONTRACT: &str = "0xcc5e4edd9f5f8dba8bb65734541df7a1c081c67b";
+
+/// ExecFeeFactor of Neo N3 mainnet, used when the RPC node cannot be reached
+const DEFAULT_EXEC_FEE_FACTOR: u64 = 30;
+
+/// GAS per datoshi
+const GAS_PER_DATOSHI: f64 = 1e-8;
+
+/// How long a fetched chain gas price is reused
+const GAS_PRICE_TTL: Duration = Duration::from_secs(60);
+
+/// Number of recent invocations the execution metrics are averaged over
+const METRICS_WINDOW: i64 = 100;
+
+/// Invocation cost estimator
+pub struct CostEstimator {
+    /// Database pool
+    db: PgPool,
+
+    /// Pricing service
+    pricing_service: Arc<dyn PricingServiceTrait>,
+
+    /// Neo N3 RPC URL
+    neo_rpc_url: String,
+
+    /// Cached chain gas price and when it was fetched
+    gas_price: RwLock<Option<(Instant, f64)>>,
+}
+
+impl CostEstimator {
+    /// Create a new cost estimator
+    pub fn new(
+        db: PgPool,
+        pricing_service: Arc<dyn PricingServiceTrait>,
+        neo_rpc_url: String,
+    ) -> Self {
+        Self {
+            db,
+            pricing_service,
+            neo_rpc_url,
+            gas_price: RwLock::new(None),
+        }
+    }
+
+    /// Quote the cost of invoking a function on behalf of a user
+    pub async fn quote(
+        &self,
+        function: &Function,
+        user_id: Uuid,
+        max_chain_gas: Option<u64>,
+    ) -> Result<CostQuote, ApiError> {
+        let request = QuoteRequest {
+            limits: invocation_limits(function.security_level, max_chain_gas),
+            metrics: self.execution_metrics(function.id).await?,
+            chain_gas_price: self.chain_gas_price().await,
+        };
+
+        self.pricing_service
+            .estimate_invocation_cost(&user_id.to_string(), &request)
+            .await
+            .map_err(pricing_error)
+    }
+
+    /// Reject `count` invocations whose worst-case cost together can exceed `max_cost`
+    pub async fn guard(
+        &self,
+        function: &Function,
+        user_id: Uuid,
+        count: u32,
+        max_cost: f64,
+    ) -> Result<CostQuote, ApiError> {
+        let quote = self.quote(function, user_id, None).await?;
+        quote
+            .check_batch_max_cost(count, max_cost)
+            .map_err(pricing_error)?;
+        Ok(quote)
+    }
+
+    /// Execution metrics of the function's recent invocations
+    async fn execution_metrics(
+        &self,
+        function_id: Uuid,
+    ) -> Result<Option<ExecutionMetrics>, ApiError> {
+        let (invocations, avg_execution_time_ms): (i64, Option<f64>) = sqlx::query_as(
+            r#"
+            SELECT COUNT(*), AVG(execution_time_ms)::FLOAT8
+            FROM (
+                SELECT execution_time_ms FROM function_invocations
+                WHERE function_id = $1
+                ORDER BY created_at DESC
+                LIMIT $2
+            ) recent
+            "#,
+        )
+        .bind(function_id.to_string())
+        .bind(METRICS_WINDOW)
+        .fetch_one(&self.db)
+        .await
+        .map_err(|e| ApiError::Database(format!("Failed to get execution metrics: {}", e)))?;
+
+        if invocations == 0 {
+            return Ok(None);
+        }
+
+        Ok(Some(ExecutionMetrics {
+            invocations: invocations as u64,
+            avg_execution_time_ms,
+            ..ExecutionMetrics::default()
+        }))
+    }
+
+    /// Chain gas price in GAS per unit of chain gas, where a unit is one datoshi of opcode
+    /// price before the network's ExecFeeFactor is applied
+    async fn chain_gas_price(&self) -> f64 {
+        if let Some((fetched_at, price)) = *self.gas_price.read().unwrap() {
+            if fetched_at.elapsed() < GAS_PRICE_TTL {
+                return price;
+            }
+        }
+
+        let factor = match self.fetch_exec_fee_factor().await {
+            Ok(factor) => factor,
+            Err(e) => {
+                log::warn!(
+                    "Failed to fetch ExecFeeFactor, using {}: {}",
+                    DEFAULT_EXEC_FEE_FACTOR,
+                    e
+                );
+                DEFAULT_EXEC_FEE_FACTOR
+            }
+        };
+
+        let price = factor as f64 * GAS_PER_DATOSHI;
+        *self.gas_price.write().unwrap() = Some((Instant::now(), price));
+        price
+    }
+
+    async fn fetch_exec_fee_factor(&self) -> Result<u64, String> {
+        let request = serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "invokefunction",
+            "params": [POLICY_CONTRACT, "getExecFeeFactor", []],
+        });
+
+        let response: serde_json::Value = reqwest::Client::new()
+            .post(&self.neo_rpc_url)
+            .json(&request)
+            .timeout(Duration::from_secs(5))
+            .send()
+            .await
+            .map_err(|e| e.to_string())?
+            .json()
+            .await
+            .map_err(|e| e.to_string())?;
+
+        response["result"]["stack"][0]["value"]
+            .as_str()
+            .and_then(|value| value.parse().ok())
+            .ok_or_else(|| format!("unexpected response: {}", response))
+    }
+}
+
+/// Resource limits of an invocation of a function at a security level
+fn invocation_limits(security_level: SecurityLevel, max_chain_gas: Option<u64>) -> ResourceLimits {
+    ResourceLimits {
+        max_chain_gas: max_chain_gas.unwrap_or(0),
+        tee: security_level == SecurityLevel::Tee,
+        ..ResourceLimits::default()
+    }
+}
+
+fn pricing_error(e: PricingError) -> ApiError {
+    match e {
+        PricingError::CostLimitExceeded(message) => ApiError::Validation(message),
+        PricingError::InvalidInput(message) => ApiError::Validation(message),
+        e => ApiError::Service(format!("Failed to estimate invocation cost: {}", e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use r3e_built_in_services::pricing::{
+        MemoryPricingStorage, PricingService, PricingStorage, PricingTier, ResourcePricing,
+        ResourceType,
+    };
+
+    async fn pricing_service() -> PricingService<MemoryPricingStorage> {
+        let storage = MemoryPricingStorage::new();
+        storage
+            .add_resource_pricing(ResourcePricing {
+                resource_type: ResourceType::TeeUsage,
+                tier: PricingTier::Basic,
+                base_price: 0.0,
+                price_per_unit: 0.0001,
+                free_tier_limit: None,
+                min_billable_units: 0,
+                max_billable_units: None,
+                volume_discounts: Vec::new(),
+            })
+            .await
+            .unwrap();
+        PricingService::new(Arc::new(storage))
+    }
+
+    #[test]
+    fn test_invocation_limits() {
+        let limits = invocation_limits(SecurityLevel::Standard, None);
+        assert!(!limits.tee);
+        assert_eq!(limits.max_chain_gas, 0);
+
+        let limits = invocation_limits(SecurityLevel::Tee, Some(1_000));
+        assert!(limits.tee);
+        assert_eq!(limits.max_chain_gas, 1_000);
+        assert_eq!(
+            limits.max_execution_time_ms,
+            ResourceLimits::default().max_execution_time_ms
+        );
+    }
+
+    #[tokio::test]
+    async fn test_max_cost_guard() {
+        let pricing = pricing_service().await;
+        let request = QuoteRequest {
+            limits: invocation_limits(SecurityLevel::Tee, Some(1_000_000)),
+            metrics: None,
+            chain_gas_price: 1e-6,
+        };
+        let quote = pricing
+            .estimate_invocation_cost("alice", &request)
+            .await
+            .unwrap();
+        // 10s of TEE time and 1M units of chain gas, nothing else is priced
+        assert!((quote.max_cost - 2.0).abs() < 1e-9);
+
+        assert!(quote.check_max_cost(2.5).map_err(pricing_error).is_ok());
+        assert!(quote
+            .check_max_cost(quote.max_cost)
+            .map_err(pricing_error)
+            .is_ok());
+        assert!(matches!(
+            quote.check_max_cost(1.5).map_err(pricing_error),
+            Err(ApiError::Validation(message)) if message.contains("exceeds the limit")
+        ));
+
+        // A batch is limited by the worst-case cost of all of its invocations
+        assert!(quote
+            .check_batch_max_cost(2, 4.0)
+            .map_err(pricing_error)
+            .is_ok());
+        assert!(matches!(
+            quote.check_batch_max_cost(2, 3.5).map_err(pricing_error),
+            Err(ApiError::Validation(message)) if message.contains("of 2 invocations")
+        ));
+
+        let request = QuoteRequest {
+            chain_gas_price: -1.0,
+            ..request
+        };
+        assert!(matches!(
+            pricing
+                .estimate_invocation_cost("alice", &request)
+                .await
+                .map_err(pricing_error),
+            Err(ApiError::Validation(_))
+        ));
+    }
+}
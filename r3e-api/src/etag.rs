@@ -0,0 +1,56 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+use axum::http::{header, HeaderMap, HeaderValue};
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use crate::error::ApiError;
+
+/// Entity tag of a resource revision, derived from its last update time
+pub fn etag(updated_at: &DateTime<Utc>) -> String {
+    format!("\"{}\"", updated_at.timestamp_micros())
+}
+
+/// JSON response carrying the entity tag of the resource
+pub fn with_etag<T: Serialize>(updated_at: &DateTime<Utc>, body: T) -> Response {
+    let mut response = Json(body).into_response();
+    if let Ok(value) = HeaderValue::from_str(&etag(updated_at)) {
+        response.headers_mut().insert(header::ETAG, value);
+    }
+    response
+}
+
+/// Check the `If-Match` header of an update against the current revision of the resource.
+///
+/// Returns the update time the update must still find for it to apply, `None` when the
+/// request has no precondition, and fails when the client's copy is already stale.
+pub fn if_match(
+    headers: &HeaderMap,
+    updated_at: &DateTime<Utc>,
+) -> Result<Option<DateTime<Utc>>, ApiError> {
+    let Some(value) = headers.get(header::IF_MATCH) else {
+        return Ok(None);
+    };
+
+    let value = value
+        .to_str()
+        .map_err(|_| ApiError::Validation("If-Match must be ASCII".to_string()))?
+        .trim();
+    if value == "*" {
+        return Ok(None);
+    }
+
+    // Revisions are compared strongly, weak tags never match
+    let current = etag(updated_at);
+    if value.split(',').map(str::trim).any(|tag| tag == current) {
+        Ok(Some(*updated_at))
+    } else {
+        Err(ApiError::PreconditionFailed(format!(
+            "Resource was modified, its current ETag is {}",
+            current
+        )))
+    }
+}
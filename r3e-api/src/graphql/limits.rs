@@ -0,0 +1,437 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+//! Depth and complexity limits of GraphQL queries.
+//!
+//! Every query is measured once validated: its depth is the deepest selection, its complexity
+//! the number of fields selected, with list fields counting their selection once per item
+//! requested. Queries beyond the budget of the caller's role are rejected before they run.
+
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use async_graphql::extensions::{Extension, ExtensionContext, ExtensionFactory, NextValidation};
+use async_graphql::{ServerError, ValidationResult};
+use axum::async_trait;
+use r3e_core::ErrorCode;
+
+use crate::auth::Auth;
+use crate::models::user::UserRole;
+
+/// Depth and complexity a query may reach
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QueryBudget {
+    /// Deepest selection
+    pub max_depth: usize,
+
+    /// Fields selected, list fields counting once per item
+    pub max_complexity: usize,
+}
+
+/// Query budgets per role
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QueryBudgets {
+    pub admin: QueryBudget,
+    pub developer: QueryBudget,
+    pub viewer: QueryBudget,
+}
+
+impl Default for QueryBudgets {
+    fn default() -> Self {
+        Self {
+            admin: QueryBudget {
+                max_depth: 15,
+                max_complexity: 5000,
+            },
+            developer: QueryBudget {
+                max_depth: 10,
+                max_complexity: 1000,
+            },
+            viewer: QueryBudget {
+                max_depth: 8,
+                max_complexity: 250,
+            },
+        }
+    }
+}
+
+impl QueryBudgets {
+    /// Parse budgets given as `role=depth:complexity` pairs separated by commas, e.g.
+    /// `viewer=8:250,developer=10:1000`. Roles left out keep their default budget.
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let mut budgets = Self::default();
+        for entry in s
+            .split(',')
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+        {
+            let (role, budget) = entry
+                .split_once('=')
+                .ok_or_else(|| format!("Invalid query budget: {}", entry))?;
+            let (depth, complexity) = budget
+                .split_once(':')
+                .ok_or_else(|| format!("Invalid query budget: {}", entry))?;
+            let budget = QueryBudget {
+                max_depth: depth
+                    .trim()
+                    .parse()
+                    .map_err(|_| format!("Invalid query depth: {}", entry))?,
+                max_complexity: complexity
+                    .trim()
+                    .parse()
+                    .map_err(|_| format!("Invalid query complexity: {}", entry))?,
+            };
+            match role.trim() {
+                "admin" => budgets.admin = budget,
+                "developer" => budgets.developer = budget,
+                "viewer" => budgets.viewer = budget,
+                role => return Err(format!("Unknown role: {}", role)),
+            }
+        }
+        Ok(budgets)
+    }
+
+    /// Budget of a role
+    pub fn of(&self, role: UserRole) -> QueryBudget {
+        match role {
+            UserRole::Admin => self.admin,
+            UserRole::Developer => self.developer,
+            UserRole::Viewer => self.viewer,
+        }
+    }
+}
+
+/// Roles, in the order of the metrics arrays
+const ROLES: [UserRole; 3] = [UserRole::Admin, UserRole::Developer, UserRole::Viewer];
+
+fn role_index(role: UserRole) -> usize {
+    match role {
+        UserRole::Admin => 0,
+        UserRole::Developer => 1,
+        UserRole::Viewer => 2,
+    }
+}
+
+fn role_label(role: UserRole) -> &'static str {
+    match role {
+        UserRole::Admin => "admin",
+        UserRole::Developer => "developer",
+        UserRole::Viewer => "viewer",
+    }
+}
+
+/// Why a query was rejected
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QueryRejection {
+    /// Nested too deep
+    Depth,
+
+    /// Too many fields
+    Complexity,
+}
+
+impl QueryRejection {
+    fn label(&self) -> &'static str {
+        match self {
+            QueryRejection::Depth => "depth",
+            QueryRejection::Complexity => "complexity",
+        }
+    }
+
+    fn index(&self) -> usize {
+        *self as usize
+    }
+}
+
+/// Counters of measured queries, by role
+#[derive(Debug, Default)]
+struct QueryMetrics {
+    measured: [AtomicU64; 3],
+    rejected: [[AtomicU64; 2]; 3],
+    expensive: [AtomicU64; 3],
+    complexity_sum: [AtomicU64; 3],
+}
+
+/// Limits enforced on GraphQL queries, installed on the schema as an extension
+#[derive(Debug, Clone)]
+pub struct QueryLimits {
+    budgets: QueryBudgets,
+    expensive_complexity: usize,
+    metrics: Arc<QueryMetrics>,
+}
+
+impl QueryLimits {
+    /// Enforce the budgets, counting queries at or above `expensive_complexity` as expensive
+    pub fn new(budgets: QueryBudgets, expensive_complexity: usize) -> Self {
+        Self {
+            budgets,
+            expensive_complexity,
+            metrics: Arc::new(QueryMetrics::default()),
+        }
+    }
+
+    /// Check a measured query against the budget of a role, recording it in the metrics
+    pub fn check(
+        &self,
+        role: UserRole,
+        depth: usize,
+        complexity: usize,
+    ) -> Result<(), QueryRejection> {
+        let index = role_index(role);
+        let metrics = &self.metrics;
+        metrics.measured[index].fetch_add(1, Ordering::Relaxed);
+        metrics.complexity_sum[index].fetch_add(complexity as u64, Ordering::Relaxed);
+
+        let budget = self.budgets.of(role);
+        let rejection = if depth > budget.max_depth {
+            Some(QueryRejection::Depth)
+        } else if complexity > budget.max_complexity {
+            Some(QueryRejection::Complexity)
+        } else {
+            None
+        };
+
+        if complexity >= self.expensive_complexity {
+            metrics.expensive[index].fetch_add(1, Ordering::Relaxed);
+            tracing::warn!(
+                "Expensive GraphQL query from {}: depth {}, complexity {}{}",
+                role_label(role),
+                depth,
+                complexity,
+                if rejection.is_some() {
+                    ", rejected"
+                } else {
+                    ""
+                }
+            );
+        }
+
+        match rejection {
+            Some(rejection) => {
+                metrics.rejected[index][rejection.index()].fetch_add(1, Ordering::Relaxed);
+                Err(rejection)
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Metrics in the Prometheus text format
+    pub fn render_metrics(&self) -> String {
+        let mut out = String::new();
+        let metrics = &self.metrics;
+
+        let _ = writeln!(
+            out,
+            "# HELP r3e_api_graphql_queries_total GraphQL queries measured, by role"
+        );
+        let _ = writeln!(out, "# TYPE r3e_api_graphql_queries_total counter");
+        for role in ROLES {
+            let _ = writeln!(
+                out,
+                "r3e_api_graphql_queries_total{{role=\"{}\"}} {}",
+                role_label(role),
+                metrics.measured[role_index(role)].load(Ordering::Relaxed)
+            );
+        }
+
+        let _ = writeln!(
+            out,
+            "# HELP r3e_api_graphql_queries_rejected_total GraphQL queries over budget, by role and reason"
+        );
+        let _ = writeln!(out, "# TYPE r3e_api_graphql_queries_rejected_total counter");
+        for role in ROLES {
+            for rejection in [QueryRejection::Depth, QueryRejection::Complexity] {
+                let _ = writeln!(
+                    out,
+                    "r3e_api_graphql_queries_rejected_total{{role=\"{}\",reason=\"{}\"}} {}",
+                    role_label(role),
+                    rejection.label(),
+                    metrics.rejected[role_index(role)][rejection.index()].load(Ordering::Relaxed)
+                );
+            }
+        }
+
+        let _ = writeln!(
+            out,
+            "# HELP r3e_api_graphql_queries_expensive_total GraphQL queries at or above the expensive complexity, by role"
+        );
+        let _ = writeln!(
+            out,
+            "# TYPE r3e_api_graphql_queries_expensive_total counter"
+        );
+        for role in ROLES {
+            let _ = writeln!(
+                out,
+                "r3e_api_graphql_queries_expensive_total{{role=\"{}\"}} {}",
+                role_label(role),
+                metrics.expensive[role_index(role)].load(Ordering::Relaxed)
+            );
+        }
+
+        let _ = writeln!(
+            out,
+            "# HELP r3e_api_graphql_query_complexity Complexity of GraphQL queries, by role"
+        );
+        let _ = writeln!(out, "# TYPE r3e_api_graphql_query_complexity summary");
+        for role in ROLES {
+            let index = role_index(role);
+            let _ = writeln!(
+                out,
+                "r3e_api_graphql_query_complexity_sum{{role=\"{}\"}} {}",
+                role_label(role),
+                metrics.complexity_sum[index].load(Ordering::Relaxed)
+            );
+            let _ = writeln!(
+                out,
+                "r3e_api_graphql_query_complexity_count{{role=\"{}\"}} {}",
+                role_label(role),
+                metrics.measured[index].load(Ordering::Relaxed)
+            );
+        }
+
+        out
+    }
+}
+
+impl ExtensionFactory for QueryLimits {
+    fn create(&self) -> Arc<dyn Extension> {
+        Arc::new(QueryLimitExtension {
+            limits: self.clone(),
+        })
+    }
+}
+
+struct QueryLimitExtension {
+    limits: QueryLimits,
+}
+
+#[async_trait]
+impl Extension for QueryLimitExtension {
+    async fn validation(
+        &self,
+        ctx: &ExtensionContext<'_>,
+        next: NextValidation<'_>,
+    ) -> Result<ValidationResult, Vec<ServerError>> {
+        let result = next.run(ctx).await?;
+
+        // Requests reach the schema authenticated, the smallest budget is only a fallback
+        let role = ctx
+            .data_opt::<Auth>()
+            .map_or(UserRole::Viewer, |auth| auth.user.role);
+        let budget = self.limits.budgets.of(role);
+
+        match self.limits.check(role, result.depth, result.complexity) {
+            Ok(()) => Ok(result),
+            Err(rejection) => {
+                let (value, limit) = match rejection {
+                    QueryRejection::Depth => (result.depth, budget.max_depth),
+                    QueryRejection::Complexity => (result.complexity, budget.max_complexity),
+                };
+                let mut error = ServerError::new(
+                    format!(
+                        "Query {} {} exceeds the limit of {}",
+                        rejection.label(),
+                        value,
+                        limit
+                    ),
+                    None,
+                );
+                let extensions = error.extensions.get_or_insert_with(Default::default);
+                extensions.set("code", ErrorCode::Validation.as_str());
+                extensions.set("reason", rejection.label());
+                extensions.set(rejection.label(), value as u64);
+                extensions.set("limit", limit as u64);
+                Err(vec![error])
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_graphql::{EmptyMutation, EmptySubscription, Object, Schema};
+
+    struct Node;
+
+    #[Object]
+    impl Node {
+        async fn value(&self) -> i32 {
+            1
+        }
+
+        async fn child(&self) -> Node {
+            Node
+        }
+
+        #[graphql(complexity = "(count as usize).saturating_mul(child_complexity)")]
+        async fn children(&self, count: u32) -> Vec<Node> {
+            (0..count).map(|_| Node).collect()
+        }
+    }
+
+    fn schema(limits: &QueryLimits) -> Schema<Node, EmptyMutation, EmptySubscription> {
+        Schema::build(Node, EmptyMutation, EmptySubscription)
+            .extension(limits.clone())
+            .finish()
+    }
+
+    #[test]
+    fn test_parse_budgets() {
+        let budgets = QueryBudgets::parse("viewer=4:50, admin=20:10000").unwrap();
+        assert_eq!(
+            budgets.viewer,
+            QueryBudget {
+                max_depth: 4,
+                max_complexity: 50
+            }
+        );
+        assert_eq!(budgets.admin.max_complexity, 10000);
+        assert_eq!(budgets.developer, QueryBudgets::default().developer);
+
+        assert!(QueryBudgets::parse("guest=1:1").is_err());
+        assert!(QueryBudgets::parse("viewer=4").is_err());
+        assert_eq!(QueryBudgets::parse("").unwrap(), QueryBudgets::default());
+    }
+
+    #[tokio::test]
+    async fn test_query_limits() {
+        let budgets = QueryBudgets::parse("viewer=3:20").unwrap();
+        let limits = QueryLimits::new(budgets, 10);
+        let schema = schema(&limits);
+
+        let response = schema.execute("{ child { child { value } } }").await;
+        assert!(response.errors.is_empty());
+
+        // Too deep
+        let response = schema
+            .execute("{ child { child { child { child { value } } } } }")
+            .await;
+        assert_eq!(response.errors.len(), 1);
+        let extensions = response.errors[0].extensions.as_ref().unwrap();
+        assert_eq!(
+            extensions.get("reason"),
+            Some(&async_graphql::Value::from("depth"))
+        );
+
+        // List fields count their selection once per item
+        let response = schema.execute("{ children(count: 30) { value } }").await;
+        assert_eq!(response.errors.len(), 1);
+        let extensions = response.errors[0].extensions.as_ref().unwrap();
+        assert_eq!(
+            extensions.get("code"),
+            Some(&async_graphql::Value::from("R3E-1001"))
+        );
+
+        let metrics = limits.render_metrics();
+        assert!(metrics.contains("r3e_api_graphql_queries_total{role=\"viewer\"} 3"));
+        assert!(metrics.contains(
+            "r3e_api_graphql_queries_rejected_total{role=\"viewer\",reason=\"depth\"} 1"
+        ));
+        assert!(metrics.contains(
+            "r3e_api_graphql_queries_rejected_total{role=\"viewer\",reason=\"complexity\"} 1"
+        ));
+        assert!(metrics.contains("r3e_api_graphql_queries_expensive_total{role=\"viewer\"} 1"));
+    }
+}
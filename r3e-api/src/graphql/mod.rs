@@ -1,5 +1,6 @@
 // Copyright @ 2023 - 2024, R3E Network
 // All Rights Reserved
 
+pub mod limits;
 pub mod schema;
 pub mod types;
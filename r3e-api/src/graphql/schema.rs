@@ -1,28 +1,75 @@
 // Copyright @ 2023 - 2024, R3E Network
 // All Rights Reserved
 
-use async_graphql::{Context, EmptySubscription, Object, Schema, SimpleObject};
+use async_graphql::{Context, EmptySubscription, Object, Schema};
+use chrono::{DateTime, Utc};
 use std::sync::Arc;
 use uuid::Uuid;
+use validator::Validate;
 
 use crate::auth::Auth;
+use crate::authz::{
+    admit_invocation, authorize_create_function, authorize_function, authorize_role_change,
+    authorize_service, authorize_user,
+};
 use crate::error::ApiError;
+use crate::graphql::limits::QueryLimits;
 use crate::graphql::types::{
-    FunctionInput, FunctionObject, FunctionResult, ServiceInput, ServiceObject, ServiceResult,
-    UserInput, UserObject, UserResult,
+    ApiKeyResult, FunctionInput, FunctionObject, FunctionResult, FunctionUpdateInput,
+    SearchResultObject, ServiceInput, ServiceObject, ServiceResult, UserInput, UserObject,
+    UserResult,
+};
+use crate::models::function::{
+    CreateFunctionRequest, FunctionInvocationResponse, FunctionStatus, TriggerType,
+    UpdateFunctionRequest,
 };
+use crate::models::service::{CreateServiceRequest, UpdateServiceRequest};
+use crate::models::user::{CreateUserRequest, UpdateUserRequest};
+use crate::search::SearchKind;
 use crate::service::ApiService;
+use crate::sessions::DeviceInfo;
 
 /// API GraphQL schema
 pub type ApiSchema = Schema<QueryRoot, MutationRoot, EmptySubscription>;
 
-/// Create the GraphQL schema
-pub fn create_schema(api_service: Arc<ApiService>) -> ApiSchema {
+/// Create the GraphQL schema, rejecting queries beyond the depth and complexity limits
+pub fn create_schema(api_service: Arc<ApiService>, limits: QueryLimits) -> ApiSchema {
     Schema::build(QueryRoot, MutationRoot, EmptySubscription)
         .data(api_service)
+        .extension(limits)
         .finish()
 }
 
+/// Authenticated user of the request
+fn auth<'a>(ctx: &Context<'a>) -> Result<&'a Auth, ApiError> {
+    ctx.data::<Auth>()
+        .map_err(|e| ApiError::Authentication(format!("Authentication required: {}", e)))
+}
+
+/// API service
+fn api_service<'a>(ctx: &Context<'a>) -> Result<&'a Arc<ApiService>, ApiError> {
+    ctx.data::<Arc<ApiService>>()
+        .map_err(|e| ApiError::Server(format!("Failed to get API service: {}", e)))
+}
+
+/// Validate an input with the rules of the matching REST request
+fn validate(request: &impl Validate) -> Result<(), ApiError> {
+    request
+        .validate()
+        .map_err(|e| ApiError::Validation(e.to_string()))
+}
+
+/// Result of an invocation
+fn invocation_result(response: FunctionInvocationResponse) -> FunctionResult {
+    FunctionResult {
+        success: true,
+        message: "Function invoked successfully".to_string(),
+        function: None,
+        invocation_result: Some(response.result),
+        execution_time_ms: Some(response.execution_time_ms),
+    }
+}
+
 /// GraphQL query root
 pub struct QueryRoot;
 
@@ -30,29 +77,19 @@ pub struct QueryRoot;
 impl QueryRoot {
     /// Get the current user
     async fn me(&self, ctx: &Context<'_>) -> Result<UserObject, ApiError> {
-        let auth = ctx
-            .data::<Auth>()
-            .map_err(|e| ApiError::Authentication(format!("Authentication required: {}", e)))?;
+        let auth = auth(ctx)?;
 
         Ok(UserObject::from(auth.user.clone()))
     }
 
     /// Get a user by ID
     async fn user(&self, ctx: &Context<'_>, id: Uuid) -> Result<UserObject, ApiError> {
-        let auth = ctx
-            .data::<Auth>()
-            .map_err(|e| ApiError::Authentication(format!("Authentication required: {}", e)))?;
+        let auth = auth(ctx)?;
 
-        let api_service = ctx
-            .data::<Arc<ApiService>>()
-            .map_err(|e| ApiError::Server(format!("Failed to get API service: {}", e)))?;
+        let api_service = api_service(ctx)?;
 
         // Check if the user is an admin or the user is getting their own profile
-        if auth.user.role != crate::models::user::UserRole::Admin && auth.user.id != id {
-            return Err(ApiError::Authorization(
-                "You are not authorized to view this user".to_string(),
-            ));
-        }
+        authorize_user(auth, id, "view")?;
 
         // Get the user
         let user = api_service.auth_service.get_user_by_id(id).await?;
@@ -62,28 +99,21 @@ impl QueryRoot {
 
     /// Get a service by ID
     async fn service(&self, ctx: &Context<'_>, id: Uuid) -> Result<ServiceObject, ApiError> {
-        let auth = ctx
-            .data::<Auth>()
-            .map_err(|e| ApiError::Authentication(format!("Authentication required: {}", e)))?;
+        let auth = auth(ctx)?;
 
-        let api_service = ctx
-            .data::<Arc<ApiService>>()
-            .map_err(|e| ApiError::Server(format!("Failed to get API service: {}", e)))?;
+        let api_service = api_service(ctx)?;
 
         // Get the service
         let service = api_service.service_service.get_service(id).await?;
 
         // Check if the user owns the service
-        if service.user_id != auth.user.id {
-            return Err(ApiError::Authorization(
-                "You are not authorized to view this service".to_string(),
-            ));
-        }
+        authorize_service(auth, &service, "view")?;
 
         Ok(ServiceObject::from(service))
     }
 
     /// List services
+    #[graphql(complexity = "(limit.unwrap_or(10) as usize).saturating_mul(child_complexity)")]
     async fn services(
         &self,
         ctx: &Context<'_>,
@@ -95,13 +125,9 @@ impl QueryRoot {
         limit: Option<u32>,
         offset: Option<u32>,
     ) -> Result<Vec<ServiceObject>, ApiError> {
-        let auth = ctx
-            .data::<Auth>()
-            .map_err(|e| ApiError::Authentication(format!("Authentication required: {}", e)))?;
+        let auth = auth(ctx)?;
 
-        let api_service = ctx
-            .data::<Arc<ApiService>>()
-            .map_err(|e| ApiError::Server(format!("Failed to get API service: {}", e)))?;
+        let api_service = api_service(ctx)?;
 
         // Parse the service type
         let service_type = if let Some(service_type) = service_type {
@@ -152,28 +178,21 @@ impl QueryRoot {
 
     /// Get a function by ID
     async fn function(&self, ctx: &Context<'_>, id: Uuid) -> Result<FunctionObject, ApiError> {
-        let auth = ctx
-            .data::<Auth>()
-            .map_err(|e| ApiError::Authentication(format!("Authentication required: {}", e)))?;
+        let auth = auth(ctx)?;
 
-        let api_service = ctx
-            .data::<Arc<ApiService>>()
-            .map_err(|e| ApiError::Server(format!("Failed to get API service: {}", e)))?;
+        let api_service = api_service(ctx)?;
 
         // Get the function
         let function = api_service.function_service.get_function(id).await?;
 
         // Check if the user owns the function
-        if function.user_id != auth.user.id {
-            return Err(ApiError::Authorization(
-                "You are not authorized to view this function".to_string(),
-            ));
-        }
+        authorize_function(auth, &function, "view")?;
 
         Ok(FunctionObject::from(function))
     }
 
     /// List functions
+    #[graphql(complexity = "(limit.unwrap_or(10) as usize).saturating_mul(child_complexity)")]
     async fn functions(
         &self,
         ctx: &Context<'_>,
@@ -184,13 +203,9 @@ impl QueryRoot {
         limit: Option<u32>,
         offset: Option<u32>,
     ) -> Result<Vec<FunctionObject>, ApiError> {
-        let auth = ctx
-            .data::<Auth>()
-            .map_err(|e| ApiError::Authentication(format!("Authentication required: {}", e)))?;
+        let auth = auth(ctx)?;
 
-        let api_service = ctx
-            .data::<Arc<ApiService>>()
-            .map_err(|e| ApiError::Server(format!("Failed to get API service: {}", e)))?;
+        let api_service = api_service(ctx)?;
 
         // Parse the status
         let status = if let Some(status) = status {
@@ -221,6 +236,7 @@ impl QueryRoot {
     }
 
     /// Discover services
+    #[graphql(complexity = "(limit.unwrap_or(10) as usize).saturating_mul(child_complexity)")]
     async fn discover_services(
         &self,
         ctx: &Context<'_>,
@@ -230,9 +246,7 @@ impl QueryRoot {
         limit: Option<u32>,
         offset: Option<u32>,
     ) -> Result<Vec<ServiceObject>, ApiError> {
-        let api_service = ctx
-            .data::<Arc<ApiService>>()
-            .map_err(|e| ApiError::Server(format!("Failed to get API service: {}", e)))?;
+        let api_service = api_service(ctx)?;
 
         // Parse the service type
         let service_type = if let Some(service_type) = service_type {
@@ -257,6 +271,37 @@ impl QueryRoot {
 
         Ok(services.into_iter().map(ServiceObject::from).collect())
     }
+
+    /// Search functions and services by name, description and code identifiers
+    #[graphql(complexity = "(limit.unwrap_or(10).min(100) as usize).saturating_mul(child_complexity)")]
+    async fn search(
+        &self,
+        ctx: &Context<'_>,
+        q: String,
+        kind: Option<String>,
+        limit: Option<u32>,
+        offset: Option<u32>,
+    ) -> Result<Vec<SearchResultObject>, ApiError> {
+        let auth = auth(ctx)?;
+
+        let api_service = api_service(ctx)?;
+
+        // Parse the kind
+        let kind = kind
+            .map(|kind| kind.parse::<SearchKind>())
+            .transpose()
+            .map_err(ApiError::Validation)?;
+
+        let (hits, _) = api_service.search_index.search(
+            &q,
+            kind,
+            auth.user.id,
+            limit.unwrap_or(10).min(100) as usize,
+            offset.unwrap_or(0) as usize,
+        );
+
+        Ok(hits.into_iter().map(SearchResultObject::from).collect())
+    }
 }
 
 /// GraphQL mutation root
@@ -266,18 +311,20 @@ pub struct MutationRoot;
 impl MutationRoot {
     /// Register a new user
     async fn register(&self, ctx: &Context<'_>, input: UserInput) -> Result<UserResult, ApiError> {
-        let api_service = ctx
-            .data::<Arc<ApiService>>()
-            .map_err(|e| ApiError::Server(format!("Failed to get API service: {}", e)))?;
+        let api_service = api_service(ctx)?;
+
+        // Validate the input
+        let request = CreateUserRequest::from(input);
+        validate(&request)?;
 
         // Create the user
         let user = api_service
             .auth_service
             .create_user(
-                &input.username,
-                &input.email,
-                &input.password,
-                input.role.unwrap_or_default(),
+                &request.username,
+                &request.email,
+                &request.password,
+                request.role.unwrap_or_default(),
             )
             .await?;
 
@@ -285,6 +332,7 @@ impl MutationRoot {
             success: true,
             message: "User registered successfully".to_string(),
             user: Some(UserObject::from(user)),
+            token: None,
         })
     }
 
@@ -295,14 +343,13 @@ impl MutationRoot {
         username_or_email: String,
         password: String,
     ) -> Result<UserResult, ApiError> {
-        let api_service = ctx
-            .data::<Arc<ApiService>>()
-            .map_err(|e| ApiError::Server(format!("Failed to get API service: {}", e)))?;
+        let api_service = api_service(ctx)?;
 
         // Login the user
+        let device = ctx.data_opt::<DeviceInfo>().cloned().unwrap_or_default();
         let (user, token) = api_service
             .auth_service
-            .login(&username_or_email, &password)
+            .login(&username_or_email, &password, &device)
             .await?;
 
         Ok(UserResult {
@@ -320,37 +367,28 @@ impl MutationRoot {
         id: Uuid,
         input: UserInput,
     ) -> Result<UserResult, ApiError> {
-        let auth = ctx
-            .data::<Auth>()
-            .map_err(|e| ApiError::Authentication(format!("Authentication required: {}", e)))?;
+        let auth = auth(ctx)?;
+        let api_service = api_service(ctx)?;
 
-        let api_service = ctx
-            .data::<Arc<ApiService>>()
-            .map_err(|e| ApiError::Server(format!("Failed to get API service: {}", e)))?;
+        // Validate the input
+        let request = UpdateUserRequest::from(input);
+        validate(&request)?;
 
         // Check if the user is an admin or the user is updating their own profile
-        if auth.user.role != crate::models::user::UserRole::Admin && auth.user.id != id {
-            return Err(ApiError::Authorization(
-                "You are not authorized to update this user".to_string(),
-            ));
-        }
+        authorize_user(auth, id, "update")?;
 
         // Check if a non-admin user is trying to change their role
-        if auth.user.role != crate::models::user::UserRole::Admin && input.role.is_some() {
-            return Err(ApiError::Authorization(
-                "You are not authorized to change your role".to_string(),
-            ));
-        }
+        authorize_role_change(auth, request.role)?;
 
         // Update the user
         let user = api_service
             .auth_service
             .update_user(
                 id,
-                Some(&input.username),
-                Some(&input.email),
-                Some(&input.password),
-                input.role,
+                request.username.as_deref(),
+                request.email.as_deref(),
+                request.password.as_deref(),
+                request.role,
             )
             .await?;
 
@@ -358,25 +396,17 @@ impl MutationRoot {
             success: true,
             message: "User updated successfully".to_string(),
             user: Some(UserObject::from(user)),
+            token: None,
         })
     }
 
     /// Delete a user
     async fn delete_user(&self, ctx: &Context<'_>, id: Uuid) -> Result<UserResult, ApiError> {
-        let auth = ctx
-            .data::<Auth>()
-            .map_err(|e| ApiError::Authentication(format!("Authentication required: {}", e)))?;
-
-        let api_service = ctx
-            .data::<Arc<ApiService>>()
-            .map_err(|e| ApiError::Server(format!("Failed to get API service: {}", e)))?;
+        let auth = auth(ctx)?;
+        let api_service = api_service(ctx)?;
 
         // Check if the user is an admin or the user is deleting their own profile
-        if auth.user.role != crate::models::user::UserRole::Admin && auth.user.id != id {
-            return Err(ApiError::Authorization(
-                "You are not authorized to delete this user".to_string(),
-            ));
-        }
+        authorize_user(auth, id, "delete")?;
 
         // Delete the user
         api_service.auth_service.delete_user(id).await?;
@@ -385,6 +415,24 @@ impl MutationRoot {
             success: true,
             message: "User deleted successfully".to_string(),
             user: None,
+            token: None,
+        })
+    }
+
+    /// Create an API key for the current user, replacing the previous one
+    async fn create_api_key(&self, ctx: &Context<'_>) -> Result<ApiKeyResult, ApiError> {
+        let auth = auth(ctx)?;
+        let api_service = api_service(ctx)?;
+
+        let api_key = api_service
+            .auth_service
+            .create_api_key(auth.user.id)
+            .await?;
+
+        Ok(ApiKeyResult {
+            success: true,
+            message: "API key created successfully".to_string(),
+            api_key,
         })
     }
 
@@ -394,24 +442,23 @@ impl MutationRoot {
         ctx: &Context<'_>,
         input: ServiceInput,
     ) -> Result<ServiceResult, ApiError> {
-        let auth = ctx
-            .data::<Auth>()
-            .map_err(|e| ApiError::Authentication(format!("Authentication required: {}", e)))?;
+        let auth = auth(ctx)?;
+        let api_service = api_service(ctx)?;
 
-        let api_service = ctx
-            .data::<Arc<ApiService>>()
-            .map_err(|e| ApiError::Server(format!("Failed to get API service: {}", e)))?;
+        // Validate the input
+        let request = CreateServiceRequest::from(input);
+        validate(&request)?;
 
         // Create the service
         let service = api_service
             .service_service
             .create_service(
                 auth.user.id,
-                &input.name,
-                input.description.as_deref(),
-                input.service_type,
-                &input.config,
-                input.visibility.unwrap_or_default(),
+                &request.name,
+                request.description.as_deref(),
+                request.service_type,
+                &request.config,
+                request.visibility.unwrap_or_default(),
             )
             .await?;
 
@@ -429,34 +476,30 @@ impl MutationRoot {
         id: Uuid,
         input: ServiceInput,
     ) -> Result<ServiceResult, ApiError> {
-        let auth = ctx
-            .data::<Auth>()
-            .map_err(|e| ApiError::Authentication(format!("Authentication required: {}", e)))?;
+        let auth = auth(ctx)?;
+        let api_service = api_service(ctx)?;
 
-        let api_service = ctx
-            .data::<Arc<ApiService>>()
-            .map_err(|e| ApiError::Server(format!("Failed to get API service: {}", e)))?;
+        // Validate the input
+        let request = UpdateServiceRequest::from(input);
+        validate(&request)?;
 
         // Get the service
         let service = api_service.service_service.get_service(id).await?;
 
         // Check if the user owns the service
-        if service.user_id != auth.user.id {
-            return Err(ApiError::Authorization(
-                "You are not authorized to update this service".to_string(),
-            ));
-        }
+        authorize_service(auth, &service, "update")?;
 
         // Update the service
         let service = api_service
             .service_service
             .update_service(
                 id,
-                Some(&input.name),
-                input.description.as_deref(),
-                Some(&input.config),
-                input.status,
-                input.visibility,
+                request.name.as_deref(),
+                request.description.as_deref(),
+                request.config.as_ref(),
+                request.status,
+                request.visibility,
+                None,
             )
             .await?;
 
@@ -469,23 +512,14 @@ impl MutationRoot {
 
     /// Delete a service
     async fn delete_service(&self, ctx: &Context<'_>, id: Uuid) -> Result<ServiceResult, ApiError> {
-        let auth = ctx
-            .data::<Auth>()
-            .map_err(|e| ApiError::Authentication(format!("Authentication required: {}", e)))?;
-
-        let api_service = ctx
-            .data::<Arc<ApiService>>()
-            .map_err(|e| ApiError::Server(format!("Failed to get API service: {}", e)))?;
+        let auth = auth(ctx)?;
+        let api_service = api_service(ctx)?;
 
         // Get the service
         let service = api_service.service_service.get_service(id).await?;
 
         // Check if the user owns the service
-        if service.user_id != auth.user.id {
-            return Err(ApiError::Authorization(
-                "You are not authorized to delete this service".to_string(),
-            ));
-        }
+        authorize_service(auth, &service, "delete")?;
 
         // Delete the service
         api_service.service_service.delete_service(id).await?;
@@ -497,45 +531,43 @@ impl MutationRoot {
         })
     }
 
-    /// Create a function
+    /// Register a function
     async fn create_function(
         &self,
         ctx: &Context<'_>,
         input: FunctionInput,
     ) -> Result<FunctionResult, ApiError> {
-        let auth = ctx
-            .data::<Auth>()
-            .map_err(|e| ApiError::Authentication(format!("Authentication required: {}", e)))?;
+        let auth = auth(ctx)?;
+        let api_service = api_service(ctx)?;
 
-        let api_service = ctx
-            .data::<Arc<ApiService>>()
-            .map_err(|e| ApiError::Server(format!("Failed to get API service: {}", e)))?;
+        // Validate the input
+        let request = CreateFunctionRequest::from(input);
+        validate(&request)?;
 
         // Check if the user owns the service
-        let service = api_service
-            .service_service
-            .get_service(input.service_id)
-            .await?;
+        authorize_create_function(api_service, auth, request.service_id).await?;
 
-        if service.user_id != auth.user.id {
-            return Err(ApiError::Authorization(
-                "You are not authorized to create functions for this service".to_string(),
-            ));
-        }
+        // The input has no upload, so the code is required
+        let code = api_service
+            .upload_store
+            .function_code(auth.user.id, &request.code, request.upload_id)
+            .await?;
 
         // Create the function
         let function = api_service
             .function_service
             .create_function(
                 auth.user.id,
-                input.service_id,
-                &input.name,
-                input.description.as_deref(),
-                &input.code,
-                input.runtime.unwrap_or_default(),
-                input.trigger_type,
-                &input.trigger_config,
-                input.security_level.unwrap_or_default(),
+                request.service_id,
+                &request.name,
+                request.description.as_deref(),
+                &code,
+                request.runtime.unwrap_or_default(),
+                request.trigger_type,
+                &request.trigger_config,
+                request.security_level.unwrap_or_default(),
+                request.input_schema.as_ref(),
+                request.output_schema.as_ref(),
             )
             .await?;
 
@@ -543,6 +575,8 @@ impl MutationRoot {
             success: true,
             message: "Function created successfully".to_string(),
             function: Some(FunctionObject::from(function)),
+            invocation_result: None,
+            execution_time_ms: None,
         })
     }
 
@@ -551,39 +585,38 @@ impl MutationRoot {
         &self,
         ctx: &Context<'_>,
         id: Uuid,
-        input: FunctionInput,
+        input: FunctionUpdateInput,
     ) -> Result<FunctionResult, ApiError> {
-        let auth = ctx
-            .data::<Auth>()
-            .map_err(|e| ApiError::Authentication(format!("Authentication required: {}", e)))?;
+        let auth = auth(ctx)?;
+        let api_service = api_service(ctx)?;
 
-        let api_service = ctx
-            .data::<Arc<ApiService>>()
-            .map_err(|e| ApiError::Server(format!("Failed to get API service: {}", e)))?;
+        // Validate the input
+        let if_updated_at = input.if_updated_at;
+        let request = UpdateFunctionRequest::from(input);
+        validate(&request)?;
 
         // Get the function
         let function = api_service.function_service.get_function(id).await?;
 
         // Check if the user owns the function
-        if function.user_id != auth.user.id {
-            return Err(ApiError::Authorization(
-                "You are not authorized to update this function".to_string(),
-            ));
-        }
+        authorize_function(auth, &function, "update")?;
 
         // Update the function
         let function = api_service
             .function_service
             .update_function(
                 id,
-                Some(&input.name),
-                input.description.as_deref(),
-                Some(&input.code),
-                input.runtime,
-                Some(input.trigger_type),
-                Some(&input.trigger_config),
-                input.security_level,
-                input.status,
+                request.name.as_deref(),
+                request.description.as_deref(),
+                request.code.as_deref(),
+                request.runtime,
+                request.trigger_type,
+                request.trigger_config.as_ref(),
+                request.security_level,
+                request.status,
+                request.input_schema.as_ref(),
+                request.output_schema.as_ref(),
+                if_updated_at,
             )
             .await?;
 
@@ -591,6 +624,98 @@ impl MutationRoot {
             success: true,
             message: "Function updated successfully".to_string(),
             function: Some(FunctionObject::from(function)),
+            invocation_result: None,
+            execution_time_ms: None,
+        })
+    }
+
+    /// Replace the trigger of a function
+    async fn set_function_trigger(
+        &self,
+        ctx: &Context<'_>,
+        id: Uuid,
+        trigger_type: TriggerType,
+        trigger_config: serde_json::Value,
+        if_updated_at: Option<DateTime<Utc>>,
+    ) -> Result<FunctionResult, ApiError> {
+        let auth = auth(ctx)?;
+        let api_service = api_service(ctx)?;
+
+        // Get the function
+        let function = api_service.function_service.get_function(id).await?;
+
+        // Check if the user owns the function
+        authorize_function(auth, &function, "update")?;
+
+        // Update the trigger
+        let function = api_service
+            .function_service
+            .update_function(
+                id,
+                None,
+                None,
+                None,
+                None,
+                Some(trigger_type),
+                Some(&trigger_config),
+                None,
+                None,
+                None,
+                None,
+                if_updated_at,
+            )
+            .await?;
+
+        Ok(FunctionResult {
+            success: true,
+            message: "Function trigger updated successfully".to_string(),
+            function: Some(FunctionObject::from(function)),
+            invocation_result: None,
+            execution_time_ms: None,
+        })
+    }
+
+    /// Activate or deactivate a function
+    async fn set_function_status(
+        &self,
+        ctx: &Context<'_>,
+        id: Uuid,
+        status: FunctionStatus,
+    ) -> Result<FunctionResult, ApiError> {
+        let auth = auth(ctx)?;
+        let api_service = api_service(ctx)?;
+
+        // Get the function
+        let function = api_service.function_service.get_function(id).await?;
+
+        // Check if the user owns the function
+        authorize_function(auth, &function, "update")?;
+
+        // Update the status
+        let function = api_service
+            .function_service
+            .update_function(
+                id,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                Some(status),
+                None,
+                None,
+                None,
+            )
+            .await?;
+
+        Ok(FunctionResult {
+            success: true,
+            message: "Function status updated successfully".to_string(),
+            function: Some(FunctionObject::from(function)),
+            invocation_result: None,
+            execution_time_ms: None,
         })
     }
 
@@ -600,23 +725,14 @@ impl MutationRoot {
         ctx: &Context<'_>,
         id: Uuid,
     ) -> Result<FunctionResult, ApiError> {
-        let auth = ctx
-            .data::<Auth>()
-            .map_err(|e| ApiError::Authentication(format!("Authentication required: {}", e)))?;
-
-        let api_service = ctx
-            .data::<Arc<ApiService>>()
-            .map_err(|e| ApiError::Server(format!("Failed to get API service: {}", e)))?;
+        let auth = auth(ctx)?;
+        let api_service = api_service(ctx)?;
 
         // Get the function
         let function = api_service.function_service.get_function(id).await?;
 
         // Check if the user owns the function
-        if function.user_id != auth.user.id {
-            return Err(ApiError::Authorization(
-                "You are not authorized to delete this function".to_string(),
-            ));
-        }
+        authorize_function(auth, &function, "delete")?;
 
         // Delete the function
         api_service.function_service.delete_function(id).await?;
@@ -625,6 +741,8 @@ impl MutationRoot {
             success: true,
             message: "Function deleted successfully".to_string(),
             function: None,
+            invocation_result: None,
+            execution_time_ms: None,
         })
     }
 
@@ -634,49 +752,59 @@ impl MutationRoot {
         ctx: &Context<'_>,
         id: Uuid,
         input: serde_json::Value,
+        max_cost: Option<f64>,
     ) -> Result<FunctionResult, ApiError> {
-        let auth = ctx
-            .data::<Auth>()
-            .map_err(|e| ApiError::Authentication(format!("Authentication required: {}", e)))?;
-
-        let api_service = ctx
-            .data::<Arc<ApiService>>()
-            .map_err(|e| ApiError::Server(format!("Failed to get API service: {}", e)))?;
+        let auth = auth(ctx)?;
+        let api_service = api_service(ctx)?;
 
         // Get the function
         let function = api_service.function_service.get_function(id).await?;
 
-        // Check if the function is active
-        if function.status != crate::models::function::FunctionStatus::Active {
-            return Err(ApiError::Validation("Function is not active".to_string()));
-        }
+        // Check that the function may run for this user and within the cost limit
+        admit_invocation(api_service, auth, &function, max_cost).await?;
 
-        // Check if the user owns the function or the function's service is public
-        let service = api_service
-            .service_service
-            .get_service(function.service_id)
+        // Invoke the function
+        let response = api_service
+            .function_service
+            .invoke_function(id, &input)
+            .await?;
+
+        Ok(FunctionResult {
+            function: Some(FunctionObject::from(function)),
+            ..invocation_result(response)
+        })
+    }
+
+    /// Invoke a function of a service by name
+    async fn invoke_service(
+        &self,
+        ctx: &Context<'_>,
+        service_id: Uuid,
+        function: String,
+        input: serde_json::Value,
+        max_cost: Option<f64>,
+    ) -> Result<FunctionResult, ApiError> {
+        let auth = auth(ctx)?;
+        let api_service = api_service(ctx)?;
+
+        // Get the function
+        let function = api_service
+            .function_service
+            .get_function_by_name(service_id, &function)
             .await?;
 
-        if function.user_id != auth.user.id
-            && service.visibility != crate::models::service::ServiceVisibility::Public
-        {
-            return Err(ApiError::Authorization(
-                "You are not authorized to invoke this function".to_string(),
-            ));
-        }
+        // Check that the function may run for this user and within the cost limit
+        admit_invocation(api_service, auth, &function, max_cost).await?;
 
         // Invoke the function
         let response = api_service
             .function_service
-            .invoke_function(id, &input)
+            .invoke_function(function.id, &input)
             .await?;
 
         Ok(FunctionResult {
-            success: true,
-            message: "Function invoked successfully".to_string(),
             function: Some(FunctionObject::from(function)),
-            invocation_result: Some(response.result),
-            execution_time_ms: Some(response.execution_time_ms),
+            ..invocation_result(response)
         })
     }
 }
@@ -6,11 +6,16 @@ use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
-use crate::models::function::{Function, FunctionStatus, Runtime, SecurityLevel, TriggerType};
+use crate::models::function::{
+    CreateFunctionRequest, Function, FunctionStatus, Runtime, SecurityLevel, TriggerType,
+    UpdateFunctionRequest,
+};
 use crate::models::service::{
-    Service, ServiceStatus, ServiceSummary, ServiceType, ServiceVisibility,
+    CreateServiceRequest, Service, ServiceStatus, ServiceSummary, ServiceType, ServiceVisibility,
+    UpdateServiceRequest,
 };
-use crate::models::user::{User, UserRole};
+use crate::models::user::{CreateUserRequest, UpdateUserRequest, User, UserRole};
+use crate::search::SearchHit;
 
 /// User object
 #[derive(Debug, Clone, SimpleObject)]
@@ -60,7 +65,29 @@ pub struct UserInput {
     pub password: String,
 
     /// User role
-    pub role: Option<String>,
+    pub role: Option<UserRole>,
+}
+
+impl From<UserInput> for CreateUserRequest {
+    fn from(input: UserInput) -> Self {
+        Self {
+            username: input.username,
+            email: input.email,
+            password: input.password,
+            role: input.role,
+        }
+    }
+}
+
+impl From<UserInput> for UpdateUserRequest {
+    fn from(input: UserInput) -> Self {
+        Self {
+            username: Some(input.username),
+            email: Some(input.email),
+            password: Some(input.password),
+            role: input.role,
+        }
+    }
 }
 
 /// User result
@@ -175,6 +202,30 @@ pub struct ServiceInput {
     pub visibility: Option<ServiceVisibility>,
 }
 
+impl From<ServiceInput> for CreateServiceRequest {
+    fn from(input: ServiceInput) -> Self {
+        Self {
+            name: input.name,
+            description: input.description,
+            service_type: input.service_type,
+            config: input.config,
+            visibility: input.visibility,
+        }
+    }
+}
+
+impl From<ServiceInput> for UpdateServiceRequest {
+    fn from(input: ServiceInput) -> Self {
+        Self {
+            name: Some(input.name),
+            description: input.description,
+            config: Some(input.config),
+            status: input.status,
+            visibility: input.visibility,
+        }
+    }
+}
+
 /// Service result
 #[derive(Debug, Clone, SimpleObject)]
 pub struct ServiceResult {
@@ -286,8 +337,83 @@ pub struct FunctionInput {
     /// Function security level
     pub security_level: Option<SecurityLevel>,
 
+    /// JSON Schema the invocation input must conform to
+    pub input_schema: Option<serde_json::Value>,
+
+    /// JSON Schema the function output must conform to
+    pub output_schema: Option<serde_json::Value>,
+}
+
+impl From<FunctionInput> for CreateFunctionRequest {
+    fn from(input: FunctionInput) -> Self {
+        Self {
+            service_id: input.service_id,
+            name: input.name,
+            description: input.description,
+            code: input.code,
+            upload_id: None,
+            runtime: input.runtime,
+            trigger_type: input.trigger_type,
+            trigger_config: input.trigger_config,
+            security_level: input.security_level,
+            input_schema: input.input_schema,
+            output_schema: input.output_schema,
+        }
+    }
+}
+
+/// Function update input, fields left out are kept
+#[derive(Debug, Clone, Default, InputObject)]
+pub struct FunctionUpdateInput {
+    /// Function name
+    pub name: Option<String>,
+
+    /// Function description
+    pub description: Option<String>,
+
+    /// Function code
+    pub code: Option<String>,
+
+    /// Function runtime
+    pub runtime: Option<Runtime>,
+
+    /// Function trigger type
+    pub trigger_type: Option<TriggerType>,
+
+    /// Function trigger configuration
+    pub trigger_config: Option<serde_json::Value>,
+
+    /// Function security level
+    pub security_level: Option<SecurityLevel>,
+
     /// Function status
     pub status: Option<FunctionStatus>,
+
+    /// JSON Schema the invocation input must conform to
+    pub input_schema: Option<serde_json::Value>,
+
+    /// JSON Schema the function output must conform to
+    pub output_schema: Option<serde_json::Value>,
+
+    /// `updatedAt` of the revision the update applies to, like `If-Match` in REST
+    pub if_updated_at: Option<DateTime<Utc>>,
+}
+
+impl From<FunctionUpdateInput> for UpdateFunctionRequest {
+    fn from(input: FunctionUpdateInput) -> Self {
+        Self {
+            name: input.name,
+            description: input.description,
+            code: input.code,
+            runtime: input.runtime,
+            trigger_type: input.trigger_type,
+            trigger_config: input.trigger_config,
+            security_level: input.security_level,
+            status: input.status,
+            input_schema: input.input_schema,
+            output_schema: input.output_schema,
+        }
+    }
 }
 
 /// Function result
@@ -308,3 +434,47 @@ pub struct FunctionResult {
     /// Execution time in milliseconds
     pub execution_time_ms: Option<u64>,
 }
+
+/// API key result
+#[derive(Debug, Clone, SimpleObject)]
+pub struct ApiKeyResult {
+    /// Success
+    pub success: bool,
+
+    /// Message
+    pub message: String,
+
+    /// API key, sent in the `X-API-Key` header; it is only shown once
+    pub api_key: String,
+}
+
+/// Search result object
+#[derive(Debug, Clone, SimpleObject)]
+pub struct SearchResultObject {
+    /// Result kind: `function` or `service`
+    pub kind: String,
+
+    /// Function or service ID
+    pub id: Uuid,
+
+    /// Name
+    pub name: String,
+
+    /// Description
+    pub description: Option<String>,
+
+    /// Relevance score
+    pub score: f64,
+}
+
+impl From<SearchHit> for SearchResultObject {
+    fn from(hit: SearchHit) -> Self {
+        Self {
+            kind: format!("{:?}", hit.kind).to_lowercase(),
+            id: hit.id,
+            name: hit.name,
+            description: hit.description,
+            score: hit.score,
+        }
+    }
+}
@@ -0,0 +1,168 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+use std::time::Duration;
+
+use r3e_deno::heap::HeapReport;
+use serde::Serialize;
+use sqlx::PgPool;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::error::ApiError;
+
+/// Heap report of an invocation that ran out of memory, without its snapshot
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct StoredHeapReport {
+    /// Invocation ID
+    pub invocation_id: Uuid,
+
+    /// Function ID
+    pub function_id: Uuid,
+
+    /// Heap statistics and largest object types
+    #[schema(value_type = Object)]
+    pub report: HeapReport,
+
+    /// Size of the downloadable heap snapshot in bytes, 0 without one
+    pub snapshot_size: u64,
+
+    /// Creation timestamp (secs since epoch)
+    pub created_at: i64,
+}
+
+/// Heap reports of invocations that ran out of memory, kept for `ttl`
+#[derive(Clone)]
+pub struct HeapReportStore {
+    /// Database pool
+    db: PgPool,
+
+    /// How long a report and its snapshot are kept
+    ttl: Duration,
+
+    /// Largest heap snapshot kept in bytes, larger ones are dropped from the report
+    max_snapshot_size: usize,
+}
+
+impl HeapReportStore {
+    /// Create a new heap report store
+    pub fn new(db: PgPool, ttl: Duration, max_snapshot_size: usize) -> Self {
+        Self {
+            db,
+            ttl,
+            max_snapshot_size,
+        }
+    }
+
+    /// Store the heap report of an invocation
+    pub async fn store(
+        &self,
+        invocation_id: Uuid,
+        function_id: Uuid,
+        user_id: Uuid,
+        mut report: HeapReport,
+    ) -> Result<(), ApiError> {
+        let snapshot = report
+            .snapshot
+            .take()
+            .filter(|snapshot| snapshot.len() <= self.max_snapshot_size);
+        let report = serde_json::to_value(&report)
+            .map_err(|e| ApiError::Server(format!("Failed to encode heap report: {}", e)))?;
+        let now = chrono::Utc::now().timestamp();
+
+        sqlx::query(
+            "INSERT INTO invocation_heap_reports \
+             (invocation_id, function_id, user_id, report, snapshot, created_at, expires_at) \
+             VALUES ($1, $2, $3, $4, $5, $6, $7) ON CONFLICT (invocation_id) DO NOTHING",
+        )
+        .bind(invocation_id)
+        .bind(function_id)
+        .bind(user_id)
+        .bind(report)
+        .bind(snapshot)
+        .bind(now)
+        .bind(now + self.ttl.as_secs() as i64)
+        .execute(&self.db)
+        .await
+        .map_err(|e| ApiError::Database(format!("Failed to store heap report: {}", e)))?;
+
+        Ok(())
+    }
+
+    /// Get the heap report of an invocation of a function
+    pub async fn get(
+        &self,
+        function_id: Uuid,
+        invocation_id: Uuid,
+    ) -> Result<Option<StoredHeapReport>, ApiError> {
+        let row: Option<(serde_json::Value, Option<i32>, i64)> = sqlx::query_as(
+            "SELECT report, octet_length(snapshot), created_at FROM invocation_heap_reports \
+             WHERE invocation_id = $1 AND function_id = $2",
+        )
+        .bind(invocation_id)
+        .bind(function_id)
+        .fetch_optional(&self.db)
+        .await
+        .map_err(|e| ApiError::Database(format!("Failed to get heap report: {}", e)))?;
+
+        let Some((report, snapshot_size, created_at)) = row else {
+            return Ok(None);
+        };
+        let report = serde_json::from_value(report)
+            .map_err(|e| ApiError::Server(format!("Failed to decode heap report: {}", e)))?;
+
+        Ok(Some(StoredHeapReport {
+            invocation_id,
+            function_id,
+            report,
+            snapshot_size: snapshot_size.unwrap_or(0) as u64,
+            created_at,
+        }))
+    }
+
+    /// Get the heap snapshot of an invocation of a function
+    pub async fn get_snapshot(
+        &self,
+        function_id: Uuid,
+        invocation_id: Uuid,
+    ) -> Result<Option<String>, ApiError> {
+        let row: Option<(Option<String>,)> = sqlx::query_as(
+            "SELECT snapshot FROM invocation_heap_reports \
+             WHERE invocation_id = $1 AND function_id = $2",
+        )
+        .bind(invocation_id)
+        .bind(function_id)
+        .fetch_optional(&self.db)
+        .await
+        .map_err(|e| ApiError::Database(format!("Failed to get heap snapshot: {}", e)))?;
+
+        Ok(row.and_then(|(snapshot,)| snapshot))
+    }
+
+    /// Delete expired reports
+    pub async fn purge_expired(&self) -> Result<u64, ApiError> {
+        let result = sqlx::query("DELETE FROM invocation_heap_reports WHERE expires_at <= $1")
+            .bind(chrono::Utc::now().timestamp())
+            .execute(&self.db)
+            .await
+            .map_err(|e| ApiError::Database(format!("Failed to purge heap reports: {}", e)))?;
+
+        Ok(result.rows_affected())
+    }
+
+    /// Purge expired reports every interval
+    pub fn spawn_purge(&self, interval: Duration) -> tokio::task::JoinHandle<()> {
+        let store = self.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(interval);
+            loop {
+                interval.tick().await;
+                match store.purge_expired().await {
+                    Ok(0) => {}
+                    Ok(purged) => log::debug!("Purged {} expired heap reports", purged),
+                    Err(e) => log::warn!("{}", e),
+                }
+            }
+        })
+    }
+}
@@ -0,0 +1,321 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+use std::time::Duration;
+
+use axum::http::{HeaderMap, HeaderValue, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+
+use crate::error::ApiError;
+
+/// Header carrying the client chosen idempotency key
+pub const IDEMPOTENCY_KEY_HEADER: &str = "Idempotency-Key";
+
+/// Header marking a response replayed for a duplicate request
+pub const IDEMPOTENT_REPLAYED_HEADER: &str = "Idempotent-Replayed";
+
+/// Maximum length of an idempotency key
+const MAX_KEY_LENGTH: usize = 255;
+
+/// Response stored for an idempotency key
+#[derive(Debug, Clone)]
+pub struct StoredResponse {
+    /// HTTP status code
+    pub status: u16,
+
+    /// Response body
+    pub body: serde_json::Value,
+}
+
+impl IntoResponse for StoredResponse {
+    fn into_response(self) -> Response {
+        let status = StatusCode::from_u16(self.status).unwrap_or(StatusCode::OK);
+        let mut response = (status, Json(self.body)).into_response();
+        response
+            .headers_mut()
+            .insert(IDEMPOTENT_REPLAYED_HEADER, HeaderValue::from_static("true"));
+        response
+    }
+}
+
+/// Outcome of claiming an idempotency key
+#[derive(Debug)]
+pub enum Claim {
+    /// First request with this key, the caller executes it and completes the key
+    New,
+
+    /// Duplicate of a completed request, its response is returned instead
+    Replay(StoredResponse),
+}
+
+/// Idempotency keys, persisting the first response of a request per (user, key)
+#[derive(Clone)]
+pub struct IdempotencyStore {
+    /// Database pool
+    db: PgPool,
+
+    /// How long a key and its response are kept
+    ttl: Duration,
+}
+
+impl IdempotencyStore {
+    /// Create a new idempotency store
+    pub fn new(db: PgPool, ttl: Duration) -> Self {
+        Self { db, ttl }
+    }
+
+    /// Claim a key for a request, identified by its fingerprint.
+    ///
+    /// Fails with a conflict while a request with the same key is still executing, and
+    /// when the key was already used for a different request.
+    pub async fn claim(&self, user: &str, key: &str, fingerprint: &str) -> Result<Claim, ApiError> {
+        let now = chrono::Utc::now().timestamp();
+
+        // Drop the key if it expired, so it can be claimed again
+        sqlx::query(
+            "DELETE FROM idempotency_keys WHERE user_id = $1 AND idempotency_key = $2 AND expires_at <= $3",
+        )
+        .bind(user)
+        .bind(key)
+        .bind(now)
+        .execute(&self.db)
+        .await
+        .map_err(|e| ApiError::Database(format!("Failed to expire idempotency key: {}", e)))?;
+
+        let inserted = sqlx::query(
+            "INSERT INTO idempotency_keys (user_id, idempotency_key, request_hash, created_at, expires_at) \
+             VALUES ($1, $2, $3, $4, $5) ON CONFLICT (user_id, idempotency_key) DO NOTHING",
+        )
+        .bind(user)
+        .bind(key)
+        .bind(fingerprint)
+        .bind(now)
+        .bind(now + self.ttl.as_secs() as i64)
+        .execute(&self.db)
+        .await
+        .map_err(|e| ApiError::Database(format!("Failed to claim idempotency key: {}", e)))?;
+        if inserted.rows_affected() == 1 {
+            return Ok(Claim::New);
+        }
+
+        let (request_hash, status, body): (String, Option<i32>, Option<serde_json::Value>) =
+            sqlx::query_as(
+                "SELECT request_hash, status_code, response FROM idempotency_keys \
+                 WHERE user_id = $1 AND idempotency_key = $2",
+            )
+            .bind(user)
+            .bind(key)
+            .fetch_one(&self.db)
+            .await
+            .map_err(|e| ApiError::Database(format!("Failed to get idempotency key: {}", e)))?;
+
+        existing_claim(&request_hash, fingerprint, status, body)
+    }
+
+    /// Store the response of a claimed key
+    pub async fn complete(
+        &self,
+        user: &str,
+        key: &str,
+        response: &StoredResponse,
+    ) -> Result<(), ApiError> {
+        sqlx::query(
+            "UPDATE idempotency_keys SET status_code = $3, response = $4 \
+             WHERE user_id = $1 AND idempotency_key = $2",
+        )
+        .bind(user)
+        .bind(key)
+        .bind(response.status as i32)
+        .bind(&response.body)
+        .execute(&self.db)
+        .await
+        .map_err(|e| ApiError::Database(format!("Failed to store idempotent response: {}", e)))?;
+
+        Ok(())
+    }
+
+    /// Release a claimed key whose request failed, so it can be retried
+    pub async fn release(&self, user: &str, key: &str) -> Result<(), ApiError> {
+        sqlx::query(
+            "DELETE FROM idempotency_keys \
+             WHERE user_id = $1 AND idempotency_key = $2 AND status_code IS NULL",
+        )
+        .bind(user)
+        .bind(key)
+        .execute(&self.db)
+        .await
+        .map_err(|e| ApiError::Database(format!("Failed to release idempotency key: {}", e)))?;
+
+        Ok(())
+    }
+
+    /// Delete expired keys
+    pub async fn purge_expired(&self) -> Result<u64, ApiError> {
+        let result = sqlx::query("DELETE FROM idempotency_keys WHERE expires_at <= $1")
+            .bind(chrono::Utc::now().timestamp())
+            .execute(&self.db)
+            .await
+            .map_err(|e| ApiError::Database(format!("Failed to purge idempotency keys: {}", e)))?;
+
+        Ok(result.rows_affected())
+    }
+
+    /// Purge expired keys every interval
+    pub fn spawn_purge(&self, interval: Duration) -> tokio::task::JoinHandle<()> {
+        let store = self.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(interval);
+            loop {
+                interval.tick().await;
+                match store.purge_expired().await {
+                    Ok(0) => {}
+                    Ok(purged) => log::debug!("Purged {} expired idempotency keys", purged),
+                    Err(e) => log::warn!("{}", e),
+                }
+            }
+        })
+    }
+}
+
+/// Claim of a key already held by a request, replaying its response once it completed
+fn existing_claim(
+    request_hash: &str,
+    fingerprint: &str,
+    status: Option<i32>,
+    body: Option<serde_json::Value>,
+) -> Result<Claim, ApiError> {
+    if request_hash != fingerprint {
+        return Err(ApiError::Conflict(
+            "Idempotency key was already used for a different request".to_string(),
+        ));
+    }
+
+    match (status, body) {
+        (Some(status), Some(body)) => Ok(Claim::Replay(StoredResponse {
+            status: status as u16,
+            body,
+        })),
+        _ => Err(ApiError::Conflict(
+            "A request with this idempotency key is still in progress".to_string(),
+        )),
+    }
+}
+
+/// Get the idempotency key of a request, if any
+pub fn idempotency_key(headers: &HeaderMap) -> Result<Option<String>, ApiError> {
+    let Some(value) = headers.get(IDEMPOTENCY_KEY_HEADER) else {
+        return Ok(None);
+    };
+
+    let key = value
+        .to_str()
+        .map_err(|_| ApiError::Validation("Idempotency key must be ASCII".to_string()))?
+        .trim();
+    if key.is_empty() || key.len() > MAX_KEY_LENGTH {
+        return Err(ApiError::Validation(format!(
+            "Idempotency key must be 1 to {} characters",
+            MAX_KEY_LENGTH
+        )));
+    }
+
+    Ok(Some(key.to_string()))
+}
+
+/// Fingerprint of a request, so a key reused for a different request is detected
+pub fn fingerprint<T: Serialize>(route: &str, request: &T) -> Result<String, ApiError> {
+    let body = serde_json::to_vec(request)
+        .map_err(|e| ApiError::Server(format!("Failed to serialize request: {}", e)))?;
+
+    let mut hasher = Sha256::new();
+    hasher.update(route.as_bytes());
+    hasher.update([0]);
+    hasher.update(&body);
+    Ok(hex::encode(hasher.finalize()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(key: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(IDEMPOTENCY_KEY_HEADER, HeaderValue::from_str(key).unwrap());
+        headers
+    }
+
+    #[test]
+    fn test_idempotency_key() {
+        assert_eq!(idempotency_key(&HeaderMap::new()).unwrap(), None);
+        assert_eq!(
+            idempotency_key(&headers(" order-1001 "))
+                .unwrap()
+                .as_deref(),
+            Some("order-1001")
+        );
+        assert!(idempotency_key(&headers(&"k".repeat(MAX_KEY_LENGTH))).is_ok());
+
+        for invalid in [" ".to_string(), "k".repeat(MAX_KEY_LENGTH + 1)] {
+            assert!(matches!(
+                idempotency_key(&headers(&invalid)),
+                Err(ApiError::Validation(_))
+            ));
+        }
+        let mut non_ascii = HeaderMap::new();
+        non_ascii.insert(
+            IDEMPOTENCY_KEY_HEADER,
+            HeaderValue::from_bytes("bestellung-ä".as_bytes()).unwrap(),
+        );
+        assert!(idempotency_key(&non_ascii).is_err());
+    }
+
+    #[test]
+    fn test_fingerprint() {
+        let request = serde_json::json!({ "input": { "order": 1001 } });
+        let hash = fingerprint("/functions/42/invoke", &request).unwrap();
+        assert_eq!(hash.len(), 64);
+        assert_eq!(fingerprint("/functions/42/invoke", &request).unwrap(), hash);
+
+        // The same body sent to another route, or another body, is a different request
+        assert_ne!(fingerprint("/functions/43/invoke", &request).unwrap(), hash);
+        let other = serde_json::json!({ "input": { "order": 1002 } });
+        assert_ne!(fingerprint("/functions/42/invoke", &other).unwrap(), hash);
+    }
+
+    #[test]
+    fn test_existing_claim() {
+        let body = serde_json::json!({ "result": 1 });
+        match existing_claim("hash", "hash", Some(201), Some(body.clone())) {
+            Ok(Claim::Replay(stored)) => {
+                assert_eq!(stored.status, 201);
+                assert_eq!(stored.body, body);
+            }
+            other => panic!("expected a replay, got {:?}", other),
+        }
+
+        // Still executing, or used for a different request
+        for (request_hash, status, body) in [
+            ("hash", None, None),
+            ("other", Some(200), Some(body.clone())),
+        ] {
+            assert!(matches!(
+                existing_claim(request_hash, "hash", status, body),
+                Err(ApiError::Conflict(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_replayed_response() {
+        let response = StoredResponse {
+            status: 202,
+            body: serde_json::json!({ "accepted": true }),
+        }
+        .into_response();
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+        assert_eq!(response.headers()[IDEMPOTENT_REPLAYED_HEADER], "true");
+    }
+}
@@ -3,14 +3,38 @@
 
 use std::sync::Arc;
 
+pub mod approvals;
+pub mod audit;
 pub mod auth;
+pub mod authz;
+pub mod batch;
 pub mod config;
+pub mod domains;
+pub mod encryption;
+pub mod environments;
 pub mod error;
+pub mod estimate;
+pub mod etag;
 pub mod graphql;
+pub mod heap_reports;
+pub mod idempotency;
+pub mod load_shed;
 pub mod models;
+pub mod mtls;
+pub mod oidc;
+pub mod openapi;
+pub mod replays;
 pub mod routes;
+pub mod sdk;
+pub mod search;
+pub mod secret_transfer;
 pub mod service;
+pub mod sessions;
+pub mod signing;
+pub mod snapshot;
+pub mod uploads;
 pub mod utils;
+pub mod workflow;
 
 use axum::{
     routing::{get, post},
@@ -25,10 +49,16 @@ use tower_http::{
 
 use crate::config::Config;
 use crate::error::ApiError;
+use crate::graphql::limits::{QueryBudgets, QueryLimits};
 use crate::graphql::schema::create_schema;
+use crate::load_shed::{LoadShedConfig, LoadShedLayer, LoadShedder};
 use crate::routes::{
-    auth::auth_routes, functions::function_routes, graphql::graphql_routes, health::health_routes,
-    services::service_routes,
+    admin::admin_routes, alert_rules::alert_rule_routes, analytics::analytics_routes,
+    auth::auth_routes, billing::billing_routes, domains::domain_routes,
+    environments::environment_routes, functions::function_routes, graphql::graphql_routes,
+    health::health_routes, notifications::notification_routes, permissions::permission_routes,
+    policies::policy_routes, quota::quota_routes, secrets::secret_routes, services::service_routes,
+    signing::signing_routes, tee::tee_routes, uploads::upload_routes, workflows::workflow_routes,
 };
 use crate::service::ApiService;
 
@@ -42,23 +72,79 @@ pub async fn start_server(config: Config) -> Result<(), ApiError> {
     // Create the API service
     let api_service = Arc::new(ApiService::new(config.clone()).await?);
 
-    // Create the GraphQL schema
-    let schema = create_schema(Arc::clone(&api_service));
+    // Create the GraphQL schema, limiting the depth and complexity of queries per role
+    let budgets = match &config.graphql_query_budgets {
+        Some(budgets) => QueryBudgets::parse(budgets).map_err(ApiError::Server)?,
+        None => QueryBudgets::default(),
+    };
+    let query_limits = QueryLimits::new(budgets, config.graphql_expensive_complexity);
+    let schema = create_schema(Arc::clone(&api_service), query_limits.clone());
+
+    // Shed load beyond what the API can handle, lowest priority first
+    let load_shedder = Arc::new(LoadShedder::new(LoadShedConfig {
+        max_concurrency: config.load_shed_max_concurrency.max(1),
+        max_queue: config.load_shed_max_queue,
+        list_queue_share: config.load_shed_list_queue_share,
+        queue_timeout: std::time::Duration::from_millis(config.load_shed_queue_timeout_ms),
+    }));
+    let metrics_shedder = Arc::clone(&load_shedder);
 
     // Create the router
     let app = Router::new()
         .merge(health_routes())
+        .merge(openapi::openapi_routes())
+        .route(
+            "/metrics",
+            get(move || async move {
+                metrics_shedder.render_metrics() + &query_limits.render_metrics()
+            }),
+        )
         .merge(auth_routes(Arc::clone(&api_service)))
         .merge(function_routes(Arc::clone(&api_service)))
+        .merge(upload_routes(Arc::clone(&api_service)))
+        .merge(environment_routes(Arc::clone(&api_service)))
+        .merge(secret_routes(Arc::clone(&api_service)))
+        .merge(permission_routes(Arc::clone(&api_service)))
+        .merge(policy_routes(Arc::clone(&api_service)))
+        .merge(analytics_routes(Arc::clone(&api_service)))
+        .merge(alert_rule_routes(Arc::clone(&api_service)))
         .merge(service_routes(Arc::clone(&api_service)))
+        .merge(admin_routes(Arc::clone(&api_service)))
+        .merge(quota_routes(Arc::clone(&api_service)))
+        .merge(billing_routes(Arc::clone(&api_service)))
+        .merge(notification_routes(Arc::clone(&api_service)))
+        .merge(workflow_routes(Arc::clone(&api_service)))
+        .merge(domain_routes(Arc::clone(&api_service)))
+        .merge(tee_routes(Arc::clone(&api_service)))
+        .merge(signing_routes(Arc::clone(&api_service)))
         .merge(graphql_routes(schema))
+        .layer(axum::middleware::from_fn_with_state(
+            Arc::clone(&api_service),
+            domains::route_custom_domain,
+        ))
+        .layer(axum::middleware::from_fn_with_state(
+            Arc::clone(&api_service),
+            signing::sign_responses,
+        ))
+        .layer(axum::middleware::from_fn(audit::audit_context))
         .layer(
             CorsLayer::new()
                 .allow_origin(Any)
                 .allow_methods(Any)
-                .allow_headers(Any),
+                .allow_headers(Any)
+                // Clients read ETags to send them back in If-Match, and response signatures
+                .expose_headers([
+                    axum::http::header::ETAG,
+                    axum::http::HeaderName::from_static("x-request-id"),
+                    axum::http::HeaderName::from_static(signing::SIGNATURE_HEADER),
+                    axum::http::HeaderName::from_static(signing::SIGNATURE_KEY_HEADER),
+                    axum::http::HeaderName::from_static(signing::SIGNATURE_ALGORITHM_HEADER),
+                    axum::http::HeaderName::from_static(signing::SIGNATURE_TIMESTAMP_HEADER),
+                    axum::http::HeaderName::from_static(signing::SIGNATURE_DIGEST_HEADER),
+                ]),
         )
         .layer(CompressionLayer::new())
+        .layer(LoadShedLayer::new(load_shedder))
         .layer(TraceLayer::new_for_http())
         .with_state(api_service);
 
@@ -69,6 +155,29 @@ pub async fn start_server(config: Config) -> Result<(), ApiError> {
 
     tracing::info!("API server listening on http://0.0.0.0:{}", config.port);
 
+    // Workers and internal services call the same routes over mutual TLS
+    if let Some(mtls_config) = config.mtls.clone() {
+        let internal = TcpListener::bind(&format!("0.0.0.0:{}", config.internal_port))
+            .await
+            .map_err(|e| {
+                ApiError::Server(format!(
+                    "Failed to bind to port {}: {}",
+                    config.internal_port, e
+                ))
+            })?;
+        tracing::info!(
+            "Internal API listening on https://0.0.0.0:{}",
+            config.internal_port
+        );
+
+        let internal_app = app.clone();
+        tokio::spawn(async move {
+            if let Err(e) = mtls::serve_internal(internal, internal_app, mtls_config).await {
+                tracing::error!("Internal API stopped: {}", e);
+            }
+        });
+    }
+
     axum::serve(listener, app)
         .await
         .map_err(|e| ApiError::Server(format!("Server error: {}", e)))?;
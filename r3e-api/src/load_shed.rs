@@ -0,0 +1,512 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+//! Load shedding and backpressure.
+//!
+//! At most `max_concurrency` requests are handled at once, the others wait in a bounded queue.
+//! Requests are classed by priority: health checks are never held back, invocations may fill
+//! the whole queue, and everything else only part of it, so it is shed first. Shed requests
+//! get a `Retry-After` header.
+
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use axum::body::Body;
+use axum::http::{header, HeaderValue, Method, Request};
+use axum::response::{IntoResponse, Response};
+use futures::future::BoxFuture;
+use r3e_core::{ErrorBody, ErrorCode};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tower::{Layer, Service};
+
+use crate::error::ApiError;
+
+/// Priority class of a request, highest first
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Priority {
+    /// Health checks and metrics scrapes, never held back
+    Health,
+
+    /// Function invocations
+    Invoke,
+
+    /// Listings and every other request
+    List,
+}
+
+impl Priority {
+    /// Every priority class
+    pub const ALL: [Priority; 3] = [Priority::Health, Priority::Invoke, Priority::List];
+
+    /// Class of a request
+    pub fn of(method: &Method, path: &str) -> Self {
+        if path == "/health" || path == "/metrics" {
+            return Priority::Health;
+        }
+        let last = path.trim_end_matches('/').rsplit('/').next().unwrap_or("");
+        if method == Method::POST && (last == "invoke" || last == "invoke-batch") {
+            return Priority::Invoke;
+        }
+        Priority::List
+    }
+
+    /// Label of the class in metrics
+    pub fn label(&self) -> &'static str {
+        match self {
+            Priority::Health => "health",
+            Priority::Invoke => "invoke",
+            Priority::List => "list",
+        }
+    }
+
+    fn index(&self) -> usize {
+        *self as usize
+    }
+}
+
+/// Load shedding configuration
+#[derive(Debug, Clone)]
+pub struct LoadShedConfig {
+    /// Requests handled at once
+    pub max_concurrency: usize,
+
+    /// Requests waiting for a slot
+    pub max_queue: usize,
+
+    /// Share of the queue requests below invocations may take (in percent)
+    pub list_queue_share: u8,
+
+    /// Time a request waits for a slot before it is shed
+    pub queue_timeout: Duration,
+}
+
+impl Default for LoadShedConfig {
+    fn default() -> Self {
+        Self {
+            max_concurrency: 512,
+            max_queue: 1024,
+            list_queue_share: 50,
+            queue_timeout: Duration::from_secs(2),
+        }
+    }
+}
+
+/// Why a request was shed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShedReason {
+    /// The queue share of its class was full
+    QueueFull,
+
+    /// No slot freed up in time
+    QueueTimeout,
+}
+
+impl ShedReason {
+    fn label(&self) -> &'static str {
+        match self {
+            ShedReason::QueueFull => "queue_full",
+            ShedReason::QueueTimeout => "queue_timeout",
+        }
+    }
+
+    fn index(&self) -> usize {
+        *self as usize
+    }
+}
+
+/// Shed request
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Shed {
+    /// Class of the request
+    pub priority: Priority,
+
+    /// Why it was shed
+    pub reason: ShedReason,
+
+    /// Seconds the client should wait before retrying
+    pub retry_after: u64,
+}
+
+impl Shed {
+    /// Error of the response: invocations are shed only when the API is overloaded, other
+    /// requests are asked to back off while invocations are still served
+    pub fn error(&self) -> ApiError {
+        let (code, message) = match self.priority {
+            Priority::List => (
+                ErrorCode::RateLimited,
+                "Too many requests, retry later".to_string(),
+            ),
+            _ => (
+                ErrorCode::Unavailable,
+                "The API is overloaded, retry later".to_string(),
+            ),
+        };
+        ErrorBody::new(code, message)
+            .with_details(serde_json::json!({
+                "priority": self.priority.label(),
+                "reason": self.reason.label(),
+                "retry_after": self.retry_after,
+            }))
+            .into()
+    }
+}
+
+impl IntoResponse for Shed {
+    fn into_response(self) -> Response {
+        let mut response = self.error().into_response();
+        response
+            .headers_mut()
+            .insert(header::RETRY_AFTER, HeaderValue::from(self.retry_after));
+        response
+    }
+}
+
+/// Counters of admitted and shed requests
+#[derive(Default)]
+struct Metrics {
+    admitted: [AtomicU64; 3],
+    shed: [[AtomicU64; 2]; 3],
+    wait_micros: [AtomicU64; 3],
+    waited: [AtomicU64; 3],
+}
+
+/// Admission control of requests by priority
+pub struct LoadShedder {
+    config: LoadShedConfig,
+    slots: Arc<Semaphore>,
+    queued: AtomicUsize,
+    metrics: Metrics,
+}
+
+/// Admitted request, holding its slot until dropped
+pub struct Admission {
+    _permit: Option<OwnedSemaphorePermit>,
+}
+
+impl LoadShedder {
+    /// Create a new load shedder
+    pub fn new(config: LoadShedConfig) -> Self {
+        Self {
+            slots: Arc::new(Semaphore::new(config.max_concurrency)),
+            config,
+            queued: AtomicUsize::new(0),
+            metrics: Metrics::default(),
+        }
+    }
+
+    /// Requests waiting for a slot
+    pub fn queued(&self) -> usize {
+        self.queued.load(Ordering::SeqCst)
+    }
+
+    /// Requests being handled
+    pub fn in_flight(&self) -> usize {
+        self.config.max_concurrency - self.slots.available_permits()
+    }
+
+    /// Requests of a class that may wait for a slot
+    fn queue_limit(&self, priority: Priority) -> usize {
+        match priority {
+            Priority::Health => usize::MAX,
+            Priority::Invoke => self.config.max_queue,
+            Priority::List => self.config.max_queue * self.config.list_queue_share as usize / 100,
+        }
+    }
+
+    /// Seconds until a slot is likely free, growing with the queue
+    fn retry_after(&self) -> u64 {
+        let waves = self.queued() / self.config.max_concurrency.max(1);
+        (1 + waves as u64).min(60)
+    }
+
+    fn shed(&self, priority: Priority, reason: ShedReason) -> Shed {
+        self.metrics.shed[priority.index()][reason.index()].fetch_add(1, Ordering::Relaxed);
+        Shed {
+            priority,
+            reason,
+            retry_after: self.retry_after(),
+        }
+    }
+
+    /// Admit a request, waiting for a slot if none is free
+    pub async fn admit(&self, priority: Priority) -> Result<Admission, Shed> {
+        let admitted = &self.metrics.admitted[priority.index()];
+        if priority == Priority::Health {
+            admitted.fetch_add(1, Ordering::Relaxed);
+            return Ok(Admission { _permit: None });
+        }
+
+        if let Ok(permit) = self.slots.clone().try_acquire_owned() {
+            admitted.fetch_add(1, Ordering::Relaxed);
+            return Ok(Admission {
+                _permit: Some(permit),
+            });
+        }
+
+        // Take a place in the queue, if the class has room left
+        let limit = self.queue_limit(priority);
+        let queued = self
+            .queued
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |queued| {
+                (queued < limit).then_some(queued + 1)
+            });
+        if queued.is_err() {
+            return Err(self.shed(priority, ShedReason::QueueFull));
+        }
+
+        let started = Instant::now();
+        let permit = tokio::time::timeout(
+            self.config.queue_timeout,
+            self.slots.clone().acquire_owned(),
+        )
+        .await;
+        self.queued.fetch_sub(1, Ordering::SeqCst);
+
+        let wait = started.elapsed().as_micros() as u64;
+        self.metrics.wait_micros[priority.index()].fetch_add(wait, Ordering::Relaxed);
+        self.metrics.waited[priority.index()].fetch_add(1, Ordering::Relaxed);
+
+        match permit {
+            Ok(Ok(permit)) => {
+                admitted.fetch_add(1, Ordering::Relaxed);
+                Ok(Admission {
+                    _permit: Some(permit),
+                })
+            }
+            _ => Err(self.shed(priority, ShedReason::QueueTimeout)),
+        }
+    }
+
+    /// Metrics in the Prometheus text format
+    pub fn render_metrics(&self) -> String {
+        let mut out = String::new();
+
+        let _ = writeln!(
+            out,
+            "# HELP r3e_api_requests_admitted_total Requests admitted, by priority class"
+        );
+        let _ = writeln!(out, "# TYPE r3e_api_requests_admitted_total counter");
+        for priority in Priority::ALL {
+            let _ = writeln!(
+                out,
+                "r3e_api_requests_admitted_total{{class=\"{}\"}} {}",
+                priority.label(),
+                self.metrics.admitted[priority.index()].load(Ordering::Relaxed)
+            );
+        }
+
+        let _ = writeln!(
+            out,
+            "# HELP r3e_api_requests_shed_total Requests shed, by priority class and reason"
+        );
+        let _ = writeln!(out, "# TYPE r3e_api_requests_shed_total counter");
+        for priority in Priority::ALL {
+            for reason in [ShedReason::QueueFull, ShedReason::QueueTimeout] {
+                let _ = writeln!(
+                    out,
+                    "r3e_api_requests_shed_total{{class=\"{}\",reason=\"{}\"}} {}",
+                    priority.label(),
+                    reason.label(),
+                    self.metrics.shed[priority.index()][reason.index()].load(Ordering::Relaxed)
+                );
+            }
+        }
+
+        let _ = writeln!(
+            out,
+            "# HELP r3e_api_queue_wait_seconds Time requests waited for a slot"
+        );
+        let _ = writeln!(out, "# TYPE r3e_api_queue_wait_seconds summary");
+        for priority in Priority::ALL {
+            let _ = writeln!(
+                out,
+                "r3e_api_queue_wait_seconds_sum{{class=\"{}\"}} {}",
+                priority.label(),
+                self.metrics.wait_micros[priority.index()].load(Ordering::Relaxed) as f64 / 1e6
+            );
+            let _ = writeln!(
+                out,
+                "r3e_api_queue_wait_seconds_count{{class=\"{}\"}} {}",
+                priority.label(),
+                self.metrics.waited[priority.index()].load(Ordering::Relaxed)
+            );
+        }
+
+        let _ = writeln!(
+            out,
+            "# HELP r3e_api_requests_in_flight Requests being handled"
+        );
+        let _ = writeln!(out, "# TYPE r3e_api_requests_in_flight gauge");
+        let _ = writeln!(out, "r3e_api_requests_in_flight {}", self.in_flight());
+
+        let _ = writeln!(
+            out,
+            "# HELP r3e_api_requests_queued Requests waiting for a slot"
+        );
+        let _ = writeln!(out, "# TYPE r3e_api_requests_queued gauge");
+        let _ = writeln!(out, "r3e_api_requests_queued {}", self.queued());
+
+        out
+    }
+}
+
+/// Load shedding layer
+#[derive(Clone)]
+pub struct LoadShedLayer {
+    /// Admission control
+    shedder: Arc<LoadShedder>,
+}
+
+impl LoadShedLayer {
+    /// Create a new load shedding layer
+    pub fn new(shedder: Arc<LoadShedder>) -> Self {
+        Self { shedder }
+    }
+}
+
+impl<S> Layer<S> for LoadShedLayer {
+    type Service = LoadShedMiddleware<S>;
+
+    fn layer(&self, service: S) -> Self::Service {
+        LoadShedMiddleware {
+            inner: service,
+            shedder: self.shedder.clone(),
+        }
+    }
+}
+
+/// Load shedding middleware
+#[derive(Clone)]
+pub struct LoadShedMiddleware<S> {
+    /// Inner service
+    inner: S,
+
+    /// Admission control
+    shedder: Arc<LoadShedder>,
+}
+
+impl<S> Service<Request<Body>> for LoadShedMiddleware<S>
+where
+    S: Service<Request<Body>, Response = Response> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = Response;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<Body>) -> Self::Future {
+        // Take the service that was polled ready, leaving a clone in its place
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let shedder = self.shedder.clone();
+
+        Box::pin(async move {
+            let priority = Priority::of(request.method(), request.uri().path());
+            match shedder.admit(priority).await {
+                Ok(_admission) => inner.call(request).await,
+                Err(shed) => {
+                    tracing::warn!(
+                        "Shed {} {} ({}, {})",
+                        request.method(),
+                        request.uri().path(),
+                        shed.priority.label(),
+                        shed.reason.label()
+                    );
+                    Ok(shed.into_response())
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use r3e_core::CodedError;
+
+    fn shedder(max_concurrency: usize, max_queue: usize) -> Arc<LoadShedder> {
+        Arc::new(LoadShedder::new(LoadShedConfig {
+            max_concurrency,
+            max_queue,
+            list_queue_share: 50,
+            queue_timeout: Duration::from_millis(50),
+        }))
+    }
+
+    #[test]
+    fn test_priority() {
+        assert_eq!(Priority::of(&Method::GET, "/health"), Priority::Health);
+        assert_eq!(
+            Priority::of(&Method::POST, "/functions/1/invoke"),
+            Priority::Invoke
+        );
+        assert_eq!(
+            Priority::of(&Method::POST, "/services/1/functions/price/invoke"),
+            Priority::Invoke
+        );
+        assert_eq!(Priority::of(&Method::GET, "/functions"), Priority::List);
+        assert_eq!(
+            Priority::of(&Method::GET, "/functions/1/invoke"),
+            Priority::List
+        );
+    }
+
+    #[tokio::test]
+    async fn test_lower_classes_are_shed_first() {
+        let shedder = shedder(1, 2);
+        let _busy = shedder.admit(Priority::List).await.unwrap();
+
+        // The list share of the queue is one request, invocations may take both places
+        let waiting = {
+            let shedder = shedder.clone();
+            tokio::spawn(async move { shedder.admit(Priority::List).await.map(|_| ()) })
+        };
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert_eq!(shedder.queued(), 1);
+
+        let shed = shedder.admit(Priority::List).await.err().unwrap();
+        assert_eq!(shed.reason, ShedReason::QueueFull);
+        assert_eq!(shed.error().code(), ErrorCode::RateLimited);
+
+        let shed = shedder.admit(Priority::Invoke).await.err().unwrap();
+        assert_eq!(shed.reason, ShedReason::QueueTimeout);
+        assert_eq!(shed.error().code(), ErrorCode::Unavailable);
+        assert!(shed.retry_after >= 1);
+
+        // Health checks are never held back
+        assert!(shedder.admit(Priority::Health).await.is_ok());
+
+        assert!(waiting.await.unwrap().is_err());
+        assert_eq!(shedder.queued(), 0);
+
+        let metrics = shedder.render_metrics();
+        assert!(
+            metrics.contains("r3e_api_requests_shed_total{class=\"list\",reason=\"queue_full\"} 1")
+        );
+        assert!(metrics.contains("r3e_api_requests_admitted_total{class=\"health\"} 1"));
+        assert!(metrics.contains("r3e_api_requests_in_flight 1"));
+    }
+
+    #[tokio::test]
+    async fn test_queued_request_gets_freed_slot() {
+        let shedder = shedder(1, 2);
+        let busy = shedder.admit(Priority::Invoke).await.unwrap();
+
+        let waiting = {
+            let shedder = shedder.clone();
+            tokio::spawn(async move { shedder.admit(Priority::Invoke).await.map(|_| ()) })
+        };
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        drop(busy);
+
+        assert!(waiting.await.unwrap().is_ok());
+        assert_eq!(shedder.in_flight(), 0);
+    }
+}
@@ -0,0 +1,66 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use utoipa::ToSchema;
+use uuid::Uuid;
+use validator::Validate;
+
+/// Custom domain mapping, routing requests for a domain and path prefix to a function
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow, ToSchema)]
+pub struct DomainMapping {
+    /// Mapping ID
+    pub id: Uuid,
+
+    /// User ID
+    pub user_id: Uuid,
+
+    /// Domain name, lowercase without a trailing dot
+    pub domain: String,
+
+    /// Path prefix the mapping serves, `/` for the whole domain
+    pub path_prefix: String,
+
+    /// Function ID
+    pub function_id: Uuid,
+
+    /// Token the domain's TXT record must hold to verify the mapping
+    pub verification_token: String,
+
+    /// Whether the domain was verified, only verified mappings route requests
+    pub verified: bool,
+
+    /// Created at
+    pub created_at: DateTime<Utc>,
+
+    /// Verified at
+    pub verified_at: Option<DateTime<Utc>>,
+}
+
+/// Create domain mapping request
+#[derive(Debug, Clone, Serialize, Deserialize, Validate, ToSchema)]
+pub struct CreateDomainMappingRequest {
+    /// Domain name
+    #[validate(length(min = 3, max = 253))]
+    pub domain: String,
+
+    /// Path prefix, defaults to the whole domain
+    #[validate(length(min = 1, max = 255))]
+    pub path_prefix: Option<String>,
+
+    /// ID of the HTTP-triggered function serving the requests
+    pub function_id: Uuid,
+}
+
+/// Domain mapping response, with the DNS record verifying the domain
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct DomainMappingResponse {
+    /// Mapping
+    #[serde(flatten)]
+    pub mapping: DomainMapping,
+
+    /// Name of the TXT record holding the verification token
+    pub verification_record: String,
+}
@@ -0,0 +1,119 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+use std::collections::HashMap;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use utoipa::ToSchema;
+use uuid::Uuid;
+use validator::Validate;
+
+/// Environment functions are released to and promoted through, e.g. dev, staging and prod
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow, ToSchema)]
+pub struct Environment {
+    /// Environment ID
+    pub id: Uuid,
+
+    /// User ID
+    pub user_id: Uuid,
+
+    /// Environment name
+    pub name: String,
+
+    /// Position in the promotion order, versions are promoted to later environments only
+    pub position: i32,
+
+    /// Configuration passed to the functions running in the environment
+    #[sqlx(json)]
+    pub config: HashMap<String, String>,
+
+    /// Secrets bound for the functions, by the name functions read them with
+    #[sqlx(json)]
+    pub secrets: HashMap<String, String>,
+
+    /// Created at
+    pub created_at: DateTime<Utc>,
+
+    /// Updated at
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Create environment request
+#[derive(Debug, Clone, Serialize, Deserialize, Validate, ToSchema)]
+pub struct CreateEnvironmentRequest {
+    /// Environment name, lowercase letters, digits and dashes
+    #[validate(length(min = 1, max = 32))]
+    pub name: String,
+
+    /// Position in the promotion order, after the last environment by default
+    pub position: Option<i32>,
+
+    /// Configuration passed to the functions
+    #[serde(default)]
+    pub config: HashMap<String, String>,
+
+    /// Secrets bound for the functions, by the name functions read them with
+    #[serde(default)]
+    pub secrets: HashMap<String, String>,
+}
+
+/// Update environment request, fields left out are kept
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct UpdateEnvironmentRequest {
+    /// Position in the promotion order
+    pub position: Option<i32>,
+
+    /// Configuration passed to the functions, replacing the current one
+    pub config: Option<HashMap<String, String>>,
+
+    /// Secret bindings, replacing the current ones
+    pub secrets: Option<HashMap<String, String>>,
+}
+
+/// Version of a function released to an environment
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow, ToSchema)]
+pub struct FunctionRelease {
+    /// Function ID
+    pub function_id: Uuid,
+
+    /// Environment name
+    pub environment: String,
+
+    /// Released version
+    pub version: i32,
+
+    /// Hash of the released code
+    pub hash: String,
+
+    /// Environment the version was promoted from, none if it was released from the function
+    pub promoted_from: Option<String>,
+
+    /// User who released the version
+    pub released_by: Uuid,
+
+    /// Released at
+    pub released_at: DateTime<Utc>,
+}
+
+/// Release the current code of a function to an environment
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ReleaseFunctionRequest {
+    /// Environment name
+    pub environment: String,
+}
+
+/// Promote the version released to one environment to the next
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct PromoteFunctionRequest {
+    /// Environment the version is released to
+    pub from: String,
+
+    /// Environment the version is promoted to
+    pub to: String,
+
+    /// Version to promote, which must be the one released to `from`; guards against
+    /// promoting a version released since it was tested
+    pub version: Option<i32>,
+}
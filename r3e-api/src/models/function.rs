@@ -1,14 +1,18 @@
 // Copyright @ 2023 - 2024, R3E Network
 // All Rights Reserved
 
-use chrono::{DateTime, Utc};
+use async_graphql::Enum;
+use chrono::{DateTime, TimeZone, Utc};
+use r3e_core::ErrorCode;
+use r3e_store::{LogLevel, LogRecord, LogRetention};
 use serde::{Deserialize, Serialize};
 use sqlx::FromRow;
+use utoipa::{IntoParams, ToSchema};
 use uuid::Uuid;
 use validator::Validate;
 
 /// Function trigger type
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Enum, ToSchema)]
 #[serde(rename_all = "lowercase")]
 pub enum TriggerType {
     /// HTTP trigger
@@ -28,7 +32,7 @@ pub enum TriggerType {
 }
 
 /// Function runtime
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Enum, ToSchema)]
 #[serde(rename_all = "lowercase")]
 pub enum Runtime {
     /// JavaScript runtime
@@ -45,7 +49,7 @@ impl Default for Runtime {
 }
 
 /// Function security level
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Enum, ToSchema)]
 #[serde(rename_all = "lowercase")]
 pub enum SecurityLevel {
     /// Standard security level
@@ -62,7 +66,7 @@ impl Default for SecurityLevel {
 }
 
 /// Function status
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Enum, ToSchema)]
 #[serde(rename_all = "lowercase")]
 pub enum FunctionStatus {
     /// Creating
@@ -85,7 +89,7 @@ impl Default for FunctionStatus {
 }
 
 /// Function model
-#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow, ToSchema)]
 pub struct Function {
     /// Function ID
     pub id: Uuid,
@@ -126,6 +130,18 @@ pub struct Function {
     /// Function hash
     pub hash: String,
 
+    /// JSON Schema the invocation input must conform to
+    #[sqlx(default)]
+    pub input_schema: Option<serde_json::Value>,
+
+    /// JSON Schema the function output must conform to
+    #[sqlx(default)]
+    pub output_schema: Option<serde_json::Value>,
+
+    /// Identifier of the tenant KEK the code is sealed with at rest, if the owner is enrolled
+    #[sqlx(default)]
+    pub code_key_id: Option<String>,
+
     /// Created at
     pub created_at: DateTime<Utc>,
 
@@ -134,7 +150,7 @@ pub struct Function {
 }
 
 /// Create function request
-#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+#[derive(Debug, Clone, Serialize, Deserialize, Validate, ToSchema)]
 pub struct CreateFunctionRequest {
     /// Service ID
     pub service_id: Uuid,
@@ -147,10 +163,15 @@ pub struct CreateFunctionRequest {
     #[validate(length(min = 0, max = 500))]
     pub description: Option<String>,
 
-    /// Function code
-    #[validate(length(min = 1, max = 1000000))]
+    /// Function code, left out when the code comes from `upload_id`
+    #[serde(default)]
+    #[validate(length(max = 1000000))]
     pub code: String,
 
+    /// Completed chunked upload holding the code of a large bundle
+    #[serde(default)]
+    pub upload_id: Option<Uuid>,
+
     /// Function runtime
     pub runtime: Option<Runtime>,
 
@@ -162,10 +183,18 @@ pub struct CreateFunctionRequest {
 
     /// Function security level
     pub security_level: Option<SecurityLevel>,
+
+    /// JSON Schema the invocation input must conform to
+    #[serde(default)]
+    pub input_schema: Option<serde_json::Value>,
+
+    /// JSON Schema the function output must conform to
+    #[serde(default)]
+    pub output_schema: Option<serde_json::Value>,
 }
 
 /// Update function request
-#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+#[derive(Debug, Clone, Serialize, Deserialize, Validate, ToSchema)]
 pub struct UpdateFunctionRequest {
     /// Function name
     #[validate(length(min = 3, max = 50))]
@@ -193,20 +222,95 @@ pub struct UpdateFunctionRequest {
 
     /// Function status
     pub status: Option<FunctionStatus>,
+
+    /// JSON Schema the invocation input must conform to
+    #[serde(default)]
+    pub input_schema: Option<serde_json::Value>,
+
+    /// JSON Schema the function output must conform to
+    #[serde(default)]
+    pub output_schema: Option<serde_json::Value>,
 }
 
 /// Function invocation request
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct FunctionInvocationRequest {
     /// Function ID
     pub function_id: Uuid,
 
     /// Invocation input
     pub input: serde_json::Value,
+
+    /// Maximum worst-case cost the caller accepts (in GAS)
+    #[serde(default)]
+    pub max_cost: Option<f64>,
+}
+
+/// Function batch invocation request
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct FunctionBatchInvocationRequest {
+    /// Inputs, the function is invoked once per input
+    pub inputs: Vec<serde_json::Value>,
+
+    /// Maximum number of invocations running at once
+    #[serde(default)]
+    pub max_parallelism: Option<usize>,
+
+    /// Maximum worst-case cost of all the invocations of the batch the caller accepts (in GAS)
+    #[serde(default)]
+    pub max_cost: Option<f64>,
+}
+
+/// Result of one item of a batch invocation
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct BatchItemResult {
+    /// Index of the input in the request
+    pub index: usize,
+
+    /// Invocation response, if the function ran
+    pub invocation: Option<FunctionInvocationResponse>,
+
+    /// Error message
+    pub error: Option<String>,
+
+    /// Time spent on the item in milliseconds
+    pub duration_ms: u64,
+}
+
+/// Function batch invocation response
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct FunctionBatchInvocationResponse {
+    /// Function ID
+    pub function_id: Uuid,
+
+    /// Results in the order of the inputs
+    pub items: Vec<BatchItemResult>,
+
+    /// Number of items
+    pub total: usize,
+
+    /// Number of items that ran successfully
+    pub succeeded: usize,
+
+    /// Number of items that failed
+    pub failed: usize,
+
+    /// Time spent on the whole batch in milliseconds
+    pub duration_ms: u64,
+}
+
+/// Function cost estimate request
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+pub struct FunctionEstimateRequest {
+    /// Maximum chain gas spent by transactions the function submits
+    pub max_chain_gas: Option<u64>,
+
+    /// Maximum worst-case cost to check the quote against (in GAS)
+    pub max_cost: Option<f64>,
 }
 
 /// Function invocation response
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct FunctionInvocationResponse {
     /// Invocation ID
     pub invocation_id: Uuid,
@@ -225,20 +329,49 @@ pub struct FunctionInvocationResponse {
 
     /// Error message
     pub error: Option<String>,
+
+    /// Machine-readable error code
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<String>, example = "R3E-3001")]
+    pub error_code: Option<ErrorCode>,
 }
 
-/// Function logs request
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct FunctionLogsRequest {
+/// Function schema response, describing the function's input and output for client codegen
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct FunctionSchemaResponse {
     /// Function ID
     pub function_id: Uuid,
 
+    /// Function name
+    pub name: String,
+
+    /// JSON Schema of the invocation input
+    pub input_schema: Option<serde_json::Value>,
+
+    /// JSON Schema of the function output
+    pub output_schema: Option<serde_json::Value>,
+}
+
+/// Function logs request
+#[derive(Debug, Clone, Serialize, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct FunctionLogsRequest {
     /// Start time
     pub start_time: Option<DateTime<Utc>>,
 
     /// End time
     pub end_time: Option<DateTime<Utc>>,
 
+    /// Lowest level returned
+    #[param(value_type = Option<String>, example = "warn")]
+    pub level: Option<LogLevel>,
+
+    /// Substring the message must contain
+    pub contains: Option<String>,
+
+    /// Regular expression the message must match
+    pub regex: Option<String>,
+
     /// Limit
     pub limit: Option<u32>,
 
@@ -247,19 +380,17 @@ pub struct FunctionLogsRequest {
 }
 
 /// Function log entry
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct FunctionLogEntry {
-    /// Log ID
-    pub id: Uuid,
-
     /// Function ID
-    pub function_id: Uuid,
+    pub function_id: String,
 
     /// Invocation ID
-    pub invocation_id: Option<Uuid>,
+    pub invocation_id: Option<String>,
 
     /// Log level
-    pub level: String,
+    #[schema(value_type = String, example = "info")]
+    pub level: LogLevel,
 
     /// Log message
     pub message: String,
@@ -268,8 +399,23 @@ pub struct FunctionLogEntry {
     pub timestamp: DateTime<Utc>,
 }
 
+impl From<LogRecord> for FunctionLogEntry {
+    fn from(record: LogRecord) -> Self {
+        Self {
+            function_id: record.function_id,
+            invocation_id: record.invocation_id,
+            level: record.level,
+            message: record.message,
+            timestamp: Utc
+                .timestamp_millis_opt(record.timestamp_ms as i64)
+                .single()
+                .unwrap_or_default(),
+        }
+    }
+}
+
 /// Function logs response
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct FunctionLogsResponse {
     /// Log entries
     pub logs: Vec<FunctionLogEntry>,
@@ -280,3 +426,36 @@ pub struct FunctionLogsResponse {
     /// Has more
     pub has_more: bool,
 }
+
+/// Log retention policy of a function
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct LogRetentionResponse {
+    /// Logs older than this many seconds are pruned
+    pub max_age_secs: Option<u64>,
+
+    /// The oldest logs are pruned once the function's logs exceed this many bytes
+    pub max_bytes: Option<u64>,
+
+    /// Whether the platform default applies, the function has no policy of its own
+    pub is_default: bool,
+}
+
+/// Update log retention request
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct UpdateLogRetentionRequest {
+    /// Logs older than this many seconds are pruned, none to keep them regardless of age
+    pub max_age_secs: Option<u64>,
+
+    /// The oldest logs are pruned once the function's logs exceed this many bytes, none for
+    /// no size limit
+    pub max_bytes: Option<u64>,
+}
+
+impl From<UpdateLogRetentionRequest> for LogRetention {
+    fn from(request: UpdateLogRetentionRequest) -> Self {
+        Self {
+            max_age_secs: request.max_age_secs,
+            max_bytes: request.max_bytes,
+        }
+    }
+}
@@ -1,10 +1,20 @@
 // Copyright @ 2023 - 2024, R3E Network
 // All Rights Reserved
 
+pub mod domain;
+pub mod environment;
 pub mod function;
+pub mod replay;
 pub mod service;
+pub mod session;
+pub mod upload;
 pub mod user;
 
+pub use domain::*;
+pub use environment::*;
 pub use function::*;
+pub use replay::*;
 pub use service::*;
+pub use session::*;
+pub use upload::*;
 pub use user::*;
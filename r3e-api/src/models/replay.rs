@@ -0,0 +1,98 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use uuid::Uuid;
+use validator::Validate;
+
+/// State of a replay
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ReplayStatus {
+    /// Events are being replayed
+    Running,
+
+    /// Every event of the range was replayed
+    Completed,
+
+    /// Reading the events failed, or the range held more events than a replay may
+    Failed,
+
+    /// Cancelled by the owner
+    Cancelled,
+}
+
+/// Replay of indexed chain events into a function
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Replay {
+    /// Replay ID
+    pub id: Uuid,
+
+    /// Function ID
+    pub function_id: Uuid,
+
+    /// Hash of the contract whose events are replayed
+    pub contract: String,
+
+    /// Range start (inclusive)
+    pub from: DateTime<Utc>,
+
+    /// Range end (exclusive)
+    pub to: DateTime<Utc>,
+
+    /// Released version the events are replayed into, the current code if neither it nor an
+    /// environment is set
+    pub version: Option<i32>,
+
+    /// Environment whose released version, configuration and secrets the events are replayed
+    /// into
+    pub environment: Option<String>,
+
+    /// Events replayed per second
+    pub rate_per_sec: u32,
+
+    /// Status
+    pub status: ReplayStatus,
+
+    /// Invocations that succeeded
+    pub replayed: u64,
+
+    /// Invocations that failed
+    pub failed: u64,
+
+    /// Why the replay failed
+    pub error: Option<String>,
+
+    /// Created at
+    pub created_at: DateTime<Utc>,
+
+    /// When the replay completed, failed or was cancelled
+    pub finished_at: Option<DateTime<Utc>>,
+}
+
+/// Create replay request
+#[derive(Debug, Clone, Serialize, Deserialize, Validate, ToSchema)]
+pub struct CreateReplayRequest {
+    /// Hash of the contract whose events are replayed
+    #[validate(length(min = 1, max = 128))]
+    pub contract: String,
+
+    /// Range start (inclusive)
+    pub from: DateTime<Utc>,
+
+    /// Range end (exclusive)
+    pub to: DateTime<Utc>,
+
+    /// Released version to replay the events into
+    pub version: Option<i32>,
+
+    /// Environment to replay the events into, with its released version, configuration and
+    /// secrets
+    pub environment: Option<String>,
+
+    /// Events replayed per second, at most the platform rate
+    #[validate(range(min = 1))]
+    pub rate_per_sec: Option<u32>,
+}
@@ -1,14 +1,18 @@
 // Copyright @ 2023 - 2024, R3E Network
 // All Rights Reserved
 
+use async_graphql::Enum;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use sqlx::FromRow;
+use utoipa::{IntoParams, ToSchema};
 use uuid::Uuid;
 use validator::Validate;
 
+use crate::sdk::SdkTarget;
+
 /// Service type
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Enum, ToSchema)]
 #[serde(rename_all = "lowercase")]
 pub enum ServiceType {
     /// Standard service
@@ -31,7 +35,7 @@ impl Default for ServiceType {
 }
 
 /// Service status
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Enum, ToSchema)]
 #[serde(rename_all = "lowercase")]
 pub enum ServiceStatus {
     /// Creating
@@ -54,7 +58,7 @@ impl Default for ServiceStatus {
 }
 
 /// Service visibility
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Enum, ToSchema)]
 #[serde(rename_all = "lowercase")]
 pub enum ServiceVisibility {
     /// Public service
@@ -71,7 +75,7 @@ impl Default for ServiceVisibility {
 }
 
 /// Service model
-#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow, ToSchema)]
 pub struct Service {
     /// Service ID
     pub id: Uuid,
@@ -108,7 +112,7 @@ pub struct Service {
 }
 
 /// Create service request
-#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+#[derive(Debug, Clone, Serialize, Deserialize, Validate, ToSchema)]
 pub struct CreateServiceRequest {
     /// Service name
     #[validate(length(min = 3, max = 50))]
@@ -129,7 +133,7 @@ pub struct CreateServiceRequest {
 }
 
 /// Update service request
-#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+#[derive(Debug, Clone, Serialize, Deserialize, Validate, ToSchema)]
 pub struct UpdateServiceRequest {
     /// Service name
     #[validate(length(min = 3, max = 50))]
@@ -150,7 +154,7 @@ pub struct UpdateServiceRequest {
 }
 
 /// Service summary
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct ServiceSummary {
     /// Service ID
     pub id: Uuid,
@@ -181,7 +185,8 @@ pub struct ServiceSummary {
 }
 
 /// Service list request
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
 pub struct ServiceListRequest {
     /// User ID
     pub user_id: Option<Uuid>,
@@ -206,7 +211,7 @@ pub struct ServiceListRequest {
 }
 
 /// Service list response
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct ServiceListResponse {
     /// Services
     pub services: Vec<ServiceSummary>,
@@ -218,8 +223,28 @@ pub struct ServiceListResponse {
     pub has_more: bool,
 }
 
+/// Service invocation request, invoking a function of the service by name
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ServiceInvocationRequest {
+    /// Invocation input
+    pub input: serde_json::Value,
+
+    /// Maximum worst-case cost the caller accepts (in GAS)
+    #[serde(default)]
+    pub max_cost: Option<f64>,
+}
+
+/// Service SDK request
+#[derive(Debug, Clone, Default, Serialize, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ServiceSdkRequest {
+    /// Where the generated client runs, `http` by default
+    pub target: Option<SdkTarget>,
+}
+
 /// Service discovery request
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
 pub struct ServiceDiscoveryRequest {
     /// Service type
     pub service_type: Option<ServiceType>,
@@ -238,7 +263,7 @@ pub struct ServiceDiscoveryRequest {
 }
 
 /// Service discovery response
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct ServiceDiscoveryResponse {
     /// Services
     pub services: Vec<ServiceSummary>,
@@ -0,0 +1,86 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+/// Login session, one per issued token
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow, ToSchema)]
+pub struct Session {
+    /// Session ID, the `jti` of its token
+    pub id: Uuid,
+
+    /// User ID
+    pub user_id: Uuid,
+
+    /// User agent of the device that logged in
+    pub user_agent: Option<String>,
+
+    /// IP address of the device that logged in
+    pub ip_address: Option<String>,
+
+    /// Created at
+    pub created_at: DateTime<Utc>,
+
+    /// Last request made with the session
+    pub last_seen_at: DateTime<Utc>,
+
+    /// Expires at, together with its token
+    pub expires_at: DateTime<Utc>,
+
+    /// Revoked at
+    pub revoked_at: Option<DateTime<Utc>>,
+
+    /// Why the session was revoked
+    pub revoked_reason: Option<String>,
+}
+
+/// Active session of a user
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct SessionInfo {
+    /// Session ID
+    pub id: Uuid,
+
+    /// User agent of the device that logged in
+    pub user_agent: Option<String>,
+
+    /// IP address of the device that logged in
+    pub ip_address: Option<String>,
+
+    /// Created at
+    pub created_at: DateTime<Utc>,
+
+    /// Last request made with the session
+    pub last_seen_at: DateTime<Utc>,
+
+    /// Expires at
+    pub expires_at: DateTime<Utc>,
+
+    /// Whether this is the session of the request
+    pub current: bool,
+}
+
+impl SessionInfo {
+    /// Session as listed to a request made with `current`
+    pub fn new(session: Session, current: Uuid) -> Self {
+        Self {
+            current: session.id == current,
+            id: session.id,
+            user_agent: session.user_agent,
+            ip_address: session.ip_address,
+            created_at: session.created_at,
+            last_seen_at: session.last_seen_at,
+            expires_at: session.expires_at,
+        }
+    }
+}
+
+/// Revoke sessions response
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct RevokeSessionsResponse {
+    /// Number of sessions revoked
+    pub revoked: u64,
+}
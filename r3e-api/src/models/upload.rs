@@ -0,0 +1,75 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use uuid::Uuid;
+use validator::Validate;
+
+/// State of a chunked upload
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum UploadStatus {
+    /// Parts are being uploaded
+    Uploading,
+
+    /// Parts were assembled into the bundle, which functions can be created from
+    Completed,
+}
+
+/// Chunked upload of a function bundle
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Upload {
+    /// Upload ID
+    pub id: Uuid,
+
+    /// Bundle size in bytes
+    pub size: u64,
+
+    /// Hex encoded SHA-256 digest of the bundle
+    pub sha256: String,
+
+    /// Size of every part but the last one, in bytes
+    pub part_size: u64,
+
+    /// Number of parts
+    pub part_count: u32,
+
+    /// Indexes of the parts received so far, in order
+    pub received_parts: Vec<u32>,
+
+    /// Status
+    pub status: UploadStatus,
+
+    /// Created at
+    pub created_at: DateTime<Utc>,
+
+    /// When the upload is discarded unless a part is uploaded or it is used before
+    pub expires_at: DateTime<Utc>,
+}
+
+/// Create upload request
+#[derive(Debug, Clone, Serialize, Deserialize, Validate, ToSchema)]
+pub struct CreateUploadRequest {
+    /// Bundle size in bytes
+    #[validate(range(min = 1))]
+    pub size: u64,
+
+    /// Hex encoded SHA-256 digest of the bundle, checked once the parts are assembled
+    #[validate(length(equal = 64))]
+    pub sha256: String,
+}
+
+/// Uploaded part
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct UploadPart {
+    /// Part index, from 0
+    pub index: u32,
+
+    /// Part size in bytes
+    pub size: u64,
+
+    /// Hex encoded SHA-256 digest of the part
+    pub sha256: String,
+}
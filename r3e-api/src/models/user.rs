@@ -1,14 +1,16 @@
 // Copyright @ 2023 - 2024, R3E Network
 // All Rights Reserved
 
+use async_graphql::Enum;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use sqlx::FromRow;
+use utoipa::{IntoParams, ToSchema};
 use uuid::Uuid;
 use validator::Validate;
 
 /// User role
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Enum, ToSchema)]
 #[serde(rename_all = "lowercase")]
 pub enum UserRole {
     /// Admin user
@@ -28,7 +30,7 @@ impl Default for UserRole {
 }
 
 /// User model
-#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow, ToSchema)]
 pub struct User {
     /// User ID
     pub id: Uuid,
@@ -58,7 +60,7 @@ pub struct User {
 }
 
 /// Create user request
-#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+#[derive(Debug, Clone, Serialize, Deserialize, Validate, ToSchema)]
 pub struct CreateUserRequest {
     /// Username
     #[validate(length(min = 3, max = 50))]
@@ -77,7 +79,7 @@ pub struct CreateUserRequest {
 }
 
 /// Update user request
-#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+#[derive(Debug, Clone, Serialize, Deserialize, Validate, ToSchema)]
 pub struct UpdateUserRequest {
     /// Username
     #[validate(length(min = 3, max = 50))]
@@ -96,7 +98,7 @@ pub struct UpdateUserRequest {
 }
 
 /// Login request
-#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+#[derive(Debug, Clone, Serialize, Deserialize, Validate, ToSchema)]
 pub struct LoginRequest {
     /// Username or email
     #[validate(length(min = 3, max = 100))]
@@ -108,7 +110,7 @@ pub struct LoginRequest {
 }
 
 /// Login response
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct LoginResponse {
     /// User
     pub user: User,
@@ -123,8 +125,42 @@ pub struct LoginResponse {
     pub expires_in: u64,
 }
 
+/// Query of the callback the identity provider redirects to after an OIDC login
+#[derive(Debug, Clone, Serialize, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct OidcCallbackQuery {
+    /// Authorization code
+    pub code: Option<String>,
+
+    /// State of the login
+    pub state: Option<String>,
+
+    /// Error code, if the provider denied the login
+    pub error: Option<String>,
+
+    /// Error description
+    pub error_description: Option<String>,
+}
+
+/// Login methods the API accepts
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct AuthProvidersResponse {
+    /// Username or email and password
+    pub password: bool,
+
+    /// Issuer of the OpenID Connect provider, if OIDC login is enabled
+    pub oidc_issuer: Option<String>,
+}
+
+/// API key response
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ApiKeyResponse {
+    /// API key, sent in the `X-API-Key` header; it is only shown once
+    pub api_key: String,
+}
+
 /// User profile
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct UserProfile {
     /// User ID
     pub id: Uuid,
@@ -0,0 +1,75 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+//! Internal listener of the API, requiring mutual TLS.
+//!
+//! Workers and internal services call the API on a separate port with certificates of the
+//! internal CA. Connections whose SPIFFE ID is not in `MTLS_ALLOWED_PEERS` are closed after
+//! the handshake, and handlers read the SPIFFE ID of the caller from the `PeerIdentity`
+//! extension of the request.
+
+use axum::{body::Body, extract::Request, Router};
+use hyper::body::Incoming;
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use hyper_util::server::conn::auto;
+use r3e_core::mtls::{MtlsConfig, SpiffeId, TlsAcceptor};
+use tokio::net::TcpListener;
+use tower::ServiceExt;
+
+use crate::error::ApiError;
+
+/// SPIFFE ID of the peer of a request received on the internal listener
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PeerIdentity(pub SpiffeId);
+
+/// Serve the router on the internal listener, authorizing peers by their SPIFFE ID
+pub async fn serve_internal(
+    listener: TcpListener,
+    app: Router,
+    mtls: MtlsConfig,
+) -> Result<(), ApiError> {
+    mtls.provision()
+        .map_err(|e| ApiError::Server(e.to_string()))?;
+    let acceptor = TlsAcceptor::from(
+        mtls.server_config()
+            .map_err(|e| ApiError::Server(e.to_string()))?,
+    );
+
+    loop {
+        let (stream, remote) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                tracing::warn!("Internal listener failed to accept: {}", e);
+                continue;
+            }
+        };
+
+        let (acceptor, app, mtls) = (acceptor.clone(), app.clone(), mtls.clone());
+        tokio::spawn(async move {
+            let (stream, peer) = match r3e_core::mtls::accept(&acceptor, stream).await {
+                Ok(accepted) => accepted,
+                Err(e) => {
+                    tracing::warn!("Internal connection from {} failed: {}", remote, e);
+                    return;
+                }
+            };
+            if let Err(e) = mtls.authorize(peer.as_ref()) {
+                tracing::warn!("Internal connection from {} refused: {}", remote, e);
+                return;
+            }
+
+            let service = hyper::service::service_fn(move |mut request: Request<Incoming>| {
+                if let Some(peer) = &peer {
+                    request.extensions_mut().insert(PeerIdentity(peer.clone()));
+                }
+                app.clone().oneshot(request.map(Body::new))
+            });
+            if let Err(e) = auto::Builder::new(TokioExecutor::new())
+                .serve_connection(TokioIo::new(stream), service)
+                .await
+            {
+                tracing::debug!("Internal connection from {} closed: {}", remote, e);
+            }
+        });
+    }
+}
@@ -0,0 +1,712 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+//! OpenID Connect login.
+//!
+//! Users of an enterprise identity provider log in with the authorization code flow and PKCE.
+//! The ID token is validated against the provider's JWKS, the user is provisioned on first
+//! login and linked to the provider's subject, and the role is mapped from a claim such as
+//! `groups`. After that the user gets a session and token like any password login.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD as BASE64_URL, Engine as _};
+use chrono::Utc;
+use jsonwebtoken::jwk::{Jwk, JwkSet};
+use jsonwebtoken::{decode, decode_header, Algorithm, DecodingKey, Validation};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use tokio::sync::RwLock;
+use uuid::Uuid;
+
+use crate::auth::AuthService;
+use crate::error::ApiError;
+use crate::models::user::{User, UserRole};
+
+/// Time a login has to come back from the provider
+const LOGIN_STATE_TTL: i64 = 600;
+
+/// Minimum time between JWKS fetches triggered by an unknown key ID
+const JWKS_REFRESH_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Maximum length of usernames derived from claims, as accepted by registration
+const MAX_USERNAME_LENGTH: usize = 50;
+
+/// OpenID Connect provider configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OidcConfig {
+    /// Issuer URL, discovery is read from `{issuer}/.well-known/openid-configuration`
+    pub issuer: String,
+
+    /// Client ID registered with the provider
+    pub client_id: String,
+
+    /// Client secret, `None` for public clients relying on PKCE alone
+    pub client_secret: Option<String>,
+
+    /// URL of the callback route the provider redirects back to
+    pub redirect_url: String,
+
+    /// Scopes requested, `openid` is always included
+    pub scopes: Vec<String>,
+
+    /// Claim holding the groups or roles of the user, dotted for nested claims
+    pub role_claim: String,
+
+    /// Role granted for each value of the role claim
+    pub role_mapping: HashMap<String, UserRole>,
+
+    /// Role of users none of whose claim values are mapped
+    pub default_role: UserRole,
+
+    /// Whether the role is updated from the claims on every login, not just the first
+    pub sync_roles: bool,
+
+    /// Algorithm of ID tokens signed with keys that do not name their own
+    pub id_token_algorithm: Algorithm,
+}
+
+impl OidcConfig {
+    /// Parse a role mapping of the form `group:role,group:role`
+    pub fn parse_role_mapping(mapping: &str) -> Result<HashMap<String, UserRole>, ApiError> {
+        mapping
+            .split(',')
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+            .map(|entry| {
+                let (value, role) = entry.rsplit_once(':').ok_or_else(|| {
+                    ApiError::Validation(format!("Invalid OIDC role mapping: {}", entry))
+                })?;
+                Ok((value.trim().to_string(), parse_role(role.trim())?))
+            })
+            .collect()
+    }
+}
+
+/// Parse a role name
+pub fn parse_role(role: &str) -> Result<UserRole, ApiError> {
+    match role.to_ascii_lowercase().as_str() {
+        "admin" => Ok(UserRole::Admin),
+        "developer" => Ok(UserRole::Developer),
+        "viewer" => Ok(UserRole::Viewer),
+        _ => Err(ApiError::Validation(format!("Unknown role: {}", role))),
+    }
+}
+
+/// Endpoints of the provider, from its discovery document
+#[derive(Debug, Clone, Deserialize)]
+struct Discovery {
+    issuer: String,
+    authorization_endpoint: String,
+    token_endpoint: String,
+    jwks_uri: String,
+}
+
+/// Token endpoint response
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    id_token: String,
+}
+
+/// Login started by [`OidcProvider::begin_login`]
+#[derive(Debug, Clone)]
+pub struct LoginRedirect {
+    /// URL of the provider to send the browser to
+    pub url: String,
+
+    /// State the provider passes back to the callback
+    pub state: String,
+}
+
+/// Identity asserted by a validated ID token
+#[derive(Debug, Clone, PartialEq)]
+pub struct OidcIdentity {
+    /// Subject, unique per provider
+    pub subject: String,
+
+    /// Email, if shared
+    pub email: Option<String>,
+
+    /// Whether the provider verified the email
+    pub email_verified: bool,
+
+    /// Preferred username, if shared
+    pub preferred_username: Option<String>,
+
+    /// Role mapped from the claims
+    pub role: UserRole,
+}
+
+/// OpenID Connect provider
+pub struct OidcProvider {
+    /// Configuration
+    config: OidcConfig,
+
+    /// Database pool
+    db: PgPool,
+
+    /// HTTP client
+    client: reqwest::Client,
+
+    /// Discovery document, fetched on first use
+    discovery: RwLock<Option<Discovery>>,
+
+    /// Signing keys and when they were fetched
+    jwks: RwLock<Option<(JwkSet, Instant)>>,
+}
+
+impl OidcProvider {
+    /// Create a provider
+    pub fn new(db: PgPool, config: OidcConfig) -> Self {
+        Self {
+            config,
+            db,
+            client: reqwest::Client::new(),
+            discovery: RwLock::new(None),
+            jwks: RwLock::new(None),
+        }
+    }
+
+    /// Configuration
+    pub fn config(&self) -> &OidcConfig {
+        &self.config
+    }
+
+    /// Start a login, returning where to redirect the browser
+    pub async fn begin_login(&self) -> Result<LoginRedirect, ApiError> {
+        let discovery = self.discovery().await?;
+
+        let state = random_token();
+        let nonce = random_token();
+        let code_verifier = random_token();
+
+        sqlx::query(
+            "INSERT INTO oidc_login_states (state, nonce, code_verifier, expires_at)
+             VALUES ($1, $2, $3, $4)",
+        )
+        .bind(&state)
+        .bind(&nonce)
+        .bind(&code_verifier)
+        .bind(Utc::now() + chrono::Duration::seconds(LOGIN_STATE_TTL))
+        .execute(&self.db)
+        .await
+        .map_err(|e| ApiError::Database(format!("Failed to store login state: {}", e)))?;
+
+        Ok(LoginRedirect {
+            url: self.authorization_url(
+                &discovery.authorization_endpoint,
+                &state,
+                &nonce,
+                &code_challenge(&code_verifier),
+            )?,
+            state,
+        })
+    }
+
+    /// Finish a login: exchange the code, validate the ID token and return the user,
+    /// provisioning it on first login
+    pub async fn complete_login(
+        &self,
+        auth_service: &AuthService,
+        code: &str,
+        state: &str,
+    ) -> Result<User, ApiError> {
+        // Login states are single use
+        let row: Option<(String, String, chrono::DateTime<Utc>)> = sqlx::query_as(
+            "DELETE FROM oidc_login_states WHERE state = $1
+             RETURNING nonce, code_verifier, expires_at",
+        )
+        .bind(state)
+        .fetch_optional(&self.db)
+        .await
+        .map_err(|e| ApiError::Database(format!("Failed to get login state: {}", e)))?;
+        let (nonce, code_verifier, expires_at) = row.ok_or_else(|| {
+            ApiError::Authentication("Unknown or already used login state".to_string())
+        })?;
+        if expires_at < Utc::now() {
+            return Err(ApiError::Authentication("Login has expired".to_string()));
+        }
+
+        let id_token = self.exchange_code(code, &code_verifier).await?;
+        let identity = self.validate_id_token(&id_token, &nonce).await?;
+        self.provision(auth_service, &identity).await
+    }
+
+    /// Delete login states that never came back from the provider
+    pub async fn purge_expired_states(&self) -> Result<u64, ApiError> {
+        sqlx::query("DELETE FROM oidc_login_states WHERE expires_at < $1")
+            .bind(Utc::now())
+            .execute(&self.db)
+            .await
+            .map(|result| result.rows_affected())
+            .map_err(|e| ApiError::Database(format!("Failed to purge login states: {}", e)))
+    }
+
+    /// Purge abandoned login states periodically
+    pub fn spawn_purge(self: Arc<Self>, interval: Duration) -> tokio::task::JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(interval);
+            loop {
+                interval.tick().await;
+                match self.purge_expired_states().await {
+                    Ok(0) => {}
+                    Ok(purged) => log::debug!("Purged {} expired OIDC login states", purged),
+                    Err(e) => log::warn!("{}", e),
+                }
+            }
+        })
+    }
+
+    fn authorization_url(
+        &self,
+        endpoint: &str,
+        state: &str,
+        nonce: &str,
+        code_challenge: &str,
+    ) -> Result<String, ApiError> {
+        let mut scopes = vec!["openid".to_string()];
+        scopes.extend(
+            self.config
+                .scopes
+                .iter()
+                .filter(|scope| scope.as_str() != "openid")
+                .cloned(),
+        );
+
+        url::Url::parse_with_params(
+            endpoint,
+            &[
+                ("response_type", "code"),
+                ("client_id", self.config.client_id.as_str()),
+                ("redirect_uri", self.config.redirect_url.as_str()),
+                ("scope", scopes.join(" ").as_str()),
+                ("state", state),
+                ("nonce", nonce),
+                ("code_challenge", code_challenge),
+                ("code_challenge_method", "S256"),
+            ],
+        )
+        .map(String::from)
+        .map_err(|e| ApiError::ExternalService(format!("Invalid authorization endpoint: {}", e)))
+    }
+
+    async fn exchange_code(&self, code: &str, code_verifier: &str) -> Result<String, ApiError> {
+        let discovery = self.discovery().await?;
+
+        let mut form = vec![
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", self.config.redirect_url.as_str()),
+            ("client_id", self.config.client_id.as_str()),
+            ("code_verifier", code_verifier),
+        ];
+        if let Some(client_secret) = &self.config.client_secret {
+            form.push(("client_secret", client_secret.as_str()));
+        }
+
+        let response = self
+            .client
+            .post(&discovery.token_endpoint)
+            .form(&form)
+            .send()
+            .await
+            .map_err(|e| ApiError::ExternalService(format!("Token request failed: {}", e)))?;
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            return Err(ApiError::Authentication(format!(
+                "Identity provider rejected the code ({}): {}",
+                status, body
+            )));
+        }
+
+        response
+            .json::<TokenResponse>()
+            .await
+            .map(|tokens| tokens.id_token)
+            .map_err(|e| ApiError::ExternalService(format!("Invalid token response: {}", e)))
+    }
+
+    /// Validate the signature, issuer, audience, expiry and nonce of an ID token
+    async fn validate_id_token(
+        &self,
+        id_token: &str,
+        nonce: &str,
+    ) -> Result<OidcIdentity, ApiError> {
+        let discovery = self.discovery().await?;
+        let header = decode_header(id_token)
+            .map_err(|e| ApiError::Authentication(format!("Invalid ID token: {}", e)))?;
+        let kid = header
+            .kid
+            .ok_or_else(|| ApiError::Authentication("ID token has no key ID".to_string()))?;
+        let jwk = self.signing_key(&kid).await?;
+        let mut validation = id_token_validation(header.alg, &jwk, self.config.id_token_algorithm)?;
+        let key = DecodingKey::from_jwk(&jwk)
+            .map_err(|e| ApiError::Authentication(format!("Invalid signing key: {}", e)))?;
+
+        validation.set_issuer(&[&discovery.issuer]);
+        validation.set_audience(&[&self.config.client_id]);
+        let claims = decode::<Value>(id_token, &key, &validation)
+            .map_err(|e| ApiError::Authentication(format!("Invalid ID token: {}", e)))?
+            .claims;
+
+        if claims["nonce"].as_str() != Some(nonce) {
+            return Err(ApiError::Authentication(
+                "ID token nonce does not match the login".to_string(),
+            ));
+        }
+
+        self.identity(&claims)
+    }
+
+    /// Identity and role asserted by the claims of a validated ID token
+    fn identity(&self, claims: &Value) -> Result<OidcIdentity, ApiError> {
+        let subject = claims["sub"]
+            .as_str()
+            .filter(|sub| !sub.is_empty())
+            .ok_or_else(|| ApiError::Authentication("ID token has no subject".to_string()))?;
+
+        Ok(OidcIdentity {
+            subject: subject.to_string(),
+            email: claims["email"].as_str().map(str::to_string),
+            email_verified: claims["email_verified"].as_bool().unwrap_or(false),
+            preferred_username: claims["preferred_username"].as_str().map(str::to_string),
+            role: map_role(
+                claim_values(claims, &self.config.role_claim),
+                &self.config.role_mapping,
+                self.config.default_role,
+            ),
+        })
+    }
+
+    /// User of an identity, linking or creating it on first login
+    async fn provision(
+        &self,
+        auth_service: &AuthService,
+        identity: &OidcIdentity,
+    ) -> Result<User, ApiError> {
+        let linked: Option<(Uuid,)> = sqlx::query_as(
+            "UPDATE user_identities SET last_login_at = $3
+             WHERE issuer = $1 AND subject = $2
+             RETURNING user_id",
+        )
+        .bind(&self.config.issuer)
+        .bind(&identity.subject)
+        .bind(Utc::now())
+        .fetch_optional(&self.db)
+        .await
+        .map_err(|e| ApiError::Database(format!("Failed to get identity: {}", e)))?;
+
+        if let Some((user_id,)) = linked {
+            let user = auth_service.get_user_by_id(user_id).await?;
+            if self.config.sync_roles && user.role != identity.role {
+                log::info!(
+                    "Updating role of user {} from {:?} to {:?} from OIDC claims",
+                    user.id,
+                    user.role,
+                    identity.role
+                );
+                return auth_service
+                    .update_user(user.id, None, None, None, Some(identity.role))
+                    .await;
+            }
+            return Ok(user);
+        }
+
+        let email = identity.email.as_deref().ok_or_else(|| {
+            ApiError::Authentication("Identity provider did not share an email".to_string())
+        })?;
+        let user = match auth_service.get_user_by_username_or_email(email).await {
+            // Only a verified email proves the identity owns the existing account
+            Ok(user) if identity.email_verified && user.email == email => user,
+            Ok(_) => {
+                return Err(ApiError::Conflict(format!(
+                    "An account with email {} exists and the identity provider did not verify it",
+                    email
+                )))
+            }
+            Err(ApiError::NotFound(_)) => self.create_user(auth_service, identity, email).await?,
+            Err(e) => return Err(e),
+        };
+
+        sqlx::query(
+            "INSERT INTO user_identities (issuer, subject, user_id, created_at, last_login_at)
+             VALUES ($1, $2, $3, $4, $4)",
+        )
+        .bind(&self.config.issuer)
+        .bind(&identity.subject)
+        .bind(user.id)
+        .bind(Utc::now())
+        .execute(&self.db)
+        .await
+        .map_err(|e| ApiError::Database(format!("Failed to link identity: {}", e)))?;
+        log::info!(
+            "Linked OIDC subject {} of {} to user {}",
+            identity.subject,
+            self.config.issuer,
+            user.id
+        );
+
+        Ok(user)
+    }
+
+    async fn create_user(
+        &self,
+        auth_service: &AuthService,
+        identity: &OidcIdentity,
+        email: &str,
+    ) -> Result<User, ApiError> {
+        let base = username_from(identity.preferred_username.as_deref(), email);
+        // Users logging in through the provider have no usable password
+        let password = random_token();
+
+        let mut username = base.clone();
+        for _ in 0..3 {
+            match auth_service
+                .create_user(&username, email, &password, identity.role)
+                .await
+            {
+                Err(ApiError::Validation(message)) if message.starts_with("Username") => {
+                    let suffix = &random_token()[..6];
+                    let length = MAX_USERNAME_LENGTH - suffix.len() - 1;
+                    username = format!("{}-{}", truncate(&base, length), suffix);
+                }
+                result => return result,
+            }
+        }
+
+        Err(ApiError::Conflict(format!("No free username for {}", base)))
+    }
+
+    async fn discovery(&self) -> Result<Discovery, ApiError> {
+        if let Some(discovery) = self.discovery.read().await.as_ref() {
+            return Ok(discovery.clone());
+        }
+
+        let url = format!(
+            "{}/.well-known/openid-configuration",
+            self.config.issuer.trim_end_matches('/')
+        );
+        let discovery: Discovery = self.fetch_json(&url).await?;
+        *self.discovery.write().await = Some(discovery.clone());
+        Ok(discovery)
+    }
+
+    /// Key of a key ID, refetching the JWKS when the provider rotated its keys
+    async fn signing_key(&self, kid: &str) -> Result<Jwk, ApiError> {
+        let cached = self.jwks.read().await.clone();
+        if let Some((jwks, fetched_at)) = &cached {
+            if let Some(jwk) = jwks.find(kid) {
+                return Ok(jwk.clone());
+            }
+            if fetched_at.elapsed() < JWKS_REFRESH_INTERVAL {
+                return Err(ApiError::Authentication(format!(
+                    "Unknown signing key: {}",
+                    kid
+                )));
+            }
+        }
+
+        let discovery = self.discovery().await?;
+        let jwks: JwkSet = self.fetch_json(&discovery.jwks_uri).await?;
+        let jwk = jwks.find(kid).cloned();
+        *self.jwks.write().await = Some((jwks, Instant::now()));
+
+        jwk.ok_or_else(|| ApiError::Authentication(format!("Unknown signing key: {}", kid)))
+    }
+
+    async fn fetch_json<T: serde::de::DeserializeOwned>(&self, url: &str) -> Result<T, ApiError> {
+        self.client
+            .get(url)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| ApiError::ExternalService(format!("Failed to fetch {}: {}", url, e)))?
+            .json()
+            .await
+            .map_err(|e| ApiError::ExternalService(format!("Invalid response from {}: {}", url, e)))
+    }
+}
+
+/// Validation of an ID token signed with `jwk`, for the algorithm the key names or else the
+/// configured one. The token's header must name the same algorithm, it does not get to pick.
+fn id_token_validation(
+    header_alg: Algorithm,
+    jwk: &Jwk,
+    configured: Algorithm,
+) -> Result<Validation, ApiError> {
+    let algorithm = match &jwk.common.key_algorithm {
+        Some(key_alg) => serde_json::to_value(key_alg)
+            .and_then(serde_json::from_value::<Algorithm>)
+            .map_err(|_| {
+                ApiError::Authentication(format!(
+                    "Signing key algorithm {:?} cannot sign ID tokens",
+                    key_alg
+                ))
+            })?,
+        None => configured,
+    };
+    if header_alg != algorithm {
+        return Err(ApiError::Authentication(format!(
+            "ID token algorithm {:?} does not match the signing key's {:?}",
+            header_alg, algorithm
+        )));
+    }
+
+    Ok(Validation::new(algorithm))
+}
+
+/// Random URL-safe token of 256 bits, used for states, nonces and PKCE verifiers
+fn random_token() -> String {
+    BASE64_URL.encode(rand::random::<[u8; 32]>())
+}
+
+/// PKCE S256 challenge of a code verifier
+fn code_challenge(code_verifier: &str) -> String {
+    BASE64_URL.encode(Sha256::digest(code_verifier.as_bytes()))
+}
+
+/// String values of a claim, following dots into nested objects
+fn claim_values<'a>(claims: &'a Value, path: &str) -> Vec<&'a str> {
+    let claim = path
+        .split('.')
+        .try_fold(claims, |value, key| value.get(key));
+    match claim {
+        Some(Value::String(value)) => vec![value.as_str()],
+        Some(Value::Array(values)) => values.iter().filter_map(Value::as_str).collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Most privileged role any claim value maps to
+fn map_role(
+    values: Vec<&str>,
+    mapping: &HashMap<String, UserRole>,
+    default_role: UserRole,
+) -> UserRole {
+    let rank = |role: &UserRole| match role {
+        UserRole::Viewer => 0,
+        UserRole::Developer => 1,
+        UserRole::Admin => 2,
+    };
+    values
+        .into_iter()
+        .filter_map(|value| mapping.get(value).copied())
+        .max_by_key(rank)
+        .unwrap_or(default_role)
+}
+
+/// Username of a new user, from the preferred username or the local part of the email
+fn username_from(preferred_username: Option<&str>, email: &str) -> String {
+    let source = preferred_username
+        .filter(|name| !name.trim().is_empty())
+        .unwrap_or_else(|| email.split('@').next().unwrap_or(email));
+    let username: String = source
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    // Registration requires at least 3 characters
+    let username = format!("{:_<3}", username);
+    truncate(&username, MAX_USERNAME_LENGTH).to_string()
+}
+
+fn truncate(s: &str, max: usize) -> &str {
+    match s.char_indices().nth(max) {
+        Some((index, _)) => &s[..index],
+        None => s,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_code_challenge() {
+        // RFC 7636 appendix B
+        assert_eq!(
+            code_challenge("dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk"),
+            "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM"
+        );
+    }
+
+    #[test]
+    fn test_role_mapping() {
+        let mapping =
+            OidcConfig::parse_role_mapping("r3e-admins:admin, r3e-devs:Developer").unwrap();
+        assert_eq!(mapping["r3e-admins"], UserRole::Admin);
+        assert_eq!(mapping["r3e-devs"], UserRole::Developer);
+        assert!(OidcConfig::parse_role_mapping("r3e-admins").is_err());
+        assert!(OidcConfig::parse_role_mapping("r3e-admins:owner").is_err());
+
+        let claims = json!({
+            "groups": ["staff", "r3e-devs"],
+            "realm_access": { "roles": ["r3e-admins"] },
+            "department": "r3e-devs"
+        });
+        let role = |path| map_role(claim_values(&claims, path), &mapping, UserRole::Viewer);
+        assert_eq!(role("groups"), UserRole::Developer);
+        assert_eq!(role("realm_access.roles"), UserRole::Admin);
+        assert_eq!(role("department"), UserRole::Developer);
+        assert_eq!(role("roles"), UserRole::Viewer);
+    }
+
+    #[test]
+    fn test_id_token_algorithm() {
+        let jwk = |alg: Option<&str>| -> Jwk {
+            let mut jwk = json!({ "kty": "RSA", "kid": "k1", "n": "sXch", "e": "AQAB" });
+            if let Some(alg) = alg {
+                jwk["alg"] = json!(alg);
+            }
+            serde_json::from_value(jwk).unwrap()
+        };
+        let validation = |header_alg, jwk: &Jwk, configured| {
+            id_token_validation(header_alg, jwk, configured).map(|v| v.algorithms)
+        };
+
+        // The key's algorithm wins over the header and the configuration
+        let rs256 = jwk(Some("RS256"));
+        assert_eq!(
+            validation(Algorithm::RS256, &rs256, Algorithm::RS256).unwrap(),
+            [Algorithm::RS256]
+        );
+        assert!(validation(Algorithm::HS256, &rs256, Algorithm::RS256).is_err());
+        assert!(validation(Algorithm::RS512, &rs256, Algorithm::RS512).is_err());
+
+        // Keys without an algorithm take the configured one
+        let any = jwk(None);
+        assert_eq!(
+            validation(Algorithm::PS256, &any, Algorithm::PS256).unwrap(),
+            [Algorithm::PS256]
+        );
+        assert!(validation(Algorithm::RS256, &any, Algorithm::PS256).is_err());
+
+        // Encryption keys sign nothing
+        assert!(validation(Algorithm::RS256, &jwk(Some("RSA-OAEP")), Algorithm::RS256).is_err());
+    }
+
+    #[test]
+    fn test_username_from() {
+        assert_eq!(
+            username_from(Some("jane.doe"), "jane@corp.example"),
+            "jane.doe"
+        );
+        assert_eq!(username_from(None, "j d@corp.example"), "j_d");
+        assert_eq!(username_from(Some(" "), "al@corp.example"), "al_");
+        assert_eq!(
+            username_from(None, &format!("{}@x", "a".repeat(80))).len(),
+            50
+        );
+    }
+}
@@ -0,0 +1,374 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+//! OpenAPI description of the API.
+//!
+//! The document is generated from the `#[utoipa::path]` annotations of the route handlers and
+//! the request and response types they use, so it cannot drift from the handlers. It is served
+//! as OpenAPI 3.1 at `/openapi.json`, with Swagger UI at `/docs`. Every operation documents the
+//! [`ErrorBody`](r3e_core::ErrorBody) of failed requests as its default response.
+
+use axum::{response::Html, routing::get, Json, Router};
+use r3e_core::ErrorCode;
+use utoipa::openapi::schema::{ObjectBuilder, Type};
+use utoipa::openapi::security::{ApiKey, ApiKeyValue, HttpAuthScheme, HttpBuilder, SecurityScheme};
+use utoipa::openapi::{ContentBuilder, Ref, ResponseBuilder, Schema};
+use utoipa::{Modify, OpenApi};
+
+use crate::routes::{
+    admin, alert_rules, analytics, auth, billing, domains, environments, functions, graphql,
+    health, notifications, permissions, policies, quota, secrets, services, signing, tee, uploads,
+    workflows,
+};
+
+/// Name of the error body schema
+pub const ERROR_BODY_SCHEMA: &str = "ErrorBody";
+
+/// OpenAPI document of the API
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "R3E FaaS API", description = "Functions, services and platform administration"),
+    paths(
+        health::health_check,
+        auth::register,
+        auth::login,
+        auth::providers,
+        auth::oidc_login,
+        auth::oidc_callback,
+        auth::logout,
+        auth::list_sessions,
+        auth::revoke_session,
+        auth::revoke_other_sessions,
+        auth::me,
+        auth::create_api_key,
+        auth::get_user,
+        auth::update_user,
+        auth::list_user_sessions,
+        auth::revoke_user_sessions,
+        auth::delete_user,
+        functions::list_functions,
+        functions::search,
+        functions::get_function,
+        functions::create_function,
+        functions::get_function_schema,
+        functions::update_function,
+        functions::delete_function,
+        functions::invoke_function,
+        functions::invoke_batch,
+        functions::estimate_function,
+        functions::get_function_logs,
+        functions::get_log_retention,
+        functions::set_log_retention,
+        functions::get_heap_report,
+        functions::download_heap_snapshot,
+        functions::create_replay,
+        functions::get_replay,
+        functions::cancel_replay,
+        uploads::create_upload,
+        uploads::get_upload,
+        uploads::put_part,
+        uploads::complete_upload,
+        uploads::delete_upload,
+        environments::list_environments,
+        environments::create_environment,
+        environments::get_environment,
+        environments::update_environment,
+        environments::delete_environment,
+        environments::list_releases,
+        environments::release_function,
+        environments::promote_function,
+        secrets::get_transfer_key,
+        secrets::export_secrets,
+        secrets::import_secrets,
+        permissions::list_permissions,
+        permissions::grant_permission,
+        permissions::approve_permission,
+        permissions::deny_permission,
+        permissions::revoke_permission,
+        permissions::list_permission_uses,
+        policies::list_policies,
+        policies::create_policy,
+        policies::get_policy,
+        policies::update_policy,
+        policies::delete_policy,
+        policies::list_policy_decisions,
+        analytics::get_function_analytics,
+        alert_rules::list_alert_rules,
+        alert_rules::create_alert_rule,
+        alert_rules::get_alert_rule,
+        alert_rules::update_alert_rule,
+        alert_rules::delete_alert_rule,
+        alert_rules::evaluate_alert_rule,
+        services::list_services,
+        services::get_service,
+        services::create_service,
+        services::update_service,
+        services::delete_service,
+        services::invoke_service,
+        services::get_service_sdk,
+        services::discover_services,
+        admin::export_snapshot,
+        admin::import_snapshot,
+        admin::query_audit_log,
+        admin::verify_audit_log,
+        admin::propose_operation,
+        admin::list_operations,
+        admin::get_operation,
+        admin::export_operation,
+        admin::approve_operation,
+        admin::cancel_operation,
+        admin::get_tenant_encryption,
+        admin::enroll_tenant,
+        admin::rotate_tenant_key,
+        quota::list_plans,
+        quota::get_my_plan,
+        quota::get_my_usage,
+        quota::get_usage,
+        quota::set_plan,
+        billing::get_account,
+        billing::add_payer,
+        billing::list_invoices,
+        billing::get_invoice,
+        billing::run_billing_cycle,
+        notifications::list_channels,
+        notifications::create_channel,
+        notifications::get_channel,
+        notifications::update_channel,
+        notifications::delete_channel,
+        notifications::test_channel,
+        notifications::list_deliveries,
+        notifications::raise_alert,
+        workflows::create_workflow,
+        workflows::list_workflows,
+        workflows::get_workflow,
+        workflows::update_workflow,
+        workflows::delete_workflow,
+        workflows::start_run,
+        workflows::list_runs,
+        workflows::get_run,
+        workflows::get_run_graph,
+        workflows::cancel_run,
+        domains::create_domain,
+        domains::list_domains,
+        domains::get_domain,
+        domains::verify_domain,
+        domains::delete_domain,
+        domains::tls_check,
+        tee::verify_attestation,
+        tee::get_measurements,
+        signing::list_signing_keys,
+        signing::rotate_signing_key,
+        graphql::graphql_handler,
+    ),
+    tags(
+        (name = "health", description = "Liveness"),
+        (name = "auth", description = "Login, sessions and API keys"),
+        (name = "users", description = "User accounts"),
+        (name = "functions", description = "Functions, their invocations and logs"),
+        (name = "uploads", description = "Chunked uploads of function bundles"),
+        (name = "environments", description = "Environments and the function versions released to them"),
+        (name = "secrets", description = "Bulk import and export of function secrets"),
+        (name = "permissions", description = "Sandbox permission grants of functions"),
+        (name = "policies", description = "Admin policies decided before invocations and op dispatch"),
+        (name = "analytics", description = "Usage of functions"),
+        (name = "alerts", description = "Alert rules over the usage of functions"),
+        (name = "services", description = "Services grouping functions"),
+        (name = "admin", description = "Platform administration"),
+        (name = "signing", description = "Keys signing API responses"),
+        (name = "quota", description = "Priced plans and quota usage"),
+        (name = "billing", description = "Billing accounts and invoices"),
+        (name = "notifications", description = "Alert notification channels and deliveries"),
+        (name = "workflows", description = "Workflows and their runs"),
+        (name = "domains", description = "Custom domains of HTTP functions"),
+        (name = "tee", description = "Attestation of the platform's enclaves"),
+        (name = "graphql", description = "GraphQL endpoint"),
+    ),
+    modifiers(&SecuritySchemes, &ErrorResponses)
+)]
+pub struct ApiDoc;
+
+/// Adds the `bearer` (session JWT) and `api_key` (`X-API-Key` header) security schemes
+/// operations refer to
+pub struct SecuritySchemes;
+
+impl Modify for SecuritySchemes {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "bearer",
+            SecurityScheme::Http(
+                HttpBuilder::new()
+                    .scheme(HttpAuthScheme::Bearer)
+                    .bearer_format("JWT")
+                    .build(),
+            ),
+        );
+        components.add_security_scheme(
+            "api_key",
+            SecurityScheme::ApiKey(ApiKey::Header(ApiKeyValue::new("X-API-Key"))),
+        );
+    }
+}
+
+/// Documents the error body of failed requests as the default response of every operation
+pub struct ErrorResponses;
+
+impl Modify for ErrorResponses {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        openapi
+            .components
+            .get_or_insert_with(Default::default)
+            .schemas
+            .insert(ERROR_BODY_SCHEMA.to_string(), error_body_schema().into());
+
+        let error = ResponseBuilder::new()
+            .description("Request failed")
+            .content(
+                "application/json",
+                ContentBuilder::new()
+                    .schema(Some(Ref::from_schema_name(ERROR_BODY_SCHEMA)))
+                    .build(),
+            )
+            .build();
+
+        for item in openapi.paths.paths.values_mut() {
+            let operations = [
+                &mut item.get,
+                &mut item.put,
+                &mut item.post,
+                &mut item.delete,
+                &mut item.options,
+                &mut item.head,
+                &mut item.patch,
+                &mut item.trace,
+            ];
+            for operation in operations.into_iter().flatten() {
+                operation
+                    .responses
+                    .responses
+                    .entry("default".to_string())
+                    .or_insert_with(|| error.clone().into());
+            }
+        }
+    }
+}
+
+/// Schema of [`ErrorBody`](r3e_core::ErrorBody), its codes listed from [`ErrorCode::ALL`]
+pub fn error_body_schema() -> Schema {
+    ObjectBuilder::new()
+        .description(Some("Error body of API responses"))
+        .property(
+            "code",
+            ObjectBuilder::new()
+                .schema_type(Type::String)
+                .enum_values(Some(ErrorCode::ALL.iter().map(ErrorCode::as_str)))
+                .description(Some("Machine-readable error code")),
+        )
+        .required("code")
+        .property(
+            "message",
+            ObjectBuilder::new()
+                .schema_type(Type::String)
+                .description(Some("Human-readable message")),
+        )
+        .required("message")
+        .property(
+            "details",
+            ObjectBuilder::new()
+                .schema_type(Type::Object)
+                .description(Some(
+                    "Structured details, e.g. the violations of a validation error",
+                )),
+        )
+        .into()
+}
+
+/// Swagger UI page rendering the document at `spec_url`
+pub fn swagger_ui_html(title: &str, spec_url: &str) -> String {
+    format!(
+        r##"<!DOCTYPE html>
+<html lang="en">
+<head>
+  <meta charset="utf-8">
+  <title>{title}</title>
+  <link rel="stylesheet" href="https://unpkg.com/swagger-ui-dist@5/swagger-ui.css">
+</head>
+<body>
+  <div id="swagger-ui"></div>
+  <script src="https://unpkg.com/swagger-ui-dist@5/swagger-ui-bundle.js"></script>
+  <script>
+    window.ui = SwaggerUIBundle({{ url: "{spec_url}", dom_id: "#swagger-ui" }});
+  </script>
+</body>
+</html>
+"##
+    )
+}
+
+/// OpenAPI document and Swagger UI routes
+pub fn openapi_routes() -> Router {
+    let spec = ApiDoc::openapi();
+    let title = format!("{} - Swagger UI", spec.info.title);
+
+    Router::new()
+        .route(
+            "/openapi.json",
+            get(move || {
+                let spec = spec.clone();
+                async move { Json(spec) }
+            }),
+        )
+        .route(
+            "/docs",
+            get(move || {
+                let html = swagger_ui_html(&title, "/openapi.json");
+                async move { Html(html) }
+            }),
+        )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_document_is_openapi_3_1() {
+        let spec = serde_json::to_value(ApiDoc::openapi()).unwrap();
+
+        assert!(spec["openapi"].as_str().unwrap().starts_with("3.1"));
+        assert_eq!(spec["info"]["title"], "R3E FaaS API");
+        assert!(spec["components"]["securitySchemes"]["bearer"].is_object());
+    }
+
+    #[test]
+    fn test_paths_use_openapi_templates() {
+        let spec = ApiDoc::openapi();
+
+        assert!(spec.paths.paths.contains_key("/functions/{id}/invoke"));
+        for path in spec.paths.paths.keys() {
+            assert!(!path.contains(':'), "axum path syntax in {}", path);
+        }
+    }
+
+    #[test]
+    fn test_every_operation_documents_errors() {
+        let spec = serde_json::to_value(ApiDoc::openapi()).unwrap();
+
+        let codes = &spec["components"]["schemas"][ERROR_BODY_SCHEMA]["properties"]["code"]["enum"];
+        assert_eq!(codes.as_array().unwrap().len(), ErrorCode::ALL.len());
+        assert_eq!(codes[0], "R3E-1001");
+
+        for (path, item) in spec["paths"].as_object().unwrap() {
+            for (method, operation) in item.as_object().unwrap() {
+                assert_eq!(
+                    operation["responses"]["default"]["content"]["application/json"]["schema"]
+                        ["$ref"],
+                    format!("#/components/schemas/{}", ERROR_BODY_SCHEMA),
+                    "{} {}",
+                    method,
+                    path
+                );
+            }
+        }
+    }
+}
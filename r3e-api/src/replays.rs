@@ -0,0 +1,303 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+//! Replays of indexed chain events into functions.
+//!
+//! A replay reads the events a contract emitted over a time range from the event index and
+//! invokes a function with each of them, oldest first, at a bounded rate. It runs the current
+//! code, a released version or the version released to an environment, so a fix can be
+//! checked against past events before it is promoted. Replayed invocations carry
+//! `"replay": true`; they show in the logs and analytics of the function but are not billed,
+//! the events having been billed when they first fired. Replays are kept in memory and stop
+//! with the API server.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use chrono::Utc;
+use r3e_store::{EventQuery, EventStore, IndexedEvent, MAX_EVENT_PAGE};
+use uuid::Uuid;
+
+use crate::environments::FunctionVersion;
+use crate::error::ApiError;
+use crate::models::environment::Environment;
+use crate::models::replay::{CreateReplayRequest, Replay, ReplayStatus};
+use crate::service::FunctionService;
+
+/// Replays a user may run at once
+const MAX_RUNNING_REPLAYS: usize = 4;
+
+/// How long finished replays are kept
+const FINISHED_REPLAY_TTL: Duration = Duration::from_secs(24 * 3600);
+
+/// Replay and who started it
+struct ReplayJob {
+    replay: Replay,
+    user_id: Uuid,
+    cancelled: Arc<AtomicBool>,
+}
+
+/// Replays of indexed events into functions
+#[derive(Clone)]
+pub struct ReplayStore {
+    /// Index of the events replayed
+    events: Arc<dyn EventStore>,
+
+    /// Replays by ID
+    jobs: Arc<Mutex<HashMap<Uuid, ReplayJob>>>,
+
+    /// Most events replayed per second
+    max_rate_per_sec: u32,
+
+    /// Most events a replay may invoke its function with
+    max_events: u64,
+}
+
+impl ReplayStore {
+    /// Create a new replay store
+    pub fn new(events: Arc<dyn EventStore>, max_rate_per_sec: u32, max_events: u64) -> Self {
+        Self {
+            events,
+            jobs: Arc::new(Mutex::new(HashMap::new())),
+            max_rate_per_sec: max_rate_per_sec.max(1),
+            max_events,
+        }
+    }
+
+    /// Start replaying the events of a range into a function of a user, in the background.
+    ///
+    /// `release` is the version to run instead of the current code, and `environment` the
+    /// environment it is released to, whose configuration and secrets it gets.
+    pub fn start(
+        &self,
+        function_service: FunctionService,
+        user_id: Uuid,
+        function_id: Uuid,
+        request: &CreateReplayRequest,
+        environment: Option<Environment>,
+        release: Option<FunctionVersion>,
+    ) -> Result<Replay, ApiError> {
+        if request.from >= request.to {
+            return Err(ApiError::Validation(
+                "Replay range start must be before its end".to_string(),
+            ));
+        }
+        if request.from.timestamp_millis() < 0 {
+            return Err(ApiError::Validation(
+                "Replay range cannot start before 1970".to_string(),
+            ));
+        }
+
+        let now = Utc::now();
+        let replay = Replay {
+            id: Uuid::new_v4(),
+            function_id,
+            contract: r3e_store::normalize_contract(&request.contract),
+            from: request.from,
+            to: request.to,
+            version: release.as_ref().map(|release| release.version),
+            environment: environment.as_ref().map(|env| env.name.clone()),
+            rate_per_sec: request
+                .rate_per_sec
+                .unwrap_or(self.max_rate_per_sec)
+                .clamp(1, self.max_rate_per_sec),
+            status: ReplayStatus::Running,
+            replayed: 0,
+            failed: 0,
+            error: None,
+            created_at: now,
+            finished_at: None,
+        };
+        let cancelled = Arc::new(AtomicBool::new(false));
+
+        {
+            let mut jobs = self.jobs.lock().unwrap();
+            jobs.retain(|_, job| {
+                job.replay.finished_at.map_or(true, |finished_at| {
+                    (now - finished_at)
+                        .to_std()
+                        .map_or(true, |age| age < FINISHED_REPLAY_TTL)
+                })
+            });
+
+            let running = jobs
+                .values()
+                .filter(|job| job.user_id == user_id && job.replay.status == ReplayStatus::Running)
+                .count();
+            if running >= MAX_RUNNING_REPLAYS {
+                return Err(ApiError::QuotaExceeded(format!(
+                    "At most {} replays may run at once",
+                    MAX_RUNNING_REPLAYS
+                )));
+            }
+
+            jobs.insert(
+                replay.id,
+                ReplayJob {
+                    replay: replay.clone(),
+                    user_id,
+                    cancelled: cancelled.clone(),
+                },
+            );
+        }
+
+        let store = self.clone();
+        let started = replay.clone();
+        tokio::spawn(async move {
+            let outcome = store
+                .run(
+                    &function_service,
+                    &started,
+                    user_id,
+                    &cancelled,
+                    environment.as_ref(),
+                    release.as_ref(),
+                )
+                .await;
+            store.finish(started.id, outcome);
+        });
+
+        Ok(replay)
+    }
+
+    /// Replay of a function
+    pub fn get(&self, function_id: Uuid, replay_id: Uuid) -> Result<Replay, ApiError> {
+        self.jobs
+            .lock()
+            .unwrap()
+            .get(&replay_id)
+            .filter(|job| job.replay.function_id == function_id)
+            .map(|job| job.replay.clone())
+            .ok_or_else(|| ApiError::NotFound(format!("Replay not found: {}", replay_id)))
+    }
+
+    /// Cancel a running replay of a function, the invocation in flight completes
+    pub fn cancel(&self, function_id: Uuid, replay_id: Uuid) -> Result<Replay, ApiError> {
+        let mut jobs = self.jobs.lock().unwrap();
+        let job = jobs
+            .get_mut(&replay_id)
+            .filter(|job| job.replay.function_id == function_id)
+            .ok_or_else(|| ApiError::NotFound(format!("Replay not found: {}", replay_id)))?;
+        if job.replay.status != ReplayStatus::Running {
+            return Err(ApiError::Conflict(format!(
+                "Replay {} is no longer running",
+                replay_id
+            )));
+        }
+
+        job.cancelled.store(true, Ordering::SeqCst);
+        job.replay.status = ReplayStatus::Cancelled;
+        job.replay.finished_at = Some(Utc::now());
+        Ok(job.replay.clone())
+    }
+
+    /// Invoke the function with the events of the range, page by page
+    async fn run(
+        &self,
+        function_service: &FunctionService,
+        replay: &Replay,
+        user_id: Uuid,
+        cancelled: &AtomicBool,
+        environment: Option<&Environment>,
+        release: Option<&FunctionVersion>,
+    ) -> Result<(), String> {
+        let mut ticker =
+            tokio::time::interval(Duration::from_secs_f64(1.0 / replay.rate_per_sec as f64));
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+        let mut query = EventQuery {
+            contract: replay.contract.clone(),
+            from_ms: replay.from.timestamp_millis() as u64,
+            to_ms: replay.to.timestamp_millis() as u64,
+            after: None,
+            limit: MAX_EVENT_PAGE,
+        };
+        let mut invoked = 0u64;
+        loop {
+            let page = self.events.query(&query).await.map_err(|e| e.to_string())?;
+            for event in page.events {
+                if invoked >= self.max_events {
+                    return Err(format!(
+                        "The range holds more than {} events, replay a shorter one",
+                        self.max_events
+                    ));
+                }
+
+                ticker.tick().await;
+                if cancelled.load(Ordering::SeqCst) {
+                    return Ok(());
+                }
+
+                let input = replay_input(replay, user_id, &event);
+                let succeeded = match function_service
+                    .replay_function(replay.function_id, &input, environment, release)
+                    .await
+                {
+                    Ok(response) => response.status == "success",
+                    Err(e) => {
+                        log::warn!(
+                            "Replay {} failed to invoke {}: {}",
+                            replay.id,
+                            replay.function_id,
+                            e
+                        );
+                        false
+                    }
+                };
+                invoked += 1;
+                self.count(replay.id, succeeded);
+            }
+
+            match page.next {
+                Some(next) => query.after = Some(next),
+                None => return Ok(()),
+            }
+        }
+    }
+
+    /// Count a replayed invocation
+    fn count(&self, replay_id: Uuid, succeeded: bool) {
+        if let Some(job) = self.jobs.lock().unwrap().get_mut(&replay_id) {
+            if succeeded {
+                job.replay.replayed += 1;
+            } else {
+                job.replay.failed += 1;
+            }
+        }
+    }
+
+    /// Record how a replay ended, unless it was cancelled
+    fn finish(&self, replay_id: Uuid, outcome: Result<(), String>) {
+        let mut jobs = self.jobs.lock().unwrap();
+        let Some(job) = jobs.get_mut(&replay_id) else {
+            return;
+        };
+        if job.replay.status != ReplayStatus::Running {
+            return;
+        }
+
+        match outcome {
+            Ok(()) => job.replay.status = ReplayStatus::Completed,
+            Err(error) => {
+                log::warn!("Replay {} failed: {}", replay_id, error);
+                job.replay.status = ReplayStatus::Failed;
+                job.replay.error = Some(error);
+            }
+        }
+        job.replay.finished_at = Some(Utc::now());
+    }
+}
+
+/// Input of a replayed invocation, the callback data of a trigger flagged as a replay
+fn replay_input(replay: &Replay, user_id: Uuid, event: &IndexedEvent) -> serde_json::Value {
+    serde_json::json!({
+        "replay_id": replay.id,
+        "user_id": user_id,
+        "function_id": replay.function_id,
+        "event_data": event.data,
+        "timestamp": event.timestamp_ms / 1000,
+        "replay": true,
+    })
+}
@@ -0,0 +1,588 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+use axum::{
+    extract::{Path, Query, State},
+    routing::{get, post},
+    Json, Router,
+};
+use r3e_secrets::approval::{OperationStatus, PendingOperation, SigningRequest};
+use r3e_secrets::audit::{AuditEvent, AuditEventType, AuditFilter, AuditRecord, AuditVerification};
+use r3e_secrets::envelope::{EnvelopeService, TenantKey, LOCAL_PROVIDER};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use utoipa::{IntoParams, ToSchema};
+use uuid::Uuid;
+
+use crate::auth::Auth;
+use crate::encryption::{self, RekeyReport};
+use crate::error::ApiError;
+use crate::models::user::UserRole;
+use crate::service::ApiService;
+use crate::snapshot::{self, ConflictPolicy, ImportReport, Snapshot, Stores};
+
+/// Export snapshot request
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct ExportSnapshotRequest {
+    /// Hex encoded 32-byte key encrypting the exported secrets
+    pub backup_key: String,
+}
+
+/// Import snapshot request
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct ImportSnapshotRequest {
+    /// Snapshot to restore
+    #[schema(value_type = Object)]
+    pub snapshot: Snapshot,
+
+    /// Hex encoded backup key the snapshot was exported with
+    pub backup_key: String,
+
+    /// Conflict policy, defaults to fail
+    #[serde(default)]
+    pub conflict: ConflictPolicy,
+}
+
+/// Propose operation request
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct ProposeOperationRequest {
+    /// Operation kind, e.g. `quota.set_plan`
+    pub kind: String,
+
+    /// Operation parameters
+    pub params: serde_json::Value,
+}
+
+/// Approve operation request
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct ApproveOperationRequest {
+    /// Hex encoded approver signature of the operation's signing message
+    pub signature: String,
+}
+
+/// List operations query
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ListOperationsQuery {
+    /// Status
+    #[param(value_type = Option<String>, example = "pending")]
+    pub status: Option<OperationStatus>,
+}
+
+/// Enroll tenant request
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct EnrollTenantRequest {
+    /// Provider holding the tenant's KEK, defaults to `local`
+    pub provider: Option<String>,
+}
+
+/// Tenant encryption response
+#[derive(Debug, Serialize, ToSchema)]
+pub struct TenantEncryptionResponse {
+    /// Tenant key
+    #[schema(value_type = Object)]
+    pub key: TenantKey,
+
+    /// Re-keying of the tenant's function code
+    pub report: RekeyReport,
+}
+
+/// Default number of audit records returned by a query
+const DEFAULT_AUDIT_LIMIT: usize = 100;
+
+/// Maximum number of audit records returned by a query
+const MAX_AUDIT_LIMIT: usize = 1000;
+
+/// Import snapshot response
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ImportSnapshotResponse {
+    /// Snapshot format version
+    pub version: u32,
+
+    /// Import report
+    pub report: ImportReport,
+}
+
+fn require_admin(auth: &Auth) -> Result<(), ApiError> {
+    if auth.user.role != UserRole::Admin {
+        return Err(ApiError::Authorization(
+            "Only admins can perform this action".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Record an admin action in the audit log before performing it
+async fn audit_admin_action(
+    api_service: &ApiService,
+    auth: &Auth,
+    details: String,
+) -> Result<(), ApiError> {
+    let event = AuditEvent::new(
+        AuditEventType::AdminAction,
+        auth.user.id.to_string(),
+        None,
+        None,
+        details,
+        None,
+        None,
+    );
+
+    api_service
+        .audit_store
+        .append(event)
+        .await
+        .map_err(|e| ApiError::Server(format!("Failed to record audit event: {}", e)))?;
+    Ok(())
+}
+
+/// Open the secret store the snapshots cover, along with the balance store the API holds open
+async fn snapshot_stores(api_service: &ApiService) -> Result<Stores, ApiError> {
+    let config = &api_service.config;
+    let vault = match (config.secrets_in_vault, &config.vault) {
+        (true, Some(vault)) => Some(vault),
+        (true, None) => {
+            return Err(ApiError::Server(
+                "SECRETS_BACKEND=vault requires VAULT_ADDR and VAULT_TOKEN".to_string(),
+            ))
+        }
+        (false, _) => None,
+    };
+
+    Ok(Stores {
+        secrets: Stores::open_secrets(&config.secrets_db_path, vault).await?,
+        balances: api_service.balance_storage.clone(),
+    })
+}
+
+/// Export snapshot handler
+#[utoipa::path(
+    post,
+    path = "/admin/snapshot/export",
+    tag = "admin",
+    request_body = ExportSnapshotRequest,
+    responses((status = 200, description = "Platform snapshot", body = Object)),
+    security(("bearer" = []))
+)]
+async fn export_snapshot(
+    State(api_service): State<Arc<ApiService>>,
+    auth: Auth,
+    Json(request): Json<ExportSnapshotRequest>,
+) -> Result<Json<Snapshot>, ApiError> {
+    require_admin(&auth)?;
+
+    let backup_key = snapshot::parse_key(&request.backup_key)?;
+    let stores = snapshot_stores(&api_service).await?;
+
+    log::info!("User {} exporting platform snapshot", auth.user.id);
+    audit_admin_action(
+        &api_service,
+        &auth,
+        "Exported platform snapshot".to_string(),
+    )
+    .await?;
+    let snapshot = snapshot::export(&api_service.db, &stores, &backup_key).await?;
+
+    Ok(Json(snapshot))
+}
+
+/// Import snapshot handler
+#[utoipa::path(
+    post,
+    path = "/admin/snapshot/import",
+    tag = "admin",
+    request_body = ImportSnapshotRequest,
+    responses((status = 200, description = "Import report", body = ImportSnapshotResponse)),
+    security(("bearer" = []))
+)]
+async fn import_snapshot(
+    State(api_service): State<Arc<ApiService>>,
+    auth: Auth,
+    Json(request): Json<ImportSnapshotRequest>,
+) -> Result<Json<ImportSnapshotResponse>, ApiError> {
+    require_admin(&auth)?;
+
+    let backup_key = snapshot::parse_key(&request.backup_key)?;
+    let stores = snapshot_stores(&api_service).await?;
+
+    log::info!(
+        "User {} importing platform snapshot from {} with conflict policy {:?}",
+        auth.user.id,
+        request.snapshot.created_at,
+        request.conflict
+    );
+    audit_admin_action(
+        &api_service,
+        &auth,
+        format!(
+            "Imported platform snapshot from {} with conflict policy {:?}",
+            request.snapshot.created_at, request.conflict
+        ),
+    )
+    .await?;
+    let report = snapshot::import(
+        &api_service.db,
+        &stores,
+        &request.snapshot,
+        &backup_key,
+        request.conflict,
+    )
+    .await?;
+
+    // Index what is stored now, skipped records keep their existing content
+    for function in &request.snapshot.functions {
+        let function = api_service
+            .function_service
+            .get_function(function.id)
+            .await?;
+        api_service.search_index.index_function(&function);
+    }
+    for service in &request.snapshot.services {
+        let service = api_service.service_service.get_service(service.id).await?;
+        api_service.search_index.index_service(&service);
+    }
+
+    Ok(Json(ImportSnapshotResponse {
+        version: request.snapshot.version,
+        report,
+    }))
+}
+
+/// Query audit log handler, newest records first
+#[utoipa::path(
+    get,
+    path = "/admin/audit",
+    tag = "admin",
+    params(
+        ("user_id" = Option<String>, Query, description = "User ID"),
+        ("function_id" = Option<String>, Query, description = "Function ID"),
+        ("secret_id" = Option<String>, Query, description = "Secret ID"),
+        ("event_type" = Option<String>, Query, description = "Event type"),
+        ("request_id" = Option<String>, Query, description = "Request ID"),
+        ("from" = Option<u64>, Query, description = "Earliest timestamp, inclusive"),
+        ("to" = Option<u64>, Query, description = "Latest timestamp, inclusive"),
+        ("limit" = Option<usize>, Query, description = "Maximum number of records")
+    ),
+    responses((status = 200, description = "Audit records, newest first", body = Vec<Object>)),
+    security(("bearer" = []))
+)]
+async fn query_audit_log(
+    State(api_service): State<Arc<ApiService>>,
+    auth: Auth,
+    Query(mut filter): Query<AuditFilter>,
+) -> Result<Json<Vec<AuditRecord>>, ApiError> {
+    require_admin(&auth)?;
+
+    filter.limit = Some(
+        filter
+            .limit
+            .unwrap_or(DEFAULT_AUDIT_LIMIT)
+            .min(MAX_AUDIT_LIMIT),
+    );
+    let records = api_service
+        .audit_store
+        .query(&filter)
+        .await
+        .map_err(|e| ApiError::Server(format!("Failed to query audit log: {}", e)))?;
+
+    Ok(Json(records))
+}
+
+/// Verify audit log handler
+#[utoipa::path(
+    get,
+    path = "/admin/audit/verify",
+    tag = "admin",
+    responses((status = 200, description = "Hash chain verification", body = Object)),
+    security(("bearer" = []))
+)]
+async fn verify_audit_log(
+    State(api_service): State<Arc<ApiService>>,
+    auth: Auth,
+) -> Result<Json<AuditVerification>, ApiError> {
+    require_admin(&auth)?;
+
+    let verification = api_service
+        .audit_store
+        .verify()
+        .await
+        .map_err(|e| ApiError::Server(format!("Failed to verify audit log: {}", e)))?;
+    if !verification.valid {
+        log::warn!(
+            "Audit log verification failed at record {:?}: {:?}",
+            verification.first_invalid,
+            verification.error
+        );
+    }
+
+    Ok(Json(verification))
+}
+
+/// Propose an admin operation for offline approval
+#[utoipa::path(
+    post,
+    path = "/admin/operations",
+    tag = "admin",
+    request_body = ProposeOperationRequest,
+    responses((status = 200, description = "Pending operation", body = Object)),
+    security(("bearer" = []))
+)]
+async fn propose_operation(
+    State(api_service): State<Arc<ApiService>>,
+    auth: Auth,
+    Json(request): Json<ProposeOperationRequest>,
+) -> Result<Json<PendingOperation>, ApiError> {
+    require_admin(&auth)?;
+
+    let operation = api_service
+        .approval_service
+        .propose(&request.kind, request.params, &auth.user.id.to_string())
+        .await?;
+
+    Ok(Json(operation))
+}
+
+/// List admin operations, newest first
+#[utoipa::path(
+    get,
+    path = "/admin/operations",
+    tag = "admin",
+    params(ListOperationsQuery),
+    responses((status = 200, description = "Operations, newest first", body = Vec<Object>)),
+    security(("bearer" = []))
+)]
+async fn list_operations(
+    State(api_service): State<Arc<ApiService>>,
+    auth: Auth,
+    Query(query): Query<ListOperationsQuery>,
+) -> Result<Json<Vec<PendingOperation>>, ApiError> {
+    require_admin(&auth)?;
+
+    let operations = api_service.approval_service.list(query.status).await?;
+
+    Ok(Json(operations))
+}
+
+/// Get an admin operation
+#[utoipa::path(
+    get,
+    path = "/admin/operations/{id}",
+    tag = "admin",
+    params(("id" = String, Path, description = "Operation ID")),
+    responses((status = 200, description = "Operation", body = Object)),
+    security(("bearer" = []))
+)]
+async fn get_operation(
+    State(api_service): State<Arc<ApiService>>,
+    auth: Auth,
+    Path(id): Path<String>,
+) -> Result<Json<PendingOperation>, ApiError> {
+    require_admin(&auth)?;
+
+    let operation = api_service.approval_service.get(&id).await?;
+
+    Ok(Json(operation))
+}
+
+/// Export a pending admin operation for offline signing
+#[utoipa::path(
+    get,
+    path = "/admin/operations/{id}/signing-request",
+    tag = "admin",
+    params(("id" = String, Path, description = "Operation ID")),
+    responses((status = 200, description = "Signing request for offline approval", body = Object)),
+    security(("bearer" = []))
+)]
+async fn export_operation(
+    State(api_service): State<Arc<ApiService>>,
+    auth: Auth,
+    Path(id): Path<String>,
+) -> Result<Json<SigningRequest>, ApiError> {
+    require_admin(&auth)?;
+
+    let request = api_service
+        .approval_service
+        .signing_request(&id, &auth.user.id.to_string())
+        .await?;
+
+    Ok(Json(request))
+}
+
+/// Submit the approver's signature of an admin operation, running it
+#[utoipa::path(
+    post,
+    path = "/admin/operations/{id}/approve",
+    tag = "admin",
+    params(("id" = String, Path, description = "Operation ID")),
+    request_body = ApproveOperationRequest,
+    responses((status = 200, description = "Operation after running", body = Object)),
+    security(("bearer" = []))
+)]
+async fn approve_operation(
+    State(api_service): State<Arc<ApiService>>,
+    auth: Auth,
+    Path(id): Path<String>,
+    Json(request): Json<ApproveOperationRequest>,
+) -> Result<Json<PendingOperation>, ApiError> {
+    require_admin(&auth)?;
+
+    log::info!(
+        "User {} submitting approval of operation {}",
+        auth.user.id,
+        id
+    );
+    let operation = api_service
+        .approval_service
+        .approve(&id, &request.signature, &auth.user.id.to_string())
+        .await?;
+
+    Ok(Json(operation))
+}
+
+/// Cancel a pending admin operation
+#[utoipa::path(
+    post,
+    path = "/admin/operations/{id}/cancel",
+    tag = "admin",
+    params(("id" = String, Path, description = "Operation ID")),
+    responses((status = 200, description = "Cancelled operation", body = Object)),
+    security(("bearer" = []))
+)]
+async fn cancel_operation(
+    State(api_service): State<Arc<ApiService>>,
+    auth: Auth,
+    Path(id): Path<String>,
+) -> Result<Json<PendingOperation>, ApiError> {
+    require_admin(&auth)?;
+
+    let operation = api_service
+        .approval_service
+        .cancel(&id, &auth.user.id.to_string())
+        .await?;
+
+    Ok(Json(operation))
+}
+
+fn envelope_service(api_service: &ApiService) -> Result<&EnvelopeService, ApiError> {
+    api_service.envelope_service.as_deref().ok_or_else(|| {
+        ApiError::Validation(
+            "Encryption at rest requires SECRETS_MASTER_KEY to be configured".to_string(),
+        )
+    })
+}
+
+/// Get the tenant key of a user
+#[utoipa::path(
+    get,
+    path = "/admin/tenants/{user_id}/encryption",
+    tag = "admin",
+    params(("user_id" = Uuid, Path, description = "User ID")),
+    responses((status = 200, description = "Tenant key", body = Object)),
+    security(("bearer" = []))
+)]
+async fn get_tenant_encryption(
+    State(api_service): State<Arc<ApiService>>,
+    auth: Auth,
+    Path(user_id): Path<Uuid>,
+) -> Result<Json<TenantKey>, ApiError> {
+    require_admin(&auth)?;
+
+    let key = envelope_service(&api_service)?
+        .tenant_key(&user_id.to_string())
+        .await?
+        .ok_or_else(|| ApiError::NotFound(format!("User {} is not enrolled", user_id)))?;
+
+    Ok(Json(key))
+}
+
+/// Enroll a user for encryption at rest, sealing the code of their functions
+#[utoipa::path(
+    post,
+    path = "/admin/tenants/{user_id}/encryption",
+    tag = "admin",
+    params(("user_id" = Uuid, Path, description = "User ID")),
+    request_body = EnrollTenantRequest,
+    responses((status = 200, description = "Tenant key and re-keying report", body = TenantEncryptionResponse)),
+    security(("bearer" = []))
+)]
+async fn enroll_tenant(
+    State(api_service): State<Arc<ApiService>>,
+    auth: Auth,
+    Path(user_id): Path<Uuid>,
+    Json(request): Json<EnrollTenantRequest>,
+) -> Result<Json<TenantEncryptionResponse>, ApiError> {
+    require_admin(&auth)?;
+
+    let envelope = envelope_service(&api_service)?;
+    let provider = request.provider.as_deref().unwrap_or(LOCAL_PROVIDER);
+    audit_admin_action(
+        &api_service,
+        &auth,
+        format!(
+            "Enrolled user {} for encryption at rest with {}",
+            user_id, provider
+        ),
+    )
+    .await?;
+    let key = envelope.enroll(&user_id.to_string(), provider).await?;
+    let report = encryption::rekey_function_code(&api_service.db, envelope, user_id).await?;
+
+    Ok(Json(TenantEncryptionResponse { key, report }))
+}
+
+/// Rotate the tenant key of a user, re-keying the code of their functions
+#[utoipa::path(
+    post,
+    path = "/admin/tenants/{user_id}/encryption/rotate",
+    tag = "admin",
+    params(("user_id" = Uuid, Path, description = "User ID")),
+    responses((status = 200, description = "Tenant key and re-keying report", body = TenantEncryptionResponse)),
+    security(("bearer" = []))
+)]
+async fn rotate_tenant_key(
+    State(api_service): State<Arc<ApiService>>,
+    auth: Auth,
+    Path(user_id): Path<Uuid>,
+) -> Result<Json<TenantEncryptionResponse>, ApiError> {
+    require_admin(&auth)?;
+
+    let envelope = envelope_service(&api_service)?;
+    audit_admin_action(
+        &api_service,
+        &auth,
+        format!("Rotated the tenant key of user {}", user_id),
+    )
+    .await?;
+    let key = envelope.rotate(&user_id.to_string()).await?;
+    let report = encryption::rekey_function_code(&api_service.db, envelope, user_id).await?;
+
+    Ok(Json(TenantEncryptionResponse { key, report }))
+}
+
+/// Admin routes
+pub fn admin_routes(api_service: Arc<ApiService>) -> Router {
+    Router::new()
+        .route("/admin/snapshot/export", post(export_snapshot))
+        .route("/admin/snapshot/import", post(import_snapshot))
+        .route("/admin/audit", get(query_audit_log))
+        .route("/admin/audit/verify", get(verify_audit_log))
+        .route("/admin/operations", get(list_operations))
+        .route("/admin/operations", post(propose_operation))
+        .route("/admin/operations/:id", get(get_operation))
+        .route(
+            "/admin/operations/:id/signing-request",
+            get(export_operation),
+        )
+        .route("/admin/operations/:id/approve", post(approve_operation))
+        .route("/admin/operations/:id/cancel", post(cancel_operation))
+        .route(
+            "/admin/tenants/:user_id/encryption",
+            get(get_tenant_encryption),
+        )
+        .route("/admin/tenants/:user_id/encryption", post(enroll_tenant))
+        .route(
+            "/admin/tenants/:user_id/encryption/rotate",
+            post(rotate_tenant_key),
+        )
+        .with_state(api_service)
+}
@@ -0,0 +1,212 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    routing::{get, post},
+    Json, Router,
+};
+use chrono::Utc;
+use r3e_built_in_services::alert_rules::{AlertRule, AlertRuleRequest, RuleEvaluation};
+use std::sync::Arc;
+use uuid::Uuid;
+
+use crate::auth::Auth;
+use crate::authz::authorize_function;
+use crate::error::ApiError;
+use crate::service::ApiService;
+
+/// Check the user owns the function whose alert rules are managed
+async fn authorize(
+    api_service: &ApiService,
+    auth: &Auth,
+    function_id: Uuid,
+) -> Result<(), ApiError> {
+    let function = api_service
+        .function_service
+        .get_function(function_id)
+        .await?;
+    authorize_function(auth, &function, "manage alert rules of")
+}
+
+/// List the alert rules of a function
+#[utoipa::path(
+    get,
+    path = "/functions/{id}/alert-rules",
+    tag = "alerts",
+    params(("id" = Uuid, Path, description = "Function ID")),
+    responses((status = 200, description = "Alert rules", body = Vec<Object>)),
+    security(("bearer" = []))
+)]
+async fn list_alert_rules(
+    State(api_service): State<Arc<ApiService>>,
+    auth: Auth,
+    Path(id): Path<Uuid>,
+) -> Result<Json<Vec<AlertRule>>, ApiError> {
+    authorize(&api_service, &auth, id).await?;
+    let rules = api_service
+        .alert_rule_service
+        .list_rules(&id.to_string())
+        .await?;
+
+    Ok(Json(rules))
+}
+
+/// Create an alert rule of a function
+#[utoipa::path(
+    post,
+    path = "/functions/{id}/alert-rules",
+    tag = "alerts",
+    params(("id" = Uuid, Path, description = "Function ID")),
+    request_body(content = Object, description = "Rule name, condition, severity and whether it is enabled"),
+    responses((status = 201, description = "Alert rule", body = Object)),
+    security(("bearer" = []))
+)]
+async fn create_alert_rule(
+    State(api_service): State<Arc<ApiService>>,
+    auth: Auth,
+    Path(id): Path<Uuid>,
+    Json(request): Json<AlertRuleRequest>,
+) -> Result<(StatusCode, Json<AlertRule>), ApiError> {
+    let function = api_service.function_service.get_function(id).await?;
+    authorize_function(&auth, &function, "manage alert rules of")?;
+
+    // Alerts go to the channels of the function's owner
+    let rule = api_service
+        .alert_rule_service
+        .create_rule(&id.to_string(), &function.user_id.to_string(), request)
+        .await?;
+
+    Ok((StatusCode::CREATED, Json(rule)))
+}
+
+/// Get an alert rule of a function
+#[utoipa::path(
+    get,
+    path = "/functions/{id}/alert-rules/{rule_id}",
+    tag = "alerts",
+    params(
+        ("id" = Uuid, Path, description = "Function ID"),
+        ("rule_id" = String, Path, description = "Alert rule ID")
+    ),
+    responses((status = 200, description = "Alert rule", body = Object)),
+    security(("bearer" = []))
+)]
+async fn get_alert_rule(
+    State(api_service): State<Arc<ApiService>>,
+    auth: Auth,
+    Path((id, rule_id)): Path<(Uuid, String)>,
+) -> Result<Json<AlertRule>, ApiError> {
+    authorize(&api_service, &auth, id).await?;
+    let rule = api_service
+        .alert_rule_service
+        .get_rule(&id.to_string(), &rule_id)
+        .await?;
+
+    Ok(Json(rule))
+}
+
+/// Replace the settings of an alert rule
+#[utoipa::path(
+    put,
+    path = "/functions/{id}/alert-rules/{rule_id}",
+    tag = "alerts",
+    params(
+        ("id" = Uuid, Path, description = "Function ID"),
+        ("rule_id" = String, Path, description = "Alert rule ID")
+    ),
+    request_body(content = Object, description = "Rule name, condition, severity and whether it is enabled"),
+    responses((status = 200, description = "Alert rule", body = Object)),
+    security(("bearer" = []))
+)]
+async fn update_alert_rule(
+    State(api_service): State<Arc<ApiService>>,
+    auth: Auth,
+    Path((id, rule_id)): Path<(Uuid, String)>,
+    Json(request): Json<AlertRuleRequest>,
+) -> Result<Json<AlertRule>, ApiError> {
+    authorize(&api_service, &auth, id).await?;
+    let rule = api_service
+        .alert_rule_service
+        .update_rule(&id.to_string(), &rule_id, request)
+        .await?;
+
+    Ok(Json(rule))
+}
+
+/// Delete an alert rule
+#[utoipa::path(
+    delete,
+    path = "/functions/{id}/alert-rules/{rule_id}",
+    tag = "alerts",
+    params(
+        ("id" = Uuid, Path, description = "Function ID"),
+        ("rule_id" = String, Path, description = "Alert rule ID")
+    ),
+    responses((status = 204, description = "Alert rule deleted")),
+    security(("bearer" = []))
+)]
+async fn delete_alert_rule(
+    State(api_service): State<Arc<ApiService>>,
+    auth: Auth,
+    Path((id, rule_id)): Path<(Uuid, String)>,
+) -> Result<StatusCode, ApiError> {
+    authorize(&api_service, &auth, id).await?;
+    api_service
+        .alert_rule_service
+        .delete_rule(&id.to_string(), &rule_id)
+        .await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Work out the metric of an alert rule now, without alerting
+#[utoipa::path(
+    post,
+    path = "/functions/{id}/alert-rules/{rule_id}/evaluate",
+    tag = "alerts",
+    params(
+        ("id" = Uuid, Path, description = "Function ID"),
+        ("rule_id" = String, Path, description = "Alert rule ID")
+    ),
+    responses((status = 200, description = "Value of the metric and whether the rule fires", body = Object)),
+    security(("bearer" = []))
+)]
+async fn evaluate_alert_rule(
+    State(api_service): State<Arc<ApiService>>,
+    auth: Auth,
+    Path((id, rule_id)): Path<(Uuid, String)>,
+) -> Result<Json<RuleEvaluation>, ApiError> {
+    authorize(&api_service, &auth, id).await?;
+    let rule = api_service
+        .alert_rule_service
+        .get_rule(&id.to_string(), &rule_id)
+        .await?;
+    let evaluation = api_service
+        .alert_rule_service
+        .evaluate(&rule, Utc::now().timestamp() as u64)
+        .await?;
+
+    Ok(Json(evaluation))
+}
+
+/// Alert rule routes
+pub fn alert_rule_routes(api_service: Arc<ApiService>) -> Router {
+    Router::new()
+        .route(
+            "/functions/:id/alert-rules",
+            get(list_alert_rules).post(create_alert_rule),
+        )
+        .route(
+            "/functions/:id/alert-rules/:rule_id",
+            get(get_alert_rule)
+                .put(update_alert_rule)
+                .delete(delete_alert_rule),
+        )
+        .route(
+            "/functions/:id/alert-rules/:rule_id/evaluate",
+            post(evaluate_alert_rule),
+        )
+        .with_state(api_service)
+}
@@ -0,0 +1,133 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+use axum::{
+    extract::{Path, Query, State},
+    routing::get,
+    Json, Router,
+};
+use chrono::Utc;
+use r3e_store::{Granularity, UsageAggregate, UsageSummary};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use utoipa::{IntoParams, ToSchema};
+use uuid::Uuid;
+
+use crate::auth::Auth;
+use crate::authz::authorize_function;
+use crate::error::ApiError;
+use crate::service::ApiService;
+
+/// Maximum number of buckets returned by a query
+const MAX_BUCKETS: u64 = 1000;
+
+/// Function analytics query
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct AnalyticsQuery {
+    /// Bucket width, `hour` (default) or `day`
+    #[param(value_type = Option<String>)]
+    pub granularity: Option<Granularity>,
+
+    /// Range start (secs since epoch), one day or 30 days back by default
+    pub from: Option<u64>,
+
+    /// Range end (secs since epoch, exclusive), now by default
+    pub to: Option<u64>,
+}
+
+/// Function analytics response
+#[derive(Debug, Serialize, ToSchema)]
+pub struct FunctionAnalyticsResponse {
+    /// Function ID
+    pub function_id: Uuid,
+
+    /// Bucket width
+    #[schema(value_type = String, example = "hour")]
+    pub granularity: Granularity,
+
+    /// Range start (secs since epoch)
+    pub from: u64,
+
+    /// Range end (secs since epoch)
+    pub to: u64,
+
+    /// Usage of the whole range
+    #[schema(value_type = Object)]
+    pub total: UsageSummary,
+
+    /// Usage per bucket with invocations, oldest first
+    #[schema(value_type = Vec<Object>)]
+    pub buckets: Vec<UsageSummary>,
+}
+
+/// Usage of a function over a time range, for dashboards.
+///
+/// Invocations show up once the minutely rollup has flushed them.
+#[utoipa::path(
+    get,
+    path = "/functions/{id}/analytics",
+    tag = "analytics",
+    params(("id" = Uuid, Path, description = "Function ID"), AnalyticsQuery),
+    responses((status = 200, description = "Usage of the function", body = FunctionAnalyticsResponse)),
+    security(("bearer" = []))
+)]
+async fn get_function_analytics(
+    State(api_service): State<Arc<ApiService>>,
+    auth: Auth,
+    Path(id): Path<Uuid>,
+    Query(query): Query<AnalyticsQuery>,
+) -> Result<Json<FunctionAnalyticsResponse>, ApiError> {
+    // Check if the user owns the function
+    let function = api_service.function_service.get_function(id).await?;
+    authorize_function(&auth, &function, "view analytics for")?;
+
+    let granularity = query.granularity.unwrap_or(Granularity::Hour);
+    let to = query.to.unwrap_or_else(|| Utc::now().timestamp() as u64);
+    let from = query.from.unwrap_or_else(|| {
+        let span = match granularity {
+            Granularity::Hour => Granularity::Day.seconds(),
+            Granularity::Day => 30 * Granularity::Day.seconds(),
+        };
+        to.saturating_sub(span)
+    });
+    if from >= to {
+        return Err(ApiError::Validation("from must be before to".to_string()));
+    }
+
+    // Widen the range to whole buckets
+    let from = granularity.bucket_start(from);
+    if (to - from).div_ceil(granularity.seconds()) > MAX_BUCKETS {
+        return Err(ApiError::Validation(format!(
+            "Time range spans more than {} buckets of one {}",
+            MAX_BUCKETS,
+            granularity.as_str()
+        )));
+    }
+
+    let aggregates = api_service
+        .analytics_store
+        .query(&id.to_string(), granularity, from, to)
+        .await?;
+
+    let mut total = UsageAggregate::new(id.to_string(), granularity, from);
+    for aggregate in &aggregates {
+        total.merge(aggregate);
+    }
+
+    Ok(Json(FunctionAnalyticsResponse {
+        function_id: id,
+        granularity,
+        from,
+        to,
+        total: total.summary(),
+        buckets: aggregates.iter().map(UsageAggregate::summary).collect(),
+    }))
+}
+
+/// Analytics routes
+pub fn analytics_routes(api_service: Arc<ApiService>) -> Router {
+    Router::new()
+        .route("/functions/:id/analytics", get(get_function_analytics))
+        .with_state(api_service)
+}
@@ -2,8 +2,10 @@
 // All Rights Reserved
 
 use axum::{
-    extract::{Path, State},
-    routing::{get, post},
+    extract::{Path, Query, State},
+    http::HeaderMap,
+    response::Redirect,
+    routing::{delete, get, post},
     Json, Router,
 };
 use std::sync::Arc;
@@ -11,13 +13,25 @@ use uuid::Uuid;
 use validator::Validate;
 
 use crate::auth::Auth;
+use crate::authz::{authorize_role_change, authorize_user};
 use crate::error::ApiError;
+use crate::models::session::{RevokeSessionsResponse, SessionInfo};
 use crate::models::user::{
-    CreateUserRequest, LoginRequest, LoginResponse, UpdateUserRequest, User, UserProfile, UserRole,
+    ApiKeyResponse, AuthProvidersResponse, CreateUserRequest, LoginRequest, LoginResponse,
+    OidcCallbackQuery, UpdateUserRequest, User, UserProfile,
 };
+use crate::oidc::OidcProvider;
 use crate::service::ApiService;
+use crate::sessions::{DeviceInfo, RevocationReason};
 
 /// Register a new user
+#[utoipa::path(
+    post,
+    path = "/auth/register",
+    tag = "auth",
+    request_body = CreateUserRequest,
+    responses((status = 200, description = "Registered user", body = UserProfile))
+)]
 async fn register(
     State(api_service): State<Arc<ApiService>>,
     Json(request): Json<CreateUserRequest>,
@@ -43,8 +57,16 @@ async fn register(
 }
 
 /// Login a user
+#[utoipa::path(
+    post,
+    path = "/auth/login",
+    tag = "auth",
+    request_body = LoginRequest,
+    responses((status = 200, description = "Access token of the new session", body = LoginResponse))
+)]
 async fn login(
     State(api_service): State<Arc<ApiService>>,
+    headers: HeaderMap,
     Json(request): Json<LoginRequest>,
 ) -> Result<Json<LoginResponse>, ApiError> {
     // Validate the request
@@ -55,7 +77,11 @@ async fn login(
     // Login the user
     let (user, token) = api_service
         .auth_service
-        .login(&request.username_or_email, &request.password)
+        .login(
+            &request.username_or_email,
+            &request.password,
+            &DeviceInfo::from_headers(&headers),
+        )
         .await?;
 
     // Return the login response
@@ -67,24 +93,237 @@ async fn login(
     }))
 }
 
+/// List the login methods
+#[utoipa::path(
+    get,
+    path = "/auth/providers",
+    tag = "auth",
+    responses((status = 200, description = "Login methods", body = AuthProvidersResponse))
+)]
+async fn providers(State(api_service): State<Arc<ApiService>>) -> Json<AuthProvidersResponse> {
+    Json(AuthProvidersResponse {
+        password: true,
+        oidc_issuer: api_service
+            .oidc_provider
+            .as_ref()
+            .map(|provider| provider.config().issuer.clone()),
+    })
+}
+
+/// OIDC provider, if OIDC login is enabled
+fn oidc_provider(api_service: &ApiService) -> Result<&OidcProvider, ApiError> {
+    api_service
+        .oidc_provider
+        .as_deref()
+        .ok_or_else(|| ApiError::NotFound("OIDC login is not enabled".to_string()))
+}
+
+/// Start an OIDC login, redirecting to the identity provider
+#[utoipa::path(
+    get,
+    path = "/auth/oidc/login",
+    tag = "auth",
+    responses((status = 303, description = "Redirect to the identity provider"))
+)]
+async fn oidc_login(State(api_service): State<Arc<ApiService>>) -> Result<Redirect, ApiError> {
+    let redirect = oidc_provider(&api_service)?.begin_login().await?;
+
+    Ok(Redirect::to(&redirect.url))
+}
+
+/// Finish an OIDC login the identity provider redirected back from
+#[utoipa::path(
+    get,
+    path = "/auth/oidc/callback",
+    tag = "auth",
+    params(OidcCallbackQuery),
+    responses((status = 200, description = "Access token of the new session", body = LoginResponse))
+)]
+async fn oidc_callback(
+    State(api_service): State<Arc<ApiService>>,
+    headers: HeaderMap,
+    Query(query): Query<OidcCallbackQuery>,
+) -> Result<Json<LoginResponse>, ApiError> {
+    let provider = oidc_provider(&api_service)?;
+
+    if let Some(error) = query.error {
+        return Err(ApiError::Authentication(format!(
+            "Identity provider denied the login: {}",
+            query.error_description.unwrap_or(error)
+        )));
+    }
+    let (Some(code), Some(state)) = (query.code, query.state) else {
+        return Err(ApiError::Validation(
+            "Missing code or state in OIDC callback".to_string(),
+        ));
+    };
+
+    // Provision or look up the user, then start a session like a password login
+    let user = provider
+        .complete_login(&api_service.auth_service, &code, &state)
+        .await?;
+    let access_token = api_service
+        .auth_service
+        .start_session(&user, &DeviceInfo::from_headers(&headers))
+        .await?;
+
+    Ok(Json(LoginResponse {
+        user,
+        access_token,
+        token_type: "Bearer".to_string(),
+        expires_in: api_service.config.jwt_expiration,
+    }))
+}
+
+/// Logout, revoking the session of the token
+#[utoipa::path(
+    post,
+    path = "/auth/logout",
+    tag = "auth",
+    responses((status = 200, description = "Session revoked")),
+    security(("bearer" = []))
+)]
+async fn logout(
+    State(api_service): State<Arc<ApiService>>,
+    auth: Auth,
+) -> Result<Json<()>, ApiError> {
+    api_service
+        .auth_service
+        .sessions()
+        .revoke(auth.user.id, auth.session_id, RevocationReason::Logout)
+        .await?;
+
+    Ok(Json(()))
+}
+
+/// List the active sessions of the current user
+#[utoipa::path(
+    get,
+    path = "/auth/sessions",
+    tag = "auth",
+    responses((status = 200, description = "Active sessions", body = Vec<SessionInfo>)),
+    security(("bearer" = []))
+)]
+async fn list_sessions(
+    State(api_service): State<Arc<ApiService>>,
+    auth: Auth,
+) -> Result<Json<Vec<SessionInfo>>, ApiError> {
+    let sessions = api_service
+        .auth_service
+        .sessions()
+        .list(auth.user.id)
+        .await?;
+
+    Ok(Json(
+        sessions
+            .into_iter()
+            .map(|session| SessionInfo::new(session, auth.session_id))
+            .collect(),
+    ))
+}
+
+/// Revoke a session of the current user
+#[utoipa::path(
+    delete,
+    path = "/auth/sessions/{session_id}",
+    tag = "auth",
+    params(("session_id" = Uuid, Path, description = "Session ID")),
+    responses((status = 200, description = "Session revoked")),
+    security(("bearer" = []))
+)]
+async fn revoke_session(
+    State(api_service): State<Arc<ApiService>>,
+    auth: Auth,
+    Path(session_id): Path<Uuid>,
+) -> Result<Json<()>, ApiError> {
+    let reason = if session_id == auth.session_id {
+        RevocationReason::Logout
+    } else {
+        RevocationReason::Revoked
+    };
+    api_service
+        .auth_service
+        .sessions()
+        .revoke(auth.user.id, session_id, reason)
+        .await?;
+
+    Ok(Json(()))
+}
+
+/// Revoke every other session of the current user
+#[utoipa::path(
+    delete,
+    path = "/auth/sessions",
+    tag = "auth",
+    responses((status = 200, description = "Revoked sessions", body = RevokeSessionsResponse)),
+    security(("bearer" = []))
+)]
+async fn revoke_other_sessions(
+    State(api_service): State<Arc<ApiService>>,
+    auth: Auth,
+) -> Result<Json<RevokeSessionsResponse>, ApiError> {
+    let revoked = api_service
+        .auth_service
+        .sessions()
+        .revoke_all(
+            auth.user.id,
+            RevocationReason::Revoked,
+            Some(auth.session_id),
+        )
+        .await?;
+
+    Ok(Json(RevokeSessionsResponse { revoked }))
+}
+
 /// Get the current user
+#[utoipa::path(
+    get,
+    path = "/auth/me",
+    tag = "auth",
+    responses((status = 200, description = "Current user", body = UserProfile)),
+    security(("bearer" = []))
+)]
 async fn me(auth: Auth) -> Result<Json<UserProfile>, ApiError> {
     // Return the user profile
     Ok(Json(UserProfile::from(auth.user)))
 }
 
+/// Create an API key for the current user, replacing the previous one
+#[utoipa::path(
+    post,
+    path = "/auth/api-key",
+    tag = "auth",
+    responses((status = 200, description = "New API key", body = ApiKeyResponse)),
+    security(("bearer" = []))
+)]
+async fn create_api_key(
+    State(api_service): State<Arc<ApiService>>,
+    auth: Auth,
+) -> Result<Json<ApiKeyResponse>, ApiError> {
+    let api_key = api_service
+        .auth_service
+        .create_api_key(auth.user.id)
+        .await?;
+
+    Ok(Json(ApiKeyResponse { api_key }))
+}
+
 /// Get a user by ID
+#[utoipa::path(
+    get,
+    path = "/users/{id}",
+    tag = "users",
+    params(("id" = Uuid, Path, description = "User ID")),
+    responses((status = 200, description = "User", body = UserProfile)),
+    security(("bearer" = []))
+)]
 async fn get_user(
     State(api_service): State<Arc<ApiService>>,
     auth: Auth,
     Path(id): Path<Uuid>,
 ) -> Result<Json<UserProfile>, ApiError> {
     // Check if the user is an admin or the user is getting their own profile
-    if auth.user.role != UserRole::Admin && auth.user.id != id {
-        return Err(ApiError::Authorization(
-            "You are not authorized to view this user".to_string(),
-        ));
-    }
+    authorize_user(&auth, id, "view")?;
 
     // Get the user
     let user = api_service.auth_service.get_user_by_id(id).await?;
@@ -94,6 +333,15 @@ async fn get_user(
 }
 
 /// Update a user
+#[utoipa::path(
+    post,
+    path = "/users/{id}",
+    tag = "users",
+    params(("id" = Uuid, Path, description = "User ID")),
+    request_body = UpdateUserRequest,
+    responses((status = 200, description = "Updated user", body = UserProfile)),
+    security(("bearer" = []))
+)]
 async fn update_user(
     State(api_service): State<Arc<ApiService>>,
     auth: Auth,
@@ -106,18 +354,10 @@ async fn update_user(
         .map_err(|e| ApiError::Validation(e.to_string()))?;
 
     // Check if the user is an admin or the user is updating their own profile
-    if auth.user.role != UserRole::Admin && auth.user.id != id {
-        return Err(ApiError::Authorization(
-            "You are not authorized to update this user".to_string(),
-        ));
-    }
+    authorize_user(&auth, id, "update")?;
 
     // Check if a non-admin user is trying to change their role
-    if auth.user.role != UserRole::Admin && request.role.is_some() {
-        return Err(ApiError::Authorization(
-            "You are not authorized to change your role".to_string(),
-        ));
-    }
+    authorize_role_change(&auth, request.role)?;
 
     // Update the user
     let user = api_service
@@ -135,18 +375,88 @@ async fn update_user(
     Ok(Json(UserProfile::from(user)))
 }
 
+/// List the active sessions of a user
+#[utoipa::path(
+    get,
+    path = "/users/{id}/sessions",
+    tag = "users",
+    params(("id" = Uuid, Path, description = "User ID")),
+    responses((status = 200, description = "Active sessions", body = Vec<SessionInfo>)),
+    security(("bearer" = []))
+)]
+async fn list_user_sessions(
+    State(api_service): State<Arc<ApiService>>,
+    auth: Auth,
+    Path(id): Path<Uuid>,
+) -> Result<Json<Vec<SessionInfo>>, ApiError> {
+    // Check if the user is an admin or the user is listing their own sessions
+    authorize_user(&auth, id, "view")?;
+
+    let sessions = api_service.auth_service.sessions().list(id).await?;
+
+    Ok(Json(
+        sessions
+            .into_iter()
+            .map(|session| SessionInfo::new(session, auth.session_id))
+            .collect(),
+    ))
+}
+
+/// Log a user out of every session
+#[utoipa::path(
+    delete,
+    path = "/users/{id}/sessions",
+    tag = "users",
+    params(("id" = Uuid, Path, description = "User ID")),
+    responses((status = 200, description = "Revoked sessions", body = RevokeSessionsResponse)),
+    security(("bearer" = []))
+)]
+async fn revoke_user_sessions(
+    State(api_service): State<Arc<ApiService>>,
+    auth: Auth,
+    Path(id): Path<Uuid>,
+) -> Result<Json<RevokeSessionsResponse>, ApiError> {
+    // Check if the user is an admin or the user is logging themselves out
+    authorize_user(&auth, id, "log out")?;
+
+    let reason = if auth.user.id != id {
+        RevocationReason::AdminForced
+    } else {
+        RevocationReason::Logout
+    };
+    let revoked = api_service
+        .auth_service
+        .sessions()
+        .revoke_all(id, reason, None)
+        .await?;
+    if reason == RevocationReason::AdminForced {
+        log::info!(
+            "Admin {} revoked {} sessions of user {}",
+            auth.user.id,
+            revoked,
+            id
+        );
+    }
+
+    Ok(Json(RevokeSessionsResponse { revoked }))
+}
+
 /// Delete a user
+#[utoipa::path(
+    delete,
+    path = "/users/{id}",
+    tag = "users",
+    params(("id" = Uuid, Path, description = "User ID")),
+    responses((status = 200, description = "User deleted")),
+    security(("bearer" = []))
+)]
 async fn delete_user(
     State(api_service): State<Arc<ApiService>>,
     auth: Auth,
     Path(id): Path<Uuid>,
 ) -> Result<Json<()>, ApiError> {
     // Check if the user is an admin or the user is deleting their own profile
-    if auth.user.role != UserRole::Admin && auth.user.id != id {
-        return Err(ApiError::Authorization(
-            "You are not authorized to delete this user".to_string(),
-        ));
-    }
+    authorize_user(&auth, id, "delete")?;
 
     // Delete the user
     api_service.auth_service.delete_user(id).await?;
@@ -160,9 +470,19 @@ pub fn auth_routes(api_service: Arc<ApiService>) -> Router {
     Router::new()
         .route("/auth/register", post(register))
         .route("/auth/login", post(login))
+        .route("/auth/providers", get(providers))
+        .route("/auth/oidc/login", get(oidc_login))
+        .route("/auth/oidc/callback", get(oidc_callback))
+        .route("/auth/logout", post(logout))
+        .route("/auth/sessions", get(list_sessions))
+        .route("/auth/sessions", delete(revoke_other_sessions))
+        .route("/auth/sessions/:session_id", delete(revoke_session))
         .route("/auth/me", get(me))
+        .route("/auth/api-key", post(create_api_key))
         .route("/users/:id", get(get_user))
         .route("/users/:id", post(update_user))
-        .route("/users/:id", axum::routing::delete(delete_user))
+        .route("/users/:id", delete(delete_user))
+        .route("/users/:id/sessions", get(list_user_sessions))
+        .route("/users/:id/sessions", delete(revoke_user_sessions))
         .with_state(api_service)
 }
@@ -0,0 +1,146 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+use axum::{
+    extract::{Path, State},
+    routing::{get, post},
+    Json, Router,
+};
+use r3e_built_in_services::billing::{BillingAccount, Invoice};
+use serde::Deserialize;
+use std::sync::Arc;
+use utoipa::ToSchema;
+
+use crate::auth::Auth;
+use crate::error::ApiError;
+use crate::models::user::UserRole;
+use crate::service::ApiService;
+
+/// Add payer request
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct AddPayerRequest {
+    /// Address the user pays invoices from
+    pub address: String,
+}
+
+/// Get the billing account of the current user
+#[utoipa::path(
+    get,
+    path = "/billing/account",
+    tag = "billing",
+    responses((status = 200, description = "Billing account", body = Object)),
+    security(("bearer" = []))
+)]
+async fn get_account(
+    State(api_service): State<Arc<ApiService>>,
+    auth: Auth,
+) -> Result<Json<BillingAccount>, ApiError> {
+    let account = api_service
+        .billing_service
+        .get_account(&auth.user.id.to_string())
+        .await?;
+
+    Ok(Json(account))
+}
+
+/// Register an address the current user pays invoices from
+#[utoipa::path(
+    post,
+    path = "/billing/payers",
+    tag = "billing",
+    request_body = AddPayerRequest,
+    responses((status = 200, description = "Billing account", body = Object)),
+    security(("bearer" = []))
+)]
+async fn add_payer(
+    State(api_service): State<Arc<ApiService>>,
+    auth: Auth,
+    Json(request): Json<AddPayerRequest>,
+) -> Result<Json<BillingAccount>, ApiError> {
+    let account = api_service
+        .billing_service
+        .add_payer(&auth.user.id.to_string(), &request.address)
+        .await?;
+
+    Ok(Json(account))
+}
+
+/// List the invoices of the current user
+#[utoipa::path(
+    get,
+    path = "/billing/invoices",
+    tag = "billing",
+    responses((status = 200, description = "Invoices", body = Vec<Object>)),
+    security(("bearer" = []))
+)]
+async fn list_invoices(
+    State(api_service): State<Arc<ApiService>>,
+    auth: Auth,
+) -> Result<Json<Vec<Invoice>>, ApiError> {
+    let invoices = api_service
+        .billing_service
+        .list_invoices(&auth.user.id.to_string())
+        .await?;
+
+    Ok(Json(invoices))
+}
+
+/// Get an invoice of the current user
+#[utoipa::path(
+    get,
+    path = "/billing/invoices/{id}",
+    tag = "billing",
+    params(("id" = String, Path, description = "Invoice ID")),
+    responses((status = 200, description = "Invoice", body = Object)),
+    security(("bearer" = []))
+)]
+async fn get_invoice(
+    State(api_service): State<Arc<ApiService>>,
+    auth: Auth,
+    Path(id): Path<String>,
+) -> Result<Json<Invoice>, ApiError> {
+    let invoice = api_service.billing_service.get_invoice(&id).await?;
+
+    // Other users' invoices are reported as missing
+    if invoice.tenant != auth.user.id.to_string() && auth.user.role != UserRole::Admin {
+        return Err(ApiError::NotFound(format!("Invoice not found: {}", id)));
+    }
+
+    Ok(Json(invoice))
+}
+
+/// Run the billing cycle now instead of waiting for the hourly run
+#[utoipa::path(
+    post,
+    path = "/admin/billing/cycle",
+    tag = "billing",
+    responses((status = 200, description = "Issued invoices", body = Vec<Object>)),
+    security(("bearer" = []))
+)]
+async fn run_billing_cycle(
+    State(api_service): State<Arc<ApiService>>,
+    auth: Auth,
+) -> Result<Json<Vec<Invoice>>, ApiError> {
+    if auth.user.role != UserRole::Admin {
+        return Err(ApiError::Authorization(
+            "Only admins can run the billing cycle".to_string(),
+        ));
+    }
+
+    log::info!("User {} running the billing cycle", auth.user.id);
+    let now = chrono::Utc::now().timestamp() as u64;
+    let invoices = api_service.billing_service.run_billing_cycle(now).await?;
+
+    Ok(Json(invoices))
+}
+
+/// Billing routes
+pub fn billing_routes(api_service: Arc<ApiService>) -> Router {
+    Router::new()
+        .route("/billing/account", get(get_account))
+        .route("/billing/payers", post(add_payer))
+        .route("/billing/invoices", get(list_invoices))
+        .route("/billing/invoices/:id", get(get_invoice))
+        .route("/admin/billing/cycle", post(run_billing_cycle))
+        .with_state(api_service)
+}
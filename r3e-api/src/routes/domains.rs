@@ -0,0 +1,196 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    routing::{delete, get, post},
+    Json, Router,
+};
+use serde::Deserialize;
+use std::sync::Arc;
+use utoipa::IntoParams;
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::auth::Auth;
+use crate::authz::{authorize_domain, authorize_function};
+use crate::domains::verification_record;
+use crate::error::ApiError;
+use crate::models::domain::{CreateDomainMappingRequest, DomainMapping, DomainMappingResponse};
+use crate::models::function::TriggerType;
+use crate::service::ApiService;
+
+/// TLS check query
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct TlsCheckQuery {
+    /// Domain a certificate is requested for
+    pub domain: String,
+}
+
+fn mapping_response(mapping: DomainMapping) -> DomainMappingResponse {
+    DomainMappingResponse {
+        verification_record: verification_record(&mapping.domain),
+        mapping,
+    }
+}
+
+/// Map a domain to a function
+#[utoipa::path(
+    post,
+    path = "/domains",
+    tag = "domains",
+    request_body = CreateDomainMappingRequest,
+    responses((status = 201, description = "Domain mapping and the TXT record verifying it", body = DomainMappingResponse)),
+    security(("bearer" = []))
+)]
+async fn create_domain(
+    State(api_service): State<Arc<ApiService>>,
+    auth: Auth,
+    Json(request): Json<CreateDomainMappingRequest>,
+) -> Result<(StatusCode, Json<DomainMappingResponse>), ApiError> {
+    request
+        .validate()
+        .map_err(|e| ApiError::Validation(e.to_string()))?;
+
+    // Only the owner maps domains to a function, and only HTTP-triggered ones
+    let function = api_service
+        .function_service
+        .get_function(request.function_id)
+        .await?;
+    authorize_function(&auth, &function, "map a domain to")?;
+    if function.trigger_type != TriggerType::Http {
+        return Err(ApiError::Validation(
+            "Only HTTP-triggered functions can be mapped to a domain".to_string(),
+        ));
+    }
+
+    let mapping = api_service
+        .domain_store
+        .create(
+            auth.user.id,
+            &request.domain,
+            request.path_prefix.as_deref(),
+            function.id,
+        )
+        .await?;
+
+    Ok((StatusCode::CREATED, Json(mapping_response(mapping))))
+}
+
+/// List the domain mappings of the current user
+#[utoipa::path(
+    get,
+    path = "/domains",
+    tag = "domains",
+    responses((status = 200, description = "Domain mappings", body = Vec<DomainMappingResponse>)),
+    security(("bearer" = []))
+)]
+async fn list_domains(
+    State(api_service): State<Arc<ApiService>>,
+    auth: Auth,
+) -> Result<Json<Vec<DomainMappingResponse>>, ApiError> {
+    let mappings = api_service.domain_store.list(auth.user.id).await?;
+
+    Ok(Json(mappings.into_iter().map(mapping_response).collect()))
+}
+
+/// Get a domain mapping
+#[utoipa::path(
+    get,
+    path = "/domains/{id}",
+    tag = "domains",
+    params(("id" = Uuid, Path, description = "Domain mapping ID")),
+    responses((status = 200, description = "Domain mapping", body = DomainMappingResponse)),
+    security(("bearer" = []))
+)]
+async fn get_domain(
+    State(api_service): State<Arc<ApiService>>,
+    auth: Auth,
+    Path(id): Path<Uuid>,
+) -> Result<Json<DomainMappingResponse>, ApiError> {
+    let mapping = api_service.domain_store.get(id).await?;
+    authorize_domain(&auth, &mapping, "view")?;
+
+    Ok(Json(mapping_response(mapping)))
+}
+
+/// Verify a domain mapping against its TXT record
+#[utoipa::path(
+    post,
+    path = "/domains/{id}/verify",
+    tag = "domains",
+    params(("id" = Uuid, Path, description = "Domain mapping ID")),
+    responses((status = 200, description = "Verified domain mapping", body = DomainMappingResponse)),
+    security(("bearer" = []))
+)]
+async fn verify_domain(
+    State(api_service): State<Arc<ApiService>>,
+    auth: Auth,
+    Path(id): Path<Uuid>,
+) -> Result<Json<DomainMappingResponse>, ApiError> {
+    let mapping = api_service.domain_store.get(id).await?;
+    authorize_domain(&auth, &mapping, "verify")?;
+
+    let mapping = api_service.domain_store.verify(id).await?;
+
+    Ok(Json(mapping_response(mapping)))
+}
+
+/// Delete a domain mapping
+#[utoipa::path(
+    delete,
+    path = "/domains/{id}",
+    tag = "domains",
+    params(("id" = Uuid, Path, description = "Domain mapping ID")),
+    responses((status = 204, description = "Domain mapping deleted")),
+    security(("bearer" = []))
+)]
+async fn delete_domain(
+    State(api_service): State<Arc<ApiService>>,
+    auth: Auth,
+    Path(id): Path<Uuid>,
+) -> Result<StatusCode, ApiError> {
+    let mapping = api_service.domain_store.get(id).await?;
+    authorize_domain(&auth, &mapping, "delete")?;
+
+    api_service.domain_store.delete(id).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Whether a certificate may be issued for a domain, asked by the TLS terminating proxy
+/// before it obtains one on demand
+#[utoipa::path(
+    get,
+    path = "/domains/tls-check",
+    tag = "domains",
+    params(TlsCheckQuery),
+    responses((status = 200, description = "A certificate may be issued for the domain"))
+)]
+async fn tls_check(
+    State(api_service): State<Arc<ApiService>>,
+    Query(query): Query<TlsCheckQuery>,
+) -> Result<StatusCode, ApiError> {
+    if api_service.domain_store.is_verified(&query.domain).await? {
+        Ok(StatusCode::OK)
+    } else {
+        Err(ApiError::NotFound(format!(
+            "No verified mapping for {}",
+            query.domain
+        )))
+    }
+}
+
+/// Domain routes
+pub fn domain_routes(api_service: Arc<ApiService>) -> Router {
+    Router::new()
+        .route("/domains", get(list_domains))
+        .route("/domains", post(create_domain))
+        .route("/domains/tls-check", get(tls_check))
+        .route("/domains/:id", get(get_domain))
+        .route("/domains/:id", delete(delete_domain))
+        .route("/domains/:id/verify", post(verify_domain))
+        .with_state(api_service)
+}
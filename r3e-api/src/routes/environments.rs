@@ -0,0 +1,236 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    routing::{get, post},
+    Json, Router,
+};
+use std::sync::Arc;
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::auth::Auth;
+use crate::authz::authorize_function;
+use crate::error::ApiError;
+use crate::models::environment::{
+    CreateEnvironmentRequest, Environment, FunctionRelease, PromoteFunctionRequest,
+    ReleaseFunctionRequest, UpdateEnvironmentRequest,
+};
+use crate::service::ApiService;
+
+/// List environments in promotion order
+#[utoipa::path(
+    get,
+    path = "/environments",
+    tag = "environments",
+    responses((status = 200, description = "Environments of the user, in promotion order", body = Vec<Environment>)),
+    security(("bearer" = []))
+)]
+async fn list_environments(
+    State(api_service): State<Arc<ApiService>>,
+    auth: Auth,
+) -> Result<Json<Vec<Environment>>, ApiError> {
+    let environments = api_service.environment_store.list(auth.user.id).await?;
+
+    Ok(Json(environments))
+}
+
+/// Create an environment
+#[utoipa::path(
+    post,
+    path = "/environments",
+    tag = "environments",
+    request_body = CreateEnvironmentRequest,
+    responses((status = 201, description = "Environment", body = Environment)),
+    security(("bearer" = []))
+)]
+async fn create_environment(
+    State(api_service): State<Arc<ApiService>>,
+    auth: Auth,
+    Json(request): Json<CreateEnvironmentRequest>,
+) -> Result<(StatusCode, Json<Environment>), ApiError> {
+    request
+        .validate()
+        .map_err(|e| ApiError::Validation(e.to_string()))?;
+
+    let environment = api_service
+        .environment_store
+        .create(auth.user.id, &request)
+        .await?;
+
+    Ok((StatusCode::CREATED, Json(environment)))
+}
+
+/// Get an environment
+#[utoipa::path(
+    get,
+    path = "/environments/{name}",
+    tag = "environments",
+    params(("name" = String, Path, description = "Environment name")),
+    responses((status = 200, description = "Environment", body = Environment)),
+    security(("bearer" = []))
+)]
+async fn get_environment(
+    State(api_service): State<Arc<ApiService>>,
+    auth: Auth,
+    Path(name): Path<String>,
+) -> Result<Json<Environment>, ApiError> {
+    let environment = api_service
+        .environment_store
+        .get(auth.user.id, &name)
+        .await?;
+
+    Ok(Json(environment))
+}
+
+/// Update the position, configuration or secret bindings of an environment
+#[utoipa::path(
+    put,
+    path = "/environments/{name}",
+    tag = "environments",
+    params(("name" = String, Path, description = "Environment name")),
+    request_body = UpdateEnvironmentRequest,
+    responses((status = 200, description = "Updated environment", body = Environment)),
+    security(("bearer" = []))
+)]
+async fn update_environment(
+    State(api_service): State<Arc<ApiService>>,
+    auth: Auth,
+    Path(name): Path<String>,
+    Json(request): Json<UpdateEnvironmentRequest>,
+) -> Result<Json<Environment>, ApiError> {
+    let environment = api_service
+        .environment_store
+        .update(auth.user.id, &name, &request)
+        .await?;
+
+    Ok(Json(environment))
+}
+
+/// Delete an environment, with the releases of functions to it
+#[utoipa::path(
+    delete,
+    path = "/environments/{name}",
+    tag = "environments",
+    params(("name" = String, Path, description = "Environment name")),
+    responses((status = 204, description = "Environment deleted")),
+    security(("bearer" = []))
+)]
+async fn delete_environment(
+    State(api_service): State<Arc<ApiService>>,
+    auth: Auth,
+    Path(name): Path<String>,
+) -> Result<StatusCode, ApiError> {
+    api_service
+        .environment_store
+        .delete(auth.user.id, &name)
+        .await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// List the versions of a function released to each environment
+#[utoipa::path(
+    get,
+    path = "/functions/{id}/releases",
+    tag = "environments",
+    params(("id" = Uuid, Path, description = "Function ID")),
+    responses((status = 200, description = "Releases, in promotion order", body = Vec<FunctionRelease>)),
+    security(("bearer" = []))
+)]
+async fn list_releases(
+    State(api_service): State<Arc<ApiService>>,
+    auth: Auth,
+    Path(id): Path<Uuid>,
+) -> Result<Json<Vec<FunctionRelease>>, ApiError> {
+    let function = api_service.function_service.get_function(id).await?;
+    authorize_function(&auth, &function, "view")?;
+
+    let releases = api_service.environment_store.releases(id).await?;
+
+    Ok(Json(releases))
+}
+
+/// Release the current code of a function to an environment
+#[utoipa::path(
+    post,
+    path = "/functions/{id}/releases",
+    tag = "environments",
+    params(("id" = Uuid, Path, description = "Function ID")),
+    request_body = ReleaseFunctionRequest,
+    responses((status = 200, description = "Release, with the version the code was recorded as", body = FunctionRelease)),
+    security(("bearer" = []))
+)]
+async fn release_function(
+    State(api_service): State<Arc<ApiService>>,
+    auth: Auth,
+    Path(id): Path<Uuid>,
+    Json(request): Json<ReleaseFunctionRequest>,
+) -> Result<Json<FunctionRelease>, ApiError> {
+    let function = api_service.function_service.get_function(id).await?;
+    authorize_function(&auth, &function, "release")?;
+
+    let release = api_service
+        .environment_store
+        .release(function.user_id, id, &request.environment, auth.user.id)
+        .await?;
+
+    Ok(Json(release))
+}
+
+/// Promote the version of a function released to one environment to a later one
+#[utoipa::path(
+    post,
+    path = "/functions/{id}/promote",
+    tag = "environments",
+    params(("id" = Uuid, Path, description = "Function ID")),
+    request_body = PromoteFunctionRequest,
+    responses((status = 200, description = "Release to the target environment", body = FunctionRelease)),
+    security(("bearer" = []))
+)]
+async fn promote_function(
+    State(api_service): State<Arc<ApiService>>,
+    auth: Auth,
+    Path(id): Path<Uuid>,
+    Json(request): Json<PromoteFunctionRequest>,
+) -> Result<Json<FunctionRelease>, ApiError> {
+    let function = api_service.function_service.get_function(id).await?;
+    authorize_function(&auth, &function, "promote")?;
+
+    let release = api_service
+        .environment_store
+        .promote(
+            function.user_id,
+            id,
+            &request.from,
+            &request.to,
+            request.version,
+            auth.user.id,
+        )
+        .await?;
+
+    Ok(Json(release))
+}
+
+/// Environment routes
+pub fn environment_routes(api_service: Arc<ApiService>) -> Router {
+    Router::new()
+        .route(
+            "/environments",
+            get(list_environments).post(create_environment),
+        )
+        .route(
+            "/environments/:name",
+            get(get_environment)
+                .put(update_environment)
+                .delete(delete_environment),
+        )
+        .route(
+            "/functions/:id/releases",
+            get(list_releases).post(release_function),
+        )
+        .route("/functions/:id/promote", post(promote_function))
+        .with_state(api_service)
+}
@@ -2,26 +2,61 @@
 // All Rights Reserved
 
 use axum::{
+    body::Body,
     extract::{Path, Query, State},
-    routing::{get, post},
+    http::{header, HeaderMap, StatusCode},
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse, Response,
+    },
+    routing::{get, post, put},
     Json, Router,
 };
 use chrono::{DateTime, Utc};
+use futures::stream::{BoxStream, StreamExt};
+use r3e_built_in_services::pricing::CostQuote;
+use r3e_deno::ext::stream::StreamChunk;
+use r3e_store::LogQuery;
 use serde::{Deserialize, Serialize};
+use std::convert::Infallible;
 use std::sync::Arc;
+use std::time::Instant;
+use utoipa::{IntoParams, ToSchema};
 use uuid::Uuid;
 use validator::Validate;
 
 use crate::auth::Auth;
+use crate::authz::{
+    admit_batch_invocation, admit_invocation, admit_replay, authorize_create_function,
+    authorize_function, authorize_invoke,
+};
+use crate::batch::run_batch;
 use crate::error::ApiError;
+use crate::etag::{self, with_etag};
+use crate::heap_reports::StoredHeapReport;
+use crate::idempotency::{self, Claim, StoredResponse};
 use crate::models::function::{
-    CreateFunctionRequest, Function, FunctionInvocationRequest, FunctionInvocationResponse,
-    FunctionLogsRequest, FunctionLogsResponse, FunctionStatus, UpdateFunctionRequest,
+    CreateFunctionRequest, Function, FunctionBatchInvocationRequest,
+    FunctionBatchInvocationResponse, FunctionEstimateRequest, FunctionInvocationRequest,
+    FunctionInvocationResponse, FunctionLogsRequest, FunctionLogsResponse, FunctionSchemaResponse,
+    LogRetentionResponse, UpdateFunctionRequest, UpdateLogRetentionRequest,
 };
+use crate::models::replay::{CreateReplayRequest, Replay};
+use crate::search::{SearchHit, SearchKind};
 use crate::service::ApiService;
 
+/// Header naming the environment an invocation runs in
+pub const ENVIRONMENT_HEADER: &str = "X-R3E-Environment";
+
+/// Maximum number of log entries returned by a query
+const MAX_LOGS_LIMIT: u32 = 1000;
+
+/// Maximum compiled size of a log search regex in bytes
+const MAX_LOG_REGEX_SIZE: usize = 1024 * 1024;
+
 /// List functions query
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
 pub struct ListFunctionsQuery {
     /// Service ID
     pub service_id: Option<Uuid>,
@@ -43,7 +78,7 @@ pub struct ListFunctionsQuery {
 }
 
 /// List functions response
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
 pub struct ListFunctionsResponse {
     /// Functions
     pub functions: Vec<Function>,
@@ -56,6 +91,14 @@ pub struct ListFunctionsResponse {
 }
 
 /// List functions handler
+#[utoipa::path(
+    get,
+    path = "/functions",
+    tag = "functions",
+    params(ListFunctionsQuery),
+    responses((status = 200, description = "Functions of the user", body = ListFunctionsResponse)),
+    security(("bearer" = []))
+)]
 async fn list_functions(
     State(api_service): State<Arc<ApiService>>,
     auth: Auth,
@@ -86,48 +129,125 @@ async fn list_functions(
     }))
 }
 
+/// Search query
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct SearchQuery {
+    /// Search terms
+    pub q: String,
+
+    /// Restrict results to `function` or `service`
+    pub kind: Option<String>,
+
+    /// Limit
+    pub limit: Option<u32>,
+
+    /// Offset
+    pub offset: Option<u32>,
+}
+
+/// Search response
+#[derive(Debug, Serialize, ToSchema)]
+pub struct SearchResponse {
+    /// Hits, best match first
+    pub results: Vec<SearchHit>,
+
+    /// Total count
+    pub total_count: u32,
+
+    /// Has more
+    pub has_more: bool,
+}
+
+/// Search functions and services handler
+#[utoipa::path(
+    get,
+    path = "/functions/search",
+    tag = "functions",
+    params(SearchQuery),
+    responses((status = 200, description = "Matching functions and services", body = SearchResponse)),
+    security(("bearer" = []))
+)]
+async fn search(
+    State(api_service): State<Arc<ApiService>>,
+    auth: Auth,
+    Query(query): Query<SearchQuery>,
+) -> Result<Json<SearchResponse>, ApiError> {
+    let kind = query
+        .kind
+        .as_deref()
+        .map(|kind| kind.parse::<SearchKind>())
+        .transpose()
+        .map_err(ApiError::Validation)?;
+    let limit = query.limit.unwrap_or(10).min(100);
+    let offset = query.offset.unwrap_or(0);
+
+    let (results, total_count) = api_service.search_index.search(
+        &query.q,
+        kind,
+        auth.user.id,
+        limit as usize,
+        offset as usize,
+    );
+
+    Ok(Json(SearchResponse {
+        results,
+        total_count: total_count as u32,
+        has_more: total_count > (offset + limit) as usize,
+    }))
+}
+
 /// Get function handler
+#[utoipa::path(
+    get,
+    path = "/functions/{id}",
+    tag = "functions",
+    params(("id" = Uuid, Path, description = "Function ID")),
+    responses((status = 200, description = "Function", body = Function, headers(("ETag" = String, description = "Version of the function, sent back in `If-Match`")))),
+    security(("bearer" = []))
+)]
 async fn get_function(
     State(api_service): State<Arc<ApiService>>,
     auth: Auth,
     Path(id): Path<Uuid>,
-) -> Result<Json<Function>, ApiError> {
+) -> Result<Response, ApiError> {
     // Get the function
     let function = api_service.function_service.get_function(id).await?;
 
     // Check if the user owns the function
-    if function.user_id != auth.user.id {
-        return Err(ApiError::Authorization(
-            "You are not authorized to view this function".to_string(),
-        ));
-    }
+    authorize_function(&auth, &function, "view")?;
 
     // Return the function
-    Ok(Json(function))
+    Ok(with_etag(&function.updated_at, function))
 }
 
 /// Create function handler
+#[utoipa::path(
+    post,
+    path = "/functions",
+    tag = "functions",
+    request_body = CreateFunctionRequest,
+    responses((status = 200, description = "Created function", body = Function, headers(("ETag" = String, description = "Version of the function, sent back in `If-Match`")))),
+    security(("bearer" = []))
+)]
 async fn create_function(
     State(api_service): State<Arc<ApiService>>,
     auth: Auth,
     Json(request): Json<CreateFunctionRequest>,
-) -> Result<Json<Function>, ApiError> {
+) -> Result<Response, ApiError> {
     // Validate the request
     request
         .validate()
         .map_err(|e| ApiError::Validation(e.to_string()))?;
 
     // Check if the user owns the service
-    let service = api_service
-        .service_service
-        .get_service(request.service_id)
-        .await?;
+    authorize_create_function(&api_service, &auth, request.service_id).await?;
 
-    if service.user_id != auth.user.id {
-        return Err(ApiError::Authorization(
-            "You are not authorized to create functions for this service".to_string(),
-        ));
-    }
+    // Take the code inline or from a completed upload
+    let code = api_service
+        .upload_store
+        .function_code(auth.user.id, &request.code, request.upload_id)
+        .await?;
 
     // Create the function
     let function = api_service
@@ -137,25 +257,79 @@ async fn create_function(
             request.service_id,
             &request.name,
             request.description.as_deref(),
-            &request.code,
+            &code,
             request.runtime.unwrap_or_default(),
             request.trigger_type,
             &request.trigger_config,
             request.security_level.unwrap_or_default(),
+            request.input_schema.as_ref(),
+            request.output_schema.as_ref(),
         )
         .await?;
 
+    // The upload was used, its bundle is kept with the function
+    if let Some(upload_id) = request.upload_id {
+        if let Err(e) = api_service
+            .upload_store
+            .delete(auth.user.id, upload_id)
+            .await
+        {
+            log::warn!("Failed to delete upload {}: {}", upload_id, e);
+        }
+    }
+
     // Return the function
-    Ok(Json(function))
+    Ok(with_etag(&function.updated_at, function))
+}
+
+/// Get function schema handler
+#[utoipa::path(
+    get,
+    path = "/functions/{id}/schema",
+    tag = "functions",
+    params(("id" = Uuid, Path, description = "Function ID")),
+    responses((status = 200, description = "Input and output schemas", body = FunctionSchemaResponse)),
+    security(("bearer" = []))
+)]
+async fn get_function_schema(
+    State(api_service): State<Arc<ApiService>>,
+    auth: Auth,
+    Path(id): Path<Uuid>,
+) -> Result<Json<FunctionSchemaResponse>, ApiError> {
+    // Get the function
+    let function = api_service.function_service.get_function(id).await?;
+
+    // Check if the user owns the function
+    authorize_function(&auth, &function, "view")?;
+
+    Ok(Json(FunctionSchemaResponse {
+        function_id: function.id,
+        name: function.name,
+        input_schema: function.input_schema,
+        output_schema: function.output_schema,
+    }))
 }
 
 /// Update function handler
+#[utoipa::path(
+    post,
+    path = "/functions/{id}",
+    tag = "functions",
+    params(
+        ("id" = Uuid, Path, description = "Function ID"),
+        ("If-Match" = Option<String>, Header, description = "ETag the update applies to")
+    ),
+    request_body = UpdateFunctionRequest,
+    responses((status = 200, description = "Updated function", body = Function, headers(("ETag" = String, description = "Version of the function, sent back in `If-Match`")))),
+    security(("bearer" = []))
+)]
 async fn update_function(
     State(api_service): State<Arc<ApiService>>,
     auth: Auth,
     Path(id): Path<Uuid>,
+    headers: HeaderMap,
     Json(request): Json<UpdateFunctionRequest>,
-) -> Result<Json<Function>, ApiError> {
+) -> Result<Response, ApiError> {
     // Validate the request
     request
         .validate()
@@ -165,11 +339,10 @@ async fn update_function(
     let function = api_service.function_service.get_function(id).await?;
 
     // Check if the user owns the function
-    if function.user_id != auth.user.id {
-        return Err(ApiError::Authorization(
-            "You are not authorized to update this function".to_string(),
-        ));
-    }
+    authorize_function(&auth, &function, "update")?;
+
+    // Reject the update if the client's copy is stale
+    let if_updated_at = etag::if_match(&headers, &function.updated_at)?;
 
     // Update the function
     let function = api_service
@@ -184,14 +357,25 @@ async fn update_function(
             request.trigger_config.as_ref(),
             request.security_level,
             request.status,
+            request.input_schema.as_ref(),
+            request.output_schema.as_ref(),
+            if_updated_at,
         )
         .await?;
 
     // Return the function
-    Ok(Json(function))
+    Ok(with_etag(&function.updated_at, function))
 }
 
 /// Delete function handler
+#[utoipa::path(
+    delete,
+    path = "/functions/{id}",
+    tag = "functions",
+    params(("id" = Uuid, Path, description = "Function ID")),
+    responses((status = 200, description = "Function deleted")),
+    security(("bearer" = []))
+)]
 async fn delete_function(
     State(api_service): State<Arc<ApiService>>,
     auth: Auth,
@@ -201,11 +385,7 @@ async fn delete_function(
     let function = api_service.function_service.get_function(id).await?;
 
     // Check if the user owns the function
-    if function.user_id != auth.user.id {
-        return Err(ApiError::Authorization(
-            "You are not authorized to delete this function".to_string(),
-        ));
-    }
+    authorize_function(&auth, &function, "delete")?;
 
     // Delete the function
     api_service.function_service.delete_function(id).await?;
@@ -214,46 +394,360 @@ async fn delete_function(
     Ok(Json(()))
 }
 
+/// Streamed response mode of an invocation
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum StreamMode {
+    /// Server-Sent Events
+    Sse,
+
+    /// Newline-delimited JSON chunks over chunked transfer encoding
+    Chunked,
+}
+
+/// Invoke function query
+#[derive(Debug, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct InvokeFunctionQuery {
+    /// Stream the function output instead of returning a single value
+    pub stream: Option<StreamMode>,
+
+    /// Run the version released to this environment of the owner, instead of the current code
+    pub environment: Option<String>,
+}
+
+impl InvokeFunctionQuery {
+    /// Stream mode requested by the query or, failing that, the Accept header
+    fn stream_mode(&self, headers: &HeaderMap) -> Option<StreamMode> {
+        self.stream.or_else(|| {
+            headers
+                .get(header::ACCEPT)
+                .and_then(|accept| accept.to_str().ok())
+                .filter(|accept| accept.contains("text/event-stream"))
+                .map(|_| StreamMode::Sse)
+        })
+    }
+
+    /// Environment requested by the query or, failing that, the `X-R3E-Environment` header
+    fn environment(&self, headers: &HeaderMap) -> Option<String> {
+        self.environment.clone().or_else(|| {
+            headers
+                .get(ENVIRONMENT_HEADER)
+                .and_then(|environment| environment.to_str().ok())
+                .map(|environment| environment.trim().to_string())
+                .filter(|environment| !environment.is_empty())
+        })
+    }
+}
+
 /// Invoke function handler
+#[utoipa::path(
+    post,
+    path = "/functions/{id}/invoke",
+    tag = "functions",
+    params(
+        ("id" = Uuid, Path, description = "Function ID"),
+        InvokeFunctionQuery,
+        ("Idempotency-Key" = Option<String>, Header, description = "Key replaying the first response of a retried invocation"),
+        ("X-R3E-Environment" = Option<String>, Header, description = "Environment to run the released version of, if not given in the query")
+    ),
+    request_body = FunctionInvocationRequest,
+    responses((
+        status = 200,
+        description = "Invocation result, or its output as it is produced when streamed",
+        content(
+            (FunctionInvocationResponse = "application/json"),
+            (String = "text/event-stream"),
+            (String = "application/x-ndjson")
+        )
+    )),
+    security(("bearer" = []))
+)]
 async fn invoke_function(
     State(api_service): State<Arc<ApiService>>,
     auth: Auth,
     Path(id): Path<Uuid>,
+    Query(query): Query<InvokeFunctionQuery>,
+    headers: HeaderMap,
     Json(request): Json<FunctionInvocationRequest>,
-) -> Result<Json<FunctionInvocationResponse>, ApiError> {
+) -> Result<Response, ApiError> {
     // Get the function
     let function = api_service.function_service.get_function(id).await?;
 
-    // Check if the function is active
-    if function.status != FunctionStatus::Active {
-        return Err(ApiError::Validation("Function is not active".to_string()));
-    }
+    // Check that the function may run for this user and within the cost limit
+    admit_invocation(&api_service, &auth, &function, request.max_cost).await?;
 
-    // Check if the user owns the function or the function's service is public
-    let service = api_service
-        .service_service
-        .get_service(function.service_id)
-        .await?;
+    let idempotency_key = idempotency::idempotency_key(&headers)?;
+
+    // Run the version released to the environment of the owner, if one is named
+    let environment = match query.environment(&headers) {
+        Some(environment) => Some(
+            api_service
+                .environment_store
+                .resolve(function.user_id, id, &environment)
+                .await?,
+        ),
+        None => None,
+    };
+
+    // Stream the function output if requested
+    if let Some(mode) = query.stream_mode(&headers) {
+        if idempotency_key.is_some() {
+            return Err(ApiError::Validation(
+                "Idempotency keys are not supported for streamed invocations".to_string(),
+            ));
+        }
+        if environment.is_some() {
+            return Err(ApiError::Validation(
+                "Environments are not supported for streamed invocations".to_string(),
+            ));
+        }
 
-    if function.user_id != auth.user.id
-        && service.visibility != crate::models::service::ServiceVisibility::Public
-    {
-        return Err(ApiError::Authorization(
-            "You are not authorized to invoke this function".to_string(),
-        ));
+        let chunks = api_service
+            .function_service
+            .invoke_function_stream(id, &request.input)
+            .await?;
+
+        return Ok(match mode {
+            StreamMode::Sse => sse_response(chunks),
+            StreamMode::Chunked => chunked_response(chunks),
+        });
+    }
+
+    // Return the first response of a retried invocation instead of invoking again
+    let user_id = auth.user.id.to_string();
+    if let Some(key) = &idempotency_key {
+        let path = match &environment {
+            Some((environment, _)) => {
+                format!("/functions/{}/invoke?environment={}", id, environment.name)
+            }
+            None => format!("/functions/{}/invoke", id),
+        };
+        let fingerprint = idempotency::fingerprint(&path, &request)?;
+        if let Claim::Replay(response) = api_service
+            .idempotency_store
+            .claim(&user_id, key, &fingerprint)
+            .await?
+        {
+            return Ok(response.into_response());
+        }
     }
 
     // Invoke the function
-    let response = api_service
-        .function_service
-        .invoke_function(id, &request.input)
-        .await?;
+    let response = match &environment {
+        Some((environment, release)) => {
+            api_service
+                .function_service
+                .invoke_function_in(id, &request.input, environment, release)
+                .await
+        }
+        None => {
+            api_service
+                .function_service
+                .invoke_function(id, &request.input)
+                .await
+        }
+    };
+
+    if let Some(key) = &idempotency_key {
+        let stored = match &response {
+            Ok(response) => {
+                api_service
+                    .idempotency_store
+                    .complete(
+                        &user_id,
+                        key,
+                        &StoredResponse {
+                            status: 200,
+                            body: serde_json::to_value(response).unwrap_or_default(),
+                        },
+                    )
+                    .await
+            }
+            // Failed invocations may be retried with the same key
+            Err(_) => api_service.idempotency_store.release(&user_id, key).await,
+        };
+        if let Err(e) = stored {
+            log::warn!("Failed to record idempotent invocation of {}: {}", id, e);
+        }
+    }
 
     // Return the response
-    Ok(Json(response))
+    Ok(Json(response?).into_response())
+}
+
+/// Invoke function over a batch of inputs handler
+#[utoipa::path(
+    post,
+    path = "/functions/{id}/invoke-batch",
+    tag = "functions",
+    params(("id" = Uuid, Path, description = "Function ID")),
+    request_body = FunctionBatchInvocationRequest,
+    responses((status = 200, description = "Results in input order", body = FunctionBatchInvocationResponse)),
+    security(("bearer" = []))
+)]
+async fn invoke_batch(
+    State(api_service): State<Arc<ApiService>>,
+    auth: Auth,
+    Path(id): Path<Uuid>,
+    Json(request): Json<FunctionBatchInvocationRequest>,
+) -> Result<Json<FunctionBatchInvocationResponse>, ApiError> {
+    if request.inputs.is_empty() {
+        return Err(ApiError::Validation("Batch has no inputs".to_string()));
+    }
+    if request.inputs.len() > api_service.config.max_batch_size {
+        return Err(ApiError::Validation(format!(
+            "Batch has {} inputs, at most {} are allowed",
+            request.inputs.len(),
+            api_service.config.max_batch_size
+        )));
+    }
+
+    // Get the function
+    let function = api_service.function_service.get_function(id).await?;
+
+    // Check that every invocation of the batch may run for this user and within the cost limit
+    admit_batch_invocation(
+        &api_service,
+        &auth,
+        &function,
+        request.inputs.len() as u32,
+        request.max_cost,
+    )
+    .await?;
+
+    // Never run more invocations at once than the platform allows
+    let parallelism = request
+        .max_parallelism
+        .unwrap_or(api_service.config.max_batch_parallelism)
+        .clamp(1, api_service.config.max_batch_parallelism.max(1));
+
+    let start_time = Instant::now();
+
+    // Fan the inputs out across the workers, keeping the results in input order
+    let function_service = &api_service.function_service;
+    let items = run_batch(&request.inputs, parallelism, |input| {
+        function_service.invoke_function(id, input)
+    })
+    .await;
+
+    let total = items.len();
+    let failed = items.iter().filter(|item| item.error.is_some()).count();
+    log::info!(
+        "Batch of {} invocations of {} finished with {} failures",
+        total,
+        id,
+        failed
+    );
+
+    Ok(Json(FunctionBatchInvocationResponse {
+        function_id: id,
+        items,
+        total,
+        succeeded: total - failed,
+        failed,
+        duration_ms: start_time.elapsed().as_millis() as u64,
+    }))
+}
+
+/// Deliver chunks as Server-Sent Events, completion and failure as `end` and `error` events
+fn sse_response(chunks: BoxStream<'static, StreamChunk>) -> Response {
+    let events = chunks.map(|chunk| {
+        let event = match chunk {
+            StreamChunk::Data { seq, event, data } => {
+                let sse = Event::default().id(seq.to_string()).data(data);
+                match event {
+                    Some(event) => sse.event(event),
+                    None => sse,
+                }
+            }
+            StreamChunk::End => Event::default().event("end").data(""),
+            StreamChunk::Error { message } => Event::default().event("error").data(message),
+        };
+        Ok::<_, Infallible>(event)
+    });
+
+    Sse::new(events)
+        .keep_alive(KeepAlive::default())
+        .into_response()
+}
+
+/// Deliver chunks as newline-delimited JSON over chunked transfer encoding
+fn chunked_response(chunks: BoxStream<'static, StreamChunk>) -> Response {
+    let body = chunks.map(|chunk| {
+        let mut line = serde_json::to_vec(&chunk).map_err(std::io::Error::other)?;
+        line.push(b'\n');
+        Ok::<_, std::io::Error>(line)
+    });
+
+    Response::builder()
+        .header(header::CONTENT_TYPE, "application/x-ndjson")
+        .header(header::CACHE_CONTROL, "no-cache")
+        .body(Body::from_stream(body))
+        .unwrap()
+}
+
+/// Function cost estimate response
+#[derive(Debug, Serialize, ToSchema)]
+pub struct FunctionEstimateResponse {
+    /// Cost quote
+    #[serde(flatten)]
+    #[schema(value_type = Object)]
+    pub quote: CostQuote,
+
+    /// Whether the worst-case cost is within the requested maximum cost
+    pub within_max_cost: Option<bool>,
+}
+
+/// Estimate function invocation cost handler
+#[utoipa::path(
+    post,
+    path = "/functions/{id}/estimate",
+    tag = "functions",
+    params(("id" = Uuid, Path, description = "Function ID")),
+    request_body(content = Option<FunctionEstimateRequest>),
+    responses((status = 200, description = "Cost quote", body = FunctionEstimateResponse)),
+    security(("bearer" = []))
+)]
+async fn estimate_function(
+    State(api_service): State<Arc<ApiService>>,
+    auth: Auth,
+    Path(id): Path<Uuid>,
+    request: Option<Json<FunctionEstimateRequest>>,
+) -> Result<Json<FunctionEstimateResponse>, ApiError> {
+    let Json(request) = request.unwrap_or_default();
+
+    // Get the function
+    let function = api_service.function_service.get_function(id).await?;
+
+    // Check if the user could invoke the function
+    authorize_invoke(&api_service, &auth, &function).await?;
+
+    // Quote the invocation
+    let quote = api_service
+        .cost_estimator
+        .quote(&function, auth.user.id, request.max_chain_gas)
+        .await?;
+
+    let within_max_cost = request
+        .max_cost
+        .map(|max_cost| quote.check_max_cost(max_cost).is_ok());
+
+    Ok(Json(FunctionEstimateResponse {
+        quote,
+        within_max_cost,
+    }))
 }
 
 /// Get function logs handler
+#[utoipa::path(
+    get,
+    path = "/functions/{id}/logs",
+    tag = "functions",
+    params(("id" = Uuid, Path, description = "Function ID"), FunctionLogsRequest),
+    responses((status = 200, description = "Log entries", body = FunctionLogsResponse)),
+    security(("bearer" = []))
+)]
 async fn get_function_logs(
     State(api_service): State<Arc<ApiService>>,
     auth: Auth,
@@ -264,37 +758,311 @@ async fn get_function_logs(
     let function = api_service.function_service.get_function(id).await?;
 
     // Check if the user owns the function
-    if function.user_id != auth.user.id {
-        return Err(ApiError::Authorization(
-            "You are not authorized to view logs for this function".to_string(),
-        ));
-    }
+    authorize_function(&auth, &function, "view logs for")?;
+
+    // Compile the pattern, bounding its size so a search cannot exhaust memory
+    let regex = query
+        .regex
+        .as_deref()
+        .map(|pattern| {
+            regex::RegexBuilder::new(pattern)
+                .size_limit(MAX_LOG_REGEX_SIZE)
+                .build()
+                .map_err(|e| ApiError::Validation(format!("Invalid regex: {}", e)))
+        })
+        .transpose()?;
+
+    let query = LogQuery {
+        from_ms: query
+            .start_time
+            .map(|time| time.timestamp_millis().max(0) as u64),
+        to_ms: query
+            .end_time
+            .map(|time| time.timestamp_millis().max(0) as u64),
+        min_level: query.level,
+        contains: query.contains,
+        regex,
+        offset: query.offset.unwrap_or(0) as usize,
+        limit: query.limit.unwrap_or(100).min(MAX_LOGS_LIMIT) as usize,
+    };
 
     // Get the logs
     let logs = api_service
         .function_service
-        .get_function_logs(
-            id,
-            query.start_time,
-            query.end_time,
-            query.limit.unwrap_or(100),
-            query.offset.unwrap_or(0),
-        )
+        .get_function_logs(id, &query)
         .await?;
 
     // Return the logs
     Ok(Json(logs))
 }
 
+/// Get the log retention policy of a function
+#[utoipa::path(
+    get,
+    path = "/functions/{id}/logs/retention",
+    tag = "functions",
+    params(("id" = Uuid, Path, description = "Function ID")),
+    responses((status = 200, description = "Log retention policy", body = LogRetentionResponse)),
+    security(("bearer" = []))
+)]
+async fn get_log_retention(
+    State(api_service): State<Arc<ApiService>>,
+    auth: Auth,
+    Path(id): Path<Uuid>,
+) -> Result<Json<LogRetentionResponse>, ApiError> {
+    // Check if the user owns the function
+    let function = api_service.function_service.get_function(id).await?;
+    authorize_function(&auth, &function, "view logs for")?;
+
+    let retention = api_service.log_store.retention(&id.to_string()).await?;
+    let is_default = retention.is_none();
+    let retention =
+        retention.unwrap_or_else(|| ApiService::default_log_retention(&api_service.config));
+
+    Ok(Json(LogRetentionResponse {
+        max_age_secs: retention.max_age_secs,
+        max_bytes: retention.max_bytes,
+        is_default,
+    }))
+}
+
+/// Set the log retention policy of a function, applied by the next pruning pass
+#[utoipa::path(
+    put,
+    path = "/functions/{id}/logs/retention",
+    tag = "functions",
+    params(("id" = Uuid, Path, description = "Function ID")),
+    request_body = UpdateLogRetentionRequest,
+    responses((status = 200, description = "Log retention policy", body = LogRetentionResponse)),
+    security(("bearer" = []))
+)]
+async fn set_log_retention(
+    State(api_service): State<Arc<ApiService>>,
+    auth: Auth,
+    Path(id): Path<Uuid>,
+    Json(request): Json<UpdateLogRetentionRequest>,
+) -> Result<Json<LogRetentionResponse>, ApiError> {
+    // Check if the user owns the function
+    let function = api_service.function_service.get_function(id).await?;
+    authorize_function(&auth, &function, "update")?;
+
+    let retention = request.into();
+    api_service
+        .log_store
+        .set_retention(&id.to_string(), retention)
+        .await?;
+
+    Ok(Json(LogRetentionResponse {
+        max_age_secs: retention.max_age_secs,
+        max_bytes: retention.max_bytes,
+        is_default: false,
+    }))
+}
+
+/// Get the heap report of an invocation that ran out of memory
+#[utoipa::path(
+    get,
+    path = "/functions/{id}/invocations/{invocation_id}/heap-report",
+    tag = "functions",
+    params(
+        ("id" = Uuid, Path, description = "Function ID"),
+        ("invocation_id" = Uuid, Path, description = "Invocation ID")
+    ),
+    responses((status = 200, description = "Heap report", body = StoredHeapReport)),
+    security(("bearer" = []))
+)]
+async fn get_heap_report(
+    State(api_service): State<Arc<ApiService>>,
+    auth: Auth,
+    Path((id, invocation_id)): Path<(Uuid, Uuid)>,
+) -> Result<Json<StoredHeapReport>, ApiError> {
+    // Check if the user owns the function
+    let function = api_service.function_service.get_function(id).await?;
+    authorize_function(&auth, &function, "view heap reports for")?;
+
+    let report = api_service
+        .heap_report_store
+        .get(id, invocation_id)
+        .await?
+        .ok_or_else(|| ApiError::NotFound(format!("Heap report not found: {}", invocation_id)))?;
+
+    Ok(Json(report))
+}
+
+/// Download the heap snapshot of an invocation that ran out of memory, loadable in the
+/// memory panel of Chrome DevTools
+#[utoipa::path(
+    get,
+    path = "/functions/{id}/invocations/{invocation_id}/heap-snapshot",
+    tag = "functions",
+    params(
+        ("id" = Uuid, Path, description = "Function ID"),
+        ("invocation_id" = Uuid, Path, description = "Invocation ID")
+    ),
+    responses((status = 200, description = "Heap snapshot", content_type = "application/json", body = Object)),
+    security(("bearer" = []))
+)]
+async fn download_heap_snapshot(
+    State(api_service): State<Arc<ApiService>>,
+    auth: Auth,
+    Path((id, invocation_id)): Path<(Uuid, Uuid)>,
+) -> Result<Response, ApiError> {
+    // Check if the user owns the function
+    let function = api_service.function_service.get_function(id).await?;
+    authorize_function(&auth, &function, "view heap reports for")?;
+
+    let snapshot = api_service
+        .heap_report_store
+        .get_snapshot(id, invocation_id)
+        .await?
+        .ok_or_else(|| ApiError::NotFound(format!("Heap snapshot not found: {}", invocation_id)))?;
+
+    let disposition = format!("attachment; filename=\"{}.heapsnapshot\"", invocation_id);
+    Ok((
+        [
+            (header::CONTENT_TYPE, "application/json".to_string()),
+            (header::CONTENT_DISPOSITION, disposition),
+        ],
+        snapshot,
+    )
+        .into_response())
+}
+
+/// Replay the events a contract emitted over a time range into a function, without billing
+/// the replayed invocations
+#[utoipa::path(
+    post,
+    path = "/functions/{id}/replays",
+    tag = "functions",
+    params(("id" = Uuid, Path, description = "Function ID")),
+    request_body = CreateReplayRequest,
+    responses((status = 202, description = "Replay started", body = Replay)),
+    security(("bearer" = []))
+)]
+async fn create_replay(
+    State(api_service): State<Arc<ApiService>>,
+    auth: Auth,
+    Path(id): Path<Uuid>,
+    Json(request): Json<CreateReplayRequest>,
+) -> Result<(StatusCode, Json<Replay>), ApiError> {
+    request
+        .validate()
+        .map_err(|e| ApiError::Validation(e.to_string()))?;
+
+    // Only the owner replays events into a function
+    let function = api_service.function_service.get_function(id).await?;
+    admit_replay(&api_service, &auth, &function).await?;
+
+    // Run the version released to the environment or the given version, if one is named
+    let (environment, release) = match (&request.environment, request.version) {
+        (Some(_), Some(_)) => {
+            return Err(ApiError::Validation(
+                "Replay into a version or an environment, not both".to_string(),
+            ))
+        }
+        (Some(environment), None) => {
+            let (environment, release) = api_service
+                .environment_store
+                .resolve(function.user_id, id, environment)
+                .await?;
+            (Some(environment), Some(release))
+        }
+        (None, Some(version)) => (
+            None,
+            Some(api_service.environment_store.version(id, version).await?),
+        ),
+        (None, None) => (None, None),
+    };
+
+    let replay = api_service.replay_store.start(
+        api_service.function_service.clone(),
+        auth.user.id,
+        id,
+        &request,
+        environment,
+        release,
+    )?;
+
+    Ok((StatusCode::ACCEPTED, Json(replay)))
+}
+
+/// Get a replay and its progress
+#[utoipa::path(
+    get,
+    path = "/functions/{id}/replays/{replay_id}",
+    tag = "functions",
+    params(
+        ("id" = Uuid, Path, description = "Function ID"),
+        ("replay_id" = Uuid, Path, description = "Replay ID")
+    ),
+    responses((status = 200, description = "Replay", body = Replay)),
+    security(("bearer" = []))
+)]
+async fn get_replay(
+    State(api_service): State<Arc<ApiService>>,
+    auth: Auth,
+    Path((id, replay_id)): Path<(Uuid, Uuid)>,
+) -> Result<Json<Replay>, ApiError> {
+    // Check if the user owns the function
+    let function = api_service.function_service.get_function(id).await?;
+    authorize_function(&auth, &function, "view replays of")?;
+
+    Ok(Json(api_service.replay_store.get(id, replay_id)?))
+}
+
+/// Cancel a running replay
+#[utoipa::path(
+    delete,
+    path = "/functions/{id}/replays/{replay_id}",
+    tag = "functions",
+    params(
+        ("id" = Uuid, Path, description = "Function ID"),
+        ("replay_id" = Uuid, Path, description = "Replay ID")
+    ),
+    responses((status = 200, description = "Cancelled replay", body = Replay)),
+    security(("bearer" = []))
+)]
+async fn cancel_replay(
+    State(api_service): State<Arc<ApiService>>,
+    auth: Auth,
+    Path((id, replay_id)): Path<(Uuid, Uuid)>,
+) -> Result<Json<Replay>, ApiError> {
+    // Check if the user owns the function
+    let function = api_service.function_service.get_function(id).await?;
+    authorize_function(&auth, &function, "cancel replays of")?;
+
+    Ok(Json(api_service.replay_store.cancel(id, replay_id)?))
+}
+
 /// Function routes
 pub fn function_routes(api_service: Arc<ApiService>) -> Router {
     Router::new()
         .route("/functions", get(list_functions))
         .route("/functions", post(create_function))
+        .route("/functions/search", get(search))
         .route("/functions/:id", get(get_function))
         .route("/functions/:id", post(update_function))
         .route("/functions/:id", axum::routing::delete(delete_function))
+        .route("/functions/:id/schema", get(get_function_schema))
         .route("/functions/:id/invoke", post(invoke_function))
+        .route("/functions/:id/invoke-batch", post(invoke_batch))
+        .route("/functions/:id/estimate", post(estimate_function))
         .route("/functions/:id/logs", get(get_function_logs))
+        .route("/functions/:id/logs/retention", get(get_log_retention))
+        .route("/functions/:id/logs/retention", put(set_log_retention))
+        .route(
+            "/functions/:id/invocations/:invocation_id/heap-report",
+            get(get_heap_report),
+        )
+        .route(
+            "/functions/:id/invocations/:invocation_id/heap-snapshot",
+            get(download_heap_snapshot),
+        )
+        .route("/functions/:id/replays", post(create_replay))
+        .route("/functions/:id/replays/:replay_id", get(get_replay))
+        .route(
+            "/functions/:id/replays/:replay_id",
+            axum::routing::delete(cancel_replay),
+        )
         .with_state(api_service)
 }
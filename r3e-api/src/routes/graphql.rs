@@ -8,6 +8,7 @@ use async_graphql::{
 use async_graphql_axum::{GraphQLRequest, GraphQLResponse};
 use axum::{
     extract::State,
+    http::HeaderMap,
     response::{Html, IntoResponse},
     routing::{get, post},
     Router,
@@ -15,14 +16,28 @@ use axum::{
 
 use crate::auth::Auth;
 use crate::graphql::schema::{ApiSchema, MutationRoot, QueryRoot};
+use crate::sessions::DeviceInfo;
 
 /// GraphQL handler
+#[utoipa::path(
+    post,
+    path = "/graphql",
+    tag = "graphql",
+    request_body(content = Object, description = "GraphQL request"),
+    responses((status = 200, description = "GraphQL response", body = Object)),
+    security(("bearer" = []))
+)]
 async fn graphql_handler(
     State(schema): State<ApiSchema>,
     auth: Auth,
+    headers: HeaderMap,
     req: GraphQLRequest,
 ) -> GraphQLResponse {
-    schema.execute(req.into_inner().data(auth)).await.into()
+    let request = req
+        .into_inner()
+        .data(auth)
+        .data(DeviceInfo::from_headers(&headers));
+    schema.execute(request).await.into()
 }
 
 /// GraphQL playground handler
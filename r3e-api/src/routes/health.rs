@@ -3,9 +3,10 @@
 
 use axum::{routing::get, Json, Router};
 use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 
 /// Health check response
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct HealthResponse {
     /// Status
     pub status: String,
@@ -15,6 +16,12 @@ pub struct HealthResponse {
 }
 
 /// Health check handler
+#[utoipa::path(
+    get,
+    path = "/health",
+    tag = "health",
+    responses((status = 200, description = "Service is up", body = HealthResponse))
+)]
 async fn health_check() -> Json<HealthResponse> {
     Json(HealthResponse {
         status: "ok".to_string(),
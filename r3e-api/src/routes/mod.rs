@@ -1,8 +1,23 @@
 // Copyright @ 2023 - 2024, R3E Network
 // All Rights Reserved
 
+pub mod admin;
+pub mod alert_rules;
+pub mod analytics;
 pub mod auth;
+pub mod billing;
+pub mod domains;
+pub mod environments;
 pub mod functions;
 pub mod graphql;
 pub mod health;
+pub mod notifications;
+pub mod permissions;
+pub mod policies;
+pub mod quota;
+pub mod secrets;
 pub mod services;
+pub mod signing;
+pub mod tee;
+pub mod uploads;
+pub mod workflows;
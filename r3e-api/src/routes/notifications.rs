@@ -0,0 +1,275 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    routing::{get, post},
+    Json, Router,
+};
+use r3e_built_in_services::alerts::{Alert, AlertSink};
+use r3e_built_in_services::notifications::{
+    ChannelRequest, Delivery, NotificationChannel, OPERATORS,
+};
+use serde::Deserialize;
+use std::sync::Arc;
+use utoipa::IntoParams;
+
+use crate::auth::Auth;
+use crate::error::ApiError;
+use crate::models::user::UserRole;
+use crate::service::ApiService;
+
+/// Most deliveries listed at once
+const MAX_DELIVERIES: usize = 500;
+
+/// Channel scope query
+#[derive(Debug, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ChannelScopeQuery {
+    /// Manage the operators' channels, notified of every alert (admins only)
+    #[serde(default)]
+    pub operators: bool,
+}
+
+/// List deliveries query
+#[derive(Debug, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ListDeliveriesQuery {
+    /// List the deliveries to the operators' channels (admins only)
+    #[serde(default)]
+    pub operators: bool,
+
+    /// Most deliveries to return, newest first
+    pub limit: Option<usize>,
+}
+
+fn require_admin(auth: &Auth) -> Result<(), ApiError> {
+    if auth.user.role != UserRole::Admin {
+        return Err(ApiError::Authorization(
+            "Only admins can manage operator notifications".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Owner of the channels a request manages
+fn channel_owner(auth: &Auth, operators: bool) -> Result<String, ApiError> {
+    if operators {
+        require_admin(auth)?;
+        return Ok(OPERATORS.to_string());
+    }
+    Ok(auth.user.id.to_string())
+}
+
+/// List the notification channels of the current user
+#[utoipa::path(
+    get,
+    path = "/notifications/channels",
+    tag = "notifications",
+    params(ChannelScopeQuery),
+    responses((status = 200, description = "Notification channels", body = Vec<Object>)),
+    security(("bearer" = []))
+)]
+async fn list_channels(
+    State(api_service): State<Arc<ApiService>>,
+    auth: Auth,
+    Query(scope): Query<ChannelScopeQuery>,
+) -> Result<Json<Vec<NotificationChannel>>, ApiError> {
+    let owner = channel_owner(&auth, scope.operators)?;
+    let channels = api_service
+        .notification_service
+        .list_channels(&owner)
+        .await?;
+
+    Ok(Json(channels))
+}
+
+/// Create a notification channel
+#[utoipa::path(
+    post,
+    path = "/notifications/channels",
+    tag = "notifications",
+    params(ChannelScopeQuery),
+    request_body(content = Object, description = "Channel name, target, routing rule and whether it is enabled"),
+    responses((status = 201, description = "Notification channel", body = Object)),
+    security(("bearer" = []))
+)]
+async fn create_channel(
+    State(api_service): State<Arc<ApiService>>,
+    auth: Auth,
+    Query(scope): Query<ChannelScopeQuery>,
+    Json(request): Json<ChannelRequest>,
+) -> Result<(StatusCode, Json<NotificationChannel>), ApiError> {
+    let owner = channel_owner(&auth, scope.operators)?;
+    let channel = api_service
+        .notification_service
+        .create_channel(&owner, request)
+        .await?;
+
+    Ok((StatusCode::CREATED, Json(channel)))
+}
+
+/// Get a notification channel
+#[utoipa::path(
+    get,
+    path = "/notifications/channels/{id}",
+    tag = "notifications",
+    params(("id" = String, Path, description = "Channel ID"), ChannelScopeQuery),
+    responses((status = 200, description = "Notification channel", body = Object)),
+    security(("bearer" = []))
+)]
+async fn get_channel(
+    State(api_service): State<Arc<ApiService>>,
+    auth: Auth,
+    Path(id): Path<String>,
+    Query(scope): Query<ChannelScopeQuery>,
+) -> Result<Json<NotificationChannel>, ApiError> {
+    let owner = channel_owner(&auth, scope.operators)?;
+    let channel = api_service
+        .notification_service
+        .get_channel(&owner, &id)
+        .await?;
+
+    Ok(Json(channel))
+}
+
+/// Replace the settings of a notification channel
+#[utoipa::path(
+    put,
+    path = "/notifications/channels/{id}",
+    tag = "notifications",
+    params(("id" = String, Path, description = "Channel ID"), ChannelScopeQuery),
+    request_body(content = Object, description = "Channel name, target, routing rule and whether it is enabled"),
+    responses((status = 200, description = "Notification channel", body = Object)),
+    security(("bearer" = []))
+)]
+async fn update_channel(
+    State(api_service): State<Arc<ApiService>>,
+    auth: Auth,
+    Path(id): Path<String>,
+    Query(scope): Query<ChannelScopeQuery>,
+    Json(request): Json<ChannelRequest>,
+) -> Result<Json<NotificationChannel>, ApiError> {
+    let owner = channel_owner(&auth, scope.operators)?;
+    let channel = api_service
+        .notification_service
+        .update_channel(&owner, &id, request)
+        .await?;
+
+    Ok(Json(channel))
+}
+
+/// Delete a notification channel
+#[utoipa::path(
+    delete,
+    path = "/notifications/channels/{id}",
+    tag = "notifications",
+    params(("id" = String, Path, description = "Channel ID"), ChannelScopeQuery),
+    responses((status = 204, description = "Notification channel deleted")),
+    security(("bearer" = []))
+)]
+async fn delete_channel(
+    State(api_service): State<Arc<ApiService>>,
+    auth: Auth,
+    Path(id): Path<String>,
+    Query(scope): Query<ChannelScopeQuery>,
+) -> Result<StatusCode, ApiError> {
+    let owner = channel_owner(&auth, scope.operators)?;
+    api_service
+        .notification_service
+        .delete_channel(&owner, &id)
+        .await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Send a test notification to a channel, whatever its routing rule
+#[utoipa::path(
+    post,
+    path = "/notifications/channels/{id}/test",
+    tag = "notifications",
+    params(("id" = String, Path, description = "Channel ID"), ChannelScopeQuery),
+    responses((status = 200, description = "Delivery of the test notification", body = Object)),
+    security(("bearer" = []))
+)]
+async fn test_channel(
+    State(api_service): State<Arc<ApiService>>,
+    auth: Auth,
+    Path(id): Path<String>,
+    Query(scope): Query<ChannelScopeQuery>,
+) -> Result<Json<Delivery>, ApiError> {
+    let owner = channel_owner(&auth, scope.operators)?;
+    let delivery = api_service
+        .notification_service
+        .test_channel(&owner, &id)
+        .await?;
+
+    Ok(Json(delivery))
+}
+
+/// List the latest deliveries to the channels of the current user, newest first
+#[utoipa::path(
+    get,
+    path = "/notifications/deliveries",
+    tag = "notifications",
+    params(ListDeliveriesQuery),
+    responses((status = 200, description = "Deliveries with their status", body = Vec<Object>)),
+    security(("bearer" = []))
+)]
+async fn list_deliveries(
+    State(api_service): State<Arc<ApiService>>,
+    auth: Auth,
+    Query(query): Query<ListDeliveriesQuery>,
+) -> Result<Json<Vec<Delivery>>, ApiError> {
+    let owner = channel_owner(&auth, query.operators)?;
+    let limit = query.limit.unwrap_or(100).min(MAX_DELIVERIES);
+    let deliveries = api_service
+        .notification_service
+        .list_deliveries(&owner, limit)
+        .await?;
+
+    Ok(Json(deliveries))
+}
+
+/// Raise an alert, forwarded by the workers, to the channels it is routed to
+#[utoipa::path(
+    post,
+    path = "/admin/alerts",
+    tag = "notifications",
+    request_body(content = Object, description = "Alert key, severity, tenant, message and timestamp"),
+    responses((status = 202, description = "Alert accepted for delivery")),
+    security(("bearer" = []))
+)]
+async fn raise_alert(
+    State(api_service): State<Arc<ApiService>>,
+    auth: Auth,
+    Json(alert): Json<Alert>,
+) -> Result<StatusCode, ApiError> {
+    require_admin(&auth)?;
+
+    api_service
+        .notification_service
+        .send(&alert)
+        .await
+        .map_err(ApiError::Service)?;
+
+    Ok(StatusCode::ACCEPTED)
+}
+
+/// Notification routes
+pub fn notification_routes(api_service: Arc<ApiService>) -> Router {
+    Router::new()
+        .route(
+            "/notifications/channels",
+            get(list_channels).post(create_channel),
+        )
+        .route(
+            "/notifications/channels/:id",
+            get(get_channel).put(update_channel).delete(delete_channel),
+        )
+        .route("/notifications/channels/:id/test", post(test_channel))
+        .route("/notifications/deliveries", get(list_deliveries))
+        .route("/admin/alerts", post(raise_alert))
+        .with_state(api_service)
+}
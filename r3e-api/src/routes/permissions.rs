@@ -0,0 +1,253 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+use axum::{
+    extract::{Path, Query, State},
+    routing::{get, post},
+    Json, Router,
+};
+use r3e_deno::sandbox::{PermissionGrant, PermissionKind, PermissionUse};
+use serde::Deserialize;
+use std::sync::Arc;
+use utoipa::{IntoParams, ToSchema};
+use uuid::Uuid;
+
+use crate::auth::Auth;
+use crate::authz::authorize_function_permissions;
+use crate::error::ApiError;
+use crate::service::ApiService;
+
+/// Default number of audit records returned by a query
+const DEFAULT_USES_LIMIT: usize = 100;
+
+/// Maximum number of audit records returned by a query
+const MAX_USES_LIMIT: usize = 1000;
+
+/// Grant permission request
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct GrantPermissionRequest {
+    /// Kind of resource
+    #[schema(value_type = String, example = "net")]
+    pub kind: PermissionKind,
+
+    /// Resource pattern: a host or `*.domain` for net, an absolute path for fs, a variable
+    /// name or `PREFIX_*` for env
+    pub resource: String,
+
+    /// Expiration timestamp (secs since epoch)
+    pub expires_at: Option<u64>,
+}
+
+/// Approve permission request
+#[derive(Debug, Default, Deserialize, ToSchema)]
+pub struct ApprovePermissionRequest {
+    /// Expiration timestamp (secs since epoch)
+    pub expires_at: Option<u64>,
+}
+
+/// Permission uses query
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct PermissionUsesQuery {
+    /// Maximum number of records, most recent first
+    pub limit: Option<usize>,
+}
+
+/// Only the owner of a function and admins manage its permissions
+async fn authorize(api_service: &ApiService, auth: &Auth, id: Uuid) -> Result<(), ApiError> {
+    let function = api_service.function_service.get_function(id).await?;
+    authorize_function_permissions(auth, &function)
+}
+
+/// List the grants of a function, pending ones included
+#[utoipa::path(
+    get,
+    path = "/functions/{id}/permissions",
+    tag = "permissions",
+    params(("id" = Uuid, Path, description = "Function ID")),
+    responses((status = 200, description = "Grants, pending ones included", body = Vec<Object>)),
+    security(("bearer" = []))
+)]
+async fn list_permissions(
+    State(api_service): State<Arc<ApiService>>,
+    auth: Auth,
+    Path(id): Path<Uuid>,
+) -> Result<Json<Vec<PermissionGrant>>, ApiError> {
+    authorize(&api_service, &auth, id).await?;
+
+    let grants = api_service.permission_grants.list(&id.to_string())?;
+
+    Ok(Json(grants))
+}
+
+/// Grant a function access to a resource without a prior request
+#[utoipa::path(
+    post,
+    path = "/functions/{id}/permissions",
+    tag = "permissions",
+    params(("id" = Uuid, Path, description = "Function ID")),
+    request_body = GrantPermissionRequest,
+    responses((status = 200, description = "Approved grant", body = Object)),
+    security(("bearer" = []))
+)]
+async fn grant_permission(
+    State(api_service): State<Arc<ApiService>>,
+    auth: Auth,
+    Path(id): Path<Uuid>,
+    Json(request): Json<GrantPermissionRequest>,
+) -> Result<Json<PermissionGrant>, ApiError> {
+    authorize(&api_service, &auth, id).await?;
+
+    log::info!(
+        "User {} granting {} access to {} to function {}",
+        auth.user.id,
+        request.kind,
+        request.resource,
+        id
+    );
+    let grant = api_service.permission_grants.grant(
+        &id.to_string(),
+        request.kind,
+        &request.resource,
+        &auth.user.id.to_string(),
+        request.expires_at,
+    )?;
+
+    Ok(Json(grant))
+}
+
+/// Approve a pending request of a function
+#[utoipa::path(
+    post,
+    path = "/functions/{id}/permissions/{grant_id}/approve",
+    tag = "permissions",
+    params(
+        ("id" = Uuid, Path, description = "Function ID"),
+        ("grant_id" = String, Path, description = "Grant ID")
+    ),
+    request_body(content = Option<ApprovePermissionRequest>),
+    responses((status = 200, description = "Approved grant", body = Object)),
+    security(("bearer" = []))
+)]
+async fn approve_permission(
+    State(api_service): State<Arc<ApiService>>,
+    auth: Auth,
+    Path((id, grant_id)): Path<(Uuid, String)>,
+    request: Option<Json<ApprovePermissionRequest>>,
+) -> Result<Json<PermissionGrant>, ApiError> {
+    authorize(&api_service, &auth, id).await?;
+
+    let request = request.map(|Json(request)| request).unwrap_or_default();
+    let grant = api_service.permission_grants.approve(
+        &id.to_string(),
+        &grant_id,
+        &auth.user.id.to_string(),
+        request.expires_at,
+    )?;
+
+    Ok(Json(grant))
+}
+
+/// Deny a pending request of a function
+#[utoipa::path(
+    post,
+    path = "/functions/{id}/permissions/{grant_id}/deny",
+    tag = "permissions",
+    params(
+        ("id" = Uuid, Path, description = "Function ID"),
+        ("grant_id" = String, Path, description = "Grant ID")
+    ),
+    responses((status = 200, description = "Denied grant", body = Object)),
+    security(("bearer" = []))
+)]
+async fn deny_permission(
+    State(api_service): State<Arc<ApiService>>,
+    auth: Auth,
+    Path((id, grant_id)): Path<(Uuid, String)>,
+) -> Result<Json<PermissionGrant>, ApiError> {
+    authorize(&api_service, &auth, id).await?;
+
+    let grant = api_service.permission_grants.deny(
+        &id.to_string(),
+        &grant_id,
+        &auth.user.id.to_string(),
+    )?;
+
+    Ok(Json(grant))
+}
+
+/// Revoke an approved grant; running functions lose access on their next use
+#[utoipa::path(
+    delete,
+    path = "/functions/{id}/permissions/{grant_id}",
+    tag = "permissions",
+    params(
+        ("id" = Uuid, Path, description = "Function ID"),
+        ("grant_id" = String, Path, description = "Grant ID")
+    ),
+    responses((status = 200, description = "Revoked grant", body = Object)),
+    security(("bearer" = []))
+)]
+async fn revoke_permission(
+    State(api_service): State<Arc<ApiService>>,
+    auth: Auth,
+    Path((id, grant_id)): Path<(Uuid, String)>,
+) -> Result<Json<PermissionGrant>, ApiError> {
+    authorize(&api_service, &auth, id).await?;
+
+    let grant = api_service.permission_grants.revoke(
+        &id.to_string(),
+        &grant_id,
+        &auth.user.id.to_string(),
+    )?;
+
+    Ok(Json(grant))
+}
+
+/// Audit records of the permission checks of a function
+#[utoipa::path(
+    get,
+    path = "/functions/{id}/permissions/uses",
+    tag = "permissions",
+    params(("id" = Uuid, Path, description = "Function ID"), PermissionUsesQuery),
+    responses((status = 200, description = "Permission checks, most recent first", body = Vec<Object>)),
+    security(("bearer" = []))
+)]
+async fn list_permission_uses(
+    State(api_service): State<Arc<ApiService>>,
+    auth: Auth,
+    Path(id): Path<Uuid>,
+    Query(query): Query<PermissionUsesQuery>,
+) -> Result<Json<Vec<PermissionUse>>, ApiError> {
+    authorize(&api_service, &auth, id).await?;
+
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_USES_LIMIT)
+        .min(MAX_USES_LIMIT);
+    let mut uses = api_service.permission_grants.uses(&id.to_string(), limit)?;
+    uses.reverse();
+
+    Ok(Json(uses))
+}
+
+/// Permission routes
+pub fn permission_routes(api_service: Arc<ApiService>) -> Router {
+    Router::new()
+        .route("/functions/:id/permissions", get(list_permissions))
+        .route("/functions/:id/permissions", post(grant_permission))
+        .route("/functions/:id/permissions/uses", get(list_permission_uses))
+        .route(
+            "/functions/:id/permissions/:grant_id/approve",
+            post(approve_permission),
+        )
+        .route(
+            "/functions/:id/permissions/:grant_id/deny",
+            post(deny_permission),
+        )
+        .route(
+            "/functions/:id/permissions/:grant_id",
+            axum::routing::delete(revoke_permission),
+        )
+        .with_state(api_service)
+}
@@ -0,0 +1,191 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+use axum::{
+    extract::{Path, Query, State},
+    routing::get,
+    Json, Router,
+};
+use r3e_deno::sandbox::{Policy, PolicyDecision, PolicyRule};
+use serde::Deserialize;
+use std::sync::Arc;
+use utoipa::IntoParams;
+use uuid::Uuid;
+
+use crate::auth::Auth;
+use crate::authz::is_admin;
+use crate::error::ApiError;
+use crate::service::ApiService;
+
+/// Default number of decisions returned by a query
+const DEFAULT_DECISIONS_LIMIT: usize = 100;
+
+/// Maximum number of decisions returned by a query
+const MAX_DECISIONS_LIMIT: usize = 1000;
+
+/// Policy decisions query
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct PolicyDecisionsQuery {
+    /// Function decided on
+    pub function_id: Option<Uuid>,
+
+    /// Maximum number of decisions, most recent first
+    pub limit: Option<usize>,
+}
+
+/// Only admins manage the policies
+fn authorize(auth: &Auth) -> Result<(), ApiError> {
+    if !is_admin(auth) {
+        return Err(ApiError::Authorization(
+            "Only admins can manage policies".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// List the policies, in the order they are decided
+#[utoipa::path(
+    get,
+    path = "/admin/policies",
+    tag = "policies",
+    responses((status = 200, description = "Policies", body = Vec<Object>)),
+    security(("bearer" = []))
+)]
+async fn list_policies(
+    State(api_service): State<Arc<ApiService>>,
+    auth: Auth,
+) -> Result<Json<Vec<Policy>>, ApiError> {
+    authorize(&auth)?;
+
+    Ok(Json(api_service.policy_engine.list()?))
+}
+
+/// Create a policy
+#[utoipa::path(
+    post,
+    path = "/admin/policies",
+    tag = "policies",
+    request_body(content = Object, description = "Policy rule"),
+    responses((status = 200, description = "Created policy", body = Object)),
+    security(("bearer" = []))
+)]
+async fn create_policy(
+    State(api_service): State<Arc<ApiService>>,
+    auth: Auth,
+    Json(rule): Json<PolicyRule>,
+) -> Result<Json<Policy>, ApiError> {
+    authorize(&auth)?;
+
+    let policy = api_service
+        .policy_engine
+        .create(rule, &auth.user.id.to_string())?;
+
+    Ok(Json(policy))
+}
+
+/// Get a policy
+#[utoipa::path(
+    get,
+    path = "/admin/policies/{id}",
+    tag = "policies",
+    params(("id" = String, Path, description = "Policy ID")),
+    responses((status = 200, description = "Policy", body = Object)),
+    security(("bearer" = []))
+)]
+async fn get_policy(
+    State(api_service): State<Arc<ApiService>>,
+    auth: Auth,
+    Path(id): Path<String>,
+) -> Result<Json<Policy>, ApiError> {
+    authorize(&auth)?;
+
+    Ok(Json(api_service.policy_engine.get(&id)?))
+}
+
+/// Replace the rule of a policy
+#[utoipa::path(
+    put,
+    path = "/admin/policies/{id}",
+    tag = "policies",
+    params(("id" = String, Path, description = "Policy ID")),
+    request_body(content = Object, description = "Policy rule"),
+    responses((status = 200, description = "Updated policy", body = Object)),
+    security(("bearer" = []))
+)]
+async fn update_policy(
+    State(api_service): State<Arc<ApiService>>,
+    auth: Auth,
+    Path(id): Path<String>,
+    Json(rule): Json<PolicyRule>,
+) -> Result<Json<Policy>, ApiError> {
+    authorize(&auth)?;
+
+    log::info!("User {} updating policy {}", auth.user.id, id);
+    let policy = api_service.policy_engine.update(&id, rule)?;
+
+    Ok(Json(policy))
+}
+
+/// Delete a policy
+#[utoipa::path(
+    delete,
+    path = "/admin/policies/{id}",
+    tag = "policies",
+    params(("id" = String, Path, description = "Policy ID")),
+    responses((status = 200, description = "Policy deleted")),
+    security(("bearer" = []))
+)]
+async fn delete_policy(
+    State(api_service): State<Arc<ApiService>>,
+    auth: Auth,
+    Path(id): Path<String>,
+) -> Result<(), ApiError> {
+    authorize(&auth)?;
+
+    log::info!("User {} deleting policy {}", auth.user.id, id);
+    api_service.policy_engine.delete(&id)?;
+
+    Ok(())
+}
+
+/// Decisions taken by the policies, before invocations and op dispatch
+#[utoipa::path(
+    get,
+    path = "/admin/policies/decisions",
+    tag = "policies",
+    params(PolicyDecisionsQuery),
+    responses((status = 200, description = "Decisions, most recent first", body = Vec<Object>)),
+    security(("bearer" = []))
+)]
+async fn list_policy_decisions(
+    State(api_service): State<Arc<ApiService>>,
+    auth: Auth,
+    Query(query): Query<PolicyDecisionsQuery>,
+) -> Result<Json<Vec<PolicyDecision>>, ApiError> {
+    authorize(&auth)?;
+
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_DECISIONS_LIMIT)
+        .min(MAX_DECISIONS_LIMIT);
+    let function_id = query.function_id.map(|id| id.to_string());
+    let mut decisions = api_service
+        .policy_engine
+        .decisions(function_id.as_deref(), limit)?;
+    decisions.reverse();
+
+    Ok(Json(decisions))
+}
+
+/// Policy routes
+pub fn policy_routes(api_service: Arc<ApiService>) -> Router {
+    Router::new()
+        .route("/admin/policies", get(list_policies).post(create_policy))
+        .route("/admin/policies/decisions", get(list_policy_decisions))
+        .route(
+            "/admin/policies/:id",
+            get(get_policy).put(update_policy).delete(delete_policy),
+        )
+        .with_state(api_service)
+}
@@ -0,0 +1,157 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+use axum::{
+    extract::{Path, State},
+    routing::{get, put},
+    Json, Router,
+};
+use r3e_built_in_services::pricing::PricePlan;
+use r3e_built_in_services::quota::{PlanTier, QuotaUsage};
+use serde::Deserialize;
+use std::sync::Arc;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::auth::Auth;
+use crate::error::ApiError;
+use crate::models::user::UserRole;
+use crate::service::ApiService;
+
+/// Set plan request
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct SetPlanRequest {
+    /// Plan tier
+    #[schema(value_type = String, example = "pro")]
+    pub tier: PlanTier,
+}
+
+fn require_admin(auth: &Auth) -> Result<(), ApiError> {
+    if auth.user.role != UserRole::Admin {
+        return Err(ApiError::Authorization(
+            "Only admins can manage quotas".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// List the priced plans, cheapest first
+#[utoipa::path(
+    get,
+    path = "/plans",
+    tag = "quota",
+    responses((status = 200, description = "Plans with their fees, included invocations, rate limits and resource ceilings", body = Vec<Object>))
+)]
+async fn list_plans(
+    State(api_service): State<Arc<ApiService>>,
+) -> Result<Json<Vec<PricePlan>>, ApiError> {
+    let plans = api_service.pricing_service.list_plans().await?;
+
+    Ok(Json(plans))
+}
+
+/// Get the plan the current user is billed on
+#[utoipa::path(
+    get,
+    path = "/plan",
+    tag = "quota",
+    responses((status = 200, description = "Plan of the user", body = Object)),
+    security(("bearer" = []))
+)]
+async fn get_my_plan(
+    State(api_service): State<Arc<ApiService>>,
+    auth: Auth,
+) -> Result<Json<PricePlan>, ApiError> {
+    let plan = api_service
+        .pricing_service
+        .get_user_plan(&auth.user.id.to_string())
+        .await?;
+
+    Ok(Json(plan))
+}
+
+/// Get the quota usage of the current user
+#[utoipa::path(
+    get,
+    path = "/quota",
+    tag = "quota",
+    responses((status = 200, description = "Quota usage", body = Object)),
+    security(("bearer" = []))
+)]
+async fn get_my_usage(
+    State(api_service): State<Arc<ApiService>>,
+    auth: Auth,
+) -> Result<Json<QuotaUsage>, ApiError> {
+    let usage = api_service
+        .quota_service
+        .get_usage(&auth.user.id.to_string())?;
+
+    Ok(Json(usage))
+}
+
+/// Get the quota usage of a user
+#[utoipa::path(
+    get,
+    path = "/admin/quota/{user_id}",
+    tag = "quota",
+    params(("user_id" = Uuid, Path, description = "User ID")),
+    responses((status = 200, description = "Quota usage", body = Object)),
+    security(("bearer" = []))
+)]
+async fn get_usage(
+    State(api_service): State<Arc<ApiService>>,
+    auth: Auth,
+    Path(user_id): Path<Uuid>,
+) -> Result<Json<QuotaUsage>, ApiError> {
+    require_admin(&auth)?;
+
+    let usage = api_service.quota_service.get_usage(&user_id.to_string())?;
+
+    Ok(Json(usage))
+}
+
+/// Move a user to another plan tier
+#[utoipa::path(
+    put,
+    path = "/admin/quota/{user_id}/plan",
+    tag = "quota",
+    params(("user_id" = Uuid, Path, description = "User ID")),
+    request_body = SetPlanRequest,
+    responses((status = 200, description = "Quota usage on the new plan", body = Object)),
+    security(("bearer" = []))
+)]
+async fn set_plan(
+    State(api_service): State<Arc<ApiService>>,
+    auth: Auth,
+    Path(user_id): Path<Uuid>,
+    Json(request): Json<SetPlanRequest>,
+) -> Result<Json<QuotaUsage>, ApiError> {
+    require_admin(&auth)?;
+
+    log::info!(
+        "User {} moving {} to the {:?} plan",
+        auth.user.id,
+        user_id,
+        request.tier
+    );
+    let tenant = user_id.to_string();
+    api_service
+        .pricing_service
+        .assign_plan(&tenant, request.tier)
+        .await?;
+    api_service.quota_service.set_plan(&tenant, request.tier)?;
+    let usage = api_service.quota_service.get_usage(&tenant)?;
+
+    Ok(Json(usage))
+}
+
+/// Quota routes
+pub fn quota_routes(api_service: Arc<ApiService>) -> Router {
+    Router::new()
+        .route("/plans", get(list_plans))
+        .route("/plan", get(get_my_plan))
+        .route("/quota", get(get_my_usage))
+        .route("/admin/quota/:user_id", get(get_usage))
+        .route("/admin/quota/:user_id/plan", put(set_plan))
+        .with_state(api_service)
+}
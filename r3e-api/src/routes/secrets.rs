@@ -0,0 +1,139 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+use axum::{
+    extract::State,
+    routing::{get, post},
+    Json, Router,
+};
+use r3e_secrets::transfer::{SealedBundle, TransferKey, TransferPublicKey};
+use serde::Deserialize;
+use std::sync::Arc;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::auth::Auth;
+use crate::error::ApiError;
+use crate::secret_transfer;
+use crate::service::ApiService;
+use crate::snapshot::{self, ConflictPolicy, ImportCounts};
+
+/// Export secrets request
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct ExportSecretsRequest {
+    /// Hex encoded SEC1 P-256 public key the bundle is sealed to
+    pub recipient_key: String,
+
+    /// Functions whose secrets are exported, all of the user's if not set
+    #[serde(default)]
+    pub function_ids: Option<Vec<Uuid>>,
+}
+
+/// Import secrets request
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct ImportSecretsRequest {
+    /// Bundle sealed to the platform transfer key
+    #[schema(value_type = Object)]
+    pub bundle: SealedBundle,
+
+    /// Conflict policy for secrets that already exist, defaults to fail
+    #[serde(default)]
+    pub conflict: ConflictPolicy,
+}
+
+fn master_key(api_service: &ApiService) -> Result<[u8; 32], ApiError> {
+    let key = api_service
+        .config
+        .secrets_master_key
+        .as_deref()
+        .ok_or_else(|| {
+            ApiError::Validation(
+                "Bulk secret transfer requires SECRETS_MASTER_KEY to be configured".to_string(),
+            )
+        })?;
+    snapshot::parse_key(key)
+}
+
+/// Get the transfer key handler
+#[utoipa::path(
+    get,
+    path = "/secrets/transfer-key",
+    tag = "secrets",
+    responses((status = 200, description = "Public key to seal import bundles to", body = Object)),
+    security(("bearer" = []))
+)]
+async fn get_transfer_key(
+    State(api_service): State<Arc<ApiService>>,
+    _auth: Auth,
+) -> Result<Json<TransferPublicKey>, ApiError> {
+    let master_key = master_key(&api_service)?;
+
+    Ok(Json(TransferKey::derive(&master_key).published()))
+}
+
+/// Export secrets handler
+#[utoipa::path(
+    post,
+    path = "/secrets/export",
+    tag = "secrets",
+    request_body = ExportSecretsRequest,
+    responses((status = 200, description = "Secrets sealed to the recipient key", body = Object)),
+    security(("bearer" = []))
+)]
+async fn export_secrets(
+    State(api_service): State<Arc<ApiService>>,
+    auth: Auth,
+    Json(request): Json<ExportSecretsRequest>,
+) -> Result<Json<SealedBundle>, ApiError> {
+    let master_key = master_key(&api_service)?;
+
+    let bundle = secret_transfer::export(
+        &api_service.db,
+        api_service.audit_store.as_ref(),
+        &master_key,
+        auth.user.id,
+        request.function_ids.as_deref(),
+        &request.recipient_key,
+    )
+    .await?;
+
+    Ok(Json(bundle))
+}
+
+/// Import secrets handler
+#[utoipa::path(
+    post,
+    path = "/secrets/import",
+    tag = "secrets",
+    request_body = ImportSecretsRequest,
+    responses((status = 200, description = "Secrets created, overwritten and skipped", body = ImportCounts)),
+    security(("bearer" = []))
+)]
+async fn import_secrets(
+    State(api_service): State<Arc<ApiService>>,
+    auth: Auth,
+    Json(request): Json<ImportSecretsRequest>,
+) -> Result<Json<ImportCounts>, ApiError> {
+    let master_key = master_key(&api_service)?;
+
+    let counts = secret_transfer::import(
+        &api_service.db,
+        api_service.audit_store.as_ref(),
+        &master_key,
+        auth.user.id,
+        &request.bundle,
+        request.conflict,
+    )
+    .await?;
+
+    Ok(Json(counts))
+}
+
+/// Secret routes
+pub fn secret_routes(api_service: Arc<ApiService>) -> Router {
+    Router::new()
+        .route("/secrets/transfer-key", get(get_transfer_key))
+        .route("/secrets/export", post(export_secrets))
+        .route("/secrets/import", post(import_secrets))
+        .with_state(api_service)
+}
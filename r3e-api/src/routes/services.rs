@@ -3,6 +3,8 @@
 
 use axum::{
     extract::{Path, Query, State},
+    http::{header, HeaderMap},
+    response::{IntoResponse, Response},
     routing::{get, post},
     Json, Router,
 };
@@ -12,14 +14,30 @@ use uuid::Uuid;
 use validator::Validate;
 
 use crate::auth::Auth;
+use crate::authz::{admit_invocation, authorize_service, authorize_service_client};
 use crate::error::ApiError;
+use crate::etag::{self, with_etag};
+use crate::models::function::{FunctionInvocationResponse, FunctionStatus};
 use crate::models::service::{
     CreateServiceRequest, Service, ServiceDiscoveryRequest, ServiceDiscoveryResponse,
-    ServiceListRequest, ServiceListResponse, ServiceStatus, ServiceSummary, UpdateServiceRequest,
+    ServiceInvocationRequest, ServiceListRequest, ServiceListResponse, ServiceSdkRequest,
+    ServiceStatus, ServiceSummary, UpdateServiceRequest,
 };
+use crate::sdk::{generate_sdk, sdk_file_name};
 use crate::service::ApiService;
 
+/// Maximum number of functions in a generated client
+const MAX_SDK_FUNCTIONS: u32 = 1000;
+
 /// List services handler
+#[utoipa::path(
+    get,
+    path = "/services",
+    tag = "services",
+    params(ServiceListRequest),
+    responses((status = 200, description = "Services of the user", body = ServiceListResponse)),
+    security(("bearer" = []))
+)]
 async fn list_services(
     State(api_service): State<Arc<ApiService>>,
     auth: Auth,
@@ -51,31 +69,43 @@ async fn list_services(
 }
 
 /// Get service handler
+#[utoipa::path(
+    get,
+    path = "/services/{id}",
+    tag = "services",
+    params(("id" = Uuid, Path, description = "Service ID")),
+    responses((status = 200, description = "Service", body = Service, headers(("ETag" = String, description = "Version of the service, sent back in `If-Match`")))),
+    security(("bearer" = []))
+)]
 async fn get_service(
     State(api_service): State<Arc<ApiService>>,
     auth: Auth,
     Path(id): Path<Uuid>,
-) -> Result<Json<Service>, ApiError> {
+) -> Result<Response, ApiError> {
     // Get the service
     let service = api_service.service_service.get_service(id).await?;
 
     // Check if the user owns the service
-    if service.user_id != auth.user.id {
-        return Err(ApiError::Authorization(
-            "You are not authorized to view this service".to_string(),
-        ));
-    }
+    authorize_service(&auth, &service, "view")?;
 
     // Return the service
-    Ok(Json(service))
+    Ok(with_etag(&service.updated_at, service))
 }
 
 /// Create service handler
+#[utoipa::path(
+    post,
+    path = "/services",
+    tag = "services",
+    request_body = CreateServiceRequest,
+    responses((status = 200, description = "Created service", body = Service, headers(("ETag" = String, description = "Version of the service, sent back in `If-Match`")))),
+    security(("bearer" = []))
+)]
 async fn create_service(
     State(api_service): State<Arc<ApiService>>,
     auth: Auth,
     Json(request): Json<CreateServiceRequest>,
-) -> Result<Json<Service>, ApiError> {
+) -> Result<Response, ApiError> {
     // Validate the request
     request
         .validate()
@@ -95,16 +125,29 @@ async fn create_service(
         .await?;
 
     // Return the service
-    Ok(Json(service))
+    Ok(with_etag(&service.updated_at, service))
 }
 
 /// Update service handler
+#[utoipa::path(
+    post,
+    path = "/services/{id}",
+    tag = "services",
+    params(
+        ("id" = Uuid, Path, description = "Service ID"),
+        ("If-Match" = Option<String>, Header, description = "ETag the update applies to")
+    ),
+    request_body = UpdateServiceRequest,
+    responses((status = 200, description = "Updated service", body = Service, headers(("ETag" = String, description = "Version of the service, sent back in `If-Match`")))),
+    security(("bearer" = []))
+)]
 async fn update_service(
     State(api_service): State<Arc<ApiService>>,
     auth: Auth,
     Path(id): Path<Uuid>,
+    headers: HeaderMap,
     Json(request): Json<UpdateServiceRequest>,
-) -> Result<Json<Service>, ApiError> {
+) -> Result<Response, ApiError> {
     // Validate the request
     request
         .validate()
@@ -114,11 +157,10 @@ async fn update_service(
     let service = api_service.service_service.get_service(id).await?;
 
     // Check if the user owns the service
-    if service.user_id != auth.user.id {
-        return Err(ApiError::Authorization(
-            "You are not authorized to update this service".to_string(),
-        ));
-    }
+    authorize_service(&auth, &service, "update")?;
+
+    // Reject the update if the client's copy is stale
+    let if_updated_at = etag::if_match(&headers, &service.updated_at)?;
 
     // Update the service
     let service = api_service
@@ -130,14 +172,23 @@ async fn update_service(
             request.config.as_ref(),
             request.status,
             request.visibility,
+            if_updated_at,
         )
         .await?;
 
     // Return the service
-    Ok(Json(service))
+    Ok(with_etag(&service.updated_at, service))
 }
 
 /// Delete service handler
+#[utoipa::path(
+    delete,
+    path = "/services/{id}",
+    tag = "services",
+    params(("id" = Uuid, Path, description = "Service ID")),
+    responses((status = 200, description = "Service deleted")),
+    security(("bearer" = []))
+)]
 async fn delete_service(
     State(api_service): State<Arc<ApiService>>,
     auth: Auth,
@@ -147,11 +198,7 @@ async fn delete_service(
     let service = api_service.service_service.get_service(id).await?;
 
     // Check if the user owns the service
-    if service.user_id != auth.user.id {
-        return Err(ApiError::Authorization(
-            "You are not authorized to delete this service".to_string(),
-        ));
-    }
+    authorize_service(&auth, &service, "delete")?;
 
     // Delete the service
     api_service.service_service.delete_service(id).await?;
@@ -160,7 +207,104 @@ async fn delete_service(
     Ok(Json(()))
 }
 
+/// Invoke a function of a service by name handler
+#[utoipa::path(
+    post,
+    path = "/services/{id}/functions/{name}/invoke",
+    tag = "services",
+    params(
+        ("id" = Uuid, Path, description = "Service ID"),
+        ("name" = String, Path, description = "Function name")
+    ),
+    request_body = ServiceInvocationRequest,
+    responses((status = 200, description = "Invocation result", body = FunctionInvocationResponse)),
+    security(("bearer" = []))
+)]
+async fn invoke_service(
+    State(api_service): State<Arc<ApiService>>,
+    auth: Auth,
+    Path((id, function)): Path<(Uuid, String)>,
+    Json(request): Json<ServiceInvocationRequest>,
+) -> Result<Json<FunctionInvocationResponse>, ApiError> {
+    // Get the function
+    let function = api_service
+        .function_service
+        .get_function_by_name(id, &function)
+        .await?;
+
+    // Check that the function may run for this user and within the cost limit
+    admit_invocation(&api_service, &auth, &function, request.max_cost).await?;
+
+    // Invoke the function
+    let response = api_service
+        .function_service
+        .invoke_function(function.id, &request.input)
+        .await?;
+
+    Ok(Json(response))
+}
+
+/// Generate the TypeScript client of a service handler
+#[utoipa::path(
+    get,
+    path = "/services/{id}/sdk",
+    tag = "services",
+    params(("id" = Uuid, Path, description = "Service ID"), ServiceSdkRequest),
+    responses((status = 200, description = "TypeScript client", content_type = "application/typescript", body = String)),
+    security(("bearer" = []))
+)]
+async fn get_service_sdk(
+    State(api_service): State<Arc<ApiService>>,
+    auth: Auth,
+    Path(id): Path<Uuid>,
+    Query(query): Query<ServiceSdkRequest>,
+) -> Result<Response, ApiError> {
+    // Get the service
+    let service = api_service.service_service.get_service(id).await?;
+
+    // Check if the user owns the service or the service is public
+    authorize_service_client(&auth, &service)?;
+
+    // Other users can only invoke the active functions
+    let status = (service.user_id != auth.user.id).then_some(FunctionStatus::Active);
+    let (mut functions, _) = api_service
+        .function_service
+        .list_functions(
+            service.user_id,
+            Some(service.id),
+            status,
+            None,
+            None,
+            MAX_SDK_FUNCTIONS,
+            0,
+        )
+        .await?;
+    functions.sort_by(|a, b| a.name.cmp(&b.name));
+
+    // Return the client as a download
+    let sdk = generate_sdk(&service, &functions, query.target.unwrap_or_default());
+    let disposition = format!("attachment; filename=\"{}\"", sdk_file_name(&service));
+    Ok((
+        [
+            (
+                header::CONTENT_TYPE,
+                "application/typescript; charset=utf-8".to_string(),
+            ),
+            (header::CONTENT_DISPOSITION, disposition),
+        ],
+        sdk,
+    )
+        .into_response())
+}
+
 /// Discover services handler
+#[utoipa::path(
+    get,
+    path = "/services/discover",
+    tag = "services",
+    params(ServiceDiscoveryRequest),
+    responses((status = 200, description = "Public services", body = ServiceDiscoveryResponse))
+)]
 async fn discover_services(
     State(api_service): State<Arc<ApiService>>,
     Query(query): Query<ServiceDiscoveryRequest>,
@@ -197,5 +341,7 @@ pub fn service_routes(api_service: Arc<ApiService>) -> Router {
         .route("/services/:id", post(update_service))
         .route("/services/:id", axum::routing::delete(delete_service))
         .route("/services/discover", get(discover_services))
+        .route("/services/:id/functions/:name/invoke", post(invoke_service))
+        .route("/services/:id/sdk", get(get_service_sdk))
         .with_state(api_service)
 }
@@ -0,0 +1,75 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+use axum::{
+    extract::State,
+    routing::{get, post},
+    Json, Router,
+};
+use serde::Deserialize;
+use std::sync::Arc;
+use utoipa::ToSchema;
+
+use crate::auth::Auth;
+use crate::authz::is_admin;
+use crate::error::ApiError;
+use crate::service::ApiService;
+use crate::signing::{PublicSigningKey, SigningAlgorithm};
+
+/// Rotate signing key request
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct RotateSigningKeyRequest {
+    /// Algorithm of the key rotated
+    pub algorithm: SigningAlgorithm,
+}
+
+/// Keys signing responses, and the rotated ones still checking the signatures they made
+#[utoipa::path(
+    get,
+    path = "/signing-keys",
+    tag = "signing",
+    responses((status = 200, description = "Published signing keys", body = Vec<PublicSigningKey>))
+)]
+async fn list_signing_keys(
+    State(api_service): State<Arc<ApiService>>,
+) -> Json<Vec<PublicSigningKey>> {
+    Json(api_service.response_signer.public_keys())
+}
+
+/// Replace the key signing responses with an algorithm
+#[utoipa::path(
+    post,
+    path = "/admin/signing-keys/rotate",
+    tag = "signing",
+    request_body = RotateSigningKeyRequest,
+    responses((status = 200, description = "New signing key", body = PublicSigningKey)),
+    security(("bearer" = []))
+)]
+async fn rotate_signing_key(
+    State(api_service): State<Arc<ApiService>>,
+    auth: Auth,
+    Json(request): Json<RotateSigningKeyRequest>,
+) -> Result<Json<PublicSigningKey>, ApiError> {
+    if !is_admin(&auth) {
+        return Err(ApiError::Authorization(
+            "Only admins can rotate signing keys".to_string(),
+        ));
+    }
+
+    log::info!(
+        "User {} rotating the {} signing key",
+        auth.user.id,
+        request.algorithm.as_str()
+    );
+    let key = api_service.response_signer.rotate(request.algorithm)?;
+
+    Ok(Json(key))
+}
+
+/// Response signing routes
+pub fn signing_routes(api_service: Arc<ApiService>) -> Router {
+    Router::new()
+        .route("/signing-keys", get(list_signing_keys))
+        .route("/admin/signing-keys/rotate", post(rotate_signing_key))
+        .with_state(api_service)
+}
@@ -0,0 +1,69 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+use axum::{
+    extract::State,
+    routing::{get, post},
+    Json, Router,
+};
+use r3e_tee::measurements::{AttestationVerdict, MeasurementPolicy};
+use r3e_tee::AttestationReport;
+use serde::Deserialize;
+use std::sync::Arc;
+use utoipa::ToSchema;
+
+use crate::error::ApiError;
+use crate::service::ApiService;
+
+/// Verify attestation request
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct VerifyAttestationRequest {
+    /// Attestation report of an SGX, Nitro or SEV enclave
+    #[schema(value_type = Object)]
+    pub report: AttestationReport,
+
+    /// Hex nonce the report must have been generated for, to check it is fresh
+    #[schema(example = "9f86d081884c7d65")]
+    pub nonce: Option<String>,
+}
+
+/// Verify an attestation report, returning its claims and whether it is trusted
+#[utoipa::path(
+    post,
+    path = "/tee/attestation/verify",
+    tag = "tee",
+    request_body = VerifyAttestationRequest,
+    responses((status = 200, description = "Claims of the report, with the trust verdict and the reasons it is not trusted", body = Object))
+)]
+async fn verify_attestation(
+    State(api_service): State<Arc<ApiService>>,
+    Json(request): Json<VerifyAttestationRequest>,
+) -> Result<Json<AttestationVerdict>, ApiError> {
+    let verdict = api_service
+        .attestation_appraiser
+        .appraise(&request.report, request.nonce.as_deref())
+        .await;
+
+    Ok(Json(verdict))
+}
+
+/// Get the measurements the platform's enclaves are expected to report
+#[utoipa::path(
+    get,
+    path = "/tee/attestation/measurements",
+    tag = "tee",
+    responses((status = 200, description = "Expected measurements per TEE platform", body = Object))
+)]
+async fn get_measurements(
+    State(api_service): State<Arc<ApiService>>,
+) -> Result<Json<MeasurementPolicy>, ApiError> {
+    Ok(Json(api_service.attestation_appraiser.policy().clone()))
+}
+
+/// TEE routes, public so third parties can check the enclaves of the platform
+pub fn tee_routes(api_service: Arc<ApiService>) -> Router {
+    Router::new()
+        .route("/tee/attestation/verify", post(verify_attestation))
+        .route("/tee/attestation/measurements", get(get_measurements))
+        .with_state(api_service)
+}
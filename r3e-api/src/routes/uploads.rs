@@ -0,0 +1,158 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+use axum::{
+    body::Bytes,
+    extract::{DefaultBodyLimit, Path, State},
+    http::{HeaderMap, StatusCode},
+    routing::{get, post, put},
+    Json, Router,
+};
+use std::sync::Arc;
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::auth::Auth;
+use crate::error::ApiError;
+use crate::models::upload::{CreateUploadRequest, Upload, UploadPart};
+use crate::service::ApiService;
+
+/// Header carrying the hex encoded SHA-256 digest of a part
+pub const PART_CHECKSUM_HEADER: &str = "X-Content-SHA256";
+
+/// Start a chunked upload of a function bundle
+#[utoipa::path(
+    post,
+    path = "/uploads",
+    tag = "uploads",
+    request_body = CreateUploadRequest,
+    responses((status = 201, description = "Upload and the size of its parts", body = Upload)),
+    security(("bearer" = []))
+)]
+async fn create_upload(
+    State(api_service): State<Arc<ApiService>>,
+    auth: Auth,
+    Json(request): Json<CreateUploadRequest>,
+) -> Result<(StatusCode, Json<Upload>), ApiError> {
+    request
+        .validate()
+        .map_err(|e| ApiError::Validation(e.to_string()))?;
+
+    let upload = api_service
+        .upload_store
+        .create(auth.user.id, request.size, &request.sha256)
+        .await?;
+
+    Ok((StatusCode::CREATED, Json(upload)))
+}
+
+/// Get an upload, with the parts received so far to resume it
+#[utoipa::path(
+    get,
+    path = "/uploads/{id}",
+    tag = "uploads",
+    params(("id" = Uuid, Path, description = "Upload ID")),
+    responses((status = 200, description = "Upload", body = Upload)),
+    security(("bearer" = []))
+)]
+async fn get_upload(
+    State(api_service): State<Arc<ApiService>>,
+    auth: Auth,
+    Path(id): Path<Uuid>,
+) -> Result<Json<Upload>, ApiError> {
+    let upload = api_service.upload_store.get(auth.user.id, id).await?;
+
+    Ok(Json(upload))
+}
+
+/// Upload a part, replacing it if it was already received
+#[utoipa::path(
+    put,
+    path = "/uploads/{id}/parts/{index}",
+    tag = "uploads",
+    params(
+        ("id" = Uuid, Path, description = "Upload ID"),
+        ("index" = u32, Path, description = "Part index, from 0"),
+        ("X-Content-SHA256" = Option<String>, Header, description = "Hex encoded SHA-256 digest of the part")
+    ),
+    request_body(content = Vec<u8>, content_type = "application/octet-stream", description = "Part content"),
+    responses((status = 200, description = "Stored part", body = UploadPart)),
+    security(("bearer" = []))
+)]
+async fn put_part(
+    State(api_service): State<Arc<ApiService>>,
+    auth: Auth,
+    Path((id, index)): Path<(Uuid, u32)>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<Json<UploadPart>, ApiError> {
+    let checksum = headers
+        .get(PART_CHECKSUM_HEADER)
+        .map(|value| {
+            value.to_str().map_err(|_| {
+                ApiError::Validation(format!("Invalid {} header", PART_CHECKSUM_HEADER))
+            })
+        })
+        .transpose()?;
+
+    let part = api_service
+        .upload_store
+        .put_part(auth.user.id, id, index, body, checksum)
+        .await?;
+
+    Ok(Json(part))
+}
+
+/// Assemble the parts into the bundle, checking its digest
+#[utoipa::path(
+    post,
+    path = "/uploads/{id}/complete",
+    tag = "uploads",
+    params(("id" = Uuid, Path, description = "Upload ID")),
+    responses((status = 200, description = "Completed upload, ready to create a function from", body = Upload)),
+    security(("bearer" = []))
+)]
+async fn complete_upload(
+    State(api_service): State<Arc<ApiService>>,
+    auth: Auth,
+    Path(id): Path<Uuid>,
+) -> Result<Json<Upload>, ApiError> {
+    let upload = api_service.upload_store.complete(auth.user.id, id).await?;
+
+    Ok(Json(upload))
+}
+
+/// Abort an upload, deleting its parts
+#[utoipa::path(
+    delete,
+    path = "/uploads/{id}",
+    tag = "uploads",
+    params(("id" = Uuid, Path, description = "Upload ID")),
+    responses((status = 204, description = "Upload deleted")),
+    security(("bearer" = []))
+)]
+async fn delete_upload(
+    State(api_service): State<Arc<ApiService>>,
+    auth: Auth,
+    Path(id): Path<Uuid>,
+) -> Result<StatusCode, ApiError> {
+    api_service.upload_store.delete(auth.user.id, id).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Upload routes
+pub fn upload_routes(api_service: Arc<ApiService>) -> Router {
+    // Parts may be larger than the default body limit
+    let part_limit = usize::try_from(api_service.config.upload_part_size).unwrap_or(usize::MAX);
+
+    Router::new()
+        .route("/uploads", post(create_upload))
+        .route("/uploads/:id", get(get_upload).delete(delete_upload))
+        .route(
+            "/uploads/:id/parts/:index",
+            put(put_part).layer(DefaultBodyLimit::max(part_limit)),
+        )
+        .route("/uploads/:id/complete", post(complete_upload))
+        .with_state(api_service)
+}
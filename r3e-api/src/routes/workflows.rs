@@ -0,0 +1,275 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+use axum::{
+    extract::{Path, State},
+    routing::{get, post},
+    Json, Router,
+};
+use r3e_built_in_services::workflow::{
+    WorkflowDefinition, WorkflowGraph, WorkflowRun, WorkflowSpec,
+};
+use serde::Deserialize;
+use serde_json::Value;
+use std::sync::Arc;
+use utoipa::ToSchema;
+
+use crate::auth::Auth;
+use crate::error::ApiError;
+use crate::service::ApiService;
+
+/// Start run request
+#[derive(Debug, Default, Deserialize, ToSchema)]
+pub struct StartRunRequest {
+    /// Run input, available to the steps as `${input}`
+    #[serde(default)]
+    pub input: Value,
+}
+
+/// Create a workflow from a JSON or YAML specification
+#[utoipa::path(
+    post,
+    path = "/workflows",
+    tag = "workflows",
+    request_body(
+        content((String = "application/json"), (String = "application/yaml")),
+        description = "Workflow specification in JSON or YAML"
+    ),
+    responses((status = 200, description = "Created workflow", body = Object)),
+    security(("bearer" = []))
+)]
+async fn create_workflow(
+    State(api_service): State<Arc<ApiService>>,
+    auth: Auth,
+    body: String,
+) -> Result<Json<WorkflowDefinition>, ApiError> {
+    let spec = WorkflowSpec::parse(&body)?;
+    let workflow = api_service
+        .workflow_service
+        .create_workflow(&auth.user.id.to_string(), spec)
+        .await?;
+
+    Ok(Json(workflow))
+}
+
+/// List the workflows of the current user
+#[utoipa::path(
+    get,
+    path = "/workflows",
+    tag = "workflows",
+    responses((status = 200, description = "Workflows", body = Vec<Object>)),
+    security(("bearer" = []))
+)]
+async fn list_workflows(
+    State(api_service): State<Arc<ApiService>>,
+    auth: Auth,
+) -> Result<Json<Vec<WorkflowDefinition>>, ApiError> {
+    let workflows = api_service
+        .workflow_service
+        .list_workflows(&auth.user.id.to_string())
+        .await?;
+
+    Ok(Json(workflows))
+}
+
+/// Get a workflow of the current user
+#[utoipa::path(
+    get,
+    path = "/workflows/{id}",
+    tag = "workflows",
+    params(("id" = String, Path, description = "Workflow ID")),
+    responses((status = 200, description = "Workflow", body = Object)),
+    security(("bearer" = []))
+)]
+async fn get_workflow(
+    State(api_service): State<Arc<ApiService>>,
+    auth: Auth,
+    Path(id): Path<String>,
+) -> Result<Json<WorkflowDefinition>, ApiError> {
+    let workflow = api_service
+        .workflow_service
+        .get_workflow(&auth.user.id.to_string(), &id)
+        .await?;
+
+    Ok(Json(workflow))
+}
+
+/// Replace the specification of a workflow of the current user
+#[utoipa::path(
+    put,
+    path = "/workflows/{id}",
+    tag = "workflows",
+    params(("id" = String, Path, description = "Workflow ID")),
+    request_body(
+        content((String = "application/json"), (String = "application/yaml")),
+        description = "Workflow specification in JSON or YAML"
+    ),
+    responses((status = 200, description = "Updated workflow", body = Object)),
+    security(("bearer" = []))
+)]
+async fn update_workflow(
+    State(api_service): State<Arc<ApiService>>,
+    auth: Auth,
+    Path(id): Path<String>,
+    body: String,
+) -> Result<Json<WorkflowDefinition>, ApiError> {
+    let spec = WorkflowSpec::parse(&body)?;
+    let workflow = api_service
+        .workflow_service
+        .update_workflow(&auth.user.id.to_string(), &id, spec)
+        .await?;
+
+    Ok(Json(workflow))
+}
+
+/// Delete a workflow of the current user
+#[utoipa::path(
+    delete,
+    path = "/workflows/{id}",
+    tag = "workflows",
+    params(("id" = String, Path, description = "Workflow ID")),
+    responses((status = 200, description = "Workflow deleted")),
+    security(("bearer" = []))
+)]
+async fn delete_workflow(
+    State(api_service): State<Arc<ApiService>>,
+    auth: Auth,
+    Path(id): Path<String>,
+) -> Result<Json<()>, ApiError> {
+    api_service
+        .workflow_service
+        .delete_workflow(&auth.user.id.to_string(), &id)
+        .await?;
+
+    Ok(Json(()))
+}
+
+/// Start a run of a workflow of the current user
+#[utoipa::path(
+    post,
+    path = "/workflows/{id}/runs",
+    tag = "workflows",
+    params(("id" = String, Path, description = "Workflow ID")),
+    request_body(content = Option<StartRunRequest>),
+    responses((status = 200, description = "Started run", body = Object)),
+    security(("bearer" = []))
+)]
+async fn start_run(
+    State(api_service): State<Arc<ApiService>>,
+    auth: Auth,
+    Path(id): Path<String>,
+    request: Option<Json<StartRunRequest>>,
+) -> Result<Json<WorkflowRun>, ApiError> {
+    let Json(request) = request.unwrap_or_default();
+    let run = api_service
+        .workflow_service
+        .start_run(&auth.user.id.to_string(), &id, request.input)
+        .await?;
+
+    Ok(Json(run))
+}
+
+/// List the runs of a workflow of the current user
+#[utoipa::path(
+    get,
+    path = "/workflows/{id}/runs",
+    tag = "workflows",
+    params(("id" = String, Path, description = "Workflow ID")),
+    responses((status = 200, description = "Runs", body = Vec<Object>)),
+    security(("bearer" = []))
+)]
+async fn list_runs(
+    State(api_service): State<Arc<ApiService>>,
+    auth: Auth,
+    Path(id): Path<String>,
+) -> Result<Json<Vec<WorkflowRun>>, ApiError> {
+    let runs = api_service
+        .workflow_service
+        .list_runs(&auth.user.id.to_string(), &id)
+        .await?;
+
+    Ok(Json(runs))
+}
+
+/// Get a run of the current user, with the state of every step
+#[utoipa::path(
+    get,
+    path = "/workflow-runs/{run_id}",
+    tag = "workflows",
+    params(("run_id" = String, Path, description = "Run ID")),
+    responses((status = 200, description = "Run with the state of every step", body = Object)),
+    security(("bearer" = []))
+)]
+async fn get_run(
+    State(api_service): State<Arc<ApiService>>,
+    auth: Auth,
+    Path(run_id): Path<String>,
+) -> Result<Json<WorkflowRun>, ApiError> {
+    let run = api_service
+        .workflow_service
+        .get_run(&auth.user.id.to_string(), &run_id)
+        .await?;
+
+    Ok(Json(run))
+}
+
+/// Get the graph of a run of the current user, for visualization
+#[utoipa::path(
+    get,
+    path = "/workflow-runs/{run_id}/graph",
+    tag = "workflows",
+    params(("run_id" = String, Path, description = "Run ID")),
+    responses((status = 200, description = "Run graph", body = Object)),
+    security(("bearer" = []))
+)]
+async fn get_run_graph(
+    State(api_service): State<Arc<ApiService>>,
+    auth: Auth,
+    Path(run_id): Path<String>,
+) -> Result<Json<WorkflowGraph>, ApiError> {
+    let graph = api_service
+        .workflow_service
+        .get_run_graph(&auth.user.id.to_string(), &run_id)
+        .await?;
+
+    Ok(Json(graph))
+}
+
+/// Cancel a run of the current user
+#[utoipa::path(
+    post,
+    path = "/workflow-runs/{run_id}/cancel",
+    tag = "workflows",
+    params(("run_id" = String, Path, description = "Run ID")),
+    responses((status = 200, description = "Cancelled run", body = Object)),
+    security(("bearer" = []))
+)]
+async fn cancel_run(
+    State(api_service): State<Arc<ApiService>>,
+    auth: Auth,
+    Path(run_id): Path<String>,
+) -> Result<Json<WorkflowRun>, ApiError> {
+    let run = api_service
+        .workflow_service
+        .cancel_run(&auth.user.id.to_string(), &run_id)
+        .await?;
+
+    Ok(Json(run))
+}
+
+/// Workflow routes
+pub fn workflow_routes(api_service: Arc<ApiService>) -> Router {
+    Router::new()
+        .route("/workflows", get(list_workflows).post(create_workflow))
+        .route(
+            "/workflows/:id",
+            get(get_workflow)
+                .put(update_workflow)
+                .delete(delete_workflow),
+        )
+        .route("/workflows/:id/runs", get(list_runs).post(start_run))
+        .route("/workflow-runs/:run_id", get(get_run))
+        .route("/workflow-runs/:run_id/graph", get(get_run_graph))
+        .route("/workflow-runs/:run_id/cancel", post(cancel_run))
+        .with_state(api_service)
+}
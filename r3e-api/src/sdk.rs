@@ -0,0 +1,610 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+//! TypeScript client SDK generation.
+//!
+//! A client is generated for a service from the input and output JSON Schemas of its
+//! functions: one type per schema and one typed method per function. The `http` target
+//! invokes the service through the API; the `platform` target is for functions running on
+//! the platform and invokes it through `r3e.services.invoke`.
+
+use std::collections::HashSet;
+use std::fmt::Write;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use utoipa::ToSchema;
+
+use crate::models::function::Function;
+use crate::models::service::Service;
+
+/// Nesting depth of a schema beyond which its type is `unknown`
+const MAX_SCHEMA_DEPTH: usize = 16;
+
+/// Names the generated module declares itself, besides the types and methods of functions
+const RESERVED_NAMES: &[&str] = &[
+    "ClientOptions",
+    "Invocation",
+    "InvokeOptions",
+    "constructor",
+    "invoke",
+    "serviceId",
+];
+
+/// Where the generated client runs
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum SdkTarget {
+    /// Outside the platform, invoking the service through the API
+    #[default]
+    Http,
+
+    /// In a function on the platform, invoking the service through the `r3e.services` op
+    Platform,
+}
+
+/// Generate the TypeScript client of a service
+pub fn generate_sdk(service: &Service, functions: &[Function], target: SdkTarget) -> String {
+    let mut names = Names::default();
+    for name in RESERVED_NAMES {
+        names.unique(name);
+    }
+    let client = names.unique(&format!("{}Client", pascal_case(&service.name, "Service")));
+
+    let mut methods = Vec::with_capacity(functions.len());
+    for function in functions {
+        let pascal = pascal_case(&function.name, "Function");
+        methods.push(Method {
+            function,
+            name: names.unique(&camel_case(&pascal)),
+            input: names.unique(&format!("{}Input", pascal)),
+            output: names.unique(&format!("{}Output", pascal)),
+        });
+    }
+
+    let mut out = String::new();
+    let _ = writeln!(
+        out,
+        "// Generated by r3e-faas for service {} ({}), version {}.",
+        string_literal(&service.name),
+        service.id,
+        service.version
+    );
+    out.push_str("// Do not edit; download it again after changing the service's functions.\n");
+
+    for method in &methods {
+        out.push('\n');
+        out.push_str(&type_declaration(
+            &method.input,
+            method.function.input_schema.as_ref(),
+        ));
+        out.push('\n');
+        out.push_str(&type_declaration(
+            &method.output,
+            method.function.output_schema.as_ref(),
+        ));
+    }
+
+    out.push('\n');
+    match target {
+        SdkTarget::Http => write_http_client(&mut out, service, &client, &methods),
+        SdkTarget::Platform => write_platform_client(&mut out, service, &client, &methods),
+    }
+    out
+}
+
+/// Suggested file name of the generated client
+pub fn sdk_file_name(service: &Service) -> String {
+    let mut name = String::new();
+    for word in words(&service.name) {
+        if !name.is_empty() {
+            name.push('-');
+        }
+        name.push_str(&word.to_ascii_lowercase());
+    }
+    if name.is_empty() {
+        name.push_str("service");
+    }
+    name.push_str(".ts");
+    name
+}
+
+/// Function of the service, with the names generated for it
+struct Method<'a> {
+    function: &'a Function,
+    name: String,
+    input: String,
+    output: String,
+}
+
+/// Identifiers already used in the generated module
+#[derive(Default)]
+struct Names(HashSet<String>);
+
+impl Names {
+    /// `name`, or `name` with the first free numeric suffix
+    fn unique(&mut self, name: &str) -> String {
+        let mut candidate = name.to_string();
+        let mut suffix = 2;
+        while !self.0.insert(candidate.clone()) {
+            candidate = format!("{}{}", name, suffix);
+            suffix += 1;
+        }
+        candidate
+    }
+}
+
+fn write_http_client(out: &mut String, service: &Service, client: &str, methods: &[Method]) {
+    out.push_str(
+        r#"export interface InvokeOptions {
+  /** Maximum worst-case cost accepted, in GAS */
+  maxCost?: number;
+}
+
+export interface Invocation<T> {
+  invocation_id: string;
+  function_id: string;
+  result: T;
+  execution_time_ms: number;
+  status: string;
+  error?: string | null;
+}
+
+export interface ClientOptions {
+  /** Base URL of the API, including its path prefix */
+  baseUrl: string;
+
+  /** Bearer token of the caller */
+  token: string;
+
+  /** fetch implementation, the global one by default */
+  fetch?: typeof fetch;
+}
+
+"#,
+    );
+
+    let _ = writeln!(out, "export class {} {{", client);
+    let _ = writeln!(
+        out,
+        "  static readonly serviceId = {};",
+        string_literal(&service.id.to_string())
+    );
+    let _ = write!(
+        out,
+        r#"
+  constructor(private readonly options: ClientOptions) {{}}
+
+  private async invoke<T>(name: string, input: unknown, options?: InvokeOptions): Promise<Invocation<T>> {{
+    const doFetch = this.options.fetch ?? fetch;
+    const baseUrl = this.options.baseUrl.replace(/\/+$/, "");
+    const url = `${{baseUrl}}/services/${{{client}.serviceId}}/functions/${{encodeURIComponent(name)}}/invoke`;
+    const response = await doFetch(url, {{
+      method: "POST",
+      headers: {{
+        "Content-Type": "application/json",
+        Authorization: `Bearer ${{this.options.token}}`,
+      }},
+      body: JSON.stringify({{ input, max_cost: options?.maxCost }}),
+    }});
+    if (!response.ok) {{
+      throw new Error(`${{name}}: ${{response.status}} ${{await response.text()}}`);
+    }}
+    return await response.json();
+  }}
+"#
+    );
+
+    for method in methods {
+        out.push('\n');
+        write_doc(out, "  ", method.function.description.as_deref());
+        let _ = write!(
+            out,
+            "  {}(input: {}, options?: InvokeOptions): Promise<Invocation<{}>> {{\n    return this.invoke({}, input, options);\n  }}\n",
+            method.name,
+            method.input,
+            method.output,
+            string_literal(&method.function.name)
+        );
+    }
+    out.push_str("}\n");
+}
+
+fn write_platform_client(out: &mut String, service: &Service, client: &str, methods: &[Method]) {
+    out.push_str(
+        r#"declare const r3e: {
+  services: {
+    invoke(serviceId: string, name: string, input: unknown): Promise<unknown>;
+  };
+};
+
+"#,
+    );
+
+    let _ = writeln!(out, "export class {} {{", client);
+    let _ = writeln!(
+        out,
+        "  static readonly serviceId = {};",
+        string_literal(&service.id.to_string())
+    );
+
+    for method in methods {
+        out.push('\n');
+        write_doc(out, "  ", method.function.description.as_deref());
+        let _ = write!(
+            out,
+            "  {}(input: {}): Promise<{}> {{\n    return r3e.services.invoke({}.serviceId, {}, input) as Promise<{}>;\n  }}\n",
+            method.name,
+            method.input,
+            method.output,
+            client,
+            string_literal(&method.function.name),
+            method.output
+        );
+    }
+    out.push_str("}\n");
+}
+
+/// Declaration of a named type, an interface for object schemas with properties
+fn type_declaration(name: &str, schema: Option<&Value>) -> String {
+    let mut out = String::new();
+    let unknown = Value::Bool(true);
+    let schema = schema.unwrap_or(&unknown);
+    write_doc(
+        &mut out,
+        "",
+        schema.get("description").and_then(Value::as_str),
+    );
+
+    let ty = ts_type(schema, 0);
+    if is_object_with_properties(schema) {
+        let _ = writeln!(out, "export interface {} {}", name, ty);
+    } else {
+        let _ = writeln!(out, "export type {} = {};", name, ty);
+    }
+    out
+}
+
+fn is_object_with_properties(schema: &Value) -> bool {
+    schema.get("type").is_none_or(|ty| ty == "object")
+        && schema
+            .get("properties")
+            .and_then(Value::as_object)
+            .is_some_and(|properties| !properties.is_empty())
+        && ["anyOf", "oneOf", "allOf", "enum", "const"]
+            .iter()
+            .all(|key| schema.get(key).is_none())
+}
+
+/// TypeScript type of a JSON Schema
+fn ts_type(schema: &Value, depth: usize) -> String {
+    if depth > MAX_SCHEMA_DEPTH {
+        return "unknown".to_string();
+    }
+    let object = match schema {
+        Value::Bool(true) => return "unknown".to_string(),
+        Value::Bool(false) => return "never".to_string(),
+        Value::Object(object) => object,
+        _ => return "unknown".to_string(),
+    };
+
+    if let Some(value) = object.get("const") {
+        return value.to_string();
+    }
+    if let Some(values) = object.get("enum").and_then(Value::as_array) {
+        return union(values.iter().map(Value::to_string).collect());
+    }
+    for (key, separator) in [("anyOf", " | "), ("oneOf", " | "), ("allOf", " & ")] {
+        if let Some(schemas) = object.get(key).and_then(Value::as_array) {
+            let types: Vec<String> = schemas
+                .iter()
+                .map(|schema| parenthesize(ts_type(schema, depth + 1)))
+                .collect();
+            return if types.is_empty() {
+                "unknown".to_string()
+            } else {
+                types.join(separator)
+            };
+        }
+    }
+
+    match object.get("type") {
+        Some(Value::String(ty)) => typed(ty, schema, depth),
+        Some(Value::Array(types)) => union(
+            types
+                .iter()
+                .filter_map(Value::as_str)
+                .map(|ty| typed(ty, schema, depth))
+                .collect(),
+        ),
+        _ if object.contains_key("properties") => typed("object", schema, depth),
+        _ => "unknown".to_string(),
+    }
+}
+
+/// TypeScript type of a schema of one JSON type
+fn typed(ty: &str, schema: &Value, depth: usize) -> String {
+    match ty {
+        "string" => "string".to_string(),
+        "integer" | "number" => "number".to_string(),
+        "boolean" => "boolean".to_string(),
+        "null" => "null".to_string(),
+        "array" => {
+            let items = schema
+                .get("items")
+                .map_or_else(|| "unknown".to_string(), |items| ts_type(items, depth + 1));
+            format!("Array<{}>", items)
+        }
+        "object" => object_type(schema, depth),
+        _ => "unknown".to_string(),
+    }
+}
+
+fn object_type(schema: &Value, depth: usize) -> String {
+    let properties = schema.get("properties").and_then(Value::as_object);
+    let Some(properties) = properties.filter(|properties| !properties.is_empty()) else {
+        let values = match schema.get("additionalProperties") {
+            Some(additional @ Value::Object(_)) => ts_type(additional, depth + 1),
+            _ => "unknown".to_string(),
+        };
+        return format!("Record<string, {}>", values);
+    };
+
+    let required: HashSet<&str> = schema
+        .get("required")
+        .and_then(Value::as_array)
+        .map(|required| required.iter().filter_map(Value::as_str).collect())
+        .unwrap_or_default();
+
+    let indent = "  ".repeat(depth + 1);
+    let mut out = String::from("{\n");
+    for (name, property) in properties {
+        write_doc(
+            &mut out,
+            &indent,
+            property.get("description").and_then(Value::as_str),
+        );
+        let _ = writeln!(
+            out,
+            "{}{}{}: {};",
+            indent,
+            property_key(name),
+            if required.contains(name.as_str()) {
+                ""
+            } else {
+                "?"
+            },
+            ts_type(property, depth + 1)
+        );
+    }
+    out.push_str(&"  ".repeat(depth));
+    out.push('}');
+    out
+}
+
+fn union(mut types: Vec<String>) -> String {
+    types.dedup();
+    match types.len() {
+        0 => "never".to_string(),
+        _ => types
+            .into_iter()
+            .map(parenthesize)
+            .collect::<Vec<_>>()
+            .join(" | "),
+    }
+}
+
+/// Parenthesize unions and intersections nested in another one
+fn parenthesize(ty: String) -> String {
+    if has_top_level_operator(&ty) {
+        format!("({})", ty)
+    } else {
+        ty
+    }
+}
+
+/// Whether a type is a union or intersection, outside of any brackets or string literal
+fn has_top_level_operator(ty: &str) -> bool {
+    let mut depth = 0usize;
+    let mut in_string = false;
+    let mut escaped = false;
+    for c in ty.chars() {
+        if in_string {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match c {
+            '"' => in_string = true,
+            '{' | '<' | '(' | '[' => depth += 1,
+            '}' | '>' | ')' | ']' => depth = depth.saturating_sub(1),
+            '|' | '&' if depth == 0 => return true,
+            _ => {}
+        }
+    }
+    false
+}
+
+fn write_doc(out: &mut String, indent: &str, doc: Option<&str>) {
+    let Some(doc) = doc.map(str::trim).filter(|doc| !doc.is_empty()) else {
+        return;
+    };
+    // Keep the comment from being closed early
+    let doc = doc.replace("*/", "*\\/");
+    let lines: Vec<&str> = doc.lines().collect();
+    if lines.len() == 1 {
+        let _ = writeln!(out, "{}/** {} */", indent, lines[0]);
+        return;
+    }
+    let _ = writeln!(out, "{}/**", indent);
+    for line in lines {
+        let _ = writeln!(out, "{} * {}", indent, line.trim_end());
+    }
+    let _ = writeln!(out, "{} */", indent);
+}
+
+fn property_key(name: &str) -> String {
+    if is_identifier(name) {
+        name.to_string()
+    } else {
+        string_literal(name)
+    }
+}
+
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_' || c == '$')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '$')
+}
+
+fn string_literal(value: &str) -> String {
+    serde_json::to_string(value).unwrap_or_else(|_| "\"\"".to_string())
+}
+
+/// Alphanumeric words of a name
+fn words(name: &str) -> impl Iterator<Item = &str> {
+    name.split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|word| !word.is_empty())
+}
+
+/// `PascalCase` identifier of a name, `fallback` if it has no words
+fn pascal_case(name: &str, fallback: &str) -> String {
+    let mut out = String::new();
+    for word in words(name) {
+        let mut chars = word.chars();
+        if let Some(first) = chars.next() {
+            out.push(first.to_ascii_uppercase());
+            out.push_str(chars.as_str());
+        }
+    }
+    if out.is_empty() {
+        return fallback.to_string();
+    }
+    if out.starts_with(|c: char| c.is_ascii_digit()) {
+        out.insert_str(0, fallback);
+    }
+    out
+}
+
+fn camel_case(pascal: &str) -> String {
+    let mut chars = pascal.chars();
+    chars.next().map_or_else(String::new, |first| {
+        first.to_ascii_lowercase().to_string() + chars.as_str()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use serde_json::json;
+    use uuid::Uuid;
+
+    use crate::models::function::{FunctionStatus, Runtime, SecurityLevel, TriggerType};
+    use crate::models::service::{ServiceStatus, ServiceType, ServiceVisibility};
+
+    fn service() -> Service {
+        Service {
+            id: Uuid::nil(),
+            user_id: Uuid::nil(),
+            name: "price oracle".to_string(),
+            description: None,
+            service_type: ServiceType::Oracle,
+            config: json!({}),
+            status: ServiceStatus::Active,
+            visibility: ServiceVisibility::Public,
+            version: "1.0.0".to_string(),
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        }
+    }
+
+    fn function(name: &str, input_schema: Option<Value>) -> Function {
+        Function {
+            id: Uuid::nil(),
+            service_id: Uuid::nil(),
+            user_id: Uuid::nil(),
+            name: name.to_string(),
+            description: Some("Get the price of an asset".to_string()),
+            code: String::new(),
+            runtime: Runtime::default(),
+            trigger_type: TriggerType::Http,
+            trigger_config: json!({}),
+            security_level: SecurityLevel::default(),
+            status: FunctionStatus::Active,
+            version: "1".to_string(),
+            hash: String::new(),
+            input_schema,
+            output_schema: Some(json!({ "type": "number" })),
+            code_key_id: None,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_ts_type() {
+        let schema = json!({
+            "type": "object",
+            "required": ["symbol"],
+            "properties": {
+                "extra": { "type": "object", "additionalProperties": { "type": "boolean" } },
+                "max-age": { "type": ["integer", "null"] },
+                "sources": { "type": "array", "items": { "enum": ["binance", "okx"] } },
+                "symbol": { "type": "string", "description": "Asset symbol" }
+            }
+        });
+        assert_eq!(
+            ts_type(&schema, 0),
+            "{\n  extra?: Record<string, boolean>;\n  \"max-age\"?: number | null;\n  sources?: Array<\"binance\" | \"okx\">;\n  /** Asset symbol */\n  symbol: string;\n}"
+        );
+        assert_eq!(
+            ts_type(
+                &json!({ "anyOf": [{ "type": "string" }, { "enum": [1, 2] }] }),
+                0
+            ),
+            "string | (1 | 2)"
+        );
+        assert_eq!(ts_type(&json!({ "$ref": "#/definitions/x" }), 0), "unknown");
+        assert_eq!(ts_type(&json!(false), 0), "never");
+    }
+
+    #[test]
+    fn test_generate_sdk() {
+        let functions = vec![
+            function(
+                "get-price",
+                Some(json!({
+                    "type": "object",
+                    "required": ["symbol"],
+                    "properties": { "symbol": { "type": "string" } }
+                })),
+            ),
+            function("get_price", None),
+        ];
+
+        let sdk = generate_sdk(&service(), &functions, SdkTarget::Http);
+        assert!(sdk.contains("export interface GetPriceInput {\n  symbol: string;\n}\n"));
+        assert!(sdk.contains("export type GetPriceOutput = number;\n"));
+        assert!(sdk.contains("export type GetPriceInput2 = unknown;\n"));
+        assert!(sdk.contains("export class PriceOracleClient {"));
+        assert!(sdk.contains(
+            "  /** Get the price of an asset */\n  getPrice(input: GetPriceInput, options?: InvokeOptions): Promise<Invocation<GetPriceOutput>> {\n    return this.invoke(\"get-price\", input, options);"
+        ));
+        assert!(sdk.contains("  getPrice2(input: GetPriceInput2, options?: InvokeOptions)"));
+
+        let sdk = generate_sdk(&service(), &functions, SdkTarget::Platform);
+        assert!(sdk.contains(
+            "return r3e.services.invoke(PriceOracleClient.serviceId, \"get-price\", input) as Promise<GetPriceOutput>;"
+        ));
+        assert!(!sdk.contains("fetch"));
+
+        assert_eq!(sdk_file_name(&service()), "price-oracle.ts");
+    }
+}
@@ -0,0 +1,503 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+//! Full-text search over functions and services.
+//!
+//! A small in-memory inverted index, rebuilt from the database on startup and kept up to
+//! date by the function and service services on every create, update and delete.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, RwLock};
+
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::models::function::Function;
+use crate::models::service::{Service, ServiceVisibility};
+
+/// Maximum number of code identifiers indexed per function
+const MAX_CODE_TOKENS: usize = 4096;
+
+/// Words too common in JavaScript code to be useful search terms
+const CODE_STOP_WORDS: &[&str] = &[
+    "async",
+    "await",
+    "break",
+    "case",
+    "catch",
+    "class",
+    "const",
+    "continue",
+    "default",
+    "delete",
+    "do",
+    "else",
+    "export",
+    "false",
+    "finally",
+    "for",
+    "function",
+    "if",
+    "import",
+    "in",
+    "instanceof",
+    "let",
+    "new",
+    "null",
+    "of",
+    "return",
+    "switch",
+    "this",
+    "throw",
+    "true",
+    "try",
+    "typeof",
+    "undefined",
+    "var",
+    "void",
+    "while",
+    "yield",
+];
+
+/// Kind of a search document
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum SearchKind {
+    /// Function
+    Function,
+
+    /// Service
+    Service,
+}
+
+impl std::str::FromStr for SearchKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "function" | "functions" => Ok(Self::Function),
+            "service" | "services" => Ok(Self::Service),
+            _ => Err(format!("Invalid search kind: {}", s)),
+        }
+    }
+}
+
+/// Indexed field of a document, in decreasing weight order
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Field {
+    Name,
+    Description,
+    Code,
+}
+
+impl Field {
+    fn weight(self) -> f64 {
+        match self {
+            Field::Name => 3.0,
+            Field::Description => 1.5,
+            Field::Code => 1.0,
+        }
+    }
+}
+
+type DocKey = (SearchKind, Uuid);
+
+/// Stored document
+#[derive(Debug, Clone)]
+struct Document {
+    owner: Uuid,
+    public: bool,
+    name: String,
+    description: Option<String>,
+    terms: HashMap<String, HashMap<Field, u32>>,
+}
+
+/// Search hit
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct SearchHit {
+    /// Document kind
+    pub kind: SearchKind,
+
+    /// Function or service ID
+    pub id: Uuid,
+
+    /// Name
+    pub name: String,
+
+    /// Description
+    pub description: Option<String>,
+
+    /// Relevance score
+    pub score: f64,
+}
+
+#[derive(Default)]
+struct IndexInner {
+    documents: HashMap<DocKey, Document>,
+    postings: HashMap<String, HashSet<DocKey>>,
+}
+
+/// Inverted index over functions and services
+#[derive(Clone, Default)]
+pub struct SearchIndex {
+    inner: Arc<RwLock<IndexInner>>,
+}
+
+impl SearchIndex {
+    /// Create an empty search index
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add or replace a function
+    pub fn index_function(&self, function: &Function) {
+        let mut terms = HashMap::new();
+        add_terms(&mut terms, Field::Name, tokenize(&function.name));
+        if let Some(description) = &function.description {
+            add_terms(&mut terms, Field::Description, tokenize(description));
+        }
+        add_terms(&mut terms, Field::Code, code_identifiers(&function.code));
+
+        self.insert(
+            (SearchKind::Function, function.id),
+            Document {
+                owner: function.user_id,
+                public: false,
+                name: function.name.clone(),
+                description: function.description.clone(),
+                terms,
+            },
+        );
+    }
+
+    /// Add or replace a service
+    pub fn index_service(&self, service: &Service) {
+        let mut terms = HashMap::new();
+        add_terms(&mut terms, Field::Name, tokenize(&service.name));
+        if let Some(description) = &service.description {
+            add_terms(&mut terms, Field::Description, tokenize(description));
+        }
+
+        self.insert(
+            (SearchKind::Service, service.id),
+            Document {
+                owner: service.user_id,
+                public: service.visibility == ServiceVisibility::Public,
+                name: service.name.clone(),
+                description: service.description.clone(),
+                terms,
+            },
+        );
+    }
+
+    /// Remove a document
+    pub fn remove(&self, kind: SearchKind, id: Uuid) {
+        let mut inner = self.inner.write().unwrap();
+        inner.remove(&(kind, id));
+    }
+
+    /// Number of indexed documents
+    pub fn len(&self) -> usize {
+        self.inner.read().unwrap().documents.len()
+    }
+
+    /// Whether the index is empty
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn insert(&self, key: DocKey, document: Document) {
+        let mut inner = self.inner.write().unwrap();
+        inner.remove(&key);
+        for term in document.terms.keys() {
+            inner.postings.entry(term.clone()).or_default().insert(key);
+        }
+        inner.documents.insert(key, document);
+    }
+
+    /// Search documents visible to `user_id`: their own, plus public services.
+    ///
+    /// All query terms must match; the last term also matches as a prefix, so results
+    /// update while the user is typing. Returns the requested page and the total hit count.
+    pub fn search(
+        &self,
+        query: &str,
+        kind: Option<SearchKind>,
+        user_id: Uuid,
+        limit: usize,
+        offset: usize,
+    ) -> (Vec<SearchHit>, usize) {
+        let terms = tokenize(query);
+        if terms.is_empty() {
+            return (Vec::new(), 0);
+        }
+
+        let inner = self.inner.read().unwrap();
+        let total_docs = inner.documents.len().max(1) as f64;
+
+        let mut scores: Option<HashMap<DocKey, f64>> = None;
+        for (i, term) in terms.iter().enumerate() {
+            // Expand the last term to all indexed terms it prefixes
+            let matched: Vec<&String> = if i == terms.len() - 1 {
+                inner
+                    .postings
+                    .keys()
+                    .filter(|t| t.starts_with(term.as_str()))
+                    .collect()
+            } else {
+                inner
+                    .postings
+                    .get_key_value(term)
+                    .map(|(t, _)| t)
+                    .into_iter()
+                    .collect()
+            };
+
+            let mut term_scores: HashMap<DocKey, f64> = HashMap::new();
+            for indexed in matched {
+                let docs = &inner.postings[indexed];
+                let idf = (1.0 + total_docs / docs.len() as f64).ln();
+                // Prefix matches rank below exact matches
+                let boost = if indexed == term { 1.0 } else { 0.5 };
+                for key in docs {
+                    let document = &inner.documents[key];
+                    if kind.is_some_and(|kind| kind != key.0)
+                        || !(document.owner == user_id || document.public)
+                    {
+                        continue;
+                    }
+
+                    let tf: f64 = document.terms[indexed]
+                        .iter()
+                        .map(|(field, count)| field.weight() * (1.0 + (*count as f64).ln()))
+                        .sum();
+                    *term_scores.entry(*key).or_default() += tf * idf * boost;
+                }
+            }
+
+            scores = Some(match scores {
+                None => term_scores,
+                Some(scores) => scores
+                    .into_iter()
+                    .filter_map(|(key, score)| term_scores.get(&key).map(|s| (key, score + s)))
+                    .collect(),
+            });
+        }
+
+        let mut hits: Vec<SearchHit> = scores
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(key, score)| {
+                let document = &inner.documents[&key];
+                SearchHit {
+                    kind: key.0,
+                    id: key.1,
+                    name: document.name.clone(),
+                    description: document.description.clone(),
+                    score,
+                }
+            })
+            .collect();
+
+        // Stable order for equal scores
+        hits.sort_by(|a, b| {
+            b.score
+                .total_cmp(&a.score)
+                .then_with(|| a.name.cmp(&b.name))
+                .then_with(|| a.id.cmp(&b.id))
+        });
+
+        let total = hits.len();
+        let hits = hits.into_iter().skip(offset).take(limit).collect();
+        (hits, total)
+    }
+}
+
+impl IndexInner {
+    fn remove(&mut self, key: &DocKey) {
+        if let Some(document) = self.documents.remove(key) {
+            for term in document.terms.keys() {
+                if let Some(docs) = self.postings.get_mut(term) {
+                    docs.remove(key);
+                    if docs.is_empty() {
+                        self.postings.remove(term);
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn add_terms(
+    terms: &mut HashMap<String, HashMap<Field, u32>>,
+    field: Field,
+    tokens: impl IntoIterator<Item = String>,
+) {
+    for token in tokens {
+        *terms.entry(token).or_default().entry(field).or_default() += 1;
+    }
+}
+
+/// Split text into lowercase terms.
+///
+/// Identifiers are also split at camelCase and snake_case boundaries, so `getPriceFeed`
+/// is found by `getpricefeed`, `price` and `feed`.
+pub fn tokenize(text: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    for word in text
+        .split(|c: char| !(c.is_alphanumeric() || c == '_' || c == '$'))
+        .filter(|w| !w.is_empty())
+    {
+        let parts = split_identifier(word);
+        let whole = word.trim_matches(|c| c == '_' || c == '$').to_lowercase();
+        if parts.len() > 1 && whole.chars().count() > 1 {
+            tokens.push(whole);
+        }
+        tokens.extend(parts.into_iter().filter(|p| p.chars().count() > 1));
+    }
+    tokens
+}
+
+fn split_identifier(word: &str) -> Vec<String> {
+    let mut parts = Vec::new();
+    let mut current = String::new();
+    let mut prev: Option<char> = None;
+    let chars: Vec<char> = word.chars().collect();
+
+    for (i, &c) in chars.iter().enumerate() {
+        if c == '_' || c == '$' {
+            if !current.is_empty() {
+                parts.push(std::mem::take(&mut current).to_lowercase());
+            }
+            prev = None;
+            continue;
+        }
+
+        // Boundaries: fooBar, FOOBar (before the last capital), foo1
+        let next_lower = chars.get(i + 1).is_some_and(|n| n.is_lowercase());
+        let boundary = match prev {
+            Some(p) => {
+                (p.is_lowercase() && c.is_uppercase())
+                    || (p.is_uppercase() && c.is_uppercase() && next_lower)
+                    || (p.is_alphabetic() != c.is_alphabetic())
+            }
+            None => false,
+        };
+        if boundary && !current.is_empty() {
+            parts.push(std::mem::take(&mut current).to_lowercase());
+        }
+
+        current.push(c);
+        prev = Some(c);
+    }
+
+    if !current.is_empty() {
+        parts.push(current.to_lowercase());
+    }
+    parts
+}
+
+/// Extract the identifiers of JavaScript code, skipping keywords, strings and comments
+fn code_identifiers(code: &str) -> Vec<String> {
+    let mut identifiers = Vec::new();
+    let mut chars = code.chars().peekable();
+    let mut current = String::new();
+
+    let flush = |current: &mut String, identifiers: &mut Vec<String>| {
+        if !current.is_empty() && !current.starts_with(|c: char| c.is_ascii_digit()) {
+            identifiers.push(std::mem::take(current));
+        }
+        current.clear();
+    };
+
+    while let Some(c) = chars.next() {
+        if identifiers.len() >= MAX_CODE_TOKENS {
+            break;
+        }
+
+        match c {
+            c if c.is_alphanumeric() || c == '_' || c == '$' => current.push(c),
+            '/' if chars.peek() == Some(&'/') => {
+                flush(&mut current, &mut identifiers);
+                for c in chars.by_ref() {
+                    if c == '\n' {
+                        break;
+                    }
+                }
+            }
+            '/' if chars.peek() == Some(&'*') => {
+                flush(&mut current, &mut identifiers);
+                chars.next();
+                let mut prev = ' ';
+                for c in chars.by_ref() {
+                    if prev == '*' && c == '/' {
+                        break;
+                    }
+                    prev = c;
+                }
+            }
+            '"' | '\'' | '`' => {
+                flush(&mut current, &mut identifiers);
+                let quote = c;
+                let mut escaped = false;
+                for c in chars.by_ref() {
+                    if escaped {
+                        escaped = false;
+                    } else if c == '\\' {
+                        escaped = true;
+                    } else if c == quote {
+                        break;
+                    }
+                }
+            }
+            _ => flush(&mut current, &mut identifiers),
+        }
+    }
+    flush(&mut current, &mut identifiers);
+
+    identifiers
+        .into_iter()
+        .filter(|identifier| !CODE_STOP_WORDS.contains(&identifier.as_str()))
+        .flat_map(|identifier| tokenize(&identifier))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tokenize() {
+        assert_eq!(
+            tokenize("getPriceFeed"),
+            vec!["getpricefeed", "get", "price", "feed"]
+        );
+        assert_eq!(
+            tokenize("NEO_price oracle"),
+            vec!["neo_price", "neo", "price", "oracle"]
+        );
+        assert_eq!(
+            tokenize("parseHTTPResponse"),
+            vec!["parsehttpresponse", "parse", "http", "response"]
+        );
+    }
+
+    #[test]
+    fn test_code_identifiers() {
+        let code = r#"
+            // fetchBalance is not called
+            export default async function handler(event) {
+                const url = "https://example.com/notAnIdentifier";
+                return computeScore(event.amount);
+            }
+        "#;
+        let identifiers = code_identifiers(code);
+        assert!(identifiers.contains(&"handler".to_string()));
+        assert!(identifiers.contains(&"computescore".to_string()));
+        assert!(identifiers.contains(&"amount".to_string()));
+        assert!(!identifiers.contains(&"fetchbalance".to_string()));
+        assert!(!identifiers.contains(&"notanidentifier".to_string()));
+        assert!(!identifiers.contains(&"const".to_string()));
+    }
+}
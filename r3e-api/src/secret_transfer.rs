@@ -0,0 +1,273 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+//! Bulk import and export of a user's function secrets.
+//!
+//! Bundles are sealed client-side with hybrid encryption (see [`r3e_secrets::transfer`]):
+//! imports to the platform transfer key derived from the secrets master key, exports to a
+//! public key supplied by the caller. An import is applied in one transaction, all of its
+//! secrets or none, and every secret it writes gets its own audit entry.
+
+use std::collections::HashSet;
+
+use r3e_secrets::audit::{AuditEvent, AuditEventType, AuditStore};
+use r3e_secrets::transfer::{
+    parse_public_key, BundledSecret, SealedBundle, SecretBundle, TransferKey,
+};
+use r3e_secrets::SecretEncryption;
+use sqlx::{FromRow, PgPool};
+use uuid::Uuid;
+
+use crate::error::ApiError;
+use crate::snapshot::{resolve, ConflictPolicy, ImportCounts};
+
+/// Maximum number of secrets in one bundle
+pub const MAX_BUNDLE_SECRETS: usize = 1000;
+
+/// Secret row, encrypted with the master key
+#[derive(Debug, Clone, FromRow)]
+struct SecretRow {
+    id: String,
+    function_id: String,
+    encrypted_data: Vec<u8>,
+    nonce: Vec<u8>,
+}
+
+fn master_cipher(master_key: &[u8; 32]) -> Result<SecretEncryption, ApiError> {
+    SecretEncryption::new(master_key).map_err(|e| ApiError::Server(e.to_string()))
+}
+
+/// IDs of the functions the user owns
+async fn owned_functions(db: &PgPool, user_id: Uuid) -> Result<HashSet<String>, ApiError> {
+    let ids: Vec<Uuid> = sqlx::query_scalar("SELECT id FROM functions WHERE user_id = $1")
+        .bind(user_id)
+        .fetch_all(db)
+        .await
+        .map_err(|e| ApiError::Database(format!("Failed to list functions: {}", e)))?;
+
+    Ok(ids.into_iter().map(|id| id.to_string()).collect())
+}
+
+/// Export the user's secrets, of the given functions or all, sealed to `recipient_key`
+pub async fn export(
+    db: &PgPool,
+    audit_store: &dyn AuditStore,
+    master_key: &[u8; 32],
+    user_id: Uuid,
+    function_ids: Option<&[Uuid]>,
+    recipient_key: &str,
+) -> Result<SealedBundle, ApiError> {
+    let recipient = parse_public_key(recipient_key)
+        .map_err(|e| ApiError::Validation(format!("Invalid recipient key: {}", e)))?;
+
+    let owned = owned_functions(db, user_id).await?;
+    if let Some(function_ids) = function_ids {
+        if let Some(id) = function_ids
+            .iter()
+            .find(|id| !owned.contains(&id.to_string()))
+        {
+            return Err(ApiError::Authorization(format!(
+                "You are not authorized to export the secrets of function {}",
+                id
+            )));
+        }
+    }
+    let wanted: Option<HashSet<String>> =
+        function_ids.map(|ids| ids.iter().map(|id| id.to_string()).collect());
+
+    let rows = sqlx::query_as::<_, SecretRow>(
+        "SELECT id, function_id, encrypted_data, nonce FROM secrets \
+         WHERE user_id = $1 ORDER BY function_id, id",
+    )
+    .bind(user_id.to_string())
+    .fetch_all(db)
+    .await
+    .map_err(|e| ApiError::Database(format!("Failed to export secrets: {}", e)))?;
+
+    let master = master_cipher(master_key)?;
+    let mut bundle = SecretBundle::default();
+    for row in rows {
+        if !wanted
+            .as_ref()
+            .map_or(true, |wanted| wanted.contains(&row.function_id))
+        {
+            continue;
+        }
+        let value = master
+            .decrypt(&row.encrypted_data, &row.nonce)
+            .map_err(|e| ApiError::Server(format!("Failed to decrypt secret {}: {}", row.id, e)))?;
+        bundle
+            .secrets
+            .push(BundledSecret::new(row.function_id, row.id, &value));
+    }
+    if bundle.secrets.len() > MAX_BUNDLE_SECRETS {
+        return Err(ApiError::Validation(format!(
+            "Export holds {} secrets, at most {} fit in a bundle, export fewer functions at a time",
+            bundle.secrets.len(),
+            MAX_BUNDLE_SECRETS
+        )));
+    }
+
+    // Record every secret leaving the platform before handing the bundle out
+    for secret in &bundle.secrets {
+        audit(
+            audit_store,
+            AuditEventType::SecretExported,
+            user_id,
+            secret,
+            "Exported in a sealed bundle".to_string(),
+        )
+        .await?;
+    }
+
+    log::info!("User {} exported {} secrets", user_id, bundle.secrets.len());
+    bundle
+        .seal(&recipient)
+        .map_err(|e| ApiError::Server(format!("Failed to seal bundle: {}", e)))
+}
+
+/// Import a bundle sealed to the platform transfer key, in one transaction
+pub async fn import(
+    db: &PgPool,
+    audit_store: &dyn AuditStore,
+    master_key: &[u8; 32],
+    user_id: Uuid,
+    sealed: &SealedBundle,
+    policy: ConflictPolicy,
+) -> Result<ImportCounts, ApiError> {
+    let bundle = TransferKey::derive(master_key)
+        .open(sealed)
+        .map_err(|e| ApiError::Validation(e.to_string()))?;
+    if bundle.secrets.len() > MAX_BUNDLE_SECRETS {
+        return Err(ApiError::Validation(format!(
+            "Bundle holds {} secrets, at most {} are imported at a time",
+            bundle.secrets.len(),
+            MAX_BUNDLE_SECRETS
+        )));
+    }
+
+    // Check the whole bundle before writing anything
+    let owned = owned_functions(db, user_id).await?;
+    let mut seen = HashSet::new();
+    let mut values = Vec::with_capacity(bundle.secrets.len());
+    for secret in &bundle.secrets {
+        if secret.id.is_empty() {
+            return Err(ApiError::Validation("Secret ID is required".to_string()));
+        }
+        if !owned.contains(&secret.function_id) {
+            return Err(ApiError::Authorization(format!(
+                "You are not authorized to import secrets into function {}",
+                secret.function_id
+            )));
+        }
+        if !seen.insert((&secret.function_id, &secret.id)) {
+            return Err(ApiError::Validation(format!(
+                "Secret {} of function {} appears twice in the bundle",
+                secret.id, secret.function_id
+            )));
+        }
+        values.push(
+            secret
+                .decode_value()
+                .map_err(|e| ApiError::Validation(e.to_string()))?,
+        );
+    }
+
+    let master = master_cipher(master_key)?;
+    let now = chrono::Utc::now().timestamp();
+    let mut tx = db
+        .begin()
+        .await
+        .map_err(|e| ApiError::Database(format!("Failed to begin import: {}", e)))?;
+    let mut counts = ImportCounts::default();
+
+    for (secret, value) in bundle.secrets.iter().zip(values) {
+        let key = format!("{}:{}", secret.function_id, secret.id);
+        let exists = sqlx::query(
+            "SELECT 1 FROM secrets WHERE user_id = $1 AND function_id = $2 AND id = $3",
+        )
+        .bind(user_id.to_string())
+        .bind(&secret.function_id)
+        .bind(&secret.id)
+        .fetch_optional(&mut *tx)
+        .await
+        .map_err(|e| ApiError::Database(format!("Failed to check secret {}: {}", key, e)))?
+        .is_some();
+        if !resolve(policy, exists, "secret", &key, &mut counts)? {
+            continue;
+        }
+
+        let (encrypted_data, nonce) = master
+            .encrypt(&value)
+            .map_err(|e| ApiError::Server(format!("Failed to encrypt secret {}: {}", key, e)))?;
+        sqlx::query(
+            r#"
+            INSERT INTO secrets (id, user_id, function_id, encrypted_data, nonce, created_at, updated_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $6)
+            ON CONFLICT (user_id, function_id, id) DO UPDATE SET
+                encrypted_data = EXCLUDED.encrypted_data, nonce = EXCLUDED.nonce,
+                updated_at = EXCLUDED.updated_at
+            "#,
+        )
+        .bind(&secret.id)
+        .bind(user_id.to_string())
+        .bind(&secret.function_id)
+        .bind(encrypted_data)
+        .bind(nonce)
+        .bind(now)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| ApiError::Database(format!("Failed to import secret {}: {}", key, e)))?;
+
+        // Recorded before the commit: a secret the audit log misses is never imported
+        let details = if exists {
+            "Overwritten by a bulk import"
+        } else {
+            "Created by a bulk import"
+        };
+        audit(
+            audit_store,
+            AuditEventType::SecretImported,
+            user_id,
+            secret,
+            details.to_string(),
+        )
+        .await?;
+    }
+
+    tx.commit()
+        .await
+        .map_err(|e| ApiError::Database(format!("Failed to commit import: {}", e)))?;
+
+    log::info!(
+        "User {} imported secrets: {} created, {} overwritten, {} skipped",
+        user_id,
+        counts.created,
+        counts.overwritten,
+        counts.skipped
+    );
+    Ok(counts)
+}
+
+async fn audit(
+    audit_store: &dyn AuditStore,
+    event_type: AuditEventType,
+    user_id: Uuid,
+    secret: &BundledSecret,
+    details: String,
+) -> Result<(), ApiError> {
+    let event = AuditEvent::new(
+        event_type,
+        user_id.to_string(),
+        Some(secret.function_id.clone()),
+        Some(secret.id.clone()),
+        details,
+        None,
+        None,
+    );
+    audit_store
+        .append(event)
+        .await
+        .map_err(|e| ApiError::Server(format!("Failed to record audit event: {}", e)))?;
+    Ok(())
+}
@@ -4,20 +4,77 @@
 use std::sync::Arc;
 
 use chrono::{DateTime, Utc};
+use futures::stream::{self, BoxStream, StreamExt};
+use r3e_built_in_services::alert_rules::{
+    spawn_alert_rules, AlertRuleService, RocksDBAlertRuleStorage,
+};
+use r3e_built_in_services::alerts::AlertManager;
+use r3e_built_in_services::balance::{
+    AdmissionConfig, BalanceAdmission, BalanceStorage, RocksDBBalanceStorage,
+};
+use r3e_built_in_services::billing::{
+    spawn_billing_cycle, BillingConfig, BillingService, BillingServiceTrait, RocksDBBillingStorage,
+};
+use r3e_built_in_services::notifications::{
+    NotificationConfig, NotificationService, RocksDBNotificationStorage,
+};
+use r3e_built_in_services::pricing::{
+    MemoryPricingStorage, PlanCatalog, PricingService, PricingServiceTrait, ResourceType,
+};
+use r3e_built_in_services::quota::{
+    QuotaConfig, QuotaEnforcer, QuotaResource, QuotaService, QuotaServiceTrait, RocksDBQuotaStorage,
+};
+use r3e_built_in_services::workflow::{
+    spawn_workflow_engine, RocksDBWorkflowStorage, WorkflowEngine, WorkflowEngineConfig,
+    WorkflowService, WorkflowServiceTrait, WorkflowStorage,
+};
+use r3e_core::schema::{format_violations, SchemaValidationError};
+use r3e_core::{CodedError, ErrorBody, ErrorCode};
+use r3e_deno::ext::stream::StreamChunk;
+use r3e_deno::heap::HeapReport;
+use r3e_deno::sandbox::{FileGrantStore, FilePolicyStore, PermissionGrants, PolicyEngine};
+use r3e_secrets::approval::ApprovalService;
+use r3e_secrets::audit::AuditStore;
+use r3e_secrets::envelope::{Envelope, EnvelopeError, EnvelopeService};
+use r3e_secrets::hashicorp::VaultClient;
+use r3e_secrets::rocksdb::{RocksDBAuditStore, RocksDBOperationStore};
+use r3e_store::{
+    spawn_log_pruning, spawn_usage_rollup, AnalyticsStore, BlobStoreConfig, EventStore,
+    KvLockService, LeaseHolder, LockService, LogLevel, LogQuery, LogRecord, LogRetention, LogStore,
+    PostgresLockService, RedisLockService, RocksDbAnalyticsStore, RocksDbEventStore,
+    RocksDbLogStore, UsageRollup, UsageSample,
+};
+use r3e_tee::attestation::AttestationServiceImpl;
+use r3e_tee::measurements::{AttestationAppraiser, MeasurementPolicy};
 use sqlx::PgPool;
 use uuid::Uuid;
 
+use crate::approvals::{SetPlanExecutor, SET_PLAN_OPERATION};
 use crate::auth::AuthService;
 use crate::config::Config;
+use crate::domains::DomainStore;
+use crate::encryption;
+use crate::environments::{EnvironmentStore, FunctionVersion};
 use crate::error::ApiError;
+use crate::estimate::CostEstimator;
+use crate::heap_reports::HeapReportStore;
+use crate::idempotency::IdempotencyStore;
+use crate::models::environment::Environment;
 use crate::models::function::{
-    Function, FunctionInvocationResponse, FunctionLogsResponse, FunctionStatus, Runtime,
-    SecurityLevel, TriggerType,
+    Function, FunctionInvocationResponse, FunctionLogEntry, FunctionLogsResponse, FunctionStatus,
+    Runtime, SecurityLevel, TriggerType,
 };
 use crate::models::service::{
     Service, ServiceStatus, ServiceSummary, ServiceType, ServiceVisibility,
 };
 use crate::models::user::UserRole;
+use crate::oidc::OidcProvider;
+use crate::replays::ReplayStore;
+use crate::search::{SearchIndex, SearchKind};
+use crate::signing::ResponseSigner;
+use crate::snapshot;
+use crate::uploads::{UploadConfig, UploadStore};
+use crate::workflow::ApiFunctionInvoker;
 
 /// API service
 pub struct ApiService {
@@ -35,6 +92,87 @@ pub struct ApiService {
 
     /// Service service
     pub service_service: ServiceService,
+
+    /// Full-text search index over functions and services
+    pub search_index: SearchIndex,
+
+    /// Invocation cost estimator
+    pub cost_estimator: CostEstimator,
+
+    /// Hash-chained audit log
+    pub audit_store: Arc<dyn AuditStore>,
+
+    /// Idempotency keys of invocations
+    pub idempotency_store: IdempotencyStore,
+
+    /// Per-tenant resource quotas
+    pub quota_service: Arc<dyn QuotaServiceTrait>,
+
+    /// Priced plans and the usage billed on them
+    pub pricing_service: Arc<dyn PricingServiceTrait>,
+
+    /// Monthly invoices and their on-chain payments
+    pub billing_service: Arc<dyn BillingServiceTrait>,
+
+    /// GAS balances of the users, credited by their deposits
+    pub balance_storage: Arc<dyn BalanceStorage>,
+
+    /// Check of the users' GAS balances invocations are admitted against
+    pub balance_admission: Arc<BalanceAdmission>,
+
+    /// Alert notification channels of users and operators
+    pub notification_service: Arc<NotificationService>,
+
+    /// Alert rules of functions, evaluated against the usage analytics
+    pub alert_rule_service: Arc<AlertRuleService>,
+
+    /// Workflows of steps and their runs
+    pub workflow_service: Arc<dyn WorkflowServiceTrait>,
+
+    /// Net, fs and env permissions granted to functions
+    pub permission_grants: PermissionGrants,
+
+    /// Admin policies decided before invocations and, by the workers, op dispatch
+    pub policy_engine: PolicyEngine,
+
+    /// Hourly and daily usage aggregates of functions
+    pub analytics_store: Arc<dyn AnalyticsStore>,
+
+    /// Invocation logs of functions
+    pub log_store: Arc<dyn LogStore>,
+
+    /// Index of the chain events delivered to triggers
+    pub event_store: Arc<dyn EventStore>,
+
+    /// Replays of indexed events into functions
+    pub replay_store: ReplayStore,
+
+    /// Heap reports of invocations that ran out of memory
+    pub heap_report_store: HeapReportStore,
+
+    /// Chunked uploads of function bundles
+    pub upload_store: UploadStore,
+
+    /// Environments functions are promoted through, and the versions released to them
+    pub environment_store: EnvironmentStore,
+
+    /// Custom domains mapped to HTTP-triggered functions
+    pub domain_store: DomainStore,
+
+    /// Appraisal of the attestation reports of enclaves against the published measurements
+    pub attestation_appraiser: Arc<AttestationAppraiser>,
+
+    /// Admin operations approved offline
+    pub approval_service: Arc<ApprovalService>,
+
+    /// Encryption at rest with per-tenant keys, if a secrets master key is configured
+    pub envelope_service: Option<Arc<EnvelopeService>>,
+
+    /// OpenID Connect login, if a provider is configured
+    pub oidc_provider: Option<Arc<OidcProvider>>,
+
+    /// Keys signing responses consumers feed into contracts
+    pub response_signer: Arc<ResponseSigner>,
 }
 
 impl ApiService {
@@ -48,11 +186,296 @@ impl ApiService {
         // Create the auth service
         let auth_service = AuthService::new(db.clone(), config.jwt_secret.clone());
 
-        // Create the function service
-        let function_service = FunctionService::new(db.clone());
+        // Build the search index from the current functions and services
+        let search_index = Self::build_search_index(&db).await?;
+
+        // Open the quotas, counting the functions and schedules that already exist
+        let plans = Self::load_plan_catalog(&config)?;
+        let quota_service = Arc::new(QuotaService::new(
+            Arc::new(
+                RocksDBQuotaStorage::new(&config.quota_db_path)
+                    .map_err(|e| ApiError::Server(format!("Failed to open quotas: {}", e)))?,
+            ),
+            Self::load_quota_config(&config, &plans)?,
+        ));
+        Self::reconcile_quota(&db, quota_service.as_ref()).await?;
 
         // Create the service service
-        let service_service = ServiceService::new(db.clone());
+        let service_service = ServiceService::new(db.clone(), search_index.clone());
+
+        // Create the cost estimator, pricing invocations on the users' plans
+        let pricing_service: Arc<dyn PricingServiceTrait> = Arc::new(
+            PricingService::new(Arc::new(MemoryPricingStorage::with_defaults())).with_plans(plans),
+        );
+        let cost_estimator = CostEstimator::new(
+            db.clone(),
+            pricing_service.clone(),
+            config.neo_rpc_url.clone(),
+        );
+
+        // Roll the invocations up into hourly and daily aggregates, flushed every minute
+        let analytics_store: Arc<dyn AnalyticsStore> = Arc::new(
+            RocksDbAnalyticsStore::new(&config.analytics_db_path)
+                .map_err(|e| ApiError::Server(format!("Failed to open analytics: {}", e)))?,
+        );
+        let usage_rollup = Arc::new(UsageRollup::new());
+        spawn_usage_rollup(
+            usage_rollup.clone(),
+            analytics_store.clone(),
+            std::time::Duration::from_secs(60),
+        );
+
+        // Keep the invocation logs, pruning them hourly
+        let log_store: Arc<dyn LogStore> = Arc::new(
+            RocksDbLogStore::new(&config.log_db_path)
+                .map_err(|e| ApiError::Server(format!("Failed to open logs: {}", e)))?,
+        );
+        spawn_log_pruning(
+            log_store.clone(),
+            Self::default_log_retention(&config),
+            std::time::Duration::from_secs(3600),
+        );
+
+        // Replay the indexed chain events into functions at a bounded rate
+        let event_store: Arc<dyn EventStore> = Arc::new(
+            RocksDbEventStore::new(&config.event_db_path)
+                .map_err(|e| ApiError::Server(format!("Failed to open event index: {}", e)))?,
+        );
+        let replay_store = ReplayStore::new(
+            event_store.clone(),
+            config.replay_rate_per_sec,
+            config.max_replay_events,
+        );
+
+        // Keep the heap reports of invocations out of memory, purging expired ones hourly
+        let heap_report_store = HeapReportStore::new(
+            db.clone(),
+            std::time::Duration::from_secs(config.heap_report_ttl),
+            config.max_heap_snapshot_size,
+        );
+        heap_report_store.spawn_purge(std::time::Duration::from_secs(3600));
+
+        // Keep the parts of chunked uploads in the blob store, collecting abandoned uploads hourly
+        let upload_store = UploadStore::new(
+            db.clone(),
+            Self::load_blob_store_config(&config)?.open()?,
+            UploadConfig {
+                part_size: config.upload_part_size.max(1),
+                max_size: config.max_upload_size,
+                ttl: std::time::Duration::from_secs(config.upload_ttl),
+                max_open_uploads: config.max_open_uploads,
+            },
+        );
+        upload_store.spawn_purge(std::time::Duration::from_secs(3600));
+
+        let environment_store = EnvironmentStore::new(db.clone());
+
+        // Seal the code of tenants enrolled for encryption at rest, with KEKs derived from the
+        // secrets master key or held by the configured Vault or AWS KMS
+        let envelope_service = match &config.secrets_master_key {
+            Some(master_key) => {
+                let vault = match &config.vault {
+                    Some(vault) => {
+                        let vault =
+                            Arc::new(VaultClient::new(vault.clone()).map_err(EnvelopeError::from)?);
+                        vault.clone().spawn_token_renewal();
+                        Some(vault)
+                    }
+                    None => None,
+                };
+                Some(Arc::new(encryption::with_key_stores(
+                    encryption::envelope_service(
+                        db.clone(),
+                        snapshot::parse_key(master_key)?,
+                        None,
+                    ),
+                    vault,
+                    config.aws_kms.as_ref(),
+                )?))
+            }
+            None => {
+                log::warn!("SECRETS_MASTER_KEY is not set, encryption at rest is unavailable");
+                None
+            }
+        };
+
+        // Create the function service
+        let mut function_service = FunctionService::new(db.clone(), search_index.clone())
+            .with_quota(quota_service.clone())
+            .with_usage_rollup(usage_rollup, pricing_service.clone())
+            .with_log_store(log_store.clone())
+            .with_heap_reports(heap_report_store.clone());
+        if let Some(envelope_service) = &envelope_service {
+            function_service = function_service.with_envelope(envelope_service.clone());
+        }
+
+        // Invoice the recorded usage monthly, checking for overdue invoices hourly, on the node
+        // holding the billing lock
+        let locks = Self::build_lock_service(&config, &db).await?;
+        let billing_service: Arc<dyn BillingServiceTrait> = Arc::new(BillingService::new(
+            Arc::new(
+                RocksDBBillingStorage::new(&config.billing_db_path)
+                    .map_err(|e| ApiError::Server(format!("Failed to open billing: {}", e)))?,
+            ),
+            pricing_service.clone(),
+            Self::load_billing_config(&config)?,
+        ));
+        spawn_billing_cycle(
+            billing_service.clone(),
+            std::time::Duration::from_secs(3600),
+            Some(Arc::new(LeaseHolder::new(
+                locks.clone(),
+                "billing-cycle",
+                std::time::Duration::from_secs(2 * 3600),
+            ))),
+        );
+
+        // Notify the users' channels of alerts
+        let notification_service = Arc::new(NotificationService::with_transports(
+            Arc::new(
+                RocksDBNotificationStorage::new(&config.notification_db_path).map_err(|e| {
+                    ApiError::Server(format!("Failed to open notifications: {}", e))
+                })?,
+            ),
+            &Self::load_notification_config(&config)?,
+        )?);
+
+        // Admit invocations against the users' GAS balances, alerting their channels when low
+        let balance_storage: Arc<dyn BalanceStorage> = Arc::new(
+            RocksDBBalanceStorage::new(&config.balance_db_path)
+                .await
+                .map_err(|e| ApiError::Server(format!("Failed to open balances: {}", e)))?,
+        );
+        let balance_admission = Arc::new(
+            BalanceAdmission::new(
+                balance_storage.clone(),
+                AdmissionConfig {
+                    soft_limit: config.balance_soft_limit,
+                    hard_limit: config.balance_hard_limit,
+                },
+            )
+            .with_alerts(Arc::new(
+                AlertManager::default().with_sink(notification_service.clone()),
+            )),
+        );
+
+        // Evaluate the users' alert rules every minute, alerting their channels
+        let alert_rule_service = Arc::new(AlertRuleService::new(
+            Arc::new(
+                RocksDBAlertRuleStorage::new(&config.alert_rule_db_path)
+                    .map_err(|e| ApiError::Server(format!("Failed to open alert rules: {}", e)))?,
+            ),
+            analytics_store.clone(),
+            Arc::new(AlertManager::default().with_sink(notification_service.clone())),
+        ));
+        spawn_alert_rules(
+            alert_rule_service.clone(),
+            std::time::Duration::from_secs(60),
+            Some(Arc::new(LeaseHolder::new(
+                locks.clone(),
+                "alert-rules",
+                std::time::Duration::from_secs(2 * 60),
+            ))),
+        );
+
+        // Drive the workflow runs, resuming the ones that were running before a restart
+        let workflow_storage: Arc<dyn WorkflowStorage> = Arc::new(
+            RocksDBWorkflowStorage::new(&config.workflow_db_path)
+                .map_err(|e| ApiError::Server(format!("Failed to open workflows: {}", e)))?,
+        );
+        let workflow_engine = Arc::new(WorkflowEngine::new(
+            workflow_storage.clone(),
+            Arc::new(ApiFunctionInvoker::new(
+                function_service.clone(),
+                billing_service.clone(),
+            )),
+            WorkflowEngineConfig::default(),
+        ));
+        spawn_workflow_engine(
+            workflow_engine.clone(),
+            Arc::new(std::sync::atomic::AtomicBool::new(false)),
+        );
+        let workflow_service: Arc<dyn WorkflowServiceTrait> =
+            Arc::new(WorkflowService::new(workflow_storage, workflow_engine));
+
+        // Open the audit log
+        let audit_store = Arc::new(
+            RocksDBAuditStore::new(&config.audit_log_path)
+                .await
+                .map_err(|e| ApiError::Server(format!("Failed to open audit log: {}", e)))?,
+        );
+
+        // Run high-value admin operations once an approver signed them offline
+        let approval_service = Arc::new(
+            ApprovalService::new(
+                Arc::new(
+                    RocksDBOperationStore::new(&config.operation_db_path)
+                        .await
+                        .map_err(|e| {
+                            ApiError::Server(format!("Failed to open admin operations: {}", e))
+                        })?,
+                ),
+                audit_store.clone(),
+                &config.admin_approver_keys,
+            )
+            .map_err(|e| ApiError::Server(e.to_string()))?
+            .with_ttl(config.operation_ttl)
+            .with_executor(
+                SET_PLAN_OPERATION,
+                Arc::new(SetPlanExecutor::new(
+                    quota_service.clone(),
+                    pricing_service.clone(),
+                )),
+            ),
+        );
+
+        // Keep the first response of idempotent requests, purging expired ones hourly
+        let idempotency_store = IdempotencyStore::new(
+            db.clone(),
+            std::time::Duration::from_secs(config.idempotency_ttl),
+        );
+        idempotency_store.spawn_purge(std::time::Duration::from_secs(3600));
+
+        // Purge expired login sessions hourly
+        auth_service
+            .sessions()
+            .spawn_purge(std::time::Duration::from_secs(3600));
+
+        // Open the permission grants the workers enforce
+        let permission_grants = PermissionGrants::new(Arc::new(
+            FileGrantStore::new(&config.permission_grants_path).map_err(|e| {
+                ApiError::Server(format!("Failed to open permission grants: {}", e))
+            })?,
+        ));
+
+        // Open the admin policies the workers also enforce
+        let policy_engine = PolicyEngine::new(Arc::new(
+            FilePolicyStore::new(&config.policy_path)
+                .map_err(|e| ApiError::Server(format!("Failed to open policies: {}", e)))?,
+        ));
+
+        // Route custom domains to their functions
+        let domain_store = DomainStore::new(db.clone(), config.domain_dns_resolver_url.clone());
+
+        // Appraise attestation reports against the measurements of the platform's enclaves
+        let attestation_appraiser = Arc::new(AttestationAppraiser::new(
+            Arc::new(AttestationServiceImpl::new()),
+            Self::load_measurement_policy(&config)?,
+        ));
+
+        // Sign responses with the published platform keys, generated on first start
+        let response_signer = Arc::new(ResponseSigner::open(
+            &config.response_signing_key_path,
+            config.response_signing_paths.clone(),
+            chrono::Duration::seconds(config.response_signing_grace_period as i64),
+        )?);
+
+        // Log users of the identity provider in, purging abandoned logins hourly
+        let oidc_provider = config.oidc.clone().map(|oidc| {
+            let provider = Arc::new(OidcProvider::new(db.clone(), oidc));
+            Arc::clone(&provider).spawn_purge(std::time::Duration::from_secs(3600));
+            provider
+        });
 
         Ok(Self {
             config,
@@ -60,20 +483,479 @@ impl ApiService {
             auth_service,
             function_service,
             service_service,
+            search_index,
+            cost_estimator,
+            audit_store,
+            idempotency_store,
+            quota_service,
+            pricing_service,
+            billing_service,
+            balance_storage,
+            balance_admission,
+            notification_service,
+            alert_rule_service,
+            workflow_service,
+            permission_grants,
+            policy_engine,
+            analytics_store,
+            log_store,
+            event_store,
+            replay_store,
+            heap_report_store,
+            upload_store,
+            environment_store,
+            domain_store,
+            attestation_appraiser,
+            approval_service,
+            envelope_service,
+            oidc_provider,
+            response_signer,
         })
     }
+
+    /// Retention of the logs of functions without a policy of their own
+    pub fn default_log_retention(config: &Config) -> LogRetention {
+        LogRetention {
+            max_age_secs: config.log_max_age_secs,
+            max_bytes: config.log_max_bytes,
+        }
+    }
+
+    /// Load the priced plans
+    fn load_plan_catalog(config: &Config) -> Result<PlanCatalog, ApiError> {
+        let Some(path) = &config.plan_config_path else {
+            return Ok(PlanCatalog::default());
+        };
+
+        let data = std::fs::read(path)
+            .map_err(|e| ApiError::Server(format!("Failed to read plan config: {}", e)))?;
+        serde_json::from_slice(&data)
+            .map_err(|e| ApiError::Server(format!("Invalid plan config {}: {}", path, e)))
+    }
+
+    /// Load the quota limits per plan tier, the resource ceilings of the plans without a
+    /// quota config
+    fn load_quota_config(config: &Config, plans: &PlanCatalog) -> Result<QuotaConfig, ApiError> {
+        let Some(path) = &config.quota_config_path else {
+            return Ok(plans.quota_config());
+        };
+
+        let data = std::fs::read(path)
+            .map_err(|e| ApiError::Server(format!("Failed to read quota config: {}", e)))?;
+        serde_json::from_slice(&data)
+            .map_err(|e| ApiError::Server(format!("Invalid quota config {}: {}", path, e)))
+    }
+
+    /// Load the deposit addresses and payment terms of invoices
+    fn load_billing_config(config: &Config) -> Result<BillingConfig, ApiError> {
+        let Some(path) = &config.billing_config_path else {
+            return Ok(BillingConfig::default());
+        };
+
+        let data = std::fs::read(path)
+            .map_err(|e| ApiError::Server(format!("Failed to read billing config: {}", e)))?;
+        serde_json::from_slice(&data)
+            .map_err(|e| ApiError::Server(format!("Invalid billing config {}: {}", path, e)))
+    }
+
+    /// Connect the lock backend keeping the schedulers on a single node
+    async fn build_lock_service(
+        config: &Config,
+        db: &PgPool,
+    ) -> Result<Arc<dyn LockService>, ApiError> {
+        let locks: Arc<dyn LockService> = match config.lock_backend.as_str() {
+            "postgres" => Arc::new(PostgresLockService::new(db.clone()).await.map_err(|e| {
+                ApiError::Server(format!("Failed to create the lock table: {}", e))
+            })?),
+            "redis" => {
+                let url = config.redis_url.as_deref().ok_or_else(|| {
+                    ApiError::Server("REDIS_URL is required by the redis lock backend".to_string())
+                })?;
+                Arc::new(
+                    RedisLockService::connect(url).await.map_err(|e| {
+                        ApiError::Server(format!("Failed to connect to Redis: {}", e))
+                    })?,
+                )
+            }
+            "rocksdb" => Arc::new(
+                KvLockService::open(&config.lock_db_path)
+                    .map_err(|e| ApiError::Server(format!("Failed to open locks: {}", e)))?,
+            ),
+            other => return Err(ApiError::Server(format!("Unknown lock backend: {}", other))),
+        };
+        Ok(locks)
+    }
+
+    /// Load the notification transports and throttling
+    fn load_notification_config(config: &Config) -> Result<NotificationConfig, ApiError> {
+        let Some(path) = &config.notification_config_path else {
+            return Ok(NotificationConfig::default());
+        };
+
+        let data = std::fs::read(path)
+            .map_err(|e| ApiError::Server(format!("Failed to read notification config: {}", e)))?;
+        serde_json::from_slice(&data)
+            .map_err(|e| ApiError::Server(format!("Invalid notification config {}: {}", path, e)))
+    }
+
+    /// Load the blob store keeping uploaded bundles
+    fn load_blob_store_config(config: &Config) -> Result<BlobStoreConfig, ApiError> {
+        let Some(path) = &config.blob_store_config_path else {
+            return Ok(BlobStoreConfig::default());
+        };
+
+        let data = std::fs::read(path)
+            .map_err(|e| ApiError::Server(format!("Failed to read blob store config: {}", e)))?;
+        serde_json::from_slice(&data)
+            .map_err(|e| ApiError::Server(format!("Invalid blob store config {}: {}", path, e)))
+    }
+
+    /// Load the measurements the platform's enclaves are expected to report
+    fn load_measurement_policy(config: &Config) -> Result<MeasurementPolicy, ApiError> {
+        let Some(path) = &config.tee_measurements_path else {
+            return Ok(MeasurementPolicy::default());
+        };
+
+        let data = std::fs::read(path)
+            .map_err(|e| ApiError::Server(format!("Failed to read TEE measurements: {}", e)))?;
+        serde_json::from_slice(&data)
+            .map_err(|e| ApiError::Server(format!("Invalid TEE measurements {}: {}", path, e)))
+    }
+
+    /// Set the function and schedule usage of every user to what the database holds
+    async fn reconcile_quota(db: &PgPool, quota: &dyn QuotaServiceTrait) -> Result<(), ApiError> {
+        let counts = sqlx::query_as::<_, (Uuid, i64, i64)>(
+            r#"
+            SELECT user_id, COUNT(*), COUNT(*) FILTER (WHERE trigger_type = 'schedule')
+            FROM functions
+            GROUP BY user_id
+            "#,
+        )
+        .fetch_all(db)
+        .await
+        .map_err(|e| ApiError::Database(format!("Failed to count functions: {}", e)))?;
+
+        for (user_id, functions, schedules) in counts {
+            let tenant = user_id.to_string();
+            quota.set_usage(&tenant, QuotaResource::Functions, functions as u64)?;
+            quota.set_usage(&tenant, QuotaResource::Schedules, schedules as u64)?;
+        }
+        Ok(())
+    }
+
+    /// Build the search index from the database
+    async fn build_search_index(db: &PgPool) -> Result<SearchIndex, ApiError> {
+        let search_index = SearchIndex::new();
+
+        let functions = sqlx::query_as::<_, Function>("SELECT * FROM functions")
+            .fetch_all(db)
+            .await
+            .map_err(|e| ApiError::Database(format!("Failed to load functions: {}", e)))?;
+        for function in &functions {
+            search_index.index_function(function);
+        }
+
+        let services = sqlx::query_as::<_, Service>("SELECT * FROM services")
+            .fetch_all(db)
+            .await
+            .map_err(|e| ApiError::Database(format!("Failed to load services: {}", e)))?;
+        for service in &services {
+            search_index.index_service(service);
+        }
+
+        log::info!(
+            "Indexed {} functions and {} services for search",
+            functions.len(),
+            services.len()
+        );
+
+        Ok(search_index)
+    }
 }
 
 /// Function service
+#[derive(Clone)]
 pub struct FunctionService {
     /// Database pool
     db: PgPool,
+
+    /// Search index
+    search_index: SearchIndex,
+
+    /// Function and schedule quotas
+    quota: Option<Arc<dyn QuotaEnforcer>>,
+
+    /// Usage analytics of invocations, and the pricing their GAS cost is worked out with
+    usage: Option<(Arc<UsageRollup>, Arc<dyn PricingServiceTrait>)>,
+
+    /// Invocation logs
+    log_store: Option<Arc<dyn LogStore>>,
+
+    /// Heap reports of invocations that ran out of memory
+    heap_reports: Option<HeapReportStore>,
+
+    /// Encryption at rest of the code of enrolled tenants
+    envelope: Option<Arc<EnvelopeService>>,
 }
 
 impl FunctionService {
     /// Create a new function service
-    pub fn new(db: PgPool) -> Self {
-        Self { db }
+    pub fn new(db: PgPool, search_index: SearchIndex) -> Self {
+        Self {
+            db,
+            search_index,
+            quota: None,
+            usage: None,
+            log_store: None,
+            heap_reports: None,
+            envelope: None,
+        }
+    }
+
+    /// Limit the number of functions and schedules each user may create
+    pub fn with_quota(mut self, quota: Arc<dyn QuotaEnforcer>) -> Self {
+        self.quota = Some(quota);
+        self
+    }
+
+    /// Record the invocations of functions for usage analytics
+    pub fn with_usage_rollup(
+        mut self,
+        rollup: Arc<UsageRollup>,
+        pricing_service: Arc<dyn PricingServiceTrait>,
+    ) -> Self {
+        self.usage = Some((rollup, pricing_service));
+        self
+    }
+
+    /// Keep the logs of invocations
+    pub fn with_log_store(mut self, log_store: Arc<dyn LogStore>) -> Self {
+        self.log_store = Some(log_store);
+        self
+    }
+
+    /// Keep the heap reports of invocations that ran out of memory
+    pub fn with_heap_reports(mut self, heap_reports: HeapReportStore) -> Self {
+        self.heap_reports = Some(heap_reports);
+        self
+    }
+
+    /// Seal the code of tenants enrolled for encryption at rest with their tenant key
+    pub fn with_envelope(mut self, envelope: Arc<EnvelopeService>) -> Self {
+        self.envelope = Some(envelope);
+        self
+    }
+
+    /// Seal code with its owner's tenant key if enrolled, returning it with the KEK identifier
+    async fn seal_code(
+        &self,
+        user_id: Uuid,
+        code: &str,
+    ) -> Result<(String, Option<String>), ApiError> {
+        match &self.envelope {
+            Some(envelope) => Ok(envelope.seal_text(&user_id.to_string(), code).await?),
+            None => Ok((code.to_string(), None)),
+        }
+    }
+
+    /// Decrypt the code of a function sealed with its owner's tenant key
+    async fn open_code(&self, mut function: Function) -> Result<Function, ApiError> {
+        if !Envelope::is_encoded(&function.code) {
+            return Ok(function);
+        }
+        let envelope = self.envelope.as_ref().ok_or_else(|| {
+            ApiError::Server(format!(
+                "Code of function {} is sealed but encryption at rest is not configured",
+                function.id
+            ))
+        })?;
+        function.code = envelope.open_text(&function.code).await?;
+        Ok(function)
+    }
+
+    /// Fail invocations the worker reports out of memory, keeping their heap report
+    async fn check_out_of_memory(
+        &self,
+        invocation_id: Uuid,
+        function: &Function,
+        worker_result: serde_json::Value,
+    ) -> Result<serde_json::Value, ApiError> {
+        let Some(report) = worker_result.get("heap_report") else {
+            return Ok(worker_result);
+        };
+        let report: HeapReport = serde_json::from_value(report.clone()).map_err(|e| {
+            ApiError::ExternalService(format!("Invalid heap report from worker service: {}", e))
+        })?;
+        let error = format!(
+            "Function ran out of memory: heap limit of {} bytes reached",
+            report.usage.heap_size_limit
+        );
+
+        let Some(heap_reports) = &self.heap_reports else {
+            return Err(ErrorBody::new(ErrorCode::OutOfMemory, error).into());
+        };
+        if let Err(e) = heap_reports
+            .store(invocation_id, function.id, function.user_id, report)
+            .await
+        {
+            log::warn!("Failed to keep heap report of {}: {}", invocation_id, e);
+            return Err(ErrorBody::new(ErrorCode::OutOfMemory, error).into());
+        }
+        let heap_report = format!(
+            "/functions/{}/invocations/{}/heap-report",
+            function.id, invocation_id
+        );
+        Err(ErrorBody::new(
+            ErrorCode::OutOfMemory,
+            format!("{}, heap report at {}", error, heap_report),
+        )
+        .with_details(serde_json::json!({ "heap_report": heap_report }))
+        .into())
+    }
+
+    /// Keep the logs the worker returned for an invocation, and its error if it failed
+    async fn store_invocation_logs(
+        &self,
+        invocation_id: Uuid,
+        function_id: Uuid,
+        result: &serde_json::Value,
+        error: Option<&str>,
+    ) {
+        let Some(log_store) = &self.log_store else {
+            return;
+        };
+
+        let now_ms = Utc::now().timestamp_millis() as u64;
+        let record = |level, message: String, timestamp_ms| LogRecord {
+            function_id: function_id.to_string(),
+            invocation_id: Some(invocation_id.to_string()),
+            level,
+            message,
+            timestamp_ms,
+        };
+
+        // Entries are plain lines or objects with a level, message and timestamp
+        let mut records: Vec<LogRecord> = result
+            .get("logs")
+            .and_then(|logs| logs.as_array())
+            .into_iter()
+            .flatten()
+            .filter_map(|entry| match entry {
+                serde_json::Value::String(message) => {
+                    Some(record(LogLevel::Info, message.clone(), now_ms))
+                }
+                serde_json::Value::Object(entry) => Some(record(
+                    entry
+                        .get("level")
+                        .and_then(|level| level.as_str())
+                        .and_then(|level| level.parse().ok())
+                        .unwrap_or(LogLevel::Info),
+                    entry.get("message")?.as_str()?.to_string(),
+                    entry
+                        .get("timestamp")
+                        .and_then(|timestamp| timestamp.as_u64())
+                        .unwrap_or(now_ms),
+                )),
+                _ => None,
+            })
+            .collect();
+        if let Some(error) = error {
+            records.push(record(
+                LogLevel::Error,
+                format!("Invocation failed: {}", error),
+                now_ms,
+            ));
+        }
+        if records.is_empty() {
+            return;
+        }
+
+        if let Err(e) = log_store.append(records).await {
+            log::error!(
+                "Failed to store logs of invocation {}: {}",
+                invocation_id,
+                e
+            );
+        }
+    }
+
+    /// Record an invocation for usage analytics, and bill it on the user's plan unless it
+    /// replays an event the function was already billed for
+    async fn record_usage(
+        &self,
+        function_id: Uuid,
+        user_id: Uuid,
+        error: bool,
+        execution_time_ms: u64,
+        replay: bool,
+    ) {
+        let Some((rollup, pricing_service)) = &self.usage else {
+            return;
+        };
+
+        if replay {
+            rollup.record(&UsageSample {
+                function_id: function_id.to_string(),
+                timestamp: Utc::now().timestamp() as u64,
+                latency_ms: execution_time_ms,
+                error,
+                gas_cost: 0.0,
+            });
+            return;
+        }
+
+        let gas_cost = match pricing_service
+            .calculate_resource_usage_cost(
+                &user_id.to_string(),
+                ResourceType::ExecutionTime,
+                execution_time_ms,
+            )
+            .await
+        {
+            Ok(gas_cost) => gas_cost,
+            Err(e) => {
+                log::warn!("Failed to price invocation of {}: {}", function_id, e);
+                0.0
+            }
+        };
+        if let Err(e) = pricing_service
+            .record_invocation(&user_id.to_string(), execution_time_ms)
+            .await
+        {
+            log::error!("Failed to bill invocation of {}: {}", function_id, e);
+        }
+
+        rollup.record(&UsageSample {
+            function_id: function_id.to_string(),
+            timestamp: Utc::now().timestamp() as u64,
+            latency_ms: execution_time_ms,
+            error,
+            gas_cost,
+        });
+    }
+
+    /// Reserve quota for a user's functions or schedules
+    fn reserve_quota(
+        &self,
+        user_id: Uuid,
+        resource: QuotaResource,
+        amount: u64,
+    ) -> Result<(), ApiError> {
+        match &self.quota {
+            Some(quota) if amount > 0 => {
+                Ok(quota.reserve(&user_id.to_string(), resource, amount)?)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Release quota of a user's deleted functions or schedules
+    fn release_quota(&self, user_id: Uuid, resource: QuotaResource, amount: u64) {
+        if let Some(quota) = &self.quota {
+            if amount > 0 {
+                quota.release(&user_id.to_string(), resource, amount);
+            }
+        }
     }
 
     /// List functions
@@ -138,7 +1020,12 @@ impl FunctionService {
             .await
             .map_err(|e| ApiError::Database(format!("Failed to list functions: {}", e)))?;
 
-        Ok((functions, total_count.0 as u32))
+        let mut opened = Vec::with_capacity(functions.len());
+        for function in functions {
+            opened.push(self.open_code(function).await?);
+        }
+
+        Ok((opened, total_count.0 as u32))
     }
 
     /// Get a function by ID
@@ -150,7 +1037,31 @@ impl FunctionService {
             .map_err(|e| ApiError::Database(format!("Failed to get function: {}", e)))?
             .ok_or_else(|| ApiError::NotFound(format!("Function not found: {}", id)))?;
 
-        Ok(function)
+        self.open_code(function).await
+    }
+
+    /// Get a function of a service by name
+    pub async fn get_function_by_name(
+        &self,
+        service_id: Uuid,
+        name: &str,
+    ) -> Result<Function, ApiError> {
+        let function = sqlx::query_as::<_, Function>(
+            "SELECT * FROM functions WHERE service_id = $1 AND name = $2 ORDER BY created_at LIMIT 1",
+        )
+        .bind(service_id)
+        .bind(name)
+        .fetch_optional(&self.db)
+        .await
+        .map_err(|e| ApiError::Database(format!("Failed to get function: {}", e)))?
+        .ok_or_else(|| {
+            ApiError::NotFound(format!(
+                "Function not found: {} in service {}",
+                name, service_id
+            ))
+        })?;
+
+        self.open_code(function).await
     }
 
     /// Create a function
@@ -166,7 +1077,22 @@ impl FunctionService {
         trigger_type: TriggerType,
         trigger_config: &serde_json::Value,
         security_level: SecurityLevel,
+        input_schema: Option<&serde_json::Value>,
+        output_schema: Option<&serde_json::Value>,
     ) -> Result<Function, ApiError> {
+        check_function_schemas(input_schema, output_schema)?;
+
+        // Seal the code if the user is enrolled for encryption at rest
+        let (stored_code, code_key_id) = self.seal_code(user_id, code).await?;
+
+        // Reserve quota, given back if the function cannot be stored
+        let schedules = u64::from(trigger_type == TriggerType::Schedule);
+        self.reserve_quota(user_id, QuotaResource::Functions, 1)?;
+        if let Err(e) = self.reserve_quota(user_id, QuotaResource::Schedules, schedules) {
+            self.release_quota(user_id, QuotaResource::Functions, 1);
+            return Err(e);
+        }
+
         // Generate a function ID
         let id = Uuid::new_v4();
 
@@ -181,10 +1107,11 @@ impl FunctionService {
             r#"
             INSERT INTO functions (
                 id, service_id, user_id, name, description, code, runtime, trigger_type,
-                trigger_config, security_level, status, version, hash, created_at, updated_at
+                trigger_config, security_level, status, version, hash, input_schema,
+                output_schema, code_key_id, created_at, updated_at
             )
             VALUES (
-                $1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15
+                $1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17, $18
             )
             RETURNING *
             "#,
@@ -194,7 +1121,7 @@ impl FunctionService {
         .bind(user_id)
         .bind(name)
         .bind(description)
-        .bind(code)
+        .bind(&stored_code)
         .bind(format!("{:?}", runtime).to_lowercase())
         .bind(format!("{:?}", trigger_type).to_lowercase())
         .bind(trigger_config)
@@ -202,11 +1129,20 @@ impl FunctionService {
         .bind(format!("{:?}", FunctionStatus::Creating).to_lowercase())
         .bind(version)
         .bind(hash)
+        .bind(input_schema)
+        .bind(output_schema)
+        .bind(code_key_id)
         .bind(Utc::now())
         .bind(Utc::now())
         .fetch_one(&self.db)
         .await
-        .map_err(|e| ApiError::Database(format!("Failed to create function: {}", e)))?;
+        .map_err(|e| {
+            self.release_quota(user_id, QuotaResource::Functions, 1);
+            self.release_quota(user_id, QuotaResource::Schedules, schedules);
+            ApiError::Database(format!("Failed to create function: {}", e))
+        })?;
+        let function = self.open_code(function).await?;
+        self.search_index.index_function(&function);
 
         // Deploy the function
         // Deploy the function using the worker service
@@ -221,7 +1157,8 @@ impl FunctionService {
             "function_id": id,
             "code": function.code,
             "runtime": function.runtime,
-            "environment": function.environment
+            "environment": function.environment,
+            "output_schema": function.output_schema
         });
 
         // Send deployment request to worker service
@@ -259,6 +1196,9 @@ impl FunctionService {
                 None,
                 None,
                 Some(FunctionStatus::Active),
+                None,
+                None,
+                None,
             )
             .await?;
 
@@ -278,10 +1218,22 @@ impl FunctionService {
         trigger_config: Option<&serde_json::Value>,
         security_level: Option<SecurityLevel>,
         status: Option<FunctionStatus>,
+        input_schema: Option<&serde_json::Value>,
+        output_schema: Option<&serde_json::Value>,
+        if_updated_at: Option<DateTime<Utc>>,
     ) -> Result<Function, ApiError> {
+        check_function_schemas(input_schema, output_schema)?;
+
         // Get the function
         let function = self.get_function(id).await?;
 
+        // Moving to a schedule trigger takes a schedule
+        let was_schedule = function.trigger_type == TriggerType::Schedule;
+        let is_schedule = trigger_type.map_or(was_schedule, |t| t == TriggerType::Schedule);
+        if is_schedule && !was_schedule {
+            self.reserve_quota(function.user_id, QuotaResource::Schedules, 1)?;
+        }
+
         // Build the query
         let mut sql = "UPDATE functions SET updated_at = $1".to_string();
         let mut params = vec![Utc::now().to_string()];
@@ -300,9 +1252,15 @@ impl FunctionService {
         }
 
         if let Some(code) = code {
-            sql.push_str(&format!(", code = ${}", param_index));
-            params.push(code.to_string());
-            param_index += 1;
+            let (stored_code, code_key_id) = self.seal_code(function.user_id, code).await?;
+            sql.push_str(&format!(
+                ", code = ${}, code_key_id = NULLIF(${}, '')",
+                param_index,
+                param_index + 1
+            ));
+            params.push(stored_code);
+            params.push(code_key_id.unwrap_or_default());
+            param_index += 2;
 
             // Generate a new function hash
             let hash = format!("{:x}", md5::compute(code));
@@ -341,16 +1299,52 @@ impl FunctionService {
             param_index += 1;
         }
 
-        // Add the WHERE clause
-        sql.push_str(&format!(" WHERE id = ${} RETURNING *", param_index));
+        if let Some(input_schema) = input_schema {
+            sql.push_str(&format!(", input_schema = ${}::jsonb", param_index));
+            params.push(input_schema.to_string());
+            param_index += 1;
+        }
+
+        if let Some(output_schema) = output_schema {
+            sql.push_str(&format!(", output_schema = ${}::jsonb", param_index));
+            params.push(output_schema.to_string());
+            param_index += 1;
+        }
+
+        // Add the WHERE clause, only updating the revision the caller read if it asked to
+        sql.push_str(&format!(" WHERE id = ${}", param_index));
         params.push(id.to_string());
+        param_index += 1;
+        if let Some(updated_at) = if_updated_at {
+            sql.push_str(&format!(" AND updated_at = ${}::timestamptz", param_index));
+            params.push(updated_at.to_rfc3339());
+        }
+        sql.push_str(" RETURNING *");
 
         // Execute the query
         let function = sqlx::query_as::<_, Function>(&sql)
             .bind_all_params(&params)
             .fetch_one(&self.db)
             .await
-            .map_err(|e| ApiError::Database(format!("Failed to update function: {}", e)))?;
+            .map_err(|e| {
+                if is_schedule && !was_schedule {
+                    self.release_quota(function.user_id, QuotaResource::Schedules, 1);
+                }
+                match e {
+                    sqlx::Error::RowNotFound if if_updated_at.is_some() => {
+                        ApiError::PreconditionFailed(format!(
+                            "Function {} was modified concurrently",
+                            id
+                        ))
+                    }
+                    e => ApiError::Database(format!("Failed to update function: {}", e)),
+                }
+            })?;
+        if was_schedule && !is_schedule {
+            self.release_quota(function.user_id, QuotaResource::Schedules, 1);
+        }
+        let function = self.open_code(function).await?;
+        self.search_index.index_function(&function);
 
         // TODO: Redeploy the function if necessary
 
@@ -368,6 +1362,23 @@ impl FunctionService {
             .execute(&self.db)
             .await
             .map_err(|e| ApiError::Database(format!("Failed to delete function: {}", e)))?;
+        self.search_index.remove(SearchKind::Function, id);
+        self.release_quota(function.user_id, QuotaResource::Functions, 1);
+        if function.trigger_type == TriggerType::Schedule {
+            self.release_quota(function.user_id, QuotaResource::Schedules, 1);
+        }
+
+        // Drop the logs of the function, a retention of zero bytes keeps none
+        if let Some(log_store) = &self.log_store {
+            let keep_none = LogRetention {
+                max_age_secs: None,
+                max_bytes: Some(0),
+            };
+            let now_ms = Utc::now().timestamp_millis() as u64;
+            if let Err(e) = log_store.prune(&id.to_string(), &keep_none, now_ms).await {
+                log::warn!("Failed to delete the logs of function {}: {}", id, e);
+            }
+        }
 
         // Undeploy the function
         // Undeploy the function using the worker service
@@ -418,6 +1429,66 @@ impl FunctionService {
         // Get the function
         let function = self.get_function(id).await?;
 
+        self.invoke(function, input, None, false).await
+    }
+
+    /// Invoke the version of a function released to an environment, with the configuration
+    /// and secrets of the environment
+    pub async fn invoke_function_in(
+        &self,
+        id: Uuid,
+        input: &serde_json::Value,
+        environment: &Environment,
+        release: &FunctionVersion,
+    ) -> Result<FunctionInvocationResponse, ApiError> {
+        let function = self.function_at(id, release).await?;
+        let context = environment_context(environment, release);
+        self.invoke(function, input, Some(context), false).await
+    }
+
+    /// Invoke a function with a replayed event, without billing it. Runs a released version
+    /// instead of the current code if given, with the configuration and secrets of an
+    /// environment if given.
+    pub async fn replay_function(
+        &self,
+        id: Uuid,
+        input: &serde_json::Value,
+        environment: Option<&Environment>,
+        release: Option<&FunctionVersion>,
+    ) -> Result<FunctionInvocationResponse, ApiError> {
+        let function = match release {
+            Some(release) => self.function_at(id, release).await?,
+            None => self.get_function(id).await?,
+        };
+        let context = match (environment, release) {
+            (Some(environment), Some(release)) => Some(environment_context(environment, release)),
+            _ => None,
+        };
+        self.invoke(function, input, context, true).await
+    }
+
+    /// Function with the code and schemas of a released version instead of the current ones
+    async fn function_at(&self, id: Uuid, release: &FunctionVersion) -> Result<Function, ApiError> {
+        let mut function = self.get_function(id).await?;
+        function.code = release.code.clone();
+        function.code_key_id = release.code_key_id.clone();
+        function.hash = release.hash.clone();
+        function.version = release.version.to_string();
+        function.input_schema = release.input_schema.clone();
+        function.output_schema = release.output_schema.clone();
+        self.open_code(function).await
+    }
+
+    /// Invoke a function on a worker, recording the result
+    async fn invoke(
+        &self,
+        function: Function,
+        input: &serde_json::Value,
+        environment: Option<serde_json::Value>,
+        replay: bool,
+    ) -> Result<FunctionInvocationResponse, ApiError> {
+        let id = function.id;
+
         // Check if the function is active
         if function.status != FunctionStatus::Active {
             return Err(ApiError::Validation("Function is not active".to_string()));
@@ -427,6 +1498,7 @@ impl FunctionService {
         if let Err(e) = crate::utils::validation::validate_function_input(input) {
             return Err(ApiError::Validation(e));
         }
+        self.validate_input(&function, input)?;
 
         // Invoke the function
         // Connect to the worker service to execute the function
@@ -448,7 +1520,7 @@ impl FunctionService {
         let worker_url = self.get_worker_service_url();
 
         // Create the request body
-        let request_body = serde_json::json!({
+        let mut request_body = serde_json::json!({
             "invocation_id": invocation_id,
             "function_id": id,
             "user_id": function.user_id,
@@ -456,10 +1528,33 @@ impl FunctionService {
             "security_level": function.security_level,
             "runtime": function.runtime,
             "timeout": self.config.function_timeout_ms,
+            "output_schema": function.output_schema,
         });
 
+        // Versions released to an environment are not the deployed code, so the code is sent
+        if let Some(environment) = environment {
+            request_body["environment"] = environment;
+            request_body["code"] = serde_json::Value::String(function.code.clone());
+        }
+
+        // Replays may run any released version, so the code is sent as well
+        if replay {
+            request_body["replay"] = serde_json::Value::Bool(true);
+            request_body["code"] = serde_json::Value::String(function.code.clone());
+        }
+
         // Execute the function
-        let result = match self.send_worker_request(&worker_url, &request_body).await {
+        let response = match self.send_worker_request(&worker_url, &request_body).await {
+            Ok(worker_result) => match check_worker_error(worker_result) {
+                Ok(worker_result) => {
+                    self.check_out_of_memory(invocation_id, &function, worker_result)
+                        .await
+                }
+                Err(e) => Err(e),
+            },
+            Err(e) => Err(e),
+        };
+        let result = match response {
             Ok(worker_result) => {
                 // Calculate execution time
                 let execution_time_ms = start_time.elapsed().as_millis() as u64;
@@ -481,6 +1576,7 @@ impl FunctionService {
                     &worker_result,
                     None,
                     execution_time_ms,
+                    replay,
                 )
                 .await?;
 
@@ -492,6 +1588,7 @@ impl FunctionService {
                     execution_time_ms,
                     status: "success".to_string(),
                     error: None,
+                    error_code: None,
                 })
             }
             Err(e) => {
@@ -516,6 +1613,7 @@ impl FunctionService {
                     &serde_json::json!(null),
                     Some(&e.to_string()),
                     execution_time_ms,
+                    replay,
                 )
                 .await?;
 
@@ -527,6 +1625,7 @@ impl FunctionService {
                     execution_time_ms,
                     status: "error".to_string(),
                     error: Some(e.to_string()),
+                    error_code: Some(e.code()),
                 })
             }
         };
@@ -534,6 +1633,74 @@ impl FunctionService {
         result
     }
 
+    /// Invoke a function, streaming the chunks it writes as they arrive.
+    ///
+    /// The stream always ends with an `End` or `Error` chunk, failures after the worker
+    /// accepted the invocation are reported in-band.
+    pub async fn invoke_function_stream(
+        &self,
+        id: Uuid,
+        input: &serde_json::Value,
+    ) -> Result<BoxStream<'static, StreamChunk>, ApiError> {
+        // Get the function
+        let function = self.get_function(id).await?;
+
+        // Check if the function is active
+        if function.status != FunctionStatus::Active {
+            return Err(ApiError::Validation("Function is not active".to_string()));
+        }
+
+        // Validate the input
+        if let Err(e) = crate::utils::validation::validate_function_input(input) {
+            return Err(ApiError::Validation(e));
+        }
+        self.validate_input(&function, input)?;
+
+        let invocation_id = Uuid::new_v4();
+        log::info!(
+            "Invoking function {} (ID: {}) with streamed output, invocation {}",
+            function.name,
+            function.id,
+            invocation_id
+        );
+
+        let request_body = serde_json::json!({
+            "invocation_id": invocation_id,
+            "function_id": id,
+            "user_id": function.user_id,
+            "input": input,
+            "security_level": function.security_level,
+            "runtime": function.runtime,
+            "timeout": self.config.function_timeout_ms,
+            "output_schema": function.output_schema,
+        });
+
+        // No overall timeout, a streamed invocation may run for as long as the function does
+        let response = reqwest::Client::new()
+            .post(format!("{}/stream", self.get_worker_service_url()))
+            .json(&request_body)
+            .send()
+            .await
+            .map_err(|e| {
+                ApiError::External(format!("Failed to send request to worker service: {}", e))
+            })?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let error_text = response
+                .text()
+                .await
+                .unwrap_or_else(|_| "Failed to get error text".to_string());
+
+            return Err(ApiError::External(format!(
+                "Worker service returned error status {}: {}",
+                status, error_text
+            )));
+        }
+
+        Ok(stream_chunks(response.bytes_stream()))
+    }
+
     /// Store function invocation result
     async fn store_invocation_result(
         &self,
@@ -544,7 +1711,19 @@ impl FunctionService {
         result: &serde_json::Value,
         error: Option<&str>,
         execution_time_ms: u64,
+        replay: bool,
     ) -> Result<(), ApiError> {
+        self.record_usage(
+            function_id,
+            user_id,
+            status != "success",
+            execution_time_ms,
+            replay,
+        )
+        .await;
+        self.store_invocation_logs(invocation_id, function_id, result, error)
+            .await;
+
         // Store the invocation result in the database
         let now = std::time::SystemTime::now()
             .duration_since(std::time::UNIX_EPOCH)
@@ -596,11 +1775,7 @@ impl FunctionService {
         let client = reqwest::Client::new();
 
         // Validate input
-        if !self.validate_input(function, input) {
-            return Err(ApiError::Validation(
-                "Invalid input for function".to_string(),
-            ));
-        }
+        self.validate_input(function, input)?;
 
         // Get the worker service URL
         let worker_url = self.get_worker_service_url();
@@ -673,115 +1848,56 @@ impl FunctionService {
         Ok(result)
     }
 
-    /// Validate function input
-    fn validate_input(&self, function: &Function, input: &serde_json::Value) -> bool {
-        // Validate the input against the function's schema
-        if let Some(schema) = &function.input_schema {
-            // Parse the schema
-            match serde_json::from_str::<serde_json::Value>(schema) {
-                Ok(schema_value) => {
-                    // Validate the input against the schema
-                    match jsonschema::JSONSchema::compile(&schema_value) {
-                        Ok(validator) => match validator.validate(input) {
-                            Ok(_) => true,
-                            Err(errors) => {
-                                for error in errors {
-                                    log::warn!("Input validation error: {}", error);
-                                }
-                                false
-                            }
-                        },
-                        Err(e) => {
-                            log::error!("Failed to compile schema: {}", e);
-                            false
-                        }
-                    }
-                }
-                Err(e) => {
-                    log::error!("Failed to parse schema: {}", e);
-                    false
-                }
+    /// Validate function input against the function's input schema, if it declares one
+    fn validate_input(
+        &self,
+        function: &Function,
+        input: &serde_json::Value,
+    ) -> Result<(), ApiError> {
+        let Some(schema) = &function.input_schema else {
+            return Ok(());
+        };
+
+        match r3e_core::schema::validate(schema, input) {
+            Ok(()) => Ok(()),
+            Err(SchemaValidationError::Violations(violations)) => {
+                Err(ApiError::Validation(format!(
+                    "Input does not match the function's input schema: {}",
+                    format_violations(&violations)
+                )))
             }
-        } else {
-            // If no schema is defined, just check that the input is a valid JSON object
-            input.is_object()
+            // Schemas are checked when stored, a broken one is on our side
+            Err(e) => Err(ApiError::Server(format!(
+                "Invalid input schema of function {}: {}",
+                function.id, e
+            ))),
         }
     }
 
-    /// Get function logs
+    /// Search the logs of a function, newest first
     pub async fn get_function_logs(
         &self,
         id: Uuid,
-        start_time: Option<DateTime<Utc>>,
-        end_time: Option<DateTime<Utc>>,
-        limit: u32,
-        offset: u32,
+        query: &LogQuery,
     ) -> Result<FunctionLogsResponse, ApiError> {
-        // Get the function
-        let function = self.get_function(id).await?;
-
-        // Fetch logs from the logging service
-        log::info!(
-            "Fetching logs for function {} ({})",
-            function.name,
-            function.id
-        );
-
-        // Call the logging service to fetch logs
-        let default_url = "http://localhost:8081/api/v1/logs".to_string();
-        let logging_url = match &self.config.logging_service_url {
-            Some(url) => url,
-            None => {
-                log::warn!("Logging service URL not configured, using default");
-                &default_url
-            }
+        let Some(log_store) = &self.log_store else {
+            return Ok(FunctionLogsResponse {
+                logs: Vec::new(),
+                total_count: 0,
+                has_more: false,
+            });
         };
 
-        let client = reqwest::Client::new();
-
-        // Create logs request
-        let logs_request = serde_json::json!({
-            "function_id": id,
-            "limit": 100
-        });
-
-        // Send logs request to logging service
-        match client.post(&logging_url).json(&logs_request).send().await {
-            Ok(response) => {
-                if response.status().is_success() {
-                    match response.json::<Vec<serde_json::Value>>().await {
-                        Ok(logs) => {
-                            return Ok(logs);
-                        }
-                        Err(e) => {
-                            log::error!("Failed to parse logs response: {}", e);
-                        }
-                    }
-                } else {
-                    log::error!("Failed to fetch logs: {}", response.status());
-                }
-            }
-            Err(e) => {
-                log::error!("Failed to fetch logs: {}", e);
-            }
-        }
-        let logs = vec![
-            serde_json::json!({
-                "timestamp": Utc::now().to_rfc3339(),
-                "level": "info",
-                "message": format!("Function {} invoked", function.name),
-            }),
-            serde_json::json!({
-                "timestamp": Utc::now().to_rfc3339(),
-                "level": "info",
-                "message": "Function execution completed",
-            }),
-        ];
+        let page = log_store.search(&id.to_string(), query).await?;
 
         Ok(FunctionLogsResponse {
-            logs,
-            total_count: 2,
-            has_more: false,
+            logs: page
+                .records
+                .into_iter()
+                .map(FunctionLogEntry::from)
+                .collect(),
+            total_count: page.total as u32,
+            has_more: page.has_more,
         })
     }
 }
@@ -790,12 +1906,15 @@ impl FunctionService {
 pub struct ServiceService {
     /// Database pool
     db: PgPool,
+
+    /// Search index
+    search_index: SearchIndex,
 }
 
 impl ServiceService {
     /// Create a new service service
-    pub fn new(db: PgPool) -> Self {
-        Self { db }
+    pub fn new(db: PgPool, search_index: SearchIndex) -> Self {
+        Self { db, search_index }
     }
 
     /// List services
@@ -924,6 +2043,7 @@ impl ServiceService {
         .fetch_one(&self.db)
         .await
         .map_err(|e| ApiError::Database(format!("Failed to create service: {}", e)))?;
+        self.search_index.index_service(&service);
 
         Ok(service)
     }
@@ -937,6 +2057,7 @@ impl ServiceService {
         config: Option<&serde_json::Value>,
         status: Option<ServiceStatus>,
         visibility: Option<ServiceVisibility>,
+        if_updated_at: Option<DateTime<Utc>>,
     ) -> Result<Service, ApiError> {
         // Get the service
         let service = self.get_service(id).await?;
@@ -976,16 +2097,31 @@ impl ServiceService {
             param_index += 1;
         }
 
-        // Add the WHERE clause
-        sql.push_str(&format!(" WHERE id = ${} RETURNING *", param_index));
+        // Add the WHERE clause, only updating the revision the caller read if it asked to
+        sql.push_str(&format!(" WHERE id = ${}", param_index));
         params.push(id.to_string());
+        param_index += 1;
+        if let Some(updated_at) = if_updated_at {
+            sql.push_str(&format!(" AND updated_at = ${}::timestamptz", param_index));
+            params.push(updated_at.to_rfc3339());
+        }
+        sql.push_str(" RETURNING *");
 
         // Execute the query
         let service = sqlx::query_as::<_, Service>(&sql)
             .bind_all_params(&params)
             .fetch_one(&self.db)
             .await
-            .map_err(|e| ApiError::Database(format!("Failed to update service: {}", e)))?;
+            .map_err(|e| match e {
+                sqlx::Error::RowNotFound if if_updated_at.is_some() => {
+                    ApiError::PreconditionFailed(format!(
+                        "Service {} was modified concurrently",
+                        id
+                    ))
+                }
+                e => ApiError::Database(format!("Failed to update service: {}", e)),
+            })?;
+        self.search_index.index_service(&service);
 
         Ok(service)
     }
@@ -998,6 +2134,7 @@ impl ServiceService {
             .execute(&self.db)
             .await
             .map_err(|e| ApiError::Database(format!("Failed to delete service: {}", e)))?;
+        self.search_index.remove(SearchKind::Service, id);
 
         Ok(())
     }
@@ -1066,3 +2203,88 @@ impl ServiceService {
         Ok((services, total_count.0 as u32))
     }
 }
+
+/// Environment an invocation runs in, as sent to the worker
+fn environment_context(environment: &Environment, release: &FunctionVersion) -> serde_json::Value {
+    serde_json::json!({
+        "name": environment.name,
+        "version": release.version,
+        "config": environment.config,
+        "secrets": environment.secrets,
+    })
+}
+
+/// Fail invocations the worker reported with an error code, keeping the code
+fn check_worker_error(worker_result: serde_json::Value) -> Result<serde_json::Value, ApiError> {
+    let Some(code) = worker_result
+        .get("error_code")
+        .and_then(|code| serde_json::from_value::<ErrorCode>(code.clone()).ok())
+    else {
+        return Ok(worker_result);
+    };
+    let message = worker_result
+        .get("error")
+        .and_then(|error| error.as_str())
+        .unwrap_or("Function invocation failed");
+    Err(ErrorBody::new(code, message).into())
+}
+
+/// Reject function input/output schemas that do not compile
+fn check_function_schemas(
+    input_schema: Option<&serde_json::Value>,
+    output_schema: Option<&serde_json::Value>,
+) -> Result<(), ApiError> {
+    for (kind, schema) in [("input", input_schema), ("output", output_schema)] {
+        if let Some(schema) = schema {
+            r3e_core::schema::check_schema(schema)
+                .map_err(|e| ApiError::Validation(format!("Invalid {} schema: {}", kind, e)))?;
+        }
+    }
+    Ok(())
+}
+
+/// Parse the newline-delimited JSON chunks of a streamed worker response
+fn stream_chunks<S, B, E>(body: S) -> BoxStream<'static, StreamChunk>
+where
+    S: futures::Stream<Item = Result<B, E>> + Send + 'static,
+    B: AsRef<[u8]> + 'static,
+    E: std::fmt::Display + 'static,
+{
+    let state = (body.boxed(), Vec::new(), false);
+    stream::unfold(state, |(mut body, mut buffer, done)| async move {
+        if done {
+            return None;
+        }
+
+        loop {
+            if let Some(pos) = buffer.iter().position(|b| *b == b'\n') {
+                let line: Vec<u8> = buffer.drain(..=pos).collect();
+                let line = &line[..line.len() - 1];
+                if line.iter().all(u8::is_ascii_whitespace) {
+                    continue;
+                }
+
+                let chunk = serde_json::from_slice(line).unwrap_or_else(|e| StreamChunk::Error {
+                    message: format!("Failed to parse worker stream chunk: {}", e),
+                });
+                let done = !matches!(chunk, StreamChunk::Data { .. });
+                return Some((chunk, (body, buffer, done)));
+            }
+
+            let chunk = match body.next().await {
+                Some(Ok(bytes)) => {
+                    buffer.extend_from_slice(bytes.as_ref());
+                    continue;
+                }
+                Some(Err(e)) => StreamChunk::Error {
+                    message: format!("Worker stream failed: {}", e),
+                },
+                None => StreamChunk::Error {
+                    message: "Worker stream ended unexpectedly".to_string(),
+                },
+            };
+            return Some((chunk, (body, buffer, true)));
+        }
+    })
+    .boxed()
+}
@@ -0,0 +1,282 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+//! Login sessions.
+//!
+//! Every token is issued for a session and carries its ID as `jti`. Revoking the session
+//! denies the token before it expires, on logout, when the user or an admin revokes it, and
+//! when the password of the user changes.
+
+use std::time::Duration;
+
+use axum::http::{header, HeaderMap};
+use chrono::Utc;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::error::ApiError;
+use crate::models::session::Session;
+
+/// Maximum length of the user agent kept for a session
+const MAX_USER_AGENT_LENGTH: usize = 255;
+
+/// How often the last request of a session is recorded (in seconds)
+const LAST_SEEN_RESOLUTION: i64 = 60;
+
+/// Why a session was revoked
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RevocationReason {
+    /// The user logged out
+    Logout,
+
+    /// The user revoked the session
+    Revoked,
+
+    /// An admin logged the user out
+    AdminForced,
+
+    /// The password of the user changed
+    PasswordChanged,
+}
+
+impl RevocationReason {
+    /// Name of the reason
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RevocationReason::Logout => "logout",
+            RevocationReason::Revoked => "revoked",
+            RevocationReason::AdminForced => "admin_forced",
+            RevocationReason::PasswordChanged => "password_changed",
+        }
+    }
+}
+
+/// Device a session was created from
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DeviceInfo {
+    /// User agent
+    pub user_agent: Option<String>,
+
+    /// Client IP address, the first `X-Forwarded-For` hop if behind a proxy
+    pub ip_address: Option<String>,
+}
+
+impl DeviceInfo {
+    /// Device of a request
+    pub fn from_headers(headers: &HeaderMap) -> Self {
+        let value = |name| {
+            headers
+                .get(name)
+                .and_then(|v| v.to_str().ok())
+                .map(str::trim)
+                .filter(|v| !v.is_empty())
+        };
+
+        let user_agent = value(header::USER_AGENT.as_str()).map(|agent| {
+            agent
+                .chars()
+                .take(MAX_USER_AGENT_LENGTH)
+                .collect::<String>()
+        });
+        let ip_address = value("X-Forwarded-For")
+            .and_then(|forwarded| forwarded.split(',').next())
+            .map(str::trim)
+            .or_else(|| value("X-Real-IP"))
+            .and_then(|ip| ip.parse::<std::net::IpAddr>().ok())
+            .map(|ip| ip.to_string());
+
+        Self {
+            user_agent,
+            ip_address,
+        }
+    }
+}
+
+/// Login sessions, persisted so revocations are shared by every API instance
+#[derive(Clone)]
+pub struct SessionStore {
+    /// Database pool
+    db: PgPool,
+}
+
+impl SessionStore {
+    /// Create a new session store
+    pub fn new(db: PgPool) -> Self {
+        Self { db }
+    }
+
+    /// Create a session for a user, expiring after `ttl`
+    pub async fn create(
+        &self,
+        user_id: Uuid,
+        device: &DeviceInfo,
+        ttl: Duration,
+    ) -> Result<Session, ApiError> {
+        let now = Utc::now();
+        let expires_at = now
+            + chrono::Duration::from_std(ttl)
+                .map_err(|e| ApiError::Server(format!("Invalid session lifetime: {}", e)))?;
+
+        sqlx::query_as::<_, Session>(
+            "INSERT INTO user_sessions (id, user_id, user_agent, ip_address, created_at, last_seen_at, expires_at) \
+             VALUES ($1, $2, $3, $4, $5, $5, $6) RETURNING *",
+        )
+        .bind(Uuid::new_v4())
+        .bind(user_id)
+        .bind(&device.user_agent)
+        .bind(&device.ip_address)
+        .bind(now)
+        .bind(expires_at)
+        .fetch_one(&self.db)
+        .await
+        .map_err(|e| ApiError::Database(format!("Failed to create session: {}", e)))
+    }
+
+    /// Whether a session is still active, recording it was used.
+    ///
+    /// Unknown, expired and revoked sessions are inactive, so their tokens are denied.
+    pub async fn touch(&self, id: Uuid) -> Result<bool, ApiError> {
+        let now = Utc::now();
+        let last_seen_at: Option<chrono::DateTime<Utc>> = sqlx::query_scalar(
+            "SELECT last_seen_at FROM user_sessions \
+             WHERE id = $1 AND revoked_at IS NULL AND expires_at > $2",
+        )
+        .bind(id)
+        .bind(now)
+        .fetch_optional(&self.db)
+        .await
+        .map_err(|e| ApiError::Database(format!("Failed to get session: {}", e)))?;
+
+        let Some(last_seen_at) = last_seen_at else {
+            return Ok(false);
+        };
+        if (now - last_seen_at).num_seconds() >= LAST_SEEN_RESOLUTION {
+            sqlx::query("UPDATE user_sessions SET last_seen_at = $2 WHERE id = $1")
+                .bind(id)
+                .bind(now)
+                .execute(&self.db)
+                .await
+                .map_err(|e| ApiError::Database(format!("Failed to update session: {}", e)))?;
+        }
+
+        Ok(true)
+    }
+
+    /// Active sessions of a user, most recently used first
+    pub async fn list(&self, user_id: Uuid) -> Result<Vec<Session>, ApiError> {
+        sqlx::query_as::<_, Session>(
+            "SELECT * FROM user_sessions \
+             WHERE user_id = $1 AND revoked_at IS NULL AND expires_at > $2 \
+             ORDER BY last_seen_at DESC",
+        )
+        .bind(user_id)
+        .bind(Utc::now())
+        .fetch_all(&self.db)
+        .await
+        .map_err(|e| ApiError::Database(format!("Failed to list sessions: {}", e)))
+    }
+
+    /// Revoke an active session of a user
+    pub async fn revoke(
+        &self,
+        user_id: Uuid,
+        id: Uuid,
+        reason: RevocationReason,
+    ) -> Result<(), ApiError> {
+        let now = Utc::now();
+        let revoked = sqlx::query(
+            "UPDATE user_sessions SET revoked_at = $3, revoked_reason = $4 \
+             WHERE id = $1 AND user_id = $2 AND revoked_at IS NULL AND expires_at > $3",
+        )
+        .bind(id)
+        .bind(user_id)
+        .bind(now)
+        .bind(reason.as_str())
+        .execute(&self.db)
+        .await
+        .map_err(|e| ApiError::Database(format!("Failed to revoke session: {}", e)))?;
+        if revoked.rows_affected() == 0 {
+            return Err(ApiError::NotFound(format!("Session not found: {}", id)));
+        }
+
+        Ok(())
+    }
+
+    /// Revoke every active session of a user but `except`, returning how many were revoked
+    pub async fn revoke_all(
+        &self,
+        user_id: Uuid,
+        reason: RevocationReason,
+        except: Option<Uuid>,
+    ) -> Result<u64, ApiError> {
+        let now = Utc::now();
+        let revoked = sqlx::query(
+            "UPDATE user_sessions SET revoked_at = $2, revoked_reason = $3 \
+             WHERE user_id = $1 AND revoked_at IS NULL AND expires_at > $2 \
+             AND ($4::uuid IS NULL OR id <> $4)",
+        )
+        .bind(user_id)
+        .bind(now)
+        .bind(reason.as_str())
+        .bind(except)
+        .execute(&self.db)
+        .await
+        .map_err(|e| ApiError::Database(format!("Failed to revoke sessions: {}", e)))?;
+
+        Ok(revoked.rows_affected())
+    }
+
+    /// Delete expired sessions; their tokens expired with them, so they need no denying
+    pub async fn purge_expired(&self) -> Result<u64, ApiError> {
+        let result = sqlx::query("DELETE FROM user_sessions WHERE expires_at <= $1")
+            .bind(Utc::now())
+            .execute(&self.db)
+            .await
+            .map_err(|e| ApiError::Database(format!("Failed to purge sessions: {}", e)))?;
+
+        Ok(result.rows_affected())
+    }
+
+    /// Purge expired sessions every interval
+    pub fn spawn_purge(&self, interval: Duration) -> tokio::task::JoinHandle<()> {
+        let store = self.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(interval);
+            loop {
+                interval.tick().await;
+                match store.purge_expired().await {
+                    Ok(0) => {}
+                    Ok(purged) => log::debug!("Purged {} expired sessions", purged),
+                    Err(e) => log::warn!("{}", e),
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_device_info() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(DeviceInfo::from_headers(&headers), DeviceInfo::default());
+
+        headers.insert(header::USER_AGENT, "r3e-cli/1.0".parse().unwrap());
+        headers.insert("X-Real-IP", "10.0.0.2".parse().unwrap());
+        headers.insert("X-Forwarded-For", "203.0.113.7, 10.0.0.1".parse().unwrap());
+        let device = DeviceInfo::from_headers(&headers);
+        assert_eq!(device.user_agent.as_deref(), Some("r3e-cli/1.0"));
+        assert_eq!(device.ip_address.as_deref(), Some("203.0.113.7"));
+
+        // Addresses that do not parse are dropped rather than stored
+        headers.insert("X-Forwarded-For", "unknown".parse().unwrap());
+        assert_eq!(DeviceInfo::from_headers(&headers).ip_address, None);
+        headers.remove("X-Forwarded-For");
+        assert_eq!(
+            DeviceInfo::from_headers(&headers).ip_address.as_deref(),
+            Some("10.0.0.2")
+        );
+    }
+}
@@ -0,0 +1,599 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+//! Detached signatures of API responses.
+//!
+//! Consumers feeding responses into contracts ask for a signature with the `X-R3E-Sign`
+//! header, naming the algorithm. JSON responses of the signed paths, e.g. invocation results,
+//! are then returned with the signature of their digest and the key that made it:
+//!
+//! ```text
+//! sha256("r3e-api:v1|{path}|{timestamp}|{canonical body}")
+//! ```
+//!
+//! where the canonical body is the response JSON with object keys sorted and no whitespace.
+//! Ed25519 signatures are of the digest. secp256r1 signatures are ECDSA over the SHA-256 of
+//! the digest, as checked by Neo's `CryptoLib.verifyWithECDsa` with `secp256r1SHA256`.
+//!
+//! The platform keys are published at `GET /signing-keys`. Rotating a key retires it: it
+//! stops signing but stays published for the grace period, so signatures it made can still
+//! be checked.
+
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+
+use axum::body::{to_bytes, Body};
+use axum::extract::{Request, State};
+use axum::http::{header, HeaderMap, HeaderName, HeaderValue};
+use axum::middleware::Next;
+use axum::response::Response;
+use chrono::{DateTime, Duration, Utc};
+use ed25519_dalek::{Signer as _, Verifier as _};
+use p256::ecdsa::signature::{Signer as _, Verifier as _};
+use p256::elliptic_curve::sec1::ToEncodedPoint;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use utoipa::ToSchema;
+
+use crate::error::ApiError;
+use crate::service::ApiService;
+
+/// Domain separator of response digests
+pub const SIGNING_DOMAIN: &str = "r3e-api:v1";
+
+/// Header a client names the algorithm of the signature it asks for in
+pub const SIGN_HEADER: &str = "x-r3e-sign";
+
+/// Header carrying the signature (hex)
+pub const SIGNATURE_HEADER: &str = "x-r3e-signature";
+
+/// Header carrying the ID of the signing key
+pub const SIGNATURE_KEY_HEADER: &str = "x-r3e-signature-key";
+
+/// Header carrying the signature algorithm
+pub const SIGNATURE_ALGORITHM_HEADER: &str = "x-r3e-signature-algorithm";
+
+/// Header carrying the signing timestamp (secs since epoch)
+pub const SIGNATURE_TIMESTAMP_HEADER: &str = "x-r3e-signature-timestamp";
+
+/// Header carrying the signed digest (hex)
+pub const SIGNATURE_DIGEST_HEADER: &str = "x-r3e-signature-digest";
+
+/// Largest response body signed
+const MAX_SIGNED_BODY_SIZE: usize = 6 * 1024 * 1024;
+
+/// Signature algorithm
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum SigningAlgorithm {
+    /// Ed25519 over the digest
+    Ed25519,
+
+    /// ECDSA secp256r1 with SHA-256 over the digest, verifiable by Neo N3 contracts
+    Secp256r1,
+}
+
+impl SigningAlgorithm {
+    /// Every algorithm, in the order keys are generated
+    pub const ALL: [SigningAlgorithm; 2] = [Self::Ed25519, Self::Secp256r1];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SigningAlgorithm::Ed25519 => "ed25519",
+            SigningAlgorithm::Secp256r1 => "secp256r1",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|algorithm| value.trim().eq_ignore_ascii_case(algorithm.as_str()))
+    }
+}
+
+/// Published key of the platform
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct PublicSigningKey {
+    /// Key ID, carried in the `X-R3E-Signature-Key` header of the responses it signs
+    pub kid: String,
+
+    /// Signature algorithm
+    pub algorithm: SigningAlgorithm,
+
+    /// Public key, 32 bytes for Ed25519 and the compressed point for secp256r1 (hex)
+    pub public_key: String,
+
+    /// Creation timestamp
+    pub created_at: DateTime<Utc>,
+
+    /// When the key was rotated out, none for the keys signing responses
+    pub retired_at: Option<DateTime<Utc>>,
+}
+
+/// Key as persisted, with its private half
+#[derive(Clone, Serialize, Deserialize)]
+struct StoredKey {
+    #[serde(flatten)]
+    public: PublicSigningKey,
+
+    /// Private key (hex)
+    private_key: String,
+}
+
+impl StoredKey {
+    /// Generate a new random key
+    fn generate(algorithm: SigningAlgorithm, now: DateTime<Utc>) -> Self {
+        let (private_key, public_key) = match algorithm {
+            SigningAlgorithm::Ed25519 => {
+                let key = ed25519_dalek::SigningKey::from_bytes(&rand::random::<[u8; 32]>());
+                (
+                    key.to_bytes().to_vec(),
+                    key.verifying_key().to_bytes().to_vec(),
+                )
+            }
+            SigningAlgorithm::Secp256r1 => {
+                // Random scalars out of the curve order are rejected, so draw again
+                let key = loop {
+                    if let Ok(key) =
+                        p256::ecdsa::SigningKey::from_slice(&rand::random::<[u8; 32]>())
+                    {
+                        break key;
+                    }
+                };
+                let public_key = key.verifying_key().to_encoded_point(true);
+                (key.to_bytes().to_vec(), public_key.as_bytes().to_vec())
+            }
+        };
+
+        Self {
+            public: PublicSigningKey {
+                kid: uuid::Uuid::new_v4().to_string(),
+                algorithm,
+                public_key: hex::encode(public_key),
+                created_at: now,
+                retired_at: None,
+            },
+            private_key: hex::encode(private_key),
+        }
+    }
+
+    fn sign(&self, digest: &[u8; 32]) -> Result<Vec<u8>, ApiError> {
+        let private_key = hex::decode(&self.private_key)
+            .map_err(|e| ApiError::Server(format!("Invalid signing key: {}", e)))?;
+        match self.public.algorithm {
+            SigningAlgorithm::Ed25519 => {
+                let bytes: [u8; 32] = private_key
+                    .try_into()
+                    .map_err(|_| ApiError::Server("Invalid signing key".to_string()))?;
+                let key = ed25519_dalek::SigningKey::from_bytes(&bytes);
+                Ok(key.sign(digest).to_bytes().to_vec())
+            }
+            SigningAlgorithm::Secp256r1 => {
+                let key = p256::ecdsa::SigningKey::from_slice(&private_key)
+                    .map_err(|e| ApiError::Server(format!("Invalid signing key: {}", e)))?;
+                let signature: p256::ecdsa::Signature = key.sign(digest);
+                Ok(signature.to_bytes().to_vec())
+            }
+        }
+    }
+}
+
+/// Detached signature of a response
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResponseSignature {
+    /// Signing key ID
+    pub kid: String,
+
+    /// Signature algorithm
+    pub algorithm: SigningAlgorithm,
+
+    /// Signing timestamp (secs since epoch)
+    pub timestamp: i64,
+
+    /// Signed digest (hex)
+    pub digest: String,
+
+    /// Signature, 64 bytes for both algorithms, `r || s` for secp256r1 (hex)
+    pub signature: String,
+}
+
+impl ResponseSignature {
+    fn insert_headers(&self, headers: &mut HeaderMap) {
+        let values = [
+            (SIGNATURE_HEADER, self.signature.clone()),
+            (SIGNATURE_KEY_HEADER, self.kid.clone()),
+            (
+                SIGNATURE_ALGORITHM_HEADER,
+                self.algorithm.as_str().to_string(),
+            ),
+            (SIGNATURE_TIMESTAMP_HEADER, self.timestamp.to_string()),
+            (SIGNATURE_DIGEST_HEADER, self.digest.clone()),
+        ];
+        for (name, value) in values {
+            if let Ok(value) = HeaderValue::from_str(&value) {
+                headers.insert(HeaderName::from_static(name), value);
+            }
+        }
+    }
+}
+
+/// JSON with object keys sorted and no whitespace
+pub fn canonical_json(value: &Value) -> String {
+    let mut out = String::new();
+    write_canonical(value, &mut out);
+    out
+}
+
+fn write_canonical(value: &Value, out: &mut String) {
+    match value {
+        Value::Object(object) => {
+            let mut entries: Vec<_> = object.iter().collect();
+            entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+            out.push('{');
+            for (i, (key, value)) in entries.into_iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                out.push_str(&Value::String(key.clone()).to_string());
+                out.push(':');
+                write_canonical(value, out);
+            }
+            out.push('}');
+        }
+        Value::Array(items) => {
+            out.push('[');
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_canonical(item, out);
+            }
+            out.push(']');
+        }
+        scalar => out.push_str(&scalar.to_string()),
+    }
+}
+
+/// Digest of a response that is signed
+pub fn response_digest(path: &str, timestamp: i64, body: &Value) -> [u8; 32] {
+    let message = format!(
+        "{}|{}|{}|{}",
+        SIGNING_DOMAIN,
+        path,
+        timestamp,
+        canonical_json(body)
+    );
+    Sha256::digest(message.as_bytes()).into()
+}
+
+/// Check a signature of a digest against a published key
+pub fn verify_signature(key: &PublicSigningKey, digest: &[u8; 32], signature: &str) -> bool {
+    let (Ok(public_key), Ok(signature)) = (hex::decode(&key.public_key), hex::decode(signature))
+    else {
+        return false;
+    };
+    match key.algorithm {
+        SigningAlgorithm::Ed25519 => {
+            let Ok(public_key) = <[u8; 32]>::try_from(public_key) else {
+                return false;
+            };
+            let (Ok(key), Ok(signature)) = (
+                ed25519_dalek::VerifyingKey::from_bytes(&public_key),
+                ed25519_dalek::Signature::from_slice(&signature),
+            ) else {
+                return false;
+            };
+            key.verify(digest, &signature).is_ok()
+        }
+        SigningAlgorithm::Secp256r1 => {
+            let (Ok(key), Ok(signature)) = (
+                p256::ecdsa::VerifyingKey::from_sec1_bytes(&public_key),
+                p256::ecdsa::Signature::from_slice(&signature),
+            ) else {
+                return false;
+            };
+            key.verify(digest, &signature).is_ok()
+        }
+    }
+}
+
+/// Whether a path matches a pattern, `*` matching a single segment
+fn path_matches(pattern: &str, path: &str) -> bool {
+    let pattern: Vec<_> = pattern.trim_end_matches('/').split('/').collect();
+    let path: Vec<_> = path.trim_end_matches('/').split('/').collect();
+    pattern.len() == path.len()
+        && pattern
+            .iter()
+            .zip(&path)
+            .all(|(pattern, segment)| *pattern == "*" || pattern == segment)
+}
+
+/// Signing keys of the platform and the paths whose responses they sign
+pub struct ResponseSigner {
+    /// File the keys are persisted in
+    path: PathBuf,
+
+    /// Patterns of the signed paths
+    paths: Vec<String>,
+
+    /// How long a retired key stays published
+    grace_period: Duration,
+
+    /// Keys, the active one of each algorithm and the retired ones still published
+    keys: RwLock<Vec<StoredKey>>,
+}
+
+impl ResponseSigner {
+    /// Open the keys persisted at `path`, generating the ones missing
+    pub fn open(
+        path: impl Into<PathBuf>,
+        paths: Vec<String>,
+        grace_period: Duration,
+    ) -> Result<Self, ApiError> {
+        let path = path.into();
+        let keys = match std::fs::read(&path) {
+            Ok(data) => serde_json::from_slice(&data)
+                .map_err(|e| ApiError::Server(format!("Invalid signing key file: {}", e)))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => {
+                return Err(ApiError::Server(format!(
+                    "Failed to read signing keys: {}",
+                    e
+                )))
+            }
+        };
+
+        let signer = Self {
+            path,
+            paths,
+            grace_period,
+            keys: RwLock::new(keys),
+        };
+        let missing: Vec<_> = SigningAlgorithm::ALL
+            .into_iter()
+            .filter(|algorithm| signer.active(*algorithm).is_none())
+            .collect();
+        if !missing.is_empty() {
+            let now = Utc::now();
+            let mut keys = signer.keys.write().unwrap();
+            keys.extend(
+                missing
+                    .into_iter()
+                    .map(|algorithm| StoredKey::generate(algorithm, now)),
+            );
+            signer.persist(&keys)?;
+        }
+        Ok(signer)
+    }
+
+    fn active(&self, algorithm: SigningAlgorithm) -> Option<StoredKey> {
+        self.keys
+            .read()
+            .unwrap()
+            .iter()
+            .find(|key| key.public.algorithm == algorithm && key.public.retired_at.is_none())
+            .cloned()
+    }
+
+    fn persist(&self, keys: &[StoredKey]) -> Result<(), ApiError> {
+        let data = serde_json::to_vec_pretty(keys)
+            .map_err(|e| ApiError::Server(format!("Failed to serialize signing keys: {}", e)))?;
+        if let Some(dir) = self.path.parent() {
+            std::fs::create_dir_all(dir).map_err(|e| {
+                ApiError::Server(format!("Failed to create signing key directory: {}", e))
+            })?;
+        }
+
+        // Write then rename, readable by the API only
+        let tmp = self
+            .path
+            .with_extension(format!("tmp.{}", std::process::id()));
+        std::fs::write(&tmp, data)
+            .and_then(|_| {
+                #[cfg(unix)]
+                {
+                    use std::os::unix::fs::PermissionsExt;
+                    std::fs::set_permissions(&tmp, std::fs::Permissions::from_mode(0o600))?;
+                }
+                std::fs::rename(&tmp, &self.path)
+            })
+            .map_err(|e| ApiError::Server(format!("Failed to write signing keys: {}", e)))
+    }
+
+    /// Keys signing responses and the retired ones still within their grace period
+    pub fn public_keys(&self) -> Vec<PublicSigningKey> {
+        let cutoff = Utc::now() - self.grace_period;
+        self.keys
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|key| key.public.retired_at.map_or(true, |at| at > cutoff))
+            .map(|key| key.public.clone())
+            .collect()
+    }
+
+    /// Replace the key of an algorithm with a new one, retiring the previous key and
+    /// dropping the keys retired past their grace period
+    pub fn rotate(&self, algorithm: SigningAlgorithm) -> Result<PublicSigningKey, ApiError> {
+        let now = Utc::now();
+        let cutoff = now - self.grace_period;
+        let key = StoredKey::generate(algorithm, now);
+
+        let mut keys = self.keys.write().unwrap();
+        let mut rotated = keys.clone();
+        rotated.retain(|key| key.public.retired_at.map_or(true, |at| at > cutoff));
+        for key in rotated.iter_mut() {
+            if key.public.algorithm == algorithm && key.public.retired_at.is_none() {
+                key.public.retired_at = Some(now);
+            }
+        }
+        rotated.push(key.clone());
+        self.persist(&rotated)?;
+        *keys = rotated;
+
+        log::info!(
+            "Rotated {} response signing key to {}",
+            algorithm.as_str(),
+            key.public.kid
+        );
+        Ok(key.public)
+    }
+
+    /// Whether responses of a path are signed
+    pub fn signs(&self, path: &str) -> bool {
+        self.paths.iter().any(|pattern| path_matches(pattern, path))
+    }
+
+    /// Sign the body of a response of a path
+    pub fn sign(
+        &self,
+        algorithm: SigningAlgorithm,
+        path: &str,
+        body: &Value,
+    ) -> Result<ResponseSignature, ApiError> {
+        let key = self
+            .active(algorithm)
+            .ok_or_else(|| ApiError::Server(format!("No {} signing key", algorithm.as_str())))?;
+
+        let timestamp = Utc::now().timestamp();
+        let digest = response_digest(path, timestamp, body);
+        let signature = key.sign(&digest)?;
+
+        Ok(ResponseSignature {
+            kid: key.public.kid,
+            algorithm,
+            timestamp,
+            digest: hex::encode(digest),
+            signature: hex::encode(signature),
+        })
+    }
+}
+
+/// Sign the JSON responses of the signed paths the client asks a signature of
+pub async fn sign_responses(
+    State(api_service): State<Arc<ApiService>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let signer = &api_service.response_signer;
+    let path = request.uri().path().to_string();
+    let Some(requested) = request
+        .headers()
+        .get(SIGN_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string)
+    else {
+        return next.run(request).await;
+    };
+    if !signer.signs(&path) {
+        return next.run(request).await;
+    }
+    let Some(algorithm) = SigningAlgorithm::parse(&requested) else {
+        return axum::response::IntoResponse::into_response(ApiError::Validation(format!(
+            "Unsupported signature algorithm '{}', expected ed25519 or secp256r1",
+            requested
+        )));
+    };
+
+    let response = next.run(request).await;
+    let is_json = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("application/json"));
+    if !response.status().is_success() || !is_json {
+        // Errors and streamed output are not signed
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    let bytes = match to_bytes(body, MAX_SIGNED_BODY_SIZE).await {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            log::error!("Failed to read response of {} to sign: {}", path, e);
+            return axum::response::IntoResponse::into_response(ApiError::Server(
+                "Response too large to sign".to_string(),
+            ));
+        }
+    };
+    let signed = serde_json::from_slice(&bytes)
+        .map_err(|e| ApiError::Server(format!("Invalid JSON response: {}", e)))
+        .and_then(|body| signer.sign(algorithm, &path, &body));
+    match signed {
+        Ok(signature) => signature.insert_headers(&mut parts.headers),
+        Err(e) => {
+            log::error!("Failed to sign response of {}: {}", path, e);
+            return axum::response::IntoResponse::into_response(e);
+        }
+    }
+
+    Response::from_parts(parts, Body::from(bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_canonical_json() {
+        let body: Value =
+            serde_json::from_str(r#"{ "b": [3, {"z": null, "a": "x\"y"}], "a": 1.5, "c": true }"#)
+                .unwrap();
+        assert_eq!(
+            canonical_json(&body),
+            r#"{"a":1.5,"b":[3,{"a":"x\"y","z":null}],"c":true}"#
+        );
+    }
+
+    #[test]
+    fn test_sign_and_rotate() {
+        let dir = std::env::temp_dir().join(format!("r3e-signing-{}", uuid::Uuid::new_v4()));
+        let path = dir.join("keys.json");
+        let signer = ResponseSigner::open(
+            &path,
+            vec!["/functions/*/invoke".to_string()],
+            Duration::days(1),
+        )
+        .unwrap();
+        assert!(signer.signs("/functions/42/invoke"));
+        assert!(!signer.signs("/functions/42"));
+        assert!(!signer.signs("/functions/42/invoke/extra"));
+
+        let body = serde_json::json!({ "result": { "price": 42 }, "function_id": "42" });
+        for algorithm in SigningAlgorithm::ALL {
+            let signature = signer
+                .sign(algorithm, "/functions/42/invoke", &body)
+                .unwrap();
+            let key = signer
+                .public_keys()
+                .into_iter()
+                .find(|key| key.kid == signature.kid)
+                .unwrap();
+            let digest = response_digest("/functions/42/invoke", signature.timestamp, &body);
+            assert_eq!(signature.digest, hex::encode(digest));
+            assert!(verify_signature(&key, &digest, &signature.signature));
+
+            // Signatures are bound to the path and body
+            let other = response_digest("/functions/43/invoke", signature.timestamp, &body);
+            assert!(!verify_signature(&key, &other, &signature.signature));
+        }
+
+        // Reopening keeps the keys; a rotated key stops signing but stays published
+        let signer = ResponseSigner::open(&path, Vec::new(), Duration::days(1)).unwrap();
+        let old = signer.active(SigningAlgorithm::Ed25519).unwrap().public;
+        let new = signer.rotate(SigningAlgorithm::Ed25519).unwrap();
+        assert_ne!(old.kid, new.kid);
+        let keys = signer.public_keys();
+        assert_eq!(keys.len(), 3);
+        assert!(keys
+            .iter()
+            .any(|key| key.kid == old.kid && key.retired_at.is_some()));
+        let signature = signer
+            .sign(SigningAlgorithm::Ed25519, "/functions/42/invoke", &body)
+            .unwrap();
+        assert_eq!(signature.kid, new.kid);
+
+        std::fs::remove_dir_all(dir).ok();
+    }
+}
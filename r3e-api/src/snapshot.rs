@@ -0,0 +1,877 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+//! Snapshot export and import of the platform state.
+//!
+//! A snapshot holds the users, services, functions, secrets and balances of a deployment in
+//! one versioned JSON archive. Users, services and functions are read from the platform
+//! database, secrets from the secret storage and balances from the balance storage.
+//!
+//! Secrets are copied as stored, still encrypted with their function keys and sealed with
+//! their tenant keys, and each record is encrypted again with a backup key chosen by the
+//! operator. Function keys and tenant KEKs stay in Vault or the KMS and are not part of the
+//! archive, so it restores into deployments sharing those key stores.
+
+use std::str::FromStr;
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+use r3e_built_in_services::balance::{BalanceStorage, RocksDBBalanceStorage, UserBalance};
+use r3e_secrets::hashicorp::{VaultClient, VaultConfig, VaultKvStorage};
+use r3e_secrets::rocksdb::RocksDBSecretStorage;
+use r3e_secrets::storage::SecretStorage;
+use r3e_secrets::{EncryptedSecret, SecretEncryption, SecretError};
+use serde::{Deserialize, Serialize};
+use sqlx::{PgPool, Postgres, Transaction};
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::error::ApiError;
+use crate::models::function::Function;
+use crate::models::service::Service;
+use crate::models::user::{User, UserRole};
+
+/// Current snapshot format version
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+/// Plaintext encrypted with the backup key, to check the key before restoring anything
+const KEY_CHECK: &[u8] = b"r3e-faas snapshot key check";
+
+/// Platform state snapshot
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Snapshot {
+    /// Snapshot format version
+    pub version: u32,
+
+    /// Created at
+    pub created_at: DateTime<Utc>,
+
+    /// Key check encrypted with the backup key
+    pub key_check: WrappedSecret,
+
+    /// Users
+    pub users: Vec<UserRecord>,
+
+    /// Services
+    pub services: Vec<Service>,
+
+    /// Functions
+    pub functions: Vec<Function>,
+
+    /// Stored secret records, each encrypted with the backup key
+    pub secrets: Vec<WrappedSecret>,
+
+    /// Balances
+    pub balances: Vec<UserBalance>,
+}
+
+/// Ciphertext and nonce, hex encoded
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WrappedSecret {
+    /// Ciphertext
+    pub ciphertext: String,
+
+    /// Nonce
+    pub nonce: String,
+}
+
+/// User with its credentials, which the API model never serializes
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UserRecord {
+    /// User ID
+    pub id: Uuid,
+
+    /// Username
+    pub username: String,
+
+    /// Email
+    pub email: String,
+
+    /// Password hash
+    pub password_hash: String,
+
+    /// User role
+    pub role: UserRole,
+
+    /// API key
+    pub api_key: Option<String>,
+
+    /// Created at
+    pub created_at: DateTime<Utc>,
+
+    /// Updated at
+    pub updated_at: DateTime<Utc>,
+}
+
+impl From<User> for UserRecord {
+    fn from(user: User) -> Self {
+        Self {
+            id: user.id,
+            username: user.username,
+            email: user.email,
+            password_hash: user.password_hash,
+            role: user.role,
+            api_key: user.api_key,
+            created_at: user.created_at,
+            updated_at: user.updated_at,
+        }
+    }
+}
+
+/// What to do with records whose ID already exists in the target deployment
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ConflictPolicy {
+    /// Abort the import and roll back
+    #[default]
+    Fail,
+
+    /// Keep the existing record
+    Skip,
+
+    /// Replace the existing record
+    Overwrite,
+}
+
+impl FromStr for ConflictPolicy {
+    type Err = ApiError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "fail" => Ok(Self::Fail),
+            "skip" => Ok(Self::Skip),
+            "overwrite" => Ok(Self::Overwrite),
+            _ => Err(ApiError::Validation(format!(
+                "Invalid conflict policy: {}, expected fail, skip or overwrite",
+                s
+            ))),
+        }
+    }
+}
+
+/// Import counts of one record kind
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct ImportCounts {
+    /// Records created
+    pub created: u32,
+
+    /// Existing records replaced
+    pub overwritten: u32,
+
+    /// Existing records kept
+    pub skipped: u32,
+}
+
+/// Import report
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+pub struct ImportReport {
+    /// Users
+    pub users: ImportCounts,
+
+    /// Services
+    pub services: ImportCounts,
+
+    /// Functions
+    pub functions: ImportCounts,
+
+    /// Secrets
+    pub secrets: ImportCounts,
+
+    /// Balances
+    pub balances: ImportCounts,
+}
+
+/// Parse a hex encoded 32-byte key
+pub fn parse_key(key: &str) -> Result<[u8; 32], ApiError> {
+    let bytes = hex::decode(key.trim().trim_start_matches("0x"))
+        .map_err(|e| ApiError::Validation(format!("Invalid key: {}", e)))?;
+
+    bytes
+        .try_into()
+        .map_err(|_| ApiError::Validation("Invalid key: expected 32 bytes".to_string()))
+}
+
+fn cipher(key: &[u8]) -> Result<SecretEncryption, ApiError> {
+    SecretEncryption::new(key).map_err(|e| ApiError::Validation(e.to_string()))
+}
+
+fn wrap(cipher: &SecretEncryption, plaintext: &[u8]) -> Result<WrappedSecret, ApiError> {
+    let (ciphertext, nonce) = cipher
+        .encrypt(plaintext)
+        .map_err(|e| ApiError::Server(e.to_string()))?;
+
+    Ok(WrappedSecret {
+        ciphertext: hex::encode(ciphertext),
+        nonce: hex::encode(nonce),
+    })
+}
+
+fn unwrap(cipher: &SecretEncryption, wrapped: &WrappedSecret) -> Result<Vec<u8>, ApiError> {
+    let ciphertext = hex::decode(&wrapped.ciphertext)
+        .map_err(|e| ApiError::Validation(format!("Invalid ciphertext: {}", e)))?;
+    let nonce = hex::decode(&wrapped.nonce)
+        .map_err(|e| ApiError::Validation(format!("Invalid nonce: {}", e)))?;
+    if nonce.len() != 12 {
+        return Err(ApiError::Validation("Invalid nonce length".to_string()));
+    }
+
+    cipher
+        .decrypt(&ciphertext, &nonce)
+        .map_err(|e| ApiError::Validation(e.to_string()))
+}
+
+impl Snapshot {
+    /// Check the snapshot version and that `backup_key` opens its secrets
+    pub fn verify(&self, backup_key: &[u8]) -> Result<(), ApiError> {
+        if self.version == 0 || self.version > SNAPSHOT_VERSION {
+            return Err(ApiError::Validation(format!(
+                "Unsupported snapshot version: {}, expected at most {}",
+                self.version, SNAPSHOT_VERSION
+            )));
+        }
+
+        match unwrap(&cipher(backup_key)?, &self.key_check) {
+            Ok(check) if check == KEY_CHECK => Ok(()),
+            _ => Err(ApiError::Validation(
+                "Backup key does not match the snapshot".to_string(),
+            )),
+        }
+    }
+}
+
+/// Stores holding the secrets and balances of the platform
+#[derive(Clone)]
+pub struct Stores {
+    /// Function secrets
+    pub secrets: Arc<dyn SecretStorage>,
+
+    /// User balances
+    pub balances: Arc<dyn BalanceStorage>,
+}
+
+impl Stores {
+    /// Open the balance database and the secrets, in the Vault KV engine if `vault` is given
+    /// and in the secrets database otherwise
+    pub async fn open(
+        secrets_db_path: &str,
+        balance_db_path: &str,
+        vault: Option<&VaultConfig>,
+    ) -> Result<Self, ApiError> {
+        let secrets = Self::open_secrets(secrets_db_path, vault).await?;
+        let balances = RocksDBBalanceStorage::new(balance_db_path)
+            .await
+            .map_err(|e| ApiError::Server(format!("Failed to open balances: {}", e)))?;
+
+        Ok(Self {
+            secrets,
+            balances: Arc::new(balances),
+        })
+    }
+
+    /// Open the secrets, in the Vault KV engine if `vault` is given and in the secrets database
+    /// otherwise
+    pub async fn open_secrets(
+        secrets_db_path: &str,
+        vault: Option<&VaultConfig>,
+    ) -> Result<Arc<dyn SecretStorage>, ApiError> {
+        Ok(match vault {
+            Some(vault) => Arc::new(VaultKvStorage::new(Arc::new(
+                VaultClient::new(vault.clone())
+                    .map_err(|e| ApiError::Server(format!("Failed to connect to Vault: {}", e)))?,
+            ))),
+            None => Arc::new(
+                RocksDBSecretStorage::new(secrets_db_path)
+                    .await
+                    .map_err(|e| ApiError::Server(format!("Failed to open secrets: {}", e)))?,
+            ),
+        })
+    }
+}
+
+/// Secrets and balances of a snapshot to write once the database import is committed
+#[derive(Default)]
+struct StoreWrites {
+    secrets: Vec<EncryptedSecret>,
+    balances: Vec<UserBalance>,
+}
+
+/// Export the platform state, encrypting the secret records with `backup_key`
+pub async fn export(db: &PgPool, stores: &Stores, backup_key: &[u8]) -> Result<Snapshot, ApiError> {
+    let backup = cipher(backup_key)?;
+
+    let users = sqlx::query_as::<_, User>("SELECT * FROM users ORDER BY created_at")
+        .fetch_all(db)
+        .await
+        .map_err(|e| ApiError::Database(format!("Failed to export users: {}", e)))?;
+
+    let services = sqlx::query_as::<_, Service>("SELECT * FROM services ORDER BY created_at")
+        .fetch_all(db)
+        .await
+        .map_err(|e| ApiError::Database(format!("Failed to export services: {}", e)))?;
+
+    let functions = sqlx::query_as::<_, Function>("SELECT * FROM functions ORDER BY created_at")
+        .fetch_all(db)
+        .await
+        .map_err(|e| ApiError::Database(format!("Failed to export functions: {}", e)))?;
+
+    let (secrets, balances) = export_stores(stores, &backup).await?;
+
+    log::info!(
+        "Exported snapshot: {} users, {} services, {} functions, {} secrets, {} balances",
+        users.len(),
+        services.len(),
+        functions.len(),
+        secrets.len(),
+        balances.len()
+    );
+
+    Ok(Snapshot {
+        version: SNAPSHOT_VERSION,
+        created_at: Utc::now(),
+        key_check: wrap(&backup, KEY_CHECK)?,
+        users: users.into_iter().map(UserRecord::from).collect(),
+        services,
+        functions,
+        secrets,
+        balances,
+    })
+}
+
+/// Read the secret records, encrypted with the backup key, and the balances
+async fn export_stores(
+    stores: &Stores,
+    backup: &SecretEncryption,
+) -> Result<(Vec<WrappedSecret>, Vec<UserBalance>), ApiError> {
+    let mut records = stores
+        .secrets
+        .list_secrets()
+        .await
+        .map_err(|e| ApiError::Server(format!("Failed to export secrets: {}", e)))?;
+    records.sort_by_key(|secret| secret.created_at);
+
+    let mut secrets = Vec::with_capacity(records.len());
+    for secret in &records {
+        let record = serde_json::to_vec(secret).map_err(|e| {
+            ApiError::Server(format!("Failed to serialize secret {}: {}", secret.id, e))
+        })?;
+        secrets.push(wrap(backup, &record)?);
+    }
+
+    let mut balances = stores
+        .balances
+        .list_balances()
+        .await
+        .map_err(|e| ApiError::Server(format!("Failed to export balances: {}", e)))?;
+    balances.sort_by(|a, b| a.user_id.cmp(&b.user_id));
+
+    Ok((secrets, balances))
+}
+
+/// Import a snapshot: the database records in one transaction, then the secrets and balances.
+///
+/// Conflicts of the secrets and balances are resolved before the transaction, so a conflict
+/// failing the import leaves every store untouched.
+pub async fn import(
+    db: &PgPool,
+    stores: &Stores,
+    snapshot: &Snapshot,
+    backup_key: &[u8],
+    policy: ConflictPolicy,
+) -> Result<ImportReport, ApiError> {
+    snapshot.verify(backup_key)?;
+
+    let mut report = ImportReport::default();
+    let writes =
+        resolve_stores(stores, snapshot, &cipher(backup_key)?, policy, &mut report).await?;
+
+    let mut tx = db
+        .begin()
+        .await
+        .map_err(|e| ApiError::Database(format!("Failed to begin import: {}", e)))?;
+
+    // Parents first: functions reference services, and both reference users
+    for user in &snapshot.users {
+        let exists = exists(
+            &mut tx,
+            "SELECT 1 FROM users WHERE id = $1",
+            &user.id.to_string(),
+        )
+        .await?;
+        if !resolve(
+            policy,
+            exists,
+            "user",
+            &user.id.to_string(),
+            &mut report.users,
+        )? {
+            continue;
+        }
+
+        sqlx::query(
+            r#"
+            INSERT INTO users (id, username, email, password_hash, role, api_key, created_at, updated_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+            ON CONFLICT (id) DO UPDATE SET
+                username = EXCLUDED.username, email = EXCLUDED.email,
+                password_hash = EXCLUDED.password_hash, role = EXCLUDED.role,
+                api_key = EXCLUDED.api_key, created_at = EXCLUDED.created_at,
+                updated_at = EXCLUDED.updated_at
+            "#,
+        )
+        .bind(user.id)
+        .bind(&user.username)
+        .bind(&user.email)
+        .bind(&user.password_hash)
+        .bind(user.role as i32)
+        .bind(&user.api_key)
+        .bind(user.created_at)
+        .bind(user.updated_at)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| ApiError::Database(format!("Failed to import user {}: {}", user.id, e)))?;
+    }
+
+    for service in &snapshot.services {
+        let exists = exists(
+            &mut tx,
+            "SELECT 1 FROM services WHERE id = $1",
+            &service.id.to_string(),
+        )
+        .await?;
+        if !resolve(
+            policy,
+            exists,
+            "service",
+            &service.id.to_string(),
+            &mut report.services,
+        )? {
+            continue;
+        }
+
+        sqlx::query(
+            r#"
+            INSERT INTO services (
+                id, user_id, name, description, service_type, config, status, visibility,
+                version, created_at, updated_at
+            )
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)
+            ON CONFLICT (id) DO UPDATE SET
+                user_id = EXCLUDED.user_id, name = EXCLUDED.name,
+                description = EXCLUDED.description, service_type = EXCLUDED.service_type,
+                config = EXCLUDED.config, status = EXCLUDED.status,
+                visibility = EXCLUDED.visibility, version = EXCLUDED.version,
+                created_at = EXCLUDED.created_at, updated_at = EXCLUDED.updated_at
+            "#,
+        )
+        .bind(service.id)
+        .bind(service.user_id)
+        .bind(&service.name)
+        .bind(&service.description)
+        .bind(format!("{:?}", service.service_type).to_lowercase())
+        .bind(&service.config)
+        .bind(format!("{:?}", service.status).to_lowercase())
+        .bind(format!("{:?}", service.visibility).to_lowercase())
+        .bind(&service.version)
+        .bind(service.created_at)
+        .bind(service.updated_at)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| {
+            ApiError::Database(format!("Failed to import service {}: {}", service.id, e))
+        })?;
+    }
+
+    for function in &snapshot.functions {
+        let exists = exists(
+            &mut tx,
+            "SELECT 1 FROM functions WHERE id = $1",
+            &function.id.to_string(),
+        )
+        .await?;
+        if !resolve(
+            policy,
+            exists,
+            "function",
+            &function.id.to_string(),
+            &mut report.functions,
+        )? {
+            continue;
+        }
+
+        sqlx::query(
+            r#"
+            INSERT INTO functions (
+                id, service_id, user_id, name, description, code, runtime, trigger_type,
+                trigger_config, security_level, status, version, hash, code_key_id, created_at,
+                updated_at
+            )
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16)
+            ON CONFLICT (id) DO UPDATE SET
+                service_id = EXCLUDED.service_id, user_id = EXCLUDED.user_id,
+                name = EXCLUDED.name, description = EXCLUDED.description, code = EXCLUDED.code,
+                runtime = EXCLUDED.runtime, trigger_type = EXCLUDED.trigger_type,
+                trigger_config = EXCLUDED.trigger_config,
+                security_level = EXCLUDED.security_level, status = EXCLUDED.status,
+                version = EXCLUDED.version, hash = EXCLUDED.hash,
+                code_key_id = EXCLUDED.code_key_id, created_at = EXCLUDED.created_at, updated_at = EXCLUDED.updated_at
+            "#,
+        )
+        .bind(function.id)
+        .bind(function.service_id)
+        .bind(function.user_id)
+        .bind(&function.name)
+        .bind(&function.description)
+        .bind(&function.code)
+        .bind(format!("{:?}", function.runtime).to_lowercase())
+        .bind(format!("{:?}", function.trigger_type).to_lowercase())
+        .bind(&function.trigger_config)
+        .bind(format!("{:?}", function.security_level).to_lowercase())
+        .bind(format!("{:?}", function.status).to_lowercase())
+        .bind(&function.version)
+        .bind(&function.hash)
+        .bind(&function.code_key_id)
+        .bind(function.created_at)
+        .bind(function.updated_at)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| {
+            ApiError::Database(format!("Failed to import function {}: {}", function.id, e))
+        })?;
+    }
+
+    tx.commit()
+        .await
+        .map_err(|e| ApiError::Database(format!("Failed to commit import: {}", e)))?;
+    write_stores(stores, writes).await?;
+
+    log::info!(
+        "Imported snapshot from {}: {:?}",
+        snapshot.created_at,
+        report
+    );
+    Ok(report)
+}
+
+/// Decrypt the secret records and apply the conflict policy to them and the balances,
+/// returning the ones to write
+async fn resolve_stores(
+    stores: &Stores,
+    snapshot: &Snapshot,
+    backup: &SecretEncryption,
+    policy: ConflictPolicy,
+    report: &mut ImportReport,
+) -> Result<StoreWrites, ApiError> {
+    let mut writes = StoreWrites::default();
+
+    for record in &snapshot.secrets {
+        let secret: EncryptedSecret = serde_json::from_slice(&unwrap(backup, record)?)
+            .map_err(|e| ApiError::Validation(format!("Invalid secret record: {}", e)))?;
+        let key = format!("{}:{}:{}", secret.user_id, secret.function_id, secret.id);
+
+        let exists = match stores
+            .secrets
+            .get_secret(&secret.user_id, &secret.function_id, &secret.id)
+            .await
+        {
+            Ok(_) => true,
+            Err(SecretError::NotFound(_)) => false,
+            Err(e) => {
+                return Err(ApiError::Server(format!(
+                    "Failed to check secret {}: {}",
+                    key, e
+                )))
+            }
+        };
+        if resolve(policy, exists, "secret", &key, &mut report.secrets)? {
+            writes.secrets.push(secret);
+        }
+    }
+
+    for balance in &snapshot.balances {
+        let exists = stores
+            .balances
+            .get_balance(&balance.user_id)
+            .await
+            .map_err(|e| {
+                ApiError::Server(format!(
+                    "Failed to check balance {}: {}",
+                    balance.user_id, e
+                ))
+            })?
+            .is_some();
+        if resolve(
+            policy,
+            exists,
+            "balance",
+            &balance.user_id,
+            &mut report.balances,
+        )? {
+            writes.balances.push(balance.clone());
+        }
+    }
+
+    Ok(writes)
+}
+
+async fn write_stores(stores: &Stores, writes: StoreWrites) -> Result<(), ApiError> {
+    for secret in writes.secrets {
+        let key = format!("{}:{}:{}", secret.user_id, secret.function_id, secret.id);
+        stores
+            .secrets
+            .store_secret(secret)
+            .await
+            .map_err(|e| ApiError::Server(format!("Failed to import secret {}: {}", key, e)))?;
+    }
+
+    for balance in writes.balances {
+        let user_id = balance.user_id.clone();
+        stores.balances.update_balance(balance).await.map_err(|e| {
+            ApiError::Server(format!("Failed to import balance {}: {}", user_id, e))
+        })?;
+    }
+
+    Ok(())
+}
+
+async fn exists(tx: &mut Transaction<'_, Postgres>, sql: &str, id: &str) -> Result<bool, ApiError> {
+    let row = sqlx::query(sql)
+        .bind(id)
+        .fetch_optional(&mut **tx)
+        .await
+        .map_err(|e| ApiError::Database(format!("Failed to check {}: {}", id, e)))?;
+
+    Ok(row.is_some())
+}
+
+/// Apply the conflict policy to one record, returning whether to write it
+pub(crate) fn resolve(
+    policy: ConflictPolicy,
+    exists: bool,
+    kind: &str,
+    id: &str,
+    counts: &mut ImportCounts,
+) -> Result<bool, ApiError> {
+    if !exists {
+        counts.created += 1;
+        return Ok(true);
+    }
+
+    match policy {
+        ConflictPolicy::Fail => Err(ApiError::Conflict(format!(
+            "{} {} already exists",
+            kind, id
+        ))),
+        ConflictPolicy::Skip => {
+            counts.skipped += 1;
+            Ok(false)
+        }
+        ConflictPolicy::Overwrite => {
+            counts.overwritten += 1;
+            Ok(true)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use r3e_built_in_services::balance::MemoryBalanceStorage;
+    use r3e_secrets::storage::MemorySecretStorage;
+
+    fn memory_stores() -> Stores {
+        Stores {
+            secrets: Arc::new(MemorySecretStorage::new()),
+            balances: Arc::new(MemoryBalanceStorage::new()),
+        }
+    }
+
+    fn snapshot(backup_key: &[u8]) -> Snapshot {
+        Snapshot {
+            version: SNAPSHOT_VERSION,
+            created_at: Utc::now(),
+            key_check: wrap(&cipher(backup_key).unwrap(), KEY_CHECK).unwrap(),
+            users: Vec::new(),
+            services: Vec::new(),
+            functions: Vec::new(),
+            secrets: Vec::new(),
+            balances: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_parse_key() {
+        let key = parse_key(&format!("0x{}", "ab".repeat(32))).unwrap();
+        assert_eq!(key, [0xab; 32]);
+
+        assert!(parse_key("abcd").is_err());
+        assert!(parse_key("not hex").is_err());
+    }
+
+    #[test]
+    fn test_rewrap_secret() {
+        let master = cipher(&[1u8; 32]).unwrap();
+        let backup = cipher(&[2u8; 32]).unwrap();
+
+        let (encrypted_data, nonce) = master.encrypt(b"api-token").unwrap();
+        let plaintext = master.decrypt(&encrypted_data, &nonce).unwrap();
+        let wrapped = wrap(&backup, &plaintext).unwrap();
+
+        assert_eq!(unwrap(&backup, &wrapped).unwrap(), b"api-token");
+        assert!(unwrap(&master, &wrapped).is_err());
+    }
+
+    #[test]
+    fn test_verify_snapshot() {
+        let snapshot = snapshot(&[2u8; 32]);
+        assert!(snapshot.verify(&[2u8; 32]).is_ok());
+        assert!(snapshot.verify(&[3u8; 32]).is_err());
+
+        let mut future = snapshot.clone();
+        future.version = SNAPSHOT_VERSION + 1;
+        assert!(future.verify(&[2u8; 32]).is_err());
+    }
+
+    #[test]
+    fn test_resolve_conflicts() {
+        let mut counts = ImportCounts::default();
+        assert!(resolve(ConflictPolicy::Fail, false, "user", "1", &mut counts).unwrap());
+        assert!(resolve(ConflictPolicy::Fail, true, "user", "1", &mut counts).is_err());
+        assert!(!resolve(ConflictPolicy::Skip, true, "user", "1", &mut counts).unwrap());
+        assert!(resolve(ConflictPolicy::Overwrite, true, "user", "1", &mut counts).unwrap());
+        assert_eq!(
+            counts,
+            ImportCounts {
+                created: 1,
+                overwritten: 1,
+                skipped: 1,
+            }
+        );
+
+        assert_eq!(
+            "skip".parse::<ConflictPolicy>().unwrap(),
+            ConflictPolicy::Skip
+        );
+        assert!("replace".parse::<ConflictPolicy>().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_export_import_stores() {
+        let function_key = SecretEncryption::generate_function_key();
+        let (encrypted_data, nonce) = cipher(&function_key)
+            .unwrap()
+            .encrypt(b"api-token")
+            .unwrap();
+
+        let source = memory_stores();
+        source
+            .secrets
+            .store_secret(EncryptedSecret::new(
+                "user-1".to_string(),
+                "function-1".to_string(),
+                Some("token".to_string()),
+                encrypted_data,
+                nonce,
+            ))
+            .await
+            .unwrap();
+        source
+            .balances
+            .update_balance(UserBalance {
+                user_id: "user-1".to_string(),
+                neo_balance: 5,
+                gas_balance: 1_000_000,
+                updated_at: 1_700_000_000,
+            })
+            .await
+            .unwrap();
+
+        let backup_key = [2u8; 32];
+        let backup = cipher(&backup_key).unwrap();
+        let (secrets, balances) = export_stores(&source, &backup).await.unwrap();
+        let mut exported = snapshot(&backup_key);
+        exported.secrets = secrets;
+        exported.balances = balances;
+
+        // Restore what went through the archive
+        let archived: Snapshot =
+            serde_json::from_slice(&serde_json::to_vec(&exported).unwrap()).unwrap();
+        archived.verify(&backup_key).unwrap();
+        assert!(!serde_json::to_string(&archived).unwrap().contains("token"));
+
+        let target = memory_stores();
+        let mut report = ImportReport::default();
+        let writes = resolve_stores(
+            &target,
+            &archived,
+            &backup,
+            ConflictPolicy::Fail,
+            &mut report,
+        )
+        .await
+        .unwrap();
+        write_stores(&target, writes).await.unwrap();
+        assert_eq!(report.secrets.created, 1);
+        assert_eq!(report.balances.created, 1);
+
+        // The secret is restored as stored, still opening with its function key
+        let secret = target
+            .secrets
+            .get_secret("user-1", "function-1", "token")
+            .await
+            .unwrap();
+        let plaintext = cipher(&function_key)
+            .unwrap()
+            .decrypt(&secret.encrypted_data, &secret.nonce)
+            .unwrap();
+        assert_eq!(plaintext, b"api-token");
+
+        let balance = target
+            .balances
+            .get_balance("user-1")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(balance.neo_balance, 5);
+        assert_eq!(balance.gas_balance, 1_000_000);
+        assert_eq!(balance.updated_at, 1_700_000_000);
+
+        // Importing again conflicts with the restored records
+        let mut report = ImportReport::default();
+        assert!(resolve_stores(
+            &target,
+            &archived,
+            &backup,
+            ConflictPolicy::Fail,
+            &mut report
+        )
+        .await
+        .is_err());
+
+        let mut report = ImportReport::default();
+        let writes = resolve_stores(
+            &target,
+            &archived,
+            &backup,
+            ConflictPolicy::Skip,
+            &mut report,
+        )
+        .await
+        .unwrap();
+        assert!(writes.secrets.is_empty() && writes.balances.is_empty());
+        assert_eq!(report.secrets.skipped, 1);
+        assert_eq!(report.balances.skipped, 1);
+
+        // A different backup key does not open the records
+        let other = cipher(&[3u8; 32]).unwrap();
+        let mut report = ImportReport::default();
+        assert!(resolve_stores(
+            &memory_stores(),
+            &archived,
+            &other,
+            ConflictPolicy::Fail,
+            &mut report
+        )
+        .await
+        .is_err());
+    }
+}
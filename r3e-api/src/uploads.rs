@@ -0,0 +1,508 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+//! Chunked uploads of function bundles.
+//!
+//! A bundle too large to send in one request is uploaded in parts. The client creates an
+//! upload with the size and SHA-256 digest of the bundle, puts the parts in any order, retrying
+//! the ones that failed, and completes the upload once every part was received. Completing
+//! assembles the parts into the bundle and checks its digest; a function is then created from
+//! the upload instead of inline code.
+//!
+//! Parts and bundles are kept in the blob store, the database only refers to them. Uploads not
+//! completed and used within their TTL are collected with their blobs.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::body::Bytes;
+use chrono::{DateTime, TimeZone, Utc};
+use r3e_store::{blob::digest, BlobRef, BlobStore};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::error::ApiError;
+use crate::models::upload::{Upload, UploadPart, UploadStatus};
+
+/// Limits of chunked uploads
+#[derive(Debug, Clone)]
+pub struct UploadConfig {
+    /// Size of every part but the last one in bytes
+    pub part_size: u64,
+
+    /// Largest bundle in bytes
+    pub max_size: u64,
+
+    /// How long an upload is kept after its last part or completion
+    pub ttl: Duration,
+
+    /// Uploads a user may have in progress at once
+    pub max_open_uploads: u32,
+}
+
+/// Row of an upload
+type UploadRow = (Uuid, i64, String, i64, Option<String>, i64, i64);
+
+/// Chunked uploads, their parts kept in a blob store
+#[derive(Clone)]
+pub struct UploadStore {
+    /// Database pool
+    db: PgPool,
+
+    /// Blob store keeping the parts and assembled bundles
+    blobs: Arc<dyn BlobStore>,
+
+    /// Limits
+    config: UploadConfig,
+}
+
+impl UploadStore {
+    /// Create a new upload store
+    pub fn new(db: PgPool, blobs: Arc<dyn BlobStore>, config: UploadConfig) -> Self {
+        Self { db, blobs, config }
+    }
+
+    /// Start an upload of a bundle of `size` bytes hashing to `sha256`
+    pub async fn create(&self, user_id: Uuid, size: u64, sha256: &str) -> Result<Upload, ApiError> {
+        if size > self.config.max_size {
+            return Err(ApiError::Validation(format!(
+                "Bundle of {} bytes exceeds the limit of {} bytes",
+                size, self.config.max_size
+            )));
+        }
+        let sha256 = parse_digest(sha256)?;
+
+        let now = Utc::now().timestamp();
+        let open: i64 = sqlx::query_scalar(
+            "SELECT COUNT(*) FROM function_uploads \
+             WHERE user_id = $1 AND bundle_digest IS NULL AND expires_at > $2",
+        )
+        .bind(user_id)
+        .bind(now)
+        .fetch_one(&self.db)
+        .await
+        .map_err(|e| ApiError::Database(format!("Failed to count uploads: {}", e)))?;
+        if open >= i64::from(self.config.max_open_uploads) {
+            return Err(ApiError::QuotaExceeded(format!(
+                "At most {} uploads can be in progress at once",
+                self.config.max_open_uploads
+            )));
+        }
+
+        let row: UploadRow = sqlx::query_as(
+            "INSERT INTO function_uploads \
+             (id, user_id, size, sha256, part_size, bundle_digest, created_at, expires_at) \
+             VALUES ($1, $2, $3, $4, $5, NULL, $6, $7) \
+             RETURNING id, size, sha256, part_size, bundle_digest, created_at, expires_at",
+        )
+        .bind(Uuid::new_v4())
+        .bind(user_id)
+        .bind(size as i64)
+        .bind(&sha256)
+        .bind(self.config.part_size as i64)
+        .bind(now)
+        .bind(now + self.config.ttl.as_secs() as i64)
+        .fetch_one(&self.db)
+        .await
+        .map_err(|e| ApiError::Database(format!("Failed to create upload: {}", e)))?;
+
+        Ok(upload_from_row(row, Vec::new()))
+    }
+
+    /// Get an upload of a user, with the parts received so far
+    pub async fn get(&self, user_id: Uuid, id: Uuid) -> Result<Upload, ApiError> {
+        let row: Option<UploadRow> = sqlx::query_as(
+            "SELECT id, size, sha256, part_size, bundle_digest, created_at, expires_at \
+             FROM function_uploads WHERE id = $1 AND user_id = $2 AND expires_at > $3",
+        )
+        .bind(id)
+        .bind(user_id)
+        .bind(Utc::now().timestamp())
+        .fetch_optional(&self.db)
+        .await
+        .map_err(|e| ApiError::Database(format!("Failed to get upload: {}", e)))?;
+        let row = row.ok_or_else(|| ApiError::NotFound(format!("Upload not found: {}", id)))?;
+
+        let received_parts: Vec<i32> = sqlx::query_scalar(
+            "SELECT part_index FROM function_upload_parts WHERE upload_id = $1 ORDER BY part_index",
+        )
+        .bind(id)
+        .fetch_all(&self.db)
+        .await
+        .map_err(|e| ApiError::Database(format!("Failed to get upload parts: {}", e)))?;
+
+        Ok(upload_from_row(
+            row,
+            received_parts
+                .into_iter()
+                .map(|index| index as u32)
+                .collect(),
+        ))
+    }
+
+    /// Store a part of an upload, replacing the part if it was already received.
+    ///
+    /// `sha256` is the digest the client computed; the part is rejected if it does not match,
+    /// so a part corrupted on the way is uploaded again.
+    pub async fn put_part(
+        &self,
+        user_id: Uuid,
+        id: Uuid,
+        index: u32,
+        data: Bytes,
+        sha256: Option<&str>,
+    ) -> Result<UploadPart, ApiError> {
+        let upload = self.get(user_id, id).await?;
+        if upload.status == UploadStatus::Completed {
+            return Err(ApiError::Conflict(format!("Upload {} is completed", id)));
+        }
+        if index >= upload.part_count {
+            return Err(ApiError::Validation(format!(
+                "Part {} is out of range, the upload has {} parts",
+                index, upload.part_count
+            )));
+        }
+        let expected = part_len(upload.size, upload.part_size, index);
+        if data.len() as u64 != expected {
+            return Err(ApiError::Validation(format!(
+                "Part {} must be {} bytes, got {}",
+                index,
+                expected,
+                data.len()
+            )));
+        }
+        let actual = digest(&data);
+        if let Some(sha256) = sha256 {
+            if parse_digest(sha256)? != actual {
+                return Err(ApiError::Validation(format!(
+                    "Part {} does not match its checksum",
+                    index
+                )));
+            }
+        }
+
+        let blob = self.blobs.put(data).await?;
+        let previous: Option<String> = sqlx::query_scalar(
+            "SELECT digest FROM function_upload_parts WHERE upload_id = $1 AND part_index = $2",
+        )
+        .bind(id)
+        .bind(index as i32)
+        .fetch_optional(&self.db)
+        .await
+        .map_err(|e| ApiError::Database(format!("Failed to get upload part: {}", e)))?;
+
+        sqlx::query(
+            "INSERT INTO function_upload_parts (upload_id, part_index, digest, size) \
+             VALUES ($1, $2, $3, $4) \
+             ON CONFLICT (upload_id, part_index) DO UPDATE SET digest = $3, size = $4",
+        )
+        .bind(id)
+        .bind(index as i32)
+        .bind(&blob.digest)
+        .bind(blob.size as i64)
+        .execute(&self.db)
+        .await
+        .map_err(|e| ApiError::Database(format!("Failed to store upload part: {}", e)))?;
+        self.extend(id).await?;
+
+        if let Some(previous) = previous.filter(|previous| *previous != blob.digest) {
+            self.release(&[previous]).await;
+        }
+
+        Ok(UploadPart {
+            index,
+            size: blob.size,
+            sha256: blob.digest,
+        })
+    }
+
+    /// Assemble the parts of an upload into the bundle, checking its digest.
+    ///
+    /// Completing a completed upload returns it unchanged, so clients can retry.
+    pub async fn complete(&self, user_id: Uuid, id: Uuid) -> Result<Upload, ApiError> {
+        let upload = self.get(user_id, id).await?;
+        if upload.status == UploadStatus::Completed {
+            return Ok(upload);
+        }
+        let missing = upload.part_count as usize - upload.received_parts.len();
+        if missing > 0 {
+            return Err(ApiError::Conflict(format!(
+                "Upload {} is missing {} of {} parts",
+                id, missing, upload.part_count
+            )));
+        }
+
+        let parts: Vec<(String, i64)> = sqlx::query_as(
+            "SELECT digest, size FROM function_upload_parts \
+             WHERE upload_id = $1 ORDER BY part_index",
+        )
+        .bind(id)
+        .fetch_all(&self.db)
+        .await
+        .map_err(|e| ApiError::Database(format!("Failed to get upload parts: {}", e)))?;
+
+        let mut bundle = Vec::with_capacity(upload.size as usize);
+        for (digest, size) in &parts {
+            bundle.extend_from_slice(&self.blobs.get(&blob_ref(digest, *size)).await?);
+        }
+        let actual = digest(&bundle);
+        if actual != upload.sha256 {
+            return Err(ApiError::Validation(format!(
+                "Bundle does not match its checksum, expected {}, got {}",
+                upload.sha256, actual
+            )));
+        }
+        if std::str::from_utf8(&bundle).is_err() {
+            return Err(ApiError::Validation(
+                "Bundle is not valid UTF-8 source code".to_string(),
+            ));
+        }
+
+        // The bundle holds the content of the parts, which are released
+        let bundle = self.blobs.put(Bytes::from(bundle)).await?;
+        sqlx::query("UPDATE function_uploads SET bundle_digest = $2 WHERE id = $1")
+            .bind(id)
+            .bind(&bundle.digest)
+            .execute(&self.db)
+            .await
+            .map_err(|e| ApiError::Database(format!("Failed to complete upload: {}", e)))?;
+        sqlx::query("DELETE FROM function_upload_parts WHERE upload_id = $1")
+            .bind(id)
+            .execute(&self.db)
+            .await
+            .map_err(|e| ApiError::Database(format!("Failed to delete upload parts: {}", e)))?;
+        self.extend(id).await?;
+        self.release(
+            &parts
+                .into_iter()
+                .map(|(digest, _)| digest)
+                .filter(|digest| *digest != bundle.digest)
+                .collect::<Vec<_>>(),
+        )
+        .await;
+
+        self.get(user_id, id).await
+    }
+
+    /// Source code of a completed upload
+    pub async fn bundle(&self, user_id: Uuid, id: Uuid) -> Result<String, ApiError> {
+        let upload = self.get(user_id, id).await?;
+        if upload.status != UploadStatus::Completed {
+            return Err(ApiError::Conflict(format!(
+                "Upload {} is not completed",
+                id
+            )));
+        }
+
+        let data = self
+            .blobs
+            .get(&blob_ref(&upload.sha256, upload.size as i64))
+            .await?;
+        String::from_utf8(data.to_vec())
+            .map_err(|_| ApiError::Validation("Bundle is not valid UTF-8 source code".to_string()))
+    }
+
+    /// Code of a function being created, inline or from an upload of the user
+    pub async fn function_code(
+        &self,
+        user_id: Uuid,
+        code: &str,
+        upload_id: Option<Uuid>,
+    ) -> Result<String, ApiError> {
+        match upload_id {
+            Some(_) if !code.is_empty() => Err(ApiError::Validation(
+                "Send either the code or an upload, not both".to_string(),
+            )),
+            Some(upload_id) => self.bundle(user_id, upload_id).await,
+            None if code.is_empty() => Err(ApiError::Validation("Code is required".to_string())),
+            None => Ok(code.to_string()),
+        }
+    }
+
+    /// Delete an upload of a user with its parts and bundle
+    pub async fn delete(&self, user_id: Uuid, id: Uuid) -> Result<(), ApiError> {
+        if !self.remove(id, Some(user_id)).await? {
+            return Err(ApiError::NotFound(format!("Upload not found: {}", id)));
+        }
+        Ok(())
+    }
+
+    /// Delete expired uploads with their blobs, returning how many were deleted
+    pub async fn purge_expired(&self) -> Result<u64, ApiError> {
+        let expired: Vec<Uuid> =
+            sqlx::query_scalar("SELECT id FROM function_uploads WHERE expires_at <= $1")
+                .bind(Utc::now().timestamp())
+                .fetch_all(&self.db)
+                .await
+                .map_err(|e| ApiError::Database(format!("Failed to list uploads: {}", e)))?;
+
+        let mut purged = 0;
+        for id in expired {
+            if self.remove(id, None).await? {
+                purged += 1;
+            }
+        }
+        Ok(purged)
+    }
+
+    /// Purge expired uploads every interval
+    pub fn spawn_purge(&self, interval: Duration) -> tokio::task::JoinHandle<()> {
+        let store = self.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(interval);
+            loop {
+                interval.tick().await;
+                match store.purge_expired().await {
+                    Ok(0) => {}
+                    Ok(purged) => log::debug!("Purged {} abandoned uploads", purged),
+                    Err(e) => log::warn!("{}", e),
+                }
+            }
+        })
+    }
+
+    /// Keep an upload for another TTL
+    async fn extend(&self, id: Uuid) -> Result<(), ApiError> {
+        sqlx::query("UPDATE function_uploads SET expires_at = $2 WHERE id = $1")
+            .bind(id)
+            .bind(Utc::now().timestamp() + self.config.ttl.as_secs() as i64)
+            .execute(&self.db)
+            .await
+            .map_err(|e| ApiError::Database(format!("Failed to extend upload: {}", e)))?;
+        Ok(())
+    }
+
+    /// Delete an upload, of a user if one is given, then release its blobs
+    async fn remove(&self, id: Uuid, user_id: Option<Uuid>) -> Result<bool, ApiError> {
+        let parts: Vec<String> =
+            sqlx::query_scalar("SELECT digest FROM function_upload_parts WHERE upload_id = $1")
+                .bind(id)
+                .fetch_all(&self.db)
+                .await
+                .map_err(|e| ApiError::Database(format!("Failed to get upload parts: {}", e)))?;
+
+        // Parts are deleted with the upload
+        let deleted: Option<(Option<String>,)> = sqlx::query_as(
+            "DELETE FROM function_uploads WHERE id = $1 AND ($2::uuid IS NULL OR user_id = $2) \
+             RETURNING bundle_digest",
+        )
+        .bind(id)
+        .bind(user_id)
+        .fetch_optional(&self.db)
+        .await
+        .map_err(|e| ApiError::Database(format!("Failed to delete upload: {}", e)))?;
+        let Some((bundle,)) = deleted else {
+            return Ok(false);
+        };
+
+        self.release(&parts.into_iter().chain(bundle).collect::<Vec<_>>())
+            .await;
+        Ok(true)
+    }
+
+    /// Delete the blobs no upload refers to anymore.
+    ///
+    /// Blobs are content-addressed, so another upload of the same content may still hold one.
+    /// Failures are logged; a blob left behind only takes space.
+    async fn release(&self, digests: &[String]) {
+        for digest in digests {
+            let referenced: Result<bool, sqlx::Error> = sqlx::query_scalar(
+                "SELECT EXISTS (SELECT 1 FROM function_upload_parts WHERE digest = $1) \
+                 OR EXISTS (SELECT 1 FROM function_uploads WHERE bundle_digest = $1)",
+            )
+            .bind(digest)
+            .fetch_one(&self.db)
+            .await;
+            match referenced {
+                Ok(true) => {}
+                Ok(false) => {
+                    if let Err(e) = self.blobs.delete(&blob_ref(digest, 0)).await {
+                        log::warn!("Failed to delete upload blob {}: {}", digest, e);
+                    }
+                }
+                Err(e) => log::warn!("Failed to check upload blob {}: {}", digest, e),
+            }
+        }
+    }
+}
+
+/// Number of parts of a bundle
+pub fn part_count(size: u64, part_size: u64) -> u32 {
+    size.div_ceil(part_size.max(1)) as u32
+}
+
+/// Size of a part of a bundle, the last part holding the remainder
+pub fn part_len(size: u64, part_size: u64, index: u32) -> u64 {
+    let start = u64::from(index) * part_size;
+    size.saturating_sub(start).min(part_size)
+}
+
+/// Lowercase a hex encoded SHA-256 digest, rejecting anything else
+fn parse_digest(sha256: &str) -> Result<String, ApiError> {
+    let sha256 = sha256.trim().to_ascii_lowercase();
+    if sha256.len() != 64 || !sha256.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err(ApiError::Validation(
+            "Checksum must be a hex encoded SHA-256 digest".to_string(),
+        ));
+    }
+    Ok(sha256)
+}
+
+/// Reference of a blob the database knows by digest
+fn blob_ref(digest: &str, size: i64) -> BlobRef {
+    BlobRef {
+        digest: digest.to_string(),
+        size: size as u64,
+        location: None,
+    }
+}
+
+fn timestamp(secs: i64) -> DateTime<Utc> {
+    Utc.timestamp_opt(secs, 0).single().unwrap_or_default()
+}
+
+fn upload_from_row(row: UploadRow, received_parts: Vec<u32>) -> Upload {
+    let (id, size, sha256, part_size, bundle_digest, created_at, expires_at) = row;
+    let (size, part_size) = (size as u64, part_size as u64);
+    Upload {
+        id,
+        size,
+        sha256,
+        part_size,
+        part_count: part_count(size, part_size),
+        received_parts,
+        status: if bundle_digest.is_some() {
+            UploadStatus::Completed
+        } else {
+            UploadStatus::Uploading
+        },
+        created_at: timestamp(created_at),
+        expires_at: timestamp(expires_at),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parts() {
+        assert_eq!(part_count(1, 4), 1);
+        assert_eq!(part_count(8, 4), 2);
+        assert_eq!(part_count(9, 4), 3);
+
+        assert_eq!(part_len(9, 4, 0), 4);
+        assert_eq!(part_len(9, 4, 1), 4);
+        assert_eq!(part_len(9, 4, 2), 1);
+        assert_eq!(part_len(8, 4, 1), 4);
+        assert_eq!(part_len(8, 4, 2), 0);
+    }
+
+    #[test]
+    fn test_parse_digest() {
+        let sha256 = digest(b"bundle");
+        assert_eq!(parse_digest(&sha256.to_uppercase()).unwrap(), sha256);
+        assert!(parse_digest(&sha256[1..]).is_err());
+        assert!(parse_digest(&format!("{}g", &sha256[1..])).is_err());
+    }
+}
@@ -0,0 +1,87 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+use axum::async_trait;
+use r3e_built_in_services::billing::{BillingError, BillingServiceTrait};
+use r3e_built_in_services::workflow::{FunctionInvoker, WorkflowError};
+use serde_json::Value;
+use std::sync::Arc;
+use uuid::Uuid;
+
+use crate::models::function::FunctionStatus;
+use crate::service::FunctionService;
+
+/// Invokes the functions of workflow steps through the function service
+///
+/// Steps may only invoke functions of the workflow owner, and not while the owner is
+/// suspended for overdue invoices.
+pub struct ApiFunctionInvoker {
+    /// Function service
+    function_service: FunctionService,
+
+    /// Billing service, checked before every invocation
+    billing_service: Arc<dyn BillingServiceTrait>,
+}
+
+impl ApiFunctionInvoker {
+    /// Create a new function invoker
+    pub fn new(
+        function_service: FunctionService,
+        billing_service: Arc<dyn BillingServiceTrait>,
+    ) -> Self {
+        Self {
+            function_service,
+            billing_service,
+        }
+    }
+}
+
+#[async_trait]
+impl FunctionInvoker for ApiFunctionInvoker {
+    async fn invoke(
+        &self,
+        owner: &str,
+        function_id: &str,
+        input: Value,
+    ) -> Result<Value, WorkflowError> {
+        let id = Uuid::parse_str(function_id)
+            .map_err(|_| WorkflowError::Step(format!("Invalid function ID: {}", function_id)))?;
+
+        // Functions of other users are reported as missing
+        let function = self
+            .function_service
+            .get_function(id)
+            .await
+            .map_err(|e| WorkflowError::Step(e.to_string()))?;
+        if function.user_id.to_string() != owner {
+            return Err(WorkflowError::Step(format!(
+                "Function not found: {}",
+                function_id
+            )));
+        }
+        if function.status != FunctionStatus::Active {
+            return Err(WorkflowError::Step(format!(
+                "Function {} is not active",
+                function_id
+            )));
+        }
+
+        match self.billing_service.check_execution(owner).await {
+            Ok(()) => {}
+            Err(err @ BillingError::Suspended(_)) => {
+                return Err(WorkflowError::Step(err.to_string()))
+            }
+            Err(err) => log::error!("Failed to check billing of {}: {}", owner, err),
+        }
+
+        let response = self
+            .function_service
+            .invoke_function(id, &input)
+            .await
+            .map_err(|e| WorkflowError::Step(e.to_string()))?;
+        match response.error {
+            None => Ok(response.result),
+            Some(error) => Err(WorkflowError::Step(error)),
+        }
+    }
+}
@@ -0,0 +1,20 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+//! User-defined alert rules.
+//!
+//! Users set rules on the metrics of their functions, such as the error rate or the p95
+//! latency over a window, or the cost of the day. The [`AlertRuleService`] evaluates them
+//! against the usage analytics rollups, and raises the alerts of the firing rules through an
+//! [`AlertManager`](crate::alerts::AlertManager), whose sinks include the notification
+//! service routing them to the owner's channels.
+
+pub mod rocksdb;
+pub mod service;
+pub mod storage;
+pub mod types;
+
+pub use rocksdb::RocksDBAlertRuleStorage;
+pub use service::*;
+pub use storage::*;
+pub use types::*;
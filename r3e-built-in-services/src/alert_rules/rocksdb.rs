@@ -0,0 +1,133 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+use async_trait::async_trait;
+use r3e_store::rocksdb::RocksDBStore;
+use r3e_store::{KvStore, SortedKvStore};
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::alert_rules::storage::AlertRuleStorage;
+use crate::alert_rules::types::{AlertRule, AlertRuleError};
+
+/// RocksDB implementation of AlertRuleStorage
+pub struct RocksDBAlertRuleStorage {
+    db: Arc<RocksDBStore>,
+    rules_cf: String,
+}
+
+impl RocksDBAlertRuleStorage {
+    /// Create a new RocksDB alert rule storage
+    pub fn new<P: AsRef<Path>>(db_path: P) -> Result<Self, AlertRuleError> {
+        let db = RocksDBStore::new(db_path).map_err(|e| {
+            AlertRuleError::Storage(format!("Failed to create RocksDB store: {}", e))
+        })?;
+
+        Ok(Self {
+            db: Arc::new(db),
+            rules_cf: "alert_rules".to_string(),
+        })
+    }
+
+    /// Scan the rules with keys in `[start, end)`, up to the last rule if `end` is empty
+    fn scan_rules(&self, start: &[u8], end: &[u8]) -> Result<Vec<AlertRule>, AlertRuleError> {
+        let mut rules = Vec::new();
+        let mut start_key = start.to_vec();
+        let mut start_exclusive = false;
+
+        loop {
+            let input = r3e_store::ScanInput {
+                start_key: &start_key,
+                start_exclusive,
+                end_key: end,
+                end_inclusive: false,
+                reverse: false,
+                max_count: 1000,
+            };
+
+            let output = self.db.scan(&self.rules_cf, input).map_err(|e| {
+                AlertRuleError::Storage(format!("Failed to scan alert rules: {}", e))
+            })?;
+
+            for (_, value) in &output.kvs {
+                rules.push(serde_json::from_slice(value).map_err(|e| {
+                    AlertRuleError::Storage(format!("Failed to deserialize alert rule: {}", e))
+                })?);
+            }
+
+            match output.kvs.last() {
+                Some((key, _)) if output.has_more => {
+                    start_key = key.clone();
+                    start_exclusive = true;
+                }
+                _ => break,
+            }
+        }
+
+        Ok(rules)
+    }
+}
+
+/// Keys are `<function ID>/<rule ID>`, so the keys of a function sort between
+/// `<function ID>/` and `<function ID>0`
+fn function_range(function_id: &str) -> (String, String) {
+    (format!("{}/", function_id), format!("{}0", function_id))
+}
+
+#[async_trait]
+impl AlertRuleStorage for RocksDBAlertRuleStorage {
+    async fn get_rule(
+        &self,
+        function_id: &str,
+        id: &str,
+    ) -> Result<Option<AlertRule>, AlertRuleError> {
+        let key = format!("{}/{}", function_id, id);
+        match self.db.get(&self.rules_cf, key.as_bytes()) {
+            Ok(value) => serde_json::from_slice(&value).map(Some).map_err(|e| {
+                AlertRuleError::Storage(format!("Failed to deserialize alert rule: {}", e))
+            }),
+            Err(r3e_store::GetError::NoSuchKey) => Ok(None),
+            Err(e) => Err(AlertRuleError::Storage(format!(
+                "Failed to get alert rule: {}",
+                e
+            ))),
+        }
+    }
+
+    async fn put_rule(&self, rule: AlertRule) -> Result<(), AlertRuleError> {
+        let key = format!("{}/{}", rule.function_id, rule.id);
+        let value = serde_json::to_vec(&rule).map_err(|e| {
+            AlertRuleError::Storage(format!("Failed to serialize alert rule: {}", e))
+        })?;
+
+        let input = r3e_store::PutInput {
+            key: key.as_bytes(),
+            value: &value,
+            if_not_exists: false,
+        };
+
+        self.db
+            .put(&self.rules_cf, input)
+            .map_err(|e| AlertRuleError::Storage(format!("Failed to store alert rule: {}", e)))
+    }
+
+    async fn delete_rule(&self, function_id: &str, id: &str) -> Result<(), AlertRuleError> {
+        let key = format!("{}/{}", function_id, id);
+        self.db
+            .delete(&self.rules_cf, key.as_bytes())
+            .map_err(|e| AlertRuleError::Storage(format!("Failed to delete alert rule: {}", e)))?;
+        Ok(())
+    }
+
+    async fn list_rules(&self, function_id: &str) -> Result<Vec<AlertRule>, AlertRuleError> {
+        let (start, end) = function_range(function_id);
+        let mut rules = self.scan_rules(start.as_bytes(), end.as_bytes())?;
+
+        rules.sort_by_key(|rule| rule.created_at);
+        Ok(rules)
+    }
+
+    async fn list_all_rules(&self) -> Result<Vec<AlertRule>, AlertRuleError> {
+        self.scan_rules(&[], &[])
+    }
+}
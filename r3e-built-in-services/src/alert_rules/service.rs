@@ -0,0 +1,575 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use r3e_store::{AnalyticsStore, Granularity, LatencyHistogram, LeaseHolder};
+use uuid::Uuid;
+
+use crate::alert_rules::storage::AlertRuleStorage;
+use crate::alert_rules::types::{
+    AlertRule, AlertRuleError, AlertRuleRequest, RuleCondition, RuleEvaluation, RuleState,
+};
+use crate::alerts::{Alert, AlertManager, AlertSeverity};
+
+/// Most rules a function may have
+pub const MAX_RULES_PER_FUNCTION: usize = 20;
+
+/// Manages the alert rules of functions and evaluates them against the usage analytics.
+///
+/// Windows are worked out from the hourly aggregates, so a window covers the hours it
+/// overlaps: a 30 minute window at 10:45 covers the invocations since 10:00. A firing rule
+/// raises an alert about the owner of its function through the [`AlertManager`], again every
+/// cooldown while it keeps firing, and an info alert once it clears.
+pub struct AlertRuleService {
+    /// Storage
+    storage: Arc<dyn AlertRuleStorage>,
+
+    /// Usage aggregates the rules are evaluated against
+    analytics: Arc<dyn AnalyticsStore>,
+
+    /// Where the alerts of firing rules are raised
+    alerts: Arc<AlertManager>,
+}
+
+impl AlertRuleService {
+    /// Create a new alert rule service
+    pub fn new(
+        storage: Arc<dyn AlertRuleStorage>,
+        analytics: Arc<dyn AnalyticsStore>,
+        alerts: Arc<AlertManager>,
+    ) -> Self {
+        Self {
+            storage,
+            analytics,
+            alerts,
+        }
+    }
+
+    /// Create a rule of a function owned by `owner`
+    pub async fn create_rule(
+        &self,
+        function_id: &str,
+        owner: &str,
+        request: AlertRuleRequest,
+    ) -> Result<AlertRule, AlertRuleError> {
+        request.condition.validate()?;
+        if self.storage.list_rules(function_id).await?.len() >= MAX_RULES_PER_FUNCTION {
+            return Err(AlertRuleError::InvalidInput(format!(
+                "At most {} alert rules are allowed per function",
+                MAX_RULES_PER_FUNCTION
+            )));
+        }
+
+        let now = chrono::Utc::now().timestamp() as u64;
+        let rule = AlertRule {
+            id: Uuid::new_v4().to_string(),
+            function_id: function_id.to_string(),
+            owner: owner.to_string(),
+            name: request.name,
+            condition: request.condition,
+            severity: request.severity,
+            enabled: request.enabled,
+            state: RuleState::Ok,
+            state_changed_at: None,
+            created_at: now,
+            updated_at: now,
+        };
+        self.storage.put_rule(rule.clone()).await?;
+
+        log::info!(
+            "alert rules: created rule {} of function {}",
+            rule.id,
+            function_id
+        );
+        Ok(rule)
+    }
+
+    /// Get a rule
+    pub async fn get_rule(&self, function_id: &str, id: &str) -> Result<AlertRule, AlertRuleError> {
+        self.storage
+            .get_rule(function_id, id)
+            .await?
+            .ok_or_else(|| AlertRuleError::NotFound(format!("Alert rule not found: {}", id)))
+    }
+
+    /// Replace the settings of a rule, which is evaluated afresh
+    pub async fn update_rule(
+        &self,
+        function_id: &str,
+        id: &str,
+        request: AlertRuleRequest,
+    ) -> Result<AlertRule, AlertRuleError> {
+        request.condition.validate()?;
+        let mut rule = self.get_rule(function_id, id).await?;
+        self.alerts.resolve(&rule.alert_key());
+
+        let now = chrono::Utc::now().timestamp() as u64;
+        rule.name = request.name;
+        rule.condition = request.condition;
+        rule.severity = request.severity;
+        rule.enabled = request.enabled;
+        rule.state = RuleState::Ok;
+        rule.state_changed_at = None;
+        rule.updated_at = now;
+        self.storage.put_rule(rule.clone()).await?;
+
+        Ok(rule)
+    }
+
+    /// Delete a rule
+    pub async fn delete_rule(&self, function_id: &str, id: &str) -> Result<(), AlertRuleError> {
+        let rule = self.get_rule(function_id, id).await?;
+        self.alerts.resolve(&rule.alert_key());
+        self.storage.delete_rule(function_id, id).await
+    }
+
+    /// List the rules of a function
+    pub async fn list_rules(&self, function_id: &str) -> Result<Vec<AlertRule>, AlertRuleError> {
+        self.storage.list_rules(function_id).await
+    }
+
+    /// Work out the metric of a rule at `now`, without raising alerts
+    pub async fn evaluate(
+        &self,
+        rule: &AlertRule,
+        now: u64,
+    ) -> Result<RuleEvaluation, AlertRuleError> {
+        let (value, threshold) = match &rule.condition {
+            RuleCondition::ErrorRate {
+                threshold,
+                window_minutes,
+                min_invocations,
+            } => {
+                let (invocations, errors, _) = self.window(rule, *window_minutes, now).await?;
+                let value = (invocations > 0 && invocations >= *min_invocations)
+                    .then(|| errors as f64 / invocations as f64);
+                (value, *threshold)
+            }
+            RuleCondition::P95Latency {
+                threshold_ms,
+                window_minutes,
+            } => {
+                let (_, _, latency) = self.window(rule, *window_minutes, now).await?;
+                let value = latency.percentile(0.95).map(|ms| ms as f64);
+                (value, *threshold_ms as f64)
+            }
+            RuleCondition::DailyCost { threshold_gas } => {
+                let from = Granularity::Day.bucket_start(now);
+                let cost = self
+                    .analytics
+                    .query(&rule.function_id, Granularity::Day, from, now + 1)
+                    .await?
+                    .iter()
+                    .map(|aggregate| aggregate.gas_cost)
+                    .sum::<f64>();
+                (Some(cost), *threshold_gas)
+            }
+        };
+
+        Ok(RuleEvaluation {
+            rule_id: rule.id.clone(),
+            value,
+            firing: value.is_some_and(|value| value > threshold),
+            evaluated_at: now,
+        })
+    }
+
+    /// Invocations, errors and latencies of the hourly aggregates overlapping a window
+    async fn window(
+        &self,
+        rule: &AlertRule,
+        window_minutes: u32,
+        now: u64,
+    ) -> Result<(u64, u64, LatencyHistogram), AlertRuleError> {
+        let from = Granularity::Hour.bucket_start(now.saturating_sub(window_minutes as u64 * 60));
+        let aggregates = self
+            .analytics
+            .query(&rule.function_id, Granularity::Hour, from, now + 1)
+            .await?;
+
+        let mut latency = LatencyHistogram::default();
+        let (mut invocations, mut errors) = (0, 0);
+        for aggregate in &aggregates {
+            invocations += aggregate.invocations;
+            errors += aggregate.errors;
+            latency.merge(&aggregate.latency);
+        }
+        Ok((invocations, errors, latency))
+    }
+
+    /// Evaluate every enabled rule at `now`, raising the alerts of the firing rules and
+    /// recording the rules changing state. Returns the number of firing rules.
+    pub async fn run_evaluation(&self, now: u64) -> Result<usize, AlertRuleError> {
+        let mut firing = 0;
+        for mut rule in self.storage.list_all_rules().await? {
+            if !rule.enabled {
+                continue;
+            }
+
+            let evaluation = match self.evaluate(&rule, now).await {
+                Ok(evaluation) => evaluation,
+                Err(e) => {
+                    log::error!("alert rules: failed to evaluate rule {}: {}", rule.id, e);
+                    continue;
+                }
+            };
+
+            let key = rule.alert_key();
+            let state = if evaluation.firing {
+                firing += 1;
+                self.alerts
+                    .raise(
+                        Alert::new(&key, rule.severity, Self::describe(&rule, &evaluation))
+                            .with_tenant(&rule.owner),
+                    )
+                    .await;
+                RuleState::Firing
+            } else {
+                if rule.state == RuleState::Firing {
+                    self.alerts.resolve(&key);
+                    self.alerts
+                        .raise(
+                            Alert::new(
+                                &key,
+                                AlertSeverity::Info,
+                                format!(
+                                    "Alert rule {} of function {} resolved",
+                                    rule.name, rule.function_id
+                                ),
+                            )
+                            .with_tenant(&rule.owner),
+                        )
+                        .await;
+                    // The next firing alerts right away
+                    self.alerts.resolve(&key);
+                }
+                RuleState::Ok
+            };
+
+            if state != rule.state {
+                rule.state = state;
+                rule.state_changed_at = Some(now);
+                self.storage.put_rule(rule).await?;
+            }
+        }
+
+        Ok(firing)
+    }
+
+    /// Message of the alert of a firing rule
+    fn describe(rule: &AlertRule, evaluation: &RuleEvaluation) -> String {
+        let value = evaluation.value.unwrap_or_default();
+        let condition = match &rule.condition {
+            RuleCondition::ErrorRate {
+                threshold,
+                window_minutes,
+                ..
+            } => format!(
+                "error rate {:.1}% over {} minutes exceeds {:.1}%",
+                value * 100.0,
+                window_minutes,
+                threshold * 100.0
+            ),
+            RuleCondition::P95Latency {
+                threshold_ms,
+                window_minutes,
+            } => format!(
+                "p95 latency {}ms over {} minutes exceeds {}ms",
+                value, window_minutes, threshold_ms
+            ),
+            RuleCondition::DailyCost { threshold_gas } => {
+                format!("cost {:.4} GAS today exceeds {} GAS", value, threshold_gas)
+            }
+        };
+
+        format!(
+            "Alert rule {} of function {}: {}",
+            rule.name, rule.function_id, condition
+        )
+    }
+}
+
+/// Evaluate the alert rules periodically, on the instance holding `lease` if one is given
+pub fn spawn_alert_rules(
+    service: Arc<AlertRuleService>,
+    interval: Duration,
+    lease: Option<Arc<LeaseHolder>>,
+) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(interval);
+        loop {
+            interval.tick().await;
+            if let Some(lease) = &lease {
+                if lease.hold().await.is_none() {
+                    continue;
+                }
+            }
+            let now = chrono::Utc::now().timestamp() as u64;
+            match service.run_evaluation(now).await {
+                Ok(0) => {}
+                Ok(firing) => log::debug!("alert rules: {} rules firing", firing),
+                Err(e) => log::error!("alert rules: evaluation failed: {}", e),
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alert_rules::storage::MemoryAlertRuleStorage;
+    use crate::alert_rules::types::MAX_WINDOW_MINUTES;
+    use crate::alerts::AlertSink;
+    use async_trait::async_trait;
+    use r3e_store::{MemoryAnalyticsStore, UsageAggregate, UsageSample};
+    use std::sync::Mutex;
+
+    const FUNCTION: &str = "function";
+    const OWNER: &str = "alice";
+
+    /// Start of a UTC day
+    const DAY: u64 = 1_700_006_400;
+
+    /// 10:45 on that day
+    const NOW: u64 = DAY + 10 * 3_600 + 45 * 60;
+
+    /// Sink keeping the alerts delivered to it
+    #[derive(Default)]
+    struct RecordingSink {
+        alerts: Mutex<Vec<Alert>>,
+    }
+
+    impl RecordingSink {
+        fn take(&self) -> Vec<Alert> {
+            self.alerts.lock().unwrap().drain(..).collect()
+        }
+    }
+
+    #[async_trait]
+    impl AlertSink for RecordingSink {
+        async fn send(&self, alert: &Alert) -> Result<(), String> {
+            self.alerts.lock().unwrap().push(alert.clone());
+            Ok(())
+        }
+    }
+
+    /// Aggregate of invocations with the given latencies, failing the first `errors`
+    fn aggregate(
+        granularity: Granularity,
+        start: u64,
+        latencies: &[u64],
+        errors: usize,
+    ) -> UsageAggregate {
+        let mut aggregate = UsageAggregate::new(FUNCTION, granularity, start);
+        for (i, latency_ms) in latencies.iter().enumerate() {
+            aggregate.record(&UsageSample {
+                function_id: FUNCTION.to_string(),
+                timestamp: start,
+                latency_ms: *latency_ms,
+                error: i < errors,
+                gas_cost: 0.25,
+            });
+        }
+        aggregate
+    }
+
+    /// 09:00 without errors at 1s, 10:00 with 3 errors out of 10 at 100ms
+    async fn setup() -> (AlertRuleService, Arc<RecordingSink>) {
+        let analytics = Arc::new(MemoryAnalyticsStore::new());
+        analytics
+            .merge(vec![
+                aggregate(Granularity::Hour, DAY + 9 * 3_600, &[1_000; 10], 0),
+                aggregate(Granularity::Hour, DAY + 10 * 3_600, &[100; 10], 3),
+                aggregate(Granularity::Day, DAY, &[100; 20], 3),
+            ])
+            .await
+            .unwrap();
+
+        let sink = Arc::new(RecordingSink::default());
+        let alerts = AlertManager::new(Duration::from_secs(3_600)).with_sink(sink.clone());
+        let service = AlertRuleService::new(
+            Arc::new(MemoryAlertRuleStorage::new()),
+            analytics,
+            Arc::new(alerts),
+        );
+        (service, sink)
+    }
+
+    fn request(condition: RuleCondition) -> AlertRuleRequest {
+        AlertRuleRequest {
+            name: condition.metric().to_string(),
+            condition,
+            severity: AlertSeverity::Critical,
+            enabled: true,
+        }
+    }
+
+    fn error_rate(threshold: f64, window_minutes: u32, min_invocations: u64) -> RuleCondition {
+        RuleCondition::ErrorRate {
+            threshold,
+            window_minutes,
+            min_invocations,
+        }
+    }
+
+    #[test]
+    fn test_validate_condition() {
+        assert!(error_rate(0.0, 1, 1).validate().is_ok());
+        assert!(error_rate(1.0, 60, 1).validate().is_err());
+        assert!(error_rate(-0.1, 60, 1).validate().is_err());
+        assert!(error_rate(0.5, 0, 1).validate().is_err());
+        assert!(error_rate(0.5, MAX_WINDOW_MINUTES + 1, 1)
+            .validate()
+            .is_err());
+
+        let latency = |threshold_ms| RuleCondition::P95Latency {
+            threshold_ms,
+            window_minutes: 60,
+        };
+        assert!(latency(1).validate().is_ok());
+        assert!(latency(0).validate().is_err());
+
+        let cost = |threshold_gas| RuleCondition::DailyCost { threshold_gas };
+        assert!(cost(0.5).validate().is_ok());
+        assert!(cost(0.0).validate().is_err());
+        assert!(cost(f64::NAN).validate().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_evaluate() {
+        let (service, _) = setup().await;
+        let evaluate = |condition| {
+            let service = &service;
+            async move {
+                let rule = service
+                    .create_rule(FUNCTION, OWNER, request(condition))
+                    .await
+                    .unwrap();
+                service.evaluate(&rule, NOW).await.unwrap()
+            }
+        };
+
+        // A 30 minute window covers the hour since 10:00, a 90 minute one 09:00 as well
+        let evaluation = evaluate(error_rate(0.2, 30, 1)).await;
+        assert_eq!((evaluation.value, evaluation.firing), (Some(0.3), true));
+        let evaluation = evaluate(error_rate(0.2, 90, 1)).await;
+        assert_eq!((evaluation.value, evaluation.firing), (Some(0.15), false));
+
+        // Too few invocations to work out a rate
+        let evaluation = evaluate(error_rate(0.2, 30, 11)).await;
+        assert_eq!((evaluation.value, evaluation.firing), (None, false));
+
+        let latency = |threshold_ms, window_minutes| RuleCondition::P95Latency {
+            threshold_ms,
+            window_minutes,
+        };
+        let evaluation = evaluate(latency(500, 30)).await;
+        assert_eq!((evaluation.value, evaluation.firing), (Some(100.0), false));
+        let evaluation = evaluate(latency(500, 90)).await;
+        assert_eq!((evaluation.value, evaluation.firing), (Some(1_000.0), true));
+
+        let evaluation = evaluate(RuleCondition::DailyCost { threshold_gas: 4.0 }).await;
+        assert_eq!((evaluation.value, evaluation.firing), (Some(5.0), true));
+    }
+
+    #[tokio::test]
+    async fn test_run_evaluation() {
+        let (service, sink) = setup().await;
+        let rule = service
+            .create_rule(FUNCTION, OWNER, request(error_rate(0.2, 30, 1)))
+            .await
+            .unwrap();
+        let mut disabled = request(error_rate(0.1, 30, 1));
+        disabled.enabled = false;
+        service
+            .create_rule(FUNCTION, OWNER, disabled)
+            .await
+            .unwrap();
+
+        // A firing rule alerts its owner once per cooldown
+        assert_eq!(service.run_evaluation(NOW).await.unwrap(), 1);
+        let alerts = sink.take();
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(alerts[0].key, rule.alert_key());
+        assert_eq!(alerts[0].severity, AlertSeverity::Critical);
+        assert_eq!(alerts[0].tenant.as_deref(), Some(OWNER));
+        assert!(
+            alerts[0].message.contains("error rate 30.0%"),
+            "{}",
+            alerts[0].message
+        );
+        let firing = service.get_rule(FUNCTION, &rule.id).await.unwrap();
+        assert_eq!(firing.state, RuleState::Firing);
+        assert_eq!(firing.state_changed_at, Some(NOW));
+
+        assert_eq!(service.run_evaluation(NOW + 60).await.unwrap(), 1);
+        assert!(sink.take().is_empty());
+        let firing = service.get_rule(FUNCTION, &rule.id).await.unwrap();
+        assert_eq!(firing.state_changed_at, Some(NOW));
+
+        // Once the window holds no invocations the rule clears, with an info alert
+        let later = NOW + 2 * 3_600;
+        assert_eq!(service.run_evaluation(later).await.unwrap(), 0);
+        let alerts = sink.take();
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(alerts[0].severity, AlertSeverity::Info);
+        let cleared = service.get_rule(FUNCTION, &rule.id).await.unwrap();
+        assert_eq!(cleared.state, RuleState::Ok);
+        assert_eq!(cleared.state_changed_at, Some(later));
+
+        // Firing again alerts right away
+        assert_eq!(service.run_evaluation(NOW).await.unwrap(), 1);
+        assert_eq!(sink.take().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_manage_rules() {
+        let (service, _) = setup().await;
+        assert!(matches!(
+            service
+                .create_rule(FUNCTION, OWNER, request(error_rate(2.0, 30, 1)))
+                .await,
+            Err(AlertRuleError::InvalidInput(_))
+        ));
+
+        let rule = service
+            .create_rule(FUNCTION, OWNER, request(error_rate(0.2, 30, 1)))
+            .await
+            .unwrap();
+        service.run_evaluation(NOW).await.unwrap();
+
+        // Updating a rule evaluates it afresh
+        let updated = service
+            .update_rule(FUNCTION, &rule.id, request(error_rate(0.5, 30, 1)))
+            .await
+            .unwrap();
+        assert_eq!(updated.state, RuleState::Ok);
+        assert_eq!(updated.state_changed_at, None);
+        assert!(matches!(
+            service
+                .update_rule("other", &rule.id, request(error_rate(0.5, 30, 1)))
+                .await,
+            Err(AlertRuleError::NotFound(_))
+        ));
+
+        for _ in 1..MAX_RULES_PER_FUNCTION {
+            service
+                .create_rule(FUNCTION, OWNER, request(error_rate(0.5, 30, 1)))
+                .await
+                .unwrap();
+        }
+        assert!(service
+            .create_rule(FUNCTION, OWNER, request(error_rate(0.5, 30, 1)))
+            .await
+            .is_err());
+
+        service.delete_rule(FUNCTION, &rule.id).await.unwrap();
+        assert!(service.get_rule(FUNCTION, &rule.id).await.is_err());
+        assert_eq!(
+            service.list_rules(FUNCTION).await.unwrap().len(),
+            MAX_RULES_PER_FUNCTION - 1
+        );
+    }
+}
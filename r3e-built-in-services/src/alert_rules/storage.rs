@@ -0,0 +1,86 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+use crate::alert_rules::types::{AlertRule, AlertRuleError};
+
+/// Alert rule storage trait
+#[async_trait]
+pub trait AlertRuleStorage: Send + Sync {
+    /// Get a rule of a function
+    async fn get_rule(
+        &self,
+        function_id: &str,
+        id: &str,
+    ) -> Result<Option<AlertRule>, AlertRuleError>;
+
+    /// Create or update a rule
+    async fn put_rule(&self, rule: AlertRule) -> Result<(), AlertRuleError>;
+
+    /// Delete a rule of a function
+    async fn delete_rule(&self, function_id: &str, id: &str) -> Result<(), AlertRuleError>;
+
+    /// List the rules of a function, oldest first
+    async fn list_rules(&self, function_id: &str) -> Result<Vec<AlertRule>, AlertRuleError>;
+
+    /// List the rules of every function
+    async fn list_all_rules(&self) -> Result<Vec<AlertRule>, AlertRuleError>;
+}
+
+/// In-memory implementation of the alert rule storage
+#[derive(Default)]
+pub struct MemoryAlertRuleStorage {
+    /// Rules by function and ID
+    rules: RwLock<HashMap<(String, String), AlertRule>>,
+}
+
+impl MemoryAlertRuleStorage {
+    /// Create a new memory-based alert rule storage
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl AlertRuleStorage for MemoryAlertRuleStorage {
+    async fn get_rule(
+        &self,
+        function_id: &str,
+        id: &str,
+    ) -> Result<Option<AlertRule>, AlertRuleError> {
+        let rules = self.rules.read().unwrap();
+        Ok(rules
+            .get(&(function_id.to_string(), id.to_string()))
+            .cloned())
+    }
+
+    async fn put_rule(&self, rule: AlertRule) -> Result<(), AlertRuleError> {
+        let mut rules = self.rules.write().unwrap();
+        rules.insert((rule.function_id.clone(), rule.id.clone()), rule);
+        Ok(())
+    }
+
+    async fn delete_rule(&self, function_id: &str, id: &str) -> Result<(), AlertRuleError> {
+        let mut rules = self.rules.write().unwrap();
+        rules.remove(&(function_id.to_string(), id.to_string()));
+        Ok(())
+    }
+
+    async fn list_rules(&self, function_id: &str) -> Result<Vec<AlertRule>, AlertRuleError> {
+        let rules = self.rules.read().unwrap();
+        let mut rules: Vec<_> = rules
+            .values()
+            .filter(|rule| rule.function_id == function_id)
+            .cloned()
+            .collect();
+        rules.sort_by_key(|rule| rule.created_at);
+        Ok(rules)
+    }
+
+    async fn list_all_rules(&self) -> Result<Vec<AlertRule>, AlertRuleError> {
+        Ok(self.rules.read().unwrap().values().cloned().collect())
+    }
+}
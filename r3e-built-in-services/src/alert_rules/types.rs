@@ -0,0 +1,224 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+use r3e_store::AnalyticsError;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::alerts::AlertSeverity;
+
+/// Longest window of a rule, in minutes
+pub const MAX_WINDOW_MINUTES: u32 = 24 * 60;
+
+/// Alert rule error
+#[derive(Debug, Error)]
+pub enum AlertRuleError {
+    #[error("Storage error: {0}")]
+    Storage(String),
+
+    #[error("Not found: {0}")]
+    NotFound(String),
+
+    #[error("Invalid input: {0}")]
+    InvalidInput(String),
+
+    #[error("{0}")]
+    Analytics(#[from] AnalyticsError),
+}
+
+fn default_min_invocations() -> u64 {
+    1
+}
+
+/// Metric condition of a rule, firing when the metric exceeds the threshold
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "metric", rename_all = "snake_case")]
+pub enum RuleCondition {
+    /// Share of failed invocations over a window
+    ErrorRate {
+        /// Share of failed invocations, between 0 and 1
+        threshold: f64,
+
+        /// Window the rate is worked out over, in minutes
+        window_minutes: u32,
+
+        /// Fewest invocations in the window for the rate to be checked
+        #[serde(default = "default_min_invocations")]
+        min_invocations: u64,
+    },
+
+    /// 95th percentile execution time over a window
+    P95Latency {
+        /// Execution time in milliseconds
+        threshold_ms: u64,
+
+        /// Window the percentile is worked out over, in minutes
+        window_minutes: u32,
+    },
+
+    /// Cost of the invocations of the current UTC day
+    DailyCost {
+        /// Cost in GAS
+        threshold_gas: f64,
+    },
+}
+
+impl RuleCondition {
+    /// Name of the metric, in alert keys
+    pub fn metric(&self) -> &'static str {
+        match self {
+            RuleCondition::ErrorRate { .. } => "error_rate",
+            RuleCondition::P95Latency { .. } => "p95_latency",
+            RuleCondition::DailyCost { .. } => "daily_cost",
+        }
+    }
+
+    /// Check the condition is well-formed
+    pub fn validate(&self) -> Result<(), AlertRuleError> {
+        let check_window = |window_minutes: u32| {
+            if window_minutes == 0 || window_minutes > MAX_WINDOW_MINUTES {
+                return Err(AlertRuleError::InvalidInput(format!(
+                    "Window must be between 1 and {} minutes",
+                    MAX_WINDOW_MINUTES
+                )));
+            }
+            Ok(())
+        };
+
+        match self {
+            RuleCondition::ErrorRate {
+                threshold,
+                window_minutes,
+                ..
+            } => {
+                if !(0.0..1.0).contains(threshold) {
+                    return Err(AlertRuleError::InvalidInput(format!(
+                        "Error rate threshold must be at least 0 and below 1: {}",
+                        threshold
+                    )));
+                }
+                check_window(*window_minutes)
+            }
+            RuleCondition::P95Latency {
+                threshold_ms,
+                window_minutes,
+            } => {
+                if *threshold_ms == 0 {
+                    return Err(AlertRuleError::InvalidInput(
+                        "Latency threshold must be positive".to_string(),
+                    ));
+                }
+                check_window(*window_minutes)
+            }
+            RuleCondition::DailyCost { threshold_gas } => {
+                if !threshold_gas.is_finite() || *threshold_gas <= 0.0 {
+                    return Err(AlertRuleError::InvalidInput(format!(
+                        "Cost threshold must be a positive amount of GAS: {}",
+                        threshold_gas
+                    )));
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Whether the condition of a rule held when it was last evaluated
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RuleState {
+    /// Below the threshold, or not evaluated yet
+    #[default]
+    Ok,
+
+    /// Above the threshold
+    Firing,
+}
+
+/// Alert rule over the metrics of a function
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AlertRule {
+    /// Rule ID
+    pub id: String,
+
+    /// Function the rule watches
+    pub function_id: String,
+
+    /// Owner of the function, whose notification channels are alerted
+    pub owner: String,
+
+    /// Display name
+    pub name: String,
+
+    /// Condition the rule fires on
+    pub condition: RuleCondition,
+
+    /// Severity of the alerts raised
+    pub severity: AlertSeverity,
+
+    /// Whether the rule is evaluated
+    pub enabled: bool,
+
+    /// State when the rule was last evaluated
+    #[serde(default)]
+    pub state: RuleState,
+
+    /// When the rule last changed state
+    #[serde(default)]
+    pub state_changed_at: Option<u64>,
+
+    /// Creation timestamp
+    pub created_at: u64,
+
+    /// Last update timestamp
+    pub updated_at: u64,
+}
+
+impl AlertRule {
+    /// Key of the alerts of the rule, e.g. `function.error_rate:<rule ID>`
+    pub fn alert_key(&self) -> String {
+        format!("function.{}:{}", self.condition.metric(), self.id)
+    }
+}
+
+/// Rule settings set by the owner of the function
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AlertRuleRequest {
+    /// Display name
+    pub name: String,
+
+    /// Condition the rule fires on
+    pub condition: RuleCondition,
+
+    /// Severity of the alerts raised
+    #[serde(default = "default_severity")]
+    pub severity: AlertSeverity,
+
+    /// Whether the rule is evaluated
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+}
+
+fn default_severity() -> AlertSeverity {
+    AlertSeverity::Warning
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+/// Value of the metric of a rule at an evaluation
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RuleEvaluation {
+    /// Rule ID
+    pub rule_id: String,
+
+    /// Value of the metric, `None` without enough invocations to work it out
+    pub value: Option<f64>,
+
+    /// Whether the value exceeds the threshold
+    pub firing: bool,
+
+    /// Evaluation timestamp
+    pub evaluated_at: u64,
+}
@@ -0,0 +1,203 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+//! Operator alerts.
+//!
+//! Services raise alerts through the [`AlertManager`], which forwards them to its sinks.
+//! Alerts are keyed, and an alert raised again within the cooldown of its key is dropped,
+//! so a condition checked on every invocation does not flood the sinks.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+/// Alert severity
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AlertSeverity {
+    /// Informational
+    Info,
+
+    /// Needs attention soon
+    Warning,
+
+    /// Needs attention now
+    Critical,
+}
+
+/// Alert raised by a service
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Alert {
+    /// Key of the condition, alerts with the same key are rate limited together
+    pub key: String,
+
+    /// Severity
+    pub severity: AlertSeverity,
+
+    /// Tenant the alert is about, if any
+    pub tenant: Option<String>,
+
+    /// Message
+    pub message: String,
+
+    /// Timestamp
+    pub timestamp: u64,
+}
+
+impl Alert {
+    /// Create a new alert
+    pub fn new(
+        key: impl Into<String>,
+        severity: AlertSeverity,
+        message: impl Into<String>,
+    ) -> Self {
+        Self {
+            key: key.into(),
+            severity,
+            tenant: None,
+            message: message.into(),
+            timestamp: chrono::Utc::now().timestamp() as u64,
+        }
+    }
+
+    /// Alert about a tenant
+    pub fn with_tenant(mut self, tenant: impl Into<String>) -> Self {
+        self.tenant = Some(tenant.into());
+        self
+    }
+}
+
+/// Destination of alerts
+#[async_trait]
+pub trait AlertSink: Send + Sync {
+    /// Deliver an alert
+    async fn send(&self, alert: &Alert) -> Result<(), String>;
+}
+
+/// Sink writing alerts to the log
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LogAlertSink;
+
+#[async_trait]
+impl AlertSink for LogAlertSink {
+    async fn send(&self, alert: &Alert) -> Result<(), String> {
+        let tenant = alert.tenant.as_deref().unwrap_or("-");
+        match alert.severity {
+            AlertSeverity::Info => {
+                log::info!("alert: [{}] {}: {}", alert.key, tenant, alert.message)
+            }
+            AlertSeverity::Warning => {
+                log::warn!("alert: [{}] {}: {}", alert.key, tenant, alert.message)
+            }
+            AlertSeverity::Critical => {
+                log::error!("alert: [{}] {}: {}", alert.key, tenant, alert.message)
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Sink forwarding alerts to the API, which notifies the channels they are routed to
+pub struct ApiAlertSink {
+    client: reqwest::Client,
+    url: String,
+    token: String,
+}
+
+impl ApiAlertSink {
+    /// Create a new sink posting to the admin alerts endpoint of the API at `base_url`
+    pub fn new(base_url: &str, token: impl Into<String>) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            url: format!("{}/admin/alerts", base_url.trim_end_matches('/')),
+            token: token.into(),
+        }
+    }
+
+    /// Post with the given client, e.g. one presenting a client certificate
+    pub fn with_client(mut self, client: reqwest::Client) -> Self {
+        self.client = client;
+        self
+    }
+}
+
+#[async_trait]
+impl AlertSink for ApiAlertSink {
+    async fn send(&self, alert: &Alert) -> Result<(), String> {
+        let response = self
+            .client
+            .post(&self.url)
+            .bearer_auth(&self.token)
+            .json(alert)
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+        if !response.status().is_success() {
+            return Err(format!("API responded {}", response.status()));
+        }
+        Ok(())
+    }
+}
+
+/// Rate-limits alerts per key and forwards them to the sinks
+pub struct AlertManager {
+    /// Sinks alerts are delivered to
+    sinks: Vec<Arc<dyn AlertSink>>,
+
+    /// How long an alert key stays quiet after an alert
+    cooldown: Duration,
+
+    /// When each key last alerted
+    last_sent: Mutex<HashMap<String, u64>>,
+}
+
+impl AlertManager {
+    /// Create a new alert manager without sinks
+    pub fn new(cooldown: Duration) -> Self {
+        Self {
+            sinks: Vec::new(),
+            cooldown,
+            last_sent: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Deliver alerts to a sink as well
+    pub fn with_sink(mut self, sink: Arc<dyn AlertSink>) -> Self {
+        self.sinks.push(sink);
+        self
+    }
+
+    /// Raise an alert, returning whether it was delivered or dropped within the cooldown
+    pub async fn raise(&self, alert: Alert) -> bool {
+        {
+            let mut last_sent = self.last_sent.lock().unwrap();
+            if let Some(sent_at) = last_sent.get(&alert.key) {
+                if alert.timestamp < sent_at.saturating_add(self.cooldown.as_secs()) {
+                    return false;
+                }
+            }
+            last_sent.insert(alert.key.clone(), alert.timestamp);
+        }
+
+        for sink in &self.sinks {
+            if let Err(err) = sink.send(&alert).await {
+                log::error!("alerts: failed to deliver alert {}: {}", alert.key, err);
+            }
+        }
+        true
+    }
+
+    /// The condition of a key cleared, its next alert is delivered right away
+    pub fn resolve(&self, key: &str) {
+        self.last_sent.lock().unwrap().remove(key);
+    }
+}
+
+impl Default for AlertManager {
+    fn default() -> Self {
+        Self::new(Duration::from_secs(3600)).with_sink(Arc::new(LogAlertSink))
+    }
+}
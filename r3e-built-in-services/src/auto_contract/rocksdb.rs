@@ -107,6 +107,7 @@ impl AutoContractStorage for RocksDBAutoContractStorage {
             start_exclusive: false,
             end_key: &[],
             end_inclusive: false,
+            reverse: false,
             max_count: 1000, // Reasonable limit
         };
 
@@ -139,6 +140,7 @@ impl AutoContractStorage for RocksDBAutoContractStorage {
             start_exclusive: false,
             end_key: &[],
             end_inclusive: false,
+            reverse: false,
             max_count: 1000, // Reasonable limit
         };
 
@@ -218,6 +220,7 @@ impl AutoContractStorage for RocksDBAutoContractStorage {
             start_exclusive: false,
             end_key: &[],
             end_inclusive: false,
+            reverse: false,
             max_count: 1000, // Reasonable limit
         };
 
@@ -0,0 +1,318 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::alerts::{Alert, AlertManager, AlertSeverity};
+use crate::balance::storage::BalanceStorage;
+
+/// GAS balance limits invocations are admitted against
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AdmissionConfig {
+    /// Invocations of tenants with less GAS run, but warn and alert that the balance is low
+    pub soft_limit: u64,
+
+    /// Invocations of tenants with less GAS are rejected
+    pub hard_limit: u64,
+}
+
+impl Default for AdmissionConfig {
+    fn default() -> Self {
+        Self {
+            soft_limit: 100_000,
+            // The base cost of an execution
+            hard_limit: 1_000,
+        }
+    }
+}
+
+/// Invocation admitted by the balance check
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AdmissionDecision {
+    /// The balance is above the soft limit
+    Admitted,
+
+    /// The balance is below the soft limit, but above the hard limit
+    LowBalance {
+        /// GAS balance of the tenant
+        balance: u64,
+    },
+}
+
+/// Invocation rejected by the balance check
+#[derive(Debug, Error)]
+pub enum AdmissionError {
+    /// The balance is below the hard limit; the tenant must deposit GAS
+    #[error("payment required: GAS balance {balance} of {tenant} is below {required}")]
+    PaymentRequired {
+        /// Tenant
+        tenant: String,
+
+        /// GAS balance of the tenant
+        balance: u64,
+
+        /// Balance needed to invoke functions
+        required: u64,
+    },
+
+    /// The balance could not be read
+    #[error("balance unavailable: {0}")]
+    Balance(String),
+}
+
+/// Admits invocations of tenants by their GAS balance, alerting when tenants run low
+pub struct BalanceAdmission {
+    /// Balances of the tenants, the ones deposits are credited to
+    balances: Arc<dyn BalanceStorage>,
+
+    /// Balance limits
+    config: AdmissionConfig,
+
+    /// Alerts raised when tenants approach or reach the limits
+    alerts: Option<Arc<AlertManager>>,
+}
+
+impl BalanceAdmission {
+    /// Create a new balance admission check over the tenants' balances
+    pub fn new(balances: Arc<dyn BalanceStorage>, config: AdmissionConfig) -> Self {
+        Self {
+            balances,
+            config,
+            alerts: None,
+        }
+    }
+
+    /// Raise alerts through the alert manager
+    pub fn with_alerts(mut self, alerts: Arc<AlertManager>) -> Self {
+        self.alerts = Some(alerts);
+        self
+    }
+
+    /// Admit an invocation of a tenant
+    pub async fn admit(&self, tenant: &str) -> Result<AdmissionDecision, AdmissionError> {
+        // Tenants that never deposited have no balance
+        let balance = self
+            .balances
+            .get_balance(tenant)
+            .await
+            .map_err(AdmissionError::Balance)?
+            .map_or(0, |balance| balance.gas_balance);
+
+        let low_key = format!("balance.low:{}", tenant);
+        let exhausted_key = format!("balance.exhausted:{}", tenant);
+
+        if balance < self.config.hard_limit {
+            self.alert(
+                Alert::new(
+                    exhausted_key,
+                    AlertSeverity::Critical,
+                    format!(
+                        "GAS balance {} is below the hard limit {}, invocations are rejected",
+                        balance, self.config.hard_limit
+                    ),
+                )
+                .with_tenant(tenant),
+            )
+            .await;
+            return Err(AdmissionError::PaymentRequired {
+                tenant: tenant.to_string(),
+                balance,
+                required: self.config.hard_limit,
+            });
+        }
+
+        // Alert again as soon as the tenant runs out after topping up
+        self.resolve(&exhausted_key);
+        if balance < self.config.soft_limit {
+            self.alert(
+                Alert::new(
+                    low_key,
+                    AlertSeverity::Warning,
+                    format!(
+                        "GAS balance {} is below the soft limit {}",
+                        balance, self.config.soft_limit
+                    ),
+                )
+                .with_tenant(tenant),
+            )
+            .await;
+            return Ok(AdmissionDecision::LowBalance { balance });
+        }
+
+        self.resolve(&low_key);
+        Ok(AdmissionDecision::Admitted)
+    }
+
+    async fn alert(&self, alert: Alert) {
+        if let Some(alerts) = &self.alerts {
+            alerts.raise(alert).await;
+        }
+    }
+
+    fn resolve(&self, key: &str) {
+        if let Some(alerts) = &self.alerts {
+            alerts.resolve(key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alerts::AlertSink;
+    use crate::balance::storage::MemoryBalanceStorage;
+    use crate::balance::types::UserBalance;
+    use async_trait::async_trait;
+    use std::sync::Mutex;
+    use std::time::Duration;
+
+    /// Sink keeping the alerts delivered to it
+    #[derive(Default)]
+    struct RecordingSink {
+        alerts: Mutex<Vec<Alert>>,
+    }
+
+    impl RecordingSink {
+        fn take(&self) -> Vec<(String, AlertSeverity)> {
+            self.alerts
+                .lock()
+                .unwrap()
+                .drain(..)
+                .map(|alert| (alert.key, alert.severity))
+                .collect()
+        }
+    }
+
+    #[async_trait]
+    impl AlertSink for RecordingSink {
+        async fn send(&self, alert: &Alert) -> Result<(), String> {
+            self.alerts.lock().unwrap().push(alert.clone());
+            Ok(())
+        }
+    }
+
+    fn admission() -> (
+        BalanceAdmission,
+        Arc<MemoryBalanceStorage>,
+        Arc<RecordingSink>,
+    ) {
+        let storage = Arc::new(MemoryBalanceStorage::new());
+        let sink = Arc::new(RecordingSink::default());
+        let alerts = AlertManager::new(Duration::from_secs(3600)).with_sink(sink.clone());
+        let config = AdmissionConfig {
+            soft_limit: 100_000,
+            hard_limit: 1_000,
+        };
+        let admission =
+            BalanceAdmission::new(storage.clone(), config).with_alerts(Arc::new(alerts));
+        (admission, storage, sink)
+    }
+
+    async fn set_balance(storage: &MemoryBalanceStorage, tenant: &str, gas_balance: u64) {
+        storage
+            .update_balance(UserBalance {
+                user_id: tenant.to_string(),
+                neo_balance: 0,
+                gas_balance,
+                updated_at: 0,
+            })
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_limits() {
+        let (admission, storage, _) = admission();
+
+        // Tenants that never deposited cannot invoke
+        match admission.admit("alice").await {
+            Err(AdmissionError::PaymentRequired {
+                tenant,
+                balance,
+                required,
+            }) => assert_eq!((tenant.as_str(), balance, required), ("alice", 0, 1_000)),
+            other => panic!("unexpected admission {:?}", other),
+        }
+
+        set_balance(&storage, "alice", 999).await;
+        assert!(matches!(
+            admission.admit("alice").await,
+            Err(AdmissionError::PaymentRequired { balance: 999, .. })
+        ));
+
+        // Between the limits invocations run with a low balance
+        for balance in [1_000, 99_999] {
+            set_balance(&storage, "alice", balance).await;
+            assert_eq!(
+                admission.admit("alice").await.unwrap(),
+                AdmissionDecision::LowBalance { balance }
+            );
+        }
+
+        set_balance(&storage, "alice", 100_000).await;
+        assert_eq!(
+            admission.admit("alice").await.unwrap(),
+            AdmissionDecision::Admitted
+        );
+    }
+
+    #[tokio::test]
+    async fn test_alerts() {
+        let (admission, storage, sink) = admission();
+
+        set_balance(&storage, "alice", 500).await;
+        admission.admit("alice").await.unwrap_err();
+        assert_eq!(
+            sink.take(),
+            [(
+                "balance.exhausted:alice".to_string(),
+                AlertSeverity::Critical
+            )]
+        );
+
+        // Every rejected invocation does not alert again
+        admission.admit("alice").await.unwrap_err();
+        assert!(sink.take().is_empty());
+
+        set_balance(&storage, "alice", 50_000).await;
+        admission.admit("alice").await.unwrap();
+        admission.admit("alice").await.unwrap();
+        assert_eq!(
+            sink.take(),
+            [("balance.low:alice".to_string(), AlertSeverity::Warning)]
+        );
+
+        // Running out again after topping up alerts right away
+        set_balance(&storage, "alice", 500).await;
+        admission.admit("alice").await.unwrap_err();
+        assert_eq!(
+            sink.take(),
+            [(
+                "balance.exhausted:alice".to_string(),
+                AlertSeverity::Critical
+            )]
+        );
+
+        // So does running low again after a deposit above the soft limit
+        set_balance(&storage, "alice", 200_000).await;
+        admission.admit("alice").await.unwrap();
+        assert!(sink.take().is_empty());
+        set_balance(&storage, "alice", 50_000).await;
+        admission.admit("alice").await.unwrap();
+        assert_eq!(
+            sink.take(),
+            [("balance.low:alice".to_string(), AlertSeverity::Warning)]
+        );
+
+        // Alerts are per tenant
+        admission.admit("bob").await.unwrap_err();
+        assert_eq!(
+            sink.take(),
+            [("balance.exhausted:bob".to_string(), AlertSeverity::Critical)]
+        );
+    }
+}
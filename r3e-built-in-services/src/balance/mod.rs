@@ -1,11 +1,13 @@
 // Copyright @ 2023 - 2024, R3E Network
 // All Rights Reserved
 
+pub mod admission;
 pub mod rocksdb;
 pub mod service;
 pub mod storage;
 pub mod types;
 
+pub use admission::*;
 pub use rocksdb::RocksDBBalanceStorage;
 pub use service::*;
 pub use storage::*;
@@ -48,6 +48,45 @@ impl BalanceStorage for RocksDBBalanceStorage {
         }
     }
 
+    async fn list_balances(&self) -> Result<Vec<UserBalance>, String> {
+        let mut balances = Vec::new();
+        let mut start_key = Vec::new();
+        let mut start_exclusive = false;
+
+        loop {
+            let input = r3e_store::ScanInput {
+                start_key: &start_key,
+                start_exclusive,
+                end_key: &[],
+                end_inclusive: false,
+                reverse: false,
+                max_count: 1000,
+            };
+
+            let output = self
+                .db
+                .scan(&self.balances_cf, input)
+                .map_err(|e| format!("Failed to scan balances: {}", e))?;
+
+            for (_, value) in &output.kvs {
+                balances.push(
+                    serde_json::from_slice::<UserBalance>(value)
+                        .map_err(|e| format!("Failed to deserialize balance: {}", e))?,
+                );
+            }
+
+            match output.kvs.last() {
+                Some((key, _)) if output.has_more => {
+                    start_key = key.clone();
+                    start_exclusive = true;
+                }
+                _ => break,
+            }
+        }
+
+        Ok(balances)
+    }
+
     async fn update_balance(&self, balance: UserBalance) -> Result<(), String> {
         let key = balance.user_id.as_bytes();
         let value = serde_json::to_vec(&balance)
@@ -86,6 +125,7 @@ impl BalanceStorage for RocksDBBalanceStorage {
             start_exclusive: false,
             end_key: &[],
             end_inclusive: false,
+            reverse: false,
             max_count: 1000, // Reasonable limit
         };
 
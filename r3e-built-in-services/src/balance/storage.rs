@@ -10,6 +10,9 @@ pub trait BalanceStorage: Send + Sync {
     /// Get user balance
     async fn get_balance(&self, user_id: &str) -> Result<Option<UserBalance>, String>;
 
+    /// List the balances of every user
+    async fn list_balances(&self) -> Result<Vec<UserBalance>, String>;
+
     /// Update user balance
     async fn update_balance(&self, balance: UserBalance) -> Result<(), String>;
 
@@ -46,6 +49,11 @@ impl BalanceStorage for MemoryBalanceStorage {
         Ok(balances.get(user_id).cloned())
     }
 
+    async fn list_balances(&self) -> Result<Vec<UserBalance>, String> {
+        let balances = self.balances.lock().await;
+        Ok(balances.values().cloned().collect())
+    }
+
     async fn update_balance(&self, balance: UserBalance) -> Result<(), String> {
         let mut balances = self.balances.lock().await;
         balances.insert(balance.user_id.clone(), balance);
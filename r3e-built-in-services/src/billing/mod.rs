@@ -0,0 +1,14 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+pub mod payment;
+pub mod rocksdb;
+pub mod service;
+pub mod storage;
+pub mod types;
+
+pub use payment::{normalize_address, parse_payments};
+pub use rocksdb::RocksDBBillingStorage;
+pub use service::*;
+pub use storage::*;
+pub use types::*;
@@ -0,0 +1,96 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+//! Detection of invoice payments in chain events.
+//!
+//! Tenants pay by sending GAS (Neo N3) or ETH (Ethereum) to the platform's deposit
+//! addresses. Payments are attributed by sender, so a tenant registers the addresses it
+//! pays from on its billing account.
+
+use r3e_event::source::event::Event;
+use r3e_event::source::token_transfer::{NeoTokenTransfers, TokenStandard};
+use serde_json::Value;
+
+use crate::billing::types::{BillingConfig, DetectedPayment, PaymentAsset};
+
+/// `0x` prefixed lowercase form of an address or script hash
+pub fn normalize_address(address: &str) -> String {
+    let address = address.trim();
+    let address = address
+        .strip_prefix("0x")
+        .or_else(|| address.strip_prefix("0X"))
+        .unwrap_or(address);
+    format!("0x{}", address.to_lowercase())
+}
+
+/// Extract the transfers to the configured deposit addresses from a chain event
+pub fn parse_payments(event: &Event, config: &BillingConfig) -> Vec<DetectedPayment> {
+    match event {
+        Event::NeoTokenTransfers(transfers) => neo_payments(transfers, config),
+        Event::NeoContractNotification(notification) => {
+            neo_payments(&NeoTokenTransfers::decode(notification), config)
+        }
+        Event::EthereumTransaction(tx) => ethereum_payment(tx, config).into_iter().collect(),
+        Event::EthereumBlock(block) => block
+            .get("transactions")
+            .and_then(Value::as_array)
+            .map(|txs| {
+                txs.iter()
+                    .filter_map(|tx| ethereum_payment(tx, config))
+                    .collect()
+            })
+            .unwrap_or_default(),
+        _ => Vec::new(),
+    }
+}
+
+fn neo_payments(transfers: &NeoTokenTransfers, config: &BillingConfig) -> Vec<DetectedPayment> {
+    let Some(deposit_address) = config.neo_deposit_address.as_deref().map(normalize_address) else {
+        return Vec::new();
+    };
+    let gas_token = normalize_address(&config.gas_token);
+
+    transfers
+        .transfers
+        .iter()
+        .filter(|transfer| {
+            transfer.standard == TokenStandard::Nep17
+                && transfer.token == gas_token
+                && transfer.to.as_deref() == Some(deposit_address.as_str())
+                && transfer.amount_value() > 0
+        })
+        .filter_map(|transfer| {
+            // Minted GAS has no sender to attribute the payment to
+            Some(DetectedPayment {
+                asset: PaymentAsset::Gas,
+                tx_hash: transfers.tx_hash.clone(),
+                index: transfer.notification_index,
+                from: transfer.from.clone()?,
+                amount: transfer.amount.clone(),
+            })
+        })
+        .collect()
+}
+
+/// Decode a plain ETH transfer from a transaction object as returned by the JSON-RPC API
+fn ethereum_payment(tx: &Value, config: &BillingConfig) -> Option<DetectedPayment> {
+    let deposit_address = normalize_address(config.eth_deposit_address.as_deref()?);
+    let field = |name: &str| tx.get(name).and_then(Value::as_str);
+
+    if normalize_address(field("to")?) != deposit_address {
+        return None;
+    }
+
+    let value = u128::from_str_radix(field("value")?.trim_start_matches("0x"), 16).ok()?;
+    if value == 0 {
+        return None;
+    }
+
+    Some(DetectedPayment {
+        asset: PaymentAsset::Eth,
+        tx_hash: field("hash")?.to_string(),
+        index: 0,
+        from: normalize_address(field("from")?),
+        amount: value.to_string(),
+    })
+}
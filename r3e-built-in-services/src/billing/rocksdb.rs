@@ -0,0 +1,181 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+use async_trait::async_trait;
+use r3e_store::rocksdb::RocksDBStore;
+use r3e_store::{KvStore, SortedKvStore};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::billing::storage::BillingStorage;
+use crate::billing::types::{BillingAccount, BillingError, Invoice, InvoicePayment};
+
+/// RocksDB implementation of BillingStorage
+pub struct RocksDBBillingStorage {
+    db: Arc<RocksDBStore>,
+    accounts_cf: String,
+    payers_cf: String,
+    invoices_cf: String,
+    payments_cf: String,
+}
+
+impl RocksDBBillingStorage {
+    /// Create a new RocksDB billing storage
+    pub fn new<P: AsRef<Path>>(db_path: P) -> Result<Self, BillingError> {
+        let db = RocksDBStore::new(db_path)
+            .map_err(|e| BillingError::Storage(format!("Failed to create RocksDB store: {}", e)))?;
+
+        Ok(Self {
+            db: Arc::new(db),
+            accounts_cf: "billing_accounts".to_string(),
+            payers_cf: "billing_payers".to_string(),
+            invoices_cf: "billing_invoices".to_string(),
+            payments_cf: "billing_payments".to_string(),
+        })
+    }
+
+    fn get_json<T: DeserializeOwned>(
+        &self,
+        cf: &str,
+        key: &str,
+    ) -> Result<Option<T>, BillingError> {
+        match self.db.get(cf, key.as_bytes()) {
+            Ok(value) => serde_json::from_slice(&value)
+                .map(Some)
+                .map_err(|e| BillingError::Storage(format!("Failed to deserialize {}: {}", cf, e))),
+            Err(r3e_store::GetError::NoSuchKey) => Ok(None),
+            Err(e) => Err(BillingError::Storage(format!(
+                "Failed to get {}: {}",
+                cf, e
+            ))),
+        }
+    }
+
+    fn put_json<T: Serialize>(&self, cf: &str, key: &str, value: &T) -> Result<(), BillingError> {
+        let value = serde_json::to_vec(value)
+            .map_err(|e| BillingError::Storage(format!("Failed to serialize {}: {}", cf, e)))?;
+
+        let input = r3e_store::PutInput {
+            key: key.as_bytes(),
+            value: &value,
+            if_not_exists: false,
+        };
+
+        self.db
+            .put(cf, input)
+            .map_err(|e| BillingError::Storage(format!("Failed to store {}: {}", cf, e)))
+    }
+
+    /// Scan the values with keys in `[start, end)`, an empty `end` scanning to the end
+    fn scan_json<T: DeserializeOwned>(
+        &self,
+        cf: &str,
+        start: &[u8],
+        end: &[u8],
+    ) -> Result<Vec<T>, BillingError> {
+        let mut values = Vec::new();
+        let mut start_key = start.to_vec();
+        let mut start_exclusive = false;
+
+        loop {
+            let input = r3e_store::ScanInput {
+                start_key: &start_key,
+                start_exclusive,
+                end_key: end,
+                end_inclusive: false,
+                reverse: false,
+                max_count: 1000,
+            };
+
+            let output = self
+                .db
+                .scan(cf, input)
+                .map_err(|e| BillingError::Storage(format!("Failed to scan {}: {}", cf, e)))?;
+
+            for (_, value) in &output.kvs {
+                values.push(serde_json::from_slice(value).map_err(|e| {
+                    BillingError::Storage(format!("Failed to deserialize {}: {}", cf, e))
+                })?);
+            }
+
+            match output.kvs.last() {
+                Some((key, _)) if output.has_more => {
+                    start_key = key.clone();
+                    start_exclusive = true;
+                }
+                _ => break,
+            }
+        }
+
+        Ok(values)
+    }
+}
+
+#[async_trait]
+impl BillingStorage for RocksDBBillingStorage {
+    async fn get_account(&self, tenant: &str) -> Result<Option<BillingAccount>, BillingError> {
+        self.get_json(&self.accounts_cf, tenant)
+    }
+
+    async fn put_account(&self, account: BillingAccount) -> Result<(), BillingError> {
+        // Index the payers first, so payments are attributed as soon as the account lists them
+        for payer in &account.payers {
+            let input = r3e_store::PutInput {
+                key: payer.as_bytes(),
+                value: account.tenant.as_bytes(),
+                if_not_exists: false,
+            };
+            self.db
+                .put(&self.payers_cf, input)
+                .map_err(|e| BillingError::Storage(format!("Failed to store payer: {}", e)))?;
+        }
+
+        self.put_json(&self.accounts_cf, &account.tenant, &account)
+    }
+
+    async fn list_accounts(&self) -> Result<Vec<BillingAccount>, BillingError> {
+        self.scan_json(&self.accounts_cf, &[], &[])
+    }
+
+    async fn find_tenant_by_payer(&self, address: &str) -> Result<Option<String>, BillingError> {
+        match self.db.get(&self.payers_cf, address.as_bytes()) {
+            Ok(value) => String::from_utf8(value)
+                .map(Some)
+                .map_err(|e| BillingError::Storage(format!("Invalid tenant of payer: {}", e))),
+            Err(r3e_store::GetError::NoSuchKey) => Ok(None),
+            Err(e) => Err(BillingError::Storage(format!("Failed to get payer: {}", e))),
+        }
+    }
+
+    async fn get_invoice(&self, invoice_id: &str) -> Result<Option<Invoice>, BillingError> {
+        self.get_json(&self.invoices_cf, invoice_id)
+    }
+
+    async fn put_invoice(&self, invoice: Invoice) -> Result<(), BillingError> {
+        self.put_json(&self.invoices_cf, &invoice.id, &invoice)
+    }
+
+    async fn list_invoices(&self, tenant: &str) -> Result<Vec<Invoice>, BillingError> {
+        // Invoice IDs are `<tenant>-<period>`, so the tenant's invoices sort between
+        // `<tenant>-` and `<tenant>.`
+        let start = format!("{}-", tenant);
+        let end = format!("{}.", tenant);
+        let mut invoices: Vec<Invoice> =
+            self.scan_json(&self.invoices_cf, start.as_bytes(), end.as_bytes())?;
+
+        // Another tenant's ID may start with this one
+        invoices.retain(|invoice| invoice.tenant == tenant);
+        invoices.sort_by_key(|invoice| invoice.period_start);
+        Ok(invoices)
+    }
+
+    async fn get_payment(&self, payment_id: &str) -> Result<Option<InvoicePayment>, BillingError> {
+        self.get_json(&self.payments_cf, payment_id)
+    }
+
+    async fn put_payment(&self, payment: InvoicePayment) -> Result<(), BillingError> {
+        self.put_json(&self.payments_cf, &payment.id, &payment)
+    }
+}
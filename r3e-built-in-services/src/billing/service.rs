@@ -0,0 +1,872 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+use async_trait::async_trait;
+use chrono::{Datelike, TimeZone, Utc};
+use r3e_event::source::event::Event;
+use r3e_event::source::{TaskError, TaskSource};
+use r3e_store::LeaseHolder;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::billing::payment::{normalize_address, parse_payments};
+use crate::billing::storage::BillingStorage;
+use crate::billing::types::{
+    AccountStanding, BillingAccount, BillingConfig, BillingError, DetectedPayment, Invoice,
+    InvoicePayment, InvoiceStatus, PaymentAllocation, PaymentAsset, GAS_FRACTIONS,
+};
+use crate::pricing::{PricingError, PricingServiceTrait};
+
+/// Billing service trait
+#[async_trait]
+pub trait BillingServiceTrait: Send + Sync {
+    /// Get the billing account of a tenant
+    async fn get_account(&self, tenant: &str) -> Result<BillingAccount, BillingError>;
+
+    /// Register an address the tenant pays from
+    async fn add_payer(&self, tenant: &str, address: &str) -> Result<BillingAccount, BillingError>;
+
+    /// List the invoices of a tenant, oldest period first
+    async fn list_invoices(&self, tenant: &str) -> Result<Vec<Invoice>, BillingError>;
+
+    /// Get an invoice by ID
+    async fn get_invoice(&self, invoice_id: &str) -> Result<Invoice, BillingError>;
+
+    /// Invoice the tenant for the billing period before `now`, `None` if it was already invoiced
+    async fn invoice_tenant(&self, tenant: &str, now: u64)
+        -> Result<Option<Invoice>, BillingError>;
+
+    /// Invoice every account for the billing period before `now` and update their standing
+    async fn run_billing_cycle(&self, now: u64) -> Result<Vec<Invoice>, BillingError>;
+
+    /// Credit a detected payment to the invoices of its sender, `None` if it was already recorded
+    async fn record_payment(
+        &self,
+        payment: DetectedPayment,
+    ) -> Result<Option<InvoicePayment>, BillingError>;
+
+    /// Fail if the tenant is suspended for overdue invoices
+    async fn check_execution(&self, tenant: &str) -> Result<(), BillingError>;
+}
+
+/// Billing service implementation
+///
+/// Invoices the usage recorded by the pricing service once a month, credits on-chain
+/// payments to the oldest open invoices first and suspends tenants whose invoices stay
+/// overdue past the grace period.
+pub struct BillingService {
+    /// Storage
+    storage: Arc<dyn BillingStorage>,
+
+    /// Pricing service the usage is billed from
+    pricing: Arc<dyn PricingServiceTrait>,
+
+    /// Configuration
+    config: BillingConfig,
+
+    /// Serializes read-modify-write of accounts and invoices
+    lock: tokio::sync::Mutex<()>,
+}
+
+/// Billing period preceding the one `now` falls in, as `(period, start, end)`
+pub fn previous_period(now: u64) -> (String, u64, u64) {
+    let now = Utc
+        .timestamp_opt(now as i64, 0)
+        .single()
+        .unwrap_or_else(Utc::now);
+    let (year, month) = match now.month() {
+        1 => (now.year() - 1, 12),
+        month => (now.year(), month - 1),
+    };
+    let month_start = |year: i32, month: u32| {
+        Utc.with_ymd_and_hms(year, month, 1, 0, 0, 0)
+            .single()
+            .map(|start| start.timestamp() as u64)
+            .unwrap_or_default()
+    };
+
+    (
+        format!("{:04}-{:02}", year, month),
+        month_start(year, month),
+        month_start(now.year(), now.month()),
+    )
+}
+
+impl BillingService {
+    /// Create a new billing service
+    pub fn new(
+        storage: Arc<dyn BillingStorage>,
+        pricing: Arc<dyn PricingServiceTrait>,
+        config: BillingConfig,
+    ) -> Self {
+        Self {
+            storage,
+            pricing,
+            config,
+            lock: tokio::sync::Mutex::new(()),
+        }
+    }
+
+    /// Get current timestamp
+    fn get_current_timestamp(&self) -> u64 {
+        chrono::Utc::now().timestamp() as u64
+    }
+
+    async fn account(&self, tenant: &str, now: u64) -> Result<BillingAccount, BillingError> {
+        Ok(self
+            .storage
+            .get_account(tenant)
+            .await?
+            .unwrap_or_else(|| BillingAccount::new(tenant, now)))
+    }
+
+    /// Value of a payment in GAS fractions
+    fn credited_value(&self, payment: &DetectedPayment) -> u64 {
+        let amount: u128 = payment.amount.parse().unwrap_or_default();
+        match payment.asset {
+            // GAS has 8 decimals, its smallest unit is a GAS fraction
+            PaymentAsset::Gas => u64::try_from(amount).unwrap_or(u64::MAX),
+            PaymentAsset::Eth => {
+                if self.config.eth_gas_rate <= 0.0 {
+                    log::warn!(
+                        "billing: no ETH rate configured, payment {} credits nothing",
+                        payment.payment_key()
+                    );
+                    return 0;
+                }
+                let gas = amount as f64 / 1e18 * self.config.eth_gas_rate;
+                (gas * GAS_FRACTIONS as f64).floor() as u64
+            }
+        }
+    }
+
+    /// Store an invoice, marking its pricing billing record as paid once it is settled
+    async fn save_invoice(&self, invoice: &Invoice, was_paid: bool) -> Result<(), BillingError> {
+        self.storage.put_invoice(invoice.clone()).await?;
+
+        if invoice.status == InvoiceStatus::Paid && !was_paid {
+            log::info!("billing: invoice {} paid", invoice.id);
+            if let Some(record_id) = &invoice.billing_record_id {
+                if let Err(e) = self.pricing.process_payment(record_id, "crypto").await {
+                    log::error!(
+                        "billing: failed to mark billing record {} as paid: {}",
+                        record_id,
+                        e
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Recompute the standing of an account from its invoices and store it
+    async fn refresh_standing(
+        &self,
+        account: &mut BillingAccount,
+        now: u64,
+    ) -> Result<(), BillingError> {
+        let invoices = self.storage.list_invoices(&account.tenant).await?;
+        let overdue_since = invoices
+            .iter()
+            .filter(|invoice| invoice.outstanding() > 0 && invoice.due_at <= now)
+            .map(|invoice| invoice.due_at)
+            .min();
+
+        let standing = match overdue_since {
+            None => AccountStanding::Good,
+            Some(due_at) if now < due_at.saturating_add(self.config.grace_period) => {
+                AccountStanding::Delinquent
+            }
+            Some(_) => AccountStanding::Suspended,
+        };
+
+        if standing != account.standing {
+            log::info!(
+                "billing: account {} {} -> {}",
+                account.tenant,
+                account.standing,
+                standing
+            );
+        }
+        account.standing = standing;
+        account.overdue_since = overdue_since;
+        account.updated_at = now;
+        self.storage.put_account(account.clone()).await
+    }
+
+    async fn invoice_locked(
+        &self,
+        tenant: &str,
+        now: u64,
+    ) -> Result<Option<Invoice>, BillingError> {
+        let (period, period_start, period_end) = previous_period(now);
+        let invoice_id = format!("{}-{}", tenant, period);
+        if self.storage.get_invoice(&invoice_id).await?.is_some() {
+            return Ok(None);
+        }
+
+        // Usage recorded so far belongs to the current period
+        let mut account = self.account(tenant, now).await?;
+        if account.created_at >= period_end {
+            return Ok(None);
+        }
+
+        let record = match self.pricing.generate_billing_record(tenant).await {
+            Ok(record) => Some(record),
+            Err(PricingError::NotFound(_)) => None,
+            Err(e) => return Err(e.into()),
+        };
+
+        // The usage is invoiced, the next period starts from zero
+        if record.is_some() {
+            let mut profile = self.pricing.get_user_billing_profile(tenant).await?;
+            profile.resource_usage.clear();
+            self.pricing.update_user_billing_profile(profile).await?;
+        }
+
+        let amount = record.as_ref().map_or(0.0, |record| record.amount);
+        let mut invoice = Invoice {
+            id: invoice_id,
+            tenant: tenant.to_string(),
+            period,
+            period_start,
+            period_end,
+            billing_record_id: record.as_ref().map(|record| record.id.clone()),
+            items: record.map(|record| record.items).unwrap_or_default(),
+            amount_due: (amount.max(0.0) * GAS_FRACTIONS as f64).ceil() as u64,
+            amount_paid: 0,
+            status: InvoiceStatus::Open,
+            issued_at: now,
+            due_at: now.saturating_add(self.config.payment_terms),
+            paid_at: None,
+        };
+
+        // Settle from the account credit first; empty invoices are paid right away
+        account.credit = invoice.apply(account.credit, now);
+        if invoice.amount_due == 0 {
+            invoice.status = InvoiceStatus::Paid;
+            invoice.paid_at = Some(now);
+        }
+
+        self.save_invoice(&invoice, false).await?;
+        self.refresh_standing(&mut account, now).await?;
+        log::info!(
+            "billing: issued invoice {} over {} GAS fractions ({})",
+            invoice.id,
+            invoice.amount_due,
+            invoice.status
+        );
+
+        Ok(Some(invoice))
+    }
+
+    /// Record the payments contained in a chain event.
+    ///
+    /// Payments that were already recorded are ignored, so replayed events are harmless.
+    pub async fn handle_event(&self, event: &Event) -> Result<Vec<InvoicePayment>, BillingError> {
+        let mut recorded = Vec::new();
+        for payment in parse_payments(event, &self.config) {
+            if let Some(payment) = self.record_payment(payment).await? {
+                recorded.push(payment);
+            }
+        }
+        Ok(recorded)
+    }
+
+    /// Watch a chain's event source for payments until `stop` is set
+    pub async fn watch(
+        &self,
+        mut source: Box<dyn TaskSource>,
+        uid: u64,
+        poll_interval: Duration,
+        stop: Arc<AtomicBool>,
+    ) {
+        log::info!("billing: watching payments");
+        while !stop.load(Ordering::Relaxed) {
+            match source.acquire_task(uid, 0).await {
+                Ok(task) => {
+                    if let Err(err) = self.handle_event(&task.event).await {
+                        log::error!("billing: failed to handle event: {}", err);
+                    }
+                }
+                Err(TaskError::NoMoreTask(_)) => {
+                    tokio::time::sleep(poll_interval).await;
+                }
+                Err(err) => {
+                    log::error!("billing: failed to acquire event: {}", err);
+                    tokio::time::sleep(poll_interval).await;
+                }
+            }
+        }
+        log::info!("billing: stopped watching payments");
+    }
+}
+
+#[async_trait]
+impl BillingServiceTrait for BillingService {
+    async fn get_account(&self, tenant: &str) -> Result<BillingAccount, BillingError> {
+        self.account(tenant, self.get_current_timestamp()).await
+    }
+
+    async fn add_payer(&self, tenant: &str, address: &str) -> Result<BillingAccount, BillingError> {
+        let address = normalize_address(address);
+        if address.len() <= 2 || !address[2..].chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(BillingError::InvalidInput(format!(
+                "Invalid payer address: {}",
+                address
+            )));
+        }
+
+        let _guard = self.lock.lock().await;
+        match self.storage.find_tenant_by_payer(&address).await? {
+            Some(owner) if owner == tenant => return self.account(tenant, 0).await,
+            Some(_) => {
+                return Err(BillingError::InvalidInput(format!(
+                    "Payer address is registered to another tenant: {}",
+                    address
+                )))
+            }
+            None => {}
+        }
+
+        let now = self.get_current_timestamp();
+        let mut account = self.account(tenant, now).await?;
+        account.payers.push(address);
+        account.updated_at = now;
+        self.storage.put_account(account.clone()).await?;
+
+        Ok(account)
+    }
+
+    async fn list_invoices(&self, tenant: &str) -> Result<Vec<Invoice>, BillingError> {
+        self.storage.list_invoices(tenant).await
+    }
+
+    async fn get_invoice(&self, invoice_id: &str) -> Result<Invoice, BillingError> {
+        self.storage
+            .get_invoice(invoice_id)
+            .await?
+            .ok_or_else(|| BillingError::NotFound(format!("Invoice not found: {}", invoice_id)))
+    }
+
+    async fn invoice_tenant(
+        &self,
+        tenant: &str,
+        now: u64,
+    ) -> Result<Option<Invoice>, BillingError> {
+        let _guard = self.lock.lock().await;
+        self.invoice_locked(tenant, now).await
+    }
+
+    async fn run_billing_cycle(&self, now: u64) -> Result<Vec<Invoice>, BillingError> {
+        let _guard = self.lock.lock().await;
+
+        let mut issued = Vec::new();
+        for mut account in self.storage.list_accounts().await? {
+            // One failing tenant does not hold up the others
+            match self.invoice_locked(&account.tenant, now).await {
+                Ok(Some(invoice)) => issued.push(invoice),
+                Ok(None) => {
+                    if let Err(e) = self.refresh_standing(&mut account, now).await {
+                        log::error!(
+                            "billing: failed to update standing of {}: {}",
+                            account.tenant,
+                            e
+                        );
+                    }
+                }
+                Err(e) => log::error!("billing: failed to invoice {}: {}", account.tenant, e),
+            }
+        }
+
+        Ok(issued)
+    }
+
+    async fn record_payment(
+        &self,
+        payment: DetectedPayment,
+    ) -> Result<Option<InvoicePayment>, BillingError> {
+        let _guard = self.lock.lock().await;
+
+        let payment_id = payment.payment_key();
+        if self.storage.get_payment(&payment_id).await?.is_some() {
+            log::debug!("billing: payment {} already recorded", payment_id);
+            return Ok(None);
+        }
+
+        let now = self.get_current_timestamp();
+        let tenant = self
+            .storage
+            .find_tenant_by_payer(&normalize_address(&payment.from))
+            .await?;
+        let mut record = InvoicePayment {
+            id: payment_id,
+            tenant: tenant.clone(),
+            credited: self.credited_value(&payment),
+            payment,
+            allocations: Vec::new(),
+            to_credit: 0,
+            received_at: now,
+        };
+
+        let Some(tenant) = tenant else {
+            // Kept for manual review, the sender may register later
+            log::warn!(
+                "billing: payment {} from unregistered sender {}",
+                record.id,
+                record.payment.from
+            );
+            self.storage.put_payment(record.clone()).await?;
+            return Ok(Some(record));
+        };
+
+        // Oldest invoices are paid first, what is left becomes account credit
+        let mut account = self.account(&tenant, now).await?;
+        let mut settled = Vec::new();
+        let mut remaining = record.credited;
+        for mut invoice in self.storage.list_invoices(&tenant).await? {
+            if remaining == 0 {
+                break;
+            }
+            if invoice.outstanding() == 0 {
+                continue;
+            }
+
+            let left = invoice.apply(remaining, now);
+            record.allocations.push(PaymentAllocation {
+                invoice_id: invoice.id.clone(),
+                amount: remaining - left,
+            });
+            remaining = left;
+            settled.push(invoice);
+        }
+        record.to_credit = remaining;
+
+        // The payment is recorded before it is applied, so a replay can never credit it twice
+        self.storage.put_payment(record.clone()).await?;
+        for invoice in &settled {
+            self.save_invoice(invoice, false).await?;
+        }
+        account.credit = account.credit.saturating_add(remaining);
+        self.refresh_standing(&mut account, now).await?;
+
+        log::info!(
+            "billing: credited payment {} of {} GAS fractions to {}",
+            record.id,
+            record.credited,
+            tenant
+        );
+
+        Ok(Some(record))
+    }
+
+    async fn check_execution(&self, tenant: &str) -> Result<(), BillingError> {
+        let now = self.get_current_timestamp();
+        let account = match self.storage.get_account(tenant).await? {
+            Some(account) => account,
+            None => {
+                // Tenants are enrolled in billing when they first run a function
+                let _guard = self.lock.lock().await;
+                if self.storage.get_account(tenant).await?.is_none() {
+                    self.storage
+                        .put_account(BillingAccount::new(tenant, now))
+                        .await?;
+                }
+                return Ok(());
+            }
+        };
+
+        // The standing is refreshed by the billing cycle, the grace period may have ended since
+        let suspended = match account.standing {
+            AccountStanding::Good => false,
+            AccountStanding::Delinquent => account
+                .overdue_since
+                .is_some_and(|due_at| now >= due_at.saturating_add(self.config.grace_period)),
+            AccountStanding::Suspended => true,
+        };
+
+        if suspended {
+            return Err(BillingError::Suspended(format!(
+                "{} has invoices overdue since {}",
+                tenant,
+                account.overdue_since.unwrap_or_default()
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Run the billing cycle every interval, only while holding `lease` if set so that a single
+/// node of a cluster issues the invoices
+pub fn spawn_billing_cycle(
+    service: Arc<dyn BillingServiceTrait>,
+    interval: Duration,
+    lease: Option<Arc<LeaseHolder>>,
+) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(interval);
+        loop {
+            interval.tick().await;
+            if let Some(lease) = &lease {
+                if lease.hold().await.is_none() {
+                    continue;
+                }
+            }
+            let now = chrono::Utc::now().timestamp() as u64;
+            match service.run_billing_cycle(now).await {
+                Ok(issued) if !issued.is_empty() => {
+                    log::info!("billing: issued {} invoices", issued.len())
+                }
+                Ok(_) => {}
+                Err(e) => log::error!("billing: billing cycle failed: {}", e),
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::billing::storage::MemoryBillingStorage;
+    use crate::pricing::types::{ResourcePricing, ResourceType};
+    use crate::pricing::{MemoryPricingStorage, PricingService, PricingStorage, PricingTier};
+    use crate::quota::PlanTier;
+
+    const PAYER: &str = "0x1234567890abcdef1234567890abcdef12345678";
+
+    fn at(year: i32, month: u32, day: u32) -> u64 {
+        Utc.with_ymd_and_hms(year, month, day, 0, 0, 0)
+            .unwrap()
+            .timestamp() as u64
+    }
+
+    struct Fixture {
+        billing: BillingService,
+        storage: Arc<MemoryBillingStorage>,
+        pricing: Arc<PricingService<MemoryPricingStorage>>,
+    }
+
+    /// Billing of "alice", enrolled on 2024-01-10, with 1000 ms of execution time used
+    async fn fixture() -> Fixture {
+        let pricing_storage = MemoryPricingStorage::new();
+        pricing_storage
+            .add_resource_pricing(ResourcePricing {
+                resource_type: ResourceType::ExecutionTime,
+                tier: PricingTier::Basic,
+                base_price: 0.0,
+                // More decimals than GAS has
+                price_per_unit: 0.0000012345678,
+                free_tier_limit: None,
+                min_billable_units: 0,
+                max_billable_units: None,
+                volume_discounts: Vec::new(),
+            })
+            .await
+            .unwrap();
+        let pricing = Arc::new(PricingService::new(Arc::new(pricing_storage)));
+        pricing.assign_plan("alice", PlanTier::Free).await.unwrap();
+        pricing
+            .record_resource_usage("alice", ResourceType::ExecutionTime, 1000)
+            .await
+            .unwrap();
+
+        let storage = Arc::new(MemoryBillingStorage::new());
+        storage
+            .put_account(BillingAccount::new("alice", at(2024, 1, 10)))
+            .await
+            .unwrap();
+
+        let config = BillingConfig {
+            eth_gas_rate: 100.0,
+            ..BillingConfig::default()
+        };
+        Fixture {
+            billing: BillingService::new(storage.clone(), pricing.clone(), config),
+            storage,
+            pricing,
+        }
+    }
+
+    fn gas(tx_hash: &str, index: u32, from: &str, amount: u64) -> DetectedPayment {
+        DetectedPayment {
+            asset: PaymentAsset::Gas,
+            tx_hash: tx_hash.to_string(),
+            index,
+            from: from.to_string(),
+            amount: amount.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_previous_period() {
+        assert_eq!(
+            previous_period(at(2024, 3, 1)),
+            ("2024-02".to_string(), at(2024, 2, 1), at(2024, 3, 1))
+        );
+        assert_eq!(
+            previous_period(at(2024, 3, 1) - 1),
+            ("2024-01".to_string(), at(2024, 1, 1), at(2024, 2, 1))
+        );
+        assert_eq!(
+            previous_period(at(2024, 1, 15)),
+            ("2023-12".to_string(), at(2023, 12, 1), at(2024, 1, 1))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_invoice_periods() {
+        let f = fixture().await;
+
+        // December ended before the account was enrolled
+        assert!(f
+            .billing
+            .invoice_tenant("alice", at(2024, 1, 20))
+            .await
+            .unwrap()
+            .is_none());
+
+        let invoice = f
+            .billing
+            .invoice_tenant("alice", at(2024, 2, 1))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(invoice.id, "alice-2024-01");
+        assert_eq!(
+            (invoice.period_start, invoice.period_end),
+            (at(2024, 1, 1), at(2024, 2, 1))
+        );
+        assert_eq!(invoice.status, InvoiceStatus::Open);
+        assert_eq!(invoice.items.len(), 1);
+        assert_eq!(invoice.due_at, at(2024, 2, 15));
+
+        // A period is invoiced once, however often the cycle runs in the next one
+        assert!(f
+            .billing
+            .invoice_tenant("alice", at(2024, 2, 29))
+            .await
+            .unwrap()
+            .is_none());
+        assert!(f
+            .billing
+            .run_billing_cycle(at(2024, 2, 10))
+            .await
+            .unwrap()
+            .is_empty());
+
+        // The invoiced usage is cleared, a period without usage is paid right away
+        let profile = f.pricing.get_user_billing_profile("alice").await.unwrap();
+        assert!(profile.resource_usage.is_empty());
+        let issued = f.billing.run_billing_cycle(at(2024, 3, 1)).await.unwrap();
+        assert_eq!(issued.len(), 1);
+        assert_eq!(issued[0].id, "alice-2024-02");
+        assert_eq!(issued[0].amount_due, 0);
+        assert_eq!(issued[0].status, InvoiceStatus::Paid);
+
+        let invoices = f.billing.list_invoices("alice").await.unwrap();
+        let ids: Vec<_> = invoices.iter().map(|invoice| invoice.id.as_str()).collect();
+        assert_eq!(ids, ["alice-2024-01", "alice-2024-02"]);
+    }
+
+    #[tokio::test]
+    async fn test_invoice_rounding() {
+        let f = fixture().await;
+        let invoice = f
+            .billing
+            .invoice_tenant("alice", at(2024, 2, 1))
+            .await
+            .unwrap()
+            .unwrap();
+
+        // 0.0012345678 GAS is rounded up to whole GAS fractions
+        assert_eq!(invoice.amount_due, 123_457);
+        assert_eq!(invoice.amount_paid, 0);
+    }
+
+    #[tokio::test]
+    async fn test_payment_matching() {
+        let f = fixture().await;
+        f.billing.add_payer("alice", PAYER).await.unwrap();
+        let january = f
+            .billing
+            .invoice_tenant("alice", at(2024, 2, 1))
+            .await
+            .unwrap()
+            .unwrap();
+
+        // Underpayment, from the payer address in another case
+        let payment = f
+            .billing
+            .record_payment(gas(
+                "0xAA",
+                0,
+                &PAYER.to_uppercase().replace("0X", "0x"),
+                100_000,
+            ))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(payment.tenant.as_deref(), Some("alice"));
+        assert_eq!(payment.credited, 100_000);
+        assert_eq!(payment.allocations.len(), 1);
+        assert_eq!(payment.allocations[0].invoice_id, january.id);
+        assert_eq!(payment.allocations[0].amount, 100_000);
+        assert_eq!(payment.to_credit, 0);
+        let invoice = f.billing.get_invoice(&january.id).await.unwrap();
+        assert_eq!(invoice.status, InvoiceStatus::PartiallyPaid);
+        assert_eq!(invoice.outstanding(), 23_457);
+
+        // The same transfer seen again is not credited twice
+        assert!(f
+            .billing
+            .record_payment(gas("0xaa", 0, PAYER, 100_000))
+            .await
+            .unwrap()
+            .is_none());
+        let invoice = f.billing.get_invoice(&january.id).await.unwrap();
+        assert_eq!(invoice.amount_paid, 100_000);
+
+        // Overpayment by another transfer of the transaction, the rest becomes credit
+        let payment = f
+            .billing
+            .record_payment(gas("0xaa", 1, PAYER, 50_000))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(payment.allocations[0].amount, 23_457);
+        assert_eq!(payment.to_credit, 26_543);
+        let invoice = f.billing.get_invoice(&january.id).await.unwrap();
+        assert_eq!(invoice.status, InvoiceStatus::Paid);
+        assert!(invoice.paid_at.is_some());
+        assert_eq!(f.billing.get_account("alice").await.unwrap().credit, 26_543);
+
+        // The credit is applied to the next invoice
+        f.pricing
+            .record_resource_usage("alice", ResourceType::ExecutionTime, 1000)
+            .await
+            .unwrap();
+        let february = f
+            .billing
+            .invoice_tenant("alice", at(2024, 3, 1))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(february.amount_due, 123_457);
+        assert_eq!(february.amount_paid, 26_543);
+        assert_eq!(february.status, InvoiceStatus::PartiallyPaid);
+        assert_eq!(f.billing.get_account("alice").await.unwrap().credit, 0);
+
+        // ETH is credited at the configured rate: 0.001 ETH is 0.1 GAS
+        let payment = f
+            .billing
+            .record_payment(DetectedPayment {
+                asset: PaymentAsset::Eth,
+                amount: "1000000000000000".to_string(),
+                ..gas("0xbb", 0, PAYER, 0)
+            })
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(payment.credited, 10_000_000);
+        assert_eq!(payment.to_credit, 10_000_000 - 96_914);
+        let invoice = f.billing.get_invoice(&february.id).await.unwrap();
+        assert_eq!(invoice.status, InvoiceStatus::Paid);
+
+        // Payments of unknown senders are kept, but credit no one
+        let payment = f
+            .billing
+            .record_payment(gas(
+                "0xcc",
+                0,
+                "0xffffffffffffffffffffffffffffffffffffffff",
+                1,
+            ))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(payment.tenant, None);
+        assert!(payment.allocations.is_empty());
+        assert!(f.storage.get_payment(&payment.id).await.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_oldest_invoice_paid_first() {
+        let f = fixture().await;
+        f.billing.add_payer("alice", PAYER).await.unwrap();
+        f.billing
+            .invoice_tenant("alice", at(2024, 2, 1))
+            .await
+            .unwrap();
+        f.pricing
+            .record_resource_usage("alice", ResourceType::ExecutionTime, 1000)
+            .await
+            .unwrap();
+        f.billing
+            .invoice_tenant("alice", at(2024, 3, 1))
+            .await
+            .unwrap();
+
+        let payment = f
+            .billing
+            .record_payment(gas("0xaa", 0, PAYER, 150_000))
+            .await
+            .unwrap()
+            .unwrap();
+        let allocations: Vec<_> = payment
+            .allocations
+            .iter()
+            .map(|allocation| (allocation.invoice_id.as_str(), allocation.amount))
+            .collect();
+        assert_eq!(
+            allocations,
+            [("alice-2024-01", 123_457), ("alice-2024-02", 26_543)]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_standing() {
+        let f = fixture().await;
+        f.billing.add_payer("alice", PAYER).await.unwrap();
+        let invoice = f
+            .billing
+            .invoice_tenant("alice", at(2024, 2, 1))
+            .await
+            .unwrap()
+            .unwrap();
+
+        f.billing
+            .run_billing_cycle(invoice.due_at - 1)
+            .await
+            .unwrap();
+        let account = f.billing.get_account("alice").await.unwrap();
+        assert_eq!(account.standing, AccountStanding::Good);
+
+        f.billing.run_billing_cycle(invoice.due_at).await.unwrap();
+        let account = f.billing.get_account("alice").await.unwrap();
+        assert_eq!(account.standing, AccountStanding::Delinquent);
+        assert_eq!(account.overdue_since, Some(invoice.due_at));
+
+        // The grace period has long ended by now, even before the cycle suspends the account
+        assert!(matches!(
+            f.billing.check_execution("alice").await,
+            Err(BillingError::Suspended(_))
+        ));
+        f.billing
+            .run_billing_cycle(invoice.due_at + BillingConfig::default().grace_period)
+            .await
+            .unwrap();
+        let account = f.billing.get_account("alice").await.unwrap();
+        assert_eq!(account.standing, AccountStanding::Suspended);
+
+        // Paying in full restores the account
+        f.billing
+            .record_payment(gas("0xaa", 0, PAYER, invoice.amount_due))
+            .await
+            .unwrap()
+            .unwrap();
+        let account = f.billing.get_account("alice").await.unwrap();
+        assert_eq!(account.standing, AccountStanding::Good);
+        assert!(f.billing.check_execution("alice").await.is_ok());
+    }
+}
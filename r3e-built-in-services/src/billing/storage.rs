@@ -0,0 +1,123 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+use crate::billing::types::{BillingAccount, BillingError, Invoice, InvoicePayment};
+
+/// Billing storage trait
+#[async_trait]
+pub trait BillingStorage: Send + Sync {
+    /// Get the account of a tenant
+    async fn get_account(&self, tenant: &str) -> Result<Option<BillingAccount>, BillingError>;
+
+    /// Create or update an account
+    async fn put_account(&self, account: BillingAccount) -> Result<(), BillingError>;
+
+    /// List all accounts
+    async fn list_accounts(&self) -> Result<Vec<BillingAccount>, BillingError>;
+
+    /// Find the tenant a payer address is registered to
+    async fn find_tenant_by_payer(&self, address: &str) -> Result<Option<String>, BillingError>;
+
+    /// Get an invoice by ID
+    async fn get_invoice(&self, invoice_id: &str) -> Result<Option<Invoice>, BillingError>;
+
+    /// Create or update an invoice
+    async fn put_invoice(&self, invoice: Invoice) -> Result<(), BillingError>;
+
+    /// List the invoices of a tenant, oldest period first
+    async fn list_invoices(&self, tenant: &str) -> Result<Vec<Invoice>, BillingError>;
+
+    /// Get a payment by its payment key
+    async fn get_payment(&self, payment_id: &str) -> Result<Option<InvoicePayment>, BillingError>;
+
+    /// Record a payment
+    async fn put_payment(&self, payment: InvoicePayment) -> Result<(), BillingError>;
+}
+
+/// In-memory implementation of the billing storage
+#[derive(Default)]
+pub struct MemoryBillingStorage {
+    /// Accounts by tenant
+    accounts: RwLock<HashMap<String, BillingAccount>>,
+
+    /// Invoices by ID
+    invoices: RwLock<HashMap<String, Invoice>>,
+
+    /// Payments by payment key
+    payments: RwLock<HashMap<String, InvoicePayment>>,
+}
+
+impl MemoryBillingStorage {
+    /// Create a new memory-based billing storage
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+fn lock_error(e: impl std::fmt::Display) -> BillingError {
+    BillingError::Storage(format!("Failed to acquire lock: {}", e))
+}
+
+#[async_trait]
+impl BillingStorage for MemoryBillingStorage {
+    async fn get_account(&self, tenant: &str) -> Result<Option<BillingAccount>, BillingError> {
+        let accounts = self.accounts.read().map_err(lock_error)?;
+        Ok(accounts.get(tenant).cloned())
+    }
+
+    async fn put_account(&self, account: BillingAccount) -> Result<(), BillingError> {
+        let mut accounts = self.accounts.write().map_err(lock_error)?;
+        accounts.insert(account.tenant.clone(), account);
+        Ok(())
+    }
+
+    async fn list_accounts(&self) -> Result<Vec<BillingAccount>, BillingError> {
+        let accounts = self.accounts.read().map_err(lock_error)?;
+        Ok(accounts.values().cloned().collect())
+    }
+
+    async fn find_tenant_by_payer(&self, address: &str) -> Result<Option<String>, BillingError> {
+        let accounts = self.accounts.read().map_err(lock_error)?;
+        Ok(accounts
+            .values()
+            .find(|account| account.payers.iter().any(|payer| payer == address))
+            .map(|account| account.tenant.clone()))
+    }
+
+    async fn get_invoice(&self, invoice_id: &str) -> Result<Option<Invoice>, BillingError> {
+        let invoices = self.invoices.read().map_err(lock_error)?;
+        Ok(invoices.get(invoice_id).cloned())
+    }
+
+    async fn put_invoice(&self, invoice: Invoice) -> Result<(), BillingError> {
+        let mut invoices = self.invoices.write().map_err(lock_error)?;
+        invoices.insert(invoice.id.clone(), invoice);
+        Ok(())
+    }
+
+    async fn list_invoices(&self, tenant: &str) -> Result<Vec<Invoice>, BillingError> {
+        let invoices = self.invoices.read().map_err(lock_error)?;
+        let mut invoices: Vec<Invoice> = invoices
+            .values()
+            .filter(|invoice| invoice.tenant == tenant)
+            .cloned()
+            .collect();
+        invoices.sort_by_key(|invoice| invoice.period_start);
+        Ok(invoices)
+    }
+
+    async fn get_payment(&self, payment_id: &str) -> Result<Option<InvoicePayment>, BillingError> {
+        let payments = self.payments.read().map_err(lock_error)?;
+        Ok(payments.get(payment_id).cloned())
+    }
+
+    async fn put_payment(&self, payment: InvoicePayment) -> Result<(), BillingError> {
+        let mut payments = self.payments.write().map_err(lock_error)?;
+        payments.insert(payment.id.clone(), payment);
+        Ok(())
+    }
+}
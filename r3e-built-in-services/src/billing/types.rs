@@ -0,0 +1,321 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::pricing::types::{BillingItem, PricingError};
+
+/// GAS fractions per GAS; invoices are kept in fractions so payments reconcile exactly
+pub const GAS_FRACTIONS: u64 = 100_000_000;
+
+/// Contract hash of the GAS token on Neo N3
+pub const GAS_TOKEN_HASH: &str = "0xd2a4cff31913016155e38e474a2c06d08be276cf";
+
+#[derive(Debug, Error)]
+pub enum BillingError {
+    #[error("Storage error: {0}")]
+    Storage(String),
+
+    #[error("Not found: {0}")]
+    NotFound(String),
+
+    #[error("Invalid input: {0}")]
+    InvalidInput(String),
+
+    #[error("Pricing error: {0}")]
+    Pricing(#[from] PricingError),
+
+    #[error("Account suspended: {0}")]
+    Suspended(String),
+}
+
+/// Asset invoices can be paid with
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PaymentAsset {
+    /// GAS on Neo N3
+    Gas,
+
+    /// Ether on Ethereum
+    Eth,
+}
+
+impl std::fmt::Display for PaymentAsset {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PaymentAsset::Gas => write!(f, "gas"),
+            PaymentAsset::Eth => write!(f, "eth"),
+        }
+    }
+}
+
+/// Invoice status
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InvoiceStatus {
+    /// Issued, nothing paid yet
+    Open,
+
+    /// Paid in part
+    PartiallyPaid,
+
+    /// Paid in full
+    Paid,
+
+    /// Cancelled, nothing is owed
+    Void,
+}
+
+impl InvoiceStatus {
+    /// Whether the invoice still expects payments
+    pub fn is_payable(&self) -> bool {
+        matches!(self, InvoiceStatus::Open | InvoiceStatus::PartiallyPaid)
+    }
+}
+
+impl std::fmt::Display for InvoiceStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            InvoiceStatus::Open => write!(f, "open"),
+            InvoiceStatus::PartiallyPaid => write!(f, "partially_paid"),
+            InvoiceStatus::Paid => write!(f, "paid"),
+            InvoiceStatus::Void => write!(f, "void"),
+        }
+    }
+}
+
+/// Monthly invoice of a tenant
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Invoice {
+    /// Invoice ID, `<tenant>-<period>` so a period is never invoiced twice
+    pub id: String,
+
+    /// Tenant
+    pub tenant: String,
+
+    /// Billing period, e.g. `2024-05`
+    pub period: String,
+
+    /// Start of the billing period
+    pub period_start: u64,
+
+    /// End of the billing period (exclusive)
+    pub period_end: u64,
+
+    /// Pricing billing record the items were taken from
+    pub billing_record_id: Option<String>,
+
+    /// Line items
+    pub items: Vec<BillingItem>,
+
+    /// Amount due (in GAS fractions)
+    pub amount_due: u64,
+
+    /// Amount paid so far, including account credit (in GAS fractions)
+    pub amount_paid: u64,
+
+    /// Status
+    pub status: InvoiceStatus,
+
+    /// Issued at
+    pub issued_at: u64,
+
+    /// Payment due at
+    pub due_at: u64,
+
+    /// Paid in full at
+    pub paid_at: Option<u64>,
+}
+
+impl Invoice {
+    /// Amount still owed (in GAS fractions)
+    pub fn outstanding(&self) -> u64 {
+        if self.status.is_payable() {
+            self.amount_due.saturating_sub(self.amount_paid)
+        } else {
+            0
+        }
+    }
+
+    /// Credit `amount` to the invoice, returning the part it could not absorb
+    pub fn apply(&mut self, amount: u64, now: u64) -> u64 {
+        let applied = amount.min(self.outstanding());
+        self.amount_paid += applied;
+        if self.status.is_payable() {
+            if self.amount_paid >= self.amount_due {
+                self.status = InvoiceStatus::Paid;
+                self.paid_at = Some(now);
+            } else if self.amount_paid > 0 {
+                self.status = InvoiceStatus::PartiallyPaid;
+            }
+        }
+        amount - applied
+    }
+}
+
+/// Part of a payment credited to an invoice
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PaymentAllocation {
+    /// Invoice ID
+    pub invoice_id: String,
+
+    /// Credited amount (in GAS fractions)
+    pub amount: u64,
+}
+
+/// On-chain transfer to a deposit address
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DetectedPayment {
+    /// Asset
+    pub asset: PaymentAsset,
+
+    /// Transaction hash
+    pub tx_hash: String,
+
+    /// Index of the transfer in the transaction
+    pub index: u32,
+
+    /// Sender address
+    pub from: String,
+
+    /// Amount in the asset's smallest unit, as a decimal string since it may exceed 2^64
+    pub amount: String,
+}
+
+impl DetectedPayment {
+    /// Unique key of the payment, so replayed chain events are credited once
+    pub fn payment_key(&self) -> String {
+        format!(
+            "{}:{}:{}",
+            self.asset,
+            self.tx_hash.to_lowercase(),
+            self.index
+        )
+    }
+}
+
+/// Recorded payment
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InvoicePayment {
+    /// Payment key
+    pub id: String,
+
+    /// Tenant the sender is registered to, `None` if the sender is unknown
+    pub tenant: Option<String>,
+
+    /// Detected transfer
+    pub payment: DetectedPayment,
+
+    /// Value of the transfer (in GAS fractions)
+    pub credited: u64,
+
+    /// Invoices the payment was applied to
+    pub allocations: Vec<PaymentAllocation>,
+
+    /// Part of the payment kept as account credit (in GAS fractions)
+    pub to_credit: u64,
+
+    /// Received at
+    pub received_at: u64,
+}
+
+/// Standing of a billing account
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AccountStanding {
+    /// No overdue invoices
+    Good,
+
+    /// Overdue invoices within the grace period, functions still run
+    Delinquent,
+
+    /// Overdue invoices past the grace period, functions do not run
+    Suspended,
+}
+
+impl std::fmt::Display for AccountStanding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AccountStanding::Good => write!(f, "good"),
+            AccountStanding::Delinquent => write!(f, "delinquent"),
+            AccountStanding::Suspended => write!(f, "suspended"),
+        }
+    }
+}
+
+/// Billing account of a tenant
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BillingAccount {
+    /// Tenant
+    pub tenant: String,
+
+    /// Addresses the tenant pays from, lowercase
+    pub payers: Vec<String>,
+
+    /// Overpayments carried to the next invoices (in GAS fractions)
+    pub credit: u64,
+
+    /// Standing
+    pub standing: AccountStanding,
+
+    /// Due date of the oldest overdue invoice
+    pub overdue_since: Option<u64>,
+
+    /// Created at
+    pub created_at: u64,
+
+    /// Updated at
+    pub updated_at: u64,
+}
+
+impl BillingAccount {
+    /// Create an account in good standing
+    pub fn new(tenant: &str, now: u64) -> Self {
+        Self {
+            tenant: tenant.to_string(),
+            payers: Vec::new(),
+            credit: 0,
+            standing: AccountStanding::Good,
+            overdue_since: None,
+            created_at: now,
+            updated_at: now,
+        }
+    }
+}
+
+/// Billing configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BillingConfig {
+    /// Neo N3 address receiving GAS payments, as a `0x` prefixed script hash
+    pub neo_deposit_address: Option<String>,
+
+    /// Ethereum address receiving ETH payments
+    pub eth_deposit_address: Option<String>,
+
+    /// GAS token contract hash
+    pub gas_token: String,
+
+    /// GAS credited per ETH received
+    pub eth_gas_rate: f64,
+
+    /// Time between issuing an invoice and its due date (in seconds)
+    pub payment_terms: u64,
+
+    /// Time an overdue account keeps running functions before it is suspended (in seconds)
+    pub grace_period: u64,
+}
+
+impl Default for BillingConfig {
+    fn default() -> Self {
+        Self {
+            neo_deposit_address: None,
+            eth_deposit_address: None,
+            gas_token: GAS_TOKEN_HASH.to_string(),
+            eth_gas_rate: 0.0,
+            payment_terms: 14 * 86400,
+            grace_period: 7 * 86400,
+        }
+    }
+}
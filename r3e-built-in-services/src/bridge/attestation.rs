@@ -0,0 +1,110 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+use async_trait::async_trait;
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+
+use crate::bridge::types::{BridgeAttestation, BridgeError, BridgeTransfer};
+use crate::tee::key_management::KeyManagementService;
+use crate::tee::{TeePlatform, TeeService};
+
+/// Domain separator of bridge attestation messages
+const ATTESTATION_DOMAIN: &str = "r3e-bridge:v1";
+
+/// Compute the digest the bridge signer attests to.
+///
+/// The digest commits to everything the destination chain needs to settle the transfer,
+/// so a signature can not be replayed for another deposit, recipient or amount.
+pub fn attestation_digest(transfer: &BridgeTransfer) -> Vec<u8> {
+    let message = format!(
+        "{}|{}|{}|{}|{}|{}|{}|{}|{}",
+        ATTESTATION_DOMAIN,
+        transfer.id,
+        transfer.deposit.deposit_key(),
+        transfer.deposit.chain,
+        transfer.deposit.to_chain,
+        transfer.destination_token.to_lowercase(),
+        transfer.deposit.recipient,
+        transfer.amount_out,
+        match transfer.mode {
+            crate::bridge::types::BridgeMode::LockAndMint => "mint",
+            crate::bridge::types::BridgeMode::BurnAndRelease => "release",
+        }
+    );
+
+    Sha256::digest(message.as_bytes()).to_vec()
+}
+
+/// Trait defining the bridge attestation signer
+#[async_trait]
+pub trait AttestationSigner: Send + Sync {
+    /// Attest that the transfer's deposit is final and may be settled
+    async fn attest(&self, transfer: &BridgeTransfer) -> Result<BridgeAttestation, BridgeError>;
+}
+
+/// Attestation signer backed by the TEE key management service.
+///
+/// If a TEE service is configured, the attestation also carries the TEE attestation report,
+/// so verifiers can check the signing key lives inside a genuine enclave.
+pub struct KeyManagementAttestationSigner {
+    /// Key management service
+    kms: Arc<dyn KeyManagementService>,
+
+    /// Signing key ID
+    key_id: String,
+
+    /// TEE service and platform used to attest the signer
+    tee: Option<(Arc<dyn TeeService>, TeePlatform)>,
+}
+
+impl KeyManagementAttestationSigner {
+    /// Create a new attestation signer
+    pub fn new(kms: Arc<dyn KeyManagementService>, key_id: String) -> Self {
+        Self {
+            kms,
+            key_id,
+            tee: None,
+        }
+    }
+
+    /// Attach TEE attestation reports to the attestations
+    pub fn with_tee(mut self, tee: Arc<dyn TeeService>, platform: TeePlatform) -> Self {
+        self.tee = Some((tee, platform));
+        self
+    }
+}
+
+#[async_trait]
+impl AttestationSigner for KeyManagementAttestationSigner {
+    async fn attest(&self, transfer: &BridgeTransfer) -> Result<BridgeAttestation, BridgeError> {
+        let digest = attestation_digest(transfer);
+        let signature =
+            self.kms.sign(&self.key_id, &digest).await.map_err(|e| {
+                BridgeError::Transaction(format!("Failed to sign attestation: {}", e))
+            })?;
+
+        let tee_report = match &self.tee {
+            Some((tee, platform)) => {
+                let report = tee.generate_attestation(*platform).await.map_err(|e| {
+                    BridgeError::Transaction(format!("Failed to generate TEE attestation: {}", e))
+                })?;
+                Some(serde_json::to_value(report).map_err(|e| {
+                    BridgeError::Transaction(format!("Failed to serialize TEE attestation: {}", e))
+                })?)
+            }
+            None => None,
+        };
+
+        Ok(BridgeAttestation {
+            digest: hex::encode(digest),
+            signature: hex::encode(signature),
+            signer: self.key_id.clone(),
+            tee_report,
+            created_at: std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs(),
+        })
+    }
+}
@@ -0,0 +1,232 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+//! Decoding of bridge deposit events emitted by the lock contracts.
+//!
+//! Lock contracts emit `Deposit(token, sender, amount, toChain, recipient)` on every chain.
+
+use base64::Engine;
+use r3e_event::source::event::Event;
+use serde_json::Value;
+
+use crate::bridge::types::{BlockchainNetwork, BridgeError, DepositEvent};
+
+/// Neo notification name of deposits
+pub const NEO_DEPOSIT_EVENT: &str = "Deposit";
+
+/// Extract the deposits made to `deposit_contract` from a source chain event.
+///
+/// `deposit_topic` is the topic0 of the Ethereum `Deposit` event and is ignored for Neo.
+/// Malformed deposits are logged and skipped rather than failing the whole event.
+pub fn parse_deposits(
+    event: &Event,
+    deposit_contract: &str,
+    deposit_topic: &str,
+) -> Vec<DepositEvent> {
+    let result = match event {
+        Event::NeoContractNotification(notification) => parse_neo_deposits(
+            &notification.tx_hash,
+            &notification.notifications,
+            deposit_contract,
+        ),
+        Event::EthereumContractEvent {
+            contract_address,
+            events,
+        } if same_address(contract_address, deposit_contract) => Ok(events
+            .iter()
+            .filter_map(|log| match parse_ethereum_deposit(log, deposit_topic) {
+                Ok(deposit) => deposit,
+                Err(err) => {
+                    log::warn!("bridge: skip malformed ethereum deposit: {}", err);
+                    None
+                }
+            })
+            .collect()),
+        _ => Ok(Vec::new()),
+    };
+
+    result.unwrap_or_else(|err| {
+        log::warn!("bridge: failed to parse deposits: {}", err);
+        Vec::new()
+    })
+}
+
+fn same_address(a: &str, b: &str) -> bool {
+    a.trim_start_matches("0x")
+        .eq_ignore_ascii_case(b.trim_start_matches("0x"))
+}
+
+fn parse_neo_deposits(
+    tx_hash: &str,
+    notifications: &str,
+    deposit_contract: &str,
+) -> Result<Vec<DepositEvent>, BridgeError> {
+    let notifications: Vec<Value> = serde_json::from_str(notifications)
+        .map_err(|e| BridgeError::InvalidInput(format!("Invalid Neo notifications: {}", e)))?;
+
+    let mut deposits = Vec::new();
+    for (index, notification) in notifications.iter().enumerate() {
+        let contract = notification.get("contract").and_then(Value::as_str);
+        let name = notification.get("eventname").and_then(Value::as_str);
+        if !contract.is_some_and(|c| same_address(c, deposit_contract))
+            || name != Some(NEO_DEPOSIT_EVENT)
+        {
+            continue;
+        }
+
+        match parse_neo_deposit(tx_hash, index as u32, notification) {
+            Ok(deposit) => deposits.push(deposit),
+            Err(err) => log::warn!("bridge: skip malformed neo deposit in {}: {}", tx_hash, err),
+        }
+    }
+
+    Ok(deposits)
+}
+
+fn parse_neo_deposit(
+    tx_hash: &str,
+    log_index: u32,
+    notification: &Value,
+) -> Result<DepositEvent, BridgeError> {
+    let items = notification
+        .get("state")
+        .and_then(|state| state.get("value"))
+        .and_then(Value::as_array)
+        .filter(|items| items.len() == 5)
+        .ok_or_else(|| BridgeError::InvalidInput("Deposit state must have 5 items".into()))?;
+
+    let amount = neo_integer(&items[2])?;
+    Ok(DepositEvent {
+        chain: BlockchainNetwork::NeoN3,
+        tx_hash: tx_hash.to_string(),
+        log_index,
+        token: neo_hash160(&items[0])?,
+        sender: neo_hash160(&items[1])?,
+        amount: u64::try_from(amount)
+            .map_err(|_| BridgeError::InvalidInput(format!("Invalid amount: {}", amount)))?,
+        to_chain: neo_string(&items[3])?.parse()?,
+        recipient: neo_string(&items[4])?,
+    })
+}
+
+fn neo_bytes(item: &Value) -> Result<Vec<u8>, BridgeError> {
+    let value = item
+        .get("value")
+        .and_then(Value::as_str)
+        .ok_or_else(|| BridgeError::InvalidInput("Expected a ByteString item".into()))?;
+    base64::engine::general_purpose::STANDARD
+        .decode(value)
+        .map_err(|e| BridgeError::InvalidInput(format!("Invalid ByteString item: {}", e)))
+}
+
+/// Script hashes are little endian on the stack, but displayed big endian
+fn neo_hash160(item: &Value) -> Result<String, BridgeError> {
+    let mut bytes = neo_bytes(item)?;
+    if bytes.len() != 20 {
+        return Err(BridgeError::InvalidInput(format!(
+            "Invalid Hash160 length: {}",
+            bytes.len()
+        )));
+    }
+    bytes.reverse();
+    Ok(format!("0x{}", hex::encode(bytes)))
+}
+
+fn neo_string(item: &Value) -> Result<String, BridgeError> {
+    String::from_utf8(neo_bytes(item)?)
+        .map_err(|e| BridgeError::InvalidInput(format!("Invalid string item: {}", e)))
+}
+
+fn neo_integer(item: &Value) -> Result<i128, BridgeError> {
+    item.get("value")
+        .and_then(Value::as_str)
+        .and_then(|value| value.parse().ok())
+        .ok_or_else(|| BridgeError::InvalidInput("Expected an Integer item".into()))
+}
+
+pub(crate) fn parse_ethereum_deposit(
+    log: &Value,
+    deposit_topic: &str,
+) -> Result<Option<DepositEvent>, BridgeError> {
+    let topics: Vec<&str> = log
+        .get("topics")
+        .and_then(Value::as_array)
+        .map(|topics| topics.iter().filter_map(Value::as_str).collect())
+        .unwrap_or_default();
+    if topics
+        .first()
+        .map_or(true, |topic| !same_address(topic, deposit_topic))
+    {
+        return Ok(None);
+    }
+    if topics.len() != 3 {
+        return Err(BridgeError::InvalidInput(
+            "Deposit log must have 3 topics".into(),
+        ));
+    }
+
+    let field = |name: &str| {
+        log.get(name)
+            .and_then(Value::as_str)
+            .ok_or_else(|| BridgeError::InvalidInput(format!("Missing log field: {}", name)))
+    };
+    let log_index = u32::from_str_radix(field("logIndex")?.trim_start_matches("0x"), 16)
+        .map_err(|e| BridgeError::InvalidInput(format!("Invalid log index: {}", e)))?;
+    let data = hex::decode(field("data")?.trim_start_matches("0x"))
+        .map_err(|e| BridgeError::InvalidInput(format!("Invalid log data: {}", e)))?;
+
+    // data: amount, offset of toChain, offset of recipient, followed by the strings
+    let amount = abi_word(&data, 0)?;
+    if amount[..24].iter().any(|b| *b != 0) {
+        return Err(BridgeError::InvalidInput("Amount exceeds u64".into()));
+    }
+    let amount = u64::from_be_bytes(amount[24..].try_into().unwrap());
+
+    Ok(Some(DepositEvent {
+        chain: BlockchainNetwork::Ethereum,
+        tx_hash: field("transactionHash")?.to_string(),
+        log_index,
+        token: topic_address(topics[1])?,
+        sender: topic_address(topics[2])?,
+        amount,
+        to_chain: abi_string(&data, 32)?.parse()?,
+        recipient: abi_string(&data, 64)?,
+    }))
+}
+
+fn topic_address(topic: &str) -> Result<String, BridgeError> {
+    let topic = topic.trim_start_matches("0x");
+    if topic.len() != 64 {
+        return Err(BridgeError::InvalidInput(format!(
+            "Invalid address topic: {}",
+            topic
+        )));
+    }
+    Ok(format!("0x{}", topic[24..].to_lowercase()))
+}
+
+fn abi_word(data: &[u8], offset: usize) -> Result<&[u8], BridgeError> {
+    data.get(offset..offset + 32)
+        .ok_or_else(|| BridgeError::InvalidInput("Log data too short".into()))
+}
+
+fn abi_usize(data: &[u8], offset: usize) -> Result<usize, BridgeError> {
+    let word = abi_word(data, offset)?;
+    if word[..24].iter().any(|b| *b != 0) {
+        return Err(BridgeError::InvalidInput("ABI offset too large".into()));
+    }
+    usize::try_from(u64::from_be_bytes(word[24..].try_into().unwrap()))
+        .map_err(|_| BridgeError::InvalidInput("ABI offset too large".into()))
+}
+
+/// Decode the dynamic `string` whose offset is stored at `head`
+fn abi_string(data: &[u8], head: usize) -> Result<String, BridgeError> {
+    let offset = abi_usize(data, head)?;
+    let len = abi_usize(data, offset)?;
+    let bytes = offset
+        .checked_add(32)
+        .and_then(|start| data.get(start..start.checked_add(len)?))
+        .ok_or_else(|| BridgeError::InvalidInput("Log data too short".into()))?;
+    String::from_utf8(bytes.to_vec())
+        .map_err(|e| BridgeError::InvalidInput(format!("Invalid string: {}", e)))
+}
@@ -1,13 +1,23 @@
 // Copyright @ 2023 - 2024, R3E Network
 // All Rights Reserved
 
+pub mod attestation;
+pub mod deposit;
+pub mod orchestrator;
+pub mod rocksdb;
 pub mod service;
 pub mod storage;
 pub mod types;
+pub mod verify;
 
+pub use attestation::{AttestationSigner, KeyManagementAttestationSigner};
+pub use orchestrator::{BridgeChainClient, BridgeChainConfig, BridgeConfig, BridgeOrchestrator};
+pub use rocksdb::RocksDBTransferStorage;
 pub use service::{BridgeService, BridgeServiceTrait};
-pub use storage::{BridgeStorage, MemoryBridgeStorage};
+pub use storage::{BridgeStorage, MemoryBridgeStorage, MemoryTransferStorage, TransferStorage};
 pub use types::{
-    AssetWrapper, BridgeError, BridgeTransaction, BridgeTransactionStatus, MessageBridge,
-    TokenBridge,
+    AssetWrapper, BridgeAttestation, BridgeError, BridgeMode, BridgeTransaction,
+    BridgeTransactionStatus, BridgeTransfer, DepositEvent, MessageBridge, TokenBridge,
+    TransferState,
 };
+pub use verify::{DepositProof, StaticTrustAnchors, TrustAnchor, TrustAnchors};
@@ -0,0 +1,1036 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+use async_trait::async_trait;
+use r3e_event::source::event::Event;
+use r3e_event::source::{TaskError, TaskSource};
+use r3e_store::LeaseHolder;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::bridge::attestation::AttestationSigner;
+use crate::bridge::deposit::parse_deposits;
+use crate::bridge::storage::{BridgeStorage, TransferStorage};
+use crate::bridge::types::{
+    BlockchainNetwork, BridgeAttestation, BridgeError, BridgeMode, BridgeTransfer, DepositEvent,
+    TransferState,
+};
+use crate::bridge::verify::{proof_block, verify_deposit, DepositProof, TrustAnchors};
+
+/// Trait defining the chain operations the bridge orchestrator needs
+///
+/// `submit_mint` and `submit_release` must be idempotent per transfer ID: destination
+/// contracts reject a transfer ID they have already settled, so a retry after a crash
+/// can never settle a deposit twice.
+#[async_trait]
+pub trait BridgeChainClient: Send + Sync {
+    /// Get the number of confirmations of a transaction, 0 if not yet included
+    async fn get_confirmations(&self, tx_hash: &str) -> Result<u64, BridgeError>;
+
+    /// Mint wrapped tokens to the recipient, returning the transaction hash
+    async fn submit_mint(
+        &self,
+        transfer: &BridgeTransfer,
+        attestation: &BridgeAttestation,
+    ) -> Result<String, BridgeError>;
+
+    /// Release locked tokens to the recipient, returning the transaction hash
+    async fn submit_release(
+        &self,
+        transfer: &BridgeTransfer,
+        attestation: &BridgeAttestation,
+    ) -> Result<String, BridgeError>;
+
+    /// Get a proof that the deposit transaction is in a block, `None` if the client can not
+    /// serve proofs
+    async fn get_deposit_proof(
+        &self,
+        _deposit: &DepositEvent,
+    ) -> Result<Option<DepositProof>, BridgeError> {
+        Ok(None)
+    }
+}
+
+/// Per chain configuration of the bridge orchestrator
+#[derive(Debug, Clone)]
+pub struct BridgeChainConfig {
+    /// Lock contract receiving deposits
+    pub deposit_contract: String,
+
+    /// Topic0 of the deposit event (Ethereum only)
+    pub deposit_topic: String,
+
+    /// Confirmations required before a transaction is considered final
+    pub required_confirmations: u64,
+}
+
+/// Bridge orchestrator configuration
+#[derive(Debug, Clone)]
+pub struct BridgeConfig {
+    /// Chain configurations
+    pub chains: HashMap<BlockchainNetwork, BridgeChainConfig>,
+
+    /// Failed attempts of a step before the transfer is marked as failed
+    pub max_attempts: u32,
+
+    /// Interval between polls when the event source is idle
+    pub poll_interval: Duration,
+}
+
+impl Default for BridgeConfig {
+    fn default() -> Self {
+        Self {
+            chains: HashMap::new(),
+            max_attempts: 10,
+            poll_interval: Duration::from_secs(5),
+        }
+    }
+}
+
+/// Lock-and-mint bridge orchestrator
+///
+/// Watches deposits on the source chains, waits for them to be final, attests them and
+/// settles them on the destination chain. Every state change is persisted before the next
+/// step starts, so the orchestrator resumes where it left off after a restart.
+pub struct BridgeOrchestrator {
+    /// Bridge configuration storage
+    bridges: Arc<dyn BridgeStorage>,
+
+    /// Transfer storage
+    transfers: Arc<dyn TransferStorage>,
+
+    /// Attestation signer
+    signer: Arc<dyn AttestationSigner>,
+
+    /// Chain clients
+    clients: HashMap<BlockchainNetwork, Arc<dyn BridgeChainClient>>,
+
+    /// Configuration
+    config: BridgeConfig,
+
+    /// Lock held while driving transfers, so that a single node of a cluster settles them
+    lease: Option<Arc<LeaseHolder>>,
+
+    /// Trust anchors deposits are verified against before they are attested
+    anchors: Option<Arc<dyn TrustAnchors>>,
+}
+
+impl BridgeOrchestrator {
+    /// Create a new bridge orchestrator
+    pub fn new(
+        bridges: Arc<dyn BridgeStorage>,
+        transfers: Arc<dyn TransferStorage>,
+        signer: Arc<dyn AttestationSigner>,
+        config: BridgeConfig,
+    ) -> Self {
+        Self {
+            bridges,
+            transfers,
+            signer,
+            clients: HashMap::new(),
+            config,
+            lease: None,
+            anchors: None,
+        }
+    }
+
+    /// Register the client of a chain
+    pub fn with_chain_client(
+        mut self,
+        chain: BlockchainNetwork,
+        client: Arc<dyn BridgeChainClient>,
+    ) -> Self {
+        self.clients.insert(chain, client);
+        self
+    }
+
+    /// Drive transfers only while holding a lock, deposits still being recorded by every node
+    pub fn with_lease(mut self, lease: Arc<LeaseHolder>) -> Self {
+        self.lease = Some(lease);
+        self
+    }
+
+    /// Attest only deposits whose proof, served by the chain client, verifies against the trust
+    /// anchors rather than trusting the chain client alone
+    pub fn with_proof_verification(mut self, anchors: Arc<dyn TrustAnchors>) -> Self {
+        self.anchors = Some(anchors);
+        self
+    }
+
+    /// Get current timestamp
+    fn get_current_timestamp(&self) -> u64 {
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs()
+    }
+
+    fn chain_config(&self, chain: BlockchainNetwork) -> Result<&BridgeChainConfig, BridgeError> {
+        self.config.chains.get(&chain).ok_or_else(|| {
+            BridgeError::UnsupportedOperation(format!("Unconfigured chain: {}", chain))
+        })
+    }
+
+    fn client(&self, chain: BlockchainNetwork) -> Result<&Arc<dyn BridgeChainClient>, BridgeError> {
+        self.clients.get(&chain).ok_or_else(|| {
+            BridgeError::UnsupportedOperation(format!("No client for chain: {}", chain))
+        })
+    }
+
+    /// Record the deposits contained in a source chain event.
+    ///
+    /// Deposits that were already recorded are ignored, so replayed events are harmless.
+    pub async fn handle_event(
+        &self,
+        chain: BlockchainNetwork,
+        event: &Event,
+    ) -> Result<Vec<BridgeTransfer>, BridgeError> {
+        let chain_config = self.chain_config(chain)?;
+        let deposits = parse_deposits(
+            event,
+            &chain_config.deposit_contract,
+            &chain_config.deposit_topic,
+        );
+
+        let mut created = Vec::new();
+        for deposit in deposits {
+            if let Some(transfer) = self.record_deposit(deposit).await? {
+                created.push(transfer);
+            }
+        }
+        Ok(created)
+    }
+
+    /// Record a single deposit, returning `None` if it was already recorded
+    pub async fn record_deposit(
+        &self,
+        deposit: DepositEvent,
+    ) -> Result<Option<BridgeTransfer>, BridgeError> {
+        let deposit_key = deposit.deposit_key();
+        if self
+            .transfers
+            .get_transfer_by_deposit(&deposit_key)
+            .await?
+            .is_some()
+        {
+            log::debug!("bridge: deposit {} already recorded", deposit_key);
+            return Ok(None);
+        }
+
+        let token_bridges = self.bridges.get_token_bridges().await?;
+        let bridge = token_bridges.iter().find(|b| {
+            b.from_chain == deposit.chain
+                && b.to_chain == deposit.to_chain
+                && b.source_token.eq_ignore_ascii_case(&deposit.token)
+        });
+
+        let now = self.get_current_timestamp();
+        let mut transfer = BridgeTransfer {
+            id: uuid::Uuid::new_v4().to_string(),
+            bridge_id: bridge.map(|b| b.id.clone()).unwrap_or_default(),
+            mode: bridge.map(|b| b.mode).unwrap_or(BridgeMode::LockAndMint),
+            deposit,
+            destination_token: bridge
+                .map(|b| b.destination_token.clone())
+                .unwrap_or_default(),
+            amount_out: 0,
+            fee: 0,
+            state: TransferState::DepositDetected,
+            confirmations: 0,
+            attestation: None,
+            destination_tx_hash: None,
+            attempts: 0,
+            error: None,
+            created_at: now,
+            updated_at: now,
+        };
+
+        // Deposits that can not be settled are still recorded, so they can be refunded
+        let validation = match bridge {
+            None => Err(format!(
+                "Token bridge not supported: {} -> {} for token {}",
+                transfer.deposit.chain, transfer.deposit.to_chain, transfer.deposit.token
+            )),
+            Some(bridge) if !bridge.enabled => {
+                Err(format!("Token bridge is disabled: {}", bridge.id))
+            }
+            Some(bridge) if transfer.deposit.amount < bridge.min_amount => Err(format!(
+                "Amount is below minimum: {} < {}",
+                transfer.deposit.amount, bridge.min_amount
+            )),
+            Some(bridge)
+                if bridge
+                    .max_amount
+                    .is_some_and(|max| transfer.deposit.amount > max) =>
+            {
+                Err(format!(
+                    "Amount is above maximum: {} > {}",
+                    transfer.deposit.amount,
+                    bridge.max_amount.unwrap_or_default()
+                ))
+            }
+            Some(bridge) => bridge
+                .split_amount(transfer.deposit.amount)
+                .map(|(fee, amount_out)| {
+                    transfer.fee = fee;
+                    transfer.amount_out = amount_out;
+                })
+                .map_err(|e| e.to_string()),
+        };
+
+        if let Err(error) = validation {
+            log::warn!("bridge: rejected deposit {}: {}", deposit_key, error);
+            transfer.state = TransferState::Failed;
+            transfer.error = Some(error);
+        }
+
+        self.transfers.create_transfer(transfer.clone()).await?;
+        log::info!(
+            "bridge: recorded deposit {} as transfer {} ({})",
+            deposit_key,
+            transfer.id,
+            transfer.state
+        );
+
+        Ok(Some(transfer))
+    }
+
+    /// Move a transfer forward by at most one state and persist the result.
+    ///
+    /// Errors of the step are recorded on the transfer; after `max_attempts` failed attempts
+    /// the transfer is marked as failed.
+    pub async fn advance(&self, transfer_id: &str) -> Result<BridgeTransfer, BridgeError> {
+        let mut transfer = self.transfers.get_transfer(transfer_id).await?;
+        if transfer.state.is_terminal() {
+            return Ok(transfer);
+        }
+
+        match self.step(&transfer).await {
+            Ok(Some(next)) => {
+                if !transfer.state.can_transition_to(next.state) {
+                    return Err(BridgeError::Transaction(format!(
+                        "Invalid transfer transition: {} -> {}",
+                        transfer.state, next.state
+                    )));
+                }
+
+                log::info!(
+                    "bridge: transfer {} {} -> {}",
+                    transfer.id,
+                    transfer.state,
+                    next.state
+                );
+                transfer = next;
+                transfer.attempts = 0;
+                transfer.error = None;
+            }
+            // Waiting for confirmations, which the step may have recorded
+            Ok(None) => return self.transfers.get_transfer(transfer_id).await,
+            Err(err) => {
+                transfer.attempts += 1;
+                transfer.error = Some(err.to_string());
+                log::warn!(
+                    "bridge: transfer {} failed in {} (attempt {}): {}",
+                    transfer.id,
+                    transfer.state,
+                    transfer.attempts,
+                    err
+                );
+
+                if transfer.attempts >= self.config.max_attempts {
+                    transfer.state = TransferState::Failed;
+                }
+            }
+        }
+
+        transfer.updated_at = self.get_current_timestamp();
+        self.transfers.update_transfer(transfer.clone()).await?;
+
+        Ok(transfer)
+    }
+
+    /// Verify the proof of a deposit, if proof verification is enabled
+    async fn verify_deposit_proof(&self, deposit: &DepositEvent) -> Result<(), BridgeError> {
+        let Some(anchors) = &self.anchors else {
+            return Ok(());
+        };
+
+        let proof = self
+            .client(deposit.chain)?
+            .get_deposit_proof(deposit)
+            .await?
+            .ok_or_else(|| {
+                BridgeError::Chain(format!("No proof of deposit {}", deposit.deposit_key()))
+            })?;
+        let block = proof_block(&proof)?;
+        let anchor = anchors.anchor(deposit.chain, block).await?.ok_or_else(|| {
+            BridgeError::Chain(format!(
+                "No trust anchor for {} block {}",
+                deposit.chain, block
+            ))
+        })?;
+        verify_deposit(deposit, &proof, &anchor, self.chain_config(deposit.chain)?)?;
+
+        log::debug!(
+            "bridge: verified deposit {} in {} block {}",
+            deposit.deposit_key(),
+            deposit.chain,
+            block
+        );
+        Ok(())
+    }
+
+    /// Compute the next state of a transfer, `None` if it can not progress yet
+    async fn step(&self, transfer: &BridgeTransfer) -> Result<Option<BridgeTransfer>, BridgeError> {
+        let mut next = transfer.clone();
+        match transfer.state {
+            TransferState::DepositDetected => {
+                let source = transfer.deposit.chain;
+                let required = self.chain_config(source)?.required_confirmations;
+                next.confirmations = self
+                    .client(source)?
+                    .get_confirmations(&transfer.deposit.tx_hash)
+                    .await?;
+                if next.confirmations < required {
+                    if next.confirmations != transfer.confirmations {
+                        // Persist the progress without changing state
+                        let mut progress = next;
+                        progress.updated_at = self.get_current_timestamp();
+                        self.transfers.update_transfer(progress).await?;
+                    }
+                    return Ok(None);
+                }
+                next.state = TransferState::DepositConfirmed;
+            }
+            TransferState::DepositConfirmed => {
+                self.verify_deposit_proof(&transfer.deposit).await?;
+                next.attestation = Some(self.signer.attest(transfer).await?);
+                next.state = TransferState::Attested;
+            }
+            TransferState::Attested => {
+                let attestation = transfer.attestation.as_ref().ok_or_else(|| {
+                    BridgeError::Transaction("Attested transfer has no attestation".into())
+                })?;
+
+                let client = self.client(transfer.deposit.to_chain)?;
+                let tx_hash = match transfer.mode {
+                    BridgeMode::LockAndMint => client.submit_mint(transfer, attestation).await?,
+                    BridgeMode::BurnAndRelease => {
+                        client.submit_release(transfer, attestation).await?
+                    }
+                };
+                next.destination_tx_hash = Some(tx_hash);
+                next.state = TransferState::Submitted;
+            }
+            TransferState::Submitted => {
+                let destination = transfer.deposit.to_chain;
+                let tx_hash = transfer.destination_tx_hash.as_deref().ok_or_else(|| {
+                    BridgeError::Transaction("Submitted transfer has no transaction hash".into())
+                })?;
+
+                let required = self.chain_config(destination)?.required_confirmations;
+                let confirmations = self.client(destination)?.get_confirmations(tx_hash).await?;
+                if confirmations < required {
+                    return Ok(None);
+                }
+                next.state = TransferState::Completed;
+            }
+            TransferState::Completed | TransferState::Failed => return Ok(None),
+        }
+
+        Ok(Some(next))
+    }
+
+    /// Advance all in-flight transfers as far as possible, returning the number of state changes
+    pub async fn process_pending(&self) -> Result<usize, BridgeError> {
+        let mut changes = 0;
+        for state in [
+            TransferState::DepositDetected,
+            TransferState::DepositConfirmed,
+            TransferState::Attested,
+            TransferState::Submitted,
+        ] {
+            for transfer in self.transfers.list_transfers_by_state(state, None).await? {
+                let mut current = transfer;
+                loop {
+                    let next = self.advance(&current.id).await?;
+                    if next.state == current.state {
+                        break;
+                    }
+                    changes += 1;
+                    current = next;
+                }
+            }
+        }
+        Ok(changes)
+    }
+
+    /// Watch a source chain's event source for deposits and drive transfers until `stop` is set
+    pub async fn watch(
+        &self,
+        chain: BlockchainNetwork,
+        mut source: Box<dyn TaskSource>,
+        uid: u64,
+        stop: Arc<AtomicBool>,
+    ) {
+        log::info!("bridge: watching {} deposits", chain);
+        while !stop.load(Ordering::Relaxed) {
+            match source.acquire_task(uid, 0).await {
+                Ok(task) => {
+                    if let Err(err) = self.handle_event(chain, &task.event).await {
+                        log::error!("bridge: failed to handle {} event: {}", chain, err);
+                    }
+                }
+                Err(TaskError::NoMoreTask(_)) => {
+                    tokio::time::sleep(self.config.poll_interval).await;
+                }
+                Err(err) => {
+                    log::error!("bridge: failed to acquire {} event: {}", chain, err);
+                    tokio::time::sleep(self.config.poll_interval).await;
+                }
+            }
+
+            if let Some(lease) = &self.lease {
+                if lease.hold().await.is_none() {
+                    continue;
+                }
+            }
+            if let Err(err) = self.process_pending().await {
+                log::error!("bridge: failed to process pending transfers: {}", err);
+            }
+        }
+        log::info!("bridge: stopped watching {} deposits", chain);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bridge::attestation::attestation_digest;
+    use crate::bridge::storage::{MemoryBridgeStorage, MemoryTransferStorage};
+    use crate::bridge::types::TokenBridge;
+    use base64::Engine;
+    use r3e_event::source::events::NeoContractNotification;
+    use serde_json::json;
+    use sha2::{Digest, Sha256};
+    use std::sync::Mutex;
+
+    const NEO_LOCK: &str = "0x5f1a6e2bb0d8c94a2f3b1c7d9e0a4b6c8d2e1f3a";
+    const ETH_LOCK: &str = "0x1d3e5f7a9b2c4d6e8f0a1b3c5d7e9f2a4b6c8d0e";
+    const DEPOSIT_TOPIC: &str =
+        "0x8f2a3c0d6e4b1a9c7d5e3f1b0a2c4e6d8f1a3b5c7d9e0f2a4b6c8d0e1f3a5b7c";
+    const NEO_GAS: &str = "0xd2a4cff31913016155e38e474a2c06d08be276cf";
+    const ETH_GAS: &str = "0x8c6f28f2f1a3c87f0f938b96d27520d9751ec8d9";
+    const ETH_RECIPIENT: &str = "0x4b0897b0513fdc7c541b6d9d7e929c4e5364d2db";
+    const NEO_RECIPIENT: &str = "NbnjKGMBJzJ6j5PHeYhjJDaQ5Vy5UYu4Fv";
+
+    #[derive(Default)]
+    struct MockChainClient {
+        confirmations: Mutex<HashMap<String, u64>>,
+        submissions: Mutex<Vec<String>>,
+        fail_submit: AtomicBool,
+    }
+
+    impl MockChainClient {
+        fn confirm(&self, tx_hash: &str, confirmations: u64) {
+            self.confirmations
+                .lock()
+                .unwrap()
+                .insert(tx_hash.to_string(), confirmations);
+        }
+
+        fn submit(&self, transfer: &BridgeTransfer) -> Result<String, BridgeError> {
+            if self.fail_submit.load(Ordering::SeqCst) {
+                return Err(BridgeError::Chain("node unavailable".into()));
+            }
+            let tx_hash = format!("0xsettle-{}", transfer.id);
+            self.submissions.lock().unwrap().push(tx_hash.clone());
+            Ok(tx_hash)
+        }
+    }
+
+    #[async_trait]
+    impl BridgeChainClient for MockChainClient {
+        async fn get_confirmations(&self, tx_hash: &str) -> Result<u64, BridgeError> {
+            Ok(self
+                .confirmations
+                .lock()
+                .unwrap()
+                .get(tx_hash)
+                .copied()
+                .unwrap_or(0))
+        }
+
+        async fn submit_mint(
+            &self,
+            transfer: &BridgeTransfer,
+            _attestation: &BridgeAttestation,
+        ) -> Result<String, BridgeError> {
+            self.submit(transfer)
+        }
+
+        async fn submit_release(
+            &self,
+            transfer: &BridgeTransfer,
+            _attestation: &BridgeAttestation,
+        ) -> Result<String, BridgeError> {
+            self.submit(transfer)
+        }
+    }
+
+    struct MockSigner;
+
+    #[async_trait]
+    impl AttestationSigner for MockSigner {
+        async fn attest(
+            &self,
+            transfer: &BridgeTransfer,
+        ) -> Result<BridgeAttestation, BridgeError> {
+            Ok(BridgeAttestation {
+                digest: hex::encode(attestation_digest(transfer)),
+                signature: "signature".to_string(),
+                signer: "test".to_string(),
+                tee_report: None,
+                created_at: 0,
+            })
+        }
+    }
+
+    fn orchestrator(
+        neo: Arc<MockChainClient>,
+        eth: Arc<MockChainClient>,
+        max_attempts: u32,
+    ) -> BridgeOrchestrator {
+        let chain = |deposit_contract: &str, required_confirmations| BridgeChainConfig {
+            deposit_contract: deposit_contract.to_string(),
+            deposit_topic: DEPOSIT_TOPIC.to_string(),
+            required_confirmations,
+        };
+        let config = BridgeConfig {
+            chains: HashMap::from([
+                (BlockchainNetwork::NeoN3, chain(NEO_LOCK, 2)),
+                (BlockchainNetwork::Ethereum, chain(ETH_LOCK, 12)),
+            ]),
+            max_attempts,
+            ..Default::default()
+        };
+
+        BridgeOrchestrator::new(
+            Arc::new(MemoryBridgeStorage::with_defaults()),
+            Arc::new(MemoryTransferStorage::new()),
+            Arc::new(MockSigner),
+            config,
+        )
+        .with_chain_client(BlockchainNetwork::NeoN3, neo)
+        .with_chain_client(BlockchainNetwork::Ethereum, eth)
+    }
+
+    fn neo_deposit(tx_hash: &str, amount: u64) -> DepositEvent {
+        DepositEvent {
+            chain: BlockchainNetwork::NeoN3,
+            tx_hash: tx_hash.to_string(),
+            log_index: 0,
+            token: NEO_GAS.to_string(),
+            sender: "0x0000000000000000000000000000000000000001".to_string(),
+            amount,
+            to_chain: BlockchainNetwork::Ethereum,
+            recipient: ETH_RECIPIENT.to_string(),
+        }
+    }
+
+    fn token_bridge(fee_percentage: f64) -> TokenBridge {
+        TokenBridge {
+            id: "test".to_string(),
+            from_chain: BlockchainNetwork::NeoN3,
+            to_chain: BlockchainNetwork::Ethereum,
+            source_token: NEO_GAS.to_string(),
+            destination_token: ETH_GAS.to_string(),
+            fee_percentage,
+            min_amount: 0,
+            max_amount: None,
+            enabled: true,
+            mode: BridgeMode::LockAndMint,
+        }
+    }
+
+    fn byte_string(bytes: &[u8]) -> serde_json::Value {
+        json!({
+            "type": "ByteString",
+            "value": base64::engine::general_purpose::STANDARD.encode(bytes),
+        })
+    }
+
+    fn hash160(address: &str) -> serde_json::Value {
+        let mut bytes = hex::decode(address.trim_start_matches("0x")).unwrap();
+        bytes.reverse();
+        byte_string(&bytes)
+    }
+
+    fn abi_word(value: usize) -> Vec<u8> {
+        let mut word = vec![0u8; 24];
+        word.extend((value as u64).to_be_bytes());
+        word
+    }
+
+    fn abi_string(value: &str) -> Vec<u8> {
+        let mut encoded = abi_word(value.len());
+        encoded.extend(value.as_bytes());
+        encoded.resize(32 + value.len().div_ceil(32) * 32, 0);
+        encoded
+    }
+
+    fn abi_deposit_data(amount: u64, to_chain: &str, recipient: &str) -> String {
+        let to_chain = abi_string(to_chain);
+        let mut data = abi_word(amount as usize);
+        data.extend(abi_word(96));
+        data.extend(abi_word(96 + to_chain.len()));
+        data.extend(to_chain);
+        data.extend(abi_string(recipient));
+        format!("0x{}", hex::encode(data))
+    }
+
+    fn address_topic(address: &str) -> String {
+        format!("0x{:0>64}", address.trim_start_matches("0x"))
+    }
+
+    #[test]
+    fn test_parse_neo_deposits() {
+        let deposit = |contract: &str, eventname: &str| {
+            json!({
+                "contract": contract,
+                "eventname": eventname,
+                "state": {
+                    "type": "Array",
+                    "value": [
+                        hash160(NEO_GAS),
+                        hash160("0x0000000000000000000000000000000000000001"),
+                        { "type": "Integer", "value": "500" },
+                        byte_string(b"ethereum"),
+                        byte_string(ETH_RECIPIENT.as_bytes()),
+                    ],
+                },
+            })
+        };
+        let notifications = json!([
+            deposit(NEO_GAS, "Deposit"),
+            deposit(NEO_LOCK, "Transfer"),
+            deposit(NEO_LOCK, "Deposit"),
+        ]);
+        let event = Event::NeoContractNotification(NeoContractNotification {
+            tx_hash: "0xneo".to_string(),
+            notifications: notifications.to_string(),
+        });
+
+        let deposits = parse_deposits(&event, NEO_LOCK, DEPOSIT_TOPIC);
+        assert_eq!(deposits.len(), 1);
+        let deposit = &deposits[0];
+        assert_eq!(deposit.chain, BlockchainNetwork::NeoN3);
+        assert_eq!(deposit.log_index, 2);
+        assert_eq!(deposit.token, NEO_GAS);
+        assert_eq!(deposit.sender, "0x0000000000000000000000000000000000000001");
+        assert_eq!(deposit.amount, 500);
+        assert_eq!(deposit.to_chain, BlockchainNetwork::Ethereum);
+        assert_eq!(deposit.recipient, ETH_RECIPIENT);
+        assert_eq!(deposit.deposit_key(), "neo_n3:0xneo:2");
+
+        assert!(parse_deposits(&event, ETH_LOCK, DEPOSIT_TOPIC).is_empty());
+    }
+
+    #[test]
+    fn test_parse_ethereum_deposits() {
+        let log = |topic0: &str, log_index: &str| {
+            json!({
+                "topics": [
+                    topic0,
+                    address_topic(ETH_GAS),
+                    address_topic(ETH_RECIPIENT),
+                ],
+                "data": abi_deposit_data(750, "neo_n3", NEO_RECIPIENT),
+                "logIndex": log_index,
+                "transactionHash": "0xeth",
+            })
+        };
+        let event = Event::EthereumContractEvent {
+            contract_address: ETH_LOCK.to_uppercase().replace("0X", "0x"),
+            events: vec![
+                log(&address_topic("0x01"), "0x3"),
+                log(DEPOSIT_TOPIC, "0x1f"),
+            ],
+        };
+
+        let deposits = parse_deposits(&event, ETH_LOCK, DEPOSIT_TOPIC);
+        assert_eq!(deposits.len(), 1);
+        let deposit = &deposits[0];
+        assert_eq!(deposit.chain, BlockchainNetwork::Ethereum);
+        assert_eq!(deposit.tx_hash, "0xeth");
+        assert_eq!(deposit.log_index, 31);
+        assert_eq!(deposit.token, ETH_GAS);
+        assert_eq!(deposit.sender, ETH_RECIPIENT);
+        assert_eq!(deposit.amount, 750);
+        assert_eq!(deposit.to_chain, BlockchainNetwork::NeoN3);
+        assert_eq!(deposit.recipient, NEO_RECIPIENT);
+
+        assert!(parse_deposits(&event, NEO_LOCK, DEPOSIT_TOPIC).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_record_deposit() {
+        let bridge = orchestrator(Default::default(), Default::default(), 3);
+
+        let transfer = bridge
+            .record_deposit(neo_deposit("0x01", 1000))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(transfer.bridge_id, "neo-eth-gas");
+        assert_eq!(transfer.state, TransferState::DepositDetected);
+        assert_eq!(transfer.destination_token, ETH_GAS);
+        assert_eq!((transfer.fee, transfer.amount_out), (1, 999));
+
+        // Replayed deposits are ignored
+        assert!(bridge
+            .record_deposit(neo_deposit("0x01", 1000))
+            .await
+            .unwrap()
+            .is_none());
+        let stored = bridge
+            .transfers
+            .get_transfer_by_deposit("neo_n3:0x01:0")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(stored.id, transfer.id);
+
+        // Deposits that can not be settled are recorded as failed
+        let mut unsupported = neo_deposit("0x02", 100);
+        unsupported.token = ETH_GAS.to_string();
+        for (deposit, error) in [
+            (unsupported, "not supported"),
+            (neo_deposit("0x03", 0), "below minimum"),
+            (neo_deposit("0x04", 1001), "above maximum"),
+        ] {
+            let transfer = bridge.record_deposit(deposit).await.unwrap().unwrap();
+            assert_eq!(transfer.state, TransferState::Failed);
+            assert!(transfer.error.unwrap().contains(error));
+            assert_eq!(transfer.amount_out, 0);
+        }
+    }
+
+    #[test]
+    fn test_split_amount() {
+        let bridge = token_bridge(0.1);
+        assert_eq!(bridge.fee_bps().unwrap(), 10);
+        assert_eq!(bridge.split_amount(10_000).unwrap(), (10, 9_990));
+        // The fee is rounded up
+        assert_eq!(bridge.split_amount(1).unwrap(), (1, 0));
+        assert_eq!(bridge.split_amount(0).unwrap(), (0, 0));
+
+        // 0.07 * 100000 / 100 is slightly above 70 in floating point
+        assert_eq!(
+            token_bridge(0.07).split_amount(100_000).unwrap(),
+            (70, 99_930)
+        );
+
+        let (fee, amount_out) = token_bridge(0.5).split_amount(u64::MAX).unwrap();
+        assert_eq!(fee, u64::MAX / 200 + 1);
+        assert_eq!(fee + amount_out, u64::MAX);
+
+        assert_eq!(
+            token_bridge(0.0).split_amount(u64::MAX).unwrap(),
+            (0, u64::MAX)
+        );
+        assert_eq!(
+            token_bridge(100.0).split_amount(u64::MAX).unwrap(),
+            (u64::MAX, 0)
+        );
+
+        for fee_percentage in [100.01, -0.01, f64::NAN, f64::INFINITY] {
+            assert!(token_bridge(fee_percentage).split_amount(100).is_err());
+        }
+    }
+
+    #[test]
+    fn test_attestation_digest() {
+        let transfer = BridgeTransfer {
+            id: "transfer-1".to_string(),
+            bridge_id: "neo-eth-gas".to_string(),
+            mode: BridgeMode::LockAndMint,
+            deposit: neo_deposit("0xAB", 1000),
+            destination_token: ETH_GAS.to_uppercase(),
+            amount_out: 999,
+            fee: 1,
+            state: TransferState::DepositConfirmed,
+            confirmations: 2,
+            attestation: None,
+            destination_tx_hash: None,
+            attempts: 0,
+            error: None,
+            created_at: 0,
+            updated_at: 0,
+        };
+
+        let message = format!(
+            "r3e-bridge:v1|transfer-1|neo_n3:0xab:0|neo_n3|ethereum|{}|{}|999|mint",
+            ETH_GAS, ETH_RECIPIENT
+        );
+        let digest = attestation_digest(&transfer);
+        assert_eq!(digest, Sha256::digest(message.as_bytes()).to_vec());
+
+        // State and bookkeeping are not attested
+        let mut progressed = transfer.clone();
+        progressed.state = TransferState::Submitted;
+        progressed.attempts = 3;
+        assert_eq!(attestation_digest(&progressed), digest);
+
+        let mut other = transfer.clone();
+        other.deposit.recipient = "0x0000000000000000000000000000000000000002".to_string();
+        assert_ne!(attestation_digest(&other), digest);
+        let mut other = transfer.clone();
+        other.amount_out = 1000;
+        assert_ne!(attestation_digest(&other), digest);
+        let mut other = transfer.clone();
+        other.mode = BridgeMode::BurnAndRelease;
+        assert_ne!(attestation_digest(&other), digest);
+        let mut other = transfer;
+        other.deposit.log_index = 1;
+        assert_ne!(attestation_digest(&other), digest);
+    }
+
+    #[tokio::test]
+    async fn test_advance_transfer() {
+        let neo = Arc::new(MockChainClient::default());
+        let eth = Arc::new(MockChainClient::default());
+        let bridge = orchestrator(neo.clone(), eth.clone(), 3);
+        let id = bridge
+            .record_deposit(neo_deposit("0x01", 1000))
+            .await
+            .unwrap()
+            .unwrap()
+            .id;
+
+        // Waiting for confirmations records the progress
+        neo.confirm("0x01", 1);
+        let transfer = bridge.advance(&id).await.unwrap();
+        assert_eq!(transfer.state, TransferState::DepositDetected);
+        assert_eq!(transfer.confirmations, 1);
+        assert_eq!(
+            bridge
+                .transfers
+                .get_transfer(&id)
+                .await
+                .unwrap()
+                .confirmations,
+            1
+        );
+
+        neo.confirm("0x01", 2);
+        let transfer = bridge.advance(&id).await.unwrap();
+        assert_eq!(transfer.state, TransferState::DepositConfirmed);
+
+        let transfer = bridge.advance(&id).await.unwrap();
+        assert_eq!(transfer.state, TransferState::Attested);
+        assert_eq!(
+            transfer.attestation.as_ref().unwrap().digest,
+            hex::encode(attestation_digest(&transfer))
+        );
+
+        let transfer = bridge.advance(&id).await.unwrap();
+        assert_eq!(transfer.state, TransferState::Submitted);
+        let tx_hash = transfer.destination_tx_hash.unwrap();
+        assert_eq!(*eth.submissions.lock().unwrap(), vec![tx_hash.clone()]);
+
+        let transfer = bridge.advance(&id).await.unwrap();
+        assert_eq!(transfer.state, TransferState::Submitted);
+
+        eth.confirm(&tx_hash, 12);
+        let transfer = bridge.advance(&id).await.unwrap();
+        assert_eq!(transfer.state, TransferState::Completed);
+
+        // Terminal transfers do not move, nor settle twice
+        let transfer = bridge.advance(&id).await.unwrap();
+        assert_eq!(transfer.state, TransferState::Completed);
+        assert_eq!(eth.submissions.lock().unwrap().len(), 1);
+        assert!(neo.submissions.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_advance_failures() {
+        let neo = Arc::new(MockChainClient::default());
+        let eth = Arc::new(MockChainClient::default());
+        let bridge = orchestrator(neo.clone(), eth.clone(), 2);
+        let id = bridge
+            .record_deposit(neo_deposit("0x01", 1000))
+            .await
+            .unwrap()
+            .unwrap()
+            .id;
+        neo.confirm("0x01", 2);
+        bridge.advance(&id).await.unwrap();
+        bridge.advance(&id).await.unwrap();
+
+        eth.fail_submit.store(true, Ordering::SeqCst);
+        let transfer = bridge.advance(&id).await.unwrap();
+        assert_eq!(transfer.state, TransferState::Attested);
+        assert_eq!(transfer.attempts, 1);
+        assert!(transfer.error.unwrap().contains("node unavailable"));
+
+        let transfer = bridge.advance(&id).await.unwrap();
+        assert_eq!(transfer.state, TransferState::Failed);
+        assert_eq!(transfer.attempts, 2);
+        assert!(eth.submissions.lock().unwrap().is_empty());
+
+        eth.fail_submit.store(false, Ordering::SeqCst);
+        let transfer = bridge.advance(&id).await.unwrap();
+        assert_eq!(transfer.state, TransferState::Failed);
+        assert!(eth.submissions.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_process_pending() {
+        let neo = Arc::new(MockChainClient::default());
+        let eth = Arc::new(MockChainClient::default());
+        let bridge = orchestrator(neo.clone(), eth.clone(), 3);
+
+        let mut deposit = neo_deposit("0xeth", 500);
+        deposit.chain = BlockchainNetwork::Ethereum;
+        deposit.token = ETH_GAS.to_string();
+        deposit.to_chain = BlockchainNetwork::NeoN3;
+        deposit.recipient = NEO_RECIPIENT.to_string();
+        let release = bridge.record_deposit(deposit).await.unwrap().unwrap();
+        assert_eq!(release.mode, BridgeMode::BurnAndRelease);
+        let mint = bridge
+            .record_deposit(neo_deposit("0xneo", 500))
+            .await
+            .unwrap()
+            .unwrap();
+
+        // Neither deposit is final yet
+        eth.confirm("0xeth", 11);
+        assert_eq!(bridge.process_pending().await.unwrap(), 0);
+
+        // The mint goes as far as waiting for its settlement, the release all the way
+        neo.confirm("0xneo", 2);
+        eth.confirm("0xeth", 12);
+        neo.confirm(&format!("0xsettle-{}", release.id), 2);
+        assert_eq!(bridge.process_pending().await.unwrap(), 7);
+        let transfers = &bridge.transfers;
+        assert_eq!(
+            transfers.get_transfer(&release.id).await.unwrap().state,
+            TransferState::Completed
+        );
+        assert_eq!(
+            transfers.get_transfer(&mint.id).await.unwrap().state,
+            TransferState::Submitted
+        );
+
+        eth.confirm(&format!("0xsettle-{}", mint.id), 12);
+        assert_eq!(bridge.process_pending().await.unwrap(), 1);
+        assert_eq!(
+            transfers.get_transfer(&mint.id).await.unwrap().state,
+            TransferState::Completed
+        );
+        assert_eq!(bridge.process_pending().await.unwrap(), 0);
+    }
+}
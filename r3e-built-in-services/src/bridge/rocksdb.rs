@@ -0,0 +1,169 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+use async_trait::async_trait;
+use r3e_store::rocksdb::RocksDBStore;
+use r3e_store::{KvStore, SortedKvStore};
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::bridge::storage::TransferStorage;
+use crate::bridge::types::{BridgeError, BridgeTransfer, TransferState};
+
+/// RocksDB implementation of TransferStorage
+pub struct RocksDBTransferStorage {
+    db: Arc<RocksDBStore>,
+    transfers_cf: String,
+    deposits_cf: String,
+}
+
+impl RocksDBTransferStorage {
+    /// Create a new RocksDB transfer storage
+    pub async fn new<P: AsRef<Path>>(db_path: P) -> Result<Self, BridgeError> {
+        let db = RocksDBStore::new(db_path)
+            .map_err(|e| BridgeError::Storage(format!("Failed to create RocksDB store: {}", e)))?;
+
+        let transfers_cf = "bridge_transfers".to_string();
+        let deposits_cf = "bridge_deposits".to_string();
+
+        Ok(Self {
+            db: Arc::new(db),
+            transfers_cf,
+            deposits_cf,
+        })
+    }
+
+    fn put_transfer(&self, transfer: &BridgeTransfer) -> Result<(), BridgeError> {
+        let value = serde_json::to_vec(transfer)
+            .map_err(|e| BridgeError::Storage(format!("Failed to serialize transfer: {}", e)))?;
+
+        let input = r3e_store::PutInput {
+            key: transfer.id.as_bytes(),
+            value: &value,
+            if_not_exists: false,
+        };
+
+        self.db
+            .put(&self.transfers_cf, input)
+            .map_err(|e| BridgeError::Storage(format!("Failed to store transfer: {}", e)))
+    }
+}
+
+#[async_trait]
+impl TransferStorage for RocksDBTransferStorage {
+    async fn create_transfer(&self, transfer: BridgeTransfer) -> Result<(), BridgeError> {
+        // Claim the deposit first, so a replayed deposit can never create a second transfer
+        let deposit_key = transfer.deposit.deposit_key();
+        let input = r3e_store::PutInput {
+            key: deposit_key.as_bytes(),
+            value: transfer.id.as_bytes(),
+            if_not_exists: true,
+        };
+
+        match self.db.put(&self.deposits_cf, input) {
+            Ok(()) => {}
+            Err(r3e_store::PutError::AlreadyExists) => {
+                return Err(BridgeError::InvalidInput(format!(
+                    "Transfer already exists for deposit: {}",
+                    deposit_key
+                )))
+            }
+            Err(e) => {
+                return Err(BridgeError::Storage(format!(
+                    "Failed to store deposit: {}",
+                    e
+                )))
+            }
+        }
+
+        self.put_transfer(&transfer)
+    }
+
+    async fn get_transfer(&self, transfer_id: &str) -> Result<BridgeTransfer, BridgeError> {
+        match self.db.get(&self.transfers_cf, transfer_id.as_bytes()) {
+            Ok(value) => serde_json::from_slice::<BridgeTransfer>(&value).map_err(|e| {
+                BridgeError::Storage(format!("Failed to deserialize transfer: {}", e))
+            }),
+            Err(r3e_store::GetError::NoSuchKey) => Err(BridgeError::NotFound(format!(
+                "Transfer not found: {}",
+                transfer_id
+            ))),
+            Err(e) => Err(BridgeError::Storage(format!(
+                "Failed to get transfer: {}",
+                e
+            ))),
+        }
+    }
+
+    async fn get_transfer_by_deposit(
+        &self,
+        deposit_key: &str,
+    ) -> Result<Option<BridgeTransfer>, BridgeError> {
+        match self.db.get(&self.deposits_cf, deposit_key.as_bytes()) {
+            Ok(value) => {
+                let transfer_id = String::from_utf8(value).map_err(|e| {
+                    BridgeError::Storage(format!("Invalid transfer ID for deposit: {}", e))
+                })?;
+                self.get_transfer(&transfer_id).await.map(Some)
+            }
+            Err(r3e_store::GetError::NoSuchKey) => Ok(None),
+            Err(e) => Err(BridgeError::Storage(format!(
+                "Failed to get deposit: {}",
+                e
+            ))),
+        }
+    }
+
+    async fn update_transfer(&self, transfer: BridgeTransfer) -> Result<(), BridgeError> {
+        // Make sure the transfer exists
+        self.get_transfer(&transfer.id).await?;
+        self.put_transfer(&transfer)
+    }
+
+    async fn list_transfers_by_state(
+        &self,
+        state: TransferState,
+        limit: Option<u32>,
+    ) -> Result<Vec<BridgeTransfer>, BridgeError> {
+        let limit = limit.unwrap_or(100) as usize;
+        let mut transfers = Vec::new();
+        let mut start_key = Vec::new();
+
+        loop {
+            let input = r3e_store::ScanInput {
+                start_key: &start_key,
+                start_exclusive: !start_key.is_empty(),
+                end_key: &[],
+                end_inclusive: false,
+                reverse: false,
+                max_count: 1000,
+            };
+
+            let output = self
+                .db
+                .scan(&self.transfers_cf, input)
+                .map_err(|e| BridgeError::Storage(format!("Failed to scan transfers: {}", e)))?;
+
+            for (_, value) in &output.kvs {
+                let transfer = serde_json::from_slice::<BridgeTransfer>(value).map_err(|e| {
+                    BridgeError::Storage(format!("Failed to deserialize transfer: {}", e))
+                })?;
+
+                if transfer.state == state {
+                    transfers.push(transfer);
+                }
+            }
+
+            match output.kvs.last() {
+                Some((key, _)) if output.has_more => start_key = key.clone(),
+                _ => break,
+            }
+        }
+
+        // Sort by creation time (oldest first)
+        transfers.sort_by(|a, b| a.created_at.cmp(&b.created_at));
+        transfers.truncate(limit);
+
+        Ok(transfers)
+    }
+}
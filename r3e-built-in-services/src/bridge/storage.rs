@@ -2,8 +2,8 @@
 // All Rights Reserved
 
 use crate::bridge::types::{
-    AssetWrapper, BlockchainNetwork, BridgeError, BridgeTransaction, BridgeTransactionStatus,
-    MessageBridge, TokenBridge,
+    AssetWrapper, BlockchainNetwork, BridgeError, BridgeMode, BridgeTransaction,
+    BridgeTransactionStatus, BridgeTransfer, MessageBridge, TokenBridge, TransferState,
 };
 use async_trait::async_trait;
 use std::collections::HashMap;
@@ -115,6 +115,7 @@ impl MemoryBridgeStorage {
                 min_amount: 1,
                 max_amount: Some(1000),
                 enabled: true,
+                mode: BridgeMode::LockAndMint,
             },
             TokenBridge {
                 id: "eth-neo-gas".to_string(),
@@ -126,6 +127,7 @@ impl MemoryBridgeStorage {
                 min_amount: 1,
                 max_amount: Some(1000),
                 enabled: true,
+                mode: BridgeMode::BurnAndRelease,
             },
         ];
 
@@ -493,3 +495,145 @@ impl BridgeStorage for MemoryBridgeStorage {
         Ok(())
     }
 }
+
+/// Trait defining the persistent storage of bridge transfers
+#[async_trait]
+pub trait TransferStorage: Send + Sync {
+    /// Create a new transfer, failing if its deposit was already recorded
+    async fn create_transfer(&self, transfer: BridgeTransfer) -> Result<(), BridgeError>;
+
+    /// Get a transfer by ID
+    async fn get_transfer(&self, transfer_id: &str) -> Result<BridgeTransfer, BridgeError>;
+
+    /// Get a transfer by its deposit key
+    async fn get_transfer_by_deposit(
+        &self,
+        deposit_key: &str,
+    ) -> Result<Option<BridgeTransfer>, BridgeError>;
+
+    /// Update a transfer
+    async fn update_transfer(&self, transfer: BridgeTransfer) -> Result<(), BridgeError>;
+
+    /// List transfers in a state, oldest first
+    async fn list_transfers_by_state(
+        &self,
+        state: TransferState,
+        limit: Option<u32>,
+    ) -> Result<Vec<BridgeTransfer>, BridgeError>;
+}
+
+/// In-memory implementation of the transfer storage
+#[derive(Default)]
+pub struct MemoryTransferStorage {
+    /// Transfers by ID
+    transfers: RwLock<HashMap<String, BridgeTransfer>>,
+
+    /// Transfer IDs by deposit key
+    deposits: RwLock<HashMap<String, String>>,
+}
+
+impl MemoryTransferStorage {
+    /// Create a new memory-based transfer storage
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl TransferStorage for MemoryTransferStorage {
+    async fn create_transfer(&self, transfer: BridgeTransfer) -> Result<(), BridgeError> {
+        let mut deposits = self
+            .deposits
+            .write()
+            .map_err(|e| BridgeError::Storage(format!("Failed to acquire write lock: {}", e)))?;
+        let mut transfers = self
+            .transfers
+            .write()
+            .map_err(|e| BridgeError::Storage(format!("Failed to acquire write lock: {}", e)))?;
+
+        // Each deposit may only be settled once
+        let deposit_key = transfer.deposit.deposit_key();
+        if deposits.contains_key(&deposit_key) || transfers.contains_key(&transfer.id) {
+            return Err(BridgeError::InvalidInput(format!(
+                "Transfer already exists for deposit: {}",
+                deposit_key
+            )));
+        }
+
+        deposits.insert(deposit_key, transfer.id.clone());
+        transfers.insert(transfer.id.clone(), transfer);
+
+        Ok(())
+    }
+
+    async fn get_transfer(&self, transfer_id: &str) -> Result<BridgeTransfer, BridgeError> {
+        let transfers = self
+            .transfers
+            .read()
+            .map_err(|e| BridgeError::Storage(format!("Failed to acquire read lock: {}", e)))?;
+
+        transfers
+            .get(transfer_id)
+            .cloned()
+            .ok_or_else(|| BridgeError::NotFound(format!("Transfer not found: {}", transfer_id)))
+    }
+
+    async fn get_transfer_by_deposit(
+        &self,
+        deposit_key: &str,
+    ) -> Result<Option<BridgeTransfer>, BridgeError> {
+        let transfer_id = {
+            let deposits = self
+                .deposits
+                .read()
+                .map_err(|e| BridgeError::Storage(format!("Failed to acquire read lock: {}", e)))?;
+            deposits.get(deposit_key).cloned()
+        };
+
+        match transfer_id {
+            Some(transfer_id) => self.get_transfer(&transfer_id).await.map(Some),
+            None => Ok(None),
+        }
+    }
+
+    async fn update_transfer(&self, transfer: BridgeTransfer) -> Result<(), BridgeError> {
+        let mut transfers = self
+            .transfers
+            .write()
+            .map_err(|e| BridgeError::Storage(format!("Failed to acquire write lock: {}", e)))?;
+
+        if !transfers.contains_key(&transfer.id) {
+            return Err(BridgeError::NotFound(format!(
+                "Transfer not found: {}",
+                transfer.id
+            )));
+        }
+
+        transfers.insert(transfer.id.clone(), transfer);
+
+        Ok(())
+    }
+
+    async fn list_transfers_by_state(
+        &self,
+        state: TransferState,
+        limit: Option<u32>,
+    ) -> Result<Vec<BridgeTransfer>, BridgeError> {
+        let transfers = self
+            .transfers
+            .read()
+            .map_err(|e| BridgeError::Storage(format!("Failed to acquire read lock: {}", e)))?;
+
+        let mut matched: Vec<BridgeTransfer> = transfers
+            .values()
+            .filter(|t| t.state == state)
+            .cloned()
+            .collect();
+
+        // Sort by creation time (oldest first)
+        matched.sort_by(|a, b| a.created_at.cmp(&b.created_at));
+        matched.truncate(limit.unwrap_or(100) as usize);
+
+        Ok(matched)
+    }
+}
@@ -60,6 +60,24 @@ impl std::fmt::Display for BlockchainNetwork {
     }
 }
 
+impl std::str::FromStr for BlockchainNetwork {
+    type Err = BridgeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "neo_n3" | "neo" => Ok(BlockchainNetwork::NeoN3),
+            "ethereum" | "eth" => Ok(BlockchainNetwork::Ethereum),
+            "binance_smart_chain" | "bsc" => Ok(BlockchainNetwork::BinanceSmartChain),
+            "polygon" => Ok(BlockchainNetwork::Polygon),
+            "solana" => Ok(BlockchainNetwork::Solana),
+            _ => Err(BridgeError::InvalidInput(format!(
+                "Unsupported blockchain network: {}",
+                s
+            ))),
+        }
+    }
+}
+
 /// Bridge transaction status
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum BridgeTransactionStatus {
@@ -155,6 +173,51 @@ pub struct TokenBridge {
 
     /// Is the bridge enabled?
     pub enabled: bool,
+
+    /// How the destination chain settles transfers
+    #[serde(default)]
+    pub mode: BridgeMode,
+}
+
+/// Basis points in a whole amount
+pub const BPS_DENOMINATOR: u64 = 10_000;
+
+impl TokenBridge {
+    /// Fee in basis points, the configured percentage rounded to a hundredth of a percent
+    pub fn fee_bps(&self) -> Result<u64, BridgeError> {
+        let bps = (self.fee_percentage * 100.0).round();
+        if !(0.0..=BPS_DENOMINATOR as f64).contains(&bps) {
+            return Err(BridgeError::InvalidInput(format!(
+                "Fee percentage must be between 0 and 100: {}",
+                self.fee_percentage
+            )));
+        }
+        Ok(bps as u64)
+    }
+
+    /// Fee and amount paid out of a deposit of `amount`, the fee rounded up
+    pub fn split_amount(&self, amount: u64) -> Result<(u64, u64), BridgeError> {
+        let fee = u128::from(amount)
+            .checked_mul(u128::from(self.fee_bps()?))
+            .map(|fee| fee.div_ceil(u128::from(BPS_DENOMINATOR)))
+            .and_then(|fee| u64::try_from(fee).ok())
+            .ok_or_else(|| BridgeError::InvalidInput(format!("Fee overflow: {}", amount)))?;
+        let amount_out = amount.checked_sub(fee).ok_or_else(|| {
+            BridgeError::InvalidInput(format!("Fee {} exceeds amount {}", fee, amount))
+        })?;
+        Ok((fee, amount_out))
+    }
+}
+
+/// How a token bridge settles transfers on the destination chain
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum BridgeMode {
+    /// Tokens are locked on the source chain and wrapped tokens are minted
+    #[default]
+    LockAndMint,
+
+    /// Wrapped tokens are burned on the source chain and the originals are released
+    BurnAndRelease,
 }
 
 /// Message bridge configuration
@@ -272,3 +335,170 @@ pub struct MessagePassingRequest {
     /// Message data
     pub message: Vec<u8>,
 }
+
+/// Bridge transfer state
+///
+/// Transfers move forward one state at a time:
+/// `DepositDetected -> DepositConfirmed -> Attested -> Submitted -> Completed`.
+/// Any non-terminal state may move to `Failed`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum TransferState {
+    /// Deposit seen on the source chain, waiting for confirmations
+    DepositDetected,
+
+    /// Deposit has enough confirmations on the source chain
+    DepositConfirmed,
+
+    /// Transfer has been attested by the bridge signer
+    Attested,
+
+    /// Mint or release transaction submitted on the destination chain
+    Submitted,
+
+    /// Destination transaction confirmed
+    Completed,
+
+    /// Transfer failed and needs manual intervention (e.g. a refund)
+    Failed,
+}
+
+impl TransferState {
+    /// Check if the transfer reached a final state
+    pub fn is_terminal(&self) -> bool {
+        matches!(self, TransferState::Completed | TransferState::Failed)
+    }
+
+    /// Check if the transfer may move from this state to `next`
+    pub fn can_transition_to(&self, next: TransferState) -> bool {
+        use TransferState::*;
+        match (self, next) {
+            (DepositDetected, DepositConfirmed)
+            | (DepositConfirmed, Attested)
+            | (Attested, Submitted)
+            | (Submitted, Completed) => true,
+            (state, Failed) => !state.is_terminal(),
+            _ => false,
+        }
+    }
+}
+
+impl std::fmt::Display for TransferState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TransferState::DepositDetected => write!(f, "deposit_detected"),
+            TransferState::DepositConfirmed => write!(f, "deposit_confirmed"),
+            TransferState::Attested => write!(f, "attested"),
+            TransferState::Submitted => write!(f, "submitted"),
+            TransferState::Completed => write!(f, "completed"),
+            TransferState::Failed => write!(f, "failed"),
+        }
+    }
+}
+
+/// Deposit observed on the source chain
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DepositEvent {
+    /// Source blockchain
+    pub chain: BlockchainNetwork,
+
+    /// Deposit transaction hash
+    pub tx_hash: String,
+
+    /// Index of the deposit within the transaction
+    pub log_index: u32,
+
+    /// Deposited token on the source chain
+    pub token: String,
+
+    /// Depositor address on the source chain
+    pub sender: String,
+
+    /// Deposited amount
+    pub amount: u64,
+
+    /// Destination blockchain
+    pub to_chain: BlockchainNetwork,
+
+    /// Recipient address on the destination chain
+    pub recipient: String,
+}
+
+impl DepositEvent {
+    /// Unique key of the deposit, used to deduplicate replayed events
+    pub fn deposit_key(&self) -> String {
+        format!(
+            "{}:{}:{}",
+            self.chain,
+            self.tx_hash.to_lowercase(),
+            self.log_index
+        )
+    }
+}
+
+/// Signed statement that a deposit is final and may be settled on the destination chain
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BridgeAttestation {
+    /// Hex encoded digest of the transfer message
+    pub digest: String,
+
+    /// Hex encoded signature over the digest
+    pub signature: String,
+
+    /// Signer key ID
+    pub signer: String,
+
+    /// TEE attestation report of the signer, if signed inside a TEE
+    pub tee_report: Option<serde_json::Value>,
+
+    /// Attestation timestamp
+    pub created_at: u64,
+}
+
+/// Lock-and-mint / burn-and-release transfer tracked by the bridge orchestrator
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BridgeTransfer {
+    /// Transfer ID
+    pub id: String,
+
+    /// Token bridge ID
+    pub bridge_id: String,
+
+    /// Settlement mode
+    pub mode: BridgeMode,
+
+    /// Deposit on the source chain
+    pub deposit: DepositEvent,
+
+    /// Token on the destination chain
+    pub destination_token: String,
+
+    /// Amount settled on the destination chain, after fees
+    pub amount_out: u64,
+
+    /// Bridge fee
+    pub fee: u64,
+
+    /// Transfer state
+    pub state: TransferState,
+
+    /// Source chain confirmations seen so far
+    pub confirmations: u64,
+
+    /// Attestation, once attested
+    pub attestation: Option<BridgeAttestation>,
+
+    /// Destination transaction hash, once submitted
+    pub destination_tx_hash: Option<String>,
+
+    /// Failed attempts of the current step
+    pub attempts: u32,
+
+    /// Last error message (if any)
+    pub error: Option<String>,
+
+    /// Creation timestamp
+    pub created_at: u64,
+
+    /// Last updated timestamp
+    pub updated_at: u64,
+}
@@ -0,0 +1,241 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+//! Light-client verification of deposits before they are attested.
+//!
+//! Without it, the attestation signer trusts the chain client: a compromised or faulty RPC
+//! node could report a deposit that never happened. With it, the chain client also serves a
+//! proof of the deposit, checked against a trust anchor sourced independently of that node.
+//!
+//! On Ethereum, the proof is the block header with the receipt and transaction trie proofs of
+//! the deposit transaction, which commit to the deposit log itself. On Neo, the proof is the
+//! block header, signed by the consensus nodes, with the Merkle path of the deposit
+//! transaction. Neo blocks do not commit to notifications, so a Neo proof shows the deposit
+//! transaction is in a signed block, but the deposit fields are still those of the client.
+
+use async_trait::async_trait;
+use base64::Engine;
+use r3e_core::proof::ethereum::{self, EthHeader};
+use r3e_core::proof::neo::{self, NeoHeader};
+use r3e_core::proof::{parse_hash, parse_hex, ProofError};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::bridge::deposit::parse_ethereum_deposit;
+use crate::bridge::orchestrator::BridgeChainConfig;
+use crate::bridge::types::{BlockchainNetwork, BridgeError, DepositEvent};
+
+impl From<ProofError> for BridgeError {
+    fn from(err: ProofError) -> Self {
+        BridgeError::Chain(err.to_string())
+    }
+}
+
+/// Proof that a deposit transaction is in a block of its source chain
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum DepositProof {
+    /// Ethereum and other EVM chains
+    Ethereum {
+        /// RLP encoded block header, hex
+        header: String,
+
+        /// Index of the deposit transaction in the block
+        tx_index: u64,
+
+        /// Receipt trie nodes from the receipt root, hex
+        receipt_proof: Vec<String>,
+
+        /// Transaction trie nodes from the transaction root, hex
+        transaction_proof: Vec<String>,
+    },
+
+    /// Neo N3
+    Neo {
+        /// Serialized block header, base64
+        header: String,
+
+        /// Index of the deposit transaction in the block
+        tx_index: usize,
+
+        /// Sibling hashes from the transaction up to the Merkle root, display hex
+        merkle_path: Vec<String>,
+    },
+}
+
+/// What a block of a source chain is verified against
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TrustAnchor {
+    /// Hash of the block
+    BlockHash([u8; 32]),
+
+    /// Consensus nodes signing the block
+    Consensus {
+        /// Script hash of the consensus nodes
+        script_hash: [u8; 20],
+
+        /// Network magic
+        network: u32,
+    },
+}
+
+/// Source of trust anchors, independent of the chain clients serving the proofs
+#[async_trait]
+pub trait TrustAnchors: Send + Sync {
+    /// Get the trust anchor of a block, `None` if it is not known yet
+    async fn anchor(
+        &self,
+        chain: BlockchainNetwork,
+        block: u64,
+    ) -> Result<Option<TrustAnchor>, BridgeError>;
+}
+
+/// Trust anchors that do not depend on the block, such as the consensus nodes of a Neo network
+/// whose committee is not expected to change
+#[derive(Debug, Clone, Default)]
+pub struct StaticTrustAnchors {
+    anchors: HashMap<BlockchainNetwork, TrustAnchor>,
+}
+
+impl StaticTrustAnchors {
+    /// Create static trust anchors, trusting no chain
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Trust the blocks of a chain with the anchor
+    pub fn with_anchor(mut self, chain: BlockchainNetwork, anchor: TrustAnchor) -> Self {
+        self.anchors.insert(chain, anchor);
+        self
+    }
+}
+
+#[async_trait]
+impl TrustAnchors for StaticTrustAnchors {
+    async fn anchor(
+        &self,
+        chain: BlockchainNetwork,
+        _block: u64,
+    ) -> Result<Option<TrustAnchor>, BridgeError> {
+        Ok(self.anchors.get(&chain).cloned())
+    }
+}
+
+/// Block number of a proof, to look its trust anchor up
+pub fn proof_block(proof: &DepositProof) -> Result<u64, BridgeError> {
+    match proof {
+        DepositProof::Ethereum { header, .. } => Ok(EthHeader::decode(&parse_hex(header)?)?.number),
+        DepositProof::Neo { header, .. } => {
+            Ok(NeoHeader::decode(&decode_base64(header)?)?.index as u64)
+        }
+    }
+}
+
+fn decode_base64(data: &str) -> Result<Vec<u8>, BridgeError> {
+    base64::engine::general_purpose::STANDARD
+        .decode(data)
+        .map_err(|e| BridgeError::InvalidInput(format!("Invalid base64 proof: {}", e)))
+}
+
+/// Verify a deposit against its proof and the trust anchor of its block
+pub fn verify_deposit(
+    deposit: &DepositEvent,
+    proof: &DepositProof,
+    anchor: &TrustAnchor,
+    chain_config: &BridgeChainConfig,
+) -> Result<(), BridgeError> {
+    match (proof, anchor) {
+        (
+            DepositProof::Ethereum {
+                header,
+                tx_index,
+                receipt_proof,
+                transaction_proof,
+            },
+            TrustAnchor::BlockHash(block_hash),
+        ) => {
+            let header = EthHeader::verify(&parse_hex(header)?, block_hash)?;
+            let parse_proof = |proof: &[String]| -> Result<Vec<Vec<u8>>, ProofError> {
+                proof.iter().map(|node| parse_hex(node)).collect()
+            };
+
+            let tx_hash = ethereum::verify_transaction(
+                &header.transactions_root,
+                *tx_index,
+                &parse_proof(transaction_proof)?,
+            )?;
+            if tx_hash != parse_hash(&deposit.tx_hash)? {
+                return Err(BridgeError::Chain(format!(
+                    "Proof is of another transaction than {}",
+                    deposit.tx_hash
+                )));
+            }
+
+            let receipt = ethereum::verify_receipt(
+                &header.receipts_root,
+                *tx_index,
+                &parse_proof(receipt_proof)?,
+            )?;
+            if !receipt.success {
+                return Err(BridgeError::Chain(format!(
+                    "Deposit transaction {} reverted",
+                    deposit.tx_hash
+                )));
+            }
+
+            // The proven log, as the chain client would have reported it
+            let contract = parse_hex(&chain_config.deposit_contract)?;
+            let proven = receipt
+                .logs
+                .iter()
+                .filter(|log| log.address[..] == contract[..])
+                .filter_map(|log| {
+                    let log = serde_json::json!({
+                        "topics": log
+                            .topics
+                            .iter()
+                            .map(|topic| format!("0x{}", hex::encode(topic)))
+                            .collect::<Vec<_>>(),
+                        "data": format!("0x{}", hex::encode(&log.data)),
+                        "logIndex": format!("0x{:x}", deposit.log_index),
+                        "transactionHash": deposit.tx_hash,
+                    });
+                    parse_ethereum_deposit(&log, &chain_config.deposit_topic)
+                        .ok()
+                        .flatten()
+                })
+                .any(|proven| proven == *deposit);
+            if !proven {
+                return Err(BridgeError::Chain(format!(
+                    "Deposit {} is not in the proven receipt",
+                    deposit.deposit_key()
+                )));
+            }
+            Ok(())
+        }
+        (
+            DepositProof::Neo {
+                header,
+                tx_index,
+                merkle_path,
+            },
+            TrustAnchor::Consensus {
+                script_hash,
+                network,
+            },
+        ) => {
+            let header = NeoHeader::verify(&decode_base64(header)?, *network, script_hash)?;
+            let path = merkle_path
+                .iter()
+                .map(|hash| neo::parse_uint256(hash))
+                .collect::<Result<Vec<_>, _>>()?;
+            header.verify_transaction(&neo::parse_uint256(&deposit.tx_hash)?, *tx_index, &path)?;
+            Ok(())
+        }
+        _ => Err(BridgeError::InvalidInput(format!(
+            "Proof of deposit {} does not match the trust anchor of {}",
+            deposit.deposit_key(),
+            deposit.chain
+        ))),
+    }
+}
@@ -0,0 +1,1177 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+//! Export of indexed datasets to the systems of users.
+//!
+//! Export sinks receive the documents indexed into a collection: webhook sinks get them
+//! posted in JSON batches, S3 sinks get them dumped as JSON lines, CSV or Parquet objects once
+//! a batch is full or its flush interval elapses. Every batch carries the schema of the
+//! collection with a version bumped whenever a field appears or changes type, and documents
+//! buffered under the previous version are flushed first, so a batch never mixes versions.
+//! Backfill jobs export the documents indexed before a sink was added.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+use arrow::datatypes::{DataType, Field, Schema};
+use r3e_core::egress::EgressGuard;
+use r3e_store::blob::{S3BlobStore, S3Config};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use uuid::Uuid;
+
+use crate::indexing::storage::IndexingStorage;
+use crate::indexing::types::{IndexingError, IndexingQuery};
+use crate::notifications::channels::{sign, SIGNATURE_HEADER};
+
+/// Header of the schema version of webhook batches
+pub const SCHEMA_VERSION_HEADER: &str = "X-R3E-Schema-Version";
+
+/// Timeout of a single webhook delivery
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Batches of documents a sink buffers while its target fails, older documents are dropped
+const MAX_PENDING_BATCHES: usize = 10;
+
+fn default_batch_size() -> usize {
+    500
+}
+
+fn default_flush_interval_secs() -> u64 {
+    60
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+/// Format of the objects S3 sinks write
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    /// One JSON document per line
+    #[default]
+    Json,
+    /// Top-level fields as columns, other values JSON encoded
+    Csv,
+    /// Top-level fields as columns, objects and arrays JSON encoded
+    Parquet,
+}
+
+impl ExportFormat {
+    /// Extension of the objects
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Json => "jsonl",
+            Self::Csv => "csv",
+            Self::Parquet => "parquet",
+        }
+    }
+
+    /// Content type of the objects
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            Self::Json => "application/x-ndjson",
+            Self::Csv => "text/csv",
+            Self::Parquet => "application/vnd.apache.parquet",
+        }
+    }
+
+    /// Encode the documents of a batch
+    pub fn encode(&self, batch: &ExportBatch) -> Result<Vec<u8>, IndexingError> {
+        match self {
+            Self::Json => {
+                let mut out = Vec::new();
+                for record in &batch.records {
+                    serde_json::to_writer(&mut out, record)
+                        .map_err(|e| IndexingError::Data(e.to_string()))?;
+                    out.push(b'\n');
+                }
+                Ok(out)
+            }
+            Self::Csv => Ok(encode_csv(&batch.schema, &batch.records).into_bytes()),
+            Self::Parquet => encode_parquet(&batch.schema, &batch.records),
+        }
+    }
+}
+
+/// Where a sink delivers its batches
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ExportTarget {
+    /// Batches posted as JSON
+    Webhook {
+        url: String,
+
+        /// Key of the HMAC-SHA256 signature of the body, sent in `X-R3E-Signature`
+        #[serde(default)]
+        secret: Option<String>,
+    },
+
+    /// Batches written as objects under `<prefix><collection>/v<schema version>/`
+    S3 {
+        #[serde(flatten)]
+        config: S3Config,
+
+        #[serde(default)]
+        format: ExportFormat,
+    },
+}
+
+/// Export sink of a collection
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExportSink {
+    /// Sink ID, generated when empty
+    #[serde(default)]
+    pub id: String,
+
+    /// Collection exported
+    pub collection: String,
+
+    pub target: ExportTarget,
+
+    /// Documents per batch
+    #[serde(default = "default_batch_size")]
+    pub batch_size: usize,
+
+    /// Longest time a document waits for its batch to be delivered, in seconds
+    #[serde(default = "default_flush_interval_secs")]
+    pub flush_interval_secs: u64,
+
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+}
+
+/// Type of a field of a dataset
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FieldKind {
+    Bool,
+    Number,
+    String,
+    Array,
+    Object,
+}
+
+impl FieldKind {
+    fn of(value: &Value) -> Option<Self> {
+        match value {
+            Value::Null => None,
+            Value::Bool(_) => Some(Self::Bool),
+            Value::Number(_) => Some(Self::Number),
+            Value::String(_) => Some(Self::String),
+            Value::Array(_) => Some(Self::Array),
+            Value::Object(_) => Some(Self::Object),
+        }
+    }
+}
+
+/// Field of a dataset
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SchemaField {
+    pub name: String,
+    pub kind: FieldKind,
+}
+
+/// Versioned schema of the top-level fields of the documents of a collection
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DatasetSchema {
+    /// Bumped whenever a field appears or changes type
+    pub version: u32,
+
+    /// Fields, by name
+    pub fields: Vec<SchemaField>,
+}
+
+impl DatasetSchema {
+    /// Merge the fields of a document, returning whether the schema changed
+    pub fn observe(&mut self, document: &Value) -> bool {
+        let Some(object) = document.as_object() else {
+            return false;
+        };
+
+        let mut fields: BTreeMap<String, FieldKind> = self
+            .fields
+            .iter()
+            .map(|field| (field.name.clone(), field.kind))
+            .collect();
+        let mut changed = false;
+        for (name, value) in object {
+            // Nulls say nothing about the type of a field
+            let Some(kind) = FieldKind::of(value) else {
+                continue;
+            };
+            if fields.insert(name.clone(), kind) != Some(kind) {
+                changed = true;
+            }
+        }
+
+        if changed || self.version == 0 {
+            self.version += 1;
+            self.fields = fields
+                .into_iter()
+                .map(|(name, kind)| SchemaField { name, kind })
+                .collect();
+        }
+        changed
+    }
+}
+
+/// Documents delivered to a sink at once
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExportBatch {
+    pub sink_id: String,
+    pub collection: String,
+    pub schema: DatasetSchema,
+
+    /// Position of the batch among the batches of the sink
+    pub sequence: u64,
+
+    /// Backfill job the batch belongs to, None for documents as they are indexed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub backfill_job: Option<String>,
+
+    pub records: Vec<Value>,
+}
+
+/// State of a backfill export job
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportJobStatus {
+    Running,
+    Completed,
+    Failed,
+}
+
+/// Backfill export job, exporting the documents of a collection to a sink
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExportJob {
+    pub id: String,
+    pub sink_id: String,
+    pub collection: String,
+    pub status: ExportJobStatus,
+
+    /// Documents exported so far
+    pub exported: u64,
+
+    /// Batches delivered so far
+    pub batches: u64,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+
+    /// Unix timestamps in seconds
+    pub started_at: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub finished_at: Option<u64>,
+}
+
+/// Documents buffered for a sink
+#[derive(Default)]
+struct SinkState {
+    records: Vec<Value>,
+    since: Option<Instant>,
+    sequence: u64,
+    /// Schema version last written next to the objects of an S3 sink
+    schema_written: u32,
+}
+
+/// Exports the documents indexed into collections to their sinks
+pub struct IndexExporter<S: IndexingStorage> {
+    storage: Arc<S>,
+    sinks: RwLock<HashMap<String, ExportSink>>,
+    states: Mutex<HashMap<String, SinkState>>,
+    schemas: Mutex<HashMap<String, DatasetSchema>>,
+    jobs: RwLock<HashMap<String, ExportJob>>,
+
+    /// Guard keeping user supplied webhook URLs off internal addresses
+    egress_guard: EgressGuard,
+}
+
+impl<S: IndexingStorage + 'static> IndexExporter<S> {
+    /// Create an exporter reading backfills from the storage
+    pub fn new(storage: Arc<S>) -> Self {
+        Self {
+            storage,
+            sinks: RwLock::new(HashMap::new()),
+            states: Mutex::new(HashMap::new()),
+            schemas: Mutex::new(HashMap::new()),
+            jobs: RwLock::new(HashMap::new()),
+            egress_guard: EgressGuard::new(),
+        }
+    }
+
+    /// Check webhook URLs against an egress guard other than the default one
+    pub fn with_egress_guard(mut self, egress_guard: EgressGuard) -> Self {
+        self.egress_guard = egress_guard;
+        self
+    }
+
+    /// Add a sink, or replace the sink with the same ID
+    pub fn add_sink(&self, mut sink: ExportSink) -> Result<ExportSink, IndexingError> {
+        if sink.collection.is_empty() {
+            return Err(IndexingError::InvalidInput(
+                "Export sink without a collection".to_string(),
+            ));
+        }
+        if sink.batch_size == 0 {
+            return Err(IndexingError::InvalidInput(
+                "Export sink batch size must be positive".to_string(),
+            ));
+        }
+        match &sink.target {
+            ExportTarget::Webhook { url, .. } => {
+                reqwest::Url::parse(url).map_err(|e| {
+                    IndexingError::InvalidInput(format!("Invalid webhook URL {}: {}", url, e))
+                })?;
+            }
+            ExportTarget::S3 { config, .. } => {
+                S3BlobStore::new(config.clone())
+                    .map_err(|e| IndexingError::InvalidInput(e.to_string()))?;
+            }
+        }
+        if sink.id.is_empty() {
+            sink.id = Uuid::new_v4().to_string();
+        }
+
+        self.sinks
+            .write()
+            .map_err(|e| IndexingError::Storage(format!("Failed to acquire write lock: {}", e)))?
+            .insert(sink.id.clone(), sink.clone());
+        Ok(sink)
+    }
+
+    /// Remove a sink, dropping the documents it buffers
+    pub fn remove_sink(&self, id: &str) -> Result<bool, IndexingError> {
+        self.lock_states()?.remove(id);
+        Ok(self
+            .sinks
+            .write()
+            .map_err(|e| IndexingError::Storage(format!("Failed to acquire write lock: {}", e)))?
+            .remove(id)
+            .is_some())
+    }
+
+    /// Sinks, of every collection
+    pub fn sinks(&self) -> Result<Vec<ExportSink>, IndexingError> {
+        Ok(self
+            .sinks
+            .read()
+            .map_err(|e| IndexingError::Storage(format!("Failed to acquire read lock: {}", e)))?
+            .values()
+            .cloned()
+            .collect())
+    }
+
+    /// Current schema of a collection
+    pub fn schema(&self, collection: &str) -> Result<Option<DatasetSchema>, IndexingError> {
+        Ok(self.lock_schemas()?.get(collection).cloned())
+    }
+
+    /// Buffer a document indexed into a collection for the sinks of the collection, delivering
+    /// the batches that are full. Batches that fail stay buffered for the next flush.
+    pub async fn record(&self, collection: &str, document: Value) -> Result<(), IndexingError> {
+        let sinks = self.collection_sinks(collection)?;
+        if sinks.is_empty() {
+            return Ok(());
+        }
+
+        let (bumped, previous) = {
+            let mut schemas = self.lock_schemas()?;
+            let schema = schemas.entry(collection.to_string()).or_default();
+            let previous = schema.clone();
+            (schema.observe(&document), previous)
+        };
+
+        let mut due = Vec::new();
+        for sink in sinks {
+            // Deliver what was buffered under the previous schema version first
+            if bumped {
+                if let Err(e) = self.flush_sink_as(&sink, previous.clone()).await {
+                    log::warn!("Export to sink {} failed: {}", sink.id, e);
+                }
+            }
+
+            let mut states = self.lock_states()?;
+            let state = states.entry(sink.id.clone()).or_default();
+            state.since.get_or_insert_with(Instant::now);
+            state.records.push(document.clone());
+            if state.records.len() >= sink.batch_size {
+                due.push(sink);
+            }
+        }
+
+        for sink in due {
+            if let Err(e) = self.flush_sink(&sink).await {
+                log::warn!("Export to sink {} failed: {}", sink.id, e);
+            }
+        }
+        Ok(())
+    }
+
+    /// Deliver the batches whose flush interval elapsed
+    pub async fn flush_due(&self) -> Result<(), IndexingError> {
+        let waiting: HashMap<String, Instant> = self
+            .lock_states()?
+            .iter()
+            .filter(|(_, state)| !state.records.is_empty())
+            .filter_map(|(id, state)| Some((id.clone(), state.since?)))
+            .collect();
+
+        for sink in self.sinks()? {
+            let Some(since) = waiting.get(&sink.id) else {
+                continue;
+            };
+            if since.elapsed() < Duration::from_secs(sink.flush_interval_secs) {
+                continue;
+            }
+            if let Err(e) = self.flush_sink(&sink).await {
+                log::warn!("Export to sink {} failed: {}", sink.id, e);
+            }
+        }
+        Ok(())
+    }
+
+    /// Deliver the documents buffered for a sink
+    pub async fn flush(&self, id: &str) -> Result<(), IndexingError> {
+        let sink = self
+            .sink(id)?
+            .ok_or_else(|| IndexingError::NotFound(format!("Export sink not found: {}", id)))?;
+        self.flush_sink(&sink).await
+    }
+
+    /// Deliver the batches whose flush interval elapsed, every period, forever
+    pub async fn run(self: Arc<Self>, period: Duration) {
+        let mut interval = tokio::time::interval(period);
+        loop {
+            interval.tick().await;
+            if let Err(e) = self.flush_due().await {
+                log::warn!("Export flush failed: {}", e);
+            }
+        }
+    }
+
+    /// Start exporting the documents of the collection of a sink that are already indexed
+    pub fn start_backfill(self: &Arc<Self>, sink_id: &str) -> Result<ExportJob, IndexingError> {
+        let sink = self.sink(sink_id)?.ok_or_else(|| {
+            IndexingError::NotFound(format!("Export sink not found: {}", sink_id))
+        })?;
+
+        let job = ExportJob {
+            id: Uuid::new_v4().to_string(),
+            sink_id: sink.id.clone(),
+            collection: sink.collection.clone(),
+            status: ExportJobStatus::Running,
+            exported: 0,
+            batches: 0,
+            error: None,
+            started_at: now_secs(),
+            finished_at: None,
+        };
+        self.jobs
+            .write()
+            .map_err(|e| IndexingError::Storage(format!("Failed to acquire write lock: {}", e)))?
+            .insert(job.id.clone(), job.clone());
+
+        let (exporter, job_id) = (Arc::clone(self), job.id.clone());
+        tokio::spawn(async move {
+            let result = exporter.backfill(&sink, &job_id).await;
+            exporter.update_job(&job_id, |job| {
+                job.finished_at = Some(now_secs());
+                match result {
+                    Ok(()) => job.status = ExportJobStatus::Completed,
+                    Err(e) => {
+                        log::warn!("Backfill export {} failed: {}", job.id, e);
+                        job.status = ExportJobStatus::Failed;
+                        job.error = Some(e.to_string());
+                    }
+                }
+            });
+        });
+        Ok(job)
+    }
+
+    /// Backfill export job
+    pub fn job(&self, id: &str) -> Result<Option<ExportJob>, IndexingError> {
+        Ok(self
+            .jobs
+            .read()
+            .map_err(|e| IndexingError::Storage(format!("Failed to acquire read lock: {}", e)))?
+            .get(id)
+            .cloned())
+    }
+
+    /// Backfill export jobs of a sink
+    pub fn jobs(&self, sink_id: &str) -> Result<Vec<ExportJob>, IndexingError> {
+        Ok(self
+            .jobs
+            .read()
+            .map_err(|e| IndexingError::Storage(format!("Failed to acquire read lock: {}", e)))?
+            .values()
+            .filter(|job| job.sink_id == sink_id)
+            .cloned()
+            .collect())
+    }
+
+    async fn backfill(&self, sink: &ExportSink, job_id: &str) -> Result<(), IndexingError> {
+        let mut skip = 0u32;
+        loop {
+            let page = self
+                .storage
+                .query(IndexingQuery {
+                    collection: sink.collection.clone(),
+                    filter: serde_json::json!({}),
+                    sort: None,
+                    limit: Some(sink.batch_size as u32),
+                    skip: Some(skip),
+                })
+                .await?;
+            if page.data.is_empty() {
+                return Ok(());
+            }
+
+            let schema = {
+                let mut schemas = self.lock_schemas()?;
+                let schema = schemas.entry(sink.collection.clone()).or_default();
+                for document in &page.data {
+                    schema.observe(document);
+                }
+                schema.clone()
+            };
+            let count = page.data.len() as u64;
+            let batch = ExportBatch {
+                sink_id: sink.id.clone(),
+                collection: sink.collection.clone(),
+                schema,
+                sequence: self.next_sequence(&sink.id)?,
+                backfill_job: Some(job_id.to_string()),
+                records: page.data,
+            };
+            self.deliver(sink, &batch).await?;
+
+            self.update_job(job_id, |job| {
+                job.exported += count;
+                job.batches += 1;
+            });
+            skip += count as u32;
+            if skip >= page.total {
+                return Ok(());
+            }
+        }
+    }
+
+    async fn flush_sink(&self, sink: &ExportSink) -> Result<(), IndexingError> {
+        let schema = self
+            .lock_schemas()?
+            .get(&sink.collection)
+            .cloned()
+            .unwrap_or_default();
+        self.flush_sink_as(sink, schema).await
+    }
+
+    /// Deliver the documents buffered for a sink as a batch of the given schema
+    async fn flush_sink_as(
+        &self,
+        sink: &ExportSink,
+        schema: DatasetSchema,
+    ) -> Result<(), IndexingError> {
+        let (records, sequence) = {
+            let mut states = self.lock_states()?;
+            let state = states.entry(sink.id.clone()).or_default();
+            if state.records.is_empty() {
+                return Ok(());
+            }
+            state.since = None;
+            state.sequence += 1;
+            (std::mem::take(&mut state.records), state.sequence)
+        };
+
+        let batch = ExportBatch {
+            sink_id: sink.id.clone(),
+            collection: sink.collection.clone(),
+            schema,
+            sequence,
+            backfill_job: None,
+            records,
+        };
+        if let Err(e) = self.deliver(sink, &batch).await {
+            self.requeue(sink, batch.records)?;
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    /// Put the documents of a failed batch back in front of the buffer of the sink
+    fn requeue(&self, sink: &ExportSink, mut records: Vec<Value>) -> Result<(), IndexingError> {
+        let mut states = self.lock_states()?;
+        let state = states.entry(sink.id.clone()).or_default();
+        records.append(&mut state.records);
+
+        let limit = sink.batch_size.saturating_mul(MAX_PENDING_BATCHES);
+        if records.len() > limit {
+            log::warn!(
+                "Export sink {} dropped {} documents its target did not accept",
+                sink.id,
+                records.len() - limit
+            );
+            records.drain(..records.len() - limit);
+        }
+        state.records = records;
+        state.since.get_or_insert_with(Instant::now);
+        Ok(())
+    }
+
+    async fn deliver(&self, sink: &ExportSink, batch: &ExportBatch) -> Result<(), IndexingError> {
+        match &sink.target {
+            ExportTarget::Webhook { url, secret } => {
+                self.post_webhook(url, secret.as_deref(), batch).await
+            }
+            ExportTarget::S3 { config, format } => self.put_s3(config, *format, batch).await,
+        }
+    }
+
+    async fn post_webhook(
+        &self,
+        url: &str,
+        secret: Option<&str>,
+        batch: &ExportBatch,
+    ) -> Result<(), IndexingError> {
+        let body = serde_json::to_vec(batch).map_err(|e| IndexingError::Data(e.to_string()))?;
+
+        // Pin the connection to the checked addresses
+        let resolved = self
+            .egress_guard
+            .check_url(url)
+            .await
+            .map_err(|e| IndexingError::InvalidInput(e.to_string()))?;
+        let client = reqwest::Client::builder()
+            .resolve_to_addrs(&resolved.host, &resolved.addrs)
+            .redirect(reqwest::redirect::Policy::none())
+            .timeout(DELIVERY_TIMEOUT)
+            .build()
+            .map_err(|e| IndexingError::Storage(format!("HTTP client: {}", e)))?;
+
+        let mut request = client
+            .post(resolved.url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .header(SCHEMA_VERSION_HEADER, batch.schema.version.to_string());
+        if let Some(secret) = secret {
+            request = request.header(SIGNATURE_HEADER, sign(secret, &body));
+        }
+
+        let response = request
+            .body(body)
+            .send()
+            .await
+            .map_err(|e| IndexingError::Storage(format!("Export webhook: {}", e)))?;
+        if !response.status().is_success() {
+            return Err(IndexingError::Storage(format!(
+                "Export webhook responded {}",
+                response.status()
+            )));
+        }
+        Ok(())
+    }
+
+    async fn put_s3(
+        &self,
+        config: &S3Config,
+        format: ExportFormat,
+        batch: &ExportBatch,
+    ) -> Result<(), IndexingError> {
+        let store =
+            S3BlobStore::new(config.clone()).map_err(|e| IndexingError::Storage(e.to_string()))?;
+        let dir = format!("{}/v{}", batch.collection, batch.schema.version);
+
+        // Consumers find the schema of the objects of a version next to them
+        let schema_written = self
+            .lock_states()?
+            .get(&batch.sink_id)
+            .map_or(0, |state| state.schema_written);
+        if schema_written != batch.schema.version {
+            let schema = serde_json::to_vec_pretty(&batch.schema)
+                .map_err(|e| IndexingError::Data(e.to_string()))?;
+            store
+                .put_object(&format!("{}/schema.json", dir), schema, "application/json")
+                .await
+                .map_err(|e| IndexingError::Storage(e.to_string()))?;
+            self.lock_states()?
+                .entry(batch.sink_id.clone())
+                .or_default()
+                .schema_written = batch.schema.version;
+        }
+
+        let name = match &batch.backfill_job {
+            Some(job) => format!("backfill-{}-{:08}", job, batch.sequence),
+            None => format!("{}-{:08}", now_secs(), batch.sequence),
+        };
+        let key = format!("{}/{}.{}", dir, name, format.extension());
+        store
+            .put_object(&key, format.encode(batch)?, format.content_type())
+            .await
+            .map_err(|e| IndexingError::Storage(e.to_string()))?;
+
+        log::debug!(
+            "Exported {} documents of {} to S3 object {}",
+            batch.records.len(),
+            batch.collection,
+            key
+        );
+        Ok(())
+    }
+
+    fn sink(&self, id: &str) -> Result<Option<ExportSink>, IndexingError> {
+        Ok(self
+            .sinks
+            .read()
+            .map_err(|e| IndexingError::Storage(format!("Failed to acquire read lock: {}", e)))?
+            .get(id)
+            .cloned())
+    }
+
+    fn collection_sinks(&self, collection: &str) -> Result<Vec<ExportSink>, IndexingError> {
+        Ok(self
+            .sinks
+            .read()
+            .map_err(|e| IndexingError::Storage(format!("Failed to acquire read lock: {}", e)))?
+            .values()
+            .filter(|sink| sink.enabled && sink.collection == collection)
+            .cloned()
+            .collect())
+    }
+
+    fn next_sequence(&self, sink_id: &str) -> Result<u64, IndexingError> {
+        let mut states = self.lock_states()?;
+        let state = states.entry(sink_id.to_string()).or_default();
+        state.sequence += 1;
+        Ok(state.sequence)
+    }
+
+    fn update_job(&self, id: &str, update: impl FnOnce(&mut ExportJob)) {
+        if let Ok(mut jobs) = self.jobs.write() {
+            if let Some(job) = jobs.get_mut(id) {
+                update(job);
+            }
+        }
+    }
+
+    fn lock_states(
+        &self,
+    ) -> Result<std::sync::MutexGuard<'_, HashMap<String, SinkState>>, IndexingError> {
+        self.states
+            .lock()
+            .map_err(|e| IndexingError::Storage(format!("Failed to acquire lock: {}", e)))
+    }
+
+    fn lock_schemas(
+        &self,
+    ) -> Result<std::sync::MutexGuard<'_, HashMap<String, DatasetSchema>>, IndexingError> {
+        self.schemas
+            .lock()
+            .map_err(|e| IndexingError::Storage(format!("Failed to acquire lock: {}", e)))
+    }
+}
+
+fn now_secs() -> u64 {
+    chrono::Utc::now().timestamp().max(0) as u64
+}
+
+/// Value of a column: strings as they are, nulls empty and other values JSON encoded
+fn column_value(value: Option<&Value>) -> String {
+    match value {
+        None | Some(Value::Null) => String::new(),
+        Some(Value::String(s)) => s.clone(),
+        Some(value) => value.to_string(),
+    }
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+fn encode_csv(schema: &DatasetSchema, records: &[Value]) -> String {
+    let mut out = schema
+        .fields
+        .iter()
+        .map(|field| csv_field(&field.name))
+        .collect::<Vec<_>>()
+        .join(",");
+    out.push('\n');
+    for record in records {
+        let row = schema
+            .fields
+            .iter()
+            .map(|field| csv_field(&column_value(record.get(&field.name))))
+            .collect::<Vec<_>>()
+            .join(",");
+        out.push_str(&row);
+        out.push('\n');
+    }
+    out
+}
+
+fn encode_parquet(schema: &DatasetSchema, records: &[Value]) -> Result<Vec<u8>, IndexingError> {
+    let arrow_error = |e: arrow::error::ArrowError| IndexingError::Data(e.to_string());
+
+    let fields: Vec<Field> = schema
+        .fields
+        .iter()
+        .map(|field| {
+            let data_type = match field.kind {
+                FieldKind::Bool => DataType::Boolean,
+                FieldKind::Number => DataType::Float64,
+                _ => DataType::Utf8,
+            };
+            Field::new(&field.name, data_type, true)
+        })
+        .collect();
+    let arrow_schema = Arc::new(Schema::new(fields));
+
+    // Values are coerced to the column types, the ones that do not fit are null
+    let rows: Vec<serde_json::Map<String, Value>> = records
+        .iter()
+        .map(|record| {
+            schema
+                .fields
+                .iter()
+                .map(|field| {
+                    let value = match (field.kind, record.get(&field.name)) {
+                        (_, None | Some(Value::Null)) => Value::Null,
+                        (FieldKind::Bool, Some(value @ Value::Bool(_)))
+                        | (FieldKind::Number, Some(value @ Value::Number(_))) => value.clone(),
+                        (FieldKind::Bool | FieldKind::Number, Some(_)) => Value::Null,
+                        (_, value) => Value::String(column_value(value)),
+                    };
+                    (field.name.clone(), value)
+                })
+                .collect()
+        })
+        .collect();
+
+    let mut decoder = arrow::json::ReaderBuilder::new(Arc::clone(&arrow_schema))
+        .with_batch_size(rows.len().max(1))
+        .build_decoder()
+        .map_err(arrow_error)?;
+    decoder.serialize(&rows).map_err(arrow_error)?;
+    let batch = decoder
+        .flush()
+        .map_err(arrow_error)?
+        .unwrap_or_else(|| arrow::record_batch::RecordBatch::new_empty(arrow_schema.clone()));
+
+    let mut out = Vec::new();
+    let mut writer = parquet::arrow::ArrowWriter::try_new(&mut out, arrow_schema, None)
+        .map_err(|e| IndexingError::Data(e.to_string()))?;
+    writer
+        .write(&batch)
+        .map_err(|e| IndexingError::Data(e.to_string()))?;
+    writer
+        .close()
+        .map_err(|e| IndexingError::Data(e.to_string()))?;
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::indexing::storage::MemoryIndexingStorage;
+    use serde_json::json;
+    use std::sync::atomic::{AtomicU16, Ordering};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    /// Request received by a webhook, with its headers lowercased
+    struct Received {
+        headers: HashMap<String, String>,
+        body: Vec<u8>,
+    }
+
+    /// Webhook on a local port, answering with `status`
+    struct Receiver {
+        url: String,
+        status: Arc<AtomicU16>,
+        requests: Arc<Mutex<Vec<Received>>>,
+    }
+
+    impl Receiver {
+        async fn start() -> Self {
+            let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let url = format!("http://{}/export", listener.local_addr().unwrap());
+            let status = Arc::new(AtomicU16::new(200));
+            let requests = Arc::new(Mutex::new(Vec::new()));
+
+            let (status_, requests_) = (status.clone(), requests.clone());
+            tokio::spawn(async move {
+                while let Ok((mut stream, _)) = listener.accept().await {
+                    let mut data = Vec::new();
+                    let mut buf = [0u8; 4096];
+                    let received = loop {
+                        let n = stream.read(&mut buf).await.unwrap();
+                        data.extend_from_slice(&buf[..n]);
+                        let Some(end) = data.windows(4).position(|w| w == b"\r\n\r\n") else {
+                            continue;
+                        };
+                        let headers: HashMap<String, String> =
+                            String::from_utf8_lossy(&data[..end])
+                                .lines()
+                                .skip(1)
+                                .filter_map(|line| line.split_once(':'))
+                                .map(|(k, v)| (k.trim().to_lowercase(), v.trim().to_string()))
+                                .collect();
+                        let length: usize = headers["content-length"].parse().unwrap();
+                        if data.len() >= end + 4 + length {
+                            let body = data[end + 4..end + 4 + length].to_vec();
+                            break Received { headers, body };
+                        }
+                    };
+                    requests_.lock().unwrap().push(received);
+
+                    let status = status_.load(Ordering::SeqCst);
+                    let response = format!(
+                        "HTTP/1.1 {} Status\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                        status
+                    );
+                    stream.write_all(response.as_bytes()).await.unwrap();
+                }
+            });
+
+            Self {
+                url,
+                status,
+                requests,
+            }
+        }
+
+        fn batches(&self) -> Vec<ExportBatch> {
+            self.requests
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|request| serde_json::from_slice(&request.body).unwrap())
+                .collect()
+        }
+    }
+
+    fn webhook_sink(url: &str, batch_size: usize) -> ExportSink {
+        ExportSink {
+            id: String::new(),
+            collection: "transfers".to_string(),
+            target: ExportTarget::Webhook {
+                url: url.to_string(),
+                secret: Some("key".to_string()),
+            },
+            batch_size,
+            flush_interval_secs: 0,
+            enabled: true,
+        }
+    }
+
+    fn exporter(storage: MemoryIndexingStorage) -> Arc<IndexExporter<MemoryIndexingStorage>> {
+        Arc::new(
+            IndexExporter::new(Arc::new(storage))
+                .with_egress_guard(EgressGuard::new().with_allow_internal(true)),
+        )
+    }
+
+    #[test]
+    fn test_dataset_schema() {
+        let mut schema = DatasetSchema::default();
+
+        // The first document sets version 1, whatever its fields
+        assert!(schema.observe(&json!({"amount": 1, "to": "NX"})));
+        assert_eq!(schema.version, 1);
+        assert_eq!(
+            schema.fields,
+            vec![
+                SchemaField {
+                    name: "amount".to_string(),
+                    kind: FieldKind::Number
+                },
+                SchemaField {
+                    name: "to".to_string(),
+                    kind: FieldKind::String
+                },
+            ]
+        );
+
+        // Known fields, fewer fields, nulls and non-objects keep the version
+        assert!(!schema.observe(&json!({"amount": 2.5, "to": null})));
+        assert!(!schema.observe(&json!({"amount": 3})));
+        assert!(!schema.observe(&json!([1, 2])));
+        assert_eq!(schema.version, 1);
+
+        // A new field or a changed type bumps it
+        assert!(schema.observe(&json!({"memo": {"text": "hi"}})));
+        assert_eq!(schema.version, 2);
+        assert!(schema.observe(&json!({"amount": "4"})));
+        assert_eq!(schema.version, 3);
+        assert_eq!(schema.fields.len(), 3);
+        assert_eq!(schema.fields[0].kind, FieldKind::String);
+    }
+
+    #[test]
+    fn test_export_formats() {
+        let records = vec![
+            json!({"amount": 1, "memo": "a, \"b\"", "tags": ["x"]}),
+            json!({"amount": 2, "memo": null}),
+        ];
+        let mut schema = DatasetSchema::default();
+        records.iter().for_each(|record| {
+            schema.observe(record);
+        });
+        let batch = ExportBatch {
+            sink_id: "sink".to_string(),
+            collection: "transfers".to_string(),
+            schema,
+            sequence: 1,
+            backfill_job: None,
+            records: records.clone(),
+        };
+
+        let json = ExportFormat::Json.encode(&batch).unwrap();
+        let lines: Vec<Value> = String::from_utf8(json)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines, records);
+
+        let csv = String::from_utf8(ExportFormat::Csv.encode(&batch).unwrap()).unwrap();
+        assert_eq!(
+            csv,
+            "amount,memo,tags\n1,\"a, \"\"b\"\"\",\"[\"\"x\"\"]\"\n2,,\n"
+        );
+
+        let parquet = ExportFormat::Parquet.encode(&batch).unwrap();
+        assert!(parquet.starts_with(b"PAR1") && parquet.ends_with(b"PAR1"));
+    }
+
+    #[test]
+    fn test_add_sink() {
+        let exporter = exporter(MemoryIndexingStorage::new());
+
+        let sink = exporter
+            .add_sink(webhook_sink("https://example.com/export", 10))
+            .unwrap();
+        assert!(!sink.id.is_empty());
+        assert_eq!(exporter.sinks().unwrap().len(), 1);
+
+        let mut invalid = webhook_sink("https://example.com/export", 10);
+        invalid.collection.clear();
+        assert!(exporter.add_sink(invalid).is_err());
+        assert!(exporter
+            .add_sink(webhook_sink("https://example.com/export", 0))
+            .is_err());
+        assert!(exporter.add_sink(webhook_sink("not a url", 10)).is_err());
+
+        assert!(exporter.remove_sink(&sink.id).unwrap());
+        assert!(!exporter.remove_sink(&sink.id).unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_webhook_export() {
+        let receiver = Receiver::start().await;
+        let exporter = exporter(MemoryIndexingStorage::new());
+        let sink = exporter.add_sink(webhook_sink(&receiver.url, 2)).unwrap();
+
+        // Full batches are delivered, signed and with their schema version
+        for amount in 1..=3 {
+            exporter
+                .record("transfers", json!({"amount": amount}))
+                .await
+                .unwrap();
+        }
+        exporter
+            .record("other", json!({"amount": 0}))
+            .await
+            .unwrap();
+        let batches = receiver.batches();
+        assert_eq!(batches.len(), 1);
+        assert_eq!(
+            batches[0].records,
+            vec![json!({"amount": 1}), json!({"amount": 2})]
+        );
+        assert_eq!((batches[0].sequence, batches[0].schema.version), (1, 1));
+        {
+            let requests = receiver.requests.lock().unwrap();
+            let headers = &requests[0].headers;
+            assert_eq!(headers["x-r3e-schema-version"], "1");
+            assert_eq!(headers["x-r3e-signature"], sign("key", &requests[0].body));
+        }
+
+        // A new field flushes what was buffered under the previous version
+        exporter
+            .record("transfers", json!({"amount": 4, "memo": "hi"}))
+            .await
+            .unwrap();
+        let batches = receiver.batches();
+        assert_eq!(batches.len(), 2);
+        assert_eq!(batches[1].records, vec![json!({"amount": 3})]);
+        assert_eq!((batches[1].sequence, batches[1].schema.version), (2, 1));
+
+        // A failed batch stays buffered for the next flush
+        receiver.status.store(500, Ordering::SeqCst);
+        exporter
+            .record("transfers", json!({"amount": 5, "memo": "hey"}))
+            .await
+            .unwrap();
+        assert_eq!(receiver.batches().len(), 3);
+        receiver.status.store(200, Ordering::SeqCst);
+        exporter.flush_due().await.unwrap();
+        let batches = receiver.batches();
+        assert_eq!(batches.len(), 4);
+        assert_eq!(batches[3].records.len(), 2);
+        assert_eq!(batches[3].schema.version, 2);
+
+        // Nothing is left to deliver
+        exporter.flush(&sink.id).await.unwrap();
+        assert_eq!(receiver.batches().len(), 4);
+        assert!(exporter.flush("unknown").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_backfill() {
+        let receiver = Receiver::start().await;
+        let storage = MemoryIndexingStorage::new();
+        for amount in 0..5 {
+            storage
+                .index_data("transfers", json!({"amount": amount}))
+                .await
+                .unwrap();
+        }
+        let exporter = exporter(storage);
+        let sink = exporter.add_sink(webhook_sink(&receiver.url, 2)).unwrap();
+
+        let job = exporter.start_backfill(&sink.id).unwrap();
+        assert_eq!(job.status, ExportJobStatus::Running);
+        let job = loop {
+            let job = exporter.job(&job.id).unwrap().unwrap();
+            if job.status != ExportJobStatus::Running {
+                break job;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        };
+        assert_eq!(job.status, ExportJobStatus::Completed);
+        assert_eq!((job.exported, job.batches), (5, 3));
+        assert!(job.finished_at.is_some());
+        assert_eq!(exporter.jobs(&sink.id).unwrap().len(), 1);
+
+        let batches = receiver.batches();
+        let mut amounts: Vec<i64> = batches
+            .iter()
+            .flat_map(|batch| &batch.records)
+            .map(|record| record["amount"].as_i64().unwrap())
+            .collect();
+        amounts.sort();
+        assert_eq!(amounts, vec![0, 1, 2, 3, 4]);
+        assert!(batches
+            .iter()
+            .all(|batch| batch.backfill_job.as_deref() == Some(job.id.as_str())));
+
+        // Backfills of unknown sinks are not started
+        assert!(exporter.start_backfill("unknown").is_err());
+    }
+}
@@ -1,10 +1,15 @@
 // Copyright @ 2023 - 2024, R3E Network
 // All Rights Reserved
 
+pub mod export;
 pub mod service;
 pub mod storage;
 pub mod types;
 
+pub use export::{
+    DatasetSchema, ExportBatch, ExportFormat, ExportJob, ExportJobStatus, ExportSink, ExportTarget,
+    IndexExporter,
+};
 pub use service::{IndexingService, IndexingServiceTrait};
 pub use storage::{IndexingStorage, MemoryIndexingStorage};
 pub use types::{IndexingError, IndexingQuery, IndexingResult};
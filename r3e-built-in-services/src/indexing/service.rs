@@ -1,6 +1,7 @@
 // Copyright @ 2023 - 2024, R3E Network
 // All Rights Reserved
 
+use crate::indexing::export::IndexExporter;
 use crate::indexing::storage::IndexingStorage;
 use crate::indexing::types::{
     CollectionStats, IndexDefinition, IndexingError, IndexingQuery, IndexingResult,
@@ -56,17 +57,52 @@ pub trait IndexingServiceTrait: Send + Sync {
 pub struct IndexingService<S: IndexingStorage> {
     /// Storage backend
     storage: Arc<S>,
+
+    /// Exporter of the indexed documents to the sinks of their collections
+    exporter: Option<Arc<IndexExporter<S>>>,
 }
 
-impl<S: IndexingStorage> IndexingService<S> {
+impl<S: IndexingStorage + 'static> IndexingService<S> {
     /// Create a new indexing service
     pub fn new(storage: Arc<S>) -> Self {
-        Self { storage }
+        Self {
+            storage,
+            exporter: None,
+        }
+    }
+
+    /// Export indexed and updated documents with an exporter
+    pub fn with_exporter(mut self, exporter: Arc<IndexExporter<S>>) -> Self {
+        self.exporter = Some(exporter);
+        self
+    }
+
+    /// Exporter of the indexed documents, if any
+    pub fn exporter(&self) -> Option<&Arc<IndexExporter<S>>> {
+        self.exporter.as_ref()
+    }
+
+    /// Hand the stored version of a document to the exporter
+    async fn export(&self, collection: &str, id: &str) {
+        let Some(exporter) = &self.exporter else {
+            return;
+        };
+        let document = match self.storage.get_document(collection, id).await {
+            Ok(Some(document)) => document,
+            Ok(None) => return,
+            Err(e) => {
+                log::warn!("Failed to read document {} for export: {}", id, e);
+                return;
+            }
+        };
+        if let Err(e) = exporter.record(collection, document).await {
+            log::warn!("Failed to export document {}: {}", id, e);
+        }
     }
 }
 
 #[async_trait]
-impl<S: IndexingStorage> IndexingServiceTrait for IndexingService<S> {
+impl<S: IndexingStorage + 'static> IndexingServiceTrait for IndexingService<S> {
     async fn query(&self, query: IndexingQuery) -> Result<IndexingResult, IndexingError> {
         self.storage.query(query).await
     }
@@ -76,7 +112,9 @@ impl<S: IndexingStorage> IndexingServiceTrait for IndexingService<S> {
         collection: &str,
         data: serde_json::Value,
     ) -> Result<String, IndexingError> {
-        self.storage.index_data(collection, data).await
+        let id = self.storage.index_data(collection, data).await?;
+        self.export(collection, &id).await;
+        Ok(id)
     }
 
     async fn get_collections(&self) -> Result<Vec<String>, IndexingError> {
@@ -104,7 +142,11 @@ impl<S: IndexingStorage> IndexingServiceTrait for IndexingService<S> {
         id: &str,
         data: serde_json::Value,
     ) -> Result<bool, IndexingError> {
-        self.storage.update_document(collection, id, data).await
+        let updated = self.storage.update_document(collection, id, data).await?;
+        if updated {
+            self.export(collection, id).await;
+        }
+        Ok(updated)
     }
 
     async fn get_document(
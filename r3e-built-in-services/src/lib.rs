@@ -2,16 +2,22 @@
 // All Rights Reserved
 
 // Re-export all built-in services
+pub mod alert_rules;
+pub mod alerts;
 pub mod auto_contract;
 pub mod balance;
+pub mod billing;
 pub mod bridge;
 pub mod fhe;
 pub mod gas_bank;
 pub mod identity;
 pub mod indexing;
+pub mod notifications;
 pub mod oracle;
 pub mod pricing;
+pub mod quota;
 pub mod tee;
+pub mod workflow;
 pub mod zk;
 
 // Error types
@@ -29,6 +35,9 @@ pub enum ServiceError {
     #[error("Balance error: {0}")]
     Balance(String),
 
+    #[error("Billing error: {0}")]
+    Billing(#[from] billing::BillingError),
+
     #[error("Indexing error: {0}")]
     Indexing(#[from] indexing::IndexingError),
 
@@ -41,9 +50,18 @@ pub enum ServiceError {
     #[error("Pricing error: {0}")]
     Pricing(#[from] pricing::PricingError),
 
+    #[error("Quota error: {0}")]
+    Quota(#[from] quota::QuotaError),
+
+    #[error("Notification error: {0}")]
+    Notification(#[from] notifications::NotificationError),
+
     #[error("Auto contract error: {0}")]
     AutoContract(#[from] auto_contract::AutoContractError),
 
+    #[error("Workflow error: {0}")]
+    Workflow(#[from] workflow::WorkflowError),
+
     #[error("Zero-Knowledge error: {0}")]
     Zk(#[from] zk::ZkError),
 
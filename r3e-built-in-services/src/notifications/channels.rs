@@ -0,0 +1,281 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+//! Transports delivering alerts to the targets of channels.
+
+use std::time::Duration;
+
+use async_trait::async_trait;
+use hmac::{Hmac, Mac};
+use lettre::message::header::ContentType;
+use lettre::message::Mailbox;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use r3e_core::egress::EgressGuard;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+
+use crate::alerts::Alert;
+use crate::notifications::types::{ChannelTarget, NotificationError};
+
+/// Header of the HMAC-SHA256 signature of webhook bodies
+pub const SIGNATURE_HEADER: &str = "X-R3E-Signature";
+
+/// Timeout of a single delivery attempt
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(10);
+
+fn default_smtp_port() -> u16 {
+    587
+}
+
+fn default_starttls() -> bool {
+    true
+}
+
+fn default_telegram_api_url() -> String {
+    "https://api.telegram.org".to_string()
+}
+
+/// SMTP server email channels are sent through
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SmtpConfig {
+    /// Server host
+    pub host: String,
+
+    /// Server port
+    #[serde(default = "default_smtp_port")]
+    pub port: u16,
+
+    /// Login user, if the server requires one
+    #[serde(default)]
+    pub username: Option<String>,
+
+    /// Login password
+    #[serde(default)]
+    pub password: Option<String>,
+
+    /// Sender address, e.g. `R3E Alerts <alerts@example.com>`
+    pub from: String,
+
+    /// Whether to upgrade the connection with STARTTLS, only disable for local relays
+    #[serde(default = "default_starttls")]
+    pub starttls: bool,
+}
+
+/// Telegram bot Telegram channels are sent by
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TelegramConfig {
+    /// Bot token issued by BotFather
+    pub bot_token: String,
+
+    /// Bot API URL
+    #[serde(default = "default_telegram_api_url")]
+    pub api_url: String,
+}
+
+/// Delivers alerts to channel targets
+#[async_trait]
+pub trait Notifier: Send + Sync {
+    /// Deliver an alert to a target
+    async fn notify(&self, target: &ChannelTarget, alert: &Alert) -> Result<(), String>;
+}
+
+/// Notifier sending email over SMTP, posting webhooks and sending Telegram messages
+pub struct Transports {
+    /// HTTP client of the Telegram bot API
+    http: reqwest::Client,
+
+    /// SMTP transport and sender, if email is configured
+    smtp: Option<(AsyncSmtpTransport<Tokio1Executor>, Mailbox)>,
+
+    /// Telegram bot, if configured
+    telegram: Option<TelegramConfig>,
+
+    /// Guard keeping user supplied webhook URLs off internal addresses
+    egress_guard: EgressGuard,
+}
+
+impl Transports {
+    /// Create the transports, email and Telegram channels failing without their configuration
+    pub fn new(
+        smtp: Option<&SmtpConfig>,
+        telegram: Option<TelegramConfig>,
+    ) -> Result<Self, NotificationError> {
+        let http = reqwest::Client::builder()
+            .timeout(DELIVERY_TIMEOUT)
+            .build()
+            .map_err(|e| NotificationError::InvalidInput(format!("HTTP client: {}", e)))?;
+
+        let smtp = match smtp {
+            Some(config) => {
+                let builder = if config.starttls {
+                    AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&config.host)
+                        .map_err(|e| NotificationError::InvalidInput(format!("SMTP: {}", e)))?
+                } else {
+                    AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&config.host)
+                };
+                let mut builder = builder.port(config.port).timeout(Some(DELIVERY_TIMEOUT));
+                if let (Some(username), Some(password)) = (&config.username, &config.password) {
+                    builder =
+                        builder.credentials(Credentials::new(username.clone(), password.clone()));
+                }
+
+                let from = config.from.parse::<Mailbox>().map_err(|e| {
+                    NotificationError::InvalidInput(format!(
+                        "Invalid sender {}: {}",
+                        config.from, e
+                    ))
+                })?;
+                Some((builder.build(), from))
+            }
+            None => None,
+        };
+
+        Ok(Self {
+            http,
+            smtp,
+            telegram,
+            egress_guard: EgressGuard::new(),
+        })
+    }
+
+    /// Check webhook URLs against an egress guard other than the default one
+    pub fn with_egress_guard(mut self, egress_guard: EgressGuard) -> Self {
+        self.egress_guard = egress_guard;
+        self
+    }
+
+    async fn send_email(&self, address: &str, alert: &Alert) -> Result<(), String> {
+        let (transport, from) = self
+            .smtp
+            .as_ref()
+            .ok_or_else(|| "email is not configured".to_string())?;
+
+        let to = address
+            .parse::<Mailbox>()
+            .map_err(|e| format!("invalid recipient {}: {}", address, e))?;
+        let message = Message::builder()
+            .from(from.clone())
+            .to(to)
+            .subject(subject(alert))
+            .header(ContentType::TEXT_PLAIN)
+            .body(text(alert))
+            .map_err(|e| format!("invalid email: {}", e))?;
+
+        transport
+            .send(message)
+            .await
+            .map_err(|e| format!("SMTP: {}", e))?;
+        Ok(())
+    }
+
+    async fn post_webhook(
+        &self,
+        url: &str,
+        secret: Option<&str>,
+        alert: &Alert,
+    ) -> Result<(), String> {
+        let body = serde_json::to_vec(alert).map_err(|e| e.to_string())?;
+
+        // Pin the connection to the checked addresses
+        let resolved = self
+            .egress_guard
+            .check_url(url)
+            .await
+            .map_err(|e| e.to_string())?;
+        let client = reqwest::Client::builder()
+            .resolve_to_addrs(&resolved.host, &resolved.addrs)
+            .redirect(reqwest::redirect::Policy::none())
+            .timeout(DELIVERY_TIMEOUT)
+            .build()
+            .map_err(|e| format!("HTTP client: {}", e))?;
+
+        let mut request = client
+            .post(resolved.url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json");
+        if let Some(secret) = secret {
+            request = request.header(SIGNATURE_HEADER, sign(secret, &body));
+        }
+
+        let response = request
+            .body(body)
+            .send()
+            .await
+            .map_err(|e| format!("webhook: {}", e))?;
+        if !response.status().is_success() {
+            return Err(format!("webhook responded {}", response.status()));
+        }
+        Ok(())
+    }
+
+    async fn send_telegram(&self, chat_id: &str, alert: &Alert) -> Result<(), String> {
+        let telegram = self
+            .telegram
+            .as_ref()
+            .ok_or_else(|| "Telegram is not configured".to_string())?;
+
+        let url = format!(
+            "{}/bot{}/sendMessage",
+            telegram.api_url.trim_end_matches('/'),
+            telegram.bot_token
+        );
+        let response = self
+            .http
+            .post(url)
+            .json(&serde_json::json!({
+                "chat_id": chat_id,
+                "text": format!("{}\n\n{}", subject(alert), text(alert)),
+            }))
+            .send()
+            .await
+            // The URL holds the bot token, keep it out of the error
+            .map_err(|e| format!("Telegram: {}", e.without_url()))?;
+        if !response.status().is_success() {
+            return Err(format!("Telegram responded {}", response.status()));
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Notifier for Transports {
+    async fn notify(&self, target: &ChannelTarget, alert: &Alert) -> Result<(), String> {
+        match target {
+            ChannelTarget::Email { address } => self.send_email(address, alert).await,
+            ChannelTarget::Webhook { url, secret } => {
+                self.post_webhook(url, secret.as_deref(), alert).await
+            }
+            ChannelTarget::Telegram { chat_id } => self.send_telegram(chat_id, alert).await,
+        }
+    }
+}
+
+/// Subject line of an alert
+pub fn subject(alert: &Alert) -> String {
+    format!(
+        "[R3E {}] {}",
+        format!("{:?}", alert.severity).to_uppercase(),
+        alert.key
+    )
+}
+
+/// Text of an alert
+pub fn text(alert: &Alert) -> String {
+    let mut text = alert.message.clone();
+    if let Some(tenant) = &alert.tenant {
+        text.push_str(&format!("\nTenant: {}", tenant));
+    }
+    let time = chrono::DateTime::from_timestamp(alert.timestamp as i64, 0)
+        .map(|time| time.to_rfc3339())
+        .unwrap_or_else(|| alert.timestamp.to_string());
+    text.push_str(&format!("\nRaised at: {}", time));
+    text
+}
+
+/// Hex encoded HMAC-SHA256 signature of a webhook body, prefixed with `sha256=`
+pub fn sign(secret: &str, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any size");
+    mac.update(body);
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
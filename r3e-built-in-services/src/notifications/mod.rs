@@ -0,0 +1,22 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+//! Alert notifications.
+//!
+//! Users configure notification channels, email over SMTP, webhooks or a Telegram bot, each
+//! with a routing rule selecting the alerts it is notified of by severity and key. The
+//! [`NotificationService`] is an [`AlertSink`](crate::alerts::AlertSink), so alerts raised
+//! through the [`AlertManager`](crate::alerts::AlertManager) reach the channels they are
+//! routed to, and every delivery is recorded with its status.
+
+pub mod channels;
+pub mod rocksdb;
+pub mod service;
+pub mod storage;
+pub mod types;
+
+pub use channels::*;
+pub use rocksdb::RocksDBNotificationStorage;
+pub use service::*;
+pub use storage::*;
+pub use types::*;
@@ -0,0 +1,181 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+use async_trait::async_trait;
+use r3e_store::rocksdb::RocksDBStore;
+use r3e_store::{KvStore, SortedKvStore};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::notifications::storage::NotificationStorage;
+use crate::notifications::types::{Delivery, NotificationChannel, NotificationError};
+
+/// RocksDB implementation of NotificationStorage
+pub struct RocksDBNotificationStorage {
+    db: Arc<RocksDBStore>,
+    channels_cf: String,
+    deliveries_cf: String,
+}
+
+impl RocksDBNotificationStorage {
+    /// Create a new RocksDB notification storage
+    pub fn new<P: AsRef<Path>>(db_path: P) -> Result<Self, NotificationError> {
+        let db = RocksDBStore::new(db_path).map_err(|e| {
+            NotificationError::Storage(format!("Failed to create RocksDB store: {}", e))
+        })?;
+
+        Ok(Self {
+            db: Arc::new(db),
+            channels_cf: "notification_channels".to_string(),
+            deliveries_cf: "notification_deliveries".to_string(),
+        })
+    }
+
+    fn get_json<T: DeserializeOwned>(
+        &self,
+        cf: &str,
+        key: &str,
+    ) -> Result<Option<T>, NotificationError> {
+        match self.db.get(cf, key.as_bytes()) {
+            Ok(value) => serde_json::from_slice(&value).map(Some).map_err(|e| {
+                NotificationError::Storage(format!("Failed to deserialize {}: {}", cf, e))
+            }),
+            Err(r3e_store::GetError::NoSuchKey) => Ok(None),
+            Err(e) => Err(NotificationError::Storage(format!(
+                "Failed to get {}: {}",
+                cf, e
+            ))),
+        }
+    }
+
+    fn put_json<T: Serialize>(
+        &self,
+        cf: &str,
+        key: &str,
+        value: &T,
+    ) -> Result<(), NotificationError> {
+        let value = serde_json::to_vec(value).map_err(|e| {
+            NotificationError::Storage(format!("Failed to serialize {}: {}", cf, e))
+        })?;
+
+        let input = r3e_store::PutInput {
+            key: key.as_bytes(),
+            value: &value,
+            if_not_exists: false,
+        };
+
+        self.db
+            .put(cf, input)
+            .map_err(|e| NotificationError::Storage(format!("Failed to store {}: {}", cf, e)))
+    }
+
+    /// Scan the values with keys in `[start, end)`
+    fn scan_json<T: DeserializeOwned>(
+        &self,
+        cf: &str,
+        start: &[u8],
+        end: &[u8],
+    ) -> Result<Vec<T>, NotificationError> {
+        let mut values = Vec::new();
+        let mut start_key = start.to_vec();
+        let mut start_exclusive = false;
+
+        loop {
+            let input = r3e_store::ScanInput {
+                start_key: &start_key,
+                start_exclusive,
+                end_key: end,
+                end_inclusive: false,
+                reverse: false,
+                max_count: 1000,
+            };
+
+            let output = self
+                .db
+                .scan(cf, input)
+                .map_err(|e| NotificationError::Storage(format!("Failed to scan {}: {}", cf, e)))?;
+
+            for (_, value) in &output.kvs {
+                values.push(serde_json::from_slice(value).map_err(|e| {
+                    NotificationError::Storage(format!("Failed to deserialize {}: {}", cf, e))
+                })?);
+            }
+
+            match output.kvs.last() {
+                Some((key, _)) if output.has_more => {
+                    start_key = key.clone();
+                    start_exclusive = true;
+                }
+                _ => break,
+            }
+        }
+
+        Ok(values)
+    }
+}
+
+/// Keys are `<owner>/<...>`, so the keys of an owner sort between `<owner>/` and `<owner>0`
+fn owner_range(owner: &str) -> (String, String) {
+    (format!("{}/", owner), format!("{}0", owner))
+}
+
+#[async_trait]
+impl NotificationStorage for RocksDBNotificationStorage {
+    async fn get_channel(
+        &self,
+        owner: &str,
+        id: &str,
+    ) -> Result<Option<NotificationChannel>, NotificationError> {
+        self.get_json(&self.channels_cf, &format!("{}/{}", owner, id))
+    }
+
+    async fn put_channel(&self, channel: NotificationChannel) -> Result<(), NotificationError> {
+        let key = format!("{}/{}", channel.owner, channel.id);
+        self.put_json(&self.channels_cf, &key, &channel)
+    }
+
+    async fn delete_channel(&self, owner: &str, id: &str) -> Result<(), NotificationError> {
+        let key = format!("{}/{}", owner, id);
+        self.db
+            .delete(&self.channels_cf, key.as_bytes())
+            .map_err(|e| {
+                NotificationError::Storage(format!("Failed to delete notification channel: {}", e))
+            })?;
+        Ok(())
+    }
+
+    async fn list_channels(
+        &self,
+        owner: &str,
+    ) -> Result<Vec<NotificationChannel>, NotificationError> {
+        let (start, end) = owner_range(owner);
+        let mut channels: Vec<NotificationChannel> =
+            self.scan_json(&self.channels_cf, start.as_bytes(), end.as_bytes())?;
+
+        channels.sort_by_key(|channel| channel.created_at);
+        Ok(channels)
+    }
+
+    async fn put_delivery(&self, delivery: Delivery) -> Result<(), NotificationError> {
+        // Zero-padded timestamps sort the deliveries of an owner in time order
+        let key = format!(
+            "{}/{:020}/{}",
+            delivery.owner, delivery.created_at, delivery.id
+        );
+        self.put_json(&self.deliveries_cf, &key, &delivery)
+    }
+
+    async fn list_deliveries(
+        &self,
+        owner: &str,
+        limit: usize,
+    ) -> Result<Vec<Delivery>, NotificationError> {
+        let (start, end) = owner_range(owner);
+        let deliveries: Vec<Delivery> =
+            self.scan_json(&self.deliveries_cf, start.as_bytes(), end.as_bytes())?;
+
+        Ok(deliveries.into_iter().rev().take(limit).collect())
+    }
+}
@@ -0,0 +1,663 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::alerts::{Alert, AlertSeverity, AlertSink};
+use crate::notifications::channels::{Notifier, SmtpConfig, TelegramConfig, Transports};
+use crate::notifications::storage::NotificationStorage;
+use crate::notifications::types::{
+    ChannelRequest, Delivery, DeliveryStatus, NotificationChannel, NotificationError, OPERATORS,
+};
+
+/// Most channels a user may have
+pub const MAX_CHANNELS_PER_OWNER: usize = 20;
+
+fn default_dedup_window_secs() -> u64 {
+    3600
+}
+
+fn default_max_per_window() -> u32 {
+    20
+}
+
+fn default_window_secs() -> u64 {
+    3600
+}
+
+fn default_max_attempts() -> u32 {
+    3
+}
+
+/// Throttling of the notifications of each channel
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ThrottleConfig {
+    /// Time the same alert is not sent to a channel again (in seconds)
+    #[serde(default = "default_dedup_window_secs")]
+    pub dedup_window_secs: u64,
+
+    /// Most notifications a channel gets within the rate window
+    #[serde(default = "default_max_per_window")]
+    pub max_per_window: u32,
+
+    /// Rate window (in seconds)
+    #[serde(default = "default_window_secs")]
+    pub window_secs: u64,
+}
+
+impl Default for ThrottleConfig {
+    fn default() -> Self {
+        Self {
+            dedup_window_secs: default_dedup_window_secs(),
+            max_per_window: default_max_per_window(),
+            window_secs: default_window_secs(),
+        }
+    }
+}
+
+/// Notification configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NotificationConfig {
+    /// SMTP server of email channels, which fail without one
+    #[serde(default)]
+    pub smtp: Option<SmtpConfig>,
+
+    /// Bot of Telegram channels, which fail without one
+    #[serde(default)]
+    pub telegram: Option<TelegramConfig>,
+
+    /// Throttling of repeated alerts
+    #[serde(default)]
+    pub throttle: ThrottleConfig,
+
+    /// Attempts of a delivery before it fails
+    #[serde(default = "default_max_attempts")]
+    pub max_attempts: u32,
+}
+
+impl Default for NotificationConfig {
+    fn default() -> Self {
+        Self {
+            smtp: None,
+            telegram: None,
+            throttle: ThrottleConfig::default(),
+            max_attempts: default_max_attempts(),
+        }
+    }
+}
+
+/// Notifications recently sent to a channel
+#[derive(Debug, Default)]
+struct ChannelHistory {
+    /// When notifications were sent within the rate window, oldest first
+    sent: VecDeque<u64>,
+
+    /// When each alert was last sent within the dedup window
+    alerts: HashMap<String, u64>,
+}
+
+/// Deduplicates and rate limits the notifications of each channel
+pub struct Throttle {
+    config: ThrottleConfig,
+    channels: Mutex<HashMap<String, ChannelHistory>>,
+}
+
+impl Throttle {
+    /// Create a new throttle
+    pub fn new(config: ThrottleConfig) -> Self {
+        Self {
+            config,
+            channels: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Alerts with the same key and message are duplicates
+    fn fingerprint(alert: &Alert) -> String {
+        format!("{}\n{}", alert.key, alert.message)
+    }
+
+    /// Admit a notification of a channel at `now`, or the status it is suppressed with
+    pub fn admit(&self, channel_id: &str, alert: &Alert, now: u64) -> Result<(), DeliveryStatus> {
+        let mut channels = self.channels.lock().unwrap();
+        let history = channels.entry(channel_id.to_string()).or_default();
+
+        let dedup_window = self.config.dedup_window_secs;
+        history
+            .alerts
+            .retain(|_, sent_at| now < sent_at.saturating_add(dedup_window));
+        let fingerprint = Self::fingerprint(alert);
+        if history.alerts.contains_key(&fingerprint) {
+            return Err(DeliveryStatus::Duplicate);
+        }
+
+        while let Some(sent_at) = history.sent.front() {
+            if now < sent_at.saturating_add(self.config.window_secs) {
+                break;
+            }
+            history.sent.pop_front();
+        }
+        if history.sent.len() >= self.config.max_per_window as usize {
+            return Err(DeliveryStatus::Throttled);
+        }
+
+        history.sent.push_back(now);
+        history.alerts.insert(fingerprint, now);
+        Ok(())
+    }
+
+    /// A notification admitted for a channel was not delivered, so the alert may be sent again
+    pub fn forget(&self, channel_id: &str, alert: &Alert) {
+        if let Some(history) = self.channels.lock().unwrap().get_mut(channel_id) {
+            history.alerts.remove(&Self::fingerprint(alert));
+        }
+    }
+}
+
+/// Routes alerts to the notification channels of users and tracks their delivery.
+///
+/// An alert about a tenant is routed to the tenant's channels, and every alert to the
+/// channels of the [`OPERATORS`]. A channel is notified of the alerts matching its routing
+/// rule, repeated alerts throttled per channel.
+#[derive(Clone)]
+pub struct NotificationService {
+    /// Storage
+    storage: Arc<dyn NotificationStorage>,
+
+    /// Transports delivering the notifications
+    notifier: Arc<dyn Notifier>,
+
+    /// Deduplication and rate limits of the channels
+    throttle: Arc<Throttle>,
+
+    /// Attempts of a delivery before it fails
+    max_attempts: u32,
+
+    /// Delay before the first retry of a delivery, doubled for every following retry
+    retry_backoff: Duration,
+}
+
+impl NotificationService {
+    /// Create a new notification service delivering through `notifier`
+    pub fn new(
+        storage: Arc<dyn NotificationStorage>,
+        notifier: Arc<dyn Notifier>,
+        config: &NotificationConfig,
+    ) -> Self {
+        Self {
+            storage,
+            notifier,
+            throttle: Arc::new(Throttle::new(config.throttle.clone())),
+            max_attempts: config.max_attempts.max(1),
+            retry_backoff: Duration::from_secs(1),
+        }
+    }
+
+    /// Create a new notification service delivering through the configured transports
+    pub fn with_transports(
+        storage: Arc<dyn NotificationStorage>,
+        config: &NotificationConfig,
+    ) -> Result<Self, NotificationError> {
+        let transports = Transports::new(config.smtp.as_ref(), config.telegram.clone())?;
+        Ok(Self::new(storage, Arc::new(transports), config))
+    }
+
+    /// Create a channel
+    pub async fn create_channel(
+        &self,
+        owner: &str,
+        request: ChannelRequest,
+    ) -> Result<NotificationChannel, NotificationError> {
+        request.target.validate()?;
+        if self.storage.list_channels(owner).await?.len() >= MAX_CHANNELS_PER_OWNER {
+            return Err(NotificationError::InvalidInput(format!(
+                "At most {} notification channels are allowed",
+                MAX_CHANNELS_PER_OWNER
+            )));
+        }
+
+        let now = chrono::Utc::now().timestamp() as u64;
+        let channel = NotificationChannel {
+            id: Uuid::new_v4().to_string(),
+            owner: owner.to_string(),
+            name: request.name,
+            target: request.target,
+            routing: request.routing,
+            enabled: request.enabled,
+            created_at: now,
+            updated_at: now,
+        };
+        self.storage.put_channel(channel.clone()).await?;
+
+        log::info!("notifications: created channel {} of {}", channel.id, owner);
+        Ok(channel)
+    }
+
+    /// Get a channel
+    pub async fn get_channel(
+        &self,
+        owner: &str,
+        id: &str,
+    ) -> Result<NotificationChannel, NotificationError> {
+        self.storage
+            .get_channel(owner, id)
+            .await?
+            .ok_or_else(|| NotificationError::NotFound(format!("Channel not found: {}", id)))
+    }
+
+    /// Replace the settings of a channel
+    pub async fn update_channel(
+        &self,
+        owner: &str,
+        id: &str,
+        request: ChannelRequest,
+    ) -> Result<NotificationChannel, NotificationError> {
+        request.target.validate()?;
+        let mut channel = self.get_channel(owner, id).await?;
+
+        channel.name = request.name;
+        channel.target = request.target;
+        channel.routing = request.routing;
+        channel.enabled = request.enabled;
+        channel.updated_at = chrono::Utc::now().timestamp() as u64;
+        self.storage.put_channel(channel.clone()).await?;
+
+        Ok(channel)
+    }
+
+    /// Delete a channel
+    pub async fn delete_channel(&self, owner: &str, id: &str) -> Result<(), NotificationError> {
+        self.get_channel(owner, id).await?;
+        self.storage.delete_channel(owner, id).await
+    }
+
+    /// List the channels of a user
+    pub async fn list_channels(
+        &self,
+        owner: &str,
+    ) -> Result<Vec<NotificationChannel>, NotificationError> {
+        self.storage.list_channels(owner).await
+    }
+
+    /// List the latest deliveries to the channels of a user, newest first
+    pub async fn list_deliveries(
+        &self,
+        owner: &str,
+        limit: usize,
+    ) -> Result<Vec<Delivery>, NotificationError> {
+        self.storage.list_deliveries(owner, limit).await
+    }
+
+    /// Send a test notification to a channel, whatever its routing rule and throttling
+    pub async fn test_channel(&self, owner: &str, id: &str) -> Result<Delivery, NotificationError> {
+        let channel = self.get_channel(owner, id).await?;
+        let alert = Alert::new(
+            "notifications.test",
+            AlertSeverity::Info,
+            format!("Test notification of channel {}", channel.name),
+        );
+
+        self.deliver(&channel, &alert).await
+    }
+
+    /// Notify the channels an alert is routed to, returning the deliveries
+    pub async fn notify(&self, alert: &Alert) -> Result<Vec<Delivery>, NotificationError> {
+        let mut owners = vec![OPERATORS];
+        if let Some(tenant) = alert
+            .tenant
+            .as_deref()
+            .filter(|tenant| *tenant != OPERATORS)
+        {
+            owners.push(tenant);
+        }
+
+        let mut deliveries = Vec::new();
+        for owner in owners {
+            for channel in self.storage.list_channels(owner).await? {
+                if !channel.enabled || !channel.routing.matches(alert) {
+                    continue;
+                }
+
+                let now = chrono::Utc::now().timestamp() as u64;
+                let delivery = match self.throttle.admit(&channel.id, alert, now) {
+                    Ok(()) => self.deliver(&channel, alert).await?,
+                    Err(status) => {
+                        let delivery = Self::delivery(&channel, alert, status, 0, None);
+                        self.storage.put_delivery(delivery.clone()).await?;
+                        delivery
+                    }
+                };
+                deliveries.push(delivery);
+            }
+        }
+
+        Ok(deliveries)
+    }
+
+    /// Deliver an alert to a channel, retrying failed attempts, and record the delivery
+    async fn deliver(
+        &self,
+        channel: &NotificationChannel,
+        alert: &Alert,
+    ) -> Result<Delivery, NotificationError> {
+        let mut backoff = self.retry_backoff;
+        let mut attempts = 0;
+        let mut error = None;
+
+        while attempts < self.max_attempts {
+            if attempts > 0 {
+                tokio::time::sleep(backoff).await;
+                backoff *= 2;
+            }
+            attempts += 1;
+
+            match self.notifier.notify(&channel.target, alert).await {
+                Ok(()) => {
+                    error = None;
+                    break;
+                }
+                Err(err) => error = Some(err),
+            }
+        }
+
+        let status = match &error {
+            None => DeliveryStatus::Delivered,
+            Some(err) => {
+                log::warn!(
+                    "notifications: failed to deliver alert {} to channel {}: {}",
+                    alert.key,
+                    channel.id,
+                    err
+                );
+                self.throttle.forget(&channel.id, alert);
+                DeliveryStatus::Failed
+            }
+        };
+
+        let delivery = Self::delivery(channel, alert, status, attempts, error);
+        self.storage.put_delivery(delivery.clone()).await?;
+        Ok(delivery)
+    }
+
+    fn delivery(
+        channel: &NotificationChannel,
+        alert: &Alert,
+        status: DeliveryStatus,
+        attempts: u32,
+        error: Option<String>,
+    ) -> Delivery {
+        Delivery {
+            id: Uuid::new_v4().to_string(),
+            channel_id: channel.id.clone(),
+            owner: channel.owner.clone(),
+            alert: alert.clone(),
+            status,
+            attempts,
+            error,
+            created_at: chrono::Utc::now().timestamp() as u64,
+        }
+    }
+}
+
+#[async_trait]
+impl AlertSink for NotificationService {
+    async fn send(&self, alert: &Alert) -> Result<(), String> {
+        // Alerts are raised on the invocation path, deliver them in the background
+        let service = self.clone();
+        let alert = alert.clone();
+        tokio::spawn(async move {
+            if let Err(err) = service.notify(&alert).await {
+                log::error!(
+                    "notifications: failed to route alert {}: {}",
+                    alert.key,
+                    err
+                );
+            }
+        });
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::notifications::storage::MemoryNotificationStorage;
+    use crate::notifications::types::{ChannelTarget, RoutingRule};
+
+    /// Notifier recording deliveries, failing the first `failures` attempts
+    #[derive(Default)]
+    struct MockNotifier {
+        failures: Mutex<u32>,
+        sent: Mutex<Vec<(ChannelTarget, String)>>,
+    }
+
+    #[async_trait]
+    impl Notifier for MockNotifier {
+        async fn notify(&self, target: &ChannelTarget, alert: &Alert) -> Result<(), String> {
+            let mut failures = self.failures.lock().unwrap();
+            if *failures > 0 {
+                *failures -= 1;
+                return Err("connection refused".to_string());
+            }
+            self.sent
+                .lock()
+                .unwrap()
+                .push((target.clone(), alert.key.clone()));
+            Ok(())
+        }
+    }
+
+    fn service(notifier: Arc<MockNotifier>) -> NotificationService {
+        let mut service = NotificationService::new(
+            Arc::new(MemoryNotificationStorage::new()),
+            notifier,
+            &NotificationConfig::default(),
+        );
+        service.retry_backoff = Duration::ZERO;
+        service
+    }
+
+    fn webhook(url: &str, routing: RoutingRule) -> ChannelRequest {
+        ChannelRequest {
+            name: url.to_string(),
+            target: ChannelTarget::Webhook {
+                url: url.to_string(),
+                secret: None,
+            },
+            routing,
+            enabled: true,
+        }
+    }
+
+    fn alert(key: &str, severity: AlertSeverity, message: &str) -> Alert {
+        Alert::new(key, severity, message)
+    }
+
+    #[test]
+    fn test_throttle() {
+        let throttle = Throttle::new(ThrottleConfig {
+            dedup_window_secs: 100,
+            max_per_window: 2,
+            window_secs: 10,
+        });
+        let low = alert("balance.low", AlertSeverity::Warning, "low");
+
+        // The same alert is a duplicate within the dedup window, on its channel only
+        assert_eq!(throttle.admit("c1", &low, 0), Ok(()));
+        assert_eq!(
+            throttle.admit("c1", &low, 50),
+            Err(DeliveryStatus::Duplicate)
+        );
+        assert_eq!(throttle.admit("c2", &low, 50), Ok(()));
+        assert_eq!(throttle.admit("c1", &low, 100), Ok(()));
+
+        // Other alerts are rate limited per window
+        let other = |n: u32| alert("balance.low", AlertSeverity::Warning, &n.to_string());
+        assert_eq!(throttle.admit("c1", &other(1), 101), Ok(()));
+        assert_eq!(
+            throttle.admit("c1", &other(2), 102),
+            Err(DeliveryStatus::Throttled)
+        );
+        assert_eq!(throttle.admit("c1", &other(2), 111), Ok(()));
+
+        // A forgotten alert may be sent again
+        throttle.forget("c1", &other(2));
+        assert_eq!(throttle.admit("c1", &other(2), 130), Ok(()));
+    }
+
+    #[tokio::test]
+    async fn test_notify_routing() {
+        let notifier = Arc::new(MockNotifier::default());
+        let service = service(notifier.clone());
+
+        let tenant = service
+            .create_channel(
+                "alice",
+                webhook("https://alice.example.com", RoutingRule::default()),
+            )
+            .await
+            .unwrap();
+        let operators = service
+            .create_channel(
+                OPERATORS,
+                webhook(
+                    "https://ops.example.com",
+                    RoutingRule {
+                        min_severity: AlertSeverity::Critical,
+                        key_prefixes: vec!["balance.".to_string()],
+                    },
+                ),
+            )
+            .await
+            .unwrap();
+        service
+            .create_channel(
+                "bob",
+                webhook("https://bob.example.com", RoutingRule::default()),
+            )
+            .await
+            .unwrap();
+        let mut disabled = webhook("https://off.example.com", RoutingRule::default());
+        disabled.enabled = false;
+        service.create_channel("alice", disabled).await.unwrap();
+
+        // A tenant's alert reaches its channels and the operators' matching ones
+        let critical =
+            alert("balance.empty", AlertSeverity::Critical, "empty").with_tenant("alice");
+        let deliveries = service.notify(&critical).await.unwrap();
+        let mut channels: Vec<_> = deliveries.iter().map(|d| d.channel_id.clone()).collect();
+        channels.sort();
+        let mut expected = vec![tenant.id.clone(), operators.id.clone()];
+        expected.sort();
+        assert_eq!(channels, expected);
+        assert!(deliveries
+            .iter()
+            .all(|d| d.status == DeliveryStatus::Delivered && d.attempts == 1));
+
+        // Below the operators' severity and outside their keys
+        let warning = alert("balance.low", AlertSeverity::Warning, "low").with_tenant("alice");
+        let deliveries = service.notify(&warning).await.unwrap();
+        assert_eq!(deliveries.len(), 1);
+        assert_eq!(deliveries[0].channel_id, tenant.id);
+        let other_key = alert("worker.down", AlertSeverity::Critical, "down");
+        assert!(service.notify(&other_key).await.unwrap().is_empty());
+
+        // The same alert again is recorded as a duplicate, not sent
+        let deliveries = service.notify(&warning).await.unwrap();
+        assert_eq!(deliveries[0].status, DeliveryStatus::Duplicate);
+        assert_eq!(deliveries[0].attempts, 0);
+        assert_eq!(notifier.sent.lock().unwrap().len(), 3);
+
+        let history = service.list_deliveries("alice", 10).await.unwrap();
+        assert_eq!(history.len(), 3);
+        assert_eq!(history[0].status, DeliveryStatus::Duplicate);
+        assert!(service.list_deliveries("bob", 10).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_delivery_retries() {
+        let notifier = Arc::new(MockNotifier::default());
+        let service = service(notifier.clone());
+        let channel = service
+            .create_channel(
+                "alice",
+                webhook("https://alice.example.com", RoutingRule::default()),
+            )
+            .await
+            .unwrap();
+        let down = alert("worker.down", AlertSeverity::Critical, "down").with_tenant("alice");
+
+        // A failed attempt is retried
+        *notifier.failures.lock().unwrap() = 1;
+        let deliveries = service.notify(&down).await.unwrap();
+        assert_eq!(deliveries[0].status, DeliveryStatus::Delivered);
+        assert_eq!(deliveries[0].attempts, 2);
+        assert_eq!(deliveries[0].error, None);
+
+        // Failing every attempt fails the delivery, and the alert is not deduplicated
+        let again =
+            alert("worker.down", AlertSeverity::Critical, "still down").with_tenant("alice");
+        *notifier.failures.lock().unwrap() = 3;
+        let deliveries = service.notify(&again).await.unwrap();
+        assert_eq!(deliveries[0].status, DeliveryStatus::Failed);
+        assert_eq!(deliveries[0].attempts, 3);
+        assert_eq!(deliveries[0].error.as_deref(), Some("connection refused"));
+        let deliveries = service.notify(&again).await.unwrap();
+        assert_eq!(deliveries[0].status, DeliveryStatus::Delivered);
+
+        // Test notifications skip the routing rule
+        let delivery = service.test_channel("alice", &channel.id).await.unwrap();
+        assert_eq!(delivery.alert.severity, AlertSeverity::Info);
+        assert_eq!(delivery.status, DeliveryStatus::Delivered);
+        assert!(service.test_channel("bob", &channel.id).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_channel_validation() {
+        let service = service(Arc::new(MockNotifier::default()));
+        let invalid = |target| ChannelRequest {
+            name: "invalid".to_string(),
+            target,
+            routing: RoutingRule::default(),
+            enabled: true,
+        };
+
+        for target in [
+            ChannelTarget::Email {
+                address: "alice".to_string(),
+            },
+            ChannelTarget::Webhook {
+                url: "ftp://example.com".to_string(),
+                secret: None,
+            },
+            ChannelTarget::Telegram {
+                chat_id: " ".to_string(),
+            },
+        ] {
+            assert!(matches!(
+                service.create_channel("alice", invalid(target)).await,
+                Err(NotificationError::InvalidInput(_))
+            ));
+        }
+
+        for i in 0..MAX_CHANNELS_PER_OWNER {
+            let url = format!("https://{}.example.com", i);
+            service
+                .create_channel("alice", webhook(&url, RoutingRule::default()))
+                .await
+                .unwrap();
+        }
+        assert!(service
+            .create_channel(
+                "alice",
+                webhook("https://x.example.com", RoutingRule::default())
+            )
+            .await
+            .is_err());
+    }
+}
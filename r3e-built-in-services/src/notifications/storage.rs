@@ -0,0 +1,116 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+use crate::notifications::types::{Delivery, NotificationChannel, NotificationError};
+
+/// Notification storage trait
+#[async_trait]
+pub trait NotificationStorage: Send + Sync {
+    /// Get a channel of an owner
+    async fn get_channel(
+        &self,
+        owner: &str,
+        id: &str,
+    ) -> Result<Option<NotificationChannel>, NotificationError>;
+
+    /// Create or update a channel
+    async fn put_channel(&self, channel: NotificationChannel) -> Result<(), NotificationError>;
+
+    /// Delete a channel of an owner
+    async fn delete_channel(&self, owner: &str, id: &str) -> Result<(), NotificationError>;
+
+    /// List the channels of an owner, oldest first
+    async fn list_channels(
+        &self,
+        owner: &str,
+    ) -> Result<Vec<NotificationChannel>, NotificationError>;
+
+    /// Record a delivery
+    async fn put_delivery(&self, delivery: Delivery) -> Result<(), NotificationError>;
+
+    /// List the latest deliveries to the channels of an owner, newest first
+    async fn list_deliveries(
+        &self,
+        owner: &str,
+        limit: usize,
+    ) -> Result<Vec<Delivery>, NotificationError>;
+}
+
+/// In-memory implementation of the notification storage
+#[derive(Default)]
+pub struct MemoryNotificationStorage {
+    /// Channels by owner and ID
+    channels: RwLock<HashMap<(String, String), NotificationChannel>>,
+
+    /// Deliveries in the order they were recorded
+    deliveries: RwLock<Vec<Delivery>>,
+}
+
+impl MemoryNotificationStorage {
+    /// Create a new memory-based notification storage
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl NotificationStorage for MemoryNotificationStorage {
+    async fn get_channel(
+        &self,
+        owner: &str,
+        id: &str,
+    ) -> Result<Option<NotificationChannel>, NotificationError> {
+        let channels = self.channels.read().unwrap();
+        Ok(channels.get(&(owner.to_string(), id.to_string())).cloned())
+    }
+
+    async fn put_channel(&self, channel: NotificationChannel) -> Result<(), NotificationError> {
+        let mut channels = self.channels.write().unwrap();
+        channels.insert((channel.owner.clone(), channel.id.clone()), channel);
+        Ok(())
+    }
+
+    async fn delete_channel(&self, owner: &str, id: &str) -> Result<(), NotificationError> {
+        let mut channels = self.channels.write().unwrap();
+        channels.remove(&(owner.to_string(), id.to_string()));
+        Ok(())
+    }
+
+    async fn list_channels(
+        &self,
+        owner: &str,
+    ) -> Result<Vec<NotificationChannel>, NotificationError> {
+        let channels = self.channels.read().unwrap();
+        let mut channels: Vec<_> = channels
+            .values()
+            .filter(|channel| channel.owner == owner)
+            .cloned()
+            .collect();
+        channels.sort_by_key(|channel| channel.created_at);
+        Ok(channels)
+    }
+
+    async fn put_delivery(&self, delivery: Delivery) -> Result<(), NotificationError> {
+        self.deliveries.write().unwrap().push(delivery);
+        Ok(())
+    }
+
+    async fn list_deliveries(
+        &self,
+        owner: &str,
+        limit: usize,
+    ) -> Result<Vec<Delivery>, NotificationError> {
+        let deliveries = self.deliveries.read().unwrap();
+        Ok(deliveries
+            .iter()
+            .rev()
+            .filter(|delivery| delivery.owner == owner)
+            .take(limit)
+            .cloned()
+            .collect())
+    }
+}
@@ -0,0 +1,235 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::alerts::{Alert, AlertSeverity};
+
+/// Owner of the channels every alert is routed to, whatever its tenant
+pub const OPERATORS: &str = "operators";
+
+/// Notification error
+#[derive(Debug, Error)]
+pub enum NotificationError {
+    #[error("Storage error: {0}")]
+    Storage(String),
+
+    #[error("Not found: {0}")]
+    NotFound(String),
+
+    #[error("Invalid input: {0}")]
+    InvalidInput(String),
+
+    #[error("Delivery failed: {0}")]
+    Delivery(String),
+}
+
+/// Where a channel delivers notifications
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum ChannelTarget {
+    /// Email sent through the configured SMTP server
+    Email {
+        /// Recipient address
+        address: String,
+    },
+
+    /// JSON alert posted to a URL
+    Webhook {
+        /// URL the alert is posted to
+        url: String,
+
+        /// Key of the HMAC-SHA256 signature of the body, sent in `X-R3E-Signature`
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        secret: Option<String>,
+    },
+
+    /// Message sent by the configured Telegram bot
+    Telegram {
+        /// Chat the bot sends to
+        chat_id: String,
+    },
+}
+
+impl ChannelTarget {
+    /// Check the target is well-formed
+    pub fn validate(&self) -> Result<(), NotificationError> {
+        match self {
+            ChannelTarget::Email { address } => {
+                let valid = address
+                    .split_once('@')
+                    .is_some_and(|(user, domain)| !user.is_empty() && domain.contains('.'));
+                if !valid {
+                    return Err(NotificationError::InvalidInput(format!(
+                        "Invalid email address: {}",
+                        address
+                    )));
+                }
+            }
+            ChannelTarget::Webhook { url, .. } => {
+                if !url.starts_with("https://") && !url.starts_with("http://") {
+                    return Err(NotificationError::InvalidInput(format!(
+                        "Webhook URL must be http or https: {}",
+                        url
+                    )));
+                }
+            }
+            ChannelTarget::Telegram { chat_id } => {
+                if chat_id.trim().is_empty() {
+                    return Err(NotificationError::InvalidInput(
+                        "Telegram chat ID is empty".to_string(),
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Target with its webhook secret hidden, for responses
+    pub fn redacted(&self) -> Self {
+        match self {
+            ChannelTarget::Webhook {
+                url,
+                secret: Some(_),
+            } => ChannelTarget::Webhook {
+                url: url.clone(),
+                secret: Some("********".to_string()),
+            },
+            target => target.clone(),
+        }
+    }
+}
+
+/// Alerts a channel is notified of
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RoutingRule {
+    /// Lowest severity notified
+    #[serde(default = "default_min_severity")]
+    pub min_severity: AlertSeverity,
+
+    /// Prefixes of the alert keys notified, e.g. `balance.`, empty for every key
+    #[serde(default)]
+    pub key_prefixes: Vec<String>,
+}
+
+fn default_min_severity() -> AlertSeverity {
+    AlertSeverity::Warning
+}
+
+impl Default for RoutingRule {
+    fn default() -> Self {
+        Self {
+            min_severity: default_min_severity(),
+            key_prefixes: Vec::new(),
+        }
+    }
+}
+
+impl RoutingRule {
+    /// Whether an alert is routed to the channel
+    pub fn matches(&self, alert: &Alert) -> bool {
+        alert.severity >= self.min_severity
+            && (self.key_prefixes.is_empty()
+                || self
+                    .key_prefixes
+                    .iter()
+                    .any(|prefix| alert.key.starts_with(prefix.as_str())))
+    }
+}
+
+/// Notification channel of a user
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NotificationChannel {
+    /// Channel ID
+    pub id: String,
+
+    /// User the channel belongs to, or [`OPERATORS`]
+    pub owner: String,
+
+    /// Display name
+    pub name: String,
+
+    /// Where notifications are delivered
+    pub target: ChannelTarget,
+
+    /// Alerts the channel is notified of
+    #[serde(default)]
+    pub routing: RoutingRule,
+
+    /// Whether the channel is notified
+    pub enabled: bool,
+
+    /// Creation timestamp
+    pub created_at: u64,
+
+    /// Last update timestamp
+    pub updated_at: u64,
+}
+
+/// Channel settings set by its owner
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChannelRequest {
+    /// Display name
+    pub name: String,
+
+    /// Where notifications are delivered
+    pub target: ChannelTarget,
+
+    /// Alerts the channel is notified of
+    #[serde(default)]
+    pub routing: RoutingRule,
+
+    /// Whether the channel is notified
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+/// Outcome of notifying a channel of an alert
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DeliveryStatus {
+    /// Delivered to the target
+    Delivered,
+
+    /// Every attempt failed
+    Failed,
+
+    /// Not sent, the same alert was delivered to the channel within the dedup window
+    Duplicate,
+
+    /// Not sent, the channel reached its notification limit
+    Throttled,
+}
+
+/// Delivery of an alert to a channel
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Delivery {
+    /// Delivery ID
+    pub id: String,
+
+    /// Channel notified
+    pub channel_id: String,
+
+    /// Owner of the channel
+    pub owner: String,
+
+    /// Alert delivered
+    pub alert: Alert,
+
+    /// Outcome
+    pub status: DeliveryStatus,
+
+    /// Delivery attempts made
+    pub attempts: u32,
+
+    /// Error of the last failed attempt
+    pub error: Option<String>,
+
+    /// Timestamp
+    pub created_at: u64,
+}
@@ -0,0 +1,441 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+use serde::{Deserialize, Serialize};
+
+use crate::pricing::types::{PricingError, PricingTier, ResourceType};
+
+/// How long a cost quote stays valid (in seconds)
+pub const QUOTE_TTL_SECS: u64 = 300;
+
+/// Resource limits of a function invocation
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResourceLimits {
+    /// Maximum execution time (in milliseconds)
+    pub max_execution_time_ms: u64,
+
+    /// Maximum memory (in MB)
+    pub max_memory_mb: u64,
+
+    /// Maximum network transfer (in MB)
+    pub max_network_mb: u64,
+
+    /// Maximum chain gas spent by transactions the function submits
+    pub max_chain_gas: u64,
+
+    /// Whether the function runs in a TEE
+    pub tee: bool,
+}
+
+impl Default for ResourceLimits {
+    fn default() -> Self {
+        Self {
+            max_execution_time_ms: 10_000,
+            max_memory_mb: 128,
+            max_network_mb: 10,
+            max_chain_gas: 0,
+            tee: false,
+        }
+    }
+}
+
+/// Historical execution metrics of a function, averaged per invocation
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ExecutionMetrics {
+    /// Number of invocations the averages are taken over
+    pub invocations: u64,
+
+    /// Average execution time (in milliseconds)
+    pub avg_execution_time_ms: Option<f64>,
+
+    /// Average memory (in MB)
+    pub avg_memory_mb: Option<f64>,
+
+    /// Average network transfer (in MB)
+    pub avg_network_mb: Option<f64>,
+
+    /// Average chain gas
+    pub avg_chain_gas: Option<f64>,
+}
+
+/// Cost quote request
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct QuoteRequest {
+    /// Resource limits of the invocation
+    pub limits: ResourceLimits,
+
+    /// Historical execution metrics, if the function has run before
+    pub metrics: Option<ExecutionMetrics>,
+
+    /// Chain gas price (in GAS per unit of chain gas)
+    pub chain_gas_price: f64,
+}
+
+/// What a quote's expected cost is based on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum QuoteBasis {
+    /// Historical execution metrics, capped by the resource limits
+    Historical,
+
+    /// Resource limits only, as the function has no execution history
+    Limits,
+}
+
+/// Cost quote line item
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QuoteItem {
+    /// Description
+    pub description: String,
+
+    /// Resource type, none for chain gas
+    pub resource_type: Option<ResourceType>,
+
+    /// Expected units
+    pub expected_units: u64,
+
+    /// Maximum units allowed by the resource limits
+    pub max_units: u64,
+
+    /// Expected cost (in GAS)
+    pub expected_cost: f64,
+
+    /// Maximum cost (in GAS)
+    pub max_cost: f64,
+}
+
+/// Pre-execution cost quote of a function invocation
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CostQuote {
+    /// Quote ID
+    pub id: String,
+
+    /// User ID
+    pub user_id: String,
+
+    /// Pricing tier the quote is priced at
+    pub tier: PricingTier,
+
+    /// What the expected cost is based on
+    pub basis: QuoteBasis,
+
+    /// Line items
+    pub items: Vec<QuoteItem>,
+
+    /// Expected cost (in GAS)
+    pub expected_cost: f64,
+
+    /// Worst-case cost when every resource limit is reached (in GAS)
+    pub max_cost: f64,
+
+    /// Created at
+    pub created_at: u64,
+
+    /// Expires at
+    pub expires_at: u64,
+}
+
+impl CostQuote {
+    /// Reject the invocation if its worst-case cost can exceed `max_cost`
+    pub fn check_max_cost(&self, max_cost: f64) -> Result<(), PricingError> {
+        self.check_batch_max_cost(1, max_cost)
+    }
+
+    /// Reject `count` invocations if their worst-case cost together can exceed `max_cost`
+    pub fn check_batch_max_cost(&self, count: u32, max_cost: f64) -> Result<(), PricingError> {
+        let total = self.max_cost * f64::from(count);
+        if total > max_cost {
+            let invocations = match count {
+                1 => String::new(),
+                count => format!(" of {} invocations", count),
+            };
+            return Err(PricingError::CostLimitExceeded(format!(
+                "worst-case cost {:.8} GAS{} exceeds the limit of {:.8} GAS",
+                total, invocations, max_cost
+            )));
+        }
+        Ok(())
+    }
+}
+
+impl QuoteRequest {
+    /// Expected and maximum units of each billed resource
+    pub(crate) fn resource_units(&self) -> Vec<(ResourceType, u64, u64)> {
+        let limits = &self.limits;
+        let metrics = self.metrics.as_ref().filter(|m| m.invocations > 0);
+        let expected = |avg: Option<f64>, max: u64| match avg {
+            Some(avg) => (avg.max(0.0).ceil() as u64).min(max),
+            None => max,
+        };
+
+        let execution_time = expected(
+            metrics.and_then(|m| m.avg_execution_time_ms),
+            limits.max_execution_time_ms,
+        );
+        let mut units = vec![
+            (
+                ResourceType::ExecutionTime,
+                execution_time,
+                limits.max_execution_time_ms,
+            ),
+            (
+                ResourceType::MemoryUsage,
+                expected(metrics.and_then(|m| m.avg_memory_mb), limits.max_memory_mb),
+                limits.max_memory_mb,
+            ),
+            (
+                ResourceType::NetworkUsage,
+                expected(
+                    metrics.and_then(|m| m.avg_network_mb),
+                    limits.max_network_mb,
+                ),
+                limits.max_network_mb,
+            ),
+        ];
+        if limits.tee {
+            units.push((
+                ResourceType::TeeUsage,
+                execution_time,
+                limits.max_execution_time_ms,
+            ));
+        }
+
+        units
+    }
+
+    /// Expected and maximum chain gas
+    pub(crate) fn chain_gas(&self) -> (u64, u64) {
+        let max = self.limits.max_chain_gas;
+        let expected = self
+            .metrics
+            .as_ref()
+            .filter(|m| m.invocations > 0)
+            .and_then(|m| m.avg_chain_gas)
+            .map(|avg| (avg.max(0.0).ceil() as u64).min(max))
+            .unwrap_or(max);
+
+        (expected, max)
+    }
+
+    /// What the expected cost is based on
+    pub(crate) fn basis(&self) -> QuoteBasis {
+        match &self.metrics {
+            Some(metrics) if metrics.invocations > 0 => QuoteBasis::Historical,
+            _ => QuoteBasis::Limits,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pricing::service::{PricingService, PricingServiceTrait};
+    use crate::pricing::storage::{MemoryPricingStorage, PricingStorage};
+    use crate::pricing::types::ResourcePricing;
+    use crate::quota::PlanTier;
+    use std::sync::Arc;
+
+    async fn service() -> PricingService<MemoryPricingStorage> {
+        let storage = MemoryPricingStorage::new();
+        for (resource_type, tier, price_per_unit) in [
+            (ResourceType::ExecutionTime, PricingTier::Basic, 0.0001),
+            (ResourceType::MemoryUsage, PricingTier::Basic, 0.0005),
+            (ResourceType::TeeUsage, PricingTier::Basic, 0.0002),
+            (ResourceType::ExecutionTime, PricingTier::Standard, 0.00005),
+        ] {
+            storage
+                .add_resource_pricing(ResourcePricing {
+                    resource_type,
+                    tier,
+                    base_price: 0.0,
+                    price_per_unit,
+                    free_tier_limit: None,
+                    min_billable_units: 0,
+                    max_billable_units: None,
+                    volume_discounts: Vec::new(),
+                })
+                .await
+                .unwrap();
+        }
+        PricingService::new(Arc::new(storage))
+    }
+
+    fn assert_cost(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() < 1e-9,
+            "{} != {}",
+            actual,
+            expected
+        );
+    }
+
+    fn item(quote: &CostQuote, resource_type: Option<ResourceType>) -> &QuoteItem {
+        quote
+            .items
+            .iter()
+            .find(|item| item.resource_type == resource_type)
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_estimate_without_history() {
+        let service = service().await;
+        let quote = service
+            .estimate_invocation_cost("alice", &QuoteRequest::default())
+            .await
+            .unwrap();
+
+        // Without history every resource is expected to reach its limit
+        assert_eq!(quote.basis, QuoteBasis::Limits);
+        assert_eq!(quote.tier, PricingTier::Basic);
+        assert_eq!(quote.items.len(), 3);
+        let execution = item(&quote, Some(ResourceType::ExecutionTime));
+        assert_eq!(
+            (execution.expected_units, execution.max_units),
+            (10_000, 10_000)
+        );
+        assert_cost(execution.max_cost, 1.0);
+        // Unpriced resources are free
+        assert_cost(item(&quote, Some(ResourceType::NetworkUsage)).max_cost, 0.0);
+        assert_cost(quote.expected_cost, 1.064);
+        assert_cost(quote.max_cost, 1.064);
+        assert_eq!(quote.expires_at, quote.created_at + QUOTE_TTL_SECS);
+
+        // Metrics of no invocation are no history
+        let request = QuoteRequest {
+            metrics: Some(ExecutionMetrics::default()),
+            ..QuoteRequest::default()
+        };
+        let quote = service
+            .estimate_invocation_cost("alice", &request)
+            .await
+            .unwrap();
+        assert_eq!(quote.basis, QuoteBasis::Limits);
+        assert_cost(quote.expected_cost, 1.064);
+    }
+
+    #[tokio::test]
+    async fn test_estimate_with_history() {
+        let service = service().await;
+        let request = QuoteRequest {
+            limits: ResourceLimits {
+                max_chain_gas: 1_000_000,
+                tee: true,
+                ..ResourceLimits::default()
+            },
+            metrics: Some(ExecutionMetrics {
+                invocations: 10,
+                avg_execution_time_ms: Some(250.4),
+                avg_memory_mb: Some(32.2),
+                avg_network_mb: None,
+                avg_chain_gas: Some(400_000.0),
+            }),
+            chain_gas_price: 30e-8,
+        };
+        let quote = service
+            .estimate_invocation_cost("alice", &request)
+            .await
+            .unwrap();
+
+        assert_eq!(quote.basis, QuoteBasis::Historical);
+        assert_eq!(quote.items.len(), 5);
+        let execution = item(&quote, Some(ResourceType::ExecutionTime));
+        assert_eq!(
+            (execution.expected_units, execution.max_units),
+            (251, 10_000)
+        );
+        assert_cost(execution.expected_cost, 0.0251);
+        let memory = item(&quote, Some(ResourceType::MemoryUsage));
+        assert_eq!((memory.expected_units, memory.max_units), (33, 128));
+        // The TEE is billed for the execution time
+        let tee = item(&quote, Some(ResourceType::TeeUsage));
+        assert_eq!((tee.expected_units, tee.max_units), (251, 10_000));
+        // No average is no better than the limit
+        let network = item(&quote, Some(ResourceType::NetworkUsage));
+        assert_eq!(network.expected_units, network.max_units);
+        let gas = item(&quote, None);
+        assert_eq!((gas.expected_units, gas.max_units), (400_000, 1_000_000));
+        assert_cost(gas.max_cost, 0.3);
+
+        assert_cost(quote.expected_cost, 0.0251 + 0.0165 + 0.0502 + 0.12);
+        assert_cost(quote.max_cost, 1.0 + 0.064 + 2.0 + 0.3);
+
+        // Averages above the limits are capped by them
+        let mut request = request;
+        request.metrics.as_mut().unwrap().avg_execution_time_ms = Some(50_000.0);
+        let quote = service
+            .estimate_invocation_cost("alice", &request)
+            .await
+            .unwrap();
+        let execution = item(&quote, Some(ResourceType::ExecutionTime));
+        assert_eq!(execution.expected_units, 10_000);
+        assert_cost(execution.expected_cost, execution.max_cost);
+
+        request.chain_gas_price = f64::NAN;
+        assert!(matches!(
+            service.estimate_invocation_cost("alice", &request).await,
+            Err(PricingError::InvalidInput(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_estimate_tier_pricing() {
+        let service = service().await;
+        let request = QuoteRequest {
+            limits: ResourceLimits {
+                max_execution_time_ms: 120_000,
+                max_memory_mb: 1024,
+                ..ResourceLimits::default()
+            },
+            ..QuoteRequest::default()
+        };
+
+        // Users on the default plan are capped by its ceilings
+        let quote = service
+            .estimate_invocation_cost("alice", &request)
+            .await
+            .unwrap();
+        assert_eq!(quote.tier, PricingTier::Basic);
+        let execution = item(&quote, Some(ResourceType::ExecutionTime));
+        assert_eq!(execution.max_units, 10_000);
+        assert_eq!(item(&quote, Some(ResourceType::MemoryUsage)).max_units, 128);
+
+        // Pro users are priced at the standard tier, within the higher ceilings of their plan
+        service.assign_plan("bob", PlanTier::Pro).await.unwrap();
+        let quote = service
+            .estimate_invocation_cost("bob", &request)
+            .await
+            .unwrap();
+        assert_eq!(quote.tier, PricingTier::Standard);
+        let execution = item(&quote, Some(ResourceType::ExecutionTime));
+        assert_eq!(execution.max_units, 60_000);
+        assert_cost(execution.max_cost, 3.0);
+        let memory = item(&quote, Some(ResourceType::MemoryUsage));
+        assert_eq!(memory.max_units, 512);
+        assert_cost(memory.max_cost, 0.0);
+        assert_cost(quote.max_cost, 3.0);
+    }
+
+    #[test]
+    fn test_check_max_cost() {
+        let quote = CostQuote {
+            id: "quote".to_string(),
+            user_id: "alice".to_string(),
+            tier: PricingTier::Basic,
+            basis: QuoteBasis::Limits,
+            items: Vec::new(),
+            expected_cost: 0.25,
+            max_cost: 0.5,
+            created_at: 0,
+            expires_at: QUOTE_TTL_SECS,
+        };
+
+        assert!(quote.check_max_cost(1.0).is_ok());
+        assert!(quote.check_max_cost(0.5).is_ok());
+        // The expected cost being within the limit is not enough
+        assert!(matches!(
+            quote.check_max_cost(0.4),
+            Err(PricingError::CostLimitExceeded(_))
+        ));
+        assert!(quote.check_max_cost(0.0).is_err());
+    }
+}
@@ -1,10 +1,16 @@
 // Copyright @ 2023 - 2024, R3E Network
 // All Rights Reserved
 
+pub mod estimate;
+pub mod plan;
 pub mod service;
 pub mod storage;
 pub mod types;
 
+pub use estimate::{
+    CostQuote, ExecutionMetrics, QuoteBasis, QuoteItem, QuoteRequest, ResourceLimits,
+};
+pub use plan::{PlanCatalog, PricePlan, RateLimit, RateLimiter};
 pub use service::{PricingService, PricingServiceTrait};
 pub use storage::{MemoryPricingStorage, PricingStorage};
 pub use types::{
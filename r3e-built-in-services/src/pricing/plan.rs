@@ -0,0 +1,340 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use r3e_store::{AtomicKvStore, CasOutcome};
+use serde::{Deserialize, Serialize};
+
+use crate::pricing::estimate::ResourceLimits;
+use crate::pricing::types::{PricingError, PricingTier};
+use crate::quota::{PlanTier, QuotaConfig, QuotaLimits};
+
+/// Invocation rate limit of a plan
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RateLimit {
+    /// Sustained invocations per minute
+    pub requests_per_minute: u32,
+
+    /// Invocations that may be made at once on top of the sustained rate
+    pub burst: u32,
+}
+
+/// Priced plan a tenant is billed on
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PricePlan {
+    /// Plan tier
+    pub tier: PlanTier,
+
+    /// Display name
+    pub name: String,
+
+    /// Monthly fee (in GAS)
+    pub monthly_fee: f64,
+
+    /// Pricing tier the resource usage of the plan is priced at
+    pub pricing_tier: PricingTier,
+
+    /// Invocations included in the monthly fee, None for unlimited
+    #[serde(default)]
+    pub included_invocations: Option<u64>,
+
+    /// Price of an invocation beyond the included ones (in GAS), None to reject them instead
+    #[serde(default)]
+    pub overage_price: Option<f64>,
+
+    /// Invocation rate limit, None for unlimited
+    #[serde(default)]
+    pub rate_limit: Option<RateLimit>,
+
+    /// Longest execution time of an invocation (in milliseconds), None for the platform default
+    #[serde(default)]
+    pub max_execution_time_ms: Option<u64>,
+
+    /// Most memory of an invocation (in MB), None for the platform default
+    #[serde(default)]
+    pub max_memory_mb: Option<u64>,
+
+    /// Resource ceilings enforced by the quota service
+    #[serde(default)]
+    pub quota: QuotaLimits,
+}
+
+impl PricePlan {
+    /// Invocations beyond the included ones and their cost (in GAS)
+    pub fn invocation_overage(&self, invocations: u64) -> (u64, f64) {
+        let Some(included) = self.included_invocations else {
+            return (0, 0.0);
+        };
+        let overage = invocations.saturating_sub(included);
+        (overage, overage as f64 * self.overage_price.unwrap_or(0.0))
+    }
+
+    /// Whether `count` more invocations are allowed after `invocations` this billing period
+    pub fn allows_invocations(&self, invocations: u64, count: u64) -> bool {
+        match (self.included_invocations, self.overage_price) {
+            (Some(included), None) => invocations.saturating_add(count) <= included,
+            _ => true,
+        }
+    }
+
+    /// Resource limits capped by the ceilings of the plan
+    pub fn cap(&self, mut limits: ResourceLimits) -> ResourceLimits {
+        if let Some(max) = self.max_execution_time_ms {
+            limits.max_execution_time_ms = limits.max_execution_time_ms.min(max);
+        }
+        if let Some(max) = self.max_memory_mb {
+            limits.max_memory_mb = limits.max_memory_mb.min(max);
+        }
+        limits
+    }
+}
+
+/// Priced plans by tier
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlanCatalog {
+    /// Tier of tenants without a plan
+    #[serde(default)]
+    pub default_tier: PlanTier,
+
+    /// Plans by tier
+    pub plans: HashMap<PlanTier, PricePlan>,
+}
+
+impl Default for PlanCatalog {
+    fn default() -> Self {
+        let quota = QuotaConfig::default();
+        let plans = [
+            PricePlan {
+                tier: PlanTier::Free,
+                name: "Free".to_string(),
+                monthly_fee: 0.0,
+                pricing_tier: PricingTier::Basic,
+                included_invocations: Some(10_000),
+                overage_price: None,
+                rate_limit: Some(RateLimit {
+                    requests_per_minute: 60,
+                    burst: 10,
+                }),
+                max_execution_time_ms: Some(10_000),
+                max_memory_mb: Some(128),
+                quota: quota.tiers[&PlanTier::Free].clone(),
+            },
+            PricePlan {
+                tier: PlanTier::Pro,
+                name: "Pro".to_string(),
+                monthly_fee: 20.0,
+                pricing_tier: PricingTier::Standard,
+                included_invocations: Some(1_000_000),
+                overage_price: Some(0.00002),
+                rate_limit: Some(RateLimit {
+                    requests_per_minute: 1_200,
+                    burst: 100,
+                }),
+                max_execution_time_ms: Some(60_000),
+                max_memory_mb: Some(512),
+                quota: quota.tiers[&PlanTier::Pro].clone(),
+            },
+            PricePlan {
+                tier: PlanTier::Enterprise,
+                name: "Enterprise".to_string(),
+                monthly_fee: 500.0,
+                pricing_tier: PricingTier::Enterprise,
+                included_invocations: None,
+                overage_price: None,
+                rate_limit: None,
+                max_execution_time_ms: None,
+                max_memory_mb: None,
+                quota: quota.tiers[&PlanTier::Enterprise].clone(),
+            },
+        ];
+
+        Self {
+            default_tier: PlanTier::Free,
+            plans: plans.into_iter().map(|plan| (plan.tier, plan)).collect(),
+        }
+    }
+}
+
+impl PlanCatalog {
+    /// Plan of a tier
+    pub fn plan(&self, tier: PlanTier) -> Result<&PricePlan, PricingError> {
+        self.plans
+            .get(&tier)
+            .ok_or_else(|| PricingError::NotFound(format!("Plan not found: {:?}", tier)))
+    }
+
+    /// Every plan, cheapest first
+    pub fn list(&self) -> Vec<PricePlan> {
+        let mut plans: Vec<_> = self.plans.values().cloned().collect();
+        plans.sort_by(|a, b| a.monthly_fee.total_cmp(&b.monthly_fee));
+        plans
+    }
+
+    /// Quota configuration enforcing the resource ceilings of the plans
+    pub fn quota_config(&self) -> QuotaConfig {
+        QuotaConfig {
+            default_tier: self.default_tier,
+            tiers: self
+                .plans
+                .iter()
+                .map(|(tier, plan)| (*tier, plan.quota.clone()))
+                .collect(),
+        }
+    }
+}
+
+/// Token bucket of a tenant
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    /// Invocations that may be made right away
+    tokens: f64,
+
+    /// When the tokens were last topped up
+    refilled_at: Instant,
+}
+
+/// Table of the shared rate windows of tenants
+pub const RATE_WINDOW_TABLE: &str = "rate_windows";
+
+/// Length of a shared rate window in seconds
+const RATE_WINDOW_SECS: u64 = 60;
+
+/// Attempts at counting an invocation in a shared window raced by other replicas
+const RATE_WINDOW_ATTEMPTS: usize = 8;
+
+/// Per-tenant invocation rate limiter
+#[derive(Default)]
+pub struct RateLimiter {
+    buckets: Mutex<HashMap<String, Bucket>>,
+
+    /// Store of the rate windows shared by the replicas of the service, if any
+    shared: Option<Arc<dyn AtomicKvStore + Send + Sync>>,
+}
+
+impl RateLimiter {
+    /// Create a new rate limiter
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a rate limiter counting invocations in one-minute windows of a store shared by
+    /// the replicas of the service, so a tenant gets one limit however many replicas there are.
+    ///
+    /// A window admits the larger of the sustained rate and the burst. Tenants fall back to
+    /// their local token bucket while the store fails.
+    pub fn shared(store: Arc<dyn AtomicKvStore + Send + Sync>) -> Self {
+        Self {
+            buckets: Mutex::default(),
+            shared: Some(store),
+        }
+    }
+
+    /// Take `count` invocations from the tenant's bucket, or the seconds to wait before retrying.
+    ///
+    /// More invocations than the bucket holds are taken at once from a full bucket, leaving it
+    /// to refill from below empty.
+    pub fn acquire(
+        &self,
+        tenant: &str,
+        limit: &RateLimit,
+        count: u32,
+        now: Instant,
+    ) -> Result<(), u64> {
+        if let Some(store) = &self.shared {
+            let unix_now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs();
+            match acquire_window(store.as_ref(), tenant, limit, count, unix_now) {
+                Ok(admitted) => return admitted,
+                Err(e) => log::warn!(
+                    "pricing: shared rate window of {} unavailable: {}",
+                    tenant,
+                    e
+                ),
+            }
+        }
+
+        let capacity = f64::from(limit.burst.max(1));
+        let per_second = f64::from(limit.requests_per_minute) / 60.0;
+
+        let mut buckets = self.buckets.lock().unwrap();
+        let bucket = buckets.entry(tenant.to_string()).or_insert(Bucket {
+            tokens: capacity,
+            refilled_at: now,
+        });
+
+        let elapsed = now.saturating_duration_since(bucket.refilled_at);
+        bucket.tokens = (bucket.tokens + elapsed.as_secs_f64() * per_second).min(capacity);
+        bucket.refilled_at = now;
+
+        let needed = f64::from(count).min(capacity);
+        if bucket.tokens >= needed {
+            bucket.tokens -= f64::from(count);
+            return Ok(());
+        }
+        if per_second <= 0.0 {
+            return Err(60);
+        }
+        Err(((needed - bucket.tokens) / per_second).ceil().max(1.0) as u64)
+    }
+}
+
+/// Count `count` invocations in the tenant's current shared window, a window start and an
+/// invocation count stored under the tenant and swapped atomically. More invocations than a
+/// window allows are counted at once in an empty window.
+fn acquire_window(
+    store: &dyn AtomicKvStore,
+    tenant: &str,
+    limit: &RateLimit,
+    count: u32,
+    unix_now: u64,
+) -> Result<Result<(), u64>, String> {
+    let acquired = u64::from(count);
+    let allowed = u64::from(limit.requests_per_minute.max(limit.burst)).max(acquired);
+    let window = unix_now - unix_now % RATE_WINDOW_SECS;
+    let retry_after = (window + RATE_WINDOW_SECS - unix_now).max(1);
+
+    let mut expected: Option<Vec<u8>> = None;
+    for _ in 0..RATE_WINDOW_ATTEMPTS {
+        let used = match expected.as_deref().and_then(decode_window) {
+            Some((start, used)) if start == window => used,
+            _ => 0,
+        };
+        if used + acquired > allowed {
+            return Ok(Err(retry_after));
+        }
+
+        let outcome = store
+            .compare_and_set(
+                RATE_WINDOW_TABLE,
+                tenant.as_bytes(),
+                expected.as_deref(),
+                Some(&encode_window(window, used + acquired)),
+            )
+            .map_err(|e| e.to_string())?;
+        match outcome {
+            CasOutcome::Set => return Ok(Ok(())),
+            CasOutcome::Conflict(found) => expected = found,
+        }
+    }
+
+    // Too many replicas are admitting the tenant's invocations at once
+    Ok(Err(1))
+}
+
+fn encode_window(start: u64, count: u64) -> [u8; 16] {
+    let mut bytes = [0; 16];
+    bytes[..8].copy_from_slice(&start.to_le_bytes());
+    bytes[8..].copy_from_slice(&count.to_le_bytes());
+    bytes
+}
+
+fn decode_window(bytes: &[u8]) -> Option<(u64, u64)> {
+    let start = u64::from_le_bytes(bytes.get(..8)?.try_into().ok()?);
+    let count = u64::from_le_bytes(bytes.get(8..16)?.try_into().ok()?);
+    Some((start, count))
+}
@@ -1,14 +1,20 @@
 // Copyright @ 2023 - 2024, R3E Network
 // All Rights Reserved
 
+use crate::pricing::estimate::{CostQuote, QuoteItem, QuoteRequest, QUOTE_TTL_SECS};
+use crate::pricing::plan::{PlanCatalog, PricePlan, RateLimiter};
 use crate::pricing::storage::PricingStorage;
 use crate::pricing::types::{
-    BillingRecord, EcosystemIncentive, NeoEcosystemIntegration, PaymentStatus, PricingError,
-    PricingTier, ResourcePricing, ResourceType, SubscriptionModel, SubscriptionType,
+    BillingItem, BillingRecord, EcosystemIncentive, NeoEcosystemIntegration, PaymentStatus,
+    PricingError, PricingTier, ResourcePricing, ResourceType, SubscriptionModel, SubscriptionType,
     UserBillingProfile, ValueAddedService,
 };
+use crate::quota::PlanTier;
 use async_trait::async_trait;
+use r3e_store::AtomicKvStore;
+use std::collections::HashMap;
 use std::sync::Arc;
+use std::time::Instant;
 
 /// Trait defining the pricing service functionality
 #[async_trait]
@@ -154,18 +160,139 @@ pub trait PricingServiceTrait: Send + Sync {
         incentive_id: &str,
         data: serde_json::Value,
     ) -> Result<(), PricingError>;
+
+    /// Quote the cost of a function invocation before running it
+    async fn estimate_invocation_cost(
+        &self,
+        user_id: &str,
+        request: &QuoteRequest,
+    ) -> Result<CostQuote, PricingError>;
+
+    /// List the priced plans, cheapest first
+    async fn list_plans(&self) -> Result<Vec<PricePlan>, PricingError>;
+
+    /// Get the plan a user is billed on, the default plan for users without one
+    async fn get_user_plan(&self, user_id: &str) -> Result<PricePlan, PricingError>;
+
+    /// Move a user to the plan of a tier, creating their billing profile if needed
+    async fn assign_plan(&self, user_id: &str, tier: PlanTier) -> Result<PricePlan, PricingError>;
+
+    /// Fail if the rate limit or the included invocations of the user's plan do not allow
+    /// `count` more invocations
+    async fn admit_invocation(&self, user_id: &str, count: u32) -> Result<(), PricingError>;
+
+    /// Record an invocation and its execution time against the user's plan
+    async fn record_invocation(
+        &self,
+        user_id: &str,
+        execution_time_ms: u64,
+    ) -> Result<(), PricingError>;
 }
 
 /// Implementation of the pricing service
 pub struct PricingService<S: PricingStorage> {
     /// Storage backend
     storage: Arc<S>,
+
+    /// Priced plans
+    plans: PlanCatalog,
+
+    /// Invocation rate limits of the plans
+    rate_limiter: RateLimiter,
+
+    /// Serializes read-modify-write of billing profiles by plan operations
+    lock: tokio::sync::Mutex<()>,
 }
 
 impl<S: PricingStorage> PricingService<S> {
-    /// Create a new pricing service
+    /// Create a new pricing service with the default plans
     pub fn new(storage: Arc<S>) -> Self {
-        Self { storage }
+        Self {
+            storage,
+            plans: PlanCatalog::default(),
+            rate_limiter: RateLimiter::new(),
+            lock: tokio::sync::Mutex::new(()),
+        }
+    }
+
+    /// Bill users on the given plans
+    pub fn with_plans(mut self, plans: PlanCatalog) -> Self {
+        self.plans = plans;
+        self
+    }
+
+    /// Enforce the rate limits of the plans across replicas, counting invocations in a store
+    /// they share
+    pub fn with_shared_rate_limits(mut self, store: Arc<dyn AtomicKvStore + Send + Sync>) -> Self {
+        self.rate_limiter = RateLimiter::shared(store);
+        self
+    }
+
+    /// Billing profile of a user, None if they have none yet
+    async fn find_profile(
+        &self,
+        user_id: &str,
+    ) -> Result<Option<UserBillingProfile>, PricingError> {
+        match self.storage.get_user_billing_profile(user_id).await {
+            Ok(profile) => Ok(Some(profile)),
+            Err(PricingError::NotFound(_)) => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Plan of a billing profile, the default plan for profiles without one
+    fn profile_plan(
+        &self,
+        profile: Option<&UserBillingProfile>,
+    ) -> Result<&PricePlan, PricingError> {
+        let tier = profile
+            .and_then(|profile| profile.plan)
+            .unwrap_or(self.plans.default_tier);
+        self.plans.plan(tier)
+    }
+
+    /// Update a user's billing profile, creating it on the default plan if they have none
+    async fn update_plan_profile(
+        &self,
+        user_id: &str,
+        update: impl FnOnce(&mut UserBillingProfile) -> Result<(), PricingError>,
+    ) -> Result<UserBillingProfile, PricingError> {
+        let _guard = self.lock.lock().await;
+        let existing = self.find_profile(user_id).await?;
+        let exists = existing.is_some();
+        let mut profile = match existing {
+            Some(profile) => profile,
+            None => {
+                let plan = self.plans.plan(self.plans.default_tier)?;
+                UserBillingProfile {
+                    user_id: user_id.to_string(),
+                    tier: plan.pricing_tier,
+                    plan: Some(plan.tier),
+                    subscription: None,
+                    subscription_start_date: None,
+                    subscription_end_date: None,
+                    resource_usage: HashMap::new(),
+                    billing_history: Vec::new(),
+                    payment_methods: Vec::new(),
+                    default_payment_method: None,
+                    value_added_services: Vec::new(),
+                    earned_incentives: Vec::new(),
+                    neo_integrations: Vec::new(),
+                }
+            }
+        };
+
+        update(&mut profile)?;
+        if exists {
+            self.storage
+                .update_user_billing_profile(profile.clone())
+                .await?;
+        } else {
+            self.storage
+                .create_user_billing_profile(profile.clone())
+                .await?;
+        }
+        Ok(profile)
     }
 
     /// Generate a new ID
@@ -222,6 +349,36 @@ impl<S: PricingStorage> PricingService<S> {
 
         cost
     }
+
+    /// Cost of `usage` units for a user, priced like the user's bill when a billing profile
+    /// exists and at the basic tier otherwise. Resources without pricing cost nothing.
+    async fn quote_resource_cost(
+        &self,
+        user_id: &str,
+        tier: Option<PricingTier>,
+        resource_type: ResourceType,
+        usage: u64,
+    ) -> Result<f64, PricingError> {
+        let result = match tier {
+            Some(_) => {
+                self.calculate_resource_usage_cost(user_id, resource_type, usage)
+                    .await
+            }
+            None => match self
+                .storage
+                .get_resource_pricing(resource_type, PricingTier::Basic)
+                .await
+            {
+                Ok(pricing) => Ok(self.calculate_resource_cost(&pricing, usage).await),
+                Err(err) => Err(err),
+            },
+        };
+
+        match result {
+            Err(PricingError::NotFound(_)) => Ok(0.0),
+            result => result,
+        }
+    }
 }
 
 #[async_trait]
@@ -607,12 +764,53 @@ impl<S: PricingStorage> PricingServiceTrait for PricingService<S> {
             billing_record.amount += subscription.base_price;
         }
 
+        // Add the plan fee and the invocations beyond the ones it includes, which the plan
+        // prices instead of the resource pricing
+        if let Some(tier) = profile.plan {
+            let plan = self.plans.plan(tier)?;
+            if plan.monthly_fee > 0.0 {
+                billing_record.items.push(BillingItem {
+                    description: format!("{} plan", plan.name),
+                    resource_type: None,
+                    quantity: 1,
+                    unit_price: plan.monthly_fee,
+                    total_price: plan.monthly_fee,
+                });
+                billing_record.amount += plan.monthly_fee;
+            }
+
+            let invocations = profile
+                .resource_usage
+                .get(&ResourceType::ApiCalls)
+                .copied()
+                .unwrap_or(0);
+            let (overage, cost) = plan.invocation_overage(invocations);
+            if cost > 0.0 {
+                billing_record.items.push(BillingItem {
+                    description: format!("{} invocations beyond the {} plan", overage, plan.name),
+                    resource_type: Some(ResourceType::ApiCalls),
+                    quantity: overage,
+                    unit_price: cost / overage as f64,
+                    total_price: cost,
+                });
+                billing_record.amount += cost;
+            }
+        }
+
         // Add resource usage fees
         for (resource_type, usage) in &profile.resource_usage {
-            // Calculate the cost
-            let cost = self
+            if profile.plan.is_some() && *resource_type == ResourceType::ApiCalls {
+                continue;
+            }
+
+            // Calculate the cost, resources the pricing tier of a plan does not price are free
+            let cost = match self
                 .calculate_resource_usage_cost(user_id, *resource_type, *usage)
-                .await?;
+                .await
+            {
+                Err(PricingError::NotFound(_)) if profile.plan.is_some() => 0.0,
+                cost => cost?,
+            };
 
             if cost > 0.0 {
                 // Add resource usage fee
@@ -798,4 +996,274 @@ impl<S: PricingStorage> PricingServiceTrait for PricingService<S> {
         // Update the user's billing profile
         self.storage.update_user_billing_profile(profile).await
     }
+
+    async fn estimate_invocation_cost(
+        &self,
+        user_id: &str,
+        request: &QuoteRequest,
+    ) -> Result<CostQuote, PricingError> {
+        if !request.chain_gas_price.is_finite() || request.chain_gas_price < 0.0 {
+            return Err(PricingError::InvalidInput(format!(
+                "Invalid chain gas price: {}",
+                request.chain_gas_price
+            )));
+        }
+
+        // Users without a billing profile are quoted at the basic tier, and every invocation
+        // runs within the ceilings of the user's plan
+        let profile = self.find_profile(user_id).await?;
+        let tier = profile.as_ref().map(|profile| profile.tier);
+        let request = &QuoteRequest {
+            limits: self
+                .profile_plan(profile.as_ref())?
+                .cap(request.limits.clone()),
+            ..request.clone()
+        };
+
+        let mut items = Vec::new();
+        for (resource_type, expected_units, max_units) in request.resource_units() {
+            let expected_cost = self
+                .quote_resource_cost(user_id, tier, resource_type, expected_units)
+                .await?;
+            let max_cost = self
+                .quote_resource_cost(user_id, tier, resource_type, max_units)
+                .await?;
+
+            items.push(QuoteItem {
+                description: format!("{} usage", resource_type),
+                resource_type: Some(resource_type),
+                expected_units,
+                max_units,
+                expected_cost,
+                max_cost,
+            });
+        }
+
+        // Gas of the transactions the function submits on chain
+        let (expected_gas, max_gas) = request.chain_gas();
+        if max_gas > 0 {
+            items.push(QuoteItem {
+                description: "chain gas".to_string(),
+                resource_type: None,
+                expected_units: expected_gas,
+                max_units: max_gas,
+                expected_cost: expected_gas as f64 * request.chain_gas_price,
+                max_cost: max_gas as f64 * request.chain_gas_price,
+            });
+        }
+
+        let now = self.get_current_timestamp();
+        Ok(CostQuote {
+            id: self.generate_id(),
+            user_id: user_id.to_string(),
+            tier: tier.unwrap_or(PricingTier::Basic),
+            basis: request.basis(),
+            expected_cost: items.iter().map(|item| item.expected_cost).sum(),
+            max_cost: items.iter().map(|item| item.max_cost).sum(),
+            items,
+            created_at: now,
+            expires_at: now + QUOTE_TTL_SECS,
+        })
+    }
+
+    async fn list_plans(&self) -> Result<Vec<PricePlan>, PricingError> {
+        Ok(self.plans.list())
+    }
+
+    async fn get_user_plan(&self, user_id: &str) -> Result<PricePlan, PricingError> {
+        let profile = self.find_profile(user_id).await?;
+        self.profile_plan(profile.as_ref()).cloned()
+    }
+
+    async fn assign_plan(&self, user_id: &str, tier: PlanTier) -> Result<PricePlan, PricingError> {
+        let plan = self.plans.plan(tier)?.clone();
+        self.update_plan_profile(user_id, |profile| {
+            profile.plan = Some(plan.tier);
+            profile.tier = plan.pricing_tier;
+            Ok(())
+        })
+        .await?;
+
+        log::info!("pricing: user {} moved to the {} plan", user_id, plan.name);
+        Ok(plan)
+    }
+
+    async fn admit_invocation(&self, user_id: &str, count: u32) -> Result<(), PricingError> {
+        let profile = self.find_profile(user_id).await?;
+        let plan = self.profile_plan(profile.as_ref())?;
+
+        let invocations = profile
+            .as_ref()
+            .and_then(|profile| profile.resource_usage.get(&ResourceType::ApiCalls))
+            .copied()
+            .unwrap_or(0);
+        if !plan.allows_invocations(invocations, u64::from(count)) {
+            return Err(PricingError::PlanLimitExceeded(format!(
+                "The {} plan includes {} invocations per billing period",
+                plan.name,
+                plan.included_invocations.unwrap_or_default()
+            )));
+        }
+
+        if let Some(limit) = &plan.rate_limit {
+            if let Err(retry_after) =
+                self.rate_limiter
+                    .acquire(user_id, limit, count, Instant::now())
+            {
+                return Err(PricingError::RateLimited(format!(
+                    "The {} plan allows {} invocations per minute, retry in {}s",
+                    plan.name, limit.requests_per_minute, retry_after
+                )));
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn record_invocation(
+        &self,
+        user_id: &str,
+        execution_time_ms: u64,
+    ) -> Result<(), PricingError> {
+        self.update_plan_profile(user_id, |profile| {
+            for (resource_type, usage) in [
+                (ResourceType::ApiCalls, 1),
+                (ResourceType::ExecutionTime, execution_time_ms),
+            ] {
+                let used = profile.resource_usage.entry(resource_type).or_insert(0);
+                *used = used.saturating_add(usage);
+            }
+            Ok(())
+        })
+        .await?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pricing::plan::RateLimit;
+    use crate::pricing::storage::MemoryPricingStorage;
+    use r3e_store::mem::MemKvStore;
+
+    /// Plans with the given included invocations and rate limit on the free tier
+    fn plans(included_invocations: Option<u64>, rate_limit: Option<RateLimit>) -> PlanCatalog {
+        let mut plans = PlanCatalog::default();
+        let free = plans.plans.get_mut(&PlanTier::Free).unwrap();
+        free.included_invocations = included_invocations;
+        free.rate_limit = rate_limit;
+        plans
+    }
+
+    fn service(plans: PlanCatalog) -> PricingService<MemoryPricingStorage> {
+        PricingService::new(Arc::new(MemoryPricingStorage::new())).with_plans(plans)
+    }
+
+    #[tokio::test]
+    async fn test_plan_assignment() {
+        let pricing = service(PlanCatalog::default());
+
+        // Users without a billing profile are on the default plan
+        let plan = pricing.get_user_plan("alice").await.unwrap();
+        assert_eq!(plan.tier, PlanTier::Free);
+
+        let plan = pricing.assign_plan("alice", PlanTier::Pro).await.unwrap();
+        assert_eq!(plan.tier, PlanTier::Pro);
+        assert_eq!(
+            pricing.get_user_plan("alice").await.unwrap().tier,
+            PlanTier::Pro
+        );
+        assert_eq!(
+            pricing.get_user_plan("bob").await.unwrap().tier,
+            PlanTier::Free
+        );
+
+        // Only the plans of the catalog can be assigned
+        let mut catalog = PlanCatalog::default();
+        catalog.plans.remove(&PlanTier::Enterprise);
+        let pricing = service(catalog);
+        assert!(matches!(
+            pricing.assign_plan("alice", PlanTier::Enterprise).await,
+            Err(PricingError::NotFound(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_included_invocations() {
+        let pricing = service(plans(Some(3), None));
+        pricing.record_invocation("alice", 10).await.unwrap();
+
+        // Two of the three included invocations are left
+        assert!(matches!(
+            pricing.admit_invocation("alice", 3).await,
+            Err(PricingError::PlanLimitExceeded(_))
+        ));
+        pricing.admit_invocation("alice", 2).await.unwrap();
+
+        for _ in 0..2 {
+            pricing.record_invocation("alice", 10).await.unwrap();
+        }
+        assert!(matches!(
+            pricing.admit_invocation("alice", 1).await,
+            Err(PricingError::PlanLimitExceeded(_))
+        ));
+
+        // Plans with an overage price keep admitting invocations beyond the included ones
+        pricing.assign_plan("alice", PlanTier::Pro).await.unwrap();
+        pricing.admit_invocation("alice", 1).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_rate_limit() {
+        let limit = RateLimit {
+            requests_per_minute: 1,
+            burst: 3,
+        };
+        let pricing = service(plans(None, Some(limit)));
+
+        pricing.admit_invocation("alice", 2).await.unwrap();
+        assert!(matches!(
+            pricing.admit_invocation("alice", 2).await,
+            Err(PricingError::RateLimited(_))
+        ));
+        pricing.admit_invocation("alice", 1).await.unwrap();
+        assert!(matches!(
+            pricing.admit_invocation("alice", 1).await,
+            Err(PricingError::RateLimited(_))
+        ));
+
+        // Each tenant has a bucket of its own, and a full bucket admits a batch beyond it
+        pricing.admit_invocation("bob", 5).await.unwrap();
+        assert!(matches!(
+            pricing.admit_invocation("bob", 1).await,
+            Err(PricingError::RateLimited(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_shared_rate_limit() {
+        let limit = RateLimit {
+            requests_per_minute: 3,
+            burst: 1,
+        };
+        let store = Arc::new(MemKvStore::new());
+        let replica = |store: &Arc<MemKvStore>| {
+            service(plans(None, Some(limit))).with_shared_rate_limits(store.clone())
+        };
+        let (first, second) = (replica(&store), replica(&store));
+
+        // The replicas count in one window of the larger of the rate and the burst
+        first.admit_invocation("alice", 2).await.unwrap();
+        assert!(matches!(
+            second.admit_invocation("alice", 2).await,
+            Err(PricingError::RateLimited(_))
+        ));
+        second.admit_invocation("alice", 1).await.unwrap();
+        assert!(matches!(
+            first.admit_invocation("alice", 1).await,
+            Err(PricingError::RateLimited(_))
+        ));
+    }
 }
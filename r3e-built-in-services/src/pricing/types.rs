@@ -5,6 +5,8 @@ use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use thiserror::Error;
 
+use crate::quota::PlanTier;
+
 #[derive(Debug, Error)]
 pub enum PricingError {
     #[error("Storage error: {0}")]
@@ -21,6 +23,15 @@ pub enum PricingError {
 
     #[error("Insufficient funds: {0}")]
     InsufficientFunds(String),
+
+    #[error("Cost limit exceeded: {0}")]
+    CostLimitExceeded(String),
+
+    #[error("Rate limited: {0}")]
+    RateLimited(String),
+
+    #[error("Plan limit exceeded: {0}")]
+    PlanLimitExceeded(String),
 }
 
 /// Pricing tier
@@ -367,6 +378,10 @@ pub struct UserBillingProfile {
     /// Pricing tier
     pub tier: PricingTier,
 
+    /// Priced plan, None for profiles billed by subscription only
+    #[serde(default)]
+    pub plan: Option<PlanTier>,
+
     /// Subscription model
     pub subscription: Option<String>,
 
@@ -0,0 +1,13 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+pub mod rocksdb;
+pub mod service;
+pub mod storage;
+pub mod types;
+
+pub use r3e_core::quota::{QuotaEnforcer, QuotaError, QuotaResource};
+pub use rocksdb::RocksDBQuotaStorage;
+pub use service::*;
+pub use storage::*;
+pub use types::*;
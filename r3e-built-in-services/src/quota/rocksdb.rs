@@ -0,0 +1,55 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+use r3e_store::rocksdb::RocksDBStore;
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::quota::storage::QuotaStorage;
+use crate::quota::types::TenantAccount;
+
+/// RocksDB implementation of QuotaStorage
+pub struct RocksDBQuotaStorage {
+    db: Arc<RocksDBStore>,
+    accounts_cf: String,
+}
+
+impl RocksDBQuotaStorage {
+    /// Create a new RocksDB quota storage
+    pub fn new<P: AsRef<Path>>(db_path: P) -> Result<Self, String> {
+        let db = RocksDBStore::new(db_path)
+            .map_err(|e| format!("Failed to create RocksDB store: {}", e))?;
+
+        Ok(Self {
+            db: Arc::new(db),
+            accounts_cf: "quota_accounts".to_string(),
+        })
+    }
+}
+
+impl QuotaStorage for RocksDBQuotaStorage {
+    fn get_account(&self, tenant: &str) -> Result<Option<TenantAccount>, String> {
+        match self.db.get(&self.accounts_cf, tenant.as_bytes()) {
+            Ok(value) => serde_json::from_slice::<TenantAccount>(&value)
+                .map(Some)
+                .map_err(|e| format!("Failed to deserialize quota account: {}", e)),
+            Err(r3e_store::GetError::NoSuchKey) => Ok(None),
+            Err(e) => Err(format!("Failed to get quota account: {}", e)),
+        }
+    }
+
+    fn put_account(&self, account: TenantAccount) -> Result<(), String> {
+        let value = serde_json::to_vec(&account)
+            .map_err(|e| format!("Failed to serialize quota account: {}", e))?;
+
+        let input = r3e_store::PutInput {
+            key: account.tenant.as_bytes(),
+            value: &value,
+            if_not_exists: false,
+        };
+
+        self.db
+            .put(&self.accounts_cf, input)
+            .map_err(|e| format!("Failed to update quota account: {}", e))
+    }
+}
@@ -0,0 +1,170 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+use std::sync::{Arc, Mutex};
+
+use r3e_core::quota::{QuotaEnforcer, QuotaError, QuotaResource};
+
+use crate::quota::storage::QuotaStorage;
+use crate::quota::types::{
+    PlanTier, QuotaConfig, QuotaLimits, QuotaUsage, ResourceUsage, TenantAccount,
+};
+
+/// Quota service trait
+pub trait QuotaServiceTrait: QuotaEnforcer {
+    /// Get the plan tier of a tenant
+    fn get_plan(&self, tenant: &str) -> Result<PlanTier, QuotaError>;
+
+    /// Move a tenant to another plan tier, keeping its usage
+    fn set_plan(&self, tenant: &str, tier: PlanTier) -> Result<(), QuotaError>;
+
+    /// Get the resource consumption of a tenant
+    fn get_usage(&self, tenant: &str) -> Result<QuotaUsage, QuotaError>;
+
+    /// Overwrite the recorded usage of a resource, e.g. to reconcile with the resources that exist
+    fn set_usage(&self, tenant: &str, resource: QuotaResource, used: u64)
+        -> Result<(), QuotaError>;
+}
+
+/// Quota service implementation
+pub struct QuotaService<S: QuotaStorage> {
+    /// Storage
+    storage: Arc<S>,
+
+    /// Limits per plan tier
+    config: QuotaConfig,
+
+    /// Serializes read-modify-write of accounts, so concurrent reservations cannot overshoot
+    lock: Mutex<()>,
+}
+
+impl<S: QuotaStorage> QuotaService<S> {
+    /// Create a new quota service
+    pub fn new(storage: Arc<S>, config: QuotaConfig) -> Self {
+        Self {
+            storage,
+            config,
+            lock: Mutex::new(()),
+        }
+    }
+
+    /// Limits of a plan tier
+    pub fn limits(&self, tier: PlanTier) -> QuotaLimits {
+        self.config.tiers.get(&tier).cloned().unwrap_or_default()
+    }
+
+    fn account(&self, tenant: &str) -> Result<TenantAccount, QuotaError> {
+        let account = self
+            .storage
+            .get_account(tenant)
+            .map_err(QuotaError::Unavailable)?;
+        Ok(account.unwrap_or_else(|| TenantAccount::new(tenant, self.config.default_tier)))
+    }
+
+    fn update(
+        &self,
+        tenant: &str,
+        update: impl FnOnce(&mut TenantAccount) -> Result<(), QuotaError>,
+    ) -> Result<(), QuotaError> {
+        let _guard = self.lock.lock().unwrap();
+        let mut account = self.account(tenant)?;
+        update(&mut account)?;
+        account.updated_at = chrono::Utc::now().timestamp() as u64;
+        self.storage
+            .put_account(account)
+            .map_err(QuotaError::Unavailable)
+    }
+}
+
+impl<S: QuotaStorage> QuotaEnforcer for QuotaService<S> {
+    fn reserve(
+        &self,
+        tenant: &str,
+        resource: QuotaResource,
+        amount: u64,
+    ) -> Result<(), QuotaError> {
+        self.update(tenant, |account| {
+            let used = account.used(resource);
+            if let Some(limit) = self.limits(account.tier).limit(resource) {
+                if used.saturating_add(amount) > limit {
+                    return Err(QuotaError::Exceeded {
+                        tenant: tenant.to_string(),
+                        resource,
+                        used,
+                        requested: amount,
+                        limit,
+                    });
+                }
+            }
+            account.usage.insert(resource, used.saturating_add(amount));
+            Ok(())
+        })
+    }
+
+    fn release(&self, tenant: &str, resource: QuotaResource, amount: u64) {
+        let released = self.update(tenant, |account| {
+            let used = account.used(resource).saturating_sub(amount);
+            account.usage.insert(resource, used);
+            Ok(())
+        });
+        if let Err(e) = released {
+            log::error!(
+                "Failed to release {} {} of {}: {}",
+                amount,
+                resource,
+                tenant,
+                e
+            );
+        }
+    }
+}
+
+impl<S: QuotaStorage> QuotaServiceTrait for QuotaService<S> {
+    fn get_plan(&self, tenant: &str) -> Result<PlanTier, QuotaError> {
+        Ok(self.account(tenant)?.tier)
+    }
+
+    fn set_plan(&self, tenant: &str, tier: PlanTier) -> Result<(), QuotaError> {
+        self.update(tenant, |account| {
+            account.tier = tier;
+            Ok(())
+        })
+    }
+
+    fn get_usage(&self, tenant: &str) -> Result<QuotaUsage, QuotaError> {
+        let account = self.account(tenant)?;
+        let limits = self.limits(account.tier);
+
+        let resources = QuotaResource::ALL
+            .iter()
+            .map(|resource| {
+                let used = account.used(*resource);
+                let limit = limits.limit(*resource);
+                ResourceUsage {
+                    resource: *resource,
+                    used,
+                    limit,
+                    remaining: limit.map(|limit| limit.saturating_sub(used)),
+                }
+            })
+            .collect();
+
+        Ok(QuotaUsage {
+            tenant: account.tenant,
+            tier: account.tier,
+            resources,
+        })
+    }
+
+    fn set_usage(
+        &self,
+        tenant: &str,
+        resource: QuotaResource,
+        used: u64,
+    ) -> Result<(), QuotaError> {
+        self.update(tenant, |account| {
+            account.usage.insert(resource, used);
+            Ok(())
+        })
+    }
+}
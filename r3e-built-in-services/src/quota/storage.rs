@@ -0,0 +1,44 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use crate::quota::types::TenantAccount;
+
+/// Quota storage trait.
+///
+/// Synchronous, as quotas are enforced from synchronous code such as key-value stores.
+pub trait QuotaStorage: Send + Sync {
+    /// Get the account of a tenant
+    fn get_account(&self, tenant: &str) -> Result<Option<TenantAccount>, String>;
+
+    /// Create or replace the account of a tenant
+    fn put_account(&self, account: TenantAccount) -> Result<(), String>;
+}
+
+/// Memory-based implementation of QuotaStorage
+#[derive(Default)]
+pub struct MemoryQuotaStorage {
+    accounts: Mutex<HashMap<String, TenantAccount>>,
+}
+
+impl MemoryQuotaStorage {
+    /// Create a new memory-based quota storage
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl QuotaStorage for MemoryQuotaStorage {
+    fn get_account(&self, tenant: &str) -> Result<Option<TenantAccount>, String> {
+        let accounts = self.accounts.lock().unwrap();
+        Ok(accounts.get(tenant).cloned())
+    }
+
+    fn put_account(&self, account: TenantAccount) -> Result<(), String> {
+        let mut accounts = self.accounts.lock().unwrap();
+        accounts.insert(account.tenant.clone(), account);
+        Ok(())
+    }
+}
@@ -0,0 +1,157 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+use std::collections::{BTreeMap, HashMap};
+
+use r3e_core::quota::QuotaResource;
+use serde::{Deserialize, Serialize};
+
+/// Plan tier of a tenant
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PlanTier {
+    /// Free tier
+    #[default]
+    Free,
+
+    /// Paid tier for individual developers and small teams
+    Pro,
+
+    /// Enterprise tier
+    Enterprise,
+}
+
+/// Resource limits of a plan tier, None for unlimited
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QuotaLimits {
+    /// Maximum number of functions
+    #[serde(default)]
+    pub functions: Option<u64>,
+
+    /// Maximum number of secrets
+    #[serde(default)]
+    pub secrets: Option<u64>,
+
+    /// Maximum number of scheduled triggers
+    #[serde(default)]
+    pub schedules: Option<u64>,
+
+    /// Maximum bytes of function storage
+    #[serde(default)]
+    pub storage_bytes: Option<u64>,
+}
+
+impl QuotaLimits {
+    /// Limit of a resource, None for unlimited
+    pub fn limit(&self, resource: QuotaResource) -> Option<u64> {
+        match resource {
+            QuotaResource::Functions => self.functions,
+            QuotaResource::Secrets => self.secrets,
+            QuotaResource::Schedules => self.schedules,
+            QuotaResource::StorageBytes => self.storage_bytes,
+        }
+    }
+}
+
+/// Quota configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QuotaConfig {
+    /// Tier of tenants without a plan
+    #[serde(default)]
+    pub default_tier: PlanTier,
+
+    /// Limits by plan tier, tiers not listed are unlimited
+    pub tiers: HashMap<PlanTier, QuotaLimits>,
+}
+
+impl Default for QuotaConfig {
+    fn default() -> Self {
+        let mut tiers = HashMap::new();
+        tiers.insert(
+            PlanTier::Free,
+            QuotaLimits {
+                functions: Some(10),
+                secrets: Some(20),
+                schedules: Some(5),
+                storage_bytes: Some(10 * 1024 * 1024),
+            },
+        );
+        tiers.insert(
+            PlanTier::Pro,
+            QuotaLimits {
+                functions: Some(100),
+                secrets: Some(500),
+                schedules: Some(100),
+                storage_bytes: Some(1024 * 1024 * 1024),
+            },
+        );
+        tiers.insert(PlanTier::Enterprise, QuotaLimits::default());
+
+        Self {
+            default_tier: PlanTier::Free,
+            tiers,
+        }
+    }
+}
+
+/// Plan and resource usage of a tenant
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TenantAccount {
+    /// Tenant ID
+    pub tenant: String,
+
+    /// Plan tier
+    pub tier: PlanTier,
+
+    /// Amount used by resource
+    pub usage: BTreeMap<QuotaResource, u64>,
+
+    /// Last updated timestamp
+    pub updated_at: u64,
+}
+
+impl TenantAccount {
+    /// A new tenant on the given tier, using nothing yet
+    pub fn new(tenant: &str, tier: PlanTier) -> Self {
+        Self {
+            tenant: tenant.to_string(),
+            tier,
+            usage: BTreeMap::new(),
+            updated_at: chrono::Utc::now().timestamp() as u64,
+        }
+    }
+
+    /// Amount of a resource used
+    pub fn used(&self, resource: QuotaResource) -> u64 {
+        self.usage.get(&resource).copied().unwrap_or(0)
+    }
+}
+
+/// Usage of a resource against its limit
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResourceUsage {
+    /// Resource
+    pub resource: QuotaResource,
+
+    /// Amount used
+    pub used: u64,
+
+    /// Limit, None for unlimited
+    pub limit: Option<u64>,
+
+    /// Amount left, None for unlimited
+    pub remaining: Option<u64>,
+}
+
+/// Resource consumption of a tenant
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QuotaUsage {
+    /// Tenant ID
+    pub tenant: String,
+
+    /// Plan tier
+    pub tier: PlanTier,
+
+    /// Usage of every limited resource
+    pub resources: Vec<ResourceUsage>,
+}
@@ -0,0 +1,830 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+use async_trait::async_trait;
+use r3e_oracle::{OracleRequest, OracleRequestStatus, OracleService};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::Notify;
+
+use crate::workflow::storage::WorkflowStorage;
+use crate::workflow::types::{
+    render_template, RunStatus, StepDefinition, StepKind, StepStatus, WorkflowError, WorkflowRun,
+};
+
+/// Trait defining how workflow steps invoke functions
+#[async_trait]
+pub trait FunctionInvoker: Send + Sync {
+    /// Invoke a function of `owner` with `input`, returning the function result
+    async fn invoke(
+        &self,
+        owner: &str,
+        function_id: &str,
+        input: Value,
+    ) -> Result<Value, WorkflowError>;
+}
+
+/// Workflow engine configuration
+#[derive(Debug, Clone)]
+pub struct WorkflowEngineConfig {
+    /// Interval between passes over the active runs
+    pub poll_interval: Duration,
+
+    /// Time an oracle step waits for its response before the attempt fails
+    pub oracle_timeout: Duration,
+}
+
+impl Default for WorkflowEngineConfig {
+    fn default() -> Self {
+        Self {
+            poll_interval: Duration::from_secs(1),
+            oracle_timeout: Duration::from_secs(300),
+        }
+    }
+}
+
+/// Readiness of a pending step
+enum Readiness {
+    /// Dependencies are still running
+    Blocked,
+
+    /// The step will not run, for the given reason
+    Skip(String),
+
+    Ready,
+}
+
+/// Workflow engine
+///
+/// Drives the runs step by step. Every step change is persisted before the next step
+/// starts, so the engine resumes where it left off after a restart; a function step that
+/// was running during a crash is attempted again.
+pub struct WorkflowEngine {
+    /// Workflow storage
+    storage: Arc<dyn WorkflowStorage>,
+
+    /// Function invoker
+    functions: Arc<dyn FunctionInvoker>,
+
+    /// Oracle service, required by oracle steps
+    oracle: Option<Arc<dyn OracleService>>,
+
+    /// Configuration
+    config: WorkflowEngineConfig,
+
+    /// Per run locks, so a run is never advanced and cancelled at the same time
+    locks: Mutex<HashMap<String, Arc<tokio::sync::Mutex<()>>>>,
+
+    /// Wakes the engine loop when a run starts
+    wake: Notify,
+}
+
+impl WorkflowEngine {
+    /// Create a new workflow engine
+    pub fn new(
+        storage: Arc<dyn WorkflowStorage>,
+        functions: Arc<dyn FunctionInvoker>,
+        config: WorkflowEngineConfig,
+    ) -> Self {
+        Self {
+            storage,
+            functions,
+            oracle: None,
+            config,
+            locks: Mutex::new(HashMap::new()),
+            wake: Notify::new(),
+        }
+    }
+
+    /// Set the oracle service used by oracle steps
+    pub fn with_oracle(mut self, oracle: Arc<dyn OracleService>) -> Self {
+        self.oracle = Some(oracle);
+        self
+    }
+
+    /// Get current timestamp
+    fn get_current_timestamp(&self) -> u64 {
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs()
+    }
+
+    fn run_lock(&self, run_id: &str) -> Arc<tokio::sync::Mutex<()>> {
+        let mut locks = self.locks.lock().unwrap_or_else(|e| e.into_inner());
+        locks.entry(run_id.to_string()).or_default().clone()
+    }
+
+    fn release_lock(&self, run: &WorkflowRun) {
+        if run.status.is_terminal() {
+            let mut locks = self.locks.lock().unwrap_or_else(|e| e.into_inner());
+            locks.remove(&run.id);
+        }
+    }
+
+    async fn load_run(&self, run_id: &str) -> Result<WorkflowRun, WorkflowError> {
+        self.storage
+            .get_run(run_id)
+            .await?
+            .ok_or_else(|| WorkflowError::NotFound(format!("Workflow run not found: {}", run_id)))
+    }
+
+    /// Wake the engine loop, e.g. after a run was started
+    pub fn notify(&self) {
+        self.wake.notify_one();
+    }
+
+    /// Advance a run as far as possible, persisting every step change
+    pub async fn advance(&self, run_id: &str) -> Result<WorkflowRun, WorkflowError> {
+        let lock = self.run_lock(run_id);
+        let _guard = lock.lock().await;
+
+        let mut run = self.load_run(run_id).await?;
+        let result = self.advance_locked(&mut run).await;
+        self.release_lock(&run);
+        result.map(|_| run)
+    }
+
+    async fn advance_locked(&self, run: &mut WorkflowRun) -> Result<(), WorkflowError> {
+        loop {
+            let mut progressed = false;
+
+            for id in run.definition.order.clone() {
+                if run.status.is_terminal() {
+                    return Ok(());
+                }
+                let Some(step) = run.definition.step(&id).cloned() else {
+                    continue;
+                };
+                let Some(status) = run.step(&id).map(|state| state.status) else {
+                    continue;
+                };
+
+                let changed = match status {
+                    StepStatus::Pending => self.start_step(run, &step).await,
+                    StepStatus::Waiting => self.poll_step(run, &step).await,
+                    _ => false,
+                };
+
+                if changed {
+                    progressed = true;
+                    let now = self.get_current_timestamp();
+                    if run.steps.iter().all(|state| state.status.is_terminal())
+                        && !run.status.is_terminal()
+                    {
+                        run.status = RunStatus::Succeeded;
+                        run.finished_at = Some(now);
+                        log::info!("workflow: run {} succeeded", run.id);
+                    }
+                    run.updated_at = now;
+                    self.storage.put_run(run.clone()).await?;
+                }
+            }
+
+            if !progressed {
+                return Ok(());
+            }
+        }
+    }
+
+    fn readiness(&self, run: &WorkflowRun, step: &StepDefinition) -> Readiness {
+        for dep in &step.depends_on {
+            match run.step(dep).map(|state| state.status) {
+                Some(StepStatus::Succeeded) => {}
+                Some(StepStatus::Skipped) => {
+                    return Readiness::Skip(format!("Dependency {} was skipped", dep))
+                }
+                _ => return Readiness::Blocked,
+            }
+        }
+
+        if let Some(guard) = &step.when {
+            let Some(condition) = run.step(&guard.step) else {
+                return Readiness::Blocked;
+            };
+            match condition.status {
+                StepStatus::Succeeded => {
+                    let result = condition
+                        .output
+                        .as_ref()
+                        .and_then(|output| output.get("result"))
+                        .and_then(Value::as_bool)
+                        .unwrap_or(false);
+                    if result != guard.equals {
+                        return Readiness::Skip(format!("Condition {} was {}", guard.step, result));
+                    }
+                }
+                StepStatus::Skipped => {
+                    return Readiness::Skip(format!("Condition {} was skipped", guard.step))
+                }
+                _ => return Readiness::Blocked,
+            }
+        }
+
+        Readiness::Ready
+    }
+
+    /// Start the next attempt of a pending step, returning whether the run changed
+    async fn start_step(&self, run: &mut WorkflowRun, step: &StepDefinition) -> bool {
+        let now = self.get_current_timestamp();
+        let Some(state) = run.step(&step.id) else {
+            return false;
+        };
+        if state.next_attempt_at.is_some_and(|at| at > now) {
+            return false;
+        }
+
+        match self.readiness(run, step) {
+            Readiness::Blocked => return false,
+            Readiness::Skip(reason) => {
+                if let Some(state) = run.step_mut(&step.id) {
+                    state.status = StepStatus::Skipped;
+                    state.error = Some(reason);
+                    state.finished_at = Some(now);
+                }
+                return true;
+            }
+            Readiness::Ready => {}
+        }
+
+        let context = run.context();
+        if let Some(state) = run.step_mut(&step.id) {
+            state.attempts += 1;
+            state.started_at.get_or_insert(now);
+            state.next_attempt_at = None;
+            state.external_id = None;
+        }
+
+        match &step.kind {
+            StepKind::Function { function_id, input } => {
+                let input = match render_template(input, &context) {
+                    Ok(input) => input,
+                    Err(e) => {
+                        self.fail_step(run, step, e.to_string(), false).await;
+                        return true;
+                    }
+                };
+                match self.functions.invoke(&run.owner, function_id, input).await {
+                    Ok(output) => self.succeed_step(run, step, output),
+                    Err(e) => self.fail_step(run, step, e.to_string(), true).await,
+                }
+            }
+            StepKind::Oracle { request_type, data } => {
+                let data = match render_template(data, &context) {
+                    Ok(data) => data,
+                    Err(e) => {
+                        self.fail_step(run, step, e.to_string(), false).await;
+                        return true;
+                    }
+                };
+                let Some(oracle) = &self.oracle else {
+                    let error = "No oracle service is configured".to_string();
+                    self.fail_step(run, step, error, false).await;
+                    return true;
+                };
+
+                let request = OracleRequest {
+                    id: uuid::Uuid::new_v4().to_string(),
+                    request_type: *request_type,
+                    data: match data {
+                        Value::String(data) => data,
+                        data => data.to_string(),
+                    },
+                    callback_url: None,
+                    requester_id: run.owner.clone(),
+                    timestamp: now,
+                    status: OracleRequestStatus::Pending,
+                };
+                match oracle.submit_request(request).await {
+                    Ok(request_id) => {
+                        if let Some(state) = run.step_mut(&step.id) {
+                            state.status = StepStatus::Waiting;
+                            state.external_id = Some(request_id);
+                            state.next_attempt_at =
+                                Some(now + self.config.oracle_timeout.as_secs());
+                        }
+                    }
+                    Err(e) => self.fail_step(run, step, e.to_string(), true).await,
+                }
+            }
+            StepKind::Delay { seconds } => {
+                if let Some(state) = run.step_mut(&step.id) {
+                    state.status = StepStatus::Waiting;
+                    state.next_attempt_at = Some(now + seconds);
+                }
+            }
+            StepKind::Condition {
+                value,
+                operator,
+                operand,
+            } => {
+                let rendered = render_template(value, &context)
+                    .and_then(|value| Ok((value, render_template(operand, &context)?)));
+                match rendered {
+                    Ok((value, operand)) => {
+                        let result = operator.evaluate(&value, &operand);
+                        self.succeed_step(run, step, serde_json::json!({ "result": result }));
+                    }
+                    Err(e) => self.fail_step(run, step, e.to_string(), false).await,
+                }
+            }
+        }
+
+        true
+    }
+
+    /// Check a waiting step, returning whether the run changed
+    async fn poll_step(&self, run: &mut WorkflowRun, step: &StepDefinition) -> bool {
+        let now = self.get_current_timestamp();
+        let Some(state) = run.step(&step.id) else {
+            return false;
+        };
+        let deadline = state.next_attempt_at.unwrap_or(0);
+
+        match &step.kind {
+            StepKind::Delay { .. } => {
+                if now < deadline {
+                    return false;
+                }
+                self.succeed_step(run, step, Value::Null);
+                true
+            }
+            StepKind::Oracle { .. } => {
+                let (Some(oracle), Some(request_id)) = (&self.oracle, state.external_id.clone())
+                else {
+                    let error = "Oracle request was lost".to_string();
+                    self.fail_step(run, step, error, true).await;
+                    return true;
+                };
+
+                match oracle.get_request_status(&request_id).await {
+                    Ok(OracleRequestStatus::Completed) | Ok(OracleRequestStatus::Failed) => {
+                        match oracle.get_response(&request_id).await {
+                            Ok(response) if response.error.is_none() => {
+                                let output = serde_json::from_str(&response.data)
+                                    .unwrap_or(Value::String(response.data));
+                                self.succeed_step(run, step, output);
+                            }
+                            Ok(response) => {
+                                let error = response.error.unwrap_or_default();
+                                self.fail_step(run, step, error, true).await;
+                            }
+                            Err(e) => self.fail_step(run, step, e.to_string(), true).await,
+                        }
+                        true
+                    }
+                    Ok(_) if now < deadline => false,
+                    Ok(_) => {
+                        if let Err(e) = oracle.cancel_request(&request_id).await {
+                            log::warn!("workflow: failed to cancel oracle request: {}", e);
+                        }
+                        let error = format!("Oracle request {} timed out", request_id);
+                        self.fail_step(run, step, error, true).await;
+                        true
+                    }
+                    Err(e) => {
+                        self.fail_step(run, step, e.to_string(), true).await;
+                        true
+                    }
+                }
+            }
+            _ => false,
+        }
+    }
+
+    fn succeed_step(&self, run: &mut WorkflowRun, step: &StepDefinition, output: Value) {
+        let now = self.get_current_timestamp();
+        if let Some(state) = run.step_mut(&step.id) {
+            state.status = StepStatus::Succeeded;
+            state.output = Some(output);
+            state.error = None;
+            state.next_attempt_at = None;
+            state.finished_at = Some(now);
+        }
+    }
+
+    /// Record a failed attempt, retrying it after the backoff or failing the run
+    async fn fail_step(
+        &self,
+        run: &mut WorkflowRun,
+        step: &StepDefinition,
+        error: String,
+        retryable: bool,
+    ) {
+        let now = self.get_current_timestamp();
+        let Some(state) = run.step_mut(&step.id) else {
+            return;
+        };
+        state.error = Some(error.clone());
+        state.next_attempt_at = None;
+
+        if retryable && state.attempts < step.retry.max_attempts {
+            let backoff = step.retry.backoff(state.attempts);
+            log::warn!(
+                "workflow: step {} of run {} failed attempt {}, retrying in {}s: {}",
+                step.id,
+                run.id,
+                state.attempts,
+                backoff,
+                error
+            );
+            state.status = StepStatus::Pending;
+            state.next_attempt_at = Some(now + backoff);
+            return;
+        }
+
+        state.status = StepStatus::Failed;
+        state.finished_at = Some(now);
+        log::error!(
+            "workflow: run {} failed at step {}: {}",
+            run.id,
+            step.id,
+            error
+        );
+        self.end_run(
+            run,
+            RunStatus::Failed,
+            Some(format!("Step {} failed: {}", step.id, error)),
+        )
+        .await;
+    }
+
+    /// End a run, skipping the steps that did not finish
+    async fn end_run(&self, run: &mut WorkflowRun, status: RunStatus, error: Option<String>) {
+        let now = self.get_current_timestamp();
+        for state in run.steps.iter_mut() {
+            if state.status.is_terminal() {
+                continue;
+            }
+            if let (Some(oracle), Some(request_id)) = (&self.oracle, &state.external_id) {
+                if let Err(e) = oracle.cancel_request(request_id).await {
+                    log::warn!("workflow: failed to cancel oracle request: {}", e);
+                }
+            }
+            state.status = StepStatus::Skipped;
+            state.next_attempt_at = None;
+            state.finished_at = Some(now);
+        }
+        run.status = status;
+        run.error = error;
+        run.finished_at = Some(now);
+        run.updated_at = now;
+    }
+
+    /// Cancel a running run
+    pub async fn cancel(&self, run_id: &str) -> Result<WorkflowRun, WorkflowError> {
+        let lock = self.run_lock(run_id);
+        let _guard = lock.lock().await;
+
+        let mut run = self.load_run(run_id).await?;
+        if run.status.is_terminal() {
+            return Err(WorkflowError::InvalidInput(format!(
+                "Workflow run {} already ended",
+                run_id
+            )));
+        }
+
+        self.end_run(&mut run, RunStatus::Cancelled, None).await;
+        self.storage.put_run(run.clone()).await?;
+        self.release_lock(&run);
+        log::info!("workflow: run {} cancelled", run.id);
+        Ok(run)
+    }
+
+    /// Advance all running runs, returning how many were processed
+    pub async fn process_pending(&self) -> Result<usize, WorkflowError> {
+        let run_ids = self.storage.list_active_runs().await?;
+        for run_id in &run_ids {
+            if let Err(err) = self.advance(run_id).await {
+                log::error!("workflow: failed to advance run {}: {}", run_id, err);
+            }
+        }
+        Ok(run_ids.len())
+    }
+
+    /// Drive the running runs until `stop` is set
+    pub async fn run(&self, stop: Arc<AtomicBool>) {
+        log::info!("workflow: engine started");
+        while !stop.load(Ordering::Relaxed) {
+            if let Err(err) = self.process_pending().await {
+                log::error!("workflow: failed to process runs: {}", err);
+            }
+
+            tokio::select! {
+                _ = tokio::time::sleep(self.config.poll_interval) => {}
+                _ = self.wake.notified() => {}
+            }
+        }
+        log::info!("workflow: engine stopped");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::workflow::service::{WorkflowService, WorkflowServiceTrait};
+    use crate::workflow::storage::MemoryWorkflowStorage;
+    use crate::workflow::types::WorkflowSpec;
+    use serde_json::json;
+
+    /// Invoker returning `{"function": <id>, "input": <input>}`, failing a function as often
+    /// as configured first
+    #[derive(Default)]
+    struct MockInvoker {
+        calls: Mutex<Vec<String>>,
+        failures: Mutex<HashMap<String, u32>>,
+    }
+
+    impl MockInvoker {
+        fn fail(&self, function_id: &str, times: u32) {
+            self.failures
+                .lock()
+                .unwrap()
+                .insert(function_id.to_string(), times);
+        }
+
+        fn calls(&self) -> Vec<String> {
+            self.calls.lock().unwrap().clone()
+        }
+    }
+
+    #[async_trait]
+    impl FunctionInvoker for MockInvoker {
+        async fn invoke(
+            &self,
+            _owner: &str,
+            function_id: &str,
+            input: Value,
+        ) -> Result<Value, WorkflowError> {
+            self.calls.lock().unwrap().push(function_id.to_string());
+            if let Some(left) = self.failures.lock().unwrap().get_mut(function_id) {
+                if *left > 0 {
+                    *left -= 1;
+                    return Err(WorkflowError::Step(format!("{} failed", function_id)));
+                }
+            }
+            Ok(json!({ "function": function_id, "input": input }))
+        }
+    }
+
+    struct Fixture {
+        storage: Arc<MemoryWorkflowStorage>,
+        invoker: Arc<MockInvoker>,
+        engine: Arc<WorkflowEngine>,
+        service: WorkflowService,
+    }
+
+    fn fixture(storage: Arc<MemoryWorkflowStorage>) -> Fixture {
+        let invoker = Arc::new(MockInvoker::default());
+        let engine = Arc::new(WorkflowEngine::new(
+            storage.clone(),
+            invoker.clone(),
+            WorkflowEngineConfig::default(),
+        ));
+        let service = WorkflowService::new(storage.clone(), engine.clone());
+        Fixture {
+            storage,
+            invoker,
+            engine,
+            service,
+        }
+    }
+
+    fn function(id: &str, depends_on: &[&str], input: Value) -> Value {
+        json!({
+            "id": id,
+            "type": "function",
+            "function_id": id,
+            "depends_on": depends_on,
+            "input": input,
+            "retry": { "max_attempts": 3, "backoff_seconds": 0 },
+        })
+    }
+
+    fn spec(steps: Vec<Value>) -> WorkflowSpec {
+        WorkflowSpec::parse(&json!({ "name": "test", "steps": steps }).to_string()).unwrap()
+    }
+
+    async fn start(f: &Fixture, steps: Vec<Value>) -> WorkflowRun {
+        let workflow = f
+            .service
+            .create_workflow("alice", spec(steps))
+            .await
+            .unwrap();
+        f.service
+            .start_run("alice", &workflow.id, json!({ "city": "Shanghai" }))
+            .await
+            .unwrap()
+    }
+
+    fn status(run: &WorkflowRun, id: &str) -> StepStatus {
+        run.step(id).unwrap().status
+    }
+
+    #[tokio::test]
+    async fn test_cycle_rejected() {
+        let f = fixture(Arc::new(MemoryWorkflowStorage::new()));
+        let cyclic = spec(vec![
+            function("a", &["c"], Value::Null),
+            function("b", &["a"], Value::Null),
+            function("c", &["b"], Value::Null),
+        ]);
+        assert!(matches!(
+            f.service.create_workflow("alice", cyclic).await,
+            Err(WorkflowError::InvalidDefinition(_))
+        ));
+
+        let self_dependent = spec(vec![function("a", &["a"], Value::Null)]);
+        assert!(matches!(
+            f.service.create_workflow("alice", self_dependent).await,
+            Err(WorkflowError::InvalidDefinition(_))
+        ));
+        assert!(f.service.list_workflows("alice").await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_fan_out_fan_in() {
+        let f = fixture(Arc::new(MemoryWorkflowStorage::new()));
+        let run = start(
+            &f,
+            vec![
+                // Listed before its dependencies, the order comes from the graph
+                function(
+                    "join",
+                    &["left", "right"],
+                    json!(["${steps.left.output.input}", "${steps.right.output.input}"]),
+                ),
+                function(
+                    "left",
+                    &["fetch"],
+                    json!("${steps.fetch.output.input.city}"),
+                ),
+                function("right", &["fetch"], json!("right of ${input.city}")),
+                function("fetch", &[], json!("${input}")),
+            ],
+        )
+        .await;
+        assert_eq!(run.definition.order.first().unwrap(), "fetch");
+        assert_eq!(run.definition.order.last().unwrap(), "join");
+
+        let run = f.engine.advance(&run.id).await.unwrap();
+        assert_eq!(run.status, RunStatus::Succeeded);
+        assert!(run.finished_at.is_some());
+
+        let calls = f.invoker.calls();
+        assert_eq!(calls.len(), 4);
+        assert_eq!(calls[0], "fetch");
+        assert_eq!(calls[3], "join");
+        assert_eq!(
+            run.step("join").unwrap().output,
+            Some(json!({
+                "function": "join",
+                "input": ["Shanghai", "right of Shanghai"],
+            }))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_step_retried() {
+        let f = fixture(Arc::new(MemoryWorkflowStorage::new()));
+        f.invoker.fail("flaky", 2);
+        let run = start(
+            &f,
+            vec![
+                function("flaky", &[], Value::Null),
+                function("after", &["flaky"], Value::Null),
+            ],
+        )
+        .await;
+
+        let run = f.engine.advance(&run.id).await.unwrap();
+        assert_eq!(run.status, RunStatus::Succeeded);
+        let flaky = run.step("flaky").unwrap();
+        assert_eq!(flaky.attempts, 3);
+        assert_eq!(flaky.error, None);
+        assert_eq!(f.invoker.calls(), ["flaky", "flaky", "flaky", "after"]);
+    }
+
+    #[tokio::test]
+    async fn test_step_retry_backoff() {
+        let f = fixture(Arc::new(MemoryWorkflowStorage::new()));
+        f.invoker.fail("flaky", 1);
+        let mut step = function("flaky", &[], Value::Null);
+        step["retry"]["backoff_seconds"] = json!(60);
+        let run = start(&f, vec![step]).await;
+
+        // The retry waits for its backoff
+        let run = f.engine.advance(&run.id).await.unwrap();
+        assert_eq!(run.status, RunStatus::Running);
+        let flaky = run.step("flaky").unwrap();
+        assert_eq!(flaky.status, StepStatus::Pending);
+        assert_eq!(flaky.attempts, 1);
+        assert_eq!(flaky.error.as_deref(), Some("Step error: flaky failed"));
+        assert!(flaky.next_attempt_at.is_some_and(|at| at > run.updated_at));
+
+        f.engine.advance(&run.id).await.unwrap();
+        assert_eq!(f.invoker.calls().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_step_failure_fails_run() {
+        let f = fixture(Arc::new(MemoryWorkflowStorage::new()));
+        f.invoker.fail("broken", u32::MAX);
+        let run = start(
+            &f,
+            vec![
+                function("broken", &[], Value::Null),
+                function("after", &["broken"], Value::Null),
+            ],
+        )
+        .await;
+
+        let run = f.engine.advance(&run.id).await.unwrap();
+        assert_eq!(run.status, RunStatus::Failed);
+        assert_eq!(status(&run, "broken"), StepStatus::Failed);
+        assert_eq!(run.step("broken").unwrap().attempts, 3);
+        assert_eq!(status(&run, "after"), StepStatus::Skipped);
+        assert!(run
+            .error
+            .as_deref()
+            .unwrap()
+            .starts_with("Step broken failed"));
+        assert_eq!(f.invoker.calls(), ["broken", "broken", "broken"]);
+
+        // Ended runs are not picked up again
+        assert_eq!(f.engine.process_pending().await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_condition_skips_branch() {
+        let f = fixture(Arc::new(MemoryWorkflowStorage::new()));
+        let mut yes = function("yes", &[], Value::Null);
+        yes["when"] = json!({ "step": "check" });
+        let mut no = function("no", &[], Value::Null);
+        no["when"] = json!({ "step": "check", "equals": false });
+        let run = start(
+            &f,
+            vec![
+                json!({
+                    "id": "check",
+                    "type": "condition",
+                    "value": "${input.city}",
+                    "operator": "eq",
+                    "operand": "Shanghai",
+                }),
+                yes,
+                no,
+                function("after_no", &["no"], Value::Null),
+            ],
+        )
+        .await;
+
+        let run = f.engine.advance(&run.id).await.unwrap();
+        assert_eq!(run.status, RunStatus::Succeeded);
+        assert_eq!(status(&run, "yes"), StepStatus::Succeeded);
+        assert_eq!(status(&run, "no"), StepStatus::Skipped);
+        assert_eq!(status(&run, "after_no"), StepStatus::Skipped);
+        assert_eq!(f.invoker.calls(), ["yes"]);
+    }
+
+    #[tokio::test]
+    async fn test_resume_after_restart() {
+        let storage = Arc::new(MemoryWorkflowStorage::new());
+        let f = fixture(storage.clone());
+        let run = start(
+            &f,
+            vec![
+                function("first", &[], Value::Null),
+                json!({ "id": "wait", "type": "delay", "seconds": 3600, "depends_on": ["first"] }),
+                function("last", &["wait"], json!("${steps.first.output.function}")),
+            ],
+        )
+        .await;
+
+        let run = f.engine.advance(&run.id).await.unwrap();
+        assert_eq!(run.status, RunStatus::Running);
+        assert_eq!(status(&run, "first"), StepStatus::Succeeded);
+        assert_eq!(status(&run, "wait"), StepStatus::Waiting);
+        assert_eq!(f.invoker.calls(), ["first"]);
+
+        // The process restarts with the run in the store, by when the delay has elapsed
+        drop(f);
+        let mut stored = storage.get_run(&run.id).await.unwrap().unwrap();
+        stored.step_mut("wait").unwrap().next_attempt_at = Some(0);
+        storage.put_run(stored).await.unwrap();
+
+        let f = fixture(storage);
+        assert_eq!(f.engine.process_pending().await.unwrap(), 1);
+        let run = f.storage.get_run(&run.id).await.unwrap().unwrap();
+        assert_eq!(run.status, RunStatus::Succeeded);
+        // Finished steps are not run again, their outputs survive the restart
+        assert_eq!(f.invoker.calls(), ["last"]);
+        assert_eq!(
+            run.step("last").unwrap().output,
+            Some(json!({ "function": "last", "input": "first" }))
+        );
+    }
+}
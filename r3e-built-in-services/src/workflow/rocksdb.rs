@@ -0,0 +1,203 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+use async_trait::async_trait;
+use r3e_store::rocksdb::RocksDBStore;
+use r3e_store::{KvStore, SortedKvStore};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::workflow::storage::WorkflowStorage;
+use crate::workflow::types::{WorkflowDefinition, WorkflowError, WorkflowRun};
+
+/// RocksDB implementation of WorkflowStorage
+pub struct RocksDBWorkflowStorage {
+    db: Arc<RocksDBStore>,
+    workflows_cf: String,
+    runs_cf: String,
+    run_index_cf: String,
+    active_runs_cf: String,
+}
+
+impl RocksDBWorkflowStorage {
+    /// Create a new RocksDB workflow storage
+    pub fn new<P: AsRef<Path>>(db_path: P) -> Result<Self, WorkflowError> {
+        let db = RocksDBStore::new(db_path).map_err(|e| {
+            WorkflowError::Storage(format!("Failed to create RocksDB store: {}", e))
+        })?;
+
+        Ok(Self {
+            db: Arc::new(db),
+            workflows_cf: "workflow_definitions".to_string(),
+            runs_cf: "workflow_runs".to_string(),
+            run_index_cf: "workflow_run_index".to_string(),
+            active_runs_cf: "workflow_active_runs".to_string(),
+        })
+    }
+
+    fn get_json<T: DeserializeOwned>(
+        &self,
+        cf: &str,
+        key: &str,
+    ) -> Result<Option<T>, WorkflowError> {
+        match self.db.get(cf, key.as_bytes()) {
+            Ok(value) => serde_json::from_slice(&value).map(Some).map_err(|e| {
+                WorkflowError::Storage(format!("Failed to deserialize {}: {}", cf, e))
+            }),
+            Err(r3e_store::GetError::NoSuchKey) => Ok(None),
+            Err(e) => Err(WorkflowError::Storage(format!(
+                "Failed to get {}: {}",
+                cf, e
+            ))),
+        }
+    }
+
+    fn put_bytes(&self, cf: &str, key: &str, value: &[u8]) -> Result<(), WorkflowError> {
+        let input = r3e_store::PutInput {
+            key: key.as_bytes(),
+            value,
+            if_not_exists: false,
+        };
+
+        self.db
+            .put(cf, input)
+            .map_err(|e| WorkflowError::Storage(format!("Failed to store {}: {}", cf, e)))
+    }
+
+    fn put_json<T: Serialize>(&self, cf: &str, key: &str, value: &T) -> Result<(), WorkflowError> {
+        let value = serde_json::to_vec(value)
+            .map_err(|e| WorkflowError::Storage(format!("Failed to serialize {}: {}", cf, e)))?;
+        self.put_bytes(cf, key, &value)
+    }
+
+    fn delete(&self, cf: &str, key: &str) -> Result<bool, WorkflowError> {
+        self.db
+            .delete(cf, key.as_bytes())
+            .map(|old| old.is_some())
+            .map_err(|e| WorkflowError::Storage(format!("Failed to delete {}: {}", cf, e)))
+    }
+
+    /// Scan the entries with keys in `[start, end)`, an empty `end` scanning to the end
+    fn scan(
+        &self,
+        cf: &str,
+        start: &[u8],
+        end: &[u8],
+    ) -> Result<Vec<(Vec<u8>, Vec<u8>)>, WorkflowError> {
+        let mut kvs = Vec::new();
+        let mut start_key = start.to_vec();
+        let mut start_exclusive = false;
+
+        loop {
+            let input = r3e_store::ScanInput {
+                start_key: &start_key,
+                start_exclusive,
+                end_key: end,
+                end_inclusive: false,
+                reverse: false,
+                max_count: 1000,
+            };
+
+            let output = self
+                .db
+                .scan(cf, input)
+                .map_err(|e| WorkflowError::Storage(format!("Failed to scan {}: {}", cf, e)))?;
+
+            let next = match output.kvs.last() {
+                Some((key, _)) if output.has_more => Some(key.clone()),
+                _ => None,
+            };
+            kvs.extend(output.kvs);
+
+            match next {
+                Some(key) => {
+                    start_key = key;
+                    start_exclusive = true;
+                }
+                None => break,
+            }
+        }
+
+        Ok(kvs)
+    }
+}
+
+#[async_trait]
+impl WorkflowStorage for RocksDBWorkflowStorage {
+    async fn get_workflow(&self, id: &str) -> Result<Option<WorkflowDefinition>, WorkflowError> {
+        self.get_json(&self.workflows_cf, id)
+    }
+
+    async fn put_workflow(&self, workflow: WorkflowDefinition) -> Result<(), WorkflowError> {
+        self.put_json(&self.workflows_cf, &workflow.id, &workflow)
+    }
+
+    async fn delete_workflow(&self, id: &str) -> Result<bool, WorkflowError> {
+        self.delete(&self.workflows_cf, id)
+    }
+
+    async fn list_workflows(&self, owner: &str) -> Result<Vec<WorkflowDefinition>, WorkflowError> {
+        let mut workflows = Vec::new();
+        for (_, value) in self.scan(&self.workflows_cf, &[], &[])? {
+            let workflow: WorkflowDefinition = serde_json::from_slice(&value).map_err(|e| {
+                WorkflowError::Storage(format!("Failed to deserialize workflow: {}", e))
+            })?;
+            if workflow.owner == owner {
+                workflows.push(workflow);
+            }
+        }
+        workflows.sort_by_key(|workflow| workflow.created_at);
+        Ok(workflows)
+    }
+
+    async fn get_run(&self, id: &str) -> Result<Option<WorkflowRun>, WorkflowError> {
+        self.get_json(&self.runs_cf, id)
+    }
+
+    async fn put_run(&self, run: WorkflowRun) -> Result<(), WorkflowError> {
+        self.put_json(&self.runs_cf, &run.id, &run)?;
+        self.put_bytes(
+            &self.run_index_cf,
+            &format!("{}/{}", run.workflow_id, run.id),
+            run.id.as_bytes(),
+        )?;
+
+        // Keep the active index in step with the status, so restarts only load live runs
+        if run.status.is_terminal() {
+            self.delete(&self.active_runs_cf, &run.id)?;
+        } else {
+            self.put_bytes(&self.active_runs_cf, &run.id, &[])?;
+        }
+        Ok(())
+    }
+
+    async fn list_runs(&self, workflow_id: &str) -> Result<Vec<WorkflowRun>, WorkflowError> {
+        // Index keys are `<workflow_id>/<run_id>`, so a workflow's runs sort between
+        // `<workflow_id>/` and `<workflow_id>0`
+        let start = format!("{}/", workflow_id);
+        let end = format!("{}0", workflow_id);
+
+        let mut runs = Vec::new();
+        for (_, run_id) in self.scan(&self.run_index_cf, start.as_bytes(), end.as_bytes())? {
+            let run_id = String::from_utf8(run_id)
+                .map_err(|e| WorkflowError::Storage(format!("Invalid run index: {}", e)))?;
+            if let Some(run) = self.get_json::<WorkflowRun>(&self.runs_cf, &run_id)? {
+                runs.push(run);
+            }
+        }
+        runs.sort_by_key(|run| run.created_at);
+        Ok(runs)
+    }
+
+    async fn list_active_runs(&self) -> Result<Vec<String>, WorkflowError> {
+        self.scan(&self.active_runs_cf, &[], &[])?
+            .into_iter()
+            .map(|(key, _)| {
+                String::from_utf8(key)
+                    .map_err(|e| WorkflowError::Storage(format!("Invalid active run: {}", e)))
+            })
+            .collect()
+    }
+}
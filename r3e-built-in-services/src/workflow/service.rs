@@ -0,0 +1,242 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+use async_trait::async_trait;
+use serde_json::Value;
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+
+use crate::workflow::engine::WorkflowEngine;
+use crate::workflow::storage::WorkflowStorage;
+use crate::workflow::types::{
+    RunStatus, StepRun, WorkflowDefinition, WorkflowError, WorkflowGraph, WorkflowRun, WorkflowSpec,
+};
+
+/// Workflow service trait
+#[async_trait]
+pub trait WorkflowServiceTrait: Send + Sync {
+    /// Create a workflow from a specification
+    async fn create_workflow(
+        &self,
+        owner: &str,
+        spec: WorkflowSpec,
+    ) -> Result<WorkflowDefinition, WorkflowError>;
+
+    /// Replace the specification of a workflow; running runs keep their definition
+    async fn update_workflow(
+        &self,
+        owner: &str,
+        id: &str,
+        spec: WorkflowSpec,
+    ) -> Result<WorkflowDefinition, WorkflowError>;
+
+    /// Get a workflow
+    async fn get_workflow(
+        &self,
+        owner: &str,
+        id: &str,
+    ) -> Result<WorkflowDefinition, WorkflowError>;
+
+    /// List the workflows of an owner
+    async fn list_workflows(&self, owner: &str) -> Result<Vec<WorkflowDefinition>, WorkflowError>;
+
+    /// Delete a workflow; running runs continue
+    async fn delete_workflow(&self, owner: &str, id: &str) -> Result<(), WorkflowError>;
+
+    /// Start a run of a workflow
+    async fn start_run(
+        &self,
+        owner: &str,
+        workflow_id: &str,
+        input: Value,
+    ) -> Result<WorkflowRun, WorkflowError>;
+
+    /// Get a run
+    async fn get_run(&self, owner: &str, run_id: &str) -> Result<WorkflowRun, WorkflowError>;
+
+    /// List the runs of a workflow
+    async fn list_runs(
+        &self,
+        owner: &str,
+        workflow_id: &str,
+    ) -> Result<Vec<WorkflowRun>, WorkflowError>;
+
+    /// Cancel a run
+    async fn cancel_run(&self, owner: &str, run_id: &str) -> Result<WorkflowRun, WorkflowError>;
+
+    /// Get the graph of a run for visualization
+    async fn get_run_graph(
+        &self,
+        owner: &str,
+        run_id: &str,
+    ) -> Result<WorkflowGraph, WorkflowError>;
+}
+
+/// Workflow service implementation
+pub struct WorkflowService {
+    /// Workflow storage
+    storage: Arc<dyn WorkflowStorage>,
+
+    /// Engine driving the runs
+    engine: Arc<WorkflowEngine>,
+}
+
+impl WorkflowService {
+    /// Create a new workflow service
+    pub fn new(storage: Arc<dyn WorkflowStorage>, engine: Arc<WorkflowEngine>) -> Self {
+        Self { storage, engine }
+    }
+
+    /// Get current timestamp
+    fn get_current_timestamp(&self) -> u64 {
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs()
+    }
+
+    fn build_definition(
+        &self,
+        id: String,
+        owner: &str,
+        spec: WorkflowSpec,
+        created_at: u64,
+    ) -> Result<WorkflowDefinition, WorkflowError> {
+        let order = spec.validate()?;
+        Ok(WorkflowDefinition {
+            id,
+            owner: owner.to_string(),
+            name: spec.name,
+            description: spec.description,
+            steps: spec.steps,
+            order,
+            created_at,
+            updated_at: self.get_current_timestamp(),
+        })
+    }
+
+    /// Get a run, hiding the runs of other owners
+    async fn owned_run(&self, owner: &str, run_id: &str) -> Result<WorkflowRun, WorkflowError> {
+        match self.storage.get_run(run_id).await? {
+            Some(run) if run.owner == owner => Ok(run),
+            _ => Err(WorkflowError::NotFound(format!(
+                "Workflow run not found: {}",
+                run_id
+            ))),
+        }
+    }
+}
+
+#[async_trait]
+impl WorkflowServiceTrait for WorkflowService {
+    async fn create_workflow(
+        &self,
+        owner: &str,
+        spec: WorkflowSpec,
+    ) -> Result<WorkflowDefinition, WorkflowError> {
+        let id = uuid::Uuid::new_v4().to_string();
+        let workflow = self.build_definition(id, owner, spec, self.get_current_timestamp())?;
+        self.storage.put_workflow(workflow.clone()).await?;
+        Ok(workflow)
+    }
+
+    async fn update_workflow(
+        &self,
+        owner: &str,
+        id: &str,
+        spec: WorkflowSpec,
+    ) -> Result<WorkflowDefinition, WorkflowError> {
+        let existing = self.get_workflow(owner, id).await?;
+        let workflow = self.build_definition(existing.id, owner, spec, existing.created_at)?;
+        self.storage.put_workflow(workflow.clone()).await?;
+        Ok(workflow)
+    }
+
+    async fn get_workflow(
+        &self,
+        owner: &str,
+        id: &str,
+    ) -> Result<WorkflowDefinition, WorkflowError> {
+        match self.storage.get_workflow(id).await? {
+            Some(workflow) if workflow.owner == owner => Ok(workflow),
+            _ => Err(WorkflowError::NotFound(format!(
+                "Workflow not found: {}",
+                id
+            ))),
+        }
+    }
+
+    async fn list_workflows(&self, owner: &str) -> Result<Vec<WorkflowDefinition>, WorkflowError> {
+        self.storage.list_workflows(owner).await
+    }
+
+    async fn delete_workflow(&self, owner: &str, id: &str) -> Result<(), WorkflowError> {
+        self.get_workflow(owner, id).await?;
+        self.storage.delete_workflow(id).await?;
+        Ok(())
+    }
+
+    async fn start_run(
+        &self,
+        owner: &str,
+        workflow_id: &str,
+        input: Value,
+    ) -> Result<WorkflowRun, WorkflowError> {
+        let workflow = self.get_workflow(owner, workflow_id).await?;
+        let now = self.get_current_timestamp();
+
+        let run = WorkflowRun {
+            id: uuid::Uuid::new_v4().to_string(),
+            workflow_id: workflow.id.clone(),
+            owner: owner.to_string(),
+            input,
+            status: RunStatus::Running,
+            steps: workflow.order.iter().map(|id| StepRun::new(id)).collect(),
+            definition: workflow,
+            error: None,
+            created_at: now,
+            updated_at: now,
+            finished_at: None,
+        };
+        self.storage.put_run(run.clone()).await?;
+        self.engine.notify();
+
+        log::info!("workflow: started run {} of {}", run.id, run.workflow_id);
+        Ok(run)
+    }
+
+    async fn get_run(&self, owner: &str, run_id: &str) -> Result<WorkflowRun, WorkflowError> {
+        self.owned_run(owner, run_id).await
+    }
+
+    async fn list_runs(
+        &self,
+        owner: &str,
+        workflow_id: &str,
+    ) -> Result<Vec<WorkflowRun>, WorkflowError> {
+        let mut runs = self.storage.list_runs(workflow_id).await?;
+        runs.retain(|run| run.owner == owner);
+        Ok(runs)
+    }
+
+    async fn cancel_run(&self, owner: &str, run_id: &str) -> Result<WorkflowRun, WorkflowError> {
+        self.owned_run(owner, run_id).await?;
+        self.engine.cancel(run_id).await
+    }
+
+    async fn get_run_graph(
+        &self,
+        owner: &str,
+        run_id: &str,
+    ) -> Result<WorkflowGraph, WorkflowError> {
+        Ok(self.owned_run(owner, run_id).await?.graph())
+    }
+}
+
+/// Spawn the loop driving the workflow runs until `stop` is set
+pub fn spawn_workflow_engine(
+    engine: Arc<WorkflowEngine>,
+    stop: Arc<AtomicBool>,
+) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move { engine.run(stop).await })
+}
@@ -0,0 +1,118 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+use crate::workflow::types::{WorkflowDefinition, WorkflowError, WorkflowRun};
+
+/// Workflow storage trait
+#[async_trait]
+pub trait WorkflowStorage: Send + Sync {
+    /// Get a workflow definition by ID
+    async fn get_workflow(&self, id: &str) -> Result<Option<WorkflowDefinition>, WorkflowError>;
+
+    /// Create or update a workflow definition
+    async fn put_workflow(&self, workflow: WorkflowDefinition) -> Result<(), WorkflowError>;
+
+    /// Delete a workflow definition, returning whether it existed
+    async fn delete_workflow(&self, id: &str) -> Result<bool, WorkflowError>;
+
+    /// List the workflow definitions of an owner
+    async fn list_workflows(&self, owner: &str) -> Result<Vec<WorkflowDefinition>, WorkflowError>;
+
+    /// Get a run by ID
+    async fn get_run(&self, id: &str) -> Result<Option<WorkflowRun>, WorkflowError>;
+
+    /// Create or update a run
+    async fn put_run(&self, run: WorkflowRun) -> Result<(), WorkflowError>;
+
+    /// List the runs of a workflow, oldest first
+    async fn list_runs(&self, workflow_id: &str) -> Result<Vec<WorkflowRun>, WorkflowError>;
+
+    /// List the IDs of the runs that are still running
+    async fn list_active_runs(&self) -> Result<Vec<String>, WorkflowError>;
+}
+
+/// In-memory implementation of the workflow storage
+#[derive(Default)]
+pub struct MemoryWorkflowStorage {
+    /// Workflow definitions by ID
+    workflows: RwLock<HashMap<String, WorkflowDefinition>>,
+
+    /// Runs by ID
+    runs: RwLock<HashMap<String, WorkflowRun>>,
+}
+
+impl MemoryWorkflowStorage {
+    /// Create a new memory-based workflow storage
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+fn lock_error(e: impl std::fmt::Display) -> WorkflowError {
+    WorkflowError::Storage(format!("Failed to acquire lock: {}", e))
+}
+
+#[async_trait]
+impl WorkflowStorage for MemoryWorkflowStorage {
+    async fn get_workflow(&self, id: &str) -> Result<Option<WorkflowDefinition>, WorkflowError> {
+        let workflows = self.workflows.read().map_err(lock_error)?;
+        Ok(workflows.get(id).cloned())
+    }
+
+    async fn put_workflow(&self, workflow: WorkflowDefinition) -> Result<(), WorkflowError> {
+        let mut workflows = self.workflows.write().map_err(lock_error)?;
+        workflows.insert(workflow.id.clone(), workflow);
+        Ok(())
+    }
+
+    async fn delete_workflow(&self, id: &str) -> Result<bool, WorkflowError> {
+        let mut workflows = self.workflows.write().map_err(lock_error)?;
+        Ok(workflows.remove(id).is_some())
+    }
+
+    async fn list_workflows(&self, owner: &str) -> Result<Vec<WorkflowDefinition>, WorkflowError> {
+        let workflows = self.workflows.read().map_err(lock_error)?;
+        let mut workflows: Vec<_> = workflows
+            .values()
+            .filter(|workflow| workflow.owner == owner)
+            .cloned()
+            .collect();
+        workflows.sort_by_key(|workflow| workflow.created_at);
+        Ok(workflows)
+    }
+
+    async fn get_run(&self, id: &str) -> Result<Option<WorkflowRun>, WorkflowError> {
+        let runs = self.runs.read().map_err(lock_error)?;
+        Ok(runs.get(id).cloned())
+    }
+
+    async fn put_run(&self, run: WorkflowRun) -> Result<(), WorkflowError> {
+        let mut runs = self.runs.write().map_err(lock_error)?;
+        runs.insert(run.id.clone(), run);
+        Ok(())
+    }
+
+    async fn list_runs(&self, workflow_id: &str) -> Result<Vec<WorkflowRun>, WorkflowError> {
+        let runs = self.runs.read().map_err(lock_error)?;
+        let mut runs: Vec<_> = runs
+            .values()
+            .filter(|run| run.workflow_id == workflow_id)
+            .cloned()
+            .collect();
+        runs.sort_by_key(|run| run.created_at);
+        Ok(runs)
+    }
+
+    async fn list_active_runs(&self) -> Result<Vec<String>, WorkflowError> {
+        let runs = self.runs.read().map_err(lock_error)?;
+        Ok(runs
+            .values()
+            .filter(|run| !run.status.is_terminal())
+            .map(|run| run.id.clone())
+            .collect())
+    }
+}
@@ -0,0 +1,730 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+use r3e_oracle::OracleRequestType;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{HashMap, HashSet, VecDeque};
+use thiserror::Error;
+
+/// Maximum number of steps of a workflow
+pub const MAX_WORKFLOW_STEPS: usize = 100;
+
+/// Maximum duration of a delay step, in seconds
+pub const MAX_DELAY_SECONDS: u64 = 7 * 24 * 60 * 60;
+
+/// Maximum attempts of a step
+pub const MAX_STEP_ATTEMPTS: u32 = 20;
+
+#[derive(Debug, Error)]
+pub enum WorkflowError {
+    #[error("Storage error: {0}")]
+    Storage(String),
+
+    #[error("Not found: {0}")]
+    NotFound(String),
+
+    #[error("Invalid input: {0}")]
+    InvalidInput(String),
+
+    #[error("Invalid workflow definition: {0}")]
+    InvalidDefinition(String),
+
+    #[error("Step error: {0}")]
+    Step(String),
+}
+
+/// Retry policy of a step
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RetryPolicy {
+    /// Attempts before the step, and with it the run, fails
+    #[serde(default = "default_max_attempts")]
+    pub max_attempts: u32,
+
+    /// Delay before the first retry, in seconds
+    #[serde(default = "default_backoff_seconds")]
+    pub backoff_seconds: u64,
+
+    /// Double the delay after every failed attempt
+    #[serde(default = "default_true")]
+    pub exponential: bool,
+}
+
+fn default_max_attempts() -> u32 {
+    3
+}
+
+fn default_backoff_seconds() -> u64 {
+    5
+}
+
+fn default_true() -> bool {
+    true
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: default_max_attempts(),
+            backoff_seconds: default_backoff_seconds(),
+            exponential: true,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Delay before the next attempt after `attempts` failed attempts, in seconds
+    pub fn backoff(&self, attempts: u32) -> u64 {
+        if !self.exponential || attempts <= 1 {
+            return self.backoff_seconds;
+        }
+        let factor = 1u64 << (attempts - 1).min(16);
+        self.backoff_seconds.saturating_mul(factor)
+    }
+}
+
+/// Comparison operator of a condition step
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConditionOperator {
+    Eq,
+    Ne,
+    Gt,
+    Gte,
+    Lt,
+    Lte,
+    Contains,
+    Exists,
+}
+
+impl ConditionOperator {
+    /// Evaluate `value <operator> operand`
+    pub fn evaluate(&self, value: &Value, operand: &Value) -> bool {
+        match self {
+            ConditionOperator::Eq => value == operand,
+            ConditionOperator::Ne => value != operand,
+            ConditionOperator::Gt => compare(value, operand).is_some_and(|o| o.is_gt()),
+            ConditionOperator::Gte => compare(value, operand).is_some_and(|o| o.is_ge()),
+            ConditionOperator::Lt => compare(value, operand).is_some_and(|o| o.is_lt()),
+            ConditionOperator::Lte => compare(value, operand).is_some_and(|o| o.is_le()),
+            ConditionOperator::Contains => match value {
+                Value::String(s) => operand.as_str().is_some_and(|o| s.contains(o)),
+                Value::Array(items) => items.contains(operand),
+                Value::Object(map) => operand.as_str().is_some_and(|o| map.contains_key(o)),
+                _ => false,
+            },
+            ConditionOperator::Exists => !value.is_null(),
+        }
+    }
+}
+
+fn compare(value: &Value, operand: &Value) -> Option<std::cmp::Ordering> {
+    match (value, operand) {
+        (Value::String(a), Value::String(b)) => Some(a.cmp(b)),
+        _ => value.as_f64()?.partial_cmp(&operand.as_f64()?),
+    }
+}
+
+/// What a step does
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum StepKind {
+    /// Invoke a function of the workflow owner with the templated input
+    Function {
+        function_id: String,
+        #[serde(default)]
+        input: Value,
+    },
+
+    /// Submit an oracle request and wait for its response
+    Oracle {
+        request_type: OracleRequestType,
+        #[serde(default)]
+        data: Value,
+    },
+
+    /// Wait before the dependent steps run
+    Delay { seconds: u64 },
+
+    /// Evaluate a comparison, the output being `{"result": bool}`
+    Condition {
+        value: Value,
+        operator: ConditionOperator,
+        #[serde(default)]
+        operand: Value,
+    },
+}
+
+impl StepKind {
+    /// Values of the step that are templated before it runs
+    fn templated_values(&self) -> Vec<&Value> {
+        match self {
+            StepKind::Function { input, .. } => vec![input],
+            StepKind::Oracle { data, .. } => vec![data],
+            StepKind::Delay { .. } => vec![],
+            StepKind::Condition { value, operand, .. } => vec![value, operand],
+        }
+    }
+}
+
+/// Guard running a step only when a condition step had the expected result
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StepGuard {
+    /// ID of the condition step
+    pub step: String,
+
+    /// Result the condition must have for the step to run
+    #[serde(default = "default_true")]
+    pub equals: bool,
+}
+
+/// Step of a workflow
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StepDefinition {
+    /// Step ID, unique within the workflow
+    pub id: String,
+
+    /// Steps that must finish before this one starts
+    #[serde(default)]
+    pub depends_on: Vec<String>,
+
+    /// What the step does
+    #[serde(flatten)]
+    pub kind: StepKind,
+
+    /// Retry policy
+    #[serde(default)]
+    pub retry: RetryPolicy,
+
+    /// Run the step only when a condition step had the expected result
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub when: Option<StepGuard>,
+}
+
+impl StepDefinition {
+    /// Steps this one waits for, including the condition step of its guard
+    pub fn dependencies(&self) -> impl Iterator<Item = &str> {
+        self.depends_on
+            .iter()
+            .map(String::as_str)
+            .chain(self.when.iter().map(|guard| guard.step.as_str()))
+    }
+}
+
+/// Workflow specification, as submitted by users in JSON or YAML
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WorkflowSpec {
+    /// Workflow name
+    pub name: String,
+
+    /// Optional description
+    #[serde(default)]
+    pub description: Option<String>,
+
+    /// Steps
+    pub steps: Vec<StepDefinition>,
+}
+
+impl WorkflowSpec {
+    /// Parse a specification from JSON, or YAML when it is not JSON
+    pub fn parse(source: &str) -> Result<Self, WorkflowError> {
+        match serde_json::from_str(source) {
+            Ok(spec) => Ok(spec),
+            Err(json_err) if source.trim_start().starts_with('{') => Err(
+                WorkflowError::InvalidDefinition(format!("Invalid JSON: {}", json_err)),
+            ),
+            Err(_) => serde_yaml::from_str(source)
+                .map_err(|e| WorkflowError::InvalidDefinition(format!("Invalid YAML: {}", e))),
+        }
+    }
+
+    /// Validate the specification, returning the step IDs in execution order
+    pub fn validate(&self) -> Result<Vec<String>, WorkflowError> {
+        let invalid = |msg: String| Err(WorkflowError::InvalidDefinition(msg));
+
+        if self.name.trim().is_empty() {
+            return invalid("Workflow name is required".to_string());
+        }
+        if self.steps.is_empty() {
+            return invalid("Workflow has no steps".to_string());
+        }
+        if self.steps.len() > MAX_WORKFLOW_STEPS {
+            return invalid(format!(
+                "Workflow has {} steps, the maximum is {}",
+                self.steps.len(),
+                MAX_WORKFLOW_STEPS
+            ));
+        }
+
+        let mut steps = HashMap::new();
+        for step in &self.steps {
+            if step.id.is_empty() || step.id.contains('.') {
+                return invalid(format!("Invalid step ID: {:?}", step.id));
+            }
+            if steps.insert(step.id.as_str(), step).is_some() {
+                return invalid(format!("Duplicate step ID: {}", step.id));
+            }
+        }
+
+        for step in &self.steps {
+            for dep in step.dependencies() {
+                if !steps.contains_key(dep) {
+                    return invalid(format!("Step {} depends on unknown step {}", step.id, dep));
+                }
+                if dep == step.id {
+                    return invalid(format!("Step {} depends on itself", step.id));
+                }
+            }
+            if let Some(guard) = &step.when {
+                if !matches!(steps[guard.step.as_str()].kind, StepKind::Condition { .. }) {
+                    return invalid(format!(
+                        "Step {} is guarded by {}, which is not a condition step",
+                        step.id, guard.step
+                    ));
+                }
+            }
+            if step.retry.max_attempts == 0 || step.retry.max_attempts > MAX_STEP_ATTEMPTS {
+                return invalid(format!(
+                    "Step {} must have between 1 and {} attempts",
+                    step.id, MAX_STEP_ATTEMPTS
+                ));
+            }
+            match &step.kind {
+                StepKind::Function { function_id, .. } if function_id.is_empty() => {
+                    return invalid(format!("Step {} has no function", step.id));
+                }
+                StepKind::Delay { seconds } if *seconds > MAX_DELAY_SECONDS => {
+                    return invalid(format!(
+                        "Step {} delays {}s, the maximum is {}s",
+                        step.id, seconds, MAX_DELAY_SECONDS
+                    ));
+                }
+                _ => {}
+            }
+        }
+
+        // Order the steps topologically, which fails on cycles
+        let mut remaining: HashMap<&str, usize> = self
+            .steps
+            .iter()
+            .map(|step| {
+                (
+                    step.id.as_str(),
+                    step.dependencies().collect::<HashSet<_>>().len(),
+                )
+            })
+            .collect();
+        let mut ready: VecDeque<&str> = self
+            .steps
+            .iter()
+            .map(|step| step.id.as_str())
+            .filter(|id| remaining[id] == 0)
+            .collect();
+        let mut order = Vec::with_capacity(self.steps.len());
+        while let Some(id) = ready.pop_front() {
+            order.push(id.to_string());
+            for step in &self.steps {
+                if step.dependencies().collect::<HashSet<_>>().contains(id) {
+                    let count = remaining.get_mut(step.id.as_str()).expect("known step");
+                    *count -= 1;
+                    if *count == 0 {
+                        ready.push_back(step.id.as_str());
+                    }
+                }
+            }
+        }
+        if order.len() != self.steps.len() {
+            return invalid("Workflow steps contain a dependency cycle".to_string());
+        }
+
+        // Templates may only read the outputs of steps that finished before
+        let mut ancestors: HashMap<&str, HashSet<&str>> = HashMap::new();
+        for id in &order {
+            let step = steps[id.as_str()];
+            let mut own = HashSet::new();
+            for dep in step.dependencies() {
+                own.insert(dep);
+                own.extend(ancestors[dep].iter().copied());
+            }
+            for value in step.kind.templated_values() {
+                for reference in template_step_references(value) {
+                    if !own.contains(reference.as_str()) {
+                        return invalid(format!(
+                            "Step {} reads the output of {}, which it does not depend on",
+                            step.id, reference
+                        ));
+                    }
+                }
+            }
+            ancestors.insert(step.id.as_str(), own);
+        }
+
+        Ok(order)
+    }
+}
+
+/// Stored workflow definition
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WorkflowDefinition {
+    /// Workflow ID
+    pub id: String,
+
+    /// Owner of the workflow, whose functions the steps invoke
+    pub owner: String,
+
+    /// Workflow name
+    pub name: String,
+
+    /// Optional description
+    pub description: Option<String>,
+
+    /// Steps
+    pub steps: Vec<StepDefinition>,
+
+    /// Step IDs in execution order
+    pub order: Vec<String>,
+
+    /// Creation timestamp
+    pub created_at: u64,
+
+    /// Last update timestamp
+    pub updated_at: u64,
+}
+
+impl WorkflowDefinition {
+    /// Get a step by ID
+    pub fn step(&self, id: &str) -> Option<&StepDefinition> {
+        self.steps.iter().find(|step| step.id == id)
+    }
+}
+
+/// Status of a workflow run
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RunStatus {
+    Running,
+    Succeeded,
+    Failed,
+    Cancelled,
+}
+
+impl RunStatus {
+    /// Whether the run will not change anymore
+    pub fn is_terminal(&self) -> bool {
+        !matches!(self, RunStatus::Running)
+    }
+}
+
+/// Status of a step within a run
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StepStatus {
+    /// Waiting for its dependencies or its next attempt
+    Pending,
+
+    /// Waiting for a delay to elapse or an oracle response
+    Waiting,
+
+    Succeeded,
+
+    Failed,
+
+    /// Not run, because its guard did not hold, a dependency was skipped or the run ended
+    Skipped,
+}
+
+impl StepStatus {
+    /// Whether the step will not change anymore
+    pub fn is_terminal(&self) -> bool {
+        matches!(
+            self,
+            StepStatus::Succeeded | StepStatus::Failed | StepStatus::Skipped
+        )
+    }
+}
+
+/// State of a step within a run
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StepRun {
+    /// Step ID
+    pub id: String,
+
+    /// Step status
+    pub status: StepStatus,
+
+    /// Attempts made so far
+    pub attempts: u32,
+
+    /// Output of the step once it succeeded
+    pub output: Option<Value>,
+
+    /// Error of the last failed attempt
+    pub error: Option<String>,
+
+    /// ID of the pending oracle request of an oracle step
+    pub external_id: Option<String>,
+
+    /// Earliest time of the next attempt, or end of the delay of a delay step
+    pub next_attempt_at: Option<u64>,
+
+    /// Start of the first attempt
+    pub started_at: Option<u64>,
+
+    /// End of the step
+    pub finished_at: Option<u64>,
+}
+
+impl StepRun {
+    /// Create the state of a step that has not started
+    pub fn new(id: &str) -> Self {
+        Self {
+            id: id.to_string(),
+            status: StepStatus::Pending,
+            attempts: 0,
+            output: None,
+            error: None,
+            external_id: None,
+            next_attempt_at: None,
+            started_at: None,
+            finished_at: None,
+        }
+    }
+}
+
+/// Run of a workflow
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WorkflowRun {
+    /// Run ID
+    pub id: String,
+
+    /// Workflow ID
+    pub workflow_id: String,
+
+    /// Owner of the workflow
+    pub owner: String,
+
+    /// Definition the run executes, so updating the workflow does not affect running runs
+    pub definition: WorkflowDefinition,
+
+    /// Run input, available to templates as `${input}`
+    pub input: Value,
+
+    /// Run status
+    pub status: RunStatus,
+
+    /// Step states, in execution order
+    pub steps: Vec<StepRun>,
+
+    /// Error that ended the run
+    pub error: Option<String>,
+
+    /// Creation timestamp
+    pub created_at: u64,
+
+    /// Last update timestamp
+    pub updated_at: u64,
+
+    /// End of the run
+    pub finished_at: Option<u64>,
+}
+
+impl WorkflowRun {
+    /// Get the state of a step
+    pub fn step(&self, id: &str) -> Option<&StepRun> {
+        self.steps.iter().find(|step| step.id == id)
+    }
+
+    /// Get the mutable state of a step
+    pub fn step_mut(&mut self, id: &str) -> Option<&mut StepRun> {
+        self.steps.iter_mut().find(|step| step.id == id)
+    }
+
+    /// Template context of the run: the input and the outputs of the finished steps
+    pub fn context(&self) -> Value {
+        let steps = self
+            .steps
+            .iter()
+            .filter(|step| step.status == StepStatus::Succeeded)
+            .map(|step| {
+                let output = step.output.clone().unwrap_or(Value::Null);
+                (step.id.clone(), serde_json::json!({ "output": output }))
+            })
+            .collect::<serde_json::Map<_, _>>();
+        serde_json::json!({ "input": self.input, "steps": steps })
+    }
+
+    /// Graph of the run for visualization
+    pub fn graph(&self) -> WorkflowGraph {
+        let nodes = self
+            .definition
+            .order
+            .iter()
+            .filter_map(|id| {
+                let step = self.definition.step(id)?;
+                let state = self.step(id)?;
+                Some(GraphNode {
+                    id: id.clone(),
+                    kind: match step.kind {
+                        StepKind::Function { .. } => "function",
+                        StepKind::Oracle { .. } => "oracle",
+                        StepKind::Delay { .. } => "delay",
+                        StepKind::Condition { .. } => "condition",
+                    }
+                    .to_string(),
+                    status: state.status,
+                    attempts: state.attempts,
+                    error: state.error.clone(),
+                    started_at: state.started_at,
+                    finished_at: state.finished_at,
+                })
+            })
+            .collect();
+
+        let mut edges = Vec::new();
+        for step in &self.definition.steps {
+            for dep in &step.depends_on {
+                edges.push(GraphEdge {
+                    from: dep.clone(),
+                    to: step.id.clone(),
+                    condition: None,
+                });
+            }
+            if let Some(guard) = &step.when {
+                edges.push(GraphEdge {
+                    from: guard.step.clone(),
+                    to: step.id.clone(),
+                    condition: Some(guard.equals),
+                });
+            }
+        }
+
+        WorkflowGraph {
+            run_id: self.id.clone(),
+            status: self.status,
+            nodes,
+            edges,
+        }
+    }
+}
+
+/// Node of a run graph
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GraphNode {
+    pub id: String,
+    pub kind: String,
+    pub status: StepStatus,
+    pub attempts: u32,
+    pub error: Option<String>,
+    pub started_at: Option<u64>,
+    pub finished_at: Option<u64>,
+}
+
+/// Edge of a run graph, from a dependency to its dependent step
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GraphEdge {
+    pub from: String,
+    pub to: String,
+
+    /// Result the condition step must have, for guard edges
+    pub condition: Option<bool>,
+}
+
+/// Nodes and edges of a run, with the step states, for visualization
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WorkflowGraph {
+    pub run_id: String,
+    pub status: RunStatus,
+    pub nodes: Vec<GraphNode>,
+    pub edges: Vec<GraphEdge>,
+}
+
+/// Substitute the `${...}` references of a templated value
+///
+/// A string consisting of a single reference is replaced by the referenced value, keeping
+/// its type; references embedded in a longer string are interpolated as text. References
+/// are dotted paths into the run context, e.g. `${input.city}` or
+/// `${steps.fetch.output.items.0}`.
+pub fn render_template(value: &Value, context: &Value) -> Result<Value, WorkflowError> {
+    match value {
+        Value::String(s) => render_string(s, context),
+        Value::Array(items) => items
+            .iter()
+            .map(|item| render_template(item, context))
+            .collect::<Result<Vec<_>, _>>()
+            .map(Value::Array),
+        Value::Object(map) => map
+            .iter()
+            .map(|(key, item)| Ok((key.clone(), render_template(item, context)?)))
+            .collect::<Result<serde_json::Map<_, _>, _>>()
+            .map(Value::Object),
+        _ => Ok(value.clone()),
+    }
+}
+
+fn render_string(s: &str, context: &Value) -> Result<Value, WorkflowError> {
+    let references = template_references(s);
+    if references.is_empty() {
+        return Ok(Value::String(s.to_string()));
+    }
+    if references.len() == 1 && s.starts_with("${") && s.ends_with('}') {
+        return lookup(context, &references[0]).cloned();
+    }
+
+    let mut rendered = s.to_string();
+    for reference in references {
+        let text = match lookup(context, &reference)? {
+            Value::String(value) => value.clone(),
+            value => value.to_string(),
+        };
+        rendered = rendered.replace(&format!("${{{}}}", reference), &text);
+    }
+    Ok(Value::String(rendered))
+}
+
+fn lookup<'a>(context: &'a Value, path: &str) -> Result<&'a Value, WorkflowError> {
+    let mut current = context;
+    for segment in path.split('.') {
+        current = match current {
+            Value::Object(map) => map.get(segment),
+            Value::Array(items) => segment.parse::<usize>().ok().and_then(|i| items.get(i)),
+            _ => None,
+        }
+        .ok_or_else(|| WorkflowError::Step(format!("Unresolved reference: ${{{}}}", path)))?;
+    }
+    Ok(current)
+}
+
+fn template_references(s: &str) -> Vec<String> {
+    let mut references = Vec::new();
+    let mut rest = s;
+    while let Some(start) = rest.find("${") {
+        let after = &rest[start + 2..];
+        match after.find('}') {
+            Some(end) => {
+                references.push(after[..end].to_string());
+                rest = &after[end + 1..];
+            }
+            None => break,
+        }
+    }
+    references
+}
+
+/// IDs of the steps whose outputs a templated value reads
+fn template_step_references(value: &Value) -> Vec<String> {
+    match value {
+        Value::String(s) => template_references(s)
+            .into_iter()
+            .filter_map(|reference| {
+                let mut segments = reference.split('.');
+                match (segments.next(), segments.next()) {
+                    (Some("steps"), Some(id)) => Some(id.to_string()),
+                    _ => None,
+                }
+            })
+            .collect(),
+        Value::Array(items) => items.iter().flat_map(template_step_references).collect(),
+        Value::Object(map) => map.values().flat_map(template_step_references).collect(),
+        _ => vec![],
+    }
+}
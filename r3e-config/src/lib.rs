@@ -9,9 +9,11 @@ pub mod error;
 pub mod loader;
 pub mod provider;
 pub mod types;
+pub mod watcher;
 
 // Re-export important types
 pub use error::{Error, Result};
 pub use loader::ConfigLoader;
-pub use provider::ConfigProvider;
+pub use provider::{ConfigProvider, ConfigSubscriber};
 pub use types::*;
+pub use watcher::ConfigWatcher;
@@ -3,26 +3,99 @@
 
 //! Configuration provider.
 
+use async_trait::async_trait;
+use log::{info, warn};
 use std::sync::Arc;
-use tokio::sync::RwLock;
+use tokio::sync::{watch, RwLock};
 
 use crate::error::{Error, Result};
 use crate::types::FaasConfig;
 
+/// Component that applies configuration changes at runtime
+#[async_trait]
+pub trait ConfigSubscriber: Send + Sync {
+    /// Subscriber name, used in errors and logs
+    fn name(&self) -> &str;
+
+    /// Apply a new configuration, returning an error to reject it
+    async fn apply(&self, config: &FaasConfig) -> Result<()>;
+}
+
 /// Configuration provider
 pub struct ConfigProvider {
     /// Configuration
     config: Arc<RwLock<FaasConfig>>,
+
+    /// Change notifications
+    changes: watch::Sender<FaasConfig>,
+
+    /// Subscribers applying changes
+    subscribers: RwLock<Vec<Arc<dyn ConfigSubscriber>>>,
 }
 
 impl ConfigProvider {
     /// Create a new configuration provider
     pub fn new(config: FaasConfig) -> Self {
+        let (changes, _) = watch::channel(config.clone());
         Self {
             config: Arc::new(RwLock::new(config)),
+            changes,
+            subscribers: RwLock::new(Vec::new()),
         }
     }
 
+    /// Watch for configuration changes
+    pub fn subscribe(&self) -> watch::Receiver<FaasConfig> {
+        self.changes.subscribe()
+    }
+
+    /// Register a subscriber that applies every reloaded configuration
+    pub async fn add_subscriber(&self, subscriber: Arc<dyn ConfigSubscriber>) {
+        self.subscribers.write().await.push(subscriber);
+    }
+
+    /// Validate and apply a new configuration.
+    ///
+    /// Subscribers apply the configuration in registration order. If any of them rejects
+    /// it, the ones that already applied it are rolled back to the current configuration
+    /// and the current configuration is kept.
+    pub async fn reload(&self, config: FaasConfig) -> Result<()> {
+        config.validate()?;
+
+        let mut current = self.config.write().await;
+        let subscribers = self.subscribers.read().await.clone();
+
+        for (applied, subscriber) in subscribers.iter().enumerate() {
+            if let Err(e) = subscriber.apply(&config).await {
+                warn!(
+                    "Configuration rejected by {}, rolling back: {}",
+                    subscriber.name(),
+                    e
+                );
+                for subscriber in subscribers[..applied].iter().rev() {
+                    if let Err(e) = subscriber.apply(&current).await {
+                        warn!(
+                            "Failed to roll back configuration of {}: {}",
+                            subscriber.name(),
+                            e
+                        );
+                    }
+                }
+                return Err(Error::InvalidConfig(format!(
+                    "rejected by {}: {}",
+                    subscriber.name(),
+                    e
+                )));
+            }
+        }
+
+        *current = config.clone();
+        self.changes.send_replace(config);
+        info!("Configuration reloaded");
+
+        Ok(())
+    }
+
     /// Get a reference to the configuration
     pub async fn get_config(&self) -> FaasConfig {
         self.config.read().await.clone()
@@ -31,7 +104,8 @@ impl ConfigProvider {
     /// Update the configuration
     pub async fn update_config(&self, config: FaasConfig) {
         let mut config_lock = self.config.write().await;
-        *config_lock = config;
+        *config_lock = config.clone();
+        self.changes.send_replace(config);
     }
 
     /// Get a specific configuration value
@@ -50,7 +124,9 @@ impl ConfigProvider {
         F: FnOnce(&mut FaasConfig) -> Result<()>,
     {
         let mut config = self.config.write().await;
-        updater(&mut config)
+        updater(&mut config)?;
+        self.changes.send_replace(config.clone());
+        Ok(())
     }
 }
 
@@ -77,3 +153,103 @@ pub fn get_global_provider() -> Result<Arc<ConfigProvider>> {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    /// Subscriber recording the API port of every configuration it applies
+    struct PortSubscriber {
+        name: String,
+        rejected_port: Option<u16>,
+        applied: Mutex<Vec<u16>>,
+    }
+
+    impl PortSubscriber {
+        fn new(name: &str, rejected_port: Option<u16>) -> Arc<Self> {
+            Arc::new(Self {
+                name: name.to_string(),
+                rejected_port,
+                applied: Mutex::new(Vec::new()),
+            })
+        }
+
+        fn applied(&self) -> Vec<u16> {
+            self.applied.lock().unwrap().clone()
+        }
+    }
+
+    #[async_trait]
+    impl ConfigSubscriber for PortSubscriber {
+        fn name(&self) -> &str {
+            &self.name
+        }
+
+        async fn apply(&self, config: &FaasConfig) -> Result<()> {
+            if self.rejected_port == Some(config.api.port) {
+                return Err(Error::InvalidConfig("port in use".to_string()));
+            }
+            self.applied.lock().unwrap().push(config.api.port);
+            Ok(())
+        }
+    }
+
+    fn with_port(port: u16) -> FaasConfig {
+        let mut config = FaasConfig::default();
+        config.api.port = port;
+        config
+    }
+
+    #[tokio::test]
+    async fn test_reload() {
+        let provider = ConfigProvider::new(with_port(8080));
+        let mut changes = provider.subscribe();
+        let first = PortSubscriber::new("first", None);
+        let second = PortSubscriber::new("second", None);
+        provider.add_subscriber(first.clone()).await;
+        provider.add_subscriber(second.clone()).await;
+
+        provider.reload(with_port(9090)).await.unwrap();
+        assert_eq!(provider.get(|config| config.api.port).await, 9090);
+        assert!(changes.has_changed().unwrap());
+        assert_eq!(changes.borrow_and_update().api.port, 9090);
+        assert_eq!(first.applied(), vec![9090]);
+        assert_eq!(second.applied(), vec![9090]);
+
+        // An invalid configuration never reaches the subscribers
+        assert!(matches!(
+            provider.reload(with_port(0)).await,
+            Err(Error::InvalidConfig(_))
+        ));
+        assert_eq!(first.applied(), vec![9090]);
+        assert!(!changes.has_changed().unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_reload_rollback() {
+        let provider = ConfigProvider::new(with_port(8080));
+        let mut changes = provider.subscribe();
+        let first = PortSubscriber::new("first", None);
+        let second = PortSubscriber::new("second", Some(9999));
+        let third = PortSubscriber::new("third", None);
+        for subscriber in [&first, &second, &third] {
+            provider.add_subscriber(subscriber.clone()).await;
+        }
+
+        let err = provider.reload(with_port(9999)).await.unwrap_err();
+        assert!(err.to_string().contains("rejected by second"), "{}", err);
+
+        // The subscriber that applied the rejected configuration is rolled back, the
+        // ones after the rejecting subscriber never see it
+        assert_eq!(first.applied(), vec![9999, 8080]);
+        assert!(second.applied().is_empty());
+        assert!(third.applied().is_empty());
+        assert_eq!(provider.get(|config| config.api.port).await, 8080);
+        assert!(!changes.has_changed().unwrap());
+
+        provider.reload(with_port(9090)).await.unwrap();
+        assert_eq!(third.applied(), vec![9090]);
+        assert_eq!(changes.borrow_and_update().api.port, 9090);
+    }
+}
@@ -6,6 +6,8 @@
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
+use crate::error::{Error, Result};
+
 /// Main configuration for the R3E FaaS platform
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FaasConfig {
@@ -254,6 +256,84 @@ pub struct LoggingConfig {
     pub file: Option<String>,
 }
 
+impl FaasConfig {
+    /// Validate the configuration, rejecting values no component can run with
+    pub fn validate(&self) -> Result<()> {
+        match self.general.environment.as_str() {
+            "development" | "staging" | "production" => {}
+            other => {
+                return Err(Error::InvalidConfig(format!(
+                    "general.environment must be development, staging or production, got {}",
+                    other
+                )))
+            }
+        }
+
+        match self.storage.storage_type.as_str() {
+            "memory" => {}
+            "rocksdb" if self.storage.rocksdb_path.is_some() => {}
+            "rocksdb" => {
+                return Err(Error::InvalidConfig(
+                    "storage.rocksdb_path is required for rocksdb storage".to_string(),
+                ))
+            }
+            other => {
+                return Err(Error::InvalidConfig(format!(
+                    "storage.storage_type must be memory or rocksdb, got {}",
+                    other
+                )))
+            }
+        }
+
+        if self.runtime.js.max_memory_mb == 0 {
+            return Err(Error::InvalidConfig(
+                "runtime.js.max_memory_mb must be greater than 0".to_string(),
+            ));
+        }
+        if self.runtime.js.max_execution_time_ms == 0 {
+            return Err(Error::InvalidConfig(
+                "runtime.js.max_execution_time_ms must be greater than 0".to_string(),
+            ));
+        }
+        if self
+            .runtime
+            .sandbox
+            .allowed_domains
+            .iter()
+            .any(|domain| domain.trim().is_empty())
+        {
+            return Err(Error::InvalidConfig(
+                "runtime.sandbox.allowed_domains must not contain empty domains".to_string(),
+            ));
+        }
+
+        if self.services.oracle.enabled && self.services.oracle.rate_limit == 0 {
+            return Err(Error::InvalidConfig(
+                "services.oracle.rate_limit must be greater than 0".to_string(),
+            ));
+        }
+
+        if self.api.port == 0 {
+            return Err(Error::InvalidConfig(
+                "api.port must be greater than 0".to_string(),
+            ));
+        }
+        if self.api.enable_auth && self.api.jwt_secret.is_none() {
+            return Err(Error::InvalidConfig(
+                "api.jwt_secret is required when authentication is enabled".to_string(),
+            ));
+        }
+
+        match self.logging.level.as_str() {
+            "trace" | "debug" | "info" | "warn" | "error" => Ok(()),
+            other => Err(Error::InvalidConfig(format!(
+                "logging.level must be trace, debug, info, warn or error, got {}",
+                other
+            ))),
+        }
+    }
+}
+
 impl Default for FaasConfig {
     fn default() -> Self {
         Self {
@@ -430,3 +510,61 @@ impl Default for LoggingConfig {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate() {
+        FaasConfig::default().validate().unwrap();
+
+        let mut rocksdb = FaasConfig::default();
+        rocksdb.storage.storage_type = "rocksdb".to_string();
+        rocksdb.storage.rocksdb_path = Some("./data/rocksdb".to_string());
+        rocksdb.validate().unwrap();
+
+        let invalid: Vec<(&str, fn(&mut FaasConfig))> = vec![
+            ("general.environment", |c| {
+                c.general.environment = "test".to_string()
+            }),
+            ("storage.rocksdb_path", |c| {
+                c.storage.storage_type = "rocksdb".to_string()
+            }),
+            ("storage.storage_type", |c| {
+                c.storage.storage_type = "sled".to_string()
+            }),
+            ("runtime.js.max_memory_mb", |c| {
+                c.runtime.js.max_memory_mb = 0
+            }),
+            ("runtime.js.max_execution_time_ms", |c| {
+                c.runtime.js.max_execution_time_ms = 0
+            }),
+            ("runtime.sandbox.allowed_domains", |c| {
+                c.runtime.sandbox.allowed_domains = vec![" ".to_string()]
+            }),
+            ("services.oracle.rate_limit", |c| {
+                c.services.oracle.rate_limit = 0
+            }),
+            ("api.port", |c| c.api.port = 0),
+            ("api.jwt_secret", |c| c.api.enable_auth = true),
+            ("logging.level", |c| c.logging.level = "verbose".to_string()),
+        ];
+        for (field, update) in invalid {
+            let mut config = FaasConfig::default();
+            update(&mut config);
+            match config.validate() {
+                Err(Error::InvalidConfig(message)) => {
+                    assert!(message.starts_with(field), "{}", message)
+                }
+                other => panic!("{} accepted: {:?}", field, other.map(|_| ())),
+            }
+        }
+
+        // The oracle rate limit only matters when the oracle is enabled
+        let mut oracle = FaasConfig::default();
+        oracle.services.oracle.enabled = false;
+        oracle.services.oracle.rate_limit = 0;
+        oracle.validate().unwrap();
+    }
+}
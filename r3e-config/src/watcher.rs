@@ -0,0 +1,229 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+//! Configuration hot-reload.
+
+use log::{debug, info, warn};
+use std::collections::{BTreeMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use tokio::task::JoinHandle;
+
+use crate::error::{Error, Result};
+use crate::loader::ConfigLoader;
+use crate::provider::ConfigProvider;
+
+/// Prefix of environment variables overriding the configuration
+const ENV_PREFIX: &str = "R3E_FAAS";
+
+/// Watches the configuration file, the `.env` file and `R3E_FAAS` environment variables,
+/// reloading the provider's configuration when any of them changes
+pub struct ConfigWatcher {
+    /// Configuration provider
+    provider: Arc<ConfigProvider>,
+
+    /// Configuration file
+    config_path: Option<String>,
+
+    /// Environment file
+    env_file: Option<PathBuf>,
+
+    /// Interval between checks
+    poll_interval: Duration,
+
+    /// Configuration file modification time at the last check
+    config_modified: Option<SystemTime>,
+
+    /// Environment file modification time at the last check
+    env_file_modified: Option<SystemTime>,
+
+    /// Variables set from the environment file
+    env_file_keys: HashSet<String>,
+
+    /// Overriding environment variables at the last check
+    env: BTreeMap<String, String>,
+}
+
+impl ConfigWatcher {
+    /// Create a new configuration watcher
+    pub fn new(provider: Arc<ConfigProvider>, config_path: Option<&str>) -> Self {
+        let config_path = config_path.map(|path| path.to_string());
+        let config_modified = config_path.as_deref().and_then(modified);
+
+        Self {
+            provider,
+            config_path,
+            env_file: None,
+            poll_interval: Duration::from_secs(5),
+            config_modified,
+            env_file_modified: None,
+            env_file_keys: HashSet::new(),
+            env: env_overrides(),
+        }
+    }
+
+    /// Refresh environment variables from the given `.env` file, overriding the process
+    /// environment
+    pub fn with_env_file<P: Into<PathBuf>>(mut self, path: P) -> Self {
+        // Left unset so the first check loads the file and records the keys it sets
+        self.env_file_modified = None;
+        self.env_file = Some(path.into());
+        self
+    }
+
+    /// Set the interval between checks
+    pub fn with_poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = poll_interval;
+        self
+    }
+
+    /// Check for changes every poll interval
+    pub fn spawn(mut self) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(self.poll_interval);
+            loop {
+                interval.tick().await;
+                if let Err(e) = self.check_once().await {
+                    warn!(
+                        "Configuration reload failed, keeping current configuration: {}",
+                        e
+                    );
+                }
+            }
+        })
+    }
+
+    /// Check for changes once, returning whether a new configuration was applied
+    pub async fn check_once(&mut self) -> Result<bool> {
+        if let Some(path) = self.env_file.clone() {
+            let env_file_modified = modified(&path);
+            if env_file_modified != self.env_file_modified {
+                self.env_file_modified = env_file_modified;
+                self.refresh_env_file(&path)?;
+            }
+        }
+
+        let config_modified = self.config_path.as_deref().and_then(modified);
+        let env = env_overrides();
+        if config_modified == self.config_modified && env == self.env {
+            return Ok(false);
+        }
+
+        // Remember the change even if it is rejected, so a bad configuration is reported
+        // once rather than on every check
+        self.config_modified = config_modified;
+        self.env = env;
+
+        debug!("Configuration change detected, reloading");
+        let config = ConfigLoader::load(self.config_path.as_deref())?;
+        self.provider.reload(config).await?;
+        info!("Applied reloaded configuration");
+
+        Ok(true)
+    }
+
+    fn refresh_env_file(&mut self, path: &Path) -> Result<()> {
+        let mut keys = HashSet::new();
+        if path.exists() {
+            let entries = dotenv::from_path_iter(path)
+                .map_err(|e| Error::EnvVar(format!("{}: {}", path.display(), e)))?;
+            for entry in entries {
+                let (key, value) =
+                    entry.map_err(|e| Error::EnvVar(format!("{}: {}", path.display(), e)))?;
+                std::env::set_var(&key, value);
+                keys.insert(key);
+            }
+        }
+
+        // Variables removed from the file no longer override the configuration
+        for key in self.env_file_keys.difference(&keys) {
+            std::env::remove_var(key);
+        }
+        self.env_file_keys = keys;
+
+        Ok(())
+    }
+}
+
+fn modified<P: AsRef<Path>>(path: P) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+fn env_overrides() -> BTreeMap<String, String> {
+    std::env::vars()
+        .filter(|(key, _)| key.starts_with(ENV_PREFIX))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::FaasConfig;
+    use std::fs::File;
+
+    /// Write a file and move its modification time forward, so the change is seen even
+    /// within the file system's timestamp resolution
+    fn write(path: &Path, contents: &str, generation: u64) {
+        std::fs::write(path, contents).unwrap();
+        File::options()
+            .write(true)
+            .open(path)
+            .unwrap()
+            .set_modified(SystemTime::now() + Duration::from_secs(generation * 10))
+            .unwrap();
+    }
+
+    fn config_json(port: u16, level: &str) -> String {
+        let mut config = FaasConfig::default();
+        config.api.port = port;
+        config.logging.level = level.to_string();
+        serde_json::to_string(&config).unwrap()
+    }
+
+    async fn port(provider: &ConfigProvider) -> u16 {
+        provider.get(|config| config.api.port).await
+    }
+
+    #[tokio::test]
+    async fn test_check_once() {
+        let dir = std::env::temp_dir().join(format!("r3e-config-watcher-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let config_path = dir.join("config.json");
+        let env_file = dir.join(".env");
+        write(&config_path, &config_json(8080, "info"), 0);
+
+        let provider = Arc::new(ConfigProvider::new(
+            ConfigLoader::load(config_path.to_str()).unwrap(),
+        ));
+        let mut watcher =
+            ConfigWatcher::new(provider.clone(), config_path.to_str()).with_env_file(&env_file);
+
+        // Nothing changed
+        assert!(!watcher.check_once().await.unwrap());
+
+        write(&config_path, &config_json(9090, "info"), 1);
+        assert!(watcher.check_once().await.unwrap());
+        assert_eq!(port(&provider).await, 9090);
+
+        // A rejected configuration is reported once and the current one kept
+        write(&config_path, &config_json(7070, "verbose"), 2);
+        assert!(watcher.check_once().await.is_err());
+        assert!(!watcher.check_once().await.unwrap());
+        assert_eq!(port(&provider).await, 9090);
+
+        // Variables of the environment file override the configuration file, until they
+        // are removed from it
+        write(&config_path, &config_json(9090, "info"), 3);
+        write(&env_file, "R3E_FAAS__API__PORT=6060\n", 1);
+        assert!(watcher.check_once().await.unwrap());
+        assert_eq!(port(&provider).await, 6060);
+
+        write(&env_file, "# no overrides\n", 2);
+        assert!(watcher.check_once().await.unwrap());
+        assert!(std::env::var("R3E_FAAS__API__PORT").is_err());
+        assert_eq!(port(&provider).await, 9090);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
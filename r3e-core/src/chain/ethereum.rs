@@ -0,0 +1,383 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use serde_json::{json, Value};
+
+use crate::chain::transport::invalid_response;
+use crate::chain::{
+    poll_events, Block, BlockRef, CallResult, ChainClient, ChainConfig, ChainError, ChainEvent,
+    ChainMetrics, ContractCall, EventFilter, EventSource, EventStream, JsonRpcTransport,
+    TxSimulation,
+};
+use crate::rpc_pool::RpcEndpointPool;
+
+/// JSON-RPC error code of a reverted call
+const EXECUTION_REVERTED: i64 = 3;
+
+/// Selector of `Error(string)`, the revert data of `require` and `revert` with a message
+const ERROR_SELECTOR: [u8; 4] = [0x08, 0xc3, 0x79, 0xa0];
+
+/// Selector of `Panic(uint256)`, the revert data of failed assertions and arithmetic errors
+const PANIC_SELECTOR: [u8; 4] = [0x4e, 0x48, 0x7b, 0x71];
+
+/// Ethereum JSON-RPC client
+#[derive(Clone)]
+pub struct EthereumChainClient {
+    transport: Arc<JsonRpcTransport>,
+}
+
+impl EthereumChainClient {
+    /// Create a client for an Ethereum network
+    pub fn new(config: ChainConfig) -> Self {
+        Self {
+            transport: Arc::new(JsonRpcTransport::new(config)),
+        }
+    }
+
+    /// Create a client sharing an existing endpoint pool
+    pub fn with_pool(config: ChainConfig, pool: Arc<RpcEndpointPool>) -> Self {
+        Self {
+            transport: Arc::new(JsonRpcTransport::with_pool(config, pool)),
+        }
+    }
+}
+
+#[async_trait]
+impl ChainClient for EthereumChainClient {
+    fn chain(&self) -> &str {
+        &self.transport.config().chain
+    }
+
+    fn network(&self) -> &str {
+        &self.transport.config().network
+    }
+
+    async fn block_number(&self) -> Result<u64, ChainError> {
+        let number = self.transport.request("eth_blockNumber", json!([])).await?;
+        parse_quantity("eth_blockNumber", &number)
+    }
+
+    async fn get_block(&self, block: BlockRef) -> Result<Block, ChainError> {
+        let (method, params) = match block {
+            BlockRef::Latest => ("eth_getBlockByNumber", json!(["latest", false])),
+            BlockRef::Number(number) => (
+                "eth_getBlockByNumber",
+                json!([format!("{:#x}", number), false]),
+            ),
+            BlockRef::Hash(hash) => ("eth_getBlockByHash", json!([hash, false])),
+        };
+
+        let block = self.transport.request(method, params).await?;
+        parse_block(method, &block)
+    }
+
+    async fn call_contract(&self, call: &ContractCall) -> Result<CallResult, ChainError> {
+        let data = call.data.as_ref().ok_or_else(|| {
+            ChainError::InvalidRequest(format!(
+                "Ethereum call to {} needs ABI encoded data",
+                call.method
+            ))
+        })?;
+
+        let mut tx = json!({
+            "to": call.contract,
+            "data": format!("0x{}", hex::encode(data)),
+        });
+        if let Some(sender) = &call.sender {
+            tx["from"] = json!(sender);
+        }
+
+        match self
+            .transport
+            .request("eth_call", json!([tx, "latest"]))
+            .await
+        {
+            Ok(result) => Ok(CallResult {
+                success: true,
+                result,
+                gas_used: None,
+                error: None,
+            }),
+            Err(e) => reverted(e),
+        }
+    }
+
+    async fn simulate_tx(&self, tx: &TxSimulation) -> Result<CallResult, ChainError> {
+        let mut call = json!({ "data": format!("0x{}", hex::encode(&tx.data)) });
+        if let Some(to) = &tx.to {
+            call["to"] = json!(to);
+        }
+        if let Some(from) = tx.signers.first() {
+            call["from"] = json!(from);
+        }
+
+        // eth_call returns the revert data, which eth_estimateGas does not on every node
+        let result = match self
+            .transport
+            .request("eth_call", json!([call, "latest"]))
+            .await
+        {
+            Ok(result) => result,
+            Err(e) => return reverted(e),
+        };
+        let gas = match self
+            .transport
+            .request("eth_estimateGas", json!([call]))
+            .await
+        {
+            Ok(gas) => parse_quantity("eth_estimateGas", &gas)?,
+            Err(e) => return reverted(e),
+        };
+
+        Ok(CallResult {
+            success: true,
+            result,
+            gas_used: Some(gas),
+            error: None,
+        })
+    }
+
+    async fn send_raw_tx(&self, tx: &[u8]) -> Result<String, ChainError> {
+        let tx = format!("0x{}", hex::encode(tx));
+        let hash = self
+            .transport
+            .request("eth_sendRawTransaction", json!([tx]))
+            .await?;
+
+        hash.as_str()
+            .map(String::from)
+            .ok_or_else(|| invalid_response("eth_sendRawTransaction", "missing transaction hash"))
+    }
+
+    async fn subscribe_events(&self, filter: EventFilter) -> Result<EventStream, ChainError> {
+        let poll_interval = self.transport.config().poll_interval;
+        Ok(poll_events(self.clone(), filter, poll_interval))
+    }
+
+    async fn request(&self, method: &str, params: Value) -> Result<Value, ChainError> {
+        self.transport.request(method, params).await
+    }
+
+    fn metrics(&self) -> ChainMetrics {
+        self.transport.metrics()
+    }
+}
+
+#[async_trait]
+impl EventSource for EthereumChainClient {
+    async fn block_number(&self) -> Result<u64, ChainError> {
+        ChainClient::block_number(self).await
+    }
+
+    async fn events_in_range(
+        &self,
+        from: u64,
+        to: u64,
+        filter: &EventFilter,
+    ) -> Result<Vec<ChainEvent>, ChainError> {
+        let mut query = json!({
+            "fromBlock": format!("{:#x}", from),
+            "toBlock": format!("{:#x}", to),
+        });
+        if let Some(contract) = &filter.contract {
+            query["address"] = json!(contract);
+        }
+        if let Some(event) = &filter.event {
+            query["topics"] = json!([event]);
+        }
+
+        let logs = self
+            .transport
+            .request("eth_getLogs", json!([query]))
+            .await?;
+        logs.as_array()
+            .ok_or_else(|| invalid_response("eth_getLogs", "expected an array of logs"))?
+            .iter()
+            .map(parse_log)
+            .collect()
+    }
+}
+
+/// Whether a JSON-RPC error is a reverted call rather than a failed request
+fn is_revert(code: i64, message: &str) -> bool {
+    code == EXECUTION_REVERTED || message.starts_with("execution reverted")
+}
+
+/// Failed call result of a reverted call, other errors are returned as is
+fn reverted(error: ChainError) -> Result<CallResult, ChainError> {
+    match error {
+        ChainError::Rpc {
+            code,
+            message,
+            data,
+            ..
+        } if is_revert(code, &message) => Ok(CallResult {
+            success: false,
+            result: data.clone().unwrap_or(Value::Null),
+            gas_used: None,
+            error: Some(
+                data.as_ref()
+                    .and_then(Value::as_str)
+                    .and_then(revert_reason)
+                    .unwrap_or(message),
+            ),
+        }),
+        e => Err(e),
+    }
+}
+
+/// Decode the reason of `Error(string)` and `Panic(uint256)` revert data
+fn revert_reason(data: &str) -> Option<String> {
+    let data = hex::decode(data.trim_start_matches("0x")).ok()?;
+    let (selector, args) = (data.get(..4)?, &data[4..]);
+
+    if selector == ERROR_SELECTOR {
+        let len = u64::from_be_bytes(args.get(56..64)?.try_into().ok()?) as usize;
+        let reason = args.get(64..64usize.checked_add(len)?)?;
+        String::from_utf8(reason.to_vec()).ok()
+    } else if selector == PANIC_SELECTOR {
+        let code = u64::from_be_bytes(args.get(24..32)?.try_into().ok()?);
+        Some(format!("panic code {:#x}", code))
+    } else {
+        None
+    }
+}
+
+fn parse_quantity(method: &str, value: &Value) -> Result<u64, ChainError> {
+    value
+        .as_str()
+        .and_then(|quantity| u64::from_str_radix(quantity.trim_start_matches("0x"), 16).ok())
+        .ok_or_else(|| invalid_response(method, format!("invalid quantity {}", value)))
+}
+
+fn parse_block(method: &str, block: &Value) -> Result<Block, ChainError> {
+    if block.is_null() {
+        return Err(invalid_response(method, "block not found"));
+    }
+
+    Ok(Block {
+        number: parse_quantity(method, &block["number"])?,
+        hash: block["hash"]
+            .as_str()
+            .ok_or_else(|| invalid_response(method, "invalid hash"))?
+            .to_string(),
+        timestamp: parse_quantity(method, &block["timestamp"])?,
+        transactions: block["transactions"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|tx| tx.as_str().map(String::from))
+            .collect(),
+    })
+}
+
+fn parse_log(log: &Value) -> Result<ChainEvent, ChainError> {
+    let text = |name: &str| {
+        log[name]
+            .as_str()
+            .map(String::from)
+            .ok_or_else(|| invalid_response("eth_getLogs", format!("invalid {}", name)))
+    };
+
+    Ok(ChainEvent {
+        block_number: parse_quantity("eth_getLogs", &log["blockNumber"])?,
+        tx_hash: text("transactionHash")?,
+        contract: text("address")?,
+        event: log["topics"][0].as_str().unwrap_or_default().to_string(),
+        data: json!({
+            "topics": log["topics"],
+            "data": log["data"],
+            "log_index": log["logIndex"],
+        }),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_block_and_log() {
+        let block = parse_block(
+            "eth_getBlockByNumber",
+            &json!({
+                "number": "0x10",
+                "hash": "0xblock",
+                "timestamp": "0x65f0a1b2",
+                "transactions": ["0xtx1"]
+            }),
+        )
+        .unwrap();
+        assert_eq!(block.number, 16);
+        assert_eq!(block.timestamp, 0x65f0a1b2);
+        assert_eq!(block.transactions, vec!["0xtx1"]);
+
+        assert!(parse_block("eth_getBlockByNumber", &Value::Null).is_err());
+
+        let event = parse_log(&json!({
+            "blockNumber": "0x11",
+            "transactionHash": "0xtx2",
+            "address": "0xcontract",
+            "topics": ["0xddf252ad", "0x01"],
+            "data": "0x",
+            "logIndex": "0x0"
+        }))
+        .unwrap();
+        assert_eq!(event.block_number, 17);
+        assert_eq!(event.event, "0xddf252ad");
+        assert_eq!(event.data["topics"][1], "0x01");
+    }
+
+    #[test]
+    fn test_revert_reason() {
+        // Error("insufficient balance")
+        let error = concat!(
+            "0x08c379a0",
+            "0000000000000000000000000000000000000000000000000000000000000020",
+            "0000000000000000000000000000000000000000000000000000000000000014",
+            "696e73756666696369656e742062616c616e6365000000000000000000000000"
+        );
+        assert_eq!(
+            revert_reason(error).as_deref(),
+            Some("insufficient balance")
+        );
+
+        // Panic(0x11), an arithmetic overflow
+        let panic = concat!(
+            "0x4e487b71",
+            "0000000000000000000000000000000000000000000000000000000000000011"
+        );
+        assert_eq!(revert_reason(panic).as_deref(), Some("panic code 0x11"));
+
+        assert_eq!(revert_reason("0xdeadbeef"), None);
+        assert_eq!(revert_reason(&error[..80]), None);
+
+        let result = reverted(ChainError::Rpc {
+            method: "eth_call".to_string(),
+            code: 3,
+            message: "execution reverted".to_string(),
+            data: Some(json!(error)),
+        })
+        .unwrap();
+        assert!(!result.success);
+        assert_eq!(result.error.as_deref(), Some("insufficient balance"));
+
+        assert!(reverted(ChainError::Rpc {
+            method: "eth_call".to_string(),
+            code: -32000,
+            message: "header not found".to_string(),
+            data: None,
+        })
+        .is_err());
+    }
+
+    #[test]
+    fn test_is_revert() {
+        assert!(is_revert(3, "execution reverted: insufficient balance"));
+        assert!(is_revert(-32000, "execution reverted"));
+        assert!(!is_revert(-32000, "header not found"));
+    }
+}
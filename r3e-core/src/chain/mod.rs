@@ -0,0 +1,570 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+//! Provider-agnostic blockchain RPC clients.
+//!
+//! [`ChainClient`] covers the calls the platform makes to any chain: reading blocks, calling
+//! contracts, broadcasting signed transactions and following contract events. Clients talk
+//! JSON-RPC over an [`RpcEndpointPool`](crate::rpc_pool::RpcEndpointPool), so failover, retries and per-method metrics behave
+//! the same on every chain. [`ChainClients`] builds the clients from the environment and
+//! shares them per chain and network, so endpoint health is tracked across callers.
+
+mod ethereum;
+mod neo;
+mod transport;
+
+use std::collections::{HashMap, VecDeque};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use futures::Stream;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use thiserror::Error;
+
+use crate::rpc_pool::{EndpointMetrics, RpcPoolConfig, RpcPoolError};
+
+pub use ethereum::EthereumChainClient;
+pub use neo::{stack_item_bytes, stack_item_integer, NeoChainClient};
+pub use transport::{JsonRpcTransport, MethodMetrics};
+
+/// Largest block range scanned for events in one request
+const MAX_EVENT_BLOCK_RANGE: u64 = 100;
+
+/// Chain client error
+#[derive(Debug, Error)]
+pub enum ChainError {
+    /// No endpoint could serve the request
+    #[error(transparent)]
+    Pool(#[from] RpcPoolError),
+
+    /// The node rejected the request
+    #[error("chain: {method} failed with code {code}: {message}")]
+    Rpc {
+        /// JSON-RPC method
+        method: String,
+        /// JSON-RPC error code
+        code: i64,
+        /// JSON-RPC error message
+        message: String,
+        /// JSON-RPC error data, such as the return data of a reverted call
+        data: Option<Value>,
+    },
+
+    /// The node returned a response that could not be understood
+    #[error("chain: invalid response to {method}: {message}")]
+    InvalidResponse {
+        /// JSON-RPC method
+        method: String,
+        /// What was wrong with the response
+        message: String,
+    },
+
+    /// The request is invalid for the chain
+    #[error("chain: invalid request: {0}")]
+    InvalidRequest(String),
+
+    /// The chain or network is not supported
+    #[error("chain: unsupported {0}")]
+    Unsupported(String),
+}
+
+/// Block to read
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BlockRef {
+    /// The latest block
+    Latest,
+    /// Block at a height
+    Number(u64),
+    /// Block with a hash
+    Hash(String),
+}
+
+/// Block header and transaction hashes
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Block {
+    /// Block height
+    pub number: u64,
+
+    /// Block hash
+    pub hash: String,
+
+    /// Block timestamp (secs since epoch)
+    pub timestamp: u64,
+
+    /// Hashes of the transactions in the block
+    pub transactions: Vec<String>,
+}
+
+/// Read-only contract call
+///
+/// Neo invokes `method` with `params`, which are plain JSON values or typed contract
+/// parameters such as `{"type": "Hash160", "value": "0x..."}`. Ethereum sends the ABI
+/// encoded `data` and ignores `params`, as calldata encoding needs the contract ABI.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ContractCall {
+    /// Contract hash or address
+    pub contract: String,
+
+    /// Contract method
+    pub method: String,
+
+    /// Method parameters
+    #[serde(default)]
+    pub params: Vec<Value>,
+
+    /// ABI encoded calldata
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data: Option<Vec<u8>>,
+
+    /// Account the call is made from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sender: Option<String>,
+}
+
+/// Result of a read-only contract call
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CallResult {
+    /// Whether the call completed without faulting or reverting
+    pub success: bool,
+
+    /// Returned value: the result stack on Neo, the hex encoded return data on Ethereum
+    pub result: Value,
+
+    /// Gas consumed by the call, in the smallest unit of the chain
+    pub gas_used: Option<u64>,
+
+    /// Exception or revert reason of a failed call
+    pub error: Option<String>,
+}
+
+/// Transaction payload to run without broadcasting it
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TxSimulation {
+    /// Accounts signing the transaction, the first one is the sender on Ethereum
+    #[serde(default)]
+    pub signers: Vec<String>,
+
+    /// Called contract, unused on Neo where the script names the contracts it calls
+    #[serde(default)]
+    pub to: Option<String>,
+
+    /// Invocation script on Neo, calldata on Ethereum
+    pub data: Vec<u8>,
+}
+
+/// Contract events to follow
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct EventFilter {
+    /// Contract emitting the events, any contract if not set
+    #[serde(default)]
+    pub contract: Option<String>,
+
+    /// Event name on Neo, hex encoded first topic on Ethereum, any event if not set
+    #[serde(default)]
+    pub event: Option<String>,
+
+    /// First block to scan, the next block if not set
+    #[serde(default)]
+    pub from_block: Option<u64>,
+}
+
+/// Contract event
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChainEvent {
+    /// Height of the block holding the transaction
+    pub block_number: u64,
+
+    /// Hash of the transaction emitting the event
+    pub tx_hash: String,
+
+    /// Contract emitting the event
+    pub contract: String,
+
+    /// Event name on Neo, first topic on Ethereum
+    pub event: String,
+
+    /// Event payload: the notification state on Neo, the topics and data on Ethereum
+    pub data: Value,
+}
+
+/// Stream of contract events
+pub type EventStream = Pin<Box<dyn Stream<Item = Result<ChainEvent, ChainError>> + Send>>;
+
+/// Request and endpoint metrics of a chain client
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChainMetrics {
+    /// Chain
+    pub chain: String,
+
+    /// Network
+    pub network: String,
+
+    /// Metrics per JSON-RPC method
+    pub methods: HashMap<String, MethodMetrics>,
+
+    /// Metrics per endpoint, in priority order
+    pub endpoints: Vec<EndpointMetrics>,
+}
+
+/// Blockchain RPC client
+#[async_trait]
+pub trait ChainClient: Send + Sync {
+    /// Chain of the client, e.g. `neo` or `ethereum`
+    fn chain(&self) -> &str;
+
+    /// Network of the client, e.g. `mainnet`
+    fn network(&self) -> &str;
+
+    /// Height of the latest block
+    async fn block_number(&self) -> Result<u64, ChainError>;
+
+    /// Read a block
+    async fn get_block(&self, block: BlockRef) -> Result<Block, ChainError>;
+
+    /// Call a contract without changing state
+    async fn call_contract(&self, call: &ContractCall) -> Result<CallResult, ChainError>;
+
+    /// Run a transaction payload against the latest state without broadcasting it,
+    /// returning its result, the gas it would consume and why it would fail
+    async fn simulate_tx(&self, tx: &TxSimulation) -> Result<CallResult, ChainError>;
+
+    /// Broadcast a signed transaction, returning its hash.
+    ///
+    /// Safe to retry, a node receiving the same transaction twice only relays it once.
+    async fn send_raw_tx(&self, tx: &[u8]) -> Result<String, ChainError>;
+
+    /// Follow contract events, polling for new blocks
+    async fn subscribe_events(&self, filter: EventFilter) -> Result<EventStream, ChainError>;
+
+    /// Send a JSON-RPC request for calls not covered by the trait
+    async fn request(&self, method: &str, params: Value) -> Result<Value, ChainError>;
+
+    /// Request and endpoint metrics
+    fn metrics(&self) -> ChainMetrics;
+}
+
+/// Endpoints and retry policy of a chain network
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChainConfig {
+    /// Chain, `neo` or `ethereum`
+    pub chain: String,
+
+    /// Network, e.g. `mainnet` or `testnet`
+    pub network: String,
+
+    /// RPC endpoint URLs, in priority order
+    pub rpc_urls: Vec<String>,
+
+    /// Circuit breaker and timeout settings of the endpoints
+    #[serde(default)]
+    pub pool: RpcPoolConfig,
+
+    /// Retries after every endpoint failed a request
+    #[serde(default = "default_max_retries")]
+    pub max_retries: u32,
+
+    /// Delay before the first retry, doubled for every following retry
+    #[serde(default = "default_retry_backoff", with = "duration_millis")]
+    pub retry_backoff: Duration,
+
+    /// Interval of polling for new blocks when following events
+    #[serde(default = "default_poll_interval", with = "duration_millis")]
+    pub poll_interval: Duration,
+}
+
+fn default_max_retries() -> u32 {
+    2
+}
+
+fn default_retry_backoff() -> Duration {
+    Duration::from_millis(500)
+}
+
+fn default_poll_interval() -> Duration {
+    Duration::from_secs(5)
+}
+
+mod duration_millis {
+    use serde::{Deserialize, Deserializer, Serializer};
+    use std::time::Duration;
+
+    pub fn serialize<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u64(duration.as_millis() as u64)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+        u64::deserialize(deserializer).map(Duration::from_millis)
+    }
+}
+
+/// Default Neo N3 mainnet RPC endpoints
+pub const DEFAULT_NEO_MAINNET_RPC_URLS: &[&str] =
+    &["http://seed1.neo.org:10332", "http://seed2.neo.org:10332"];
+
+/// Default Neo N3 testnet RPC endpoints
+pub const DEFAULT_NEO_TESTNET_RPC_URLS: &[&str] = &[
+    "http://seed1t5.neo.org:20332",
+    "http://seed2t5.neo.org:20332",
+];
+
+impl ChainConfig {
+    /// Create a configuration with default retry settings
+    pub fn new(
+        chain: impl Into<String>,
+        network: impl Into<String>,
+        rpc_urls: impl IntoIterator<Item = impl Into<String>>,
+    ) -> Self {
+        Self {
+            chain: chain.into(),
+            network: network.into(),
+            rpc_urls: rpc_urls.into_iter().map(Into::into).collect(),
+            pool: RpcPoolConfig::default(),
+            max_retries: default_max_retries(),
+            retry_backoff: default_retry_backoff(),
+            poll_interval: default_poll_interval(),
+        }
+    }
+
+    /// Configuration of a chain network from the environment.
+    ///
+    /// Endpoints are read from the comma separated `<CHAIN>_RPC_URLS` on mainnet and
+    /// `<CHAIN>_<NETWORK>_RPC_URLS` on other networks, where `CHAIN` is `NEO` or `ETH`.
+    /// Neo falls back to the public seed nodes, Ethereum mainnet to the single `ETH_RPC_URL`.
+    pub fn from_env(chain: &str, network: &str) -> Result<Self, ChainError> {
+        let (prefix, defaults): (&str, Vec<String>) = match (chain, network) {
+            ("neo", "mainnet") => ("NEO", to_strings(DEFAULT_NEO_MAINNET_RPC_URLS)),
+            ("neo", "testnet") => ("NEO", to_strings(DEFAULT_NEO_TESTNET_RPC_URLS)),
+            ("neo", _) => ("NEO", Vec::new()),
+            ("ethereum", "mainnet") => ("ETH", std::env::var("ETH_RPC_URL").into_iter().collect()),
+            ("ethereum", _) => ("ETH", Vec::new()),
+            _ => return Err(ChainError::Unsupported(format!("chain {}", chain))),
+        };
+
+        let var = if network == "mainnet" {
+            format!("{}_RPC_URLS", prefix)
+        } else {
+            format!("{}_{}_RPC_URLS", prefix, network.to_uppercase())
+        };
+        let urls: Vec<String> = std::env::var(&var)
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|url| !url.is_empty())
+            .map(String::from)
+            .collect();
+
+        let urls = if urls.is_empty() { defaults } else { urls };
+        if urls.is_empty() {
+            return Err(ChainError::Unsupported(format!(
+                "{} network {}, set {}",
+                chain, network, var
+            )));
+        }
+
+        Ok(Self::new(chain, network, urls))
+    }
+}
+
+fn to_strings(urls: &[&str]) -> Vec<String> {
+    urls.iter().map(|url| url.to_string()).collect()
+}
+
+/// Create a client for a chain network
+pub fn connect(config: ChainConfig) -> Result<Arc<dyn ChainClient>, ChainError> {
+    match config.chain.as_str() {
+        "neo" => Ok(Arc::new(NeoChainClient::new(config))),
+        "ethereum" => Ok(Arc::new(EthereumChainClient::new(config))),
+        chain => Err(ChainError::Unsupported(format!("chain {}", chain))),
+    }
+}
+
+/// Chain clients shared per chain and network
+#[derive(Default)]
+pub struct ChainClients {
+    clients: Mutex<HashMap<(String, String), Arc<dyn ChainClient>>>,
+}
+
+impl ChainClients {
+    /// Create an empty set of clients, connected from the environment on first use
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Use a client for its chain and network instead of connecting from the environment
+    pub fn insert(&self, client: Arc<dyn ChainClient>) {
+        let key = (client.chain().to_string(), client.network().to_string());
+        self.clients.lock().unwrap().insert(key, client);
+    }
+
+    /// Client of a chain network, connected from the environment on first use
+    pub fn get(&self, chain: &str, network: &str) -> Result<Arc<dyn ChainClient>, ChainError> {
+        let mut clients = self.clients.lock().unwrap();
+        let key = (chain.to_string(), network.to_string());
+        if let Some(client) = clients.get(&key) {
+            return Ok(Arc::clone(client));
+        }
+
+        let client = connect(ChainConfig::from_env(chain, network)?)?;
+        clients.insert(key, Arc::clone(&client));
+        Ok(client)
+    }
+
+    /// Metrics of all connected clients
+    pub fn metrics(&self) -> Vec<ChainMetrics> {
+        self.clients
+            .lock()
+            .unwrap()
+            .values()
+            .map(|client| client.metrics())
+            .collect()
+    }
+}
+
+/// Block scanning used to follow events by polling
+#[async_trait]
+trait EventSource: Clone + Send + Sync + 'static {
+    async fn block_number(&self) -> Result<u64, ChainError>;
+
+    async fn events_in_range(
+        &self,
+        from: u64,
+        to: u64,
+        filter: &EventFilter,
+    ) -> Result<Vec<ChainEvent>, ChainError>;
+}
+
+struct PollState<S> {
+    source: S,
+    filter: EventFilter,
+    poll_interval: Duration,
+    next_block: Option<u64>,
+    pending: VecDeque<ChainEvent>,
+}
+
+/// Follow events by scanning new blocks every poll interval. Errors are yielded after
+/// waiting one interval, and the scan resumes from the block that failed.
+fn poll_events<S: EventSource>(
+    source: S,
+    filter: EventFilter,
+    poll_interval: Duration,
+) -> EventStream {
+    let state = PollState {
+        source,
+        next_block: filter.from_block,
+        filter,
+        poll_interval,
+        pending: VecDeque::new(),
+    };
+
+    Box::pin(futures::stream::unfold(state, |mut state| async move {
+        loop {
+            if let Some(event) = state.pending.pop_front() {
+                return Some((Ok(event), state));
+            }
+
+            let head = match state.source.block_number().await {
+                Ok(head) => head,
+                Err(e) => {
+                    tokio::time::sleep(state.poll_interval).await;
+                    return Some((Err(e), state));
+                }
+            };
+            let from = *state.next_block.get_or_insert(head + 1);
+            if from > head {
+                tokio::time::sleep(state.poll_interval).await;
+                continue;
+            }
+
+            let to = head.min(from + MAX_EVENT_BLOCK_RANGE - 1);
+            match state.source.events_in_range(from, to, &state.filter).await {
+                Ok(events) => {
+                    state.pending.extend(events);
+                    state.next_block = Some(to + 1);
+                }
+                Err(e) => {
+                    tokio::time::sleep(state.poll_interval).await;
+                    return Some((Err(e), state));
+                }
+            }
+        }
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::StreamExt;
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    #[derive(Clone)]
+    struct FakeChain {
+        head: Arc<AtomicU64>,
+    }
+
+    #[async_trait]
+    impl EventSource for FakeChain {
+        async fn block_number(&self) -> Result<u64, ChainError> {
+            Ok(self.head.load(Ordering::SeqCst))
+        }
+
+        async fn events_in_range(
+            &self,
+            from: u64,
+            to: u64,
+            filter: &EventFilter,
+        ) -> Result<Vec<ChainEvent>, ChainError> {
+            Ok((from..=to)
+                .map(|block_number| ChainEvent {
+                    block_number,
+                    tx_hash: format!("0x{:02x}", block_number),
+                    contract: filter.contract.clone().unwrap_or_default(),
+                    event: "Transfer".to_string(),
+                    data: Value::Null,
+                })
+                .collect())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_poll_events_resumes_after_last_block() {
+        let head = Arc::new(AtomicU64::new(3));
+        let filter = EventFilter {
+            contract: Some("0xcontract".to_string()),
+            from_block: Some(2),
+            ..EventFilter::default()
+        };
+        let mut events = poll_events(
+            FakeChain { head: head.clone() },
+            filter,
+            Duration::from_millis(1),
+        );
+
+        assert_eq!(events.next().await.unwrap().unwrap().block_number, 2);
+        assert_eq!(events.next().await.unwrap().unwrap().block_number, 3);
+
+        head.store(4, Ordering::SeqCst);
+        let event = events.next().await.unwrap().unwrap();
+        assert_eq!(event.block_number, 4);
+        assert_eq!(event.contract, "0xcontract");
+    }
+
+    #[test]
+    fn test_config_from_env() {
+        let config = ChainConfig::from_env("neo", "testnet").unwrap();
+        assert_eq!(config.chain, "neo");
+        assert!(!config.rpc_urls.is_empty());
+
+        assert!(matches!(
+            ChainConfig::from_env("ethereum", "holesky"),
+            Err(ChainError::Unsupported(_))
+        ));
+        assert!(matches!(
+            ChainConfig::from_env("solana", "mainnet"),
+            Err(ChainError::Unsupported(_))
+        ));
+    }
+}
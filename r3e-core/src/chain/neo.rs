@@ -0,0 +1,406 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use base64::Engine;
+use serde_json::{json, Value};
+
+use crate::chain::transport::invalid_response;
+use crate::chain::{
+    poll_events, Block, BlockRef, CallResult, ChainClient, ChainConfig, ChainError, ChainEvent,
+    ChainMetrics, ContractCall, EventFilter, EventSource, EventStream, JsonRpcTransport,
+    TxSimulation,
+};
+use crate::rpc_pool::RpcEndpointPool;
+
+/// Neo N3 JSON-RPC client
+#[derive(Clone)]
+pub struct NeoChainClient {
+    transport: Arc<JsonRpcTransport>,
+}
+
+impl NeoChainClient {
+    /// Create a client for a Neo network
+    pub fn new(config: ChainConfig) -> Self {
+        Self {
+            transport: Arc::new(JsonRpcTransport::new(config)),
+        }
+    }
+
+    /// Create a client sharing an existing endpoint pool
+    pub fn with_pool(config: ChainConfig, pool: Arc<RpcEndpointPool>) -> Self {
+        Self {
+            transport: Arc::new(JsonRpcTransport::with_pool(config, pool)),
+        }
+    }
+
+    /// Notifications of a transaction
+    async fn transaction_events(
+        &self,
+        block_number: u64,
+        tx_hash: &str,
+        filter: &EventFilter,
+    ) -> Result<Vec<ChainEvent>, ChainError> {
+        let log = self
+            .transport
+            .request("getapplicationlog", json!([tx_hash]))
+            .await?;
+
+        let notifications = log
+            .get("executions")
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+            .filter_map(|execution| execution.get("notifications").and_then(Value::as_array))
+            .flatten();
+
+        let mut events = Vec::new();
+        for notification in notifications {
+            let contract = notification
+                .get("contract")
+                .and_then(Value::as_str)
+                .unwrap_or_default();
+            let event = notification
+                .get("eventname")
+                .and_then(Value::as_str)
+                .unwrap_or_default();
+            let contract_matches = filter
+                .contract
+                .as_deref()
+                .map_or(true, |wanted| same_script_hash(wanted, contract));
+            let event_matches = filter
+                .event
+                .as_deref()
+                .map_or(true, |wanted| wanted == event);
+
+            if contract_matches && event_matches {
+                events.push(ChainEvent {
+                    block_number,
+                    tx_hash: tx_hash.to_string(),
+                    contract: contract.to_string(),
+                    event: event.to_string(),
+                    data: notification.get("state").cloned().unwrap_or(Value::Null),
+                });
+            }
+        }
+
+        Ok(events)
+    }
+}
+
+#[async_trait]
+impl ChainClient for NeoChainClient {
+    fn chain(&self) -> &str {
+        &self.transport.config().chain
+    }
+
+    fn network(&self) -> &str {
+        &self.transport.config().network
+    }
+
+    async fn block_number(&self) -> Result<u64, ChainError> {
+        let count = self.transport.request("getblockcount", json!([])).await?;
+        count
+            .as_u64()
+            .and_then(|count| count.checked_sub(1))
+            .ok_or_else(|| invalid_response("getblockcount", "expected a positive block count"))
+    }
+
+    async fn get_block(&self, block: BlockRef) -> Result<Block, ChainError> {
+        let id = match block {
+            BlockRef::Latest => json!(ChainClient::block_number(self).await?),
+            BlockRef::Number(number) => json!(number),
+            BlockRef::Hash(hash) => json!(hash),
+        };
+
+        let block = self
+            .transport
+            .request("getblock", json!([id, true]))
+            .await?;
+        parse_block(&block)
+    }
+
+    async fn call_contract(&self, call: &ContractCall) -> Result<CallResult, ChainError> {
+        let params = call
+            .params
+            .iter()
+            .map(contract_parameter)
+            .collect::<Result<Vec<_>, _>>()?;
+        let signers = signers(call.sender.iter());
+
+        let result = self
+            .transport
+            .request(
+                "invokefunction",
+                json!([call.contract, call.method, params, signers]),
+            )
+            .await?;
+        parse_invocation("invokefunction", &result)
+    }
+
+    async fn simulate_tx(&self, tx: &TxSimulation) -> Result<CallResult, ChainError> {
+        let script = base64::engine::general_purpose::STANDARD.encode(&tx.data);
+        let signers = signers(tx.signers.iter());
+
+        let result = self
+            .transport
+            .request("invokescript", json!([script, signers]))
+            .await?;
+        parse_invocation("invokescript", &result)
+    }
+
+    async fn send_raw_tx(&self, tx: &[u8]) -> Result<String, ChainError> {
+        let tx = base64::engine::general_purpose::STANDARD.encode(tx);
+        let result = self
+            .transport
+            .request("sendrawtransaction", json!([tx]))
+            .await?;
+
+        result
+            .get("hash")
+            .and_then(Value::as_str)
+            .map(String::from)
+            .ok_or_else(|| invalid_response("sendrawtransaction", "missing transaction hash"))
+    }
+
+    async fn subscribe_events(&self, filter: EventFilter) -> Result<EventStream, ChainError> {
+        let poll_interval = self.transport.config().poll_interval;
+        Ok(poll_events(self.clone(), filter, poll_interval))
+    }
+
+    async fn request(&self, method: &str, params: Value) -> Result<Value, ChainError> {
+        self.transport.request(method, params).await
+    }
+
+    fn metrics(&self) -> ChainMetrics {
+        self.transport.metrics()
+    }
+}
+
+#[async_trait]
+impl EventSource for NeoChainClient {
+    async fn block_number(&self) -> Result<u64, ChainError> {
+        ChainClient::block_number(self).await
+    }
+
+    async fn events_in_range(
+        &self,
+        from: u64,
+        to: u64,
+        filter: &EventFilter,
+    ) -> Result<Vec<ChainEvent>, ChainError> {
+        let mut events = Vec::new();
+        for number in from..=to {
+            let block = self.get_block(BlockRef::Number(number)).await?;
+            for tx_hash in &block.transactions {
+                events.extend(self.transaction_events(number, tx_hash, filter).await?);
+            }
+        }
+        Ok(events)
+    }
+}
+
+/// Integer value of a `Integer` or `Boolean` stack item
+pub fn stack_item_integer(item: &Value) -> Option<i128> {
+    match item.get("type")?.as_str()? {
+        "Integer" => item.get("value")?.as_str()?.parse().ok(),
+        "Boolean" => item.get("value")?.as_bool().map(i128::from),
+        _ => None,
+    }
+}
+
+/// Bytes of a `ByteString` or `Buffer` stack item
+pub fn stack_item_bytes(item: &Value) -> Option<Vec<u8>> {
+    match item.get("type")?.as_str()? {
+        "ByteString" | "Buffer" => base64::engine::general_purpose::STANDARD
+            .decode(item.get("value")?.as_str()?)
+            .ok(),
+        _ => None,
+    }
+}
+
+/// Signers witnessing calls made by the entry script
+fn signers<'a>(accounts: impl Iterator<Item = &'a String>) -> Vec<Value> {
+    accounts
+        .map(|account| json!({ "account": account, "scopes": "CalledByEntry" }))
+        .collect()
+}
+
+/// Whether two script hashes are equal, ignoring case and the `0x` prefix
+fn same_script_hash(a: &str, b: &str) -> bool {
+    let strip = |hash: &str| hash.trim_start_matches("0x").to_lowercase();
+    strip(a) == strip(b)
+}
+
+/// Convert a JSON value to a contract parameter. Typed parameters are passed as is.
+fn contract_parameter(value: &Value) -> Result<Value, ChainError> {
+    let parameter = match value {
+        Value::Null => json!({ "type": "Any" }),
+        Value::Bool(b) => json!({ "type": "Boolean", "value": b }),
+        Value::Number(n) if n.is_i64() || n.is_u64() => {
+            json!({ "type": "Integer", "value": n.to_string() })
+        }
+        Value::Number(n) => {
+            return Err(ChainError::InvalidRequest(format!(
+                "Neo parameters must be integers, got {}",
+                n
+            )))
+        }
+        Value::String(s) => json!({ "type": "String", "value": s }),
+        Value::Array(items) => json!({
+            "type": "Array",
+            "value": items.iter().map(contract_parameter).collect::<Result<Vec<_>, _>>()?,
+        }),
+        Value::Object(object) if object.contains_key("type") => value.clone(),
+        Value::Object(_) => {
+            return Err(ChainError::InvalidRequest(
+                "Neo object parameters need a type".to_string(),
+            ))
+        }
+    };
+    Ok(parameter)
+}
+
+fn parse_block(block: &Value) -> Result<Block, ChainError> {
+    let field = |name: &str| {
+        block
+            .get(name)
+            .ok_or_else(|| invalid_response("getblock", format!("missing {}", name)))
+    };
+
+    Ok(Block {
+        number: field("index")?
+            .as_u64()
+            .ok_or_else(|| invalid_response("getblock", "invalid index"))?,
+        hash: field("hash")?
+            .as_str()
+            .ok_or_else(|| invalid_response("getblock", "invalid hash"))?
+            .to_string(),
+        // Neo block times are in milliseconds
+        timestamp: field("time")?
+            .as_u64()
+            .ok_or_else(|| invalid_response("getblock", "invalid time"))?
+            / 1000,
+        transactions: block
+            .get("tx")
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+            .filter_map(|tx| tx.get("hash").and_then(Value::as_str).map(String::from))
+            .collect(),
+    })
+}
+
+fn parse_invocation(method: &str, result: &Value) -> Result<CallResult, ChainError> {
+    let state = result
+        .get("state")
+        .and_then(Value::as_str)
+        .ok_or_else(|| invalid_response(method, "missing state"))?;
+
+    Ok(CallResult {
+        success: state == "HALT",
+        result: result.get("stack").cloned().unwrap_or(Value::Null),
+        gas_used: result
+            .get("gasconsumed")
+            .and_then(Value::as_str)
+            .and_then(|gas| gas.parse().ok()),
+        error: result
+            .get("exception")
+            .and_then(Value::as_str)
+            .map(String::from)
+            .or_else(|| (state != "HALT").then(|| format!("VM state {}", state))),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_contract_parameters() {
+        let params = json!(["NEO", 42, true, null, [1], { "type": "Hash160", "value": "0xab" }]);
+        let params: Vec<Value> = params
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|value| contract_parameter(value).unwrap())
+            .collect();
+
+        assert_eq!(params[0], json!({ "type": "String", "value": "NEO" }));
+        assert_eq!(params[1], json!({ "type": "Integer", "value": "42" }));
+        assert_eq!(params[2], json!({ "type": "Boolean", "value": true }));
+        assert_eq!(params[3], json!({ "type": "Any" }));
+        assert_eq!(
+            params[4],
+            json!({ "type": "Array", "value": [{ "type": "Integer", "value": "1" }] })
+        );
+        assert_eq!(params[5], json!({ "type": "Hash160", "value": "0xab" }));
+
+        assert!(contract_parameter(&json!(1.5)).is_err());
+        assert!(contract_parameter(&json!({ "value": 1 })).is_err());
+    }
+
+    #[test]
+    fn test_parse_block_and_invocation() {
+        let block = parse_block(&json!({
+            "hash": "0xblock",
+            "index": 12,
+            "time": 1_700_000_000_123u64,
+            "tx": [{ "hash": "0xtx1" }, { "hash": "0xtx2" }]
+        }))
+        .unwrap();
+        assert_eq!(block.number, 12);
+        assert_eq!(block.timestamp, 1_700_000_000);
+        assert_eq!(block.transactions, vec!["0xtx1", "0xtx2"]);
+
+        let halted = parse_invocation(
+            "invokefunction",
+            &json!({
+            "state": "HALT",
+            "gasconsumed": "2007570",
+            "exception": null,
+            "stack": [{ "type": "Integer", "value": "100" }]
+            }),
+        )
+        .unwrap();
+        assert!(halted.success);
+        assert_eq!(halted.gas_used, Some(2007570));
+        assert_eq!(halted.error, None);
+
+        let faulted = parse_invocation(
+            "invokescript",
+            &json!({
+            "state": "FAULT",
+            "gasconsumed": "1000",
+            "exception": "ABORT is executed",
+            "stack": []
+            }),
+        )
+        .unwrap();
+        assert!(!faulted.success);
+        assert_eq!(faulted.error.as_deref(), Some("ABORT is executed"));
+    }
+
+    #[test]
+    fn test_stack_items() {
+        let integer = json!({ "type": "Integer", "value": "-7" });
+        let string = json!({ "type": "ByteString", "value": "TkVP" });
+
+        assert_eq!(stack_item_integer(&integer), Some(-7));
+        assert_eq!(
+            stack_item_integer(&json!({ "type": "Boolean", "value": true })),
+            Some(1)
+        );
+        assert_eq!(stack_item_integer(&string), None);
+        assert_eq!(stack_item_bytes(&string), Some(b"NEO".to_vec()));
+        assert_eq!(stack_item_bytes(&integer), None);
+    }
+
+    #[test]
+    fn test_same_script_hash() {
+        assert!(same_script_hash("0xABcd", "abcd"));
+        assert!(!same_script_hash("0xabcd", "0xabce"));
+    }
+}
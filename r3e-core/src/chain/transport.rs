@@ -0,0 +1,214 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::chain::{ChainConfig, ChainError, ChainMetrics};
+use crate::rpc_pool::RpcEndpointPool;
+
+/// Metrics of a JSON-RPC method
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MethodMetrics {
+    /// Total requests
+    pub requests: u64,
+
+    /// Requests failing after all retries, or rejected by the node
+    pub failures: u64,
+
+    /// Retries after every endpoint failed
+    pub retries: u64,
+
+    /// Total latency of completed requests, in milliseconds
+    pub total_latency_ms: f64,
+}
+
+#[derive(Deserialize)]
+struct RpcResponse {
+    #[serde(default)]
+    result: Option<Value>,
+    #[serde(default)]
+    error: Option<RpcErrorObject>,
+}
+
+#[derive(Deserialize)]
+struct RpcErrorObject {
+    code: i64,
+    message: String,
+    #[serde(default)]
+    data: Option<Value>,
+}
+
+/// JSON-RPC over HTTP to a pool of endpoints.
+///
+/// Transport errors fail over to the next endpoint and, once every endpoint failed, are
+/// retried with exponential backoff. Errors returned by a node are final and neither fail
+/// over nor count against the endpoint.
+#[derive(Debug)]
+pub struct JsonRpcTransport {
+    config: ChainConfig,
+    pool: Arc<RpcEndpointPool>,
+    http: reqwest::Client,
+    next_id: AtomicU64,
+    methods: Mutex<HashMap<String, MethodMetrics>>,
+}
+
+impl JsonRpcTransport {
+    /// Create a transport to the endpoints of a chain network
+    pub fn new(config: ChainConfig) -> Self {
+        let pool = Arc::new(RpcEndpointPool::new(
+            format!("{}/{}", config.chain, config.network),
+            config.rpc_urls.clone(),
+            config.pool.clone(),
+        ));
+        Self::with_pool(config, pool)
+    }
+
+    /// Create a transport sharing an existing endpoint pool
+    pub fn with_pool(config: ChainConfig, pool: Arc<RpcEndpointPool>) -> Self {
+        Self {
+            config,
+            pool,
+            http: reqwest::Client::new(),
+            next_id: AtomicU64::new(1),
+            methods: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Chain network configuration
+    pub fn config(&self) -> &ChainConfig {
+        &self.config
+    }
+
+    /// Endpoint pool
+    pub fn pool(&self) -> &Arc<RpcEndpointPool> {
+        &self.pool
+    }
+
+    /// Send a request, returning its result
+    pub async fn request(&self, method: &str, params: Value) -> Result<Value, ChainError> {
+        let started = Instant::now();
+        let mut retries = 0;
+
+        let result = loop {
+            let body = serde_json::json!({
+                "jsonrpc": "2.0",
+                "id": self.next_id.fetch_add(1, Ordering::Relaxed),
+                "method": method,
+                "params": params,
+            });
+            let (http, body) = (&self.http, &body);
+            let response = self
+                .pool
+                .call(|url| async move {
+                    let response = http
+                        .post(&url)
+                        .json(body)
+                        .send()
+                        .await
+                        .map_err(|e| e.to_string())?;
+                    if !response.status().is_success() {
+                        return Err(format!("HTTP {}", response.status()));
+                    }
+                    response
+                        .json::<RpcResponse>()
+                        .await
+                        .map_err(|e| e.to_string())
+                })
+                .await;
+
+            match response {
+                Ok(RpcResponse {
+                    error: Some(error), ..
+                }) => {
+                    break Err(ChainError::Rpc {
+                        method: method.to_string(),
+                        code: error.code,
+                        message: error.message,
+                        data: error.data,
+                    })
+                }
+                Ok(RpcResponse { result, .. }) => break Ok(result.unwrap_or(Value::Null)),
+                Err(e) if retries < self.config.max_retries => {
+                    let backoff = self.retry_delay(retries);
+                    retries += 1;
+                    log::debug!(
+                        "chain: {} {} failed, retrying in {:?}: {}",
+                        self.config.chain,
+                        method,
+                        backoff,
+                        e
+                    );
+                    tokio::time::sleep(backoff).await;
+                }
+                Err(e) => break Err(ChainError::Pool(e)),
+            }
+        };
+
+        let mut methods = self.methods.lock().unwrap();
+        let metrics = methods.entry(method.to_string()).or_default();
+        metrics.requests += 1;
+        metrics.retries += retries as u64;
+        metrics.total_latency_ms += started.elapsed().as_secs_f64() * 1000.0;
+        if result.is_err() {
+            metrics.failures += 1;
+        }
+
+        result
+    }
+
+    /// Request and endpoint metrics
+    pub fn metrics(&self) -> ChainMetrics {
+        ChainMetrics {
+            chain: self.config.chain.clone(),
+            network: self.config.network.clone(),
+            methods: self.methods.lock().unwrap().clone(),
+            endpoints: self.pool.metrics(),
+        }
+    }
+
+    fn retry_delay(&self, retries: u32) -> Duration {
+        self.config
+            .retry_backoff
+            .saturating_mul(1 << retries.min(16))
+    }
+}
+
+/// Error for a response missing a field or holding a field of the wrong type
+pub(crate) fn invalid_response(method: &str, message: impl Into<String>) -> ChainError {
+    ChainError::InvalidResponse {
+        method: method.to_string(),
+        message: message.into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_unreachable_endpoints_are_retried() {
+        let mut config = ChainConfig::new("neo", "testnet", ["http://127.0.0.1:1"]);
+        config.max_retries = 2;
+        config.retry_backoff = Duration::from_millis(1);
+        let transport = JsonRpcTransport::new(config);
+
+        let result = transport
+            .request("getblockcount", Value::Array(vec![]))
+            .await;
+        assert!(matches!(result, Err(ChainError::Pool(_))));
+
+        let metrics = transport.metrics();
+        let method = &metrics.methods["getblockcount"];
+        assert_eq!(
+            (method.requests, method.failures, method.retries),
+            (1, 1, 2)
+        );
+        assert_eq!(metrics.endpoints[0].requests, 3);
+    }
+}
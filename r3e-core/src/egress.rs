@@ -0,0 +1,236 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+//! Egress protection for outbound requests.
+//!
+//! Resolves destination hosts and rejects loopback, private, link-local and other
+//! internal address ranges, so user supplied URLs cannot reach internal services
+//! such as cloud metadata endpoints (SSRF).
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+use ipnet::IpNet;
+use thiserror::Error;
+use url::{Host, Url};
+
+/// Egress error
+#[derive(Debug, Error)]
+pub enum EgressError {
+    /// The URL is malformed or not supported
+    #[error("egress: invalid url: {0}")]
+    InvalidUrl(String),
+
+    /// The host could not be resolved
+    #[error("egress: failed to resolve {0}: {1}")]
+    Resolve(String, String),
+
+    /// The host resolves to a blocked address
+    #[error("egress: destination {0} ({1}) is not allowed")]
+    Blocked(String, IpAddr),
+}
+
+/// URL checked by the egress guard, with the addresses it is allowed to connect to.
+///
+/// Callers should pin connections to `addrs` (e.g. `reqwest::ClientBuilder::resolve_to_addrs`)
+/// so a second DNS lookup cannot rebind the host to an internal address.
+#[derive(Debug, Clone)]
+pub struct ResolvedUrl {
+    /// Checked URL
+    pub url: Url,
+
+    /// Host name of the URL
+    pub host: String,
+
+    /// Allowed socket addresses of the host
+    pub addrs: Vec<SocketAddr>,
+}
+
+/// Egress guard
+#[derive(Debug, Clone, Default)]
+pub struct EgressGuard {
+    /// Allow all internal ranges, for development setups only
+    allow_internal: bool,
+
+    /// Ranges allowed even though they are internal, e.g. a trusted service subnet
+    allowed_cidrs: Vec<IpNet>,
+}
+
+impl EgressGuard {
+    /// Create a new egress guard blocking all internal ranges
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Allow or block all internal ranges
+    pub fn with_allow_internal(mut self, allow_internal: bool) -> Self {
+        self.allow_internal = allow_internal;
+        self
+    }
+
+    /// Allow the given ranges even if they are internal
+    pub fn with_allowed_cidrs(mut self, cidrs: impl IntoIterator<Item = IpNet>) -> Self {
+        self.allowed_cidrs.extend(cidrs);
+        self
+    }
+
+    /// Check a single IP address
+    pub fn is_allowed(&self, ip: IpAddr) -> bool {
+        self.allow_internal
+            || self.allowed_cidrs.iter().any(|cidr| cidr.contains(&ip))
+            || !is_internal(ip)
+    }
+
+    /// Resolve `host` and check all of its addresses.
+    /// Fails if any address is blocked, since the client may connect to any of them.
+    pub async fn resolve(&self, host: &str, port: u16) -> Result<Vec<SocketAddr>, EgressError> {
+        let addrs: Vec<SocketAddr> = tokio::net::lookup_host((host, port))
+            .await
+            .map_err(|err| EgressError::Resolve(host.to_string(), err.to_string()))?
+            .collect();
+        if addrs.is_empty() {
+            return Err(EgressError::Resolve(
+                host.to_string(),
+                "no addresses".into(),
+            ));
+        }
+
+        if let Some(addr) = addrs.iter().find(|addr| !self.is_allowed(addr.ip())) {
+            return Err(EgressError::Blocked(host.to_string(), addr.ip()));
+        }
+        Ok(addrs)
+    }
+
+    /// Parse and check an http(s) URL
+    pub async fn check_url(&self, url: &str) -> Result<ResolvedUrl, EgressError> {
+        let url = Url::parse(url).map_err(|err| EgressError::InvalidUrl(err.to_string()))?;
+        self.check(url).await
+    }
+
+    /// Check an already parsed http(s) URL
+    pub async fn check(&self, url: Url) -> Result<ResolvedUrl, EgressError> {
+        if url.scheme() != "http" && url.scheme() != "https" {
+            return Err(EgressError::InvalidUrl(format!(
+                "unsupported scheme '{}'",
+                url.scheme()
+            )));
+        }
+
+        let port = url
+            .port_or_known_default()
+            .ok_or_else(|| EgressError::InvalidUrl(format!("no port for {}", url)))?;
+        let (host, addrs) = match url.host() {
+            Some(Host::Domain(domain)) => (domain.to_string(), self.resolve(domain, port).await?),
+            Some(Host::Ipv4(ip)) => (ip.to_string(), self.check_literal(IpAddr::V4(ip), port)?),
+            Some(Host::Ipv6(ip)) => (ip.to_string(), self.check_literal(IpAddr::V6(ip), port)?),
+            None => return Err(EgressError::InvalidUrl(format!("no host in {}", url))),
+        };
+
+        Ok(ResolvedUrl { url, host, addrs })
+    }
+
+    fn check_literal(&self, ip: IpAddr, port: u16) -> Result<Vec<SocketAddr>, EgressError> {
+        if !self.is_allowed(ip) {
+            return Err(EgressError::Blocked(ip.to_string(), ip));
+        }
+        Ok(vec![SocketAddr::new(ip, port)])
+    }
+}
+
+/// Whether `ip` is in a loopback, private, link-local or otherwise non-public range
+pub fn is_internal(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_internal_v4(ip),
+        IpAddr::V6(ip) => is_internal_v6(ip),
+    }
+}
+
+fn is_internal_v4(ip: Ipv4Addr) -> bool {
+    let [a, b, c, _] = ip.octets();
+    ip.is_unspecified()
+        || ip.is_loopback()
+        || ip.is_private()
+        || ip.is_link_local() // 169.254.0.0/16, incl. cloud metadata
+        || ip.is_broadcast()
+        || ip.is_multicast()
+        || ip.is_documentation()
+        || a == 0 // 0.0.0.0/8
+        || (a == 100 && (b & 0xc0) == 64) // 100.64.0.0/10, carrier-grade NAT
+        || (a == 192 && b == 0 && c == 0) // 192.0.0.0/24, IETF protocol assignments
+        || (a == 198 && (b & 0xfe) == 18) // 198.18.0.0/15, benchmarking
+        || a >= 240 // 240.0.0.0/4, reserved
+}
+
+fn is_internal_v6(ip: Ipv6Addr) -> bool {
+    // IPv4-mapped (::ffff:0:0/96) and NAT64 (64:ff9b::/96) addresses reach IPv4 hosts
+    if let Some(v4) = ip.to_ipv4_mapped() {
+        return is_internal_v4(v4);
+    }
+    let segments = ip.segments();
+    if segments[..6] == [0x64, 0xff9b, 0, 0, 0, 0] {
+        let [.., a, b, c, d] = ip.octets();
+        return is_internal_v4(Ipv4Addr::new(a, b, c, d));
+    }
+
+    ip.is_unspecified()
+        || ip.is_loopback()
+        || ip.is_multicast()
+        || (segments[0] & 0xfe00) == 0xfc00 // fc00::/7, unique local
+        || (segments[0] & 0xffc0) == 0xfe80 // fe80::/10, link-local
+        || (segments[0] & 0xffc0) == 0xfec0 // fec0::/10, deprecated site-local
+        || (segments[0] == 0x2001 && segments[1] == 0x0db8) // 2001:db8::/32, documentation
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_internal() {
+        for ip in [
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "0.0.0.0",
+            "::1",
+            "fd00::1",
+            "fe80::1",
+            "::ffff:10.0.0.1",
+            "64:ff9b::a9fe:a9fe",
+        ] {
+            assert!(
+                is_internal(ip.parse().unwrap()),
+                "{} should be internal",
+                ip
+            );
+        }
+
+        for ip in ["1.1.1.1", "8.8.8.8", "2606:4700:4700::1111"] {
+            assert!(!is_internal(ip.parse().unwrap()), "{} should be public", ip);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_check_url() {
+        let guard = EgressGuard::new();
+        assert!(matches!(
+            guard
+                .check_url("http://169.254.169.254/latest/meta-data")
+                .await,
+            Err(EgressError::Blocked(..))
+        ));
+        assert!(matches!(
+            guard.check_url("http://localhost:8080/").await,
+            Err(EgressError::Blocked(..))
+        ));
+        assert!(matches!(
+            guard.check_url("file:///etc/passwd").await,
+            Err(EgressError::InvalidUrl(..))
+        ));
+
+        let guard = guard.with_allowed_cidrs(["10.0.0.0/8".parse().unwrap()]);
+        assert!(guard.check_url("http://10.0.0.5/").await.is_ok());
+    }
+}
@@ -3,6 +3,7 @@
 
 //! Error types for the core crate.
 
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
 /// Error type for the core crate
@@ -23,7 +24,349 @@ pub enum Error {
     /// Signal hook error
     #[error("Signal hook error: {0}")]
     SignalHook(String),
+
+    /// Egress error
+    #[error(transparent)]
+    Egress(#[from] crate::egress::EgressError),
+
+    /// RPC pool error
+    #[error(transparent)]
+    RpcPool(#[from] crate::rpc_pool::RpcPoolError),
+
+    /// Chain client error
+    #[error(transparent)]
+    Chain(#[from] crate::chain::ChainError),
+
+    /// Quota error
+    #[error(transparent)]
+    Quota(#[from] crate::quota::QuotaError),
 }
 
 /// Result type for the core crate
 pub type Result<T> = std::result::Result<T, Error>;
+
+/// Machine-readable error code shared by the APIs, services and workers.
+///
+/// Codes are stable: clients branch on them instead of on messages. The first digit is the
+/// category: 1 invalid requests, 2 access and limits, 3 function execution, 4 upstream
+/// services and 5 platform failures.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorCode {
+    /// The request is malformed or fails validation
+    Validation,
+    /// The resource does not exist
+    NotFound,
+    /// The request conflicts with the current state of the resource
+    Conflict,
+    /// A precondition of the request does not hold
+    PreconditionFailed,
+    /// The caller is not authenticated
+    Unauthenticated,
+    /// The caller may not perform the request
+    Forbidden,
+    /// The request would exceed a quota of the tenant
+    QuotaExceeded,
+    /// Too many requests in too short a time
+    RateLimited,
+    /// The account must be paid up first
+    PaymentRequired,
+    /// The function threw or failed
+    ExecutionFailed,
+    /// The function code could not be loaded or compiled
+    CompileFailed,
+    /// The function exceeded its time limit
+    Timeout,
+    /// The function reached its heap limit
+    OutOfMemory,
+    /// The function attempted something its sandbox forbids
+    SandboxViolation,
+    /// The function exceeded a resource limit other than time and memory
+    ResourceLimit,
+    /// The function output does not match its output schema
+    InvalidOutput,
+    /// An external service failed
+    Upstream,
+    /// A blockchain node failed or rejected the request
+    Blockchain,
+    /// Unexpected platform failure
+    Internal,
+    /// Storage failure
+    Storage,
+    /// Invalid platform configuration
+    Configuration,
+    /// The service is temporarily unavailable
+    Unavailable,
+}
+
+impl ErrorCode {
+    /// Every error code
+    pub const ALL: [ErrorCode; 22] = [
+        ErrorCode::Validation,
+        ErrorCode::NotFound,
+        ErrorCode::Conflict,
+        ErrorCode::PreconditionFailed,
+        ErrorCode::Unauthenticated,
+        ErrorCode::Forbidden,
+        ErrorCode::QuotaExceeded,
+        ErrorCode::RateLimited,
+        ErrorCode::PaymentRequired,
+        ErrorCode::ExecutionFailed,
+        ErrorCode::CompileFailed,
+        ErrorCode::Timeout,
+        ErrorCode::OutOfMemory,
+        ErrorCode::SandboxViolation,
+        ErrorCode::ResourceLimit,
+        ErrorCode::InvalidOutput,
+        ErrorCode::Upstream,
+        ErrorCode::Blockchain,
+        ErrorCode::Internal,
+        ErrorCode::Storage,
+        ErrorCode::Configuration,
+        ErrorCode::Unavailable,
+    ];
+
+    /// Code as sent to clients, e.g. `R3E-1001`
+    pub const fn as_str(&self) -> &'static str {
+        match self {
+            ErrorCode::Validation => "R3E-1001",
+            ErrorCode::NotFound => "R3E-1002",
+            ErrorCode::Conflict => "R3E-1003",
+            ErrorCode::PreconditionFailed => "R3E-1004",
+            ErrorCode::Unauthenticated => "R3E-2001",
+            ErrorCode::Forbidden => "R3E-2002",
+            ErrorCode::QuotaExceeded => "R3E-2003",
+            ErrorCode::RateLimited => "R3E-2004",
+            ErrorCode::PaymentRequired => "R3E-2005",
+            ErrorCode::ExecutionFailed => "R3E-3001",
+            ErrorCode::CompileFailed => "R3E-3002",
+            ErrorCode::Timeout => "R3E-3003",
+            ErrorCode::OutOfMemory => "R3E-3004",
+            ErrorCode::SandboxViolation => "R3E-3005",
+            ErrorCode::ResourceLimit => "R3E-3006",
+            ErrorCode::InvalidOutput => "R3E-3007",
+            ErrorCode::Upstream => "R3E-4001",
+            ErrorCode::Blockchain => "R3E-4002",
+            ErrorCode::Internal => "R3E-5001",
+            ErrorCode::Storage => "R3E-5002",
+            ErrorCode::Configuration => "R3E-5003",
+            ErrorCode::Unavailable => "R3E-5004",
+        }
+    }
+
+    /// HTTP status of responses failing with the code
+    pub const fn http_status(&self) -> u16 {
+        match self {
+            ErrorCode::Validation => 400,
+            ErrorCode::NotFound => 404,
+            ErrorCode::Conflict => 409,
+            ErrorCode::PreconditionFailed => 412,
+            ErrorCode::Unauthenticated => 401,
+            ErrorCode::Forbidden | ErrorCode::QuotaExceeded => 403,
+            ErrorCode::RateLimited => 429,
+            ErrorCode::PaymentRequired => 402,
+            ErrorCode::ExecutionFailed
+            | ErrorCode::CompileFailed
+            | ErrorCode::OutOfMemory
+            | ErrorCode::SandboxViolation
+            | ErrorCode::ResourceLimit
+            | ErrorCode::InvalidOutput => 422,
+            ErrorCode::Timeout => 504,
+            ErrorCode::Upstream | ErrorCode::Blockchain => 502,
+            ErrorCode::Internal | ErrorCode::Storage | ErrorCode::Configuration => 500,
+            ErrorCode::Unavailable => 503,
+        }
+    }
+
+    /// Whether retrying the same request may succeed
+    pub const fn is_retryable(&self) -> bool {
+        matches!(
+            self,
+            ErrorCode::RateLimited
+                | ErrorCode::Timeout
+                | ErrorCode::Upstream
+                | ErrorCode::Blockchain
+                | ErrorCode::Unavailable
+        )
+    }
+}
+
+impl std::fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl std::str::FromStr for ErrorCode {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        ErrorCode::ALL
+            .into_iter()
+            .find(|code| code.as_str() == s)
+            .ok_or_else(|| format!("unknown error code: {}", s))
+    }
+}
+
+impl Serialize for ErrorCode {
+    fn serialize<S: serde::Serializer>(
+        &self,
+        serializer: S,
+    ) -> std::result::Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for ErrorCode {
+    fn deserialize<D: serde::Deserializer<'de>>(
+        deserializer: D,
+    ) -> std::result::Result<Self, D::Error> {
+        let code = String::deserialize(deserializer)?;
+        code.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// Error body of API responses
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ErrorBody {
+    /// Machine-readable error code
+    pub code: ErrorCode,
+
+    /// Human-readable message
+    pub message: String,
+
+    /// Structured details, e.g. the violations of a validation error
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub details: Option<serde_json::Value>,
+}
+
+impl ErrorBody {
+    /// Create an error body without details
+    pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+            details: None,
+        }
+    }
+
+    /// Attach structured details
+    pub fn with_details(mut self, details: serde_json::Value) -> Self {
+        self.details = Some(details);
+        self
+    }
+}
+
+impl std::fmt::Display for ErrorBody {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.code, self.message)
+    }
+}
+
+/// Error with a machine-readable code
+pub trait CodedError: std::fmt::Display {
+    /// Code of the error
+    fn code(&self) -> ErrorCode;
+
+    /// Error body of the error, with its message
+    fn to_body(&self) -> ErrorBody {
+        ErrorBody::new(self.code(), self.to_string())
+    }
+}
+
+impl CodedError for Error {
+    fn code(&self) -> ErrorCode {
+        match self {
+            Error::Io(_) => ErrorCode::Storage,
+            Error::Serialization(_) => ErrorCode::Validation,
+            Error::V8(_) => ErrorCode::ExecutionFailed,
+            Error::SignalHook(_) => ErrorCode::Internal,
+            Error::Egress(e) => e.code(),
+            Error::RpcPool(e) => e.code(),
+            Error::Chain(e) => e.code(),
+            Error::Quota(e) => e.code(),
+        }
+    }
+}
+
+impl CodedError for crate::egress::EgressError {
+    fn code(&self) -> ErrorCode {
+        use crate::egress::EgressError;
+
+        match self {
+            EgressError::InvalidUrl(_) => ErrorCode::Validation,
+            EgressError::Resolve(..) => ErrorCode::Upstream,
+            EgressError::Blocked(..) => ErrorCode::SandboxViolation,
+        }
+    }
+}
+
+impl CodedError for crate::rpc_pool::RpcPoolError {
+    fn code(&self) -> ErrorCode {
+        use crate::rpc_pool::RpcPoolError;
+
+        match self {
+            RpcPoolError::NoEndpoints(_) => ErrorCode::Configuration,
+            RpcPoolError::Unavailable(_) => ErrorCode::Unavailable,
+            RpcPoolError::AllFailed { .. } => ErrorCode::Blockchain,
+        }
+    }
+}
+
+impl CodedError for crate::chain::ChainError {
+    fn code(&self) -> ErrorCode {
+        use crate::chain::ChainError;
+
+        match self {
+            ChainError::Pool(e) => e.code(),
+            ChainError::Rpc { .. } | ChainError::InvalidResponse { .. } => ErrorCode::Blockchain,
+            ChainError::InvalidRequest(_) | ChainError::Unsupported(_) => ErrorCode::Validation,
+        }
+    }
+}
+
+impl CodedError for crate::quota::QuotaError {
+    fn code(&self) -> ErrorCode {
+        use crate::quota::QuotaError;
+
+        match self {
+            QuotaError::Exceeded { .. } => ErrorCode::QuotaExceeded,
+            QuotaError::Unavailable(_) => ErrorCode::Unavailable,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_codes_are_unique() {
+        for (i, code) in ErrorCode::ALL.iter().enumerate() {
+            assert_eq!(code.as_str().parse::<ErrorCode>().unwrap(), *code);
+            assert!(ErrorCode::ALL[i + 1..]
+                .iter()
+                .all(|other| other.as_str() != code.as_str()));
+        }
+        assert!("R3E-9999".parse::<ErrorCode>().is_err());
+    }
+
+    #[test]
+    fn test_error_body_json() {
+        let body = ErrorBody::new(ErrorCode::QuotaExceeded, "over the invocation limit")
+            .with_details(serde_json::json!({ "limit": 100 }));
+        let json = serde_json::to_value(&body).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "code": "R3E-2003",
+                "message": "over the invocation limit",
+                "details": { "limit": 100 },
+            })
+        );
+        assert_eq!(serde_json::from_value::<ErrorBody>(json).unwrap(), body);
+
+        // Details are omitted when there are none
+        let json = serde_json::to_value(ErrorBody::new(ErrorCode::NotFound, "no such function"));
+        assert!(json.unwrap().get("details").is_none());
+    }
+}
@@ -5,15 +5,22 @@
 //!
 //! Core functionality and shared types for the R3E FaaS platform.
 
+pub mod chain;
 pub mod config;
+pub mod egress;
 pub mod encoding;
 pub mod error;
+pub mod mtls;
+pub mod proof;
+pub mod quota;
+pub mod rpc_pool;
+pub mod schema;
 pub mod types;
 
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Once};
 
-pub use error::{Error, Result};
+pub use error::{CodedError, Error, ErrorBody, ErrorCode, Result};
 pub use r3e_proc_macros::BytesLike;
 pub use types::Platform;
 
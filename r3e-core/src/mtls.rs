@@ -0,0 +1,493 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+//! Mutual TLS between the API, the workers and the internal services.
+//!
+//! Every service holds a certificate issued by a shared CA, carrying its SPIFFE ID, e.g.
+//! `spiffe://r3e.local/worker/eu-1`, as a URI subject alternative name. Servers only accept
+//! clients presenting a certificate of the CA, and authorize them by their SPIFFE ID against
+//! patterns where `*` matches one path segment. Certificates are read from the configured
+//! files; when they are missing and the key of the internal CA is configured, they are
+//! issued at startup.
+
+use std::fmt;
+use std::io::BufReader;
+use std::net::TcpStream;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+
+use rcgen::{
+    BasicConstraints, CertificateParams, DnType, ExtendedKeyUsagePurpose, Ia5String, IsCa, KeyPair,
+    KeyUsagePurpose, SanType,
+};
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::server::WebPkiClientVerifier;
+use rustls::{RootCertStore, ServerConnection, StreamOwned};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use x509_parser::extensions::GeneralName;
+
+pub use rustls::ServerConfig;
+pub use tokio_rustls::TlsAcceptor;
+
+/// mTLS error
+#[derive(Debug, Error)]
+pub enum MtlsError {
+    #[error("mtls: {0}: {1}")]
+    Io(String, std::io::Error),
+
+    #[error("mtls: invalid certificate: {0}")]
+    Certificate(String),
+
+    #[error("mtls: tls error: {0}")]
+    Tls(String),
+
+    #[error("mtls: invalid SPIFFE ID: {0}")]
+    InvalidSpiffeId(String),
+
+    #[error("mtls: peer {0} is not authorized")]
+    Unauthorized(String),
+}
+
+/// SPIFFE ID of a service, `spiffe://<trust domain>/<path>`
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SpiffeId {
+    pub trust_domain: String,
+    pub path: String,
+}
+
+impl FromStr for SpiffeId {
+    type Err = MtlsError;
+
+    fn from_str(id: &str) -> Result<Self, Self::Err> {
+        let invalid = || MtlsError::InvalidSpiffeId(id.to_string());
+        let rest = id.strip_prefix("spiffe://").ok_or_else(invalid)?;
+        let (trust_domain, path) = rest.split_once('/').unwrap_or((rest, ""));
+        let valid_domain = !trust_domain.is_empty()
+            && trust_domain
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || "-._".contains(c));
+        if !valid_domain || (!path.is_empty() && path.split('/').any(str::is_empty)) {
+            return Err(invalid());
+        }
+
+        Ok(Self {
+            trust_domain: trust_domain.to_string(),
+            path: path.to_string(),
+        })
+    }
+}
+
+impl fmt::Display for SpiffeId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.path.is_empty() {
+            write!(f, "spiffe://{}", self.trust_domain)
+        } else {
+            write!(f, "spiffe://{}/{}", self.trust_domain, self.path)
+        }
+    }
+}
+
+impl SpiffeId {
+    /// Whether the ID matches a pattern, where `*` matches one path segment
+    pub fn matches(&self, pattern: &str) -> bool {
+        let id = self.to_string();
+        let (mut id, mut pattern) = (id.split('/'), pattern.split('/'));
+        loop {
+            match (id.next(), pattern.next()) {
+                (None, None) => return true,
+                (Some(_), Some("*")) => {}
+                (Some(segment), Some(expected)) if segment == expected => {}
+                _ => return false,
+            }
+        }
+    }
+}
+
+/// mTLS configuration of a service
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MtlsConfig {
+    /// Certificate of the service, PEM
+    pub cert_path: PathBuf,
+
+    /// Private key of the service, PEM
+    pub key_path: PathBuf,
+
+    /// Certificate of the CA the peers' certificates are issued by, PEM
+    pub ca_path: PathBuf,
+
+    /// Private key of the internal CA, to issue the certificate of the service when missing
+    #[serde(default)]
+    pub ca_key_path: Option<PathBuf>,
+
+    /// SPIFFE ID of the service, in the certificate issued by the internal CA
+    #[serde(default)]
+    pub identity: Option<String>,
+
+    /// DNS names of the service, in the certificate issued by the internal CA
+    #[serde(default = "default_dns_names")]
+    pub dns_names: Vec<String>,
+
+    /// SPIFFE IDs of the peers allowed to connect, `*` matches one path segment.
+    /// Empty allows every peer with a certificate of the CA.
+    #[serde(default)]
+    pub allowed_peers: Vec<String>,
+}
+
+fn default_dns_names() -> Vec<String> {
+    vec!["localhost".to_string()]
+}
+
+impl MtlsConfig {
+    /// Configuration of a service using the certificates in `dir`: `cert.pem`, `key.pem`
+    /// and `ca.pem`
+    pub fn in_dir(dir: impl AsRef<Path>) -> Self {
+        let dir = dir.as_ref();
+        Self {
+            cert_path: dir.join("cert.pem"),
+            key_path: dir.join("key.pem"),
+            ca_path: dir.join("ca.pem"),
+            ca_key_path: None,
+            identity: None,
+            dns_names: default_dns_names(),
+            allowed_peers: Vec::new(),
+        }
+    }
+
+    /// Issue the certificate of the service with the internal CA if it is missing
+    pub fn provision(&self) -> Result<(), MtlsError> {
+        if self.cert_path.exists() && self.key_path.exists() {
+            return Ok(());
+        }
+
+        let (Some(ca_key_path), Some(identity)) = (&self.ca_key_path, &self.identity) else {
+            return Err(MtlsError::Certificate(format!(
+                "{} missing and no internal CA key and identity to issue it",
+                self.cert_path.display()
+            )));
+        };
+
+        let ca = InternalCa::load(&read_string(&self.ca_path)?, &read_string(ca_key_path)?)?;
+        let issued = ca.issue(&identity.parse()?, &self.dns_names)?;
+        write_file(&self.cert_path, &issued.cert_pem, false)?;
+        write_file(&self.key_path, &issued.key_pem, true)?;
+
+        log::info!(
+            "mtls: issued certificate of {} to {}",
+            identity,
+            self.cert_path.display()
+        );
+        Ok(())
+    }
+
+    /// Server configuration requiring clients to present a certificate of the CA
+    pub fn server_config(&self) -> Result<Arc<ServerConfig>, MtlsError> {
+        let mut roots = RootCertStore::empty();
+        for cert in read_certs(&self.ca_path)? {
+            roots
+                .add(cert)
+                .map_err(|err| MtlsError::Certificate(err.to_string()))?;
+        }
+
+        let verifier = WebPkiClientVerifier::builder(Arc::new(roots))
+            .build()
+            .map_err(|err| MtlsError::Tls(err.to_string()))?;
+        let config = ServerConfig::builder()
+            .with_client_cert_verifier(verifier)
+            .with_single_cert(read_certs(&self.cert_path)?, read_key(&self.key_path)?)
+            .map_err(|err| MtlsError::Tls(err.to_string()))?;
+
+        Ok(Arc::new(config))
+    }
+
+    /// HTTP client builder presenting the certificate of the service, and trusting only
+    /// servers with a certificate of the CA
+    pub fn client_builder(&self) -> Result<reqwest::ClientBuilder, MtlsError> {
+        let ca = reqwest::Certificate::from_pem(read_string(&self.ca_path)?.as_bytes())
+            .map_err(|err| MtlsError::Certificate(err.to_string()))?;
+        let pem = format!(
+            "{}\n{}",
+            read_string(&self.cert_path)?,
+            read_string(&self.key_path)?
+        );
+        let identity = reqwest::Identity::from_pem(pem.as_bytes())
+            .map_err(|err| MtlsError::Certificate(err.to_string()))?;
+
+        Ok(reqwest::Client::builder()
+            .use_rustls_tls()
+            .tls_built_in_root_certs(false)
+            .add_root_certificate(ca)
+            .identity(identity))
+    }
+
+    /// HTTP client presenting the certificate of the service
+    pub fn client(&self) -> Result<reqwest::Client, MtlsError> {
+        self.client_builder()?
+            .build()
+            .map_err(|err| MtlsError::Tls(err.to_string()))
+    }
+
+    /// Authorize a peer by its SPIFFE ID
+    pub fn authorize(&self, peer: Option<&SpiffeId>) -> Result<(), MtlsError> {
+        if self.allowed_peers.is_empty() {
+            return Ok(());
+        }
+
+        let Some(peer) = peer else {
+            return Err(MtlsError::Unauthorized("without SPIFFE ID".to_string()));
+        };
+        if !self
+            .allowed_peers
+            .iter()
+            .any(|pattern| peer.matches(pattern))
+        {
+            return Err(MtlsError::Unauthorized(peer.to_string()));
+        }
+        Ok(())
+    }
+}
+
+/// Certificate and private key issued by the internal CA, PEM
+#[derive(Debug, Clone)]
+pub struct IssuedCertificate {
+    pub cert_pem: String,
+    pub key_pem: String,
+}
+
+/// Internal CA issuing the certificates of the services
+pub struct InternalCa {
+    cert: rcgen::Certificate,
+    key: KeyPair,
+    cert_pem: String,
+}
+
+impl InternalCa {
+    /// Generate a CA for a trust domain
+    pub fn generate(trust_domain: &str) -> Result<Self, MtlsError> {
+        let key = KeyPair::generate().map_err(certificate_error)?;
+        let mut params = CertificateParams::new(Vec::<String>::new()).map_err(certificate_error)?;
+        params
+            .distinguished_name
+            .push(DnType::CommonName, format!("{} internal CA", trust_domain));
+        params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        params.key_usages = vec![
+            KeyUsagePurpose::KeyCertSign,
+            KeyUsagePurpose::CrlSign,
+            KeyUsagePurpose::DigitalSignature,
+        ];
+
+        let cert = params.self_signed(&key).map_err(certificate_error)?;
+        let cert_pem = cert.pem();
+        Ok(Self {
+            cert,
+            key,
+            cert_pem,
+        })
+    }
+
+    /// Load a CA from its certificate and private key
+    pub fn load(cert_pem: &str, key_pem: &str) -> Result<Self, MtlsError> {
+        let key = KeyPair::from_pem(key_pem).map_err(certificate_error)?;
+        let params = CertificateParams::from_ca_cert_pem(cert_pem).map_err(certificate_error)?;
+        let cert = params.self_signed(&key).map_err(certificate_error)?;
+        Ok(Self {
+            cert,
+            key,
+            cert_pem: cert_pem.to_string(),
+        })
+    }
+
+    pub fn cert_pem(&self) -> &str {
+        &self.cert_pem
+    }
+
+    pub fn key_pem(&self) -> String {
+        self.key.serialize_pem()
+    }
+
+    /// Issue the certificate of a service, usable as client and server certificate
+    pub fn issue(
+        &self,
+        identity: &SpiffeId,
+        dns_names: &[String],
+    ) -> Result<IssuedCertificate, MtlsError> {
+        let key = KeyPair::generate().map_err(certificate_error)?;
+        let mut params = CertificateParams::new(dns_names.to_vec()).map_err(certificate_error)?;
+        params
+            .distinguished_name
+            .push(DnType::CommonName, identity.to_string());
+        let uri = Ia5String::try_from(identity.to_string()).map_err(certificate_error)?;
+        params.subject_alt_names.push(SanType::URI(uri));
+        params.key_usages = vec![
+            KeyUsagePurpose::DigitalSignature,
+            KeyUsagePurpose::KeyEncipherment,
+        ];
+        params.extended_key_usages = vec![
+            ExtendedKeyUsagePurpose::ServerAuth,
+            ExtendedKeyUsagePurpose::ClientAuth,
+        ];
+
+        let cert = params
+            .signed_by(&key, &self.cert, &self.key)
+            .map_err(certificate_error)?;
+        Ok(IssuedCertificate {
+            cert_pem: cert.pem(),
+            key_pem: key.serialize_pem(),
+        })
+    }
+}
+
+/// SPIFFE ID in the URI subject alternative names of a certificate
+pub fn peer_identity(cert: &CertificateDer<'_>) -> Option<SpiffeId> {
+    let (_, cert) = x509_parser::parse_x509_certificate(cert.as_ref()).ok()?;
+    let names = cert.subject_alternative_name().ok()??;
+    names
+        .value
+        .general_names
+        .iter()
+        .find_map(|name| match name {
+            GeneralName::URI(uri) => uri.parse().ok(),
+            _ => None,
+        })
+}
+
+/// Complete the TLS handshake of a blocking connection, returning the stream and the SPIFFE
+/// ID of the peer
+pub fn accept_blocking(
+    config: Arc<ServerConfig>,
+    mut tcp: TcpStream,
+) -> Result<(StreamOwned<ServerConnection, TcpStream>, Option<SpiffeId>), MtlsError> {
+    let mut conn = ServerConnection::new(config).map_err(|err| MtlsError::Tls(err.to_string()))?;
+    while conn.is_handshaking() {
+        conn.complete_io(&mut tcp)
+            .map_err(|err| MtlsError::Io("handshake".to_string(), err))?;
+    }
+
+    let peer = conn
+        .peer_certificates()
+        .and_then(|certs| certs.first())
+        .and_then(peer_identity);
+    Ok((StreamOwned::new(conn, tcp), peer))
+}
+
+/// Complete the TLS handshake of a connection, returning the stream and the SPIFFE ID of the
+/// peer
+pub async fn accept<S>(
+    acceptor: &TlsAcceptor,
+    stream: S,
+) -> Result<(tokio_rustls::server::TlsStream<S>, Option<SpiffeId>), MtlsError>
+where
+    S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin,
+{
+    let stream = acceptor
+        .accept(stream)
+        .await
+        .map_err(|err| MtlsError::Io("handshake".to_string(), err))?;
+    let peer = stream
+        .get_ref()
+        .1
+        .peer_certificates()
+        .and_then(|certs| certs.first())
+        .and_then(peer_identity);
+    Ok((stream, peer))
+}
+
+fn certificate_error(err: impl fmt::Display) -> MtlsError {
+    MtlsError::Certificate(err.to_string())
+}
+
+fn read_string(path: &Path) -> Result<String, MtlsError> {
+    std::fs::read_to_string(path).map_err(|err| MtlsError::Io(path.display().to_string(), err))
+}
+
+fn read_certs(path: &Path) -> Result<Vec<CertificateDer<'static>>, MtlsError> {
+    let pem = read_string(path)?;
+    let certs = rustls_pemfile::certs(&mut BufReader::new(pem.as_bytes()))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|err| MtlsError::Io(path.display().to_string(), err))?;
+    if certs.is_empty() {
+        return Err(MtlsError::Certificate(format!(
+            "no certificate in {}",
+            path.display()
+        )));
+    }
+    Ok(certs)
+}
+
+fn read_key(path: &Path) -> Result<PrivateKeyDer<'static>, MtlsError> {
+    let pem = read_string(path)?;
+    rustls_pemfile::private_key(&mut BufReader::new(pem.as_bytes()))
+        .map_err(|err| MtlsError::Io(path.display().to_string(), err))?
+        .ok_or_else(|| MtlsError::Certificate(format!("no private key in {}", path.display())))
+}
+
+fn write_file(path: &Path, content: &str, private: bool) -> Result<(), MtlsError> {
+    let io_error = |err| MtlsError::Io(path.display().to_string(), err);
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).map_err(io_error)?;
+    }
+
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(if private { 0o600 } else { 0o644 });
+    }
+
+    let mut file = options.open(path).map_err(io_error)?;
+    std::io::Write::write_all(&mut file, content.as_bytes()).map_err(io_error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_spiffe_id() {
+        let id: SpiffeId = "spiffe://r3e.local/worker/eu-1".parse().unwrap();
+        assert_eq!(id.trust_domain, "r3e.local");
+        assert_eq!(id.path, "worker/eu-1");
+        assert_eq!(id.to_string(), "spiffe://r3e.local/worker/eu-1");
+
+        assert!(id.matches("spiffe://r3e.local/worker/*"));
+        assert!(id.matches("spiffe://r3e.local/worker/eu-1"));
+        assert!(!id.matches("spiffe://r3e.local/api/*"));
+        assert!(!id.matches("spiffe://r3e.local/*"));
+        assert!(!id.matches("spiffe://other.local/worker/*"));
+
+        assert!("https://r3e.local/worker".parse::<SpiffeId>().is_err());
+        assert!("spiffe://R3E/worker".parse::<SpiffeId>().is_err());
+        assert!("spiffe://r3e.local//worker".parse::<SpiffeId>().is_err());
+    }
+
+    #[test]
+    fn test_provision_and_authorize() {
+        let dir = tempfile::tempdir().unwrap();
+        let ca = InternalCa::generate("r3e.local").unwrap();
+        std::fs::write(dir.path().join("ca.pem"), ca.cert_pem()).unwrap();
+        std::fs::write(dir.path().join("ca-key.pem"), ca.key_pem()).unwrap();
+
+        let mut config = MtlsConfig::in_dir(dir.path());
+        assert!(config.provision().is_err());
+
+        config.ca_key_path = Some(dir.path().join("ca-key.pem"));
+        config.identity = Some("spiffe://r3e.local/worker/eu-1".to_string());
+        config.provision().unwrap();
+        config.server_config().unwrap();
+
+        let certs = read_certs(&config.cert_path).unwrap();
+        let peer = peer_identity(&certs[0]).unwrap();
+        assert_eq!(peer.to_string(), "spiffe://r3e.local/worker/eu-1");
+
+        assert!(config.authorize(None).is_ok());
+        config.allowed_peers = vec!["spiffe://r3e.local/api/*".to_string()];
+        assert!(config.authorize(Some(&peer)).is_err());
+        assert!(config.authorize(None).is_err());
+        config
+            .allowed_peers
+            .push("spiffe://r3e.local/worker/*".to_string());
+        assert!(config.authorize(Some(&peer)).is_ok());
+    }
+}
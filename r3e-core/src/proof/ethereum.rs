@@ -0,0 +1,498 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+//! Ethereum block headers and Merkle-Patricia proofs.
+//!
+//! A header is verified by hashing it against a trusted block hash. Its transactions, receipts
+//! and state are then verified with the proofs `eth_getProof` and the transaction and receipt
+//! tries of the block yield: a path of trie nodes from the root in the header down to the
+//! proven value, each node hashed into its parent.
+
+use serde::{Serialize, Serializer};
+use sha3::{Digest, Keccak256};
+
+use super::rlp::{self, Item, Value};
+use super::{serialize_hex, ProofError};
+
+/// Root of the empty trie, the Keccak-256 of the encoding of an empty string
+const EMPTY_ROOT: [u8; 32] = [
+    0x56, 0xe8, 0x1f, 0x17, 0x1b, 0xcc, 0x55, 0xa6, 0xff, 0x83, 0x45, 0xe6, 0x92, 0xc0, 0xf8, 0x6e,
+    0x5b, 0x48, 0xe0, 0x1b, 0x99, 0x6c, 0xad, 0xc0, 0x01, 0x62, 0x2f, 0xb5, 0xe3, 0x63, 0xb4, 0x21,
+];
+
+/// Keccak-256 hash
+pub fn keccak256(data: &[u8]) -> [u8; 32] {
+    Keccak256::digest(data).into()
+}
+
+/// Block header
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EthHeader {
+    /// Block hash
+    #[serde(serialize_with = "serialize_hex")]
+    pub hash: [u8; 32],
+    /// Hash of the parent block
+    #[serde(serialize_with = "serialize_hex")]
+    pub parent_hash: [u8; 32],
+    /// Root of the state trie
+    #[serde(serialize_with = "serialize_hex")]
+    pub state_root: [u8; 32],
+    /// Root of the transaction trie
+    #[serde(serialize_with = "serialize_hex")]
+    pub transactions_root: [u8; 32],
+    /// Root of the receipt trie
+    #[serde(serialize_with = "serialize_hex")]
+    pub receipts_root: [u8; 32],
+    /// Block number
+    pub number: u64,
+    /// Block timestamp (secs since epoch)
+    pub timestamp: u64,
+}
+
+impl EthHeader {
+    /// Decode an RLP encoded header, hashing it
+    pub fn decode(encoded: &[u8]) -> Result<Self, ProofError> {
+        let header = rlp::decode(encoded)?;
+        let fields = header.list()?;
+        if fields.len() < 15 {
+            return Err(ProofError::Malformed(format!(
+                "header: {} fields",
+                fields.len()
+            )));
+        }
+
+        Ok(Self {
+            hash: keccak256(encoded),
+            parent_hash: fields[0].fixed()?,
+            state_root: fields[3].fixed()?,
+            transactions_root: fields[4].fixed()?,
+            receipts_root: fields[5].fixed()?,
+            number: fields[8].uint()?,
+            timestamp: fields[11].uint()?,
+        })
+    }
+
+    /// Decode an RLP encoded header, checking it hashes to a trusted block hash
+    pub fn verify(encoded: &[u8], trusted_hash: &[u8; 32]) -> Result<Self, ProofError> {
+        let header = Self::decode(encoded)?;
+        if &header.hash != trusted_hash {
+            return Err(ProofError::Mismatch("block hash".to_string()));
+        }
+        Ok(header)
+    }
+}
+
+/// Log emitted by a transaction
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct EthLog {
+    /// Contract that emitted the log
+    #[serde(serialize_with = "serialize_hex")]
+    pub address: [u8; 20],
+    /// Topics
+    #[serde(serialize_with = "serialize_hex_list")]
+    pub topics: Vec<[u8; 32]>,
+    /// Data
+    #[serde(serialize_with = "serialize_hex")]
+    pub data: Vec<u8>,
+}
+
+/// Transaction receipt
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EthReceipt {
+    /// EIP-2718 transaction type, 0 for legacy transactions
+    pub tx_type: u8,
+    /// Whether the transaction succeeded; receipts from before Byzantium hold a state root
+    /// instead and count as succeeded
+    pub success: bool,
+    /// Gas used by the block up to and including the transaction
+    pub cumulative_gas_used: u64,
+    /// Logs
+    pub logs: Vec<EthLog>,
+}
+
+/// Account state
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EthAccount {
+    /// Nonce
+    pub nonce: u64,
+    /// Balance in wei, big-endian
+    #[serde(serialize_with = "serialize_quantity")]
+    pub balance: Vec<u8>,
+    /// Root of the storage trie
+    #[serde(serialize_with = "serialize_hex")]
+    pub storage_root: [u8; 32],
+    /// Hash of the code
+    #[serde(serialize_with = "serialize_hex")]
+    pub code_hash: [u8; 32],
+}
+
+fn serialize_hex_list<S: Serializer>(hashes: &[[u8; 32]], s: S) -> Result<S::Ok, S::Error> {
+    s.collect_seq(hashes.iter().map(|hash| format!("0x{}", hex::encode(hash))))
+}
+
+/// Serialize a big-endian integer as a JSON-RPC quantity
+fn serialize_quantity<S: Serializer>(bytes: &[u8], s: S) -> Result<S::Ok, S::Error> {
+    let digits = hex::encode(bytes);
+    let digits = digits.trim_start_matches('0');
+    s.serialize_str(&format!(
+        "0x{}",
+        if digits.is_empty() { "0" } else { digits }
+    ))
+}
+
+/// Reference from a trie node to a child
+#[derive(Clone, Copy)]
+enum Child<'a> {
+    Empty,
+    Hash([u8; 32]),
+    /// Node shorter than a hash, embedded in its parent
+    Inline(&'a [u8]),
+}
+
+fn child<'a>(item: &Item<'a>) -> Result<Child<'a>, ProofError> {
+    match item.value {
+        Value::Bytes([]) => Ok(Child::Empty),
+        Value::Bytes(hash) if hash.len() == 32 => Ok(Child::Hash(item.fixed()?)),
+        Value::List(_) if item.raw.len() < 32 => Ok(Child::Inline(item.raw)),
+        _ => Err(ProofError::Malformed("trie: invalid child".to_string())),
+    }
+}
+
+fn nibbles(key: &[u8]) -> Vec<u8> {
+    key.iter().flat_map(|b| [b >> 4, b & 0x0f]).collect()
+}
+
+/// Decode the hex-prefix encoded path of a leaf or extension node, and whether it is a leaf
+fn hex_prefix(encoded: &[u8]) -> Result<(bool, Vec<u8>), ProofError> {
+    let first = *encoded
+        .first()
+        .ok_or_else(|| ProofError::Malformed("trie: empty path".to_string()))?;
+    let flag = first >> 4;
+    if flag > 3 {
+        return Err(ProofError::Malformed("trie: invalid path flag".to_string()));
+    }
+
+    let mut path = nibbles(&encoded[1..]);
+    if flag & 1 == 1 {
+        path.insert(0, first & 0x0f);
+    }
+    Ok((flag & 2 == 2, path))
+}
+
+/// Verify a Merkle-Patricia proof of a key against a trie root, returning the value of the
+/// key, or `None` if the proof shows the key is not set. Nodes of the proof are in order from
+/// the root; nodes embedded in their parent are not part of it.
+pub fn verify_proof(
+    root: &[u8; 32],
+    key: &[u8],
+    proof: &[Vec<u8>],
+) -> Result<Option<Vec<u8>>, ProofError> {
+    if proof.is_empty() && root == &EMPTY_ROOT {
+        return Ok(None);
+    }
+
+    let path = nibbles(key);
+    let mut path = &path[..];
+    let mut nodes = proof.iter();
+    let mut next = Child::Hash(*root);
+    loop {
+        let encoded: &[u8] = match next {
+            Child::Empty => return Ok(None),
+            Child::Hash(hash) => {
+                let node = nodes.next().ok_or_else(|| {
+                    ProofError::Malformed("trie: proof ends before the key".to_string())
+                })?;
+                if keccak256(node) != hash {
+                    return Err(ProofError::Mismatch("trie node".to_string()));
+                }
+                node
+            }
+            Child::Inline(node) => node,
+        };
+
+        let node = rlp::decode(encoded)?;
+        let items = node.list()?;
+        match items.len() {
+            17 => {
+                let Some((nibble, rest)) = path.split_first() else {
+                    let value = items[16].bytes()?;
+                    return Ok((!value.is_empty()).then(|| value.to_vec()));
+                };
+                next = child(&items[*nibble as usize])?;
+                path = rest;
+            }
+            2 => {
+                let (leaf, key) = hex_prefix(items[0].bytes()?)?;
+                if leaf {
+                    return Ok((key == path)
+                        .then(|| items[1].bytes().map(<[u8]>::to_vec))
+                        .transpose()?);
+                }
+                let Some(rest) = path.strip_prefix(&key[..]) else {
+                    return Ok(None);
+                };
+                next = child(&items[1])?;
+                path = rest;
+            }
+            n => {
+                return Err(ProofError::Malformed(format!(
+                    "trie: node with {} items",
+                    n
+                )))
+            }
+        }
+    }
+}
+
+/// Verify a transaction against the transaction root of a header, returning its hash
+pub fn verify_transaction(
+    transactions_root: &[u8; 32],
+    index: u64,
+    proof: &[Vec<u8>],
+) -> Result<[u8; 32], ProofError> {
+    let transaction = verify_proof(transactions_root, &rlp::encode_uint(index), proof)?
+        .ok_or(ProofError::NotFound)?;
+    Ok(keccak256(&transaction))
+}
+
+/// Verify a receipt against the receipt root of a header
+pub fn verify_receipt(
+    receipts_root: &[u8; 32],
+    index: u64,
+    proof: &[Vec<u8>],
+) -> Result<EthReceipt, ProofError> {
+    let receipt = verify_proof(receipts_root, &rlp::encode_uint(index), proof)?
+        .ok_or(ProofError::NotFound)?;
+    decode_receipt(&receipt)
+}
+
+fn decode_receipt(encoded: &[u8]) -> Result<EthReceipt, ProofError> {
+    // Typed receipts are the type byte followed by the encoded receipt
+    let (tx_type, encoded) = match encoded.first() {
+        Some(tx_type) if *tx_type < 0x80 => (*tx_type, &encoded[1..]),
+        _ => (0, encoded),
+    };
+
+    let receipt = rlp::decode(encoded)?;
+    let fields = receipt.list()?;
+    if fields.len() != 4 {
+        return Err(ProofError::Malformed(format!(
+            "receipt: {} fields",
+            fields.len()
+        )));
+    }
+
+    let status = fields[0].bytes()?;
+    let logs = fields[3]
+        .list()?
+        .iter()
+        .map(|log| {
+            let fields = log.list()?;
+            if fields.len() != 3 {
+                return Err(ProofError::Malformed("log: expected 3 fields".to_string()));
+            }
+            Ok(EthLog {
+                address: fields[0].fixed()?,
+                topics: fields[1]
+                    .list()?
+                    .iter()
+                    .map(Item::fixed)
+                    .collect::<Result<_, _>>()?,
+                data: fields[2].bytes()?.to_vec(),
+            })
+        })
+        .collect::<Result<_, ProofError>>()?;
+
+    Ok(EthReceipt {
+        tx_type,
+        success: status == [1] || status.len() == 32,
+        cumulative_gas_used: fields[1].uint()?,
+        logs,
+    })
+}
+
+/// Verify an account against the state root of a header, `None` if the account does not
+/// exist
+pub fn verify_account(
+    state_root: &[u8; 32],
+    address: &[u8; 20],
+    proof: &[Vec<u8>],
+) -> Result<Option<EthAccount>, ProofError> {
+    let Some(account) = verify_proof(state_root, &keccak256(address), proof)? else {
+        return Ok(None);
+    };
+
+    let account = rlp::decode(&account)?;
+    let fields = account.list()?;
+    if fields.len() != 4 {
+        return Err(ProofError::Malformed(format!(
+            "account: {} fields",
+            fields.len()
+        )));
+    }
+    Ok(Some(EthAccount {
+        nonce: fields[0].uint()?,
+        balance: fields[1].bytes()?.to_vec(),
+        storage_root: fields[2].fixed()?,
+        code_hash: fields[3].fixed()?,
+    }))
+}
+
+/// Verify a storage slot against the storage root of an account, returning its value; slots
+/// never set are zero
+pub fn verify_storage(
+    storage_root: &[u8; 32],
+    slot: &[u8; 32],
+    proof: &[Vec<u8>],
+) -> Result<[u8; 32], ProofError> {
+    let mut value = [0u8; 32];
+    if let Some(encoded) = verify_proof(storage_root, &keccak256(slot), proof)? {
+        let bytes = rlp::decode(&encoded)?.bytes()?;
+        if bytes.len() > 32 {
+            return Err(ProofError::Malformed(
+                "storage: value exceeds 32 bytes".to_string(),
+            ));
+        }
+        value[32 - bytes.len()..].copy_from_slice(bytes);
+    }
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::rlp::{encode_bytes, encode_list, encode_uint};
+    use super::*;
+
+    fn hex_prefix_encode(path: &[u8], leaf: bool) -> Vec<u8> {
+        let flag = u8::from(leaf) * 2 + (path.len() % 2) as u8;
+        let mut nibbles = vec![flag];
+        if path.len() % 2 == 0 {
+            nibbles.push(0);
+        }
+        nibbles.extend_from_slice(path);
+        nibbles.chunks(2).map(|n| (n[0] << 4) | n[1]).collect()
+    }
+
+    fn leaf(path: &[u8], value: &[u8]) -> Vec<u8> {
+        encode_list(&[
+            encode_bytes(&hex_prefix_encode(path, true)),
+            encode_bytes(value),
+        ])
+    }
+
+    fn receipt(success: bool, topic: u8) -> Vec<u8> {
+        let log = encode_list(&[
+            encode_bytes(&[0x11; 20]),
+            encode_list(&[encode_bytes(&[topic; 32])]),
+            encode_bytes(b"deposit"),
+        ]);
+        let mut encoded = vec![0x02];
+        encoded.extend(encode_list(&[
+            encode_uint(success as u64),
+            encode_uint(21000),
+            encode_bytes(&[0; 256]),
+            encode_list(&[log]),
+        ]));
+        encoded
+    }
+
+    #[test]
+    fn test_receipt_proofs() {
+        // Receipts 1 and 2 share the nibble 0 of their keys, under an extension
+        let leaf1 = leaf(&[], &receipt(true, 0xaa));
+        let leaf2 = leaf(&[], &receipt(false, 0xbb));
+        let mut children = vec![encode_bytes(&[]); 17];
+        children[1] = encode_bytes(&keccak256(&leaf1));
+        children[2] = encode_bytes(&keccak256(&leaf2));
+        let branch = encode_list(&children);
+        let extension = encode_list(&[
+            encode_bytes(&hex_prefix_encode(&[0], false)),
+            encode_bytes(&keccak256(&branch)),
+        ]);
+        let root = keccak256(&extension);
+
+        let proof = vec![extension.clone(), branch.clone(), leaf1];
+        let receipt1 = verify_receipt(&root, 1, &proof).unwrap();
+        assert_eq!(receipt1.tx_type, 2);
+        assert!(receipt1.success);
+        assert_eq!(receipt1.cumulative_gas_used, 21000);
+        assert_eq!(receipt1.logs[0].address, [0x11; 20]);
+        assert_eq!(receipt1.logs[0].topics, vec![[0xaa; 32]]);
+        assert_eq!(receipt1.logs[0].data, b"deposit");
+
+        let proof = vec![extension.clone(), branch.clone(), leaf2];
+        assert!(!verify_receipt(&root, 2, &proof).unwrap().success);
+
+        // Absent from the branch
+        let proof = vec![extension.clone(), branch.clone()];
+        assert_eq!(verify_receipt(&root, 3, &proof), Err(ProofError::NotFound));
+
+        // A forged receipt does not hash into the branch
+        let proof = vec![extension, branch, leaf(&[], &receipt(true, 0xcc))];
+        assert!(matches!(
+            verify_receipt(&root, 1, &proof),
+            Err(ProofError::Mismatch(_))
+        ));
+    }
+
+    #[test]
+    fn test_account_and_storage_proofs() {
+        let slot = [0u8; 32];
+        let storage_leaf = leaf(&nibbles(&keccak256(&slot)), &encode_uint(0x2a));
+        let storage_root = keccak256(&storage_leaf);
+        let value = verify_storage(&storage_root, &slot, &[storage_leaf.clone()]).unwrap();
+        assert_eq!(value[31], 0x2a);
+        assert_eq!(
+            verify_storage(&storage_root, &[1; 32], &[storage_leaf]).unwrap(),
+            [0; 32]
+        );
+
+        let address = [0x22; 20];
+        let account = encode_list(&[
+            encode_uint(7),
+            encode_uint(1_000_000),
+            encode_bytes(&storage_root),
+            encode_bytes(&keccak256(b"")),
+        ]);
+        let account_leaf = leaf(&nibbles(&keccak256(&address)), &account);
+        let state_root = keccak256(&account_leaf);
+        let account = verify_account(&state_root, &address, &[account_leaf])
+            .unwrap()
+            .unwrap();
+        assert_eq!(account.nonce, 7);
+        assert_eq!(account.storage_root, storage_root);
+        assert_eq!(
+            serde_json::to_value(&account).unwrap()["balance"],
+            "0xf4240"
+        );
+
+        assert_eq!(verify_account(&EMPTY_ROOT, &address, &[]), Ok(None));
+    }
+
+    #[test]
+    fn test_header() {
+        let mut fields = vec![encode_bytes(&[0x01; 32])];
+        fields.extend((0..15).map(|i| match i {
+            2 | 3 | 4 => encode_bytes(&[i as u8; 32]),
+            7 => encode_uint(19_000_000),
+            10 => encode_uint(1_700_000_000),
+            _ => encode_uint(i),
+        }));
+        let encoded = encode_list(&fields);
+        let hash = keccak256(&encoded);
+
+        let header = EthHeader::verify(&encoded, &hash).unwrap();
+        assert_eq!(header.parent_hash, [0x01; 32]);
+        assert_eq!(header.state_root, [2; 32]);
+        assert_eq!(header.receipts_root, [4; 32]);
+        assert_eq!(header.number, 19_000_000);
+        assert_eq!(header.timestamp, 1_700_000_000);
+        assert_eq!(
+            EthHeader::verify(&encoded, &[0; 32]),
+            Err(ProofError::Mismatch("block hash".to_string()))
+        );
+    }
+}
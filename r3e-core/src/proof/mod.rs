@@ -0,0 +1,60 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+//! Verification of cross-chain facts without trusting the node that served them.
+//!
+//! The checks are those of a light client. [`ethereum`] verifies block headers against a
+//! trusted block hash, then transactions, receipts, accounts and storage slots against the
+//! Merkle-Patricia roots of a verified header. [`neo`] verifies block headers and state roots
+//! against the script hash of the consensus nodes trusted to sign them, then transactions
+//! against the Merkle root of a verified header and storage items against a verified state
+//! root. The trusted hashes are for the caller to source independently of the node serving
+//! the proofs, such as from a checkpoint or from the previous verified header.
+
+pub mod ethereum;
+pub mod neo;
+mod rlp;
+
+use serde::Serializer;
+use thiserror::Error;
+
+/// Proof verification error
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum ProofError {
+    /// The proof or the data proven could not be decoded
+    #[error("proof: malformed {0}")]
+    Malformed(String),
+
+    /// The proof does not lead to the trusted root or hash
+    #[error("proof: {0} does not match")]
+    Mismatch(String),
+
+    /// The proof shows the key is not in the trie
+    #[error("proof: key not found")]
+    NotFound,
+
+    /// The witness is not signed by the trusted signers
+    #[error("proof: invalid witness: {0}")]
+    Witness(String),
+}
+
+/// Decode a 32-byte hash from hex, with or without `0x`
+pub fn parse_hash(hash: &str) -> Result<[u8; 32], ProofError> {
+    parse_hex(hash)?
+        .try_into()
+        .map_err(|_| ProofError::Malformed(format!("hash {}", hash)))
+}
+
+/// Decode hex, with or without `0x`
+pub fn parse_hex(data: &str) -> Result<Vec<u8>, ProofError> {
+    hex::decode(data.trim_start_matches("0x"))
+        .map_err(|e| ProofError::Malformed(format!("hex: {}", e)))
+}
+
+/// Serialize bytes as `0x` prefixed hex
+pub(crate) fn serialize_hex<S: Serializer>(
+    bytes: &impl AsRef<[u8]>,
+    s: S,
+) -> Result<S::Ok, S::Error> {
+    s.serialize_str(&format!("0x{}", hex::encode(bytes)))
+}
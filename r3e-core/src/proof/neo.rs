@@ -0,0 +1,759 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+//! Neo N3 block headers, state roots and their proofs.
+//!
+//! Headers and state roots carry the witness of the consensus nodes that signed them: an
+//! invocation script pushing the signatures and the multi-signature verification script of the
+//! nodes, whose script hash a verified header names as `next_consensus` for the next block.
+//! Starting from a trusted script hash, each header verified hands over the one to verify the
+//! next with. Transactions are then verified against the Merkle root of a header, and storage
+//! items against a state root with the proofs of `getproof`.
+
+use std::collections::HashMap;
+
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
+use p256::ecdsa::signature::Verifier;
+use p256::ecdsa::{Signature, VerifyingKey};
+use ripemd::Ripemd160;
+use serde::{Serialize, Serializer};
+use sha2::{Digest, Sha256};
+
+use super::ProofError;
+
+/// Network magic of the N3 MainNet
+pub const MAINNET_MAGIC: u32 = 860_833_102;
+
+/// Network magic of the N3 TestNet
+pub const TESTNET_MAGIC: u32 = 894_710_606;
+
+/// Version byte of N3 addresses
+const ADDRESS_VERSION: u8 = 0x35;
+
+// VM opcodes of verification and invocation scripts
+const PUSHINT8: u8 = 0x00;
+const PUSHINT16: u8 = 0x01;
+const PUSH1: u8 = 0x11;
+const PUSH16: u8 = 0x20;
+const PUSHDATA1: u8 = 0x0c;
+const SYSCALL: u8 = 0x41;
+
+/// Most signers of a multi-signature script
+const MAX_SIGNERS: usize = 1024;
+
+/// Longest key of the state trie, in bytes
+const MAX_STATE_KEY: usize = 64 + 4;
+
+/// Single SHA-256, the hash of headers and state roots
+fn sha256(data: &[u8]) -> [u8; 32] {
+    Sha256::digest(data).into()
+}
+
+/// Double SHA-256, the hash of Merkle tree and trie nodes
+pub fn hash256(data: &[u8]) -> [u8; 32] {
+    sha256(&sha256(data))
+}
+
+/// Script hash of a verification script
+pub fn script_hash(script: &[u8]) -> [u8; 20] {
+    Ripemd160::digest(Sha256::digest(script)).into()
+}
+
+/// Parse a script hash from an address or from its `0x` prefixed big-endian hex
+pub fn parse_script_hash(value: &str) -> Result<[u8; 20], ProofError> {
+    if let Some(hex) = value.strip_prefix("0x") {
+        let mut hash: [u8; 20] = super::parse_hex(hex)?
+            .try_into()
+            .map_err(|_| ProofError::Malformed(format!("script hash {}", value)))?;
+        hash.reverse();
+        return Ok(hash);
+    }
+
+    let decoded = bs58::decode(value)
+        .with_check(None)
+        .into_vec()
+        .map_err(|e| ProofError::Malformed(format!("address {}: {}", value, e)))?;
+    match decoded.split_first() {
+        Some((&ADDRESS_VERSION, hash)) => hash
+            .try_into()
+            .map_err(|_| ProofError::Malformed(format!("address {}", value))),
+        _ => Err(ProofError::Malformed(format!("address {}", value))),
+    }
+}
+
+/// Parse a hash from its `0x` prefixed big-endian hex, as N3 displays hashes
+pub fn parse_uint256(value: &str) -> Result<[u8; 32], ProofError> {
+    let mut hash = super::parse_hash(value)?;
+    hash.reverse();
+    Ok(hash)
+}
+
+/// Serialize a little-endian hash as N3 displays it
+fn serialize_reversed<S: Serializer>(hash: &impl AsRef<[u8]>, s: S) -> Result<S::Ok, S::Error> {
+    let mut hash = hash.as_ref().to_vec();
+    hash.reverse();
+    s.serialize_str(&format!("0x{}", hex::encode(hash)))
+}
+
+fn serialize_base64<S: Serializer>(bytes: &[u8], s: S) -> Result<S::Ok, S::Error> {
+    s.serialize_str(&BASE64.encode(bytes))
+}
+
+/// Reader of the N3 binary serialization
+struct Reader<'a> {
+    data: &'a [u8],
+    what: &'static str,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8], what: &'static str) -> Self {
+        Self { data, what }
+    }
+
+    fn malformed(&self) -> ProofError {
+        ProofError::Malformed(self.what.to_string())
+    }
+
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], ProofError> {
+        if self.data.len() < len {
+            return Err(self.malformed());
+        }
+        let (bytes, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(bytes)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], ProofError> {
+        Ok(self.bytes(N)?.try_into().expect("N bytes"))
+    }
+
+    fn u8(&mut self) -> Result<u8, ProofError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32, ProofError> {
+        Ok(u32::from_le_bytes(self.array()?))
+    }
+
+    fn u64(&mut self) -> Result<u64, ProofError> {
+        Ok(u64::from_le_bytes(self.array()?))
+    }
+
+    fn var_int(&mut self, max: u64) -> Result<u64, ProofError> {
+        let value = match self.u8()? {
+            0xfd => u16::from_le_bytes(self.array()?) as u64,
+            0xfe => self.u32()? as u64,
+            0xff => self.u64()?,
+            n => n as u64,
+        };
+        if value > max {
+            return Err(self.malformed());
+        }
+        Ok(value)
+    }
+
+    fn var_bytes(&mut self, max: usize) -> Result<&'a [u8], ProofError> {
+        let len = self.var_int(max as u64)? as usize;
+        self.bytes(len)
+    }
+
+    fn finish(&self) -> Result<(), ProofError> {
+        if self.data.is_empty() {
+            Ok(())
+        } else {
+            Err(self.malformed())
+        }
+    }
+}
+
+/// Witness of a header or state root
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct NeoWitness {
+    /// Script pushing the signatures
+    #[serde(serialize_with = "serialize_base64")]
+    pub invocation: Vec<u8>,
+    /// Script checking the signatures
+    #[serde(serialize_with = "serialize_base64")]
+    pub verification: Vec<u8>,
+}
+
+impl NeoWitness {
+    fn read(reader: &mut Reader) -> Result<Self, ProofError> {
+        // A single witness, as headers and state roots have a single signer
+        if reader.var_int(1)? != 1 {
+            return Err(reader.malformed());
+        }
+        Ok(Self {
+            invocation: reader.var_bytes(1024)?.to_vec(),
+            verification: reader.var_bytes(1024)?.to_vec(),
+        })
+    }
+
+    /// Check the witness signs `hash` on `network`, with a verification script of the trusted
+    /// script hash
+    pub fn verify(
+        &self,
+        network: u32,
+        hash: &[u8; 32],
+        trusted: &[u8; 20],
+    ) -> Result<(), ProofError> {
+        if &script_hash(&self.verification) != trusted {
+            return Err(ProofError::Mismatch("consensus script hash".to_string()));
+        }
+
+        let (threshold, keys) = verification_keys(&self.verification)?;
+        let signatures = invocation_signatures(&self.invocation)?;
+        if signatures.len() != threshold {
+            return Err(ProofError::Witness(format!(
+                "{} signatures, {} required",
+                signatures.len(),
+                threshold
+            )));
+        }
+
+        let mut message = network.to_le_bytes().to_vec();
+        message.extend_from_slice(hash);
+
+        // As CheckMultisig, signatures are in the order of their keys
+        let mut keys = keys.iter();
+        for signature in &signatures {
+            let signature = Signature::from_slice(signature)
+                .map_err(|e| ProofError::Witness(format!("invalid signature: {}", e)))?;
+            if !keys.any(|key| key.verify(&message, &signature).is_ok()) {
+                return Err(ProofError::Witness("signature does not verify".to_string()));
+            }
+        }
+        Ok(())
+    }
+}
+
+fn read_push_int(reader: &mut Reader) -> Result<usize, ProofError> {
+    let value = match reader.u8()? {
+        op @ PUSH1..=PUSH16 => (op - PUSH1 + 1) as i64,
+        PUSHINT8 => reader.u8()? as i8 as i64,
+        PUSHINT16 => i16::from_le_bytes(reader.array()?) as i64,
+        _ => return Err(reader.malformed()),
+    };
+    usize::try_from(value).map_err(|_| reader.malformed())
+}
+
+/// Interop hash of a syscall, the first 4 bytes of the SHA-256 of its name
+fn syscall(name: &str) -> [u8; 4] {
+    sha256(name.as_bytes())[..4].try_into().expect("4 bytes")
+}
+
+/// Threshold and keys of a single or multi-signature verification script
+fn verification_keys(script: &[u8]) -> Result<(usize, Vec<VerifyingKey>), ProofError> {
+    let mut reader = Reader::new(script, "verification script");
+    let key = |reader: &mut Reader| -> Result<VerifyingKey, ProofError> {
+        if reader.bytes(2)? != [PUSHDATA1, 33] {
+            return Err(reader.malformed());
+        }
+        VerifyingKey::from_sec1_bytes(reader.bytes(33)?)
+            .map_err(|e| ProofError::Witness(format!("invalid public key: {}", e)))
+    };
+
+    if script.first() == Some(&PUSHDATA1) {
+        let key = key(&mut reader)?;
+        if reader.u8()? != SYSCALL || reader.array()? != syscall("System.Crypto.CheckSig") {
+            return Err(reader.malformed());
+        }
+        reader.finish()?;
+        return Ok((1, vec![key]));
+    }
+
+    let threshold = read_push_int(&mut reader)?;
+    let mut keys = Vec::new();
+    while reader.data.first() == Some(&PUSHDATA1) && keys.len() < MAX_SIGNERS {
+        keys.push(key(&mut reader)?);
+    }
+    if read_push_int(&mut reader)? != keys.len()
+        || threshold == 0
+        || threshold > keys.len()
+        || reader.u8()? != SYSCALL
+        || reader.array()? != syscall("System.Crypto.CheckMultisig")
+    {
+        return Err(reader.malformed());
+    }
+    reader.finish()?;
+    Ok((threshold, keys))
+}
+
+/// Signatures an invocation script pushes
+fn invocation_signatures(script: &[u8]) -> Result<Vec<&[u8]>, ProofError> {
+    let mut reader = Reader::new(script, "invocation script");
+    let mut signatures = Vec::new();
+    while !reader.data.is_empty() {
+        if reader.bytes(2)? != [PUSHDATA1, 64] {
+            return Err(reader.malformed());
+        }
+        signatures.push(reader.bytes(64)?);
+    }
+    Ok(signatures)
+}
+
+/// Block header
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NeoHeader {
+    /// Block hash
+    #[serde(serialize_with = "serialize_reversed")]
+    pub hash: [u8; 32],
+    /// Version
+    pub version: u32,
+    /// Hash of the previous block
+    #[serde(serialize_with = "serialize_reversed")]
+    pub prev_hash: [u8; 32],
+    /// Merkle root of the transactions
+    #[serde(serialize_with = "serialize_reversed")]
+    pub merkle_root: [u8; 32],
+    /// Block timestamp (millis since epoch)
+    pub timestamp: u64,
+    /// Nonce
+    pub nonce: u64,
+    /// Block index
+    pub index: u32,
+    /// Index of the consensus node that proposed the block
+    pub primary_index: u8,
+    /// Script hash of the consensus nodes signing the next block
+    #[serde(serialize_with = "serialize_reversed")]
+    pub next_consensus: [u8; 20],
+    /// Witness of the consensus nodes
+    pub witness: NeoWitness,
+}
+
+impl NeoHeader {
+    /// Decode a serialized header, as `getblockheader` returns it when not verbose
+    pub fn decode(data: &[u8]) -> Result<Self, ProofError> {
+        let mut reader = Reader::new(data, "header");
+        let version = reader.u32()?;
+        let prev_hash = reader.array()?;
+        let merkle_root = reader.array()?;
+        let timestamp = reader.u64()?;
+        let nonce = reader.u64()?;
+        let index = reader.u32()?;
+        let primary_index = reader.u8()?;
+        let next_consensus = reader.array()?;
+        let unsigned = &data[..data.len() - reader.data.len()];
+        let witness = NeoWitness::read(&mut reader)?;
+        reader.finish()?;
+
+        Ok(Self {
+            hash: sha256(unsigned),
+            version,
+            prev_hash,
+            merkle_root,
+            timestamp,
+            nonce,
+            index,
+            primary_index,
+            next_consensus,
+            witness,
+        })
+    }
+
+    /// Decode a serialized header, checking it is signed on `network` by the consensus nodes
+    /// of the trusted script hash: the `next_consensus` of the previous header
+    pub fn verify(data: &[u8], network: u32, trusted: &[u8; 20]) -> Result<Self, ProofError> {
+        let header = Self::decode(data)?;
+        header.witness.verify(network, &header.hash, trusted)?;
+        Ok(header)
+    }
+
+    /// Check a transaction is in the block, given its index and Merkle path
+    pub fn verify_transaction(
+        &self,
+        tx_hash: &[u8; 32],
+        index: usize,
+        path: &[[u8; 32]],
+    ) -> Result<(), ProofError> {
+        verify_transaction(&self.merkle_root, tx_hash, index, path)
+    }
+}
+
+/// State root, signed by the state validators
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NeoStateRoot {
+    /// Hash of the state root
+    #[serde(serialize_with = "serialize_reversed")]
+    pub hash: [u8; 32],
+    /// Version
+    pub version: u8,
+    /// Block index
+    pub index: u32,
+    /// Root of the state trie after the block
+    #[serde(serialize_with = "serialize_reversed")]
+    pub root_hash: [u8; 32],
+    /// Witness of the state validators
+    pub witness: NeoWitness,
+}
+
+impl NeoStateRoot {
+    /// State root of its parts, as `getstateroot` returns them
+    pub fn new(version: u8, index: u32, root_hash: [u8; 32], witness: NeoWitness) -> Self {
+        let mut unsigned = vec![version];
+        unsigned.extend_from_slice(&index.to_le_bytes());
+        unsigned.extend_from_slice(&root_hash);
+        Self {
+            hash: sha256(&unsigned),
+            version,
+            index,
+            root_hash,
+            witness,
+        }
+    }
+
+    /// Decode a serialized state root
+    pub fn decode(data: &[u8]) -> Result<Self, ProofError> {
+        let mut reader = Reader::new(data, "state root");
+        let version = reader.u8()?;
+        let index = reader.u32()?;
+        let root_hash = reader.array()?;
+        let witness = NeoWitness::read(&mut reader)?;
+        reader.finish()?;
+        Ok(Self::new(version, index, root_hash, witness))
+    }
+
+    /// Check the state root is signed on `network` by the state validators of the trusted
+    /// script hash
+    pub fn verify(&self, network: u32, trusted: &[u8; 20]) -> Result<(), ProofError> {
+        self.witness.verify(network, &self.hash, trusted)
+    }
+}
+
+/// Merkle root of transaction hashes, the last hash of an odd level paired with itself
+pub fn merkle_root(hashes: &[[u8; 32]]) -> [u8; 32] {
+    let mut level = hashes.to_vec();
+    if level.is_empty() {
+        return [0; 32];
+    }
+    while level.len() > 1 {
+        level = level
+            .chunks(2)
+            .map(|pair| merkle_parent(&pair[0], pair.get(1).unwrap_or(&pair[0])))
+            .collect();
+    }
+    level[0]
+}
+
+/// Merkle path of the transaction at `index`, the sibling hashes from the leaf up
+pub fn merkle_path(hashes: &[[u8; 32]], mut index: usize) -> Option<Vec<[u8; 32]>> {
+    if index >= hashes.len() {
+        return None;
+    }
+
+    let mut path = Vec::new();
+    let mut level = hashes.to_vec();
+    while level.len() > 1 {
+        path.push(*level.get(index ^ 1).unwrap_or(&level[index]));
+        level = level
+            .chunks(2)
+            .map(|pair| merkle_parent(&pair[0], pair.get(1).unwrap_or(&pair[0])))
+            .collect();
+        index /= 2;
+    }
+    Some(path)
+}
+
+fn merkle_parent(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+    let mut pair = [0u8; 64];
+    pair[..32].copy_from_slice(left);
+    pair[32..].copy_from_slice(right);
+    hash256(&pair)
+}
+
+/// Check a transaction is under a Merkle root, given its index and Merkle path
+pub fn verify_transaction(
+    merkle_root: &[u8; 32],
+    tx_hash: &[u8; 32],
+    index: usize,
+    path: &[[u8; 32]],
+) -> Result<(), ProofError> {
+    if &merkle_root_of_path(tx_hash, index, path) != merkle_root {
+        return Err(ProofError::Mismatch("merkle root".to_string()));
+    }
+    Ok(())
+}
+
+fn merkle_root_of_path(leaf: &[u8; 32], mut index: usize, path: &[[u8; 32]]) -> [u8; 32] {
+    let mut hash = *leaf;
+    for sibling in path {
+        hash = if index % 2 == 0 {
+            merkle_parent(&hash, sibling)
+        } else {
+            merkle_parent(sibling, &hash)
+        };
+        index /= 2;
+    }
+    hash
+}
+
+/// Storage item proven against a state root
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NeoStorageItem {
+    /// ID of the contract owning the item
+    pub contract_id: i32,
+    /// Storage key
+    #[serde(serialize_with = "super::serialize_hex")]
+    pub key: Vec<u8>,
+    /// Stored value
+    #[serde(serialize_with = "super::serialize_hex")]
+    pub value: Vec<u8>,
+}
+
+// Types of the nodes of the state trie
+const BRANCH_NODE: u8 = 0x00;
+const EXTENSION_NODE: u8 = 0x01;
+const LEAF_NODE: u8 = 0x02;
+const HASH_NODE: u8 = 0x03;
+const EMPTY_NODE: u8 = 0x04;
+
+/// Children of a branch node, the last one holding the value of the branch's own path
+const BRANCH_CHILDREN: usize = 17;
+
+/// Verify a storage item against the root hash of a verified state root, with a proof as
+/// `getproof` returns it: the storage key followed by the trie nodes on its path
+pub fn verify_state_proof(
+    root_hash: &[u8; 32],
+    proof: &[u8],
+) -> Result<NeoStorageItem, ProofError> {
+    let mut reader = Reader::new(proof, "state proof");
+    let key = reader.var_bytes(MAX_STATE_KEY)?;
+    if key.len() < 4 {
+        return Err(reader.malformed());
+    }
+    let count = reader.var_int(u8::MAX as u64)?;
+    let mut nodes = HashMap::new();
+    for _ in 0..count {
+        let node = reader.var_bytes(u16::MAX as usize)?;
+        nodes.insert(hash256(node), node);
+    }
+    reader.finish()?;
+
+    let path: Vec<u8> = key.iter().flat_map(|b| [b >> 4, b & 0x0f]).collect();
+    let mut path = &path[..];
+    let mut next = *root_hash;
+    loop {
+        let node = nodes
+            .get(&next)
+            .ok_or_else(|| ProofError::Malformed("state proof: missing node".to_string()))?;
+        let mut reader = Reader::new(node, "state trie node");
+        let child = match reader.u8()? {
+            LEAF_NODE => {
+                let value = reader.var_bytes(u16::MAX as usize)?;
+                if !path.is_empty() {
+                    return Err(ProofError::NotFound);
+                }
+                return Ok(NeoStorageItem {
+                    contract_id: i32::from_le_bytes(key[..4].try_into().expect("4 bytes")),
+                    key: key[4..].to_vec(),
+                    value: value.to_vec(),
+                });
+            }
+            BRANCH_NODE => {
+                let mut children = Vec::with_capacity(BRANCH_CHILDREN);
+                for _ in 0..BRANCH_CHILDREN {
+                    children.push(read_child(&mut reader)?);
+                }
+                match path.split_first() {
+                    Some((nibble, rest)) => {
+                        path = rest;
+                        children.swap_remove(*nibble as usize)
+                    }
+                    None => children.swap_remove(BRANCH_CHILDREN - 1),
+                }
+            }
+            EXTENSION_NODE => {
+                let key = reader.var_bytes(MAX_STATE_KEY * 2)?;
+                let child = read_child(&mut reader)?;
+                path = path.strip_prefix(key).ok_or(ProofError::NotFound)?;
+                child
+            }
+            _ => return Err(reader.malformed()),
+        };
+        next = child.ok_or(ProofError::NotFound)?;
+    }
+}
+
+/// Hash of a child of a trie node, `None` for an empty child
+fn read_child(reader: &mut Reader) -> Result<Option<[u8; 32]>, ProofError> {
+    match reader.u8()? {
+        HASH_NODE => Ok(Some(reader.array()?)),
+        EMPTY_NODE => Ok(None),
+        _ => Err(reader.malformed()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use p256::ecdsa::signature::Signer;
+    use p256::ecdsa::SigningKey;
+
+    fn multisig(keys: &[SigningKey], threshold: u8) -> Vec<u8> {
+        let mut script = vec![PUSH1 + threshold - 1];
+        for key in keys {
+            script.extend_from_slice(&[PUSHDATA1, 33]);
+            script.extend_from_slice(key.verifying_key().to_encoded_point(true).as_bytes());
+        }
+        script.push(PUSH1 + keys.len() as u8 - 1);
+        script.push(SYSCALL);
+        script.extend_from_slice(&syscall("System.Crypto.CheckMultisig"));
+        script
+    }
+
+    fn witness(keys: &[&SigningKey], message: &[u8], verification: &[u8]) -> Vec<u8> {
+        let mut invocation = Vec::new();
+        for key in keys {
+            let signature: Signature = key.sign(message);
+            invocation.extend_from_slice(&[PUSHDATA1, 64]);
+            invocation.extend_from_slice(&signature.to_bytes());
+        }
+        let mut witness = vec![1, invocation.len() as u8];
+        witness.extend(invocation);
+        witness.push(verification.len() as u8);
+        witness.extend_from_slice(verification);
+        witness
+    }
+
+    #[test]
+    fn test_verify_header() {
+        let keys: Vec<SigningKey> = (1..=4u8)
+            .map(|i| SigningKey::from_slice(&[i; 32]).unwrap())
+            .collect();
+        let verification = multisig(&keys, 3);
+        let consensus = script_hash(&verification);
+
+        let mut unsigned = 0u32.to_le_bytes().to_vec();
+        unsigned.extend_from_slice(&[0x11; 32]);
+        unsigned.extend_from_slice(&[0x22; 32]);
+        unsigned.extend_from_slice(&1_700_000_000_000u64.to_le_bytes());
+        unsigned.extend_from_slice(&42u64.to_le_bytes());
+        unsigned.extend_from_slice(&100u32.to_le_bytes());
+        unsigned.push(2);
+        unsigned.extend_from_slice(&consensus);
+        let mut message = TESTNET_MAGIC.to_le_bytes().to_vec();
+        message.extend_from_slice(&sha256(&unsigned));
+
+        let signed = |signers: &[&SigningKey]| {
+            let mut data = unsigned.clone();
+            data.extend(witness(signers, &message, &verification));
+            data
+        };
+
+        let header = NeoHeader::verify(
+            &signed(&[&keys[0], &keys[1], &keys[3]]),
+            TESTNET_MAGIC,
+            &consensus,
+        )
+        .unwrap();
+        assert_eq!(header.index, 100);
+        assert_eq!(header.primary_index, 2);
+        assert_eq!(header.merkle_root, [0x22; 32]);
+        assert_eq!(header.next_consensus, consensus);
+
+        // Too few signatures, signatures out of the order of the keys, another network and
+        // another consensus
+        assert!(matches!(
+            NeoHeader::verify(&signed(&[&keys[0], &keys[1]]), TESTNET_MAGIC, &consensus),
+            Err(ProofError::Witness(_))
+        ));
+        assert!(matches!(
+            NeoHeader::verify(
+                &signed(&[&keys[1], &keys[0], &keys[3]]),
+                TESTNET_MAGIC,
+                &consensus
+            ),
+            Err(ProofError::Witness(_))
+        ));
+        assert!(matches!(
+            NeoHeader::verify(
+                &signed(&[&keys[0], &keys[1], &keys[3]]),
+                MAINNET_MAGIC,
+                &consensus
+            ),
+            Err(ProofError::Witness(_))
+        ));
+        assert!(matches!(
+            NeoHeader::verify(
+                &signed(&[&keys[0], &keys[1], &keys[3]]),
+                TESTNET_MAGIC,
+                &[0; 20]
+            ),
+            Err(ProofError::Mismatch(_))
+        ));
+    }
+
+    #[test]
+    fn test_merkle_path() {
+        let hashes: Vec<[u8; 32]> = (0..5u8).map(|i| [i; 32]).collect();
+        let root = merkle_root(&hashes);
+        assert_eq!(merkle_root(&hashes[..1]), hashes[0]);
+
+        for (index, hash) in hashes.iter().enumerate() {
+            let path = merkle_path(&hashes, index).unwrap();
+            assert_eq!(merkle_root_of_path(hash, index, &path), root);
+        }
+        let path = merkle_path(&hashes, 4).unwrap();
+        assert_ne!(merkle_root_of_path(&hashes[3], 4, &path), root);
+        assert!(merkle_path(&hashes, 5).is_none());
+    }
+
+    #[test]
+    fn test_state_proof() {
+        let mut key = 5i32.to_le_bytes().to_vec();
+        key.push(0xab);
+
+        // Leaf under an extension of all but the last nibble, under a branch on the last
+        let leaf = [vec![LEAF_NODE, 3], b"neo".to_vec()].concat();
+        let mut branch = vec![BRANCH_NODE];
+        for i in 0..BRANCH_CHILDREN {
+            if i == 0x0b {
+                branch.push(HASH_NODE);
+                branch.extend_from_slice(&hash256(&leaf));
+            } else {
+                branch.push(EMPTY_NODE);
+            }
+        }
+        let nibbles: Vec<u8> = key.iter().flat_map(|b| [b >> 4, b & 0x0f]).collect();
+        let prefix = &nibbles[..nibbles.len() - 1];
+        let mut extension = vec![EXTENSION_NODE, prefix.len() as u8];
+        extension.extend_from_slice(prefix);
+        extension.push(HASH_NODE);
+        extension.extend_from_slice(&hash256(&branch));
+        let root = hash256(&extension);
+
+        let mut proof = vec![key.len() as u8];
+        proof.extend_from_slice(&key);
+        proof.push(3);
+        for node in [&extension, &branch, &leaf] {
+            proof.push(node.len() as u8);
+            proof.extend_from_slice(node);
+        }
+
+        let item = verify_state_proof(&root, &proof).unwrap();
+        assert_eq!(item.contract_id, 5);
+        assert_eq!(item.key, vec![0xab]);
+        assert_eq!(item.value, b"neo");
+
+        assert!(matches!(
+            verify_state_proof(&[0; 32], &proof),
+            Err(ProofError::Malformed(_))
+        ));
+    }
+
+    #[test]
+    fn test_parse_script_hash() {
+        let hash = parse_script_hash("0xef4073a0f2b305a38ec4050e4d3d28bc40ea63f5").unwrap();
+        assert_eq!(hash[0], 0xf5);
+        assert_eq!(hash[19], 0xef);
+
+        let mut payload = vec![ADDRESS_VERSION];
+        payload.extend_from_slice(&hash);
+        let address = bs58::encode(payload).with_check().into_string();
+        assert_eq!(parse_script_hash(&address).unwrap(), hash);
+        assert!(parse_script_hash("NotAnAddress").is_err());
+    }
+}
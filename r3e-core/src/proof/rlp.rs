@@ -0,0 +1,192 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+//! Recursive length prefix encoding, as Ethereum serializes headers, trie nodes and receipts.
+
+use super::ProofError;
+
+/// Decoded item, borrowing from the encoding
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Item<'a> {
+    /// Encoding of the item, prefix included
+    pub raw: &'a [u8],
+    /// Decoded value
+    pub value: Value<'a>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Value<'a> {
+    Bytes(&'a [u8]),
+    List(Vec<Item<'a>>),
+}
+
+impl<'a> Item<'a> {
+    /// Bytes of a string item
+    pub fn bytes(&self) -> Result<&'a [u8], ProofError> {
+        match self.value {
+            Value::Bytes(bytes) => Ok(bytes),
+            Value::List(_) => Err(malformed("expected a string")),
+        }
+    }
+
+    /// Items of a list item
+    pub fn list(&self) -> Result<&[Item<'a>], ProofError> {
+        match &self.value {
+            Value::List(items) => Ok(items),
+            Value::Bytes(_) => Err(malformed("expected a list")),
+        }
+    }
+
+    /// Big-endian integer of at most 8 bytes
+    pub fn uint(&self) -> Result<u64, ProofError> {
+        let bytes = self.bytes()?;
+        if bytes.len() > 8 {
+            return Err(malformed("integer exceeds 64 bits"));
+        }
+        Ok(bytes.iter().fold(0, |n, b| (n << 8) | *b as u64))
+    }
+
+    /// String of exactly `N` bytes
+    pub fn fixed<const N: usize>(&self) -> Result<[u8; N], ProofError> {
+        self.bytes()?
+            .try_into()
+            .map_err(|_| malformed(&format!("expected {} bytes", N)))
+    }
+}
+
+fn malformed(message: &str) -> ProofError {
+    ProofError::Malformed(format!("rlp: {}", message))
+}
+
+/// Decode an item spanning all of `data`
+pub fn decode(data: &[u8]) -> Result<Item<'_>, ProofError> {
+    let (item, rest) = decode_item(data)?;
+    if !rest.is_empty() {
+        return Err(malformed("trailing bytes"));
+    }
+    Ok(item)
+}
+
+fn decode_item(data: &[u8]) -> Result<(Item<'_>, &[u8]), ProofError> {
+    let prefix = *data.first().ok_or_else(|| malformed("unexpected end"))?;
+    let (is_list, offset, len) = match prefix {
+        0x00..=0x7f => (false, 0, 1),
+        0x80..=0xb7 => (false, 1, (prefix - 0x80) as usize),
+        0xb8..=0xbf => {
+            let len_len = (prefix - 0xb7) as usize;
+            (false, 1 + len_len, long_length(data, len_len)?)
+        }
+        0xc0..=0xf7 => (true, 1, (prefix - 0xc0) as usize),
+        0xf8..=0xff => {
+            let len_len = (prefix - 0xf7) as usize;
+            (true, 1 + len_len, long_length(data, len_len)?)
+        }
+    };
+
+    let end = offset
+        .checked_add(len)
+        .filter(|end| *end <= data.len())
+        .ok_or_else(|| malformed("unexpected end"))?;
+    let payload = &data[offset..end];
+    let value = if is_list {
+        let mut items = Vec::new();
+        let mut rest = payload;
+        while !rest.is_empty() {
+            let (item, next) = decode_item(rest)?;
+            items.push(item);
+            rest = next;
+        }
+        Value::List(items)
+    } else {
+        Value::Bytes(payload)
+    };
+
+    Ok((
+        Item {
+            raw: &data[..end],
+            value,
+        },
+        &data[end..],
+    ))
+}
+
+fn long_length(data: &[u8], len_len: usize) -> Result<usize, ProofError> {
+    let bytes = data
+        .get(1..1 + len_len)
+        .ok_or_else(|| malformed("unexpected end"))?;
+    if len_len > 8 || bytes[0] == 0 {
+        return Err(malformed("invalid length"));
+    }
+    let len = bytes.iter().fold(0u64, |n, b| (n << 8) | *b as u64);
+    usize::try_from(len).map_err(|_| malformed("invalid length"))
+}
+
+fn encode_length(len: usize, offset: u8) -> Vec<u8> {
+    if len <= 55 {
+        return vec![offset + len as u8];
+    }
+    let bytes = (len as u64).to_be_bytes();
+    let skip = bytes.iter().take_while(|b| **b == 0).count();
+    let mut prefix = vec![offset + 55 + (8 - skip) as u8];
+    prefix.extend_from_slice(&bytes[skip..]);
+    prefix
+}
+
+/// Encode a string
+pub fn encode_bytes(bytes: &[u8]) -> Vec<u8> {
+    if bytes.len() == 1 && bytes[0] < 0x80 {
+        return bytes.to_vec();
+    }
+    let mut encoded = encode_length(bytes.len(), 0x80);
+    encoded.extend_from_slice(bytes);
+    encoded
+}
+
+/// Encode an integer, as a big-endian string without leading zeros
+pub fn encode_uint(n: u64) -> Vec<u8> {
+    let bytes = n.to_be_bytes();
+    let skip = bytes.iter().take_while(|b| **b == 0).count();
+    encode_bytes(&bytes[skip..])
+}
+
+/// Encode a list of encoded items
+#[cfg(test)]
+pub fn encode_list(items: &[Vec<u8>]) -> Vec<u8> {
+    let payload = items.concat();
+    let mut encoded = encode_length(payload.len(), 0xc0);
+    encoded.extend_from_slice(&payload);
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_roundtrip() {
+        assert_eq!(encode_uint(0), vec![0x80]);
+        assert_eq!(encode_uint(0x7f), vec![0x7f]);
+        assert_eq!(encode_uint(0x400), vec![0x82, 0x04, 0x00]);
+        assert_eq!(encode_bytes(b"dog"), vec![0x83, b'd', b'o', b'g']);
+
+        let long = vec![0xaa; 60];
+        let encoded = encode_list(&[encode_bytes(b"cat"), encode_bytes(&long)]);
+        assert_eq!(&encoded[..2], &[0xf8, 4 + 62]);
+
+        let item = decode(&encoded).unwrap();
+        let items = item.list().unwrap();
+        assert_eq!(items[0].bytes().unwrap(), b"cat");
+        assert_eq!(items[1].bytes().unwrap(), &long[..]);
+        assert_eq!(items[1].raw, &encode_bytes(&long)[..]);
+        assert_eq!(decode(&encode_uint(0x400)).unwrap().uint().unwrap(), 0x400);
+    }
+
+    #[test]
+    fn test_malformed() {
+        assert!(decode(&[]).is_err());
+        assert!(decode(&[0x83, b'd']).is_err());
+        assert!(decode(&[0x80, 0x80]).is_err());
+        assert!(decode(&[0xb8, 0x00]).is_err());
+        assert!(decode(&[0xc2, 0x80]).is_err());
+    }
+}
@@ -0,0 +1,85 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+//! Per-tenant resource quotas.
+//!
+//! Crates owning a resource reserve it through a [`QuotaEnforcer`] before creating it and
+//! release it once it is deleted, so a tenant never holds more than its plan allows.
+
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+/// Resource limited by a quota
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum QuotaResource {
+    /// Deployed functions
+    Functions,
+    /// Stored secrets
+    Secrets,
+    /// Scheduled function triggers
+    Schedules,
+    /// Bytes of keys and values in function storage
+    StorageBytes,
+}
+
+impl QuotaResource {
+    /// All resources limited by quotas
+    pub const ALL: [QuotaResource; 4] = [
+        QuotaResource::Functions,
+        QuotaResource::Secrets,
+        QuotaResource::Schedules,
+        QuotaResource::StorageBytes,
+    ];
+
+    /// Name of the resource
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            QuotaResource::Functions => "functions",
+            QuotaResource::Secrets => "secrets",
+            QuotaResource::Schedules => "schedules",
+            QuotaResource::StorageBytes => "storage_bytes",
+        }
+    }
+}
+
+impl fmt::Display for QuotaResource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Quota error
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum QuotaError {
+    /// A reservation would exceed the tenant's limit
+    #[error("quota: {tenant} over its {resource} limit: {used} + {requested} > {limit}")]
+    Exceeded {
+        /// Tenant
+        tenant: String,
+        /// Resource
+        resource: QuotaResource,
+        /// Amount already used
+        used: u64,
+        /// Amount requested
+        requested: u64,
+        /// Limit of the tenant's plan
+        limit: u64,
+    },
+
+    /// Usage could not be read or recorded
+    #[error("quota: unavailable: {0}")]
+    Unavailable(String),
+}
+
+/// Enforces the resource limits of tenants
+pub trait QuotaEnforcer: Send + Sync {
+    /// Reserve an amount of a resource for a tenant, failing if it would exceed the limit
+    fn reserve(&self, tenant: &str, resource: QuotaResource, amount: u64)
+        -> Result<(), QuotaError>;
+
+    /// Release a reserved amount, e.g. once the resource is deleted
+    fn release(&self, tenant: &str, resource: QuotaResource, amount: u64);
+}
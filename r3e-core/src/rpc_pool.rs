@@ -0,0 +1,533 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+//! Failover pool of blockchain RPC endpoints.
+//!
+//! Each endpoint has a circuit breaker: after `failure_threshold` consecutive failures it
+//! opens and is skipped for `open_duration`, then admits a single trial request (half-open)
+//! which closes the circuit on success or re-opens it on failure. Calls go to the first
+//! available endpoint in configured order and fail over to the next one on error, so the
+//! first endpoint acts as the primary and is used again as soon as it recovers.
+
+use std::future::Future;
+use std::sync::{Arc, Mutex, Weak};
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+/// Weight of the latest sample in the latency and error rate averages
+const EWMA_ALPHA: f64 = 0.2;
+
+/// RPC pool error
+#[derive(Debug, Error)]
+pub enum RpcPoolError {
+    /// The pool has no endpoints
+    #[error("rpc pool: no endpoints configured for {0}")]
+    NoEndpoints(String),
+
+    /// Every endpoint has an open circuit
+    #[error("rpc pool: all endpoints of {0} are unavailable")]
+    Unavailable(String),
+
+    /// Every available endpoint failed the call
+    #[error("rpc pool: all endpoints of {chain} failed, last error: {last_error}")]
+    AllFailed {
+        /// Chain of the pool
+        chain: String,
+        /// Error of the last endpoint tried
+        last_error: String,
+    },
+}
+
+/// RPC pool configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RpcPoolConfig {
+    /// Consecutive failures opening the circuit of an endpoint
+    #[serde(default = "default_failure_threshold")]
+    pub failure_threshold: u32,
+
+    /// Time an open circuit rejects requests before admitting a trial request
+    #[serde(default = "default_open_duration", with = "duration_millis")]
+    pub open_duration: Duration,
+
+    /// Timeout of a single request to an endpoint
+    #[serde(default = "default_request_timeout", with = "duration_millis")]
+    pub request_timeout: Duration,
+
+    /// Interval of background health checks
+    #[serde(default = "default_health_check_interval", with = "duration_millis")]
+    pub health_check_interval: Duration,
+}
+
+fn default_failure_threshold() -> u32 {
+    3
+}
+
+fn default_open_duration() -> Duration {
+    Duration::from_secs(30)
+}
+
+fn default_request_timeout() -> Duration {
+    Duration::from_secs(10)
+}
+
+fn default_health_check_interval() -> Duration {
+    Duration::from_secs(15)
+}
+
+impl Default for RpcPoolConfig {
+    fn default() -> Self {
+        Self {
+            failure_threshold: default_failure_threshold(),
+            open_duration: default_open_duration(),
+            request_timeout: default_request_timeout(),
+            health_check_interval: default_health_check_interval(),
+        }
+    }
+}
+
+mod duration_millis {
+    use serde::{Deserialize, Deserializer, Serializer};
+    use std::time::Duration;
+
+    pub fn serialize<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u64(duration.as_millis() as u64)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+        u64::deserialize(deserializer).map(Duration::from_millis)
+    }
+}
+
+/// Circuit breaker state of an endpoint
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CircuitState {
+    /// Requests are sent normally
+    Closed,
+    /// Requests are rejected until the open duration elapses
+    Open,
+    /// A single trial request is in flight, another one is admitted if it does not complete
+    /// within the open duration
+    HalfOpen,
+}
+
+/// Metrics of an endpoint
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EndpointMetrics {
+    /// Endpoint URL
+    pub url: String,
+
+    /// Circuit breaker state
+    pub state: CircuitState,
+
+    /// Total requests
+    pub requests: u64,
+
+    /// Total failed requests
+    pub failures: u64,
+
+    /// Current consecutive failures
+    pub consecutive_failures: u32,
+
+    /// Moving average latency of successful requests, in milliseconds
+    pub latency_ms: f64,
+
+    /// Moving average error rate, between 0 and 1
+    pub error_rate: f64,
+
+    /// Last error
+    pub last_error: Option<String>,
+}
+
+#[derive(Debug)]
+struct EndpointState {
+    circuit: CircuitState,
+    opened_at: Option<Instant>,
+    trial_started_at: Option<Instant>,
+    requests: u64,
+    failures: u64,
+    consecutive_failures: u32,
+    latency_ms: f64,
+    error_rate: f64,
+    last_error: Option<String>,
+}
+
+#[derive(Debug)]
+struct Endpoint {
+    url: String,
+    state: Mutex<EndpointState>,
+}
+
+/// Pool of RPC endpoints of a chain
+#[derive(Debug)]
+pub struct RpcEndpointPool {
+    chain: String,
+    endpoints: Vec<Endpoint>,
+    config: RpcPoolConfig,
+}
+
+impl RpcEndpointPool {
+    /// Create a pool of endpoints, in priority order
+    pub fn new(
+        chain: impl Into<String>,
+        urls: impl IntoIterator<Item = impl Into<String>>,
+        config: RpcPoolConfig,
+    ) -> Self {
+        let endpoints = urls
+            .into_iter()
+            .map(|url| Endpoint {
+                url: url.into(),
+                state: Mutex::new(EndpointState {
+                    circuit: CircuitState::Closed,
+                    opened_at: None,
+                    trial_started_at: None,
+                    requests: 0,
+                    failures: 0,
+                    consecutive_failures: 0,
+                    latency_ms: 0.0,
+                    error_rate: 0.0,
+                    last_error: None,
+                }),
+            })
+            .collect();
+
+        Self {
+            chain: chain.into(),
+            endpoints,
+            config,
+        }
+    }
+
+    /// Create a pool from a comma separated list of URLs in the environment variable `var`,
+    /// falling back to `defaults` if it is unset or empty
+    pub fn from_env(chain: impl Into<String>, var: &str, defaults: &[&str]) -> Self {
+        let urls: Vec<String> = std::env::var(var)
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|url| !url.is_empty())
+            .map(String::from)
+            .collect();
+
+        if urls.is_empty() {
+            Self::new(chain, defaults.iter().copied(), RpcPoolConfig::default())
+        } else {
+            Self::new(chain, urls, RpcPoolConfig::default())
+        }
+    }
+
+    /// Chain of the pool
+    pub fn chain(&self) -> &str {
+        &self.chain
+    }
+
+    /// Endpoint URLs, in priority order
+    pub fn urls(&self) -> Vec<String> {
+        self.endpoints.iter().map(|e| e.url.clone()).collect()
+    }
+
+    /// Pool configuration
+    pub fn config(&self) -> &RpcPoolConfig {
+        &self.config
+    }
+
+    /// Call `f` with the URL of the first available endpoint, failing over to the next
+    /// endpoint on error or timeout.
+    ///
+    /// `f` may be called once per endpoint, so it must be safe to retry.
+    pub async fn call<T, E, F, Fut>(&self, mut f: F) -> Result<T, RpcPoolError>
+    where
+        F: FnMut(String) -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+        E: std::fmt::Display,
+    {
+        if self.endpoints.is_empty() {
+            return Err(RpcPoolError::NoEndpoints(self.chain.clone()));
+        }
+
+        let mut last_error = None;
+        for endpoint in &self.endpoints {
+            if !self.admit(endpoint) {
+                continue;
+            }
+
+            let started = Instant::now();
+            match tokio::time::timeout(self.config.request_timeout, f(endpoint.url.clone())).await {
+                Ok(Ok(value)) => {
+                    self.record_success(endpoint, started.elapsed());
+                    return Ok(value);
+                }
+                Ok(Err(err)) => {
+                    let err = err.to_string();
+                    self.record_failure(endpoint, &err);
+                    last_error = Some(err);
+                }
+                Err(_) => {
+                    let err = format!("timed out after {:?}", self.config.request_timeout);
+                    self.record_failure(endpoint, &err);
+                    last_error = Some(err);
+                }
+            }
+        }
+
+        match last_error {
+            Some(last_error) => Err(RpcPoolError::AllFailed {
+                chain: self.chain.clone(),
+                last_error,
+            }),
+            None => Err(RpcPoolError::Unavailable(self.chain.clone())),
+        }
+    }
+
+    /// Metrics of all endpoints, in priority order
+    pub fn metrics(&self) -> Vec<EndpointMetrics> {
+        self.endpoints
+            .iter()
+            .map(|endpoint| {
+                let state = endpoint.state.lock().unwrap();
+                EndpointMetrics {
+                    url: endpoint.url.clone(),
+                    state: state.circuit,
+                    requests: state.requests,
+                    failures: state.failures,
+                    consecutive_failures: state.consecutive_failures,
+                    latency_ms: state.latency_ms,
+                    error_rate: state.error_rate,
+                    last_error: state.last_error.clone(),
+                }
+            })
+            .collect()
+    }
+
+    /// Whether at least one endpoint has a closed circuit
+    pub fn is_healthy(&self) -> bool {
+        self.endpoints
+            .iter()
+            .any(|e| e.state.lock().unwrap().circuit == CircuitState::Closed)
+    }
+
+    /// Probe every endpoint with `probe` every health check interval, until the pool is
+    /// dropped. Probes feed the circuit breakers like regular requests, so failing endpoints
+    /// are taken out of rotation and recovered endpoints are put back without waiting for
+    /// live traffic to hit them.
+    pub fn spawn_health_checks<P, Fut>(self: &Arc<Self>, probe: P) -> tokio::task::JoinHandle<()>
+    where
+        P: Fn(String) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<(), String>> + Send,
+    {
+        let pool: Weak<Self> = Arc::downgrade(self);
+        let interval = self.config.health_check_interval;
+
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                let Some(pool) = pool.upgrade() else {
+                    return;
+                };
+
+                for endpoint in &pool.endpoints {
+                    if !pool.admit(endpoint) {
+                        continue;
+                    }
+
+                    let started = Instant::now();
+                    match tokio::time::timeout(
+                        pool.config.request_timeout,
+                        probe(endpoint.url.clone()),
+                    )
+                    .await
+                    {
+                        Ok(Ok(())) => pool.record_success(endpoint, started.elapsed()),
+                        Ok(Err(err)) => pool.record_failure(endpoint, &err),
+                        Err(_) => pool.record_failure(endpoint, "health check timed out"),
+                    }
+                }
+            }
+        })
+    }
+
+    /// Whether a request may be sent to the endpoint, moving an open circuit whose open
+    /// duration elapsed to half-open.
+    ///
+    /// A trial request that neither succeeds nor fails within the open duration, because its
+    /// caller was cancelled, is given up on and another trial request is admitted, so the
+    /// endpoint can not stay half-open forever.
+    fn admit(&self, endpoint: &Endpoint) -> bool {
+        let mut state = endpoint.state.lock().unwrap();
+        let since = match state.circuit {
+            CircuitState::Closed => return true,
+            CircuitState::HalfOpen => state.trial_started_at,
+            CircuitState::Open => state.opened_at,
+        };
+
+        let elapsed = since.map_or(true, |at| at.elapsed() >= self.config.open_duration);
+        if elapsed {
+            state.circuit = CircuitState::HalfOpen;
+            state.trial_started_at = Some(Instant::now());
+        }
+        elapsed
+    }
+
+    fn record_success(&self, endpoint: &Endpoint, latency: Duration) {
+        let mut state = endpoint.state.lock().unwrap();
+        let latency_ms = latency.as_secs_f64() * 1000.0;
+        state.latency_ms = if state.requests == state.failures {
+            latency_ms
+        } else {
+            EWMA_ALPHA * latency_ms + (1.0 - EWMA_ALPHA) * state.latency_ms
+        };
+        state.requests += 1;
+        state.error_rate *= 1.0 - EWMA_ALPHA;
+        state.consecutive_failures = 0;
+
+        if state.circuit != CircuitState::Closed {
+            log::info!(
+                "rpc pool: {} endpoint {} recovered",
+                self.chain,
+                endpoint.url
+            );
+            state.circuit = CircuitState::Closed;
+            state.opened_at = None;
+            state.trial_started_at = None;
+        }
+    }
+
+    fn record_failure(&self, endpoint: &Endpoint, error: &str) {
+        let mut state = endpoint.state.lock().unwrap();
+        state.requests += 1;
+        state.failures += 1;
+        state.consecutive_failures += 1;
+        state.error_rate = EWMA_ALPHA + (1.0 - EWMA_ALPHA) * state.error_rate;
+        state.last_error = Some(error.to_string());
+
+        let trip = match state.circuit {
+            CircuitState::Closed => state.consecutive_failures >= self.config.failure_threshold,
+            CircuitState::HalfOpen | CircuitState::Open => true,
+        };
+        if trip {
+            if state.circuit == CircuitState::Closed {
+                log::warn!(
+                    "rpc pool: {} endpoint {} disabled after {} failures: {}",
+                    self.chain,
+                    endpoint.url,
+                    state.consecutive_failures,
+                    error
+                );
+            }
+            state.circuit = CircuitState::Open;
+            state.opened_at = Some(Instant::now());
+            state.trial_started_at = None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pool(open_duration: Duration) -> RpcEndpointPool {
+        RpcEndpointPool::new(
+            "neo",
+            ["http://primary", "http://secondary"],
+            RpcPoolConfig {
+                failure_threshold: 2,
+                open_duration,
+                ..RpcPoolConfig::default()
+            },
+        )
+    }
+
+    async fn call(pool: &RpcEndpointPool, primary_up: bool) -> Result<String, RpcPoolError> {
+        pool.call(|url| async move {
+            if url == "http://primary" && !primary_up {
+                Err("connection refused")
+            } else {
+                Ok(url)
+            }
+        })
+        .await
+    }
+
+    #[tokio::test]
+    async fn test_failover_and_circuit_breaker() {
+        let pool = pool(Duration::from_secs(60));
+
+        assert_eq!(call(&pool, true).await.unwrap(), "http://primary");
+
+        // Failures fail over to the secondary until the primary circuit opens
+        assert_eq!(call(&pool, false).await.unwrap(), "http://secondary");
+        assert_eq!(call(&pool, false).await.unwrap(), "http://secondary");
+        let metrics = pool.metrics();
+        assert_eq!(metrics[0].state, CircuitState::Open);
+        assert_eq!(metrics[0].failures, 2);
+        assert_eq!(metrics[1].state, CircuitState::Closed);
+
+        // The open primary is skipped even though it is back up
+        assert_eq!(call(&pool, true).await.unwrap(), "http://secondary");
+        assert_eq!(pool.metrics()[0].requests, 3);
+        assert!(pool.is_healthy());
+    }
+
+    #[tokio::test]
+    async fn test_half_open_recovery() {
+        let pool = pool(Duration::ZERO);
+
+        call(&pool, false).await.unwrap();
+        call(&pool, false).await.unwrap();
+        assert_eq!(pool.metrics()[0].state, CircuitState::Open);
+
+        // A failed trial request re-opens the circuit at once
+        assert_eq!(call(&pool, false).await.unwrap(), "http://secondary");
+        assert_eq!(pool.metrics()[0].state, CircuitState::Open);
+
+        // A successful trial request closes it
+        assert_eq!(call(&pool, true).await.unwrap(), "http://primary");
+        let metrics = pool.metrics();
+        assert_eq!(metrics[0].state, CircuitState::Closed);
+        assert_eq!(metrics[0].consecutive_failures, 0);
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_trial() {
+        let open_duration = Duration::from_millis(50);
+        let pool = pool(open_duration);
+
+        call(&pool, false).await.unwrap();
+        call(&pool, false).await.unwrap();
+        tokio::time::sleep(open_duration).await;
+
+        // The caller gives up while the trial request to the primary is in flight
+        let trial = pool.call(|url| async move {
+            if url == "http://primary" {
+                std::future::pending::<()>().await;
+            }
+            Ok::<_, String>(url)
+        });
+        assert!(tokio::time::timeout(Duration::from_millis(10), trial)
+            .await
+            .is_err());
+        assert_eq!(pool.metrics()[0].state, CircuitState::HalfOpen);
+
+        // No other trial is admitted until the open duration elapses
+        assert_eq!(call(&pool, true).await.unwrap(), "http://secondary");
+        assert_eq!(pool.metrics()[0].state, CircuitState::HalfOpen);
+
+        tokio::time::sleep(open_duration).await;
+        assert_eq!(call(&pool, true).await.unwrap(), "http://primary");
+        assert_eq!(pool.metrics()[0].state, CircuitState::Closed);
+    }
+
+    #[tokio::test]
+    async fn test_all_failed() {
+        let pool = RpcEndpointPool::new("neo", ["http://a"], RpcPoolConfig::default());
+        let result: Result<(), _> = pool.call(|_| async { Err("boom") }).await;
+        assert!(matches!(result, Err(RpcPoolError::AllFailed { .. })));
+
+        let empty = RpcEndpointPool::new("neo", Vec::<String>::new(), RpcPoolConfig::default());
+        let result: Result<(), _> = empty.call(|_| async { Ok::<_, String>(()) }).await;
+        assert!(matches!(result, Err(RpcPoolError::NoEndpoints(_))));
+    }
+}
@@ -0,0 +1,137 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+//! JSON Schema validation of function input and output.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Maximum number of violations reported for one document
+pub const MAX_VIOLATIONS: usize = 32;
+
+/// Schema error
+#[derive(Debug, thiserror::Error)]
+pub enum SchemaError {
+    #[error("schema: invalid schema: {0}")]
+    InvalidSchema(String),
+}
+
+/// A place where a document does not conform to its schema
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SchemaViolation {
+    /// JSON Pointer to the offending value, empty for the document root
+    pub path: String,
+
+    /// What is wrong with the value
+    pub message: String,
+}
+
+impl std::fmt::Display for SchemaViolation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let path = if self.path.is_empty() {
+            "/"
+        } else {
+            &self.path
+        };
+        write!(f, "{}: {}", path, self.message)
+    }
+}
+
+/// Compiled JSON Schema
+pub struct SchemaValidator {
+    validator: jsonschema::Validator,
+}
+
+impl SchemaValidator {
+    /// Compile a schema; the draft is taken from `$schema`, 2020-12 when absent.
+    /// Remote `$ref`s are not resolved.
+    pub fn new(schema: &Value) -> Result<Self, SchemaError> {
+        let validator = jsonschema::validator_for(schema)
+            .map_err(|e| SchemaError::InvalidSchema(e.to_string()))?;
+        Ok(Self { validator })
+    }
+
+    /// Validate a document, returning up to `MAX_VIOLATIONS` violations
+    pub fn validate(&self, document: &Value) -> Result<(), Vec<SchemaViolation>> {
+        let violations: Vec<_> = self
+            .validator
+            .iter_errors(document)
+            .take(MAX_VIOLATIONS)
+            .map(|e| SchemaViolation {
+                path: e.instance_path.to_string(),
+                message: e.to_string(),
+            })
+            .collect();
+
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(violations)
+        }
+    }
+}
+
+/// Check that a schema compiles
+pub fn check_schema(schema: &Value) -> Result<(), SchemaError> {
+    SchemaValidator::new(schema).map(|_| ())
+}
+
+/// Validate a document against a schema in one go
+pub fn validate(schema: &Value, document: &Value) -> Result<(), SchemaValidationError> {
+    SchemaValidator::new(schema)?
+        .validate(document)
+        .map_err(SchemaValidationError::Violations)
+}
+
+/// Failure of a one-shot validation
+#[derive(Debug, thiserror::Error)]
+pub enum SchemaValidationError {
+    #[error(transparent)]
+    Schema(#[from] SchemaError),
+
+    #[error("{}", format_violations(.0))]
+    Violations(Vec<SchemaViolation>),
+}
+
+/// Render violations as `path: message` entries separated by `; `
+pub fn format_violations(violations: &[SchemaViolation]) -> String {
+    violations
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_validate() {
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "amount": { "type": "integer", "minimum": 1 },
+                "to": { "type": "string" }
+            },
+            "required": ["amount", "to"]
+        });
+        let validator = SchemaValidator::new(&schema).unwrap();
+
+        assert!(validator
+            .validate(&json!({"amount": 5, "to": "NX"}))
+            .is_ok());
+
+        let violations = validator.validate(&json!({"amount": 0})).unwrap_err();
+        assert_eq!(violations.len(), 2);
+        assert!(violations.iter().any(|v| v.path == "/amount"));
+        assert!(violations.iter().any(|v| v.path.is_empty()));
+
+        assert!(matches!(
+            validate(&schema, &json!("nope")),
+            Err(SchemaValidationError::Violations(v)) if v.len() == 1
+        ));
+        assert!(check_schema(&json!({"type": 12})).is_err());
+    }
+}
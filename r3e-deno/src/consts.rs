@@ -103,6 +103,19 @@ lazy_static::lazy_static! {
         "op_leak_tracing_get_all",
         "op_leak_tracing_get",
         "op_defer",
+        "op_fetch",
+        "op_timer_admit",
+        "op_timer_fire",
+        "op_service_invoke",
+        "op_proof_eth_header",
+        "op_proof_eth_transaction",
+        "op_proof_eth_receipt",
+        "op_proof_eth_account",
+        "op_proof_eth_storage",
+        "op_proof_neo_header",
+        "op_proof_neo_transaction",
+        "op_proof_neo_state_root",
+        "op_proof_neo_state",
     ]);
 }
 
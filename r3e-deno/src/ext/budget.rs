@@ -0,0 +1,242 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+//! Cost budget of the ops reaching outside the runtime.
+//!
+//! Time and heap limits do not stop a function from making thousands of fetch, chain, NEP-11,
+//! TEE, oracle or service calls. Each of those ops is charged to the invocation's budget before
+//! it runs, and the time it waits on the outside world is added up. The op exhausting the budget
+//! fails and terminates the invocation, which then fails with
+//! [`ExecError::OpBudget`](crate::ExecError::OpBudget) naming it.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use deno_core::error::{range_error, AnyError};
+use deno_core::v8::IsolateHandle;
+use deno_core::OpState;
+use serde::{Deserialize, Serialize};
+
+/// External op limits of one invocation
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OpLimits {
+    /// External op calls an invocation may make
+    pub max_calls: u32,
+
+    /// Calls an invocation may make of single ops, by op name, within `max_calls`
+    pub max_calls_per_op: HashMap<String, u32>,
+
+    /// Time an invocation may wait on external ops, summed over all calls
+    pub max_external_latency: Duration,
+}
+
+impl Default for OpLimits {
+    fn default() -> Self {
+        Self {
+            max_calls: 500,
+            max_calls_per_op: HashMap::new(),
+            max_external_latency: Duration::from_secs(60),
+        }
+    }
+}
+
+/// Limit an op exhausted, naming the op
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, thiserror::Error)]
+pub enum OpBudgetError {
+    #[error("{op}: more than {limit} external op calls made")]
+    TooManyCalls { op: String, limit: u32 },
+
+    #[error("{op}: more than {limit} calls of the op made")]
+    TooManyOpCalls { op: String, limit: u32 },
+
+    #[error("{op}: external ops waited more than {limit:?} in total")]
+    Latency { op: String, limit: Duration },
+}
+
+impl OpBudgetError {
+    /// Op that exhausted the budget
+    pub fn op(&self) -> &str {
+        match self {
+            OpBudgetError::TooManyCalls { op, .. }
+            | OpBudgetError::TooManyOpCalls { op, .. }
+            | OpBudgetError::Latency { op, .. } => op,
+        }
+    }
+}
+
+/// External op calls of the running invocation, kept in the op state
+#[derive(Default)]
+pub(crate) struct OpBudget {
+    limits: OpLimits,
+    epoch: u32,
+    calls: u32,
+    op_calls: HashMap<&'static str, u32>,
+    latency: Duration,
+    exceeded: Option<OpBudgetError>,
+    isolate: Option<IsolateHandle>,
+}
+
+/// External op call in flight, finished to charge the time it waited
+#[derive(Debug)]
+#[must_use]
+pub(crate) struct OpCall {
+    op: &'static str,
+    epoch: u32,
+    started: Instant,
+}
+
+impl OpBudget {
+    /// Create a budget terminating `isolate` when it is exhausted
+    pub(crate) fn new(limits: OpLimits, isolate: IsolateHandle) -> Self {
+        Self {
+            limits,
+            isolate: Some(isolate),
+            ..Default::default()
+        }
+    }
+
+    /// Start the budget of a new invocation
+    pub(crate) fn reset(&mut self) {
+        self.epoch = self.epoch.wrapping_add(1);
+        self.calls = 0;
+        self.op_calls.clear();
+        self.latency = Duration::ZERO;
+        self.exceeded = None;
+    }
+
+    /// The limit the invocation exhausted, if any
+    pub(crate) fn take_exceeded(&mut self) -> Option<OpBudgetError> {
+        self.exceeded.take()
+    }
+
+    /// Admit one more call of `op`
+    fn admit(&mut self, op: &'static str, now: Instant) -> Result<OpCall, OpBudgetError> {
+        if let Some(exceeded) = &self.exceeded {
+            return Err(exceeded.clone());
+        }
+
+        let op_calls = self.op_calls.get(op).copied().unwrap_or_default();
+        let op_limit = self.limits.max_calls_per_op.get(op).copied();
+        if self.calls >= self.limits.max_calls {
+            return Err(self.exhaust(OpBudgetError::TooManyCalls {
+                op: op.to_string(),
+                limit: self.limits.max_calls,
+            }));
+        }
+        if let Some(limit) = op_limit.filter(|limit| op_calls >= *limit) {
+            return Err(self.exhaust(OpBudgetError::TooManyOpCalls {
+                op: op.to_string(),
+                limit,
+            }));
+        }
+
+        self.calls += 1;
+        self.op_calls.insert(op, op_calls + 1);
+        Ok(OpCall {
+            op,
+            epoch: self.epoch,
+            started: now,
+        })
+    }
+
+    /// Charge the time a call waited, ignoring calls of earlier invocations
+    fn finish(&mut self, call: OpCall, now: Instant) -> Result<(), OpBudgetError> {
+        if call.epoch != self.epoch || self.exceeded.is_some() {
+            return Ok(());
+        }
+        self.latency += now.saturating_duration_since(call.started);
+        if self.latency > self.limits.max_external_latency {
+            return Err(self.exhaust(OpBudgetError::Latency {
+                op: call.op.to_string(),
+                limit: self.limits.max_external_latency,
+            }));
+        }
+        Ok(())
+    }
+
+    /// Record the exhausted limit and terminate the invocation, so catching the op's error
+    /// does not let it carry on
+    fn exhaust(&mut self, err: OpBudgetError) -> OpBudgetError {
+        if let Some(isolate) = &self.isolate {
+            isolate.terminate_execution();
+        }
+        self.exceeded.insert(err).clone()
+    }
+}
+
+/// Charge a call of an external op to the invocation's budget before it runs
+pub(crate) fn start_op(state: &mut OpState, op: &'static str) -> Result<OpCall, AnyError> {
+    state
+        .borrow_mut::<OpBudget>()
+        .admit(op, Instant::now())
+        .map_err(|err| range_error(err.to_string()))
+}
+
+/// Charge the time an external op call waited to the invocation's budget
+pub(crate) fn finish_op(state: &mut OpState, call: OpCall) -> Result<(), AnyError> {
+    state
+        .borrow_mut::<OpBudget>()
+        .finish(call, Instant::now())
+        .map_err(|err| range_error(err.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_op_budget() {
+        let mut budget = OpBudget {
+            limits: OpLimits {
+                max_calls: 3,
+                max_calls_per_op: HashMap::from([("op_fetch".to_string(), 1)]),
+                max_external_latency: Duration::from_secs(5),
+            },
+            ..Default::default()
+        };
+        let start = Instant::now();
+
+        let call = budget.admit("op_fetch", start).unwrap();
+        budget.finish(call, start + Duration::from_secs(2)).unwrap();
+        assert_eq!(
+            budget.admit("op_fetch", start).err(),
+            Some(OpBudgetError::TooManyOpCalls {
+                op: "op_fetch".to_string(),
+                limit: 1
+            })
+        );
+
+        // Once exhausted, every op fails with the same error
+        let exceeded = budget.admit("op_service_invoke", start).unwrap_err();
+        assert_eq!(exceeded.op(), "op_fetch");
+        assert_eq!(budget.take_exceeded(), Some(exceeded));
+
+        // Latency adds up over calls
+        budget.reset();
+        let first = budget.admit("op_service_invoke", start).unwrap();
+        let second = budget.admit("op_service_invoke", start).unwrap();
+        budget
+            .finish(first, start + Duration::from_secs(3))
+            .unwrap();
+        let err = budget
+            .finish(second, start + Duration::from_secs(3))
+            .unwrap_err();
+        assert!(matches!(err, OpBudgetError::Latency { op, .. } if op == "op_service_invoke"));
+
+        // Calls of an earlier invocation are not charged to the next one
+        budget.reset();
+        let stale = budget.admit("op_fetch", start).unwrap();
+        budget.reset();
+        budget
+            .finish(stale, start + Duration::from_secs(60))
+            .unwrap();
+        for _ in 0..3 {
+            let call = budget.admit("op_chain_eth_call", start).unwrap();
+            budget.finish(call, start).unwrap();
+        }
+        assert!(matches!(
+            budget.admit("op_chain_eth_call", start),
+            Err(OpBudgetError::TooManyCalls { limit: 3, .. })
+        ));
+    }
+}
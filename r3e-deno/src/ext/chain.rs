@@ -0,0 +1,443 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+//! Read-only chain queries of functions: balances, contract storage, blocks and Ethereum
+//! calls, made through the platform's RPC endpoints.
+
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+
+use base64::Engine;
+use deno_core::error::{range_error, type_error, AnyError};
+use deno_core::{op2, CancelFuture, OpState};
+use r3e_core::chain::{
+    stack_item_integer, Block, BlockRef, ChainClient, ChainClients, ChainError, ContractCall,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+
+use super::budget::{finish_op, start_op, OpCall};
+use super::InvocationCancel;
+use crate::sandbox::{check_permission, SandboxConfig};
+
+/// Script hash of the native NEO token
+const NEO_TOKEN: &str = "0xef4073a0f2b305a38ec4050e4d3d28bc40ea63f5";
+
+/// Script hash of the native GAS token
+const GAS_TOKEN: &str = "0xd2a4cff31913016155e38e474a2c06d08be276cf";
+
+/// Version byte of Neo N3 addresses
+const ADDRESS_VERSION: u8 = 0x35;
+
+/// RPC error code of a missing storage item
+const UNKNOWN_STORAGE_ITEM: i64 = -104;
+
+/// Limits on the chain queries of an invocation
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChainLimits {
+    /// RPC calls an invocation may make
+    pub max_rpc_calls: u32,
+}
+
+impl Default for ChainLimits {
+    fn default() -> Self {
+        Self { max_rpc_calls: 50 }
+    }
+}
+
+/// Networks the chain queries of functions are made on
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChainNetworks {
+    /// Neo N3 network, e.g. `mainnet` or `testnet`
+    #[serde(default = "default_network")]
+    pub neo: String,
+
+    /// Ethereum network
+    #[serde(default = "default_network")]
+    pub ethereum: String,
+}
+
+fn default_network() -> String {
+    "mainnet".to_string()
+}
+
+impl Default for ChainNetworks {
+    fn default() -> Self {
+        Self {
+            neo: default_network(),
+            ethereum: default_network(),
+        }
+    }
+}
+
+/// Chain clients the query ops use, taken from the op state
+pub struct ChainQueries {
+    clients: Arc<ChainClients>,
+    networks: ChainNetworks,
+}
+
+impl ChainQueries {
+    pub fn new(clients: Arc<ChainClients>, networks: ChainNetworks) -> Self {
+        Self { clients, networks }
+    }
+
+    fn client(&self, chain: &str) -> Result<Arc<dyn ChainClient>, ChainError> {
+        let network = match chain {
+            "neo" => &self.networks.neo,
+            _ => &self.networks.ethereum,
+        };
+        self.clients.get(chain, network)
+    }
+}
+
+/// RPC calls made by the running invocation, kept in the op state
+#[derive(Debug)]
+pub(crate) struct RpcBudget {
+    limits: ChainLimits,
+    used: u32,
+}
+
+impl Default for RpcBudget {
+    fn default() -> Self {
+        Self::new(ChainLimits::default())
+    }
+}
+
+impl RpcBudget {
+    pub(crate) fn new(limits: ChainLimits) -> Self {
+        Self { limits, used: 0 }
+    }
+
+    /// Start the budget of a new invocation
+    pub(crate) fn reset(&mut self) {
+        self.used = 0;
+    }
+
+    /// Admit one more RPC call
+    fn admit(&mut self) -> Result<(), String> {
+        if self.used >= self.limits.max_rpc_calls {
+            return Err(format!(
+                "more than {} chain RPC calls made",
+                self.limits.max_rpc_calls
+            ));
+        }
+        self.used += 1;
+        Ok(())
+    }
+}
+
+/// Check the sandbox and admin policies, and charge the call of `op` to the invocation's
+/// budgets
+fn admit(
+    state: &Rc<RefCell<OpState>>,
+    op: &'static str,
+    chain: &str,
+    method: &str,
+    contract: Option<&str>,
+) -> Result<(Arc<dyn ChainClient>, InvocationCancel, OpCall), AnyError> {
+    let mut state = state.borrow_mut();
+    let config = state
+        .borrow::<Arc<Mutex<SandboxConfig>>>()
+        .lock()
+        .unwrap()
+        .clone();
+    check_permission("chain", &config).map_err(AnyError::msg)?;
+    super::check_op_policy(
+        &state,
+        "chain",
+        json!({ "chain": chain, "chain_method": method, "contract": contract }),
+    )?;
+
+    let client = state
+        .try_borrow::<Arc<ChainQueries>>()
+        .ok_or_else(|| type_error("chain queries are not available"))?
+        .client(chain)
+        .map_err(|e| AnyError::msg(e.to_string()))?;
+    state
+        .borrow_mut::<RpcBudget>()
+        .admit()
+        .map_err(range_error)?;
+    let call = start_op(&mut state, op)?;
+
+    Ok((client, state.borrow::<InvocationCancel>().clone(), call))
+}
+
+/// Run a query, failing it when the invocation ends first
+async fn query<T>(
+    state: &Rc<RefCell<OpState>>,
+    (cancel, call): (InvocationCancel, OpCall),
+    method: &str,
+    query: impl std::future::Future<Output = Result<T, ChainError>>,
+) -> Result<T, AnyError> {
+    let result = query.or_cancel(cancel.0).await;
+    finish_op(&mut state.borrow_mut(), call)?;
+    match result {
+        Ok(result) => result.map_err(|e| AnyError::msg(format!("{}: {}", method, e))),
+        Err(_) => Err(AnyError::msg(format!(
+            "{} cancelled: the invocation ended",
+            method
+        ))),
+    }
+}
+
+/// NEP-17 balance of an account
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BalanceResult {
+    /// Script hash of the token
+    pub asset: String,
+
+    /// Balance in the token's smallest unit, as a decimal string
+    pub amount: String,
+}
+
+/// Get the NEP-17 balance of an address. `asset` is `NEO`, `GAS` or a token script hash.
+#[op2(async)]
+#[serde]
+pub async fn op_chain_neo_get_balance(
+    state: Rc<RefCell<OpState>>,
+    #[string] address: String,
+    #[string] asset: String,
+) -> Result<BalanceResult, AnyError> {
+    let asset = match asset.to_ascii_uppercase().as_str() {
+        "NEO" => NEO_TOKEN.to_string(),
+        "GAS" => GAS_TOKEN.to_string(),
+        _ => normalize_hash160(&asset)?,
+    };
+    let account = account_hash(&address)?;
+    let (client, cancel, call) = admit(
+        &state,
+        "op_chain_neo_get_balance",
+        "neo",
+        "getBalance",
+        Some(&asset),
+    )?;
+
+    let contract_call = ContractCall {
+        contract: asset.clone(),
+        method: "balanceOf".to_string(),
+        params: vec![json!({ "type": "Hash160", "value": account })],
+        ..Default::default()
+    };
+    let result = query(
+        &state,
+        (cancel, call),
+        "neo.getBalance",
+        client.call_contract(&contract_call),
+    )
+    .await?;
+    if !result.success {
+        return Err(AnyError::msg(format!(
+            "neo.getBalance: balanceOf faulted: {}",
+            result.error.unwrap_or_default()
+        )));
+    }
+
+    let amount = result
+        .result
+        .get(0)
+        .and_then(stack_item_integer)
+        .ok_or_else(|| AnyError::msg("neo.getBalance: balanceOf returned no integer"))?;
+    Ok(BalanceResult {
+        asset,
+        amount: amount.to_string(),
+    })
+}
+
+/// Get a storage item of a contract as hex, or null if the key is not set
+#[op2(async)]
+#[serde]
+pub async fn op_chain_neo_get_storage(
+    state: Rc<RefCell<OpState>>,
+    #[string] contract: String,
+    #[string] key: String,
+) -> Result<Option<String>, AnyError> {
+    let contract = normalize_hash160(&contract)?;
+    let key = hex::decode(key.trim_start_matches("0x"))
+        .map_err(|e| type_error(format!("invalid storage key: {}", e)))?;
+    let (client, cancel, call) = admit(
+        &state,
+        "op_chain_neo_get_storage",
+        "neo",
+        "getStorage",
+        Some(&contract),
+    )?;
+
+    let key = base64::engine::general_purpose::STANDARD.encode(key);
+    let request = client.request("getstorage", json!([contract, key]));
+    let result = request.or_cancel(cancel.0).await;
+    finish_op(&mut state.borrow_mut(), call)?;
+    let value = match result {
+        Ok(Ok(value)) => value,
+        Ok(Err(ChainError::Rpc { code, message, .. }))
+            if code == UNKNOWN_STORAGE_ITEM || message.contains("Unknown storage") =>
+        {
+            return Ok(None);
+        }
+        Ok(Err(e)) => return Err(AnyError::msg(format!("neo.getStorage: {}", e))),
+        Err(_) => {
+            return Err(AnyError::msg(
+                "neo.getStorage cancelled: the invocation ended",
+            ))
+        }
+    };
+
+    let Some(value) = value.as_str() else {
+        return Ok(None);
+    };
+    let value = base64::engine::general_purpose::STANDARD
+        .decode(value)
+        .map_err(|e| AnyError::msg(format!("neo.getStorage: invalid storage value: {}", e)))?;
+    Ok(Some(hex::encode(value)))
+}
+
+/// Get a Neo N3 block by height, or the latest block without a height
+#[op2(async)]
+#[serde]
+pub async fn op_chain_neo_get_block(
+    state: Rc<RefCell<OpState>>,
+    #[serde] height: Option<u64>,
+) -> Result<Block, AnyError> {
+    let (client, cancel, call) = admit(&state, "op_chain_neo_get_block", "neo", "getBlock", None)?;
+    let block = match height {
+        Some(height) => BlockRef::Number(height),
+        None => BlockRef::Latest,
+    };
+    query(
+        &state,
+        (cancel, call),
+        "neo.getBlock",
+        client.get_block(block),
+    )
+    .await
+}
+
+/// Result of an Ethereum call
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EthCallResult {
+    /// Whether the call completed without reverting
+    pub success: bool,
+
+    /// Hex encoded return data
+    pub result: serde_json::Value,
+
+    /// Revert reason of a failed call
+    pub error: Option<String>,
+}
+
+/// Call an Ethereum contract with ABI encoded calldata, without changing state
+#[op2(async)]
+#[serde]
+pub async fn op_chain_eth_call(
+    state: Rc<RefCell<OpState>>,
+    #[string] to: String,
+    #[string] data: String,
+) -> Result<EthCallResult, AnyError> {
+    let data = hex::decode(data.trim_start_matches("0x"))
+        .map_err(|e| type_error(format!("invalid calldata: {}", e)))?;
+    let (client, cancel, call) = admit(&state, "op_chain_eth_call", "ethereum", "call", Some(&to))?;
+
+    let contract_call = ContractCall {
+        contract: to,
+        method: "eth_call".to_string(),
+        data: Some(data),
+        ..Default::default()
+    };
+    let result = query(
+        &state,
+        (cancel, call),
+        "eth.call",
+        client.call_contract(&contract_call),
+    )
+    .await?;
+    Ok(EthCallResult {
+        success: result.success,
+        result: result.result,
+        error: result.error,
+    })
+}
+
+/// Script hash in display form, `0x` followed by 40 lowercase hex digits
+fn normalize_hash160(hash: &str) -> Result<String, AnyError> {
+    let digits = hash.trim_start_matches("0x");
+    if digits.len() != 40 || !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(type_error(format!("invalid script hash: {}", hash)));
+    }
+    Ok(format!("0x{}", digits.to_ascii_lowercase()))
+}
+
+/// Script hash of a Neo N3 address, in display form. Script hashes are accepted as is.
+fn account_hash(address: &str) -> Result<String, AnyError> {
+    if address.starts_with("0x") {
+        return normalize_hash160(address);
+    }
+
+    let invalid = || type_error(format!("invalid Neo N3 address: {}", address));
+    let bytes = base58_decode(address).ok_or_else(invalid)?;
+    if bytes.len() != 25 || bytes[0] != ADDRESS_VERSION {
+        return Err(invalid());
+    }
+    let (payload, checksum) = bytes.split_at(21);
+    if Sha256::digest(Sha256::digest(payload))[..4] != *checksum {
+        return Err(invalid());
+    }
+
+    let mut hash = payload[1..].to_vec();
+    hash.reverse();
+    Ok(format!("0x{}", hex::encode(hash)))
+}
+
+fn base58_decode(input: &str) -> Option<Vec<u8>> {
+    const ALPHABET: &[u8] = b"123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+
+    let mut bytes: Vec<u8> = Vec::new();
+    for c in input.bytes() {
+        let mut carry = ALPHABET.iter().position(|&a| a == c)? as u32;
+        for byte in bytes.iter_mut().rev() {
+            carry += *byte as u32 * 58;
+            *byte = carry as u8;
+            carry >>= 8;
+        }
+        while carry > 0 {
+            bytes.insert(0, carry as u8);
+            carry >>= 8;
+        }
+    }
+
+    let zeros = input.bytes().take_while(|&c| c == b'1').count();
+    let mut decoded = vec![0; zeros];
+    decoded.extend(bytes);
+    Some(decoded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_account_hash() {
+        assert_eq!(
+            account_hash("NcMM5XeJHhunGPj9MXCywRQTQhMrpG5jNf").unwrap(),
+            "0x5c2ca22f9d8bd6b1f4d2ba3cf1e7d4c1c7af4eb4"
+        );
+        // Checksum mismatch
+        assert!(account_hash("NcMM5XeJHhunGPj9MXCywRQTQhMrpG5jNg").is_err());
+        assert_eq!(
+            account_hash("0xEF4073A0F2B305A38EC4050E4D3D28BC40EA63F5").unwrap(),
+            NEO_TOKEN
+        );
+        assert!(normalize_hash160("0x1234").is_err());
+    }
+
+    #[test]
+    fn test_rpc_budget() {
+        let mut budget = RpcBudget::new(ChainLimits { max_rpc_calls: 2 });
+        assert!(budget.admit().is_ok());
+        assert!(budget.admit().is_ok());
+        assert!(budget.admit().is_err());
+
+        budget.reset();
+        assert!(budget.admit().is_ok());
+    }
+}
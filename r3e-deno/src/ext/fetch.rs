@@ -0,0 +1,343 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use deno_core::error::AnyError;
+use deno_core::{op2, OpState};
+use r3e_core::egress::EgressGuard;
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use super::budget::{finish_op, start_op};
+use crate::sandbox::{
+    check_permission, FunctionGrants, FunctionPolicy, PermissionKind, SandboxConfig,
+};
+
+/// Fetch request from JavaScript
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FetchRequest {
+    pub url: String,
+
+    #[serde(default)]
+    pub method: Option<String>,
+
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+
+    #[serde(default)]
+    pub body: Option<String>,
+}
+
+/// Fetch response to JavaScript
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FetchResponse {
+    pub url: String,
+    pub status: u16,
+    pub status_text: String,
+    pub headers: HashMap<String, String>,
+    pub body: String,
+}
+
+#[op2(async)]
+#[serde]
+pub async fn op_fetch(
+    state: Rc<RefCell<OpState>>,
+    #[serde] request: FetchRequest,
+) -> Result<FetchResponse, AnyError> {
+    let config = state
+        .borrow()
+        .borrow::<Arc<Mutex<SandboxConfig>>>()
+        .lock()
+        .unwrap()
+        .clone();
+    let grants = state.borrow().try_borrow::<FunctionGrants>().cloned();
+    let function_policy = state.borrow().try_borrow::<FunctionPolicy>().cloned();
+    let call = start_op(&mut state.borrow_mut(), "op_fetch")?;
+
+    let start = Instant::now();
+    let method = request.method.clone().unwrap_or_else(|| "GET".into());
+    let target = request.url.clone();
+    let result = do_fetch(
+        &config,
+        grants.as_ref(),
+        function_policy.as_ref(),
+        &method,
+        request,
+    )
+    .await;
+
+    // Audit log every outbound request, including the denied ones
+    match &result {
+        Ok(response) => log::info!(
+            "egress: {} {} -> {} ({} bytes, {}ms)",
+            method,
+            response.url,
+            response.status,
+            response.body.len(),
+            start.elapsed().as_millis()
+        ),
+        Err(err) => log::warn!("egress: {} {} failed: {}", method, target, err),
+    }
+    finish_op(&mut state.borrow_mut(), call)?;
+    result
+}
+
+async fn do_fetch(
+    config: &SandboxConfig,
+    grants: Option<&FunctionGrants>,
+    function_policy: Option<&FunctionPolicy>,
+    method: &str,
+    request: FetchRequest,
+) -> Result<FetchResponse, AnyError> {
+    // Without a blanket permission, every host needs a grant of the function
+    let grants = match (check_permission("net", config), grants) {
+        (Ok(()), _) => None,
+        (Err(_), Some(grants)) => Some(grants),
+        (Err(message), None) => return Err(AnyError::msg(message)),
+    };
+
+    let policy = config.net_policy.clone();
+    let egress_guard = EgressGuard::new().with_allowed_cidrs(policy.allowed_cidrs.clone());
+    let mut url = Url::parse(&request.url)
+        .map_err(|e| AnyError::msg(format!("Invalid URL '{}': {}", request.url, e)))?;
+    let mut method = reqwest::Method::from_bytes(method.to_ascii_uppercase().as_bytes())
+        .map_err(|_| AnyError::msg(format!("Invalid HTTP method: {}", method)))?;
+    let mut headers = request.headers;
+    let mut body = request.body;
+
+    // Redirects are followed manually, so every hop is checked against the egress rules
+    let mut redirects = 0;
+    let mut response = loop {
+        policy
+            .check_url(&url)
+            .map_err(|e| AnyError::msg(format!("{} {}: {}", method, url, e)))?;
+        if let Some(grants) = grants {
+            grants
+                .authorize(PermissionKind::Net, url.host_str().unwrap_or_default())
+                .map_err(|e| AnyError::msg(format!("{} {}: {}", method, url, e)))?;
+        }
+        super::check_function_policy(
+            function_policy,
+            "net",
+            serde_json::json!({
+                "method": method.as_str(),
+                "url": url.as_str(),
+                "host": url.host_str(),
+            }),
+        )
+        .map_err(|e| AnyError::msg(format!("{} {}: {}", method, url, e)))?;
+        let resolved = egress_guard
+            .check(url.clone())
+            .await
+            .map_err(|e| AnyError::msg(format!("{} {}: {}", method, url, e)))?;
+
+        // Pin the connection to the checked addresses
+        let client = reqwest::Client::builder()
+            .resolve_to_addrs(&resolved.host, &resolved.addrs)
+            .redirect(reqwest::redirect::Policy::none())
+            .timeout(policy.timeout)
+            .build()
+            .map_err(|e| AnyError::msg(format!("Failed to create HTTP client: {}", e)))?;
+
+        let mut builder = client.request(method.clone(), url.clone());
+        for (name, value) in &headers {
+            builder = builder.header(name, value);
+        }
+        if let Some(body) = body.clone() {
+            builder = builder.body(body);
+        }
+
+        let response = builder
+            .send()
+            .await
+            .map_err(|e| AnyError::msg(format!("Failed to fetch: {}", e)))?;
+
+        let status = response.status();
+        let location = response
+            .headers()
+            .get(reqwest::header::LOCATION)
+            .and_then(|location| location.to_str().ok());
+        let Some(location) =
+            location.filter(|_| status.is_redirection() && policy.max_redirects > 0)
+        else {
+            break response;
+        };
+
+        if redirects >= policy.max_redirects {
+            return Err(AnyError::msg(format!(
+                "Too many redirects (max {})",
+                policy.max_redirects
+            )));
+        }
+        redirects += 1;
+
+        let next = url.join(location).map_err(|e| {
+            AnyError::msg(format!("Invalid redirect location '{}': {}", location, e))
+        })?;
+        if next.host_str() != url.host_str() {
+            // Do not leak credentials to another host
+            headers.retain(|name, _| {
+                !name.eq_ignore_ascii_case("authorization") && !name.eq_ignore_ascii_case("cookie")
+            });
+        }
+        if status == reqwest::StatusCode::SEE_OTHER
+            || (method == reqwest::Method::POST
+                && (status == reqwest::StatusCode::MOVED_PERMANENTLY
+                    || status == reqwest::StatusCode::FOUND))
+        {
+            method = reqwest::Method::GET;
+            body = None;
+        }
+        url = next;
+    };
+
+    if let Some(len) = response.content_length() {
+        if len as usize > policy.max_response_size {
+            return Err(AnyError::msg(format!(
+                "Response size {} exceeds limit {}",
+                len, policy.max_response_size
+            )));
+        }
+    }
+
+    let mut body = Vec::new();
+    while let Some(chunk) = response
+        .chunk()
+        .await
+        .map_err(|e| AnyError::msg(format!("Failed to read response: {}", e)))?
+    {
+        if body.len() + chunk.len() > policy.max_response_size {
+            return Err(AnyError::msg(format!(
+                "Response size exceeds limit {}",
+                policy.max_response_size
+            )));
+        }
+        body.extend_from_slice(&chunk);
+    }
+
+    let headers = response
+        .headers()
+        .iter()
+        .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.to_string())))
+        .collect();
+
+    Ok(FetchResponse {
+        url: response.url().to_string(),
+        status: response.status().as_u16(),
+        status_text: response
+            .status()
+            .canonical_reason()
+            .unwrap_or_default()
+            .to_string(),
+        headers,
+        body: String::from_utf8_lossy(&body).into_owned(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sandbox::{
+        Condition, ConditionOp, MemoryPolicyStore, NetPolicy, PolicyEffect, PolicyEngine,
+        PolicyRule, PolicyStage,
+    };
+
+    fn request(url: &str) -> FetchRequest {
+        FetchRequest {
+            url: url.to_string(),
+            method: None,
+            headers: HashMap::new(),
+            body: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_fetch_denied() {
+        // Without network access nor grants, nothing is fetched
+        let config = SandboxConfig::default();
+        let err = do_fetch(
+            &config,
+            None,
+            None,
+            "GET",
+            request("https://api.example.com"),
+        )
+        .await
+        .unwrap_err();
+        assert!(err.to_string().contains("Network access is not allowed"));
+
+        // With network access, the egress rules still apply
+        let config = SandboxConfig {
+            allow_net: true,
+            net_policy: NetPolicy {
+                allowed_hosts: vec!["api.example.com".into()],
+                ..NetPolicy::default()
+            },
+            ..SandboxConfig::default()
+        };
+        for url in ["https://other.example.com", "ftp://api.example.com"] {
+            let err = do_fetch(&config, None, None, "GET", request(url))
+                .await
+                .unwrap_err();
+            assert!(err.to_string().contains("not allowed"), "{}", err);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_fetch_op_policy() {
+        let engine = PolicyEngine::new(Arc::new(MemoryPolicyStore::new()));
+        engine
+            .create(
+                PolicyRule {
+                    name: "no-example-api".to_string(),
+                    description: None,
+                    stage: PolicyStage::Op,
+                    effect: PolicyEffect::Deny,
+                    conditions: vec![
+                        Condition {
+                            attribute: "op".to_string(),
+                            op: ConditionOp::Eq,
+                            value: "net".into(),
+                        },
+                        Condition {
+                            attribute: "host".to_string(),
+                            op: ConditionOp::Eq,
+                            value: "api.example.com".into(),
+                        },
+                    ],
+                    message: Some("api.example.com is off limits".to_string()),
+                    priority: 0,
+                    enabled: true,
+                },
+                "admin",
+            )
+            .unwrap();
+        let policy = FunctionPolicy::new(engine.clone(), "7", "1");
+
+        // Admin policies deny hosts the sandbox allows
+        let config = SandboxConfig {
+            allow_net: true,
+            ..SandboxConfig::default()
+        };
+        let err = do_fetch(
+            &config,
+            None,
+            Some(&policy),
+            "GET",
+            request("https://api.example.com/data"),
+        )
+        .await
+        .unwrap_err();
+        assert!(err.to_string().contains("off limits"), "{}", err);
+
+        let decisions = engine.decisions(Some("1"), 10).unwrap();
+        assert_eq!(decisions.len(), 1);
+        assert!(!decisions[0].allowed);
+        assert_eq!(decisions[0].attributes["method"], "GET");
+    }
+}
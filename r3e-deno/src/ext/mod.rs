@@ -1,19 +1,34 @@
 // Copyright @ 2023 - 2024, R3E Network
 // All Rights Reserved
 
+pub mod budget;
+pub mod chain;
 pub mod encoding;
+pub mod fetch;
 pub mod fhe;
 pub mod neo;
 pub mod neo_services;
 pub mod oracle;
+pub mod proof;
 pub mod sandbox_permissions;
+pub mod services;
+pub mod stream;
 pub mod tee;
+pub mod timers;
 pub mod zk;
 
-use deno_core::extension;
+use std::rc::Rc;
+
+use deno_core::{extension, CancelHandle, OpState};
 
 use crate::js_op;
-use crate::sandbox::SandboxConfig;
+use crate::sandbox::{FunctionPolicy, SandboxConfig};
+use budget::OpBudget;
+use chain::{
+    op_chain_eth_call, op_chain_neo_get_balance, op_chain_neo_get_block, op_chain_neo_get_storage,
+    RpcBudget,
+};
+use fetch::op_fetch;
 use fhe::{
     op_fhe_add, op_fhe_decrypt, op_fhe_encrypt, op_fhe_estimate_noise_budget, op_fhe_generate_keys,
     op_fhe_get_ciphertext, op_fhe_multiply, op_fhe_negate, op_fhe_subtract,
@@ -29,15 +44,27 @@ use neo_services::{
     op_neo_gas_bank_deposit, op_neo_gas_bank_get_account, op_neo_gas_bank_get_gas_price,
     op_neo_gas_bank_pay_gas, op_neo_gas_bank_withdraw, op_neo_meta_tx_get_next_nonce,
     op_neo_meta_tx_get_status, op_neo_meta_tx_get_transaction, op_neo_meta_tx_submit,
+    op_neo_nft_balance_of, op_neo_nft_get_token, op_neo_nft_owner_of, op_neo_nft_properties,
+    op_neo_nft_tokens_of, op_neo_nft_transfer,
 };
 use oracle::{
     op_oracle_cancel_request, op_oracle_get_price, op_oracle_get_random,
-    op_oracle_get_request_status, op_oracle_get_response, op_oracle_submit_request,
+    op_oracle_get_request_status, op_oracle_get_response, op_oracle_request_and_wait,
+    op_oracle_submit_request,
+};
+use proof::{
+    op_proof_eth_account, op_proof_eth_header, op_proof_eth_receipt, op_proof_eth_storage,
+    op_proof_eth_transaction, op_proof_neo_header, op_proof_neo_state, op_proof_neo_state_root,
+    op_proof_neo_transaction,
 };
 use sandbox_permissions::op_request_permission;
+use services::op_service_invoke;
 use std::sync::{Arc, Mutex};
+use stream::{op_stream_enabled, op_stream_write, StreamSink};
+use timers::{op_timer_admit, op_timer_fire, TimerBudget};
 use tee::{
-    op_neo_tee_execute, op_tee_execute, op_tee_generate_attestation, op_tee_verify_attestation,
+    op_neo_tee_execute, op_tee_execute, op_tee_generate_attestation, op_tee_sealed_delete,
+    op_tee_sealed_get, op_tee_sealed_put, op_tee_verify_attestation,
 };
 use zk::{op_zk_compile_circuit, op_zk_generate_keys, op_zk_generate_proof, op_zk_verify_proof};
 
@@ -45,20 +72,29 @@ extension!(
     r3e,
     ops = [
         op_defer,
+        op_fetch,
         op_neo_create_rpc_client,
         op_neo_create_key_pair,
         op_neo_create_transaction,
         op_neo_invoke_script,
+        op_chain_neo_get_balance,
+        op_chain_neo_get_storage,
+        op_chain_neo_get_block,
+        op_chain_eth_call,
         op_oracle_submit_request,
         op_oracle_get_request_status,
         op_oracle_get_response,
         op_oracle_cancel_request,
         op_oracle_get_price,
         op_oracle_get_random,
+        op_oracle_request_and_wait,
         op_tee_execute,
         op_tee_generate_attestation,
         op_tee_verify_attestation,
         op_neo_tee_execute,
+        op_tee_sealed_put,
+        op_tee_sealed_get,
+        op_tee_sealed_delete,
         op_neo_gas_bank_create_account,
         op_neo_gas_bank_get_account,
         op_neo_gas_bank_deposit,
@@ -74,6 +110,12 @@ extension!(
         op_neo_abstract_account_execute_operation,
         op_neo_abstract_account_get_operation_status,
         op_neo_abstract_account_get_next_nonce,
+        op_neo_nft_owner_of,
+        op_neo_nft_tokens_of,
+        op_neo_nft_balance_of,
+        op_neo_nft_properties,
+        op_neo_nft_get_token,
+        op_neo_nft_transfer,
         op_request_permission,
         op_zk_compile_circuit,
         op_zk_generate_keys,
@@ -88,11 +130,30 @@ extension!(
         op_fhe_negate,
         op_fhe_get_ciphertext,
         op_fhe_estimate_noise_budget,
+        op_stream_write,
+        op_stream_enabled,
+        op_timer_admit,
+        op_timer_fire,
+        op_service_invoke,
+        op_proof_eth_header,
+        op_proof_eth_transaction,
+        op_proof_eth_receipt,
+        op_proof_eth_account,
+        op_proof_eth_storage,
+        op_proof_neo_header,
+        op_proof_neo_transaction,
+        op_proof_neo_state_root,
+        op_proof_neo_state,
     ],
     esm_entry_point = "ext:r3e/r3e.js",
-    esm = [dir "src/js", "r3e.js", "encoding.js", "infra.js", "time.js", "neo.js", "oracle.js", "tee.js", "neo_services.js", "zk.js", "fhe.js"],
+    esm = [dir "src/js", "r3e.js", "encoding.js", "infra.js", "time.js", "fetch.js", "sandbox.js", "neo.js", "chain.js", "oracle.js", "tee.js", "neo_services.js", "zk.js", "fhe.js", "stream.js", "services.js", "proof.js"],
     state = |state| {
         state.put(Arc::new(Mutex::new(SandboxConfig::default())));
+        state.put(StreamSink::default());
+        state.put(TimerBudget::default());
+        state.put(RpcBudget::default());
+        state.put(OpBudget::default());
+        state.put(InvocationCancel::default());
         Ok(())
     }
 );
@@ -110,3 +171,61 @@ pub fn op_allowed(
     // In a real implementation, this would check permissions based on the sandbox configuration
     Ok(())
 }
+
+/// Check the admin policies of the running function, if any, before dispatching `op`
+pub fn check_op_policy(
+    state: &deno_core::OpState,
+    op: &str,
+    attributes: serde_json::Value,
+) -> Result<(), deno_core::error::AnyError> {
+    check_function_policy(state.try_borrow::<FunctionPolicy>(), op, attributes)
+}
+
+/// Check the admin policies of a function, if any, before `op` acts on its behalf, as ops that
+/// no longer hold the runtime state do
+pub fn check_function_policy(
+    policy: Option<&FunctionPolicy>,
+    op: &str,
+    attributes: serde_json::Value,
+) -> Result<(), deno_core::error::AnyError> {
+    let Some(policy) = policy else {
+        return Ok(());
+    };
+    let attributes = match attributes {
+        serde_json::Value::Object(attributes) => attributes,
+        _ => serde_json::Map::new(),
+    };
+    policy
+        .check_op(op, attributes)
+        .map_err(deno_core::error::AnyError::msg)
+}
+
+/// Tenant and function of the running invocation, set by the worker, that ops acting on the
+/// function's behalf are attributed to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvocationIdentity {
+    /// Tenant owning the function
+    pub tenant: String,
+
+    /// Function ID
+    pub function_id: String,
+}
+
+/// Cancelled when the invocation that started the pending async ops ends, so ops waiting on
+/// external work do not outlive it
+#[derive(Clone)]
+pub struct InvocationCancel(pub Rc<CancelHandle>);
+
+impl Default for InvocationCancel {
+    fn default() -> Self {
+        Self(CancelHandle::new_rc())
+    }
+}
+
+impl InvocationCancel {
+    /// Cancel the ops of the invocation that ended, and start afresh for the next one
+    pub fn end_invocation(state: &mut OpState) {
+        let ended = std::mem::take(state.borrow_mut::<InvocationCancel>());
+        ended.0.cancel();
+    }
+}
@@ -2,8 +2,10 @@
 // All Rights Reserved
 
 use deno_core::error::AnyError;
-use deno_core::op2;
+use deno_core::{op2, CancelFuture, OpState};
 use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::rc::Rc;
 use std::sync::Arc;
 
 // Import NeoRust SDK types
@@ -25,10 +27,14 @@ use r3e_neo_services::{
         GasBankAccount, GasBankDeposit, GasBankService, GasBankTransaction, GasBankWithdrawal,
     },
     meta_tx::{MetaTxRecord, MetaTxRequest, MetaTxResponse, MetaTxService, MetaTxStatus},
+    nft::{NftServiceTrait, NftTransferRequest},
     types::FeeModel,
     Error,
 };
 
+use super::budget::{finish_op, start_op, OpCall};
+use super::InvocationCancel;
+
 // Gas Bank operations
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -302,6 +308,7 @@ pub fn op_neo_meta_tx_get_transaction(request_id: String) -> Result<String, AnyE
         status: MetaTxStatus::Confirmed,
         created_at: chrono::Utc::now().timestamp() as u64,
         updated_at: chrono::Utc::now().timestamp() as u64,
+        fee_bumps: Vec::new(),
     };
 
     Ok(serde_json::to_string(&record)
@@ -528,3 +535,148 @@ pub fn op_neo_abstract_account_get_next_nonce(address: String) -> Result<u64, An
     let nonce = 42; // Mock nonce
     Ok(nonce)
 }
+
+// NEP-11 operations
+
+/// Check the admin policies of a NEP-11 call and charge it to the op budget, returning the
+/// NEP-11 service of the runtime
+fn admit_nft(
+    state: &Rc<RefCell<OpState>>,
+    op: &'static str,
+    method: &str,
+    contract: &str,
+) -> Result<(Arc<dyn NftServiceTrait>, OpCall), AnyError> {
+    let mut state = state.borrow_mut();
+    super::check_op_policy(
+        &state,
+        "nft",
+        serde_json::json!({ "nft_method": method, "contract": contract }),
+    )?;
+    let nft_service = state.borrow::<Arc<dyn NftServiceTrait>>().clone();
+    let call = start_op(&mut state, op)?;
+    Ok((nft_service, call))
+}
+
+/// Await a NEP-11 service call without blocking the event loop, returning its result as JSON.
+/// The call is dropped when the invocation ends first.
+async fn nft_call<T: Serialize>(
+    state: &Rc<RefCell<OpState>>,
+    op_call: OpCall,
+    method: &str,
+    call: impl std::future::Future<Output = Result<T, Error>>,
+) -> Result<String, AnyError> {
+    let cancel = state.borrow().borrow::<InvocationCancel>().clone();
+    let result = call.or_cancel(cancel.0).await;
+    finish_op(&mut state.borrow_mut(), op_call)?;
+    let result = match result {
+        Ok(result) => {
+            result.map_err(|e| AnyError::msg(format!("NEP-11 {} failed: {}", method, e)))?
+        }
+        Err(_) => {
+            return Err(AnyError::msg(format!(
+                "NEP-11 {} cancelled: the invocation ended",
+                method
+            )))
+        }
+    };
+
+    serde_json::to_string(&result)
+        .map_err(|e| AnyError::msg(format!("Failed to serialize {} result: {}", method, e)))
+}
+
+#[op2(async)]
+#[string]
+pub async fn op_neo_nft_owner_of(
+    state: Rc<RefCell<OpState>>,
+    #[string] contract: String,
+    #[string] token_id: String,
+) -> Result<String, AnyError> {
+    let (nft_service, call) = admit_nft(&state, "op_neo_nft_owner_of", "ownerOf", &contract)?;
+    nft_call(
+        &state,
+        call,
+        "ownerOf",
+        nft_service.owners_of(&contract, &token_id),
+    )
+    .await
+}
+
+#[op2(async)]
+#[string]
+pub async fn op_neo_nft_tokens_of(
+    state: Rc<RefCell<OpState>>,
+    #[string] contract: String,
+    #[string] owner: String,
+) -> Result<String, AnyError> {
+    let (nft_service, call) = admit_nft(&state, "op_neo_nft_tokens_of", "tokensOf", &contract)?;
+    nft_call(
+        &state,
+        call,
+        "tokensOf",
+        nft_service.tokens_of(&contract, &owner),
+    )
+    .await
+}
+
+#[op2(async)]
+#[string]
+pub async fn op_neo_nft_balance_of(
+    state: Rc<RefCell<OpState>>,
+    #[string] contract: String,
+    #[string] owner: String,
+) -> Result<String, AnyError> {
+    let (nft_service, call) = admit_nft(&state, "op_neo_nft_balance_of", "balanceOf", &contract)?;
+    // Balances are returned as decimal strings since they may exceed 2^53
+    nft_call(&state, call, "balanceOf", async {
+        nft_service
+            .balance_of(&contract, &owner)
+            .await
+            .map(|balance| balance.to_string())
+    })
+    .await
+}
+
+#[op2(async)]
+#[string]
+pub async fn op_neo_nft_properties(
+    state: Rc<RefCell<OpState>>,
+    #[string] contract: String,
+    #[string] token_id: String,
+) -> Result<String, AnyError> {
+    let (nft_service, call) = admit_nft(&state, "op_neo_nft_properties", "properties", &contract)?;
+    nft_call(
+        &state,
+        call,
+        "properties",
+        nft_service.properties(&contract, &token_id),
+    )
+    .await
+}
+
+#[op2(async)]
+#[string]
+pub async fn op_neo_nft_get_token(
+    state: Rc<RefCell<OpState>>,
+    #[string] contract: String,
+    #[string] token_id: String,
+) -> Result<String, AnyError> {
+    let (nft_service, call) = admit_nft(&state, "op_neo_nft_get_token", "getToken", &contract)?;
+    nft_call(
+        &state,
+        call,
+        "getToken",
+        nft_service.get_token(&contract, &token_id),
+    )
+    .await
+}
+
+#[op2(async)]
+#[string]
+pub async fn op_neo_nft_transfer(
+    state: Rc<RefCell<OpState>>,
+    #[serde] request: NftTransferRequest,
+) -> Result<String, AnyError> {
+    let (nft_service, call) =
+        admit_nft(&state, "op_neo_nft_transfer", "transfer", &request.contract)?;
+    nft_call(&state, call, "transfer", nft_service.transfer(request)).await
+}
@@ -2,9 +2,13 @@
 // All Rights Reserved
 
 use deno_core::error::AnyError;
-use deno_core::op2;
+use deno_core::{op2, CancelFuture, CancelHandle, OpState};
 use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::rc::Rc;
 use std::sync::Arc;
+use std::time::Duration;
+use uuid::Uuid;
 
 use r3e_oracle::service::create_oracle_request;
 use r3e_oracle::types::{PriceRequest, PriceResponse, RandomMethod, RandomRequest, RandomResponse};
@@ -13,6 +17,23 @@ use r3e_oracle::{
     OracleService,
 };
 
+use super::budget::{finish_op, start_op};
+use super::{InvocationCancel, InvocationIdentity};
+
+/// Time `op_oracle_request_and_wait` waits for a response by default, and at most
+const DEFAULT_WAIT_TIMEOUT_MS: u64 = 30_000;
+const MAX_WAIT_TIMEOUT_MS: u64 = 300_000;
+
+/// First polling interval of `op_oracle_request_and_wait` by default, and at least
+const DEFAULT_POLL_INTERVAL_MS: u64 = 250;
+const MIN_POLL_INTERVAL_MS: u64 = 50;
+
+/// Polling interval the backoff grows up to, unless the first interval is longer
+const MAX_POLL_BACKOFF_MS: u64 = 2_000;
+
+/// Consecutive failures to read the status of a request before waiting fails
+const MAX_POLL_FAILURES: u32 = 5;
+
 // Oracle request operations
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -31,9 +52,49 @@ pub struct OracleRequestResult {
 #[op2]
 #[serde]
 pub fn op_oracle_submit_request(
+    state: &mut OpState,
     #[serde] config: OracleRequestConfig,
-    #[state] oracle_service: &Arc<dyn OracleService>,
 ) -> Result<OracleRequestResult, AnyError> {
+    submit_request(state, config)
+}
+
+fn submit_request(
+    state: &mut OpState,
+    config: OracleRequestConfig,
+) -> Result<OracleRequestResult, AnyError> {
+    let request = build_request(state, config)?;
+    let call = start_op(state, "op_oracle_submit_request")?;
+    let oracle_service = state.borrow::<Arc<dyn OracleService>>().clone();
+
+    // Store request ID for response
+    let request_id = request.id.clone();
+
+    // Submit request
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    let result = rt.block_on(async {
+        oracle_service
+            .submit_request(request)
+            .await
+            .map_err(|e| AnyError::msg(format!("Failed to submit request: {}", e)))
+    });
+    finish_op(state, call)?;
+    result?;
+
+    Ok(OracleRequestResult { request_id })
+}
+
+fn build_request(state: &OpState, config: OracleRequestConfig) -> Result<OracleRequest, AnyError> {
+    // Price requests name the providers asked, which admin policies may restrict
+    let providers = config.data.get("sources").cloned().unwrap_or_default();
+    super::check_op_policy(
+        state,
+        "oracle",
+        serde_json::json!({
+            "request_type": config.request_type,
+            "providers": providers,
+        }),
+    )?;
+
     // Convert request type string to enum
     let request_type = match config.request_type.as_str() {
         "price" => OracleRequestType::Price,
@@ -53,23 +114,20 @@ pub fn op_oracle_submit_request(
     let data = serde_json::to_string(&config.data)
         .map_err(|e| AnyError::msg(format!("Failed to serialize request data: {}", e)))?;
 
-    // Create oracle request
-    let request =
-        create_oracle_request(request_type, data, config.callback_url, config.requester_id);
-
-    // Store request ID for response
-    let request_id = request.id.clone();
-
-    // Submit request
-    let rt = tokio::runtime::Runtime::new().unwrap();
-    rt.block_on(async {
-        oracle_service
-            .submit_request(request)
-            .await
-            .map_err(|e| AnyError::msg(format!("Failed to submit request: {}", e)))
-    })?;
+    // Requests of a worker's invocations are attributed to the function's tenant, whatever
+    // requester the script names
+    let requester_id = match state.try_borrow::<InvocationIdentity>() {
+        Some(identity) => identity.tenant.clone(),
+        None => config.requester_id,
+    };
 
-    Ok(OracleRequestResult { request_id })
+    // Create oracle request
+    Ok(create_oracle_request(
+        request_type,
+        data,
+        config.callback_url,
+        requester_id,
+    ))
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -80,9 +138,12 @@ pub struct OracleStatusResult {
 #[op2]
 #[serde]
 pub fn op_oracle_get_request_status(
+    state: &mut OpState,
     #[string] request_id: String,
-    #[state] oracle_service: &Arc<dyn OracleService>,
 ) -> Result<OracleStatusResult, AnyError> {
+    let call = start_op(state, "op_oracle_get_request_status")?;
+    let oracle_service = state.borrow::<Arc<dyn OracleService>>().clone();
+
     // Get request status
     let rt = tokio::runtime::Runtime::new().unwrap();
     let status = rt.block_on(async {
@@ -90,7 +151,9 @@ pub fn op_oracle_get_request_status(
             .get_request_status(&request_id)
             .await
             .map_err(|e| AnyError::msg(format!("Failed to get request status: {}", e)))
-    })?;
+    });
+    finish_op(state, call)?;
+    let status = status?;
 
     // Convert status to string
     let status_str = match status {
@@ -116,9 +179,12 @@ pub struct OracleResponseResult {
 #[op2]
 #[serde]
 pub fn op_oracle_get_response(
+    state: &mut OpState,
     #[string] request_id: String,
-    #[state] oracle_service: &Arc<dyn OracleService>,
 ) -> Result<OracleResponseResult, AnyError> {
+    let call = start_op(state, "op_oracle_get_response")?;
+    let oracle_service = state.borrow::<Arc<dyn OracleService>>().clone();
+
     // Get response
     let rt = tokio::runtime::Runtime::new().unwrap();
     let response = rt.block_on(async {
@@ -126,18 +192,222 @@ pub fn op_oracle_get_response(
             .get_response(&request_id)
             .await
             .map_err(|e| AnyError::msg(format!("Failed to get response: {}", e)))
-    })?;
+    });
+    finish_op(state, call)?;
+    let response = response?;
+
+    Ok(response_result(response))
+}
 
+fn response_result(response: OracleResponse) -> OracleResponseResult {
     // Parse response data
     let data = serde_json::from_str(&response.data)
         .unwrap_or_else(|_| serde_json::Value::String(response.data.clone()));
 
-    Ok(OracleResponseResult {
+    OracleResponseResult {
         data,
         status_code: response.status_code,
         timestamp: response.timestamp,
         error: response.error,
-    })
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OracleWaitConfig {
+    #[serde(flatten)]
+    pub request: OracleRequestConfig,
+
+    /// Time to wait for the response in milliseconds
+    pub timeout_ms: Option<u64>,
+
+    /// First polling interval in milliseconds, growing up to 2 seconds
+    pub poll_interval_ms: Option<u64>,
+
+    /// Key of the request across retries of the invocation: a retry of the same function with
+    /// the same key waits for the request already submitted instead of submitting another
+    pub idempotency_key: Option<String>,
+}
+
+/// Submit an oracle request and wait for its response without blocking the event loop.
+///
+/// The request is cancelled when the invocation ends or is terminated first, and when the
+/// wait times out unless it has an idempotency key, so that a retry can still pick it up.
+#[op2(async)]
+#[serde]
+pub async fn op_oracle_request_and_wait(
+    state: Rc<RefCell<OpState>>,
+    #[serde] config: OracleWaitConfig,
+) -> Result<OracleResponseResult, AnyError> {
+    let (oracle_service, cancel, mut request, call) = {
+        let mut state = state.borrow_mut();
+        let mut request = build_request(&state, config.request)?;
+        if let Some(key) = &config.idempotency_key {
+            let identity = state.try_borrow::<InvocationIdentity>().ok_or_else(|| {
+                AnyError::msg("Idempotency keys need the identity of the invocation")
+            })?;
+            request.id = idempotent_request_id(identity, key);
+        }
+        let call = start_op(&mut state, "op_oracle_request_and_wait")?;
+        (
+            state.borrow::<Arc<dyn OracleService>>().clone(),
+            state.borrow::<InvocationCancel>().clone(),
+            request,
+            call,
+        )
+    };
+    let timeout = config
+        .timeout_ms
+        .unwrap_or(DEFAULT_WAIT_TIMEOUT_MS)
+        .min(MAX_WAIT_TIMEOUT_MS);
+    let poll_interval = config
+        .poll_interval_ms
+        .unwrap_or(DEFAULT_POLL_INTERVAL_MS)
+        .max(MIN_POLL_INTERVAL_MS);
+
+    let result = request_and_wait(
+        oracle_service,
+        cancel.0,
+        request,
+        config.idempotency_key.is_some(),
+        Duration::from_millis(timeout),
+        Duration::from_millis(poll_interval),
+    )
+    .await;
+    finish_op(&mut state.borrow_mut(), call)?;
+    result
+}
+
+/// ID of the request of an idempotency key, in a namespace of the invocation's tenant and
+/// function so that the key of one function never names the request of another
+fn idempotent_request_id(identity: &InvocationIdentity, key: &str) -> String {
+    let function = format!("{}:{}", identity.tenant, identity.function_id);
+    let namespace = Uuid::new_v5(&Uuid::NAMESPACE_OID, function.as_bytes());
+    Uuid::new_v5(&namespace, key.as_bytes()).to_string()
+}
+
+/// Submit the request, or attach to the one of its ID if idempotent, and wait for its response
+async fn request_and_wait(
+    oracle_service: Arc<dyn OracleService>,
+    cancel: Rc<CancelHandle>,
+    request: OracleRequest,
+    idempotent: bool,
+    timeout: Duration,
+    poll_interval: Duration,
+) -> Result<OracleResponseResult, AnyError> {
+    // A retry re-attaches to the request of the same key, completed or not, if it is its own
+    let attached = if idempotent {
+        match oracle_service.get_request(&request.id).await {
+            Ok(submitted) if submitted.requester_id == request.requester_id => true,
+            Ok(_) => {
+                return Err(AnyError::msg(format!(
+                    "Oracle request {} belongs to another requester",
+                    request.id
+                )))
+            }
+            Err(_) => false,
+        }
+    } else {
+        false
+    };
+    let request_id = request.id.clone();
+    if !attached {
+        oracle_service
+            .submit_request(request)
+            .await
+            .map_err(|e| AnyError::msg(format!("Failed to submit request: {}", e)))?;
+    }
+
+    // Dropped with the op when the runtime is discarded, as after a kill or a timeout
+    let mut pending = PendingRequest {
+        oracle_service: Arc::clone(&oracle_service),
+        request_id: Some(request_id.clone()),
+    };
+    let wait = wait_for_response(oracle_service.as_ref(), &request_id, poll_interval);
+    match tokio::time::timeout(timeout, wait).or_cancel(cancel).await {
+        Ok(Ok(result)) => {
+            pending.request_id = None;
+            result
+        }
+        Ok(Err(_)) => {
+            if idempotent {
+                pending.request_id = None;
+            }
+            Err(AnyError::msg(format!(
+                "Oracle request {} timed out after {}ms",
+                request_id,
+                timeout.as_millis()
+            )))
+        }
+        Err(_) => Err(AnyError::msg(format!(
+            "Oracle request {} cancelled: the invocation ended",
+            request_id
+        ))),
+    }
+}
+
+/// Poll the status of a request until it completes or fails, backing off between polls
+async fn wait_for_response(
+    oracle_service: &dyn OracleService,
+    request_id: &str,
+    poll_interval: Duration,
+) -> Result<OracleResponseResult, AnyError> {
+    let max_interval = poll_interval.max(Duration::from_millis(MAX_POLL_BACKOFF_MS));
+    let mut interval = poll_interval;
+    let mut failures = 0;
+    loop {
+        match oracle_service.get_request_status(request_id).await {
+            Ok(status @ (OracleRequestStatus::Completed | OracleRequestStatus::Failed)) => {
+                let response = oracle_service
+                    .get_response(request_id)
+                    .await
+                    .map_err(|e| AnyError::msg(format!("Failed to get response: {}", e)))?;
+                if status == OracleRequestStatus::Failed {
+                    return Err(AnyError::msg(format!(
+                        "Oracle request failed: {}",
+                        response.error.as_deref().unwrap_or("unknown error")
+                    )));
+                }
+                return Ok(response_result(response));
+            }
+            Ok(_) => failures = 0,
+            Err(e) => {
+                failures += 1;
+                if failures >= MAX_POLL_FAILURES {
+                    return Err(AnyError::msg(format!(
+                        "Failed to get request status: {}",
+                        e
+                    )));
+                }
+            }
+        }
+
+        tokio::time::sleep(interval).await;
+        interval = (interval * 3 / 2).min(max_interval);
+    }
+}
+
+/// Request cancelled with the oracle service when dropped before it is cleared
+struct PendingRequest {
+    oracle_service: Arc<dyn OracleService>,
+    request_id: Option<String>,
+}
+
+impl Drop for PendingRequest {
+    fn drop(&mut self) {
+        let Some(request_id) = self.request_id.take() else {
+            return;
+        };
+        let Ok(handle) = tokio::runtime::Handle::try_current() else {
+            return;
+        };
+
+        let oracle_service = Arc::clone(&self.oracle_service);
+        handle.spawn(async move {
+            if let Err(e) = oracle_service.cancel_request(&request_id).await {
+                log::warn!("oracle: cancel request {} failed: {}", request_id, e);
+            }
+        });
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -148,9 +418,12 @@ pub struct OracleCancelResult {
 #[op2]
 #[serde]
 pub fn op_oracle_cancel_request(
+    state: &mut OpState,
     #[string] request_id: String,
-    #[state] oracle_service: &Arc<dyn OracleService>,
 ) -> Result<OracleCancelResult, AnyError> {
+    let call = start_op(state, "op_oracle_cancel_request")?;
+    let oracle_service = state.borrow::<Arc<dyn OracleService>>().clone();
+
     // Cancel request
     let rt = tokio::runtime::Runtime::new().unwrap();
     let success = rt.block_on(async {
@@ -158,7 +431,9 @@ pub fn op_oracle_cancel_request(
             .cancel_request(&request_id)
             .await
             .map_err(|e| AnyError::msg(format!("Failed to cancel request: {}", e)))
-    })?;
+    });
+    finish_op(state, call)?;
+    let success = success?;
 
     Ok(OracleCancelResult { success })
 }
@@ -170,20 +445,22 @@ pub struct PriceRequestConfig {
     pub symbol: String,
     pub currency: Option<String>,
     pub sources: Option<Vec<String>>,
+    pub max_age: Option<u64>,
     pub requester_id: String,
 }
 
 #[op2]
 #[serde]
 pub fn op_oracle_get_price(
+    state: &mut OpState,
     #[serde] config: PriceRequestConfig,
-    #[state] oracle_service: &Arc<dyn OracleService>,
 ) -> Result<OracleRequestResult, AnyError> {
     // Create price request
     let price_request = PriceRequest {
         symbol: config.symbol,
         currency: config.currency.unwrap_or_else(|| "USD".to_string()),
         sources: config.sources.unwrap_or_default(),
+        max_age: config.max_age,
     };
 
     // Convert to JSON
@@ -199,7 +476,7 @@ pub fn op_oracle_get_price(
     };
 
     // Submit request
-    op_oracle_submit_request(oracle_config, oracle_service)
+    submit_request(state, oracle_config)
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -215,8 +492,8 @@ pub struct RandomRequestConfig {
 #[op2]
 #[serde]
 pub fn op_oracle_get_random(
+    state: &mut OpState,
     #[serde] config: RandomRequestConfig,
-    #[state] oracle_service: &Arc<dyn OracleService>,
 ) -> Result<OracleRequestResult, AnyError> {
     // Convert method string to enum
     let method = match config.method.as_deref() {
@@ -254,5 +531,185 @@ pub fn op_oracle_get_random(
     };
 
     // Submit request
-    op_oracle_submit_request(oracle_config, oracle_service)
+    submit_request(state, oracle_config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+
+    /// Oracle service answering the requests marked completed, leaving the others pending
+    #[derive(Default)]
+    struct MockOracle {
+        requests: Mutex<HashMap<String, (OracleRequest, OracleRequestStatus)>>,
+        submitted: Mutex<Vec<String>>,
+        cancelled: Mutex<Vec<String>>,
+    }
+
+    impl MockOracle {
+        fn insert(&self, request: OracleRequest, status: OracleRequestStatus) {
+            let id = request.id.clone();
+            self.requests.lock().unwrap().insert(id, (request, status));
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl OracleService for MockOracle {
+        async fn submit_request(&self, request: OracleRequest) -> Result<String, OracleError> {
+            let id = request.id.clone();
+            self.submitted.lock().unwrap().push(id.clone());
+            self.insert(request, OracleRequestStatus::Pending);
+            Ok(id)
+        }
+
+        async fn get_request(&self, request_id: &str) -> Result<OracleRequest, OracleError> {
+            self.requests
+                .lock()
+                .unwrap()
+                .get(request_id)
+                .map(|(request, _)| request.clone())
+                .ok_or_else(|| OracleError::Validation(request_id.to_string()))
+        }
+
+        async fn get_request_status(
+            &self,
+            request_id: &str,
+        ) -> Result<OracleRequestStatus, OracleError> {
+            self.requests
+                .lock()
+                .unwrap()
+                .get(request_id)
+                .map(|(_, status)| *status)
+                .ok_or_else(|| OracleError::Validation(request_id.to_string()))
+        }
+
+        async fn get_response(&self, request_id: &str) -> Result<OracleResponse, OracleError> {
+            Ok(OracleResponse {
+                request_id: request_id.to_string(),
+                data: "42".to_string(),
+                status_code: 200,
+                timestamp: 0,
+                error: None,
+                signature: None,
+            })
+        }
+
+        async fn cancel_request(&self, request_id: &str) -> Result<bool, OracleError> {
+            self.cancelled.lock().unwrap().push(request_id.to_string());
+            Ok(true)
+        }
+    }
+
+    fn identity(tenant: &str, function_id: &str) -> InvocationIdentity {
+        InvocationIdentity {
+            tenant: tenant.to_string(),
+            function_id: function_id.to_string(),
+        }
+    }
+
+    fn request(id: &str, requester_id: &str) -> OracleRequest {
+        let mut request = create_oracle_request(
+            OracleRequestType::Price,
+            "{}".to_string(),
+            None,
+            requester_id.to_string(),
+        );
+        request.id = id.to_string();
+        request
+    }
+
+    async fn wait(
+        oracle: &Arc<MockOracle>,
+        request: OracleRequest,
+        idempotent: bool,
+        cancel: Rc<CancelHandle>,
+    ) -> Result<OracleResponseResult, AnyError> {
+        let result = request_and_wait(
+            oracle.clone(),
+            cancel,
+            request,
+            idempotent,
+            Duration::from_millis(200),
+            Duration::from_millis(MIN_POLL_INTERVAL_MS),
+        )
+        .await;
+        // Let the cancellation of a dropped request reach the service
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        result
+    }
+
+    #[test]
+    fn test_idempotent_request_id() {
+        let id = idempotent_request_id(&identity("7", "1"), "order-1");
+        assert_eq!(id, idempotent_request_id(&identity("7", "1"), "order-1"));
+        assert_ne!(id, idempotent_request_id(&identity("7", "1"), "order-2"));
+        assert_ne!(id, idempotent_request_id(&identity("7", "2"), "order-1"));
+        assert_ne!(id, idempotent_request_id(&identity("8", "1"), "order-1"));
+    }
+
+    #[tokio::test]
+    async fn test_attach() {
+        let oracle = Arc::new(MockOracle::default());
+        let id = idempotent_request_id(&identity("7", "1"), "order-1");
+        oracle.insert(request(&id, "7"), OracleRequestStatus::Completed);
+
+        // The retry waits for the request already submitted
+        let result = wait(&oracle, request(&id, "7"), true, CancelHandle::new_rc())
+            .await
+            .unwrap();
+        assert_eq!(result.data, serde_json::json!(42));
+        assert!(oracle.submitted.lock().unwrap().is_empty());
+
+        // The request of another requester is not attached to, nor replaced
+        let err = wait(&oracle, request(&id, "8"), true, CancelHandle::new_rc())
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("another requester"), "{}", err);
+        assert!(oracle.submitted.lock().unwrap().is_empty());
+
+        // Without a request of the key yet, it is submitted
+        let other = idempotent_request_id(&identity("7", "1"), "order-2");
+        let err = wait(&oracle, request(&other, "7"), true, CancelHandle::new_rc())
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("timed out"), "{}", err);
+        assert_eq!(*oracle.submitted.lock().unwrap(), vec![other]);
+    }
+
+    #[tokio::test]
+    async fn test_timeout() {
+        // A request without a key is cancelled when the wait times out
+        let oracle = Arc::new(MockOracle::default());
+        let err = wait(&oracle, request("once", "7"), false, CancelHandle::new_rc())
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("timed out after 200ms"), "{}", err);
+        assert_eq!(*oracle.cancelled.lock().unwrap(), vec!["once".to_string()]);
+
+        // One with a key is left for a retry to pick up
+        let oracle = Arc::new(MockOracle::default());
+        let id = idempotent_request_id(&identity("7", "1"), "order-1");
+        let err = wait(&oracle, request(&id, "7"), true, CancelHandle::new_rc())
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("timed out"), "{}", err);
+        assert!(oracle.cancelled.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_cancel() {
+        // Requests of an invocation that ended are cancelled, with a key or not
+        for idempotent in [false, true] {
+            let oracle = Arc::new(MockOracle::default());
+            let cancel = CancelHandle::new_rc();
+            cancel.cancel();
+            let err = wait(&oracle, request("ended", "7"), idempotent, cancel)
+                .await
+                .unwrap_err();
+            assert!(err.to_string().contains("the invocation ended"), "{}", err);
+            assert_eq!(*oracle.cancelled.lock().unwrap(), vec!["ended".to_string()]);
+        }
+    }
 }
@@ -0,0 +1,251 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+//! Verification of cross-chain proofs from functions.
+//!
+//! The ops only compute: functions fetch headers and proofs over `fetch` or the chain ops, and
+//! verify them here against hashes they trust, so a single RPC node cannot lie to them. Hashes
+//! and binary data are hex for Ethereum, and base64 or `0x` prefixed display hex for Neo, as
+//! the RPC nodes of each chain return them.
+
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
+use deno_core::error::{type_error, AnyError};
+use deno_core::op2;
+use serde::Deserialize;
+
+use r3e_core::proof::ethereum::{self, EthAccount, EthHeader, EthReceipt};
+use r3e_core::proof::neo::{self, NeoHeader, NeoStateRoot, NeoStorageItem, NeoWitness};
+use r3e_core::proof::{parse_hash, parse_hex};
+
+/// Most nodes in a proof
+const MAX_PROOF_NODES: usize = 64;
+
+fn parse_proof(proof: &[String]) -> Result<Vec<Vec<u8>>, AnyError> {
+    if proof.len() > MAX_PROOF_NODES {
+        return Err(type_error(format!(
+            "proof of {} nodes exceeds the limit of {} nodes",
+            proof.len(),
+            MAX_PROOF_NODES
+        )));
+    }
+    Ok(proof
+        .iter()
+        .map(|node| parse_hex(node))
+        .collect::<Result<_, _>>()?)
+}
+
+fn parse_base64(data: &str) -> Result<Vec<u8>, AnyError> {
+    BASE64
+        .decode(data)
+        .map_err(|e| type_error(format!("invalid base64: {}", e)))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct EthHeaderArgs {
+    /// RLP encoded header
+    pub header: String,
+    /// Block hash trusted by the function
+    pub trusted_hash: String,
+}
+
+/// Decode an Ethereum header, checking it hashes to the trusted block hash
+#[op2]
+#[serde]
+pub fn op_proof_eth_header(#[serde] args: EthHeaderArgs) -> Result<EthHeader, AnyError> {
+    let header = parse_hex(&args.header)?;
+    Ok(EthHeader::verify(
+        &header,
+        &parse_hash(&args.trusted_hash)?,
+    )?)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct EthTrieArgs {
+    /// Trie root, of a verified header or account
+    pub root: String,
+    /// Key proven: transaction index, account address or storage slot
+    pub key: serde_json::Value,
+    /// Trie nodes from the root, as `eth_getProof` returns them
+    pub proof: Vec<String>,
+}
+
+impl EthTrieArgs {
+    fn index(&self) -> Result<u64, AnyError> {
+        self.key
+            .as_u64()
+            .ok_or_else(|| type_error("index must be a non-negative integer"))
+    }
+
+    fn key<const N: usize>(&self) -> Result<[u8; N], AnyError> {
+        let key = self
+            .key
+            .as_str()
+            .ok_or_else(|| type_error("key must be a hex string"))?;
+        parse_hex(key)?
+            .try_into()
+            .map_err(|_| type_error(format!("key must be {} bytes", N)))
+    }
+}
+
+/// Verify a transaction against the transaction root of a header, returning its hash
+#[op2]
+#[string]
+pub fn op_proof_eth_transaction(#[serde] args: EthTrieArgs) -> Result<String, AnyError> {
+    let hash = ethereum::verify_transaction(
+        &parse_hash(&args.root)?,
+        args.index()?,
+        &parse_proof(&args.proof)?,
+    )?;
+    Ok(format!("0x{}", hex::encode(hash)))
+}
+
+/// Verify a receipt against the receipt root of a header
+#[op2]
+#[serde]
+pub fn op_proof_eth_receipt(#[serde] args: EthTrieArgs) -> Result<EthReceipt, AnyError> {
+    Ok(ethereum::verify_receipt(
+        &parse_hash(&args.root)?,
+        args.index()?,
+        &parse_proof(&args.proof)?,
+    )?)
+}
+
+/// Verify an account against the state root of a header, null if it does not exist
+#[op2]
+#[serde]
+pub fn op_proof_eth_account(#[serde] args: EthTrieArgs) -> Result<Option<EthAccount>, AnyError> {
+    Ok(ethereum::verify_account(
+        &parse_hash(&args.root)?,
+        &args.key()?,
+        &parse_proof(&args.proof)?,
+    )?)
+}
+
+/// Verify a storage slot against the storage root of an account, returning its value
+#[op2]
+#[string]
+pub fn op_proof_eth_storage(#[serde] args: EthTrieArgs) -> Result<String, AnyError> {
+    let value = ethereum::verify_storage(
+        &parse_hash(&args.root)?,
+        &args.key()?,
+        &parse_proof(&args.proof)?,
+    )?;
+    Ok(format!("0x{}", hex::encode(value)))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct NeoHeaderArgs {
+    /// Base64 serialized header
+    pub header: String,
+    /// Network magic
+    pub network: u32,
+    /// Consensus script hash trusted by the function, as an address or display hex
+    pub consensus: String,
+}
+
+/// Decode a Neo header, checking it is signed by the trusted consensus nodes
+#[op2]
+#[serde]
+pub fn op_proof_neo_header(#[serde] args: NeoHeaderArgs) -> Result<NeoHeader, AnyError> {
+    let header = parse_base64(&args.header)?;
+    Ok(NeoHeader::verify(
+        &header,
+        args.network,
+        &neo::parse_script_hash(&args.consensus)?,
+    )?)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct NeoTransactionArgs {
+    /// Merkle root of a verified header
+    pub merkle_root: String,
+    /// Transaction hash
+    pub tx_hash: String,
+    /// Index of the transaction in the block
+    pub index: usize,
+    /// Sibling hashes from the transaction up
+    pub path: Vec<String>,
+}
+
+/// Verify a transaction is in a block, given the Merkle root of its verified header
+#[op2]
+pub fn op_proof_neo_transaction(#[serde] args: NeoTransactionArgs) -> Result<(), AnyError> {
+    let path = args
+        .path
+        .iter()
+        .map(|hash| neo::parse_uint256(hash))
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(neo::verify_transaction(
+        &neo::parse_uint256(&args.merkle_root)?,
+        &neo::parse_uint256(&args.tx_hash)?,
+        args.index,
+        &path,
+    )?)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct NeoWitnessArgs {
+    pub invocation: String,
+    pub verification: String,
+}
+
+/// State root, as `getstateroot` returns it
+#[derive(Debug, Deserialize)]
+pub struct NeoStateRootArgs {
+    pub version: u8,
+    pub index: u32,
+    pub roothash: String,
+    pub witnesses: Vec<NeoWitnessArgs>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct NeoStateRootVerifyArgs {
+    pub state_root: NeoStateRootArgs,
+    /// Network magic
+    pub network: u32,
+    /// State validators script hash trusted by the function, as an address or display hex
+    pub validators: String,
+}
+
+/// Check a state root is signed by the trusted state validators
+#[op2]
+#[serde]
+pub fn op_proof_neo_state_root(
+    #[serde] args: NeoStateRootVerifyArgs,
+) -> Result<NeoStateRoot, AnyError> {
+    let state_root = args.state_root;
+    let [witness] = &state_root.witnesses[..] else {
+        return Err(type_error("state root must have a single witness"));
+    };
+    let witness = NeoWitness {
+        invocation: parse_base64(&witness.invocation)?,
+        verification: parse_base64(&witness.verification)?,
+    };
+
+    let state_root = NeoStateRoot::new(
+        state_root.version,
+        state_root.index,
+        neo::parse_uint256(&state_root.roothash)?,
+        witness,
+    );
+    state_root.verify(args.network, &neo::parse_script_hash(&args.validators)?)?;
+    Ok(state_root)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct NeoStateArgs {
+    /// Root hash of a verified state root
+    pub root_hash: String,
+    /// Base64 proof, as `getproof` returns it
+    pub proof: String,
+}
+
+/// Verify a storage item against the root hash of a verified state root
+#[op2]
+#[serde]
+pub fn op_proof_neo_state(#[serde] args: NeoStateArgs) -> Result<NeoStorageItem, AnyError> {
+    Ok(neo::verify_state_proof(
+        &neo::parse_uint256(&args.root_hash)?,
+        &parse_base64(&args.proof)?,
+    )?)
+}
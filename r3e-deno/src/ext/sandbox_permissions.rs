@@ -2,12 +2,14 @@
 // All Rights Reserved
 
 use deno_core::error::AnyError;
-use deno_core::op2;
+use deno_core::{op2, OpState};
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 use std::sync::Mutex;
 
-use crate::sandbox::{check_permission, SandboxConfig};
+use crate::sandbox::{
+    check_permission, FunctionGrants, GrantStatus, PermissionKind, SandboxConfig,
+};
 
 /// Sandbox permission request
 #[derive(Debug, Serialize, Deserialize)]
@@ -21,24 +23,71 @@ pub struct PermissionRequest {
 pub struct PermissionResponse {
     pub granted: bool,
     pub message: Option<String>,
+
+    /// Grant covering the resource, or the pending grant awaiting approval
+    #[serde(default)]
+    pub grant_id: Option<String>,
+}
+
+impl PermissionResponse {
+    fn granted(grant_id: Option<String>) -> Self {
+        Self {
+            granted: true,
+            message: None,
+            grant_id,
+        }
+    }
+
+    fn denied(message: String, grant_id: Option<String>) -> Self {
+        Self {
+            granted: false,
+            message: Some(message),
+            grant_id,
+        }
+    }
 }
 
 #[op2]
 #[serde]
 pub fn op_request_permission(
+    state: &mut OpState,
     #[serde] request: PermissionRequest,
-    #[state] sandbox_config: &Arc<Mutex<SandboxConfig>>,
 ) -> Result<PermissionResponse, AnyError> {
-    let config = sandbox_config.lock().unwrap();
+    let config = state
+        .borrow::<Arc<Mutex<SandboxConfig>>>()
+        .lock()
+        .unwrap()
+        .clone();
 
-    match check_permission(&request.operation, &config) {
-        Ok(_) => Ok(PermissionResponse {
-            granted: true,
-            message: None,
-        }),
-        Err(message) => Ok(PermissionResponse {
-            granted: false,
-            message: Some(message),
-        }),
+    let denied = match check_permission(&request.operation, &config) {
+        Ok(_) => return Ok(PermissionResponse::granted(None)),
+        Err(message) => message,
+    };
+
+    // Without a blanket permission, net, fs and env access is granted per resource
+    let (Some(kind), Some(resource), Some(grants)) = (
+        PermissionKind::from_operation(&request.operation),
+        request.resource.as_deref(),
+        state.try_borrow::<FunctionGrants>(),
+    ) else {
+        return Ok(PermissionResponse::denied(denied, None));
+    };
+
+    let grant = match grants.request(kind, resource) {
+        Ok(grant) => grant,
+        Err(err) => return Ok(PermissionResponse::denied(err.to_string(), None)),
+    };
+    match grant.status {
+        GrantStatus::Approved => match grants.authorize(kind, resource) {
+            Ok(()) => Ok(PermissionResponse::granted(Some(grant.id))),
+            Err(message) => Ok(PermissionResponse::denied(message, Some(grant.id))),
+        },
+        _ => Ok(PermissionResponse::denied(
+            format!(
+                "{} access to '{}' is pending approval (grant {})",
+                kind, resource, grant.id
+            ),
+            Some(grant.id),
+        )),
     }
 }
@@ -0,0 +1,143 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use deno_core::error::{type_error, AnyError};
+use deno_core::{op2, OpState};
+use r3e_core::mtls::MtlsConfig;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use super::budget::{finish_op, start_op};
+
+/// Invokes functions of platform services for the running function, the op behind the
+/// generated `platform` clients
+#[async_trait]
+pub trait ServiceInvoker: Send + Sync {
+    /// Invoke a function of a service by name, returning its result
+    async fn invoke(&self, service_id: &str, function: &str, input: Value)
+        -> Result<Value, String>;
+}
+
+/// API endpoint and credentials the worker invokes services with
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ServiceApiConfig {
+    /// Base URL of the API, including its path prefix
+    pub base_url: String,
+
+    /// Bearer token of the platform account invoking services
+    pub token: String,
+
+    /// Certificate the worker presents to the internal listener of the API
+    #[serde(default)]
+    pub mtls: Option<MtlsConfig>,
+}
+
+impl ServiceApiConfig {
+    /// HTTP client calling the API, with the certificate of the worker if configured
+    pub fn client(&self) -> reqwest::Client {
+        match self.mtls.as_ref().map(MtlsConfig::client) {
+            Some(Ok(client)) => client,
+            Some(Err(err)) => {
+                log::error!(
+                    "services: calling the API without a client certificate: {}",
+                    err
+                );
+                reqwest::Client::new()
+            }
+            None => reqwest::Client::new(),
+        }
+    }
+}
+
+/// Service invoker calling the API
+pub struct HttpServiceInvoker {
+    client: reqwest::Client,
+    base_url: String,
+    token: String,
+}
+
+impl HttpServiceInvoker {
+    pub fn new(config: &ServiceApiConfig) -> Self {
+        Self {
+            client: config.client(),
+            base_url: config.base_url.trim_end_matches('/').to_string(),
+            token: config.token.clone(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct InvocationResponse {
+    result: Value,
+    status: String,
+    error: Option<String>,
+}
+
+#[async_trait]
+impl ServiceInvoker for HttpServiceInvoker {
+    async fn invoke(
+        &self,
+        service_id: &str,
+        function: &str,
+        input: Value,
+    ) -> Result<Value, String> {
+        let mut url = reqwest::Url::parse(&self.base_url).map_err(|err| err.to_string())?;
+        url.path_segments_mut()
+            .map_err(|_| format!("invalid API base URL: {}", self.base_url))?
+            .extend(["services", service_id, "functions", function, "invoke"]);
+
+        let response = self
+            .client
+            .post(url)
+            .bearer_auth(&self.token)
+            .json(&serde_json::json!({ "input": input }))
+            .send()
+            .await
+            .map_err(|err| err.to_string())?;
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            return Err(format!("{}: {}", status, body));
+        }
+
+        let response: InvocationResponse = response.json().await.map_err(|err| err.to_string())?;
+        match response.error {
+            Some(error) => Err(error),
+            None if response.status != "success" => {
+                Err(format!("invocation ended with status {}", response.status))
+            }
+            None => Ok(response.result),
+        }
+    }
+}
+
+/// Invoke a function of a platform service
+#[op2(async)]
+#[serde]
+pub async fn op_service_invoke(
+    state: Rc<RefCell<OpState>>,
+    #[string] service_id: String,
+    #[string] function: String,
+    #[serde] input: Value,
+) -> Result<Value, AnyError> {
+    super::check_op_policy(
+        &state.borrow(),
+        "service",
+        serde_json::json!({ "service": service_id, "service_function": function }),
+    )?;
+    let invoker = state
+        .borrow()
+        .try_borrow::<Arc<dyn ServiceInvoker>>()
+        .cloned()
+        .ok_or_else(|| type_error("service invocation is not available"))?;
+    let call = start_op(&mut state.borrow_mut(), "op_service_invoke")?;
+
+    let result = invoker.invoke(&service_id, &function, input).await;
+    finish_op(&mut state.borrow_mut(), call)?;
+    result.map_err(|err| AnyError::msg(format!("{}/{}: {}", service_id, function, err)))
+}
@@ -0,0 +1,127 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use deno_core::error::{type_error, AnyError};
+use deno_core::{op2, OpState};
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+
+/// Chunks buffered before a writing function waits for the caller to catch up
+pub const STREAM_BUFFER: usize = 64;
+
+/// Maximum size of one chunk written by a function
+pub const MAX_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Incremental output of a streaming function invocation.
+///
+/// Streams are carried as newline-delimited JSON, one chunk per line, and always end with
+/// an `end` or an `error` chunk.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum StreamChunk {
+    /// Data written by the function
+    Data {
+        /// Sequence number, starting at 0
+        seq: u64,
+
+        /// Event name, for Server-Sent Events
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        event: Option<String>,
+
+        /// Chunk data
+        data: String,
+    },
+
+    /// The function completed
+    End,
+
+    /// The function failed
+    Error {
+        /// Error message
+        message: String,
+    },
+}
+
+/// Chunk written from JavaScript
+#[derive(Debug, Deserialize)]
+pub struct StreamWrite {
+    #[serde(default)]
+    pub event: Option<String>,
+
+    pub data: String,
+}
+
+/// Stream of the running invocation, kept in the op state
+#[derive(Default)]
+pub(crate) struct StreamSink {
+    sender: Option<mpsc::Sender<StreamChunk>>,
+    seq: u64,
+}
+
+impl StreamSink {
+    pub(crate) fn open(&mut self, buffer: usize) -> mpsc::Receiver<StreamChunk> {
+        let (tx, rx) = mpsc::channel(buffer);
+        self.sender = Some(tx);
+        self.seq = 0;
+        rx
+    }
+
+    pub(crate) fn close(&mut self) -> Option<mpsc::Sender<StreamChunk>> {
+        self.sender.take()
+    }
+}
+
+/// Write a chunk to the invocation stream.
+///
+/// Waits while the caller is behind, so a fast producer cannot buffer without bound.
+/// Returns false when the invocation is not streamed or the caller went away.
+#[op2(async)]
+pub async fn op_stream_write(
+    state: Rc<RefCell<OpState>>,
+    #[serde] chunk: StreamWrite,
+) -> Result<bool, AnyError> {
+    if chunk.data.len() > MAX_CHUNK_SIZE {
+        return Err(type_error(format!(
+            "stream chunk of {} bytes exceeds the limit of {} bytes",
+            chunk.data.len(),
+            MAX_CHUNK_SIZE
+        )));
+    }
+    if let Some(event) = &chunk.event {
+        if event.is_empty() || event.contains(['\n', '\r']) {
+            return Err(type_error("stream event name must be a single non-empty line"));
+        }
+    }
+
+    let (sender, seq) = {
+        let mut state = state.borrow_mut();
+        let sink = state.borrow_mut::<StreamSink>();
+        let Some(sender) = sink.sender.clone() else {
+            return Ok(false);
+        };
+
+        let seq = sink.seq;
+        sink.seq += 1;
+        (sender, seq)
+    };
+
+    let chunk = StreamChunk::Data {
+        seq,
+        event: chunk.event,
+        data: chunk.data,
+    };
+    Ok(sender.send(chunk).await.is_ok())
+}
+
+/// Whether the running invocation is streamed
+#[op2(fast)]
+pub fn op_stream_enabled(state: &mut OpState) -> bool {
+    state
+        .borrow::<StreamSink>()
+        .sender
+        .as_ref()
+        .is_some_and(|sender| !sender.is_closed())
+}
@@ -3,9 +3,11 @@
 
 use deno_core::error::AnyError;
 use deno_core::op2;
+use deno_core::OpState;
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 
+use r3e_tee::sealing::FunctionSealedStorage;
 use r3e_tee::service::create_default_neo_tee_service;
 use r3e_tee::types::{ExecutionOptions, NeoTeeRequest, NeoTeeResponse};
 use r3e_tee::{
@@ -13,6 +15,21 @@ use r3e_tee::{
     TeeSecurityLevel, TeeService,
 };
 
+use super::budget::{finish_op, start_op};
+
+/// Check the admin policies of a TEE operation on a platform, if it names one
+fn check_tee_policy(
+    state: &OpState,
+    operation: &str,
+    platform: Option<&str>,
+) -> Result<(), AnyError> {
+    super::check_op_policy(
+        state,
+        "tee",
+        serde_json::json!({ "tee_operation": operation, "platform": platform }),
+    )
+}
+
 // TEE execution operations
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -40,9 +57,12 @@ pub struct TeeExecutionResult {
 #[op2]
 #[serde]
 pub fn op_tee_execute(
+    state: &mut OpState,
     #[serde] config: TeeExecutionConfig,
-    #[state] tee_service: &Arc<dyn TeeService>,
 ) -> Result<TeeExecutionResult, AnyError> {
+    check_tee_policy(state, "execute", config.platform.as_deref())?;
+    let tee_service = state.borrow::<Arc<dyn TeeService>>().clone();
+
     // Convert platform string to enum
     let platform = match config.platform.as_deref() {
         Some("sgx") => Some(TeePlatform::Sgx),
@@ -86,12 +106,11 @@ pub fn op_tee_execute(
 
     // Execute the request
     let rt = tokio::runtime::Runtime::new().unwrap();
-    let response = rt.block_on(async {
-        tee_service
-            .execute(request)
-            .await
-            .map_err(|e| AnyError::msg(format!("Failed to execute TEE request: {}", e)))
-    })?;
+    let call = start_op(state, "op_tee_execute")?;
+    let response = rt.block_on(tee_service.execute(request));
+    finish_op(state, call)?;
+    let response =
+        response.map_err(|e| AnyError::msg(format!("Failed to execute TEE request: {}", e)))?;
 
     // Convert response to result
     let result = TeeExecutionResult {
@@ -119,9 +138,12 @@ pub struct TeeAttestationResult {
 #[op2]
 #[serde]
 pub fn op_tee_generate_attestation(
+    state: &mut OpState,
     #[serde] config: TeeAttestationConfig,
-    #[state] tee_service: &Arc<dyn TeeService>,
 ) -> Result<TeeAttestationResult, AnyError> {
+    check_tee_policy(state, "generate_attestation", Some(&config.platform))?;
+    let tee_service = state.borrow::<Arc<dyn TeeService>>().clone();
+
     // Convert platform string to enum
     let platform = match config.platform.as_str() {
         "sgx" => TeePlatform::Sgx,
@@ -138,12 +160,11 @@ pub fn op_tee_generate_attestation(
 
     // Generate attestation
     let rt = tokio::runtime::Runtime::new().unwrap();
-    let attestation = rt.block_on(async {
-        tee_service
-            .generate_attestation(platform)
-            .await
-            .map_err(|e| AnyError::msg(format!("Failed to generate attestation: {}", e)))
-    })?;
+    let call = start_op(state, "op_tee_generate_attestation")?;
+    let attestation = rt.block_on(tee_service.generate_attestation(platform));
+    finish_op(state, call)?;
+    let attestation =
+        attestation.map_err(|e| AnyError::msg(format!("Failed to generate attestation: {}", e)))?;
 
     Ok(TeeAttestationResult { attestation })
 }
@@ -161,17 +182,19 @@ pub struct TeeVerifyAttestationResult {
 #[op2]
 #[serde]
 pub fn op_tee_verify_attestation(
+    state: &mut OpState,
     #[serde] config: TeeVerifyAttestationConfig,
-    #[state] tee_service: &Arc<dyn TeeService>,
 ) -> Result<TeeVerifyAttestationResult, AnyError> {
+    check_tee_policy(state, "verify_attestation", None)?;
+    let tee_service = state.borrow::<Arc<dyn TeeService>>().clone();
+
     // Verify attestation
     let rt = tokio::runtime::Runtime::new().unwrap();
-    let is_valid = rt.block_on(async {
-        tee_service
-            .verify_attestation(&config.attestation)
-            .await
-            .map_err(|e| AnyError::msg(format!("Failed to verify attestation: {}", e)))
-    })?;
+    let call = start_op(state, "op_tee_verify_attestation")?;
+    let is_valid = rt.block_on(tee_service.verify_attestation(&config.attestation));
+    finish_op(state, call)?;
+    let is_valid =
+        is_valid.map_err(|e| AnyError::msg(format!("Failed to verify attestation: {}", e)))?;
 
     Ok(TeeVerifyAttestationResult { is_valid })
 }
@@ -202,9 +225,20 @@ pub struct NeoTeeExecutionResult {
 #[op2]
 #[serde]
 pub fn op_neo_tee_execute(
+    state: &mut OpState,
     #[serde] config: NeoTeeExecutionConfig,
-    #[state] tee_service: &Arc<dyn TeeService>,
 ) -> Result<NeoTeeExecutionResult, AnyError> {
+    super::check_op_policy(
+        state,
+        "tee",
+        serde_json::json!({
+            "tee_operation": "neo_execute",
+            "contract": config.script_hash,
+            "contract_method": config.operation,
+        }),
+    )?;
+    let tee_service = state.borrow::<Arc<dyn TeeService>>().clone();
+
     // Check if the service is a Neo TEE service
     let neo_tee_service = match tee_service.downcast_ref::<r3e_tee::service::NeoTeeService>() {
         Some(service) => service,
@@ -228,12 +262,11 @@ pub fn op_neo_tee_execute(
 
     // Execute the request
     let rt = tokio::runtime::Runtime::new().unwrap();
-    let response = rt.block_on(async {
-        neo_tee_service
-            .execute_neo_request(&request)
-            .await
-            .map_err(|e| AnyError::msg(format!("Failed to execute Neo TEE request: {}", e)))
-    })?;
+    let call = start_op(state, "op_neo_tee_execute")?;
+    let response = rt.block_on(neo_tee_service.execute_neo_request(&request));
+    finish_op(state, call)?;
+    let response =
+        response.map_err(|e| AnyError::msg(format!("Failed to execute Neo TEE request: {}", e)))?;
 
     // Convert response to result
     let result = NeoTeeExecutionResult {
@@ -247,3 +280,50 @@ pub fn op_neo_tee_execute(
 
     Ok(result)
 }
+
+// Sealed storage operations
+
+/// Sealed storage of the running function, failing if the worker runs without it
+fn sealed_storage(state: &OpState) -> Result<&FunctionSealedStorage, AnyError> {
+    state
+        .try_borrow::<FunctionSealedStorage>()
+        .ok_or_else(|| AnyError::msg("TEE sealed storage is not available"))
+}
+
+#[op2]
+pub fn op_tee_sealed_put(
+    state: &mut OpState,
+    #[string] key: String,
+    #[serde] value: serde_json::Value,
+) -> Result<(), AnyError> {
+    check_tee_policy(state, "sealed_put", None)?;
+    let value = serde_json::to_vec(&value)?;
+    sealed_storage(state)?
+        .put(&key, &value)
+        .map_err(|e| AnyError::msg(format!("Failed to seal value: {}", e)))
+}
+
+#[op2]
+#[serde]
+pub fn op_tee_sealed_get(
+    state: &mut OpState,
+    #[string] key: String,
+) -> Result<Option<serde_json::Value>, AnyError> {
+    check_tee_policy(state, "sealed_get", None)?;
+    let value = sealed_storage(state)?
+        .get(&key)
+        .map_err(|e| AnyError::msg(format!("Failed to unseal value: {}", e)))?;
+
+    match value {
+        Some(value) => Ok(Some(serde_json::from_slice(&value)?)),
+        None => Ok(None),
+    }
+}
+
+#[op2]
+pub fn op_tee_sealed_delete(state: &mut OpState, #[string] key: String) -> Result<bool, AnyError> {
+    check_tee_policy(state, "sealed_delete", None)?;
+    sealed_storage(state)?
+        .delete(&key)
+        .map_err(|e| AnyError::msg(format!("Failed to delete sealed value: {}", e)))
+}
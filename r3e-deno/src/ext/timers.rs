@@ -0,0 +1,171 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+use std::time::{Duration, Instant};
+
+use deno_core::error::{range_error, AnyError};
+use deno_core::{op2, OpState};
+use serde::{Deserialize, Serialize};
+
+/// Timer limits of one invocation.
+///
+/// `setTimeout` and `setInterval` are admitted against these limits, so a function cannot
+/// keep its runtime busy with long sleeps or endless intervals.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TimerLimits {
+    /// Timers an invocation may create
+    pub max_timers: u32,
+
+    /// How long after the start of an invocation its timers may still fire
+    pub max_wall_clock: Duration,
+}
+
+impl Default for TimerLimits {
+    fn default() -> Self {
+        Self {
+            max_timers: 1000,
+            max_wall_clock: Duration::from_secs(30),
+        }
+    }
+}
+
+/// Why a timer was refused
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum TimerBudgetError {
+    #[error("more than {0} timers created")]
+    TooManyTimers(u32),
+
+    #[error("timers must fire within {0:?} of the invocation start")]
+    WallClock(Duration),
+}
+
+/// Timers used by the running invocation, kept in the op state
+#[derive(Debug)]
+pub(crate) struct TimerBudget {
+    limits: TimerLimits,
+    epoch: u32,
+    started: Instant,
+    created: u32,
+    exceeded: Option<TimerBudgetError>,
+}
+
+impl Default for TimerBudget {
+    fn default() -> Self {
+        Self::new(TimerLimits::default())
+    }
+}
+
+impl TimerBudget {
+    pub(crate) fn new(limits: TimerLimits) -> Self {
+        Self {
+            limits,
+            epoch: 0,
+            started: Instant::now(),
+            created: 0,
+            exceeded: None,
+        }
+    }
+
+    /// Start the budget of a new invocation.
+    ///
+    /// Timers left over from earlier invocations are cancelled when they next fire.
+    pub(crate) fn reset(&mut self) {
+        self.epoch = self.epoch.wrapping_add(1);
+        self.started = Instant::now();
+        self.created = 0;
+        self.exceeded = None;
+    }
+
+    /// The limit the invocation exceeded, if any
+    pub(crate) fn take_exceeded(&mut self) -> Option<TimerBudgetError> {
+        self.exceeded.take()
+    }
+
+    fn deadline(&self) -> Instant {
+        self.started + self.limits.max_wall_clock
+    }
+
+    /// Admit a new timer, returning the epoch it belongs to
+    fn admit(&mut self, delay: Duration, now: Instant) -> Result<u32, TimerBudgetError> {
+        let result = if self.created >= self.limits.max_timers {
+            Err(TimerBudgetError::TooManyTimers(self.limits.max_timers))
+        } else if now + delay > self.deadline() {
+            Err(TimerBudgetError::WallClock(self.limits.max_wall_clock))
+        } else {
+            self.created += 1;
+            Ok(self.epoch)
+        };
+        result.map_err(|err| self.exceeded.insert(err).clone())
+    }
+
+    /// Whether a timer of `epoch` may run its callback.
+    ///
+    /// Timers of earlier invocations are stale and must be cancelled. Intervals fail once
+    /// the invocation runs past its wall clock budget.
+    fn fire(&mut self, epoch: u32, repeat: bool, now: Instant) -> Result<bool, TimerBudgetError> {
+        if epoch != self.epoch {
+            return Ok(false);
+        }
+        if repeat && now > self.deadline() {
+            let err = TimerBudgetError::WallClock(self.limits.max_wall_clock);
+            return Err(self.exceeded.insert(err).clone());
+        }
+        Ok(true)
+    }
+}
+
+/// Admit a timer about to be queued, returning its epoch
+#[op2(fast)]
+pub fn op_timer_admit(state: &mut OpState, delay: f64) -> Result<u32, AnyError> {
+    // NaN and negative delays fire immediately, as in browsers
+    let delay = Duration::from_secs_f64(delay.max(0.0).min(u32::MAX as f64) / 1000.0);
+    state
+        .borrow_mut::<TimerBudget>()
+        .admit(delay, Instant::now())
+        .map_err(|err| range_error(err.to_string()))
+}
+
+/// Whether a fired timer may run its callback
+#[op2(fast)]
+pub fn op_timer_fire(state: &mut OpState, epoch: u32, repeat: bool) -> Result<bool, AnyError> {
+    state
+        .borrow_mut::<TimerBudget>()
+        .fire(epoch, repeat, Instant::now())
+        .map_err(|err| range_error(err.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_timer_budget() {
+        let mut budget = TimerBudget::new(TimerLimits {
+            max_timers: 2,
+            max_wall_clock: Duration::from_secs(5),
+        });
+        let start = budget.started;
+
+        assert_eq!(budget.admit(Duration::from_secs(1), start), Ok(0));
+        assert_eq!(
+            budget.admit(Duration::from_secs(6), start),
+            Err(TimerBudgetError::WallClock(Duration::from_secs(5)))
+        );
+        assert_eq!(budget.admit(Duration::from_secs(5), start), Ok(0));
+        assert_eq!(
+            budget.admit(Duration::ZERO, start),
+            Err(TimerBudgetError::TooManyTimers(2))
+        );
+        assert!(budget.take_exceeded().is_some());
+        assert!(budget.take_exceeded().is_none());
+
+        let late = start + Duration::from_secs(6);
+        assert_eq!(budget.fire(0, false, late), Ok(true));
+        assert!(budget.fire(0, true, late).is_err());
+
+        budget.reset();
+        assert_eq!(budget.fire(0, false, late), Ok(false));
+        assert_eq!(budget.admit(Duration::ZERO, budget.started), Ok(1));
+        assert!(budget.take_exceeded().is_none());
+    }
+}
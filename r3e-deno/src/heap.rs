@@ -0,0 +1,254 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+//! Heap diagnostics of invocations running out of memory.
+//!
+//! When an isolate approaches its heap limit, the runtime terminates the invocation and
+//! reports the heap statistics, the object types retaining the most memory and, unless
+//! disabled in the sandbox configuration, a heap snapshot loadable in Chrome DevTools.
+
+use std::collections::HashMap;
+
+use deno_core::v8;
+use serde::{Deserialize, Serialize};
+
+/// Number of object types kept in a heap report
+pub const MAX_REPORTED_TYPES: usize = 20;
+
+/// Error type for heap snapshot analysis
+#[derive(Debug, thiserror::Error)]
+pub enum HeapSnapshotError {
+    #[error("heap: snapshot is not valid JSON: {0}")]
+    Parse(#[from] serde_json::Error),
+
+    #[error("heap: unexpected snapshot format: {0}")]
+    Format(String),
+}
+
+/// Heap statistics of an isolate
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HeapUsage {
+    /// Size of the heap in bytes
+    pub total_heap_size: u64,
+
+    /// Committed size of the heap in bytes
+    pub total_physical_size: u64,
+
+    /// Bytes used by live and not yet collected objects
+    pub used_heap_size: u64,
+
+    /// Heap limit in bytes
+    pub heap_size_limit: u64,
+
+    /// Bytes allocated with malloc by V8
+    pub malloced_memory: u64,
+
+    /// Bytes of external memory, such as array buffers, kept alive by JavaScript objects
+    pub external_memory: u64,
+
+    /// Number of native contexts
+    pub number_of_native_contexts: u64,
+
+    /// Number of contexts detached but not yet collected, a common sign of leaks
+    pub number_of_detached_contexts: u64,
+}
+
+impl From<&v8::HeapStatistics> for HeapUsage {
+    fn from(stats: &v8::HeapStatistics) -> Self {
+        Self {
+            total_heap_size: stats.total_heap_size() as u64,
+            total_physical_size: stats.total_physical_size() as u64,
+            used_heap_size: stats.used_heap_size() as u64,
+            heap_size_limit: stats.heap_size_limit() as u64,
+            malloced_memory: stats.malloced_memory() as u64,
+            external_memory: stats.external_memory() as u64,
+            number_of_native_contexts: stats.number_of_native_contexts() as u64,
+            number_of_detached_contexts: stats.number_of_detached_contexts() as u64,
+        }
+    }
+}
+
+/// Memory retained by the objects of one type
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ObjectTypeStats {
+    /// Constructor name of objects, or the node type in parentheses, e.g. `(string)`
+    pub name: String,
+
+    /// Number of objects
+    pub count: u64,
+
+    /// Shallow size of the objects in bytes
+    pub self_size: u64,
+}
+
+/// Heap diagnostics of an invocation that reached the heap limit
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct HeapReport {
+    /// Heap statistics when the limit was reached
+    pub usage: HeapUsage,
+
+    /// Object types with the largest shallow size, largest first
+    #[serde(default)]
+    pub largest_types: Vec<ObjectTypeStats>,
+
+    /// Heap snapshot in the `.heapsnapshot` JSON format
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub snapshot: Option<String>,
+}
+
+impl HeapReport {
+    /// Size of the heap snapshot in bytes, 0 without a snapshot
+    pub fn snapshot_size(&self) -> usize {
+        self.snapshot.as_ref().map_or(0, String::len)
+    }
+}
+
+#[derive(Deserialize)]
+struct RawSnapshot {
+    snapshot: RawHeader,
+    nodes: Vec<u64>,
+    strings: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct RawHeader {
+    meta: RawMeta,
+}
+
+#[derive(Deserialize)]
+struct RawMeta {
+    node_fields: Vec<String>,
+    node_types: Vec<serde_json::Value>,
+}
+
+/// Aggregate the nodes of a heap snapshot by type, returning the `limit` largest.
+///
+/// Objects are grouped by constructor name like the summary view of DevTools, other nodes
+/// such as strings, arrays and code by their node type.
+pub fn largest_object_types(
+    snapshot: &str,
+    limit: usize,
+) -> Result<Vec<ObjectTypeStats>, HeapSnapshotError> {
+    let raw: RawSnapshot = serde_json::from_str(snapshot)?;
+    let meta = &raw.snapshot.meta;
+
+    let field = |name: &str| {
+        meta.node_fields
+            .iter()
+            .position(|field| field == name)
+            .ok_or_else(|| HeapSnapshotError::Format(format!("missing node field {}", name)))
+    };
+    let (type_field, name_field, size_field) =
+        (field("type")?, field("name")?, field("self_size")?);
+    let node_types: Vec<&str> = meta
+        .node_types
+        .first()
+        .and_then(|types| types.as_array())
+        .ok_or_else(|| HeapSnapshotError::Format("missing node types".to_string()))?
+        .iter()
+        .map(|node_type| node_type.as_str().unwrap_or_default())
+        .collect();
+
+    let width = meta.node_fields.len();
+    if width == 0 || raw.nodes.len() % width != 0 {
+        return Err(HeapSnapshotError::Format(
+            "node count does not match the node fields".to_string(),
+        ));
+    }
+
+    let mut types: HashMap<String, (u64, u64)> = HashMap::new();
+    for node in raw.nodes.chunks_exact(width) {
+        let node_type = node_types.get(node[type_field] as usize).copied();
+        let name = match node_type {
+            Some("object") | Some("native") => raw
+                .strings
+                .get(node[name_field] as usize)
+                .cloned()
+                .ok_or_else(|| HeapSnapshotError::Format("node name out of range".to_string()))?,
+            Some(node_type) => format!("({})", node_type),
+            None => "(unknown)".to_string(),
+        };
+
+        let entry = types.entry(name).or_default();
+        entry.0 += 1;
+        entry.1 += node[size_field];
+    }
+
+    let mut types: Vec<ObjectTypeStats> = types
+        .into_iter()
+        .map(|(name, (count, self_size))| ObjectTypeStats {
+            name,
+            count,
+            self_size,
+        })
+        .collect();
+    types.sort_by(|a, b| {
+        b.self_size
+            .cmp(&a.self_size)
+            .then_with(|| a.name.cmp(&b.name))
+    });
+    types.truncate(limit);
+    Ok(types)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SNAPSHOT: &str = r#"{
+        "snapshot": {
+            "meta": {
+                "node_fields": ["type", "name", "id", "self_size", "edge_count", "trace_node_id"],
+                "node_types": [["hidden", "array", "string", "object", "code", "closure"], "string"],
+                "edge_fields": ["type", "name_or_index", "to_node"],
+                "edge_types": [["context", "element"], "string"]
+            },
+            "node_count": 5
+        },
+        "nodes": [
+            3, 0, 1, 64, 0, 0,
+            3, 0, 3, 64, 0, 0,
+            3, 1, 5, 16, 0, 0,
+            2, 2, 7, 100, 0, 0,
+            5, 3, 9, 32, 0, 0
+        ],
+        "edges": [],
+        "strings": ["Leak", "Point", "a leaked string", "handler"]
+    }"#;
+
+    #[test]
+    fn test_largest_object_types() {
+        let types = largest_object_types(SNAPSHOT, MAX_REPORTED_TYPES).unwrap();
+        let summary: Vec<_> = types
+            .iter()
+            .map(|stats| (stats.name.as_str(), stats.count, stats.self_size))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("Leak", 2, 128),
+                ("(string)", 1, 100),
+                ("(closure)", 1, 32),
+                ("Point", 1, 16)
+            ]
+        );
+
+        let types = largest_object_types(SNAPSHOT, 1).unwrap();
+        assert_eq!(types.len(), 1);
+        assert_eq!(types[0].name, "Leak");
+    }
+
+    #[test]
+    fn test_invalid_snapshot() {
+        assert!(matches!(
+            largest_object_types("not json", 1),
+            Err(HeapSnapshotError::Parse(_))
+        ));
+
+        let truncated = SNAPSHOT.replace("5, 3, 9, 32, 0, 0", "5, 3, 9, 32, 0");
+        assert!(matches!(
+            largest_object_types(&truncated, 1),
+            Err(HeapSnapshotError::Format(_))
+        ));
+    }
+}
@@ -0,0 +1,71 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+// Read-only chain queries, made through the platform's RPC endpoints. Each call counts
+// against the RPC call budget of the invocation.
+
+function toHex(bytes) {
+  return Array.from(bytes, (byte) => byte.toString(16).padStart(2, "0")).join("");
+}
+
+function fromHex(hex) {
+  const bytes = new Uint8Array(hex.length / 2);
+  for (let i = 0; i < bytes.length; i++) {
+    bytes[i] = parseInt(hex.substr(i * 2, 2), 16);
+  }
+  return bytes;
+}
+
+/**
+ * Neo N3 queries, exposed on `r3e.neo`
+ */
+export const neoQueries = {
+  /**
+   * Gets the NEP-17 balance of an account
+   * @param {string} address - Neo N3 address or script hash
+   * @param {string} [asset="GAS"] - `NEO`, `GAS` or the script hash of a NEP-17 token
+   * @returns {Promise<{asset: string, amount: bigint}>} Balance in the token's smallest unit
+   */
+  async getBalance(address, asset = "GAS") {
+    const balance = await Deno.core.ops.op_chain_neo_get_balance(String(address), String(asset));
+    return { asset: balance.asset, amount: BigInt(balance.amount) };
+  },
+
+  /**
+   * Reads a storage item of a contract
+   * @param {string} contract - Script hash of the contract
+   * @param {Uint8Array|string} key - Storage key, strings are UTF-8 encoded
+   * @returns {Promise<Uint8Array|null>} Stored value, or null if the key is not set
+   */
+  async getStorage(contract, key) {
+    const bytes = typeof key === "string" ? Deno.core.encode(key) : key;
+    const value = await Deno.core.ops.op_chain_neo_get_storage(String(contract), toHex(bytes));
+    return value === null ? null : fromHex(value);
+  },
+
+  /**
+   * Reads a block
+   * @param {number} [height] - Block height, the latest block if omitted
+   * @returns {Promise<{number: number, hash: string, timestamp: number, transactions: string[]}>}
+   */
+  getBlock(height = null) {
+    return Deno.core.ops.op_chain_neo_get_block(height);
+  },
+};
+
+/**
+ * Ethereum queries, exposed as `r3e.eth`
+ */
+export const eth = {
+  /**
+   * Calls a contract without changing state
+   * @param {string} to - Contract address
+   * @param {Uint8Array|string} data - ABI encoded calldata, as bytes or 0x-prefixed hex
+   * @returns {Promise<{success: boolean, result: string, error: string|null}>} Hex encoded
+   *   return data, or the revert reason
+   */
+  call(to, data) {
+    const calldata = typeof data === "string" ? data : `0x${toHex(data)}`;
+    return Deno.core.ops.op_chain_eth_call(String(to), calldata);
+  },
+};
@@ -0,0 +1,60 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+// Fetch API, subject to the sandbox network policy
+
+/**
+ * Response of a fetch request
+ */
+export class Response {
+  constructor(response) {
+    this.url = response.url;
+    this.status = response.status;
+    this.statusText = response.status_text;
+    this.headers = new Map(Object.entries(response.headers));
+    this.ok = response.status >= 200 && response.status < 300;
+    this._body = response.body;
+  }
+
+  /**
+   * Read the response body as text
+   * @returns {Promise<string>} Response body
+   */
+  async text() {
+    return this._body;
+  }
+
+  /**
+   * Parse the response body as JSON
+   * @returns {Promise<Object>} Parsed response body
+   */
+  async json() {
+    return JSON.parse(this._body);
+  }
+}
+
+/**
+ * Fetch a resource from the network
+ * @param {string} url - Resource URL
+ * @param {Object} [init] - Request options: method, headers and body
+ * @returns {Promise<Response>} Response
+ */
+export async function fetch(url, init = {}) {
+  let body = init.body ?? null;
+  if (body !== null && typeof body !== "string") {
+    body = JSON.stringify(body);
+  }
+
+  const headers = init.headers instanceof Map
+    ? Object.fromEntries(init.headers)
+    : { ...(init.headers || {}) };
+
+  const response = await Deno.core.ops.op_fetch({
+    url: String(url),
+    method: init.method || "GET",
+    headers,
+    body,
+  });
+
+  return new Response(response);
+}
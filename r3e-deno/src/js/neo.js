@@ -3,6 +3,8 @@
 
 // Neo N3 JavaScript API for r3e-faas platform
 
+import { neoQueries } from "./chain.js";
+
 /**
  * Creates a Neo N3 RPC client for interacting with the blockchain
  * @param {Object} config - Configuration for the RPC client
@@ -147,3 +149,6 @@ export const Neo = {
     addressToScriptHash
   }
 };
+
+// The runtime exposes the API and the chain queries as `r3e.neo`
+export const neo = { ...Neo, ...neoQueries };
@@ -353,3 +353,83 @@ export class AbstractAccountService {
     return Deno.core.ops.op_neo_abstract_account_get_next_nonce(address);
   }
 }
+
+/**
+ * NEP-11 Service
+ * Provides non-fungible token operations for Neo N3 blockchain
+ */
+export class NftService {
+  /**
+   * Gets the owners of a token, a single owner unless the token is divisible
+   * @param {string} contract - Token contract hash
+   * @param {string} tokenId - Hex encoded token ID
+   * @returns {Promise<string[]>} Owner script hashes
+   */
+  static async ownerOf(contract, tokenId) {
+    const result = await Deno.core.ops.op_neo_nft_owner_of(contract, tokenId);
+    return JSON.parse(result);
+  }
+
+  /**
+   * Gets the tokens of an owner
+   * @param {string} contract - Token contract hash
+   * @param {string} owner - Owner address or script hash
+   * @returns {Promise<string[]>} Hex encoded token IDs
+   */
+  static async tokensOf(contract, owner) {
+    const result = await Deno.core.ops.op_neo_nft_tokens_of(contract, owner);
+    return JSON.parse(result);
+  }
+
+  /**
+   * Gets the number of tokens of an owner
+   * @param {string} contract - Token contract hash
+   * @param {string} owner - Owner address or script hash
+   * @returns {Promise<bigint>} Balance
+   */
+  static async balanceOf(contract, owner) {
+    const result = await Deno.core.ops.op_neo_nft_balance_of(contract, owner);
+    return BigInt(JSON.parse(result));
+  }
+
+  /**
+   * Gets the properties of a token
+   * @param {string} contract - Token contract hash
+   * @param {string} tokenId - Hex encoded token ID
+   * @returns {Promise<Object>} Token properties
+   */
+  static async properties(contract, tokenId) {
+    const result = await Deno.core.ops.op_neo_nft_properties(contract, tokenId);
+    return JSON.parse(result);
+  }
+
+  /**
+   * Gets a token with its owners and properties
+   * @param {string} contract - Token contract hash
+   * @param {string} tokenId - Hex encoded token ID
+   * @returns {Promise<Object>} Token
+   */
+  static async getToken(contract, tokenId) {
+    const result = await Deno.core.ops.op_neo_nft_get_token(contract, tokenId);
+    return JSON.parse(result);
+  }
+
+  /**
+   * Transfers a token from an abstract account
+   * @param {Object} request - Transfer request
+   * @param {string} request.account_address - Abstract account address
+   * @param {string} request.contract - Token contract hash
+   * @param {string} request.to - Recipient address or script hash
+   * @param {string} request.token_id - Hex encoded token ID
+   * @param {string} [request.amount] - Amount, only for divisible tokens
+   * @param {string} [request.data] - Data passed to the recipient's onNEP11Payment
+   * @param {Object[]} request.signatures - Account signatures
+   * @param {number} request.nonce - Operation nonce
+   * @param {number} request.deadline - Operation deadline (timestamp)
+   * @returns {Promise<Object>} Operation response
+   */
+  static async transfer(request) {
+    const result = await Deno.core.ops.op_neo_nft_transfer(request);
+    return JSON.parse(result);
+  }
+}
@@ -55,6 +55,31 @@ class Oracle {
     const result = Deno.core.ops.op_oracle_cancel_request(requestId);
     return result.success;
   }
+
+  /**
+   * Submit an oracle request and wait for its response, polling inside the runtime.
+   * The request is cancelled if the invocation ends first.
+   * @param {string} requestType - Type of oracle request (price, random, weather, sports, custom)
+   * @param {Object} data - Request data
+   * @param {string} requesterId - Requester ID
+   * @param {Object} [options] - Wait options
+   * @param {number} [options.timeout=30000] - Timeout in milliseconds, at most 300000
+   * @param {number} [options.pollInterval=250] - First polling interval in milliseconds
+   * @param {string} [options.idempotencyKey] - Key a retried invocation waits for the same request with
+   * @param {string} [options.callbackUrl] - Optional callback URL
+   * @returns {Promise<Object>} Oracle response
+   */
+  static requestAndWait(requestType, data, requesterId, options = {}) {
+    return Deno.core.ops.op_oracle_request_and_wait({
+      request_type: requestType,
+      data,
+      callback_url: options.callbackUrl ?? null,
+      requester_id: requesterId,
+      timeout_ms: options.timeout ?? null,
+      poll_interval_ms: options.pollInterval ?? null,
+      idempotency_key: options.idempotencyKey ?? null,
+    });
+  }
   
   /**
    * Get price data for a cryptocurrency
@@ -62,13 +87,16 @@ class Oracle {
    * @param {string} [currency="USD"] - Currency to convert to
    * @param {string[]} [sources=[]] - Preferred price sources
    * @param {string} requesterId - Requester ID
+   * @param {Object} [options={}] - Price options
+   * @param {number} [options.maxAge] - Maximum age in seconds of cached prices
    * @returns {Promise<string>} Request ID
    */
-  static async getPrice(symbol, currency = "USD", sources = [], requesterId) {
+  static async getPrice(symbol, currency = "USD", sources = [], requesterId, options = {}) {
     const config = {
       symbol,
       currency,
       sources,
+      max_age: options.maxAge ?? null,
       requester_id: requesterId,
     };
     
@@ -273,4 +301,7 @@ class Oracle {
   }
 }
 
-export { Oracle };
+// The runtime exposes the class as `r3e.oracle`
+const oracle = Oracle;
+
+export { Oracle, oracle };
@@ -0,0 +1,114 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+// Cross-chain proof verification, so functions need not trust the RPC node serving the data.
+// Every check throws when the proof does not lead to the trusted hash.
+
+export const proof = {
+  eth: {
+    /**
+     * Decode an Ethereum header, checking it hashes to a trusted block hash
+     * @param {string} header - RLP encoded header, hex
+     * @param {string} trustedHash - Block hash the function trusts
+     * @returns {Object} Header, with its state, transaction and receipt roots
+     */
+    verifyHeader(header, trustedHash) {
+      return Deno.core.ops.op_proof_eth_header({ header, trusted_hash: trustedHash });
+    },
+
+    /**
+     * Verify a transaction against the transaction root of a verified header
+     * @param {string} root - Transaction root
+     * @param {number} index - Index of the transaction in the block
+     * @param {string[]} nodes - Trie nodes from the root, hex
+     * @returns {string} Transaction hash
+     */
+    verifyTransaction(root, index, nodes) {
+      return Deno.core.ops.op_proof_eth_transaction({ root, key: index, proof: nodes });
+    },
+
+    /**
+     * Verify a receipt against the receipt root of a verified header
+     * @param {string} root - Receipt root
+     * @param {number} index - Index of the transaction in the block
+     * @param {string[]} nodes - Trie nodes from the root, hex
+     * @returns {Object} Receipt, with its status and logs
+     */
+    verifyReceipt(root, index, nodes) {
+      return Deno.core.ops.op_proof_eth_receipt({ root, key: index, proof: nodes });
+    },
+
+    /**
+     * Verify an account against the state root of a verified header
+     * @param {string} root - State root
+     * @param {string} address - Account address
+     * @param {string[]} nodes - `accountProof` of `eth_getProof`
+     * @returns {Object|null} Account, or null if it does not exist
+     */
+    verifyAccount(root, address, nodes) {
+      return Deno.core.ops.op_proof_eth_account({ root, key: address, proof: nodes });
+    },
+
+    /**
+     * Verify a storage slot against the storage root of a verified account
+     * @param {string} root - Storage root
+     * @param {string} slot - Storage slot, 32 bytes hex
+     * @param {string[]} nodes - `storageProof[].proof` of `eth_getProof`
+     * @returns {string} Slot value, 32 bytes hex
+     */
+    verifyStorage(root, slot, nodes) {
+      return Deno.core.ops.op_proof_eth_storage({ root, key: slot, proof: nodes });
+    },
+  },
+
+  neo: {
+    /** Network magic of the N3 MainNet */
+    MAINNET: 860833102,
+
+    /** Network magic of the N3 TestNet */
+    TESTNET: 894710606,
+
+    /**
+     * Decode a Neo header, checking it is signed by trusted consensus nodes
+     * @param {string} header - Serialized header, base64
+     * @param {number} network - Network magic
+     * @param {string} consensus - Trusted consensus script hash, as an address or hex
+     * @returns {Object} Header, whose `nextConsensus` verifies the next one
+     */
+    verifyHeader(header, network, consensus) {
+      return Deno.core.ops.op_proof_neo_header({ header, network, consensus });
+    },
+
+    /**
+     * Check a transaction is under the Merkle root of a verified header
+     * @param {string} merkleRoot - Merkle root of the header
+     * @param {string} txHash - Transaction hash
+     * @param {number} index - Index of the transaction in the block
+     * @param {string[]} path - Sibling hashes from the transaction up
+     */
+    verifyTransaction(merkleRoot, txHash, index, path) {
+      Deno.core.ops.op_proof_neo_transaction({ merkle_root: merkleRoot, tx_hash: txHash, index, path });
+    },
+
+    /**
+     * Check a state root is signed by trusted state validators
+     * @param {Object} stateRoot - State root, as `getstateroot` returns it
+     * @param {number} network - Network magic
+     * @param {string} validators - Trusted state validators script hash, as an address or hex
+     * @returns {Object} State root
+     */
+    verifyStateRoot(stateRoot, network, validators) {
+      return Deno.core.ops.op_proof_neo_state_root({ state_root: stateRoot, network, validators });
+    },
+
+    /**
+     * Verify a storage item against the root hash of a verified state root
+     * @param {string} rootHash - Root hash of the state root
+     * @param {string} nodes - Proof, as `getproof` returns it
+     * @returns {Object} Storage item, with its contract ID, key and value
+     */
+    verifyStorage(rootHash, nodes) {
+      return Deno.core.ops.op_proof_neo_state({ root_hash: rootHash, proof: nodes });
+    },
+  },
+};
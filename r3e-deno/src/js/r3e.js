@@ -2,20 +2,28 @@
 // All Rights Reserved
 
 import { defer } from "./infra.js";
-import { sleep } from "./time.js";
+import { sleep, setTimeout, setInterval, clearTimeout, clearInterval } from "./time.js";
+import { fetch, Response } from "./fetch.js";
 import { encode, decode } from "./encoding.js";
 import { neo } from "./neo.js";
+import { eth } from "./chain.js";
 import { oracle } from "./oracle.js";
 import { tee } from "./tee.js";
 import { neoServices } from "./neo_services.js";
 import { sandbox } from "./sandbox.js";
+import { stream } from "./stream.js";
+import { services } from "./services.js";
+import { proof } from "./proof.js";
 import * as zkModule from "./zk.js";
 import * as fheModule from "./fhe.js";
 
+// Functions get the budgeted timers as globals, as in Deno
+Object.assign(globalThis, { setTimeout, setInterval, clearTimeout, clearInterval });
+
 // Export the ZK module as 'zk'
 export const zk = zkModule;
 
 // Export the FHE module as 'fhe'
 export const fhe = fheModule;
 
-export { defer, sleep, encode, decode, neo, oracle, tee, neoServices, sandbox };
+export { defer, sleep, fetch, Response, encode, decode, neo, eth, oracle, tee, neoServices, sandbox, stream, services, proof };
@@ -0,0 +1,32 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+/**
+ * Platform services, invoked from functions.
+ *
+ * Typed clients for a service are generated by `GET /services/{id}/sdk?target=platform`.
+ */
+export const services = {
+  /**
+   * Invokes a function of a service by name
+   * @param {string} serviceId - Service ID
+   * @param {string} name - Function name
+   * @param {any} input - Invocation input
+   * @returns {Promise<any>} The function's result
+   */
+  invoke(serviceId, name, input = null) {
+    return Deno.core.ops.op_service_invoke(String(serviceId), String(name), input);
+  },
+
+  /**
+   * Creates an untyped client calling the functions of a service as methods
+   * @param {string} serviceId - Service ID
+   * @returns {object} Client whose method `name(input)` invokes the function `name`
+   */
+  client(serviceId) {
+    return new Proxy({}, {
+      get: (_target, name) =>
+        typeof name === "string" ? (input) => services.invoke(serviceId, name, input) : undefined,
+    });
+  },
+};
@@ -0,0 +1,32 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+// Streaming output, delivered to the caller while the function is still running
+
+export const stream = {
+  /**
+   * Whether the caller asked for a streamed response
+   * @returns {boolean} True if written chunks reach the caller
+   */
+  get enabled() {
+    return Deno.core.ops.op_stream_enabled();
+  },
+
+  /**
+   * Write a chunk to the caller, waiting while the caller is behind
+   * @param {*} data - Chunk data, non-string values are sent as JSON
+   * @param {Object} [options] - Chunk options
+   * @param {string} [options.event] - Event name, for Server-Sent Events
+   * @returns {Promise<boolean>} False if the response is not streamed or the caller went away
+   */
+  async write(data, options = {}) {
+    if (typeof data !== "string") {
+      data = JSON.stringify(data);
+    }
+
+    return await Deno.core.ops.op_stream_write({
+      event: options.event ?? null,
+      data,
+    });
+  },
+};
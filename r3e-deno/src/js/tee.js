@@ -90,6 +90,35 @@ class TEE {
     return Deno.core.ops.op_neo_tee_execute(config);
   }
   
+  /**
+   * Seal a value into the function's private storage, readable only by an enclave
+   * with the same measurement
+   * @param {string} key - Storage key
+   * @param {*} value - JSON serializable value
+   * @returns {Promise<void>}
+   */
+  static async sealedPut(key, value) {
+    Deno.core.ops.op_tee_sealed_put(key, value);
+  }
+
+  /**
+   * Unseal a value from the function's private storage
+   * @param {string} key - Storage key
+   * @returns {Promise<*>} The value, or null if the key is not set
+   */
+  static async sealedGet(key) {
+    return Deno.core.ops.op_tee_sealed_get(key);
+  }
+
+  /**
+   * Delete a value from the function's private storage
+   * @param {string} key - Storage key
+   * @returns {Promise<boolean>} Whether the key was set
+   */
+  static async sealedDelete(key) {
+    return Deno.core.ops.op_tee_sealed_delete(key);
+  }
+
   /**
    * Create a secure key pair in the TEE
    * @param {string} [algorithm="EC"] - Key algorithm (EC, RSA)
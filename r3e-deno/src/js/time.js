@@ -3,7 +3,7 @@
 // Copyright @ 2023 - 2024, R3E Network
 // All Rights Reserved
 
-import { op_defer } from "ext:core/ops";
+import { op_defer, op_timer_admit, op_timer_fire } from "ext:core/ops";
 import { asString, uncurryThis } from "./infra.js";
 
 function checkThis(thisArg) {
@@ -35,6 +35,9 @@ export function setImmediate(callback, ...args) {
 
 /**
  * Call a callback function after a delay.
+ *
+ * Timers are admitted against the timer budget of the invocation; a timer that would
+ * exceed it throws a RangeError.
  */
 export function setTimeout(callback, timeout = 0, ...args) {
     checkThis(this);
@@ -44,9 +47,15 @@ export function setTimeout(callback, timeout = 0, ...args) {
         callback = () => primordials.indirectEval(unboundCallback);
     }
 
+    timeout = Number(timeout) || 0;
+    const epoch = op_timer_admit(timeout);
     const unboundCallback = callback;
     const asyncContext = getAsyncContext();
     callback = () => {
+        // Timers of an earlier invocation are dropped
+        if (!op_timer_fire(epoch, false)) {
+            return;
+        }
         const oldContext = getAsyncContext();
         try {
             setAsyncContext(asyncContext);
@@ -65,7 +74,10 @@ export function setTimeout(callback, timeout = 0, ...args) {
 }
 
 /**
- * Call a callback function after a delay.
+ * Call a callback function repeatedly, with a delay between calls.
+ *
+ * An interval still running when the wall clock budget of the invocation runs out fails
+ * the invocation.
  */
 export function setInterval(callback, timeout = 0, ...args) {
     checkThis(this);
@@ -73,9 +85,23 @@ export function setInterval(callback, timeout = 0, ...args) {
         const unboundCallback = asString(callback);
         callback = () => primordials.indirectEval(unboundCallback);
     }
+    timeout = Number(timeout) || 0;
+    const epoch = op_timer_admit(timeout);
     const unboundCallback = callback;
     const asyncContext = getAsyncContext();
+    let id;
     callback = () => {
+        let admitted;
+        try {
+            admitted = op_timer_fire(epoch, true);
+        } catch (err) {
+            core.cancelTimer(id);
+            throw err;
+        }
+        if (!admitted) {
+            core.cancelTimer(id);
+            return;
+        }
         const oldContext = getAsyncContext(asyncContext);
         try {
             setAsyncContext(asyncContext);
@@ -85,12 +111,13 @@ export function setInterval(callback, timeout = 0, ...args) {
         }
     };
 
-    return core.queueUserTimer(
+    id = core.queueUserTimer(
         core.getTimerDepth() + 1,
         true,
         timeout,
         callback,
     );
+    return id;
 }
 
 /**
@@ -109,6 +136,13 @@ export function clearInterval(id = 0) {
     core.cancelTimer(id);
 }
 
+/**
+ * Resolve after a delay.
+ */
+export function sleep(ms = 0) {
+    return new Promise((resolve) => setTimeout(resolve, ms));
+}
+
 // Defer to avoid starving the event loop. Not using queueMicrotask()
 // for that reason: it lets promises make forward progress but can
 // still starve other parts of the event loop.
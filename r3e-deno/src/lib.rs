@@ -3,8 +3,10 @@
 
 pub mod consts;
 pub mod ext;
+pub mod heap;
 pub mod sandbox;
 pub mod security;
+pub mod source_map;
 
 #[cfg(test)]
 pub mod lib_test;
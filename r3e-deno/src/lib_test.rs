@@ -93,6 +93,39 @@ async fn test_load_module_other() {
     std::println!("load export default err: {}", err);
 }
 
+#[tokio::test]
+async fn test_run_module_export() {
+    let code = r#"
+        export function warmup() {
+            globalThis.warmed = true;
+        }
+        export default function() {
+            return globalThis.warmed;
+        }
+    "#;
+    let mut runtime = JsRuntime::new(RuntimeConfig::default());
+    let module = runtime
+        .load_main_module(code.into())
+        .await
+        .expect("load module should be ok");
+
+    let _ = runtime
+        .eval_module(module)
+        .await
+        .expect("eval module should be ok");
+
+    runtime
+        .run_module_export(module, "warmup", &[])
+        .await
+        .expect("run warmup export should be ok");
+
+    let err = runtime
+        .run_module_export(module, "ping", &[])
+        .await
+        .expect_err("run missing export should be failed");
+    assert!(matches!(err, ExecError::OnExecute(msg) if msg == "ping export not found"));
+}
+
 #[tokio::test]
 async fn test_call_async_js_fn() {
     let mut runtime = JsRuntime::new(RuntimeConfig::default());
@@ -179,3 +212,542 @@ async fn test_function_args() {
     assert_eq!(got.trigger_time, 100);
     assert_eq!(got.source, Source::Bitcoin);
 }
+
+#[tokio::test]
+async fn test_stream_chunks() {
+    let mut runtime = JsRuntime::new(RuntimeConfig::default());
+    let code = r#"
+        export default async function() {
+            await r3e.stream.write("hello");
+            await r3e.stream.write({ n: 1 }, { event: "progress" });
+            return r3e.stream.enabled;
+        }
+    "#;
+    let module = runtime
+        .load_main_module(code.into())
+        .await
+        .expect("load module should be ok");
+
+    let _ = runtime
+        .eval_module(module)
+        .await
+        .expect("eval module should be ok");
+
+    let mut chunks = runtime.open_stream(ext::stream::STREAM_BUFFER);
+    runtime
+        .run_module_default(module, &[])
+        .await
+        .expect("run module should be ok");
+    drop(runtime.close_stream());
+
+    let mut got = Vec::new();
+    while let Some(chunk) = chunks.recv().await {
+        got.push(chunk);
+    }
+    assert_eq!(
+        got,
+        vec![
+            ext::stream::StreamChunk::Data {
+                seq: 0,
+                event: None,
+                data: "hello".into(),
+            },
+            ext::stream::StreamChunk::Data {
+                seq: 1,
+                event: Some("progress".into()),
+                data: r#"{"n":1}"#.into(),
+            },
+        ]
+    );
+}
+
+#[tokio::test]
+async fn test_timer_budget() {
+    let mut runtime = JsRuntime::new(RuntimeConfig {
+        sandbox_config: Some(sandbox::SandboxConfig {
+            timer_limits: ext::timers::TimerLimits {
+                max_timers: 3,
+                max_wall_clock: std::time::Duration::from_millis(500),
+            },
+            ..Default::default()
+        }),
+        ..Default::default()
+    });
+    let code = r#"
+        export default async function(event) {
+            if (event.sleep) {
+                await r3e.sleep(event.sleep);
+            }
+            let ticks = 0;
+            await new Promise((resolve) => {
+                const id = setInterval(() => {
+                    if (++ticks == event.ticks) {
+                        clearInterval(id);
+                        resolve();
+                    }
+                }, 10);
+            });
+        }
+    "#;
+    let module = runtime
+        .load_main_module(code.into())
+        .await
+        .expect("load module should be ok");
+
+    let _ = runtime
+        .eval_module(module)
+        .await
+        .expect("eval module should be ok");
+
+    let run = |sleep: u64, ticks: u64| serde_json::json!({ "sleep": sleep, "ticks": ticks });
+    let event = runtime.to_global(&run(10, 3)).unwrap();
+    runtime
+        .run_module_default(module, &[event])
+        .await
+        .expect("run within the budget should be ok");
+
+    // Sleeping past the wall clock budget is refused
+    let event = runtime.to_global(&run(1000, 3)).unwrap();
+    let err = runtime
+        .run_module_default(module, &[event])
+        .await
+        .expect_err("long sleep should fail");
+    assert!(matches!(err, ExecError::TimerBudget(_)), "{}", err);
+
+    // An interval running past it fails the invocation
+    let event = runtime.to_global(&run(0, 1000)).unwrap();
+    let err = runtime
+        .run_module_default(module, &[event])
+        .await
+        .expect_err("endless interval should fail");
+    assert!(matches!(err, ExecError::TimerBudget(_)), "{}", err);
+}
+
+#[tokio::test]
+async fn test_op_budget_nft_tee() {
+    use std::collections::HashMap;
+    use std::sync::Arc;
+
+    use crate::ext::budget::{OpBudgetError, OpLimits};
+    use r3e_neo_services::abstract_account::AccountOperationResponse;
+    use r3e_neo_services::nft::{NftServiceTrait, NftToken, NftTransferRequest};
+    use r3e_neo_services::Error;
+    use r3e_tee::{
+        AttestationReport, TeeError, TeeExecutionRequest, TeeExecutionResponse, TeePlatform,
+        TeeService,
+    };
+
+    struct Nft;
+
+    #[async_trait::async_trait]
+    impl NftServiceTrait for Nft {
+        async fn owners_of(&self, _: &str, _: &str) -> Result<Vec<String>, Error> {
+            Ok(vec!["NOwner".to_string()])
+        }
+
+        async fn tokens_of(&self, _: &str, _: &str) -> Result<Vec<String>, Error> {
+            Ok(vec!["01".to_string()])
+        }
+
+        async fn balance_of(&self, _: &str, _: &str) -> Result<u128, Error> {
+            Ok(1)
+        }
+
+        async fn properties(&self, _: &str, _: &str) -> Result<serde_json::Value, Error> {
+            Ok(serde_json::json!({ "name": "token" }))
+        }
+
+        async fn get_token(&self, _: &str, _: &str) -> Result<NftToken, Error> {
+            Err(Error::RpcError("not used".to_string()))
+        }
+
+        async fn transfer(&self, _: NftTransferRequest) -> Result<AccountOperationResponse, Error> {
+            Err(Error::RpcError("not used".to_string()))
+        }
+    }
+
+    struct Tee;
+
+    #[async_trait::async_trait]
+    impl TeeService for Tee {
+        fn supported_platforms(&self) -> Vec<TeePlatform> {
+            vec![TeePlatform::Simulated]
+        }
+
+        async fn execute(&self, _: TeeExecutionRequest) -> Result<TeeExecutionResponse, TeeError> {
+            Err(TeeError::Enclave("not used".to_string()))
+        }
+
+        async fn generate_attestation(
+            &self,
+            _: TeePlatform,
+        ) -> Result<AttestationReport, TeeError> {
+            Err(TeeError::Attestation("not used".to_string()))
+        }
+
+        async fn verify_attestation(&self, _: &AttestationReport) -> Result<bool, TeeError> {
+            Ok(true)
+        }
+    }
+
+    let mut runtime = JsRuntime::new(RuntimeConfig {
+        sandbox_config: Some(sandbox::SandboxConfig {
+            op_limits: OpLimits {
+                max_calls: 3,
+                max_calls_per_op: HashMap::from([("op_neo_nft_owner_of".to_string(), 1)]),
+                ..Default::default()
+            },
+            ..Default::default()
+        }),
+        ..Default::default()
+    });
+    runtime.put_state::<Arc<dyn NftServiceTrait>>(Arc::new(Nft));
+    runtime.put_state::<Arc<dyn TeeService>>(Arc::new(Tee));
+
+    let code = r#"
+        export default async function(event) {
+            const { ops } = Deno.core;
+            if (event == "nft") {
+                await ops.op_neo_nft_owner_of("0xc0", "01");
+                await ops.op_neo_nft_owner_of("0xc0", "02");
+            } else {
+                await ops.op_neo_nft_balance_of("0xc0", "NOwner");
+                await ops.op_neo_nft_tokens_of("0xc0", "NOwner");
+                await ops.op_neo_nft_properties("0xc0", "01");
+                try {
+                    ops.op_tee_generate_attestation({ platform: "simulated" });
+                } catch (e) {}
+            }
+        }
+    "#;
+    let module = runtime
+        .load_main_module(code.into())
+        .await
+        .expect("load module should be ok");
+
+    let _ = runtime
+        .eval_module(module)
+        .await
+        .expect("eval module should be ok");
+
+    // NEP-11 calls are limited per op
+    let event = runtime.to_global(&serde_json::json!("nft")).unwrap();
+    let err = runtime
+        .run_module_default(module, &[event])
+        .await
+        .expect_err("second ownerOf should fail");
+    let ExecError::OpBudget(exceeded) = err else {
+        panic!("unexpected error: {}", err);
+    };
+    assert_eq!(
+        exceeded,
+        OpBudgetError::TooManyOpCalls {
+            op: "op_neo_nft_owner_of".to_string(),
+            limit: 1
+        }
+    );
+
+    // NEP-11 calls use up the invocation's calls, and the TEE op going over them fails it
+    // even when caught
+    let event = runtime.to_global(&serde_json::json!("tee")).unwrap();
+    let err = runtime
+        .run_module_default(module, &[event])
+        .await
+        .expect_err("TEE call past the budget should fail");
+    let ExecError::OpBudget(exceeded) = err else {
+        panic!("unexpected error: {}", err);
+    };
+    assert_eq!(
+        exceeded,
+        OpBudgetError::TooManyCalls {
+            op: "op_tee_generate_attestation".to_string(),
+            limit: 3
+        }
+    );
+}
+
+#[tokio::test]
+async fn test_heap_limit_report() {
+    let max_heap_size = 16 * 1024 * 1024;
+    let mut runtime = JsRuntime::new(RuntimeConfig {
+        max_heap_size,
+        sandbox_config: Some(sandbox::SandboxConfig {
+            max_heap_size,
+            ..Default::default()
+        }),
+    });
+    let code = r#"
+        class Leak {
+            constructor(i) {
+                this.payload = new Array(64).fill(i);
+            }
+        }
+
+        const leaked = [];
+        export default function() {
+            for (let i = 0; ; i++) {
+                leaked.push(new Leak(i));
+            }
+        }
+    "#;
+    let module = runtime
+        .load_main_module(code.into())
+        .await
+        .expect("load module should be ok");
+
+    let _ = runtime
+        .eval_module(module)
+        .await
+        .expect("eval module should be ok");
+
+    // The invocation is terminated with the heap diagnostics instead of crashing
+    let event = runtime.to_global(&serde_json::json!({})).unwrap();
+    let err = runtime
+        .run_module_default(module, &[event])
+        .await
+        .expect_err("unbounded allocation should fail");
+    let ExecError::OutOfMemory(report) = err else {
+        panic!("unexpected error: {}", err);
+    };
+    assert_eq!(report.usage.heap_size_limit, max_heap_size as u64);
+    assert!(report.snapshot_size() > 0);
+    assert!(report.largest_types.iter().any(|stats| stats.name == "Leak"));
+}
+
+#[test]
+fn test_permission_grants() {
+    use std::sync::Arc;
+
+    use crate::sandbox::{
+        FileGrantStore, FunctionGrants, GrantStatus, PermissionGrants, PermissionKind,
+    };
+
+    let dir = tempfile::tempdir().expect("tempdir should be ok");
+    let store = FileGrantStore::new(dir.path()).expect("open grant store should be ok");
+    let grants = FunctionGrants::new(PermissionGrants::new(Arc::new(store)), "fn/1");
+
+    // Nothing is granted until approved
+    assert!(grants
+        .authorize(PermissionKind::Net, "api.example.com")
+        .is_err());
+    let pending = grants
+        .request(PermissionKind::Net, "https://api.example.com/v1/prices")
+        .expect("request should be ok");
+    assert_eq!(pending.status, GrantStatus::Pending);
+    assert_eq!(pending.resource, "api.example.com");
+    let again = grants
+        .request(PermissionKind::Net, "api.example.com")
+        .expect("request should be ok");
+    assert_eq!(again.id, pending.id);
+
+    // Reopening the store sees the approval
+    let store = FileGrantStore::new(dir.path()).expect("open grant store should be ok");
+    let admin = PermissionGrants::new(Arc::new(store));
+    admin
+        .approve("fn/1", &pending.id, "admin", None)
+        .expect("approve should be ok");
+    admin
+        .grant("fn/1", PermissionKind::Fs, "/data/fn1", "admin", None)
+        .expect("grant should be ok");
+    admin
+        .grant("fn/1", PermissionKind::Env, "PRICE_*", "admin", None)
+        .expect("grant should be ok");
+    assert!(admin
+        .grant("fn/1", PermissionKind::Fs, "data", "admin", None)
+        .is_err());
+
+    assert!(grants
+        .authorize(PermissionKind::Net, "API.example.com")
+        .is_ok());
+    assert!(grants
+        .authorize(PermissionKind::Net, "evil.example.com")
+        .is_err());
+    assert!(grants
+        .authorize(PermissionKind::Fs, "/data/fn1/cache.json")
+        .is_ok());
+    assert!(grants
+        .authorize(PermissionKind::Fs, "/data/fn1/../fn2/cache.json")
+        .is_err());
+    assert!(grants.authorize(PermissionKind::Fs, "/data/fn10").is_err());
+    assert!(grants.authorize(PermissionKind::Env, "PRICE_FEED").is_ok());
+    assert!(grants.authorize(PermissionKind::Env, "HOME").is_err());
+
+    admin
+        .revoke("fn/1", &pending.id, "admin")
+        .expect("revoke should be ok");
+    assert!(grants
+        .authorize(PermissionKind::Net, "api.example.com")
+        .is_err());
+
+    // Every check is audited
+    let uses = admin.uses("fn/1", 100).expect("uses should be ok");
+    assert_eq!(uses.len(), 9);
+    assert_eq!(uses.iter().filter(|u| u.allowed).count(), 3);
+    assert!(uses[1].grant_id.as_deref() == Some(pending.id.as_str()));
+    assert_eq!(admin.uses("fn/1", 2).unwrap().len(), 2);
+}
+
+#[test]
+fn test_policies() {
+    use std::sync::Arc;
+
+    use crate::sandbox::{
+        Condition, ConditionOp, FilePolicyStore, FunctionPolicy, PolicyEffect, PolicyEngine,
+        PolicyRule, PolicyStage,
+    };
+
+    let rule = |name: &str, stage, effect, priority, conditions| PolicyRule {
+        name: name.to_string(),
+        description: None,
+        stage,
+        effect,
+        conditions,
+        message: None,
+        priority,
+        enabled: true,
+    };
+    let condition = |attribute: &str, op, value: serde_json::Value| Condition {
+        attribute: attribute.to_string(),
+        op,
+        value,
+    };
+
+    let dir = tempfile::tempdir().expect("tempdir should be ok");
+    let store = FilePolicyStore::new(dir.path()).expect("open policy store should be ok");
+    let engine = PolicyEngine::new(Arc::new(store));
+
+    // Functions of tenant 7 cannot ask the example providers, except trusted ones
+    let deny = engine
+        .create(
+            rule(
+                "no-example-oracles",
+                PolicyStage::Op,
+                PolicyEffect::Deny,
+                10,
+                vec![
+                    condition("tenant", ConditionOp::Eq, "7".into()),
+                    condition("op", ConditionOp::Eq, "oracle".into()),
+                    condition("providers", ConditionOp::Glob, "*.example.com".into()),
+                ],
+            ),
+            "admin",
+        )
+        .expect("create should be ok");
+    engine
+        .create(
+            rule(
+                "trusted-oracles",
+                PolicyStage::Op,
+                PolicyEffect::Allow,
+                0,
+                vec![condition(
+                    "providers",
+                    ConditionOp::In,
+                    serde_json::json!(["trusted.example.com"]),
+                )],
+            ),
+            "admin",
+        )
+        .expect("create should be ok");
+    assert!(engine
+        .create(
+            rule(
+                "invalid",
+                PolicyStage::Op,
+                PolicyEffect::Deny,
+                0,
+                vec![condition("providers", ConditionOp::In, "x".into())],
+            ),
+            "admin",
+        )
+        .is_err());
+
+    // Functions tagged confidential must run in a TEE
+    engine
+        .create(
+            rule(
+                "confidential-needs-tee",
+                PolicyStage::Invoke,
+                PolicyEffect::Deny,
+                0,
+                vec![
+                    condition("tags", ConditionOp::Eq, "confidential".into()),
+                    condition("security_level", ConditionOp::Ne, "tee".into()),
+                ],
+            ),
+            "admin",
+        )
+        .expect("create should be ok");
+
+    // Reopening the store sees the policies
+    let store = FilePolicyStore::new(dir.path()).expect("open policy store should be ok");
+    let engine = PolicyEngine::new(Arc::new(store));
+    let tenant7 = FunctionPolicy::new(engine.clone(), "7", "1");
+    let tenant8 = FunctionPolicy::new(engine.clone(), "8", "2");
+    let providers = |providers: serde_json::Value| {
+        serde_json::json!({ "providers": providers })
+            .as_object()
+            .cloned()
+            .unwrap()
+    };
+
+    assert!(tenant7
+        .check_op("oracle", providers(serde_json::json!(["feed.example.com"])))
+        .is_err());
+    assert!(tenant7
+        .check_op(
+            "oracle",
+            providers(serde_json::json!(["trusted.example.com"]))
+        )
+        .is_ok());
+    assert!(tenant7
+        .check_op("oracle", providers(serde_json::json!(["coingecko"])))
+        .is_ok());
+    assert!(tenant8
+        .check_op("oracle", providers(serde_json::json!(["feed.example.com"])))
+        .is_ok());
+
+    let invoke = |tags: serde_json::Value, security_level: &str| {
+        let attributes = serde_json::json!({ "tags": tags, "security_level": security_level });
+        engine.evaluate(
+            PolicyStage::Invoke,
+            attributes.as_object().cloned().unwrap(),
+        )
+    };
+    assert!(!invoke(serde_json::json!(["confidential"]), "standard").allowed);
+    assert!(invoke(serde_json::json!(["confidential"]), "tee").allowed);
+    assert!(invoke(serde_json::json!(["public"]), "standard").allowed);
+
+    // Disabled policies are not decided
+    let mut disabled = deny.rule.clone();
+    disabled.enabled = false;
+    engine
+        .update(&deny.id, disabled)
+        .expect("update should be ok");
+    assert!(tenant7
+        .check_op("oracle", providers(serde_json::json!(["feed.example.com"])))
+        .is_ok());
+    engine.delete(&deny.id).expect("delete should be ok");
+    assert!(engine.delete(&deny.id).is_err());
+
+    // Decisions of matching policies are logged
+    let decisions = engine.decisions(None, 100).expect("decisions should be ok");
+    assert_eq!(decisions.len(), 3);
+    assert_eq!(decisions.iter().filter(|d| d.allowed).count(), 1);
+    assert_eq!(decisions[0].policy_id, deny.id);
+    let function_decisions = engine
+        .decisions(Some("1"), 100)
+        .expect("decisions should be ok");
+    assert_eq!(function_decisions.len(), 2);
+    assert!(engine
+        .decisions(Some("2"), 100)
+        .expect("decisions should be ok")
+        .iter()
+        .all(|d| d.function_id() == Some("2")));
+
+    // The limit keeps the most recent decisions of the function
+    let latest = engine
+        .decisions(Some("1"), 1)
+        .expect("decisions should be ok");
+    assert_eq!(latest, function_decisions[1..]);
+}
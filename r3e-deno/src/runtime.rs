@@ -1,13 +1,24 @@
 // Copyright @ 2023 - 2024, R3E Network
 // All Rights Reserved
 
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
 use deno_core::error::JsError;
+pub use deno_core::v8::IsolateHandle;
 use deno_core::{v8, Extension, JsRuntime as Runtime, RuntimeOptions};
 use serde::Serialize;
-
-use crate::ext::op_allowed;
+use tokio::sync::mpsc;
+
+use crate::ext::budget::{OpBudget, OpBudgetError};
+use crate::ext::chain::RpcBudget;
+use crate::ext::stream::{StreamChunk, StreamSink};
+use crate::ext::timers::TimerBudget;
+use crate::ext::{op_allowed, InvocationCancel};
+use crate::heap::{largest_object_types, HeapReport, HeapUsage, MAX_REPORTED_TYPES};
 use crate::sandbox::{create_v8_flags, create_v8_params, SandboxConfig, SandboxContext};
-use r3e_core::make_v8_platform;
+use crate::source_map::SourceMap;
+use r3e_core::{make_v8_platform, CodedError, ErrorCode};
 
 #[derive(Debug)]
 pub struct RuntimeConfig {
@@ -24,9 +35,15 @@ impl Default for RuntimeConfig {
     }
 }
 
+pub const MAIN_MODULE_SPECIFIER: &str = "file://main.js";
+
 pub struct JsRuntime {
     runtime: Runtime,
     sandbox_context: Option<SandboxContext>,
+    source_map: Option<SourceMap>,
+    // Heap limit the isolate reached, 0 until it does
+    heap_limit_reached: Arc<AtomicUsize>,
+    heap_snapshot_on_oom: bool,
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -45,6 +62,28 @@ pub enum ExecError {
 
     #[error("exec: timeout: execution exceeded time limit")]
     Timeout,
+
+    #[error("exec: timer budget exceeded: {0}")]
+    TimerBudget(String),
+
+    #[error("exec: op budget exceeded: {0}")]
+    OpBudget(OpBudgetError),
+
+    #[error("exec: out of memory: heap limit of {} bytes reached", .0.usage.heap_size_limit)]
+    OutOfMemory(Box<HeapReport>),
+}
+
+impl CodedError for ExecError {
+    fn code(&self) -> ErrorCode {
+        match self {
+            ExecError::OnLoad(_) | ExecError::OnCompile(_) => ErrorCode::CompileFailed,
+            ExecError::OnExecute(_) => ErrorCode::ExecutionFailed,
+            ExecError::SandboxViolation(_) => ErrorCode::SandboxViolation,
+            ExecError::Timeout => ErrorCode::Timeout,
+            ExecError::TimerBudget(_) | ExecError::OpBudget(_) => ErrorCode::ResourceLimit,
+            ExecError::OutOfMemory(_) => ErrorCode::OutOfMemory,
+        }
+    }
 }
 
 impl JsRuntime {
@@ -76,6 +115,36 @@ impl JsRuntime {
             ..Default::default()
         });
 
+        // Expose the effective sandbox configuration to the ops
+        runtime
+            .op_state()
+            .borrow_mut()
+            .put(Arc::new(Mutex::new(sandbox_config.clone())));
+        runtime
+            .op_state()
+            .borrow_mut()
+            .put(TimerBudget::new(sandbox_config.timer_limits));
+        runtime
+            .op_state()
+            .borrow_mut()
+            .put(RpcBudget::new(sandbox_config.chain_limits));
+        let isolate = runtime.v8_isolate().thread_safe_handle();
+        runtime
+            .op_state()
+            .borrow_mut()
+            .put(OpBudget::new(sandbox_config.op_limits.clone(), isolate));
+
+        // Terminate invocations reaching the heap limit instead of crashing the process,
+        // raising the limit so the isolate can unwind and be snapshotted
+        let heap_limit_reached = Arc::new(AtomicUsize::new(0));
+        let reached = heap_limit_reached.clone();
+        let isolate = runtime.v8_isolate().thread_safe_handle();
+        runtime.add_near_heap_limit_callback(move |current_limit, initial_limit| {
+            let _ = reached.compare_exchange(0, initial_limit, Ordering::SeqCst, Ordering::SeqCst);
+            isolate.terminate_execution();
+            current_limit + current_limit / 2
+        });
+
         // Create sandbox context if needed
         let sandbox_context = if config.sandbox_config.is_some() {
             Some(SandboxContext::new(sandbox_config, runtime.v8_isolate()))
@@ -86,11 +155,92 @@ impl JsRuntime {
         Self {
             runtime,
             sandbox_context,
+            source_map: None,
+            heap_limit_reached,
+            heap_snapshot_on_oom: sandbox_config.heap_snapshot_on_oom,
+        }
+    }
+
+    /// Set the source map of the main module, used to map error stack frames
+    /// back to the original sources.
+    pub fn set_source_map(&mut self, source_map: Option<SourceMap>) {
+        self.source_map = source_map;
+    }
+
+    fn map_error(&self, err: String) -> String {
+        let Some(source_map) = self.source_map.as_ref() else {
+            return err;
+        };
+
+        let specifier = deno_core::resolve_url(MAIN_MODULE_SPECIFIER).unwrap();
+        source_map.remap_stack(&err, specifier.as_str())
+    }
+
+    /// Heap diagnostics if the isolate reached its heap limit since the last call.
+    ///
+    /// The limit was raised to let the invocation unwind, so the runtime should not be
+    /// reused afterwards.
+    fn take_heap_report(&mut self) -> Option<HeapReport> {
+        let heap_limit = self.heap_limit_reached.swap(0, Ordering::SeqCst);
+        if heap_limit == 0 {
+            return None;
+        }
+
+        let isolate = self.runtime.v8_isolate();
+        isolate.cancel_terminate_execution();
+
+        // Statistics first, taking the snapshot collects garbage
+        let mut stats = v8::HeapStatistics::default();
+        isolate.get_heap_statistics(&mut stats);
+        let mut report = HeapReport {
+            usage: HeapUsage::from(&stats),
+            ..Default::default()
+        };
+        report.usage.heap_size_limit = heap_limit as u64;
+        if !self.heap_snapshot_on_oom {
+            return Some(report);
+        }
+
+        let mut snapshot = Vec::new();
+        isolate.take_heap_snapshot(|chunk| {
+            snapshot.extend_from_slice(chunk);
+            true
+        });
+        match String::from_utf8(snapshot) {
+            Ok(snapshot) => {
+                match largest_object_types(&snapshot, MAX_REPORTED_TYPES) {
+                    Ok(largest_types) => report.largest_types = largest_types,
+                    Err(err) => log::warn!("runtime: analyze heap snapshot failed: {}", err),
+                }
+                report.snapshot = Some(snapshot);
+            }
+            Err(err) => log::warn!("runtime: heap snapshot is not UTF-8: {}", err),
+        }
+        Some(report)
+    }
+
+    /// Fail with the heap diagnostics if a failed call reached the heap limit
+    fn check_heap_limit<T, E>(&mut self, result: &Result<T, E>) -> Result<(), ExecError> {
+        if result.is_ok() {
+            return Ok(());
+        }
+        match self.take_heap_report() {
+            Some(report) => Err(ExecError::OutOfMemory(Box::new(report))),
+            None => Ok(()),
         }
     }
 
     // must execute in the tokio context
     pub fn execute(&mut self, code: &str) -> Result<(), ExecError> {
+        let result = self.execute_script(code);
+        self.check_heap_limit(&result)?;
+        result.map_err(|err| match err {
+            ExecError::OnExecute(err) => ExecError::OnExecute(self.map_error(err)),
+            err => err,
+        })
+    }
+
+    fn execute_script(&mut self, code: &str) -> Result<(), ExecError> {
         let mut scope = self.runtime.handle_scope();
         let script = v8::String::new(&mut scope, code)
             .ok_or_else(|| ExecError::OnCompile("code too long"))?;
@@ -117,23 +267,26 @@ impl JsRuntime {
     }
 
     pub async fn load_main_module(&mut self, code: String) -> Result<usize, ExecError> {
-        let specifier = deno_core::resolve_url("file://main.js").unwrap();
+        let specifier = deno_core::resolve_url(MAIN_MODULE_SPECIFIER).unwrap();
         let module = self
             .runtime
             .load_main_es_module_from_code(&specifier, code)
-            .await
-            .map_err(|err| ExecError::OnLoad(err.to_string()))?;
+            .await;
+        self.check_heap_limit(&module)?;
+        let module = module.map_err(|err| ExecError::OnLoad(self.map_error(err.to_string())))?;
 
         Ok(module)
     }
 
     pub async fn eval_module(&mut self, module: usize) -> Result<(), ExecError> {
-        let result = self.runtime.mod_evaluate(module).await.map_err(|err| {
+        let result = self.runtime.mod_evaluate(module).await;
+        self.check_heap_limit(&result)?;
+        let result = result.map_err(|err| {
             // Check if this is a termination exception (timeout)
             if err.to_string().contains("execution terminated") {
                 return ExecError::Timeout;
             }
-            ExecError::OnExecute(err.to_string())
+            ExecError::OnExecute(self.map_error(err.to_string()))
         })?;
 
         Ok(result)
@@ -145,7 +298,17 @@ impl JsRuntime {
         module: usize,
         args: &[v8::Global<v8::Value>],
     ) -> Result<(), ExecError> {
-        let default_fn = {
+        self.run_module_export(module, "default", args).await
+    }
+
+    /// Call a function exported by a module, after eval_module completed
+    pub async fn run_module_export(
+        &mut self,
+        module: usize,
+        name: &str,
+        args: &[v8::Global<v8::Value>],
+    ) -> Result<(), ExecError> {
+        let export_fn = {
             let module = self
                 .runtime
                 .get_module_namespace(module)
@@ -154,34 +317,98 @@ impl JsRuntime {
             let scope = &mut self.runtime.handle_scope();
             let module = v8::Local::<v8::Object>::new(scope, module);
 
-            let default_name = v8::String::new(scope, "default").unwrap();
-            let default_export = module
-                .get(scope, default_name.into())
-                .ok_or_else(|| ExecError::OnExecute("default export not found".into()))?;
+            let export_name = v8::String::new(scope, name).unwrap();
+            let export = module
+                .get(scope, export_name.into())
+                .filter(|export| !export.is_undefined())
+                .ok_or_else(|| ExecError::OnExecute(format!("{} export not found", name)))?;
 
-            let default_fn = v8::Local::<v8::Function>::try_from(default_export)
-                .map_err(|_err| ExecError::OnExecute("default export is not a function".into()))?;
+            let export_fn = v8::Local::<v8::Function>::try_from(export).map_err(|_err| {
+                ExecError::OnExecute(format!("{} export is not a function", name))
+            })?;
 
-            v8::Global::new(scope, default_fn)
+            v8::Global::new(scope, export_fn)
         };
 
+        // Each invocation gets a fresh timer, RPC call and op budget
+        self.runtime
+            .op_state()
+            .borrow_mut()
+            .borrow_mut::<TimerBudget>()
+            .reset();
+        self.runtime
+            .op_state()
+            .borrow_mut()
+            .borrow_mut::<RpcBudget>()
+            .reset();
+        self.runtime
+            .op_state()
+            .borrow_mut()
+            .borrow_mut::<OpBudget>()
+            .reset();
+
         let options = Default::default();
-        let call = self.runtime.call_with_args(&default_fn, args);
-        let result = self
+        let call = self.runtime.call_with_args(&export_fn, args);
+        let result = self.runtime.with_event_loop_promise(call, options).await;
+        InvocationCancel::end_invocation(&mut self.runtime.op_state().borrow_mut());
+        let exceeded = self
             .runtime
-            .with_event_loop_promise(call, options)
-            .await
-            .map_err(|err| {
-                // Check if this is a termination exception (timeout)
-                if err.to_string().contains("execution terminated") {
-                    return ExecError::Timeout;
-                }
-                ExecError::OnExecute(err.to_string())
-            })?;
+            .op_state()
+            .borrow_mut()
+            .borrow_mut::<TimerBudget>()
+            .take_exceeded();
+        let op_exceeded = self
+            .runtime
+            .op_state()
+            .borrow_mut()
+            .borrow_mut::<OpBudget>()
+            .take_exceeded();
+        self.check_heap_limit(&result)?;
+        if let Some(op_exceeded) = op_exceeded {
+            // The budget terminated the invocation, the runtime itself is fine to reuse
+            self.runtime.v8_isolate().cancel_terminate_execution();
+            return Err(ExecError::OpBudget(op_exceeded));
+        }
+        let result = result.map_err(|err| {
+            // Check if this is a termination exception (timeout)
+            if err.to_string().contains("execution terminated") {
+                return ExecError::Timeout;
+            }
+            if let Some(exceeded) = exceeded {
+                return ExecError::TimerBudget(exceeded.to_string());
+            }
+            ExecError::OnExecute(self.map_error(err.to_string()))
+        })?;
 
         Ok(result)
     }
 
+    /// Make a value available to the ops, such as the oracle or TEE service they take
+    /// from the op state.
+    pub fn put_state<T: 'static>(&mut self, value: T) {
+        self.runtime.op_state().borrow_mut().put(value);
+    }
+
+    /// Stream the chunks the function writes to the returned receiver.
+    ///
+    /// Without an open stream, `r3e.stream.write` is a no-op returning false.
+    pub fn open_stream(&mut self, buffer: usize) -> mpsc::Receiver<StreamChunk> {
+        self.runtime
+            .op_state()
+            .borrow_mut()
+            .borrow_mut::<StreamSink>()
+            .open(buffer)
+    }
+
+    /// Detach the stream, returning its sender to emit the final chunk with.
+    pub fn close_stream(&mut self) -> Option<mpsc::Sender<StreamChunk>> {
+        self.runtime
+            .op_state()
+            .borrow_mut()
+            .borrow_mut::<StreamSink>()
+            .close()
+    }
+
     pub fn to_global(
         &mut self,
         value: &impl Serialize,
@@ -202,6 +429,11 @@ impl JsRuntime {
         self.runtime.v8_isolate().terminate_execution();
     }
 
+    /// Handle terminating the running execution from another thread
+    pub fn isolate_handle(&mut self) -> IsolateHandle {
+        self.runtime.v8_isolate().thread_safe_handle()
+    }
+
     pub fn enter(&mut self) {
         let isolate = self.runtime.v8_isolate();
         unsafe { isolate.enter() };
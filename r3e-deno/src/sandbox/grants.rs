@@ -0,0 +1,685 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+//! Per-function permission grants.
+//!
+//! A function asks for access to a resource with `requestPermission`; when no grant covers it
+//! the request is kept as a pending grant, which an admin or the function owner approves
+//! through the API. Approved grants are checked when an op is dispatched, and every check is
+//! recorded as a [`PermissionUse`].
+
+use std::collections::HashMap;
+use std::fmt;
+use std::io::Write;
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use uuid::Uuid;
+
+/// Error type for permission grants
+#[derive(Debug, Error)]
+pub enum GrantError {
+    #[error("grants: no such grant: {0}")]
+    NotFound(String),
+
+    #[error("grants: invalid grant: {0}")]
+    Invalid(String),
+
+    #[error("grants: storage error: {0}")]
+    Storage(String),
+}
+
+/// Kind of resource a grant gives access to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PermissionKind {
+    /// Outbound network access to a host
+    Net,
+
+    /// File system access below a path
+    Fs,
+
+    /// Read access to an environment variable
+    Env,
+}
+
+impl PermissionKind {
+    /// Kind of a sandbox operation, `None` for operations without grants
+    pub fn from_operation(operation: &str) -> Option<Self> {
+        match operation {
+            "net" => Some(Self::Net),
+            "fs" => Some(Self::Fs),
+            "env" => Some(Self::Env),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Net => "net",
+            Self::Fs => "fs",
+            Self::Env => "env",
+        }
+    }
+
+    /// Check a resource pattern of a grant:
+    /// - net: a host, `*.example.com` or `*`
+    /// - fs: an absolute path, granting everything below it
+    /// - env: a variable name, or a prefix followed by `*`
+    pub fn check_pattern(&self, pattern: &str) -> Result<(), GrantError> {
+        let valid = match self {
+            Self::Net => {
+                let host = pattern.strip_prefix("*.").unwrap_or(pattern);
+                pattern == "*"
+                    || (!host.is_empty()
+                        && host
+                            .chars()
+                            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | ':')))
+            }
+            Self::Fs => normalize_path(pattern).is_some(),
+            Self::Env => {
+                let name = pattern.strip_suffix('*').unwrap_or(pattern);
+                !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+            }
+        };
+
+        if valid {
+            Ok(())
+        } else {
+            Err(GrantError::Invalid(format!(
+                "'{}' is not a valid {} resource",
+                pattern, self
+            )))
+        }
+    }
+
+    /// Whether the resource pattern of a grant covers `resource`
+    pub fn matches(&self, pattern: &str, resource: &str) -> bool {
+        match self {
+            Self::Net => {
+                let host = net_host(resource).to_ascii_lowercase();
+                let pattern = pattern.to_ascii_lowercase();
+                match pattern.strip_prefix("*.") {
+                    _ if pattern == "*" => true,
+                    Some(suffix) => host
+                        .strip_suffix(suffix)
+                        .is_some_and(|prefix| prefix.ends_with('.')),
+                    None => host == pattern,
+                }
+            }
+            Self::Fs => match (normalize_path(pattern), normalize_path(resource)) {
+                (Some(pattern), Some(resource)) => resource.starts_with(pattern),
+                _ => false,
+            },
+            Self::Env => match pattern.strip_suffix('*') {
+                Some(prefix) => resource.starts_with(prefix),
+                None => resource == pattern,
+            },
+        }
+    }
+}
+
+impl fmt::Display for PermissionKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Host of a net resource, which is either a host or a URL
+fn net_host(resource: &str) -> &str {
+    let rest = resource
+        .split_once("://")
+        .map_or(resource, |(_, rest)| rest);
+    let authority = rest.split(['/', '?', '#']).next().unwrap_or_default();
+    let host = authority
+        .rsplit_once('@')
+        .map_or(authority, |(_, host)| host);
+    // Keep IPv6 literals whole, drop the port of anything else
+    match host.strip_prefix('[') {
+        Some(v6) => v6.split(']').next().unwrap_or_default(),
+        None => host.split(':').next().unwrap_or_default(),
+    }
+    .trim_end_matches('.')
+}
+
+/// Absolute path without `.` components; paths escaping with `..` are rejected
+fn normalize_path(path: &str) -> Option<PathBuf> {
+    let path = Path::new(path);
+    if !path.is_absolute() {
+        return None;
+    }
+
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::RootDir | Component::Prefix(_) | Component::Normal(_) => {
+                normalized.push(component)
+            }
+            Component::CurDir => {}
+            Component::ParentDir => return None,
+        }
+    }
+    Some(normalized)
+}
+
+/// Status of a grant
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GrantStatus {
+    /// Requested by the function, waiting for approval
+    Pending,
+
+    /// Approved, the function may use the resource
+    Approved,
+
+    /// Refused when pending
+    Denied,
+
+    /// Withdrawn after approval
+    Revoked,
+}
+
+/// Permission of a function to use a resource
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PermissionGrant {
+    /// Grant ID
+    pub id: String,
+
+    /// Function the grant is for
+    pub function_id: String,
+
+    /// Kind of resource
+    pub kind: PermissionKind,
+
+    /// Resource pattern, see [`PermissionKind::check_pattern`]
+    pub resource: String,
+
+    /// Grant status
+    pub status: GrantStatus,
+
+    /// Requested at timestamp (secs since epoch)
+    pub requested_at: u64,
+
+    /// User who approved, denied or revoked the grant
+    #[serde(default)]
+    pub decided_by: Option<String>,
+
+    /// Decided at timestamp (secs since epoch)
+    #[serde(default)]
+    pub decided_at: Option<u64>,
+
+    /// Expiration timestamp (secs since epoch), never when absent
+    #[serde(default)]
+    pub expires_at: Option<u64>,
+}
+
+impl PermissionGrant {
+    /// Whether the grant allows the use of `resource` at `now`
+    pub fn covers(&self, kind: PermissionKind, resource: &str, now: u64) -> bool {
+        self.status == GrantStatus::Approved
+            && self.kind == kind
+            && self.expires_at.is_none_or(|expires_at| now < expires_at)
+            && kind.matches(&self.resource, resource)
+    }
+}
+
+/// Audit record of a permission check at op dispatch
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PermissionUse {
+    /// Function using the resource
+    pub function_id: String,
+
+    /// Kind of resource
+    pub kind: PermissionKind,
+
+    /// Resource used
+    pub resource: String,
+
+    /// Whether the use was allowed
+    pub allowed: bool,
+
+    /// Grant that allowed the use
+    #[serde(default)]
+    pub grant_id: Option<String>,
+
+    /// Timestamp (secs since epoch)
+    pub timestamp: u64,
+}
+
+/// Storage of grants and their audit records
+pub trait GrantStore: Send + Sync {
+    /// Grants of a function, in request order
+    fn grants(&self, function_id: &str) -> Result<Vec<PermissionGrant>, GrantError>;
+
+    /// Insert or replace a grant
+    fn put_grant(&self, grant: &PermissionGrant) -> Result<(), GrantError>;
+
+    /// Append an audit record
+    fn record_use(&self, record: &PermissionUse) -> Result<(), GrantError>;
+
+    /// Most recent audit records of a function, oldest first
+    fn uses(&self, function_id: &str, limit: usize) -> Result<Vec<PermissionUse>, GrantError>;
+}
+
+/// In-memory grant store
+#[derive(Debug, Default)]
+pub struct MemoryGrantStore {
+    grants: Mutex<HashMap<String, Vec<PermissionGrant>>>,
+    uses: Mutex<HashMap<String, Vec<PermissionUse>>>,
+}
+
+impl MemoryGrantStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl GrantStore for MemoryGrantStore {
+    fn grants(&self, function_id: &str) -> Result<Vec<PermissionGrant>, GrantError> {
+        let grants = self.grants.lock().unwrap();
+        Ok(grants.get(function_id).cloned().unwrap_or_default())
+    }
+
+    fn put_grant(&self, grant: &PermissionGrant) -> Result<(), GrantError> {
+        let mut grants = self.grants.lock().unwrap();
+        upsert(grants.entry(grant.function_id.clone()).or_default(), grant);
+        Ok(())
+    }
+
+    fn record_use(&self, record: &PermissionUse) -> Result<(), GrantError> {
+        let mut uses = self.uses.lock().unwrap();
+        uses.entry(record.function_id.clone())
+            .or_default()
+            .push(record.clone());
+        Ok(())
+    }
+
+    fn uses(&self, function_id: &str, limit: usize) -> Result<Vec<PermissionUse>, GrantError> {
+        let uses = self.uses.lock().unwrap();
+        let uses = uses.get(function_id).map(Vec::as_slice).unwrap_or_default();
+        Ok(uses[uses.len().saturating_sub(limit)..].to_vec())
+    }
+}
+
+fn upsert(grants: &mut Vec<PermissionGrant>, grant: &PermissionGrant) {
+    match grants.iter_mut().find(|g| g.id == grant.id) {
+        Some(existing) => *existing = grant.clone(),
+        None => grants.push(grant.clone()),
+    }
+}
+
+/// Grant store keeping a JSON file of grants and a JSON lines audit log per function in a
+/// directory, so the API and the workers on a host share it
+pub struct FileGrantStore {
+    dir: PathBuf,
+    // Serializes read-modify-write of the grant files within the process
+    lock: Mutex<()>,
+}
+
+impl FileGrantStore {
+    /// Open the store in `dir`, creating it if needed
+    pub fn new(dir: impl Into<PathBuf>) -> Result<Self, GrantError> {
+        let dir = dir.into();
+        std::fs::create_dir_all(&dir)
+            .map_err(|e| GrantError::Storage(format!("Failed to create grant directory: {}", e)))?;
+        Ok(Self {
+            dir,
+            lock: Mutex::new(()),
+        })
+    }
+
+    /// File name safe encoding of a function ID
+    fn file_stem(function_id: &str) -> String {
+        function_id
+            .bytes()
+            .map(|b| match b {
+                b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'-' => (b as char).to_string(),
+                _ => format!("_{:02x}", b),
+            })
+            .collect()
+    }
+
+    fn grants_path(&self, function_id: &str) -> PathBuf {
+        self.dir
+            .join(format!("{}.grants.json", Self::file_stem(function_id)))
+    }
+
+    fn uses_path(&self, function_id: &str) -> PathBuf {
+        self.dir
+            .join(format!("{}.uses.jsonl", Self::file_stem(function_id)))
+    }
+
+    fn read_grants(&self, function_id: &str) -> Result<Vec<PermissionGrant>, GrantError> {
+        match std::fs::read(self.grants_path(function_id)) {
+            Ok(data) => serde_json::from_slice(&data)
+                .map_err(|e| GrantError::Storage(format!("Invalid grant file: {}", e))),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+            Err(e) => Err(GrantError::Storage(format!("Failed to read grants: {}", e))),
+        }
+    }
+}
+
+impl GrantStore for FileGrantStore {
+    fn grants(&self, function_id: &str) -> Result<Vec<PermissionGrant>, GrantError> {
+        self.read_grants(function_id)
+    }
+
+    fn put_grant(&self, grant: &PermissionGrant) -> Result<(), GrantError> {
+        let _guard = self.lock.lock().unwrap();
+        let mut grants = self.read_grants(&grant.function_id)?;
+        upsert(&mut grants, grant);
+
+        let data = serde_json::to_vec_pretty(&grants)
+            .map_err(|e| GrantError::Storage(format!("Failed to serialize grants: {}", e)))?;
+
+        // Write then rename, so a reader never sees a partial file
+        let path = self.grants_path(&grant.function_id);
+        let tmp = path.with_extension(format!("tmp.{}", std::process::id()));
+        std::fs::write(&tmp, data)
+            .and_then(|_| std::fs::rename(&tmp, &path))
+            .map_err(|e| GrantError::Storage(format!("Failed to write grants: {}", e)))
+    }
+
+    fn record_use(&self, record: &PermissionUse) -> Result<(), GrantError> {
+        let mut line = serde_json::to_vec(record)
+            .map_err(|e| GrantError::Storage(format!("Failed to serialize record: {}", e)))?;
+        line.push(b'\n');
+
+        std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.uses_path(&record.function_id))
+            .and_then(|mut file| file.write_all(&line))
+            .map_err(|e| GrantError::Storage(format!("Failed to record use: {}", e)))
+    }
+
+    fn uses(&self, function_id: &str, limit: usize) -> Result<Vec<PermissionUse>, GrantError> {
+        let data = match std::fs::read_to_string(self.uses_path(function_id)) {
+            Ok(data) => data,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => {
+                return Err(GrantError::Storage(format!(
+                    "Failed to read audit records: {}",
+                    e
+                )))
+            }
+        };
+
+        let lines: Vec<_> = data.lines().filter(|line| !line.is_empty()).collect();
+        lines[lines.len().saturating_sub(limit)..]
+            .iter()
+            .map(|line| {
+                serde_json::from_str(line)
+                    .map_err(|e| GrantError::Storage(format!("Invalid audit record: {}", e)))
+            })
+            .collect()
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// Permission grants of all functions
+#[derive(Clone)]
+pub struct PermissionGrants {
+    store: Arc<dyn GrantStore>,
+}
+
+impl PermissionGrants {
+    pub fn new(store: Arc<dyn GrantStore>) -> Self {
+        Self { store }
+    }
+
+    /// Grants of a function
+    pub fn list(&self, function_id: &str) -> Result<Vec<PermissionGrant>, GrantError> {
+        self.store.grants(function_id)
+    }
+
+    /// Record a request of the function for `resource`, returning the grant covering it if
+    /// there is one, and the pending grant otherwise. Requests for the same resource share
+    /// one pending grant.
+    pub fn request(
+        &self,
+        function_id: &str,
+        kind: PermissionKind,
+        resource: &str,
+    ) -> Result<PermissionGrant, GrantError> {
+        // A function asks for a URL, the grant is for its host
+        let resource = match kind {
+            PermissionKind::Net => net_host(resource),
+            _ => resource,
+        };
+        kind.check_pattern(resource)?;
+
+        let now = now();
+        let grants = self.store.grants(function_id)?;
+        let existing = grants
+            .iter()
+            .find(|g| g.covers(kind, resource, now))
+            .or_else(|| {
+                grants.iter().find(|g| {
+                    g.status == GrantStatus::Pending && g.kind == kind && g.resource == resource
+                })
+            });
+        if let Some(grant) = existing {
+            return Ok(grant.clone());
+        }
+
+        let grant = PermissionGrant {
+            id: Uuid::new_v4().to_string(),
+            function_id: function_id.to_string(),
+            kind,
+            resource: resource.to_string(),
+            status: GrantStatus::Pending,
+            requested_at: now,
+            decided_by: None,
+            decided_at: None,
+            expires_at: None,
+        };
+        self.store.put_grant(&grant)?;
+        log::info!(
+            "grants: {} requested {} access to {} ({})",
+            function_id,
+            kind,
+            resource,
+            grant.id
+        );
+        Ok(grant)
+    }
+
+    /// Grant the function access to `resource` right away
+    pub fn grant(
+        &self,
+        function_id: &str,
+        kind: PermissionKind,
+        resource: &str,
+        granted_by: &str,
+        expires_at: Option<u64>,
+    ) -> Result<PermissionGrant, GrantError> {
+        kind.check_pattern(resource)?;
+
+        let now = now();
+        let grant = PermissionGrant {
+            id: Uuid::new_v4().to_string(),
+            function_id: function_id.to_string(),
+            kind,
+            resource: resource.to_string(),
+            status: GrantStatus::Approved,
+            requested_at: now,
+            decided_by: Some(granted_by.to_string()),
+            decided_at: Some(now),
+            expires_at,
+        };
+        self.store.put_grant(&grant)?;
+        Ok(grant)
+    }
+
+    /// Approve a pending grant
+    pub fn approve(
+        &self,
+        function_id: &str,
+        grant_id: &str,
+        approved_by: &str,
+        expires_at: Option<u64>,
+    ) -> Result<PermissionGrant, GrantError> {
+        self.decide(function_id, grant_id, approved_by, |grant| {
+            if grant.status != GrantStatus::Pending {
+                return Err(GrantError::Invalid(format!(
+                    "Grant {} is not pending",
+                    grant.id
+                )));
+            }
+            grant.status = GrantStatus::Approved;
+            grant.expires_at = expires_at;
+            Ok(())
+        })
+    }
+
+    /// Deny a pending grant
+    pub fn deny(
+        &self,
+        function_id: &str,
+        grant_id: &str,
+        denied_by: &str,
+    ) -> Result<PermissionGrant, GrantError> {
+        self.decide(function_id, grant_id, denied_by, |grant| {
+            if grant.status != GrantStatus::Pending {
+                return Err(GrantError::Invalid(format!(
+                    "Grant {} is not pending",
+                    grant.id
+                )));
+            }
+            grant.status = GrantStatus::Denied;
+            Ok(())
+        })
+    }
+
+    /// Revoke an approved grant
+    pub fn revoke(
+        &self,
+        function_id: &str,
+        grant_id: &str,
+        revoked_by: &str,
+    ) -> Result<PermissionGrant, GrantError> {
+        self.decide(function_id, grant_id, revoked_by, |grant| {
+            if grant.status != GrantStatus::Approved {
+                return Err(GrantError::Invalid(format!(
+                    "Grant {} is not approved",
+                    grant.id
+                )));
+            }
+            grant.status = GrantStatus::Revoked;
+            Ok(())
+        })
+    }
+
+    fn decide(
+        &self,
+        function_id: &str,
+        grant_id: &str,
+        decided_by: &str,
+        decision: impl FnOnce(&mut PermissionGrant) -> Result<(), GrantError>,
+    ) -> Result<PermissionGrant, GrantError> {
+        let mut grant = self
+            .store
+            .grants(function_id)?
+            .into_iter()
+            .find(|g| g.id == grant_id)
+            .ok_or_else(|| GrantError::NotFound(grant_id.to_string()))?;
+
+        decision(&mut grant)?;
+        grant.decided_by = Some(decided_by.to_string());
+        grant.decided_at = Some(now());
+        self.store.put_grant(&grant)?;
+        log::info!(
+            "grants: {} {:?} {} access of {} to {}",
+            decided_by,
+            grant.status,
+            grant.kind,
+            function_id,
+            grant.resource
+        );
+        Ok(grant)
+    }
+
+    /// Check whether the function may use `resource`, recording the use
+    pub fn authorize(
+        &self,
+        function_id: &str,
+        kind: PermissionKind,
+        resource: &str,
+    ) -> Result<(), String> {
+        let now = now();
+        let grant = self
+            .store
+            .grants(function_id)
+            .map_err(|e| e.to_string())?
+            .into_iter()
+            .find(|g| g.covers(kind, resource, now));
+
+        let record = PermissionUse {
+            function_id: function_id.to_string(),
+            kind,
+            resource: resource.to_string(),
+            allowed: grant.is_some(),
+            grant_id: grant.as_ref().map(|g| g.id.clone()),
+            timestamp: now,
+        };
+        if let Err(e) = self.store.record_use(&record) {
+            // An unaudited use is not allowed
+            log::error!("grants: {}", e);
+            return Err(format!("Failed to audit {} access to {}", kind, resource));
+        }
+
+        match grant {
+            Some(_) => Ok(()),
+            None => Err(format!("No {} permission granted for '{}'", kind, resource)),
+        }
+    }
+
+    /// Most recent audit records of a function
+    pub fn uses(&self, function_id: &str, limit: usize) -> Result<Vec<PermissionUse>, GrantError> {
+        self.store.uses(function_id, limit)
+    }
+}
+
+/// Permission grants of a single function, as put into the function's runtime state
+#[derive(Clone)]
+pub struct FunctionGrants {
+    grants: PermissionGrants,
+    function_id: String,
+}
+
+impl FunctionGrants {
+    pub fn new(grants: PermissionGrants, function_id: impl Into<String>) -> Self {
+        Self {
+            grants,
+            function_id: function_id.into(),
+        }
+    }
+
+    pub fn function_id(&self) -> &str {
+        &self.function_id
+    }
+
+    /// Check whether the function may use `resource`, recording the use
+    pub fn authorize(&self, kind: PermissionKind, resource: &str) -> Result<(), String> {
+        self.grants.authorize(&self.function_id, kind, resource)
+    }
+
+    /// Ask for access to `resource`
+    pub fn request(
+        &self,
+        kind: PermissionKind,
+        resource: &str,
+    ) -> Result<PermissionGrant, GrantError> {
+        self.grants.request(&self.function_id, kind, resource)
+    }
+}
@@ -4,9 +4,25 @@
 use deno_core::v8;
 use std::time::Duration;
 
+mod grants;
+mod net_policy;
+mod policy;
 mod threat_monitor;
+pub use grants::{
+    FileGrantStore, FunctionGrants, GrantError, GrantStatus, GrantStore, MemoryGrantStore,
+    PermissionGrant, PermissionGrants, PermissionKind, PermissionUse,
+};
+pub use net_policy::NetPolicy;
+pub use policy::{
+    Condition, ConditionOp, Decision, FilePolicyStore, FunctionPolicy, MemoryPolicyStore, Policy,
+    PolicyDecision, PolicyEffect, PolicyEngine, PolicyError, PolicyRule, PolicyStage, PolicyStore,
+    MAX_POLICY_CONDITIONS,
+};
 pub use threat_monitor::ThreatMonitor;
 
+use crate::ext::budget::OpLimits;
+use crate::ext::chain::ChainLimits;
+use crate::ext::timers::TimerLimits;
 use crate::security::threat_detection::{ThreatDetectionConfig, ThreatDetectionService};
 
 /// Sandbox configuration for JavaScript runtime
@@ -38,6 +54,24 @@ pub struct SandboxConfig {
 
     /// Allow high resolution time
     pub allow_hrtime: bool,
+
+    /// Allow read-only queries of the platform's chain RPC endpoints
+    pub allow_chain: bool,
+
+    /// Egress rules for network access
+    pub net_policy: NetPolicy,
+
+    /// Limits on the timers of an invocation
+    pub timer_limits: TimerLimits,
+
+    /// Limits on the chain queries of an invocation
+    pub chain_limits: ChainLimits,
+
+    /// Limits on the external op calls of an invocation
+    pub op_limits: OpLimits,
+
+    /// Take a heap snapshot when an invocation reaches the heap limit
+    pub heap_snapshot_on_oom: bool,
 }
 
 impl Default for SandboxConfig {
@@ -52,6 +86,12 @@ impl Default for SandboxConfig {
             allow_env: false,
             allow_run: false,
             allow_hrtime: false,
+            allow_chain: true,
+            net_policy: NetPolicy::default(),
+            timer_limits: TimerLimits::default(),
+            chain_limits: ChainLimits::default(),
+            op_limits: OpLimits::default(),
+            heap_snapshot_on_oom: true,
         }
     }
 }
@@ -153,6 +193,9 @@ pub fn check_permission(operation: &str, config: &SandboxConfig) -> Result<(), S
         "hrtime" if !config.allow_hrtime => {
             Err("High resolution time is not allowed in this sandbox".to_string())
         }
+        "chain" if !config.allow_chain => {
+            Err("Chain queries are not allowed in this sandbox".to_string())
+        }
         _ => Ok(()),
     }
 }
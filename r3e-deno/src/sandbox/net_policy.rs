@@ -0,0 +1,326 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+use std::net::IpAddr;
+use std::time::Duration;
+
+use ipnet::IpNet;
+use serde::Deserialize;
+use url::{Host, Url};
+
+/// Egress rules applied to outbound requests of a function
+#[derive(Debug, Clone)]
+pub struct NetPolicy {
+    /// Allowed hosts, exact (`api.example.com`) or wildcard (`*.example.com`).
+    /// Empty `allowed_hosts` and `allowed_cidrs` means any destination is allowed.
+    pub allowed_hosts: Vec<String>,
+
+    /// Allowed IP ranges for IP literal destinations. Internal ranges are blocked by
+    /// the egress guard unless listed here.
+    pub allowed_cidrs: Vec<IpNet>,
+
+    /// Allowed URL schemes
+    pub allowed_schemes: Vec<String>,
+
+    /// Maximum response body size in bytes
+    pub max_response_size: usize,
+
+    /// Request timeout, including reading the body
+    pub timeout: Duration,
+
+    /// Maximum number of redirects to follow, 0 disables redirects
+    pub max_redirects: usize,
+}
+
+impl Default for NetPolicy {
+    fn default() -> Self {
+        Self {
+            allowed_hosts: Vec::new(),
+            allowed_cidrs: Vec::new(),
+            allowed_schemes: vec!["https".into(), "http".into()],
+            max_response_size: 4 * 1024 * 1024, // 4MB
+            timeout: Duration::from_secs(10),
+            max_redirects: 5,
+        }
+    }
+}
+
+/// Egress rules a function sets in the `net` object of its metadata, unset ones are inherited
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FunctionNetPolicy {
+    allowed_hosts: Option<Vec<String>>,
+    allowed_cidrs: Option<Vec<String>>,
+    allowed_schemes: Option<Vec<String>>,
+    max_response_size: Option<usize>,
+    timeout_ms: Option<u64>,
+    max_redirects: Option<usize>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct NetMetadata {
+    #[serde(default)]
+    net: Option<FunctionNetPolicy>,
+}
+
+impl NetPolicy {
+    /// Policy of a function, this policy narrowed by the `net` object of the function's JSON
+    /// encoded metadata, which may be empty.
+    ///
+    /// A function can only restrict egress: the hosts, ranges and schemes it lists must be
+    /// allowed by this policy, and its limits are capped by this policy's.
+    pub fn for_function(&self, metadata: &str) -> Result<Self, String> {
+        if metadata.trim().is_empty() {
+            return Ok(self.clone());
+        }
+
+        let metadata: NetMetadata = serde_json::from_str(metadata)
+            .map_err(|err| format!("invalid net metadata: {}", err))?;
+        let Some(net) = metadata.net else {
+            return Ok(self.clone());
+        };
+
+        let mut policy = self.clone();
+        if let Some(hosts) = net.allowed_hosts {
+            if let Some(host) = hosts.iter().find(|host| !self.covers_host(host)) {
+                return Err(format!("net metadata: host '{}' is not allowed", host));
+            }
+            policy.allowed_hosts = hosts;
+        }
+        if let Some(cidrs) = net.allowed_cidrs {
+            let cidrs = cidrs
+                .iter()
+                .map(|cidr| {
+                    cidr.parse::<IpNet>()
+                        .map_err(|err| format!("net metadata: invalid range '{}': {}", cidr, err))
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            // Ranges are exempted from the egress guard, so they are never inherited from an
+            // open policy
+            if let Some(cidr) = cidrs
+                .iter()
+                .find(|cidr| !self.allowed_cidrs.iter().any(|c| c.contains(*cidr)))
+            {
+                return Err(format!("net metadata: range '{}' is not allowed", cidr));
+            }
+            policy.allowed_cidrs = cidrs;
+        }
+        if let Some(schemes) = net.allowed_schemes {
+            if let Some(scheme) = schemes.iter().find(|s| !self.allowed_schemes.contains(*s)) {
+                return Err(format!("net metadata: scheme '{}' is not allowed", scheme));
+            }
+            policy.allowed_schemes = schemes;
+        }
+        if let Some(max_response_size) = net.max_response_size {
+            policy.max_response_size = max_response_size.min(self.max_response_size);
+        }
+        if let Some(timeout_ms) = net.timeout_ms {
+            policy.timeout = Duration::from_millis(timeout_ms).min(self.timeout);
+        }
+        if let Some(max_redirects) = net.max_redirects {
+            policy.max_redirects = max_redirects.min(self.max_redirects);
+        }
+
+        Ok(policy)
+    }
+
+    /// Whether every destination matched by the allowed host `host` is allowed by this policy
+    fn covers_host(&self, host: &str) -> bool {
+        if self.allowed_hosts.is_empty() && self.allowed_cidrs.is_empty() {
+            return true;
+        }
+
+        let host = host.trim_end_matches('.').to_ascii_lowercase();
+        match host.strip_prefix("*.") {
+            Some(suffix) => self.allowed_hosts.iter().any(|allowed| {
+                allowed
+                    .to_ascii_lowercase()
+                    .strip_prefix("*.")
+                    .is_some_and(|allowed| {
+                        suffix == allowed
+                            || suffix
+                                .strip_suffix(allowed)
+                                .is_some_and(|prefix| prefix.ends_with('.'))
+                    })
+            }),
+            None => match host.parse::<IpAddr>() {
+                Ok(ip) => self.is_ip_allowed(ip),
+                Err(_) => self.is_host_allowed(&host),
+            },
+        }
+    }
+
+    /// Check if the destination `url` is allowed by this policy
+    pub fn check_url(&self, url: &Url) -> Result<(), String> {
+        if !self.allowed_schemes.iter().any(|s| s == url.scheme()) {
+            return Err(format!("URL scheme '{}' is not allowed", url.scheme()));
+        }
+
+        let host = url
+            .host()
+            .ok_or_else(|| format!("URL '{}' has no host", url))?;
+        if self.allowed_hosts.is_empty() && self.allowed_cidrs.is_empty() {
+            return Ok(());
+        }
+
+        let allowed = match host {
+            Host::Domain(domain) => self.is_host_allowed(domain),
+            Host::Ipv4(ip) => self.is_ip_allowed(IpAddr::V4(ip)),
+            Host::Ipv6(ip) => self.is_ip_allowed(IpAddr::V6(ip)),
+        };
+        if !allowed {
+            return Err(format!("Egress to '{}' is not allowed", host));
+        }
+        Ok(())
+    }
+
+    fn is_host_allowed(&self, host: &str) -> bool {
+        let host = host.trim_end_matches('.').to_ascii_lowercase();
+        self.allowed_hosts.iter().any(|allowed| {
+            let allowed = allowed.to_ascii_lowercase();
+            match allowed.strip_prefix("*.") {
+                Some(suffix) => host
+                    .strip_suffix(suffix)
+                    .is_some_and(|prefix| prefix.ends_with('.')),
+                None => host == allowed,
+            }
+        })
+    }
+
+    fn is_ip_allowed(&self, ip: IpAddr) -> bool {
+        self.allowed_cidrs.iter().any(|cidr| cidr.contains(&ip))
+            || self
+                .allowed_hosts
+                .iter()
+                .any(|host| *host == ip.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(hosts: &[&str], cidrs: &[&str]) -> NetPolicy {
+        NetPolicy {
+            allowed_hosts: hosts.iter().map(|h| h.to_string()).collect(),
+            allowed_cidrs: cidrs.iter().map(|c| c.parse().unwrap()).collect(),
+            ..NetPolicy::default()
+        }
+    }
+
+    fn check(policy: &NetPolicy, url: &str) -> Result<(), String> {
+        policy.check_url(&Url::parse(url).unwrap())
+    }
+
+    #[test]
+    fn test_check_url_hosts() {
+        let open = NetPolicy::default();
+        assert!(check(&open, "https://api.example.com/v1").is_ok());
+        assert!(check(&open, "http://10.0.0.1/").is_ok());
+
+        let policy = policy(&["api.example.com", "*.example.org"], &[]);
+        assert!(check(&policy, "https://api.example.com/v1").is_ok());
+        assert!(check(&policy, "https://API.Example.com./v1").is_ok());
+        assert!(check(&policy, "https://example.com").is_err());
+        assert!(check(&policy, "https://evil-api.example.com").is_err());
+
+        // Wildcards match subdomains at any depth, but not the domain itself
+        assert!(check(&policy, "https://a.example.org").is_ok());
+        assert!(check(&policy, "https://a.b.example.org").is_ok());
+        assert!(check(&policy, "https://example.org").is_err());
+        assert!(check(&policy, "https://notexample.org").is_err());
+        assert!(check(&policy, "https://example.org.evil.com").is_err());
+    }
+
+    #[test]
+    fn test_check_url_cidrs() {
+        let policy = policy(&["192.0.2.7"], &["10.1.0.0/16", "fd00::/8"]);
+        assert!(check(&policy, "http://10.1.2.3/").is_ok());
+        assert!(check(&policy, "http://10.2.0.1/").is_err());
+        assert!(check(&policy, "http://[fd00::1]/").is_ok());
+        assert!(check(&policy, "http://[fe80::1]/").is_err());
+
+        // IP literals may also be listed as hosts
+        assert!(check(&policy, "http://192.0.2.7/").is_ok());
+        assert!(check(&policy, "http://192.0.2.8/").is_err());
+
+        // Ranges do not allow domains
+        assert!(check(&policy, "https://api.example.com").is_err());
+    }
+
+    #[test]
+    fn test_check_url_schemes_and_ports() {
+        let open = NetPolicy::default();
+        assert!(check(&open, "ftp://example.com/file").is_err());
+        assert!(check(&open, "file:///etc/passwd").is_err());
+        assert!(check(&open, "ws://example.com").is_err());
+
+        let https_only = NetPolicy {
+            allowed_schemes: vec!["https".into()],
+            ..policy(&["api.example.com"], &["10.1.0.0/16"])
+        };
+        assert!(check(&https_only, "https://api.example.com").is_ok());
+        assert!(check(&https_only, "http://api.example.com").is_err());
+
+        // Ports do not change the destination host
+        assert!(check(&https_only, "https://api.example.com:8443/v1").is_ok());
+        assert!(check(&https_only, "https://10.1.0.1:8443/").is_ok());
+        assert!(check(&https_only, "https://other.example.com:443/").is_err());
+    }
+
+    #[test]
+    fn test_for_function() {
+        let base = NetPolicy {
+            max_redirects: 3,
+            ..policy(&["api.example.com", "*.example.org"], &["10.1.0.0/16"])
+        };
+        assert_eq!(
+            base.for_function("").unwrap().allowed_hosts,
+            base.allowed_hosts
+        );
+        assert_eq!(
+            base.for_function(r#"{"tee": true}"#).unwrap().allowed_hosts,
+            base.allowed_hosts
+        );
+
+        let function = base
+            .for_function(
+                r#"{"net": {
+                    "allowed_hosts": ["*.eu.example.org", "api.example.com"],
+                    "allowed_cidrs": ["10.1.2.0/24"],
+                    "allowed_schemes": ["https"],
+                    "timeout_ms": 60000,
+                    "max_redirects": 0
+                }}"#,
+            )
+            .unwrap();
+        assert!(check(&function, "https://x.eu.example.org").is_ok());
+        assert!(check(&function, "https://x.us.example.org").is_err());
+        assert!(check(&function, "http://api.example.com").is_err());
+        assert!(check(&function, "https://10.1.2.3").is_ok());
+        assert!(check(&function, "https://10.1.3.3").is_err());
+        assert_eq!(function.timeout, base.timeout);
+        assert_eq!(function.max_redirects, 0);
+
+        // A function can not widen the policy
+        for metadata in [
+            r#"{"net": {"allowed_hosts": ["other.example.com"]}}"#,
+            r#"{"net": {"allowed_hosts": ["*.com"]}}"#,
+            r#"{"net": {"allowed_cidrs": ["10.0.0.0/8"]}}"#,
+            r#"{"net": {"allowed_schemes": ["ftp"]}}"#,
+            r#"{"net": {"allowed_cidrs": ["not a range"]}}"#,
+            r#"{"net": {"allow_net": true}}"#,
+        ] {
+            assert!(base.for_function(metadata).is_err(), "{}", metadata);
+        }
+
+        // Ranges are exempted from the egress guard, even an open policy does not grant them
+        let open = NetPolicy::default();
+        assert!(open
+            .for_function(r#"{"net": {"allowed_hosts": ["a.example.com"]}}"#)
+            .is_ok());
+        assert!(open
+            .for_function(r#"{"net": {"allowed_cidrs": ["10.0.0.0/8"]}}"#)
+            .is_err());
+    }
+}
@@ -0,0 +1,701 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+//! Admin policies decided before invocations and op dispatch.
+//!
+//! A policy is a rule over the attributes of what is being decided: the tenant, tags and
+//! security level of a function about to be invoked, or the op a running function calls and
+//! its arguments, e.g. the oracle providers it asks. The enabled policies of a stage are tried
+//! in priority order and the first one whose conditions all hold decides; what no policy
+//! matches is allowed. Every decision taken by a policy is logged as a [`PolicyDecision`].
+//! Like the grants, policies are kept in a directory shared by the API and the workers.
+
+use std::collections::VecDeque;
+use std::io::{BufRead, BufReader, Write};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use thiserror::Error;
+use uuid::Uuid;
+
+/// Most conditions a policy may have
+pub const MAX_POLICY_CONDITIONS: usize = 32;
+
+/// Error type for policies
+#[derive(Debug, Error)]
+pub enum PolicyError {
+    #[error("policies: no such policy: {0}")]
+    NotFound(String),
+
+    #[error("policies: invalid policy: {0}")]
+    Invalid(String),
+
+    #[error("policies: storage error: {0}")]
+    Storage(String),
+}
+
+/// When a policy is decided
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PolicyStage {
+    /// Before a function is invoked, by the API
+    Invoke,
+
+    /// Before an op of a running function is dispatched, by the worker
+    Op,
+}
+
+/// Outcome of a matching policy
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PolicyEffect {
+    Allow,
+    Deny,
+}
+
+/// Comparison of a condition
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConditionOp {
+    /// The attribute, or one of its items, equals the value
+    Eq,
+
+    /// Neither the attribute nor its items equal the value
+    Ne,
+
+    /// The attribute, or one of its items, is in the value list
+    In,
+
+    /// Neither the attribute nor its items are in the value list
+    NotIn,
+
+    /// The attribute, or one of its items, matches the value pattern, `*` matching any text
+    Glob,
+
+    /// The attribute is set if the value is true, unset if it is false
+    Exists,
+}
+
+/// Condition over an attribute of what is decided
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Condition {
+    /// Attribute name, e.g. `tenant`, `tags` or `op`
+    pub attribute: String,
+
+    /// Comparison
+    pub op: ConditionOp,
+
+    /// Value compared with
+    #[serde(default)]
+    pub value: Value,
+}
+
+impl Condition {
+    fn check(&self) -> Result<(), PolicyError> {
+        if self.attribute.is_empty() || self.attribute.len() > 64 {
+            return Err(PolicyError::Invalid(
+                "condition attributes are 1 to 64 characters".to_string(),
+            ));
+        }
+
+        let valid = match self.op {
+            ConditionOp::Eq | ConditionOp::Ne => is_scalar(&self.value),
+            ConditionOp::In | ConditionOp::NotIn => self
+                .value
+                .as_array()
+                .is_some_and(|values| values.iter().all(is_scalar)),
+            ConditionOp::Glob => self.value.is_string(),
+            ConditionOp::Exists => self.value.is_boolean(),
+        };
+        if !valid {
+            return Err(PolicyError::Invalid(format!(
+                "invalid value for {:?} on '{}'",
+                self.op, self.attribute
+            )));
+        }
+        Ok(())
+    }
+
+    /// Whether the condition holds for the attributes
+    pub fn holds(&self, attributes: &Map<String, Value>) -> bool {
+        let attribute = attributes.get(&self.attribute).filter(|v| !v.is_null());
+        let items: Vec<&Value> = match attribute {
+            Some(Value::Array(items)) => items.iter().collect(),
+            Some(value) => vec![value],
+            None => Vec::new(),
+        };
+        let any_in = |values: &[Value]| items.iter().any(|item| values.contains(item));
+
+        match self.op {
+            ConditionOp::Eq => any_in(std::slice::from_ref(&self.value)),
+            ConditionOp::Ne => !any_in(std::slice::from_ref(&self.value)),
+            ConditionOp::In => any_in(self.value.as_array().map_or(&[], Vec::as_slice)),
+            ConditionOp::NotIn => !any_in(self.value.as_array().map_or(&[], Vec::as_slice)),
+            ConditionOp::Glob => {
+                let pattern = self.value.as_str().unwrap_or_default();
+                items
+                    .iter()
+                    .filter_map(|item| item.as_str())
+                    .any(|item| glob_match(pattern, item))
+            }
+            ConditionOp::Exists => attribute.is_some() == self.value.as_bool().unwrap_or(true),
+        }
+    }
+}
+
+fn is_scalar(value: &Value) -> bool {
+    value.is_string() || value.is_number() || value.is_boolean()
+}
+
+/// Whether `text` matches `pattern`, `*` matching any text
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = text.strip_prefix(first) else {
+        return false;
+    };
+
+    let parts: Vec<&str> = parts.collect();
+    let Some((last, middle)) = parts.split_last() else {
+        // No `*`, the pattern is the whole text
+        return rest.is_empty();
+    };
+    for part in middle {
+        match rest.find(part) {
+            Some(at) => rest = &rest[at + part.len()..],
+            None => return false,
+        }
+    }
+    rest.ends_with(last)
+}
+
+/// Rule of a policy, as created or updated by an admin
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PolicyRule {
+    /// Policy name
+    pub name: String,
+
+    /// What the policy is for
+    #[serde(default)]
+    pub description: Option<String>,
+
+    /// When the policy is decided
+    pub stage: PolicyStage,
+
+    /// Outcome when the conditions hold
+    pub effect: PolicyEffect,
+
+    /// Conditions that must all hold, none matching everything
+    #[serde(default)]
+    pub conditions: Vec<Condition>,
+
+    /// Reason given to the caller when the policy denies
+    #[serde(default)]
+    pub message: Option<String>,
+
+    /// Order the policies of a stage are tried in, lowest first
+    #[serde(default)]
+    pub priority: i32,
+
+    /// Whether the policy is decided
+    #[serde(default = "enabled")]
+    pub enabled: bool,
+}
+
+fn enabled() -> bool {
+    true
+}
+
+impl PolicyRule {
+    fn check(&self) -> Result<(), PolicyError> {
+        if self.name.trim().is_empty() || self.name.len() > 64 {
+            return Err(PolicyError::Invalid(
+                "policy names are 1 to 64 characters".to_string(),
+            ));
+        }
+        if self.conditions.len() > MAX_POLICY_CONDITIONS {
+            return Err(PolicyError::Invalid(format!(
+                "a policy has at most {} conditions",
+                MAX_POLICY_CONDITIONS
+            )));
+        }
+        self.conditions.iter().try_for_each(Condition::check)
+    }
+
+    /// Whether the conditions of the rule all hold for the attributes
+    pub fn matches(&self, attributes: &Map<String, Value>) -> bool {
+        self.conditions
+            .iter()
+            .all(|condition| condition.holds(attributes))
+    }
+}
+
+/// Policy set by an admin
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Policy {
+    /// Policy ID
+    pub id: String,
+
+    /// Rule
+    #[serde(flatten)]
+    pub rule: PolicyRule,
+
+    /// Admin who created the policy
+    pub created_by: String,
+
+    /// Created at timestamp (secs since epoch)
+    pub created_at: u64,
+
+    /// Updated at timestamp (secs since epoch)
+    pub updated_at: u64,
+}
+
+/// Log record of a decision taken by a policy
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PolicyDecision {
+    /// Stage decided
+    pub stage: PolicyStage,
+
+    /// Policy that decided
+    pub policy_id: String,
+
+    /// Name of the policy that decided
+    pub policy_name: String,
+
+    /// Whether the request was allowed
+    pub allowed: bool,
+
+    /// Attributes decided on
+    pub attributes: Map<String, Value>,
+
+    /// Timestamp (secs since epoch)
+    pub timestamp: u64,
+}
+
+impl PolicyDecision {
+    /// Function decided on, if known
+    pub fn function_id(&self) -> Option<&str> {
+        self.attributes.get("function").and_then(Value::as_str)
+    }
+}
+
+/// Outcome of evaluating the policies of a stage
+#[derive(Debug, Clone, PartialEq)]
+pub struct Decision {
+    /// Whether the request is allowed
+    pub allowed: bool,
+
+    /// Policy that decided, None if no policy matched
+    pub policy_id: Option<String>,
+
+    /// Why the request was denied
+    pub reason: Option<String>,
+}
+
+impl Decision {
+    fn allow() -> Self {
+        Self {
+            allowed: true,
+            policy_id: None,
+            reason: None,
+        }
+    }
+
+    fn deny(policy_id: Option<String>, reason: String) -> Self {
+        Self {
+            allowed: false,
+            policy_id,
+            reason: Some(reason),
+        }
+    }
+}
+
+/// Storage of policies and their decision log
+pub trait PolicyStore: Send + Sync {
+    /// All policies
+    fn policies(&self) -> Result<Vec<Policy>, PolicyError>;
+
+    /// Insert or replace a policy
+    fn put_policy(&self, policy: &Policy) -> Result<(), PolicyError>;
+
+    /// Delete a policy, false if there is none with the ID
+    fn delete_policy(&self, id: &str) -> Result<bool, PolicyError>;
+
+    /// Append a decision to the log
+    fn record_decision(&self, decision: &PolicyDecision) -> Result<(), PolicyError>;
+
+    /// Most recent decisions, of a function if given, oldest first
+    fn decisions(
+        &self,
+        function_id: Option<&str>,
+        limit: usize,
+    ) -> Result<Vec<PolicyDecision>, PolicyError>;
+}
+
+/// In-memory policy store
+#[derive(Debug, Default)]
+pub struct MemoryPolicyStore {
+    policies: Mutex<Vec<Policy>>,
+    decisions: Mutex<Vec<PolicyDecision>>,
+}
+
+impl MemoryPolicyStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl PolicyStore for MemoryPolicyStore {
+    fn policies(&self) -> Result<Vec<Policy>, PolicyError> {
+        Ok(self.policies.lock().unwrap().clone())
+    }
+
+    fn put_policy(&self, policy: &Policy) -> Result<(), PolicyError> {
+        upsert(&mut self.policies.lock().unwrap(), policy);
+        Ok(())
+    }
+
+    fn delete_policy(&self, id: &str) -> Result<bool, PolicyError> {
+        let mut policies = self.policies.lock().unwrap();
+        let before = policies.len();
+        policies.retain(|p| p.id != id);
+        Ok(policies.len() < before)
+    }
+
+    fn record_decision(&self, decision: &PolicyDecision) -> Result<(), PolicyError> {
+        self.decisions.lock().unwrap().push(decision.clone());
+        Ok(())
+    }
+
+    fn decisions(
+        &self,
+        function_id: Option<&str>,
+        limit: usize,
+    ) -> Result<Vec<PolicyDecision>, PolicyError> {
+        let decisions = self.decisions.lock().unwrap();
+        let mut recent: Vec<_> = decisions
+            .iter()
+            .rev()
+            .filter(|d| function_id.is_none() || d.function_id() == function_id)
+            .take(limit)
+            .cloned()
+            .collect();
+        recent.reverse();
+        Ok(recent)
+    }
+}
+
+fn upsert(policies: &mut Vec<Policy>, policy: &Policy) {
+    match policies.iter_mut().find(|p| p.id == policy.id) {
+        Some(existing) => *existing = policy.clone(),
+        None => policies.push(policy.clone()),
+    }
+}
+
+/// Policy store keeping a JSON file of policies and a JSON lines decision log in a directory,
+/// so the API and the workers on a host share it
+pub struct FilePolicyStore {
+    dir: PathBuf,
+    // Serializes read-modify-write of the policy file within the process
+    lock: Mutex<()>,
+}
+
+impl FilePolicyStore {
+    /// Open the store in `dir`, creating it if needed
+    pub fn new(dir: impl Into<PathBuf>) -> Result<Self, PolicyError> {
+        let dir = dir.into();
+        std::fs::create_dir_all(&dir).map_err(|e| {
+            PolicyError::Storage(format!("Failed to create policy directory: {}", e))
+        })?;
+        Ok(Self {
+            dir,
+            lock: Mutex::new(()),
+        })
+    }
+
+    fn policies_path(&self) -> PathBuf {
+        self.dir.join("policies.json")
+    }
+
+    fn decisions_path(&self) -> PathBuf {
+        self.dir.join("decisions.jsonl")
+    }
+
+    fn write_policies(&self, policies: &[Policy]) -> Result<(), PolicyError> {
+        let data = serde_json::to_vec_pretty(policies)
+            .map_err(|e| PolicyError::Storage(format!("Failed to serialize policies: {}", e)))?;
+
+        // Write then rename, so a reader never sees a partial file
+        let path = self.policies_path();
+        let tmp = path.with_extension(format!("tmp.{}", std::process::id()));
+        std::fs::write(&tmp, data)
+            .and_then(|_| std::fs::rename(&tmp, &path))
+            .map_err(|e| PolicyError::Storage(format!("Failed to write policies: {}", e)))
+    }
+}
+
+impl PolicyStore for FilePolicyStore {
+    fn policies(&self) -> Result<Vec<Policy>, PolicyError> {
+        match std::fs::read(self.policies_path()) {
+            Ok(data) => serde_json::from_slice(&data)
+                .map_err(|e| PolicyError::Storage(format!("Invalid policy file: {}", e))),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+            Err(e) => Err(PolicyError::Storage(format!(
+                "Failed to read policies: {}",
+                e
+            ))),
+        }
+    }
+
+    fn put_policy(&self, policy: &Policy) -> Result<(), PolicyError> {
+        let _guard = self.lock.lock().unwrap();
+        let mut policies = self.policies()?;
+        upsert(&mut policies, policy);
+        self.write_policies(&policies)
+    }
+
+    fn delete_policy(&self, id: &str) -> Result<bool, PolicyError> {
+        let _guard = self.lock.lock().unwrap();
+        let mut policies = self.policies()?;
+        let before = policies.len();
+        policies.retain(|p| p.id != id);
+        if policies.len() == before {
+            return Ok(false);
+        }
+        self.write_policies(&policies)?;
+        Ok(true)
+    }
+
+    fn record_decision(&self, decision: &PolicyDecision) -> Result<(), PolicyError> {
+        let mut line = serde_json::to_vec(decision)
+            .map_err(|e| PolicyError::Storage(format!("Failed to serialize decision: {}", e)))?;
+        line.push(b'\n');
+
+        std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.decisions_path())
+            .and_then(|mut file| file.write_all(&line))
+            .map_err(|e| PolicyError::Storage(format!("Failed to record decision: {}", e)))
+    }
+
+    fn decisions(
+        &self,
+        function_id: Option<&str>,
+        limit: usize,
+    ) -> Result<Vec<PolicyDecision>, PolicyError> {
+        let file = match std::fs::File::open(self.decisions_path()) {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => {
+                return Err(PolicyError::Storage(format!(
+                    "Failed to read decisions: {}",
+                    e
+                )))
+            }
+        };
+
+        // Stream the log, keeping only the most recent decisions of the function
+        let mut recent = VecDeque::new();
+        for line in BufReader::new(file).lines() {
+            let line =
+                line.map_err(|e| PolicyError::Storage(format!("Failed to read decisions: {}", e)))?;
+            if line.is_empty() || limit == 0 {
+                continue;
+            }
+            let decision: PolicyDecision = serde_json::from_str(&line)
+                .map_err(|e| PolicyError::Storage(format!("Invalid decision: {}", e)))?;
+            if function_id.is_some() && decision.function_id() != function_id {
+                continue;
+            }
+            if recent.len() == limit {
+                recent.pop_front();
+            }
+            recent.push_back(decision);
+        }
+        Ok(recent.into())
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// Policies of the platform
+#[derive(Clone)]
+pub struct PolicyEngine {
+    store: Arc<dyn PolicyStore>,
+}
+
+impl PolicyEngine {
+    pub fn new(store: Arc<dyn PolicyStore>) -> Self {
+        Self { store }
+    }
+
+    /// Policies, in the order they are tried
+    pub fn list(&self) -> Result<Vec<Policy>, PolicyError> {
+        let mut policies = self.store.policies()?;
+        policies.sort_by(|a, b| {
+            (
+                a.rule.stage == PolicyStage::Op,
+                a.rule.priority,
+                a.created_at,
+            )
+                .cmp(&(
+                    b.rule.stage == PolicyStage::Op,
+                    b.rule.priority,
+                    b.created_at,
+                ))
+        });
+        Ok(policies)
+    }
+
+    /// Policy by ID
+    pub fn get(&self, id: &str) -> Result<Policy, PolicyError> {
+        self.store
+            .policies()?
+            .into_iter()
+            .find(|p| p.id == id)
+            .ok_or_else(|| PolicyError::NotFound(id.to_string()))
+    }
+
+    /// Create a policy
+    pub fn create(&self, rule: PolicyRule, created_by: &str) -> Result<Policy, PolicyError> {
+        rule.check()?;
+
+        let now = now();
+        let policy = Policy {
+            id: Uuid::new_v4().to_string(),
+            rule,
+            created_by: created_by.to_string(),
+            created_at: now,
+            updated_at: now,
+        };
+        self.store.put_policy(&policy)?;
+        log::info!(
+            "policies: {} created {} ({})",
+            created_by,
+            policy.rule.name,
+            policy.id
+        );
+        Ok(policy)
+    }
+
+    /// Replace the rule of a policy
+    pub fn update(&self, id: &str, rule: PolicyRule) -> Result<Policy, PolicyError> {
+        rule.check()?;
+
+        let mut policy = self.get(id)?;
+        policy.rule = rule;
+        policy.updated_at = now();
+        self.store.put_policy(&policy)?;
+        Ok(policy)
+    }
+
+    /// Delete a policy
+    pub fn delete(&self, id: &str) -> Result<(), PolicyError> {
+        if !self.store.delete_policy(id)? {
+            return Err(PolicyError::NotFound(id.to_string()));
+        }
+        Ok(())
+    }
+
+    /// Decide a request of a stage on its attributes, logging the decision of the policy
+    /// that took it
+    pub fn evaluate(&self, stage: PolicyStage, attributes: Map<String, Value>) -> Decision {
+        let policies = match self.list() {
+            Ok(policies) => policies,
+            Err(e) => {
+                // Requests are not let through policies that cannot be read
+                log::error!("policies: {}", e);
+                return Decision::deny(None, "Failed to read policies".to_string());
+            }
+        };
+        let Some(policy) = policies
+            .into_iter()
+            .filter(|p| p.rule.enabled && p.rule.stage == stage)
+            .find(|p| p.rule.matches(&attributes))
+        else {
+            return Decision::allow();
+        };
+
+        let allowed = policy.rule.effect == PolicyEffect::Allow;
+        let record = PolicyDecision {
+            stage,
+            policy_id: policy.id.clone(),
+            policy_name: policy.rule.name.clone(),
+            allowed,
+            attributes,
+            timestamp: now(),
+        };
+        if let Err(e) = self.store.record_decision(&record) {
+            // An unlogged decision does not allow anything
+            log::error!("policies: {}", e);
+            return Decision::deny(Some(policy.id), "Failed to log policy decision".to_string());
+        }
+
+        if allowed {
+            return Decision {
+                allowed,
+                policy_id: Some(policy.id),
+                reason: None,
+            };
+        }
+        let reason = policy
+            .rule
+            .message
+            .clone()
+            .unwrap_or_else(|| format!("Denied by policy '{}'", policy.rule.name));
+        Decision::deny(Some(policy.id), reason)
+    }
+
+    /// Most recent decisions, of a function if given, oldest first
+    pub fn decisions(
+        &self,
+        function_id: Option<&str>,
+        limit: usize,
+    ) -> Result<Vec<PolicyDecision>, PolicyError> {
+        self.store.decisions(function_id, limit)
+    }
+}
+
+/// Policies applied to the ops of a single function, as put into the function's runtime state
+#[derive(Clone)]
+pub struct FunctionPolicy {
+    engine: PolicyEngine,
+    tenant: String,
+    function_id: String,
+}
+
+impl FunctionPolicy {
+    pub fn new(
+        engine: PolicyEngine,
+        tenant: impl Into<String>,
+        function_id: impl Into<String>,
+    ) -> Self {
+        Self {
+            engine,
+            tenant: tenant.into(),
+            function_id: function_id.into(),
+        }
+    }
+
+    /// Check whether the function may dispatch `op` with the attributes of its arguments
+    pub fn check_op(&self, op: &str, mut attributes: Map<String, Value>) -> Result<(), String> {
+        attributes.insert("op".to_string(), op.into());
+        attributes.insert("tenant".to_string(), self.tenant.clone().into());
+        attributes.insert("function".to_string(), self.function_id.clone().into());
+
+        let decision = self.engine.evaluate(PolicyStage::Op, attributes);
+        match decision.reason {
+            Some(reason) if !decision.allowed => Err(reason),
+            _ => Ok(()),
+        }
+    }
+}
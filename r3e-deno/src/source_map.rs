@@ -0,0 +1,297 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+use serde::Deserialize;
+
+/// Source map errors
+#[derive(Debug, thiserror::Error)]
+pub enum SourceMapError {
+    #[error("source-map: invalid json: {0}")]
+    InvalidJson(String),
+
+    #[error("source-map: unsupported version: {0}")]
+    UnsupportedVersion(u32),
+
+    #[error("source-map: invalid mappings: {0}")]
+    InvalidMappings(String),
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RawSourceMap {
+    version: u32,
+
+    #[serde(default)]
+    source_root: Option<String>,
+
+    #[serde(default)]
+    sources: Vec<Option<String>>,
+
+    #[serde(default)]
+    names: Vec<String>,
+
+    mappings: String,
+}
+
+/// A single decoded segment of a generated line
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Segment {
+    generated_column: u32,
+    source: Option<u32>,
+    original_line: u32,
+    original_column: u32,
+    name: Option<u32>,
+}
+
+/// Position in the original sources
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OriginalPosition {
+    /// Source file, e.g. `src/index.ts` or `node_modules/dep/index.js`
+    pub source: String,
+
+    /// 1-based line
+    pub line: u32,
+
+    /// 1-based column
+    pub column: u32,
+
+    /// Original symbol name, if recorded
+    pub name: Option<String>,
+}
+
+/// Parsed source map (revision 3)
+#[derive(Debug, Clone)]
+pub struct SourceMap {
+    sources: Vec<String>,
+    names: Vec<String>,
+    lines: Vec<Vec<Segment>>,
+}
+
+impl SourceMap {
+    pub fn parse(json: &str) -> Result<Self, SourceMapError> {
+        let raw: RawSourceMap = serde_json::from_str(json)
+            .map_err(|err| SourceMapError::InvalidJson(err.to_string()))?;
+        if raw.version != 3 {
+            return Err(SourceMapError::UnsupportedVersion(raw.version));
+        }
+
+        let root = raw
+            .source_root
+            .filter(|root| !root.is_empty())
+            .map(|root| format!("{}/", root.trim_end_matches('/')))
+            .unwrap_or_default();
+        let sources = raw
+            .sources
+            .into_iter()
+            .map(|source| format!("{}{}", root, source.unwrap_or_default()))
+            .collect();
+
+        Ok(Self {
+            sources,
+            names: raw.names,
+            lines: decode_mappings(&raw.mappings)?,
+        })
+    }
+
+    /// Look up the original position of a generated 1-based `line` and `column`,
+    /// as reported in V8 stack frames.
+    pub fn lookup(&self, line: u32, column: u32) -> Option<OriginalPosition> {
+        let segments = self.lines.get(line.checked_sub(1)? as usize)?;
+        let column = column.saturating_sub(1);
+
+        // the closest segment starting at or before the column
+        let idx = segments.partition_point(|seg| seg.generated_column <= column);
+        let segment = segments.get(idx.checked_sub(1)?)?;
+
+        let source = self.sources.get(segment.source? as usize)?;
+        Some(OriginalPosition {
+            source: source.clone(),
+            line: segment.original_line + 1,
+            column: segment.original_column + 1,
+            name: segment
+                .name
+                .and_then(|name| self.names.get(name as usize))
+                .cloned(),
+        })
+    }
+
+    /// Rewrite every `<specifier>:<line>:<column>` location in `stack` to its original
+    /// position. Frames of other modules or without a mapping are left untouched.
+    pub fn remap_stack(&self, stack: &str, specifier: &str) -> String {
+        let mut out = String::with_capacity(stack.len());
+        let mut rest = stack;
+        while let Some(start) = rest.find(specifier) {
+            let after = &rest[start + specifier.len()..];
+            out.push_str(&rest[..start]);
+
+            match parse_location(after)
+                .and_then(|(line, column, len)| self.lookup(line, column).map(|pos| (pos, len)))
+            {
+                Some((pos, len)) => {
+                    out.push_str(&format!("{}:{}:{}", pos.source, pos.line, pos.column));
+                    rest = &after[len..];
+                }
+                None => {
+                    out.push_str(specifier);
+                    rest = after;
+                }
+            }
+        }
+        out.push_str(rest);
+        out
+    }
+}
+
+/// Parse `:<line>:<column>`, returning the consumed length
+fn parse_location(s: &str) -> Option<(u32, u32, usize)> {
+    let (line, line_len) = parse_number(s.strip_prefix(':')?)?;
+    let (column, column_len) = parse_number(s[1 + line_len..].strip_prefix(':')?)?;
+    Some((line, column, 2 + line_len + column_len))
+}
+
+fn parse_number(s: &str) -> Option<(u32, usize)> {
+    let len = s.bytes().take_while(u8::is_ascii_digit).count();
+    Some((s[..len].parse().ok()?, len))
+}
+
+fn decode_mappings(mappings: &str) -> Result<Vec<Vec<Segment>>, SourceMapError> {
+    let mut lines = Vec::new();
+    let (mut source, mut original_line, mut original_column, mut name) = (0i64, 0i64, 0i64, 0i64);
+
+    for line in mappings.split(';') {
+        let mut segments = Vec::new();
+        let mut generated_column = 0i64;
+        for raw in line.split(',').filter(|raw| !raw.is_empty()) {
+            let fields = decode_vlq(raw)?;
+            generated_column += fields[0];
+
+            let mut segment = Segment {
+                generated_column: to_u32(generated_column)?,
+                source: None,
+                original_line: 0,
+                original_column: 0,
+                name: None,
+            };
+            match fields.len() {
+                1 => {}
+                4 | 5 => {
+                    source += fields[1];
+                    original_line += fields[2];
+                    original_column += fields[3];
+                    segment.source = Some(to_u32(source)?);
+                    segment.original_line = to_u32(original_line)?;
+                    segment.original_column = to_u32(original_column)?;
+                    if let Some(delta) = fields.get(4) {
+                        name += delta;
+                        segment.name = Some(to_u32(name)?);
+                    }
+                }
+                n => {
+                    return Err(SourceMapError::InvalidMappings(format!(
+                        "segment '{}' has {} fields",
+                        raw, n
+                    )))
+                }
+            }
+            segments.push(segment);
+        }
+
+        segments.sort_by_key(|seg| seg.generated_column);
+        lines.push(segments);
+    }
+
+    Ok(lines)
+}
+
+fn to_u32(value: i64) -> Result<u32, SourceMapError> {
+    u32::try_from(value)
+        .map_err(|_| SourceMapError::InvalidMappings(format!("value {} out of range", value)))
+}
+
+fn decode_vlq(segment: &str) -> Result<Vec<i64>, SourceMapError> {
+    let mut values = Vec::with_capacity(5);
+    let (mut value, mut shift) = (0i64, 0u32);
+    for ch in segment.bytes() {
+        let digit = match ch {
+            b'A'..=b'Z' => ch - b'A',
+            b'a'..=b'z' => ch - b'a' + 26,
+            b'0'..=b'9' => ch - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            _ => {
+                return Err(SourceMapError::InvalidMappings(format!(
+                    "invalid base64 char '{}'",
+                    ch as char
+                )))
+            }
+        } as i64;
+
+        if shift > 60 {
+            return Err(SourceMapError::InvalidMappings(
+                "vlq value too large".into(),
+            ));
+        }
+        value += (digit & 0x1f) << shift;
+        if digit & 0x20 != 0 {
+            shift += 5;
+            continue;
+        }
+
+        values.push(if value & 1 == 1 {
+            -(value >> 1)
+        } else {
+            value >> 1
+        });
+        value = 0;
+        shift = 0;
+    }
+
+    if shift != 0 {
+        return Err(SourceMapError::InvalidMappings(format!(
+            "truncated segment '{}'",
+            segment
+        )));
+    }
+    Ok(values)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // main.js line 1 -> src/index.ts:3:5, line 2 -> node_modules/dep/index.js:10:1 (name "helper")
+    const MAP: &str = r#"{
+        "version": 3,
+        "sources": ["src/index.ts", "node_modules/dep/index.js"],
+        "names": ["helper"],
+        "mappings": "AAEI;ACOJA"
+    }"#;
+
+    #[test]
+    fn test_lookup() {
+        let map = SourceMap::parse(MAP).unwrap();
+
+        let pos = map.lookup(1, 1).unwrap();
+        assert_eq!(pos.source, "src/index.ts");
+        assert_eq!((pos.line, pos.column), (3, 5));
+
+        let pos = map.lookup(2, 7).unwrap();
+        assert_eq!(pos.source, "node_modules/dep/index.js");
+        assert_eq!((pos.line, pos.column), (10, 1));
+        assert_eq!(pos.name.as_deref(), Some("helper"));
+
+        assert!(map.lookup(3, 1).is_none());
+    }
+
+    #[test]
+    fn test_remap_stack() {
+        let map = SourceMap::parse(MAP).unwrap();
+        let stack = "Error: boom\n    at helper (file://main.js/:2:3)\n    at file://main.js/:1:1\n    at other.js:1:1";
+
+        let remapped = map.remap_stack(stack, "file://main.js/");
+        assert_eq!(
+            remapped,
+            "Error: boom\n    at helper (node_modules/dep/index.js:10:1)\n    at src/index.ts:3:5\n    at other.js:1:1"
+        );
+    }
+}
@@ -0,0 +1,18 @@
+-- Create custom_domains table mapping domains and path prefixes to HTTP-triggered functions
+CREATE TABLE IF NOT EXISTS custom_domains (
+    id UUID PRIMARY KEY,
+    user_id UUID NOT NULL,
+    domain VARCHAR(253) NOT NULL,
+    path_prefix VARCHAR(255) NOT NULL,
+    function_id UUID NOT NULL,
+    verification_token VARCHAR(64) NOT NULL,
+    verified BOOLEAN NOT NULL DEFAULT FALSE,
+    created_at TIMESTAMPTZ NOT NULL,
+    verified_at TIMESTAMPTZ
+);
+
+-- Only one verified mapping may serve a path of a domain
+CREATE UNIQUE INDEX IF NOT EXISTS idx_custom_domains_verified_path ON custom_domains(domain, path_prefix) WHERE verified;
+
+-- Create index on user_id for the mappings of a user
+CREATE INDEX IF NOT EXISTS idx_custom_domains_user_id ON custom_domains(user_id);
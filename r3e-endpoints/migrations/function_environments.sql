@@ -0,0 +1,42 @@
+-- Create environments table holding the environments functions are promoted through, e.g. dev, staging and prod
+CREATE TABLE IF NOT EXISTS environments (
+    id UUID PRIMARY KEY,
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    name VARCHAR(32) NOT NULL,
+    position INTEGER NOT NULL,
+    config JSONB NOT NULL DEFAULT '{}',
+    secrets JSONB NOT NULL DEFAULT '{}',
+    created_at TIMESTAMPTZ NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL
+);
+
+-- Environment names are unique per user
+CREATE UNIQUE INDEX IF NOT EXISTS idx_environments_user_name ON environments(user_id, name);
+
+-- Create function_versions table keeping the code of every released version of a function
+CREATE TABLE IF NOT EXISTS function_versions (
+    function_id UUID NOT NULL REFERENCES functions(id) ON DELETE CASCADE,
+    version INTEGER NOT NULL,
+    code TEXT NOT NULL,
+    code_key_id VARCHAR(255),
+    hash VARCHAR(64) NOT NULL,
+    input_schema JSONB,
+    output_schema JSONB,
+    created_at TIMESTAMPTZ NOT NULL,
+    PRIMARY KEY (function_id, version)
+);
+
+-- Create function_releases table with the version of a function each environment runs
+CREATE TABLE IF NOT EXISTS function_releases (
+    function_id UUID NOT NULL REFERENCES functions(id) ON DELETE CASCADE,
+    environment_id UUID NOT NULL REFERENCES environments(id) ON DELETE CASCADE,
+    version INTEGER NOT NULL,
+    promoted_from VARCHAR(32),
+    released_by UUID NOT NULL,
+    released_at TIMESTAMPTZ NOT NULL,
+    PRIMARY KEY (function_id, environment_id),
+    FOREIGN KEY (function_id, version) REFERENCES function_versions(function_id, version)
+);
+
+-- Create index on environment_id for the releases of an environment
+CREATE INDEX IF NOT EXISTS idx_function_releases_environment_id ON function_releases(environment_id);
@@ -0,0 +1,3 @@
+-- Add the JSON Schemas functions may declare for their input and output
+ALTER TABLE functions ADD COLUMN IF NOT EXISTS input_schema JSONB;
+ALTER TABLE functions ADD COLUMN IF NOT EXISTS output_schema JSONB;
@@ -0,0 +1,29 @@
+-- Create function_uploads table tracking chunked uploads of function bundles
+CREATE TABLE IF NOT EXISTS function_uploads (
+    id UUID PRIMARY KEY,
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    size BIGINT NOT NULL,
+    sha256 VARCHAR(64) NOT NULL,
+    part_size BIGINT NOT NULL,
+    bundle_digest VARCHAR(64),
+    created_at BIGINT NOT NULL,
+    expires_at BIGINT NOT NULL
+);
+
+-- Create index on user_id for counting the open uploads of a user
+CREATE INDEX IF NOT EXISTS idx_function_uploads_user_id ON function_uploads(user_id);
+
+-- Create index on expires_at for collecting abandoned uploads
+CREATE INDEX IF NOT EXISTS idx_function_uploads_expires_at ON function_uploads(expires_at);
+
+-- Create function_upload_parts table referencing the parts kept in the blob store
+CREATE TABLE IF NOT EXISTS function_upload_parts (
+    upload_id UUID NOT NULL REFERENCES function_uploads(id) ON DELETE CASCADE,
+    part_index INTEGER NOT NULL,
+    digest VARCHAR(64) NOT NULL,
+    size BIGINT NOT NULL,
+    PRIMARY KEY (upload_id, part_index)
+);
+
+-- Create index on digest for checking whether a blob is still referenced
+CREATE INDEX IF NOT EXISTS idx_function_upload_parts_digest ON function_upload_parts(digest);
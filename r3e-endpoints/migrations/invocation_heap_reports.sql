@@ -0,0 +1,16 @@
+-- Create invocation_heap_reports table keeping the heap diagnostics of invocations that ran out of memory
+CREATE TABLE IF NOT EXISTS invocation_heap_reports (
+    invocation_id UUID PRIMARY KEY,
+    function_id UUID NOT NULL,
+    user_id UUID NOT NULL,
+    report JSONB NOT NULL,
+    snapshot TEXT,
+    created_at BIGINT NOT NULL,
+    expires_at BIGINT NOT NULL
+);
+
+-- Create index on function_id for the reports of a function
+CREATE INDEX IF NOT EXISTS idx_invocation_heap_reports_function_id ON invocation_heap_reports(function_id);
+
+-- Create index on expires_at for purging expired reports
+CREATE INDEX IF NOT EXISTS idx_invocation_heap_reports_expires_at ON invocation_heap_reports(expires_at);
@@ -0,0 +1,14 @@
+-- Create tenant_keys table keeping the KEK identifiers of tenants enrolled for encryption at rest
+CREATE TABLE IF NOT EXISTS tenant_keys (
+    tenant VARCHAR(255) PRIMARY KEY,
+    provider VARCHAR(32) NOT NULL,
+    kek_id VARCHAR(255) NOT NULL,
+    retired_kek_ids JSONB NOT NULL DEFAULT '[]',
+    created_at BIGINT NOT NULL
+);
+
+-- Add the KEK identifier function code is sealed with, NULL for plain code
+ALTER TABLE functions ADD COLUMN IF NOT EXISTS code_key_id VARCHAR(255);
+
+-- Create index on code_key_id for the functions sealed with a KEK
+CREATE INDEX IF NOT EXISTS idx_functions_code_key_id ON functions(code_key_id);
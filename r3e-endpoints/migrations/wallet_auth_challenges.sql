@@ -0,0 +1,5 @@
+-- Bind wallet login challenges to the origin of the client they were issued to
+ALTER TABLE auth_challenges ADD COLUMN IF NOT EXISTS origin VARCHAR(255);
+
+-- Create index on expires_at for sweeping expired challenges
+CREATE INDEX IF NOT EXISTS idx_auth_challenges_expires_at ON auth_challenges(expires_at);
@@ -0,0 +1,12 @@
+-- Create auth_failures table counting failed wallet logins per address and client, shared by
+-- every endpoint instance
+CREATE TABLE IF NOT EXISTS auth_failures (
+    failure_key VARCHAR(512) NOT NULL,
+    failed_at BIGINT NOT NULL
+);
+
+-- Create index on failure_key for checking lockouts
+CREATE INDEX IF NOT EXISTS idx_auth_failures_key ON auth_failures(failure_key, failed_at);
+
+-- Create index on failed_at for sweeping aged out failures
+CREATE INDEX IF NOT EXISTS idx_auth_failures_failed_at ON auth_failures(failed_at);
@@ -0,0 +1,430 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+//! Wallet login challenges.
+//!
+//! A challenge is a nonce a wallet signs to log in. It is bound to the address, blockchain
+//! and origin it was issued for, expires after a TTL and is deleted by the first attempt to
+//! redeem it, so each challenge can be tried once. Failed attempts are counted per address and
+//! client in the database, so every instance sees them, and a client with too many recent
+//! failures for an address is locked out of it until they age out. Other clients can still
+//! log in to the address.
+
+use std::time::Duration;
+
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::error::Error;
+
+/// Maximum length of the origin a challenge is bound to
+const MAX_ORIGIN_LENGTH: usize = 255;
+
+/// Maximum length of the client identity failures are counted under
+const MAX_CLIENT_LENGTH: usize = 128;
+
+/// Challenge settings
+#[derive(Debug, Clone, Copy)]
+pub struct ChallengeConfig {
+    /// How long a challenge can be redeemed
+    pub ttl: Duration,
+
+    /// Failed attempts a client may make for an address within the failure window
+    pub max_failures: u32,
+
+    /// Window failed attempts are counted over
+    pub failure_window: Duration,
+}
+
+impl Default for ChallengeConfig {
+    fn default() -> Self {
+        Self {
+            ttl: Duration::from_secs(300),
+            max_failures: 5,
+            failure_window: Duration::from_secs(900),
+        }
+    }
+}
+
+/// Challenge issued to a wallet
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuthChallenge {
+    /// Challenge ID, also the nonce of the message
+    pub id: String,
+
+    /// Wallet address
+    pub address: String,
+
+    /// Blockchain type
+    pub blockchain_type: String,
+
+    /// Origin of the client the challenge was issued to
+    pub origin: Option<String>,
+
+    /// Message to sign
+    pub message: String,
+
+    /// Expiration timestamp
+    pub expires_at: u64,
+
+    /// Creation timestamp
+    pub created_at: u64,
+}
+
+/// Message a wallet signs, naming everything the challenge is bound to
+pub fn challenge_message(
+    address: &str,
+    origin: Option<&str>,
+    issued_at: u64,
+    nonce: &str,
+) -> String {
+    format!(
+        "Sign this message to authenticate with R3E FaaS platform.\n\nWallet: {}\nOrigin: {}\nTimestamp: {}\nNonce: {}",
+        address,
+        origin.unwrap_or("none"),
+        issued_at,
+        nonce
+    )
+}
+
+/// Failed attempts per address and client, kept in the database
+#[derive(Debug, Clone)]
+struct FailureLimiter {
+    db: PgPool,
+    max_failures: u32,
+    window: u64,
+}
+
+impl FailureLimiter {
+    fn new(db: PgPool, config: &ChallengeConfig) -> Self {
+        Self {
+            db,
+            max_failures: config.max_failures,
+            window: config.failure_window.as_secs(),
+        }
+    }
+
+    /// Seconds until the client may try the address again, if it is locked out
+    async fn locked_for(&self, key: &str, now: u64) -> Result<Option<u64>, Error> {
+        let recent: Vec<(i64,)> = sqlx::query_as(
+            "SELECT failed_at FROM auth_failures WHERE failure_key = $1 AND failed_at > $2 \
+             ORDER BY failed_at DESC LIMIT $3",
+        )
+        .bind(key)
+        .bind(now.saturating_sub(self.window) as i64)
+        .bind(self.max_failures as i64)
+        .fetch_all(&self.db)
+        .await
+        .map_err(|e| Error::Database(format!("Failed to check login failures: {}", e)))?;
+
+        let recent: Vec<u64> = recent.into_iter().map(|(at,)| at as u64).collect();
+        Ok(lockout(&recent, self.max_failures, self.window, now))
+    }
+
+    async fn record(&self, key: &str, now: u64) -> Result<(), Error> {
+        sqlx::query("INSERT INTO auth_failures (failure_key, failed_at) VALUES ($1, $2)")
+            .bind(key)
+            .bind(now as i64)
+            .execute(&self.db)
+            .await
+            .map_err(|e| Error::Database(format!("Failed to record login failure: {}", e)))?;
+        Ok(())
+    }
+
+    async fn clear(&self, key: &str) -> Result<(), Error> {
+        sqlx::query("DELETE FROM auth_failures WHERE failure_key = $1")
+            .bind(key)
+            .execute(&self.db)
+            .await
+            .map_err(|e| Error::Database(format!("Failed to clear login failures: {}", e)))?;
+        Ok(())
+    }
+
+    /// Forget failures that aged out
+    async fn sweep(&self, now: u64) -> Result<u64, Error> {
+        let result = sqlx::query("DELETE FROM auth_failures WHERE failed_at <= $1")
+            .bind(now.saturating_sub(self.window) as i64)
+            .execute(&self.db)
+            .await
+            .map_err(|e| Error::Database(format!("Failed to purge login failures: {}", e)))?;
+        Ok(result.rows_affected())
+    }
+}
+
+/// Seconds until a client is unlocked, given its failures within the window, newest first
+fn lockout(recent: &[u64], max_failures: u32, window: u64, now: u64) -> Option<u64> {
+    if recent.len() < max_failures as usize || max_failures == 0 {
+        return None;
+    }
+
+    // Locked until enough failures age out to get under the limit
+    let unlocking = recent[max_failures as usize - 1];
+    Some((unlocking + window).saturating_sub(now))
+}
+
+/// Key failures are counted under, the address and the client trying it. Ethereum addresses
+/// are case-insensitive.
+fn failure_key(blockchain_type: &str, address: &str, client: &str) -> String {
+    let address = match blockchain_type {
+        "ethereum" => address.to_ascii_lowercase(),
+        _ => address.to_string(),
+    };
+    let client = match client.char_indices().nth(MAX_CLIENT_LENGTH) {
+        Some((end, _)) => &client[..end],
+        None => client,
+    };
+    format!("{}:{}:{}", blockchain_type, address, client)
+}
+
+/// Single-use wallet login challenges
+#[derive(Clone)]
+pub struct ChallengeStore {
+    /// Database pool
+    db: PgPool,
+
+    /// Challenge settings
+    config: ChallengeConfig,
+
+    /// Failed attempts per address and client
+    failures: FailureLimiter,
+}
+
+impl ChallengeStore {
+    /// Create a new challenge store
+    pub fn new(db: PgPool, config: ChallengeConfig) -> Self {
+        Self {
+            failures: FailureLimiter::new(db.clone(), &config),
+            db,
+            config,
+        }
+    }
+
+    /// Issue a challenge for an address, bound to the origin of the request
+    pub async fn issue(
+        &self,
+        address: &str,
+        blockchain_type: &str,
+        origin: Option<&str>,
+        client: &str,
+    ) -> Result<AuthChallenge, Error> {
+        let now = chrono::Utc::now().timestamp() as u64;
+        self.check_locked(blockchain_type, address, client, now)
+            .await?;
+        if origin.is_some_and(|origin| origin.len() > MAX_ORIGIN_LENGTH) {
+            return Err(Error::Validation("Origin is too long".to_string()));
+        }
+
+        let id = Uuid::new_v4().to_string();
+        let challenge = AuthChallenge {
+            message: challenge_message(address, origin, now, &id),
+            id,
+            address: address.to_string(),
+            blockchain_type: blockchain_type.to_string(),
+            origin: origin.map(str::to_string),
+            expires_at: now + self.config.ttl.as_secs(),
+            created_at: now,
+        };
+
+        sqlx::query(
+            "INSERT INTO auth_challenges (id, address, blockchain_type, origin, message, expires_at, created_at) \
+             VALUES ($1, $2, $3, $4, $5, $6, $7)",
+        )
+        .bind(&challenge.id)
+        .bind(&challenge.address)
+        .bind(&challenge.blockchain_type)
+        .bind(&challenge.origin)
+        .bind(&challenge.message)
+        .bind(challenge.expires_at as i64)
+        .bind(challenge.created_at as i64)
+        .execute(&self.db)
+        .await
+        .map_err(|e| Error::Database(format!("Failed to store auth challenge: {}", e)))?;
+
+        Ok(challenge)
+    }
+
+    /// Redeem a challenge for the address, blockchain and origin it was issued for.
+    ///
+    /// The challenge is deleted whether or not it matches, so it cannot be tried again.
+    /// Unknown, expired and mismatched challenges count as failed attempts of the client for
+    /// the address.
+    pub async fn redeem(
+        &self,
+        id: &str,
+        address: &str,
+        blockchain_type: &str,
+        origin: Option<&str>,
+        client: &str,
+    ) -> Result<AuthChallenge, Error> {
+        let now = chrono::Utc::now().timestamp() as u64;
+        self.check_locked(blockchain_type, address, client, now)
+            .await?;
+
+        let row: Option<(String, String, String, Option<String>, String, i64, i64)> =
+            sqlx::query_as(
+                "DELETE FROM auth_challenges WHERE id = $1 \
+                 RETURNING id, address, blockchain_type, origin, message, expires_at, created_at",
+            )
+            .bind(id)
+            .fetch_optional(&self.db)
+            .await
+            .map_err(|e| Error::Database(format!("Failed to redeem auth challenge: {}", e)))?;
+
+        let Some((
+            id,
+            stored_address,
+            stored_blockchain_type,
+            stored_origin,
+            message,
+            expires_at,
+            created_at,
+        )) = row
+        else {
+            self.record_failure(blockchain_type, address, client).await;
+            return Err(Error::Authentication("Invalid or expired challenge".into()));
+        };
+        let challenge = AuthChallenge {
+            id,
+            address: stored_address,
+            blockchain_type: stored_blockchain_type,
+            origin: stored_origin,
+            message,
+            expires_at: expires_at as u64,
+            created_at: created_at as u64,
+        };
+
+        if now > challenge.expires_at {
+            self.record_failure(blockchain_type, address, client).await;
+            return Err(Error::Authentication("Challenge has expired".into()));
+        }
+        if challenge.address != address
+            || challenge.blockchain_type != blockchain_type
+            || challenge.origin.as_deref() != origin
+        {
+            log::warn!(
+                "Challenge {} redeemed for {}/{} from {:?}, issued for {}/{} from {:?}",
+                challenge.id,
+                address,
+                blockchain_type,
+                origin,
+                challenge.address,
+                challenge.blockchain_type,
+                challenge.origin
+            );
+            self.record_failure(blockchain_type, address, client).await;
+            return Err(Error::Authentication(
+                "Challenge was issued for a different wallet or origin".into(),
+            ));
+        }
+
+        Ok(challenge)
+    }
+
+    /// Count a failed attempt of a client for an address, such as an invalid signature
+    pub async fn record_failure(&self, blockchain_type: &str, address: &str, client: &str) {
+        let now = chrono::Utc::now().timestamp() as u64;
+        let key = failure_key(blockchain_type, address, client);
+        if let Err(e) = self.failures.record(&key, now).await {
+            log::warn!("{}", e);
+        }
+    }
+
+    /// Forget the failed attempts of a client that logged in to an address
+    pub async fn record_success(&self, blockchain_type: &str, address: &str, client: &str) {
+        let key = failure_key(blockchain_type, address, client);
+        if let Err(e) = self.failures.clear(&key).await {
+            log::warn!("{}", e);
+        }
+    }
+
+    async fn check_locked(
+        &self,
+        blockchain_type: &str,
+        address: &str,
+        client: &str,
+        now: u64,
+    ) -> Result<(), Error> {
+        let key = failure_key(blockchain_type, address, client);
+        match self.failures.locked_for(&key, now).await? {
+            Some(retry_after) => Err(Error::RateLimited(format!(
+                "Too many failed login attempts, retry in {} seconds",
+                retry_after
+            ))),
+            None => Ok(()),
+        }
+    }
+
+    /// Delete expired challenges and forget failures that aged out
+    pub async fn purge_expired(&self) -> Result<u64, Error> {
+        let now = chrono::Utc::now().timestamp();
+        self.failures.sweep(now as u64).await?;
+
+        let result = sqlx::query("DELETE FROM auth_challenges WHERE expires_at < $1")
+            .bind(now)
+            .execute(&self.db)
+            .await
+            .map_err(|e| Error::Database(format!("Failed to purge auth challenges: {}", e)))?;
+
+        Ok(result.rows_affected())
+    }
+
+    /// Purge expired challenges every interval
+    pub fn spawn_sweep(&self, interval: Duration) -> tokio::task::JoinHandle<()> {
+        let store = self.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(interval);
+            loop {
+                interval.tick().await;
+                match store.purge_expired().await {
+                    Ok(0) => {}
+                    Ok(purged) => log::debug!("Purged {} expired auth challenges", purged),
+                    Err(e) => log::warn!("{}", e),
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_failure_lockout() {
+        // Under the limit
+        assert_eq!(lockout(&[20, 10], 3, 100, 20), None);
+        // Locked until the oldest of the last 3 failures ages out
+        assert_eq!(lockout(&[30, 20, 10], 3, 100, 30), Some(80));
+        // Unlocked once the first failure ages out, locked again by one more
+        assert_eq!(lockout(&[30, 20], 3, 100, 110), None);
+        assert_eq!(lockout(&[110, 30, 20], 3, 100, 110), Some(10));
+        assert_eq!(lockout(&[30, 20, 10], 0, 100, 30), None);
+    }
+
+    #[test]
+    fn test_failure_key() {
+        let key = failure_key("ethereum", "0xABCD", "203.0.113.5");
+        assert_eq!(key, failure_key("ethereum", "0xabcd", "203.0.113.5"));
+        assert_ne!(key, failure_key("neo_n3", "0xabcd", "203.0.113.5"));
+        assert_ne!(
+            failure_key("neo_n3", "NWallet", "203.0.113.5"),
+            failure_key("neo_n3", "NWallet", "198.51.100.7")
+        );
+
+        // Long client identities are cut
+        let long = failure_key("neo_n3", "NWallet", &"é".repeat(300));
+        assert_eq!(
+            long.chars().count(),
+            "neo_n3:NWallet:".len() + MAX_CLIENT_LENGTH
+        );
+    }
+
+    #[test]
+    fn test_challenge_message_binds_origin() {
+        let message = challenge_message("NWallet", Some("https://app.example.com"), 1, "nonce");
+        assert!(message.contains("Wallet: NWallet\n"));
+        assert!(message.contains("Origin: https://app.example.com\n"));
+        assert!(message.ends_with("Nonce: nonce"));
+        assert_ne!(message, challenge_message("NWallet", None, 1, "nonce"));
+    }
+}
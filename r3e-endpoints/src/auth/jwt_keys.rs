@@ -0,0 +1,450 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+use chrono::{DateTime, Duration, Utc};
+use jsonwebtoken::{decode, decode_header, encode, DecodingKey, EncodingKey, Header, Validation};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, RwLock};
+use tokio::task::JoinHandle;
+use tracing::{info, warn};
+
+use crate::error::Error;
+use r3e_secrets::service::SecretService;
+use r3e_secrets::{SecretEncryption, SecretError};
+
+/// Key ID of the key seeded from the configured JWT secret, also used for tokens without a kid
+pub const LEGACY_KID: &str = "legacy";
+
+/// Secret service owner of the persisted key ring
+const KEY_RING_OWNER: &str = "system";
+
+/// Secret service namespace of the persisted key ring
+const KEY_RING_NAMESPACE: &str = "jwt_keys";
+
+/// Secret ID of the persisted key ring
+const KEY_RING_ID: &str = "key_ring";
+
+/// JWT key ring configuration
+#[derive(Debug, Clone)]
+pub struct JwtKeyRingConfig {
+    /// How long a key signs new tokens before it is rotated out
+    pub rotation_interval: Duration,
+
+    /// How long a rotated-out key still verifies tokens, at least the token lifetime
+    pub grace_period: Duration,
+
+    /// Whether tokens signed by a rotated-out key are automatically re-issued
+    pub reissue: bool,
+
+    /// Tokens expiring within this window are re-issued too
+    pub reissue_before: Duration,
+}
+
+impl Default for JwtKeyRingConfig {
+    fn default() -> Self {
+        Self {
+            rotation_interval: Duration::days(7),
+            grace_period: Duration::days(1),
+            reissue: true,
+            reissue_before: Duration::zero(),
+        }
+    }
+}
+
+/// JWT signing key
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JwtSigningKey {
+    /// Key ID, carried in the `kid` header of the tokens it signs
+    pub kid: String,
+
+    /// HMAC secret
+    pub secret: String,
+
+    /// Creation timestamp
+    pub created_at: DateTime<Utc>,
+
+    /// When the key was rotated out, none for the active key
+    pub retired_at: Option<DateTime<Utc>>,
+}
+
+impl JwtSigningKey {
+    /// Generate a new random key
+    fn generate(now: DateTime<Utc>) -> Self {
+        Self {
+            kid: uuid::Uuid::new_v4().to_string(),
+            secret: hex::encode(SecretEncryption::generate_function_key()),
+            created_at: now,
+            retired_at: None,
+        }
+    }
+}
+
+/// Verified JWT token
+#[derive(Debug, Clone)]
+pub struct VerifiedToken<T> {
+    /// Token claims
+    pub claims: T,
+
+    /// Key ID the token was signed with
+    pub kid: String,
+
+    /// Whether the token was signed by a rotated-out key
+    pub stale: bool,
+}
+
+/// Encrypted persistence of the key ring in the secret service
+pub struct KeyRingStore {
+    /// Secret service
+    secret_service: Arc<dyn SecretService>,
+
+    /// Key encrypting the key ring
+    master_key: [u8; 32],
+}
+
+impl KeyRingStore {
+    /// Create a new key ring store
+    pub fn new(secret_service: Arc<dyn SecretService>, master_key: [u8; 32]) -> Self {
+        Self {
+            secret_service,
+            master_key,
+        }
+    }
+
+    async fn load(&self) -> Result<Option<Vec<JwtSigningKey>>, Error> {
+        let data = match self
+            .secret_service
+            .get_secret(
+                KEY_RING_OWNER,
+                KEY_RING_NAMESPACE,
+                KEY_RING_ID,
+                &self.master_key,
+            )
+            .await
+        {
+            Ok(data) => data,
+            Err(SecretError::NotFound(_)) => return Ok(None),
+            Err(e) => {
+                return Err(Error::Internal(format!(
+                    "Failed to load JWT key ring: {}",
+                    e
+                )))
+            }
+        };
+
+        serde_json::from_slice(&data)
+            .map(Some)
+            .map_err(|e| Error::Internal(format!("Failed to parse JWT key ring: {}", e)))
+    }
+
+    async fn save(&self, keys: &[JwtSigningKey]) -> Result<(), Error> {
+        let data = serde_json::to_vec(keys)
+            .map_err(|e| Error::Internal(format!("Failed to serialize JWT key ring: {}", e)))?;
+
+        self.secret_service
+            .store_secret(
+                KEY_RING_OWNER,
+                KEY_RING_NAMESPACE,
+                KEY_RING_ID,
+                &data,
+                &self.master_key,
+            )
+            .await
+            .map_err(|e| Error::Internal(format!("Failed to store JWT key ring: {}", e)))
+    }
+}
+
+/// JWT signing key ring.
+///
+/// New tokens are signed by the active key, rotated-out keys keep verifying the tokens
+/// they signed until their grace period ends.
+pub struct JwtKeyRing {
+    /// Configuration
+    config: JwtKeyRingConfig,
+
+    /// Keys, the active key last
+    keys: RwLock<Vec<JwtSigningKey>>,
+
+    /// Encrypted persistence, if configured
+    store: Option<KeyRingStore>,
+}
+
+impl JwtKeyRing {
+    /// Create a new key ring, seeded with the configured JWT secret
+    pub fn new(config: JwtKeyRingConfig, jwt_secret: &str) -> Self {
+        let seed = JwtSigningKey {
+            kid: LEGACY_KID.to_string(),
+            secret: jwt_secret.to_string(),
+            created_at: Utc::now(),
+            retired_at: None,
+        };
+
+        Self {
+            config,
+            keys: RwLock::new(vec![seed]),
+            store: None,
+        }
+    }
+
+    /// Persist the key ring encrypted in the secret service
+    pub fn with_store(mut self, store: KeyRingStore) -> Self {
+        self.store = Some(store);
+        self
+    }
+
+    /// Key ring configuration
+    pub fn config(&self) -> &JwtKeyRingConfig {
+        &self.config
+    }
+
+    /// Restore the persisted key ring, or persist the seeded one if there is none
+    pub async fn load(&self) -> Result<(), Error> {
+        let Some(store) = &self.store else {
+            return Ok(());
+        };
+
+        match store.load().await? {
+            Some(keys) if !keys.is_empty() => {
+                info!("Loaded JWT key ring with {} keys", keys.len());
+                *self.keys.write().unwrap() = keys;
+            }
+            _ => {
+                let keys = self.keys.read().unwrap().clone();
+                store.save(&keys).await?;
+            }
+        }
+
+        self.prune().await
+    }
+
+    /// Key ID of the active key
+    pub fn active_kid(&self) -> String {
+        let guard = self.keys.read().unwrap();
+        guard.last().map(|key| key.kid.clone()).unwrap_or_default()
+    }
+
+    /// Sign claims with the active key
+    pub fn sign<T: Serialize>(&self, claims: &T) -> Result<String, Error> {
+        let guard = self.keys.read().unwrap();
+        let key = guard
+            .last()
+            .ok_or_else(|| Error::Internal("JWT key ring is empty".into()))?;
+
+        let header = Header {
+            kid: Some(key.kid.clone()),
+            ..Header::default()
+        };
+        encode(
+            &header,
+            claims,
+            &EncodingKey::from_secret(key.secret.as_bytes()),
+        )
+        .map_err(|e| Error::Internal(format!("Failed to create JWT token: {}", e)))
+    }
+
+    /// Verify a token against the key it names, tokens without a kid use the legacy key
+    pub fn verify<T: DeserializeOwned>(&self, token: &str) -> Result<VerifiedToken<T>, Error> {
+        let header = decode_header(token)
+            .map_err(|e| Error::Authentication(format!("Invalid JWT token: {}", e)))?;
+        let kid = header.kid.unwrap_or_else(|| LEGACY_KID.to_string());
+
+        let now = Utc::now();
+        let (secret, stale) = {
+            let guard = self.keys.read().unwrap();
+            let key = guard
+                .iter()
+                .find(|key| key.kid == kid)
+                .ok_or_else(|| Error::Authentication(format!("Unknown JWT key: {}", kid)))?;
+
+            if let Some(retired_at) = key.retired_at {
+                if retired_at + self.config.grace_period <= now {
+                    return Err(Error::Authentication(format!("Expired JWT key: {}", kid)));
+                }
+            }
+            (key.secret.clone(), key.retired_at.is_some())
+        };
+
+        let token_data = decode::<T>(
+            token,
+            &DecodingKey::from_secret(secret.as_bytes()),
+            &Validation::default(),
+        )
+        .map_err(|e| Error::Authentication(format!("Invalid JWT token: {}", e)))?;
+
+        Ok(VerifiedToken {
+            claims: token_data.claims,
+            kid,
+            stale,
+        })
+    }
+
+    /// Whether a verified token expiring at `exp` should be re-issued
+    pub fn needs_reissue<T>(&self, token: &VerifiedToken<T>, exp: u64) -> bool {
+        if !self.config.reissue {
+            return false;
+        }
+
+        let reissue_at = Utc::now() + self.config.reissue_before;
+        token.stale || (exp as i64) <= reissue_at.timestamp()
+    }
+
+    /// Rotate in a new active key, returning its key ID
+    pub async fn rotate(&self) -> Result<String, Error> {
+        let now = Utc::now();
+        let mut keys = self.keys.read().unwrap().clone();
+        for key in keys.iter_mut().filter(|key| key.retired_at.is_none()) {
+            key.retired_at = Some(now);
+        }
+
+        let key = JwtSigningKey::generate(now);
+        let kid = key.kid.clone();
+        keys.push(key);
+        retain_verifiable(&mut keys, self.config.grace_period, now);
+
+        // Persist before switching, so a restart cannot lose the key tokens were signed with
+        if let Some(store) = &self.store {
+            store.save(&keys).await?;
+        }
+        *self.keys.write().unwrap() = keys;
+
+        info!("Rotated JWT signing key: kid={}", kid);
+        Ok(kid)
+    }
+
+    /// Rotate the active key if its rotation interval has passed
+    pub async fn rotate_if_due(&self) -> Result<Option<String>, Error> {
+        let due = {
+            let guard = self.keys.read().unwrap();
+            guard.last().map_or(true, |key| {
+                key.created_at + self.config.rotation_interval <= Utc::now()
+            })
+        };
+
+        if !due {
+            self.prune().await?;
+            return Ok(None);
+        }
+        self.rotate().await.map(Some)
+    }
+
+    /// Drop rotated-out keys whose grace period ended
+    async fn prune(&self) -> Result<(), Error> {
+        let mut keys = self.keys.read().unwrap().clone();
+        let len = keys.len();
+        retain_verifiable(&mut keys, self.config.grace_period, Utc::now());
+        if keys.len() == len {
+            return Ok(());
+        }
+
+        if let Some(store) = &self.store {
+            store.save(&keys).await?;
+        }
+        *self.keys.write().unwrap() = keys;
+        Ok(())
+    }
+
+    /// Check for due rotations every `check_interval` in the background
+    pub fn spawn_rotation(self: Arc<Self>, check_interval: std::time::Duration) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(check_interval);
+            loop {
+                interval.tick().await;
+                if let Err(e) = self.rotate_if_due().await {
+                    warn!("Failed to rotate JWT signing key: {}", e);
+                }
+            }
+        })
+    }
+}
+
+fn retain_verifiable(keys: &mut Vec<JwtSigningKey>, grace_period: Duration, now: DateTime<Utc>) {
+    keys.retain(|key| match key.retired_at {
+        Some(retired_at) => retired_at + grace_period > now,
+        None => true,
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Serialize, Deserialize)]
+    struct Claims {
+        sub: String,
+        exp: u64,
+    }
+
+    fn claims() -> Claims {
+        Claims {
+            sub: "NWallet".to_string(),
+            exp: Utc::now().timestamp() as u64 + 3600,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_rotation_keeps_old_tokens_valid() {
+        let ring = JwtKeyRing::new(JwtKeyRingConfig::default(), "secret");
+        let old_token = ring.sign(&claims()).unwrap();
+
+        let kid = ring.rotate().await.unwrap();
+        assert_eq!(ring.active_kid(), kid);
+
+        let old = ring.verify::<Claims>(&old_token).unwrap();
+        assert_eq!(old.kid, LEGACY_KID);
+        assert!(old.stale);
+        assert!(ring.needs_reissue(&old, old.claims.exp));
+
+        let new = ring
+            .verify::<Claims>(&ring.sign(&claims()).unwrap())
+            .unwrap();
+        assert_eq!(new.kid, kid);
+        assert!(!new.stale);
+        assert!(!ring.needs_reissue(&new, new.claims.exp));
+    }
+
+    #[tokio::test]
+    async fn test_rotation_drops_keys_after_grace_period() {
+        let config = JwtKeyRingConfig {
+            grace_period: Duration::zero(),
+            ..JwtKeyRingConfig::default()
+        };
+        let ring = JwtKeyRing::new(config, "secret");
+        let old_token = ring.sign(&claims()).unwrap();
+
+        ring.rotate().await.unwrap();
+        assert!(matches!(
+            ring.verify::<Claims>(&old_token),
+            Err(Error::Authentication(_))
+        ));
+        assert_eq!(ring.keys.read().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_legacy_tokens_without_kid() {
+        let token = encode(
+            &Header::default(),
+            &claims(),
+            &EncodingKey::from_secret(b"secret"),
+        )
+        .unwrap();
+
+        let ring = JwtKeyRing::new(JwtKeyRingConfig::default(), "secret");
+        let verified = ring.verify::<Claims>(&token).unwrap();
+        assert_eq!(verified.kid, LEGACY_KID);
+        assert!(!verified.stale);
+    }
+
+    #[tokio::test]
+    async fn test_rotate_if_due() {
+        let ring = JwtKeyRing::new(JwtKeyRingConfig::default(), "secret");
+        assert_eq!(ring.rotate_if_due().await.unwrap(), None);
+
+        let config = JwtKeyRingConfig {
+            rotation_interval: Duration::zero(),
+            ..JwtKeyRingConfig::default()
+        };
+        let ring = JwtKeyRing::new(config, "secret");
+        assert!(ring.rotate_if_due().await.unwrap().is_some());
+        assert_ne!(ring.active_kid(), LEGACY_KID);
+    }
+}
@@ -5,15 +5,17 @@ use chrono::{DateTime, Duration, Utc};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::sync::{Arc, RwLock};
+use utoipa::ToSchema;
 use uuid::Uuid;
 use tracing::{debug, error, info, warn};
 
 use crate::error::Error;
 use r3e_secrets::{SecretEncryption, SecretError};
+use r3e_secrets::hashicorp::VaultFunctionKeys;
 use r3e_secrets::service::SecretService;
 
 /// API key with metadata
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct ApiKey {
     /// Key ID
     pub id: String,
@@ -47,6 +49,9 @@ pub struct KeyRotationService {
     
     /// Default rotation period in days (when to trigger rotation)
     default_rotation_days: i64,
+    
+    /// Function keys the API keys are encrypted with
+    function_keys: Option<Arc<VaultFunctionKeys>>,
 }
 
 impl KeyRotationService {
@@ -57,6 +62,24 @@ impl KeyRotationService {
             api_keys: Arc::new(RwLock::new(HashMap::new())),
             default_expiration_days: 30,
             default_rotation_days: 15,
+            function_keys: None,
+        }
+    }
+    
+    /// Use the function keys stored in Vault to encrypt the API keys, rather than a fresh key
+    pub fn with_function_keys(mut self, function_keys: Arc<VaultFunctionKeys>) -> Self {
+        self.function_keys = Some(function_keys);
+        self
+    }
+    
+    /// Key the API keys of a user are encrypted with
+    async fn function_key(&self, user_id: &str) -> Result<[u8; 32], Error> {
+        match &self.function_keys {
+            Some(function_keys) => function_keys
+                .function_key(user_id, "api_keys")
+                .await
+                .map_err(|e| Error::Internal(format!("Failed to get function key: {}", e))),
+            None => Ok(SecretEncryption::generate_function_key()),
         }
     }
     
@@ -76,8 +99,8 @@ impl KeyRotationService {
         let key_id = Uuid::new_v4().to_string();
         let key_value = Uuid::new_v4().to_string();
         
-        // Generate a function key for encryption
-        let function_key = SecretEncryption::generate_function_key();
+        // Get the function key for encryption
+        let function_key = self.function_key(user_id).await?;
         
         // Store the key in the secret service
         self.secret_service
@@ -133,8 +156,8 @@ impl KeyRotationService {
         let new_key_id = Uuid::new_v4().to_string();
         let new_key_value = Uuid::new_v4().to_string();
         
-        // Generate a function key for encryption
-        let function_key = SecretEncryption::generate_function_key();
+        // Get the function key for encryption
+        let function_key = self.function_key(user_id).await?;
         
         // Store the new key in the secret service
         self.secret_service
@@ -199,8 +222,8 @@ impl KeyRotationService {
             return Ok(false);
         }
         
-        // Generate a function key for encryption (this would normally be retrieved from a secure store)
-        let function_key = SecretEncryption::generate_function_key();
+        // Get the function key the key was encrypted with
+        let function_key = self.function_key(user_id).await?;
         
         // Get the stored key value
         let stored_value = match self.secret_service
@@ -1,18 +1,22 @@
 // Copyright @ 2023 - 2024, R3E Network
 // All Rights Reserved
 
+pub mod challenges;
+pub mod jwt_keys;
 pub mod key_rotation;
 
 use crate::error::Error;
 use crate::types::BlockchainType;
 use chrono::Utc;
-use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 use tracing::{debug, error, info, warn};
 
+use r3e_secrets::hashicorp::VaultFunctionKeys;
 use r3e_secrets::service::SecretService;
 
+use jwt_keys::JwtKeyRing;
+
 /// JWT claims
 #[derive(Debug, Serialize, Deserialize)]
 pub struct JwtClaims {
@@ -34,23 +38,44 @@ pub struct JwtClaims {
 
 /// Authentication service
 pub struct AuthService {
-    /// JWT secret
-    jwt_secret: String,
+    /// JWT signing key ring
+    jwt_keys: Arc<JwtKeyRing>,
 
     /// JWT expiration in seconds
     jwt_expiration: u64,
 
     /// Secret service for API keys
     secret_service: Arc<dyn SecretService>,
+
+    /// Function keys the API keys are encrypted with
+    function_keys: Option<Arc<VaultFunctionKeys>>,
 }
 
 impl AuthService {
     /// Create a new authentication service
-    pub fn new(jwt_secret: String, jwt_expiration: u64, secret_service: Arc<dyn SecretService>) -> Self {
+    pub fn new(jwt_keys: Arc<JwtKeyRing>, jwt_expiration: u64, secret_service: Arc<dyn SecretService>) -> Self {
         Self {
-            jwt_secret,
+            jwt_keys,
             jwt_expiration,
             secret_service,
+            function_keys: None,
+        }
+    }
+
+    /// Use the function keys stored in Vault to encrypt the API keys, rather than a fresh key
+    pub fn with_function_keys(mut self, function_keys: Arc<VaultFunctionKeys>) -> Self {
+        self.function_keys = Some(function_keys);
+        self
+    }
+
+    /// Key the API keys of a user are encrypted with
+    async fn function_key(&self, user_id: &str) -> Result<[u8; 32], Error> {
+        match &self.function_keys {
+            Some(function_keys) => function_keys
+                .function_key(user_id, "api_keys")
+                .await
+                .map_err(|e| Error::Internal(format!("Failed to get function key: {}", e))),
+            None => Ok(r3e_secrets::SecretEncryption::generate_function_key()),
         }
     }
 
@@ -71,26 +96,16 @@ impl AuthService {
             exp: now + self.jwt_expiration,
         };
 
-        // Create JWT token
-        encode(
-            &Header::default(),
-            &claims,
-            &EncodingKey::from_secret(self.jwt_secret.as_bytes()),
-        )
-        .map_err(|e| Error::Internal(format!("Failed to create JWT token: {}", e)))
+        // Create JWT token, signed by the active key
+        self.jwt_keys.sign(&claims)
     }
 
     /// Verify JWT token
     pub fn verify_jwt_token(&self, token: &str) -> Result<JwtClaims, Error> {
-        // Decode JWT token
-        let token_data = decode::<JwtClaims>(
-            token,
-            &DecodingKey::from_secret(self.jwt_secret.as_bytes()),
-            &Validation::default(),
-        )
-        .map_err(|e| Error::Authentication(format!("Invalid JWT token: {}", e)))?;
-
-        Ok(token_data.claims)
+        // Decode JWT token with the key it was signed by
+        let token = self.jwt_keys.verify::<JwtClaims>(token)?;
+
+        Ok(token.claims)
     }
 
     /// Create API key
@@ -99,8 +114,8 @@ impl AuthService {
         let key_id = uuid::Uuid::new_v4().to_string();
         let key_value = uuid::Uuid::new_v4().to_string();
 
-        // Generate a function key for encryption
-        let function_key = r3e_secrets::SecretEncryption::generate_function_key();
+        // Get the function key for encryption
+        let function_key = self.function_key(user_id).await?;
 
         // Store the key in the secret service
         self.secret_service
@@ -121,8 +136,8 @@ impl AuthService {
 
     /// Validate API key
     pub async fn validate_api_key(&self, key_id: &str, key_value: &str, user_id: &str) -> Result<bool, Error> {
-        // Generate a function key for encryption (this would normally be retrieved from a secure store)
-        let function_key = r3e_secrets::SecretEncryption::generate_function_key();
+        // Get the function key the key was encrypted with
+        let function_key = self.function_key(user_id).await?;
 
         // Get the stored key value
         let stored_value = match self.secret_service
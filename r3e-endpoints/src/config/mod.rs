@@ -1,6 +1,9 @@
 // Copyright @ 2023 - 2024, R3E Network
 // All Rights Reserved
 
+use r3e_neo_services::signer::SignerConfig;
+use r3e_secrets::aws_kms::AwsKmsConfig;
+use r3e_secrets::hashicorp::VaultConfig;
 use serde::{Deserialize, Serialize};
 use std::env;
 
@@ -21,14 +24,69 @@ pub struct Config {
     /// JWT expiration (in seconds)
     pub jwt_expiration: u64,
 
+    /// JWT signing key rotation interval (in seconds), 0 disables rotation
+    pub jwt_rotation_interval: u64,
+
+    /// How long a rotated-out JWT signing key still verifies tokens (in seconds)
+    pub jwt_key_grace_period: u64,
+
+    /// Whether tokens signed by a rotated-out key are automatically re-issued
+    pub jwt_reissue: bool,
+
+    /// Tokens expiring within this window are automatically re-issued (in seconds)
+    pub jwt_reissue_before: u64,
+
+    /// Hex encoded key encrypting the persisted JWT key ring
+    pub secrets_master_key: Option<String>,
+
+    /// Where secrets are stored
+    pub secrets_backend: SecretsBackend,
+
+    /// HashiCorp Vault storing secrets and function keys, and holding tenant KEKs
+    pub vault: Option<VaultConfig>,
+
+    /// AWS KMS holding tenant KEKs
+    pub aws_kms: Option<AwsKmsConfig>,
+
     /// Neo N3 RPC URL
     pub neo_rpc_url: String,
 
     /// Ethereum RPC URL
     pub eth_rpc_url: String,
 
-    /// Relayer wallet private key
-    pub relayer_private_key: String,
+    /// Relayer signer
+    pub relayer_signer: SignerConfig,
+
+    /// How long idempotency keys and their responses are kept (in seconds)
+    pub idempotency_ttl: u64,
+
+    /// How long a wallet login challenge can be redeemed (in seconds)
+    pub wallet_challenge_ttl: u64,
+
+    /// Failed wallet logins a client may make for an address within the failure window
+    pub wallet_max_failed_attempts: u32,
+
+    /// Window failed wallet logins are counted over (in seconds)
+    pub wallet_failure_window: u64,
+
+    /// Number of MPC parties sharing threshold keys
+    pub mpc_parties: u16,
+
+    /// Secret the MPC parties seal their key shares with; shares are kept in memory if unset
+    pub mpc_seal_secret: Option<String>,
+
+    /// Hex encoded P-256 public keys approving admin operations offline
+    pub admin_approver_keys: Vec<String>,
+
+    /// Time an admin operation can be approved in (in seconds)
+    pub operation_ttl: u64,
+
+    /// Path of a JSON file with the relayer fee models per service, a 1% fee without one
+    pub fee_schedule_path: Option<String>,
+
+    /// Path of the JSON file with the Ethereum forwarders per network, written by
+    /// `r3e-faas forwarder deploy`
+    pub forwarders_path: Option<String>,
 }
 
 impl Config {
@@ -57,6 +115,58 @@ impl Config {
             .parse::<u64>()
             .map_err(|e| Error::Configuration(format!("Invalid JWT expiration: {}", e)))?;
 
+        // Get the JWT signing key rotation interval
+        let jwt_rotation_interval = env::var("JWT_ROTATION_INTERVAL")
+            .unwrap_or_else(|_| "604800".to_string())
+            .parse::<u64>()
+            .map_err(|e| Error::Configuration(format!("Invalid JWT rotation interval: {}", e)))?;
+
+        // Get the JWT signing key grace period, which defaults to the token lifetime
+        let jwt_key_grace_period = match env::var("JWT_KEY_GRACE_PERIOD") {
+            Ok(value) => value.parse::<u64>().map_err(|e| {
+                Error::Configuration(format!("Invalid JWT key grace period: {}", e))
+            })?,
+            Err(_) => jwt_expiration,
+        };
+        if jwt_key_grace_period < jwt_expiration {
+            return Err(Error::Configuration(
+                "JWT_KEY_GRACE_PERIOD must not be shorter than JWT_EXPIRATION".to_string(),
+            ));
+        }
+
+        // Get the JWT re-issuance settings
+        let jwt_reissue = env::var("JWT_REISSUE")
+            .unwrap_or_else(|_| "true".to_string())
+            .parse::<bool>()
+            .map_err(|e| Error::Configuration(format!("Invalid JWT reissue flag: {}", e)))?;
+
+        let jwt_reissue_before = env::var("JWT_REISSUE_BEFORE")
+            .unwrap_or_else(|_| "0".to_string())
+            .parse::<u64>()
+            .map_err(|e| Error::Configuration(format!("Invalid JWT reissue window: {}", e)))?;
+
+        // Get the secrets master key
+        let secrets_master_key = env::var("SECRETS_MASTER_KEY").ok();
+
+        // Get the secrets backend and the external key stores
+        let vault = r3e_api::config::vault_from_env();
+        let secrets_backend = match env::var("SECRETS_BACKEND").as_deref() {
+            Ok("rocksdb") | Err(_) => SecretsBackend::RocksDb,
+            Ok("vault") if vault.is_some() => SecretsBackend::Vault,
+            Ok("vault") => {
+                return Err(Error::Configuration(
+                    "SECRETS_BACKEND=vault requires VAULT_ADDR and VAULT_TOKEN".to_string(),
+                ))
+            }
+            Ok(other) => {
+                return Err(Error::Configuration(format!(
+                    "Invalid secrets backend: {}",
+                    other
+                )))
+            }
+        };
+        let aws_kms = r3e_api::config::aws_kms_from_env();
+
         // Get the Neo N3 RPC URL
         let neo_rpc_url =
             env::var("NEO_RPC_URL").unwrap_or_else(|_| "https://rpc.neo.org:443".to_string());
@@ -65,18 +175,141 @@ impl Config {
         let eth_rpc_url = env::var("ETH_RPC_URL")
             .unwrap_or_else(|_| "https://mainnet.infura.io/v3/your-api-key".to_string());
 
-        // Get the relayer wallet private key
-        let relayer_private_key = env::var("RELAYER_PRIVATE_KEY")
-            .map_err(|_| Error::Configuration("RELAYER_PRIVATE_KEY is not set".to_string()))?;
+        // Get the relayer signer
+        let relayer_signer = relayer_signer_from_env()?;
+
+        // Get the idempotency key lifetime
+        let idempotency_ttl = env::var("IDEMPOTENCY_TTL")
+            .unwrap_or_else(|_| "86400".to_string())
+            .parse::<u64>()
+            .map_err(|e| Error::Configuration(format!("Invalid idempotency TTL: {}", e)))?;
+
+        // Get the wallet login settings
+        let wallet_challenge_ttl = env::var("WALLET_CHALLENGE_TTL")
+            .unwrap_or_else(|_| "300".to_string())
+            .parse::<u64>()
+            .map_err(|e| Error::Configuration(format!("Invalid wallet challenge TTL: {}", e)))?;
+
+        let wallet_max_failed_attempts = env::var("WALLET_MAX_FAILED_ATTEMPTS")
+            .unwrap_or_else(|_| "5".to_string())
+            .parse::<u32>()
+            .map_err(|e| {
+                Error::Configuration(format!("Invalid wallet max failed attempts: {}", e))
+            })?;
+
+        let wallet_failure_window = env::var("WALLET_FAILURE_WINDOW")
+            .unwrap_or_else(|_| "900".to_string())
+            .parse::<u64>()
+            .map_err(|e| Error::Configuration(format!("Invalid wallet failure window: {}", e)))?;
+
+        // Get the MPC settings
+        let mpc_parties = env::var("MPC_PARTIES")
+            .unwrap_or_else(|_| "3".to_string())
+            .parse::<u16>()
+            .map_err(|e| Error::Configuration(format!("Invalid MPC party count: {}", e)))?;
+        let mpc_seal_secret = env::var("MPC_SEAL_SECRET").ok();
+
+        // Get the admin operation approval settings
+        let admin_approver_keys = env::var("ADMIN_APPROVER_KEYS")
+            .unwrap_or_default()
+            .split(',')
+            .map(|key| key.trim().to_string())
+            .filter(|key| !key.is_empty())
+            .collect();
+        let operation_ttl = env::var("OPERATION_TTL")
+            .unwrap_or_else(|_| "86400".to_string())
+            .parse::<u64>()
+            .map_err(|e| Error::Configuration(format!("Invalid operation TTL: {}", e)))?;
+
+        // Get the relayer fee schedule
+        let fee_schedule_path = env::var("FEE_SCHEDULE_PATH").ok();
+
+        // Get the Ethereum forwarders
+        let forwarders_path = env::var("FORWARDERS_PATH").ok();
 
         Ok(Self {
             port,
             database_url,
             jwt_secret,
             jwt_expiration,
+            jwt_rotation_interval,
+            jwt_key_grace_period,
+            jwt_reissue,
+            jwt_reissue_before,
+            secrets_master_key,
+            secrets_backend,
+            vault,
+            aws_kms,
             neo_rpc_url,
             eth_rpc_url,
-            relayer_private_key,
+            relayer_signer,
+            idempotency_ttl,
+            wallet_challenge_ttl,
+            wallet_max_failed_attempts,
+            wallet_failure_window,
+            mpc_parties,
+            mpc_seal_secret,
+            admin_approver_keys,
+            operation_ttl,
+            fee_schedule_path,
+            forwarders_path,
         })
     }
 }
+
+/// Store of the secrets, selected by `SECRETS_BACKEND`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SecretsBackend {
+    /// Local RocksDB database, the default
+    RocksDb,
+
+    /// KV v2 engine of HashiCorp Vault, with function keys stored wrapped alongside
+    Vault,
+}
+
+/// Relayer signer selected by `RELAYER_SIGNER`, a local private key by default
+fn relayer_signer_from_env() -> Result<SignerConfig, Error> {
+    let signer = env::var("RELAYER_SIGNER").unwrap_or_else(|_| "local".to_string());
+    let signer = match signer.as_str() {
+        "local" => SignerConfig::Local {
+            private_key: required_env("RELAYER_PRIVATE_KEY")?,
+        },
+        "tee" => SignerConfig::Tee {
+            key_id: required_env("RELAYER_TEE_KEY_ID")?,
+            public_key_id: required_env("RELAYER_TEE_PUBLIC_KEY_ID")?,
+        },
+        "remote" => SignerConfig::Remote {
+            url: required_env("RELAYER_SIGNER_URL")?,
+            key_id: required_env("RELAYER_SIGNER_KEY_ID")?,
+            auth_token: env::var("RELAYER_SIGNER_TOKEN").ok(),
+            timeout_ms: env::var("RELAYER_SIGNER_TIMEOUT_MS")
+                .unwrap_or_else(|_| "5000".to_string())
+                .parse::<u64>()
+                .map_err(|e| {
+                    Error::Configuration(format!("Invalid relayer signer timeout: {}", e))
+                })?,
+        },
+        "pkcs11" => SignerConfig::Pkcs11 {
+            module_path: required_env("RELAYER_PKCS11_MODULE")?,
+            token_label: required_env("RELAYER_PKCS11_TOKEN")?,
+            pin: required_env("RELAYER_PKCS11_PIN")?,
+            key_label: required_env("RELAYER_PKCS11_KEY_LABEL")?,
+        },
+        "mpc" => SignerConfig::Mpc {
+            key_id: required_env("RELAYER_MPC_KEY_ID")?,
+        },
+        other => {
+            return Err(Error::Configuration(format!(
+                "Invalid relayer signer: {}",
+                other
+            )))
+        }
+    };
+
+    Ok(signer)
+}
+
+fn required_env(name: &str) -> Result<String, Error> {
+    env::var(name).map_err(|_| Error::Configuration(format!("{} is not set", name)))
+}
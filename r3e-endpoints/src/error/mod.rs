@@ -3,7 +3,8 @@
 
 use axum::http::StatusCode;
 use axum::response::{IntoResponse, Response};
-use serde::{Deserialize, Serialize};
+use axum::Json;
+use r3e_core::{CodedError, ErrorBody, ErrorCode};
 use thiserror::Error;
 
 /// API error
@@ -33,6 +34,14 @@ pub enum Error {
     #[error("Not found: {0}")]
     NotFound(String),
 
+    /// Conflict error
+    #[error("Conflict: {0}")]
+    Conflict(String),
+
+    /// Rate limit error
+    #[error("Rate limited: {0}")]
+    RateLimited(String),
+
     /// Network error
     #[error("Network error: {0}")]
     Network(String),
@@ -44,46 +53,111 @@ pub enum Error {
     /// Internal error
     #[error("Internal error: {0}")]
     Internal(String),
-}
 
-/// API error response
-#[derive(Debug, Serialize, Deserialize)]
-pub struct ErrorResponse {
-    /// Error message
-    pub message: String,
+    /// Error carrying its own code, e.g. one propagated from a service
+    #[error("{}", .0.message)]
+    Coded(ErrorBody),
+}
 
-    /// Error code
-    pub code: String,
+/// API error response, `{code, message, details}`
+pub type ErrorResponse = ErrorBody;
+
+impl CodedError for Error {
+    fn code(&self) -> ErrorCode {
+        match self {
+            Error::Configuration(_) => ErrorCode::Configuration,
+            Error::Database(_) => ErrorCode::Storage,
+            Error::Authentication(_) => ErrorCode::Unauthenticated,
+            Error::Authorization(_) => ErrorCode::Forbidden,
+            Error::Validation(_) => ErrorCode::Validation,
+            Error::NotFound(_) => ErrorCode::NotFound,
+            Error::Conflict(_) => ErrorCode::Conflict,
+            Error::RateLimited(_) => ErrorCode::RateLimited,
+            Error::Network(_) => ErrorCode::Upstream,
+            Error::Blockchain(_) => ErrorCode::Blockchain,
+            Error::Internal(_) => ErrorCode::Internal,
+            Error::Coded(body) => body.code,
+        }
+    }
 
-    /// Error details
-    pub details: Option<serde_json::Value>,
+    fn to_body(&self) -> ErrorBody {
+        match self {
+            Error::Coded(body) => body.clone(),
+            Error::Configuration(message)
+            | Error::Database(message)
+            | Error::Authentication(message)
+            | Error::Authorization(message)
+            | Error::Validation(message)
+            | Error::NotFound(message)
+            | Error::Conflict(message)
+            | Error::RateLimited(message)
+            | Error::Network(message)
+            | Error::Blockchain(message)
+            | Error::Internal(message) => ErrorBody::new(self.code(), message.clone()),
+        }
+    }
 }
 
 impl IntoResponse for Error {
     fn into_response(self) -> Response {
-        let (status, code) = match self {
-            Error::Configuration(_) => (StatusCode::INTERNAL_SERVER_ERROR, "CONFIGURATION_ERROR"),
-            Error::Database(_) => (StatusCode::INTERNAL_SERVER_ERROR, "DATABASE_ERROR"),
-            Error::Authentication(_) => (StatusCode::UNAUTHORIZED, "AUTHENTICATION_ERROR"),
-            Error::Authorization(_) => (StatusCode::FORBIDDEN, "AUTHORIZATION_ERROR"),
-            Error::Validation(_) => (StatusCode::BAD_REQUEST, "VALIDATION_ERROR"),
-            Error::NotFound(_) => (StatusCode::NOT_FOUND, "NOT_FOUND"),
-            Error::Network(_) => (StatusCode::BAD_GATEWAY, "NETWORK_ERROR"),
-            Error::Blockchain(_) => (StatusCode::BAD_GATEWAY, "BLOCKCHAIN_ERROR"),
-            Error::Internal(_) => (StatusCode::INTERNAL_SERVER_ERROR, "INTERNAL_ERROR"),
-        };
-
-        let body = ErrorResponse {
-            message: self.to_string(),
-            code: code.to_string(),
-            details: None,
-        };
-
-        let body = serde_json::to_string(&body).unwrap_or_else(|_| {
-            r#"{"message":"Failed to serialize error response","code":"INTERNAL_ERROR"}"#
-                .to_string()
-        });
-
-        (status, body).into_response()
+        let body = self.to_body();
+        let status = StatusCode::from_u16(body.code.http_status())
+            .unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+
+        (status, Json(body)).into_response()
+    }
+}
+
+impl From<ErrorBody> for Error {
+    fn from(body: ErrorBody) -> Self {
+        Error::Coded(body)
+    }
+}
+
+impl From<r3e_secrets::approval::ApprovalError> for Error {
+    fn from(err: r3e_secrets::approval::ApprovalError) -> Self {
+        use r3e_secrets::approval::ApprovalError;
+
+        match err {
+            ApprovalError::NotFound(msg) => Error::NotFound(msg),
+            ApprovalError::UnknownKind(_) | ApprovalError::InvalidParams(_) => {
+                Error::Validation(err.to_string())
+            }
+            ApprovalError::InvalidState(..) | ApprovalError::Expired(_) => {
+                Error::Conflict(err.to_string())
+            }
+            ApprovalError::InvalidSignature(_) => Error::Authorization(err.to_string()),
+            ApprovalError::InvalidKey(_) | ApprovalError::Storage(_) | ApprovalError::Audit(_) => {
+                Error::Internal(err.to_string())
+            }
+        }
+    }
+}
+
+impl From<r3e_core::chain::ChainError> for Error {
+    fn from(err: r3e_core::chain::ChainError) -> Self {
+        Error::Coded(err.to_body())
+    }
+}
+
+impl From<r3e_api::error::ApiError> for Error {
+    fn from(err: r3e_api::error::ApiError) -> Self {
+        use r3e_api::error::ApiError;
+
+        match err {
+            ApiError::Authentication(msg) => Error::Authentication(msg),
+            ApiError::Authorization(msg) => Error::Authorization(msg),
+            ApiError::Validation(msg) => Error::Validation(msg),
+            ApiError::NotFound(msg) => Error::NotFound(msg),
+            ApiError::Conflict(msg) => Error::Conflict(msg),
+            ApiError::Database(msg) => Error::Database(msg),
+            ApiError::ExternalService(msg) => Error::Network(msg),
+            ApiError::Service(msg) | ApiError::Server(msg) => Error::Internal(msg),
+            // Keep the codes the endpoints have no variant for
+            ApiError::PreconditionFailed(_)
+            | ApiError::QuotaExceeded(_)
+            | ApiError::PaymentRequired(_)
+            | ApiError::Coded(_) => Error::Coded(err.to_body()),
+        }
     }
 }
@@ -1,8 +1,10 @@
 // Copyright @ 2023 - 2024, R3E Network
 // All Rights Reserved
 
+pub mod auth;
 pub mod config;
 pub mod error;
+pub mod middleware;
 pub mod routes;
 pub mod service;
 pub mod types;
@@ -0,0 +1,160 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Instant;
+
+use axum::http::{header, HeaderValue, Request, Response, StatusCode};
+use futures::future::BoxFuture;
+use r3e_secrets::audit::{AuditContext, AuditEvent, AuditEventType, AuditStore};
+use tower::{Layer, Service};
+use tracing::{warn, Instrument};
+use uuid::Uuid;
+
+use crate::auth::jwt_keys::JwtKeyRing;
+
+/// Request ID header, taken from the request or generated, and echoed in the response
+pub const REQUEST_ID_HEADER: &str = "X-Request-Id";
+
+/// Audit layer, appending every handled request to the hash-chained audit log
+///
+/// Handlers run within the request's [`AuditContext`], so audit events they record carry
+/// the request ID, source IP and user agent.
+#[derive(Clone)]
+pub struct AuditLayer {
+    /// Audit store
+    audit_store: Arc<dyn AuditStore>,
+
+    /// JWT signing key ring, identifying the caller
+    jwt_keys: Arc<JwtKeyRing>,
+}
+
+impl AuditLayer {
+    /// Create a new audit layer
+    pub fn new(audit_store: Arc<dyn AuditStore>, jwt_keys: Arc<JwtKeyRing>) -> Self {
+        Self {
+            audit_store,
+            jwt_keys,
+        }
+    }
+}
+
+impl<S> Layer<S> for AuditLayer {
+    type Service = AuditMiddleware<S>;
+
+    fn layer(&self, service: S) -> Self::Service {
+        AuditMiddleware {
+            inner: service,
+            layer: self.clone(),
+        }
+    }
+}
+
+/// Audit middleware
+#[derive(Clone)]
+pub struct AuditMiddleware<S> {
+    /// Inner service
+    inner: S,
+
+    /// Audit settings
+    layer: AuditLayer,
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for AuditMiddleware<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+    ReqBody: Send + 'static,
+    ResBody: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<ReqBody>) -> Self::Future {
+        let mut inner = self.inner.clone();
+        let audit_store = self.layer.audit_store.clone();
+
+        // Only a verified token identifies the caller, identity headers can be forged
+        let user_id = request
+            .headers()
+            .get(header::AUTHORIZATION)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("Bearer "))
+            .and_then(|token| self.layer.jwt_keys.verify::<serde_json::Value>(token).ok())
+            .and_then(|verified| verified.claims.get("sub")?.as_str().map(str::to_string));
+        let request_id = header_value(&request, REQUEST_ID_HEADER)
+            .filter(|id| !id.is_empty() && id.len() <= 128)
+            .unwrap_or_else(|| Uuid::new_v4().to_string());
+        let context = AuditContext {
+            request_id: Some(request_id.clone()),
+            user_id: user_id.clone(),
+            source_ip: header_value(&request, "X-Forwarded-For")
+                .and_then(|v| v.split(',').next().map(|ip| ip.trim().to_string())),
+            user_agent: header_value(&request, header::USER_AGENT.as_str()),
+        };
+        let user_id = user_id.unwrap_or_else(|| "anonymous".to_string());
+        let method = request.method().clone();
+        let path = request.uri().path().to_string();
+        let span = tracing::info_span!("request", request_id = %request_id);
+
+        let start = Instant::now();
+
+        let future = async move {
+            let mut result = inner.call(request).await;
+            if let (Ok(response), Ok(value)) = (&mut result, HeaderValue::from_str(&request_id)) {
+                response.headers_mut().insert(REQUEST_ID_HEADER, value);
+            }
+
+            let status = match &result {
+                Ok(response) => response.status(),
+                Err(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            };
+            let event_type = match status {
+                StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => {
+                    AuditEventType::UnauthorizedAccess
+                }
+                _ => AuditEventType::ApiRequest,
+            };
+            let event = AuditEvent::new(
+                event_type,
+                user_id,
+                None,
+                None,
+                format!(
+                    "{} {} -> {} in {}ms",
+                    method,
+                    path,
+                    status.as_u16(),
+                    start.elapsed().as_millis()
+                ),
+                None,
+                None,
+            );
+
+            if let Err(err) = audit_store.append(event).await {
+                warn!(
+                    "Failed to append audit event for {} {}: {}",
+                    method, path, err
+                );
+            }
+
+            result
+        };
+
+        Box::pin(context.scope(future).instrument(span))
+    }
+}
+
+fn header_value<B>(request: &Request<B>, name: &str) -> Option<String> {
+    request
+        .headers()
+        .get(name)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.to_string())
+}
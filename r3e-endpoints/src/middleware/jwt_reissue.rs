@@ -0,0 +1,131 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use axum::http::{header, HeaderValue, Request, Response};
+use chrono::Utc;
+use futures::future::BoxFuture;
+use tower::{Layer, Service};
+use tracing::{debug, warn};
+
+use crate::auth::jwt_keys::JwtKeyRing;
+
+/// Response header carrying a re-issued JWT token
+pub const REFRESHED_TOKEN_HEADER: &str = "X-Refreshed-Token";
+
+/// JWT re-issuance layer.
+///
+/// Bearer tokens signed by a rotated-out key, or close to expiry, get a fresh token signed
+/// by the active key in the `X-Refreshed-Token` response header.
+#[derive(Clone)]
+pub struct JwtReissueLayer {
+    /// JWT signing key ring
+    jwt_keys: Arc<JwtKeyRing>,
+
+    /// JWT expiration in seconds
+    jwt_expiration: u64,
+}
+
+impl JwtReissueLayer {
+    /// Create a new JWT re-issuance layer
+    pub fn new(jwt_keys: Arc<JwtKeyRing>, jwt_expiration: u64) -> Self {
+        Self {
+            jwt_keys,
+            jwt_expiration,
+        }
+    }
+
+    /// Fresh token with the claims of `token`, if it needs re-issuing
+    fn reissue(&self, token: &str) -> Option<String> {
+        let verified = self.jwt_keys.verify::<serde_json::Value>(token).ok()?;
+        let exp = verified.claims.get("exp")?.as_u64()?;
+        if !self.jwt_keys.needs_reissue(&verified, exp) {
+            return None;
+        }
+
+        let now = Utc::now().timestamp() as u64;
+        let mut claims = verified.claims;
+        claims["iat"] = now.into();
+        claims["exp"] = (now + self.jwt_expiration).into();
+
+        match self.jwt_keys.sign(&claims) {
+            Ok(token) => {
+                debug!(
+                    "Re-issued JWT token signed by {} with {}",
+                    verified.kid,
+                    self.jwt_keys.active_kid()
+                );
+                Some(token)
+            }
+            Err(err) => {
+                warn!("Failed to re-issue JWT token: {}", err);
+                None
+            }
+        }
+    }
+}
+
+impl<S> Layer<S> for JwtReissueLayer {
+    type Service = JwtReissueMiddleware<S>;
+
+    fn layer(&self, service: S) -> Self::Service {
+        JwtReissueMiddleware {
+            inner: service,
+            layer: self.clone(),
+        }
+    }
+}
+
+/// JWT re-issuance middleware
+#[derive(Clone)]
+pub struct JwtReissueMiddleware<S> {
+    /// Inner service
+    inner: S,
+
+    /// Re-issuance settings
+    layer: JwtReissueLayer,
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for JwtReissueMiddleware<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+    ReqBody: Send + 'static,
+    ResBody: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<ReqBody>) -> Self::Future {
+        let mut inner = self.inner.clone();
+
+        // Re-issue the bearer token before the handler runs, the key ring may rotate meanwhile
+        let refreshed = request
+            .headers()
+            .get(header::AUTHORIZATION)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("Bearer "))
+            .and_then(|token| self.layer.reissue(token));
+
+        Box::pin(async move {
+            let mut result = inner.call(request).await;
+
+            if let (Some(token), Ok(response)) = (refreshed, result.as_mut()) {
+                if response.status().is_success() {
+                    if let Ok(value) = HeaderValue::from_str(&token) {
+                        response.headers_mut().insert(REFRESHED_TOKEN_HEADER, value);
+                    }
+                }
+            }
+
+            result
+        })
+    }
+}
@@ -2,12 +2,14 @@
 // All Rights Reserved
 
 pub mod audit;
+pub mod jwt_reissue;
 pub mod key_rotation;
 pub mod rate_limit;
 pub mod security_headers;
 pub mod validation;
 
 pub use audit::AuditLayer;
+pub use jwt_reissue::JwtReissueLayer;
 pub use key_rotation::KeyRotationLayer;
 pub use rate_limit::RateLimitLayer;
 pub use security_headers::SecurityHeadersLayer;
@@ -0,0 +1,149 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+use std::sync::Arc;
+
+use axum::{
+    extract::{Json, State},
+    http::{header, HeaderMap},
+};
+use r3e_neo_services::wallet::{
+    ManagedAccount, Nep6Wallet, ScryptParams, WalletExportRequest, WalletImportRequest,
+    WalletImportResult, WalletService,
+};
+use serde::Deserialize;
+use utoipa::ToSchema;
+
+use crate::{error::Error, service::EndpointService, utils::verify_jwt_token};
+
+/// Import wallet request
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct ImportWalletRequest {
+    /// NEP-6 wallet file
+    #[schema(value_type = Object)]
+    pub wallet: Nep6Wallet,
+
+    /// Password the account keys are encrypted with
+    pub password: String,
+}
+
+/// Export wallet request
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct ExportWalletRequest {
+    /// Accounts to export, all of yours if not set
+    #[serde(default)]
+    pub addresses: Option<Vec<String>>,
+
+    /// Password to encrypt the account keys with
+    pub password: String,
+
+    /// scrypt parameters of the account keys, those of NEP-2 if not set
+    #[serde(default)]
+    #[schema(value_type = Option<Object>)]
+    pub scrypt: Option<ScryptParams>,
+}
+
+/// Wallet address of the bearer token
+fn owner(service: &EndpointService, headers: &HeaderMap) -> Result<String, Error> {
+    let token = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .ok_or_else(|| Error::Authentication("Bearer token required".to_string()))?;
+
+    Ok(verify_jwt_token(token, &service.jwt_keys)?.sub)
+}
+
+fn wallet_service(service: &EndpointService) -> Result<&WalletService, Error> {
+    service.wallet_service.as_deref().ok_or_else(|| {
+        Error::Configuration(
+            "Managed accounts require SECRETS_MASTER_KEY to be configured".to_string(),
+        )
+    })
+}
+
+fn wallet_error(e: r3e_neo_services::Error) -> Error {
+    use r3e_neo_services::Error as NeoError;
+
+    match e {
+        NeoError::NotFound(msg) => Error::NotFound(msg),
+        NeoError::InvalidParameter(msg) => Error::Validation(msg),
+        e => Error::Internal(format!("Wallet error: {}", e)),
+    }
+}
+
+/// Import wallet handler
+#[utoipa::path(
+    post,
+    path = "/accounts/import",
+    tag = "accounts",
+    request_body = ImportWalletRequest,
+    responses((status = 200, description = "Accounts imported and skipped", body = Object)),
+    security(("bearer" = []))
+)]
+pub async fn import_wallet(
+    State(service): State<Arc<EndpointService>>,
+    headers: HeaderMap,
+    Json(request): Json<ImportWalletRequest>,
+) -> Result<Json<WalletImportResult>, Error> {
+    let owner = owner(&service, &headers)?;
+    let result = wallet_service(&service)?
+        .import(WalletImportRequest {
+            owner,
+            wallet: request.wallet,
+            password: request.password,
+        })
+        .await
+        .map_err(wallet_error)?;
+
+    Ok(Json(result))
+}
+
+/// Export wallet handler
+#[utoipa::path(
+    post,
+    path = "/accounts/export",
+    tag = "accounts",
+    request_body = ExportWalletRequest,
+    responses((status = 200, description = "NEP-6 wallet of the accounts", body = Object)),
+    security(("bearer" = []))
+)]
+pub async fn export_wallet(
+    State(service): State<Arc<EndpointService>>,
+    headers: HeaderMap,
+    Json(request): Json<ExportWalletRequest>,
+) -> Result<Json<Nep6Wallet>, Error> {
+    let owner = owner(&service, &headers)?;
+    let wallet = wallet_service(&service)?
+        .export(WalletExportRequest {
+            owner,
+            addresses: request.addresses,
+            password: request.password,
+            scrypt: request.scrypt,
+        })
+        .await
+        .map_err(wallet_error)?;
+
+    Ok(Json(wallet))
+}
+
+/// List accounts handler
+#[utoipa::path(
+    get,
+    path = "/accounts",
+    tag = "accounts",
+    responses((status = 200, description = "Imported accounts, oldest first", body = Vec<Object>)),
+    security(("bearer" = []))
+)]
+pub async fn list_accounts(
+    State(service): State<Arc<EndpointService>>,
+    headers: HeaderMap,
+) -> Result<Json<Vec<ManagedAccount>>, Error> {
+    let owner = owner(&service, &headers)?;
+    let accounts = wallet_service(&service)?
+        .list_accounts(&owner)
+        .await
+        .map_err(wallet_error)?;
+
+    Ok(Json(accounts))
+}
@@ -9,20 +9,21 @@ use axum::{
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 use tracing::{debug, error, info, warn};
+use utoipa::ToSchema;
 
 use crate::auth::key_rotation::{ApiKey, KeyRotationService};
 use crate::error::Error;
 use crate::service::EndpointService;
 
 /// Create API key request
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, ToSchema)]
 pub struct CreateApiKeyRequest {
     /// User ID
     pub user_id: String,
 }
 
 /// Create API key response
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
 pub struct CreateApiKeyResponse {
     /// API key ID
     pub key_id: String,
@@ -35,14 +36,14 @@ pub struct CreateApiKeyResponse {
 }
 
 /// Rotate API key request
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, ToSchema)]
 pub struct RotateApiKeyRequest {
     /// User ID
     pub user_id: String,
 }
 
 /// Rotate API key response
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
 pub struct RotateApiKeyResponse {
     /// New API key ID
     pub key_id: String,
@@ -55,20 +56,27 @@ pub struct RotateApiKeyResponse {
 }
 
 /// Revoke API key request
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, ToSchema)]
 pub struct RevokeApiKeyRequest {
     /// User ID
     pub user_id: String,
 }
 
 /// List API keys response
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
 pub struct ListApiKeysResponse {
     /// API keys
     pub keys: Vec<ApiKey>,
 }
 
 /// Create a new API key
+#[utoipa::path(
+    post,
+    path = "/auth/api-keys",
+    tag = "auth",
+    request_body = CreateApiKeyRequest,
+    responses((status = 200, description = "API key, its value shown only once", body = CreateApiKeyResponse))
+)]
 pub async fn create_api_key(
     State(service): State<Arc<EndpointService>>,
     Json(request): Json<CreateApiKeyRequest>,
@@ -88,6 +96,14 @@ pub async fn create_api_key(
 }
 
 /// Rotate an API key
+#[utoipa::path(
+    post,
+    path = "/auth/api-keys/{key_id}",
+    tag = "auth",
+    params(("key_id" = String, Path, description = "API key ID")),
+    request_body = RotateApiKeyRequest,
+    responses((status = 200, description = "Replacement API key", body = RotateApiKeyResponse))
+)]
 pub async fn rotate_api_key(
     State(service): State<Arc<EndpointService>>,
     Path(key_id): Path<String>,
@@ -110,6 +126,14 @@ pub async fn rotate_api_key(
 }
 
 /// Revoke an API key
+#[utoipa::path(
+    delete,
+    path = "/auth/api-keys/{key_id}",
+    tag = "auth",
+    params(("key_id" = String, Path, description = "API key ID")),
+    request_body = RevokeApiKeyRequest,
+    responses((status = 204, description = "API key revoked"))
+)]
 pub async fn revoke_api_key(
     State(service): State<Arc<EndpointService>>,
     Path(key_id): Path<String>,
@@ -128,6 +152,13 @@ pub async fn revoke_api_key(
 }
 
 /// List API keys for a user
+#[utoipa::path(
+    get,
+    path = "/auth/api-keys/user/{user_id}",
+    tag = "auth",
+    params(("user_id" = String, Path, description = "User ID")),
+    responses((status = 200, description = "API keys of the user", body = ListApiKeysResponse))
+)]
 pub async fn list_api_keys(
     State(service): State<Arc<EndpointService>>,
     Path(user_id): Path<String>,
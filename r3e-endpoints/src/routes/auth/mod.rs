@@ -13,12 +13,13 @@ use axum::{
 };
 use chrono::Utc;
 use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 use uuid::Uuid;
 
 use crate::{error::Error, service::EndpointService, utils::generate_jwt_token};
 
 /// Login request
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct LoginRequest {
     /// Username
     pub username: String,
@@ -28,7 +29,7 @@ pub struct LoginRequest {
 }
 
 /// Login response
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct LoginResponse {
     /// User ID
     pub user_id: String,
@@ -44,7 +45,7 @@ pub struct LoginResponse {
 }
 
 /// Register request
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct RegisterRequest {
     /// Username
     pub username: String,
@@ -57,7 +58,7 @@ pub struct RegisterRequest {
 }
 
 /// Register response
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct RegisterResponse {
     /// User ID
     pub user_id: String,
@@ -73,14 +74,14 @@ pub struct RegisterResponse {
 }
 
 /// Refresh request
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct RefreshRequest {
     /// Token
     pub token: String,
 }
 
 /// Refresh response
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct RefreshResponse {
     /// Token
     pub token: String,
@@ -90,6 +91,13 @@ pub struct RefreshResponse {
 }
 
 /// Login handler
+#[utoipa::path(
+    post,
+    path = "/auth/login",
+    tag = "auth",
+    request_body = LoginRequest,
+    responses((status = 200, description = "Session token", body = LoginResponse))
+)]
 pub async fn login(
     State(service): State<Arc<EndpointService>>,
     Json(request): Json<LoginRequest>,
@@ -133,7 +141,7 @@ pub async fn login(
         &user.id,
         &user.blockchain_type,
         &connection_id,
-        &service.config.jwt_secret,
+        &service.jwt_keys,
         service.config.jwt_expiration,
     )?;
 
@@ -156,6 +164,13 @@ pub async fn login(
 }
 
 /// Register handler
+#[utoipa::path(
+    post,
+    path = "/auth/register",
+    tag = "auth",
+    request_body = RegisterRequest,
+    responses((status = 200, description = "Registered user and session token", body = RegisterResponse))
+)]
 pub async fn register(
     State(service): State<Arc<EndpointService>>,
     Json(request): Json<RegisterRequest>,
@@ -224,7 +239,7 @@ pub async fn register(
         &user_id,
         &blockchain_type,
         &connection_id,
-        &service.config.jwt_secret,
+        &service.jwt_keys,
         service.config.jwt_expiration,
     )?;
 
@@ -247,12 +262,19 @@ pub async fn register(
 }
 
 /// Refresh handler
+#[utoipa::path(
+    post,
+    path = "/auth/refresh",
+    tag = "auth",
+    request_body = RefreshRequest,
+    responses((status = 200, description = "Refreshed token", body = RefreshResponse))
+)]
 pub async fn refresh(
     State(service): State<Arc<EndpointService>>,
     Json(request): Json<RefreshRequest>,
 ) -> Result<Json<RefreshResponse>, Error> {
     // Verify the token
-    let claims = match crate::utils::verify_jwt_token(&request.token, &service.config.jwt_secret) {
+    let claims = match crate::utils::verify_jwt_token(&request.token, &service.jwt_keys) {
         Ok(claims) => claims,
         Err(e) => {
             log::warn!("Token validation failed: {}", e);
@@ -286,7 +308,7 @@ pub async fn refresh(
         &claims.sub,
         &claims.blockchain_type,
         &claims.connection_id,
-        &service.config.jwt_secret,
+        &service.jwt_keys,
         service.config.jwt_expiration,
     )?;
 
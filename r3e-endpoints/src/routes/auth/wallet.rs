@@ -5,7 +5,7 @@ use std::sync::Arc;
 
 use axum::{
     extract::{Json, State},
-    http::StatusCode,
+    http::{header, HeaderMap, StatusCode},
 };
 use chrono::Utc;
 use serde::{Deserialize, Serialize};
@@ -74,9 +74,34 @@ pub struct AuthenticateWalletResponse {
     pub expires_at: u64,
 }
 
+/// Origin of the client making a request, which challenges are bound to
+fn request_origin(headers: &HeaderMap) -> Result<Option<&str>, Error> {
+    headers
+        .get(header::ORIGIN)
+        .map(|origin| {
+            origin
+                .to_str()
+                .map_err(|_| Error::Validation("Invalid Origin header".into()))
+        })
+        .transpose()
+}
+
+/// Client making a request, failed logins are counted per address and client so that one
+/// client's failures do not lock others out of the address
+fn request_client(headers: &HeaderMap) -> &str {
+    headers
+        .get("X-Forwarded-For")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.split(',').next())
+        .map(str::trim)
+        .filter(|ip| !ip.is_empty())
+        .unwrap_or("unknown")
+}
+
 /// Connect wallet handler - Step 1 of wallet authentication
 pub async fn connect_wallet(
     State(service): State<Arc<EndpointService>>,
+    headers: HeaderMap,
     Json(request): Json<ConnectWalletRequest>,
 ) -> Result<Json<ConnectWalletResponse>, Error> {
     // Validate the blockchain type
@@ -93,37 +118,22 @@ pub async fn connect_wallet(
     // Validate the wallet address format based on blockchain type
     validate_address(&blockchain_type, &request.address)?;
 
-    // Generate a random challenge
-    let challenge_id = Uuid::new_v4().to_string();
-    let timestamp = Utc::now().timestamp() as u64;
-
-    // Create a challenge message that includes address and timestamp to prevent replay attacks
-    let challenge = format!(
-        "Sign this message to authenticate with R3E FaaS platform.\n\nWallet: {}\nTimestamp: {}\nNonce: {}",
-        request.address,
-        timestamp,
-        challenge_id
-    );
-
-    // Store the challenge in the database with expiration
-    let expires_at = timestamp + 300; // 5 minutes expiration
-    service
-        .db_client
-        .store_auth_challenge(
-            &challenge_id,
+    // Issue a single-use challenge bound to the address and the origin of the client
+    let challenge = service
+        .challenge_store
+        .issue(
             &request.address,
             &blockchain_type,
-            &challenge,
-            expires_at,
+            request_origin(&headers)?,
+            request_client(&headers),
         )
-        .await
-        .map_err(|e| Error::Internal(format!("Failed to store challenge: {}", e)))?;
+        .await?;
 
     // Return the challenge for the user to sign
     let response = ConnectWalletResponse {
-        challenge,
-        challenge_id,
-        expires_at,
+        challenge: challenge.message,
+        challenge_id: challenge.id,
+        expires_at: challenge.expires_at,
     };
 
     log::info!(
@@ -137,46 +147,21 @@ pub async fn connect_wallet(
 /// Authenticate wallet handler - Step 2 of wallet authentication
 pub async fn authenticate_wallet(
     State(service): State<Arc<EndpointService>>,
+    headers: HeaderMap,
     Json(request): Json<AuthenticateWalletRequest>,
 ) -> Result<Json<AuthenticateWalletResponse>, Error> {
-    // Retrieve the challenge from the database
+    // Redeem the challenge; it is consumed even if the signature turns out to be invalid,
+    // and must have been issued for this address, blockchain and origin
     let challenge = service
-        .db_client
-        .get_auth_challenge(&request.challenge_id)
-        .await
-        .map_err(|e| Error::Internal(format!("Database error: {}", e)))?;
-
-    // Check if challenge exists
-    let challenge = match challenge {
-        Some(c) => c,
-        None => {
-            log::warn!("Challenge not found: {}", request.challenge_id);
-            return Err(Error::Authentication("Invalid or expired challenge".into()));
-        }
-    };
-
-    // Check if challenge has expired
-    let now = Utc::now().timestamp() as u64;
-    if now > challenge.expires_at {
-        log::warn!("Challenge expired: {}", request.challenge_id);
-        return Err(Error::Authentication("Challenge has expired".into()));
-    }
-
-    // Verify that the address and blockchain type match the stored challenge
-    if request.address != challenge.address || request.blockchain_type != challenge.blockchain_type
-    {
-        log::warn!(
-            "Address/blockchain mismatch for challenge: {} (got: {}/{}, expected: {}/{})",
-            request.challenge_id,
-            request.address,
-            request.blockchain_type,
-            challenge.address,
-            challenge.blockchain_type
-        );
-        return Err(Error::Authentication(
-            "Address or blockchain type mismatch".into(),
-        ));
-    }
+        .challenge_store
+        .redeem(
+            &request.challenge_id,
+            &request.address,
+            &request.blockchain_type,
+            request_origin(&headers)?,
+            request_client(&headers),
+        )
+        .await?;
 
     // Get the default signature curve if not provided
     let signature_curve =
@@ -200,8 +185,24 @@ pub async fn authenticate_wallet(
 
     if !signature_valid {
         log::warn!("Invalid signature for wallet: {}", request.address);
+        service
+            .challenge_store
+            .record_failure(
+                &request.blockchain_type,
+                &request.address,
+                request_client(&headers),
+            )
+            .await;
         return Err(Error::Authentication("Invalid signature".into()));
     }
+    service
+        .challenge_store
+        .record_success(
+            &request.blockchain_type,
+            &request.address,
+            request_client(&headers),
+        )
+        .await;
 
     // Look up user by wallet address, or create a new user if not found
     let user = service
@@ -238,7 +239,7 @@ pub async fn authenticate_wallet(
         &request.address,
         &request.blockchain_type,
         &connection_id,
-        &service.config.jwt_secret,
+        &service.jwt_keys,
         service.config.jwt_expiration,
     )?;
 
@@ -249,12 +250,6 @@ pub async fn authenticate_wallet(
         .await
         .map_err(|e| Error::Internal(format!("Failed to create session: {}", e)))?;
 
-    // Delete the used challenge
-    let _ = service
-        .db_client
-        .delete_auth_challenge(&request.challenge_id)
-        .await;
-
     let response = AuthenticateWalletResponse {
         user_id,
         address: request.address,
@@ -0,0 +1,176 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+use std::sync::Arc;
+
+use axum::{
+    extract::{Json, Path, State},
+    http::{header, HeaderMap},
+};
+use r3e_core::{ErrorBody, ErrorCode};
+use r3e_neo_services::deploy::{ContractDeployment, DeployRequest, DeploymentEstimate};
+use serde::Deserialize;
+use utoipa::ToSchema;
+
+use crate::{error::Error, service::EndpointService, utils::verify_jwt_token};
+
+/// Deploy contract request, paid by the gas bank account of the caller
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct DeployContractRequest {
+    /// Function the contract belongs to
+    pub function_id: String,
+
+    /// Base64 encoded NEF file
+    pub nef: String,
+
+    /// Manifest JSON
+    pub manifest: String,
+
+    /// Gas bank approval of the payment, when it exceeds the approval threshold
+    #[serde(default)]
+    pub approval_id: Option<String>,
+}
+
+impl DeployContractRequest {
+    fn into_request(self, owner: String) -> DeployRequest {
+        DeployRequest {
+            function_id: self.function_id,
+            owner,
+            nef: self.nef,
+            manifest: self.manifest,
+            approval_id: self.approval_id,
+        }
+    }
+}
+
+/// Wallet address of the bearer token
+fn owner(service: &EndpointService, headers: &HeaderMap) -> Result<String, Error> {
+    let token = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .ok_or_else(|| Error::Authentication("Bearer token required".to_string()))?;
+
+    Ok(verify_jwt_token(token, &service.jwt_keys)?.sub)
+}
+
+fn deploy_error(e: r3e_neo_services::Error) -> Error {
+    use r3e_neo_services::Error as NeoError;
+
+    match e {
+        NeoError::NotFound(msg) => Error::NotFound(msg),
+        NeoError::InvalidParameter(msg) => Error::Validation(msg),
+        NeoError::ContractError(msg) => Error::Conflict(msg),
+        NeoError::InsufficientFunds(msg) => {
+            Error::Coded(ErrorBody::new(ErrorCode::PaymentRequired, msg))
+        }
+        NeoError::GasBankError(msg) => {
+            Error::Coded(ErrorBody::new(ErrorCode::PreconditionFailed, msg))
+        }
+        NeoError::RpcError(msg) | NeoError::TransactionError(msg) => Error::Blockchain(msg),
+        e => Error::Internal(format!("Deployment error: {}", e)),
+    }
+}
+
+/// Estimate contract deployment handler
+#[utoipa::path(
+    post,
+    path = "/contracts/estimate",
+    tag = "contracts",
+    request_body = DeployContractRequest,
+    responses((status = 200, description = "Deployment fees and gas bank funds", body = Object)),
+    security(("bearer" = []))
+)]
+pub async fn estimate(
+    State(service): State<Arc<EndpointService>>,
+    headers: HeaderMap,
+    Json(request): Json<DeployContractRequest>,
+) -> Result<Json<DeploymentEstimate>, Error> {
+    let owner = owner(&service, &headers)?;
+    let estimate = service
+        .deployment_service
+        .estimate(&request.into_request(owner))
+        .await
+        .map_err(deploy_error)?;
+
+    Ok(Json(estimate))
+}
+
+/// Deploy contract handler
+#[utoipa::path(
+    post,
+    path = "/contracts",
+    tag = "contracts",
+    request_body = DeployContractRequest,
+    responses((status = 200, description = "Pending deployment", body = Object)),
+    security(("bearer" = []))
+)]
+pub async fn deploy(
+    State(service): State<Arc<EndpointService>>,
+    headers: HeaderMap,
+    Json(request): Json<DeployContractRequest>,
+) -> Result<Json<ContractDeployment>, Error> {
+    let owner = owner(&service, &headers)?;
+    let deployment = service
+        .deployment_service
+        .deploy(request.into_request(owner))
+        .await
+        .map_err(deploy_error)?;
+
+    Ok(Json(deployment))
+}
+
+/// Get contract deployment handler
+#[utoipa::path(
+    get,
+    path = "/contracts/{id}",
+    tag = "contracts",
+    params(("id" = String, Path, description = "Deployment ID")),
+    responses((status = 200, description = "Deployment", body = Object)),
+    security(("bearer" = []))
+)]
+pub async fn get_deployment(
+    State(service): State<Arc<EndpointService>>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+) -> Result<Json<ContractDeployment>, Error> {
+    let owner = owner(&service, &headers)?;
+    let deployment = service
+        .deployment_service
+        .get_deployment(&id)
+        .await
+        .map_err(deploy_error)?;
+
+    // Other owners' deployments are reported as missing
+    if deployment.owner != owner {
+        return Err(Error::NotFound(format!("Deployment not found: {}", id)));
+    }
+    Ok(Json(deployment))
+}
+
+/// List function contracts handler
+#[utoipa::path(
+    get,
+    path = "/functions/{function_id}/contracts",
+    tag = "contracts",
+    params(("function_id" = String, Path, description = "Function ID")),
+    responses((status = 200, description = "Contracts deployed for the function, oldest first", body = Vec<Object>)),
+    security(("bearer" = []))
+)]
+pub async fn list_function_contracts(
+    State(service): State<Arc<EndpointService>>,
+    headers: HeaderMap,
+    Path(function_id): Path<String>,
+) -> Result<Json<Vec<ContractDeployment>>, Error> {
+    let owner = owner(&service, &headers)?;
+    let deployments = service
+        .deployment_service
+        .list_deployments(&function_id)
+        .await
+        .map_err(deploy_error)?
+        .into_iter()
+        .filter(|deployment| deployment.owner == owner)
+        .collect();
+
+    Ok(Json(deployments))
+}
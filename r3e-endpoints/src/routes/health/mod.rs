@@ -3,9 +3,10 @@
 
 use axum::Json;
 use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 
 /// Health check response
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct HealthCheckResponse {
     /// Status
     pub status: String,
@@ -18,6 +19,12 @@ pub struct HealthCheckResponse {
 }
 
 /// Health check handler
+#[utoipa::path(
+    get,
+    path = "/health",
+    tag = "health",
+    responses((status = 200, description = "Service is up", body = HealthCheckResponse))
+)]
 pub async fn health_check() -> Json<HealthCheckResponse> {
     Json(HealthCheckResponse {
         status: "ok".to_string(),
@@ -5,31 +5,133 @@ use std::sync::Arc;
 
 use axum::{
     extract::{Json, Path, State},
-    http::StatusCode,
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
 };
 use chrono::Utc;
+use r3e_api::idempotency::{self, Claim, StoredResponse};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
 use crate::{
     error::Error,
     service::EndpointService,
-    types::{MetaTransactionRequest, MetaTransactionResponse},
+    types::{MetaTransactionQuoteRequest, MetaTransactionRequest, MetaTransactionResponse},
     utils::verify_jwt_token,
 };
 
 /// Submit meta transaction handler
+///
+/// A request carrying an `Idempotency-Key` header is submitted once per sender and key,
+/// retries get the first response back.
+#[utoipa::path(
+    post,
+    path = "/meta-tx/submit",
+    tag = "meta_tx",
+    request_body = MetaTransactionRequest,
+    params(("Idempotency-Key" = Option<String>, Header, description = "Submits the transaction once per sender and key")),
+    responses((status = 200, description = "Relayed meta transaction", body = MetaTransactionResponse))
+)]
 pub async fn submit(
     State(service): State<Arc<EndpointService>>,
+    headers: HeaderMap,
     Json(request): Json<MetaTransactionRequest>,
-) -> Result<Json<MetaTransactionResponse>, Error> {
-    // Convert to r3e-neo-services MetaTxRequest
-    let meta_tx_request = r3e_neo_services::meta_tx::types::MetaTxRequest {
-        tx_data: request.tx_data.clone(),
-        sender: request.sender.clone(),
-        signature: request.signature.clone(),
-        nonce: request.nonce,
-        deadline: request.deadline,
+) -> Result<Response, Error> {
+    let idempotency_key = idempotency::idempotency_key(&headers)?;
+    if let Some(key) = &idempotency_key {
+        let fingerprint = idempotency::fingerprint("/meta-tx/submit", &request)?;
+        if let Claim::Replay(stored) = service
+            .idempotency_store
+            .claim(&request.sender, key, &fingerprint)
+            .await?
+        {
+            let status = StatusCode::from_u16(stored.status).unwrap_or(StatusCode::OK);
+            return Ok((
+                status,
+                [(idempotency::IDEMPOTENT_REPLAYED_HEADER, "true")],
+                Json(stored.body),
+            )
+                .into_response());
+        }
+    }
+
+    let response = submit_meta_tx(&service, &request).await;
+
+    if let Some(key) = &idempotency_key {
+        let stored = match &response {
+            Ok(response) => {
+                service
+                    .idempotency_store
+                    .complete(
+                        &request.sender,
+                        key,
+                        &StoredResponse {
+                            status: 200,
+                            body: serde_json::to_value(response).unwrap_or_default(),
+                        },
+                    )
+                    .await
+            }
+            // Failed submissions may be retried with the same key
+            Err(_) => {
+                service
+                    .idempotency_store
+                    .release(&request.sender, key)
+                    .await
+            }
+        };
+        if let Err(e) = stored {
+            log::warn!("Failed to record idempotent meta transaction: {}", e);
+        }
+    }
+
+    Ok(Json(response?).into_response())
+}
+
+/// Simulate meta transaction handler
+///
+/// Runs the transaction against the latest chain state without relaying it, returning the
+/// expected result, gas cost and any revert reason.
+#[utoipa::path(
+    post,
+    path = "/meta-tx/simulate",
+    tag = "meta_tx",
+    request_body = MetaTransactionRequest,
+    responses((status = 200, description = "Expected result, gas cost and revert reason", body = Object))
+)]
+pub async fn simulate(
+    State(service): State<Arc<EndpointService>>,
+    Json(request): Json<MetaTransactionRequest>,
+) -> Result<Json<r3e_neo_services::meta_tx::types::MetaTxSimulation>, Error> {
+    let simulation = service
+        .meta_tx_service
+        .simulate(meta_tx_request(&request))
+        .await
+        .map_err(|e| Error::Blockchain(format!("Failed to simulate meta transaction: {}", e)))?;
+
+    Ok(Json(simulation))
+}
+
+/// Quote meta transaction fee handler
+///
+/// Prices the transaction at the current network fees under the fee model of its service. The
+/// returned `fee_model` and `fee_amount` are signed with the transaction, submissions paying
+/// less than the fee quoted at submission time are rejected.
+#[utoipa::path(
+    post,
+    path = "/meta-tx/quote",
+    tag = "meta_tx",
+    request_body = MetaTransactionQuoteRequest,
+    responses((status = 200, description = "Network fees and the fee charged to the sender", body = Object))
+)]
+pub async fn quote(
+    State(service): State<Arc<EndpointService>>,
+    Json(request): Json<MetaTransactionQuoteRequest>,
+) -> Result<Json<r3e_neo_services::fee::FeeQuote>, Error> {
+    let quote_request = r3e_neo_services::meta_tx::types::FeeQuoteRequest {
+        tx_data: request.tx_data,
+        target_address: request.target_contract.clone().unwrap_or_default(),
+        sender: request.sender,
         blockchain_type: match request.blockchain_type {
             crate::types::BlockchainType::NeoN3 => {
                 r3e_neo_services::meta_tx::types::BlockchainType::NeoN3
@@ -38,24 +140,28 @@ pub async fn submit(
                 r3e_neo_services::meta_tx::types::BlockchainType::Ethereum
             }
         },
-        target_contract: request.target_contract.clone(),
-        signature_curve: match request.signature_curve {
-            crate::types::SignatureCurve::Secp256r1 => {
-                r3e_neo_services::meta_tx::types::SignatureCurve::Secp256r1
-            }
-            crate::types::SignatureCurve::Secp256k1 => {
-                r3e_neo_services::meta_tx::types::SignatureCurve::Secp256k1
-            }
-        },
-        fee_model: r3e_neo_services::types::FeeModel::Percentage(1.0),
-        fee_amount: 0,
-        timestamp: request.timestamp,
+        target_contract: request.target_contract,
+        chain_id: request.chain_id,
     };
 
+    let quote = service
+        .meta_tx_service
+        .quote(&quote_request)
+        .await
+        .map_err(|e| Error::Blockchain(format!("Failed to quote meta transaction fee: {}", e)))?;
+
+    Ok(Json(quote))
+}
+
+/// Submit a meta transaction to the relayer
+async fn submit_meta_tx(
+    service: &EndpointService,
+    request: &MetaTransactionRequest,
+) -> Result<MetaTransactionResponse, Error> {
     // Submit the meta transaction
     let response = service
         .meta_tx_service
-        .submit(meta_tx_request)
+        .submit(meta_tx_request(request))
         .await
         .map_err(|e| Error::Blockchain(format!("Failed to submit meta transaction: {}", e)))?;
 
@@ -69,10 +175,50 @@ pub async fn submit(
         timestamp: response.timestamp,
     };
 
-    Ok(Json(api_response))
+    Ok(api_response)
+}
+
+/// Convert an API request to a r3e-neo-services MetaTxRequest
+fn meta_tx_request(
+    request: &MetaTransactionRequest,
+) -> r3e_neo_services::meta_tx::types::MetaTxRequest {
+    r3e_neo_services::meta_tx::types::MetaTxRequest {
+        tx_data: request.tx_data.clone(),
+        sender: request.sender.clone(),
+        signature: request.signature.clone(),
+        nonce: request.nonce,
+        deadline: request.deadline,
+        blockchain_type: match request.blockchain_type {
+            crate::types::BlockchainType::NeoN3 => {
+                r3e_neo_services::meta_tx::types::BlockchainType::NeoN3
+            }
+            crate::types::BlockchainType::Ethereum => {
+                r3e_neo_services::meta_tx::types::BlockchainType::Ethereum
+            }
+        },
+        target_contract: request.target_contract.clone(),
+        signature_curve: match request.signature_curve {
+            crate::types::SignatureCurve::Secp256r1 => {
+                r3e_neo_services::meta_tx::types::SignatureCurve::Secp256r1
+            }
+            crate::types::SignatureCurve::Secp256k1 => {
+                r3e_neo_services::meta_tx::types::SignatureCurve::Secp256k1
+            }
+        },
+        fee_model: request.fee_model.clone(),
+        fee_amount: request.fee_amount,
+        timestamp: request.timestamp,
+    }
 }
 
 /// Get meta transaction status handler
+#[utoipa::path(
+    get,
+    path = "/meta-tx/status/{id}",
+    tag = "meta_tx",
+    params(("id" = String, Path, description = "Meta transaction request ID")),
+    responses((status = 200, description = "Meta transaction status", body = String))
+)]
 pub async fn get_status(
     State(service): State<Arc<EndpointService>>,
     Path(id): Path<String>,
@@ -87,6 +233,13 @@ pub async fn get_status(
 }
 
 /// Get meta transaction handler
+#[utoipa::path(
+    get,
+    path = "/meta-tx/transaction/{id}",
+    tag = "meta_tx",
+    params(("id" = String, Path, description = "Meta transaction request ID")),
+    responses((status = 200, description = "Meta transaction record", body = Object))
+)]
 pub async fn get_transaction(
     State(service): State<Arc<EndpointService>>,
     Path(id): Path<String>,
@@ -103,6 +256,13 @@ pub async fn get_transaction(
 }
 
 /// Get next nonce handler
+#[utoipa::path(
+    get,
+    path = "/meta-tx/nonce/{address}",
+    tag = "meta_tx",
+    params(("address" = String, Path, description = "Sender address")),
+    responses((status = 200, description = "Next nonce of the sender", body = u64))
+)]
 pub async fn get_next_nonce(
     State(service): State<Arc<EndpointService>>,
     Path(address): Path<String>,
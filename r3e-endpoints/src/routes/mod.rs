@@ -1,27 +1,110 @@
 // Copyright @ 2023 - 2024, R3E Network
 // All Rights Reserved
 
+mod accounts;
 mod auth;
+mod contracts;
 mod health;
 mod meta_tx;
+mod mpc;
+mod operations;
 mod services;
 mod wallet;
 
 use std::sync::Arc;
 
 use axum::{
+    response::Html,
     routing::{delete, get, post},
-    Router,
+    Json, Router,
 };
+use r3e_api::openapi::{swagger_ui_html, ErrorResponses, SecuritySchemes};
+use utoipa::OpenApi;
 
-use crate::middleware::KeyRotationLayer;
+use crate::middleware::{AuditLayer, JwtReissueLayer, KeyRotationLayer};
 use crate::service::EndpointService;
 
+/// OpenAPI document of the endpoints, served at `/openapi.json` with Swagger UI at `/docs`
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "R3E FaaS Endpoints",
+        description = "Wallet authentication, meta transactions, MPC signing and services"
+    ),
+    paths(
+        health::health_check,
+        auth::login,
+        auth::register,
+        auth::refresh,
+        auth::api_keys::create_api_key,
+        auth::api_keys::rotate_api_key,
+        auth::api_keys::revoke_api_key,
+        auth::api_keys::list_api_keys,
+        wallet::connect,
+        wallet::sign_message,
+        wallet::verify_signature,
+        meta_tx::submit,
+        meta_tx::simulate,
+        meta_tx::quote,
+        meta_tx::get_status,
+        meta_tx::get_transaction,
+        meta_tx::get_next_nonce,
+        mpc::create_key,
+        mpc::list_keys,
+        mpc::get_key,
+        mpc::sign,
+        mpc::rotate_shares,
+        contracts::estimate,
+        contracts::deploy,
+        contracts::get_deployment,
+        contracts::list_function_contracts,
+        accounts::import_wallet,
+        accounts::export_wallet,
+        accounts::list_accounts,
+        operations::propose,
+        operations::list,
+        operations::get,
+        operations::signing_request,
+        operations::approve,
+        operations::cancel,
+        services::list_services,
+        services::get_service,
+        services::invoke_service,
+    ),
+    tags(
+        (name = "health", description = "Liveness"),
+        (name = "auth", description = "Login and API keys"),
+        (name = "wallet", description = "Wallet connections and signatures"),
+        (name = "meta_tx", description = "Relayed meta transactions"),
+        (name = "mpc", description = "Threshold keys and signing"),
+        (name = "contracts", description = "Neo N3 contracts deployed for functions"),
+        (name = "accounts", description = "Neo N3 accounts imported from NEP-6 wallets"),
+        (name = "operations", description = "Admin operations approved by offline signature"),
+        (name = "services", description = "Service discovery and invocation"),
+    ),
+    modifiers(&SecuritySchemes, &ErrorResponses)
+)]
+pub struct ApiDoc;
+
 /// Create the router
 pub fn create_router(service: Arc<EndpointService>) -> Router {
     // Create the key rotation layer
     let key_rotation_layer = KeyRotationLayer::new(service.key_rotation_service());
 
+    // Create the JWT re-issuance layer
+    let jwt_reissue_layer =
+        JwtReissueLayer::new(service.jwt_keys.clone(), service.config.jwt_expiration);
+
+    // Create the audit layer
+    let audit_layer = AuditLayer::new(service.audit_store.clone(), service.jwt_keys.clone());
+
+    // Create the OpenAPI document and its Swagger UI page
+    let spec = ApiDoc::openapi();
+    let docs = swagger_ui_html(
+        &format!("{} - Swagger UI", spec.info.title),
+        "/openapi.json",
+    );
+
     // Create the router
     Router::new()
         // Health routes
@@ -50,17 +133,54 @@ pub fn create_router(service: Arc<EndpointService>) -> Router {
         .route("/wallet/verify", post(wallet::verify_signature))
         // Meta transaction routes
         .route("/meta-tx/submit", post(meta_tx::submit))
+        .route("/meta-tx/simulate", post(meta_tx::simulate))
+        .route("/meta-tx/quote", post(meta_tx::quote))
         .route("/meta-tx/status/:id", get(meta_tx::get_status))
         .route("/meta-tx/transaction/:id", get(meta_tx::get_transaction))
         .route("/meta-tx/nonce/:address", get(meta_tx::get_next_nonce))
+        // MPC routes
+        .route("/mpc/keys", post(mpc::create_key))
+        .route("/mpc/keys", get(mpc::list_keys))
+        .route("/mpc/keys/:key_id", get(mpc::get_key))
+        .route("/mpc/keys/:key_id/sign", post(mpc::sign))
+        .route("/mpc/keys/:key_id/rotate", post(mpc::rotate_shares))
+        // Contract deployment routes
+        .route("/contracts/estimate", post(contracts::estimate))
+        .route("/contracts", post(contracts::deploy))
+        .route("/contracts/:id", get(contracts::get_deployment))
+        .route(
+            "/functions/:function_id/contracts",
+            get(contracts::list_function_contracts),
+        )
+        // Managed account routes
+        .route("/accounts", get(accounts::list_accounts))
+        .route("/accounts/import", post(accounts::import_wallet))
+        .route("/accounts/export", post(accounts::export_wallet))
+        // Admin operation routes
+        .route("/operations", post(operations::propose))
+        .route("/operations", get(operations::list))
+        .route("/operations/:id", get(operations::get))
+        .route(
+            "/operations/:id/signing-request",
+            get(operations::signing_request),
+        )
+        .route("/operations/:id/approve", post(operations::approve))
+        .route("/operations/:id/cancel", post(operations::cancel))
         // Service routes
         .route("/services", get(services::list_services))
         .route("/services/:id", get(services::get_service))
         .route("/services/:id/invoke", post(services::invoke_service))
+        // API description routes
+        .route("/openapi.json", get(move || async move { Json(spec) }))
+        .route("/docs", get(move || async move { Html(docs) }))
         // Add the service state
         .with_state(service)
         // Add the key rotation middleware
         .layer(key_rotation_layer)
+        // Add the JWT re-issuance middleware
+        .layer(jwt_reissue_layer)
+        // Add the audit middleware, outermost so every request is recorded
+        .layer(audit_layer)
 }
 
 pub fn auth_routes() -> Router<Arc<EndpointService>> {
@@ -79,3 +199,25 @@ pub fn auth_routes() -> Router<Arc<EndpointService>> {
             get(auth::api_keys::list_api_keys),
         )
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_document_covers_routes() {
+        let spec = ApiDoc::openapi();
+
+        assert!(spec.paths.paths.contains_key("/mpc/keys/{key_id}/sign"));
+        assert!(spec.paths.paths.contains_key("/meta-tx/submit"));
+        assert!(spec.paths.paths.contains_key("/meta-tx/quote"));
+        assert!(spec
+            .paths
+            .paths
+            .contains_key("/functions/{function_id}/contracts"));
+        assert!(spec.paths.paths.contains_key("/accounts/import"));
+        for path in spec.paths.paths.keys() {
+            assert!(!path.contains(':'), "axum path syntax in {}", path);
+        }
+    }
+}
@@ -0,0 +1,215 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+use std::sync::Arc;
+
+use axum::{
+    extract::{Json, Path, State},
+    http::{header, HeaderMap},
+};
+use r3e_neo_services::mpc::ThresholdKey;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::{error::Error, service::EndpointService, utils::verify_jwt_token};
+
+/// Create threshold key request
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct CreateKeyRequest {
+    /// Number of parties that may be compromised without exposing the key
+    pub threshold: u16,
+}
+
+/// Sign request
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct SignRequest {
+    /// Hex encoded message; its SHA-256 digest is signed
+    pub message: String,
+}
+
+/// Sign response
+#[derive(Debug, Serialize, ToSchema)]
+pub struct SignResponse {
+    /// Key ID
+    pub key_id: String,
+
+    /// Hex encoded compressed public key
+    pub public_key: String,
+
+    /// Hex encoded 64-byte `r || s` signature
+    pub signature: String,
+}
+
+/// Wallet address of the bearer token
+fn owner(service: &EndpointService, headers: &HeaderMap) -> Result<String, Error> {
+    let token = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .ok_or_else(|| Error::Authentication("Bearer token required".to_string()))?;
+
+    Ok(verify_jwt_token(token, &service.jwt_keys)?.sub)
+}
+
+fn mpc_error(e: r3e_neo_services::Error) -> Error {
+    match e {
+        r3e_neo_services::Error::NotFound(msg) => Error::NotFound(msg),
+        e => Error::Internal(format!("MPC error: {}", e)),
+    }
+}
+
+/// Key of the caller; other owners' keys are reported as missing
+async fn owned_key(
+    service: &EndpointService,
+    owner: &str,
+    key_id: &str,
+) -> Result<ThresholdKey, Error> {
+    let key = service
+        .mpc_service
+        .get_key(key_id)
+        .await
+        .map_err(mpc_error)?;
+    if key.owner != owner {
+        return Err(Error::NotFound(format!(
+            "Threshold key not found: {}",
+            key_id
+        )));
+    }
+    Ok(key)
+}
+
+/// Create threshold key handler
+#[utoipa::path(
+    post,
+    path = "/mpc/keys",
+    tag = "mpc",
+    request_body = CreateKeyRequest,
+    responses((status = 200, description = "Threshold key", body = Object)),
+    security(("bearer" = []))
+)]
+pub async fn create_key(
+    State(service): State<Arc<EndpointService>>,
+    headers: HeaderMap,
+    Json(request): Json<CreateKeyRequest>,
+) -> Result<Json<ThresholdKey>, Error> {
+    let owner = owner(&service, &headers)?;
+
+    let parties = service.config.mpc_parties as usize;
+    if request.threshold == 0 || 2 * request.threshold as usize + 1 > parties {
+        return Err(Error::Validation(format!(
+            "Threshold must be between 1 and {} with {} parties",
+            parties.saturating_sub(1) / 2,
+            parties
+        )));
+    }
+
+    let key = service
+        .mpc_service
+        .create_key(&owner, request.threshold)
+        .await
+        .map_err(mpc_error)?;
+
+    Ok(Json(key))
+}
+
+/// List threshold keys handler
+#[utoipa::path(
+    get,
+    path = "/mpc/keys",
+    tag = "mpc",
+    responses((status = 200, description = "Threshold keys of the caller", body = Vec<Object>)),
+    security(("bearer" = []))
+)]
+pub async fn list_keys(
+    State(service): State<Arc<EndpointService>>,
+    headers: HeaderMap,
+) -> Result<Json<Vec<ThresholdKey>>, Error> {
+    let owner = owner(&service, &headers)?;
+    let keys = service
+        .mpc_service
+        .list_keys(&owner)
+        .await
+        .map_err(mpc_error)?;
+
+    Ok(Json(keys))
+}
+
+/// Get threshold key handler
+#[utoipa::path(
+    get,
+    path = "/mpc/keys/{key_id}",
+    tag = "mpc",
+    params(("key_id" = String, Path, description = "Threshold key ID")),
+    responses((status = 200, description = "Threshold key", body = Object)),
+    security(("bearer" = []))
+)]
+pub async fn get_key(
+    State(service): State<Arc<EndpointService>>,
+    headers: HeaderMap,
+    Path(key_id): Path<String>,
+) -> Result<Json<ThresholdKey>, Error> {
+    let owner = owner(&service, &headers)?;
+    let key = owned_key(&service, &owner, &key_id).await?;
+
+    Ok(Json(key))
+}
+
+/// Sign handler
+#[utoipa::path(
+    post,
+    path = "/mpc/keys/{key_id}/sign",
+    tag = "mpc",
+    params(("key_id" = String, Path, description = "Threshold key ID")),
+    request_body = SignRequest,
+    responses((status = 200, description = "Signature", body = SignResponse)),
+    security(("bearer" = []))
+)]
+pub async fn sign(
+    State(service): State<Arc<EndpointService>>,
+    headers: HeaderMap,
+    Path(key_id): Path<String>,
+    Json(request): Json<SignRequest>,
+) -> Result<Json<SignResponse>, Error> {
+    let owner = owner(&service, &headers)?;
+    let key = owned_key(&service, &owner, &key_id).await?;
+
+    let message = hex::decode(request.message.trim_start_matches("0x"))
+        .map_err(|e| Error::Validation(format!("Invalid message: {}", e)))?;
+    let signature = service
+        .mpc_service
+        .sign(&key.id, &message)
+        .await
+        .map_err(mpc_error)?;
+
+    Ok(Json(SignResponse {
+        key_id: key.id,
+        public_key: key.public_key,
+        signature: hex::encode(signature),
+    }))
+}
+
+/// Rotate key shares handler
+#[utoipa::path(
+    post,
+    path = "/mpc/keys/{key_id}/rotate",
+    tag = "mpc",
+    params(("key_id" = String, Path, description = "Threshold key ID")),
+    responses((status = 200, description = "Threshold key with fresh shares", body = Object)),
+    security(("bearer" = []))
+)]
+pub async fn rotate_shares(
+    State(service): State<Arc<EndpointService>>,
+    headers: HeaderMap,
+    Path(key_id): Path<String>,
+) -> Result<Json<ThresholdKey>, Error> {
+    let owner = owner(&service, &headers)?;
+    let key = owned_key(&service, &owner, &key_id).await?;
+
+    let key = service
+        .mpc_service
+        .rotate_shares(&key.id)
+        .await
+        .map_err(mpc_error)?;
+
+    Ok(Json(key))
+}
@@ -0,0 +1,183 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+use std::sync::Arc;
+
+use axum::{
+    extract::{Json, Path, Query, State},
+    http::{header, HeaderMap},
+};
+use r3e_secrets::approval::{OperationStatus, PendingOperation, SigningRequest};
+use serde::Deserialize;
+use utoipa::{IntoParams, ToSchema};
+
+use crate::{error::Error, service::EndpointService, utils::verify_jwt_token};
+
+/// Propose operation request
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct ProposeOperationRequest {
+    /// Operation kind, e.g. `gas_bank.withdraw`
+    pub kind: String,
+
+    /// Operation parameters
+    pub params: serde_json::Value,
+}
+
+/// Approve operation request
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct ApproveOperationRequest {
+    /// Hex encoded approver signature of the operation's signing message
+    pub signature: String,
+}
+
+/// List operations query
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ListOperationsQuery {
+    /// Status
+    #[param(value_type = Option<String>, example = "pending")]
+    pub status: Option<OperationStatus>,
+}
+
+/// Subject of the bearer token; the approver's offline signature, not the caller, authorizes
+/// execution
+fn actor(service: &EndpointService, headers: &HeaderMap) -> Result<String, Error> {
+    let token = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .ok_or_else(|| Error::Authentication("Bearer token required".to_string()))?;
+
+    Ok(verify_jwt_token(token, &service.jwt_keys)?.sub)
+}
+
+/// Propose operation handler
+#[utoipa::path(
+    post,
+    path = "/operations",
+    tag = "operations",
+    request_body = ProposeOperationRequest,
+    responses((status = 200, description = "Pending operation", body = Object)),
+    security(("bearer" = []))
+)]
+pub async fn propose(
+    State(service): State<Arc<EndpointService>>,
+    headers: HeaderMap,
+    Json(request): Json<ProposeOperationRequest>,
+) -> Result<Json<PendingOperation>, Error> {
+    let actor = actor(&service, &headers)?;
+    let operation = service
+        .approval_service
+        .propose(&request.kind, request.params, &actor)
+        .await?;
+
+    Ok(Json(operation))
+}
+
+/// List operations handler
+#[utoipa::path(
+    get,
+    path = "/operations",
+    tag = "operations",
+    params(ListOperationsQuery),
+    responses((status = 200, description = "Operations", body = Vec<Object>)),
+    security(("bearer" = []))
+)]
+pub async fn list(
+    State(service): State<Arc<EndpointService>>,
+    headers: HeaderMap,
+    Query(query): Query<ListOperationsQuery>,
+) -> Result<Json<Vec<PendingOperation>>, Error> {
+    actor(&service, &headers)?;
+    let operations = service.approval_service.list(query.status).await?;
+
+    Ok(Json(operations))
+}
+
+/// Get operation handler
+#[utoipa::path(
+    get,
+    path = "/operations/{id}",
+    tag = "operations",
+    params(("id" = String, Path, description = "Operation ID")),
+    responses((status = 200, description = "Operation", body = Object)),
+    security(("bearer" = []))
+)]
+pub async fn get(
+    State(service): State<Arc<EndpointService>>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+) -> Result<Json<PendingOperation>, Error> {
+    actor(&service, &headers)?;
+    let operation = service.approval_service.get(&id).await?;
+
+    Ok(Json(operation))
+}
+
+/// Export operation for offline signing handler
+#[utoipa::path(
+    get,
+    path = "/operations/{id}/signing-request",
+    tag = "operations",
+    params(("id" = String, Path, description = "Operation ID")),
+    responses((status = 200, description = "Message for the approver to sign offline", body = Object)),
+    security(("bearer" = []))
+)]
+pub async fn signing_request(
+    State(service): State<Arc<EndpointService>>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+) -> Result<Json<SigningRequest>, Error> {
+    let actor = actor(&service, &headers)?;
+    let request = service
+        .approval_service
+        .signing_request(&id, &actor)
+        .await?;
+
+    Ok(Json(request))
+}
+
+/// Approve operation handler
+#[utoipa::path(
+    post,
+    path = "/operations/{id}/approve",
+    tag = "operations",
+    params(("id" = String, Path, description = "Operation ID")),
+    request_body = ApproveOperationRequest,
+    responses((status = 200, description = "Approved operation", body = Object)),
+    security(("bearer" = []))
+)]
+pub async fn approve(
+    State(service): State<Arc<EndpointService>>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+    Json(request): Json<ApproveOperationRequest>,
+) -> Result<Json<PendingOperation>, Error> {
+    let actor = actor(&service, &headers)?;
+    let operation = service
+        .approval_service
+        .approve(&id, &request.signature, &actor)
+        .await?;
+
+    Ok(Json(operation))
+}
+
+/// Cancel operation handler
+#[utoipa::path(
+    post,
+    path = "/operations/{id}/cancel",
+    tag = "operations",
+    params(("id" = String, Path, description = "Operation ID")),
+    responses((status = 200, description = "Cancelled operation", body = Object)),
+    security(("bearer" = []))
+)]
+pub async fn cancel(
+    State(service): State<Arc<EndpointService>>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+) -> Result<Json<PendingOperation>, Error> {
+    let actor = actor(&service, &headers)?;
+    let operation = service.approval_service.cancel(&id, &actor).await?;
+
+    Ok(Json(operation))
+}
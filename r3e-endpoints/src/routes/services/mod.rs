@@ -9,6 +9,7 @@ use axum::{
 };
 use chrono::Utc;
 use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 use uuid::Uuid;
 
 use crate::{
@@ -17,7 +18,7 @@ use crate::{
 };
 
 /// Service
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct Service {
     /// Service ID
     pub id: Uuid,
@@ -45,7 +46,7 @@ pub struct Service {
 }
 
 /// Service function
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct ServiceFunction {
     /// Function name
     pub name: String,
@@ -67,7 +68,7 @@ pub struct ServiceFunction {
 }
 
 /// Service function parameter
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct ServiceFunctionParameter {
     /// Parameter name
     pub name: String,
@@ -83,6 +84,12 @@ pub struct ServiceFunctionParameter {
 }
 
 /// List services handler
+#[utoipa::path(
+    get,
+    path = "/services",
+    tag = "services",
+    responses((status = 200, description = "Services", body = Vec<Service>))
+)]
 pub async fn list_services(
     State(service): State<Arc<EndpointService>>,
 ) -> Result<Json<Vec<Service>>, Error> {
@@ -104,6 +111,13 @@ pub async fn list_services(
 }
 
 /// Get service handler
+#[utoipa::path(
+    get,
+    path = "/services/{id}",
+    tag = "services",
+    params(("id" = Uuid, Path, description = "Service ID")),
+    responses((status = 200, description = "Service", body = Service))
+)]
 pub async fn get_service(
     State(service): State<Arc<EndpointService>>,
     Path(id): Path<String>,
@@ -142,6 +156,14 @@ pub async fn get_service(
 }
 
 /// Invoke service handler
+#[utoipa::path(
+    post,
+    path = "/services/{id}/invoke",
+    tag = "services",
+    params(("id" = Uuid, Path, description = "Service ID")),
+    request_body = ServiceInvocationRequest,
+    responses((status = 200, description = "Invocation result", body = ServiceInvocationResponse))
+)]
 pub async fn invoke_service(
     State(service): State<Arc<EndpointService>>,
     Path(id): Path<String>,
@@ -206,7 +228,7 @@ pub async fn invoke_service(
 
     // Verify authentication if present
     if let Some(token) = &request.auth_token {
-        match crate::utils::verify_jwt_token(token, &service.config.jwt_secret) {
+        match crate::utils::verify_jwt_token(token, &service.jwt_keys) {
             Ok(_) => {
                 log::debug!(
                     "Auth token verified for function: {}.{}",
@@ -8,8 +8,8 @@ use axum::{
     http::StatusCode,
 };
 use chrono::Utc;
-use jsonwebtoken::{encode, EncodingKey, Header};
 use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 use uuid::Uuid;
 
 use crate::{
@@ -19,7 +19,7 @@ use crate::{
         BlockchainType, MessageSigningRequest, MessageSigningResponse, SignatureCurve,
         WalletConnectionRequest, WalletConnectionResponse,
     },
-    utils::verify_signature,
+    utils::{eip712, verify_signature, verify_typed_data_signature},
 };
 
 /// JWT claims
@@ -42,18 +42,36 @@ struct Claims {
 }
 
 /// Connect wallet handler
+#[utoipa::path(
+    post,
+    path = "/wallet/connect",
+    tag = "wallet",
+    request_body = WalletConnectionRequest,
+    responses((status = 200, description = "Wallet connection and session token", body = WalletConnectionResponse))
+)]
 pub async fn connect(
     State(service): State<Arc<EndpointService>>,
     Json(request): Json<WalletConnectionRequest>,
 ) -> Result<Json<WalletConnectionResponse>, Error> {
-    // Verify the signature
-    let is_valid = verify_signature(
-        &request.blockchain_type,
-        &request.signature_curve,
-        &request.address,
-        &request.message,
-        &request.signature,
-    )?;
+    // Verify the signature, typed-data signatures when the request carries types
+    let is_valid = match &request.types {
+        Some(types) => verify_typed_data_signature(
+            &request.blockchain_type,
+            &request.address,
+            request.domain.as_ref(),
+            types,
+            request.primary_type.as_deref(),
+            &request.message,
+            &request.signature,
+        )?,
+        None => verify_signature(
+            &request.blockchain_type,
+            &request.signature_curve,
+            &request.address,
+            &request.message,
+            &request.signature,
+        )?,
+    };
 
     if !is_valid {
         return Err(Error::Authentication("Invalid signature".to_string()));
@@ -72,13 +90,8 @@ pub async fn connect(
         exp: now + service.config.jwt_expiration,
     };
 
-    // Create JWT token
-    let token = encode(
-        &Header::default(),
-        &claims,
-        &EncodingKey::from_secret(service.config.jwt_secret.as_bytes()),
-    )
-    .map_err(|e| Error::Internal(format!("Failed to create JWT token: {}", e)))?;
+    // Create JWT token, signed by the active key
+    let token = service.jwt_keys.sign(&claims)?;
 
     // Create response
     let response = WalletConnectionResponse {
@@ -93,6 +106,13 @@ pub async fn connect(
 }
 
 /// Sign message handler
+#[utoipa::path(
+    post,
+    path = "/wallet/sign",
+    tag = "wallet",
+    request_body = MessageSigningRequest,
+    responses((status = 200, description = "Signing request", body = MessageSigningResponse))
+)]
 pub async fn sign_message(
     State(service): State<Arc<EndpointService>>,
     Json(request): Json<MessageSigningRequest>,
@@ -102,7 +122,23 @@ pub async fn sign_message(
 
     // For this example, we'll return a mock response
     let request_id = Uuid::new_v4().to_string();
-    let message_hash = format!("0x{}", hex::encode([0u8; 32]));
+
+    // Typed data is hashed as the wallet will sign it
+    let message_hash = match &request.types {
+        Some(types) => {
+            let message = serde_json::from_str::<serde_json::Value>(&request.message)
+                .map_err(|e| Error::Validation(format!("Typed-data message is not JSON: {}", e)))?;
+            let domain = request
+                .domain
+                .clone()
+                .unwrap_or_else(|| serde_json::json!({}));
+            let typed_data =
+                eip712::typed_data(&domain, types, request.primary_type.as_deref(), &message)?;
+            eip712::typed_data_hash(&typed_data)?
+        }
+        None => [0u8; 32],
+    };
+    let message_hash = format!("0x{}", hex::encode(message_hash));
 
     let response = MessageSigningResponse {
         request_id,
@@ -115,18 +151,36 @@ pub async fn sign_message(
 }
 
 /// Verify signature handler
+#[utoipa::path(
+    post,
+    path = "/wallet/verify",
+    tag = "wallet",
+    request_body = VerifySignatureRequest,
+    responses((status = 200, description = "Verification result", body = VerifySignatureResponse))
+)]
 pub async fn verify_signature(
     State(service): State<Arc<EndpointService>>,
     Json(request): Json<VerifySignatureRequest>,
 ) -> Result<Json<VerifySignatureResponse>, Error> {
-    // Verify the signature
-    let is_valid = verify_signature(
-        &request.blockchain_type,
-        &request.signature_curve,
-        &request.address,
-        &request.message,
-        &request.signature,
-    )?;
+    // Verify the signature, typed-data signatures when the request carries types
+    let is_valid = match &request.types {
+        Some(types) => verify_typed_data_signature(
+            &request.blockchain_type,
+            &request.address,
+            request.domain.as_ref(),
+            types,
+            request.primary_type.as_deref(),
+            &request.message,
+            &request.signature,
+        )?,
+        None => verify_signature(
+            &request.blockchain_type,
+            &request.signature_curve,
+            &request.address,
+            &request.message,
+            &request.signature,
+        )?,
+    };
 
     let response = VerifySignatureResponse {
         is_valid,
@@ -137,7 +191,7 @@ pub async fn verify_signature(
 }
 
 /// Verify signature request
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct VerifySignatureRequest {
     /// Blockchain type
     pub blockchain_type: BlockchainType,
@@ -148,15 +202,27 @@ pub struct VerifySignatureRequest {
     /// Address
     pub address: String,
 
-    /// Message
+    /// Message, the JSON message for EIP-712 signatures
     pub message: String,
 
     /// Signature
     pub signature: String,
+
+    /// Domain (for EIP-712 signatures)
+    #[serde(default)]
+    pub domain: Option<serde_json::Value>,
+
+    /// Types (for EIP-712 signatures)
+    #[serde(default)]
+    pub types: Option<serde_json::Value>,
+
+    /// Primary type (for EIP-712 signatures), inferred from the types when absent
+    #[serde(default)]
+    pub primary_type: Option<String>,
 }
 
 /// Verify signature response
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct VerifySignatureResponse {
     /// Is valid
     pub is_valid: bool,
@@ -0,0 +1,62 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+//! Executors of the admin operations the endpoints run after offline approval.
+
+use std::sync::Arc;
+
+use axum::async_trait;
+use r3e_neo_services::gas_bank::rocksdb::RocksDBGasBankStorage;
+use r3e_neo_services::gas_bank::service::{GasBankService, GasBankServiceTrait};
+use r3e_secrets::approval::OperationExecutor;
+use serde::Deserialize;
+use serde_json::Value;
+
+/// Kind of the operation withdrawing gas from a gas bank account
+pub const GAS_BANK_WITHDRAW_OPERATION: &str = "gas_bank.withdraw";
+
+/// Parameters of a gas bank withdrawal
+#[derive(Debug, Deserialize)]
+struct WithdrawParams {
+    /// Account address
+    address: String,
+
+    /// Amount of gas
+    amount: u64,
+}
+
+/// Withdraws gas from a gas bank account
+pub struct GasBankWithdrawExecutor {
+    /// Gas bank service
+    gas_bank_service: Arc<GasBankService<RocksDBGasBankStorage>>,
+}
+
+impl GasBankWithdrawExecutor {
+    /// Create a new gas bank withdrawal executor
+    pub fn new(gas_bank_service: Arc<GasBankService<RocksDBGasBankStorage>>) -> Self {
+        Self { gas_bank_service }
+    }
+}
+
+#[async_trait]
+impl OperationExecutor for GasBankWithdrawExecutor {
+    fn validate(&self, params: &Value) -> Result<(), String> {
+        let params = WithdrawParams::deserialize(params).map_err(|e| e.to_string())?;
+        if params.amount == 0 {
+            return Err("amount must be positive".to_string());
+        }
+        Ok(())
+    }
+
+    async fn execute(&self, params: &Value) -> Result<Value, String> {
+        let params = WithdrawParams::deserialize(params).map_err(|e| e.to_string())?;
+
+        let withdrawal = self
+            .gas_bank_service
+            .withdraw(&params.address, params.amount)
+            .await
+            .map_err(|e| e.to_string())?;
+
+        serde_json::to_value(withdrawal).map_err(|e| e.to_string())
+    }
+}
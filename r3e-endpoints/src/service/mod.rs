@@ -1,23 +1,51 @@
 // Copyright @ 2023 - 2024, R3E Network
 // All Rights Reserved
 
+pub mod approvals;
+
 use std::sync::Arc;
 
 use neo3::neo_clients::{HttpProvider, RpcClient};
-use neo3::neo_crypto::keys::PrivateKey;
-use neo3::neo_protocol::wallet::Wallet;
+use r3e_api::idempotency::IdempotencyStore;
+use r3e_core::chain::ChainClients;
+use r3e_neo_services::chain_state::ChainStateCache;
+use r3e_neo_services::deploy::{DeploymentService, RocksDBDeploymentStorage};
+use r3e_neo_services::fee::{FeeOracle, FeeSchedule};
 use r3e_neo_services::gas_bank::rocksdb::RocksDBGasBankStorage;
 use r3e_neo_services::gas_bank::service::GasBankService;
 use r3e_neo_services::meta_tx::service::MetaTxService;
 use r3e_neo_services::meta_tx::storage::MetaTxStorage;
-use r3e_neo_services::types::FeeModel;
+use r3e_neo_services::meta_tx::types::BlockchainType;
+use r3e_neo_services::meta_tx::ForwarderConfig;
+use r3e_neo_services::meta_tx::{
+    NeoRelayBackend, RocksDBMetaTxStorage, TxManager, TxManagerConfig,
+};
+use r3e_neo_services::mpc::rocksdb::RocksDBMpcStorage;
+use r3e_neo_services::mpc::{
+    LocalParty, MemoryShareStore, MpcParty, MpcService, MpcServiceTrait, SealedShareStore,
+    ShareStore,
+};
+use r3e_neo_services::signer::RelayerSigner;
+use r3e_neo_services::wallet::{RocksDBManagedAccountStorage, WalletService};
+use r3e_secrets::approval::ApprovalService;
+use r3e_secrets::audit::AuditStore;
+use r3e_secrets::aws_kms::AwsKmsWrapper;
+use r3e_secrets::hashicorp::{VaultClient, VaultFunctionKeys, VaultKvStorage};
+use r3e_secrets::rocksdb::{RocksDBAuditStore, RocksDBOperationStore};
 use r3e_secrets::service::{SecretService, SecretServiceImpl};
+use r3e_secrets::storage::SecretStorage;
+use r3e_tee::sealing::{
+    EnclaveIdentity, FileSealedBlobStore, SealPolicy, SealedStorage, SimulatedSealer,
+};
 use sqlx::PgPool;
 use url::Url;
 
+use crate::auth::challenges::{ChallengeConfig, ChallengeStore};
+use crate::auth::jwt_keys::{JwtKeyRing, JwtKeyRingConfig, KeyRingStore};
 use crate::auth::key_rotation::KeyRotationService;
-use crate::config::Config;
+use crate::config::{Config, SecretsBackend};
 use crate::error::Error;
+use crate::service::approvals::{GasBankWithdrawExecutor, GAS_BANK_WITHDRAW_OPERATION};
 
 /// Endpoint service
 pub struct EndpointService {
@@ -30,20 +58,44 @@ pub struct EndpointService {
     /// Neo N3 RPC client
     pub neo_rpc_client: Arc<RpcClient>,
 
-    /// Relayer wallet
-    pub relayer_wallet: Arc<Wallet>,
+    /// Relayer signer
+    pub relayer_signer: Arc<dyn RelayerSigner>,
+
+    /// MPC signing service
+    pub mpc_service: Arc<dyn MpcServiceTrait>,
 
     /// Gas bank service
     pub gas_bank_service: Arc<GasBankService<RocksDBGasBankStorage>>,
 
+    /// Contract deployment service
+    pub deployment_service: Arc<DeploymentService>,
+
     /// Meta transaction service
     pub meta_tx_service: Arc<MetaTxService<dyn MetaTxStorage>>,
 
     /// Secret service
     pub secret_service: Arc<dyn SecretService>,
 
+    /// Accounts imported from NEP-6 wallets, available with a secrets master key
+    pub wallet_service: Option<Arc<WalletService>>,
+
     /// Key rotation service
     pub key_rotation_service: Arc<KeyRotationService>,
+
+    /// JWT signing key ring
+    pub jwt_keys: Arc<JwtKeyRing>,
+
+    /// Hash-chained audit log
+    pub audit_store: Arc<dyn AuditStore>,
+
+    /// Admin operations approved offline
+    pub approval_service: Arc<ApprovalService>,
+
+    /// Idempotency keys of meta transactions
+    pub idempotency_store: IdempotencyStore,
+
+    /// Wallet login challenges
+    pub challenge_store: ChallengeStore,
 }
 
 impl EndpointService {
@@ -63,11 +115,15 @@ impl EndpointService {
 
         let neo_rpc_client = Arc::new(RpcClient::new(neo_provider));
 
-        // Create relayer wallet
-        let private_key = PrivateKey::from_str(&config.relayer_private_key)
-            .map_err(|e| Error::Configuration(format!("Invalid relayer private key: {}", e)))?;
+        // Create the MPC service
+        let mpc_service = create_mpc_service(&config).await?;
 
-        let relayer_wallet = Arc::new(Wallet::from_private_key(private_key));
+        // Create the relayer signer, no TEE key management service is available here
+        let relayer_signer = config
+            .relayer_signer
+            .build(None, Some(mpc_service.clone()))
+            .await
+            .map_err(|e| Error::Configuration(format!("Invalid relayer signer: {}", e)))?;
 
         // Create Gas Bank storage
         let gas_bank_storage = Arc::new(
@@ -78,49 +134,270 @@ impl EndpointService {
                 })?,
         );
 
+        // Cache the Neo N3 block height and network fees, shared by the services building
+        // transactions
+        let chain_state = Arc::new(ChainStateCache::new(&config.neo_rpc_url));
+        chain_state.clone().spawn();
+
+        // Price relayed transactions with the live network fees
+        let fee_schedule = load_fee_schedule(&config)?;
+        let chains = Arc::new(ChainClients::new());
+        let fee_oracle = Arc::new(FeeOracle::new(chains.clone(), chain_state.clone()));
+
         // Create Gas Bank service
-        let gas_bank_service = Arc::new(GasBankService::new(
-            gas_bank_storage,
-            neo_rpc_client.clone(),
-            relayer_wallet.clone(),
-            "mainnet".to_string(),
-            FeeModel::Percentage(1.0),
-            1_000_000_000,
-        ));
+        let gas_bank_service = Arc::new(
+            GasBankService::new(
+                gas_bank_storage,
+                neo_rpc_client.clone(),
+                relayer_signer.clone(),
+                "mainnet".to_string(),
+                fee_schedule.default_model.clone(),
+                1_000_000_000,
+            )
+            .with_chain_state(chain_state.clone()),
+        );
 
         // Create Meta Transaction storage
-        let meta_tx_storage = Arc::new(MockMetaTxStorage::new());
+        let meta_tx_storage = Arc::new(
+            RocksDBMetaTxStorage::new("./data/meta_tx")
+                .await
+                .map_err(|e| {
+                    Error::Database(format!("Failed to create Meta Transaction storage: {}", e))
+                })?,
+        );
 
         // Create Meta Transaction service
-        let meta_tx_service = Arc::new(MetaTxService::new(
-            meta_tx_storage,
-            neo_rpc_client.clone(),
-            relayer_wallet.clone(),
-            "mainnet".to_string(),
-            FeeModel::Percentage(1.0),
+        let meta_tx_service = Arc::new(
+            MetaTxService::new(
+                meta_tx_storage.clone(),
+                neo_rpc_client.clone(),
+                relayer_signer.clone(),
+                "mainnet".to_string(),
+                fee_schedule.default_model.clone(),
+            )
+            .with_chain_clients(chains)
+            .with_chain_state(chain_state.clone())
+            .with_fee_schedule(fee_schedule)
+            .with_fee_oracle(fee_oracle)
+            .with_forwarders(load_forwarders(&config)?),
+        );
+
+        // Rebroadcast stuck relayed transactions with a higher fee
+        let neo_relay_backend = Arc::new(
+            NeoRelayBackend::new(&config.neo_rpc_url, relayer_signer.clone())
+                .await
+                .map_err(|e| {
+                    Error::Network(format!("Failed to create Neo N3 relay backend: {}", e))
+                })?
+                .with_chain_state(chain_state),
+        );
+        Arc::new(
+            TxManager::new(meta_tx_storage, TxManagerConfig::default())
+                .with_backend(BlockchainType::NeoN3, neo_relay_backend.clone()),
+        )
+        .spawn();
+
+        // Deploy function contracts through the relayer, tracking them every 15 seconds
+        let deployment_service = Arc::new(DeploymentService::new(
+            Arc::new(
+                RocksDBDeploymentStorage::new("./data/deployments")
+                    .await
+                    .map_err(|e| {
+                        Error::Database(format!("Failed to create deployment storage: {}", e))
+                    })?,
+            ),
+            gas_bank_service.clone(),
+            neo_relay_backend,
         ));
+        deployment_service
+            .clone()
+            .spawn(std::time::Duration::from_secs(15));
+
+        // Connect to Vault, keeping its token renewed
+        let vault = match &config.vault {
+            Some(vault) => {
+                let vault = Arc::new(
+                    VaultClient::new(vault.clone())
+                        .map_err(|e| Error::Configuration(e.to_string()))?,
+                );
+                vault.clone().spawn_token_renewal();
+                Some(vault)
+            }
+            None => None,
+        };
+
+        // Create Secret storage, with function keys stored wrapped in Vault alongside the
+        // secrets
+        let mut function_keys = None;
+        let secret_storage: Arc<dyn SecretStorage> = match (config.secrets_backend, &vault) {
+            (SecretsBackend::Vault, Some(vault)) => {
+                let keys = match config.aws_kms.as_ref().filter(|kms| kms.key_id.is_some()) {
+                    Some(kms) => VaultFunctionKeys::new(
+                        vault.clone(),
+                        Arc::new(AwsKmsWrapper::new(kms.clone()).map_err(|e| {
+                            Error::Configuration(format!("Failed to create AWS KMS: {}", e))
+                        })?),
+                        kms.key_id.as_deref().unwrap_or_default(),
+                    ),
+                    None => VaultFunctionKeys::with_transit(vault.clone())
+                        .await
+                        .map_err(|e| {
+                            Error::Network(format!("Failed to create function keys: {}", e))
+                        })?,
+                };
+                function_keys = Some(Arc::new(keys));
+                Arc::new(VaultKvStorage::new(vault.clone()))
+            }
+            _ => Arc::new(
+                r3e_secrets::rocksdb::RocksDBSecretStorage::new("./data/secrets")
+                    .await
+                    .map_err(|e| {
+                        Error::Database(format!("Failed to create Secret storage: {}", e))
+                    })?,
+            ),
+        };
+
+        // Seal the secrets of tenants enrolled for encryption at rest with their tenant key
+        let mut secret_service = SecretServiceImpl::new(secret_storage);
+        if let Some(master_key) = &config.secrets_master_key {
+            let master_key = r3e_api::snapshot::parse_key(master_key).map_err(|_| {
+                Error::Configuration("SECRETS_MASTER_KEY must be 32 hex encoded bytes".to_string())
+            })?;
+            let envelope = r3e_api::encryption::with_key_stores(
+                r3e_api::encryption::envelope_service(db.clone(), master_key, None),
+                vault.clone(),
+                config.aws_kms.as_ref(),
+            )
+            .map_err(|e| Error::Configuration(e.to_string()))?;
+            secret_service = secret_service.with_envelope(Arc::new(envelope));
+        }
+        let secret_service: Arc<dyn SecretService> = Arc::new(secret_service);
+
+        // Hold the keys of imported wallet accounts in the secret service, encrypted with
+        // the master key
+        let wallet_service = match &config.secrets_master_key {
+            Some(master_key) => {
+                let master_key = r3e_api::snapshot::parse_key(master_key).map_err(|_| {
+                    Error::Configuration(
+                        "SECRETS_MASTER_KEY must be 32 hex encoded bytes".to_string(),
+                    )
+                })?;
+                let storage = RocksDBManagedAccountStorage::new("./data/managed_accounts")
+                    .await
+                    .map_err(|e| {
+                        Error::Database(format!("Failed to create account storage: {}", e))
+                    })?;
+                Some(Arc::new(WalletService::new(
+                    Arc::new(storage),
+                    secret_service.clone(),
+                    master_key,
+                )))
+            }
+            None => None,
+        };
 
-        // Create Secret service
-        let secret_storage = Arc::new(
-            r3e_secrets::rocksdb::RocksDBSecretStorage::new("./data/secrets")
+        // Create the audit log
+        let audit_store: Arc<dyn AuditStore> = Arc::new(
+            RocksDBAuditStore::new("./data/audit")
                 .await
-                .map_err(|e| Error::Database(format!("Failed to create Secret storage: {}", e)))?,
+                .map_err(|e| Error::Database(format!("Failed to create audit store: {}", e)))?,
         );
 
-        let secret_service = Arc::new(SecretServiceImpl::new(secret_storage));
+        // Withdraw from the gas bank only once an approver signed the withdrawal offline
+        let approval_service = Arc::new(
+            ApprovalService::new(
+                Arc::new(
+                    RocksDBOperationStore::new("./data/operations")
+                        .await
+                        .map_err(|e| {
+                            Error::Database(format!("Failed to create operation store: {}", e))
+                        })?,
+                ),
+                audit_store.clone(),
+                &config.admin_approver_keys,
+            )
+            .map_err(|e| Error::Configuration(e.to_string()))?
+            .with_ttl(config.operation_ttl)
+            .with_executor(
+                GAS_BANK_WITHDRAW_OPERATION,
+                Arc::new(GasBankWithdrawExecutor::new(gas_bank_service.clone())),
+            ),
+        );
 
         // Create Key Rotation service
-        let key_rotation_service = Arc::new(KeyRotationService::new(secret_service.clone()));
+        let mut key_rotation_service = KeyRotationService::new(secret_service.clone());
+        if let Some(function_keys) = function_keys {
+            key_rotation_service = key_rotation_service.with_function_keys(function_keys);
+        }
+        let key_rotation_service = Arc::new(key_rotation_service);
+
+        // Create the JWT key ring, persisted encrypted if a master key is configured
+        let jwt_keys_config = JwtKeyRingConfig {
+            rotation_interval: chrono::Duration::seconds(config.jwt_rotation_interval as i64),
+            grace_period: chrono::Duration::seconds(config.jwt_key_grace_period as i64),
+            reissue: config.jwt_reissue,
+            reissue_before: chrono::Duration::seconds(config.jwt_reissue_before as i64),
+        };
+        let mut jwt_keys = JwtKeyRing::new(jwt_keys_config, &config.jwt_secret);
+        match &config.secrets_master_key {
+            Some(master_key) => {
+                let master_key: [u8; 32] = hex::decode(master_key)
+                    .ok()
+                    .and_then(|key| key.try_into().ok())
+                    .ok_or_else(|| {
+                        Error::Configuration(
+                            "SECRETS_MASTER_KEY must be 32 hex encoded bytes".to_string(),
+                        )
+                    })?;
+                jwt_keys =
+                    jwt_keys.with_store(KeyRingStore::new(secret_service.clone(), master_key));
+            }
+            None => log::warn!("SECRETS_MASTER_KEY is not set, rotated JWT keys are not persisted"),
+        }
+        jwt_keys.load().await?;
+
+        let jwt_keys = Arc::new(jwt_keys);
+        if config.jwt_rotation_interval > 0 {
+            jwt_keys
+                .clone()
+                .spawn_rotation(std::time::Duration::from_secs(60));
+        }
+
+        // Keep the first response of idempotent requests, purging expired ones hourly
+        let idempotency_store = IdempotencyStore::new(
+            db.clone(),
+            std::time::Duration::from_secs(config.idempotency_ttl),
+        );
+        idempotency_store.spawn_purge(std::time::Duration::from_secs(3600));
+
+        // Issue single-use wallet login challenges, sweeping expired ones every minute
+        let challenge_store = ChallengeStore::new(
+            db.clone(),
+            ChallengeConfig {
+                ttl: std::time::Duration::from_secs(config.wallet_challenge_ttl),
+                max_failures: config.wallet_max_failed_attempts,
+                failure_window: std::time::Duration::from_secs(config.wallet_failure_window),
+            },
+        );
+        challenge_store.spawn_sweep(std::time::Duration::from_secs(60));
 
         Ok(Self {
             config,
             db,
             neo_rpc_client,
-            relayer_wallet,
+            relayer_signer,
+            mpc_service,
             gas_bank_service,
+            deployment_service,
             meta_tx_service,
             secret_service,
+            wallet_service,
             key_rotation_service,
+            jwt_keys,
+            audit_store,
+            approval_service,
+            idempotency_store,
+            challenge_store,
         })
     }
 
@@ -130,68 +407,67 @@ impl EndpointService {
     }
 }
 
-// Mock implementation of MetaTxStorage for development
-use async_trait::async_trait;
-use r3e_neo_services::meta_tx::types::{MetaTxRecord, MetaTxStatus};
-use std::collections::HashMap;
-use std::sync::Mutex;
-
-struct MockMetaTxStorage {
-    records: Mutex<HashMap<String, MetaTxRecord>>,
-    nonces: Mutex<HashMap<String, u64>>,
-}
-
-impl MockMetaTxStorage {
-    fn new() -> Self {
-        Self {
-            records: Mutex::new(HashMap::new()),
-            nonces: Mutex::new(HashMap::new()),
-        }
-    }
+/// Create the MPC service with its parties in this process, sealing their shares if a seal
+/// secret is configured
+/// Load the relayer fee models per service
+fn load_fee_schedule(config: &Config) -> Result<FeeSchedule, Error> {
+    let Some(path) = &config.fee_schedule_path else {
+        return Ok(FeeSchedule::default());
+    };
+
+    let data = std::fs::read(path)
+        .map_err(|e| Error::Configuration(format!("Failed to read fee schedule: {}", e)))?;
+    serde_json::from_slice(&data)
+        .map_err(|e| Error::Configuration(format!("Invalid fee schedule {}: {}", path, e)))
 }
 
-#[async_trait]
-impl MetaTxStorage for MockMetaTxStorage {
-    async fn create_record(&self, record: MetaTxRecord) -> Result<(), r3e_neo_services::Error> {
-        let mut records = self.records.lock().unwrap();
-        records.insert(record.request_id.clone(), record);
-        Ok(())
-    }
-
-    async fn update_record(&self, record: MetaTxRecord) -> Result<(), r3e_neo_services::Error> {
-        let mut records = self.records.lock().unwrap();
-        records.insert(record.request_id.clone(), record);
-        Ok(())
-    }
-
-    async fn get_record(
-        &self,
-        request_id: &str,
-    ) -> Result<Option<MetaTxRecord>, r3e_neo_services::Error> {
-        let records = self.records.lock().unwrap();
-        Ok(records.get(request_id).cloned())
-    }
+/// Load the Ethereum forwarders meta transactions are signed for
+fn load_forwarders(config: &Config) -> Result<ForwarderConfig, Error> {
+    let Some(path) = &config.forwarders_path else {
+        return Ok(ForwarderConfig::default());
+    };
 
-    async fn get_records_by_sender(
-        &self,
-        sender: &str,
-    ) -> Result<Vec<MetaTxRecord>, r3e_neo_services::Error> {
-        let records = self.records.lock().unwrap();
-        let mut result = Vec::new();
+    ForwarderConfig::load(path).map_err(|e| Error::Configuration(e.to_string()))
+}
 
-        for record in records.values() {
-            if record.request.sender == sender {
-                result.push(record.clone());
-            }
+async fn create_mpc_service(config: &Config) -> Result<Arc<dyn MpcServiceTrait>, Error> {
+    let mpc_storage = Arc::new(
+        RocksDBMpcStorage::new("./data/mpc")
+            .await
+            .map_err(|e| Error::Database(format!("Failed to create MPC storage: {}", e)))?,
+    );
+
+    let sealed_storage = match &config.mpc_seal_secret {
+        Some(secret) => {
+            // Sealed to the signer, so shares survive upgrades of the service
+            let identity = EnclaveIdentity::simulated(b"r3e-endpoints-mpc", "r3e-endpoints");
+            let sealer = SimulatedSealer::new(identity, secret.as_bytes())
+                .map_err(|e| Error::Configuration(format!("Invalid MPC seal secret: {}", e)))?;
+            let store = FileSealedBlobStore::new("./data/mpc_shares")
+                .map_err(|e| Error::Database(format!("Failed to create MPC share store: {}", e)))?;
+            Some(Arc::new(
+                SealedStorage::new(Arc::new(sealer), Arc::new(store))
+                    .with_policy(SealPolicy::MrSigner),
+            ))
         }
-
-        Ok(result)
+        None => {
+            log::warn!("MPC_SEAL_SECRET is not set, MPC key shares are not persisted");
+            None
+        }
+    };
+
+    let mut parties: Vec<Arc<dyn MpcParty>> = Vec::new();
+    for i in 1..=config.mpc_parties {
+        let id = format!("party-{}", i);
+        let shares: Arc<dyn ShareStore> = match &sealed_storage {
+            Some(storage) => Arc::new(SealedShareStore::new(storage.clone(), &id)),
+            None => Arc::new(MemoryShareStore::new()),
+        };
+        parties.push(Arc::new(LocalParty::new(&id, shares)));
     }
 
-    async fn get_nonce(&self, sender: &str) -> Result<u64, r3e_neo_services::Error> {
-        let mut nonces = self.nonces.lock().unwrap();
-        let nonce = nonces.entry(sender.to_string()).or_insert(0);
-        *nonce += 1;
-        Ok(*nonce)
-    }
+    let mpc_service = MpcService::new(mpc_storage, parties)
+        .await
+        .map_err(|e| Error::Configuration(format!("Invalid MPC parties: {}", e)))?;
+    Ok(Arc::new(mpc_service))
 }
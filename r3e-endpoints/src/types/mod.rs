@@ -2,11 +2,12 @@
 // All Rights Reserved
 
 use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 use uuid::Uuid;
 use validator::{Validate, ValidationError};
 
 /// Blockchain type
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
 #[serde(rename_all = "lowercase")]
 pub enum BlockchainType {
     /// Neo N3 blockchain
@@ -17,7 +18,7 @@ pub enum BlockchainType {
 }
 
 /// Signature curve
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
 #[serde(rename_all = "lowercase")]
 pub enum SignatureCurve {
     /// secp256r1 curve (used by Neo N3)
@@ -28,7 +29,7 @@ pub enum SignatureCurve {
 }
 
 /// Wallet connection request
-#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+#[derive(Debug, Clone, Serialize, Deserialize, Validate, ToSchema)]
 pub struct WalletConnectionRequest {
     /// Blockchain type
     pub blockchain_type: BlockchainType,
@@ -46,17 +47,29 @@ pub struct WalletConnectionRequest {
     #[validate(custom = "validate_signature")]
     pub signature: String,
 
-    /// Message that was signed
+    /// Message that was signed, the JSON message for EIP-712 signatures
     #[validate(length(min = 1, message = "Message cannot be empty"))]
     pub message: String,
 
+    /// Domain (for EIP-712 signatures)
+    #[serde(default)]
+    pub domain: Option<serde_json::Value>,
+
+    /// Types (for EIP-712 signatures)
+    #[serde(default)]
+    pub types: Option<serde_json::Value>,
+
+    /// Primary type (for EIP-712 signatures), inferred from the types when absent
+    #[serde(default)]
+    pub primary_type: Option<String>,
+
     /// Timestamp
     #[validate(custom = "validate_timestamp")]
     pub timestamp: u64,
 }
 
 /// Wallet connection response
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct WalletConnectionResponse {
     /// Connection ID
     pub connection_id: String,
@@ -75,7 +88,7 @@ pub struct WalletConnectionResponse {
 }
 
 /// Message signing request
-#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+#[derive(Debug, Clone, Serialize, Deserialize, Validate, ToSchema)]
 pub struct MessageSigningRequest {
     /// Connection ID
     #[validate(length(min = 1, message = "Connection ID cannot be empty"))]
@@ -91,13 +104,17 @@ pub struct MessageSigningRequest {
     /// Types (for EIP-712 signatures)
     pub types: Option<serde_json::Value>,
 
+    /// Primary type (for EIP-712 signatures), inferred from the types when absent
+    #[serde(default)]
+    pub primary_type: Option<String>,
+
     /// Timestamp
     #[validate(custom = "validate_timestamp")]
     pub timestamp: u64,
 }
 
 /// Message signing response
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct MessageSigningResponse {
     /// Request ID
     pub request_id: String,
@@ -116,7 +133,7 @@ mod validation;
 pub use validation::*;
 
 /// Service invocation request
-#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+#[derive(Debug, Clone, Serialize, Deserialize, Validate, ToSchema)]
 pub struct ServiceInvocationRequest {
     /// Service ID
     #[validate(custom = "validate_uuid")]
@@ -139,7 +156,7 @@ pub struct ServiceInvocationRequest {
 }
 
 /// Service invocation response
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct ServiceInvocationResponse {
     /// Invocation ID
     pub invocation_id: String,
@@ -161,7 +178,7 @@ pub struct ServiceInvocationResponse {
 }
 
 /// Meta transaction request
-#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+#[derive(Debug, Clone, Serialize, Deserialize, Validate, ToSchema)]
 pub struct MetaTransactionRequest {
     /// Transaction data
     #[validate(length(min = 1, message = "Transaction data cannot be empty"))]
@@ -189,13 +206,41 @@ pub struct MetaTransactionRequest {
     /// Signature curve
     pub signature_curve: SignatureCurve,
 
+    /// Fee model of the service, as quoted by `/meta-tx/quote`
+    #[serde(default)]
+    pub fee_model: Option<String>,
+
+    /// Fee paid to the relayer, as quoted by `/meta-tx/quote`
+    #[serde(default)]
+    pub fee_amount: u64,
+
     /// Timestamp
     #[validate(custom = "validate_timestamp")]
     pub timestamp: u64,
 }
 
+/// Meta transaction fee quote request
+#[derive(Debug, Clone, Serialize, Deserialize, Validate, ToSchema)]
+pub struct MetaTransactionQuoteRequest {
+    /// Transaction data
+    #[validate(length(min = 1, message = "Transaction data cannot be empty"))]
+    pub tx_data: String,
+
+    /// Sender address
+    pub sender: String,
+
+    /// Blockchain type
+    pub blockchain_type: BlockchainType,
+
+    /// Target contract, naming the service the transaction is relayed for
+    pub target_contract: Option<String>,
+
+    /// Chain ID (for Ethereum transactions)
+    pub chain_id: Option<u64>,
+}
+
 /// Meta transaction response
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct MetaTransactionResponse {
     /// Request ID
     pub request_id: String,
@@ -0,0 +1,163 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+//! EIP-712 typed structured data hashing and signature verification.
+
+use ethers_core::types::transaction::eip712::{Eip712, TypedData, Types};
+use ethers_core::types::{Address, RecoveryMessage, Signature};
+use serde_json::Value;
+
+use crate::error::Error;
+
+/// Name of the domain type, which is never the primary type of a message
+const DOMAIN_TYPE: &str = "EIP712Domain";
+
+/// Typed data as wallets sign it with `eth_signTypedData_v4`
+pub fn typed_data(
+    domain: &Value,
+    types: &Value,
+    primary_type: Option<&str>,
+    message: &Value,
+) -> Result<TypedData, Error> {
+    let parsed_types: Types = serde_json::from_value(types.clone())
+        .map_err(|e| Error::Validation(format!("Invalid EIP-712 types: {}", e)))?;
+    let primary_type = match primary_type {
+        Some(primary_type) => primary_type.to_string(),
+        None => infer_primary_type(&parsed_types)?,
+    };
+    if !parsed_types.contains_key(&primary_type) {
+        return Err(Error::Validation(format!(
+            "EIP-712 primary type {} is not defined",
+            primary_type
+        )));
+    }
+
+    serde_json::from_value(serde_json::json!({
+        "domain": domain,
+        "types": types,
+        "primaryType": primary_type,
+        "message": message,
+    }))
+    .map_err(|e| Error::Validation(format!("Invalid EIP-712 typed data: {}", e)))
+}
+
+/// The primary type is the only type no other type refers to
+fn infer_primary_type(types: &Types) -> Result<String, Error> {
+    let referenced = |name: &str| {
+        types.values().flatten().any(|field| {
+            let base = field.r#type.split('[').next().unwrap_or_default();
+            base == name
+        })
+    };
+
+    let roots: Vec<_> = types
+        .keys()
+        .filter(|name| name.as_str() != DOMAIN_TYPE && !referenced(name))
+        .collect();
+    match roots.as_slice() {
+        [root] => Ok(root.to_string()),
+        [] => Err(Error::Validation(
+            "EIP-712 types have no primary type".to_string(),
+        )),
+        _ => Err(Error::Validation(format!(
+            "EIP-712 primary type is ambiguous between {}, it must be given",
+            roots
+                .iter()
+                .map(|name| name.as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        ))),
+    }
+}
+
+/// `keccak256(0x1901 || domainSeparator || hashStruct(message))`
+pub fn typed_data_hash(typed_data: &TypedData) -> Result<[u8; 32], Error> {
+    typed_data
+        .encode_eip712()
+        .map_err(|e| Error::Validation(format!("Failed to hash EIP-712 typed data: {}", e)))
+}
+
+/// Check that `signature` over the typed data was made by `address`
+pub fn verify_typed_data_signature(
+    address: &str,
+    typed_data: &TypedData,
+    signature: &str,
+) -> Result<bool, Error> {
+    let address = address
+        .parse::<Address>()
+        .map_err(|e| Error::Validation(format!("Invalid Ethereum address: {}", e)))?;
+    let signature = signature
+        .parse::<Signature>()
+        .map_err(|e| Error::Validation(format!("Invalid signature encoding: {}", e)))?;
+    let hash = typed_data_hash(typed_data)?;
+
+    match signature.recover(RecoveryMessage::Hash(hash.into())) {
+        Ok(signer) => Ok(signer == address),
+        // A signature no key could have made is not a valid signature of the address
+        Err(_) => Ok(false),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    // The `Mail` example of the EIP-712 specification, signed with the key `keccak256("cow")`
+    fn mail() -> (Value, Value, Value) {
+        let domain = json!({
+            "name": "Ether Mail",
+            "version": "1",
+            "chainId": 1,
+            "verifyingContract": "0xCcCCccccCCCCcCCCCCCcCcCccCcCCCcCcccccccC"
+        });
+        let types = json!({
+            "EIP712Domain": [
+                { "name": "name", "type": "string" },
+                { "name": "version", "type": "string" },
+                { "name": "chainId", "type": "uint256" },
+                { "name": "verifyingContract", "type": "address" }
+            ],
+            "Person": [
+                { "name": "name", "type": "string" },
+                { "name": "wallet", "type": "address" }
+            ],
+            "Mail": [
+                { "name": "from", "type": "Person" },
+                { "name": "to", "type": "Person" },
+                { "name": "contents", "type": "string" }
+            ]
+        });
+        let message = json!({
+            "from": { "name": "Cow", "wallet": "0xCD2a3d9F938E13CD947Ec05AbC7FE734Df8DD826" },
+            "to": { "name": "Bob", "wallet": "0xbBbBBBBbbBBBbbbBbbBbbbbBBbBbbbbBbBbbBBbB" },
+            "contents": "Hello, Bob!"
+        });
+        (domain, types, message)
+    }
+
+    #[test]
+    fn test_typed_data_hash() {
+        let (domain, types, message) = mail();
+        let typed_data = typed_data(&domain, &types, None, &message).unwrap();
+        assert_eq!(typed_data.primary_type, "Mail");
+        assert_eq!(
+            hex::encode(typed_data_hash(&typed_data).unwrap()),
+            "be609aee343fb3c4b28e1df9e632fca64fcfaede20f02e86244efddf30957bd2"
+        );
+    }
+
+    #[test]
+    fn test_verify_typed_data_signature() {
+        let (domain, types, message) = mail();
+        let typed_data = typed_data(&domain, &types, Some("Mail"), &message).unwrap();
+        let signature = "0x4355c47d63924e8a72e509b65029052eb6c299d53a04e167c5775fd466751c9d\
+                         07299936d304c153f6443dfa05f40ff007d72911b6f72307f996231605b915621c";
+
+        let cow = "0xCD2a3d9F938E13CD947Ec05AbC7FE734Df8DD826";
+        assert!(verify_typed_data_signature(cow, &typed_data, signature).unwrap());
+
+        let bob = "0xbBbBBBBbbBBBbbbBbbBbbbbBBbBbbbbBbBbbBBbB";
+        assert!(!verify_typed_data_signature(bob, &typed_data, signature).unwrap());
+    }
+}
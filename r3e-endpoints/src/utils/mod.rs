@@ -1,12 +1,15 @@
 // Copyright @ 2023 - 2024, R3E Network
 // All Rights Reserved
 
+use crate::auth::jwt_keys::JwtKeyRing;
 use crate::error::Error;
 use crate::types::{BlockchainType, SignatureCurve};
 use ethers_core::types::Signature as EthSignature;
 use neo3::neo_crypto::keys::PublicKey;
 use neo3::neo_types::address::Address;
 
+pub mod eip712;
+
 /// Verify a signature
 pub fn verify_signature(
     blockchain_type: &str,
@@ -192,16 +195,39 @@ pub fn verify_signature(
     }
 }
 
+/// Verify an EIP-712 typed-data signature, `message` being the JSON message that was signed
+pub fn verify_typed_data_signature(
+    blockchain_type: &BlockchainType,
+    address: &str,
+    domain: Option<&serde_json::Value>,
+    types: &serde_json::Value,
+    primary_type: Option<&str>,
+    message: &str,
+    signature: &str,
+) -> Result<bool, Error> {
+    if *blockchain_type != BlockchainType::Ethereum {
+        return Err(Error::Validation(
+            "Typed-data signatures are only supported for Ethereum".into(),
+        ));
+    }
+
+    let message = serde_json::from_str::<serde_json::Value>(message)
+        .map_err(|e| Error::Validation(format!("Typed-data message is not JSON: {}", e)))?;
+    let domain = domain.cloned().unwrap_or_else(|| serde_json::json!({}));
+    let typed_data = eip712::typed_data(&domain, types, primary_type, &message)?;
+
+    eip712::verify_typed_data_signature(address, &typed_data, signature)
+}
+
 /// Generate JWT token
 pub fn generate_jwt_token(
     address: &str,
     blockchain_type: &BlockchainType,
     connection_id: &str,
-    jwt_secret: &str,
+    jwt_keys: &JwtKeyRing,
     jwt_expiration: u64,
 ) -> Result<String, Error> {
     use chrono::Utc;
-    use jsonwebtoken::{encode, EncodingKey, Header};
     use serde::{Deserialize, Serialize};
 
     #[derive(Debug, Serialize, Deserialize)]
@@ -232,18 +258,12 @@ pub fn generate_jwt_token(
         exp: now + jwt_expiration,
     };
 
-    // Create JWT token
-    encode(
-        &Header::default(),
-        &claims,
-        &EncodingKey::from_secret(jwt_secret.as_bytes()),
-    )
-    .map_err(|e| Error::Internal(format!("Failed to create JWT token: {}", e)))
+    // Create JWT token, signed by the active key
+    jwt_keys.sign(&claims)
 }
 
-/// Verify JWT token
-pub fn verify_jwt_token(token: &str, jwt_secret: &str) -> Result<JwtClaims, Error> {
-    use jsonwebtoken::{decode, DecodingKey, Validation};
+/// Verify JWT token against the key ring
+pub fn verify_jwt_token(token: &str, jwt_keys: &JwtKeyRing) -> Result<JwtClaims, Error> {
     use serde::{Deserialize, Serialize};
 
     #[derive(Debug, Serialize, Deserialize)]
@@ -264,13 +284,8 @@ pub fn verify_jwt_token(token: &str, jwt_secret: &str) -> Result<JwtClaims, Erro
         pub exp: u64,
     }
 
-    // Decode JWT token
-    let token_data = decode::<JwtClaims>(
-        token,
-        &DecodingKey::from_secret(jwt_secret.as_bytes()),
-        &Validation::default(),
-    )
-    .map_err(|e| Error::Authentication(format!("Invalid JWT token: {}", e)))?;
+    // Decode JWT token with the key it was signed by
+    let token = jwt_keys.verify::<JwtClaims>(token)?;
 
-    Ok(token_data.claims)
+    Ok(token.claims)
 }
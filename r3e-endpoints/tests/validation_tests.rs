@@ -146,6 +146,9 @@ async fn test_validation_middleware_wallet_connection() {
         signature_curve: r3e_endpoints::types::SignatureCurve::Secp256r1,
         signature: "0x1234567890abcdef1234567890abcdef1234567890abcdef1234567890abcdef".to_string(),
         message: "Connect wallet to R3E FaaS".to_string(),
+        domain: None,
+        types: None,
+        primary_type: None,
         timestamp: chrono::Utc::now().timestamp() as u64,
     };
 
@@ -61,6 +61,11 @@ export default async function(event) {
   };
 }
 "#.to_string(),
+        code_hash: None,
+        source_map: None,
+        owner: None,
+        input_schema: None,
+        output_schema: None,
     }
 }
 
@@ -122,6 +127,11 @@ export default async function(event) {
 }
 "#
         .to_string(),
+        code_hash: None,
+        source_map: None,
+        owner: None,
+        input_schema: None,
+        output_schema: None,
     }
 }
 
@@ -194,6 +204,11 @@ export default async function(event) {
 }
 "#
         .to_string(),
+        code_hash: None,
+        source_map: None,
+        owner: None,
+        input_schema: None,
+        output_schema: None,
     }
 }
 
@@ -305,6 +320,11 @@ export default async function(request) {
   };
 }
 "#.to_string(),
+        code_hash: None,
+        source_map: None,
+        owner: None,
+        input_schema: None,
+        output_schema: None,
     }
 }
 
@@ -433,6 +453,11 @@ export default async function(request) {
 }
 "#
         .to_string(),
+        code_hash: None,
+        source_map: None,
+        owner: None,
+        input_schema: None,
+        output_schema: None,
     }
 }
 
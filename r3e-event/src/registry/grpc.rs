@@ -0,0 +1,665 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+//! Dynamic gRPC client.
+//!
+//! Service schemas are fetched at runtime through gRPC server reflection into a descriptor
+//! pool, so registered services can be invoked with JSON parameters without generated code.
+
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use prost::Message;
+use prost_reflect::{DescriptorPool, DynamicMessage, MessageDescriptor, MethodDescriptor};
+use prost_types::{FileDescriptorProto, FileDescriptorSet};
+use r3e_core::egress::EgressGuard;
+use serde_json::Value;
+use tokio::net::TcpStream;
+use tokio::sync::RwLock;
+use tonic::codec::{Codec, DecodeBuf, Decoder, EncodeBuf, Encoder};
+use tonic::codegen::http::uri::PathAndQuery;
+use tonic::metadata::MetadataValue;
+use tonic::transport::{Certificate, Channel, ClientTlsConfig, Endpoint, Uri};
+use tonic::{Code, Status};
+use tonic_reflection::pb::server_reflection_client::ServerReflectionClient;
+use tonic_reflection::pb::server_reflection_request::MessageRequest;
+use tonic_reflection::pb::server_reflection_response::MessageResponse;
+use tonic_reflection::pb::ServerReflectionRequest;
+
+/// Default deadline of a gRPC call
+pub const DEFAULT_GRPC_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// gRPC invocation error
+#[derive(Debug, thiserror::Error)]
+pub enum GrpcError {
+    #[error("grpc: invalid configuration: {0}")]
+    Config(String),
+
+    #[error("grpc: failed to connect to {0}: {1}")]
+    Connect(String, String),
+
+    #[error("grpc: reflection failed: {0}")]
+    Reflection(String),
+
+    #[error("grpc: method not found: {0}")]
+    MethodNotFound(String),
+
+    #[error("grpc: streaming method {0} is not supported")]
+    Streaming(String),
+
+    #[error("grpc: invalid request for {0}: {1}")]
+    InvalidRequest(String, String),
+
+    #[error("grpc: invalid response: {0}")]
+    InvalidResponse(String),
+
+    #[error("grpc: call failed with {code:?}: {message}")]
+    Status { code: Code, message: String },
+}
+
+impl From<Status> for GrpcError {
+    fn from(status: Status) -> Self {
+        GrpcError::Status {
+            code: status.code(),
+            message: status.message().to_string(),
+        }
+    }
+}
+
+/// Target of a gRPC call
+#[derive(Debug, Clone)]
+pub struct GrpcTarget {
+    /// Endpoint, e.g. `https://svc.example.com:443` or `host:port` for plaintext
+    pub endpoint: String,
+
+    /// Use TLS, implied by an `https://` endpoint
+    pub tls: bool,
+
+    /// PEM encoded CA certificate trusted in addition to the system roots
+    pub ca_cert_pem: Option<String>,
+
+    /// TLS server name, the endpoint host by default
+    pub tls_domain: Option<String>,
+
+    /// Deadline of the call, sent to the server as `grpc-timeout`
+    pub timeout: Duration,
+}
+
+impl GrpcTarget {
+    /// Build a target from a service adapter config, with an optional per-function timeout
+    pub fn from_config(
+        config: &serde_json::Map<String, Value>,
+        function_timeout_ms: Option<u64>,
+    ) -> Result<Self, GrpcError> {
+        let endpoint = match config.get("endpoint") {
+            Some(Value::String(endpoint)) => endpoint.clone(),
+            _ => {
+                return Err(GrpcError::Config(
+                    "missing or invalid endpoint in adapter configuration".to_string(),
+                ))
+            }
+        };
+
+        let timeout_ms =
+            function_timeout_ms.or_else(|| config.get("timeout_ms").and_then(Value::as_u64));
+        Ok(Self {
+            tls: endpoint.starts_with("https://")
+                || matches!(config.get("tls"), Some(Value::Bool(true))),
+            endpoint,
+            ca_cert_pem: config
+                .get("ca_cert_pem")
+                .and_then(Value::as_str)
+                .map(str::to_string),
+            tls_domain: config
+                .get("tls_domain")
+                .and_then(Value::as_str)
+                .map(str::to_string),
+            timeout: timeout_ms
+                .map(Duration::from_millis)
+                .unwrap_or(DEFAULT_GRPC_TIMEOUT),
+        })
+    }
+
+    fn url(&self) -> String {
+        if self.endpoint.contains("://") {
+            self.endpoint.clone()
+        } else if self.tls {
+            format!("https://{}", self.endpoint)
+        } else {
+            format!("http://{}", self.endpoint)
+        }
+    }
+}
+
+/// Dynamic gRPC client, caching the descriptor pool fetched from each endpoint
+#[derive(Clone, Default)]
+pub struct DynamicGrpcClient {
+    egress_guard: EgressGuard,
+    pools: Arc<RwLock<HashMap<String, DescriptorPool>>>,
+}
+
+impl DynamicGrpcClient {
+    /// Create a new dynamic gRPC client, connecting only where the egress guard allows
+    pub fn new(egress_guard: EgressGuard) -> Self {
+        Self {
+            egress_guard,
+            pools: Arc::new(RwLock::new(HashMap::new())),
+        }
+    }
+
+    /// Forget the cached schemas of an endpoint, e.g. after the service was redeployed
+    pub async fn invalidate(&self, endpoint: &str) {
+        self.pools.write().await.remove(endpoint);
+    }
+
+    /// Invoke a unary method with JSON parameters, returning the JSON response
+    pub async fn invoke(
+        &self,
+        target: &GrpcTarget,
+        service: &str,
+        method: &str,
+        parameters: &Value,
+        metadata: &[(&'static str, String)],
+    ) -> Result<Value, GrpcError> {
+        let channel = self.connect(target).await?;
+        let method = self.method(target, &channel, service, method).await?;
+        if method.is_client_streaming() || method.is_server_streaming() {
+            return Err(GrpcError::Streaming(method.full_name().to_string()));
+        }
+
+        let message =
+            DynamicMessage::deserialize(method.input(), parameters.clone()).map_err(|e| {
+                GrpcError::InvalidRequest(method.full_name().to_string(), e.to_string())
+            })?;
+
+        let mut request = tonic::Request::new(message);
+        request.set_timeout(target.timeout);
+        for (key, value) in metadata {
+            let value = MetadataValue::try_from(value.as_str())
+                .map_err(|e| GrpcError::Config(format!("invalid metadata {}: {}", key, e)))?;
+            request.metadata_mut().insert(*key, value);
+        }
+
+        let path = PathAndQuery::from_str(&format!(
+            "/{}/{}",
+            method.parent_service().full_name(),
+            method.name()
+        ))
+        .map_err(|e| GrpcError::Config(e.to_string()))?;
+
+        let mut client = tonic::client::Grpc::new(channel);
+        client
+            .ready()
+            .await
+            .map_err(|e| GrpcError::Connect(target.endpoint.clone(), e.to_string()))?;
+        let response = client
+            .unary(request, path, DynamicCodec::new(method.output()))
+            .await?;
+
+        serde_json::to_value(response.into_inner())
+            .map_err(|e| GrpcError::InvalidResponse(e.to_string()))
+    }
+
+    /// Connect to a target, pinned to the addresses checked by the egress guard
+    async fn connect(&self, target: &GrpcTarget) -> Result<Channel, GrpcError> {
+        let url = target.url();
+        let resolved = self
+            .egress_guard
+            .check_url(&url)
+            .await
+            .map_err(|e| GrpcError::Connect(target.endpoint.clone(), e.to_string()))?;
+
+        let mut endpoint = Endpoint::from_shared(url)
+            .map_err(|e| GrpcError::Config(e.to_string()))?
+            .connect_timeout(target.timeout)
+            .timeout(target.timeout);
+        if target.tls {
+            let mut tls = ClientTlsConfig::new().domain_name(
+                target
+                    .tls_domain
+                    .clone()
+                    .unwrap_or_else(|| resolved.host.clone()),
+            );
+            if let Some(ca_cert_pem) = &target.ca_cert_pem {
+                tls = tls.ca_certificate(Certificate::from_pem(ca_cert_pem));
+            }
+            endpoint = endpoint
+                .tls_config(tls)
+                .map_err(|e| GrpcError::Config(e.to_string()))?;
+        }
+
+        let addrs = Arc::new(resolved.addrs);
+        endpoint
+            .connect_with_connector(tower::service_fn(move |_: Uri| {
+                let addrs = addrs.clone();
+                async move { TcpStream::connect(addrs.as_slice()).await }
+            }))
+            .await
+            .map_err(|e| GrpcError::Connect(target.endpoint.clone(), e.to_string()))
+    }
+
+    /// Find a method, fetching the endpoint's schemas if it is not cached yet
+    async fn method(
+        &self,
+        target: &GrpcTarget,
+        channel: &Channel,
+        service: &str,
+        method: &str,
+    ) -> Result<MethodDescriptor, GrpcError> {
+        let find = |pool: &DescriptorPool| {
+            pool.get_service_by_name(service)
+                .and_then(|service| service.methods().find(|m| m.name() == method))
+        };
+
+        if let Some(pool) = self.pools.read().await.get(&target.endpoint) {
+            if let Some(method) = find(pool) {
+                return Ok(method);
+            }
+        }
+
+        // Not cached, or the schema changed since it was fetched
+        let pool = fetch_descriptor_pool(channel.clone(), service).await?;
+        let found = find(&pool);
+        self.pools
+            .write()
+            .await
+            .insert(target.endpoint.clone(), pool);
+
+        found.ok_or_else(|| GrpcError::MethodNotFound(format!("{}/{}", service, method)))
+    }
+}
+
+/// Fetch the file defining a service and all its dependencies through server reflection
+async fn fetch_descriptor_pool(
+    channel: Channel,
+    service: &str,
+) -> Result<DescriptorPool, GrpcError> {
+    let mut client = ServerReflectionClient::new(channel);
+    let mut files: HashMap<String, FileDescriptorProto> = HashMap::new();
+    let mut pending = vec![MessageRequest::FileContainingSymbol(service.to_string())];
+
+    while let Some(request) = pending.pop() {
+        for file in reflect(&mut client, request).await? {
+            if files.contains_key(file.name()) {
+                continue;
+            }
+            for dependency in &file.dependency {
+                if !files.contains_key(dependency) {
+                    pending.push(MessageRequest::FileByFilename(dependency.clone()));
+                }
+            }
+            files.insert(file.name().to_string(), file);
+        }
+    }
+
+    DescriptorPool::from_file_descriptor_set(FileDescriptorSet {
+        file: files.into_values().collect(),
+    })
+    .map_err(|e| GrpcError::Reflection(e.to_string()))
+}
+
+async fn reflect(
+    client: &mut ServerReflectionClient<Channel>,
+    request: MessageRequest,
+) -> Result<Vec<FileDescriptorProto>, GrpcError> {
+    let request = ServerReflectionRequest {
+        host: String::new(),
+        message_request: Some(request),
+    };
+    let mut responses = client
+        .server_reflection_info(tokio_stream::iter(vec![request]))
+        .await
+        .map_err(|e| GrpcError::Reflection(e.message().to_string()))?
+        .into_inner();
+
+    let response = responses
+        .message()
+        .await
+        .map_err(|e| GrpcError::Reflection(e.message().to_string()))?
+        .ok_or_else(|| GrpcError::Reflection("no response".to_string()))?;
+
+    match response.message_response {
+        Some(MessageResponse::FileDescriptorResponse(response)) => response
+            .file_descriptor_proto
+            .iter()
+            .map(|file| {
+                FileDescriptorProto::decode(file.as_slice())
+                    .map_err(|e| GrpcError::Reflection(e.to_string()))
+            })
+            .collect(),
+        Some(MessageResponse::ErrorResponse(error)) => {
+            Err(GrpcError::Reflection(error.error_message))
+        }
+        _ => Err(GrpcError::Reflection("unexpected response".to_string())),
+    }
+}
+
+/// Codec encoding and decoding dynamic messages
+struct DynamicCodec {
+    output: MessageDescriptor,
+}
+
+impl DynamicCodec {
+    fn new(output: MessageDescriptor) -> Self {
+        Self { output }
+    }
+}
+
+impl Codec for DynamicCodec {
+    type Encode = DynamicMessage;
+    type Decode = DynamicMessage;
+    type Encoder = DynamicEncoder;
+    type Decoder = DynamicDecoder;
+
+    fn encoder(&mut self) -> Self::Encoder {
+        DynamicEncoder
+    }
+
+    fn decoder(&mut self) -> Self::Decoder {
+        DynamicDecoder(self.output.clone())
+    }
+}
+
+struct DynamicEncoder;
+
+impl Encoder for DynamicEncoder {
+    type Item = DynamicMessage;
+    type Error = Status;
+
+    fn encode(&mut self, item: Self::Item, dst: &mut EncodeBuf<'_>) -> Result<(), Self::Error> {
+        item.encode(dst)
+            .map_err(|e| Status::internal(format!("failed to encode request: {}", e)))
+    }
+}
+
+struct DynamicDecoder(MessageDescriptor);
+
+impl Decoder for DynamicDecoder {
+    type Item = DynamicMessage;
+    type Error = Status;
+
+    fn decode(&mut self, src: &mut DecodeBuf<'_>) -> Result<Option<Self::Item>, Self::Error> {
+        DynamicMessage::decode(self.0.clone(), src)
+            .map(Some)
+            .map_err(|e| Status::internal(format!("failed to decode response: {}", e)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use prost_reflect::Value as ReflectValue;
+    use prost_types::field_descriptor_proto::{Label, Type};
+    use prost_types::{
+        DescriptorProto, FieldDescriptorProto, MethodDescriptorProto, ServiceDescriptorProto,
+    };
+    use serde_json::json;
+    use std::convert::Infallible;
+    use std::task::{Context, Poll};
+    use tonic::body::BoxBody;
+    use tonic::codegen::{http, BoxFuture, Service};
+    use tonic::server::{Grpc, UnaryService};
+    use tonic::transport::{Body, NamedService, Server};
+
+    fn message(name: &str, fields: &[(&str, i32, Type)]) -> DescriptorProto {
+        DescriptorProto {
+            name: Some(name.to_string()),
+            field: fields
+                .iter()
+                .map(|(name, number, field_type)| FieldDescriptorProto {
+                    name: Some(name.to_string()),
+                    number: Some(*number),
+                    label: Some(Label::Optional as i32),
+                    r#type: Some(*field_type as i32),
+                    json_name: Some(name.to_string()),
+                    ..Default::default()
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    fn method(name: &str, server_streaming: bool) -> MethodDescriptorProto {
+        MethodDescriptorProto {
+            name: Some(name.to_string()),
+            input_type: Some(".test.EchoRequest".to_string()),
+            output_type: Some(".test.EchoResponse".to_string()),
+            server_streaming: Some(server_streaming),
+            ..Default::default()
+        }
+    }
+
+    /// Schema of `test.Echo`, with a unary `Echo` and a server streaming `Watch` method
+    fn echo_schema() -> FileDescriptorSet {
+        FileDescriptorSet {
+            file: vec![FileDescriptorProto {
+                name: Some("echo.proto".to_string()),
+                package: Some("test".to_string()),
+                message_type: vec![
+                    message("EchoRequest", &[("text", 1, Type::String)]),
+                    message(
+                        "EchoResponse",
+                        &[("text", 1, Type::String), ("length", 2, Type::Int32)],
+                    ),
+                ],
+                service: vec![ServiceDescriptorProto {
+                    name: Some("Echo".to_string()),
+                    method: vec![method("Echo", false), method("Watch", true)],
+                    ..Default::default()
+                }],
+                syntax: Some("proto3".to_string()),
+                ..Default::default()
+            }],
+        }
+    }
+
+    /// `test.Echo` server, echoing the text of requests authorized with `Bearer token`
+    #[derive(Clone)]
+    struct EchoServer(DescriptorPool);
+
+    impl NamedService for EchoServer {
+        const NAME: &'static str = "test.Echo";
+    }
+
+    impl Service<http::Request<Body>> for EchoServer {
+        type Response = http::Response<BoxBody>;
+        type Error = Infallible;
+        type Future = BoxFuture<Self::Response, Self::Error>;
+
+        fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, request: http::Request<Body>) -> Self::Future {
+            let pool = self.0.clone();
+            Box::pin(async move {
+                let input = pool.get_message_by_name("test.EchoRequest").unwrap();
+                let mut grpc = Grpc::new(DynamicCodec::new(input));
+                Ok(grpc.unary(EchoMethod(pool), request).await)
+            })
+        }
+    }
+
+    struct EchoMethod(DescriptorPool);
+
+    impl UnaryService<DynamicMessage> for EchoMethod {
+        type Response = DynamicMessage;
+        type Future = BoxFuture<tonic::Response<DynamicMessage>, Status>;
+
+        fn call(&mut self, request: tonic::Request<DynamicMessage>) -> Self::Future {
+            let output = self.0.get_message_by_name("test.EchoResponse").unwrap();
+            Box::pin(async move {
+                let authorization = request.metadata().get("authorization");
+                if authorization.map(|value| value.as_bytes()) != Some(b"Bearer token") {
+                    return Err(Status::unauthenticated("invalid token"));
+                }
+                if request.metadata().get("grpc-timeout").is_none() {
+                    return Err(Status::invalid_argument("missing deadline"));
+                }
+
+                let text = request
+                    .get_ref()
+                    .get_field_by_name("text")
+                    .and_then(|text| text.as_str().map(str::to_string))
+                    .unwrap_or_default();
+                let mut response = DynamicMessage::new(output);
+                response.set_field_by_name("length", ReflectValue::I32(text.len() as i32));
+                response.set_field_by_name("text", ReflectValue::String(text));
+                Ok(tonic::Response::new(response))
+            })
+        }
+    }
+
+    /// Serve `test.Echo` with server reflection on a local port
+    async fn echo_server() -> String {
+        let schema = echo_schema();
+        let pool = DescriptorPool::from_file_descriptor_set(schema.clone()).unwrap();
+        let reflection = tonic_reflection::server::Builder::configure()
+            .register_encoded_file_descriptor_set(&schema.encode_to_vec())
+            .build()
+            .unwrap();
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let endpoint = listener.local_addr().unwrap().to_string();
+        tokio::spawn(
+            Server::builder()
+                .add_service(reflection)
+                .add_service(EchoServer(pool))
+                .serve_with_incoming(tokio_stream::wrappers::TcpListenerStream::new(listener)),
+        );
+        endpoint
+    }
+
+    fn target(endpoint: &str) -> GrpcTarget {
+        let config = json!({ "endpoint": endpoint });
+        GrpcTarget::from_config(config.as_object().unwrap(), None).unwrap()
+    }
+
+    #[test]
+    fn test_target_from_config() {
+        let config = |config: Value| config.as_object().unwrap().clone();
+
+        let target =
+            GrpcTarget::from_config(&config(json!({ "endpoint": "svc:50051" })), None).unwrap();
+        assert!(!target.tls);
+        assert_eq!(target.url(), "http://svc:50051");
+        assert_eq!(target.timeout, DEFAULT_GRPC_TIMEOUT);
+
+        let tls = config(json!({ "endpoint": "svc:443", "tls": true, "timeout_ms": 500 }));
+        let target = GrpcTarget::from_config(&tls, None).unwrap();
+        assert_eq!(target.url(), "https://svc:443");
+        assert_eq!(target.timeout, Duration::from_millis(500));
+
+        // The function timeout overrides the service one
+        let target = GrpcTarget::from_config(&tls, Some(100)).unwrap();
+        assert_eq!(target.timeout, Duration::from_millis(100));
+
+        let https = config(json!({ "endpoint": "https://svc.example.com" }));
+        let target = GrpcTarget::from_config(&https, None).unwrap();
+        assert!(target.tls);
+        assert_eq!(target.url(), "https://svc.example.com");
+
+        for invalid in [json!({}), json!({ "endpoint": 50051 })] {
+            assert!(matches!(
+                GrpcTarget::from_config(&config(invalid), None),
+                Err(GrpcError::Config(_))
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_invoke() {
+        let endpoint = echo_server().await;
+        let target = target(&endpoint);
+        let client = DynamicGrpcClient::new(EgressGuard::new().with_allow_internal(true));
+        let authorization = [("authorization", "Bearer token".to_string())];
+
+        let response = client
+            .invoke(
+                &target,
+                "test.Echo",
+                "Echo",
+                &json!({ "text": "hello" }),
+                &authorization,
+            )
+            .await
+            .unwrap();
+        assert_eq!(response, json!({ "text": "hello", "length": 5 }));
+
+        // Failures of the call are reported with their status code
+        let err = client
+            .invoke(
+                &target,
+                "test.Echo",
+                "Echo",
+                &json!({ "text": "hello" }),
+                &[],
+            )
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(
+                err,
+                GrpcError::Status {
+                    code: Code::Unauthenticated,
+                    ..
+                }
+            ),
+            "{}",
+            err
+        );
+
+        let err = client
+            .invoke(
+                &target,
+                "test.Echo",
+                "Echo",
+                &json!({ "text": 5 }),
+                &authorization,
+            )
+            .await
+            .unwrap_err();
+        assert!(matches!(err, GrpcError::InvalidRequest(_, _)), "{}", err);
+
+        let err = client
+            .invoke(&target, "test.Echo", "Watch", &json!({}), &authorization)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, GrpcError::Streaming(_)), "{}", err);
+
+        let err = client
+            .invoke(&target, "test.Echo", "Shout", &json!({}), &authorization)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, GrpcError::MethodNotFound(_)), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn test_descriptor_cache() {
+        let endpoint = echo_server().await;
+        let target = target(&endpoint);
+        let client = DynamicGrpcClient::new(EgressGuard::new().with_allow_internal(true));
+
+        client
+            .invoke(&target, "test.Echo", "Watch", &json!({}), &[])
+            .await
+            .unwrap_err();
+        assert!(client.pools.read().await.contains_key(&endpoint));
+
+        client.invalidate(&endpoint).await;
+        assert!(client.pools.read().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_egress_guard() {
+        // Internal addresses are refused unless the guard allows them
+        let endpoint = echo_server().await;
+        let err = DynamicGrpcClient::new(EgressGuard::new())
+            .invoke(&target(&endpoint), "test.Echo", "Echo", &json!({}), &[])
+            .await
+            .unwrap_err();
+        assert!(matches!(err, GrpcError::Connect(_, _)), "{}", err);
+    }
+}
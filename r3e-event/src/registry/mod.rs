@@ -2,17 +2,26 @@
 // All Rights Reserved
 
 pub mod examples;
+pub mod grpc;
 pub mod rocksdb;
 pub mod registry;
+pub mod response_cache;
 pub mod service;
 pub mod storage;
+pub mod templates;
 
 use std::collections::HashMap;
 use std::sync::{Arc, RwLock};
 use std::time::{SystemTime, UNIX_EPOCH};
+
+use bytes::Bytes;
+use r3e_store::{BlobError, BlobRef, BlobStore};
 use uuid::Uuid;
 
-use crate::registry::storage::FunctionStorage;
+use crate::registry::storage::{
+    FunctionCursor, FunctionQuery, FunctionStorage, DEFAULT_PAGE_SIZE, MAX_PAGE_SIZE,
+};
+use crate::registry::templates::{FunctionTemplate, TemplateRef};
 
 // Re-export registry types 
 pub use registry::*;
@@ -29,6 +38,35 @@ pub struct FunctionMetadata {
     pub permissions: Option<Permissions>,
     pub resources: Option<Resources>,
     pub code: String,
+    /// Blob holding the code when it is too large to inline, `code` is then empty in storage
+    #[serde(default)]
+    pub code_blob: Option<BlobRef>,
+    /// Hex encoded SHA-256 of the code, which storage keeps once per hash
+    #[serde(default)]
+    pub code_hash: Option<String>,
+    #[serde(default)]
+    pub source_map: Option<String>,
+    #[serde(default)]
+    pub owner: Option<String>,
+    /// JSON Schema the invocation input must conform to
+    #[serde(default)]
+    pub input_schema: Option<serde_json::Value>,
+    /// JSON Schema the function output must conform to
+    #[serde(default)]
+    pub output_schema: Option<serde_json::Value>,
+    /// Template version the function was created from
+    #[serde(default)]
+    pub template: Option<TemplateRef>,
+}
+
+impl FunctionMetadata {
+    /// Hash of the code kept in the storage's code table, `None` for inline or blob code
+    pub fn stored_code_hash(&self) -> Option<&str> {
+        match &self.code_blob {
+            Some(_) => None,
+            None => self.code_hash.as_deref(),
+        }
+    }
 }
 
 // Trigger configuration
@@ -63,6 +101,17 @@ pub struct RegisterFunctionRequest {
     pub permissions: Option<Permissions>,
     pub resources: Option<Resources>,
     pub code: String,
+    /// Hash of code the registry already has, used instead of `code` when that is empty
+    #[serde(default)]
+    pub code_hash: Option<String>,
+    #[serde(default)]
+    pub source_map: Option<String>,
+    #[serde(default)]
+    pub owner: Option<String>,
+    #[serde(default)]
+    pub input_schema: Option<serde_json::Value>,
+    #[serde(default)]
+    pub output_schema: Option<serde_json::Value>,
 }
 
 #[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
@@ -79,6 +128,18 @@ pub struct UpdateFunctionRequest {
     pub permissions: Option<Permissions>,
     pub resources: Option<Resources>,
     pub code: Option<String>,
+    /// Hash of code the registry already has, used instead of `code` when that is not set
+    #[serde(default)]
+    pub code_hash: Option<String>,
+    #[serde(default)]
+    pub source_map: Option<String>,
+    #[serde(default)]
+    pub input_schema: Option<serde_json::Value>,
+    #[serde(default)]
+    pub output_schema: Option<serde_json::Value>,
+    /// Version the update was based on, stale updates are rejected with a conflict
+    #[serde(default)]
+    pub expected_version: Option<u32>,
 }
 
 #[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
@@ -101,6 +162,10 @@ pub struct ListFunctionsRequest {
     pub page_token: String,
     pub page_size: u32,
     pub trigger_type: String,
+    #[serde(default)]
+    pub name_prefix: String,
+    #[serde(default)]
+    pub owner: String,
 }
 
 #[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
@@ -119,9 +184,80 @@ pub struct DeleteFunctionResponse {
     pub success: bool,
 }
 
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct HasCodeRequest {
+    pub hash: String,
+}
+
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct HasCodeResponse {
+    pub exists: bool,
+}
+
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct PublishTemplateRequest {
+    /// Template to publish, its version and timestamps are set by the registry
+    pub template: FunctionTemplate,
+    /// Latest version the update was based on, stale updates are rejected with a conflict
+    #[serde(default)]
+    pub expected_version: Option<u32>,
+}
+
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct PublishTemplateResponse {
+    pub template: Option<FunctionTemplate>,
+}
+
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct GetTemplateRequest {
+    pub id: String,
+    /// Version to get, the latest if not set
+    #[serde(default)]
+    pub version: Option<u32>,
+}
+
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct GetTemplateResponse {
+    pub template: Option<FunctionTemplate>,
+}
+
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct ListTemplatesRequest {
+    #[serde(default)]
+    pub category: String,
+}
+
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct ListTemplatesResponse {
+    pub templates: Vec<FunctionTemplate>,
+}
+
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct InstantiateTemplateRequest {
+    pub template_id: String,
+    /// Template version to instantiate, the latest if not set
+    #[serde(default)]
+    pub version: Option<u32>,
+    /// Name of the new function, the template name if empty
+    #[serde(default)]
+    pub name: String,
+    /// Parameter values by name
+    #[serde(default)]
+    pub parameters: HashMap<String, String>,
+    /// User the new function belongs to
+    pub owner: String,
+}
+
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct InstantiateTemplateResponse {
+    pub metadata: Option<FunctionMetadata>,
+}
+
 /// Function registry for managing user-provided JavaScript functions
 pub struct FunctionRegistry {
     storage: Arc<RwLock<Box<dyn FunctionStorage>>>,
+    blob_store: Option<Arc<dyn BlobStore>>,
+    inline_code_limit: usize,
 }
 
 impl FunctionRegistry {
@@ -129,7 +265,125 @@ impl FunctionRegistry {
     pub fn new(storage: Box<dyn FunctionStorage>) -> Self {
         Self {
             storage: Arc::new(RwLock::new(storage)),
+            blob_store: None,
+            inline_code_limit: usize::MAX,
+        }
+    }
+
+    /// Keep code larger than `inline_code_limit` bytes in a blob store, referenced from the
+    /// metadata
+    pub fn with_blob_store(
+        mut self,
+        blob_store: Arc<dyn BlobStore>,
+        inline_code_limit: usize,
+    ) -> Self {
+        self.blob_store = Some(blob_store);
+        self.inline_code_limit = inline_code_limit;
+        self
+    }
+
+    /// Upload code over the inline limit to the blob store
+    async fn offload_code(&self, code: &str) -> Result<Option<BlobRef>, RegistryError> {
+        match &self.blob_store {
+            Some(blob_store) if code.len() > self.inline_code_limit => blob_store
+                .put(Bytes::copy_from_slice(code.as_bytes()))
+                .await
+                .map(Some)
+                .map_err(|e| RegistryError::Storage(e.to_string())),
+            _ => Ok(None),
+        }
+    }
+
+    /// Code of a request: the uploaded code, or the stored code with the given hash
+    async fn request_code(
+        &self,
+        code: Option<String>,
+        code_hash: Option<String>,
+    ) -> Result<Option<(String, String)>, RegistryError> {
+        match (code, code_hash) {
+            (Some(code), expected) if !code.is_empty() || expected.is_none() => {
+                let hash = BlobRef::of(code.as_bytes()).digest;
+                if let Some(expected) = expected {
+                    if !expected.eq_ignore_ascii_case(&hash) {
+                        return Err(RegistryError::Validation(format!(
+                            "code hashes to {}, not {}",
+                            hash, expected
+                        )));
+                    }
+                }
+                Ok(Some((code, hash)))
+            }
+            (_, Some(hash)) => {
+                let hash = hash.to_ascii_lowercase();
+                match self.find_code(&hash).await? {
+                    Some(code) => Ok(Some((code, hash))),
+                    None => Err(RegistryError::Validation(format!(
+                        "no code with hash {}, upload the code instead",
+                        hash
+                    ))),
+                }
+            }
+            (None, None) => Ok(None),
+        }
+    }
+
+    /// Find code by content hash in storage or the blob store
+    async fn find_code(&self, hash: &str) -> Result<Option<String>, RegistryError> {
+        let stored = self.storage.read().unwrap().get_code(hash)?;
+        if stored.is_some() {
+            return Ok(stored);
+        }
+
+        let Some(blob_store) = &self.blob_store else {
+            return Ok(None);
+        };
+        let blob = BlobRef {
+            digest: hash.to_string(),
+            size: 0,
+            location: None,
+        };
+        match blob_store.get(&blob).await {
+            Ok(code) => String::from_utf8(code.to_vec())
+                .map(Some)
+                .map_err(|e| RegistryError::Storage(format!("function code is not UTF-8: {}", e))),
+            Err(BlobError::NotFound(_)) => Ok(None),
+            Err(e) => Err(RegistryError::Storage(e.to_string())),
+        }
+    }
+
+    /// Whether the registry has code with a content hash, so clients can skip uploading it
+    pub async fn has_code(&self, request: HasCodeRequest) -> Result<HasCodeResponse, RegistryError> {
+        let exists = self
+            .find_code(&request.hash.to_ascii_lowercase())
+            .await?
+            .is_some();
+        Ok(HasCodeResponse { exists })
+    }
+
+    /// Fetch code kept by hash or offloaded back into metadata read from storage
+    async fn resolve_code(&self, metadata: &mut FunctionMetadata) -> Result<(), RegistryError> {
+        if !metadata.code.is_empty() {
+            return Ok(());
+        }
+        if let Some(hash) = metadata.stored_code_hash().map(str::to_string) {
+            let code = self.storage.read().unwrap().get_code(&hash)?;
+            metadata.code = code.ok_or_else(|| {
+                RegistryError::Storage(format!("code {} of function {} is missing", hash, metadata.id))
+            })?;
+            return Ok(());
         }
+
+        let (Some(blob), Some(blob_store)) = (&metadata.code_blob, &self.blob_store) else {
+            return Ok(());
+        };
+
+        let code = blob_store
+            .get(blob)
+            .await
+            .map_err(|e| RegistryError::Storage(e.to_string()))?;
+        metadata.code = String::from_utf8(code.to_vec())
+            .map_err(|e| RegistryError::Storage(format!("function code is not UTF-8: {}", e)))?;
+        Ok(())
     }
 
     /// Register a new function
@@ -137,6 +391,22 @@ impl FunctionRegistry {
         &self,
         request: RegisterFunctionRequest,
     ) -> Result<RegisterFunctionResponse, RegistryError> {
+        self.register(request, None).await
+    }
+
+    /// Register a new function, created from `template` if set
+    async fn register(
+        &self,
+        request: RegisterFunctionRequest,
+        template: Option<TemplateRef>,
+    ) -> Result<RegisterFunctionResponse, RegistryError> {
+        check_schemas(&request.input_schema, &request.output_schema)?;
+        let (code, code_hash) = self
+            .request_code(Some(request.code), request.code_hash)
+            .await?
+            .unwrap_or_default();
+        let code_blob = self.offload_code(&code).await?;
+
         // Generate a unique ID for the function
         let id = Uuid::new_v4().to_string();
 
@@ -157,11 +427,18 @@ impl FunctionRegistry {
             trigger: request.trigger,
             permissions: request.permissions,
             resources: request.resources,
-            code: request.code,
+            code,
+            code_blob,
+            code_hash: Some(code_hash),
+            source_map: request.source_map,
+            owner: request.owner,
+            input_schema: request.input_schema,
+            output_schema: request.output_schema,
+            template,
         };
 
         // Store the function metadata
-        self.storage.write().unwrap().store_function(&metadata)?;
+        store_metadata(&mut self.storage.write().unwrap(), &metadata, None)?;
 
         Ok(RegisterFunctionResponse {
             metadata: Some(metadata),
@@ -173,8 +450,31 @@ impl FunctionRegistry {
         &self,
         request: UpdateFunctionRequest,
     ) -> Result<UpdateFunctionResponse, RegistryError> {
+        check_schemas(&request.input_schema, &request.output_schema)?;
+
+        // Upload new code before locking, so the lock is not held across the upload
+        let code = self.request_code(request.code, request.code_hash).await?;
+        let code_blob = match &code {
+            Some((code, _)) => self.offload_code(code).await?,
+            None => None,
+        };
+
+        // Hold the write lock from read to write, so concurrent updates cannot interleave
+        let mut storage = self.storage.write().unwrap();
+
         // Get the existing function
-        let mut metadata = self.storage.read().unwrap().get_function(&request.id)?;
+        let mut metadata = storage.get_function(&request.id)?;
+        let previous_code_hash = metadata.stored_code_hash().map(str::to_string);
+
+        // Reject updates based on a version that was since replaced
+        if let Some(expected) = request.expected_version {
+            if metadata.version != expected {
+                return Err(RegistryError::Conflict(format!(
+                    "function {} is at version {}, expected {}",
+                    request.id, metadata.version, expected
+                )));
+            }
+        }
 
         // Get current timestamp
         let now = SystemTime::now()
@@ -203,8 +503,24 @@ impl FunctionRegistry {
             metadata.resources = Some(resources);
         }
 
-        if let Some(code) = request.code {
+        if let Some((code, code_hash)) = code {
             metadata.code = code;
+            metadata.code_blob = code_blob;
+            metadata.code_hash = Some(code_hash);
+            // a stale source map would point frames at the wrong locations
+            metadata.source_map = None;
+        }
+
+        if let Some(source_map) = request.source_map {
+            metadata.source_map = Some(source_map);
+        }
+
+        if let Some(input_schema) = request.input_schema {
+            metadata.input_schema = Some(input_schema);
+        }
+
+        if let Some(output_schema) = request.output_schema {
+            metadata.output_schema = Some(output_schema);
         }
 
         // Increment version
@@ -212,7 +528,9 @@ impl FunctionRegistry {
         metadata.updated_at = now;
 
         // Store the updated function metadata
-        self.storage.write().unwrap().store_function(&metadata)?;
+        store_metadata(&mut storage, &metadata, previous_code_hash.as_deref())?;
+        drop(storage);
+        self.resolve_code(&mut metadata).await?;
 
         Ok(UpdateFunctionResponse {
             metadata: Some(metadata),
@@ -224,27 +542,47 @@ impl FunctionRegistry {
         &self,
         request: GetFunctionRequest,
     ) -> Result<GetFunctionResponse, RegistryError> {
-        let metadata = self.storage.read().unwrap().get_function(&request.id)?;
+        let mut metadata = self.storage.read().unwrap().get_function(&request.id)?;
+        self.resolve_code(&mut metadata).await?;
         Ok(GetFunctionResponse {
             metadata: Some(metadata),
         })
     }
 
-    /// List functions with optional filtering
+    /// List functions, most recently updated first, with optional filtering.
+    ///
+    /// Code kept in the blob store is not fetched, those functions list with empty code and
+    /// their `code_blob` reference.
     pub async fn list_functions(
         &self,
         request: ListFunctionsRequest,
     ) -> Result<ListFunctionsResponse, RegistryError> {
-        let functions = self.storage.read().unwrap().list_functions(
-            request.page_token,
-            request.page_size,
-            request.trigger_type,
-        )?;
+        let cursor = if request.page_token.is_empty() {
+            None
+        } else {
+            Some(FunctionCursor::from_page_token(&request.page_token)?)
+        };
+
+        let limit = match request.page_size as usize {
+            0 => DEFAULT_PAGE_SIZE,
+            page_size => page_size.min(MAX_PAGE_SIZE),
+        };
+
+        let query = FunctionQuery {
+            cursor,
+            limit,
+            trigger_type: request.trigger_type,
+            name_prefix: request.name_prefix,
+            owner: request.owner,
+        };
+        let page = self.storage.read().unwrap().list_functions(&query)?;
 
-        // For simplicity, we're not implementing pagination in this example
         Ok(ListFunctionsResponse {
-            functions,
-            next_page_token: "".to_string(),
+            functions: page.functions,
+            next_page_token: page
+                .next_cursor
+                .map(|cursor| cursor.to_page_token())
+                .unwrap_or_default(),
         })
     }
 
@@ -253,9 +591,216 @@ impl FunctionRegistry {
         &self,
         request: DeleteFunctionRequest,
     ) -> Result<DeleteFunctionResponse, RegistryError> {
-        let success = self.storage.write().unwrap().delete_function(&request.id)?;
+        let mut storage = self.storage.write().unwrap();
+        let code_hash = match storage.get_function(&request.id) {
+            Ok(metadata) => metadata.stored_code_hash().map(str::to_string),
+            Err(RegistryError::NotFound(_)) => None,
+            Err(e) => return Err(e),
+        };
+
+        let success = storage.delete_function(&request.id)?;
+        if let (true, Some(hash)) = (success, code_hash) {
+            storage.release_code(&hash)?;
+        }
         Ok(DeleteFunctionResponse { success })
     }
+
+    /// Publish a template, as a new version if one with its ID exists
+    pub async fn publish_template(
+        &self,
+        request: PublishTemplateRequest,
+    ) -> Result<PublishTemplateResponse, RegistryError> {
+        let mut template = request.template;
+        template.validate()?;
+        check_schemas(&template.input_schema, &template.output_schema)?;
+
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+
+        // Hold the write lock from read to write, so concurrent publishes cannot interleave
+        let mut storage = self.storage.write().unwrap();
+        let latest = match storage.get_template(&template.id, None) {
+            Ok(latest) => Some(latest),
+            Err(RegistryError::NotFound(_)) => None,
+            Err(e) => return Err(e),
+        };
+
+        let latest_version = latest.as_ref().map_or(0, |latest| latest.version);
+        if let Some(expected) = request.expected_version {
+            if latest_version != expected {
+                return Err(RegistryError::Conflict(format!(
+                    "template {} is at version {}, expected {}",
+                    template.id, latest_version, expected
+                )));
+            }
+        }
+
+        template.version = latest_version + 1;
+        template.created_at = latest.map_or(now, |latest| latest.created_at);
+        template.updated_at = now;
+        storage.store_template(&template)?;
+
+        Ok(PublishTemplateResponse {
+            template: Some(template),
+        })
+    }
+
+    /// Publish the built-in templates the catalog does not have yet
+    pub async fn seed_templates(&self) -> Result<(), RegistryError> {
+        for template in templates::builtin_templates() {
+            let existing = self
+                .storage
+                .read()
+                .unwrap()
+                .get_template(&template.id, None);
+            let exists = match existing {
+                Ok(_) => true,
+                Err(RegistryError::NotFound(_)) => false,
+                Err(e) => return Err(e),
+            };
+            if exists {
+                continue;
+            }
+            // A template published concurrently wins, the seed is only a starting point
+            let published = self
+                .publish_template(PublishTemplateRequest {
+                    template,
+                    expected_version: Some(0),
+                })
+                .await;
+            match published {
+                Ok(_) | Err(RegistryError::Conflict(_)) => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+
+    /// Get a template, the latest version unless one is requested
+    pub async fn get_template(
+        &self,
+        request: GetTemplateRequest,
+    ) -> Result<GetTemplateResponse, RegistryError> {
+        let template = self
+            .storage
+            .read()
+            .unwrap()
+            .get_template(&request.id, request.version)?;
+        Ok(GetTemplateResponse {
+            template: Some(template),
+        })
+    }
+
+    /// List the latest version of every template, optionally of one category
+    pub async fn list_templates(
+        &self,
+        request: ListTemplatesRequest,
+    ) -> Result<ListTemplatesResponse, RegistryError> {
+        let mut templates = self.storage.read().unwrap().list_templates()?;
+        if !request.category.is_empty() {
+            templates.retain(|template| template.category == request.category);
+        }
+        Ok(ListTemplatesResponse { templates })
+    }
+
+    /// Register a function owned by the requesting user from a template filled with their
+    /// parameters
+    pub async fn instantiate_template(
+        &self,
+        request: InstantiateTemplateRequest,
+    ) -> Result<InstantiateTemplateResponse, RegistryError> {
+        if request.owner.is_empty() {
+            return Err(RegistryError::Validation(
+                "owner is required to instantiate a template".to_string(),
+            ));
+        }
+
+        let template = self
+            .storage
+            .read()
+            .unwrap()
+            .get_template(&request.template_id, request.version)?;
+        let rendered = template.render(&request.parameters)?;
+
+        let name = if request.name.is_empty() {
+            template.name.clone()
+        } else {
+            request.name
+        };
+        let registration = RegisterFunctionRequest {
+            name,
+            description: template.description.clone(),
+            trigger: rendered.trigger,
+            permissions: template.permissions.clone(),
+            resources: template.resources.clone(),
+            code: rendered.code,
+            code_hash: None,
+            source_map: None,
+            owner: Some(request.owner),
+            input_schema: template.input_schema.clone(),
+            output_schema: template.output_schema.clone(),
+        };
+        let template_ref = TemplateRef {
+            id: template.id,
+            version: template.version,
+        };
+
+        let response = self.register(registration, Some(template_ref)).await?;
+        Ok(InstantiateTemplateResponse {
+            metadata: response.metadata,
+        })
+    }
+}
+
+/// Store metadata, keeping its code once per hash or in the blob store.
+///
+/// `previous_code_hash` is the hash of the code the function referenced before, whose
+/// reference is dropped once the metadata is stored.
+fn store_metadata(
+    storage: &mut Box<dyn FunctionStorage>,
+    metadata: &FunctionMetadata,
+    previous_code_hash: Option<&str>,
+) -> Result<(), RegistryError> {
+    let code_hash = metadata.stored_code_hash();
+    if code_hash.is_none() && metadata.code_blob.is_none() {
+        // Functions stored before code was kept by hash keep their code inline
+        storage.store_function(metadata)?;
+    } else {
+        let changed = code_hash.is_some() && code_hash != previous_code_hash;
+        if let (true, Some(hash)) = (changed, code_hash) {
+            storage.put_code(hash, &metadata.code)?;
+        }
+
+        let mut stored = metadata.clone();
+        stored.code = String::new();
+        if let Err(e) = storage.store_function(&stored) {
+            if let (true, Some(hash)) = (changed, code_hash) {
+                storage.release_code(hash)?;
+            }
+            return Err(e);
+        }
+    }
+
+    match previous_code_hash {
+        Some(previous) if Some(previous) != code_hash => storage.release_code(previous).map(|_| ()),
+        _ => Ok(()),
+    }
+}
+
+/// Reject input/output schemas that do not compile
+fn check_schemas(
+    input_schema: &Option<serde_json::Value>,
+    output_schema: &Option<serde_json::Value>,
+) -> Result<(), RegistryError> {
+    for (kind, schema) in [("input", input_schema), ("output", output_schema)] {
+        if let Some(schema) = schema {
+            r3e_core::schema::check_schema(schema)
+                .map_err(|e| RegistryError::Validation(format!("{} {}", kind, e)))?;
+        }
+    }
+    Ok(())
 }
 
 /// Error types for function registry operations
@@ -270,6 +815,9 @@ pub enum RegistryError {
     #[error("validation error: {0}")]
     Validation(String),
 
+    #[error("conflict: {0}")]
+    Conflict(String),
+
     #[error("internal error: {0}")]
     Internal(String),
 }
@@ -342,8 +890,14 @@ pub mod db {
 }
 
 use crate::registry::db::DatabaseClient;
+use crate::registry::grpc::{DynamicGrpcClient, GrpcTarget};
 use crate::registry::models::{Service, ServiceSignature};
+use crate::registry::response_cache::ResponseCache;
 // Arc is already imported above
+use r3e_core::chain::{
+    stack_item_bytes, stack_item_integer, CallResult, ChainClient, ChainClients, ContractCall,
+};
+use r3e_core::egress::EgressGuard;
 use tokio::sync::RwLock as TokioRwLock;
 
 /// Service registry for managing and invoking services
@@ -353,6 +907,10 @@ pub struct ServiceRegistry {
     service_cache: Arc<TokioRwLock<HashMap<uuid::Uuid, Service>>>,
     cache_ttl: std::time::Duration,
     last_cache_refresh: Arc<TokioRwLock<std::time::Instant>>,
+    egress_guard: EgressGuard,
+    grpc_client: DynamicGrpcClient,
+    response_cache: Option<ResponseCache>,
+    chains: Arc<ChainClients>,
 }
 
 impl ServiceRegistry {
@@ -363,6 +921,49 @@ impl ServiceRegistry {
             service_cache: Arc::new(TokioRwLock::new(HashMap::new())),
             cache_ttl: std::time::Duration::from_secs(60), // 1 minute cache TTL
             last_cache_refresh: Arc::new(TokioRwLock::new(std::time::Instant::now())),
+            egress_guard: EgressGuard::new(),
+            grpc_client: DynamicGrpcClient::new(EgressGuard::new()),
+            response_cache: None,
+            chains: Arc::new(ChainClients::new()),
+        }
+    }
+
+    /// Share chain clients with other services, so endpoint health is tracked across them
+    pub fn with_chain_clients(mut self, chains: Arc<ChainClients>) -> Self {
+        self.chains = chains;
+        self
+    }
+
+    /// Set the egress guard applied to HTTP and gRPC service adapters
+    pub fn with_egress_guard(mut self, egress_guard: EgressGuard) -> Self {
+        self.grpc_client = DynamicGrpcClient::new(egress_guard.clone());
+        self.egress_guard = egress_guard;
+        self
+    }
+
+    /// Cache the responses of read-only functions with a `cache_ttl_secs` in their adapter config
+    pub fn with_response_cache(mut self, response_cache: ResponseCache) -> Self {
+        self.response_cache = Some(response_cache);
+        self
+    }
+
+    /// Invalidate the cached responses of a service, or of one of its functions.
+    /// Returns the number of responses invalidated.
+    pub async fn invalidate_cached_responses(
+        &self,
+        service_id: &Uuid,
+        function_name: Option<&str>,
+    ) -> usize {
+        match &self.response_cache {
+            Some(response_cache) => response_cache.invalidate(service_id, function_name).await,
+            None => 0,
+        }
+    }
+
+    /// Invalidate all cached responses
+    pub async fn clear_response_cache(&self) {
+        if let Some(response_cache) = &self.response_cache {
+            response_cache.clear().await;
         }
     }
 
@@ -441,6 +1042,9 @@ impl ServiceRegistry {
                 // Update in cache
                 let mut cache = self.service_cache.write().await;
                 cache.insert(*service_id, service);
+
+                // Responses of the previous configuration are stale
+                self.invalidate_cached_responses(service_id, None).await;
                 Ok(())
             }
             Err(e) => Err(format!("Failed to update service in database: {}", e)),
@@ -455,6 +1059,7 @@ impl ServiceRegistry {
                 // Remove from cache
                 let mut cache = self.service_cache.write().await;
                 cache.remove(service_id);
+                self.invalidate_cached_responses(service_id, None).await;
                 Ok(())
             }
             Err(e) => Err(format!("Failed to delete service from database: {}", e)),
@@ -528,8 +1133,22 @@ impl ServiceRegistry {
             }
         }
 
+        // Return the cached response of a read-only function, signed calls are never cached
+        let cache_ttl = match (&self.response_cache, signature) {
+            (Some(_), None) => ResponseCache::function_ttl(function),
+            _ => None,
+        };
+        if let (Some(response_cache), Some(_)) = (&self.response_cache, cache_ttl) {
+            if let Some(response) = response_cache
+                .get(service_id, function_name, parameters, auth_token)
+                .await
+            {
+                return Ok(response);
+            }
+        }
+
         // Execute the function based on the adapter type
-        match service.adapter_type.as_str() {
+        let response = match service.adapter_type.as_str() {
             "http" => {
                 self.execute_http_function(
                     &service,
@@ -574,7 +1193,22 @@ impl ServiceRegistry {
                 "Unsupported adapter type: {}",
                 service.adapter_type
             )),
+        }?;
+
+        if let (Some(response_cache), Some(ttl)) = (&self.response_cache, cache_ttl) {
+            response_cache
+                .insert(
+                    service_id,
+                    function_name,
+                    parameters,
+                    auth_token,
+                    response.clone(),
+                    ttl,
+                )
+                .await;
         }
+
+        Ok(response)
     }
 
     /// Refresh the service cache if needed
@@ -656,8 +1290,19 @@ impl ServiceRegistry {
             _ => "POST".to_string(),
         };
 
+        // Refuse internal destinations and pin the connection to the checked addresses
+        let resolved = self
+            .egress_guard
+            .check_url(&url)
+            .await
+            .map_err(|e| e.to_string())?;
+
         // Build the request
-        let client = reqwest::Client::new();
+        let client = reqwest::Client::builder()
+            .resolve_to_addrs(&resolved.host, &resolved.addrs)
+            .redirect(reqwest::redirect::Policy::none())
+            .build()
+            .map_err(|e| format!("Failed to create HTTP client: {}", e))?;
         let mut request_builder = match method.as_str() {
             "GET" => client.get(&url),
             "POST" => client.post(&url),
@@ -744,20 +1389,15 @@ impl ServiceRegistry {
         service: &Service,
         function_name: &str,
         parameters: &Value,
-        _auth_token: Option<&str>,
-        _signature: Option<&ServiceSignature>,
+        auth_token: Option<&str>,
+        signature: Option<&ServiceSignature>,
     ) -> Result<Value, String> {
-        // Get the endpoint URL from the service adapter configuration
+        // Get the endpoint from the service adapter configuration
         let config = match &service.adapter_config {
             Value::Object(config) => config,
             _ => return Err("Invalid adapter configuration".to_string()),
         };
 
-        let endpoint = match config.get("endpoint") {
-            Some(Value::String(url)) => url,
-            _ => return Err("Missing or invalid endpoint in adapter configuration".to_string()),
-        };
-
         // Find the service and method names
         let function = service
             .functions
@@ -789,44 +1429,36 @@ impl ServiceRegistry {
             _ => return Err("Invalid function adapter configuration".to_string()),
         };
 
-        // Use tonic to create a gRPC client and make the call
-        // For a real implementation, we would need to use reflection or generated code
-        // This is a simplified version that uses the gRPC reflection service
-
-        // Convert parameters to bytes
-        let param_bytes = match serde_json::to_vec(parameters) {
-            Ok(bytes) => bytes,
-            Err(e) => return Err(format!("Failed to serialize parameters: {}", e)),
-        };
-
-        // Use the Reflection API to make a dynamic gRPC call
-        // Note: In a real implementation, we would use generated code for type safety
-
-        // For this simplified example, we'll use the grpcurl command-line tool
-        // In a real implementation, we would use a proper gRPC client library
-        use std::process::Command;
+        // A function timeout overrides the service one
+        let function_timeout_ms = function
+            .adapter_config
+            .get("timeout_ms")
+            .and_then(Value::as_u64);
+        let target =
+            GrpcTarget::from_config(config, function_timeout_ms).map_err(|e| e.to_string())?;
 
-        let output = Command::new("grpcurl")
-            .arg("-d")
-            .arg(format!("'{}'", serde_json::to_string(parameters).unwrap()))
-            .arg("-plaintext")
-            .arg(endpoint)
-            .arg(format!("{}/{}", grpc_service, grpc_method))
-            .output()
-            .map_err(|e| format!("Failed to execute gRPC call: {}", e))?;
-
-        if !output.status.success() {
-            return Err(format!(
-                "gRPC call failed: {}",
-                String::from_utf8_lossy(&output.stderr)
-            ));
+        // Forward authentication and signature as metadata
+        let mut metadata = Vec::new();
+        if let Some(token) = auth_token {
+            let auth_type = match config.get("auth_type") {
+                Some(Value::String(auth_type)) => auth_type.as_str(),
+                _ => "Bearer",
+            };
+            metadata.push(("authorization", format!("{} {}", auth_type, token)));
         }
-
-        // Parse the response JSON
-        match serde_json::from_slice::<Value>(&output.stdout) {
-            Ok(result) => Ok(result),
-            Err(e) => Err(format!("Failed to parse gRPC response: {}", e)),
+        if let Some(sig) = signature {
+            metadata.push(("x-signature", sig.signature.clone()));
+            metadata.push(("x-address", sig.address.clone()));
+            metadata.push(("x-blockchain-type", sig.blockchain_type.clone()));
+            if let Some(curve) = &sig.signature_curve {
+                metadata.push(("x-signature-curve", curve.clone()));
+            }
         }
+
+        self.grpc_client
+            .invoke(&target, grpc_service, grpc_method, parameters, &metadata)
+            .await
+            .map_err(|e| e.to_string())
     }
 
     /// Execute a blockchain function
@@ -948,48 +1580,24 @@ impl ServiceRegistry {
         is_readonly: bool,
         signature: Option<&ServiceSignature>,
     ) -> Result<Value, String> {
-        use ethers::{
-            contract::{abigen, Contract},
-            core::types::{Address, U256},
-            providers::{Http, Provider},
-            signers::{LocalWallet, Signer},
-        };
+        use ethers::abi::{ParamType, Token};
+        use ethers::core::types::{Address, U256};
+        use ethers::signers::LocalWallet;
 
         // Parse the contract address
         let address = contract_address
             .parse::<Address>()
             .map_err(|e| format!("Invalid Ethereum address: {}", e))?;
 
-        // Get the RPC URL based on the network
-        let rpc_url = match network {
-            "mainnet" => "https://mainnet.infura.io/v3/your-project-id",
-            "sepolia" => "https://sepolia.infura.io/v3/your-project-id",
-            "goerli" => "https://goerli.infura.io/v3/your-project-id",
-            _ => return Err(format!("Unsupported Ethereum network: {}", network)),
-        };
-
-        // Create a provider
-        let provider = Provider::<Http>::try_from(rpc_url)
-            .map_err(|e| format!("Failed to create Ethereum provider: {}", e))?;
+        // Get the shared client of the network
+        let chain = self
+            .chains
+            .get("ethereum", network)
+            .map_err(|e| e.to_string())?;
 
-        // Create a contract instance
+        // Execute the contract method based on the name
         // For simplicity, we'll assume an ABI for common ERC20 functions
         // In a real implementation, we would use a dynamic ABI based on the contract
-        abigen!(
-            ERC20,
-            r#"[
-                function balanceOf(address owner) view returns (uint256)
-                function transfer(address to, uint256 amount) returns (bool)
-                function approve(address spender, uint256 amount) returns (bool)
-                function allowance(address owner, address spender) view returns (uint256)
-                function transferFrom(address from, address to, uint256 amount) returns (bool)
-            ]"#
-        );
-
-        // Create a contract instance
-        let contract = ERC20::new(address, Arc::new(provider.clone()));
-
-        // Execute the contract method based on the name
         match contract_method {
             "balanceOf" => {
                 // Get the owner address from parameters
@@ -1001,11 +1609,39 @@ impl ServiceRegistry {
                 };
 
                 // Call the balanceOf method
-                match contract.balance_of(owner).call().await {
-                    Ok(balance) => Ok(serde_json::json!({
+                let call = ContractCall {
+                    contract: contract_address.to_string(),
+                    method: contract_method.to_string(),
+                    data: Some(ethereum_calldata(
+                        "balanceOf(address)",
+                        &[Token::Address(owner)],
+                    )),
+                    ..ContractCall::default()
+                };
+                let result = chain
+                    .call_contract(&call)
+                    .await
+                    .map_err(|e| format!("Failed to call balanceOf: {}", e))?;
+                if !result.success {
+                    return Err(format!(
+                        "Failed to call balanceOf: {}",
+                        result.error.unwrap_or_default()
+                    ));
+                }
+
+                let output = result
+                    .result
+                    .as_str()
+                    .and_then(|output| hex::decode(output.trim_start_matches("0x")).ok())
+                    .ok_or_else(|| "Invalid balanceOf response".to_string())?;
+                match ethers::abi::decode(&[ParamType::Uint(256)], &output)
+                    .map_err(|e| format!("Failed to decode balanceOf response: {}", e))?
+                    .as_slice()
+                {
+                    [Token::Uint(balance)] => Ok(serde_json::json!({
                         "balance": balance.to_string()
                     })),
-                    Err(e) => Err(format!("Failed to call balanceOf: {}", e)),
+                    _ => Err("Invalid balanceOf response".to_string()),
                 }
             }
             "transfer" => {
@@ -1027,8 +1663,7 @@ impl ServiceRegistry {
                 };
 
                 let amount = match parameters.get("amount") {
-                    Some(Value::String(amount)) => amount
-                        .parse::<U256>()
+                    Some(Value::String(amount)) => U256::from_dec_str(amount)
                         .map_err(|e| format!("Invalid amount: {}", e))?,
                     _ => return Err("Missing or invalid amount parameter".to_string()),
                 };
@@ -1037,20 +1672,18 @@ impl ServiceRegistry {
                 // This is a simplified example - in reality, we'd recover the wallet from the signature
                 let wallet = LocalWallet::new(&mut rand::thread_rng());
 
-                // Create a new client with the wallet
-                let client = ethers::middleware::SignerMiddleware::new(provider, wallet);
-                let contract = ERC20::new(address, Arc::new(client));
-
                 // Call the transfer method
-                match contract.transfer(to, amount).send().await {
-                    Ok(tx) => {
-                        let tx_hash = tx.tx_hash();
-                        Ok(serde_json::json!({
-                            "tx_hash": format!("{:?}", tx_hash)
-                        }))
-                    }
-                    Err(e) => Err(format!("Failed to call transfer: {}", e)),
-                }
+                let data = ethereum_calldata(
+                    "transfer(address,uint256)",
+                    &[Token::Address(to), Token::Uint(amount)],
+                );
+                let tx_hash = send_ethereum_transaction(chain.as_ref(), &wallet, address, data)
+                    .await
+                    .map_err(|e| format!("Failed to call transfer: {}", e))?;
+
+                Ok(serde_json::json!({
+                    "tx_hash": tx_hash
+                }))
             }
             _ => Err(format!(
                 "Unsupported Ethereum contract method: {}",
@@ -1069,19 +1702,10 @@ impl ServiceRegistry {
         is_readonly: bool,
         signature: Option<&ServiceSignature>,
     ) -> Result<Value, String> {
-        use neo3::neo_clients::{HttpProvider, RpcClient};
-        use neo3::prelude::{ScriptHash, ContractParameter, ScriptBuilder, Wallet, StackItem, InvocationResult};
-        
-        // Get the RPC URL based on the network
-        let rpc_url = match network {
-            "mainnet" => "http://seed1.neo.org:10332",
-            "testnet" => "http://seed1t5.neo.org:20332",
-            _ => return Err(format!("Unsupported Neo network: {}", network)),
-        };
+        use neo3::prelude::{ScriptBuilder, ScriptHash, Wallet};
 
-        // Create a provider
-        let provider = HttpProvider::new(rpc_url).map_err(|e| format!("Failed to create Neo provider: {}", e))?;
-        let client = RpcClient::new(provider);
+        // Get the shared client of the network
+        let chain = self.chains.get("neo", network).map_err(|e| e.to_string())?;
 
         // Parse the contract hash
         let contract_hash = contract_address
@@ -1090,15 +1714,19 @@ impl ServiceRegistry {
 
         // Execute the contract method based on whether it's read-only or not
         if is_readonly {
-            // For read-only operations, build a script to invoke
-            let params = self.convert_neo_parameters(parameters)?;
-            
-            // Build the script
-            let script = ScriptBuilder::build_script(contract_hash.clone(), contract_method.to_string(), params)
-                .map_err(|e| format!("Failed to build script: {}", e))?;
-            
-            // Invoke the script
-            match client.invoke_script(&script).await {
+            // For read-only operations, invoke the method without a transaction
+            let params = match parameters {
+                Value::Object(param_map) => param_map.values().cloned().collect(),
+                _ => Vec::new(),
+            };
+            let call = ContractCall {
+                contract: contract_address.to_string(),
+                method: contract_method.to_string(),
+                params,
+                ..ContractCall::default()
+            };
+
+            match chain.call_contract(&call).await {
                 Ok(result) => {
                     // Parse the result
                     self.parse_neo_result(&result)
@@ -1116,19 +1744,25 @@ impl ServiceRegistry {
             let wallet = Wallet::new_from_wif("your_wif_private_key")
                 .map_err(|e| format!("Failed to create Neo wallet: {}", e))?;
 
-            // For write operations, also build a script
+            // For write operations, build a script
             let params = self.convert_neo_parameters(parameters)?;
             
             // Build the script
             let script = ScriptBuilder::build_script(contract_hash, contract_method.to_string(), params)
                 .map_err(|e| format!("Failed to build script: {}", e))?;
+
+            // Bound the validity of the transaction from the current height
+            let height = chain
+                .block_number()
+                .await
+                .map_err(|e| format!("Failed to get Neo block height: {}", e))?;
             
             // Create a transaction
             let tx = neo3::prelude::TransactionBuilder::new()
                 .script(script)
                 .signers(vec![wallet.get_account_signer()])
-                .build_with_client(&client)
-                .await
+                .valid_until_block(height + 5760) // Valid for ~1 day
+                .build()
                 .map_err(|e| format!("Failed to build Neo transaction: {}", e))?;
             
             // Sign the transaction
@@ -1136,9 +1770,9 @@ impl ServiceRegistry {
                 .map_err(|e| format!("Failed to sign Neo transaction: {}", e))?;
             
             // Send the transaction
-            match signed_tx.send(&client).await {
+            match chain.send_raw_tx(&signed_tx.to_array()).await {
                 Ok(hash) => Ok(serde_json::json!({
-                    "tx_hash": hash.to_string()
+                    "tx_hash": hash
                 })),
                 Err(e) => Err(format!("Failed to send Neo transaction: {}", e)),
             }
@@ -1187,39 +1821,37 @@ impl ServiceRegistry {
     }
 
     /// Parse a Neo contract result
-    fn parse_neo_result(&self, result: &neo3::prelude::InvocationResult) -> Result<Value, String> {
-        if result.state != "FAULT" {
-            // Parse the stack items
-            if !result.stack.is_empty() {
-                if let Some(item) = result.stack.first() {
-                    match item {
-                        neo3::prelude::StackItem::Integer(n) => {
-                            Ok(serde_json::json!({ "result": n.to_string() }))
-                        }
-                        neo3::prelude::StackItem::ByteString(bs) => {
-                            // Try to convert to a UTF-8 string if possible
-                            match String::from_utf8(bs.clone()) {
-                                Ok(s) => Ok(serde_json::json!({ "result": s })),
-                                Err(_) => {
-                                    // Use base64 encoding for binary data
-                                    Ok(serde_json::json!({ "result": base64::encode(bs) }))
-                                }
-                            }
-                        }
-                        neo3::prelude::StackItem::Boolean(b) => Ok(serde_json::json!({ "result": b })),
-                        _ => Err("Unsupported Neo result type".to_string()),
+    fn parse_neo_result(&self, result: &CallResult) -> Result<Value, String> {
+        if !result.success {
+            return Err(format!(
+                "Neo contract execution failed: {:?}",
+                result.error
+            ));
+        }
+
+        // Parse the first stack item
+        let Some(item) = result.result.get(0) else {
+            return Ok(serde_json::json!({ "result": null }));
+        };
+        match item.get("type").and_then(Value::as_str) {
+            Some("Integer") => match stack_item_integer(item) {
+                Some(n) => Ok(serde_json::json!({ "result": n.to_string() })),
+                None => Err("Invalid Neo integer result".to_string()),
+            },
+            Some("ByteString") | Some("Buffer") => {
+                let bytes = stack_item_bytes(item)
+                    .ok_or_else(|| "Invalid Neo byte string result".to_string())?;
+                // Try to convert to a UTF-8 string if possible
+                match String::from_utf8(bytes) {
+                    Ok(s) => Ok(serde_json::json!({ "result": s })),
+                    Err(_) => {
+                        // Keep the base64 encoding of binary data
+                        Ok(serde_json::json!({ "result": item["value"] }))
                     }
-                } else {
-                    Ok(serde_json::json!({ "result": null }))
                 }
-            } else {
-                Ok(serde_json::json!({ "result": null }))
             }
-        } else {
-            Err(format!(
-                "Neo contract execution failed: {:?}",
-                result.exception
-            ))
+            Some("Boolean") => Ok(serde_json::json!({ "result": item["value"] })),
+            _ => Err("Unsupported Neo result type".to_string()),
         }
     }
 
@@ -1317,3 +1949,61 @@ impl ServiceRegistry {
         }
     }
 }
+
+/// ABI encoded call of a function with its canonical signature
+fn ethereum_calldata(signature: &str, tokens: &[ethers::abi::Token]) -> Vec<u8> {
+    let mut data = ethers::utils::id(signature).to_vec();
+    data.extend(ethers::abi::encode(tokens));
+    data
+}
+
+/// Sign a contract call with a local wallet and broadcast it, returning the transaction hash
+async fn send_ethereum_transaction(
+    chain: &dyn ChainClient,
+    wallet: &ethers::signers::LocalWallet,
+    to: ethers::core::types::Address,
+    data: Vec<u8>,
+) -> Result<String, String> {
+    use ethers::core::types::{transaction::eip2718::TypedTransaction, TransactionRequest, U256};
+    use ethers::signers::Signer;
+
+    let quantity = |method: &'static str, params: Value| async move {
+        let value = chain.request(method, params).await.map_err(|e| e.to_string())?;
+        value
+            .as_str()
+            .and_then(|quantity| U256::from_str_radix(quantity.trim_start_matches("0x"), 16).ok())
+            .ok_or_else(|| format!("Invalid {} response: {}", method, value))
+    };
+
+    // Fill in the transaction from the chain state
+    let from = format!("{:?}", wallet.address());
+    let call = serde_json::json!({
+        "from": from,
+        "to": format!("{:?}", to),
+        "data": format!("0x{}", hex::encode(&data)),
+    });
+    let chain_id = quantity("eth_chainId", serde_json::json!([])).await?.as_u64();
+    let nonce = quantity("eth_getTransactionCount", serde_json::json!([from, "pending"])).await?;
+    let gas_price = quantity("eth_gasPrice", serde_json::json!([])).await?;
+    let gas = quantity("eth_estimateGas", serde_json::json!([call])).await?;
+
+    let tx: TypedTransaction = TransactionRequest::new()
+        .from(wallet.address())
+        .to(to)
+        .data(data)
+        .nonce(nonce)
+        .gas(gas)
+        .gas_price(gas_price)
+        .chain_id(chain_id)
+        .into();
+    let signature = wallet
+        .clone()
+        .with_chain_id(chain_id)
+        .sign_transaction_sync(&tx)
+        .map_err(|e| format!("Failed to sign transaction: {}", e))?;
+
+    chain
+        .send_raw_tx(&tx.rlp_signed(&signature))
+        .await
+        .map_err(|e| e.to_string())
+}
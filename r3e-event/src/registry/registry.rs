@@ -33,6 +33,12 @@ pub struct FunctionMetadata {
     /// Function code
     #[prost(string, tag = "10")]
     pub code: ::prost::alloc::string::String,
+    /// Owner of the function
+    #[prost(string, tag = "11")]
+    pub owner: ::prost::alloc::string::String,
+    /// Hex encoded SHA-256 of the function code
+    #[prost(string, tag = "12")]
+    pub code_hash: ::prost::alloc::string::String,
 }
 /// Trigger configuration for function execution
 #[derive(serde::Serialize, serde::Deserialize)]
@@ -215,6 +221,9 @@ pub struct RegisterFunctionRequest {
     /// Function code
     #[prost(string, tag = "6")]
     pub code: ::prost::alloc::string::String,
+    /// Hash of code the registry already has, used when code is empty
+    #[prost(string, tag = "7")]
+    pub code_hash: ::prost::alloc::string::String,
 }
 /// Function registration response
 #[derive(serde::Serialize, serde::Deserialize)]
@@ -251,6 +260,12 @@ pub struct UpdateFunctionRequest {
     /// Function code (optional)
     #[prost(string, optional, tag = "7")]
     pub code: ::core::option::Option<::prost::alloc::string::String>,
+    /// Version the update was based on, stale updates are rejected (optional)
+    #[prost(uint32, optional, tag = "8")]
+    pub expected_version: ::core::option::Option<u32>,
+    /// Hash of code the registry already has, used when code is not set (optional)
+    #[prost(string, optional, tag = "9")]
+    pub code_hash: ::core::option::Option<::prost::alloc::string::String>,
 }
 /// Function update response
 #[derive(serde::Serialize, serde::Deserialize)]
@@ -293,6 +308,12 @@ pub struct ListFunctionsRequest {
     /// Filter by trigger type
     #[prost(enumeration = "TriggerType", optional, tag = "3")]
     pub trigger_type: ::core::option::Option<i32>,
+    /// Filter by function name prefix
+    #[prost(string, tag = "4")]
+    pub name_prefix: ::prost::alloc::string::String,
+    /// Filter by owner
+    #[prost(string, tag = "5")]
+    pub owner: ::prost::alloc::string::String,
 }
 /// Function list response
 #[derive(serde::Serialize, serde::Deserialize)]
@@ -324,6 +345,177 @@ pub struct DeleteFunctionResponse {
     #[prost(bool, tag = "1")]
     pub success: bool,
 }
+/// Code lookup request
+#[derive(serde::Serialize, serde::Deserialize)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct HasCodeRequest {
+    /// Hex encoded SHA-256 of the code
+    #[prost(string, tag = "1")]
+    pub hash: ::prost::alloc::string::String,
+}
+/// Code lookup response
+#[derive(serde::Serialize, serde::Deserialize)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct HasCodeResponse {
+    /// Whether the registry has the code
+    #[prost(bool, tag = "1")]
+    pub exists: bool,
+}
+/// Parameter a template is instantiated with
+#[derive(serde::Serialize, serde::Deserialize)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct TemplateParameter {
+    /// Parameter name, used as {{name}} in the template
+    #[prost(string, tag = "1")]
+    pub name: ::prost::alloc::string::String,
+    /// Parameter description
+    #[prost(string, tag = "2")]
+    pub description: ::prost::alloc::string::String,
+    /// Value type: string, number, boolean or script_hash
+    #[prost(string, tag = "3")]
+    pub r#type: ::prost::alloc::string::String,
+    /// Whether a value must be supplied
+    #[prost(bool, tag = "4")]
+    pub required: bool,
+    /// Value used when none is supplied (optional)
+    #[prost(string, optional, tag = "5")]
+    pub default: ::core::option::Option<::prost::alloc::string::String>,
+}
+/// Version of a function template
+#[derive(serde::Serialize, serde::Deserialize)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct FunctionTemplate {
+    /// Template ID, shared by all versions
+    #[prost(string, tag = "1")]
+    pub id: ::prost::alloc::string::String,
+    /// Template name
+    #[prost(string, tag = "2")]
+    pub name: ::prost::alloc::string::String,
+    /// Template description
+    #[prost(string, tag = "3")]
+    pub description: ::prost::alloc::string::String,
+    /// Catalog category
+    #[prost(string, tag = "4")]
+    pub category: ::prost::alloc::string::String,
+    /// Version, set by the registry on publish
+    #[prost(uint32, tag = "5")]
+    pub version: u32,
+    /// Creation timestamp of the first version
+    #[prost(uint64, tag = "6")]
+    pub created_at: u64,
+    /// Publish timestamp of this version
+    #[prost(uint64, tag = "7")]
+    pub updated_at: u64,
+    /// Declared parameters
+    #[prost(message, repeated, tag = "8")]
+    pub parameters: ::prost::alloc::vec::Vec<TemplateParameter>,
+    /// Function code with {{parameter}} placeholders
+    #[prost(string, tag = "9")]
+    pub code: ::prost::alloc::string::String,
+    /// Trigger configuration with {{parameter}} placeholders
+    #[prost(message, optional, tag = "10")]
+    pub trigger: ::core::option::Option<TriggerConfig>,
+    /// Permission configuration
+    #[prost(message, optional, tag = "11")]
+    pub permissions: ::core::option::Option<PermissionConfig>,
+    /// Resource limits
+    #[prost(message, optional, tag = "12")]
+    pub resources: ::core::option::Option<ResourceLimits>,
+}
+/// Template publish request
+#[derive(serde::Serialize, serde::Deserialize)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct PublishTemplateRequest {
+    /// Template to publish
+    #[prost(message, optional, tag = "1")]
+    pub template: ::core::option::Option<FunctionTemplate>,
+    /// Latest version the update was based on, stale updates are rejected (optional)
+    #[prost(uint32, optional, tag = "2")]
+    pub expected_version: ::core::option::Option<u32>,
+}
+/// Template publish response
+#[derive(serde::Serialize, serde::Deserialize)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct PublishTemplateResponse {
+    /// Published template version
+    #[prost(message, optional, tag = "1")]
+    pub template: ::core::option::Option<FunctionTemplate>,
+}
+/// Template get request
+#[derive(serde::Serialize, serde::Deserialize)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct GetTemplateRequest {
+    /// Template ID
+    #[prost(string, tag = "1")]
+    pub id: ::prost::alloc::string::String,
+    /// Version, the latest if not set (optional)
+    #[prost(uint32, optional, tag = "2")]
+    pub version: ::core::option::Option<u32>,
+}
+/// Template get response
+#[derive(serde::Serialize, serde::Deserialize)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct GetTemplateResponse {
+    /// Template version
+    #[prost(message, optional, tag = "1")]
+    pub template: ::core::option::Option<FunctionTemplate>,
+}
+/// Template list request
+#[derive(serde::Serialize, serde::Deserialize)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ListTemplatesRequest {
+    /// Filter by category
+    #[prost(string, tag = "1")]
+    pub category: ::prost::alloc::string::String,
+}
+/// Template list response
+#[derive(serde::Serialize, serde::Deserialize)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ListTemplatesResponse {
+    /// Latest version of every template
+    #[prost(message, repeated, tag = "1")]
+    pub templates: ::prost::alloc::vec::Vec<FunctionTemplate>,
+}
+/// Template instantiation request
+#[derive(serde::Serialize, serde::Deserialize)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct InstantiateTemplateRequest {
+    /// Template ID
+    #[prost(string, tag = "1")]
+    pub template_id: ::prost::alloc::string::String,
+    /// Template version, the latest if not set (optional)
+    #[prost(uint32, optional, tag = "2")]
+    pub version: ::core::option::Option<u32>,
+    /// Name of the new function, the template name if empty
+    #[prost(string, tag = "3")]
+    pub name: ::prost::alloc::string::String,
+    /// Parameter values by name
+    #[prost(map = "string, string", tag = "4")]
+    pub parameters: ::std::collections::HashMap<::prost::alloc::string::String, ::prost::alloc::string::String>,
+    /// Owner of the new function
+    #[prost(string, tag = "5")]
+    pub owner: ::prost::alloc::string::String,
+}
+/// Template instantiation response
+#[derive(serde::Serialize, serde::Deserialize)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct InstantiateTemplateResponse {
+    /// Registered function metadata
+    #[prost(message, optional, tag = "1")]
+    pub metadata: ::core::option::Option<FunctionMetadata>,
+}
 /// Types of triggers
 #[derive(serde::Serialize, serde::Deserialize)]
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
@@ -531,6 +723,106 @@ pub mod function_registry_client {
             );
             self.inner.unary(request.into_request(), path, codec).await
         }
+        /// Check whether code with a content hash is stored, so it need not be uploaded
+        pub async fn has_code(
+            &mut self,
+            request: impl tonic::IntoRequest<super::HasCodeRequest>,
+        ) -> Result<tonic::Response<super::HasCodeResponse>, tonic::Status> {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::new(
+                        tonic::Code::Unknown,
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/registry.FunctionRegistry/HasCode",
+            );
+            self.inner.unary(request.into_request(), path, codec).await
+        }
+        /// Publish a template, as a new version if its ID exists
+        pub async fn publish_template(
+            &mut self,
+            request: impl tonic::IntoRequest<super::PublishTemplateRequest>,
+        ) -> Result<tonic::Response<super::PublishTemplateResponse>, tonic::Status> {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::new(
+                        tonic::Code::Unknown,
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/registry.FunctionRegistry/PublishTemplate",
+            );
+            self.inner.unary(request.into_request(), path, codec).await
+        }
+        /// Get a template version
+        pub async fn get_template(
+            &mut self,
+            request: impl tonic::IntoRequest<super::GetTemplateRequest>,
+        ) -> Result<tonic::Response<super::GetTemplateResponse>, tonic::Status> {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::new(
+                        tonic::Code::Unknown,
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/registry.FunctionRegistry/GetTemplate",
+            );
+            self.inner.unary(request.into_request(), path, codec).await
+        }
+        /// List the latest version of every template
+        pub async fn list_templates(
+            &mut self,
+            request: impl tonic::IntoRequest<super::ListTemplatesRequest>,
+        ) -> Result<tonic::Response<super::ListTemplatesResponse>, tonic::Status> {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::new(
+                        tonic::Code::Unknown,
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/registry.FunctionRegistry/ListTemplates",
+            );
+            self.inner.unary(request.into_request(), path, codec).await
+        }
+        /// Register a function owned by the caller from a template filled with parameters
+        pub async fn instantiate_template(
+            &mut self,
+            request: impl tonic::IntoRequest<super::InstantiateTemplateRequest>,
+        ) -> Result<tonic::Response<super::InstantiateTemplateResponse>, tonic::Status> {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::new(
+                        tonic::Code::Unknown,
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/registry.FunctionRegistry/InstantiateTemplate",
+            );
+            self.inner.unary(request.into_request(), path, codec).await
+        }
     }
 }
 /// Generated server implementations.
@@ -565,6 +857,31 @@ pub mod function_registry_server {
             &self,
             request: tonic::Request<super::DeleteFunctionRequest>,
         ) -> Result<tonic::Response<super::DeleteFunctionResponse>, tonic::Status>;
+        /// Check whether code with a content hash is stored, so it need not be uploaded
+        async fn has_code(
+            &self,
+            request: tonic::Request<super::HasCodeRequest>,
+        ) -> Result<tonic::Response<super::HasCodeResponse>, tonic::Status>;
+        /// Publish a template, as a new version if its ID exists
+        async fn publish_template(
+            &self,
+            request: tonic::Request<super::PublishTemplateRequest>,
+        ) -> Result<tonic::Response<super::PublishTemplateResponse>, tonic::Status>;
+        /// Get a template version
+        async fn get_template(
+            &self,
+            request: tonic::Request<super::GetTemplateRequest>,
+        ) -> Result<tonic::Response<super::GetTemplateResponse>, tonic::Status>;
+        /// List the latest version of every template
+        async fn list_templates(
+            &self,
+            request: tonic::Request<super::ListTemplatesRequest>,
+        ) -> Result<tonic::Response<super::ListTemplatesResponse>, tonic::Status>;
+        /// Register a function owned by the caller from a template filled with parameters
+        async fn instantiate_template(
+            &self,
+            request: tonic::Request<super::InstantiateTemplateRequest>,
+        ) -> Result<tonic::Response<super::InstantiateTemplateResponse>, tonic::Status>;
     }
     /// Function registry service
     #[derive(Debug)]
@@ -826,6 +1143,206 @@ pub mod function_registry_server {
                     };
                     Box::pin(fut)
                 }
+                "/registry.FunctionRegistry/HasCode" => {
+                    #[allow(non_camel_case_types)]
+                    struct HasCodeSvc<T: FunctionRegistry>(pub Arc<T>);
+                    impl<
+                        T: FunctionRegistry,
+                    > tonic::server::UnaryService<super::HasCodeRequest>
+                    for HasCodeSvc<T> {
+                        type Response = super::HasCodeResponse;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::HasCodeRequest>,
+                        ) -> Self::Future {
+                            let inner = self.0.clone();
+                            let fut = async move {
+                                (*inner).has_code(request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = HasCodeSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/registry.FunctionRegistry/PublishTemplate" => {
+                    #[allow(non_camel_case_types)]
+                    struct PublishTemplateSvc<T: FunctionRegistry>(pub Arc<T>);
+                    impl<
+                        T: FunctionRegistry,
+                    > tonic::server::UnaryService<super::PublishTemplateRequest>
+                    for PublishTemplateSvc<T> {
+                        type Response = super::PublishTemplateResponse;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::PublishTemplateRequest>,
+                        ) -> Self::Future {
+                            let inner = self.0.clone();
+                            let fut = async move {
+                                (*inner).publish_template(request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = PublishTemplateSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/registry.FunctionRegistry/GetTemplate" => {
+                    #[allow(non_camel_case_types)]
+                    struct GetTemplateSvc<T: FunctionRegistry>(pub Arc<T>);
+                    impl<
+                        T: FunctionRegistry,
+                    > tonic::server::UnaryService<super::GetTemplateRequest>
+                    for GetTemplateSvc<T> {
+                        type Response = super::GetTemplateResponse;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::GetTemplateRequest>,
+                        ) -> Self::Future {
+                            let inner = self.0.clone();
+                            let fut = async move {
+                                (*inner).get_template(request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = GetTemplateSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/registry.FunctionRegistry/ListTemplates" => {
+                    #[allow(non_camel_case_types)]
+                    struct ListTemplatesSvc<T: FunctionRegistry>(pub Arc<T>);
+                    impl<
+                        T: FunctionRegistry,
+                    > tonic::server::UnaryService<super::ListTemplatesRequest>
+                    for ListTemplatesSvc<T> {
+                        type Response = super::ListTemplatesResponse;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::ListTemplatesRequest>,
+                        ) -> Self::Future {
+                            let inner = self.0.clone();
+                            let fut = async move {
+                                (*inner).list_templates(request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = ListTemplatesSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/registry.FunctionRegistry/InstantiateTemplate" => {
+                    #[allow(non_camel_case_types)]
+                    struct InstantiateTemplateSvc<T: FunctionRegistry>(pub Arc<T>);
+                    impl<
+                        T: FunctionRegistry,
+                    > tonic::server::UnaryService<super::InstantiateTemplateRequest>
+                    for InstantiateTemplateSvc<T> {
+                        type Response = super::InstantiateTemplateResponse;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::InstantiateTemplateRequest>,
+                        ) -> Self::Future {
+                            let inner = self.0.clone();
+                            let fut = async move {
+                                (*inner).instantiate_template(request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = InstantiateTemplateSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
                 _ => {
                     Box::pin(async move {
                         Ok(
@@ -0,0 +1,260 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use serde_json::Value;
+use tokio::sync::RwLock;
+use uuid::Uuid;
+
+use crate::registry::models::ServiceFunction;
+
+/// Default maximum number of cached responses
+pub const DEFAULT_MAX_ENTRIES: usize = 10_000;
+
+/// Cache key of a service function invocation
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+struct CacheKey {
+    service_id: Uuid,
+    function: String,
+
+    /// Canonical JSON of the parameters
+    parameters: String,
+
+    /// Hash of the auth token, responses may depend on the caller
+    auth: Option<u64>,
+}
+
+#[derive(Clone, Debug)]
+struct CacheEntry {
+    response: Value,
+    expires_at: Instant,
+}
+
+/// Cache of read-only service function responses.
+///
+/// A function is cached when its `adapter_config` sets `cache_ttl_secs` and does not set
+/// `readonly` to false, e.g. `{"method": "balanceOf", "readonly": true, "cache_ttl_secs": 15}`.
+#[derive(Clone)]
+pub struct ResponseCache {
+    entries: Arc<RwLock<HashMap<CacheKey, CacheEntry>>>,
+    max_entries: usize,
+}
+
+impl Default for ResponseCache {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_ENTRIES)
+    }
+}
+
+impl ResponseCache {
+    /// Create a new response cache holding at most `max_entries` responses
+    pub fn new(max_entries: usize) -> Self {
+        Self {
+            entries: Arc::new(RwLock::new(HashMap::new())),
+            max_entries,
+        }
+    }
+
+    /// TTL of a function's responses, None if the function is not cacheable
+    pub fn function_ttl(function: &ServiceFunction) -> Option<Duration> {
+        let config = function.adapter_config.as_object()?;
+        if let Some(Value::Bool(false)) = config.get("readonly") {
+            return None;
+        }
+
+        match config.get("cache_ttl_secs").and_then(Value::as_u64) {
+            Some(ttl) if ttl > 0 => Some(Duration::from_secs(ttl)),
+            _ => None,
+        }
+    }
+
+    /// Get the cached response of an invocation, if any and not expired
+    pub async fn get(
+        &self,
+        service_id: &Uuid,
+        function: &str,
+        parameters: &Value,
+        auth_token: Option<&str>,
+    ) -> Option<Value> {
+        let key = cache_key(service_id, function, parameters, auth_token);
+        let entries = self.entries.read().await;
+        entries
+            .get(&key)
+            .filter(|entry| entry.expires_at > Instant::now())
+            .map(|entry| entry.response.clone())
+    }
+
+    /// Cache the response of an invocation for the given TTL
+    pub async fn insert(
+        &self,
+        service_id: &Uuid,
+        function: &str,
+        parameters: &Value,
+        auth_token: Option<&str>,
+        response: Value,
+        ttl: Duration,
+    ) {
+        if self.max_entries == 0 {
+            return;
+        }
+
+        let key = cache_key(service_id, function, parameters, auth_token);
+        let now = Instant::now();
+        let mut entries = self.entries.write().await;
+        if entries.len() >= self.max_entries && !entries.contains_key(&key) {
+            entries.retain(|_, entry| entry.expires_at > now);
+        }
+
+        // Still full, evict the response expiring first
+        if entries.len() >= self.max_entries && !entries.contains_key(&key) {
+            let oldest = entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.expires_at)
+                .map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                entries.remove(&oldest);
+            }
+        }
+
+        entries.insert(
+            key,
+            CacheEntry {
+                response,
+                expires_at: now + ttl,
+            },
+        );
+    }
+
+    /// Invalidate the cached responses of a service, or of one of its functions
+    pub async fn invalidate(&self, service_id: &Uuid, function: Option<&str>) -> usize {
+        let mut entries = self.entries.write().await;
+        let before = entries.len();
+        entries.retain(|key, _| {
+            key.service_id != *service_id || function.is_some_and(|f| key.function != f)
+        });
+        before - entries.len()
+    }
+
+    /// Invalidate all cached responses
+    pub async fn clear(&self) {
+        self.entries.write().await.clear();
+    }
+
+    /// Number of cached responses, including expired ones not yet evicted
+    pub async fn len(&self) -> usize {
+        self.entries.read().await.len()
+    }
+
+    /// Whether no responses are cached
+    pub async fn is_empty(&self) -> bool {
+        self.entries.read().await.is_empty()
+    }
+}
+
+fn cache_key(
+    service_id: &Uuid,
+    function: &str,
+    parameters: &Value,
+    auth_token: Option<&str>,
+) -> CacheKey {
+    CacheKey {
+        service_id: *service_id,
+        function: function.to_string(),
+        // serde_json maps are sorted, so equal parameters serialize equally
+        parameters: parameters.to_string(),
+        auth: auth_token.map(|token| {
+            let mut hasher = DefaultHasher::new();
+            token.hash(&mut hasher);
+            hasher.finish()
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn function(adapter_config: Value) -> ServiceFunction {
+        ServiceFunction {
+            name: "balanceOf".to_string(),
+            description: String::new(),
+            parameters: Vec::new(),
+            requires_auth: false,
+            requires_signature: false,
+            adapter_config,
+        }
+    }
+
+    #[test]
+    fn test_function_ttl() {
+        let ttl = ResponseCache::function_ttl(&function(json!({"cache_ttl_secs": 15})));
+        assert_eq!(ttl, Some(Duration::from_secs(15)));
+
+        let ttl = ResponseCache::function_ttl(&function(
+            json!({"readonly": false, "cache_ttl_secs": 15}),
+        ));
+        assert_eq!(ttl, None);
+
+        assert_eq!(ResponseCache::function_ttl(&function(json!({}))), None);
+    }
+
+    #[tokio::test]
+    async fn test_response_cache() {
+        let cache = ResponseCache::new(2);
+        let service_id = Uuid::new_v4();
+        let params = json!({"account": "NX8", "asset": "GAS"});
+        let ttl = Duration::from_secs(60);
+
+        cache
+            .insert(&service_id, "balanceOf", &params, None, json!(1), ttl)
+            .await;
+        let reordered = json!({"asset": "GAS", "account": "NX8"});
+        assert_eq!(
+            cache.get(&service_id, "balanceOf", &reordered, None).await,
+            Some(json!(1))
+        );
+        assert_eq!(
+            cache
+                .get(&service_id, "balanceOf", &params, Some("t"))
+                .await,
+            None
+        );
+
+        // Full, the response expiring first is evicted
+        cache
+            .insert(
+                &service_id,
+                "symbol",
+                &json!({}),
+                None,
+                json!("GAS"),
+                Duration::from_secs(90),
+            )
+            .await;
+        cache
+            .insert(
+                &service_id,
+                "decimals",
+                &json!({}),
+                None,
+                json!(8),
+                Duration::from_secs(120),
+            )
+            .await;
+        assert_eq!(cache.len().await, 2);
+        assert_eq!(
+            cache.get(&service_id, "balanceOf", &params, None).await,
+            None
+        );
+
+        assert_eq!(cache.invalidate(&service_id, Some("symbol")).await, 1);
+        assert_eq!(cache.invalidate(&service_id, None).await, 1);
+        assert!(cache.is_empty().await);
+    }
+}
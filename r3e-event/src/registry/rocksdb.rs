@@ -1,23 +1,77 @@
 // Copyright @ 2023 - 2024, R3E Network
 // All Rights Reserved
 
+use crate::registry::storage::{FunctionCursor, FunctionPage, FunctionQuery};
+use crate::registry::templates::FunctionTemplate;
 use crate::registry::FunctionMetadata;
 use crate::registry::RegistryError;
 use r3e_store::RocksDBStore;
 use r3e_store::rocksdb::RocksDbConfig;
+use r3e_store::{
+    decode_record, encode_record, migrate_cf, MigrationStats, TableOptions, ValueCompression,
+    Versioned,
+};
+use std::collections::HashMap;
 use std::path::Path;
 
 /// RocksDB implementation of function storage
 pub struct RocksDBFunctionStorage {
     db: RocksDBStore,
     cf_name: String,
+    // List order index, keyed by `FunctionCursor::to_key`
+    index_cf_name: String,
+    // Code by content hash, and the number of functions referencing it
+    code_cf_name: String,
+    code_refs_cf_name: String,
+    // Template versions keyed by `{id}/{version}`, and the latest version of each template
+    template_cf_name: String,
+    template_latest_cf_name: String,
+}
+
+/// Number of index entries read per scan
+const INDEX_SCAN_BATCH: usize = 256;
+
+/// Function metadata is stored as a versioned record, bump the version and add a migration
+/// when changing it other than by adding optional fields
+impl Versioned for FunctionMetadata {
+    const SCHEMA: &'static str = "function_metadata";
+    const VERSION: u32 = 1;
+}
+
+impl Versioned for FunctionTemplate {
+    const SCHEMA: &'static str = "function_template";
+    const VERSION: u32 = 1;
+}
+
+/// Key of a template version, zero padded so versions sort in order
+fn template_key(id: &str, version: u32) -> String {
+    format!("{}/{:010}", id, version)
 }
 
 impl RocksDBFunctionStorage {
     /// Create a new RocksDB function storage
     pub fn new<P: AsRef<Path>>(db_path: P) -> Result<Self, RegistryError> {
+        let cf_name = "functions".to_string();
+        let index_cf_name = "functions_by_updated".to_string();
+        let code_cf_name = "function_code".to_string();
+        let code_refs_cf_name = "function_code_refs".to_string();
+        let template_cf_name = "function_templates".to_string();
+        let template_latest_cf_name = "function_templates_latest".to_string();
+
+        // Function code and source maps compress well, large bundles are chunked
+        let table_options = HashMap::from([
+            (
+                cf_name.clone(),
+                TableOptions::compressed(ValueCompression::Zstd { level: 3 }),
+            ),
+            (
+                code_cf_name.clone(),
+                TableOptions::compressed(ValueCompression::Zstd { level: 3 }),
+            ),
+        ]);
         let config = RocksDbConfig {
             path: db_path.as_ref().to_string_lossy().to_string(),
+            table_options,
             ..Default::default()
         };
         
@@ -26,33 +80,142 @@ impl RocksDBFunctionStorage {
         // Open the database
         db.open().map_err(|e| RegistryError::Storage(format!("Failed to open RocksDB store: {}", e)))?;
         
-        let cf_name = "functions".to_string();
-        
-        // Create column family if it doesn't exist
-        db.create_cf_if_missing(&cf_name)
-            .map_err(|e| RegistryError::Storage(format!("Failed to create column family: {}", e)))?;
+        // Create column families if they don't exist
+        for cf in [
+            &cf_name,
+            &index_cf_name,
+            &code_cf_name,
+            &code_refs_cf_name,
+            &template_cf_name,
+            &template_latest_cf_name,
+        ] {
+            db.create_cf_if_missing(cf)
+                .map_err(|e| RegistryError::Storage(format!("Failed to create column family: {}", e)))?;
+        }
+
+        let storage = Self {
+            db,
+            cf_name,
+            index_cf_name,
+            code_cf_name,
+            code_refs_cf_name,
+            template_cf_name,
+            template_latest_cf_name,
+        };
+        storage.rebuild_index()?;
 
-        Ok(Self { db, cf_name })
+        Ok(storage)
+    }
+
+    /// Index the functions missing from the list index, such as the ones stored by releases
+    /// before it existed; returns the number of entries added
+    fn rebuild_index(&self) -> Result<usize, RegistryError> {
+        let mut added = 0;
+        let mut start = Vec::new();
+        loop {
+            let entries: Vec<(Box<[u8]>, Vec<u8>)> = self
+                .db
+                .scan_cf(&self.cf_name, &start, INDEX_SCAN_BATCH)
+                .map_err(|e| RegistryError::Storage(format!("Failed to scan functions: {}", e)))?;
+            let exhausted = entries.len() < INDEX_SCAN_BATCH;
+
+            for (key, value) in entries {
+                if *key == *start {
+                    continue;
+                }
+                start = key.to_vec();
+
+                let metadata = decode_record::<FunctionMetadata>(&value)
+                    .map_err(|e| RegistryError::Storage(e.to_string()))?
+                    .value;
+                let index_key = FunctionCursor::of(&metadata).to_key();
+                let indexed = self
+                    .db
+                    .get_cf::<_, ()>(&self.index_cf_name, &index_key)
+                    .map_err(|e| RegistryError::Storage(format!("Failed to read index: {}", e)))?
+                    .is_some();
+                if !indexed {
+                    self.db
+                        .put_cf(&self.index_cf_name, &index_key, &())
+                        .map_err(|e| {
+                            RegistryError::Storage(format!("Failed to update index: {}", e))
+                        })?;
+                    added += 1;
+                }
+            }
+
+            if exhausted {
+                break;
+            }
+        }
+
+        if added > 0 {
+            log::info!(
+                "registry: indexed {} functions missing from the list index",
+                added
+            );
+        }
+        Ok(added)
+    }
+
+    fn get_code_refs(&self, hash: &str) -> Result<u64, RegistryError> {
+        self.db
+            .get_cf::<_, u64>(&self.code_refs_cf_name, hash)
+            .map(Option::unwrap_or_default)
+            .map_err(|e| RegistryError::Storage(format!("Failed to get code references: {}", e)))
+    }
+
+    /// Upgrade the function metadata written by older releases to the current version
+    pub fn migrate(&self, dry_run: bool) -> Result<MigrationStats, RegistryError> {
+        migrate_cf::<FunctionMetadata>(&self.db, &self.cf_name, dry_run)
+            .map_err(|e| RegistryError::Storage(format!("Failed to migrate functions: {}", e)))
+    }
+
+    fn get_existing(&self, id: &str) -> Result<Option<FunctionMetadata>, RegistryError> {
+        match crate::registry::FunctionStorage::get_function(self, id) {
+            Ok(metadata) => Ok(Some(metadata)),
+            Err(RegistryError::NotFound(_)) => Ok(None),
+            Err(e) => Err(e),
+        }
     }
 }
 
 impl crate::registry::FunctionStorage for RocksDBFunctionStorage {
     fn store_function(&mut self, metadata: &FunctionMetadata) -> Result<(), RegistryError> {
         let key = &metadata.id;
-        let value =
-            serde_json::to_vec(metadata).map_err(|e| RegistryError::Storage(e.to_string()))?;
+        let value = encode_record(metadata).map_err(|e| RegistryError::Storage(e.to_string()))?;
+
+        // Move the index entry if the function was updated
+        if let Some(previous) = self.get_existing(key)? {
+            self.db
+                .delete_cf(&self.index_cf_name, FunctionCursor::of(&previous).to_key())
+                .map_err(|e| RegistryError::Storage(format!("Failed to update index: {}", e)))?;
+        }
 
         self.db
             .put_cf(&self.cf_name, key, &value)
-            .map_err(|e| RegistryError::Storage(format!("Failed to store function: {}", e)))
+            .map_err(|e| RegistryError::Storage(format!("Failed to store function: {}", e)))?;
+
+        self.db
+            .put_cf(&self.index_cf_name, FunctionCursor::of(metadata).to_key(), &())
+            .map_err(|e| RegistryError::Storage(format!("Failed to update index: {}", e)))
     }
 
     fn get_function(&self, id: &str) -> Result<FunctionMetadata, RegistryError> {
         match self.db.get_cf::<_, Vec<u8>>(&self.cf_name, id) {
             Ok(Some(value)) => {
-                let metadata: FunctionMetadata = serde_json::from_slice(&value)
+                let decoded = decode_record::<FunctionMetadata>(&value)
                     .map_err(|e| RegistryError::Storage(e.to_string()))?;
-                Ok(metadata)
+
+                // Write metadata of older releases back upgraded, the list index is unchanged
+                if decoded.outdated {
+                    let upgraded = encode_record(&decoded.value)
+                        .map_err(|e| RegistryError::Storage(e.to_string()))?;
+                    self.db.put_cf(&self.cf_name, id, &upgraded).map_err(|e| {
+                        RegistryError::Storage(format!("Failed to upgrade function: {}", e))
+                    })?;
+                }
+                Ok(decoded.value)
             }
             Ok(None) => Err(RegistryError::NotFound(format!(
                 "Function not found: {}",
@@ -62,56 +225,246 @@ impl crate::registry::FunctionStorage for RocksDBFunctionStorage {
         }
     }
 
-    fn list_functions(
-        &self,
-        _page_token: String,
-        page_size: u32,
-        trigger_type: String,
-    ) -> Result<Vec<FunctionMetadata>, RegistryError> {
-        // Create a prefix iterator to collect the results
-        let iter: Box<dyn Iterator<Item = (Box<[u8]>, Box<[u8]>)> + Send> = 
-            self.db.prefix_iter_cf(&self.cf_name, b"")
-            .map_err(|e| RegistryError::Storage(format!("Failed to scan functions: {}", e)))?;
-        
+    fn list_functions(&self, query: &FunctionQuery) -> Result<FunctionPage, RegistryError> {
+        let mut start = query.cursor.as_ref().map(|c| c.to_key()).unwrap_or_default();
+        let mut skip_start = query.cursor.is_some();
         let mut functions = Vec::new();
-        let mut count = 0;
-        
-        for (_, value_boxed) in iter {
-            if count >= page_size {
-                break;
+
+        // Walk the index in list order until the page (plus one look-ahead) is full
+        'scan: loop {
+            let entries: Vec<(Box<[u8]>, ())> = self
+                .db
+                .scan_cf(&self.index_cf_name, &start, INDEX_SCAN_BATCH)
+                .map_err(|e| RegistryError::Storage(format!("Failed to scan functions: {}", e)))?;
+            let exhausted = entries.len() < INDEX_SCAN_BATCH;
+
+            for (key, _) in entries {
+                if skip_start && *key == *start {
+                    continue;
+                }
+                start = key.to_vec();
+                skip_start = true;
+
+                let cursor = FunctionCursor::from_key(&key).ok_or_else(|| {
+                    RegistryError::Storage("Invalid function index key".to_string())
+                })?;
+                // Skip entries left behind by an interrupted update or delete
+                let metadata = match self.get_existing(&cursor.id)? {
+                    Some(metadata) if FunctionCursor::of(&metadata) == cursor => metadata,
+                    _ => continue,
+                };
+
+                if query.matches(&metadata) {
+                    functions.push(metadata);
+                    if functions.len() > query.limit {
+                        break 'scan;
+                    }
+                }
             }
-            
-            let value_vec = value_boxed.to_vec();
-            
-            let metadata: FunctionMetadata = serde_json::from_slice(&value_vec)
-                .map_err(|e| RegistryError::Storage(e.to_string()))?;
-            
-            // If trigger_type is empty, include all functions
-            if trigger_type.is_empty() || metadata.trigger.as_ref().map_or(false, |t| t.trigger_type == trigger_type) {
-                functions.push(metadata);
-                count += 1;
+
+            if exhausted {
+                break;
             }
         }
 
-        Ok(functions)
+        Ok(FunctionPage::from_matches(functions, query.limit))
     }
 
     fn delete_function(&mut self, id: &str) -> Result<bool, RegistryError> {
         // Check if function exists
-        let exists = match self.db.get_cf::<_, Vec<u8>>(&self.cf_name, id) {
-            Ok(Some(_)) => true,
-            Ok(None) => false,
-            Err(e) => return Err(RegistryError::Storage(format!("Failed to get function: {}", e))),
+        let metadata = match self.get_existing(id)? {
+            Some(metadata) => metadata,
+            None => return Ok(false),
         };
 
-        if !exists {
-            return Ok(false);
-        }
-
         self.db
             .delete_cf(&self.cf_name, id)
             .map_err(|e| RegistryError::Storage(format!("Failed to delete function: {}", e)))?;
 
+        self.db
+            .delete_cf(&self.index_cf_name, FunctionCursor::of(&metadata).to_key())
+            .map_err(|e| RegistryError::Storage(format!("Failed to update index: {}", e)))?;
+
         Ok(true)
     }
+
+    fn put_code(&mut self, hash: &str, code: &str) -> Result<(), RegistryError> {
+        let refs = self.get_code_refs(hash)?;
+        if refs == 0 {
+            self.db
+                .put_cf(&self.code_cf_name, hash, &code)
+                .map_err(|e| RegistryError::Storage(format!("Failed to store code: {}", e)))?;
+        }
+
+        self.db
+            .put_cf(&self.code_refs_cf_name, hash, &(refs + 1))
+            .map_err(|e| RegistryError::Storage(format!("Failed to store code references: {}", e)))
+    }
+
+    fn get_code(&self, hash: &str) -> Result<Option<String>, RegistryError> {
+        self.db
+            .get_cf::<_, String>(&self.code_cf_name, hash)
+            .map_err(|e| RegistryError::Storage(format!("Failed to get code: {}", e)))
+    }
+
+    fn release_code(&mut self, hash: &str) -> Result<bool, RegistryError> {
+        let refs = self.get_code_refs(hash)?;
+        if refs > 1 {
+            self.db
+                .put_cf(&self.code_refs_cf_name, hash, &(refs - 1))
+                .map_err(|e| {
+                    RegistryError::Storage(format!("Failed to store code references: {}", e))
+                })?;
+            return Ok(false);
+        }
+
+        // Delete the count first, a crash in between leaves code the next upload overwrites
+        self.db
+            .delete_cf(&self.code_refs_cf_name, hash)
+            .map_err(|e| {
+                RegistryError::Storage(format!("Failed to delete code references: {}", e))
+            })?;
+        self.db
+            .delete_cf(&self.code_cf_name, hash)
+            .map_err(|e| RegistryError::Storage(format!("Failed to delete code: {}", e)))?;
+        Ok(refs == 1)
+    }
+
+    fn store_template(&mut self, template: &FunctionTemplate) -> Result<(), RegistryError> {
+        let value = encode_record(template).map_err(|e| RegistryError::Storage(e.to_string()))?;
+        self.db
+            .put_cf(
+                &self.template_cf_name,
+                template_key(&template.id, template.version),
+                &value,
+            )
+            .map_err(|e| RegistryError::Storage(format!("Failed to store template: {}", e)))?;
+
+        // Point at the new version only once it is stored
+        self.db
+            .put_cf(
+                &self.template_latest_cf_name,
+                &template.id,
+                &template.version,
+            )
+            .map_err(|e| RegistryError::Storage(format!("Failed to store template: {}", e)))
+    }
+
+    fn get_template(
+        &self,
+        id: &str,
+        version: Option<u32>,
+    ) -> Result<FunctionTemplate, RegistryError> {
+        let version = match version {
+            Some(version) => version,
+            None => self
+                .db
+                .get_cf::<_, u32>(&self.template_latest_cf_name, id)
+                .map_err(|e| RegistryError::Storage(format!("Failed to get template: {}", e)))?
+                .ok_or_else(|| RegistryError::NotFound(format!("template {}", id)))?,
+        };
+
+        match self
+            .db
+            .get_cf::<_, Vec<u8>>(&self.template_cf_name, template_key(id, version))
+        {
+            Ok(Some(value)) => decode_record::<FunctionTemplate>(&value)
+                .map(|decoded| decoded.value)
+                .map_err(|e| RegistryError::Storage(e.to_string())),
+            Ok(None) => Err(RegistryError::NotFound(format!(
+                "template {} version {}",
+                id, version
+            ))),
+            Err(e) => Err(RegistryError::Storage(format!(
+                "Failed to get template: {}",
+                e
+            ))),
+        }
+    }
+
+    fn list_templates(&self) -> Result<Vec<FunctionTemplate>, RegistryError> {
+        let mut templates = Vec::new();
+        let mut start = Vec::new();
+        loop {
+            let entries: Vec<(Box<[u8]>, u32)> = self
+                .db
+                .scan_cf(&self.template_latest_cf_name, &start, INDEX_SCAN_BATCH)
+                .map_err(|e| RegistryError::Storage(format!("Failed to scan templates: {}", e)))?;
+            let exhausted = entries.len() < INDEX_SCAN_BATCH;
+
+            for (key, version) in entries {
+                if *key == *start {
+                    continue;
+                }
+                start = key.to_vec();
+                let id = String::from_utf8(key.to_vec())
+                    .map_err(|_| RegistryError::Storage("Invalid template key".to_string()))?;
+                templates.push(self.get_template(&id, Some(version))?);
+            }
+
+            if exhausted {
+                return Ok(templates);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::registry::FunctionStorage;
+
+    fn function(i: u64) -> FunctionMetadata {
+        FunctionMetadata {
+            id: format!("fn-{}", i),
+            name: format!("function-{}", i),
+            description: String::new(),
+            version: 1,
+            created_at: i,
+            updated_at: i,
+            trigger: None,
+            permissions: None,
+            resources: None,
+            code: String::new(),
+            code_blob: None,
+            code_hash: None,
+            source_map: None,
+            owner: None,
+            input_schema: None,
+            output_schema: None,
+            template: None,
+        }
+    }
+
+    #[test]
+    fn test_index_rows_stored_before_the_index() {
+        let dir = tempfile::tempdir().unwrap();
+
+        {
+            let mut storage = RocksDBFunctionStorage::new(dir.path()).unwrap();
+            storage.store_function(&function(1)).unwrap();
+            storage.store_function(&function(3)).unwrap();
+
+            // A row written by a release without the list index
+            let legacy = function(2);
+            storage
+                .db
+                .put_cf(
+                    &storage.cf_name,
+                    &legacy.id,
+                    &encode_record(&legacy).unwrap(),
+                )
+                .unwrap();
+            let page = storage.list_functions(&FunctionQuery::default()).unwrap();
+            assert_eq!(page.functions.len(), 2);
+        }
+
+        let storage = RocksDBFunctionStorage::new(dir.path()).unwrap();
+        let page = storage.list_functions(&FunctionQuery::default()).unwrap();
+        let ids: Vec<&str> = page.functions.iter().map(|f| f.id.as_str()).collect();
+        assert_eq!(ids, ["fn-3", "fn-2", "fn-1"]);
+
+        // Opening again finds nothing left to index
+        assert_eq!(storage.rebuild_index().unwrap(), 0);
+    }
 }
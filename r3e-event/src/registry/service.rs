@@ -9,8 +9,11 @@ use crate::registry::{
         FunctionRegistry as FunctionRegistryService, FunctionRegistryServer,
     },
     DeleteFunctionRequest, DeleteFunctionResponse, GetFunctionRequest,
-    GetFunctionResponse, ListFunctionsRequest, ListFunctionsResponse, RegisterFunctionRequest,
-    RegisterFunctionResponse, UpdateFunctionRequest, UpdateFunctionResponse,
+    GetFunctionResponse, GetTemplateRequest, GetTemplateResponse, HasCodeRequest, HasCodeResponse,
+    InstantiateTemplateRequest, InstantiateTemplateResponse, ListFunctionsRequest,
+    ListFunctionsResponse, ListTemplatesRequest, ListTemplatesResponse, PublishTemplateRequest,
+    PublishTemplateResponse, RegisterFunctionRequest, RegisterFunctionResponse,
+    UpdateFunctionRequest, UpdateFunctionResponse,
 };
 
 use crate::registry::{FunctionRegistry, RegistryError};
@@ -93,6 +96,66 @@ impl FunctionRegistryService for FunctionRegistryImpl {
             Err(err) => Err(registry_error_to_status(err)),
         }
     }
+
+    async fn has_code(
+        &self,
+        request: Request<HasCodeRequest>,
+    ) -> Result<Response<HasCodeResponse>, Status> {
+        let request = request.into_inner();
+
+        match self.registry.has_code(request).await {
+            Ok(response) => Ok(Response::new(response)),
+            Err(err) => Err(registry_error_to_status(err)),
+        }
+    }
+
+    async fn publish_template(
+        &self,
+        request: Request<PublishTemplateRequest>,
+    ) -> Result<Response<PublishTemplateResponse>, Status> {
+        let request = request.into_inner();
+
+        match self.registry.publish_template(request).await {
+            Ok(response) => Ok(Response::new(response)),
+            Err(err) => Err(registry_error_to_status(err)),
+        }
+    }
+
+    async fn get_template(
+        &self,
+        request: Request<GetTemplateRequest>,
+    ) -> Result<Response<GetTemplateResponse>, Status> {
+        let request = request.into_inner();
+
+        match self.registry.get_template(request).await {
+            Ok(response) => Ok(Response::new(response)),
+            Err(err) => Err(registry_error_to_status(err)),
+        }
+    }
+
+    async fn list_templates(
+        &self,
+        request: Request<ListTemplatesRequest>,
+    ) -> Result<Response<ListTemplatesResponse>, Status> {
+        let request = request.into_inner();
+
+        match self.registry.list_templates(request).await {
+            Ok(response) => Ok(Response::new(response)),
+            Err(err) => Err(registry_error_to_status(err)),
+        }
+    }
+
+    async fn instantiate_template(
+        &self,
+        request: Request<InstantiateTemplateRequest>,
+    ) -> Result<Response<InstantiateTemplateResponse>, Status> {
+        let request = request.into_inner();
+
+        match self.registry.instantiate_template(request).await {
+            Ok(response) => Ok(Response::new(response)),
+            Err(err) => Err(registry_error_to_status(err)),
+        }
+    }
 }
 
 /// Convert registry errors to gRPC status
@@ -100,6 +163,7 @@ fn registry_error_to_status(err: RegistryError) -> Status {
     match err {
         RegistryError::NotFound(msg) => Status::not_found(msg),
         RegistryError::Validation(msg) => Status::invalid_argument(msg),
+        RegistryError::Conflict(msg) => Status::aborted(msg),
         RegistryError::Storage(msg) => Status::internal(format!("Storage error: {}", msg)),
         RegistryError::Internal(msg) => Status::internal(format!("Internal error: {}", msg)),
     }
@@ -1,12 +1,205 @@
 // Copyright @ 2023 - 2024, R3E Network
 // All Rights Reserved
 
-use std::collections::HashMap;
+use std::cmp::Ordering;
+use std::collections::{BTreeSet, HashMap};
 use std::fmt::Debug;
+use std::ops::Bound;
 
+use base64::Engine;
+
+use crate::registry::templates::FunctionTemplate;
 use crate::registry::FunctionMetadata;
 use crate::registry::RegistryError;
 
+/// Page size used when a list request does not specify one
+pub const DEFAULT_PAGE_SIZE: usize = 50;
+
+/// Largest page size a list request may ask for
+pub const MAX_PAGE_SIZE: usize = 1000;
+
+/// Position of a function in the list order: most recently updated first, ties broken by ID.
+///
+/// Cursors are exclusive, so a page starts right after the function the cursor points at.
+/// A function updated between two page requests moves to the front of the list and may be
+/// missed by the running listing, but no function is ever returned twice.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FunctionCursor {
+    pub updated_at: u64,
+    pub id: String,
+}
+
+impl FunctionCursor {
+    /// Cursor pointing at a function
+    pub fn of(metadata: &FunctionMetadata) -> Self {
+        Self {
+            updated_at: metadata.updated_at,
+            id: metadata.id.clone(),
+        }
+    }
+
+    /// Storage key of the cursor, whose byte order matches the list order
+    pub fn to_key(&self) -> Vec<u8> {
+        let mut key = (u64::MAX - self.updated_at).to_be_bytes().to_vec();
+        key.extend_from_slice(self.id.as_bytes());
+        key
+    }
+
+    /// Parse a storage key created by `to_key`
+    pub fn from_key(key: &[u8]) -> Option<Self> {
+        if key.len() < 8 {
+            return None;
+        }
+
+        let (updated_at, id) = key.split_at(8);
+        Some(Self {
+            updated_at: u64::MAX - u64::from_be_bytes(updated_at.try_into().ok()?),
+            id: String::from_utf8(id.to_vec()).ok()?,
+        })
+    }
+
+    /// Encode the cursor as an opaque page token
+    pub fn to_page_token(&self) -> String {
+        base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(self.to_key())
+    }
+
+    /// Decode a page token created by `to_page_token`
+    pub fn from_page_token(token: &str) -> Result<Self, RegistryError> {
+        base64::engine::general_purpose::URL_SAFE_NO_PAD
+            .decode(token)
+            .ok()
+            .and_then(|key| Self::from_key(&key))
+            .ok_or_else(|| RegistryError::Validation(format!("invalid page token: {}", token)))
+    }
+}
+
+impl Ord for FunctionCursor {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other
+            .updated_at
+            .cmp(&self.updated_at)
+            .then_with(|| self.id.cmp(&other.id))
+    }
+}
+
+impl PartialOrd for FunctionCursor {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Function list query
+#[derive(Clone, Debug)]
+pub struct FunctionQuery {
+    /// Start after this function, or at the beginning if `None`
+    pub cursor: Option<FunctionCursor>,
+
+    /// Maximum number of functions to return
+    pub limit: usize,
+
+    /// Only functions with this trigger type, if not empty
+    pub trigger_type: String,
+
+    /// Only functions whose name starts with this prefix, if not empty
+    pub name_prefix: String,
+
+    /// Only functions of this owner, if not empty
+    pub owner: String,
+}
+
+impl Default for FunctionQuery {
+    fn default() -> Self {
+        Self {
+            cursor: None,
+            limit: DEFAULT_PAGE_SIZE,
+            trigger_type: String::new(),
+            name_prefix: String::new(),
+            owner: String::new(),
+        }
+    }
+}
+
+impl FunctionQuery {
+    /// Check whether a function passes the filters of the query
+    pub fn matches(&self, metadata: &FunctionMetadata) -> bool {
+        (self.trigger_type.is_empty()
+            || metadata
+                .trigger
+                .as_ref()
+                .map_or(false, |t| t.trigger_type == self.trigger_type))
+            && metadata.name.starts_with(&self.name_prefix)
+            && (self.owner.is_empty() || metadata.owner.as_deref() == Some(self.owner.as_str()))
+    }
+}
+
+/// Page of a function listing
+#[derive(Clone, Debug, Default)]
+pub struct FunctionPage {
+    /// Functions of the page, in list order
+    pub functions: Vec<FunctionMetadata>,
+
+    /// Cursor of the next page, `None` if this is the last page
+    pub next_cursor: Option<FunctionCursor>,
+}
+
+impl FunctionPage {
+    /// Build a page from up to `limit + 1` matching functions in list order
+    pub(crate) fn from_matches(mut functions: Vec<FunctionMetadata>, limit: usize) -> Self {
+        let next_cursor = if functions.len() > limit {
+            functions.truncate(limit);
+            functions.last().map(FunctionCursor::of)
+        } else {
+            None
+        };
+
+        Self {
+            functions,
+            next_cursor,
+        }
+    }
+}
+
+/// List order index of in-memory storages
+#[derive(Default)]
+struct FunctionIndex {
+    cursors: BTreeSet<FunctionCursor>,
+}
+
+impl FunctionIndex {
+    fn insert(&mut self, previous: Option<&FunctionMetadata>, metadata: &FunctionMetadata) {
+        if let Some(previous) = previous {
+            self.cursors.remove(&FunctionCursor::of(previous));
+        }
+        self.cursors.insert(FunctionCursor::of(metadata));
+    }
+
+    fn remove(&mut self, metadata: &FunctionMetadata) {
+        self.cursors.remove(&FunctionCursor::of(metadata));
+    }
+
+    fn list(
+        &self,
+        functions: &HashMap<String, FunctionMetadata>,
+        query: &FunctionQuery,
+    ) -> FunctionPage {
+        let start = match &query.cursor {
+            Some(cursor) => Bound::Excluded(cursor),
+            None => Bound::Unbounded,
+        };
+
+        let matches = self
+            .cursors
+            .range((start, Bound::Unbounded))
+            .filter_map(|cursor| functions.get(&cursor.id))
+            .filter(|metadata| query.matches(metadata))
+            .take(query.limit + 1)
+            .cloned()
+            .collect();
+
+        FunctionPage::from_matches(matches, query.limit)
+    }
+}
+
 /// Storage interface for function metadata
 pub trait FunctionStorage: Send + Sync {
     /// Store a function metadata
@@ -15,21 +208,110 @@ pub trait FunctionStorage: Send + Sync {
     /// Get a function metadata by ID
     fn get_function(&self, id: &str) -> Result<FunctionMetadata, RegistryError>;
 
-    /// List functions with optional filtering
-    fn list_functions(
-        &self,
-        page_token: String,
-        page_size: u32,
-        trigger_type: String,
-    ) -> Result<Vec<FunctionMetadata>, RegistryError>;
+    /// List a page of functions matching the query
+    fn list_functions(&self, query: &FunctionQuery) -> Result<FunctionPage, RegistryError>;
 
     /// Delete a function by ID
     fn delete_function(&mut self, id: &str) -> Result<bool, RegistryError>;
+
+    /// Store code under its content hash, or add a reference if it is already stored
+    fn put_code(&mut self, hash: &str, code: &str) -> Result<(), RegistryError>;
+
+    /// Get code by content hash
+    fn get_code(&self, hash: &str) -> Result<Option<String>, RegistryError>;
+
+    /// Drop a reference to code, deleting the code with its last reference.
+    ///
+    /// Returns whether the code was deleted.
+    fn release_code(&mut self, hash: &str) -> Result<bool, RegistryError>;
+
+    /// Store a template version, keeping the earlier versions
+    fn store_template(&mut self, template: &FunctionTemplate) -> Result<(), RegistryError>;
+
+    /// Get a template version, the latest if `version` is not set
+    fn get_template(
+        &self,
+        id: &str,
+        version: Option<u32>,
+    ) -> Result<FunctionTemplate, RegistryError>;
+
+    /// List the latest version of every template, by ID
+    fn list_templates(&self) -> Result<Vec<FunctionTemplate>, RegistryError>;
+}
+
+/// Pick a version out of the versions of a template, ordered oldest first
+fn pick_template(
+    id: &str,
+    versions: Option<&Vec<FunctionTemplate>>,
+    version: Option<u32>,
+) -> Result<FunctionTemplate, RegistryError> {
+    let versions = versions.ok_or_else(|| RegistryError::NotFound(format!("template {}", id)))?;
+    match version {
+        Some(version) => versions.iter().find(|t| t.version == version),
+        None => versions.last(),
+    }
+    .cloned()
+    .ok_or_else(|| RegistryError::NotFound(format!("template {} version {:?}", id, version)))
+}
+
+/// Latest versions of the templates, by ID
+fn latest_templates(templates: &HashMap<String, Vec<FunctionTemplate>>) -> Vec<FunctionTemplate> {
+    let mut latest: Vec<FunctionTemplate> = templates
+        .values()
+        .filter_map(|versions| versions.last().cloned())
+        .collect();
+    latest.sort_by(|a, b| a.id.cmp(&b.id));
+    latest
+}
+
+/// Reference counts of the code kept by content hash
+#[derive(Default)]
+struct CodeRefs {
+    counts: HashMap<String, u64>,
+}
+
+impl CodeRefs {
+    /// Count the code references of stored functions
+    fn of<'a>(functions: impl IntoIterator<Item = &'a FunctionMetadata>) -> Self {
+        let mut refs = Self::default();
+        for metadata in functions {
+            if let Some(hash) = metadata.stored_code_hash() {
+                refs.retain(hash);
+            }
+        }
+        refs
+    }
+
+    /// Add a reference, returning whether it is the first
+    fn retain(&mut self, hash: &str) -> bool {
+        let count = self.counts.entry(hash.to_string()).or_default();
+        *count += 1;
+        *count == 1
+    }
+
+    /// Drop a reference, returning whether it was the last
+    fn release(&mut self, hash: &str) -> bool {
+        match self.counts.get_mut(hash) {
+            Some(count) if *count > 1 => {
+                *count -= 1;
+                false
+            }
+            Some(_) => {
+                self.counts.remove(hash);
+                true
+            }
+            None => false,
+        }
+    }
 }
 
 /// In-memory implementation of function storage
 pub struct MemoryStorage {
     functions: HashMap<String, FunctionMetadata>,
+    index: FunctionIndex,
+    code: HashMap<String, String>,
+    code_refs: CodeRefs,
+    templates: HashMap<String, Vec<FunctionTemplate>>,
 }
 
 impl MemoryStorage {
@@ -37,13 +319,18 @@ impl MemoryStorage {
     pub fn new() -> Self {
         Self {
             functions: HashMap::new(),
+            index: FunctionIndex::default(),
+            code: HashMap::new(),
+            code_refs: CodeRefs::default(),
+            templates: HashMap::new(),
         }
     }
 }
 
 impl FunctionStorage for MemoryStorage {
     fn store_function(&mut self, metadata: &FunctionMetadata) -> Result<(), RegistryError> {
-        self.functions.insert(metadata.id.clone(), metadata.clone());
+        let previous = self.functions.insert(metadata.id.clone(), metadata.clone());
+        self.index.insert(previous.as_ref(), metadata);
         Ok(())
     }
 
@@ -54,41 +341,71 @@ impl FunctionStorage for MemoryStorage {
             .ok_or_else(|| RegistryError::NotFound(id.to_string()))
     }
 
-    fn list_functions(
-        &self,
-        _page_token: String,
-        _page_size: u32,
-        trigger_type: String,
-    ) -> Result<Vec<FunctionMetadata>, RegistryError> {
-        let mut functions = Vec::new();
+    fn list_functions(&self, query: &FunctionQuery) -> Result<FunctionPage, RegistryError> {
+        Ok(self.index.list(&self.functions, query))
+    }
 
-        for metadata in self.functions.values() {
-            // Filter by trigger type if specified
-            if !trigger_type.is_empty() {
-                if let Some(trigger) = &metadata.trigger {
-                    if trigger.trigger_type != trigger_type {
-                        continue;
-                    }
-                } else {
-                    continue;
-                }
+    fn delete_function(&mut self, id: &str) -> Result<bool, RegistryError> {
+        match self.functions.remove(id) {
+            Some(metadata) => {
+                self.index.remove(&metadata);
+                Ok(true)
             }
+            None => Ok(false),
+        }
+    }
 
-            functions.push(metadata.clone());
+    fn put_code(&mut self, hash: &str, code: &str) -> Result<(), RegistryError> {
+        if self.code_refs.retain(hash) {
+            self.code.insert(hash.to_string(), code.to_string());
         }
+        Ok(())
+    }
 
-        Ok(functions)
+    fn get_code(&self, hash: &str) -> Result<Option<String>, RegistryError> {
+        Ok(self.code.get(hash).cloned())
     }
 
-    fn delete_function(&mut self, id: &str) -> Result<bool, RegistryError> {
-        Ok(self.functions.remove(id).is_some())
+    fn release_code(&mut self, hash: &str) -> Result<bool, RegistryError> {
+        let deleted = self.code_refs.release(hash);
+        if deleted {
+            self.code.remove(hash);
+        }
+        Ok(deleted)
+    }
+
+    fn store_template(&mut self, template: &FunctionTemplate) -> Result<(), RegistryError> {
+        self.templates
+            .entry(template.id.clone())
+            .or_default()
+            .push(template.clone());
+        Ok(())
+    }
+
+    fn get_template(
+        &self,
+        id: &str,
+        version: Option<u32>,
+    ) -> Result<FunctionTemplate, RegistryError> {
+        pick_template(id, self.templates.get(id), version)
+    }
+
+    fn list_templates(&self) -> Result<Vec<FunctionTemplate>, RegistryError> {
+        Ok(latest_templates(&self.templates))
     }
 }
 
 /// File-based implementation of function storage
+///
+/// Code is kept in the `code` subdirectory, one file per content hash. Reference counts are
+/// rebuilt from the stored functions on load. Templates are kept in the `templates`
+/// subdirectory, one file with all versions per template.
 pub struct FileStorage {
     base_dir: std::path::PathBuf,
     functions: HashMap<String, FunctionMetadata>,
+    index: FunctionIndex,
+    code_refs: CodeRefs,
+    templates: HashMap<String, Vec<FunctionTemplate>>,
 }
 
 impl FileStorage {
@@ -96,11 +413,13 @@ impl FileStorage {
     pub fn new(base_dir: impl Into<std::path::PathBuf>) -> Result<Self, RegistryError> {
         let base_dir = base_dir.into();
 
-        // Create the base directory if it doesn't exist
-        std::fs::create_dir_all(&base_dir)?;
+        // Create the base, code and template directories if they don't exist
+        std::fs::create_dir_all(base_dir.join("code"))?;
+        std::fs::create_dir_all(base_dir.join("templates"))?;
 
         // Load existing functions from the base directory
         let mut functions = HashMap::new();
+        let mut index = FunctionIndex::default();
         for entry in std::fs::read_dir(&base_dir)? {
             let entry = entry?;
             let path = entry.path();
@@ -110,13 +429,31 @@ impl FileStorage {
                 let metadata: FunctionMetadata = serde_json::from_str(&content)
                     .map_err(|e| RegistryError::Storage(e.to_string()))?;
 
-                functions.insert(metadata.id.clone(), metadata);
+                let previous = functions.insert(metadata.id.clone(), metadata.clone());
+                index.insert(previous.as_ref(), &metadata);
+            }
+        }
+
+        let mut templates = HashMap::new();
+        for entry in std::fs::read_dir(base_dir.join("templates"))? {
+            let path = entry?.path();
+            if path.is_file() && path.extension().map_or(false, |ext| ext == "json") {
+                let content = std::fs::read_to_string(&path)?;
+                let versions: Vec<FunctionTemplate> = serde_json::from_str(&content)
+                    .map_err(|e| RegistryError::Storage(e.to_string()))?;
+                if let Some(latest) = versions.last() {
+                    templates.insert(latest.id.clone(), versions);
+                }
             }
         }
 
+        let code_refs = CodeRefs::of(functions.values());
         Ok(Self {
             base_dir,
             functions,
+            index,
+            code_refs,
+            templates,
         })
     }
 
@@ -124,12 +461,25 @@ impl FileStorage {
     fn get_file_path(&self, id: &str) -> std::path::PathBuf {
         self.base_dir.join(format!("{}.json", id))
     }
+
+    /// Get the file path of the code with a content hash
+    fn get_code_path(&self, hash: &str) -> Result<std::path::PathBuf, RegistryError> {
+        // The hash becomes a file name, so it must not be able to leave the directory
+        if hash.is_empty() || !hash.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(RegistryError::Validation(format!(
+                "invalid code hash: {}",
+                hash
+            )));
+        }
+        Ok(self.base_dir.join("code").join(format!("{}.js", hash)))
+    }
 }
 
 impl FunctionStorage for FileStorage {
     fn store_function(&mut self, metadata: &FunctionMetadata) -> Result<(), RegistryError> {
         // Store in memory
-        self.functions.insert(metadata.id.clone(), metadata.clone());
+        let previous = self.functions.insert(metadata.id.clone(), metadata.clone());
+        self.index.insert(previous.as_ref(), metadata);
 
         // Store on disk
         let path = self.get_file_path(&metadata.id);
@@ -148,36 +498,18 @@ impl FunctionStorage for FileStorage {
             .ok_or_else(|| RegistryError::NotFound(id.to_string()))
     }
 
-    fn list_functions(
-        &self,
-        _page_token: String,
-        _page_size: u32,
-        trigger_type: String,
-    ) -> Result<Vec<FunctionMetadata>, RegistryError> {
-        let mut functions = Vec::new();
-
-        for metadata in self.functions.values() {
-            // Filter by trigger type if specified
-            if !trigger_type.is_empty() {
-                if let Some(trigger) = &metadata.trigger {
-                    if trigger.trigger_type != trigger_type {
-                        continue;
-                    }
-                } else {
-                    continue;
-                }
-            }
-
-            functions.push(metadata.clone());
-        }
-
-        Ok(functions)
+    fn list_functions(&self, query: &FunctionQuery) -> Result<FunctionPage, RegistryError> {
+        Ok(self.index.list(&self.functions, query))
     }
 
     fn delete_function(&mut self, id: &str) -> Result<bool, RegistryError> {
         // Remove from memory
-        let exists = self.functions.remove(id).is_some();
+        let removed = self.functions.remove(id);
+        if let Some(metadata) = &removed {
+            self.index.remove(metadata);
+        }
 
+        let exists = removed.is_some();
         if exists {
             // Remove from disk
             let path = self.get_file_path(id);
@@ -188,4 +520,479 @@ impl FunctionStorage for FileStorage {
 
         Ok(exists)
     }
+
+    fn put_code(&mut self, hash: &str, code: &str) -> Result<(), RegistryError> {
+        let path = self.get_code_path(hash)?;
+        if self.code_refs.retain(hash) && !path.exists() {
+            std::fs::write(path, code)?;
+        }
+        Ok(())
+    }
+
+    fn get_code(&self, hash: &str) -> Result<Option<String>, RegistryError> {
+        match std::fs::read_to_string(self.get_code_path(hash)?) {
+            Ok(code) => Ok(Some(code)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn release_code(&mut self, hash: &str) -> Result<bool, RegistryError> {
+        let path = self.get_code_path(hash)?;
+        let deleted = self.code_refs.release(hash);
+        if deleted && path.exists() {
+            std::fs::remove_file(path)?;
+        }
+        Ok(deleted)
+    }
+
+    fn store_template(&mut self, template: &FunctionTemplate) -> Result<(), RegistryError> {
+        // The ID becomes a file name, so it must not be able to leave the directory
+        crate::registry::templates::check_template_id(&template.id)?;
+
+        let mut versions = self
+            .templates
+            .get(&template.id)
+            .cloned()
+            .unwrap_or_default();
+        versions.push(template.clone());
+        let content = serde_json::to_string_pretty(&versions)
+            .map_err(|e| RegistryError::Storage(e.to_string()))?;
+        let path = self
+            .base_dir
+            .join("templates")
+            .join(format!("{}.json", template.id));
+        std::fs::write(path, content)?;
+
+        self.templates.insert(template.id.clone(), versions);
+        Ok(())
+    }
+
+    fn get_template(
+        &self,
+        id: &str,
+        version: Option<u32>,
+    ) -> Result<FunctionTemplate, RegistryError> {
+        pick_template(id, self.templates.get(id), version)
+    }
+
+    fn list_templates(&self) -> Result<Vec<FunctionTemplate>, RegistryError> {
+        Ok(latest_templates(&self.templates))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::registry::TriggerConfig;
+
+    fn function(i: usize) -> FunctionMetadata {
+        FunctionMetadata {
+            id: format!("fn-{:05}", i),
+            name: format!("{}-{}", if i % 2 == 0 { "even" } else { "odd" }, i),
+            description: String::new(),
+            version: 1,
+            created_at: 0,
+            // Many functions share a timestamp, so ties must be ordered by ID
+            updated_at: (i / 7) as u64,
+            trigger: Some(TriggerConfig {
+                trigger_type: if i % 3 == 0 { "schedule" } else { "blockchain" }.to_string(),
+                config: serde_json::Value::Null,
+            }),
+            permissions: None,
+            resources: None,
+            code: String::new(),
+            code_blob: None,
+            code_hash: None,
+            source_map: None,
+            owner: Some(format!("user-{}", i % 5)),
+            input_schema: None,
+            output_schema: None,
+            template: None,
+        }
+    }
+
+    fn list_all(storage: &dyn FunctionStorage, mut query: FunctionQuery) -> Vec<FunctionMetadata> {
+        let mut functions = Vec::new();
+        loop {
+            let page = storage.list_functions(&query).unwrap();
+            assert!(page.functions.len() <= query.limit);
+            functions.extend(page.functions);
+            match page.next_cursor {
+                Some(cursor) => {
+                    // Round trip the cursor through a page token like clients do
+                    query.cursor =
+                        Some(FunctionCursor::from_page_token(&cursor.to_page_token()).unwrap());
+                }
+                None => return functions,
+            }
+        }
+    }
+
+    #[test]
+    fn test_cursor_key_order() {
+        let newer = FunctionCursor {
+            updated_at: 10,
+            id: "b".into(),
+        };
+        let older = FunctionCursor {
+            updated_at: 9,
+            id: "a".into(),
+        };
+        assert!(newer < older);
+        assert!(newer.to_key() < older.to_key());
+        assert_eq!(FunctionCursor::from_key(&newer.to_key()), Some(newer));
+        assert!(FunctionCursor::from_page_token("not a token!").is_err());
+    }
+
+    #[test]
+    fn test_paginate_large_dataset() {
+        let mut storage = MemoryStorage::new();
+        for i in 0..5000 {
+            storage.store_function(&function(i)).unwrap();
+        }
+
+        let functions = list_all(
+            &storage,
+            FunctionQuery {
+                limit: 128,
+                ..Default::default()
+            },
+        );
+        assert_eq!(functions.len(), 5000);
+
+        let cursors: Vec<FunctionCursor> = functions.iter().map(FunctionCursor::of).collect();
+        let mut sorted = cursors.clone();
+        sorted.sort();
+        sorted.dedup();
+        assert_eq!(cursors, sorted);
+        assert_eq!(functions[0].updated_at, 4999 / 7);
+    }
+
+    #[test]
+    fn test_filter_large_dataset() {
+        let mut storage = MemoryStorage::new();
+        for i in 0..5000 {
+            storage.store_function(&function(i)).unwrap();
+        }
+
+        let query = FunctionQuery {
+            limit: 100,
+            trigger_type: "schedule".into(),
+            name_prefix: "even-".into(),
+            owner: "user-1".into(),
+            ..Default::default()
+        };
+        let functions = list_all(&storage, query.clone());
+        let expected = (0..5000)
+            .filter(|i| i % 3 == 0 && i % 2 == 0 && i % 5 == 1)
+            .count();
+        assert_eq!(functions.len(), expected);
+        assert!(functions.iter().all(|f| query.matches(f)));
+    }
+
+    #[test]
+    fn test_update_and_delete_reindex() {
+        let mut storage = MemoryStorage::new();
+        for i in 0..100 {
+            storage.store_function(&function(i)).unwrap();
+        }
+
+        let mut updated = function(3);
+        updated.updated_at = 1_000;
+        storage.store_function(&updated).unwrap();
+        storage.delete_function(&function(99).id).unwrap();
+
+        let functions = list_all(
+            &storage,
+            FunctionQuery {
+                limit: 10,
+                ..Default::default()
+            },
+        );
+        assert_eq!(functions.len(), 99);
+        assert_eq!(functions[0].id, updated.id);
+        assert_eq!(functions.iter().filter(|f| f.id == updated.id).count(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_stale_update_conflicts() {
+        use crate::registry::{FunctionRegistry, UpdateFunctionRequest};
+
+        let mut storage = MemoryStorage::new();
+        storage.store_function(&function(1)).unwrap();
+        let registry = FunctionRegistry::new(Box::new(storage));
+
+        let update = |name: &str, expected_version| UpdateFunctionRequest {
+            id: function(1).id,
+            name: Some(name.to_string()),
+            description: None,
+            trigger: None,
+            permissions: None,
+            resources: None,
+            code: None,
+            code_hash: None,
+            source_map: None,
+            input_schema: None,
+            output_schema: None,
+            expected_version,
+        };
+
+        // Both clients read version 1, only the first update applies
+        let first = registry.update_function(update("first", Some(1))).await;
+        assert_eq!(first.unwrap().metadata.unwrap().version, 2);
+        assert!(matches!(
+            registry.update_function(update("second", Some(1))).await,
+            Err(RegistryError::Conflict(_))
+        ));
+
+        // Updates without an expected version still apply
+        let forced = registry.update_function(update("forced", None)).await;
+        assert_eq!(forced.unwrap().metadata.unwrap().name, "forced");
+    }
+
+    #[tokio::test]
+    async fn test_large_code_in_blob_store() {
+        use crate::registry::{
+            FunctionRegistry, GetFunctionRequest, ListFunctionsRequest, UpdateFunctionRequest,
+        };
+        use r3e_store::MemoryBlobStore;
+        use std::sync::Arc;
+
+        let mut storage = MemoryStorage::new();
+        storage.store_function(&function(1)).unwrap();
+        let blobs = Arc::new(MemoryBlobStore::new());
+        let registry = FunctionRegistry::new(Box::new(storage)).with_blob_store(blobs.clone(), 16);
+
+        let code = "export default () => 'a bundle over the inline limit';".to_string();
+        let updated = registry
+            .update_function(UpdateFunctionRequest {
+                id: function(1).id,
+                name: None,
+                description: None,
+                trigger: None,
+                permissions: None,
+                resources: None,
+                code: Some(code.clone()),
+                code_hash: None,
+                source_map: None,
+                input_schema: None,
+                output_schema: None,
+                expected_version: None,
+            })
+            .await
+            .unwrap()
+            .metadata
+            .unwrap();
+        assert_eq!(updated.code, code);
+        assert_eq!(blobs.len(), 1);
+
+        // Storage only keeps the reference, reads resolve it
+        let listed = registry
+            .list_functions(ListFunctionsRequest {
+                page_token: String::new(),
+                page_size: 10,
+                trigger_type: String::new(),
+                name_prefix: String::new(),
+                owner: String::new(),
+            })
+            .await
+            .unwrap();
+        assert!(listed.functions[0].code.is_empty());
+        assert_eq!(listed.functions[0].code_blob, updated.code_blob);
+
+        let fetched = registry
+            .get_function(GetFunctionRequest { id: function(1).id })
+            .await
+            .unwrap()
+            .metadata
+            .unwrap();
+        assert_eq!(fetched.code, code);
+    }
+
+    #[tokio::test]
+    async fn test_code_deduplicated_by_hash() {
+        use crate::registry::{
+            DeleteFunctionRequest, FunctionRegistry, GetFunctionRequest, HasCodeRequest,
+            RegisterFunctionRequest,
+        };
+
+        let registry = FunctionRegistry::new(Box::new(MemoryStorage::new()));
+        let register = |code: &str, code_hash: Option<String>| RegisterFunctionRequest {
+            name: "shared".to_string(),
+            description: String::new(),
+            trigger: None,
+            permissions: None,
+            resources: None,
+            code: code.to_string(),
+            code_hash,
+            source_map: None,
+            owner: None,
+            input_schema: None,
+            output_schema: None,
+        };
+        let has_code = |hash: &str| {
+            registry.has_code(HasCodeRequest {
+                hash: hash.to_string(),
+            })
+        };
+
+        // Identical code is stored once, and can be referenced by hash alone
+        let code = "export default () => 'shared';";
+        let first = registry
+            .register_function(register(code, None))
+            .await
+            .unwrap();
+        let first = first.metadata.unwrap();
+        let hash = first.code_hash.clone().unwrap();
+        let second = registry
+            .register_function(register(code, None))
+            .await
+            .unwrap();
+        let second = second.metadata.unwrap();
+        assert_eq!(second.code_hash, Some(hash.clone()));
+        let third = registry
+            .register_function(register("", Some(hash.clone())))
+            .await
+            .unwrap()
+            .metadata
+            .unwrap();
+        assert!(has_code(&hash).await.unwrap().exists);
+
+        let fetched = registry
+            .get_function(GetFunctionRequest {
+                id: third.id.clone(),
+            })
+            .await
+            .unwrap()
+            .metadata
+            .unwrap();
+        assert_eq!(fetched.code, code);
+
+        // Unknown or mismatching hashes are rejected
+        assert!(matches!(
+            registry
+                .register_function(register("", Some("00".repeat(32))))
+                .await,
+            Err(RegistryError::Validation(_))
+        ));
+        assert!(matches!(
+            registry
+                .register_function(register("other", Some(hash.clone())))
+                .await,
+            Err(RegistryError::Validation(_))
+        ));
+
+        // The code is dropped with its last reference
+        for (i, metadata) in [&first, &second, &third].into_iter().enumerate() {
+            assert!(has_code(&hash).await.unwrap().exists);
+            registry
+                .delete_function(DeleteFunctionRequest {
+                    id: metadata.id.clone(),
+                })
+                .await
+                .unwrap();
+            assert_eq!(has_code(&hash).await.unwrap().exists, i < 2);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_template_versions_and_instantiation() {
+        use crate::registry::templates::builtin_templates;
+        use crate::registry::{
+            FunctionRegistry, GetTemplateRequest, InstantiateTemplateRequest, ListTemplatesRequest,
+            PublishTemplateRequest,
+        };
+
+        let registry = FunctionRegistry::new(Box::new(MemoryStorage::new()));
+        registry.seed_templates().await.unwrap();
+        // Seeding again keeps the published versions
+        registry.seed_templates().await.unwrap();
+        let listed = registry
+            .list_templates(ListTemplatesRequest {
+                category: String::new(),
+            })
+            .await
+            .unwrap();
+        assert_eq!(listed.templates.len(), builtin_templates().len());
+        assert!(listed.templates.iter().all(|t| t.version == 1));
+
+        // Updates add a version, stale ones conflict
+        let mut updated = builtin_templates()
+            .into_iter()
+            .find(|t| t.id == "price-alert")
+            .unwrap();
+        updated.description = "Updated".to_string();
+        let publish = |expected_version| PublishTemplateRequest {
+            template: updated.clone(),
+            expected_version,
+        };
+        let v2 = registry.publish_template(publish(Some(1))).await.unwrap();
+        assert_eq!(v2.template.unwrap().version, 2);
+        assert!(matches!(
+            registry.publish_template(publish(Some(1))).await,
+            Err(RegistryError::Conflict(_))
+        ));
+        let v1 = registry
+            .get_template(GetTemplateRequest {
+                id: "price-alert".to_string(),
+                version: Some(1),
+            })
+            .await
+            .unwrap()
+            .template
+            .unwrap();
+        assert_ne!(v1.description, "Updated");
+
+        // Instances belong to the user and record the template version
+        let instantiate = |parameters: &[(&str, &str)]| InstantiateTemplateRequest {
+            template_id: "price-alert".to_string(),
+            version: Some(1),
+            name: "neo-alert".to_string(),
+            parameters: parameters
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+            owner: "user-1".to_string(),
+        };
+        let metadata = registry
+            .instantiate_template(instantiate(&[
+                ("symbol", "NEO"),
+                ("threshold", "12.5"),
+                ("webhook_url", "https://example.com/hook"),
+            ]))
+            .await
+            .unwrap()
+            .metadata
+            .unwrap();
+        assert_eq!(metadata.owner.as_deref(), Some("user-1"));
+        assert_eq!(metadata.template.as_ref().map(|t| t.version), Some(1));
+        assert!(metadata.code.contains("const THRESHOLD = 12.5;"));
+        assert!(metadata.code.contains("const ABOVE = true;"));
+        assert_eq!(
+            metadata.trigger.unwrap().config["config"]["assets"][0],
+            "NEO"
+        );
+
+        // Missing, unknown and mistyped parameters are rejected
+        for parameters in [
+            &[("symbol", "NEO")][..],
+            &[
+                ("symbol", "NEO"),
+                ("threshold", "1"),
+                ("webhook_url", "x"),
+                ("extra", "1"),
+            ],
+            &[
+                ("symbol", "NEO"),
+                ("threshold", "high"),
+                ("webhook_url", "x"),
+            ],
+        ] {
+            assert!(matches!(
+                registry.instantiate_template(instantiate(parameters)).await,
+                Err(RegistryError::Validation(_))
+            ));
+        }
+    }
 }
@@ -0,0 +1,577 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+//! Catalog of parameterized templates new functions start from.
+//!
+//! The code and trigger config of a template hold `{{parameter}}` placeholders. Instantiating
+//! a template checks the supplied values against its declared parameters and fills them in:
+//! into code as JavaScript literals, so a value cannot inject code, and into trigger config as
+//! JSON values. Publishing a template again under its ID adds a version, earlier versions stay
+//! available to the functions created from them.
+
+use std::collections::{HashMap, HashSet};
+
+use serde_json::Value;
+
+use crate::registry::{Permissions, RegistryError, Resources, TriggerConfig};
+
+/// Longest template ID accepted
+pub const MAX_TEMPLATE_ID_LEN: usize = 64;
+
+/// Type of a template parameter value
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ParameterType {
+    String,
+    Number,
+    Boolean,
+    /// Hex encoded Neo N3 script hash, `0x` prefixed
+    ScriptHash,
+}
+
+/// Parameter a template is instantiated with
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct TemplateParameter {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(rename = "type")]
+    pub param_type: ParameterType,
+    #[serde(default)]
+    pub required: bool,
+    /// Value used when the parameter is not supplied
+    #[serde(default)]
+    pub default: Option<String>,
+}
+
+/// Version of a function template
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct FunctionTemplate {
+    pub id: String,
+    pub name: String,
+    pub description: String,
+    #[serde(default)]
+    pub category: String,
+    /// Set by the registry when the template is published
+    #[serde(default)]
+    pub version: u32,
+    #[serde(default)]
+    pub created_at: u64,
+    #[serde(default)]
+    pub updated_at: u64,
+    #[serde(default)]
+    pub parameters: Vec<TemplateParameter>,
+    pub code: String,
+    pub trigger: Option<TriggerConfig>,
+    pub permissions: Option<Permissions>,
+    pub resources: Option<Resources>,
+    #[serde(default)]
+    pub input_schema: Option<Value>,
+    #[serde(default)]
+    pub output_schema: Option<Value>,
+}
+
+/// Template version a function was instantiated from
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct TemplateRef {
+    pub id: String,
+    pub version: u32,
+}
+
+/// Code and trigger of a template with its parameters filled in
+#[derive(Clone, Debug)]
+pub struct RenderedTemplate {
+    pub code: String,
+    pub trigger: Option<TriggerConfig>,
+}
+
+/// Reject template IDs other than lowercase ASCII letters, digits, `-` and `_`, since storage
+/// uses them as keys and file names
+pub fn check_template_id(id: &str) -> Result<(), RegistryError> {
+    let valid = !id.is_empty()
+        && id.len() <= MAX_TEMPLATE_ID_LEN
+        && id
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_');
+    if valid {
+        Ok(())
+    } else {
+        Err(RegistryError::Validation(format!(
+            "invalid template ID {:?}, use at most {} lowercase letters, digits, '-' and '_'",
+            id, MAX_TEMPLATE_ID_LEN
+        )))
+    }
+}
+
+impl ParameterType {
+    /// Parse a supplied value
+    fn parse(self, name: &str, value: &str) -> Result<Value, RegistryError> {
+        let invalid = |expected: &str| {
+            RegistryError::Validation(format!(
+                "parameter {} must be {}, got {:?}",
+                name, expected, value
+            ))
+        };
+        match self {
+            ParameterType::String => Ok(Value::String(value.to_string())),
+            ParameterType::Number => value
+                .trim()
+                .parse::<f64>()
+                .ok()
+                .and_then(serde_json::Number::from_f64)
+                .map(Value::Number)
+                .ok_or_else(|| invalid("a finite number")),
+            ParameterType::Boolean => match value.trim() {
+                "true" => Ok(Value::Bool(true)),
+                "false" => Ok(Value::Bool(false)),
+                _ => Err(invalid("true or false")),
+            },
+            ParameterType::ScriptHash => {
+                let hex = value.trim().strip_prefix("0x").unwrap_or(value.trim());
+                if hex.len() == 40 && hex.chars().all(|c| c.is_ascii_hexdigit()) {
+                    Ok(Value::String(format!("0x{}", hex.to_ascii_lowercase())))
+                } else {
+                    Err(invalid("a 20 byte hex script hash"))
+                }
+            }
+        }
+    }
+}
+
+impl FunctionTemplate {
+    /// Check the template is well formed before it is published
+    pub fn validate(&self) -> Result<(), RegistryError> {
+        check_template_id(&self.id)?;
+        if self.name.trim().is_empty() {
+            return Err(RegistryError::Validation(
+                "template name is required".to_string(),
+            ));
+        }
+
+        let mut declared = HashSet::new();
+        for parameter in &self.parameters {
+            if !is_identifier(&parameter.name) {
+                return Err(RegistryError::Validation(format!(
+                    "invalid parameter name {:?}",
+                    parameter.name
+                )));
+            }
+            if !declared.insert(parameter.name.as_str()) {
+                return Err(RegistryError::Validation(format!(
+                    "parameter {} is declared twice",
+                    parameter.name
+                )));
+            }
+            if let Some(default) = &parameter.default {
+                parameter.param_type.parse(&parameter.name, default)?;
+            }
+        }
+
+        let mut used = placeholders(&self.code);
+        if let Some(trigger) = &self.trigger {
+            config_placeholders(&trigger.config, &mut used);
+        }
+        if let Some(undeclared) = used.iter().find(|name| !declared.contains(name.as_str())) {
+            return Err(RegistryError::Validation(format!(
+                "placeholder {{{{{}}}}} has no declared parameter",
+                undeclared
+            )));
+        }
+        Ok(())
+    }
+
+    /// Values of all parameters: the supplied ones checked against their type, defaults for
+    /// the rest
+    pub fn resolve(
+        &self,
+        values: &HashMap<String, String>,
+    ) -> Result<HashMap<String, Value>, RegistryError> {
+        if let Some(unknown) = values
+            .keys()
+            .find(|name| !self.parameters.iter().any(|p| &p.name == *name))
+        {
+            return Err(RegistryError::Validation(format!(
+                "template {} has no parameter {}",
+                self.id, unknown
+            )));
+        }
+
+        let mut resolved = HashMap::new();
+        for parameter in &self.parameters {
+            let value = match (values.get(&parameter.name), &parameter.default) {
+                (Some(value), _) | (None, Some(value)) => value,
+                (None, None) if parameter.required => {
+                    return Err(RegistryError::Validation(format!(
+                        "parameter {} is required",
+                        parameter.name
+                    )));
+                }
+                (None, None) => {
+                    resolved.insert(parameter.name.clone(), Value::Null);
+                    continue;
+                }
+            };
+            resolved.insert(
+                parameter.name.clone(),
+                parameter.param_type.parse(&parameter.name, value)?,
+            );
+        }
+        Ok(resolved)
+    }
+
+    /// Fill the parameters into the code and trigger
+    pub fn render(
+        &self,
+        values: &HashMap<String, String>,
+    ) -> Result<RenderedTemplate, RegistryError> {
+        let resolved = self.resolve(values)?;
+
+        // JSON literals are JavaScript literals, strings come out quoted and escaped
+        let code = substitute(&self.code, |name| {
+            resolved.get(name).map(|value| value.to_string())
+        });
+        let trigger = self.trigger.as_ref().map(|trigger| TriggerConfig {
+            trigger_type: trigger.trigger_type.clone(),
+            config: render_config(&trigger.config, &resolved),
+        });
+        Ok(RenderedTemplate { code, trigger })
+    }
+}
+
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Find the next `{{name}}` placeholder, as the byte range it spans and the trimmed name
+fn next_placeholder(text: &str) -> Option<(usize, usize, &str)> {
+    let start = text.find("{{")?;
+    let end = start + 2 + text[start + 2..].find("}}")?;
+    let name = text[start + 2..end].trim();
+    if is_identifier(name) {
+        Some((start, end + 2, name))
+    } else {
+        // Not a placeholder, e.g. an object literal in code, look past its opening braces
+        next_placeholder(&text[start + 1..])
+            .map(|(s, e, name)| (s + start + 1, e + start + 1, name))
+    }
+}
+
+/// Names of the placeholders in a text
+fn placeholders(text: &str) -> HashSet<String> {
+    let mut names = HashSet::new();
+    let mut rest = text;
+    while let Some((_, end, name)) = next_placeholder(rest) {
+        names.insert(name.to_string());
+        rest = &rest[end..];
+    }
+    names
+}
+
+fn config_placeholders(config: &Value, names: &mut HashSet<String>) {
+    match config {
+        Value::String(text) => names.extend(placeholders(text)),
+        Value::Array(items) => items
+            .iter()
+            .for_each(|item| config_placeholders(item, names)),
+        Value::Object(fields) => fields
+            .values()
+            .for_each(|field| config_placeholders(field, names)),
+        _ => {}
+    }
+}
+
+/// Replace the placeholders `fill` knows the value of
+fn substitute(text: &str, fill: impl Fn(&str) -> Option<String>) -> String {
+    let mut rendered = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some((start, end, name)) = next_placeholder(rest) {
+        rendered.push_str(&rest[..start]);
+        match fill(name) {
+            Some(value) => rendered.push_str(&value),
+            None => rendered.push_str(&rest[start..end]),
+        }
+        rest = &rest[end..];
+    }
+    rendered.push_str(rest);
+    rendered
+}
+
+/// A string that is one placeholder becomes the typed value, placeholders within longer
+/// strings are filled in as text
+fn render_config(config: &Value, values: &HashMap<String, Value>) -> Value {
+    match config {
+        Value::String(text) => {
+            if let Some((0, end, name)) = next_placeholder(text) {
+                if end == text.len() {
+                    if let Some(value) = values.get(name) {
+                        return value.clone();
+                    }
+                }
+            }
+            Value::String(substitute(text, |name| {
+                values.get(name).map(|value| match value {
+                    Value::String(text) => text.clone(),
+                    Value::Null => String::new(),
+                    value => value.to_string(),
+                })
+            }))
+        }
+        Value::Array(items) => Value::Array(
+            items
+                .iter()
+                .map(|item| render_config(item, values))
+                .collect(),
+        ),
+        Value::Object(fields) => Value::Object(
+            fields
+                .iter()
+                .map(|(key, field)| (key.clone(), render_config(field, values)))
+                .collect(),
+        ),
+        value => value.clone(),
+    }
+}
+
+fn parameter(
+    name: &str,
+    description: &str,
+    param_type: ParameterType,
+    default: Option<&str>,
+) -> TemplateParameter {
+    TemplateParameter {
+        name: name.to_string(),
+        description: description.to_string(),
+        param_type,
+        required: default.is_none(),
+        default: default.map(str::to_string),
+    }
+}
+
+/// Templates the catalog is seeded with
+pub fn builtin_templates() -> Vec<FunctionTemplate> {
+    vec![price_alert(), nft_mint_watcher()]
+}
+
+/// Alert a webhook when a price crosses a threshold
+fn price_alert() -> FunctionTemplate {
+    FunctionTemplate {
+        id: "price-alert".to_string(),
+        name: "Price Alert".to_string(),
+        description: "Calls a webhook when the oracle price of an asset crosses a threshold"
+            .to_string(),
+        category: "oracle".to_string(),
+        version: 0,
+        created_at: 0,
+        updated_at: 0,
+        parameters: vec![
+            parameter(
+                "symbol",
+                "Asset symbol, e.g. NEO",
+                ParameterType::String,
+                None,
+            ),
+            parameter(
+                "threshold",
+                "Price in USD that triggers the alert",
+                ParameterType::Number,
+                None,
+            ),
+            parameter(
+                "above",
+                "Alert when the price rises above the threshold, or else falls below it",
+                ParameterType::Boolean,
+                Some("true"),
+            ),
+            parameter(
+                "webhook_url",
+                "URL the alert is posted to",
+                ParameterType::String,
+                None,
+            ),
+        ],
+        code: r#"
+// Price Alert Function
+const SYMBOL = {{symbol}};
+const THRESHOLD = {{threshold}};
+const ABOVE = {{above}};
+const WEBHOOK_URL = {{webhook_url}};
+
+export default async function(event) {
+  const price = event.price;
+  if (typeof price !== "number") {
+    return { alerted: false, reason: "no price in event" };
+  }
+
+  const crossed = ABOVE ? price > THRESHOLD : price < THRESHOLD;
+  if (!crossed) {
+    return { alerted: false, symbol: SYMBOL, price };
+  }
+
+  await fetch(WEBHOOK_URL, {
+    method: "POST",
+    headers: { "Content-Type": "application/json" },
+    body: JSON.stringify({
+      symbol: SYMBOL,
+      price,
+      threshold: THRESHOLD,
+      direction: ABOVE ? "above" : "below",
+      timestamp: Date.now()
+    })
+  });
+
+  return { alerted: true, symbol: SYMBOL, price };
+}
+"#
+        .to_string(),
+        trigger: Some(TriggerConfig {
+            trigger_type: "oracle".to_string(),
+            config: serde_json::json!({
+                "type": "price",
+                "config": {
+                    "assets": ["{{symbol}}"]
+                }
+            }),
+        }),
+        permissions: Some(Permissions {
+            network: true,
+            filesystem: false,
+            environment: false,
+        }),
+        resources: Some(Resources {
+            memory_mb: 64,
+            cpu_units: 500,
+            timeout_ms: 5000,
+        }),
+        input_schema: None,
+        output_schema: None,
+    }
+}
+
+/// Report the tokens an NEP-11 contract mints
+fn nft_mint_watcher() -> FunctionTemplate {
+    FunctionTemplate {
+        id: "nft-mint-watcher".to_string(),
+        name: "NFT Mint Watcher".to_string(),
+        description: "Reports the tokens a Neo N3 NEP-11 contract mints".to_string(),
+        category: "blockchain".to_string(),
+        version: 0,
+        created_at: 0,
+        updated_at: 0,
+        parameters: vec![
+            parameter(
+                "contract_hash",
+                "Script hash of the NEP-11 contract",
+                ParameterType::ScriptHash,
+                None,
+            ),
+            parameter(
+                "webhook_url",
+                "URL mints are posted to, only logged if empty",
+                ParameterType::String,
+                Some(""),
+            ),
+        ],
+        code: r#"
+// NFT Mint Watcher Function
+const CONTRACT_HASH = {{contract_hash}};
+const WEBHOOK_URL = {{webhook_url}};
+
+export default async function(event) {
+  const notification = event.neo_notification;
+  if (!notification || notification.event_name !== "Transfer") {
+    return { minted: false };
+  }
+
+  // NEP-11 mints are transfers from no one
+  const [from, to, amount, tokenId] = notification.state.map((item) => item?.value ?? null);
+  if (from !== null) {
+    return { minted: false };
+  }
+
+  const mint = { contract: CONTRACT_HASH, to, amount, tokenId };
+  console.log("Minted:", mint);
+
+  if (WEBHOOK_URL) {
+    await fetch(WEBHOOK_URL, {
+      method: "POST",
+      headers: { "Content-Type": "application/json" },
+      body: JSON.stringify(mint)
+    });
+  }
+
+  return { minted: true, ...mint };
+}
+"#
+        .to_string(),
+        trigger: Some(TriggerConfig {
+            trigger_type: "blockchain".to_string(),
+            config: serde_json::json!({
+                "source": "Neo",
+                "event_type": "NeoContractNotification",
+                "filter": "{{contract_hash}}"
+            }),
+        }),
+        permissions: Some(Permissions {
+            network: true,
+            filesystem: false,
+            environment: false,
+        }),
+        resources: Some(Resources {
+            memory_mb: 64,
+            cpu_units: 500,
+            timeout_ms: 5000,
+        }),
+        input_schema: None,
+        output_schema: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_values_cannot_inject_code() {
+        let template = nft_mint_watcher();
+        template.validate().unwrap();
+
+        let values = HashMap::from([
+            (
+                "contract_hash".to_string(),
+                "0xEF4073A0F2B305A38EC4050E4D3D28BC40EA63F5".to_string(),
+            ),
+            (
+                "webhook_url".to_string(),
+                "\"; globalThis.leak = 1; \"".to_string(),
+            ),
+        ]);
+        let rendered = template.render(&values).unwrap();
+        assert!(rendered
+            .code
+            .contains(r#"const WEBHOOK_URL = "\"; globalThis.leak = 1; \"";"#));
+        assert!(rendered
+            .code
+            .contains(r#"const CONTRACT_HASH = "0xef4073a0f2b305a38ec4050e4d3d28bc40ea63f5";"#));
+        assert_eq!(
+            rendered.trigger.unwrap().config["filter"],
+            "0xef4073a0f2b305a38ec4050e4d3d28bc40ea63f5"
+        );
+    }
+
+    #[test]
+    fn test_undeclared_placeholders_rejected() {
+        let mut template = price_alert();
+        template.code.push_str("const EXTRA = {{ extra }};");
+        assert!(matches!(
+            template.validate(),
+            Err(RegistryError::Validation(_))
+        ));
+
+        // Braces that are not placeholders are left alone
+        let mut template = price_alert();
+        template.code.push_str("const nested = {{}};");
+        template.validate().unwrap();
+    }
+}
@@ -0,0 +1,168 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+
+/// Position of an event within the chain: the block it belongs to, and its index in the events
+/// of the block, 0 for the block itself and `i + 1` for its `i`th transaction
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BlockPosition {
+    pub height: u64,
+    pub index: u32,
+    pub hash: String,
+}
+
+impl BlockPosition {
+    /// Whether the event at this position was already processed at the checkpoint
+    pub fn is_processed_at(&self, checkpoint: &BlockPosition) -> bool {
+        (self.height, self.index) <= (checkpoint.height, checkpoint.index)
+            && (self.height < checkpoint.height || self.hash == checkpoint.hash)
+    }
+}
+
+/// Last fully processed event of a source
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SourceCheckpoint {
+    /// Source name, e.g. `neo_subscription`
+    pub source: String,
+
+    pub position: BlockPosition,
+
+    /// Unix time of the commit (in seconds)
+    pub committed_at: u64,
+}
+
+/// Durable store of source checkpoints, so a restarted source resumes after the last event
+/// processed instead of at the chain head
+pub trait SourceCheckpointStore: Send + Sync {
+    /// Checkpoint of a source, none if it never committed one
+    fn load(&self, source: &str) -> std::io::Result<Option<SourceCheckpoint>>;
+
+    /// Replace the checkpoint of a source
+    fn commit(&self, checkpoint: &SourceCheckpoint) -> std::io::Result<()>;
+}
+
+/// Source checkpoints kept in memory, for tests
+#[derive(Debug, Default)]
+pub struct MemorySourceCheckpoints {
+    checkpoints: Mutex<HashMap<String, SourceCheckpoint>>,
+}
+
+impl SourceCheckpointStore for MemorySourceCheckpoints {
+    fn load(&self, source: &str) -> std::io::Result<Option<SourceCheckpoint>> {
+        Ok(self.checkpoints.lock().unwrap().get(source).cloned())
+    }
+
+    fn commit(&self, checkpoint: &SourceCheckpoint) -> std::io::Result<()> {
+        self.checkpoints
+            .lock()
+            .unwrap()
+            .insert(checkpoint.source.clone(), checkpoint.clone());
+        Ok(())
+    }
+}
+
+/// Source checkpoints kept as one JSON file per source
+#[derive(Debug, Clone)]
+pub struct FileSourceCheckpoints {
+    dir: PathBuf,
+}
+
+impl FileSourceCheckpoints {
+    /// Open the checkpoints in `dir`, creating the directory if needed
+    pub fn open(dir: impl AsRef<Path>) -> std::io::Result<Self> {
+        std::fs::create_dir_all(dir.as_ref())?;
+        Ok(Self {
+            dir: dir.as_ref().to_path_buf(),
+        })
+    }
+
+    fn path(&self, source: &str) -> PathBuf {
+        // Source names are configured, keep them from escaping the directory
+        let name: String = source
+            .chars()
+            .map(|c| match c {
+                'a'..='z' | 'A'..='Z' | '0'..='9' | '-' | '_' => c,
+                _ => '_',
+            })
+            .collect();
+        self.dir.join(format!("{}.json", name))
+    }
+}
+
+impl SourceCheckpointStore for FileSourceCheckpoints {
+    fn load(&self, source: &str) -> std::io::Result<Option<SourceCheckpoint>> {
+        match std::fs::read(self.path(source)) {
+            Ok(data) => Ok(Some(serde_json::from_slice(&data)?)),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    fn commit(&self, checkpoint: &SourceCheckpoint) -> std::io::Result<()> {
+        let data = serde_json::to_vec(checkpoint)?;
+
+        // Write, sync then rename, so a crash leaves either the old or the new checkpoint
+        let path = self.path(&checkpoint.source);
+        let tmp = path.with_extension("json.tmp");
+        {
+            use std::io::Write;
+            let mut file = std::fs::File::create(&tmp)?;
+            file.write_all(&data)?;
+            file.sync_all()?;
+        }
+        std::fs::rename(&tmp, &path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn position(height: u64, index: u32, hash: &str) -> BlockPosition {
+        BlockPosition {
+            height,
+            index,
+            hash: hash.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_is_processed_at() {
+        let checkpoint = position(10, 2, "a10");
+        assert!(position(9, 7, "a9").is_processed_at(&checkpoint));
+        assert!(position(10, 0, "a10").is_processed_at(&checkpoint));
+        assert!(position(10, 2, "a10").is_processed_at(&checkpoint));
+        assert!(!position(10, 3, "a10").is_processed_at(&checkpoint));
+        assert!(!position(11, 0, "a11").is_processed_at(&checkpoint));
+
+        // The checkpointed block was replaced while the source was down
+        assert!(!position(10, 0, "b10").is_processed_at(&checkpoint));
+    }
+
+    #[test]
+    fn test_file_source_checkpoints() {
+        let dir = std::env::temp_dir().join(format!("checkpoints-{}", uuid::Uuid::new_v4()));
+        let store = FileSourceCheckpoints::open(&dir).unwrap();
+        assert!(store.load("neo/main").unwrap().is_none());
+
+        let mut checkpoint = SourceCheckpoint {
+            source: "neo/main".to_string(),
+            position: position(42, 0, "0xd373"),
+            committed_at: 1,
+        };
+        store.commit(&checkpoint).unwrap();
+        checkpoint.position.index = 3;
+        store.commit(&checkpoint).unwrap();
+
+        let reopened = FileSourceCheckpoints::open(&dir).unwrap();
+        assert_eq!(reopened.load("neo/main").unwrap(), Some(checkpoint));
+        assert!(dir.join("neo_main.json").exists());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,610 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+//! Contract notification triggers.
+//!
+//! A [`ContractNotificationTrigger`] says "run the function when contract X emits event Y
+//! with parameters matching Z". Triggers are compiled once into [`ContractFilter`]s, which
+//! the Neo and Ethereum task sources apply to every contract notification they observe.
+
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::source::event::Event;
+use crate::source::NeoContractNotification;
+use crate::trigger::TriggerError;
+
+/// Comparison operator of a parameter predicate
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PredicateOp {
+    /// Equal to the value
+    Eq,
+    /// Not equal to the value
+    Ne,
+    /// Greater than the value (integers only)
+    Gt,
+    /// Greater than or equal to the value (integers only)
+    Gte,
+    /// Less than the value (integers only)
+    Lt,
+    /// Less than or equal to the value (integers only)
+    Lte,
+    /// Equal to any element of the value array
+    In,
+}
+
+/// Predicate on a positional event parameter.
+///
+/// For Neo the parameters are the notification state items. For Ethereum the parameters
+/// are the indexed topics (without the signature topic) followed by the 32-byte data words.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ParamPredicate {
+    /// Parameter index
+    pub index: usize,
+
+    /// Comparison operator
+    pub op: PredicateOp,
+
+    /// Value to compare against: a number, a boolean, a `0x` prefixed hex string or text
+    pub value: Value,
+}
+
+/// Trigger firing when a contract emits a specific notification
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ContractNotificationTrigger {
+    /// Blockchain network ("neo" or "ethereum")
+    pub network: String,
+
+    /// Contract hash (Neo) or address (Ethereum)
+    pub contract: String,
+
+    /// Event name (Neo), or event signature such as `Transfer(address,address,uint256)`
+    /// or topic0 hash (Ethereum)
+    pub event_name: String,
+
+    /// Parameter predicates, all of which must match
+    #[serde(default)]
+    pub predicates: Vec<ParamPredicate>,
+}
+
+impl ContractNotificationTrigger {
+    /// Create a new contract notification trigger
+    pub fn new(network: &str, contract: &str, event_name: &str) -> Self {
+        Self {
+            network: network.to_string(),
+            contract: contract.to_string(),
+            event_name: event_name.to_string(),
+            predicates: Vec::new(),
+        }
+    }
+
+    /// Add a parameter predicate
+    pub fn with_predicate(mut self, index: usize, op: PredicateOp, value: Value) -> Self {
+        self.predicates.push(ParamPredicate { index, op, value });
+        self
+    }
+
+    /// Compile the trigger into a filter
+    pub fn compile(&self) -> Result<ContractFilter, TriggerError> {
+        let network = match self.network.to_lowercase().as_str() {
+            "neo" | "neo_n3" => FilterNetwork::Neo,
+            "ethereum" | "eth" => FilterNetwork::Ethereum,
+            network => {
+                return Err(TriggerError::InvalidParameters(format!(
+                    "unsupported contract trigger network: {}",
+                    network
+                )))
+            }
+        };
+
+        let contract = decode_hex(&self.contract).ok_or_else(|| {
+            TriggerError::InvalidParameters(format!("invalid contract: {}", self.contract))
+        })?;
+        // Neo script hashes and Ethereum addresses are both 20 bytes
+        if contract.len() != 20 {
+            return Err(TriggerError::InvalidParameters(format!(
+                "invalid contract length: {}",
+                self.contract
+            )));
+        }
+
+        let event = match network {
+            FilterNetwork::Neo => self.event_name.clone(),
+            FilterNetwork::Ethereum => hex::encode(event_topic(&self.event_name)?),
+        };
+
+        let predicates = self
+            .predicates
+            .iter()
+            .map(|predicate| {
+                Ok(CompiledPredicate {
+                    index: predicate.index,
+                    op: predicate.op,
+                    values: match (predicate.op, &predicate.value) {
+                        (PredicateOp::In, Value::Array(values)) => values
+                            .iter()
+                            .map(ExpectedValue::compile)
+                            .collect::<Result<_, _>>()?,
+                        (PredicateOp::In, _) => {
+                            return Err(TriggerError::InvalidParameters(
+                                "'in' predicate requires an array value".into(),
+                            ))
+                        }
+                        (_, value) => vec![ExpectedValue::compile(value)?],
+                    },
+                })
+            })
+            .collect::<Result<_, TriggerError>>()?;
+
+        Ok(ContractFilter {
+            network,
+            contract: hex::encode(contract),
+            event,
+            predicates,
+        })
+    }
+}
+
+/// Topic0 of an Ethereum event: the keccak256 hash of its signature
+fn event_topic(event_name: &str) -> Result<[u8; 32], TriggerError> {
+    if event_name.contains('(') {
+        return Ok(ethers::utils::keccak256(event_name.replace(' ', "")));
+    }
+
+    decode_hex(event_name)
+        .and_then(|topic| topic.try_into().ok())
+        .ok_or_else(|| {
+            TriggerError::InvalidParameters(format!(
+                "ethereum event must be a signature or topic hash: {}",
+                event_name
+            ))
+        })
+}
+
+fn decode_hex(value: &str) -> Option<Vec<u8>> {
+    let value = value.strip_prefix("0x").unwrap_or(value);
+    hex::decode(value).ok()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FilterNetwork {
+    Neo,
+    Ethereum,
+}
+
+/// Value a predicate compares against, decoded once at compile time
+#[derive(Debug, Clone, PartialEq)]
+enum ExpectedValue {
+    Int(i128),
+    Bool(bool),
+    Bytes(Vec<u8>),
+    Text(String),
+}
+
+impl ExpectedValue {
+    fn compile(value: &Value) -> Result<Self, TriggerError> {
+        match value {
+            Value::Bool(value) => Ok(Self::Bool(*value)),
+            Value::Number(number) => number
+                .as_i64()
+                .map(|n| Self::Int(n as i128))
+                .or_else(|| number.as_u64().map(|n| Self::Int(n as i128)))
+                .ok_or_else(|| {
+                    TriggerError::InvalidParameters(format!("unsupported number: {}", number))
+                }),
+            Value::String(text) if text.starts_with("0x") => decode_hex(text)
+                .map(Self::Bytes)
+                .ok_or_else(|| TriggerError::InvalidParameters(format!("invalid hex: {}", text))),
+            Value::String(text) => Ok(text
+                .parse::<i128>()
+                .map(Self::Int)
+                .unwrap_or_else(|_| Self::Text(text.clone()))),
+            value => Err(TriggerError::InvalidParameters(format!(
+                "unsupported predicate value: {}",
+                value
+            ))),
+        }
+    }
+}
+
+/// Event parameter decoded from a notification
+#[derive(Debug, Clone, PartialEq)]
+enum ParamValue {
+    Int(i128),
+    Bool(bool),
+    Bytes(Vec<u8>),
+    /// Ethereum 32-byte word, which is also an unsigned integer
+    Word([u8; 32]),
+    Null,
+}
+
+impl ParamValue {
+    fn as_int(&self) -> Option<i128> {
+        match self {
+            Self::Int(value) => Some(*value),
+            Self::Word(word) if word[..16].iter().all(|b| *b == 0) => {
+                i128::try_from(u128::from_be_bytes(word[16..].try_into().ok()?)).ok()
+            }
+            _ => None,
+        }
+    }
+
+    fn equals(&self, expected: &ExpectedValue) -> bool {
+        match (self, expected) {
+            (Self::Bool(value), ExpectedValue::Bool(expected)) => value == expected,
+            (Self::Word(_), ExpectedValue::Bool(expected)) => {
+                self.as_int() == Some(*expected as i128)
+            }
+            (_, ExpectedValue::Int(expected)) => self.as_int() == Some(*expected),
+            // Neo displays script hashes big endian, but pushes them little endian
+            (Self::Bytes(bytes), ExpectedValue::Bytes(expected)) => {
+                bytes == expected || bytes.iter().rev().eq(expected.iter())
+            }
+            // Words are left padded, e.g. indexed addresses
+            (Self::Word(word), ExpectedValue::Bytes(expected)) => {
+                expected.len() <= 32
+                    && word[..32 - expected.len()].iter().all(|b| *b == 0)
+                    && word[32 - expected.len()..] == expected[..]
+            }
+            (Self::Bytes(bytes), ExpectedValue::Text(expected)) => {
+                bytes.as_slice() == expected.as_bytes()
+            }
+            _ => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct CompiledPredicate {
+    index: usize,
+    op: PredicateOp,
+    values: Vec<ExpectedValue>,
+}
+
+impl CompiledPredicate {
+    fn matches(&self, params: &[ParamValue]) -> bool {
+        let Some(param) = params.get(self.index) else {
+            return false;
+        };
+
+        let compare = |f: fn(i128, i128) -> bool| match (&self.values[0], param.as_int()) {
+            (ExpectedValue::Int(expected), Some(value)) => f(value, *expected),
+            _ => false,
+        };
+
+        match self.op {
+            PredicateOp::Eq => param.equals(&self.values[0]),
+            PredicateOp::Ne => !param.equals(&self.values[0]),
+            PredicateOp::Gt => compare(|a, b| a > b),
+            PredicateOp::Gte => compare(|a, b| a >= b),
+            PredicateOp::Lt => compare(|a, b| a < b),
+            PredicateOp::Lte => compare(|a, b| a <= b),
+            PredicateOp::In => self.values.iter().any(|value| param.equals(value)),
+        }
+    }
+}
+
+/// Compiled contract notification trigger
+#[derive(Debug, Clone)]
+pub struct ContractFilter {
+    network: FilterNetwork,
+
+    /// Lowercase hex contract, without `0x`
+    contract: String,
+
+    /// Event name (Neo) or lowercase hex topic0 without `0x` (Ethereum)
+    event: String,
+
+    predicates: Vec<CompiledPredicate>,
+}
+
+impl ContractFilter {
+    /// Contract hash or address, `0x` prefixed
+    pub fn contract(&self) -> String {
+        format!("0x{}", self.contract)
+    }
+
+    /// Topic0 of the event, `0x` prefixed (Ethereum only)
+    pub fn topic(&self) -> Option<String> {
+        (self.network == FilterNetwork::Ethereum).then(|| format!("0x{}", self.event))
+    }
+
+    /// Whether the filter applies to Ethereum events
+    pub fn is_ethereum(&self) -> bool {
+        self.network == FilterNetwork::Ethereum
+    }
+
+    fn same_contract(&self, contract: &str) -> bool {
+        contract
+            .strip_prefix("0x")
+            .unwrap_or(contract)
+            .eq_ignore_ascii_case(&self.contract)
+    }
+
+    /// Whether a Neo notification (as returned by `getapplicationlog`) matches
+    pub fn matches_neo_notification(&self, notification: &Value) -> bool {
+        if self.network != FilterNetwork::Neo {
+            return false;
+        }
+
+        let contract = notification.get("contract").and_then(Value::as_str);
+        let name = notification
+            .get("eventname")
+            .or_else(|| notification.get("eventName"))
+            .and_then(Value::as_str);
+        if !contract.is_some_and(|contract| self.same_contract(contract))
+            || name != Some(self.event.as_str())
+        {
+            return false;
+        }
+
+        if self.predicates.is_empty() {
+            return true;
+        }
+
+        let params: Vec<ParamValue> = notification
+            .get("state")
+            .and_then(|state| state.get("value"))
+            .and_then(Value::as_array)
+            .map(|items| items.iter().map(neo_param).collect())
+            .unwrap_or_default();
+        self.predicates.iter().all(|p| p.matches(&params))
+    }
+
+    /// Whether an Ethereum log matches
+    pub fn matches_ethereum_log(&self, contract_address: &str, log: &Value) -> bool {
+        if self.network != FilterNetwork::Ethereum {
+            return false;
+        }
+
+        let address = log
+            .get("address")
+            .and_then(Value::as_str)
+            .unwrap_or(contract_address);
+        if !self.same_contract(address) {
+            return false;
+        }
+
+        let topics: Vec<&str> = log
+            .get("topics")
+            .and_then(Value::as_array)
+            .map(|topics| topics.iter().filter_map(Value::as_str).collect())
+            .unwrap_or_default();
+        let Some(topic0) = topics.first() else {
+            return false;
+        };
+        if !topic0
+            .strip_prefix("0x")
+            .unwrap_or(topic0)
+            .eq_ignore_ascii_case(&self.event)
+        {
+            return false;
+        }
+
+        if self.predicates.is_empty() {
+            return true;
+        }
+
+        let data = log
+            .get("data")
+            .and_then(Value::as_str)
+            .and_then(decode_hex)
+            .unwrap_or_default();
+        let params: Vec<ParamValue> = topics[1..]
+            .iter()
+            .map(|topic| {
+                decode_hex(topic)
+                    .and_then(|topic| <[u8; 32]>::try_from(topic).ok())
+                    .map_or(ParamValue::Null, ParamValue::Word)
+            })
+            .chain(
+                data.chunks_exact(32)
+                    .map(|word| ParamValue::Word(word.try_into().unwrap())),
+            )
+            .collect();
+        self.predicates.iter().all(|p| p.matches(&params))
+    }
+}
+
+/// Decode a Neo stack item of a notification state
+fn neo_param(item: &Value) -> ParamValue {
+    let value = item.get("value");
+    match item.get("type").and_then(Value::as_str) {
+        Some("Integer") => value
+            .and_then(Value::as_str)
+            .and_then(|v| v.parse().ok())
+            .map_or(ParamValue::Null, ParamValue::Int),
+        Some("Boolean") => value
+            .and_then(Value::as_bool)
+            .map_or(ParamValue::Null, ParamValue::Bool),
+        Some("ByteString") | Some("Buffer") => value
+            .and_then(Value::as_str)
+            .and_then(|v| base64::engine::general_purpose::STANDARD.decode(v).ok())
+            .map_or(ParamValue::Null, ParamValue::Bytes),
+        _ => ParamValue::Null,
+    }
+}
+
+/// Set of compiled contract filters applied by a task source
+#[derive(Debug, Clone, Default)]
+pub struct ContractFilterSet {
+    filters: Vec<ContractFilter>,
+}
+
+impl ContractFilterSet {
+    /// Compile a set of triggers
+    pub fn compile(triggers: &[ContractNotificationTrigger]) -> Result<Self, TriggerError> {
+        let filters = triggers
+            .iter()
+            .map(ContractNotificationTrigger::compile)
+            .collect::<Result<_, _>>()?;
+        Ok(Self { filters })
+    }
+
+    /// Whether the set has no filters
+    pub fn is_empty(&self) -> bool {
+        self.filters.is_empty()
+    }
+
+    /// Compiled filters
+    pub fn filters(&self) -> &[ContractFilter] {
+        &self.filters
+    }
+
+    /// Keep only the notifications of `event` matched by a filter.
+    ///
+    /// Returns `Event::None` if a contract notification has no matching notification.
+    /// Other events, and all events if the set is empty, are returned unchanged.
+    pub fn apply(&self, event: Event) -> Event {
+        if self.filters.is_empty() {
+            return event;
+        }
+
+        match event {
+            Event::NeoContractNotification(notification) => {
+                let notifications: Vec<Value> =
+                    serde_json::from_str(&notification.notifications).unwrap_or_default();
+                let matched: Vec<Value> = notifications
+                    .into_iter()
+                    .filter(|n| self.filters.iter().any(|f| f.matches_neo_notification(n)))
+                    .collect();
+                if matched.is_empty() {
+                    return Event::None;
+                }
+
+                Event::NeoContractNotification(NeoContractNotification {
+                    tx_hash: notification.tx_hash,
+                    notifications: Value::Array(matched).to_string(),
+                })
+            }
+            Event::EthereumContractEvent {
+                contract_address,
+                events,
+            } => {
+                let matched: Vec<Value> = events
+                    .into_iter()
+                    .filter(|log| {
+                        self.filters
+                            .iter()
+                            .any(|f| f.matches_ethereum_log(&contract_address, log))
+                    })
+                    .collect();
+                if matched.is_empty() {
+                    return Event::None;
+                }
+
+                Event::EthereumContractEvent {
+                    contract_address,
+                    events: matched,
+                }
+            }
+            event => event,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const NEO_CONTRACT: &str = "0xd2a4cff31913016155e38e474a2c06d08be276cf";
+
+    fn neo_transfer(amount: &str) -> Value {
+        json!({
+            "contract": NEO_CONTRACT,
+            "eventname": "Transfer",
+            "state": {
+                "type": "Array",
+                "value": [
+                    {"type": "ByteString", "value": "z3bii9AGLEpHjuNVYQETGfPPpNI="},
+                    {"type": "Any"},
+                    {"type": "Integer", "value": amount}
+                ]
+            }
+        })
+    }
+
+    #[test]
+    fn test_neo_filter() {
+        let filter = ContractNotificationTrigger::new("neo", NEO_CONTRACT, "Transfer")
+            .with_predicate(2, PredicateOp::Gte, json!(100))
+            .compile()
+            .unwrap();
+        assert!(filter.matches_neo_notification(&neo_transfer("100")));
+        assert!(!filter.matches_neo_notification(&neo_transfer("99")));
+
+        // Hash160 parameters match in display (big endian) order
+        let filter = ContractNotificationTrigger::new("neo", NEO_CONTRACT, "Transfer")
+            .with_predicate(0, PredicateOp::Eq, json!(NEO_CONTRACT))
+            .compile()
+            .unwrap();
+        assert!(filter.matches_neo_notification(&neo_transfer("1")));
+
+        let filter = ContractNotificationTrigger::new("neo", NEO_CONTRACT, "Approval")
+            .compile()
+            .unwrap();
+        assert!(!filter.matches_neo_notification(&neo_transfer("1")));
+    }
+
+    #[test]
+    fn test_ethereum_filter() {
+        let contract = "0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48";
+        let from = "0x000000000000000000000000407d73d8a49eeb85d32cf465507dd71d507100c1";
+        let log = json!({
+            "address": contract,
+            "topics": [
+                "0xddf252ad1be2c89b69c2b068fc378daa952ba7f163c4a11628f55a4df523b3ef",
+                from,
+                "0x0000000000000000000000000000000000000000000000000000000000000001"
+            ],
+            "data": "0x00000000000000000000000000000000000000000000000000000000000003e8"
+        });
+
+        let set = ContractFilterSet::compile(&[ContractNotificationTrigger::new(
+            "ethereum",
+            contract,
+            "Transfer(address,address,uint256)",
+        )
+        .with_predicate(
+            0,
+            PredicateOp::Eq,
+            json!("0x407d73d8a49eeb85d32cf465507dd71d507100c1"),
+        )
+        .with_predicate(2, PredicateOp::Gt, json!(999))])
+        .unwrap();
+
+        let event = Event::EthereumContractEvent {
+            contract_address: contract.to_string(),
+            events: vec![log.clone()],
+        };
+        assert_eq!(set.apply(event.clone()), event);
+
+        let set = ContractFilterSet::compile(&[ContractNotificationTrigger::new(
+            "ethereum",
+            contract,
+            "Transfer(address,address,uint256)",
+        )
+        .with_predicate(2, PredicateOp::In, json!([1, 2, 3]))])
+        .unwrap();
+        assert_eq!(set.apply(event), Event::None);
+    }
+
+    #[test]
+    fn test_invalid_trigger() {
+        assert!(
+            ContractNotificationTrigger::new("ethereum", "0x1234", "Transfer")
+                .compile()
+                .is_err()
+        );
+        assert!(ContractNotificationTrigger::new(
+            "ethereum",
+            "0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48",
+            "Transfer"
+        )
+        .compile()
+        .is_err());
+    }
+}
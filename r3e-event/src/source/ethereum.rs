@@ -1,6 +1,7 @@
 // Copyright @ 2023 - 2024, R3E Network
 // All Rights Reserved
 
+use std::collections::VecDeque;
 use std::time::Duration;
 
 use async_trait::async_trait;
@@ -10,7 +11,12 @@ use log::{debug, info, warn};
 use serde_json::json;
 use uuid::Uuid;
 
-use crate::source::{event, Func, FuncError, Task, TaskError, TaskSource};
+use crate::source::reorg::{ReorgDetector, DEFAULT_REORG_DEPTH};
+use crate::source::{
+    event, ContractFilterSet, ContractNotificationTrigger, Func, FuncError, Task, TaskError,
+    TaskSource,
+};
+use crate::trigger::TriggerError;
 
 /// Ethereum task source
 pub struct EthereumTaskSource {
@@ -24,6 +30,16 @@ pub struct EthereumTaskSource {
     rpc_url: String,
     /// Filter
     filter: Option<serde_json::Value>,
+    /// Compiled contract notification triggers
+    contract_filters: ContractFilterSet,
+    /// Index of the next triggered contract to poll
+    next_contract: usize,
+    /// Hashes of recent blocks, to detect reorgs
+    reorg_detector: ReorgDetector,
+    /// Re-emit the canonical blocks after a reorg
+    reemit_canonical: bool,
+    /// Events to deliver before polling again
+    pending: VecDeque<event::Event>,
 }
 
 /// Ethereum trigger types
@@ -46,9 +62,26 @@ impl EthereumTaskSource {
             current_trigger: Trigger::EthereumNewBlock,
             rpc_url: "https://mainnet.infura.io/v3/your-project-id".to_string(),
             filter: None,
+            contract_filters: ContractFilterSet::default(),
+            next_contract: 0,
+            reorg_detector: ReorgDetector::new("ethereum", DEFAULT_REORG_DEPTH),
+            reemit_canonical: false,
+            pending: VecDeque::new(),
         }
     }
 
+    /// Set the number of recent blocks tracked to detect reorgs
+    pub fn with_reorg_depth(mut self, depth: usize) -> Self {
+        self.reorg_detector = ReorgDetector::new("ethereum", depth);
+        self
+    }
+
+    /// Re-emit the blocks of the canonical chain after a reorg, so functions can compensate
+    pub fn with_canonical_reemit(mut self, reemit_canonical: bool) -> Self {
+        self.reemit_canonical = reemit_canonical;
+        self
+    }
+
     /// Set RPC URL
     pub fn with_rpc_url(mut self, rpc_url: &str) -> Self {
         self.rpc_url = rpc_url.to_string();
@@ -61,6 +94,46 @@ impl EthereumTaskSource {
         self
     }
 
+    /// Only emit contract events matching one of the triggers.
+    /// The triggered contracts and topics are also used to narrow the log queries.
+    pub fn with_contract_triggers(
+        mut self,
+        triggers: &[ContractNotificationTrigger],
+    ) -> Result<Self, TriggerError> {
+        self.contract_filters = ContractFilterSet::compile(triggers)?;
+        for filter in self.contract_filters.filters() {
+            if !filter.is_ethereum() {
+                warn!(
+                    "Ignoring Neo contract trigger {} on Ethereum source",
+                    filter.contract()
+                );
+            }
+        }
+        Ok(self)
+    }
+
+    /// Contract to poll next and the topics triggered on it
+    fn next_triggered_contract(&mut self) -> Option<(String, Vec<String>)> {
+        let filters: Vec<_> = self
+            .contract_filters
+            .filters()
+            .iter()
+            .filter(|filter| filter.is_ethereum())
+            .collect();
+        if filters.is_empty() {
+            return None;
+        }
+
+        let contract = filters[self.next_contract % filters.len()].contract();
+        self.next_contract = self.next_contract.wrapping_add(1);
+        let topics = filters
+            .iter()
+            .filter(|filter| filter.contract() == contract)
+            .filter_map(|filter| filter.topic())
+            .collect();
+        Some((contract, topics))
+    }
+
     /// Fetch latest block
     async fn fetch_latest_block(&self) -> Result<serde_json::Value, String> {
         // Use ethers-rs to fetch the latest block
@@ -96,10 +169,73 @@ impl EthereumTaskSource {
         Ok(block)
     }
 
+    /// Fetch a block by number
+    async fn fetch_block(&self, number: u64) -> Result<serde_json::Value, String> {
+        let provider = Provider::<Http>::try_from(self.rpc_url.clone())
+            .map_err(|e| format!("Failed to create Ethereum provider: {}", e))?;
+
+        match provider.get_block(BlockNumber::Number(number.into())).await {
+            Ok(Some(block)) => serde_json::to_value(block)
+                .map_err(|e| format!("Failed to serialize block: {}", e)),
+            Ok(None) => Err(format!("Block {} not found", number)),
+            Err(e) => Err(format!("Failed to fetch block {}: {}", number, e)),
+        }
+    }
+
+    /// Track a new block, returning the events to deliver before it when it reveals a reorg
+    async fn track_block(
+        &mut self,
+        block: &serde_json::Value,
+    ) -> Result<Vec<event::Event>, String> {
+        let height = block["number"]
+            .as_str()
+            .and_then(|number| u64::from_str_radix(number.trim_start_matches("0x"), 16).ok())
+            .ok_or_else(|| "Block without number".to_string())?;
+        let hash = block["hash"].as_str().unwrap_or_default();
+        let parent_hash = block["parentHash"].as_str().unwrap_or_default();
+
+        let mut detector = self.reorg_detector.clone();
+        let this = &*self;
+        let reorg = detector
+            .observe(height, hash, parent_hash, |number| async move {
+                let block = this.fetch_block(number).await?;
+                Ok::<_, String>(block["hash"].as_str().unwrap_or_default().to_string())
+            })
+            .await?;
+        self.reorg_detector = detector;
+
+        let Some(reorg) = reorg else {
+            return Ok(Vec::new());
+        };
+        warn!(
+            "Ethereum reorg at height {}, {} blocks invalidated",
+            reorg.fork_height,
+            reorg.invalidated.len()
+        );
+
+        // The new block itself is delivered by the caller
+        let reemit: Vec<u64> = match self.reemit_canonical {
+            true => reorg
+                .canonical
+                .iter()
+                .map(|block| block.height)
+                .filter(|number| *number < height)
+                .collect(),
+            false => Vec::new(),
+        };
+
+        let mut events = vec![event::Event::ChainReorg(reorg)];
+        for number in reemit {
+            events.push(event::Event::EthereumBlock(self.fetch_block(number).await?));
+        }
+        Ok(events)
+    }
+
     /// Fetch contract events
     async fn fetch_contract_events(
         &self,
         contract_address: &str,
+        topics: &[String],
     ) -> Result<serde_json::Value, String> {
         // Use ethers-rs to fetch contract events
         log::info!("Fetching Ethereum contract events for {}", contract_address);
@@ -119,6 +255,19 @@ impl EthereumTaskSource {
                 .map_err(|e| format!("Invalid contract address: {}", e))?])
             .from_block(BlockNumber::Latest);
 
+        // Only fetch the triggered events
+        let filter = if topics.is_empty() {
+            filter
+        } else {
+            filter.topic0(
+                topics
+                    .iter()
+                    .map(|topic| topic.parse::<H256>())
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(|e| format!("Invalid event topic: {}", e))?,
+            )
+        };
+
         // Fetch the events
         let logs = match provider.get_logs(&filter).await {
             Ok(logs) => {
@@ -317,6 +466,11 @@ impl EthereumTaskSource {
 #[async_trait]
 impl TaskSource for EthereumTaskSource {
     async fn acquire_task(&mut self, uid: u64, fid: u64) -> Result<Task, TaskError> {
+        // Deliver the events of a reorg first
+        if let Some(event) = self.pending.pop_front() {
+            return Ok(Task::new(uid, fid, event));
+        }
+
         // Sleep to avoid busy waiting
         tokio::time::sleep(self.sleep).await;
 
@@ -334,18 +488,38 @@ impl TaskSource for EthereumTaskSource {
                     }
                 };
 
+                // Check whether previously delivered blocks were orphaned
+                let reorg_events = match self.track_block(&block).await {
+                    Ok(events) => events,
+                    Err(e) => {
+                        warn!("Failed to check Ethereum reorg: {}", e);
+                        Vec::new()
+                    }
+                };
+
                 // Update trigger for next time
                 self.current_trigger = Trigger::EthereumContractEvent;
 
-                // Return block event
-                event::Event::EthereumBlock(block)
+                // Return the reorg events before the block event
+                self.pending.extend(reorg_events);
+                self.pending.push_back(event::Event::EthereumBlock(block));
+                self.pending.pop_front().unwrap_or_default()
             }
             Trigger::EthereumContractEvent => {
-                // Use a mock contract address
-                let contract_address = "0x4e65fda2159562a496f9f3522f89122a3088497a";
+                // Poll the triggered contracts, or a mock contract address without triggers
+                let (contract_address, topics) =
+                    self.next_triggered_contract().unwrap_or_else(|| {
+                        (
+                            "0x4e65fda2159562a496f9f3522f89122a3088497a".to_string(),
+                            Vec::new(),
+                        )
+                    });
 
                 // Fetch contract events
-                let events = match self.fetch_contract_events(contract_address).await {
+                let events = match self
+                    .fetch_contract_events(&contract_address, &topics)
+                    .await
+                {
                     Ok(events) => events,
                     Err(e) => {
                         return Err(TaskError::Error(format!(
@@ -358,11 +532,12 @@ impl TaskSource for EthereumTaskSource {
                 // Update trigger for next time
                 self.current_trigger = Trigger::EthereumTransaction;
 
-                // Return contract event
-                event::Event::EthereumContractEvent {
-                    contract_address: contract_address.to_string(),
-                    events,
-                }
+                // Return contract event, keeping only the triggered logs
+                self.contract_filters
+                    .apply(event::Event::EthereumContractEvent {
+                        contract_address,
+                        events: events.as_array().cloned().unwrap_or_default(),
+                    })
             }
             Trigger::EthereumTransaction => {
                 // Use a mock transaction hash
@@ -457,6 +632,11 @@ impl TaskSource for EthereumTaskSource {
             "#
         );
 
-        Ok(Func { code, version: 1 })
+        Ok(Func {
+            code,
+            version: 1,
+            source_map: String::new(),
+            metadata: String::new(),
+        })
     }
 }
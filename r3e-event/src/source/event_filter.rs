@@ -5,6 +5,7 @@ use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
 use crate::source::event;
+use crate::source::token_transfer::NeoTokenTransfers;
 
 /// Event filter for filtering events from task sources
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -135,10 +136,15 @@ impl EventFilter {
             event::Event::BtcBlock(block) => self.filter_custom(&serde_json::json!(block)),
             event::Event::NeoApplicationLog(app_log) => self.filter_custom(&serde_json::json!(app_log)),
             event::Event::NeoContractNotification(notification) => self.filter_custom(&serde_json::json!(notification)),
+            event::Event::NeoTokenTransfers(transfers) => self.filter_neo_token_transfers(transfers),
             event::Event::EthereumBlock(block) => self.filter_ethereum_block(block),
             event::Event::EthereumTransaction(tx) => self.filter_ethereum_transaction(tx),
             event::Event::EthereumContractEvent { contract_address, events } => 
                 self.filter_ethereum_contract_event(contract_address, events),
+            event::Event::ChainReorg(reorg) => {
+                // Reorgs are delivered for every event type, so functions can compensate
+                self.network.as_ref().map_or(true, |network| *network == reorg.chain)
+            }
             event::Event::NearBlock(_) |
             event::Event::NearAccountChange(_) |
             event::Event::NearTransaction(_) => {
@@ -262,6 +268,54 @@ impl EventFilter {
         true
     }
 
+    /// Filter Neo token transfers; matches if any transfer matches.
+    ///
+    /// The contract address filter applies to the token contract.
+    fn filter_neo_token_transfers(&self, transfers: &NeoTokenTransfers) -> bool {
+        // Check network
+        if let Some(network) = &self.network {
+            if network != "neo" {
+                return false;
+            }
+        }
+
+        // Check event type
+        if let Some(event_type) = &self.event_type {
+            if event_type != "token_transfer" {
+                return false;
+            }
+        }
+
+        // Check transaction hash
+        if let Some(tx_hash) = &self.tx_hash {
+            if &transfers.tx_hash != tx_hash {
+                return false;
+            }
+        }
+
+        let same = |a: &str, b: &str| {
+            a.trim_start_matches("0x")
+                .eq_ignore_ascii_case(b.trim_start_matches("0x"))
+        };
+
+        transfers.transfers.iter().any(|transfer| {
+            self.contract_address
+                .as_ref()
+                .map_or(true, |token| same(&transfer.token, token))
+                && self
+                    .from
+                    .as_ref()
+                    .map_or(true, |from| transfer.from.as_ref().is_some_and(|f| same(f, from)))
+                && self
+                    .to
+                    .as_ref()
+                    .map_or(true, |to| transfer.to.as_ref().is_some_and(|t| same(t, to)))
+                && self
+                    .min_value
+                    .map_or(true, |min_value| transfer.amount_value() >= min_value as u128)
+        })
+    }
+
     /// Filter Ethereum block
     fn filter_ethereum_block(&self, block: &Value) -> bool {
         // Check network
@@ -490,6 +544,37 @@ mod tests {
         assert!(!filter.filter_neo_block(&block));
     }
 
+    #[test]
+    fn test_neo_token_transfers_filter() {
+        use crate::source::token_transfer::{TokenStandard, TokenTransfer};
+
+        let transfers = NeoTokenTransfers {
+            tx_hash: "0xabc".to_string(),
+            transfers: vec![TokenTransfer {
+                standard: TokenStandard::Nep17,
+                token: "0xd2a4cff31913016155e38e474a2c06d08be276cf".to_string(),
+                from: Some("0x1111111111111111111111111111111111111111".to_string()),
+                to: None,
+                amount: "500".to_string(),
+                token_id: None,
+                notification_index: 0,
+            }],
+        };
+
+        let filter = EventFilter::new()
+            .with_event_type("token_transfer")
+            .with_contract_address("D2A4CFF31913016155E38E474A2C06D08BE276CF")
+            .with_from("0x1111111111111111111111111111111111111111")
+            .with_min_value(500);
+        assert!(filter.filter_neo_token_transfers(&transfers));
+
+        let filter = EventFilter::new().with_to("0x1111111111111111111111111111111111111111");
+        assert!(!filter.filter_neo_token_transfers(&transfers));
+
+        let filter = EventFilter::new().with_min_value(501);
+        assert!(!filter.filter_neo_token_transfers(&transfers));
+    }
+
     #[test]
     fn test_ethereum_block_filter() {
         let block = json!({
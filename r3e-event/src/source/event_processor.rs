@@ -198,6 +198,15 @@ impl EventProcessor {
                     "timestamp": chrono::Utc::now().timestamp(),
                 })
             }
+            event::Event::NeoTokenTransfers(ref transfers) => {
+                json!({
+                    "network": "neo",
+                    "event_type": "token_transfer",
+                    "tx_hash": transfers.tx_hash,
+                    "transfers": transfers.transfers,
+                    "timestamp": chrono::Utc::now().timestamp(),
+                })
+            }
             event::Event::NeoContractEvent {
                 ref contract_address,
                 ref events,
@@ -238,6 +247,16 @@ impl EventProcessor {
                     "timestamp": chrono::Utc::now().timestamp(),
                 })
             }
+            event::Event::ChainReorg(ref reorg) => {
+                json!({
+                    "network": reorg.chain,
+                    "event_type": "chain_reorg",
+                    "fork_height": reorg.fork_height,
+                    "invalidated": reorg.invalidated,
+                    "canonical": reorg.canonical,
+                    "timestamp": chrono::Utc::now().timestamp(),
+                })
+            }
             event::Event::Custom(ref data) => {
                 json!({
                     "event_type": "custom",
@@ -417,6 +417,9 @@ pub mod event {
         /// Neo application log
         #[serde(rename = "neo_application_log")]
         NeoApplicationLog(super::NeoApplicationLog),
+        /// Decoded NEP-17/NEP-11 token transfers
+        #[serde(rename = "neo_token_transfers")]
+        NeoTokenTransfers(crate::source::token_transfer::NeoTokenTransfers),
         /// NEAR event
         #[serde(rename = "near_block")]
         NearBlock(serde_json::Value),
@@ -438,6 +441,9 @@ pub mod event {
             contract_address: String,
             events: Vec<serde_json::Value>,
         },
+        /// Chain reorganization, invalidating previously delivered blocks
+        #[serde(rename = "chain_reorg")]
+        ChainReorg(crate::source::reorg::ChainReorg),
     }
 
     impl Default for Event {
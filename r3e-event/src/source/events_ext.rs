@@ -139,3 +139,27 @@ impl From<i64> for value::Value {
         value::Value::Int64(value)
     }
 }
+
+impl source::event::Event {
+    /// Trigger type of the event, as named in its serialized form
+    pub fn kind(&self) -> &'static str {
+        use source::event::Event;
+
+        match self {
+            Event::None => "none",
+            Event::Mock(_) => "mock",
+            Event::BtcBlock(_) => "btc_block",
+            Event::NeoBlock(_) => "neo_block",
+            Event::NeoContractNotification(_) => "neo_contract_notification",
+            Event::NeoApplicationLog(_) => "neo_application_log",
+            Event::NeoTokenTransfers(_) => "neo_token_transfers",
+            Event::NearBlock(_) => "near_block",
+            Event::NearAccountChange(_) => "near_account_change",
+            Event::NearTransaction(_) => "near_transaction",
+            Event::EthereumBlock(_) => "ethereum_block",
+            Event::EthereumTransaction(_) => "ethereum_transaction",
+            Event::EthereumContractEvent { .. } => "ethereum_contract_event",
+            Event::ChainReorg(_) => "chain_reorg",
+        }
+    }
+}
@@ -422,6 +422,8 @@ impl TaskSource for MockTaskSource {
             version: 1,
             code: "async function handler(request) { return { status: 200, body: 'mock' }; }"
                 .to_string(),
+            source_map: String::new(),
+            metadata: String::new(),
         })
     }
 }
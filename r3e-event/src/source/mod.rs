@@ -1,6 +1,8 @@
 // Copyright @ 2023 - 2024, R3E Network
 // All Rights Reserved
 
+pub mod checkpoint;
+pub mod contract_filter;
 pub mod ethereum;
 pub mod event_filter;
 pub mod event_processor;
@@ -9,15 +11,21 @@ pub mod events;
 pub mod events_ext;
 pub mod mock;
 pub mod neo;
+pub mod neo_subscription;
+pub mod reorg;
 pub mod service;
+pub mod synthetic;
+pub mod token_transfer;
 
 #[cfg(test)]
 mod events_test;
 
 #[allow(unused_imports)]
 pub use {
-    ethereum::*, event_filter::*, event_processor::*, event_processor_service::*, events::*,
-    events_ext::*, mock::*, neo::*, service::*,
+    checkpoint::*, contract_filter::*, ethereum::*, event_filter::*, event_processor::*,
+    event_processor_service::*, events::*, events_ext::*, mock::*, neo::*, neo_subscription::*,
+    reorg::*, service::*,
+    synthetic::*, token_transfer::*,
 };
 
 #[derive(Debug, thiserror::Error)]
@@ -67,6 +75,23 @@ pub trait TaskSource: Send + Sync {
     async fn acquire_task(&mut self, uid: u64, fid_hint: u64) -> Result<Task, TaskError>;
 
     async fn acquire_fn(&mut self, uid: u64, fid: u64) -> Result<Func, FuncError>;
+
+    /// Take a task that is ready without waiting, None if the next one has to be waited for.
+    /// Committing after acquiring several tasks commits them all. Sources without ready tasks
+    /// buffered have none.
+    async fn try_acquire_task(
+        &mut self,
+        _uid: u64,
+        _fid_hint: u64,
+    ) -> Result<Option<Task>, TaskError> {
+        Ok(None)
+    }
+
+    /// Mark the last acquired task as processed, so a restarted source resumes after it.
+    /// Sources without checkpoints have nothing to do.
+    async fn commit_task(&mut self) -> Result<(), TaskError> {
+        Ok(())
+    }
 }
 
 pub struct TaskSourceClient {
@@ -83,6 +108,38 @@ impl TaskSourceClient {
             inner: task_source_client::TaskSourceClient::connect(addr).await?,
         })
     }
+
+    /// Connect presenting the certificate of the worker, and verifying the source against the
+    /// CA of the mutual TLS configuration
+    pub async fn connect_mtls(
+        addr: String,
+        mtls: &r3e_core::mtls::MtlsConfig,
+        domain: &str,
+    ) -> Result<Self, TaskError> {
+        use tonic::transport::{Certificate, ClientTlsConfig, Endpoint, Identity};
+
+        let read = |path: &std::path::Path| {
+            std::fs::read(path)
+                .map_err(|err| TaskError::Error(format!("{}: {}", path.display(), err)))
+        };
+        let tls = ClientTlsConfig::new()
+            .domain_name(domain)
+            .ca_certificate(Certificate::from_pem(read(&mtls.ca_path)?))
+            .identity(Identity::from_pem(
+                read(&mtls.cert_path)?,
+                read(&mtls.key_path)?,
+            ));
+
+        let channel = Endpoint::from_shared(addr)
+            .and_then(|endpoint| endpoint.tls_config(tls))
+            .map_err(|err| TaskError::Error(err.to_string()))?
+            .connect()
+            .await
+            .map_err(|err| TaskError::Error(err.to_string()))?;
+        Ok(Self {
+            inner: task_source_client::TaskSourceClient::new(channel),
+        })
+    }
 }
 
 #[async_trait::async_trait]
@@ -147,6 +204,8 @@ impl TaskSource for TaskSourceClient {
         Ok(Func {
             version: func.version,
             code: func.code,
+            source_map: func.source_map,
+            metadata: func.metadata,
         })
     }
 }
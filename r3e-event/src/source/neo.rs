@@ -2,7 +2,9 @@
 // All Rights Reserved
 
 use crate::source::events::{event, BtcBlock, Event, NeoApplication, NeoBlock, NeoContractEvent, NeoEvent, NeoTransaction};
-use crate::source::{Task, TaskError, TaskSource, Func, FuncError};
+use crate::source::{ContractFilterSet, ContractNotificationTrigger, Task, TaskError, TaskSource, Func, FuncError};
+use crate::source::{TokenTransferFilterSet, TokenTransferTrigger};
+use crate::trigger::TriggerError;
 use async_trait::async_trait;
 use chrono::Utc;
 use log::{debug, error, info, warn};
@@ -16,6 +18,7 @@ use uuid::Uuid;
 use reqwest;
 use neo3::neo_clients::{APITrait, HttpProvider, RpcClient};
 use neo3::prelude::transaction;
+use r3e_core::rpc_pool::{RpcEndpointPool, RpcPoolConfig};
 use super::event::Event as EventEnum;
 use super::service;
 use super::service::TaskSource;
@@ -37,11 +40,16 @@ pub struct NeoTaskSource {
     sleep: Duration,
     uid: u64,
     count: u64,
-    client: Arc<RwLock<Option<RpcClient<HttpProvider>>>>,
-    rpc_url: String,
+    // RPC clients by endpoint URL
+    clients: Arc<RwLock<HashMap<String, Arc<RpcClient<HttpProvider>>>>>,
+    rpc_pool: Arc<RpcEndpointPool>,
     // Track the current trigger type to rotate between different event types
     current_trigger: NeoTrigger,
     filter: Option<String>,
+    // Compiled contract notification triggers
+    contract_filters: ContractFilterSet,
+    // Compiled token transfer triggers
+    transfer_filters: TokenTransferFilterSet,
 }
 
 impl NeoTaskSource {
@@ -51,46 +59,97 @@ impl NeoTaskSource {
             sleep,
             uid,
             count: 0,
-            client: Arc::new(RwLock::new(None)),
+            clients: Arc::new(RwLock::new(HashMap::new())),
             // Default to Neo N3 TestNet
-            rpc_url: "https://testnet1.neo.org:443".to_string(),
+            rpc_pool: Arc::new(RpcEndpointPool::new(
+                "neo",
+                ["https://testnet1.neo.org:443", "https://testnet2.neo.org:443"],
+                RpcPoolConfig::default(),
+            )),
             // Start with NeoNewBlock trigger
             current_trigger: NeoTrigger::NeoNewBlock,
             filter,
+            contract_filters: ContractFilterSet::default(),
+            transfer_filters: TokenTransferFilterSet::default(),
         }
     }
 
     pub fn with_rpc_url(mut self, rpc_url: impl Into<String>) -> Self {
-        self.rpc_url = rpc_url.into();
+        self.rpc_pool = Arc::new(RpcEndpointPool::new(
+            "neo",
+            [rpc_url.into()],
+            RpcPoolConfig::default(),
+        ));
         self
     }
 
+    /// Fail over between the endpoints of a shared RPC pool
+    pub fn with_rpc_pool(mut self, rpc_pool: Arc<RpcEndpointPool>) -> Self {
+        self.rpc_pool = rpc_pool;
+        self
+    }
+
+    /// Only emit contract notifications matching one of the triggers
+    pub fn with_contract_triggers(
+        mut self,
+        triggers: &[ContractNotificationTrigger],
+    ) -> Result<Self, TriggerError> {
+        self.contract_filters = ContractFilterSet::compile(triggers)?;
+        for filter in self.contract_filters.filters() {
+            if filter.is_ethereum() {
+                warn!("Ignoring Ethereum contract trigger {} on Neo source", filter.contract());
+            }
+        }
+        Ok(self)
+    }
+
+    /// Decode contract notifications into token transfers, emitting only the transfers
+    /// matching one of the triggers
+    pub fn with_transfer_triggers(
+        mut self,
+        triggers: &[TokenTransferTrigger],
+    ) -> Result<Self, TriggerError> {
+        self.transfer_filters = TokenTransferFilterSet::compile(triggers)?;
+        Ok(self)
+    }
+
     async fn ensure_client(
         &self,
+        rpc_url: &str,
     ) -> Result<Arc<RpcClient<HttpProvider>>, Box<dyn std::error::Error + Send + Sync>> {
-        let mut client_guard = self.client.write().await;
+        let mut clients = self.clients.write().await;
 
-        if client_guard.is_none() {
-            // Create URL from string
-            let url = Url::parse(&self.rpc_url)
-                .map_err(|e| Box::new(e) as Box<dyn std::error::Error + Send + Sync>)?;
-
-            // Create HTTP provider and RPC client
-            let provider = HttpProvider::new(url).expect("Failed to create HTTP provider");
-            let client = RpcClient::new(provider);
-            *client_guard = Some(client);
+        if let Some(client) = clients.get(rpc_url) {
+            return Ok(client.clone());
         }
 
-        // Clone the client to return it
-        let client = client_guard.as_ref().unwrap().clone();
-        Ok(Arc::new(client))
+        // Create URL from string
+        let url = Url::parse(rpc_url)
+            .map_err(|e| Box::new(e) as Box<dyn std::error::Error + Send + Sync>)?;
+
+        // Create HTTP provider and RPC client
+        let provider = HttpProvider::new(url)
+            .map_err(|e| Box::new(e) as Box<dyn std::error::Error + Send + Sync>)?;
+        let client = Arc::new(RpcClient::new(provider));
+        clients.insert(rpc_url.to_string(), client.clone());
+        Ok(client)
     }
 
-    // Fetch the latest Neo block
+    // Fetch the latest Neo block, failing over between the RPC endpoints
     async fn fetch_latest_block(
         &self,
     ) -> Result<NeoBlock, Box<dyn std::error::Error + Send + Sync>> {
-        let client = self.ensure_client().await?;
+        Ok(self
+            .rpc_pool
+            .call(|rpc_url| async move { self.fetch_latest_block_from(&rpc_url).await })
+            .await?)
+    }
+
+    async fn fetch_latest_block_from(
+        &self,
+        rpc_url: &str,
+    ) -> Result<NeoBlock, Box<dyn std::error::Error + Send + Sync>> {
+        let client = self.ensure_client(rpc_url).await?;
 
         // Get the current block count
         let block_count = client
@@ -163,9 +222,16 @@ impl NeoTaskSource {
         hash: &str,
     ) -> Result<NeoTx, Box<dyn std::error::Error + Send + Sync>> {
         // GET Neo transaction
-        let endpoint = format!("{}/gettransaction/{}", self.rpc_url, hash);
-        let response = reqwest::get(&endpoint).await?;
-        let data = response.json::<serde_json::Value>().await?;
+        let data = self
+            .rpc_pool
+            .call(|rpc_url| async move {
+                let endpoint = format!("{}/gettransaction/{}", rpc_url, hash);
+                reqwest::get(&endpoint)
+                    .await?
+                    .json::<serde_json::Value>()
+                    .await
+            })
+            .await?;
 
         // Extract transaction data
         let tx = data["result"].clone();
@@ -186,12 +252,29 @@ impl NeoTaskSource {
         })
     }
 
-    // Fetch application logs for a transaction
+    // Fetch application logs for a transaction, failing over between the RPC endpoints
     async fn fetch_application_log(
         &self,
         tx_hash: &str,
     ) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
-        let client = self.ensure_client().await?;
+        // Reject malformed hashes up front, so they don't count as endpoint failures
+        let hex = tx_hash.trim_start_matches("0x");
+        if hex.len() != 64 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(format!("invalid transaction hash: {}", tx_hash).into());
+        }
+
+        Ok(self
+            .rpc_pool
+            .call(|rpc_url| async move { self.fetch_application_log_from(&rpc_url, tx_hash).await })
+            .await?)
+    }
+
+    async fn fetch_application_log_from(
+        &self,
+        rpc_url: &str,
+        tx_hash: &str,
+    ) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+        let client = self.ensure_client(rpc_url).await?;
 
         // Parse the transaction hash to H256
         let hash = tx_hash
@@ -437,7 +520,18 @@ impl NeoTaskSource {
                 self.current_trigger = NeoTrigger::NeoNewBlock;
 
                 // Create the appropriate event based on the trigger type
-                if is_notification {
+                if is_notification && !notifications.is_empty() {
+                    // Keep only the notifications matching the contract triggers, then
+                    // decode the token transfers if transfer triggers are configured
+                    let event = self.contract_filters.apply(EventEnum::NeoContractNotification(
+                        NeoContractNotification {
+                            tx_hash,
+                            notifications: serde_json::Value::Array(notifications).to_string(),
+                        },
+                    ));
+                    let event = self.transfer_filters.apply(event);
+                    Ok(Task::new(self.uid, 1, event))
+                } else if is_notification {
                     // Prepare notifications as a JSON string
                     let notifications_json = json!([
                         {
@@ -452,10 +546,12 @@ impl NeoTaskSource {
                     ]).to_string();
 
                     // Return contract notification event
-                    let event = EventEnum::NeoContractNotification(NeoContractNotification {
-                        tx_hash,
-                        notifications: notifications_json,
-                    });
+                    let event = self.contract_filters.apply(EventEnum::NeoContractNotification(
+                        NeoContractNotification {
+                            tx_hash,
+                            notifications: notifications_json,
+                        },
+                    ));
                     Ok(Task::new(self.uid, 1, event))
                 } else {
                     // Return application log event
@@ -574,6 +670,8 @@ impl NeoTaskSource {
         Ok(Func {
             version: 1,
             code: code.into(),
+            source_map: String::new(),
+            metadata: String::new(),
         })
     }
 }
@@ -614,6 +712,8 @@ impl TaskSource for NeoTaskSource {
             version: 1,
             code: "async function handler(request) { return { status: 200, body: 'neo' }; }"
                 .to_string(),
+            source_map: String::new(),
+            metadata: String::new(),
         })
     }
 }
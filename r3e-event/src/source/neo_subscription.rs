@@ -0,0 +1,660 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures_util::{SinkExt, StreamExt};
+use r3e_core::rpc_pool::RpcEndpointPool;
+use serde_json::{json, Value};
+use tokio::sync::mpsc;
+use tokio::time::Instant;
+use tokio_tungstenite::tungstenite::Message;
+
+use crate::source::checkpoint::{BlockPosition, SourceCheckpoint, SourceCheckpointStore};
+use crate::source::events::{NeoBlock, NeoBlockHeader, NeoTx, NeoWitness};
+use crate::source::reorg::{ReorgDetector, DEFAULT_REORG_DEPTH};
+use crate::source::{event, Func, FuncError, Task, TaskError, TaskSource};
+
+/// Number of events buffered between the subscription and the runner
+const EVENT_BUFFER: usize = 1024;
+
+/// Push-based Neo N3 block and transaction source.
+///
+/// Blocks are received through a WebSocket `block_added` subscription. Between reconnection
+/// attempts, or without a WebSocket endpoint at all, the block count is long-polled over RPC.
+/// Blocks missed while disconnected are backfilled in order, so every block is delivered once.
+/// Blocks replaced by a reorg are announced with a `ChainReorg` event.
+///
+/// With checkpoints, the position of every task acquired is committed once the task is
+/// processed, and a restarted source resumes right after the last committed one.
+pub struct NeoSubscriptionTaskSource {
+    rpc_pool: Arc<RpcEndpointPool>,
+    ws_url: Option<String>,
+    poll_interval: Duration,
+    max_backoff: Duration,
+    start_height: Option<u32>,
+    transactions: bool,
+    reorg_depth: usize,
+    reemit_canonical: bool,
+    functions: HashMap<u64, Func>,
+    checkpoints: Option<(String, Arc<dyn SourceCheckpointStore>)>,
+    /// Position of the last acquired task, committed once it is processed
+    acquired: Option<BlockPosition>,
+    events: Option<mpsc::Receiver<(Option<BlockPosition>, event::Event)>>,
+}
+
+impl NeoSubscriptionTaskSource {
+    /// Create a new subscription source, backfilling and polling through the RPC pool
+    pub fn new(rpc_pool: Arc<RpcEndpointPool>) -> Self {
+        Self {
+            rpc_pool,
+            ws_url: None,
+            poll_interval: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(60),
+            start_height: None,
+            transactions: false,
+            reorg_depth: DEFAULT_REORG_DEPTH,
+            reemit_canonical: false,
+            functions: HashMap::new(),
+            checkpoints: None,
+            acquired: None,
+            events: None,
+        }
+    }
+
+    /// Subscribe to new blocks on a WebSocket endpoint, e.g. `wss://node:10331/ws`
+    pub fn with_ws_url(mut self, ws_url: impl Into<String>) -> Self {
+        self.ws_url = Some(ws_url.into());
+        self
+    }
+
+    /// Set the interval of the block-count long-poll fallback
+    pub fn with_poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = poll_interval;
+        self
+    }
+
+    /// Set the maximum delay between WebSocket reconnection attempts
+    pub fn with_max_backoff(mut self, max_backoff: Duration) -> Self {
+        self.max_backoff = max_backoff;
+        self
+    }
+
+    /// Deliver blocks from the given height on, instead of only blocks produced from now on
+    pub fn with_start_height(mut self, start_height: u32) -> Self {
+        self.start_height = Some(start_height);
+        self
+    }
+
+    /// Also deliver every transaction of a block as its own event
+    pub fn with_transactions(mut self, transactions: bool) -> Self {
+        self.transactions = transactions;
+        self
+    }
+
+    /// Set the number of recent blocks tracked to detect reorgs
+    pub fn with_reorg_depth(mut self, depth: usize) -> Self {
+        self.reorg_depth = depth;
+        self
+    }
+
+    /// Re-emit the blocks of the canonical chain after a reorg, so functions can compensate
+    pub fn with_canonical_reemit(mut self, reemit_canonical: bool) -> Self {
+        self.reemit_canonical = reemit_canonical;
+        self
+    }
+
+    /// Commit the position of processed tasks under the given source name, and resume after
+    /// the committed position on start, ahead of `with_start_height`
+    pub fn with_checkpoints(
+        mut self,
+        source: impl Into<String>,
+        checkpoints: Arc<dyn SourceCheckpointStore>,
+    ) -> Self {
+        self.checkpoints = Some((source.into(), checkpoints));
+        self
+    }
+
+    /// Serve the given code for a function
+    pub fn with_function(mut self, fid: u64, code: String) -> Self {
+        self.functions.insert(
+            fid,
+            Func {
+                version: 1,
+                code,
+                source_map: String::new(),
+                metadata: String::new(),
+            },
+        );
+        self
+    }
+
+    /// Set the metadata of a function served with `with_function`
+    pub fn with_function_metadata(mut self, fid: u64, metadata: &serde_json::Value) -> Self {
+        if let Some(func) = self.functions.get_mut(&fid) {
+            func.metadata = metadata.to_string();
+        }
+        self
+    }
+
+    fn start(&mut self) -> &mut mpsc::Receiver<(Option<BlockPosition>, event::Event)> {
+        self.events.get_or_insert_with(|| {
+            let checkpoint = self.checkpoints.as_ref().and_then(|(source, checkpoints)| {
+                checkpoints.load(source).unwrap_or_else(|err| {
+                    log::error!(
+                        "neo subscription: load checkpoint of {} failed: {}",
+                        source,
+                        err
+                    );
+                    None
+                })
+            });
+
+            let (sender, receiver) = mpsc::channel(EVENT_BUFFER);
+            let mut follower = BlockFollower {
+                rpc_pool: self.rpc_pool.clone(),
+                http: reqwest::Client::new(),
+                ws_url: self.ws_url.clone(),
+                poll_interval: self.poll_interval,
+                max_backoff: self.max_backoff,
+                next_height: self.start_height,
+                transactions: self.transactions,
+                reorg_detector: ReorgDetector::new("neo", self.reorg_depth),
+                reemit_canonical: self.reemit_canonical,
+                resume_after: None,
+                sender,
+            };
+
+            // Deliver the checkpointed block again, skipping its events already processed, so
+            // a block replaced while the source was down shows up as a reorg
+            if let Some(checkpoint) = checkpoint {
+                let position = checkpoint.position;
+                log::info!(
+                    "neo subscription: resume after block {} event {}",
+                    position.height,
+                    position.index
+                );
+                follower.next_height = Some(position.height as u32);
+                follower
+                    .reorg_detector
+                    .resume_from(position.height, &position.hash);
+                follower.resume_after = Some(position);
+            }
+            tokio::spawn(follower.run());
+            receiver
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl TaskSource for NeoSubscriptionTaskSource {
+    async fn acquire_task(&mut self, uid: u64, fid_hint: u64) -> Result<Task, TaskError> {
+        match self.start().recv().await {
+            Some((position, event)) => {
+                self.acquired = position;
+                Ok(Task::new(uid, fid_hint, event))
+            }
+            None => Err(TaskError::Error("neo subscription stopped".to_string())),
+        }
+    }
+
+    async fn try_acquire_task(
+        &mut self,
+        uid: u64,
+        fid_hint: u64,
+    ) -> Result<Option<Task>, TaskError> {
+        match self.start().try_recv() {
+            Ok((position, event)) => {
+                // Tasks without a position do not move the commit back before earlier ones
+                if position.is_some() {
+                    self.acquired = position;
+                }
+                Ok(Some(Task::new(uid, fid_hint, event)))
+            }
+            Err(mpsc::error::TryRecvError::Empty) => Ok(None),
+            Err(mpsc::error::TryRecvError::Disconnected) => {
+                Err(TaskError::Error("neo subscription stopped".to_string()))
+            }
+        }
+    }
+
+    async fn commit_task(&mut self) -> Result<(), TaskError> {
+        // Reorg announcements and re-emitted blocks have no position of their own
+        let (Some((source, checkpoints)), Some(position)) =
+            (&self.checkpoints, self.acquired.take())
+        else {
+            return Ok(());
+        };
+
+        let checkpoint = SourceCheckpoint {
+            source: source.clone(),
+            position,
+            committed_at: chrono::Utc::now().timestamp() as u64,
+        };
+        checkpoints
+            .commit(&checkpoint)
+            .map_err(|err| TaskError::Error(format!("commit checkpoint of {}: {}", source, err)))
+    }
+
+    async fn acquire_fn(&mut self, uid: u64, fid: u64) -> Result<Func, FuncError> {
+        self.functions
+            .get(&fid)
+            .cloned()
+            .ok_or(FuncError::NoSuchFunc(uid, fid))
+    }
+}
+
+/// The receiving side of the source is gone
+struct Closed;
+
+/// Follows the chain head, delivering every block once and in order
+struct BlockFollower {
+    rpc_pool: Arc<RpcEndpointPool>,
+    http: reqwest::Client,
+    ws_url: Option<String>,
+    poll_interval: Duration,
+    max_backoff: Duration,
+
+    /// Height of the next block to deliver, unknown until the first block count
+    next_height: Option<u32>,
+    transactions: bool,
+
+    /// Hashes of recent blocks, to detect reorgs
+    reorg_detector: ReorgDetector,
+    reemit_canonical: bool,
+
+    /// Checkpoint resumed from, whose block and earlier events are not delivered again
+    resume_after: Option<BlockPosition>,
+    sender: mpsc::Sender<(Option<BlockPosition>, event::Event)>,
+}
+
+impl BlockFollower {
+    async fn run(mut self) {
+        let Some(ws_url) = self.ws_url.clone() else {
+            log::info!("neo subscription: no WebSocket endpoint, long-polling blocks");
+            let _ = self.poll_until(None).await;
+            return;
+        };
+
+        let mut backoff = Duration::from_secs(1);
+        loop {
+            match self.follow_ws(&ws_url, &mut backoff).await {
+                Ok(()) => log::warn!("neo subscription: {} closed", ws_url),
+                Err(Ok(err)) => log::warn!("neo subscription: {}: {}", ws_url, err),
+                Err(Err(Closed)) => return,
+            }
+
+            // Keep up by polling until it is time to reconnect
+            log::info!("neo subscription: reconnecting in {:?}", backoff);
+            if self
+                .poll_until(Some(Instant::now() + backoff))
+                .await
+                .is_err()
+            {
+                return;
+            }
+            backoff = (backoff * 2).min(self.max_backoff);
+        }
+    }
+
+    /// Subscribe to new blocks, returning once the connection is lost
+    async fn follow_ws(
+        &mut self,
+        ws_url: &str,
+        backoff: &mut Duration,
+    ) -> Result<(), Result<String, Closed>> {
+        let (mut ws, _) = tokio_tungstenite::connect_async(ws_url)
+            .await
+            .map_err(|e| Ok(format!("connect: {}", e)))?;
+
+        let subscribe = json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "subscribe",
+            "params": ["block_added"],
+        });
+        ws.send(Message::Text(subscribe.to_string()))
+            .await
+            .map_err(|e| Ok(format!("subscribe: {}", e)))?;
+        log::info!("neo subscription: subscribed to blocks on {}", ws_url);
+        *backoff = Duration::from_secs(1);
+
+        // Deliver the blocks produced while disconnected
+        self.catch_up()
+            .await
+            .map_err(|e| e.map(|e| format!("backfill: {}", e)))?;
+
+        while let Some(message) = ws.next().await {
+            let text = match message.map_err(|e| Ok(format!("receive: {}", e)))? {
+                Message::Text(text) => text,
+                Message::Close(_) => break,
+                _ => continue,
+            };
+
+            let message: Value = match serde_json::from_str(&text) {
+                Ok(message) => message,
+                Err(e) => {
+                    log::warn!("neo subscription: invalid message: {}", e);
+                    continue;
+                }
+            };
+            if let Some(error) = message.get("error") {
+                return Err(Ok(format!("subscribe: {}", error)));
+            }
+            if message["method"] != "block_added" {
+                continue;
+            }
+
+            let block = &message["params"][0];
+            let Some(height) = block["index"].as_u64().map(|height| height as u32) else {
+                log::warn!("neo subscription: block without index");
+                continue;
+            };
+            match self.next_height {
+                // Already delivered, e.g. by the backfill
+                Some(next)
+                    if height < next
+                        && self.reorg_detector.hash_at(height as u64) == block["hash"].as_str() =>
+                {
+                    continue
+                }
+                // Blocks were missed
+                Some(next) if height > next => self
+                    .backfill(height)
+                    .await
+                    .map_err(|e| e.map(|e| format!("backfill: {}", e)))?,
+                _ => {}
+            }
+            self.deliver(block).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Long-poll the block count until the deadline, or forever without one
+    async fn poll_until(&mut self, deadline: Option<Instant>) -> Result<(), Closed> {
+        loop {
+            match self.catch_up().await {
+                Ok(()) => {}
+                Err(Ok(err)) => log::warn!("neo subscription: poll: {}", err),
+                Err(Err(Closed)) => return Err(Closed),
+            }
+
+            let wake = Instant::now() + self.poll_interval;
+            match deadline {
+                Some(deadline) if deadline <= wake => {
+                    tokio::time::sleep_until(deadline).await;
+                    return Ok(());
+                }
+                _ => tokio::time::sleep_until(wake).await,
+            }
+        }
+    }
+
+    /// Deliver all blocks up to the current chain head
+    async fn catch_up(&mut self) -> Result<(), Result<String, Closed>> {
+        let count = self.rpc("getblockcount", json!([])).await.map_err(Ok)?;
+        let count = count
+            .as_u64()
+            .ok_or_else(|| Ok(format!("invalid block count: {}", count)))?
+            as u32;
+        self.backfill(count).await
+    }
+
+    /// Deliver the blocks from the next height up to, excluding, `until`
+    async fn backfill(&mut self, until: u32) -> Result<(), Result<String, Closed>> {
+        let next = *self.next_height.get_or_insert(until);
+        for height in next..until {
+            let block = self
+                .rpc("getblock", json!([height, true]))
+                .await
+                .map_err(Ok)?;
+            self.deliver(&block).await?;
+        }
+        Ok(())
+    }
+
+    async fn deliver(&mut self, block: &Value) -> Result<(), Result<String, Closed>> {
+        let block = parse_block(block);
+        let (height, hash, prev_hash) = block
+            .header
+            .as_ref()
+            .map(|header| {
+                (
+                    header.height,
+                    header.hash.clone(),
+                    header.prev_block_hash.clone(),
+                )
+            })
+            .unwrap_or_default();
+
+        // Announce the blocks orphaned by a reorg before the block replacing them
+        for event in self
+            .track_block(height, &hash, &prev_hash)
+            .await
+            .map_err(Ok)?
+        {
+            self.sender
+                .send((None, event))
+                .await
+                .map_err(|_| Err(Closed))?;
+        }
+
+        let txs = if self.transactions {
+            block.txs.clone()
+        } else {
+            Vec::new()
+        };
+
+        // Transactions are delivered as blocks without a header, like the polling source
+        let events =
+            std::iter::once(event::Event::NeoBlock(block)).chain(txs.into_iter().map(|tx| {
+                event::Event::NeoBlock(NeoBlock {
+                    header: None,
+                    txs: vec![tx],
+                })
+            }));
+        for (index, event) in events.enumerate() {
+            let position = BlockPosition {
+                height: height as u64,
+                index: index as u32,
+                hash: hash.clone(),
+            };
+            if let Some(checkpoint) = &self.resume_after {
+                if position.is_processed_at(checkpoint) {
+                    continue;
+                }
+            }
+            self.sender
+                .send((Some(position), event))
+                .await
+                .map_err(|_| Err(Closed))?;
+        }
+
+        self.next_height = Some(height + 1);
+        Ok(())
+    }
+
+    /// Track a new block, returning the events to deliver before it when it reveals a reorg
+    async fn track_block(
+        &mut self,
+        height: u32,
+        hash: &str,
+        prev_hash: &str,
+    ) -> Result<Vec<event::Event>, String> {
+        let mut detector = self.reorg_detector.clone();
+        let this = &*self;
+        let reorg = detector
+            .observe(height as u64, hash, prev_hash, |height| async move {
+                let hash = this.rpc("getblockhash", json!([height])).await?;
+                Ok::<_, String>(hash.as_str().unwrap_or_default().to_string())
+            })
+            .await?;
+        self.reorg_detector = detector;
+
+        let Some(reorg) = reorg else {
+            return Ok(Vec::new());
+        };
+        log::warn!(
+            "neo subscription: reorg at height {}, {} blocks invalidated",
+            reorg.fork_height,
+            reorg.invalidated.len()
+        );
+
+        // The new block itself is delivered by the caller
+        let reemit: Vec<u64> = match self.reemit_canonical {
+            true => reorg
+                .canonical
+                .iter()
+                .map(|block| block.height)
+                .filter(|h| *h < height as u64)
+                .collect(),
+            false => Vec::new(),
+        };
+
+        let mut events = vec![event::Event::ChainReorg(reorg)];
+        for h in reemit {
+            let block = self.rpc("getblock", json!([h, true])).await?;
+            events.push(event::Event::NeoBlock(parse_block(&block)));
+        }
+        Ok(events)
+    }
+
+    async fn rpc(&self, method: &str, params: Value) -> Result<Value, String> {
+        let request = json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": method,
+            "params": params,
+        });
+        self.rpc_pool
+            .call(|rpc_url| {
+                let request = &request;
+                async move {
+                    let response: Value = self
+                        .http
+                        .post(&rpc_url)
+                        .json(request)
+                        .send()
+                        .await
+                        .map_err(|e| e.to_string())?
+                        .json()
+                        .await
+                        .map_err(|e| e.to_string())?;
+                    match response.get("error") {
+                        Some(error) => Err(format!("{}: {}", method, error)),
+                        None => Ok(response["result"].clone()),
+                    }
+                }
+            })
+            .await
+            .map_err(|e| e.to_string())
+    }
+}
+
+/// Convert a verbose `getblock` result, or a `block_added` notification, to a Neo block
+pub fn parse_block(block: &Value) -> NeoBlock {
+    let str_field = |value: &Value, key: &str| value[key].as_str().unwrap_or_default().to_string();
+    let u64_field = |value: &Value, key: &str| match &value[key] {
+        // Fees are given as strings of integers
+        Value::String(value) => value.parse().unwrap_or(0),
+        value => value.as_u64().unwrap_or(0),
+    };
+    let witnesses = |value: &Value| -> Vec<NeoWitness> {
+        value["witnesses"]
+            .as_array()
+            .map(|witnesses| {
+                witnesses
+                    .iter()
+                    .map(|witness| NeoWitness {
+                        invocation_script: str_field(witness, "invocation"),
+                        verification_script: str_field(witness, "verification"),
+                    })
+                    .collect()
+            })
+            .unwrap_or_default()
+    };
+
+    let header = NeoBlockHeader {
+        hash: str_field(block, "hash"),
+        version: u64_field(block, "version") as u32,
+        prev_block_hash: str_field(block, "previousblockhash"),
+        merkle_root: str_field(block, "merkleroot"),
+        time: u64_field(block, "time"),
+        nonce: u64::from_str_radix(&str_field(block, "nonce"), 16).unwrap_or(0),
+        height: u64_field(block, "index") as u32,
+        primary: u64_field(block, "primary") as u32,
+        next_consensus: str_field(block, "nextconsensus"),
+        witnesses: witnesses(block),
+    };
+
+    let txs = block["tx"]
+        .as_array()
+        .map(|txs| {
+            txs.iter()
+                .map(|tx| NeoTx {
+                    hash: str_field(tx, "hash"),
+                    size: u64_field(tx, "size") as u32,
+                    version: u64_field(tx, "version") as u32,
+                    nonce: u64_field(tx, "nonce") as u32,
+                    sysfee: u64_field(tx, "sysfee"),
+                    netfee: u64_field(tx, "netfee"),
+                    valid_until_block: u64_field(tx, "validuntilblock") as u32,
+                    script: str_field(tx, "script"),
+                    signers: vec![],
+                    attributes: vec![],
+                    witnesses: witnesses(tx),
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+
+    NeoBlock {
+        header: Some(header),
+        txs,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_block() {
+        let block = json!({
+            "hash": "0xd373",
+            "size": 697,
+            "version": 0,
+            "previousblockhash": "0x9c1c",
+            "merkleroot": "0x2ad4",
+            "time": 1627894840919u64,
+            "nonce": "BA3D5E6D15A2B3C1",
+            "index": 42,
+            "primary": 3,
+            "nextconsensus": "NSiVJYZej4XsxG5CUpdwn7VRQk8iiiDMPM",
+            "witnesses": [{"invocation": "DEA=", "verification": "EwwhAw=="}],
+            "tx": [{
+                "hash": "0x8a1f",
+                "size": 252,
+                "version": 0,
+                "nonce": 1969084232,
+                "sysfee": "997775",
+                "netfee": "1234620",
+                "validuntilblock": 5760,
+                "script": "CxAMFA==",
+                "witnesses": []
+            }]
+        });
+
+        let block = parse_block(&block);
+        let header = block.header.unwrap();
+        assert_eq!(header.height, 42);
+        assert_eq!(header.nonce, 0xBA3D5E6D15A2B3C1);
+        assert_eq!(header.witnesses[0].invocation_script, "DEA=");
+        assert_eq!(block.txs.len(), 1);
+        assert_eq!(block.txs[0].sysfee, 997775);
+        assert_eq!(block.txs[0].valid_until_block, 5760);
+    }
+}
@@ -0,0 +1,228 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+use std::collections::BTreeMap;
+use std::future::Future;
+
+use serde::{Deserialize, Serialize};
+
+/// Default number of recent blocks tracked to detect reorgs
+pub const DEFAULT_REORG_DEPTH: usize = 64;
+
+/// Block at a height
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReorgBlock {
+    pub height: u64,
+    pub hash: String,
+}
+
+/// Chain reorganization, emitted before the events of the canonical chain
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChainReorg {
+    /// Chain, e.g. `neo` or `ethereum`
+    pub chain: String,
+
+    /// First height whose block was replaced
+    pub fork_height: u64,
+
+    /// Previously delivered blocks that are no longer part of the chain
+    pub invalidated: Vec<ReorgBlock>,
+
+    /// Blocks of the canonical chain from the fork height on
+    pub canonical: Vec<ReorgBlock>,
+}
+
+/// Tracks the hashes of recent blocks per height and detects when the chain reorganizes
+#[derive(Clone, Debug)]
+pub struct ReorgDetector {
+    chain: String,
+    max_depth: usize,
+    hashes: BTreeMap<u64, String>,
+}
+
+impl ReorgDetector {
+    /// Create a new reorg detector tracking the last `max_depth` blocks of a chain
+    pub fn new(chain: impl Into<String>, max_depth: usize) -> Self {
+        Self {
+            chain: chain.into(),
+            max_depth: max_depth.max(1),
+            hashes: BTreeMap::new(),
+        }
+    }
+
+    /// Hash of the tracked block at a height
+    pub fn hash_at(&self, height: u64) -> Option<&str> {
+        self.hashes.get(&height).map(String::as_str)
+    }
+
+    /// Observe a new block, returning the reorg it reveals, if any.
+    ///
+    /// `canonical_hash` fetches the current hash at a height, and is only called when the block
+    /// does not extend the tracked chain, to find where the chains forked.
+    pub async fn observe<F, Fut, E>(
+        &mut self,
+        height: u64,
+        hash: &str,
+        parent_hash: &str,
+        mut canonical_hash: F,
+    ) -> Result<Option<ChainReorg>, E>
+    where
+        F: FnMut(u64) -> Fut,
+        Fut: Future<Output = Result<String, E>>,
+    {
+        if self.hash_at(height) == Some(hash) {
+            return Ok(None);
+        }
+
+        let (lowest, tip) = match (self.hashes.keys().next(), self.hashes.keys().last()) {
+            (Some(lowest), Some(tip)) => (*lowest, *tip),
+            _ => {
+                self.record(height, hash);
+                return Ok(None);
+            }
+        };
+
+        // The block extends the tracked chain
+        if height == tip + 1 && self.hash_at(tip) == Some(parent_hash) {
+            self.record(height, hash);
+            return Ok(None);
+        }
+
+        // Walk back until the tracked chain and the canonical chain agree
+        let mut canonical = Vec::new();
+        let mut fork_height = lowest;
+        let mut current = tip.min(height.saturating_sub(1));
+        while current >= lowest && height > 0 {
+            let canonical_at = if current + 1 == height {
+                parent_hash.to_string()
+            } else {
+                canonical_hash(current).await?
+            };
+            if self.hash_at(current) == Some(canonical_at.as_str()) {
+                fork_height = current + 1;
+                break;
+            }
+            canonical.push(ReorgBlock {
+                height: current,
+                hash: canonical_at,
+            });
+            if current == lowest {
+                break;
+            }
+            current -= 1;
+        }
+        canonical.reverse();
+
+        let invalidated: Vec<ReorgBlock> = self
+            .hashes
+            .range(fork_height..)
+            .map(|(height, hash)| ReorgBlock {
+                height: *height,
+                hash: hash.clone(),
+            })
+            .collect();
+
+        // A gap above the tip, nothing delivered was replaced
+        if invalidated.is_empty() {
+            self.record(height, hash);
+            return Ok(None);
+        }
+
+        self.hashes.split_off(&fork_height);
+        for block in &canonical {
+            self.hashes.insert(block.height, block.hash.clone());
+        }
+        self.record(height, hash);
+        canonical.push(ReorgBlock {
+            height,
+            hash: hash.to_string(),
+        });
+
+        Ok(Some(ChainReorg {
+            chain: self.chain.clone(),
+            fork_height,
+            invalidated,
+            canonical,
+        }))
+    }
+
+    /// Track a block delivered before a restart, so a reorg replacing it is still detected
+    pub fn resume_from(&mut self, height: u64, hash: &str) {
+        self.record(height, hash);
+    }
+
+    fn record(&mut self, height: u64, hash: &str) {
+        // Blocks above a new block are no longer part of the chain
+        self.hashes.split_off(&height);
+        self.hashes.insert(height, hash.to_string());
+        while self.hashes.len() > self.max_depth {
+            self.hashes.pop_first();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn observe(
+        detector: &mut ReorgDetector,
+        height: u64,
+        hash: &str,
+        parent: &str,
+        canonical: &[(u64, &str)],
+    ) -> Option<ChainReorg> {
+        detector
+            .observe(height, hash, parent, |h| {
+                let hash = canonical
+                    .iter()
+                    .find(|(height, _)| *height == h)
+                    .map(|(_, hash)| hash.to_string())
+                    .ok_or(());
+                async move { hash }
+            })
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_reorg_detector() {
+        let mut detector = ReorgDetector::new("ethereum", 8);
+        assert!(observe(&mut detector, 10, "a10", "a9", &[]).await.is_none());
+        assert!(observe(&mut detector, 11, "a11", "a10", &[])
+            .await
+            .is_none());
+        assert!(observe(&mut detector, 12, "a12", "a11", &[])
+            .await
+            .is_none());
+
+        // Blocks 11 and 12 were orphaned
+        let canonical = [(10, "a10"), (11, "b11"), (12, "b12")];
+        let reorg = observe(&mut detector, 13, "b13", "b12", &canonical)
+            .await
+            .unwrap();
+        assert_eq!(reorg.fork_height, 11);
+        assert_eq!(
+            reorg.invalidated,
+            vec![
+                ReorgBlock {
+                    height: 11,
+                    hash: "a11".to_string()
+                },
+                ReorgBlock {
+                    height: 12,
+                    hash: "a12".to_string()
+                },
+            ]
+        );
+        let heights: Vec<u64> = reorg.canonical.iter().map(|b| b.height).collect();
+        assert_eq!(heights, vec![11, 12, 13]);
+        assert_eq!(detector.hash_at(12), Some("b12"));
+
+        // A gap above the tip is not a reorg
+        let canonical = [(13, "b13")];
+        assert!(observe(&mut detector, 20, "b20", "b19", &canonical)
+            .await
+            .is_none());
+    }
+}
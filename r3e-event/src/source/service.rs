@@ -39,6 +39,11 @@ pub struct Func {
     pub version: u64,
     #[prost(string, tag = "2")]
     pub code: String,
+    #[prost(string, tag = "3")]
+    pub source_map: String,
+    /// JSON object of function metadata, e.g. `{"tee": true}`, empty if none
+    #[prost(string, tag = "4")]
+    pub metadata: String,
 }
 #[derive(serde::Serialize, serde::Deserialize)]
 #[allow(clippy::derive_partial_eq_without_eq)]
@@ -0,0 +1,203 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+use std::collections::{HashMap, VecDeque};
+use std::path::PathBuf;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::source::{event, Func, FuncError, Task, TaskError, TaskSource};
+
+/// Synthetic event delivered to a function
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SyntheticEvent {
+    /// Function receiving the event
+    pub fid: u64,
+
+    /// Delay before the event is delivered (in ms)
+    #[serde(default)]
+    pub delay_ms: u64,
+
+    /// Event, e.g. `{"neo_block": {...}}` or `{"ethereum_contract_event": {...}}`
+    pub event: event::Event,
+}
+
+/// Parse synthetic events, given either as a JSON array or as one JSON event per line.
+///
+/// A trailing line without a newline may still be being written and is not parsed.
+pub fn parse_synthetic_events(content: &str) -> Result<Vec<SyntheticEvent>, serde_json::Error> {
+    if content.trim_start().starts_with('[') {
+        return serde_json::from_str(content);
+    }
+
+    let complete = match content.rfind('\n') {
+        Some(end) => &content[..end],
+        None => "",
+    };
+    complete
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(serde_json::from_str)
+        .collect()
+}
+
+/// Task source delivering synthetic Neo and Ethereum events read from a file, so functions
+/// can be exercised without a chain
+pub struct SyntheticTaskSource {
+    /// Events file
+    path: PathBuf,
+
+    /// Interval between checks for appended events
+    poll_interval: Duration,
+
+    /// Whether to wait for events appended to the file once all were delivered
+    follow: bool,
+
+    /// Number of events of the file already delivered
+    delivered: usize,
+
+    /// Events read but not yet delivered
+    pending: VecDeque<SyntheticEvent>,
+
+    /// Function code by function ID
+    functions: HashMap<u64, Func>,
+}
+
+impl SyntheticTaskSource {
+    /// Create a new synthetic task source reading events from the given file
+    pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+        Self {
+            path: path.into(),
+            poll_interval: Duration::from_millis(500),
+            follow: false,
+            delivered: 0,
+            pending: VecDeque::new(),
+            functions: HashMap::new(),
+        }
+    }
+
+    /// Keep waiting for events appended to the file once all were delivered
+    pub fn with_follow(mut self, follow: bool) -> Self {
+        self.follow = follow;
+        self
+    }
+
+    /// Set the interval between checks for appended events
+    pub fn with_poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = poll_interval;
+        self
+    }
+
+    /// Serve the given code for a function
+    pub fn with_function(mut self, fid: u64, code: String) -> Self {
+        self.functions.insert(
+            fid,
+            Func {
+                version: 1,
+                code,
+                source_map: String::new(),
+                metadata: String::new(),
+            },
+        );
+        self
+    }
+
+    /// Set the metadata of a function served with `with_function`
+    pub fn with_function_metadata(mut self, fid: u64, metadata: &serde_json::Value) -> Self {
+        if let Some(func) = self.functions.get_mut(&fid) {
+            func.metadata = metadata.to_string();
+        }
+        self
+    }
+
+    fn read_events(&mut self) -> Result<(), TaskError> {
+        let content = match std::fs::read_to_string(&self.path) {
+            Ok(content) => content,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+            Err(err) => {
+                return Err(TaskError::Error(format!(
+                    "read events {}: {}",
+                    self.path.display(),
+                    err
+                )))
+            }
+        };
+
+        let events = parse_synthetic_events(&content).map_err(|err| {
+            TaskError::EventError(format!("parse events {}: {}", self.path.display(), err))
+        })?;
+        if events.len() > self.delivered {
+            self.pending.extend(events.into_iter().skip(self.delivered));
+        }
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait]
+impl TaskSource for SyntheticTaskSource {
+    async fn acquire_task(&mut self, uid: u64, _fid_hint: u64) -> Result<Task, TaskError> {
+        loop {
+            if let Some(synthetic) = self.pending.pop_front() {
+                self.delivered += 1;
+                if synthetic.delay_ms > 0 {
+                    tokio::time::sleep(Duration::from_millis(synthetic.delay_ms)).await;
+                }
+                return Ok(Task::new(uid, synthetic.fid, synthetic.event));
+            }
+
+            self.read_events()?;
+            if self.pending.is_empty() {
+                if !self.follow {
+                    return Err(TaskError::NoMoreTask(uid));
+                }
+                tokio::time::sleep(self.poll_interval).await;
+            }
+        }
+    }
+
+    async fn try_acquire_task(
+        &mut self,
+        uid: u64,
+        _fid_hint: u64,
+    ) -> Result<Option<Task>, TaskError> {
+        // Delayed events are not ready yet
+        if !matches!(self.pending.front(), Some(synthetic) if synthetic.delay_ms == 0) {
+            return Ok(None);
+        }
+
+        let synthetic = self.pending.pop_front().expect("synthetic: pending event");
+        self.delivered += 1;
+        Ok(Some(Task::new(uid, synthetic.fid, synthetic.event)))
+    }
+
+    async fn acquire_fn(&mut self, uid: u64, fid: u64) -> Result<Func, FuncError> {
+        self.functions
+            .get(&fid)
+            .cloned()
+            .ok_or(FuncError::NoSuchFunc(uid, fid))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_synthetic_events() {
+        let array = r#"[
+            {"fid": 1, "event": {"ethereum_block": {"number": "0x1"}}},
+            {"fid": 2, "delay_ms": 100, "event": {"mock": {"message": "hello"}}}
+        ]"#;
+        let events = parse_synthetic_events(array).unwrap();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[1].fid, 2);
+        assert_eq!(events[1].delay_ms, 100);
+
+        // The last line is still being written
+        let lines = "{\"fid\": 1, \"event\": {\"ethereum_block\": {}}}\n\n{\"fid\": 2, \"ev";
+        let events = parse_synthetic_events(lines).unwrap();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].fid, 1);
+    }
+}
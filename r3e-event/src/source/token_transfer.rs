@@ -0,0 +1,540 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+//! Standardized NEP-17 and NEP-11 transfer decoding.
+//!
+//! Both token standards emit a `Transfer` notification: NEP-17 with `(from, to, amount)`
+//! and NEP-11 with `(from, to, amount, tokenId)`. The Neo task source decodes them into
+//! [`TokenTransfer`]s, so functions receive typed transfers instead of raw stack items.
+//!
+//! Recognition is by notification shape, as the contract manifest is not available to the
+//! source. Triggers should pin the `token` when a non-token contract may emit a look-alike
+//! `Transfer` notification.
+
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::source::event::Event;
+use crate::source::NeoContractNotification;
+use crate::trigger::TriggerError;
+
+/// Notification name of token transfers
+pub const TRANSFER_EVENT: &str = "Transfer";
+
+/// Maximum length of a NEP-11 token ID
+const MAX_TOKEN_ID_LEN: usize = 64;
+
+/// Token standard of a transfer
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TokenStandard {
+    /// Fungible token
+    Nep17,
+    /// Non-fungible token
+    Nep11,
+}
+
+/// Decoded token transfer
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TokenTransfer {
+    /// Token standard
+    pub standard: TokenStandard,
+
+    /// Token contract hash, `0x` prefixed big endian
+    pub token: String,
+
+    /// Sender script hash, `None` when tokens are minted
+    pub from: Option<String>,
+
+    /// Recipient script hash, `None` when tokens are burned
+    pub to: Option<String>,
+
+    /// Amount in the token's smallest unit, as a decimal string since it may exceed 2^53
+    pub amount: String,
+
+    /// Hex encoded token ID (NEP-11 only)
+    pub token_id: Option<String>,
+
+    /// Index of the notification in the transaction
+    pub notification_index: u32,
+}
+
+impl TokenTransfer {
+    /// Amount as an integer
+    pub fn amount_value(&self) -> u128 {
+        self.amount.parse().unwrap_or_default()
+    }
+
+    /// Decode a notification as returned by `getapplicationlog`.
+    ///
+    /// Returns `None` if the notification is not a well-formed NEP-17 or NEP-11 transfer.
+    pub fn decode(notification: &Value, notification_index: u32) -> Option<Self> {
+        let name = notification
+            .get("eventname")
+            .or_else(|| notification.get("eventName"))
+            .and_then(Value::as_str);
+        if name != Some(TRANSFER_EVENT) {
+            return None;
+        }
+
+        let token = notification.get("contract").and_then(Value::as_str)?;
+        let token = normalize_hash(token)?;
+        let items = notification
+            .get("state")
+            .and_then(|state| state.get("value"))
+            .and_then(Value::as_array)?;
+
+        let (standard, token_id) = match items.len() {
+            3 => (TokenStandard::Nep17, None),
+            4 => {
+                let token_id = stack_bytes(&items[3])?;
+                if token_id.len() > MAX_TOKEN_ID_LEN {
+                    return None;
+                }
+                (TokenStandard::Nep11, Some(hex::encode(token_id)))
+            }
+            _ => return None,
+        };
+
+        let amount = stack_integer(&items[2])?;
+        if amount < 0 {
+            return None;
+        }
+
+        Some(Self {
+            standard,
+            token,
+            from: stack_hash160(&items[0])?,
+            to: stack_hash160(&items[1])?,
+            amount: amount.to_string(),
+            token_id,
+            notification_index,
+        })
+    }
+}
+
+/// Token transfers of a Neo transaction
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NeoTokenTransfers {
+    /// Transaction hash
+    pub tx_hash: String,
+
+    /// Transfers, in notification order
+    pub transfers: Vec<TokenTransfer>,
+}
+
+impl NeoTokenTransfers {
+    /// Decode the transfers of a contract notification event, skipping other notifications
+    pub fn decode(notification: &NeoContractNotification) -> Self {
+        let notifications: Vec<Value> =
+            serde_json::from_str(&notification.notifications).unwrap_or_default();
+        Self {
+            tx_hash: notification.tx_hash.clone(),
+            transfers: notifications
+                .iter()
+                .enumerate()
+                .filter_map(|(index, n)| TokenTransfer::decode(n, index as u32))
+                .collect(),
+        }
+    }
+}
+
+/// `0x` prefixed lowercase script hash, or `None` if `hash` is not a 20-byte hex string
+fn normalize_hash(hash: &str) -> Option<String> {
+    let hash = hash.strip_prefix("0x").unwrap_or(hash);
+    let bytes = hex::decode(hash).ok()?;
+    (bytes.len() == 20).then(|| format!("0x{}", hex::encode(bytes)))
+}
+
+fn stack_bytes(item: &Value) -> Option<Vec<u8>> {
+    match item.get("type").and_then(Value::as_str) {
+        Some("ByteString") | Some("Buffer") => {
+            let value = item.get("value").and_then(Value::as_str)?;
+            base64::engine::general_purpose::STANDARD.decode(value).ok()
+        }
+        _ => None,
+    }
+}
+
+fn stack_integer(item: &Value) -> Option<i128> {
+    if item.get("type").and_then(Value::as_str) != Some("Integer") {
+        return None;
+    }
+    item.get("value").and_then(Value::as_str)?.parse().ok()
+}
+
+/// Decode a Hash160 stack item; `Any` (null) decodes to `Some(None)` for mints and burns.
+///
+/// Script hashes are pushed little endian, but displayed big endian.
+fn stack_hash160(item: &Value) -> Option<Option<String>> {
+    if item.get("type").and_then(Value::as_str) == Some("Any") {
+        return Some(None);
+    }
+
+    let mut bytes = stack_bytes(item)?;
+    if bytes.len() != 20 {
+        return None;
+    }
+    bytes.reverse();
+    Some(Some(format!("0x{}", hex::encode(bytes))))
+}
+
+/// Trigger firing on token transfers.
+///
+/// Every field is optional; an empty trigger fires on all transfers.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TokenTransferTrigger {
+    /// Token standard
+    #[serde(default)]
+    pub standard: Option<TokenStandard>,
+
+    /// Token contract hash
+    #[serde(default)]
+    pub token: Option<String>,
+
+    /// Sender script hash
+    #[serde(default)]
+    pub from: Option<String>,
+
+    /// Recipient script hash
+    #[serde(default)]
+    pub to: Option<String>,
+
+    /// Minimum amount, as a decimal string
+    #[serde(default)]
+    pub min_amount: Option<String>,
+
+    /// Hex encoded NEP-11 token ID
+    #[serde(default)]
+    pub token_id: Option<String>,
+}
+
+impl TokenTransferTrigger {
+    /// Create a trigger firing on all transfers
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only fire on transfers of a token standard
+    pub fn with_standard(mut self, standard: TokenStandard) -> Self {
+        self.standard = Some(standard);
+        self
+    }
+
+    /// Only fire on transfers of a token
+    pub fn with_token(mut self, token: &str) -> Self {
+        self.token = Some(token.to_string());
+        self
+    }
+
+    /// Only fire on transfers from a sender
+    pub fn with_from(mut self, from: &str) -> Self {
+        self.from = Some(from.to_string());
+        self
+    }
+
+    /// Only fire on transfers to a recipient
+    pub fn with_to(mut self, to: &str) -> Self {
+        self.to = Some(to.to_string());
+        self
+    }
+
+    /// Only fire on transfers of at least `min_amount`
+    pub fn with_min_amount(mut self, min_amount: u128) -> Self {
+        self.min_amount = Some(min_amount.to_string());
+        self
+    }
+
+    /// Only fire on transfers of a NEP-11 token, given its hex encoded ID
+    pub fn with_token_id(mut self, token_id: &str) -> Self {
+        self.token_id = Some(token_id.to_string());
+        self
+    }
+
+    /// Compile the trigger into a filter
+    pub fn compile(&self) -> Result<TokenTransferFilter, TriggerError> {
+        let hash = |field: &str, value: &Option<String>| {
+            value
+                .as_deref()
+                .map(|value| {
+                    normalize_hash(value).ok_or_else(|| {
+                        TriggerError::InvalidParameters(format!(
+                            "invalid transfer trigger {}: {}",
+                            field, value
+                        ))
+                    })
+                })
+                .transpose()
+        };
+
+        let min_amount = self
+            .min_amount
+            .as_deref()
+            .map(|amount| {
+                amount.parse::<u128>().map_err(|_| {
+                    TriggerError::InvalidParameters(format!(
+                        "invalid transfer trigger min_amount: {}",
+                        amount
+                    ))
+                })
+            })
+            .transpose()?;
+
+        let token_id = self
+            .token_id
+            .as_deref()
+            .map(|token_id| {
+                hex::decode(token_id.trim_start_matches("0x"))
+                    .ok()
+                    .filter(|bytes| bytes.len() <= MAX_TOKEN_ID_LEN)
+                    .map(hex::encode)
+                    .ok_or_else(|| {
+                        TriggerError::InvalidParameters(format!(
+                            "invalid transfer trigger token_id: {}",
+                            token_id
+                        ))
+                    })
+            })
+            .transpose()?;
+
+        Ok(TokenTransferFilter {
+            standard: self.standard,
+            token: hash("token", &self.token)?,
+            from: hash("from", &self.from)?,
+            to: hash("to", &self.to)?,
+            min_amount,
+            token_id,
+        })
+    }
+}
+
+/// Compiled token transfer trigger
+#[derive(Debug, Clone)]
+pub struct TokenTransferFilter {
+    standard: Option<TokenStandard>,
+    token: Option<String>,
+    from: Option<String>,
+    to: Option<String>,
+    min_amount: Option<u128>,
+    token_id: Option<String>,
+}
+
+impl TokenTransferFilter {
+    /// Whether a transfer matches
+    pub fn matches(&self, transfer: &TokenTransfer) -> bool {
+        self.standard.map_or(true, |s| s == transfer.standard)
+            && self.token.as_ref().map_or(true, |t| *t == transfer.token)
+            && self
+                .from
+                .as_ref()
+                .map_or(true, |f| transfer.from.as_ref() == Some(f))
+            && self
+                .to
+                .as_ref()
+                .map_or(true, |t| transfer.to.as_ref() == Some(t))
+            && self
+                .min_amount
+                .map_or(true, |m| transfer.amount_value() >= m)
+            && self
+                .token_id
+                .as_ref()
+                .map_or(true, |id| transfer.token_id.as_ref() == Some(id))
+    }
+}
+
+/// Set of compiled transfer filters applied by the Neo task source
+#[derive(Debug, Clone, Default)]
+pub struct TokenTransferFilterSet {
+    filters: Vec<TokenTransferFilter>,
+}
+
+impl TokenTransferFilterSet {
+    /// Compile a set of triggers
+    pub fn compile(triggers: &[TokenTransferTrigger]) -> Result<Self, TriggerError> {
+        let filters = triggers
+            .iter()
+            .map(TokenTransferTrigger::compile)
+            .collect::<Result<_, _>>()?;
+        Ok(Self { filters })
+    }
+
+    /// Whether the set has no filters
+    pub fn is_empty(&self) -> bool {
+        self.filters.is_empty()
+    }
+
+    /// Decode the transfers of a contract notification event, keeping the matched ones.
+    ///
+    /// Returns `Event::None` if no transfer matches. Other events, and all events if the
+    /// set is empty, are returned unchanged.
+    pub fn apply(&self, event: Event) -> Event {
+        if self.filters.is_empty() {
+            return event;
+        }
+
+        match event {
+            Event::NeoContractNotification(notification) => {
+                let mut transfers = NeoTokenTransfers::decode(&notification);
+                transfers
+                    .transfers
+                    .retain(|t| self.filters.iter().any(|f| f.matches(t)));
+                if transfers.transfers.is_empty() {
+                    return Event::None;
+                }
+
+                Event::NeoTokenTransfers(transfers)
+            }
+            event => event,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const GAS: &str = "0xd2a4cff31913016155e38e474a2c06d08be276cf";
+
+    fn hash_item(hash: &str) -> Value {
+        let mut bytes = hex::decode(hash.trim_start_matches("0x")).unwrap();
+        bytes.reverse();
+        json!({
+            "type": "ByteString",
+            "value": base64::engine::general_purpose::STANDARD.encode(bytes),
+        })
+    }
+
+    fn transfer(from: Value, to: Value, amount: &str, extra: Option<Value>) -> Value {
+        let mut items = vec![from, to, json!({ "type": "Integer", "value": amount })];
+        items.extend(extra);
+        json!({
+            "contract": GAS,
+            "eventname": "Transfer",
+            "state": { "type": "Array", "value": items },
+        })
+    }
+
+    #[test]
+    fn test_decode_nep17() {
+        let alice = "0x1111111111111111111111111111111111111111";
+        let notification = transfer(
+            hash_item(alice),
+            json!({ "type": "Any" }),
+            "100000000",
+            None,
+        );
+
+        let transfer = TokenTransfer::decode(&notification, 2).unwrap();
+        assert_eq!(transfer.standard, TokenStandard::Nep17);
+        assert_eq!(transfer.token, GAS);
+        assert_eq!(transfer.from.as_deref(), Some(alice));
+        assert_eq!(transfer.to, None);
+        assert_eq!(transfer.amount_value(), 100_000_000);
+        assert_eq!(transfer.notification_index, 2);
+    }
+
+    #[test]
+    fn test_decode_nep11() {
+        let token_id = json!({ "type": "ByteString", "value": "AQI=" });
+        let notification = transfer(
+            json!({ "type": "Any" }),
+            hash_item("0x2222222222222222222222222222222222222222"),
+            "1",
+            Some(token_id),
+        );
+
+        let transfer = TokenTransfer::decode(&notification, 0).unwrap();
+        assert_eq!(transfer.standard, TokenStandard::Nep11);
+        assert_eq!(transfer.from, None);
+        assert_eq!(transfer.token_id.as_deref(), Some("0102"));
+    }
+
+    #[test]
+    fn test_decode_rejects_malformed() {
+        let any = json!({ "type": "Any" });
+        // Wrong name
+        let mut notification = transfer(any.clone(), any.clone(), "1", None);
+        notification["eventname"] = json!("Deposit");
+        assert!(TokenTransfer::decode(&notification, 0).is_none());
+
+        // Negative amount
+        let notification = transfer(any.clone(), any.clone(), "-1", None);
+        assert!(TokenTransfer::decode(&notification, 0).is_none());
+
+        // Short script hash
+        let short = json!({ "type": "ByteString", "value": "AQI=" });
+        let notification = transfer(short, any, "1", None);
+        assert!(TokenTransfer::decode(&notification, 0).is_none());
+    }
+
+    #[test]
+    fn test_filter_set_apply() {
+        let alice = "0x1111111111111111111111111111111111111111";
+        let bob = "0x2222222222222222222222222222222222222222";
+        let notifications = json!([
+            transfer(hash_item(alice), hash_item(bob), "5", None),
+            transfer(hash_item(bob), hash_item(alice), "50", None),
+            { "contract": GAS, "eventname": "Other", "state": { "type": "Array", "value": [] } },
+        ]);
+        let event = Event::NeoContractNotification(NeoContractNotification {
+            tx_hash: "0xabc".to_string(),
+            notifications: notifications.to_string(),
+        });
+
+        let triggers = [TokenTransferTrigger::new()
+            .with_token(GAS)
+            .with_to(alice)
+            .with_min_amount(10)];
+        let set = TokenTransferFilterSet::compile(&triggers).unwrap();
+        let Event::NeoTokenTransfers(transfers) = set.apply(event.clone()) else {
+            panic!("expected token transfers");
+        };
+        assert_eq!(transfers.tx_hash, "0xabc");
+        assert_eq!(transfers.transfers.len(), 1);
+        assert_eq!(transfers.transfers[0].notification_index, 1);
+
+        let triggers = [TokenTransferTrigger::new().with_min_amount(100)];
+        let set = TokenTransferFilterSet::compile(&triggers).unwrap();
+        assert_eq!(set.apply(event), Event::None);
+
+        let triggers = [TokenTransferTrigger::new().with_from("not a hash")];
+        assert!(TokenTransferFilterSet::compile(&triggers).is_err());
+    }
+
+    #[test]
+    fn test_filter_token_id() {
+        let bob = hash_item("0x2222222222222222222222222222222222222222");
+        let mint = |token_id: &str| {
+            transfer(
+                json!({ "type": "Any" }),
+                bob.clone(),
+                "1",
+                Some(json!({ "type": "ByteString", "value": token_id })),
+            )
+        };
+        let event = Event::NeoContractNotification(NeoContractNotification {
+            tx_hash: "0xabc".to_string(),
+            notifications: json!([mint("AQI="), mint("Aw==")]).to_string(),
+        });
+
+        // Token IDs match regardless of case and prefix
+        let triggers = [TokenTransferTrigger::new()
+            .with_standard(TokenStandard::Nep11)
+            .with_token_id("0x0102")];
+        let set = TokenTransferFilterSet::compile(&triggers).unwrap();
+        let Event::NeoTokenTransfers(transfers) = set.apply(event.clone()) else {
+            panic!("expected token transfers");
+        };
+        assert_eq!(transfers.transfers.len(), 1);
+        assert_eq!(transfers.transfers[0].token_id.as_deref(), Some("0102"));
+
+        let triggers = [TokenTransferTrigger::new().with_token_id("04")];
+        let set = TokenTransferFilterSet::compile(&triggers).unwrap();
+        assert_eq!(set.apply(event), Event::None);
+
+        let triggers = [TokenTransferTrigger::new().with_token_id("not hex")];
+        assert!(TokenTransferFilterSet::compile(&triggers).is_err());
+    }
+}
@@ -0,0 +1,237 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+use std::collections::{HashSet, VecDeque};
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use r3e_store::{normalize_contract, IndexedEvent};
+use tokio::sync::Mutex;
+
+use crate::trigger::types::TriggerError;
+
+/// Default retention window of seen events
+pub const DEFAULT_DEDUP_RETENTION: Duration = Duration::from_secs(60 * 60);
+
+/// Identity of a chain event, the same across reconnects and backfills
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct EventKey {
+    /// Event source, e.g. the network
+    pub source: String,
+
+    /// Hash of the block containing the event
+    pub block_hash: String,
+
+    /// Hash of the transaction emitting the event, empty for block events
+    pub tx_hash: String,
+
+    /// Index of the event within the transaction
+    pub event_index: u64,
+}
+
+impl EventKey {
+    /// Identify an event from its data, None if it carries no block or transaction hash.
+    ///
+    /// Accepts `source` or `network`, `block_hash`, `tx_hash` or `transaction_hash`, and
+    /// `event_index` or `log_index`.
+    pub fn from_event_data(event_data: &serde_json::Value) -> Option<Self> {
+        let str_field = |keys: &[&str]| {
+            keys.iter()
+                .find_map(|key| event_data.get(*key).and_then(|v| v.as_str()))
+                .unwrap_or_default()
+                .to_string()
+        };
+
+        let key = Self {
+            source: str_field(&["source", "network"]),
+            block_hash: str_field(&["block_hash"]),
+            tx_hash: str_field(&["tx_hash", "transaction_hash"]),
+            event_index: ["event_index", "log_index"]
+                .iter()
+                .find_map(|key| event_data.get(*key).and_then(|v| v.as_u64()))
+                .unwrap_or(0),
+        };
+        if key.block_hash.is_empty() && key.tx_hash.is_empty() {
+            return None;
+        }
+        Some(key)
+    }
+}
+
+/// Event to index from its data, None if it carries no contract or is not identified.
+///
+/// Accepts `contract`, `contract_hash` or `address`, `event_name` or `name`, and the block
+/// time as `timestamp_ms` or `timestamp` in seconds, the time of indexing without one.
+pub fn indexed_event(event_data: &serde_json::Value) -> Option<IndexedEvent> {
+    let key = EventKey::from_event_data(event_data)?;
+    let str_field = |keys: &[&str]| {
+        keys.iter()
+            .find_map(|key| event_data.get(*key).and_then(|v| v.as_str()))
+            .map(str::to_string)
+    };
+
+    let contract = normalize_contract(&str_field(&["contract", "contract_hash", "address"])?);
+    if contract.is_empty() {
+        return None;
+    }
+    let timestamp_ms = event_data
+        .get("timestamp_ms")
+        .and_then(|v| v.as_u64())
+        .or_else(|| {
+            event_data
+                .get("timestamp")
+                .and_then(|v| v.as_u64())
+                .map(|secs| secs * 1000)
+        })
+        .unwrap_or_else(|| chrono::Utc::now().timestamp_millis() as u64);
+
+    Some(IndexedEvent {
+        source: key.source,
+        contract,
+        event_name: str_field(&["event_name", "name"]),
+        block_hash: key.block_hash,
+        tx_hash: key.tx_hash,
+        event_index: key.event_index,
+        timestamp_ms,
+        data: event_data.clone(),
+    })
+}
+
+impl fmt::Display for EventKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}:{}:{}",
+            self.source, self.block_hash, self.tx_hash, self.event_index
+        )
+    }
+}
+
+/// Store of events already fired, so a function fires at most once per unique event
+#[async_trait]
+pub trait DedupStore: Send + Sync {
+    /// Record that `function_id` fired for `event`.
+    /// Returns false if it already fired for it within the retention window.
+    async fn first_seen(&self, function_id: &str, event: &EventKey) -> Result<bool, TriggerError>;
+}
+
+#[derive(Default)]
+struct SeenEvents {
+    keys: HashSet<(String, EventKey)>,
+
+    /// Keys in the order they were seen, to expire the oldest first
+    order: VecDeque<(Instant, (String, EventKey))>,
+}
+
+/// In-memory dedup store forgetting events after a sliding retention window
+pub struct InMemoryDedupStore {
+    retention: Duration,
+    seen: Mutex<SeenEvents>,
+}
+
+impl InMemoryDedupStore {
+    /// Create a new in-memory dedup store remembering events for `retention`
+    pub fn new(retention: Duration) -> Self {
+        Self {
+            retention,
+            seen: Mutex::new(SeenEvents::default()),
+        }
+    }
+
+    /// Number of remembered events
+    pub async fn len(&self) -> usize {
+        self.seen.lock().await.keys.len()
+    }
+
+    /// Whether no events are remembered
+    pub async fn is_empty(&self) -> bool {
+        self.seen.lock().await.keys.is_empty()
+    }
+}
+
+impl Default for InMemoryDedupStore {
+    fn default() -> Self {
+        Self::new(DEFAULT_DEDUP_RETENTION)
+    }
+}
+
+#[async_trait]
+impl DedupStore for InMemoryDedupStore {
+    async fn first_seen(&self, function_id: &str, event: &EventKey) -> Result<bool, TriggerError> {
+        let now = Instant::now();
+        let mut seen = self.seen.lock().await;
+
+        // Forget the events older than the retention window
+        while let Some((at, _)) = seen.order.front() {
+            if now.duration_since(*at) < self.retention {
+                break;
+            }
+            if let Some((_, key)) = seen.order.pop_front() {
+                seen.keys.remove(&key);
+            }
+        }
+
+        let key = (function_id.to_string(), event.clone());
+        if !seen.keys.insert(key.clone()) {
+            return Ok(false);
+        }
+        seen.order.push_back((now, key));
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_event_key() {
+        let key = EventKey::from_event_data(&json!({
+            "network": "neo_n3",
+            "block_hash": "0xb1",
+            "transaction_hash": "0xt1",
+            "log_index": 2,
+        }))
+        .unwrap();
+        assert_eq!(key.to_string(), "neo_n3:0xb1:0xt1:2");
+
+        assert!(EventKey::from_event_data(&json!({"timestamp": 1})).is_none());
+    }
+
+    #[test]
+    fn test_indexed_event() {
+        let event = indexed_event(&json!({
+            "network": "neo_n3",
+            "contract_hash": "0xD2A4CFF31913016155E38E474A2C06D08BE276CF",
+            "event_name": "Transfer",
+            "block_hash": "0xb1",
+            "tx_hash": "0xt1",
+            "timestamp": 1_700_000_000,
+        }))
+        .unwrap();
+        assert_eq!(event.contract, "d2a4cff31913016155e38e474a2c06d08be276cf");
+        assert_eq!(event.event_name.as_deref(), Some("Transfer"));
+        assert_eq!(event.timestamp_ms, 1_700_000_000_000);
+
+        // Block events are not indexed
+        assert!(indexed_event(&json!({"block_hash": "0xb1", "timestamp": 1})).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_first_seen() {
+        let store = InMemoryDedupStore::new(Duration::from_millis(50));
+        let event =
+            EventKey::from_event_data(&json!({"block_hash": "0xb1", "tx_hash": "0xt1"})).unwrap();
+
+        assert!(store.first_seen("f1", &event).await.unwrap());
+        assert!(!store.first_seen("f1", &event).await.unwrap());
+        assert!(store.first_seen("f2", &event).await.unwrap());
+
+        // Fires again once the event left the retention window
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        assert!(store.first_seen("f1", &event).await.unwrap());
+        assert_eq!(store.len().await, 1);
+    }
+}
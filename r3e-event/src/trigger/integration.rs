@@ -6,6 +6,7 @@ use std::time::{Duration, Instant};
 
 use async_trait::async_trait;
 use log::{debug, error, info, warn};
+use r3e_store::EventStore;
 use serde_json::json;
 use tokio::sync::RwLock;
 use uuid::Uuid;
@@ -13,6 +14,7 @@ use uuid::Uuid;
 use crate::trigger::callback::{
     TriggerCallbackResult, TriggerCallbackStatus, TriggerCallbackStorage,
 };
+use crate::trigger::dedup::{indexed_event, DedupStore, EventKey, InMemoryDedupStore};
 use crate::trigger::function_service::FunctionService;
 use crate::trigger::types::{TriggerCondition, TriggerError, TriggerSource};
 
@@ -29,6 +31,12 @@ pub struct TriggerServiceIntegration {
 
     /// Maximum execution time
     max_execution_time: Duration,
+
+    /// Events functions already fired for
+    dedup_store: Arc<dyn DedupStore>,
+
+    /// Index of the events processed, for replays
+    event_store: Option<Arc<dyn EventStore>>,
 }
 
 impl TriggerServiceIntegration {
@@ -42,6 +50,8 @@ impl TriggerServiceIntegration {
             callback_storage,
             function_service,
             max_execution_time: Duration::from_secs(30),
+            dedup_store: Arc::new(InMemoryDedupStore::default()),
+            event_store: None,
         }
     }
 
@@ -51,6 +61,18 @@ impl TriggerServiceIntegration {
         self
     }
 
+    /// Set the store of events functions already fired for
+    pub fn with_dedup_store(mut self, dedup_store: Arc<dyn DedupStore>) -> Self {
+        self.dedup_store = dedup_store;
+        self
+    }
+
+    /// Index the contract events processed, so they can be replayed later
+    pub fn with_event_store(mut self, event_store: Arc<dyn EventStore>) -> Self {
+        self.event_store = Some(event_store);
+        self
+    }
+
     /// Register a trigger
     pub async fn register_trigger(
         &self,
@@ -114,7 +136,10 @@ impl TriggerServiceIntegration {
         Ok(function_triggers)
     }
 
-    /// Process an event and execute callbacks for matching triggers
+    /// Process an event and execute callbacks for matching triggers.
+    ///
+    /// A function fires at most once per chain event, even if the event is processed again
+    /// after a reconnect or backfill.
     pub async fn process_event(
         &self,
         event_data: &serde_json::Value,
@@ -122,6 +147,16 @@ impl TriggerServiceIntegration {
     ) -> Result<Vec<String>, TriggerError> {
         let triggers = self.triggers.read().await;
         let mut callback_ids = Vec::new();
+        let event_key = EventKey::from_event_data(event_data);
+
+        // Indexed whether or not a trigger matches, a function registered later can replay it
+        if let Some(event_store) = &self.event_store {
+            if let Some(event) = indexed_event(event_data) {
+                if let Err(e) = event_store.append(vec![event]).await {
+                    warn!("Failed to index event: {}", e);
+                }
+            }
+        }
 
         // Iterate through all triggers and evaluate them against the event data
         for (trigger_id, (user_id, function_id, condition)) in triggers.iter() {
@@ -130,6 +165,24 @@ impl TriggerServiceIntegration {
                 .await
             {
                 Ok(true) => {
+                    // Skip the functions that already fired for this event
+                    if let Some(event_key) = &event_key {
+                        match self.dedup_store.first_seen(function_id, event_key).await {
+                            Ok(true) => {}
+                            Ok(false) => {
+                                debug!(
+                                    "Skipping duplicate event {} for trigger {}",
+                                    event_key, trigger_id
+                                );
+                                continue;
+                            }
+                            Err(e) => {
+                                error!("Failed to deduplicate event {}: {}", event_key, e);
+                                continue;
+                            }
+                        }
+                    }
+
                     // Trigger condition matched, execute callback
                     match self
                         .execute_callback(user_id, function_id, trigger_id, event_data)
@@ -2,6 +2,7 @@
 // All Rights Reserved
 
 pub mod callback;
+pub mod dedup;
 pub mod evaluator;
 pub mod function_service;
 pub mod integration;
@@ -11,6 +12,7 @@ pub mod trigger_service;
 pub mod types;
 
 pub use callback::*;
+pub use dedup::*;
 pub use evaluator::*;
 pub use function_service::*;
 pub use integration::*;
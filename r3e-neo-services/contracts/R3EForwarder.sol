@@ -0,0 +1,134 @@
+// SPDX-License-Identifier: MIT
+// Copyright @ 2023 - 2024, R3E Network
+
+pragma solidity ^0.8.20;
+
+/// @title R3E trusted forwarder
+/// @notice Executes meta transactions signed by users with EIP-712 and relayed by the R3E
+/// relayers. Calls append the signer to the calldata, as ERC-2771 recipients expect.
+contract R3EForwarder {
+    struct MetaTransaction {
+        address from;
+        address to;
+        bytes data;
+        uint256 nonce;
+        uint256 deadline;
+        string feeModel;
+        uint256 feeAmount;
+    }
+
+    bytes32 private constant DOMAIN_TYPEHASH = keccak256(
+        "EIP712Domain(string name,string version,uint256 chainId,address verifyingContract,bytes32 salt)"
+    );
+    bytes32 private constant META_TX_TYPEHASH = keccak256(
+        "MetaTransaction(address from,address to,bytes data,uint256 nonce,uint256 deadline,string fee_model,uint256 fee_amount)"
+    );
+    bytes32 private constant NAME_HASH = keccak256("R3E Meta Transaction");
+    bytes32 private constant VERSION_HASH = keccak256("1");
+
+    // Upper bound of the s value of non-malleable signatures
+    uint256 private constant MAX_S =
+        0x7FFFFFFFFFFFFFFFFFFFFFFFFFFFFFFF5D576E7357A4501DDFE92F46681B20A0;
+
+    address public owner;
+    mapping(address => bool) public trustedRelayers;
+    mapping(address => uint256) private _nonces;
+
+    event Executed(address indexed from, address indexed to, uint256 nonce, bool success, bytes result);
+    event RelayerUpdated(address indexed relayer, bool trusted);
+    event OwnershipTransferred(address indexed previousOwner, address indexed newOwner);
+
+    modifier onlyOwner() {
+        require(msg.sender == owner, "R3EForwarder: caller is not the owner");
+        _;
+    }
+
+    constructor(address[] memory relayers) {
+        owner = msg.sender;
+        emit OwnershipTransferred(address(0), msg.sender);
+        for (uint256 i = 0; i < relayers.length; i++) {
+            _setRelayer(relayers[i], true);
+        }
+    }
+
+    /// @notice Last nonce executed for a signer, the next meta transaction must use it plus one
+    function getNonce(address from) external view returns (uint256) {
+        return _nonces[from];
+    }
+
+    function domainSeparator() public view returns (bytes32) {
+        return keccak256(
+            abi.encode(DOMAIN_TYPEHASH, NAME_HASH, VERSION_HASH, block.chainid, address(this), bytes32(0))
+        );
+    }
+
+    /// @notice EIP-712 digest a meta transaction is signed over
+    function hashMetaTransaction(MetaTransaction calldata req) public view returns (bytes32) {
+        bytes32 structHash = keccak256(
+            abi.encode(
+                META_TX_TYPEHASH,
+                req.from,
+                req.to,
+                keccak256(req.data),
+                req.nonce,
+                req.deadline,
+                keccak256(bytes(req.feeModel)),
+                req.feeAmount
+            )
+        );
+        return keccak256(abi.encodePacked("\x19\x01", domainSeparator(), structHash));
+    }
+
+    /// @notice Whether a meta transaction is signed by its sender, uses its next nonce and has
+    /// not expired
+    function verify(MetaTransaction calldata req, bytes calldata signature) public view returns (bool) {
+        if (req.from == address(0) || req.nonce != _nonces[req.from] + 1 || block.timestamp > req.deadline) {
+            return false;
+        }
+        return _recover(hashMetaTransaction(req), signature) == req.from;
+    }
+
+    /// @notice Execute a meta transaction, only trusted relayers may submit them
+    function execute(MetaTransaction calldata req, bytes calldata signature)
+        external
+        returns (bool success, bytes memory result)
+    {
+        require(trustedRelayers[msg.sender], "R3EForwarder: untrusted relayer");
+        require(verify(req, signature), "R3EForwarder: invalid signature, nonce or deadline");
+
+        _nonces[req.from] = req.nonce;
+        (success, result) = req.to.call(abi.encodePacked(req.data, req.from));
+        emit Executed(req.from, req.to, req.nonce, success, result);
+    }
+
+    function setRelayer(address relayer, bool trusted) external onlyOwner {
+        _setRelayer(relayer, trusted);
+    }
+
+    function transferOwnership(address newOwner) external onlyOwner {
+        require(newOwner != address(0), "R3EForwarder: new owner is the zero address");
+        emit OwnershipTransferred(owner, newOwner);
+        owner = newOwner;
+    }
+
+    function _setRelayer(address relayer, bool trusted) private {
+        trustedRelayers[relayer] = trusted;
+        emit RelayerUpdated(relayer, trusted);
+    }
+
+    function _recover(bytes32 digest, bytes calldata signature) private pure returns (address) {
+        if (signature.length != 65) {
+            return address(0);
+        }
+        bytes32 r = bytes32(signature[0:32]);
+        bytes32 s = bytes32(signature[32:64]);
+        uint8 v = uint8(signature[64]);
+        if (v < 27) {
+            v += 27;
+        }
+        if (uint256(s) > MAX_S || (v != 27 && v != 28)) {
+            return address(0);
+        }
+        return ecrecover(digest, v, r, s);
+    }
+}
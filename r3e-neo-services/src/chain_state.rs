@@ -0,0 +1,360 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+//! Cached Neo N3 chain state.
+//!
+//! Building and pricing a relayed transaction needs the current block height, the fee per
+//! byte and the execution fee factor. [`ChainStateCache`] keeps them in memory, refreshed in
+//! the background, so transactions are built without a round trip to the node each.
+
+use crate::error::Error;
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::RwLock;
+use tokio::task::JoinHandle;
+
+/// Script hash of the native PolicyContract
+const POLICY_CONTRACT: &str = "0xcc5e4edd9f5f8dba8bb65734541df7a1c081c67b";
+
+/// Time between blocks of Neo N3
+pub const NEO_BLOCK_TIME: Duration = Duration::from_secs(15);
+
+/// Size of the witness of a single signature account, with its count prefix
+const SINGLE_SIG_WITNESS_SIZE: u64 = 109;
+
+/// Execution price of the verification script of a single signature account, before the
+/// execution fee factor: two PUSHDATA1 and System.Crypto.CheckSig
+const SINGLE_SIG_VERIFICATION_PRICE: u64 = 2 * (1 << 3) + (1 << 15);
+
+/// Chain state transactions are built with
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChainState {
+    /// Current block height
+    pub height: u32,
+    /// Network fee per transaction byte in datoshi
+    pub fee_per_byte: u64,
+    /// Multiplier of the execution price of opcodes and syscalls
+    pub exec_fee_factor: u64,
+}
+
+impl ChainState {
+    /// Network fee of a transaction of `tx_size` bytes signed by a single signature account,
+    /// witness included
+    pub fn network_fee(&self, tx_size: usize) -> u64 {
+        (tx_size as u64 + SINGLE_SIG_WITNESS_SIZE) * self.fee_per_byte
+            + SINGLE_SIG_VERIFICATION_PRICE * self.exec_fee_factor
+    }
+}
+
+/// Chain state cache configuration
+#[derive(Debug, Clone)]
+pub struct ChainStateConfig {
+    /// Interval between background refreshes
+    pub refresh_interval: Duration,
+    /// Age after which a read refreshes the state itself, rather than waiting for the
+    /// background refresh
+    pub max_age: Duration,
+}
+
+impl Default for ChainStateConfig {
+    fn default() -> Self {
+        Self {
+            refresh_interval: NEO_BLOCK_TIME,
+            max_age: NEO_BLOCK_TIME * 4,
+        }
+    }
+}
+
+/// Neo N3 chain state, fetched from a node and cached
+pub struct ChainStateCache {
+    /// HTTP client
+    client: reqwest::Client,
+    /// RPC endpoint URL
+    rpc_url: String,
+    /// Configuration
+    config: ChainStateConfig,
+    /// Last fetched state and when it was fetched
+    state: RwLock<Option<(ChainState, Instant)>>,
+}
+
+impl ChainStateCache {
+    /// Create a chain state cache over the node at `rpc_url`
+    pub fn new(rpc_url: &str) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            rpc_url: rpc_url.to_string(),
+            config: ChainStateConfig::default(),
+            state: RwLock::new(None),
+        }
+    }
+
+    /// Set the refresh interval and maximum age of the cached state
+    pub fn with_config(mut self, config: ChainStateConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Current chain state, from the cache unless it is older than the maximum age.
+    ///
+    /// The block height of a cached state is advanced by the blocks produced since it was
+    /// fetched, so it lags the chain by at most a block.
+    pub async fn get(&self) -> Result<ChainState, Error> {
+        if let Some((state, fetched_at)) = *self.state.read().await {
+            let age = fetched_at.elapsed();
+            if age < self.config.max_age {
+                let blocks = (age.as_secs() / NEO_BLOCK_TIME.as_secs()) as u32;
+                return Ok(ChainState {
+                    height: state.height.saturating_add(blocks),
+                    ..state
+                });
+            }
+        }
+
+        self.refresh().await
+    }
+
+    /// Fetch the chain state from the node and cache it
+    pub async fn refresh(&self) -> Result<ChainState, Error> {
+        let state = self.fetch().await?;
+        *self.state.write().await = Some((state, Instant::now()));
+        debug!("Refreshed chain state: {:?}", state);
+        Ok(state)
+    }
+
+    /// Forget the cached state, after the node rejected a transaction built with it
+    pub async fn invalidate(&self) {
+        *self.state.write().await = None;
+    }
+
+    /// Keep refreshing the cached state in the background
+    pub fn spawn(self: Arc<Self>) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(self.config.refresh_interval);
+            loop {
+                interval.tick().await;
+                if let Err(e) = self.refresh().await {
+                    warn!("Failed to refresh chain state: {}", e);
+                }
+            }
+        })
+    }
+
+    async fn fetch(&self) -> Result<ChainState, Error> {
+        let (height, fee_per_byte, exec_fee_factor) = tokio::try_join!(
+            self.call("getblockcount", json!([])),
+            self.call(
+                "invokefunction",
+                json!([POLICY_CONTRACT, "getFeePerByte", []])
+            ),
+            self.call(
+                "invokefunction",
+                json!([POLICY_CONTRACT, "getExecFeeFactor", []])
+            ),
+        )?;
+
+        // The block count includes the genesis block
+        let height = height
+            .as_u64()
+            .ok_or_else(|| Error::RpcError("Invalid getblockcount response".to_string()))?;
+        Ok(ChainState {
+            height: height.saturating_sub(1) as u32,
+            fee_per_byte: Self::integer_result(&fee_per_byte, "getFeePerByte")?,
+            exec_fee_factor: Self::integer_result(&exec_fee_factor, "getExecFeeFactor")?,
+        })
+    }
+
+    /// Integer returned by an invocation
+    fn integer_result(result: &Value, method: &str) -> Result<u64, Error> {
+        if result["state"].as_str() != Some("HALT") {
+            return Err(Error::RpcError(format!(
+                "{} faulted: {}",
+                method,
+                result["exception"].as_str().unwrap_or("unknown error")
+            )));
+        }
+
+        let item = &result["stack"][0];
+        match item["type"].as_str() {
+            Some("Integer") => item["value"].as_str().and_then(|v| v.parse().ok()),
+            _ => None,
+        }
+        .ok_or_else(|| Error::RpcError(format!("Invalid {} result", method)))
+    }
+
+    async fn call(&self, method: &str, params: Value) -> Result<Value, Error> {
+        let request = json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": method,
+            "params": params,
+        });
+
+        let response: Value = self
+            .client
+            .post(&self.rpc_url)
+            .json(&request)
+            .send()
+            .await
+            .map_err(|e| Error::Network(format!("RPC request failed: {}", e)))?
+            .json()
+            .await
+            .map_err(|e| Error::Serialization(format!("Invalid RPC response: {}", e)))?;
+
+        if let Some(error) = response.get("error") {
+            return Err(Error::RpcError(format!(
+                "{} failed: {}",
+                method,
+                error["message"].as_str().unwrap_or("unknown error")
+            )));
+        }
+
+        Ok(response["result"].clone())
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    /// Node answering JSON-RPC requests with `handler(request)`, counting the requests
+    pub(crate) async fn rpc_node(handler: fn(&Value) -> Value) -> (String, Arc<AtomicUsize>) {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(AtomicUsize::new(0));
+
+        let counter = requests.clone();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let counter = counter.clone();
+                tokio::spawn(async move {
+                    let mut data = Vec::new();
+                    let mut buf = [0u8; 4096];
+                    let body = loop {
+                        let n = stream.read(&mut buf).await.unwrap();
+                        data.extend_from_slice(&buf[..n]);
+                        let Some(end) = data.windows(4).position(|w| w == b"\r\n\r\n") else {
+                            continue;
+                        };
+                        let head = String::from_utf8_lossy(&data[..end]).to_lowercase();
+                        let length: usize = head
+                            .lines()
+                            .find_map(|line| line.strip_prefix("content-length:"))
+                            .map_or(0, |length| length.trim().parse().unwrap());
+                        if data.len() >= end + 4 + length {
+                            break data[end + 4..end + 4 + length].to_vec();
+                        }
+                    };
+                    counter.fetch_add(1, Ordering::SeqCst);
+
+                    let request: Value = serde_json::from_slice(&body).unwrap();
+                    let body = handler(&request).to_string();
+                    let response = format!(
+                        "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\n\
+                         Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+                        body.len(),
+                        body
+                    );
+                    stream.write_all(response.as_bytes()).await.unwrap();
+                });
+            }
+        });
+        (url, requests)
+    }
+
+    fn integer(value: u64) -> Value {
+        json!({ "state": "HALT", "stack": [{ "type": "Integer", "value": value.to_string() }] })
+    }
+
+    /// Node at block 1000, charging 1000 datoshi per byte with an execution fee factor of 30
+    pub(crate) fn policy(request: &Value) -> Value {
+        let result = match (request["method"].as_str(), request["params"][1].as_str()) {
+            (Some("getblockcount"), _) => json!(1001),
+            (Some("invokefunction"), Some("getFeePerByte")) => integer(1000),
+            (Some("invokefunction"), Some("getExecFeeFactor")) => integer(30),
+            _ => return json!({ "jsonrpc": "2.0", "id": 1, "error": { "message": "unknown" } }),
+        };
+        json!({ "jsonrpc": "2.0", "id": 1, "result": result })
+    }
+
+    pub(crate) const STATE: ChainState = ChainState {
+        height: 1000,
+        fee_per_byte: 1000,
+        exec_fee_factor: 30,
+    };
+
+    #[test]
+    fn test_network_fee() {
+        // 359 bytes with the witness, and the CheckSig verification at a factor of 30
+        assert_eq!(STATE.network_fee(250), 359 * 1000 + 32_784 * 30);
+    }
+
+    #[tokio::test]
+    async fn test_get() {
+        let (url, requests) = rpc_node(policy).await;
+        let cache = ChainStateCache::new(&url);
+
+        // The first read fetches the state, with one request per value
+        assert_eq!(cache.get().await.unwrap(), STATE);
+        assert_eq!(requests.load(Ordering::SeqCst), 3);
+        assert_eq!(cache.get().await.unwrap(), STATE);
+        assert_eq!(requests.load(Ordering::SeqCst), 3);
+
+        cache.invalidate().await;
+        assert_eq!(cache.get().await.unwrap(), STATE);
+        assert_eq!(requests.load(Ordering::SeqCst), 6);
+
+        // A cached state advances by the blocks produced since it was fetched
+        let fetched_at = Instant::now()
+            .checked_sub(NEO_BLOCK_TIME * 2 + Duration::from_secs(1))
+            .unwrap();
+        *cache.state.write().await = Some((STATE, fetched_at));
+        assert_eq!(cache.get().await.unwrap().height, 1002);
+        assert_eq!(requests.load(Ordering::SeqCst), 6);
+
+        // A state older than the maximum age is fetched again
+        let cache = cache.with_config(ChainStateConfig {
+            refresh_interval: NEO_BLOCK_TIME,
+            max_age: NEO_BLOCK_TIME,
+        });
+        assert_eq!(cache.get().await.unwrap(), STATE);
+        assert_eq!(requests.load(Ordering::SeqCst), 9);
+    }
+
+    #[tokio::test]
+    async fn test_fetch_errors() {
+        let (url, _) = rpc_node(|request| {
+            if request["method"] == "getblockcount" {
+                return json!({ "jsonrpc": "2.0", "id": 1, "error": { "message": "busy" } });
+            }
+            json!({ "jsonrpc": "2.0", "id": 1, "result": integer(1000) })
+        })
+        .await;
+        let err = ChainStateCache::new(&url).get().await.unwrap_err();
+        assert!(
+            err.to_string().contains("getblockcount failed: busy"),
+            "{}",
+            err
+        );
+
+        let (url, _) = rpc_node(|request| {
+            let result = match request["method"].as_str() {
+                Some("getblockcount") => json!(1001),
+                _ => json!({ "state": "FAULT", "exception": "out of gas", "stack": [] }),
+            };
+            json!({ "jsonrpc": "2.0", "id": 1, "result": result })
+        })
+        .await;
+        let cache = ChainStateCache::new(&url);
+        let err = cache.refresh().await.unwrap_err();
+        assert!(err.to_string().contains("out of gas"), "{}", err);
+        assert!(cache.state.read().await.is_none());
+
+        let boolean = json!({ "state": "HALT", "stack": [{ "type": "Boolean", "value": true }] });
+        assert!(ChainStateCache::integer_result(&boolean, "getFeePerByte").is_err());
+    }
+}
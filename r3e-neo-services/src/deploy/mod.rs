@@ -0,0 +1,16 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+//! Deployment of user-uploaded Neo N3 contracts through the relayer.
+
+pub mod nef;
+pub mod rocksdb;
+pub mod service;
+pub mod storage;
+pub mod types;
+
+pub use nef::ContractPackage;
+pub use rocksdb::RocksDBDeploymentStorage;
+pub use service::{DeploymentRelay, DeploymentService};
+pub use storage::{DeploymentStorage, MemoryDeploymentStorage};
+pub use types::*;
@@ -0,0 +1,340 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+//! NEF files, manifests and the scripts deploying them.
+
+use crate::Error;
+use ripemd::Ripemd160;
+use sha2::{Digest, Sha256};
+
+/// Magic number of NEF files, `NEF3`
+const NEF_MAGIC: u32 = 0x3346_454e;
+
+/// Size of the fixed fields of a NEF file: magic, compiler and checksum
+const NEF_HEADER_SIZE: usize = 4 + 64;
+
+/// Largest NEF file accepted
+pub const MAX_NEF_SIZE: usize = 1024 * 1024;
+
+/// Largest manifest ContractManagement accepts
+pub const MAX_MANIFEST_SIZE: usize = 0xffff;
+
+/// Script hash of the native ContractManagement, in serialized byte order
+const CONTRACT_MANAGEMENT: [u8; 20] = [
+    0xfd, 0xa3, 0xfa, 0x43, 0x46, 0xea, 0x53, 0x2a, 0x25, 0x8f, 0xc4, 0x97, 0xdd, 0xad, 0xdb, 0x64,
+    0x37, 0xc9, 0xfd, 0xff,
+];
+
+/// Interop hash of System.Contract.Call
+const CONTRACT_CALL: [u8; 4] = [0x62, 0x7d, 0x5b, 0x52];
+
+/// Call flags allowing everything
+const CALL_FLAGS_ALL: i64 = 0x0f;
+
+const ABORT: u8 = 0x38;
+const PACK: u8 = 0xc0;
+const SYSCALL: u8 = 0x41;
+
+/// Compiled contract in the NEF format, with its manifest
+#[derive(Debug, Clone)]
+pub struct ContractPackage {
+    /// Serialized NEF file
+    pub nef: Vec<u8>,
+    /// Checksum stored at the end of the NEF file
+    pub checksum: u32,
+    /// Compiler that produced the NEF file
+    pub compiler: String,
+    /// Manifest JSON
+    pub manifest: String,
+    /// Contract name from the manifest
+    pub name: String,
+}
+
+impl ContractPackage {
+    /// Check a NEF file and its manifest
+    pub fn parse(nef: Vec<u8>, manifest: String) -> Result<Self, Error> {
+        if nef.len() > MAX_NEF_SIZE {
+            return Err(Error::InvalidParameter(format!(
+                "NEF file is larger than {} bytes",
+                MAX_NEF_SIZE
+            )));
+        }
+        if nef.len() < NEF_HEADER_SIZE + 4 {
+            return Err(Error::InvalidParameter("NEF file is truncated".to_string()));
+        }
+        if u32::from_le_bytes(nef[..4].try_into().unwrap()) != NEF_MAGIC {
+            return Err(Error::InvalidParameter(
+                "Not a NEF file: invalid magic".to_string(),
+            ));
+        }
+
+        let (body, checksum) = nef.split_at(nef.len() - 4);
+        let checksum = u32::from_le_bytes(checksum.try_into().unwrap());
+        if checksum != nef_checksum(body) {
+            return Err(Error::InvalidParameter(
+                "NEF file checksum mismatch".to_string(),
+            ));
+        }
+        let compiler = String::from_utf8_lossy(&nef[4..NEF_HEADER_SIZE])
+            .trim_end_matches('\0')
+            .to_string();
+
+        if manifest.len() > MAX_MANIFEST_SIZE {
+            return Err(Error::InvalidParameter(format!(
+                "Manifest is larger than {} bytes",
+                MAX_MANIFEST_SIZE
+            )));
+        }
+        let parsed: serde_json::Value = serde_json::from_str(&manifest)
+            .map_err(|e| Error::InvalidParameter(format!("Invalid manifest: {}", e)))?;
+        let name = parsed["name"]
+            .as_str()
+            .filter(|name| !name.is_empty())
+            .ok_or_else(|| Error::InvalidParameter("Manifest has no name".to_string()))?
+            .to_string();
+
+        Ok(Self {
+            nef,
+            checksum,
+            compiler,
+            manifest,
+            name,
+        })
+    }
+
+    /// Hash of the contract once deployed by `sender`, in display form
+    pub fn contract_hash(&self, sender: &[u8; 20]) -> String {
+        let mut script = vec![ABORT];
+        push_data(&mut script, sender);
+        push_int(&mut script, self.checksum as i64);
+        push_data(&mut script, self.name.as_bytes());
+
+        let mut hash: [u8; 20] = Ripemd160::digest(Sha256::digest(&script)).into();
+        hash.reverse();
+        format!("0x{}", hex::encode(hash))
+    }
+
+    /// Script calling `ContractManagement.deploy` with the NEF file and manifest
+    pub fn deploy_script(&self) -> Vec<u8> {
+        let mut script = Vec::with_capacity(self.nef.len() + self.manifest.len() + 64);
+
+        // Arguments are pushed in reverse and packed into an array
+        push_data(&mut script, self.manifest.as_bytes());
+        push_data(&mut script, &self.nef);
+        push_int(&mut script, 2);
+        script.push(PACK);
+
+        push_int(&mut script, CALL_FLAGS_ALL);
+        push_data(&mut script, b"deploy");
+        push_data(&mut script, &CONTRACT_MANAGEMENT);
+        script.push(SYSCALL);
+        script.extend_from_slice(&CONTRACT_CALL);
+        script
+    }
+}
+
+/// First 4 bytes of the double SHA-256 of a NEF file without its checksum
+fn nef_checksum(body: &[u8]) -> u32 {
+    let hash = Sha256::digest(Sha256::digest(body));
+    u32::from_le_bytes(hash[..4].try_into().unwrap())
+}
+
+/// PUSHDATA1, PUSHDATA2 or PUSHDATA4 depending on the length
+fn push_data(script: &mut Vec<u8>, data: &[u8]) {
+    if data.len() < 0x100 {
+        script.push(0x0c);
+        script.push(data.len() as u8);
+    } else if data.len() < 0x10000 {
+        script.push(0x0d);
+        script.extend_from_slice(&(data.len() as u16).to_le_bytes());
+    } else {
+        script.push(0x0e);
+        script.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    }
+    script.extend_from_slice(data);
+}
+
+/// PUSHM1 to PUSH16 for small integers, the smallest PUSHINT otherwise
+fn push_int(script: &mut Vec<u8>, value: i64) {
+    if (-1..=16).contains(&value) {
+        script.push((0x10 + value) as u8);
+        return;
+    }
+
+    let bytes = value.to_le_bytes();
+    let (opcode, size) = if i8::try_from(value).is_ok() {
+        (0x00, 1)
+    } else if i16::try_from(value).is_ok() {
+        (0x01, 2)
+    } else if i32::try_from(value).is_ok() {
+        (0x02, 4)
+    } else {
+        (0x03, 8)
+    };
+    script.push(opcode);
+    script.extend_from_slice(&bytes[..size]);
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// NEF file of a compiler and script, with a valid checksum
+    pub(crate) fn nef_file(compiler: &str, script: &[u8]) -> Vec<u8> {
+        let mut nef = NEF_MAGIC.to_le_bytes().to_vec();
+        let mut name = compiler.as_bytes().to_vec();
+        name.resize(64, 0);
+        nef.extend_from_slice(&name);
+        nef.extend_from_slice(script);
+        let checksum = nef_checksum(&nef);
+        nef.extend_from_slice(&checksum.to_le_bytes());
+        nef
+    }
+
+    fn script(f: impl FnOnce(&mut Vec<u8>)) -> Vec<u8> {
+        let mut script = Vec::new();
+        f(&mut script);
+        script
+    }
+
+    #[test]
+    fn test_parse() {
+        let nef = nef_file("neon-3.6.0", &[0x11, 0x40]);
+        let package =
+            ContractPackage::parse(nef.clone(), r#"{"name":"Counter"}"#.to_string()).unwrap();
+        assert_eq!(package.name, "Counter");
+        assert_eq!(package.compiler, "neon-3.6.0");
+        assert_eq!(
+            package.checksum,
+            u32::from_le_bytes(nef[nef.len() - 4..].try_into().unwrap())
+        );
+
+        let manifest = || r#"{"name":"Counter"}"#.to_string();
+        let mut bad_magic = nef.clone();
+        bad_magic[0] ^= 1;
+        let mut tampered = nef.clone();
+        tampered[NEF_HEADER_SIZE] ^= 1;
+        let oversized = nef_file("neon", &vec![0; MAX_NEF_SIZE]);
+        for invalid in [
+            bad_magic,
+            tampered,
+            nef[..NEF_HEADER_SIZE].to_vec(),
+            oversized,
+        ] {
+            assert!(matches!(
+                ContractPackage::parse(invalid, manifest()),
+                Err(Error::InvalidParameter(_))
+            ));
+        }
+
+        for manifest in [
+            "not json".to_string(),
+            r#"{"abi":{}}"#.to_string(),
+            r#"{"name":""}"#.to_string(),
+            format!(r#"{{"name":"{}"}}"#, "a".repeat(MAX_MANIFEST_SIZE)),
+        ] {
+            assert!(matches!(
+                ContractPackage::parse(nef.clone(), manifest),
+                Err(Error::InvalidParameter(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_push_encoding() {
+        assert_eq!(script(|s| push_int(s, -1)), [0x0f]);
+        assert_eq!(script(|s| push_int(s, 0)), [0x10]);
+        assert_eq!(script(|s| push_int(s, 16)), [0x20]);
+        assert_eq!(script(|s| push_int(s, 17)), [0x00, 0x11]);
+        assert_eq!(script(|s| push_int(s, -2)), [0x00, 0xfe]);
+        assert_eq!(script(|s| push_int(s, 200)), [0x01, 0xc8, 0x00]);
+        assert_eq!(
+            script(|s| push_int(s, 0x1234_5678)),
+            [0x02, 0x78, 0x56, 0x34, 0x12]
+        );
+        assert_eq!(
+            script(|s| push_int(s, 0xffff_ffff)),
+            [0x03, 0xff, 0xff, 0xff, 0xff, 0, 0, 0, 0]
+        );
+
+        assert_eq!(script(|s| push_data(s, b"abc")), b"\x0c\x03abc");
+        let data = vec![7; 0x100];
+        assert_eq!(script(|s| push_data(s, &data))[..3], [0x0d, 0x00, 0x01]);
+        let data = vec![7; 0x10000];
+        let pushed = script(|s| push_data(s, &data));
+        assert_eq!(pushed[..5], [0x0e, 0x00, 0x00, 0x01, 0x00]);
+        assert_eq!(pushed.len(), 5 + data.len());
+    }
+
+    #[test]
+    fn test_deploy_script() {
+        // ContractManagement is 0xfffdc93764dbaddd97c48f252a53ea4643faa3fd in display form
+        let mut display = CONTRACT_MANAGEMENT;
+        display.reverse();
+        assert_eq!(
+            hex::encode(display),
+            "fffdc93764dbaddd97c48f252a53ea4643faa3fd"
+        );
+
+        let manifest = r#"{"name":"Counter"}"#;
+        let package =
+            ContractPackage::parse(nef_file("neon", &[0x40]), manifest.to_string()).unwrap();
+        let script = package.deploy_script();
+
+        let mut expected = vec![0x0c, manifest.len() as u8];
+        expected.extend_from_slice(manifest.as_bytes());
+        expected.extend_from_slice(&[0x0c, package.nef.len() as u8]);
+        expected.extend_from_slice(&package.nef);
+        // 2 arguments packed, CallFlags.All, method and ContractManagement
+        expected.extend_from_slice(&[0x12, PACK, 0x1f, 0x0c, 0x06]);
+        expected.extend_from_slice(b"deploy");
+        expected.extend_from_slice(&[0x0c, 0x14]);
+        expected.extend_from_slice(&CONTRACT_MANAGEMENT);
+        expected.extend_from_slice(&[SYSCALL, 0x62, 0x7d, 0x5b, 0x52]);
+        assert_eq!(script, expected);
+    }
+
+    #[test]
+    fn test_contract_hash() {
+        let package = ContractPackage::parse(
+            nef_file("neon", &[0x40]),
+            r#"{"name":"Counter"}"#.to_string(),
+        )
+        .unwrap();
+        let sender = [0x11; 20];
+
+        // ABORT, sender, NEF checksum and name, hashed with SHA-256 then RIPEMD-160
+        let mut script = vec![ABORT, 0x0c, 0x14];
+        script.extend_from_slice(&sender);
+        push_int(&mut script, package.checksum as i64);
+        script.extend_from_slice(b"\x0c\x07Counter");
+        let mut hash: [u8; 20] = Ripemd160::digest(Sha256::digest(&script)).into();
+        hash.reverse();
+        assert_eq!(
+            package.contract_hash(&sender),
+            format!("0x{}", hex::encode(hash))
+        );
+
+        // The hash depends on the sender and name, not on the rest of the manifest
+        assert_ne!(
+            package.contract_hash(&sender),
+            package.contract_hash(&[0x22; 20])
+        );
+        let renamed =
+            ContractPackage::parse(package.nef.clone(), r#"{"name":"Counter2"}"#.to_string())
+                .unwrap();
+        assert_ne!(
+            package.contract_hash(&sender),
+            renamed.contract_hash(&sender)
+        );
+        let extended = ContractPackage::parse(
+            package.nef.clone(),
+            r#"{"name":"Counter","abi":{"methods":[]}}"#.to_string(),
+        )
+        .unwrap();
+        assert_eq!(
+            package.contract_hash(&sender),
+            extended.contract_hash(&sender)
+        );
+    }
+}
@@ -0,0 +1,103 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+use async_trait::async_trait;
+use r3e_store::rocksdb::RocksDbConfig;
+use r3e_store::RocksDBStore;
+use std::path::Path;
+use std::sync::Arc;
+
+use super::storage::DeploymentStorage;
+use super::types::{ContractDeployment, DeploymentStatus};
+use crate::Error;
+
+/// RocksDB implementation of DeploymentStorage
+pub struct RocksDBDeploymentStorage {
+    db: Arc<RocksDBStore>,
+    deployments_cf: String,
+}
+
+impl RocksDBDeploymentStorage {
+    /// Create a new RocksDB deployment storage
+    pub async fn new<P: AsRef<Path>>(db_path: P) -> Result<Self, Error> {
+        let config = RocksDbConfig {
+            path: db_path.as_ref().to_string_lossy().to_string(),
+            ..Default::default()
+        };
+
+        let db = RocksDBStore::new(config);
+
+        // Open the database
+        db.open()
+            .map_err(|e| Error::Storage(format!("Failed to open RocksDB store: {}", e)))?;
+
+        let deployments_cf = "contract_deployments".to_string();
+        db.create_cf_if_missing(&deployments_cf).map_err(|e| {
+            Error::Storage(format!(
+                "Failed to create column family {}: {}",
+                deployments_cf, e
+            ))
+        })?;
+
+        Ok(Self {
+            db: Arc::new(db),
+            deployments_cf,
+        })
+    }
+
+    fn list(
+        &self,
+        filter: impl Fn(&ContractDeployment) -> bool,
+    ) -> Result<Vec<ContractDeployment>, Error> {
+        // An empty prefix iterates over every deployment
+        let iter = self
+            .db
+            .prefix_iter_cf::<Vec<u8>>(&self.deployments_cf, &[])
+            .map_err(|e| Error::Storage(format!("Failed to scan deployments: {}", e)))?;
+
+        let mut deployments = Vec::new();
+        for (_, value) in iter {
+            let deployment = serde_json::from_slice::<ContractDeployment>(&value)
+                .map_err(|e| Error::Storage(format!("Failed to deserialize deployment: {}", e)))?;
+            if filter(&deployment) {
+                deployments.push(deployment);
+            }
+        }
+
+        deployments.sort_by_key(|deployment| deployment.created_at);
+        Ok(deployments)
+    }
+}
+
+#[async_trait]
+impl DeploymentStorage for RocksDBDeploymentStorage {
+    async fn get_deployment(&self, id: &str) -> Result<Option<ContractDeployment>, Error> {
+        match self.db.get_cf::<_, Vec<u8>>(&self.deployments_cf, id) {
+            Ok(Some(value)) => serde_json::from_slice(&value)
+                .map(Some)
+                .map_err(|e| Error::Storage(format!("Failed to deserialize deployment: {}", e))),
+            Ok(None) => Ok(None),
+            Err(e) => Err(Error::Storage(format!("Failed to get deployment: {}", e))),
+        }
+    }
+
+    async fn put_deployment(&self, deployment: ContractDeployment) -> Result<(), Error> {
+        let value = serde_json::to_vec(&deployment)
+            .map_err(|e| Error::Storage(format!("Failed to serialize deployment: {}", e)))?;
+
+        self.db
+            .put_cf(&self.deployments_cf, &deployment.id, &value)
+            .map_err(|e| Error::Storage(format!("Failed to store deployment: {}", e)))
+    }
+
+    async fn list_by_function(&self, function_id: &str) -> Result<Vec<ContractDeployment>, Error> {
+        self.list(|deployment| deployment.function_id == function_id)
+    }
+
+    async fn list_by_status(
+        &self,
+        status: DeploymentStatus,
+    ) -> Result<Vec<ContractDeployment>, Error> {
+        self.list(|deployment| deployment.status == status)
+    }
+}
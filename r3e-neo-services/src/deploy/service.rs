@@ -0,0 +1,590 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+use async_trait::async_trait;
+use base64::Engine;
+use log::{error, info, warn};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::task::JoinHandle;
+use uuid::Uuid;
+
+use super::nef::ContractPackage;
+use super::storage::DeploymentStorage;
+use super::types::{ContractDeployment, DeployRequest, DeploymentEstimate, DeploymentStatus};
+use crate::gas_bank::service::GasBankServiceTrait;
+use crate::gas_bank::types::{SpendDecision, SpendRequest};
+use crate::meta_tx::manager::ChainTxStatus;
+use crate::meta_tx::{NeoRelayBackend, RelayBackend, ScriptCost, SignedScript};
+use crate::Error;
+
+/// Relayer signing, broadcasting and tracking deployment transactions
+#[async_trait]
+pub trait DeploymentRelay: Send + Sync {
+    /// Account script hash of the relayer, in serialized byte order
+    async fn relayer_account(&self) -> Result<[u8; 20], Error>;
+
+    /// Price a transaction running a script
+    async fn estimate_script(&self, script: &[u8]) -> Result<ScriptCost, Error>;
+
+    /// Build a transaction running a script, signed by the relayer
+    async fn sign_script(&self, script: Vec<u8>, cost: ScriptCost) -> Result<SignedScript, Error>;
+
+    /// Broadcast a signed transaction
+    async fn send(&self, tx: &SignedScript) -> Result<(), Error>;
+
+    /// Get the on-chain status of a transaction
+    async fn tx_status(&self, tx_hash: &str) -> Result<ChainTxStatus, Error>;
+
+    /// Current block height
+    async fn block_height(&self) -> Result<u32, Error>;
+}
+
+#[async_trait]
+impl DeploymentRelay for NeoRelayBackend {
+    async fn relayer_account(&self) -> Result<[u8; 20], Error> {
+        NeoRelayBackend::relayer_account(self).await
+    }
+
+    async fn estimate_script(&self, script: &[u8]) -> Result<ScriptCost, Error> {
+        NeoRelayBackend::estimate_script(self, script).await
+    }
+
+    async fn sign_script(&self, script: Vec<u8>, cost: ScriptCost) -> Result<SignedScript, Error> {
+        NeoRelayBackend::sign_script(self, script, cost).await
+    }
+
+    async fn send(&self, tx: &SignedScript) -> Result<(), Error> {
+        NeoRelayBackend::send(self, tx).await
+    }
+
+    async fn tx_status(&self, tx_hash: &str) -> Result<ChainTxStatus, Error> {
+        RelayBackend::tx_status(self, tx_hash).await
+    }
+
+    async fn block_height(&self) -> Result<u32, Error> {
+        NeoRelayBackend::block_height(self).await
+    }
+}
+
+/// Deploys the companion contracts of functions.
+///
+/// The relayer signs and pays for the deployment transaction, and the gas bank account of
+/// the owner is charged its fees before it is broadcast. Contracts are deployed with the
+/// relayer as sender, so their hash is known before the transaction lands.
+pub struct DeploymentService {
+    /// Deployment storage
+    storage: Arc<dyn DeploymentStorage>,
+    /// Gas bank charging the owners
+    gas_bank: Arc<dyn GasBankServiceTrait>,
+    /// Relayer signing and broadcasting the deployments
+    relay: Arc<dyn DeploymentRelay>,
+}
+
+impl DeploymentService {
+    /// Create a new deployment service
+    pub fn new(
+        storage: Arc<dyn DeploymentStorage>,
+        gas_bank: Arc<dyn GasBankServiceTrait>,
+        relay: Arc<dyn DeploymentRelay>,
+    ) -> Self {
+        Self {
+            storage,
+            gas_bank,
+            relay,
+        }
+    }
+
+    /// Check the NEF file and manifest of a request
+    fn package(request: &DeployRequest) -> Result<ContractPackage, Error> {
+        if request.function_id.is_empty() {
+            return Err(Error::InvalidParameter(
+                "Function ID is required".to_string(),
+            ));
+        }
+        let nef = base64::engine::general_purpose::STANDARD
+            .decode(request.nef.trim())
+            .map_err(|e| Error::InvalidParameter(format!("Invalid base64 NEF file: {}", e)))?;
+        ContractPackage::parse(nef, request.manifest.clone())
+    }
+
+    /// Balance and unused credit of a gas bank account
+    async fn available(&self, owner: &str) -> Result<u64, Error> {
+        let account = self
+            .gas_bank
+            .get_account(owner)
+            .await?
+            .ok_or_else(|| Error::NotFound(format!("Gas bank account not found: {}", owner)))?;
+        Ok(account
+            .balance
+            .saturating_add(account.credit_limit.saturating_sub(account.used_credit)))
+    }
+
+    /// Price a deployment and check the owner's gas bank account covers it
+    pub async fn estimate(&self, request: &DeployRequest) -> Result<DeploymentEstimate, Error> {
+        let package = Self::package(request)?;
+        let contract_hash = package.contract_hash(&self.relay.relayer_account().await?);
+        let cost = self.relay.estimate_script(&package.deploy_script()).await?;
+        let available = self.available(&request.owner).await?;
+
+        Ok(DeploymentEstimate {
+            contract_hash,
+            system_fee: cost.system_fee,
+            network_fee: cost.network_fee,
+            total_fee: cost.total(),
+            available,
+            sufficient: available >= cost.total(),
+        })
+    }
+
+    /// Deploy a contract for a function, charging the owner's gas bank account
+    pub async fn deploy(&self, request: DeployRequest) -> Result<ContractDeployment, Error> {
+        let package = Self::package(&request)?;
+        let contract_hash = package.contract_hash(&self.relay.relayer_account().await?);
+
+        // A contract is deployed once, its hash only depends on the sender, NEF and name
+        for existing in self
+            .storage
+            .list_by_status(DeploymentStatus::Pending)
+            .await?
+            .into_iter()
+            .chain(
+                self.storage
+                    .list_by_status(DeploymentStatus::Deployed)
+                    .await?,
+            )
+        {
+            if existing.contract_hash == contract_hash {
+                return Err(Error::ContractError(format!(
+                    "Contract {} is already deployed by deployment {}",
+                    contract_hash, existing.id
+                )));
+            }
+        }
+
+        let script = package.deploy_script();
+        let cost = self.relay.estimate_script(&script).await?;
+        let available = self.available(&request.owner).await?;
+        if available < cost.total() {
+            return Err(Error::InsufficientFunds(format!(
+                "Deployment costs {} but {} has {} available",
+                cost.total(),
+                request.owner,
+                available
+            )));
+        }
+
+        let spend = SpendRequest {
+            address: request.owner.clone(),
+            function_id: Some(request.function_id.clone()),
+            amount: cost.total(),
+            approval_id: request.approval_id.clone(),
+        };
+        if let SpendDecision::ApprovalRequired { approval } =
+            self.gas_bank.authorize_spend(&spend).await?
+        {
+            return Err(Error::GasBankError(format!(
+                "Deployment costs {} and requires approval {}",
+                cost.total(),
+                approval.id
+            )));
+        }
+
+        // Charge the transaction before it is broadcast, refunding it if the node rejects it
+        let tx = self.relay.sign_script(script, cost).await?;
+        let payment = self.gas_bank.pay_gas_for_spend(&tx.tx_hash, &spend).await?;
+        let charged = payment.amount.saturating_add(payment.fee);
+        if let Err(e) = self.relay.send(&tx).await {
+            self.refund(&request.owner, &tx.tx_hash, charged).await;
+            return Err(e);
+        }
+
+        let now = chrono::Utc::now().timestamp() as u64;
+        let deployment = ContractDeployment {
+            id: Uuid::new_v4().to_string(),
+            function_id: request.function_id,
+            owner: request.owner,
+            name: package.name,
+            contract_hash,
+            nef_checksum: package.checksum,
+            tx_hash: tx.tx_hash,
+            valid_until_block: tx.valid_until_block,
+            system_fee: cost.system_fee,
+            network_fee: cost.network_fee,
+            charged,
+            status: DeploymentStatus::Pending,
+            error: None,
+            created_at: now,
+            updated_at: now,
+        };
+        self.storage.put_deployment(deployment.clone()).await?;
+
+        info!(
+            "Deploying contract {} ({}) for function {} in {}",
+            deployment.name, deployment.contract_hash, deployment.function_id, deployment.tx_hash
+        );
+        Ok(deployment)
+    }
+
+    /// Credit back the fees of a deployment that never landed
+    async fn refund(&self, owner: &str, tx_hash: &str, amount: u64) {
+        let refund = format!("refund:{}", tx_hash);
+        if let Err(e) = self.gas_bank.deposit(&refund, owner, amount).await {
+            error!(
+                "Failed to refund {} to {} for deployment {}: {}",
+                amount, owner, tx_hash, e
+            );
+        }
+    }
+
+    /// Get a deployment
+    pub async fn get_deployment(&self, id: &str) -> Result<ContractDeployment, Error> {
+        self.storage
+            .get_deployment(id)
+            .await?
+            .ok_or_else(|| Error::NotFound(format!("Deployment not found: {}", id)))
+    }
+
+    /// List the contracts deployed for a function
+    pub async fn list_deployments(
+        &self,
+        function_id: &str,
+    ) -> Result<Vec<ContractDeployment>, Error> {
+        self.storage.list_by_function(function_id).await
+    }
+
+    /// Check the transaction of a pending deployment, returning the deployment
+    pub async fn refresh(
+        &self,
+        mut deployment: ContractDeployment,
+    ) -> Result<ContractDeployment, Error> {
+        if deployment.status != DeploymentStatus::Pending {
+            return Ok(deployment);
+        }
+
+        match self.relay.tx_status(&deployment.tx_hash).await? {
+            ChainTxStatus::Confirmed => {
+                info!(
+                    "Deployed contract {} for function {}",
+                    deployment.contract_hash, deployment.function_id
+                );
+                deployment.status = DeploymentStatus::Deployed;
+            }
+            ChainTxStatus::Failed(error) => {
+                // The consumed GAS is spent even though the deployment faulted
+                warn!(
+                    "Deployment of contract {} faulted: {}",
+                    deployment.contract_hash, error
+                );
+                deployment.status = DeploymentStatus::Failed;
+                deployment.error = Some(error);
+            }
+            ChainTxStatus::Pending => {
+                if self.relay.block_height().await? <= deployment.valid_until_block {
+                    return Ok(deployment);
+                }
+                warn!(
+                    "Deployment of contract {} expired",
+                    deployment.contract_hash
+                );
+                self.refund(&deployment.owner, &deployment.tx_hash, deployment.charged)
+                    .await;
+                deployment.status = DeploymentStatus::Failed;
+                deployment.error = Some("Transaction expired".to_string());
+            }
+        }
+
+        deployment.updated_at = chrono::Utc::now().timestamp() as u64;
+        self.storage.put_deployment(deployment.clone()).await?;
+        Ok(deployment)
+    }
+
+    /// Check every pending deployment once
+    pub async fn poll_pending(&self) -> Result<(), Error> {
+        for deployment in self
+            .storage
+            .list_by_status(DeploymentStatus::Pending)
+            .await?
+        {
+            let id = deployment.id.clone();
+            if let Err(e) = self.refresh(deployment).await {
+                warn!("Failed to check deployment {}: {}", id, e);
+            }
+        }
+        Ok(())
+    }
+
+    /// Poll pending deployments every interval
+    pub fn spawn(self: Arc<Self>, interval: Duration) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(interval);
+            loop {
+                interval.tick().await;
+                if let Err(e) = self.poll_pending().await {
+                    warn!("Failed to poll contract deployments: {}", e);
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::deploy::nef::tests::nef_file;
+    use crate::deploy::storage::MemoryDeploymentStorage;
+    use crate::gas_bank::service::GasBankService;
+    use crate::gas_bank::storage::InMemoryGasBankStorage;
+    use crate::gas_bank::types::SpendingPolicy;
+    use crate::signer::LocalSigner;
+    use crate::types::FeeModel;
+    use neo3::prelude::{HttpProvider, RpcClient};
+    use std::collections::HashMap;
+    use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+    use std::sync::Mutex;
+
+    const OWNER: &str = "NOwner";
+    const RELAYER: [u8; 20] = [0x11; 20];
+    const COST: ScriptCost = ScriptCost {
+        system_fee: 1_000,
+        network_fee: 100,
+    };
+
+    /// Relayer of a chain where nothing is included in a block until told to
+    #[derive(Default)]
+    struct MockRelay {
+        height: AtomicU32,
+        fail_send: AtomicBool,
+        sent: Mutex<Vec<String>>,
+        statuses: Mutex<HashMap<String, ChainTxStatus>>,
+    }
+
+    #[async_trait]
+    impl DeploymentRelay for MockRelay {
+        async fn relayer_account(&self) -> Result<[u8; 20], Error> {
+            Ok(RELAYER)
+        }
+
+        async fn estimate_script(&self, _script: &[u8]) -> Result<ScriptCost, Error> {
+            Ok(COST)
+        }
+
+        async fn sign_script(
+            &self,
+            script: Vec<u8>,
+            cost: ScriptCost,
+        ) -> Result<SignedScript, Error> {
+            Ok(SignedScript {
+                tx_hash: format!("0x{}", Uuid::new_v4().simple()),
+                cost,
+                valid_until_block: self.height.load(Ordering::SeqCst) + 240,
+                raw: script,
+            })
+        }
+
+        async fn send(&self, tx: &SignedScript) -> Result<(), Error> {
+            if self.fail_send.load(Ordering::SeqCst) {
+                return Err(Error::RpcError("Insufficient network fee".to_string()));
+            }
+            self.sent.lock().unwrap().push(tx.tx_hash.clone());
+            Ok(())
+        }
+
+        async fn tx_status(&self, tx_hash: &str) -> Result<ChainTxStatus, Error> {
+            Ok(self
+                .statuses
+                .lock()
+                .unwrap()
+                .get(tx_hash)
+                .cloned()
+                .unwrap_or(ChainTxStatus::Pending))
+        }
+
+        async fn block_height(&self) -> Result<u32, Error> {
+            Ok(self.height.load(Ordering::SeqCst))
+        }
+    }
+
+    /// Deployment service charging a gas bank account holding 10000
+    async fn setup(
+        policy: SpendingPolicy,
+    ) -> (DeploymentService, Arc<GasBankService>, Arc<MockRelay>) {
+        let gas_bank = Arc::new(
+            GasBankService::new(
+                Arc::new(InMemoryGasBankStorage::new()),
+                Arc::new(RpcClient::new(
+                    HttpProvider::new("http://127.0.0.1:1").unwrap(),
+                )),
+                Arc::new(LocalSigner::from_hex(&"01".repeat(32)).unwrap()),
+                "testnet".to_string(),
+                FeeModel::Free,
+                0,
+            )
+            .with_spending_policy(policy),
+        );
+        gas_bank.deposit("0xdeposit", OWNER, 10_000).await.unwrap();
+
+        let relay = Arc::new(MockRelay::default());
+        let service = DeploymentService::new(
+            Arc::new(MemoryDeploymentStorage::new()),
+            gas_bank.clone(),
+            relay.clone(),
+        );
+        (service, gas_bank, relay)
+    }
+
+    fn request(name: &str) -> DeployRequest {
+        DeployRequest {
+            function_id: "function".to_string(),
+            owner: OWNER.to_string(),
+            nef: base64::engine::general_purpose::STANDARD.encode(nef_file("neon", &[0x40])),
+            manifest: format!(r#"{{"name":"{}"}}"#, name),
+            approval_id: None,
+        }
+    }
+
+    async fn balance(gas_bank: &GasBankService) -> u64 {
+        gas_bank.get_balance(OWNER).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_deploy() {
+        let (service, gas_bank, relay) = setup(SpendingPolicy::default()).await;
+
+        let estimate = service.estimate(&request("Counter")).await.unwrap();
+        let package = DeploymentService::package(&request("Counter")).unwrap();
+        assert_eq!(estimate.contract_hash, package.contract_hash(&RELAYER));
+        assert_eq!((estimate.total_fee, estimate.available), (1_100, 10_000));
+        assert!(estimate.sufficient);
+
+        // The owner is charged before the transaction is broadcast
+        let deployment = service.deploy(request("Counter")).await.unwrap();
+        assert_eq!(deployment.status, DeploymentStatus::Pending);
+        assert_eq!(deployment.contract_hash, estimate.contract_hash);
+        assert_eq!(deployment.charged, 1_100);
+        assert_eq!(balance(&gas_bank).await, 8_900);
+        assert_eq!(
+            *relay.sent.lock().unwrap(),
+            vec![deployment.tx_hash.clone()]
+        );
+        assert_eq!(service.list_deployments("function").await.unwrap().len(), 1);
+        assert!(service.get_deployment(&deployment.id).await.is_ok());
+
+        // The same contract is not deployed twice
+        assert!(matches!(
+            service.deploy(request("Counter")).await,
+            Err(Error::ContractError(_))
+        ));
+        assert_eq!(balance(&gas_bank).await, 8_900);
+
+        let mut invalid = request("Other");
+        invalid.function_id.clear();
+        assert!(matches!(
+            service.deploy(invalid).await,
+            Err(Error::InvalidParameter(_))
+        ));
+        let mut invalid = request("Other");
+        invalid.nef = "not base64".to_string();
+        assert!(matches!(
+            service.deploy(invalid).await,
+            Err(Error::InvalidParameter(_))
+        ));
+
+        // Owners without an account or enough GAS pay nothing
+        gas_bank.deposit("0xdeposit2", "NPoor", 500).await.unwrap();
+        let mut poor = request("Other");
+        poor.owner = "NPoor".to_string();
+        assert!(matches!(
+            service.deploy(poor).await,
+            Err(Error::InsufficientFunds(_))
+        ));
+        let mut unknown = request("Other");
+        unknown.owner = "NUnknown".to_string();
+        assert!(matches!(
+            service.deploy(unknown).await,
+            Err(Error::NotFound(_))
+        ));
+        assert_eq!(relay.sent.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_deploy_refunds_rejected_transactions() {
+        let (service, gas_bank, relay) = setup(SpendingPolicy::default()).await;
+        relay.fail_send.store(true, Ordering::SeqCst);
+
+        assert!(matches!(
+            service.deploy(request("Counter")).await,
+            Err(Error::RpcError(_))
+        ));
+        assert_eq!(balance(&gas_bank).await, 10_000);
+        assert!(service
+            .list_deployments("function")
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_deploy_requires_approval() {
+        let policy = SpendingPolicy {
+            approval_threshold: Some(1_000),
+            ..SpendingPolicy::default()
+        };
+        let (service, gas_bank, relay) = setup(policy).await;
+
+        assert!(matches!(
+            service.deploy(request("Counter")).await,
+            Err(Error::GasBankError(_))
+        ));
+        let approvals = gas_bank.get_pending_approvals().await.unwrap();
+        assert_eq!(approvals.len(), 1);
+        assert_eq!(approvals[0].amount, 1_100);
+        assert_eq!(approvals[0].function_id.as_deref(), Some("function"));
+        assert!(relay.sent.lock().unwrap().is_empty());
+        assert_eq!(balance(&gas_bank).await, 10_000);
+    }
+
+    #[tokio::test]
+    async fn test_poll_pending() {
+        let (service, gas_bank, relay) = setup(SpendingPolicy::default()).await;
+        let confirmed = service.deploy(request("Confirmed")).await.unwrap();
+        let faulted = service.deploy(request("Faulted")).await.unwrap();
+        let expired = service.deploy(request("Expired")).await.unwrap();
+        assert_eq!(balance(&gas_bank).await, 6_700);
+
+        {
+            let mut statuses = relay.statuses.lock().unwrap();
+            statuses.insert(confirmed.tx_hash.clone(), ChainTxStatus::Confirmed);
+            statuses.insert(
+                faulted.tx_hash.clone(),
+                ChainTxStatus::Failed("ABORT".to_string()),
+            );
+        }
+        service.poll_pending().await.unwrap();
+
+        let confirmed = service.get_deployment(&confirmed.id).await.unwrap();
+        assert_eq!(confirmed.status, DeploymentStatus::Deployed);
+        // The GAS a faulted deployment consumed is not refunded
+        let faulted = service.get_deployment(&faulted.id).await.unwrap();
+        assert_eq!(faulted.status, DeploymentStatus::Failed);
+        assert_eq!(faulted.error.as_deref(), Some("ABORT"));
+        let pending = service.get_deployment(&expired.id).await.unwrap();
+        assert_eq!(pending.status, DeploymentStatus::Pending);
+        assert_eq!(balance(&gas_bank).await, 6_700);
+
+        // Transactions past their last valid block are refunded
+        relay
+            .height
+            .store(expired.valid_until_block + 1, Ordering::SeqCst);
+        service.poll_pending().await.unwrap();
+        let expired = service.get_deployment(&expired.id).await.unwrap();
+        assert_eq!(expired.status, DeploymentStatus::Failed);
+        assert_eq!(expired.error.as_deref(), Some("Transaction expired"));
+        assert_eq!(balance(&gas_bank).await, 7_800);
+
+        // Finished deployments are left alone
+        let refreshed = service.refresh(confirmed.clone()).await.unwrap();
+        assert_eq!(refreshed.status, DeploymentStatus::Deployed);
+        assert_eq!(refreshed.updated_at, confirmed.updated_at);
+    }
+}
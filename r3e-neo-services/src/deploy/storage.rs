@@ -0,0 +1,83 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+use async_trait::async_trait;
+use std::collections::HashMap;
+use tokio::sync::RwLock;
+
+use super::types::{ContractDeployment, DeploymentStatus};
+use crate::Error;
+
+/// Storage of contract deployments
+#[async_trait]
+pub trait DeploymentStorage: Send + Sync {
+    /// Get a deployment
+    async fn get_deployment(&self, id: &str) -> Result<Option<ContractDeployment>, Error>;
+
+    /// Create or update a deployment
+    async fn put_deployment(&self, deployment: ContractDeployment) -> Result<(), Error>;
+
+    /// List the deployments of a function, oldest first
+    async fn list_by_function(&self, function_id: &str) -> Result<Vec<ContractDeployment>, Error>;
+
+    /// List the deployments with a status
+    async fn list_by_status(
+        &self,
+        status: DeploymentStatus,
+    ) -> Result<Vec<ContractDeployment>, Error>;
+}
+
+/// In-memory deployment storage
+#[derive(Default)]
+pub struct MemoryDeploymentStorage {
+    deployments: RwLock<HashMap<String, ContractDeployment>>,
+}
+
+impl MemoryDeploymentStorage {
+    /// Create a new in-memory deployment storage
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl DeploymentStorage for MemoryDeploymentStorage {
+    async fn get_deployment(&self, id: &str) -> Result<Option<ContractDeployment>, Error> {
+        Ok(self.deployments.read().await.get(id).cloned())
+    }
+
+    async fn put_deployment(&self, deployment: ContractDeployment) -> Result<(), Error> {
+        self.deployments
+            .write()
+            .await
+            .insert(deployment.id.clone(), deployment);
+        Ok(())
+    }
+
+    async fn list_by_function(&self, function_id: &str) -> Result<Vec<ContractDeployment>, Error> {
+        let mut deployments: Vec<ContractDeployment> = self
+            .deployments
+            .read()
+            .await
+            .values()
+            .filter(|deployment| deployment.function_id == function_id)
+            .cloned()
+            .collect();
+        deployments.sort_by_key(|deployment| deployment.created_at);
+        Ok(deployments)
+    }
+
+    async fn list_by_status(
+        &self,
+        status: DeploymentStatus,
+    ) -> Result<Vec<ContractDeployment>, Error> {
+        Ok(self
+            .deployments
+            .read()
+            .await
+            .values()
+            .filter(|deployment| deployment.status == status)
+            .cloned()
+            .collect())
+    }
+}
@@ -0,0 +1,108 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+use serde::{Deserialize, Serialize};
+
+/// Request to deploy a contract for a function
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeployRequest {
+    /// Function the contract belongs to
+    pub function_id: String,
+
+    /// Neo N3 address of the gas bank account paying for the deployment
+    pub owner: String,
+
+    /// Base64 encoded NEF file
+    pub nef: String,
+
+    /// Manifest JSON
+    pub manifest: String,
+
+    /// Gas bank approval of the payment, when it exceeds the approval threshold
+    #[serde(default)]
+    pub approval_id: Option<String>,
+}
+
+/// Cost of a deployment and whether the gas bank account covers it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeploymentEstimate {
+    /// Hash the contract will be deployed at
+    pub contract_hash: String,
+
+    /// GAS consumed by the deployment, in datoshi
+    pub system_fee: u64,
+
+    /// Network fee of the transaction, in datoshi
+    pub network_fee: u64,
+
+    /// Total fee charged to the gas bank account, in datoshi
+    pub total_fee: u64,
+
+    /// Balance and unused credit of the gas bank account, in datoshi
+    pub available: u64,
+
+    /// Whether the account can pay for the deployment
+    pub sufficient: bool,
+}
+
+/// Deployment status
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeploymentStatus {
+    /// Transaction broadcast, not yet in a block
+    Pending,
+    /// Contract deployed
+    Deployed,
+    /// Transaction faulted or expired
+    Failed,
+}
+
+/// Contract deployed for a function
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ContractDeployment {
+    /// Deployment ID
+    pub id: String,
+
+    /// Function the contract belongs to
+    pub function_id: String,
+
+    /// Neo N3 address of the gas bank account that paid for the deployment
+    pub owner: String,
+
+    /// Contract name from the manifest
+    pub name: String,
+
+    /// Contract hash in display form
+    pub contract_hash: String,
+
+    /// Checksum of the NEF file
+    pub nef_checksum: u32,
+
+    /// Hash of the deployment transaction
+    pub tx_hash: String,
+
+    /// Last block the deployment transaction may be included in
+    pub valid_until_block: u32,
+
+    /// GAS consumed by the deployment, in datoshi
+    pub system_fee: u64,
+
+    /// Network fee of the transaction, in datoshi
+    pub network_fee: u64,
+
+    /// Amount charged to the gas bank account, fees included, in datoshi
+    pub charged: u64,
+
+    /// Status
+    pub status: DeploymentStatus,
+
+    /// Reason the deployment failed
+    #[serde(default)]
+    pub error: Option<String>,
+
+    /// Created at
+    pub created_at: u64,
+
+    /// Updated at
+    pub updated_at: u64,
+}
@@ -61,6 +61,9 @@ pub enum Error {
     
     #[error("Contract error: {0}")]
     ContractError(String),
+
+    #[error("MPC error: {0}")]
+    MpcError(String),
 }
 
 impl From<neo3::neo_clients::HttpClientError> for Error {
@@ -69,6 +72,12 @@ impl From<neo3::neo_clients::HttpClientError> for Error {
     }
 }
 
+impl From<r3e_core::chain::ChainError> for Error {
+    fn from(err: r3e_core::chain::ChainError) -> Self {
+        Error::RpcError(err.to_string())
+    }
+}
+
 impl From<serde_json::Error> for Error {
     fn from(err: serde_json::Error) -> Self {
         Error::Serialization(err.to_string())
@@ -0,0 +1,476 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+//! Relayer fees.
+//!
+//! Relaying a meta transaction costs the relayer the network fees of its chain, which are
+//! recovered from the sender according to the fee model of the service the transaction is
+//! relayed for. [`FeeOracle`] prices transactions with live fee data: the fee per byte and
+//! execution fee factor of Neo N3 from the cached chain state, the base and priority fee per
+//! gas of Ethereum from the node. [`FeeSchedule`] turns the network cost of a transaction into
+//! the [`FeeQuote`] the sender signs.
+
+use crate::chain_state::ChainStateCache;
+use crate::error::Error;
+use crate::meta_tx::types::BlockchainType;
+use crate::types::FeeModel;
+use log::debug;
+use r3e_core::chain::{ChainClients, TxSimulation};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::RwLock;
+
+/// Time a quoted fee is valid for
+pub const QUOTE_TTL: Duration = Duration::from_secs(60);
+
+/// Age after which the fees of an Ethereum network are fetched again, about a block
+const ETHEREUM_FEES_MAX_AGE: Duration = Duration::from_secs(12);
+
+/// Fee data of an Ethereum network
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EthereumFees {
+    /// Base fee per gas of the latest block in wei
+    pub base_fee_per_gas: u64,
+    /// Priority fee per gas suggested by the node in wei
+    pub priority_fee_per_gas: u64,
+}
+
+impl EthereumFees {
+    /// Cost of a transaction using `gas` units.
+    ///
+    /// The base fee rises by at most 12.5% per block, so twice the current base fee covers six
+    /// full blocks in a row before the transaction is included.
+    pub fn cost(&self, gas: u64) -> NetworkCost {
+        let network_fee = gas.saturating_mul(self.priority_fee_per_gas);
+        let system_fee = gas.saturating_mul(self.base_fee_per_gas);
+        NetworkCost {
+            network_fee,
+            system_fee,
+            max_cost: network_fee.saturating_add(system_fee.saturating_mul(2)),
+        }
+    }
+}
+
+/// Cost of a transaction to the relayer, in datoshi on Neo N3 and wei on Ethereum
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NetworkCost {
+    /// Network fee on Neo N3, the priority fee on Ethereum
+    pub network_fee: u64,
+    /// System fee on Neo N3, the base fee on Ethereum
+    pub system_fee: u64,
+    /// Most the transaction may cost if fees rise before it is included
+    pub max_cost: u64,
+}
+
+impl NetworkCost {
+    /// Cost at the current fees
+    pub fn total(&self) -> u64 {
+        self.network_fee.saturating_add(self.system_fee)
+    }
+}
+
+/// Fee of relaying a meta transaction, quoted before it is signed
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FeeQuote {
+    /// Blockchain the fee is paid on
+    pub blockchain_type: BlockchainType,
+    /// Service the transaction is relayed for
+    pub service: String,
+    /// Fee model of the service, signed as the `fee_model` of the transaction
+    pub fee_model: String,
+    /// Network fee on Neo N3, the priority fee on Ethereum
+    pub network_fee: u64,
+    /// System fee on Neo N3, the base fee on Ethereum
+    pub system_fee: u64,
+    /// Fee charged to the sender, signed as the `fee_amount` of the transaction
+    pub fee_amount: u64,
+    /// Unit of the fees: `datoshi` on Neo N3, `wei` on Ethereum
+    pub unit: String,
+    /// Time after which the fee should be quoted again (secs since epoch)
+    pub expires_at: u64,
+}
+
+fn default_dynamic_margin() -> f64 {
+    10.0
+}
+
+/// Fee models of the services transactions are relayed for
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FeeSchedule {
+    /// Fee model of services without one of their own
+    pub default_model: FeeModel,
+    /// Fee models by service, keyed by the contract the relayed transactions call
+    #[serde(default)]
+    pub services: HashMap<String, FeeModel>,
+    /// Margin of the dynamic fee model in percent of the highest network cost
+    #[serde(default = "default_dynamic_margin")]
+    pub dynamic_margin: f64,
+}
+
+impl Default for FeeSchedule {
+    fn default() -> Self {
+        Self::new(FeeModel::Percentage(1.0))
+    }
+}
+
+impl FeeSchedule {
+    /// Create a fee schedule charging every service with `default_model`
+    pub fn new(default_model: FeeModel) -> Self {
+        Self {
+            default_model,
+            services: HashMap::new(),
+            dynamic_margin: default_dynamic_margin(),
+        }
+    }
+
+    /// Fee model of a service
+    pub fn model(&self, service: &str) -> &FeeModel {
+        self.services.get(service).unwrap_or(&self.default_model)
+    }
+
+    /// Fee charged to the sender of a transaction under a fee model:
+    ///
+    /// - `Fixed` charges a flat amount whatever the network cost
+    /// - `Percentage` charges the network cost plus a percentage of it
+    /// - `Dynamic` charges the highest network cost the transaction may reach, plus the margin
+    /// - `Free` charges nothing, the platform pays the network cost
+    pub fn fee_amount(&self, model: &FeeModel, cost: &NetworkCost) -> u64 {
+        match model {
+            FeeModel::Fixed(amount) => *amount,
+            FeeModel::Percentage(percentage) => {
+                let total = cost.total();
+                total.saturating_add((total as f64 * percentage / 100.0) as u64)
+            }
+            FeeModel::Dynamic => cost
+                .max_cost
+                .saturating_add((cost.max_cost as f64 * self.dynamic_margin / 100.0) as u64),
+            FeeModel::Free => 0,
+        }
+    }
+
+    /// Quote the fee of relaying a transaction for a service
+    pub fn quote(
+        &self,
+        service: &str,
+        blockchain_type: BlockchainType,
+        cost: NetworkCost,
+    ) -> FeeQuote {
+        let model = self.model(service);
+        FeeQuote {
+            blockchain_type,
+            service: service.to_string(),
+            fee_model: model.to_string(),
+            network_fee: cost.network_fee,
+            system_fee: cost.system_fee,
+            fee_amount: self.fee_amount(model, &cost),
+            unit: match blockchain_type {
+                BlockchainType::NeoN3 => "datoshi",
+                BlockchainType::Ethereum => "wei",
+            }
+            .to_string(),
+            expires_at: (chrono::Utc::now().timestamp() as u64) + QUOTE_TTL.as_secs(),
+        }
+    }
+}
+
+/// Live network fees of the chains transactions are relayed on
+pub struct FeeOracle {
+    /// Chain clients transactions are run with
+    chains: Arc<ChainClients>,
+    /// Cached Neo N3 chain state
+    chain_state: Arc<ChainStateCache>,
+    /// Last fetched fees of each Ethereum network and when they were fetched
+    ethereum: RwLock<HashMap<String, (EthereumFees, Instant)>>,
+}
+
+impl FeeOracle {
+    /// Create a fee oracle pricing Neo N3 transactions with the cached `chain_state`
+    pub fn new(chains: Arc<ChainClients>, chain_state: Arc<ChainStateCache>) -> Self {
+        Self {
+            chains,
+            chain_state,
+            ethereum: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// Network cost of a transaction of `tx_size` bytes, found by running it on `network`
+    pub async fn cost(
+        &self,
+        blockchain_type: BlockchainType,
+        network: &str,
+        tx: &TxSimulation,
+        tx_size: usize,
+    ) -> Result<NetworkCost, Error> {
+        let outcome = self
+            .chains
+            .get(blockchain_type.chain(), network)?
+            .simulate_tx(tx)
+            .await?;
+        if !outcome.success {
+            return Err(Error::TransactionError(format!(
+                "Transaction fails: {}",
+                outcome.error.as_deref().unwrap_or("unknown error")
+            )));
+        }
+
+        match blockchain_type {
+            BlockchainType::NeoN3 => {
+                let network_fee = self.chain_state.get().await?.network_fee(tx_size);
+                let system_fee = outcome.gas_used.unwrap_or_default();
+                // Neo N3 fees are set by the policy contract rather than by demand
+                Ok(NetworkCost {
+                    network_fee,
+                    system_fee,
+                    max_cost: network_fee.saturating_add(system_fee),
+                })
+            }
+            BlockchainType::Ethereum => {
+                let gas = outcome.gas_used.unwrap_or_default();
+                Ok(self.ethereum_fees(network).await?.cost(gas))
+            }
+        }
+    }
+
+    /// Current fees of an Ethereum network, cached for about a block
+    pub async fn ethereum_fees(&self, network: &str) -> Result<EthereumFees, Error> {
+        if let Some((fees, fetched_at)) = self.ethereum.read().await.get(network) {
+            if fetched_at.elapsed() < ETHEREUM_FEES_MAX_AGE {
+                return Ok(*fees);
+            }
+        }
+
+        let client = self.chains.get("ethereum", network)?;
+        let (block, priority_fee) = tokio::try_join!(
+            client.request("eth_getBlockByNumber", json!(["latest", false])),
+            client.request("eth_maxPriorityFeePerGas", json!([])),
+        )?;
+        let fees = EthereumFees {
+            base_fee_per_gas: quantity("baseFeePerGas", &block["baseFeePerGas"])?,
+            priority_fee_per_gas: quantity("eth_maxPriorityFeePerGas", &priority_fee)?,
+        };
+
+        debug!("Fetched fees of Ethereum {}: {:?}", network, fees);
+        self.ethereum
+            .write()
+            .await
+            .insert(network.to_string(), (fees, Instant::now()));
+        Ok(fees)
+    }
+}
+
+/// Hex quantity of an Ethereum RPC response
+fn quantity(name: &str, value: &Value) -> Result<u64, Error> {
+    value
+        .as_str()
+        .and_then(|quantity| u64::from_str_radix(quantity.trim_start_matches("0x"), 16).ok())
+        .ok_or_else(|| Error::RpcError(format!("Invalid {}: {}", name, value)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chain_state::tests::{policy, rpc_node, STATE};
+    use async_trait::async_trait;
+    use r3e_core::chain::{
+        Block, BlockRef, CallResult, ChainClient, ChainError, ChainMetrics, ContractCall,
+        EventFilter, EventStream,
+    };
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Chain on `testnet` running every transaction with `simulation`, Ethereum networks with a
+    /// base fee of 100 wei and a priority fee of 2 wei
+    struct MockChain {
+        chain: &'static str,
+        simulation: CallResult,
+        requests: AtomicUsize,
+    }
+
+    impl MockChain {
+        fn new(chain: &'static str, gas_used: u64, error: Option<&str>) -> Arc<Self> {
+            Arc::new(Self {
+                chain,
+                simulation: CallResult {
+                    success: error.is_none(),
+                    result: Value::Null,
+                    gas_used: Some(gas_used),
+                    error: error.map(str::to_string),
+                },
+                requests: AtomicUsize::new(0),
+            })
+        }
+    }
+
+    #[async_trait]
+    impl ChainClient for MockChain {
+        fn chain(&self) -> &str {
+            self.chain
+        }
+
+        fn network(&self) -> &str {
+            "testnet"
+        }
+
+        async fn block_number(&self) -> Result<u64, ChainError> {
+            Ok(1)
+        }
+
+        async fn get_block(&self, _block: BlockRef) -> Result<Block, ChainError> {
+            Err(ChainError::Unsupported("getblock".to_string()))
+        }
+
+        async fn call_contract(&self, _call: &ContractCall) -> Result<CallResult, ChainError> {
+            Err(ChainError::Unsupported("call".to_string()))
+        }
+
+        async fn simulate_tx(&self, _tx: &TxSimulation) -> Result<CallResult, ChainError> {
+            Ok(self.simulation.clone())
+        }
+
+        async fn send_raw_tx(&self, _tx: &[u8]) -> Result<String, ChainError> {
+            Err(ChainError::Unsupported("send".to_string()))
+        }
+
+        async fn subscribe_events(&self, _filter: EventFilter) -> Result<EventStream, ChainError> {
+            Err(ChainError::Unsupported("events".to_string()))
+        }
+
+        async fn request(&self, method: &str, _params: Value) -> Result<Value, ChainError> {
+            self.requests.fetch_add(1, Ordering::SeqCst);
+            match method {
+                "eth_getBlockByNumber" => Ok(json!({ "number": "0x1", "baseFeePerGas": "0x64" })),
+                "eth_maxPriorityFeePerGas" => Ok(json!("0x2")),
+                method => Err(ChainError::Unsupported(method.to_string())),
+            }
+        }
+
+        fn metrics(&self) -> ChainMetrics {
+            ChainMetrics {
+                chain: self.chain.to_string(),
+                network: "testnet".to_string(),
+                methods: HashMap::new(),
+                endpoints: Vec::new(),
+            }
+        }
+    }
+
+    fn ethereum_fees() -> EthereumFees {
+        EthereumFees {
+            base_fee_per_gas: 100,
+            priority_fee_per_gas: 2,
+        }
+    }
+
+    const COST: NetworkCost = NetworkCost {
+        network_fee: 1_000,
+        system_fee: 9_000,
+        max_cost: 20_000,
+    };
+
+    #[test]
+    fn test_fee_models() {
+        let mut schedule = FeeSchedule::new(FeeModel::Percentage(5.0));
+        schedule
+            .services
+            .insert("0xfixed".to_string(), FeeModel::Fixed(42));
+        assert_eq!(schedule.model("0xfixed"), &FeeModel::Fixed(42));
+        assert_eq!(schedule.model("0xother"), &FeeModel::Percentage(5.0));
+
+        assert_eq!(schedule.fee_amount(&FeeModel::Fixed(42), &COST), 42);
+        assert_eq!(
+            schedule.fee_amount(&FeeModel::Percentage(5.0), &COST),
+            10_500
+        );
+        // The margin of the dynamic model applies to the highest cost
+        assert_eq!(schedule.fee_amount(&FeeModel::Dynamic, &COST), 22_000);
+        assert_eq!(schedule.fee_amount(&FeeModel::Free, &COST), 0);
+
+        for model in [
+            FeeModel::Fixed(42),
+            FeeModel::Percentage(1.5),
+            FeeModel::Dynamic,
+            FeeModel::Free,
+        ] {
+            assert_eq!(model.to_string().parse::<FeeModel>().unwrap(), model);
+        }
+        for invalid in ["fixed:x", "percent:1", "dynamic:1", ""] {
+            assert!(invalid.parse::<FeeModel>().is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_quote() {
+        let mut schedule = FeeSchedule::default();
+        schedule
+            .services
+            .insert("0xfree".to_string(), FeeModel::Free);
+        let now = chrono::Utc::now().timestamp() as u64;
+
+        let quote = schedule.quote("0xother", BlockchainType::NeoN3, COST);
+        assert_eq!(quote.fee_model, "percentage:1");
+        assert_eq!(quote.fee_amount, 10_100);
+        assert_eq!((quote.network_fee, quote.system_fee), (1_000, 9_000));
+        assert_eq!(quote.unit, "datoshi");
+        assert!(quote.expires_at >= now + QUOTE_TTL.as_secs());
+
+        let quote = schedule.quote("0xfree", BlockchainType::Ethereum, COST);
+        assert_eq!((quote.fee_model.as_str(), quote.fee_amount), ("free", 0));
+        assert_eq!(quote.unit, "wei");
+    }
+
+    #[test]
+    fn test_ethereum_cost() {
+        let cost = ethereum_fees().cost(21_000);
+        assert_eq!(cost.network_fee, 42_000);
+        assert_eq!(cost.system_fee, 2_100_000);
+        assert_eq!(cost.total(), 2_142_000);
+        // Covers the base fee doubling before inclusion
+        assert_eq!(cost.max_cost, 4_242_000);
+
+        assert_eq!(quantity("fee", &json!("0x64")).unwrap(), 100);
+        assert!(quantity("fee", &Value::Null).is_err());
+        assert!(quantity("fee", &json!("0xzz")).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_oracle_cost() {
+        let (url, _) = rpc_node(policy).await;
+        let chains = Arc::new(ChainClients::new());
+        let neo = MockChain::new("neo", 1_000_000, None);
+        let ethereum = MockChain::new("ethereum", 21_000, None);
+        chains.insert(neo);
+        chains.insert(ethereum.clone());
+        let oracle = FeeOracle::new(chains.clone(), Arc::new(ChainStateCache::new(&url)));
+        let tx = TxSimulation::default();
+
+        // Neo N3 fees are priced with the chain state, the system fee is the gas consumed
+        let cost = oracle
+            .cost(BlockchainType::NeoN3, "testnet", &tx, 250)
+            .await
+            .unwrap();
+        assert_eq!(cost.network_fee, STATE.network_fee(250));
+        assert_eq!(cost.system_fee, 1_000_000);
+        assert_eq!(cost.max_cost, cost.total());
+
+        let cost = oracle
+            .cost(BlockchainType::Ethereum, "testnet", &tx, 0)
+            .await
+            .unwrap();
+        assert_eq!(cost, ethereum_fees().cost(21_000));
+        assert_eq!(ethereum.requests.load(Ordering::SeqCst), 2);
+
+        // The fees of an Ethereum network are cached for about a block
+        oracle.ethereum_fees("testnet").await.unwrap();
+        assert_eq!(ethereum.requests.load(Ordering::SeqCst), 2);
+
+        // A failing transaction is not priced
+        chains.insert(MockChain::new("neo", 0, Some("ABORT: not allowed")));
+        let err = oracle
+            .cost(BlockchainType::NeoN3, "testnet", &tx, 250)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::TransactionError(ref message) if message.contains("ABORT")));
+    }
+}
@@ -8,7 +8,10 @@ use std::path::Path;
 use std::sync::Arc;
 
 use super::storage::GasBankStorage;
-use super::types::{GasBankAccount, GasBankDeposit, GasBankTransaction, GasBankWithdrawal};
+use super::types::{
+    ApprovalStatus, GasBankAccount, GasBankDeposit, GasBankTransaction, GasBankWithdrawal,
+    SpendApproval,
+};
 use crate::Error;
 
 /// RocksDB implementation of GasBankStorage
@@ -19,6 +22,8 @@ pub struct RocksDBGasBankStorage {
     withdrawals_cf: String,
     transactions_cf: String,
     contract_mappings_cf: String,
+    spending_cf: String,
+    approvals_cf: String,
 }
 
 impl RocksDBGasBankStorage {
@@ -39,9 +44,19 @@ impl RocksDBGasBankStorage {
         let withdrawals_cf = "gas_bank_withdrawals".to_string();
         let transactions_cf = "gas_bank_transactions".to_string();
         let contract_mappings_cf = "gas_bank_contract_mappings".to_string();
+        let spending_cf = "gas_bank_spending".to_string();
+        let approvals_cf = "gas_bank_approvals".to_string();
         
         // Create column families if they don't exist
-        for cf in [&accounts_cf, &deposits_cf, &withdrawals_cf, &transactions_cf, &contract_mappings_cf] {
+        for cf in [
+            &accounts_cf,
+            &deposits_cf,
+            &withdrawals_cf,
+            &transactions_cf,
+            &contract_mappings_cf,
+            &spending_cf,
+            &approvals_cf,
+        ] {
             db.create_cf_if_missing(cf)
                 .map_err(|e| Error::Storage(format!("Failed to create column family {}: {}", cf, e)))?;
         }
@@ -53,6 +68,8 @@ impl RocksDBGasBankStorage {
             withdrawals_cf,
             transactions_cf,
             contract_mappings_cf,
+            spending_cf,
+            approvals_cf,
         })
     }
 }
@@ -250,4 +267,91 @@ impl GasBankStorage for RocksDBGasBankStorage {
 
         Ok(())
     }
+
+    async fn get_spending(&self, scope: &str, from_day: &str, to_day: &str) -> Result<u64, Error> {
+        let prefix = format!("{}|", scope);
+
+        let iter: Box<dyn Iterator<Item = (Box<[u8]>, Box<[u8]>)> + Send> = self
+            .db
+            .prefix_iter_cf(&self.spending_cf, prefix.as_bytes())
+            .map_err(|e| Error::Storage(format!("Failed to scan spending: {}", e)))?;
+
+        let mut total = 0u64;
+        for (key, value) in iter {
+            let day = String::from_utf8_lossy(&key[prefix.len()..]).to_string();
+            if day.as_str() < from_day || day.as_str() > to_day {
+                continue;
+            }
+
+            let amount = serde_json::from_slice::<u64>(&value)
+                .map_err(|e| Error::Storage(format!("Failed to deserialize spending: {}", e)))?;
+            total = total.saturating_add(amount);
+        }
+
+        Ok(total)
+    }
+
+    async fn add_spending(&self, scope: &str, day: &str, amount: u64) -> Result<(), Error> {
+        let key = format!("{}|{}", scope, day);
+
+        let spent = match self.db.get_cf::<_, Vec<u8>>(&self.spending_cf, &key) {
+            Ok(Some(value)) => serde_json::from_slice::<u64>(&value)
+                .map_err(|e| Error::Storage(format!("Failed to deserialize spending: {}", e)))?,
+            Ok(None) => 0,
+            Err(e) => return Err(Error::Storage(format!("Failed to get spending: {}", e))),
+        };
+
+        let value = serde_json::to_vec(&spent.saturating_add(amount))
+            .map_err(|e| Error::Storage(format!("Failed to serialize spending: {}", e)))?;
+        self.db
+            .put_cf(&self.spending_cf, key, &value)
+            .map_err(|e| Error::Storage(format!("Failed to store spending: {}", e)))?;
+
+        Ok(())
+    }
+
+    async fn get_approval(&self, id: &str) -> Result<Option<SpendApproval>, Error> {
+        match self.db.get_cf::<_, Vec<u8>>(&self.approvals_cf, id) {
+            Ok(Some(value)) => {
+                let approval = serde_json::from_slice::<SpendApproval>(&value)
+                    .map_err(|e| Error::Storage(format!("Failed to deserialize approval: {}", e)))?;
+                Ok(Some(approval))
+            }
+            Ok(None) => Ok(None),
+            Err(e) => Err(Error::Storage(format!("Failed to get approval: {}", e))),
+        }
+    }
+
+    async fn put_approval(&self, approval: SpendApproval) -> Result<(), Error> {
+        let key = approval.id.clone();
+        let value = serde_json::to_vec(&approval)
+            .map_err(|e| Error::Storage(format!("Failed to serialize approval: {}", e)))?;
+
+        self.db
+            .put_cf(&self.approvals_cf, key, &value)
+            .map_err(|e| Error::Storage(format!("Failed to store approval: {}", e)))?;
+
+        Ok(())
+    }
+
+    async fn get_approvals_by_status(
+        &self,
+        status: &ApprovalStatus,
+    ) -> Result<Vec<SpendApproval>, Error> {
+        let iter: Box<dyn Iterator<Item = (Box<[u8]>, Box<[u8]>)> + Send> = self
+            .db
+            .prefix_iter_cf(&self.approvals_cf, b"")
+            .map_err(|e| Error::Storage(format!("Failed to scan approvals: {}", e)))?;
+
+        let mut approvals = Vec::new();
+        for (_, value) in iter {
+            let approval = serde_json::from_slice::<SpendApproval>(&value)
+                .map_err(|e| Error::Storage(format!("Failed to deserialize approval: {}", e)))?;
+            if &approval.status == status {
+                approvals.push(approval);
+            }
+        }
+
+        Ok(approvals)
+    }
 }
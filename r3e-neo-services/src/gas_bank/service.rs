@@ -2,17 +2,24 @@
 // All Rights Reserved
 
 use super::storage::GasBankStorage;
-use super::types::{GasBankAccount, GasBankDeposit, GasBankTransaction, GasBankWithdrawal};
+use super::types::{
+    ApprovalStatus, GasBankAccount, GasBankDeposit, GasBankTransaction, GasBankWithdrawal,
+    SpendApproval, SpendDecision, SpendRequest, SpendingLimit, SpendingPolicy,
+};
+use crate::chain_state::ChainStateCache;
+use crate::signer::RelayerSigner;
 use crate::types::FeeModel;
 use crate::Error;
 use async_trait::async_trait;
-use chrono::Utc;
+use chrono::{DateTime, Utc};
 use log::{debug, error, info, warn};
 use neo3::neo_clients::APITrait;
 use neo3::prelude::{
-    HttpProvider, RpcClient, Transaction, TransactionBuilder, Wallet,
+    HttpProvider, RpcClient, Transaction, TransactionBuilder,
 };
 use std::sync::Arc;
+use tokio::sync::Mutex;
+use uuid::Uuid;
 
 /// Gas bank service trait
 #[async_trait]
@@ -47,6 +54,34 @@ pub trait GasBankServiceTrait: Send + Sync {
         amount: u64,
     ) -> Result<GasBankTransaction, Error>;
 
+    /// Check a gas payment against the spending policy before the relayer signs
+    async fn authorize_spend(&self, request: &SpendRequest) -> Result<SpendDecision, Error>;
+
+    /// Pay gas for transaction, enforcing spending limits and approvals
+    async fn pay_gas_for_spend(
+        &self,
+        tx_hash: &str,
+        request: &SpendRequest,
+    ) -> Result<GasBankTransaction, Error>;
+
+    /// Approve a pending spend approval
+    async fn approve_spend(
+        &self,
+        approval_id: &str,
+        approver: &str,
+    ) -> Result<SpendApproval, Error>;
+
+    /// Reject a pending spend approval
+    async fn reject_spend(
+        &self,
+        approval_id: &str,
+        approver: &str,
+        reason: Option<String>,
+    ) -> Result<SpendApproval, Error>;
+
+    /// Get spend approvals waiting for approvers
+    async fn get_pending_approvals(&self) -> Result<Vec<SpendApproval>, Error>;
+
     /// Get gas price
     async fn get_gas_price(&self) -> Result<u64, Error>;
 
@@ -90,14 +125,20 @@ pub struct GasBankService {
     storage: Arc<dyn GasBankStorage>,
     /// Neo RPC client
     rpc_client: Arc<RpcClient<HttpProvider>>,
-    /// Neo wallet
-    relayer_wallet: Arc<Wallet>,
+    /// Relayer signer
+    relayer_signer: Arc<dyn RelayerSigner>,
     /// Network identifier
     network: String,
     /// Default fee model
     default_fee_model: FeeModel,
     /// Default credit limit
     default_credit_limit: u64,
+    /// Spending limits and approval rules
+    spending_policy: SpendingPolicy,
+    /// Serializes payments so concurrent spends cannot overrun a limit
+    spend_lock: Mutex<()>,
+    /// Cached chain state fees are calculated with
+    chain_state: Option<Arc<ChainStateCache>>,
 }
 
 impl GasBankService {
@@ -105,7 +146,7 @@ impl GasBankService {
     pub fn new(
         storage: Arc<dyn GasBankStorage>,
         rpc_client: Arc<RpcClient<HttpProvider>>,
-        relayer_wallet: Arc<Wallet>,
+        relayer_signer: Arc<dyn RelayerSigner>,
         network: String,
         default_fee_model: FeeModel,
         default_credit_limit: u64,
@@ -113,13 +154,193 @@ impl GasBankService {
         Self {
             storage,
             rpc_client,
-            relayer_wallet,
+            relayer_signer,
             network,
             default_fee_model,
             default_credit_limit,
+            spending_policy: SpendingPolicy::default(),
+            spend_lock: Mutex::new(()),
+            chain_state: None,
         }
     }
 
+    /// Enforce spending limits and approvals
+    pub fn with_spending_policy(mut self, spending_policy: SpendingPolicy) -> Self {
+        self.spending_policy = spending_policy;
+        self
+    }
+
+    /// Calculate fees with cached chain state rather than fixed defaults
+    pub fn with_chain_state(mut self, chain_state: Arc<ChainStateCache>) -> Self {
+        self.chain_state = Some(chain_state);
+        self
+    }
+
+    /// Check a payment against a spending limit
+    async fn check_limit(
+        &self,
+        scope: &str,
+        limit: &SpendingLimit,
+        amount: u64,
+        now: DateTime<Utc>,
+    ) -> Result<(), Error> {
+        let today = now.format("%Y-%m-%d").to_string();
+        let periods = [
+            ("daily", limit.daily, today.clone()),
+            ("monthly", limit.monthly, now.format("%Y-%m-01").to_string()),
+        ];
+
+        for (period, cap, from_day) in periods {
+            let Some(cap) = cap else {
+                continue;
+            };
+
+            let spent = self.storage.get_spending(scope, &from_day, &today).await?;
+            if spent.saturating_add(amount) > cap {
+                return Err(Error::GasBankError(format!(
+                    "{} spending limit exceeded for {}: {} + {} > {}",
+                    period, scope, spent, amount, cap
+                )));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Check an approval covers a payment
+    fn check_approval(
+        approval: &SpendApproval,
+        request: &SpendRequest,
+        now: u64,
+    ) -> Result<(), Error> {
+        if approval.status != ApprovalStatus::Approved {
+            return Err(Error::GasBankError(format!(
+                "Spend approval {} is {:?}",
+                approval.id, approval.status
+            )));
+        }
+        if now > approval.expires_at {
+            return Err(Error::GasBankError(format!(
+                "Spend approval {} has expired",
+                approval.id
+            )));
+        }
+        if approval.address != request.address
+            || approval.function_id != request.function_id
+            || request.amount > approval.amount
+        {
+            return Err(Error::GasBankError(format!(
+                "Spend approval {} does not cover this payment",
+                approval.id
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// Get a pending approval an approver may act on
+    async fn pending_approval(
+        &self,
+        approval_id: &str,
+        approver: &str,
+    ) -> Result<SpendApproval, Error> {
+        if !self.spending_policy.approvers.iter().any(|a| a == approver) {
+            return Err(Error::AuthError(format!(
+                "{} is not a gas bank approver",
+                approver
+            )));
+        }
+
+        let approval = self
+            .storage
+            .get_approval(approval_id)
+            .await?
+            .ok_or_else(|| Error::NotFound(format!("Spend approval not found: {}", approval_id)))?;
+        if approval.status != ApprovalStatus::Pending {
+            return Err(Error::GasBankError(format!(
+                "Spend approval {} is {:?}",
+                approval.id, approval.status
+            )));
+        }
+        if Utc::now().timestamp() as u64 > approval.expires_at {
+            return Err(Error::GasBankError(format!(
+                "Spend approval {} has expired",
+                approval.id
+            )));
+        }
+
+        Ok(approval)
+    }
+
+    /// Check a payment against the spending policy, creating an approval request if needed
+    async fn authorize(&self, request: &SpendRequest) -> Result<SpendDecision, Error> {
+        let now = Utc::now();
+        let policy = &self.spending_policy;
+
+        let user_limit = policy
+            .user_limits
+            .get(&request.address)
+            .unwrap_or(&policy.default_user_limit);
+        self.check_limit(
+            &user_scope(&request.address),
+            user_limit,
+            request.amount,
+            now,
+        )
+        .await?;
+        if let Some(function_id) = &request.function_id {
+            if let Some(function_limit) = policy.function_limits.get(function_id) {
+                self.check_limit(
+                    &function_scope(function_id),
+                    function_limit,
+                    request.amount,
+                    now,
+                )
+                .await?;
+            }
+        }
+
+        let threshold = match policy.approval_threshold {
+            Some(threshold) if request.amount > threshold => threshold,
+            _ => return Ok(SpendDecision::Allowed),
+        };
+
+        let now = now.timestamp() as u64;
+        if let Some(approval_id) = &request.approval_id {
+            let approval = self
+                .storage
+                .get_approval(approval_id)
+                .await?
+                .ok_or_else(|| {
+                    Error::NotFound(format!("Spend approval not found: {}", approval_id))
+                })?;
+            Self::check_approval(&approval, request, now)?;
+            return Ok(SpendDecision::Allowed);
+        }
+
+        let approval = SpendApproval {
+            id: Uuid::new_v4().to_string(),
+            address: request.address.clone(),
+            function_id: request.function_id.clone(),
+            amount: request.amount,
+            status: ApprovalStatus::Pending,
+            required_approvals: policy.required_approvals.max(1),
+            approved_by: Vec::new(),
+            rejected_by: None,
+            reason: None,
+            created_at: now,
+            expires_at: now + policy.approval_ttl,
+            updated_at: now,
+        };
+        self.storage.put_approval(approval.clone()).await?;
+
+        info!(
+            "Gas payment of {} for {} exceeds approval threshold {}, created approval {}",
+            request.amount, request.address, threshold, approval.id
+        );
+        Ok(SpendDecision::ApprovalRequired { approval })
+    }
+
     /// Calculate fee for amount
     async fn calculate_fee(&self, amount: u64, fee_model: &FeeModel) -> Result<u64, Error> {
         match fee_model {
@@ -137,9 +358,14 @@ impl GasBankService {
     }
 
     /// Calculate fee for gas transfer
-    async fn calculate_gas_transfer_fee(&self, _tx_data: &Option<Vec<u8>>) -> Result<u64, Error> {
-        // In a real implementation, we would use the Neo3 API to calculate the fee
-        // For now, return a fixed fee for Neo transactions
+    async fn calculate_gas_transfer_fee(&self, tx_data: &Option<Vec<u8>>) -> Result<u64, Error> {
+        // Price the transaction with the cached network fees
+        if let Some(chain_state) = &self.chain_state {
+            let tx_size = tx_data.as_ref().map_or(0, Vec::len);
+            return Ok(chain_state.get().await?.network_fee(tx_size));
+        }
+
+        // Without chain state, return a fixed fee for Neo transactions
         Ok(1_000_000) // 0.001 GAS
     }
 
@@ -184,16 +410,17 @@ impl GasBankService {
     }
 
     /// Estimate transaction fee
-    async fn estimate_transaction_fee(&self, _tx_data: Vec<u8>) -> Result<u64, Error> {
-        // In a real implementation, we would use the Neo3 API to estimate the fee
-        // For now, return a fixed fee
-        Ok(1_000_000) // 0.001 GAS
+    async fn estimate_transaction_fee(&self, tx_data: Vec<u8>) -> Result<u64, Error> {
+        self.calculate_gas_transfer_fee(&Some(tx_data)).await
     }
 
     /// Send transaction
     async fn send_transaction(&self, tx_data: Vec<u8>) -> Result<String, Error> {
-        // Use the relayer wallet to sign and send the transaction
-        debug!("Sending transaction with relayer wallet");
+        // Use the relayer signer to sign and send the transaction
+        debug!(
+            "Sending transaction with {:?} relayer signer",
+            self.relayer_signer.kind()
+        );
 
         // Send the raw transaction
         let tx_hex = hex::encode(tx_data);
@@ -346,6 +573,7 @@ impl GasBankServiceTrait for GasBankService {
             fee,
             timestamp: chrono::Utc::now().timestamp() as u64,
             status: "confirmed".to_string(),
+            function_id: None,
         };
 
         // Store transaction
@@ -360,6 +588,37 @@ impl GasBankServiceTrait for GasBankService {
         address: &str,
         amount: u64,
     ) -> Result<GasBankTransaction, Error> {
+        let request = SpendRequest {
+            address: address.to_string(),
+            function_id: None,
+            amount,
+            approval_id: None,
+        };
+
+        self.pay_gas_for_spend(tx_hash, &request).await
+    }
+
+    async fn authorize_spend(&self, request: &SpendRequest) -> Result<SpendDecision, Error> {
+        let _guard = self.spend_lock.lock().await;
+        self.authorize(request).await
+    }
+
+    async fn pay_gas_for_spend(
+        &self,
+        tx_hash: &str,
+        request: &SpendRequest,
+    ) -> Result<GasBankTransaction, Error> {
+        let _guard = self.spend_lock.lock().await;
+        let address = request.address.as_str();
+        let amount = request.amount;
+
+        if let SpendDecision::ApprovalRequired { approval } = self.authorize(request).await? {
+            return Err(Error::GasBankError(format!(
+                "Gas payment of {} for {} requires approval {}",
+                amount, address, approval.id
+            )));
+        }
+
         // Get account
         let mut account = match self.storage.get_account(address).await? {
             Some(account) => account,
@@ -408,17 +667,95 @@ impl GasBankServiceTrait for GasBankService {
             fee,
             timestamp: chrono::Utc::now().timestamp() as u64,
             status: "confirmed".to_string(),
+            function_id: request.function_id.clone(),
         };
 
         // Store transaction
         self.storage.add_transaction(transaction.clone()).await?;
 
+        // Count the payment against the spending limits
+        let today = Utc::now().format("%Y-%m-%d").to_string();
+        self.storage
+            .add_spending(&user_scope(address), &today, amount)
+            .await?;
+        if let Some(function_id) = &request.function_id {
+            self.storage
+                .add_spending(&function_scope(function_id), &today, amount)
+                .await?;
+        }
+
+        // An approval covers a single payment
+        if let Some(approval_id) = &request.approval_id {
+            if let Some(mut approval) = self.storage.get_approval(approval_id).await? {
+                approval.status = ApprovalStatus::Used;
+                approval.updated_at = Utc::now().timestamp() as u64;
+                self.storage.put_approval(approval).await?;
+            }
+        }
+
         Ok(transaction)
     }
 
+    async fn approve_spend(
+        &self,
+        approval_id: &str,
+        approver: &str,
+    ) -> Result<SpendApproval, Error> {
+        let _guard = self.spend_lock.lock().await;
+        let mut approval = self.pending_approval(approval_id, approver).await?;
+
+        if !approval.approved_by.iter().any(|a| a == approver) {
+            approval.approved_by.push(approver.to_string());
+        }
+        if approval.approved_by.len() >= approval.required_approvals {
+            approval.status = ApprovalStatus::Approved;
+            info!(
+                "Spend approval {} approved by {:?}",
+                approval.id, approval.approved_by
+            );
+        }
+        approval.updated_at = Utc::now().timestamp() as u64;
+
+        self.storage.put_approval(approval.clone()).await?;
+        Ok(approval)
+    }
+
+    async fn reject_spend(
+        &self,
+        approval_id: &str,
+        approver: &str,
+        reason: Option<String>,
+    ) -> Result<SpendApproval, Error> {
+        let _guard = self.spend_lock.lock().await;
+        let mut approval = self.pending_approval(approval_id, approver).await?;
+
+        approval.status = ApprovalStatus::Rejected;
+        approval.rejected_by = Some(approver.to_string());
+        approval.reason = reason;
+        approval.updated_at = Utc::now().timestamp() as u64;
+        info!("Spend approval {} rejected by {}", approval.id, approver);
+
+        self.storage.put_approval(approval.clone()).await?;
+        Ok(approval)
+    }
+
+    async fn get_pending_approvals(&self) -> Result<Vec<SpendApproval>, Error> {
+        let now = Utc::now().timestamp() as u64;
+        Ok(self
+            .storage
+            .get_approvals_by_status(&ApprovalStatus::Pending)
+            .await?
+            .into_iter()
+            .filter(|approval| approval.expires_at >= now)
+            .collect())
+    }
+
     async fn get_gas_price(&self) -> Result<u64, Error> {
-        // Use a default gas price since we can't easily retrieve it from the Neo blockchain
-        // In a real implementation, this would be retrieved from the network or configuration
+        // The fee per byte is the closest Neo has to a gas price
+        if let Some(chain_state) = &self.chain_state {
+            return Ok(chain_state.get().await?.fee_per_byte);
+        }
+
         Ok(1000) // Default gas price
     }
 
@@ -531,3 +868,222 @@ impl GasBankServiceTrait for GasBankService {
         Ok(transaction)
     }
 }
+
+/// Spending scope of a user
+fn user_scope(address: &str) -> String {
+    format!("user:{}", address)
+}
+
+/// Spending scope of a function
+fn function_scope(function_id: &str) -> String {
+    format!("function:{}", function_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gas_bank::storage::InMemoryGasBankStorage;
+    use crate::signer::LocalSigner;
+    use std::collections::HashMap;
+
+    const USER: &str = "NUser";
+
+    /// Gas bank charging a fee of 10 per payment, with 10000 deposited for `USER`
+    async fn setup(policy: SpendingPolicy) -> (GasBankService, Arc<InMemoryGasBankStorage>) {
+        let storage = Arc::new(InMemoryGasBankStorage::new());
+        let service = GasBankService::new(
+            storage.clone(),
+            Arc::new(RpcClient::new(
+                HttpProvider::new("http://127.0.0.1:1").unwrap(),
+            )),
+            Arc::new(LocalSigner::from_hex(&"01".repeat(32)).unwrap()),
+            "testnet".to_string(),
+            FeeModel::Fixed(10),
+            0,
+        )
+        .with_spending_policy(policy);
+        service.deposit("0xdeposit", USER, 10_000).await.unwrap();
+        (service, storage)
+    }
+
+    fn spend(address: &str, function_id: Option<&str>, amount: u64) -> SpendRequest {
+        SpendRequest {
+            address: address.to_string(),
+            function_id: function_id.map(str::to_string),
+            amount,
+            approval_id: None,
+        }
+    }
+
+    fn limit(daily: Option<u64>, monthly: Option<u64>) -> SpendingLimit {
+        SpendingLimit { daily, monthly }
+    }
+
+    #[tokio::test]
+    async fn test_spending_limits() {
+        let policy = SpendingPolicy {
+            default_user_limit: limit(Some(1_000), None),
+            user_limits: HashMap::from([
+                ("NUnlimited".to_string(), limit(None, None)),
+                ("NMonthly".to_string(), limit(None, Some(100))),
+            ]),
+            function_limits: HashMap::from([("function".to_string(), limit(Some(300), None))]),
+            ..SpendingPolicy::default()
+        };
+        let (service, _) = setup(policy).await;
+
+        // Payments count against the daily limit of the user, fees aside
+        let payment = service
+            .pay_gas_for_spend("0x1", &spend(USER, None, 600))
+            .await
+            .unwrap();
+        assert_eq!(payment.fee, 10);
+        assert_eq!(service.get_balance(USER).await.unwrap(), 9_390);
+        assert!(matches!(
+            service
+                .pay_gas_for_spend("0x2", &spend(USER, None, 500))
+                .await,
+            Err(Error::GasBankError(_))
+        ));
+        assert!(matches!(
+            service.authorize_spend(&spend(USER, None, 500)).await,
+            Err(Error::GasBankError(_))
+        ));
+        assert!(matches!(
+            service.authorize_spend(&spend(USER, None, 400)).await,
+            Ok(SpendDecision::Allowed)
+        ));
+        assert_eq!(service.get_balance(USER).await.unwrap(), 9_390);
+
+        // And against the limit of the function they pay for
+        service
+            .pay_gas_for_spend("0x3", &spend(USER, Some("function"), 200))
+            .await
+            .unwrap();
+        assert!(service
+            .pay_gas_for_spend("0x4", &spend(USER, Some("function"), 150))
+            .await
+            .is_err());
+        service
+            .pay_gas_for_spend("0x5", &spend(USER, Some("other"), 150))
+            .await
+            .unwrap();
+
+        // Users may have limits of their own
+        service.deposit("0xd1", "NUnlimited", 10_000).await.unwrap();
+        service
+            .pay_gas_for_spend("0x6", &spend("NUnlimited", None, 5_000))
+            .await
+            .unwrap();
+        service.deposit("0xd2", "NMonthly", 10_000).await.unwrap();
+        service
+            .pay_gas_for_spend("0x7", &spend("NMonthly", None, 60))
+            .await
+            .unwrap();
+        assert!(service
+            .pay_gas_for_spend("0x8", &spend("NMonthly", None, 60))
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_spend_approval() {
+        let policy = SpendingPolicy {
+            approval_threshold: Some(1_000),
+            approvers: vec!["NApprover1".to_string(), "NApprover2".to_string()],
+            required_approvals: 2,
+            ..SpendingPolicy::default()
+        };
+        let (service, _) = setup(policy).await;
+
+        assert!(matches!(
+            service.authorize_spend(&spend(USER, None, 1_000)).await,
+            Ok(SpendDecision::Allowed)
+        ));
+        let SpendDecision::ApprovalRequired { approval } = service
+            .authorize_spend(&spend(USER, Some("function"), 2_000))
+            .await
+            .unwrap()
+        else {
+            panic!("payment above the threshold allowed");
+        };
+        assert_eq!(approval.status, ApprovalStatus::Pending);
+        assert_eq!(approval.required_approvals, 2);
+        assert_eq!(service.get_pending_approvals().await.unwrap().len(), 1);
+
+        // Every required approver has to approve, once
+        assert!(matches!(
+            service.approve_spend(&approval.id, USER).await,
+            Err(Error::AuthError(_))
+        ));
+        for _ in 0..2 {
+            let pending = service
+                .approve_spend(&approval.id, "NApprover1")
+                .await
+                .unwrap();
+            assert_eq!(pending.status, ApprovalStatus::Pending);
+            assert_eq!(pending.approved_by.len(), 1);
+        }
+        let approved = service
+            .approve_spend(&approval.id, "NApprover2")
+            .await
+            .unwrap();
+        assert_eq!(approved.status, ApprovalStatus::Approved);
+
+        // The approval covers the payment it was requested for, once
+        let mut payment = spend(USER, Some("function"), 2_000);
+        payment.approval_id = Some(approval.id.clone());
+        let mut larger = payment.clone();
+        larger.amount = 2_001;
+        let mut other_function = payment.clone();
+        other_function.function_id = None;
+        for uncovered in [&larger, &other_function] {
+            assert!(service.pay_gas_for_spend("0x1", uncovered).await.is_err());
+        }
+        service.pay_gas_for_spend("0x2", &payment).await.unwrap();
+        assert_eq!(service.get_balance(USER).await.unwrap(), 7_990);
+        assert!(service.pay_gas_for_spend("0x3", &payment).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_rejected_and_expired_approvals() {
+        let policy = SpendingPolicy {
+            approval_threshold: Some(1_000),
+            approvers: vec!["NApprover".to_string()],
+            ..SpendingPolicy::default()
+        };
+        let (service, storage) = setup(policy).await;
+
+        let SpendDecision::ApprovalRequired { approval } = service
+            .authorize_spend(&spend(USER, None, 2_000))
+            .await
+            .unwrap()
+        else {
+            panic!("payment above the threshold allowed");
+        };
+        let rejected = service
+            .reject_spend(&approval.id, "NApprover", Some("too much".to_string()))
+            .await
+            .unwrap();
+        assert_eq!(rejected.status, ApprovalStatus::Rejected);
+        assert_eq!(rejected.rejected_by.as_deref(), Some("NApprover"));
+        assert!(service.get_pending_approvals().await.unwrap().is_empty());
+        assert!(service
+            .approve_spend(&approval.id, "NApprover")
+            .await
+            .is_err());
+        let mut payment = spend(USER, None, 2_000);
+        payment.approval_id = Some(approval.id.clone());
+        assert!(service.pay_gas_for_spend("0x1", &payment).await.is_err());
+
+        // An approval is not used past its expiry
+        let mut expired = approval.clone();
+        expired.id = "expired".to_string();
+        expired.status = ApprovalStatus::Approved;
+        expired.expires_at = expired.created_at - 1;
+        storage.put_approval(expired).await.unwrap();
+        payment.approval_id = Some("expired".to_string());
+        assert!(service.pay_gas_for_spend("0x2", &payment).await.is_err());
+        assert_eq!(service.get_balance(USER).await.unwrap(), 10_000);
+    }
+}
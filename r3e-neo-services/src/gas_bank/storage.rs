@@ -1,7 +1,10 @@
 // Copyright @ 2023 - 2024, R3E Network
 // All Rights Reserved
 
-use super::types::{GasBankAccount, GasBankDeposit, GasBankTransaction, GasBankWithdrawal};
+use super::types::{
+    ApprovalStatus, GasBankAccount, GasBankDeposit, GasBankTransaction, GasBankWithdrawal,
+    SpendApproval,
+};
 use crate::Error;
 use async_trait::async_trait;
 
@@ -47,6 +50,24 @@ pub trait GasBankStorage: Send + Sync {
         contract_hash: &str,
         address: &str,
     ) -> Result<(), Error>;
+
+    /// Get the amount spent in a spending scope between two UTC days (`YYYY-MM-DD`), inclusive
+    async fn get_spending(&self, scope: &str, from_day: &str, to_day: &str) -> Result<u64, Error>;
+
+    /// Add to the amount spent in a spending scope on a UTC day
+    async fn add_spending(&self, scope: &str, day: &str, amount: u64) -> Result<(), Error>;
+
+    /// Get spend approval
+    async fn get_approval(&self, id: &str) -> Result<Option<SpendApproval>, Error>;
+
+    /// Create or update spend approval
+    async fn put_approval(&self, approval: SpendApproval) -> Result<(), Error>;
+
+    /// Get spend approvals by status
+    async fn get_approvals_by_status(
+        &self,
+        status: &ApprovalStatus,
+    ) -> Result<Vec<SpendApproval>, Error>;
 }
 
 /// In-memory gas bank storage implementation
@@ -56,6 +77,8 @@ pub struct InMemoryGasBankStorage {
     withdrawals: tokio::sync::RwLock<Vec<GasBankWithdrawal>>,
     transactions: tokio::sync::RwLock<Vec<GasBankTransaction>>,
     contract_mappings: tokio::sync::RwLock<std::collections::HashMap<String, String>>,
+    spending: tokio::sync::RwLock<std::collections::HashMap<(String, String), u64>>,
+    approvals: tokio::sync::RwLock<std::collections::HashMap<String, SpendApproval>>,
 }
 
 impl InMemoryGasBankStorage {
@@ -67,6 +90,8 @@ impl InMemoryGasBankStorage {
             withdrawals: tokio::sync::RwLock::new(Vec::new()),
             transactions: tokio::sync::RwLock::new(Vec::new()),
             contract_mappings: tokio::sync::RwLock::new(std::collections::HashMap::new()),
+            spending: tokio::sync::RwLock::new(std::collections::HashMap::new()),
+            approvals: tokio::sync::RwLock::new(std::collections::HashMap::new()),
         }
     }
 }
@@ -165,4 +190,46 @@ impl GasBankStorage for InMemoryGasBankStorage {
         mappings.insert(contract_hash.to_string(), address.to_string());
         Ok(())
     }
+
+    async fn get_spending(&self, scope: &str, from_day: &str, to_day: &str) -> Result<u64, Error> {
+        let spending = self.spending.read().await;
+        Ok(spending
+            .iter()
+            .filter(|((s, day), _)| {
+                s == scope && day.as_str() >= from_day && day.as_str() <= to_day
+            })
+            .map(|(_, amount)| *amount)
+            .sum())
+    }
+
+    async fn add_spending(&self, scope: &str, day: &str, amount: u64) -> Result<(), Error> {
+        let mut spending = self.spending.write().await;
+        *spending
+            .entry((scope.to_string(), day.to_string()))
+            .or_insert(0) += amount;
+        Ok(())
+    }
+
+    async fn get_approval(&self, id: &str) -> Result<Option<SpendApproval>, Error> {
+        let approvals = self.approvals.read().await;
+        Ok(approvals.get(id).cloned())
+    }
+
+    async fn put_approval(&self, approval: SpendApproval) -> Result<(), Error> {
+        let mut approvals = self.approvals.write().await;
+        approvals.insert(approval.id.clone(), approval);
+        Ok(())
+    }
+
+    async fn get_approvals_by_status(
+        &self,
+        status: &ApprovalStatus,
+    ) -> Result<Vec<SpendApproval>, Error> {
+        let approvals = self.approvals.read().await;
+        Ok(approvals
+            .values()
+            .filter(|a| &a.status == status)
+            .cloned()
+            .collect())
+    }
 }
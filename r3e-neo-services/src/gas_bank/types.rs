@@ -3,6 +3,7 @@
 
 use crate::types::FeeModel;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 
 /// Gas bank account
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -72,4 +73,123 @@ pub struct GasBankTransaction {
     pub timestamp: u64,
     /// Status
     pub status: String,
+    /// Function the gas was paid for
+    #[serde(default)]
+    pub function_id: Option<String>,
+}
+
+/// Spending cap, unset periods are unlimited
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SpendingLimit {
+    /// Gas spendable per UTC day
+    pub daily: Option<u64>,
+    /// Gas spendable per UTC calendar month
+    pub monthly: Option<u64>,
+}
+
+/// Gas bank spending policy
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SpendingPolicy {
+    /// Limit of users without a limit of their own
+    #[serde(default)]
+    pub default_user_limit: SpendingLimit,
+    /// Limits by user address
+    #[serde(default)]
+    pub user_limits: HashMap<String, SpendingLimit>,
+    /// Limits by function ID
+    #[serde(default)]
+    pub function_limits: HashMap<String, SpendingLimit>,
+    /// Payments above this amount need approval before the relayer signs
+    #[serde(default)]
+    pub approval_threshold: Option<u64>,
+    /// Addresses allowed to approve payments
+    #[serde(default)]
+    pub approvers: Vec<String>,
+    /// Approvals needed, more than one makes approval multi-sig
+    pub required_approvals: usize,
+    /// Seconds an approval request stays valid
+    pub approval_ttl: u64,
+}
+
+impl Default for SpendingPolicy {
+    fn default() -> Self {
+        Self {
+            default_user_limit: SpendingLimit::default(),
+            user_limits: HashMap::new(),
+            function_limits: HashMap::new(),
+            approval_threshold: None,
+            approvers: Vec::new(),
+            required_approvals: 1,
+            approval_ttl: 24 * 60 * 60,
+        }
+    }
+}
+
+/// Gas payment to authorize
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SpendRequest {
+    /// User address
+    pub address: String,
+    /// Function the gas is paid for
+    pub function_id: Option<String>,
+    /// Gas amount
+    pub amount: u64,
+    /// Approval granted for this payment
+    pub approval_id: Option<String>,
+}
+
+/// Outcome of authorizing a gas payment
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "decision", rename_all = "snake_case")]
+pub enum SpendDecision {
+    /// Payment within limits
+    Allowed,
+    /// Payment above the approval threshold, retry with the approval ID once approved
+    ApprovalRequired {
+        /// Created approval request
+        approval: SpendApproval,
+    },
+}
+
+/// Approval request status
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ApprovalStatus {
+    /// Waiting for approvers
+    Pending,
+    /// Approved, not yet spent
+    Approved,
+    /// Rejected by an approver
+    Rejected,
+    /// Spent by a payment
+    Used,
+}
+
+/// Approval request for a payment above the approval threshold
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SpendApproval {
+    /// Approval ID
+    pub id: String,
+    /// User address
+    pub address: String,
+    /// Function the gas is paid for
+    pub function_id: Option<String>,
+    /// Gas amount
+    pub amount: u64,
+    /// Status
+    pub status: ApprovalStatus,
+    /// Approvals needed
+    pub required_approvals: usize,
+    /// Approvers who approved
+    pub approved_by: Vec<String>,
+    /// Approver who rejected
+    pub rejected_by: Option<String>,
+    /// Rejection reason
+    pub reason: Option<String>,
+    /// Created timestamp
+    pub created_at: u64,
+    /// Expiry timestamp
+    pub expires_at: u64,
+    /// Last updated timestamp
+    pub updated_at: u64,
 }
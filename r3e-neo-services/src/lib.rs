@@ -2,10 +2,17 @@
 // All Rights Reserved
 
 pub mod abstract_account;
+pub mod chain_state;
+pub mod deploy;
 pub mod error;
+pub mod fee;
 pub mod gas_bank;
 pub mod meta_tx;
+pub mod mpc;
+pub mod nft;
+pub mod signer;
 pub mod types;
+pub mod wallet;
 
 pub use error::Error;
 pub use types::*;
@@ -0,0 +1,562 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+//! Trusted forwarder of Ethereum meta transactions.
+//!
+//! Users sign a meta transaction with EIP-712 for the forwarder of its network, and relayers
+//! trusted by the forwarder submit it with `execute`, which calls the target with the signer
+//! appended to the calldata as ERC-2771 recipients expect. The forwarder tracks the last nonce
+//! of each signer, so a meta transaction is executed once.
+
+use super::types::MetaTxRequest;
+use crate::error::Error;
+use ethers::abi::{encode, Token};
+use ethers::contract::{abigen, ContractCall, ContractFactory};
+use ethers::middleware::SignerMiddleware;
+use ethers::providers::{Http, Middleware, Provider};
+use ethers::signers::{LocalWallet, Signer};
+use ethers::types::{Address, Bytes, Signature, H256, U256};
+use ethers::utils::keccak256;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Arc;
+
+abigen!(R3EForwarder, "./contracts/R3EForwarder.abi.json");
+
+/// Solidity source of the forwarder
+pub const FORWARDER_SOURCE: &str = include_str!("../../contracts/R3EForwarder.sol");
+
+/// ABI of the forwarder
+pub const FORWARDER_ABI: &str = include_str!("../../contracts/R3EForwarder.abi.json");
+
+/// Hex creation bytecode of the forwarder, built by `make forwarder`
+#[cfg(feature = "embedded-forwarder")]
+pub const FORWARDER_BYTECODE: Option<&str> =
+    Some(include_str!("../../contracts/build/R3EForwarder.bin"));
+
+/// Hex creation bytecode of the forwarder, only embedded with the `embedded-forwarder` feature
+#[cfg(not(feature = "embedded-forwarder"))]
+pub const FORWARDER_BYTECODE: Option<&str> = None;
+
+/// EIP-712 domain name of the forwarder
+pub const FORWARDER_DOMAIN_NAME: &str = "R3E Meta Transaction";
+
+/// EIP-712 domain version of the forwarder
+pub const FORWARDER_DOMAIN_VERSION: &str = "1";
+
+const DOMAIN_TYPE: &str = "EIP712Domain(string name,string version,uint256 chainId,address verifyingContract,bytes32 salt)";
+const META_TX_TYPE: &str = "MetaTransaction(address from,address to,bytes data,uint256 nonce,uint256 deadline,string fee_model,uint256 fee_amount)";
+
+/// Ethereum client signing with a local key
+pub type SignerClient = SignerMiddleware<Provider<Http>, LocalWallet>;
+
+/// Forwarder of an Ethereum network
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ForwarderNetwork {
+    /// Network name, e.g. `mainnet` or `sepolia`
+    pub network: String,
+
+    /// EIP-155 chain ID
+    pub chain_id: u64,
+
+    /// JSON-RPC endpoint the forwarder is deployed and configured through
+    pub rpc_url: String,
+
+    /// Address of the deployed forwarder, None until it is deployed
+    #[serde(default)]
+    pub address: Option<String>,
+
+    /// Relayers the forwarder trusts
+    #[serde(default)]
+    pub relayers: Vec<String>,
+}
+
+impl ForwarderNetwork {
+    /// Record relayers trusted and distrusted by the forwarder
+    pub fn update_relayers(&mut self, add: &[Address], remove: &[Address]) {
+        self.relayers
+            .retain(|r| parse_address(r).map_or(true, |relayer| !remove.contains(&relayer)));
+        for relayer in add {
+            let relayer = format!("{:?}", relayer);
+            if !self.relayers.contains(&relayer) {
+                self.relayers.push(relayer);
+            }
+        }
+    }
+}
+
+/// Forwarders per Ethereum network, kept in a JSON file the deployment tooling updates
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ForwarderConfig {
+    pub networks: Vec<ForwarderNetwork>,
+}
+
+impl ForwarderConfig {
+    /// Load the forwarders from a file, none if it does not exist yet
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let path = path.as_ref();
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let data = std::fs::read(path)
+            .map_err(|e| Error::ConfigError(format!("Failed to read forwarders: {}", e)))?;
+        serde_json::from_slice(&data).map_err(|e| {
+            Error::ConfigError(format!("Invalid forwarders {}: {}", path.display(), e))
+        })
+    }
+
+    /// Save the forwarders to a file
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), Error> {
+        let data = serde_json::to_vec_pretty(self)
+            .map_err(|e| Error::Serialization(format!("Failed to encode forwarders: {}", e)))?;
+        std::fs::write(path, data)
+            .map_err(|e| Error::ConfigError(format!("Failed to write forwarders: {}", e)))
+    }
+
+    /// Forwarder of a network by name
+    pub fn network(&self, network: &str) -> Option<&ForwarderNetwork> {
+        self.networks.iter().find(|n| n.network == network)
+    }
+
+    /// Add or replace the forwarder of a network
+    pub fn upsert(&mut self, network: ForwarderNetwork) {
+        match self
+            .networks
+            .iter_mut()
+            .find(|n| n.network == network.network)
+        {
+            Some(existing) => *existing = network,
+            None => self.networks.push(network),
+        }
+    }
+
+    /// Address of the forwarder deployed on a chain, if any
+    pub fn address(&self, chain_id: u64) -> Result<Option<Address>, Error> {
+        self.networks
+            .iter()
+            .find(|n| n.chain_id == chain_id)
+            .and_then(|n| n.address.as_deref())
+            .map(parse_address)
+            .transpose()
+    }
+}
+
+/// Parse a hex Ethereum address
+pub fn parse_address(address: &str) -> Result<Address, Error> {
+    Address::from_str(address)
+        .map_err(|e| Error::InvalidParameter(format!("Invalid address {}: {}", address, e)))
+}
+
+/// Creation bytecode of the forwarder, read from a `solc --bin` output file, or the embedded
+/// bytecode without one
+pub fn load_bytecode(path: Option<&Path>) -> Result<Bytes, Error> {
+    let hex_code = match path {
+        Some(path) => std::fs::read_to_string(path).map_err(|e| {
+            Error::ConfigError(format!(
+                "Failed to read forwarder bytecode {}: {}",
+                path.display(),
+                e
+            ))
+        })?,
+        None => FORWARDER_BYTECODE
+            .ok_or_else(|| {
+                Error::ConfigError(
+                    "No forwarder bytecode embedded, build it with `make forwarder`".to_string(),
+                )
+            })?
+            .to_string(),
+    };
+
+    let code = hex::decode(hex_code.trim().trim_start_matches("0x"))
+        .map_err(|e| Error::ConfigError(format!("Invalid forwarder bytecode: {}", e)))?;
+    if code.is_empty() {
+        return Err(Error::ConfigError("Empty forwarder bytecode".to_string()));
+    }
+    Ok(code.into())
+}
+
+/// Client of a network signing with a hex private key
+pub fn signer_client(
+    network: &ForwarderNetwork,
+    private_key: &str,
+) -> Result<Arc<SignerClient>, Error> {
+    let provider = Provider::<Http>::try_from(network.rpc_url.as_str())
+        .map_err(|e| Error::ConfigError(format!("Invalid RPC URL {}: {}", network.rpc_url, e)))?;
+    let wallet = LocalWallet::from_str(private_key.trim_start_matches("0x"))
+        .map_err(|e| Error::WalletError(format!("Invalid private key: {}", e)))?
+        .with_chain_id(network.chain_id);
+    Ok(Arc::new(SignerMiddleware::new(provider, wallet)))
+}
+
+/// Deploy a forwarder trusting the given relayers, owned by the deployer
+pub async fn deploy_forwarder(
+    client: Arc<SignerClient>,
+    bytecode: Bytes,
+    relayers: Vec<Address>,
+) -> Result<Address, Error> {
+    let factory = ContractFactory::new(R3EFORWARDER_ABI.clone(), bytecode, client);
+    let contract = factory
+        .deploy(relayers)
+        .map_err(|e| Error::ContractError(format!("Failed to encode forwarder deployment: {}", e)))?
+        .send()
+        .await
+        .map_err(|e| Error::ContractError(format!("Failed to deploy forwarder: {}", e)))?;
+    Ok(contract.address())
+}
+
+/// Trust or distrust relayers, skipping those already set. Returns the relayers updated.
+pub async fn set_relayers(
+    client: Arc<SignerClient>,
+    forwarder: Address,
+    relayers: &[Address],
+    trusted: bool,
+) -> Result<Vec<Address>, Error> {
+    let contract = R3EForwarder::new(forwarder, client);
+
+    let mut updated = Vec::new();
+    for relayer in relayers {
+        let current = contract
+            .trusted_relayers(*relayer)
+            .call()
+            .await
+            .map_err(|e| Error::ContractError(format!("Failed to read relayer: {}", e)))?;
+        if current == trusted {
+            continue;
+        }
+
+        contract
+            .set_relayer(*relayer, trusted)
+            .send()
+            .await
+            .map_err(|e| Error::ContractError(format!("Failed to set relayer: {}", e)))?
+            .await
+            .map_err(|e| Error::ContractError(format!("Failed to confirm relayer: {}", e)))?;
+        updated.push(*relayer);
+    }
+
+    Ok(updated)
+}
+
+/// Meta transaction of a request, as the forwarder executes it
+pub fn meta_transaction(request: &MetaTxRequest) -> Result<MetaTransaction, Error> {
+    let data = hex::decode(request.tx_data.trim_start_matches("0x"))
+        .map_err(|e| Error::InvalidParameter(format!("Invalid hex transaction data: {}", e)))?;
+
+    Ok(MetaTransaction {
+        from: parse_address(&request.sender)?,
+        to: parse_address(&request.target_address)?,
+        data: data.into(),
+        nonce: U256::from(request.nonce),
+        deadline: U256::from(request.deadline),
+        fee_model: request.fee_model.clone().unwrap_or_default(),
+        fee_amount: U256::from(request.fee_amount),
+    })
+}
+
+/// EIP-712 digest the forwarder deployed at `forwarder` on `chain_id` checks signatures against
+pub fn forwarder_digest(chain_id: u64, forwarder: Address, tx: &MetaTransaction) -> [u8; 32] {
+    let domain = keccak256(encode(&[
+        Token::FixedBytes(keccak256(DOMAIN_TYPE).to_vec()),
+        Token::FixedBytes(keccak256(FORWARDER_DOMAIN_NAME).to_vec()),
+        Token::FixedBytes(keccak256(FORWARDER_DOMAIN_VERSION).to_vec()),
+        Token::Uint(U256::from(chain_id)),
+        Token::Address(forwarder),
+        Token::FixedBytes(vec![0; 32]),
+    ]));
+    let message = keccak256(encode(&[
+        Token::FixedBytes(keccak256(META_TX_TYPE).to_vec()),
+        Token::Address(tx.from),
+        Token::Address(tx.to),
+        Token::FixedBytes(keccak256(&tx.data).to_vec()),
+        Token::Uint(tx.nonce),
+        Token::Uint(tx.deadline),
+        Token::FixedBytes(keccak256(tx.fee_model.as_bytes()).to_vec()),
+        Token::Uint(tx.fee_amount),
+    ]));
+
+    let mut digest = Vec::with_capacity(66);
+    digest.extend_from_slice(b"\x19\x01");
+    digest.extend_from_slice(&domain);
+    digest.extend_from_slice(&message);
+    keccak256(digest)
+}
+
+/// Whether a hex signature of a meta transaction was made by its sender for a forwarder
+pub fn verify_forwarder_signature(
+    chain_id: u64,
+    forwarder: Address,
+    tx: &MetaTransaction,
+    signature: &str,
+) -> Result<bool, Error> {
+    let signature = Signature::from_str(signature.trim_start_matches("0x"))
+        .map_err(|e| Error::InvalidSignature(format!("Invalid signature: {}", e)))?;
+    let digest = H256::from(forwarder_digest(chain_id, forwarder, tx));
+    Ok(signature
+        .recover(digest)
+        .is_ok_and(|signer| signer == tx.from))
+}
+
+/// Typed client of a deployed forwarder, the relayer checks and executes meta transactions with
+pub struct ForwarderClient<M> {
+    contract: R3EForwarder<M>,
+}
+
+impl<M: Middleware + 'static> ForwarderClient<M> {
+    /// Client of the forwarder at an address
+    pub fn new(forwarder: Address, client: Arc<M>) -> Self {
+        Self {
+            contract: R3EForwarder::new(forwarder, client),
+        }
+    }
+
+    /// Address of the forwarder
+    pub fn address(&self) -> Address {
+        self.contract.address()
+    }
+
+    /// Next nonce a signer must use
+    pub async fn next_nonce(&self, from: Address) -> Result<u64, Error> {
+        let last =
+            self.contract.get_nonce(from).call().await.map_err(|e| {
+                Error::ContractError(format!("Failed to read forwarder nonce: {}", e))
+            })?;
+        Ok(last.as_u64() + 1)
+    }
+
+    /// Whether the forwarder would execute a meta transaction with a signature
+    pub async fn verify(&self, tx: &MetaTransaction, signature: Bytes) -> Result<bool, Error> {
+        self.contract
+            .verify(tx.clone(), signature)
+            .call()
+            .await
+            .map_err(|e| Error::ContractError(format!("Failed to verify meta transaction: {}", e)))
+    }
+
+    /// Call executing a meta transaction, sent by a trusted relayer
+    pub fn execute(
+        &self,
+        tx: &MetaTransaction,
+        signature: Bytes,
+    ) -> ContractCall<M, (bool, Bytes)> {
+        self.contract.execute(tx.clone(), signature)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::types::transaction::eip712::{Eip712, TypedData};
+    use serde_json::json;
+
+    const CHAIN_ID: u64 = 11_155_111;
+    const TARGET: &str = "0x00000000000000000000000000000000000000aa";
+
+    fn forwarder() -> Address {
+        parse_address("0x00000000000000000000000000000000000000ff").unwrap()
+    }
+
+    fn wallet() -> LocalWallet {
+        LocalWallet::from_str(&"01".repeat(32)).unwrap()
+    }
+
+    fn request(sender: Address) -> MetaTxRequest {
+        serde_json::from_value(json!({
+            "tx_data": "0xa9059cbb",
+            "sender": format!("{:?}", sender),
+            "target_address": TARGET,
+            "signature": "",
+            "nonce": 7,
+            "deadline": 1_700_000_000u64,
+            "fee_amount": 1_000,
+            "timestamp": 1_699_999_000u64,
+            "blockchain_type": "ethereum",
+            "signature_curve": "secp256k1",
+            "chain_id": CHAIN_ID,
+            "fee_model": "fixed:1000",
+        }))
+        .unwrap()
+    }
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("r3e-forwarder-{}-{}", std::process::id(), name))
+    }
+
+    #[test]
+    fn test_forwarder_config() {
+        let path = temp_path("forwarders.json");
+        let mut config = ForwarderConfig::load(&path).unwrap();
+        assert!(config.networks.is_empty());
+
+        let mut sepolia = ForwarderNetwork {
+            network: "sepolia".to_string(),
+            chain_id: CHAIN_ID,
+            rpc_url: "https://sepolia.example.com".to_string(),
+            address: None,
+            relayers: Vec::new(),
+        };
+        config.upsert(sepolia.clone());
+        assert_eq!(config.address(CHAIN_ID).unwrap(), None);
+
+        // Deploying replaces the network
+        sepolia.address = Some(format!("{:?}", forwarder()));
+        config.upsert(sepolia);
+        assert_eq!(config.networks.len(), 1);
+        config.save(&path).unwrap();
+
+        let config = ForwarderConfig::load(&path).unwrap();
+        assert_eq!(config.network("sepolia").unwrap().chain_id, CHAIN_ID);
+        assert!(config.network("mainnet").is_none());
+        assert_eq!(config.address(CHAIN_ID).unwrap(), Some(forwarder()));
+        assert_eq!(config.address(1).unwrap(), None);
+
+        std::fs::write(&path, "{ \"networks\": [").unwrap();
+        assert!(matches!(
+            ForwarderConfig::load(&path),
+            Err(Error::ConfigError(_))
+        ));
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(parse_address("0x1234").is_err());
+    }
+
+    #[test]
+    fn test_update_relayers() {
+        let relayer = |n: u8| Address::from_low_u64_be(u64::from(n));
+        let mut network = ForwarderNetwork {
+            network: "sepolia".to_string(),
+            chain_id: CHAIN_ID,
+            rpc_url: "https://sepolia.example.com".to_string(),
+            address: Some(format!("{:?}", forwarder())),
+            relayers: vec![
+                format!("{:?}", relayer(0xa1)),
+                format!("{:?}", relayer(0xb2)),
+            ],
+        };
+
+        network.update_relayers(&[relayer(0xb2), relayer(0xc3)], &[relayer(0xa1)]);
+        assert_eq!(
+            network.relayers,
+            vec![
+                format!("{:?}", relayer(0xb2)),
+                format!("{:?}", relayer(0xc3))
+            ]
+        );
+
+        // Removal matches addresses whatever their case
+        network.relayers[0] = network.relayers[0].to_uppercase().replacen("0X", "0x", 1);
+        network.update_relayers(&[], &[relayer(0xb2)]);
+        assert_eq!(network.relayers, vec![format!("{:?}", relayer(0xc3))]);
+    }
+
+    #[test]
+    fn test_load_bytecode() {
+        let path = temp_path("R3EForwarder.bin");
+        std::fs::write(&path, "0x6080604052\n").unwrap();
+        assert_eq!(
+            load_bytecode(Some(&path)).unwrap().to_vec(),
+            vec![0x60, 0x80, 0x60, 0x40, 0x52]
+        );
+
+        for invalid in ["", "not hex"] {
+            std::fs::write(&path, invalid).unwrap();
+            assert!(load_bytecode(Some(&path)).is_err());
+        }
+        std::fs::remove_file(&path).unwrap();
+        assert!(load_bytecode(Some(&path)).is_err());
+
+        #[cfg(not(feature = "embedded-forwarder"))]
+        assert!(matches!(load_bytecode(None), Err(Error::ConfigError(_))));
+    }
+
+    #[test]
+    fn test_meta_transaction() {
+        let sender = wallet().address();
+        let tx = meta_transaction(&request(sender)).unwrap();
+        assert_eq!(tx.from, sender);
+        assert_eq!(tx.to, parse_address(TARGET).unwrap());
+        assert_eq!(tx.data.to_vec(), vec![0xa9, 0x05, 0x9c, 0xbb]);
+        assert_eq!(tx.nonce, U256::from(7));
+        assert_eq!(tx.fee_model, "fixed:1000");
+        assert_eq!(tx.fee_amount, U256::from(1_000));
+
+        let mut invalid = request(sender);
+        invalid.tx_data = "0xzz".to_string();
+        assert!(meta_transaction(&invalid).is_err());
+    }
+
+    #[test]
+    fn test_forwarder_digest() {
+        // The digest is the EIP-712 hash of the typed data the forwarder checks
+        let tx = meta_transaction(&request(wallet().address())).unwrap();
+        let typed_data: TypedData = serde_json::from_value(json!({
+            "types": {
+                "EIP712Domain": [
+                    { "name": "name", "type": "string" },
+                    { "name": "version", "type": "string" },
+                    { "name": "chainId", "type": "uint256" },
+                    { "name": "verifyingContract", "type": "address" },
+                    { "name": "salt", "type": "bytes32" },
+                ],
+                "MetaTransaction": [
+                    { "name": "from", "type": "address" },
+                    { "name": "to", "type": "address" },
+                    { "name": "data", "type": "bytes" },
+                    { "name": "nonce", "type": "uint256" },
+                    { "name": "deadline", "type": "uint256" },
+                    { "name": "fee_model", "type": "string" },
+                    { "name": "fee_amount", "type": "uint256" },
+                ],
+            },
+            "primaryType": "MetaTransaction",
+            "domain": {
+                "name": FORWARDER_DOMAIN_NAME,
+                "version": FORWARDER_DOMAIN_VERSION,
+                "chainId": CHAIN_ID,
+                "verifyingContract": format!("{:?}", forwarder()),
+                "salt": [0u8; 32],
+            },
+            "message": {
+                "from": format!("{:?}", tx.from),
+                "to": TARGET,
+                "data": "0xa9059cbb",
+                "nonce": 7,
+                "deadline": 1_700_000_000u64,
+                "fee_model": "fixed:1000",
+                "fee_amount": 1_000,
+            },
+        }))
+        .unwrap();
+
+        assert_eq!(
+            forwarder_digest(CHAIN_ID, forwarder(), &tx),
+            typed_data.encode_eip712().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_verify_forwarder_signature() {
+        let wallet = wallet();
+        let tx = meta_transaction(&request(wallet.address())).unwrap();
+        let digest = H256::from(forwarder_digest(CHAIN_ID, forwarder(), &tx));
+        let signature = format!("0x{}", wallet.sign_hash(digest).unwrap());
+
+        assert!(verify_forwarder_signature(CHAIN_ID, forwarder(), &tx, &signature).unwrap());
+
+        // Signed for another network or forwarder, or for other terms
+        assert!(!verify_forwarder_signature(1, forwarder(), &tx, &signature).unwrap());
+        let other_forwarder = parse_address(TARGET).unwrap();
+        assert!(!verify_forwarder_signature(CHAIN_ID, other_forwarder, &tx, &signature).unwrap());
+        let mut higher_fee = tx.clone();
+        higher_fee.fee_amount = U256::from(2_000);
+        assert!(
+            !verify_forwarder_signature(CHAIN_ID, forwarder(), &higher_fee, &signature).unwrap()
+        );
+
+        assert!(matches!(
+            verify_forwarder_signature(CHAIN_ID, forwarder(), &tx, "0x1234"),
+            Err(Error::InvalidSignature(_))
+        ));
+    }
+}
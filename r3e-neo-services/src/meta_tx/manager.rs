@@ -0,0 +1,860 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+use crate::chain_state::{ChainState, ChainStateCache};
+use crate::error::Error;
+use crate::meta_tx::neo_tx::{hash_to_display, NeoTransaction, CONFLICTS_ATTRIBUTE_SIZE};
+use crate::meta_tx::storage::MetaTxStorage;
+use crate::meta_tx::types::{BlockchainType, FeeBump, MetaTxRecord, MetaTxStatus};
+use crate::signer::{script_hash, sign_witness, RelayerSigner};
+use async_trait::async_trait;
+use base64::Engine;
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::task::JoinHandle;
+
+/// Minimum gas price increase Ethereum nodes accept for a replacement transaction
+pub const ETHEREUM_MIN_BUMP_PERCENT: u64 = 10;
+
+/// Transaction manager configuration
+#[derive(Debug, Clone)]
+pub struct TxManagerConfig {
+    /// Interval between polls of pending transactions
+    pub poll_interval: Duration,
+    /// Time without confirmation after which a transaction is rebroadcast
+    pub stuck_after: Duration,
+    /// Fee increase per rebroadcast in percent
+    pub bump_percent: u64,
+    /// Highest fee a rebroadcast may pay
+    pub max_fee: u64,
+    /// Maximum number of rebroadcasts before a transaction is marked stuck
+    pub max_bumps: usize,
+}
+
+impl Default for TxManagerConfig {
+    fn default() -> Self {
+        Self {
+            poll_interval: Duration::from_secs(15),
+            stuck_after: Duration::from_secs(60),
+            bump_percent: 20,
+            // 1 GAS
+            max_fee: 100_000_000,
+            max_bumps: 5,
+        }
+    }
+}
+
+/// On-chain status of a relayed transaction
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChainTxStatus {
+    /// Not yet included in a block
+    Pending,
+    /// Included in a block and executed successfully
+    Confirmed,
+    /// Included in a block but execution failed
+    Failed(String),
+}
+
+/// Rebroadcast transaction
+#[derive(Debug, Clone)]
+pub struct Rebroadcast {
+    /// Transaction hash
+    pub tx_hash: String,
+    /// Fee actually paid
+    pub fee: u64,
+}
+
+/// Chain specific access for the transaction manager
+#[async_trait]
+pub trait RelayBackend: Send + Sync {
+    /// Get the on-chain status of a transaction
+    async fn tx_status(&self, tx_hash: &str) -> Result<ChainTxStatus, Error>;
+
+    /// Fee paid by the originally relayed transaction
+    async fn original_fee(&self, record: &MetaTxRecord) -> Result<u64, Error>;
+
+    /// Rebroadcast the transaction with a higher fee, replacing the given transactions
+    async fn rebroadcast(
+        &self,
+        record: &MetaTxRecord,
+        replaces: &[String],
+        fee: u64,
+    ) -> Result<Rebroadcast, Error>;
+}
+
+/// Tracks relayed transactions, rebroadcasting them with a higher fee when they get stuck
+pub struct TxManager<S: MetaTxStorage + ?Sized> {
+    /// Storage
+    storage: Arc<S>,
+    /// Backends by blockchain
+    backends: HashMap<BlockchainType, Arc<dyn RelayBackend>>,
+    /// Configuration
+    config: TxManagerConfig,
+}
+
+impl<S: MetaTxStorage + ?Sized + 'static> TxManager<S> {
+    /// Create a new transaction manager
+    pub fn new(storage: Arc<S>, config: TxManagerConfig) -> Self {
+        Self {
+            storage,
+            backends: HashMap::new(),
+            config,
+        }
+    }
+
+    /// Manage transactions relayed to the given blockchain
+    pub fn with_backend(
+        mut self,
+        blockchain_type: BlockchainType,
+        backend: Arc<dyn RelayBackend>,
+    ) -> Self {
+        self.backends.insert(blockchain_type, backend);
+        self
+    }
+
+    /// Poll pending transactions every poll interval
+    pub fn spawn(self: Arc<Self>) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(self.config.poll_interval);
+            loop {
+                interval.tick().await;
+                if let Err(e) = self.poll_once().await {
+                    warn!("Failed to poll relayed transactions: {}", e);
+                }
+            }
+        })
+    }
+
+    /// Check every submitted or stuck transaction once
+    pub async fn poll_once(&self) -> Result<(), Error> {
+        let mut records = self
+            .storage
+            .get_records_by_status(&MetaTxStatus::Submitted)
+            .await?;
+        records.extend(
+            self.storage
+                .get_records_by_status(&MetaTxStatus::Stuck)
+                .await?,
+        );
+
+        for record in records {
+            let request_id = record.request_id.clone();
+            if let Err(e) = self.check(record).await {
+                warn!("Failed to check meta transaction {}: {}", request_id, e);
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn check(&self, mut record: MetaTxRecord) -> Result<(), Error> {
+        let backend = match self.backends.get(&record.request.blockchain_type) {
+            Some(backend) => backend.clone(),
+            None => return Ok(()),
+        };
+        let Some(response) = record.response.as_ref() else {
+            return Ok(());
+        };
+
+        // Any broadcast may be the one that lands, newest first
+        let mut hashes = vec![response.original_hash.clone()];
+        hashes.extend(record.fee_bumps.iter().map(|bump| bump.tx_hash.clone()));
+        for tx_hash in hashes.iter().rev() {
+            match backend.tx_status(tx_hash).await? {
+                ChainTxStatus::Pending => continue,
+                ChainTxStatus::Confirmed => {
+                    info!(
+                        "Meta transaction {} confirmed in {}",
+                        record.request_id, tx_hash
+                    );
+                    return self
+                        .finish(record, tx_hash, MetaTxStatus::Confirmed, None)
+                        .await;
+                }
+                ChainTxStatus::Failed(error) => {
+                    warn!(
+                        "Meta transaction {} failed in {}: {}",
+                        record.request_id, tx_hash, error
+                    );
+                    return self
+                        .finish(record, tx_hash, MetaTxStatus::Failed, Some(error))
+                        .await;
+                }
+            }
+        }
+
+        let now = chrono::Utc::now().timestamp() as u64;
+        if record.request.deadline > 0 && now > record.request.deadline {
+            info!("Meta transaction {} expired", record.request_id);
+            let tx_hash = hashes.last().cloned().unwrap_or_default();
+            return self
+                .finish(record, &tx_hash, MetaTxStatus::Expired, None)
+                .await;
+        }
+
+        if record.status == MetaTxStatus::Stuck {
+            return Ok(());
+        }
+
+        let last_broadcast = record
+            .fee_bumps
+            .last()
+            .map(|bump| bump.timestamp)
+            .unwrap_or(record.created_at);
+        if now < last_broadcast + self.config.stuck_after.as_secs() {
+            return Ok(());
+        }
+
+        let current_fee = match record.fee_bumps.last() {
+            Some(bump) => bump.fee,
+            None => backend.original_fee(&record).await?,
+        };
+        let fee = bumped_fee(
+            record.request.blockchain_type,
+            current_fee,
+            self.config.bump_percent,
+        );
+        if record.fee_bumps.len() >= self.config.max_bumps || fee > self.config.max_fee {
+            warn!(
+                "Meta transaction {} stuck after {} fee bumps",
+                record.request_id,
+                record.fee_bumps.len()
+            );
+            record.status = MetaTxStatus::Stuck;
+            return self.update(record, now).await;
+        }
+
+        let rebroadcast = backend.rebroadcast(&record, &hashes, fee).await?;
+        info!(
+            "Rebroadcast meta transaction {} as {} with fee {}",
+            record.request_id, rebroadcast.tx_hash, rebroadcast.fee
+        );
+        record.fee_bumps.push(FeeBump {
+            tx_hash: rebroadcast.tx_hash.clone(),
+            fee: rebroadcast.fee,
+            timestamp: now,
+        });
+        if let Some(response) = record.response.as_mut() {
+            response.relayed_hash = Some(rebroadcast.tx_hash);
+        }
+        self.update(record, now).await
+    }
+
+    async fn finish(
+        &self,
+        mut record: MetaTxRecord,
+        tx_hash: &str,
+        status: MetaTxStatus,
+        error: Option<String>,
+    ) -> Result<(), Error> {
+        let now = chrono::Utc::now().timestamp() as u64;
+        if let Some(response) = record.response.as_mut() {
+            response.relayed_hash = Some(tx_hash.to_string());
+            response.status = status.to_string();
+            response.error = error;
+            response.timestamp = now;
+        }
+        record.status = status;
+        self.update(record, now).await
+    }
+
+    async fn update(&self, mut record: MetaTxRecord, now: u64) -> Result<(), Error> {
+        debug!(
+            "Meta transaction {} is {}",
+            record.request_id,
+            record.status.to_string()
+        );
+        record.updated_at = now;
+        self.storage.update_record(record).await
+    }
+}
+
+/// Fee of a replacement transaction, at least one unit above the current fee
+pub fn bumped_fee(blockchain_type: BlockchainType, current_fee: u64, bump_percent: u64) -> u64 {
+    let percent = match blockchain_type {
+        BlockchainType::Ethereum => bump_percent.max(ETHEREUM_MIN_BUMP_PERCENT),
+        BlockchainType::NeoN3 => bump_percent,
+    };
+    let bumped = current_fee.saturating_mul(100 + percent) / 100;
+    bumped.max(current_fee.saturating_add(1))
+}
+
+/// Default network fee per transaction byte in datoshi
+const NEO_FEE_PER_BYTE: u64 = 1_000;
+
+/// Default execution fee factor of Neo N3
+const NEO_EXEC_FEE_FACTOR: u64 = 30;
+
+/// Blocks a rebroadcast Neo transaction stays valid for
+const NEO_VALID_UNTIL_INCREMENT: u32 = 240;
+
+/// Fees of a transaction running a script, signed by the relayer alone
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScriptCost {
+    /// GAS the script consumes when run, in datoshi
+    pub system_fee: u64,
+    /// Fee of the size and verification of the transaction, in datoshi
+    pub network_fee: u64,
+}
+
+impl ScriptCost {
+    /// Total fee paid by the relayer
+    pub fn total(&self) -> u64 {
+        self.system_fee.saturating_add(self.network_fee)
+    }
+}
+
+/// Transaction signed by the relayer, not yet broadcast
+#[derive(Debug, Clone)]
+pub struct SignedScript {
+    /// Transaction hash in display form
+    pub tx_hash: String,
+    /// Fees paid by the transaction
+    pub cost: ScriptCost,
+    /// Last block the transaction may be included in
+    pub valid_until_block: u32,
+    /// Serialized transaction
+    pub(crate) raw: Vec<u8>,
+}
+
+/// Neo N3 relay backend.
+///
+/// Rebroadcasts raise the network fee and add a Conflicts attribute for every earlier
+/// broadcast, so the replacement evicts them from the memory pool. The transaction is
+/// re-signed with the relayer signer, so only transactions the relayer signs alone can
+/// be bumped.
+pub struct NeoRelayBackend {
+    /// HTTP client
+    client: reqwest::Client,
+    /// RPC endpoint URL
+    rpc_url: String,
+    /// Relayer signer
+    signer: Arc<dyn RelayerSigner>,
+    /// Network magic
+    network_magic: u32,
+    /// Cached chain state, saving the node round trips of each rebroadcast
+    chain_state: Option<Arc<ChainStateCache>>,
+}
+
+impl NeoRelayBackend {
+    /// Create a Neo N3 relay backend, fetching the network magic from the node
+    pub async fn new(rpc_url: &str, signer: Arc<dyn RelayerSigner>) -> Result<Self, Error> {
+        let mut backend = Self {
+            client: reqwest::Client::new(),
+            rpc_url: rpc_url.to_string(),
+            signer,
+            network_magic: 0,
+            chain_state: None,
+        };
+
+        let version = backend.call("getversion", json!([])).await?;
+        backend.network_magic = version["protocol"]["network"]
+            .as_u64()
+            .ok_or_else(|| Error::RpcError("Missing network magic in getversion".to_string()))?
+            as u32;
+
+        Ok(backend)
+    }
+
+    /// Take the block height and fee per byte from cached chain state
+    pub fn with_chain_state(mut self, chain_state: Arc<ChainStateCache>) -> Self {
+        self.chain_state = Some(chain_state);
+        self
+    }
+
+    /// Current block height and network fee per byte
+    async fn chain_state(&self) -> Result<(u32, u64), Error> {
+        let state = self.current_state().await?;
+        Ok((state.height, state.fee_per_byte))
+    }
+
+    /// Current chain state, with the default fees without cached chain state
+    async fn current_state(&self) -> Result<ChainState, Error> {
+        if let Some(chain_state) = &self.chain_state {
+            return chain_state.get().await;
+        }
+
+        let height = self
+            .call("getblockcount", json!([]))
+            .await?
+            .as_u64()
+            .ok_or_else(|| Error::RpcError("Invalid getblockcount response".to_string()))?
+            as u32;
+        Ok(ChainState {
+            height,
+            fee_per_byte: NEO_FEE_PER_BYTE,
+            exec_fee_factor: NEO_EXEC_FEE_FACTOR,
+        })
+    }
+
+    /// Current block height
+    pub async fn block_height(&self) -> Result<u32, Error> {
+        Ok(self.current_state().await?.height)
+    }
+
+    /// Account script hash of the relayer, in serialized byte order
+    pub async fn relayer_account(&self) -> Result<[u8; 20], Error> {
+        script_hash(&self.signer.public_key().await?)
+    }
+
+    /// Run a script against the latest chain state as the relayer and price the transaction
+    /// running it
+    pub async fn estimate_script(&self, script: &[u8]) -> Result<ScriptCost, Error> {
+        let account = self.relayer_account().await?;
+        let mut signer = account;
+        signer.reverse();
+        let result = self
+            .call(
+                "invokescript",
+                json!([
+                    base64::engine::general_purpose::STANDARD.encode(script),
+                    [{ "account": format!("0x{}", hex::encode(signer)), "scopes": "CalledByEntry" }]
+                ]),
+            )
+            .await?;
+        if result["state"].as_str() != Some("HALT") {
+            return Err(Error::TransactionError(format!(
+                "Script faulted: {}",
+                result["exception"].as_str().unwrap_or("unknown error")
+            )));
+        }
+        let system_fee = result["gasconsumed"]
+            .as_str()
+            .and_then(|gas| gas.parse::<u64>().ok())
+            .ok_or_else(|| Error::RpcError("Invalid invokescript response".to_string()))?;
+
+        let state = self.current_state().await?;
+        let tx = NeoTransaction::new(account, script.to_vec(), 0, 0, 0);
+        Ok(ScriptCost {
+            system_fee,
+            network_fee: state.network_fee(tx.encode_unsigned().len()),
+        })
+    }
+
+    /// Build a transaction running a script, signed and paid for by the relayer alone
+    pub async fn sign_script(
+        &self,
+        script: Vec<u8>,
+        cost: ScriptCost,
+    ) -> Result<SignedScript, Error> {
+        let account = self.relayer_account().await?;
+        let state = self.current_state().await?;
+        let valid_until_block = state.height + NEO_VALID_UNTIL_INCREMENT;
+        let mut tx = NeoTransaction::new(
+            account,
+            script,
+            cost.system_fee as i64,
+            cost.network_fee as i64,
+            valid_until_block,
+        );
+        tx.witnesses =
+            vec![sign_witness(self.signer.as_ref(), self.network_magic, &tx.hash()).await?];
+
+        Ok(SignedScript {
+            tx_hash: hash_to_display(&tx.hash()),
+            cost,
+            valid_until_block,
+            raw: tx.encode(),
+        })
+    }
+
+    /// Broadcast a transaction signed by the relayer
+    pub async fn send(&self, tx: &SignedScript) -> Result<(), Error> {
+        let raw = base64::engine::general_purpose::STANDARD.encode(&tx.raw);
+        if let Err(e) = self.call("sendrawtransaction", json!([raw])).await {
+            if let Some(chain_state) = &self.chain_state {
+                chain_state.invalidate().await;
+            }
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    async fn call(&self, method: &str, params: Value) -> Result<Value, Error> {
+        let request = json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": method,
+            "params": params,
+        });
+
+        let response: Value = self
+            .client
+            .post(&self.rpc_url)
+            .json(&request)
+            .send()
+            .await
+            .map_err(|e| Error::Network(format!("RPC request failed: {}", e)))?
+            .json()
+            .await
+            .map_err(|e| Error::Serialization(format!("Invalid RPC response: {}", e)))?;
+
+        if let Some(error) = response.get("error") {
+            return Err(Error::RpcError(format!(
+                "{} failed: {}",
+                method,
+                error["message"].as_str().unwrap_or("unknown error")
+            )));
+        }
+
+        Ok(response["result"].clone())
+    }
+
+    fn decode(record: &MetaTxRecord) -> Result<NeoTransaction, Error> {
+        let data = hex::decode(&record.request.tx_data)
+            .map_err(|e| Error::InvalidParameter(format!("Invalid hex transaction data: {}", e)))?;
+        NeoTransaction::decode(&data)
+    }
+}
+
+#[async_trait]
+impl RelayBackend for NeoRelayBackend {
+    async fn tx_status(&self, tx_hash: &str) -> Result<ChainTxStatus, Error> {
+        // The application log only exists once the transaction is in a block
+        let log = match self.call("getapplicationlog", json!([tx_hash])).await {
+            Ok(log) => log,
+            Err(Error::RpcError(_)) => return Ok(ChainTxStatus::Pending),
+            Err(e) => return Err(e),
+        };
+
+        let execution = &log["executions"][0];
+        match execution["vmstate"].as_str() {
+            Some("HALT") => Ok(ChainTxStatus::Confirmed),
+            Some(_) => Ok(ChainTxStatus::Failed(
+                execution["exception"]
+                    .as_str()
+                    .unwrap_or("execution faulted")
+                    .to_string(),
+            )),
+            None => Ok(ChainTxStatus::Pending),
+        }
+    }
+
+    async fn original_fee(&self, record: &MetaTxRecord) -> Result<u64, Error> {
+        Ok(Self::decode(record)?.network_fee as u64)
+    }
+
+    async fn rebroadcast(
+        &self,
+        record: &MetaTxRecord,
+        replaces: &[String],
+        fee: u64,
+    ) -> Result<Rebroadcast, Error> {
+        let mut tx = Self::decode(record)?;
+
+        let relayer = script_hash(&self.signer.public_key().await?)?;
+        if tx.signers.len() != 1 || tx.signers[0].account != relayer {
+            return Err(Error::TransactionError(
+                "Only transactions signed by the relayer alone can be rebroadcast".to_string(),
+            ));
+        }
+
+        let (height, fee_per_byte) = self.chain_state().await?;
+
+        // Each Conflicts attribute grows the transaction and so its minimum network fee
+        let mut added = 0;
+        for tx_hash in replaces {
+            if tx.add_conflict(tx_hash)? {
+                added += 1;
+            }
+        }
+        let fee = fee + added * CONFLICTS_ATTRIBUTE_SIZE * fee_per_byte;
+        tx.network_fee = fee as i64;
+
+        if tx.valid_until_block <= height {
+            tx.valid_until_block = height + NEO_VALID_UNTIL_INCREMENT;
+        }
+
+        tx.witnesses =
+            vec![sign_witness(self.signer.as_ref(), self.network_magic, &tx.hash()).await?];
+
+        let raw = base64::engine::general_purpose::STANDARD.encode(tx.encode());
+        if let Err(e) = self.call("sendrawtransaction", json!([raw])).await {
+            // The cached height or fee may be what the node rejected
+            if let Some(chain_state) = &self.chain_state {
+                chain_state.invalidate().await;
+            }
+            return Err(e);
+        }
+
+        Ok(Rebroadcast {
+            tx_hash: hash_to_display(&tx.hash()),
+            fee,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::meta_tx::storage::InMemoryMetaTxStorage;
+    use crate::meta_tx::types::{MetaTxRequest, MetaTxResponse, SignatureCurve};
+    use tokio::sync::Mutex;
+
+    const ORIGINAL_HASH: &str = "0xoriginal";
+
+    /// Gas price of the originally relayed transaction in wei
+    const ORIGINAL_FEE: u64 = 1_000_000_000;
+
+    /// Backend of a chain where nothing confirms until told to
+    #[derive(Default)]
+    struct MockBackend {
+        statuses: Mutex<HashMap<String, ChainTxStatus>>,
+        rebroadcasts: Mutex<Vec<(Vec<String>, u64)>>,
+    }
+
+    impl MockBackend {
+        async fn set_status(&self, tx_hash: &str, status: ChainTxStatus) {
+            self.statuses
+                .lock()
+                .await
+                .insert(tx_hash.to_string(), status);
+        }
+
+        async fn rebroadcasts(&self) -> Vec<(Vec<String>, u64)> {
+            self.rebroadcasts.lock().await.clone()
+        }
+    }
+
+    #[async_trait]
+    impl RelayBackend for MockBackend {
+        async fn tx_status(&self, tx_hash: &str) -> Result<ChainTxStatus, Error> {
+            Ok(self
+                .statuses
+                .lock()
+                .await
+                .get(tx_hash)
+                .cloned()
+                .unwrap_or(ChainTxStatus::Pending))
+        }
+
+        async fn original_fee(&self, _record: &MetaTxRecord) -> Result<u64, Error> {
+            Ok(ORIGINAL_FEE)
+        }
+
+        async fn rebroadcast(
+            &self,
+            _record: &MetaTxRecord,
+            replaces: &[String],
+            fee: u64,
+        ) -> Result<Rebroadcast, Error> {
+            let mut rebroadcasts = self.rebroadcasts.lock().await;
+            rebroadcasts.push((replaces.to_vec(), fee));
+            Ok(Rebroadcast {
+                tx_hash: format!("0xbump{}", rebroadcasts.len()),
+                fee,
+            })
+        }
+    }
+
+    fn config() -> TxManagerConfig {
+        TxManagerConfig {
+            stuck_after: Duration::from_secs(60),
+            // Below the minimum bump Ethereum nodes accept
+            bump_percent: 5,
+            max_fee: 2 * ORIGINAL_FEE,
+            max_bumps: 5,
+            ..TxManagerConfig::default()
+        }
+    }
+
+    /// Ethereum meta transaction relayed long enough ago to be stuck
+    async fn setup(
+        config: TxManagerConfig,
+    ) -> (
+        TxManager<InMemoryMetaTxStorage>,
+        Arc<InMemoryMetaTxStorage>,
+        Arc<MockBackend>,
+    ) {
+        let now = chrono::Utc::now().timestamp() as u64;
+        let storage = Arc::new(InMemoryMetaTxStorage::new());
+        storage
+            .create_record(MetaTxRecord {
+                request_id: "request".to_string(),
+                request: MetaTxRequest {
+                    tx_data: "0x".to_string(),
+                    sender: "0xsender".to_string(),
+                    target_address: "0xtarget".to_string(),
+                    signature: "0x".to_string(),
+                    nonce: 7,
+                    deadline: 0,
+                    fee_amount: 0,
+                    timestamp: now - 120,
+                    blockchain_type: BlockchainType::Ethereum,
+                    signature_curve: SignatureCurve::Secp256k1,
+                    target_contract: None,
+                    chain_id: None,
+                    function: None,
+                    fee_model: None,
+                },
+                response: Some(MetaTxResponse {
+                    request_id: "request".to_string(),
+                    original_hash: ORIGINAL_HASH.to_string(),
+                    relayed_hash: Some(ORIGINAL_HASH.to_string()),
+                    status: MetaTxStatus::Submitted.to_string(),
+                    error: None,
+                    timestamp: now - 120,
+                }),
+                status: MetaTxStatus::Submitted,
+                created_at: now - 120,
+                updated_at: now - 120,
+                fee_bumps: Vec::new(),
+            })
+            .await
+            .unwrap();
+
+        let backend = Arc::new(MockBackend::default());
+        let manager = TxManager::new(storage.clone(), config)
+            .with_backend(BlockchainType::Ethereum, backend.clone());
+        (manager, storage, backend)
+    }
+
+    async fn record(storage: &InMemoryMetaTxStorage) -> MetaTxRecord {
+        storage.get_record("request").await.unwrap().unwrap()
+    }
+
+    /// Let the stuck period of the last broadcast pass
+    async fn age(storage: &InMemoryMetaTxStorage) {
+        let mut record = record(storage).await;
+        for bump in &mut record.fee_bumps {
+            bump.timestamp -= 120;
+        }
+        storage.update_record(record).await.unwrap();
+    }
+
+    #[test]
+    fn test_bumped_fee() {
+        assert_eq!(bumped_fee(BlockchainType::NeoN3, 1_000, 5), 1_050);
+        assert_eq!(bumped_fee(BlockchainType::Ethereum, 1_000, 5), 1_100);
+        assert_eq!(bumped_fee(BlockchainType::Ethereum, 1_000, 20), 1_200);
+        // Rounding never leaves the fee unchanged
+        assert_eq!(bumped_fee(BlockchainType::NeoN3, 1, 5), 2);
+        assert_eq!(bumped_fee(BlockchainType::Ethereum, u64::MAX, 5), u64::MAX);
+    }
+
+    #[tokio::test]
+    async fn test_stuck_transaction_replaced() {
+        let (manager, storage, backend) = setup(config()).await;
+
+        manager.poll_once().await.unwrap();
+        let rebroadcasts = backend.rebroadcasts().await;
+        assert_eq!(rebroadcasts.len(), 1);
+        let (replaces, fee) = &rebroadcasts[0];
+        assert_eq!(replaces, &[ORIGINAL_HASH.to_string()]);
+        assert!(*fee >= ORIGINAL_FEE * (100 + ETHEREUM_MIN_BUMP_PERCENT) / 100);
+
+        let record = record(&storage).await;
+        assert_eq!(record.status, MetaTxStatus::Submitted);
+        assert_eq!(record.fee_bumps.len(), 1);
+        assert_eq!(record.fee_bumps[0].fee, *fee);
+        assert_eq!(
+            record.response.unwrap().relayed_hash.as_deref(),
+            Some("0xbump1")
+        );
+
+        // The replacement gets its own time to confirm
+        manager.poll_once().await.unwrap();
+        assert_eq!(backend.rebroadcasts().await.len(), 1);
+
+        // The next replacement bumps the replacement's fee and replaces every broadcast
+        age(&storage).await;
+        manager.poll_once().await.unwrap();
+        let rebroadcasts = backend.rebroadcasts().await;
+        assert_eq!(rebroadcasts.len(), 2);
+        let (replaces, second_fee) = &rebroadcasts[1];
+        assert_eq!(
+            replaces,
+            &[ORIGINAL_HASH.to_string(), "0xbump1".to_string()]
+        );
+        assert!(*second_fee >= fee * (100 + ETHEREUM_MIN_BUMP_PERCENT) / 100);
+    }
+
+    #[tokio::test]
+    async fn test_fee_cap() {
+        let config = TxManagerConfig {
+            max_fee: ORIGINAL_FEE * 115 / 100,
+            ..config()
+        };
+        let (manager, storage, backend) = setup(config.clone()).await;
+
+        manager.poll_once().await.unwrap();
+        age(&storage).await;
+        manager.poll_once().await.unwrap();
+
+        // The second bump would exceed the cap, the transaction is left stuck instead
+        let rebroadcasts = backend.rebroadcasts().await;
+        assert_eq!(rebroadcasts.len(), 1);
+        assert!(rebroadcasts.iter().all(|(_, fee)| *fee <= config.max_fee));
+        let stuck = record(&storage).await;
+        assert_eq!(stuck.status, MetaTxStatus::Stuck);
+        assert_eq!(stuck.fee_bumps.len(), 1);
+
+        // Stuck transactions are not bumped again, but may still confirm
+        age(&storage).await;
+        manager.poll_once().await.unwrap();
+        assert_eq!(backend.rebroadcasts().await.len(), 1);
+        backend
+            .set_status("0xbump1", ChainTxStatus::Confirmed)
+            .await;
+        manager.poll_once().await.unwrap();
+        assert_eq!(record(&storage).await.status, MetaTxStatus::Confirmed);
+    }
+
+    #[tokio::test]
+    async fn test_max_bumps() {
+        let config = TxManagerConfig {
+            max_fee: u64::MAX,
+            max_bumps: 2,
+            ..config()
+        };
+        let (manager, storage, backend) = setup(config).await;
+
+        for _ in 0..4 {
+            manager.poll_once().await.unwrap();
+            age(&storage).await;
+        }
+        assert_eq!(backend.rebroadcasts().await.len(), 2);
+        assert_eq!(record(&storage).await.status, MetaTxStatus::Stuck);
+    }
+
+    #[tokio::test]
+    async fn test_confirmed_original_stops_bumping() {
+        let (manager, storage, backend) = setup(config()).await;
+        manager.poll_once().await.unwrap();
+        assert_eq!(backend.rebroadcasts().await.len(), 1);
+
+        // The original lands after all, its replacement can no longer
+        backend
+            .set_status(ORIGINAL_HASH, ChainTxStatus::Confirmed)
+            .await;
+        age(&storage).await;
+        manager.poll_once().await.unwrap();
+
+        let record = record(&storage).await;
+        assert_eq!(record.status, MetaTxStatus::Confirmed);
+        let response = record.response.unwrap();
+        assert_eq!(response.relayed_hash.as_deref(), Some(ORIGINAL_HASH));
+        assert_eq!(response.status, "confirmed");
+
+        manager.poll_once().await.unwrap();
+        assert_eq!(backend.rebroadcasts().await.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_failed_transaction() {
+        let (manager, storage, backend) = setup(config()).await;
+        backend
+            .set_status(ORIGINAL_HASH, ChainTxStatus::Failed("reverted".to_string()))
+            .await;
+        manager.poll_once().await.unwrap();
+
+        assert!(backend.rebroadcasts().await.is_empty());
+        let record = record(&storage).await;
+        assert_eq!(record.status, MetaTxStatus::Failed);
+        assert_eq!(record.response.unwrap().error.as_deref(), Some("reverted"));
+    }
+}
@@ -2,10 +2,21 @@
 // All Rights Reserved
 
 pub mod eip712;
+pub mod forwarder;
+pub mod manager;
+mod neo_tx;
+pub mod nonce;
+pub mod rocksdb;
 pub mod service;
 pub mod storage;
 pub mod types;
 
 pub use eip712::{EIP712Domain, EIP712Type, EIP712TypedData, MetaTxMessage};
+pub use forwarder::{ForwarderClient, ForwarderConfig, ForwarderNetwork};
+pub use manager::{
+    NeoRelayBackend, RelayBackend, ScriptCost, SignedScript, TxManager, TxManagerConfig,
+};
+pub use nonce::NonceStore;
+pub use rocksdb::RocksDBMetaTxStorage;
 pub use service::MetaTxService;
 pub use types::*;
@@ -0,0 +1,349 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+//! Minimal Neo N3 transaction codec, enough to rewrite the fee fields of a relayed
+//! transaction and re-sign it.
+
+use crate::signer::Witness;
+use crate::Error;
+use sha2::{Digest, Sha256};
+
+/// Conflicts transaction attribute type
+const CONFLICTS_ATTRIBUTE: u8 = 0x21;
+
+/// Size of a Conflicts attribute: type byte and hash
+pub(crate) const CONFLICTS_ATTRIBUTE_SIZE: u64 = 33;
+
+/// Maximum number of signers and attributes of a transaction
+const MAX_TRANSACTION_ATTRIBUTES: usize = 16;
+
+/// Witness scope of a signer whose witness only applies to the entry script
+const CALLED_BY_ENTRY: u8 = 0x01;
+
+/// Witness scope flags followed by a variable length list
+const CUSTOM_CONTRACTS: u8 = 0x10;
+const CUSTOM_GROUPS: u8 = 0x20;
+const WITNESS_RULES: u8 = 0x40;
+
+/// Transaction signer, kept encoded
+#[derive(Debug, Clone)]
+pub(crate) struct NeoSigner {
+    /// Account script hash
+    pub account: [u8; 20],
+    /// Encoded signer
+    raw: Vec<u8>,
+}
+
+impl NeoSigner {
+    /// Account script hash in display form: `0x` prefixed, big-endian hex
+    pub fn account_to_display(&self) -> String {
+        let mut reversed = self.account;
+        reversed.reverse();
+        format!("0x{}", hex::encode(reversed))
+    }
+}
+
+/// Neo N3 transaction
+#[derive(Debug, Clone)]
+pub(crate) struct NeoTransaction {
+    pub version: u8,
+    pub nonce: u32,
+    pub system_fee: i64,
+    pub network_fee: i64,
+    pub valid_until_block: u32,
+    pub signers: Vec<NeoSigner>,
+    /// Encoded attributes
+    attributes: Vec<Vec<u8>>,
+    pub script: Vec<u8>,
+    pub witnesses: Vec<Witness>,
+}
+
+impl NeoTransaction {
+    /// Unsigned transaction running a script, with a single signer whose witness only applies
+    /// to the entry script
+    pub fn new(
+        account: [u8; 20],
+        script: Vec<u8>,
+        system_fee: i64,
+        network_fee: i64,
+        valid_until_block: u32,
+    ) -> Self {
+        let mut raw = Vec::with_capacity(21);
+        raw.extend_from_slice(&account);
+        raw.push(CALLED_BY_ENTRY);
+
+        Self {
+            version: 0,
+            nonce: rand::random(),
+            system_fee,
+            network_fee,
+            valid_until_block,
+            signers: vec![NeoSigner { account, raw }],
+            attributes: Vec::new(),
+            script,
+            witnesses: Vec::new(),
+        }
+    }
+
+    /// Decode a serialized transaction
+    pub fn decode(data: &[u8]) -> Result<Self, Error> {
+        let mut reader = Reader { data, pos: 0 };
+
+        let version = reader.u8()?;
+        let nonce = reader.u32()?;
+        let system_fee = reader.u64()? as i64;
+        let network_fee = reader.u64()? as i64;
+        let valid_until_block = reader.u32()?;
+
+        let signer_count = reader.count(MAX_TRANSACTION_ATTRIBUTES)?;
+        let mut signers = Vec::with_capacity(signer_count);
+        for _ in 0..signer_count {
+            signers.push(reader.signer()?);
+        }
+
+        let attribute_count = reader.count(MAX_TRANSACTION_ATTRIBUTES - signer_count)?;
+        let mut attributes = Vec::with_capacity(attribute_count);
+        for _ in 0..attribute_count {
+            attributes.push(reader.attribute()?);
+        }
+
+        let script = reader.var_bytes()?.to_vec();
+
+        let mut witnesses = Vec::new();
+        if reader.pos < data.len() {
+            let witness_count = reader.count(MAX_TRANSACTION_ATTRIBUTES)?;
+            for _ in 0..witness_count {
+                witnesses.push(Witness {
+                    invocation: reader.var_bytes()?.to_vec(),
+                    verification: reader.var_bytes()?.to_vec(),
+                });
+            }
+        }
+
+        if reader.pos != data.len() {
+            return Err(Error::ParseError(
+                "Trailing bytes after transaction".to_string(),
+            ));
+        }
+
+        Ok(Self {
+            version,
+            nonce,
+            system_fee,
+            network_fee,
+            valid_until_block,
+            signers,
+            attributes,
+            script,
+            witnesses,
+        })
+    }
+
+    /// Serialize the transaction without witnesses, the signed part
+    pub fn encode_unsigned(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(64 + self.script.len());
+        out.push(self.version);
+        out.extend_from_slice(&self.nonce.to_le_bytes());
+        out.extend_from_slice(&self.system_fee.to_le_bytes());
+        out.extend_from_slice(&self.network_fee.to_le_bytes());
+        out.extend_from_slice(&self.valid_until_block.to_le_bytes());
+        write_var_int(&mut out, self.signers.len() as u64);
+        for signer in &self.signers {
+            out.extend_from_slice(&signer.raw);
+        }
+        write_var_int(&mut out, self.attributes.len() as u64);
+        for attribute in &self.attributes {
+            out.extend_from_slice(attribute);
+        }
+        write_var_bytes(&mut out, &self.script);
+        out
+    }
+
+    /// Serialize the signed transaction
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = self.encode_unsigned();
+        write_var_int(&mut out, self.witnesses.len() as u64);
+        for witness in &self.witnesses {
+            write_var_bytes(&mut out, &witness.invocation);
+            write_var_bytes(&mut out, &witness.verification);
+        }
+        out
+    }
+
+    /// Transaction hash in internal byte order
+    pub fn hash(&self) -> [u8; 32] {
+        Sha256::digest(self.encode_unsigned()).into()
+    }
+
+    /// Add a Conflicts attribute for a hash given in display form, unless present
+    pub fn add_conflict(&mut self, tx_hash: &str) -> Result<bool, Error> {
+        let hash = hash_from_display(tx_hash)?;
+        let mut attribute = Vec::with_capacity(CONFLICTS_ATTRIBUTE_SIZE as usize);
+        attribute.push(CONFLICTS_ATTRIBUTE);
+        attribute.extend_from_slice(&hash);
+
+        if self.attributes.contains(&attribute) {
+            return Ok(false);
+        }
+        if self.signers.len() + self.attributes.len() >= MAX_TRANSACTION_ATTRIBUTES {
+            return Err(Error::TransactionError(
+                "Transaction attribute limit reached".to_string(),
+            ));
+        }
+
+        self.attributes.push(attribute);
+        Ok(true)
+    }
+}
+
+/// Hash in display form: `0x` prefixed, big-endian hex
+pub(crate) fn hash_to_display(hash: &[u8; 32]) -> String {
+    let mut reversed = *hash;
+    reversed.reverse();
+    format!("0x{}", hex::encode(reversed))
+}
+
+fn hash_from_display(tx_hash: &str) -> Result<[u8; 32], Error> {
+    let bytes = hex::decode(tx_hash.trim_start_matches("0x"))
+        .map_err(|e| Error::ParseError(format!("Invalid transaction hash: {}", e)))?;
+    let mut hash: [u8; 32] = bytes
+        .try_into()
+        .map_err(|_| Error::ParseError(format!("Invalid transaction hash: {}", tx_hash)))?;
+    hash.reverse();
+    Ok(hash)
+}
+
+fn write_var_int(out: &mut Vec<u8>, value: u64) {
+    if value < 0xfd {
+        out.push(value as u8);
+    } else if value <= 0xffff {
+        out.push(0xfd);
+        out.extend_from_slice(&(value as u16).to_le_bytes());
+    } else if value <= 0xffff_ffff {
+        out.push(0xfe);
+        out.extend_from_slice(&(value as u32).to_le_bytes());
+    } else {
+        out.push(0xff);
+        out.extend_from_slice(&value.to_le_bytes());
+    }
+}
+
+fn write_var_bytes(out: &mut Vec<u8>, bytes: &[u8]) {
+    write_var_int(out, bytes.len() as u64);
+    out.extend_from_slice(bytes);
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], Error> {
+        let end = self
+            .pos
+            .checked_add(len)
+            .filter(|end| *end <= self.data.len())
+            .ok_or_else(|| Error::ParseError("Unexpected end of transaction".to_string()))?;
+        let bytes = &self.data[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, Error> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32, Error> {
+        Ok(u32::from_le_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Result<u64, Error> {
+        Ok(u64::from_le_bytes(self.bytes(8)?.try_into().unwrap()))
+    }
+
+    fn var_int(&mut self) -> Result<u64, Error> {
+        Ok(match self.u8()? {
+            0xfd => u16::from_le_bytes(self.bytes(2)?.try_into().unwrap()) as u64,
+            0xfe => self.u32()? as u64,
+            0xff => self.u64()?,
+            value => value as u64,
+        })
+    }
+
+    fn count(&mut self, max: usize) -> Result<usize, Error> {
+        let count = self.var_int()?;
+        if count > max as u64 {
+            return Err(Error::ParseError(format!(
+                "Too many transaction entries: {}",
+                count
+            )));
+        }
+        Ok(count as usize)
+    }
+
+    fn var_bytes(&mut self) -> Result<&'a [u8], Error> {
+        let len = self.var_int()?;
+        if len > self.data.len() as u64 {
+            return Err(Error::ParseError(
+                "Unexpected end of transaction".to_string(),
+            ));
+        }
+        self.bytes(len as usize)
+    }
+
+    fn signer(&mut self) -> Result<NeoSigner, Error> {
+        let start = self.pos;
+        let account: [u8; 20] = self.bytes(20)?.try_into().unwrap();
+        let scopes = self.u8()?;
+        if scopes & CUSTOM_CONTRACTS != 0 {
+            let count = self.count(MAX_TRANSACTION_ATTRIBUTES)?;
+            self.bytes(count * 20)?;
+        }
+        if scopes & CUSTOM_GROUPS != 0 {
+            let count = self.count(MAX_TRANSACTION_ATTRIBUTES)?;
+            self.bytes(count * 33)?;
+        }
+        if scopes & WITNESS_RULES != 0 {
+            return Err(Error::ParseError(
+                "Signers with witness rules are not supported".to_string(),
+            ));
+        }
+
+        Ok(NeoSigner {
+            account,
+            raw: self.data[start..self.pos].to_vec(),
+        })
+    }
+
+    fn attribute(&mut self) -> Result<Vec<u8>, Error> {
+        let start = self.pos;
+        match self.u8()? {
+            // HighPriority
+            0x01 => {}
+            // OracleResponse
+            0x11 => {
+                self.bytes(9)?;
+                self.var_bytes()?;
+            }
+            // NotValidBefore
+            0x20 => {
+                self.bytes(4)?;
+            }
+            CONFLICTS_ATTRIBUTE => {
+                self.bytes(32)?;
+            }
+            // NotaryAssisted
+            0x22 => {
+                self.bytes(1)?;
+            }
+            other => {
+                return Err(Error::ParseError(format!(
+                    "Unsupported transaction attribute: {:#04x}",
+                    other
+                )))
+            }
+        }
+        Ok(self.data[start..self.pos].to_vec())
+    }
+}
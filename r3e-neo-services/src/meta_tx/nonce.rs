@@ -0,0 +1,67 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+use crate::error::Error;
+use r3e_store::{encode_counter, AtomicKvStore, CasOutcome};
+use std::sync::Arc;
+
+/// Table of the last nonce relayed per sender
+pub const NONCE_TABLE: &str = "meta_tx_nonces";
+
+/// Meta transaction nonces of senders, kept as counters of an atomic store so relayers sharing
+/// it relay each nonce once
+pub struct NonceStore {
+    store: Arc<dyn AtomicKvStore + Send + Sync>,
+}
+
+impl NonceStore {
+    /// Create a new nonce store
+    pub fn new(store: Arc<dyn AtomicKvStore + Send + Sync>) -> Self {
+        Self { store }
+    }
+
+    /// Last nonce relayed for a sender, 0 if none was
+    pub fn last(&self, sender: &str) -> Result<u64, Error> {
+        let last = self
+            .store
+            .increment(NONCE_TABLE, sender.as_bytes(), 0)
+            .map_err(|e| Error::Storage(e.to_string()))?;
+        Ok(last.max(0) as u64)
+    }
+
+    /// Next nonce a sender must sign, nonces starting at 1
+    pub fn next(&self, sender: &str) -> Result<u64, Error> {
+        Ok(self.last(sender)? + 1)
+    }
+
+    /// Use the nonce of a transaction, which must be the next nonce of its sender
+    pub fn claim(&self, sender: &str, nonce: u64) -> Result<(), Error> {
+        let last = self.last(sender)?;
+        if nonce != last + 1 {
+            return Err(Error::InvalidParameter(format!(
+                "Invalid nonce {} for {}, expected {}",
+                nonce,
+                sender,
+                last + 1
+            )));
+        }
+
+        // Another relayer may have claimed the nonce since it was read
+        let outcome = self
+            .store
+            .compare_and_set(
+                NONCE_TABLE,
+                sender.as_bytes(),
+                Some(&encode_counter(last as i64)),
+                Some(&encode_counter(nonce as i64)),
+            )
+            .map_err(|e| Error::Storage(e.to_string()))?;
+        match outcome {
+            CasOutcome::Set => Ok(()),
+            CasOutcome::Conflict(_) => Err(Error::InvalidParameter(format!(
+                "Nonce {} of {} was already used",
+                nonce, sender
+            ))),
+        }
+    }
+}
@@ -0,0 +1,167 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+use async_trait::async_trait;
+use r3e_store::rocksdb::RocksDbConfig;
+use r3e_store::RocksDBStore;
+use r3e_store::{decode_record, encode_record, migrate_cf, MigrationStats, Versioned};
+use std::path::Path;
+
+use super::storage::MetaTxStorage;
+use super::types::{MetaTxRecord, MetaTxStatus};
+use crate::Error;
+
+/// Number of records read per scan
+const RECORD_SCAN_BATCH: usize = 256;
+
+/// Meta transaction records are stored as versioned records, bump the version and add a
+/// migration when changing them other than by adding optional fields
+impl Versioned for MetaTxRecord {
+    const SCHEMA: &'static str = "meta_tx_record";
+    const VERSION: u32 = 1;
+}
+
+/// RocksDB implementation of MetaTxStorage
+pub struct RocksDBMetaTxStorage {
+    db: RocksDBStore,
+    records_cf: String,
+}
+
+impl RocksDBMetaTxStorage {
+    /// Create a new RocksDB meta transaction storage
+    pub async fn new<P: AsRef<Path>>(db_path: P) -> Result<Self, Error> {
+        let config = RocksDbConfig {
+            path: db_path.as_ref().to_string_lossy().to_string(),
+            ..Default::default()
+        };
+
+        let db = RocksDBStore::new(config);
+
+        // Open the database
+        db.open()
+            .map_err(|e| Error::Storage(format!("Failed to open RocksDB store: {}", e)))?;
+
+        let records_cf = "meta_tx_records".to_string();
+        db.create_cf_if_missing(&records_cf).map_err(|e| {
+            Error::Storage(format!(
+                "Failed to create column family {}: {}",
+                records_cf, e
+            ))
+        })?;
+
+        Ok(Self { db, records_cf })
+    }
+
+    /// Upgrade the records written by older releases to the current version
+    pub fn migrate(&self, dry_run: bool) -> Result<MigrationStats, Error> {
+        migrate_cf::<MetaTxRecord>(&self.db, &self.records_cf, dry_run)
+            .map_err(|e| Error::Storage(format!("Failed to migrate records: {}", e)))
+    }
+
+    fn put_record(&self, record: &MetaTxRecord) -> Result<(), Error> {
+        let value = encode_record(record)
+            .map_err(|e| Error::Serialization(format!("Failed to serialize record: {}", e)))?;
+        self.db
+            .put_cf(&self.records_cf, &record.request_id, &value)
+            .map_err(|e| Error::Storage(format!("Failed to store record: {}", e)))
+    }
+
+    /// Records matching a filter, in request ID order
+    fn find_records<F>(&self, filter: F) -> Result<Vec<MetaTxRecord>, Error>
+    where
+        F: Fn(&MetaTxRecord) -> bool,
+    {
+        let mut start = Vec::new();
+        let mut records = Vec::new();
+
+        loop {
+            let entries: Vec<(Box<[u8]>, Vec<u8>)> = self
+                .db
+                .scan_cf(&self.records_cf, &start, RECORD_SCAN_BATCH)
+                .map_err(|e| Error::Storage(format!("Failed to scan records: {}", e)))?;
+            let exhausted = entries.len() < RECORD_SCAN_BATCH;
+
+            for (key, value) in entries {
+                if !start.is_empty() && *key == *start {
+                    continue;
+                }
+                start = key.to_vec();
+
+                let record = decode_record::<MetaTxRecord>(&value)
+                    .map_err(|e| Error::Serialization(format!("Invalid record: {}", e)))?
+                    .value;
+                if filter(&record) {
+                    records.push(record);
+                }
+            }
+
+            if exhausted {
+                break;
+            }
+        }
+
+        Ok(records)
+    }
+}
+
+#[async_trait]
+impl MetaTxStorage for RocksDBMetaTxStorage {
+    async fn get_record(&self, request_id: &str) -> Result<Option<MetaTxRecord>, Error> {
+        let value = match self.db.get_cf::<_, Vec<u8>>(&self.records_cf, request_id) {
+            Ok(Some(value)) => value,
+            Ok(None) => return Ok(None),
+            Err(e) => return Err(Error::Storage(format!("Failed to get record: {}", e))),
+        };
+
+        let decoded = decode_record::<MetaTxRecord>(&value)
+            .map_err(|e| Error::Serialization(format!("Invalid record: {}", e)))?;
+
+        // Write records of older releases back upgraded
+        if decoded.outdated {
+            self.put_record(&decoded.value)?;
+        }
+        Ok(Some(decoded.value))
+    }
+
+    async fn get_records_by_sender(&self, sender: &str) -> Result<Vec<MetaTxRecord>, Error> {
+        self.find_records(|r| r.request.sender == sender)
+    }
+
+    async fn get_records_by_status(
+        &self,
+        status: &MetaTxStatus,
+    ) -> Result<Vec<MetaTxRecord>, Error> {
+        self.find_records(|r| &r.status == status)
+    }
+
+    async fn create_record(&self, record: MetaTxRecord) -> Result<(), Error> {
+        if self.get_record(&record.request_id).await?.is_some() {
+            return Err(Error::InvalidParameter(format!(
+                "Record already exists with ID: {}",
+                record.request_id
+            )));
+        }
+        self.put_record(&record)
+    }
+
+    async fn update_record(&self, record: MetaTxRecord) -> Result<(), Error> {
+        if self.get_record(&record.request_id).await?.is_none() {
+            return Err(Error::NotFound(format!(
+                "Record not found with ID: {}",
+                record.request_id
+            )));
+        }
+        self.put_record(&record)
+    }
+
+    async fn get_nonce(&self, sender: &str) -> Result<u64, Error> {
+        let max_nonce = self
+            .get_records_by_sender(sender)
+            .await?
+            .iter()
+            .map(|r| r.request.nonce)
+            .max()
+            .unwrap_or(0);
+        Ok(max_nonce + 1)
+    }
+}
@@ -1,13 +1,22 @@
 // Copyright @ 2023 - 2024, R3E Network
 // All Rights Reserved
 
+use crate::chain_state::ChainStateCache;
 use crate::error::Error;
+use crate::fee::{FeeOracle, FeeQuote, FeeSchedule};
 use crate::gas_bank::service::{GasBankService, GasBankServiceTrait};
 use crate::gas_bank::storage::GasBankStorage;
 use crate::meta_tx::eip712::types::{EIP712Domain, MetaTxMessage};
 use crate::meta_tx::eip712::utils::{get_typed_data, verify_eip712_signature};
+use crate::meta_tx::forwarder::{meta_transaction, verify_forwarder_signature, ForwarderConfig};
+use crate::meta_tx::neo_tx::NeoTransaction;
+use crate::meta_tx::nonce::NonceStore;
 use crate::meta_tx::storage::MetaTxStorage;
-use crate::meta_tx::types::{BlockchainType, MetaTxRecord, MetaTxRequest, MetaTxResponse, MetaTxStatus};
+use crate::signer::RelayerSigner;
+use crate::meta_tx::types::{
+    BlockchainType, FeeQuoteRequest, MetaTxRecord, MetaTxRequest, MetaTxResponse,
+    MetaTxSimulation, MetaTxStatus,
+};
 use crate::types::FeeModel;
 use async_trait::async_trait;
 use chrono::{DateTime, Utc};
@@ -16,7 +25,8 @@ use ethers::types::Signature;
 use hex;
 use log::{debug, error, info};
 use neo3::neo_clients::APITrait;
-use neo3::prelude::{HttpProvider, RpcClient, Wallet};
+use neo3::prelude::{HttpProvider, RpcClient};
+use r3e_core::chain::{ChainClients, TxSimulation};
 use std::sync::Arc;
 use uuid::Uuid;
 
@@ -26,6 +36,12 @@ pub trait MetaTxServiceTrait: Send + Sync {
     /// Submit meta transaction
     async fn submit(&self, request: MetaTxRequest) -> Result<MetaTxResponse, Error>;
 
+    /// Simulate meta transaction without relaying it
+    async fn simulate(&self, request: MetaTxRequest) -> Result<MetaTxSimulation, Error>;
+
+    /// Quote the fee of relaying a meta transaction, before it is signed
+    async fn quote(&self, request: &FeeQuoteRequest) -> Result<FeeQuote, Error>;
+
     /// Get meta transaction status
     async fn get_status(&self, request_id: &str) -> Result<String, Error>;
 
@@ -48,16 +64,26 @@ pub struct MetaTxService<S: MetaTxStorage> {
     storage: Arc<S>,
     /// RPC client
     rpc_client: Arc<RpcClient<HttpProvider>>,
-    /// Relayer wallet
-    relayer_wallet: Arc<Wallet>,
+    /// Relayer signer
+    relayer_signer: Arc<dyn RelayerSigner>,
     /// Network type
     network: String,
-    /// Default fee model
-    default_fee_model: FeeModel,
+    /// Fee models of the services transactions are relayed for
+    fee_schedule: FeeSchedule,
+    /// Live network fees transactions are priced with, fees are not checked without it
+    fee_oracle: Option<Arc<FeeOracle>>,
     /// Chain ID
     chain_id: u64,
     /// Gas bank storage
     gas_bank_storage: Arc<dyn GasBankStorage>,
+    /// Chain clients used to simulate transactions
+    chains: Arc<ChainClients>,
+    /// Cached Neo N3 chain state transactions are priced and checked with
+    chain_state: Option<Arc<ChainStateCache>>,
+    /// Nonces relayed per sender, replays are not rejected without it
+    nonces: Option<Arc<NonceStore>>,
+    /// Trusted forwarders Ethereum meta transactions are signed for, per network
+    forwarders: ForwarderConfig,
 }
 
 impl<S: MetaTxStorage> MetaTxService<S> {
@@ -65,7 +91,7 @@ impl<S: MetaTxStorage> MetaTxService<S> {
     pub fn new(
         storage: Arc<S>,
         rpc_client: Arc<RpcClient<HttpProvider>>,
-        relayer_wallet: Arc<Wallet>,
+        relayer_signer: Arc<dyn RelayerSigner>,
         network: String,
         default_fee_model: FeeModel,
         chain_id: u64,
@@ -74,61 +100,163 @@ impl<S: MetaTxStorage> MetaTxService<S> {
         Self {
             storage,
             rpc_client,
-            relayer_wallet,
+            relayer_signer,
             network,
-            default_fee_model,
+            fee_schedule: FeeSchedule::new(default_fee_model),
+            fee_oracle: None,
             chain_id,
             gas_bank_storage,
+            chains: Arc::new(ChainClients::new()),
+            chain_state: None,
+            nonces: None,
+            forwarders: ForwarderConfig::default(),
         }
     }
 
-    /// Calculate fee for meta transaction
-    async fn calculate_fee(&self, tx_data: &str, fee_model: &FeeModel) -> Result<u64, Error> {
-        match fee_model {
-            FeeModel::Fixed(fixed_fee) => Ok(*fixed_fee),
-            FeeModel::Percentage(percentage) => {
-                // Calculate fee based on transaction size
-                let tx_size = tx_data.len() as u64;
-                let tx_base_fee = 100_000; // 0.0001 GAS
-                
-                // Calculate dynamic fee based on transaction size
-                let size_fee = tx_size * tx_base_fee / 1000;
-                
-                // Add network fee
-                let network_fee = self.get_estimated_fee(BlockchainType::NeoN3).await?;
-                
-                // Calculate percentage fee
-                let fee = (size_fee + network_fee) as f64 * percentage / 100.0;
-                
-                Ok(fee as u64)
+    /// Share chain clients with other services, so endpoint health is tracked across them
+    pub fn with_chain_clients(mut self, chains: Arc<ChainClients>) -> Self {
+        self.chains = chains;
+        self
+    }
+
+    /// Price and check Neo N3 transactions with cached chain state, shared with the gas bank
+    pub fn with_chain_state(mut self, chain_state: Arc<ChainStateCache>) -> Self {
+        self.chain_state = Some(chain_state);
+        self
+    }
+
+    /// Relay each nonce of a sender once and in order, tracking nonces in a store shared by
+    /// the relayers
+    pub fn with_nonce_store(mut self, nonces: Arc<NonceStore>) -> Self {
+        self.nonces = Some(nonces);
+        self
+    }
+
+    /// Check Ethereum meta transactions are signed for the trusted forwarder of their network,
+    /// which executes them
+    pub fn with_forwarders(mut self, forwarders: ForwarderConfig) -> Self {
+        self.forwarders = forwarders;
+        self
+    }
+
+    /// Address of the forwarder executing an Ethereum meta transaction, None if its network has
+    /// none
+    fn forwarder(&self, request: &MetaTxRequest) -> Result<Option<ethers::types::Address>, Error> {
+        if request.blockchain_type != BlockchainType::Ethereum {
+            return Ok(None);
+        }
+        self.forwarders.address(self.chain_id_of(request))
+    }
+
+    fn chain_id_of(&self, request: &MetaTxRequest) -> u64 {
+        request
+            .chain_id
+            .unwrap_or_else(|| request.blockchain_type.to_chain_id())
+    }
+
+    /// Charge services the fee models of a fee schedule, instead of the default fee model
+    pub fn with_fee_schedule(mut self, fee_schedule: FeeSchedule) -> Self {
+        self.fee_schedule = fee_schedule;
+        self
+    }
+
+    /// Price transactions with live network fees, rejecting those not paying the quoted fee
+    pub fn with_fee_oracle(mut self, fee_oracle: Arc<FeeOracle>) -> Self {
+        self.fee_oracle = Some(fee_oracle);
+        self
+    }
+
+    /// Network a transaction is relayed on, the transaction to run and its size in bytes
+    fn decode_tx(&self, request: &FeeQuoteRequest) -> Result<(String, TxSimulation, usize), Error> {
+        match request.blockchain_type {
+            BlockchainType::NeoN3 => {
+                let data = hex::decode(&request.tx_data)
+                    .map_err(|e| Error::InvalidParameter(format!("Invalid hex transaction data: {}", e)))?;
+                let tx = NeoTransaction::decode(&data)?;
+
+                let simulation = TxSimulation {
+                    signers: tx.signers.iter().map(|signer| signer.account_to_display()).collect(),
+                    to: None,
+                    data: tx.script,
+                };
+                Ok((self.network.clone(), simulation, data.len()))
             }
-            FeeModel::Dynamic => {
-                // Calculate dynamic fee based on network congestion
-                let network_fee = self.get_estimated_fee(BlockchainType::NeoN3).await?;
-                let data_size_fee = tx_data.len() as u64 * network_fee / 1000;
-                Ok(data_size_fee + network_fee)
+            BlockchainType::Ethereum => {
+                let data = hex::decode(request.tx_data.trim_start_matches("0x"))
+                    .map_err(|e| Error::InvalidParameter(format!("Invalid hex transaction data: {}", e)))?;
+                let chain_id = request
+                    .chain_id
+                    .unwrap_or_else(|| request.blockchain_type.to_chain_id());
+
+                let size = data.len();
+                let simulation = TxSimulation {
+                    signers: vec![request.sender.clone()],
+                    to: Some(request.service().to_string()),
+                    data,
+                };
+                Ok((ethereum_network(chain_id)?.to_string(), simulation, size))
             }
-            FeeModel::Free => Ok(0),
         }
     }
 
-    /// Get estimated fee
-    async fn get_estimated_fee(&self, blockchain_type: BlockchainType) -> Result<u64, Error> {
-        // For Neo N3, we can use the network usage as an estimate
-        if blockchain_type == BlockchainType::NeoN3 {
-            // Use a fixed value for now since get_network_usage is not available
-            return Ok(1_000_000); // 0.001 GAS as network fee
+    /// Quote the fee of relaying a transaction from the live network fees
+    pub async fn quote(&self, request: &FeeQuoteRequest) -> Result<FeeQuote, Error> {
+        let fee_oracle = self
+            .fee_oracle
+            .as_ref()
+            .ok_or_else(|| Error::ConfigError("No fee oracle configured".to_string()))?;
+
+        let (network, tx, tx_size) = self.decode_tx(request)?;
+        let cost = fee_oracle
+            .cost(request.blockchain_type, &network, &tx, tx_size)
+            .await?;
+
+        let quote = self
+            .fee_schedule
+            .quote(request.service(), request.blockchain_type, cost);
+        debug!("Quoted meta transaction fee: {:?}", quote);
+        Ok(quote)
+    }
+
+    /// Check a transaction pays the fee of its service at the current network fees
+    async fn check_fee(&self, request: &MetaTxRequest) -> Result<(), Error> {
+        if self.fee_oracle.is_none() {
+            return Ok(());
         }
-        
-        // For Ethereum, we would need to get gas price and estimate gas
-        // For now, return a default value
-        Ok(100_000_000) // 0.1 GAS or 0.000001 ETH
+
+        let quote = self.quote(&FeeQuoteRequest::from(request)).await?;
+        if request.fee_model.as_deref() != Some(quote.fee_model.as_str()) {
+            return Err(Error::InvalidParameter(format!(
+                "Fee model {} does not match the {} fee model of the service",
+                request.fee_model.as_deref().unwrap_or("none"),
+                quote.fee_model
+            )));
+        }
+        if request.fee_amount < quote.fee_amount {
+            return Err(Error::InsufficientFunds(format!(
+                "Fee amount {} is below the quoted fee {} {}",
+                request.fee_amount, quote.fee_amount, quote.unit
+            )));
+        }
+
+        Ok(())
     }
 
     /// Verify signature
     async fn verify_signature(&self, request: &MetaTxRequest) -> Result<bool, Error> {
         debug!("Verifying signature: {:?}", request);
 
+        // Signed for the forwarder, exactly as it checks the signature before executing
+        if let Some(forwarder) = self.forwarder(request)? {
+            let tx = meta_transaction(request)?;
+            return verify_forwarder_signature(
+                self.chain_id_of(request),
+                forwarder,
+                &tx,
+                &request.signature,
+            );
+        }
+
         // Create EIP-712 typed data for the meta transaction
         let domain = EIP712Domain {
             name: "R3E Meta Transaction".to_string(),
@@ -186,8 +314,20 @@ impl<S: MetaTxStorage> MetaTxService<S> {
         // Validate based on blockchain type
         match request.blockchain_type {
             BlockchainType::NeoN3 => {
-                // Validate Neo N3 transaction
-                // Additional checks can be added here
+                // Reject transactions that expired before they could be relayed
+                if let Some(chain_state) = &self.chain_state {
+                    let data = hex::decode(&request.tx_data).map_err(|e| {
+                        Error::InvalidParameter(format!("Invalid hex transaction data: {}", e))
+                    })?;
+                    let tx = NeoTransaction::decode(&data)?;
+                    let height = chain_state.get().await?.height;
+                    if tx.valid_until_block <= height {
+                        return Err(Error::InvalidParameter(format!(
+                            "Transaction expired at block {}, current height is {}",
+                            tx.valid_until_block, height
+                        )));
+                    }
+                }
             }
             BlockchainType::Ethereum => {
                 // Validate Ethereum transaction
@@ -215,7 +355,7 @@ impl<S: MetaTxStorage> MetaTxService<S> {
         // Parse the transaction data
         debug!("Relaying Neo N3 transaction: {:?}", request);
 
-        // For Neo N3, we need to use the relayer wallet to pay for the transaction fees
+        // For Neo N3, we need to use the relayer signer to pay for the transaction fees
         let rpc_client = self.rpc_client.clone();
         
         // Decode the hex transaction data
@@ -264,26 +404,37 @@ impl<S: MetaTxStorage> MetaTxService<S> {
             return Err(Error::InvalidParameter("Invalid signature".to_string()));
         }
 
-        // Create a domain for the EIP-712 message
-        let domain = EIP712Domain {
-            name: "R3E Meta Transaction".to_string(),
-            version: "1".to_string(),
-            chain_id: request.chain_id.unwrap_or_else(|| request.blockchain_type.to_chain_id()),
-            verifying_contract: "0x0000000000000000000000000000000000000000".to_string(),
-            salt: None,
-        };
-
-        // Get the meta transaction message
-        let message = MetaTxMessage::from_request(request.clone());
+        // Meta transactions for a forwarder were checked against its domain above
+        if self.forwarder(&request)?.is_none() {
+            // Create a domain for the EIP-712 message
+            let domain = EIP712Domain {
+                name: "R3E Meta Transaction".to_string(),
+                version: "1".to_string(),
+                chain_id: request.chain_id.unwrap_or_else(|| request.blockchain_type.to_chain_id()),
+                verifying_contract: "0x0000000000000000000000000000000000000000".to_string(),
+                salt: None,
+            };
+
+            // Get the meta transaction message
+            let message = MetaTxMessage::from_request(request.clone());
+
+            // Create the typed data
+            let typed_data = get_typed_data(domain, message)?;
+
+            // Verify the EIP-712 signature
+            let signature_is_valid = verify_eip712_signature(&typed_data, &request.signature, &request.sender)?;
+            if !signature_is_valid {
+                error!("Invalid EIP-712 signature");
+                return Err(Error::InvalidParameter("Invalid EIP-712 signature".to_string()));
+            }
+        }
 
-        // Create the typed data
-        let typed_data = get_typed_data(domain, message)?;
+        // Check the signed fee covers the network cost
+        self.check_fee(&request).await?;
 
-        // Verify the EIP-712 signature
-        let signature_is_valid = verify_eip712_signature(&typed_data, &request.signature, &request.sender)?;
-        if !signature_is_valid {
-            error!("Invalid EIP-712 signature");
-            return Err(Error::InvalidParameter("Invalid EIP-712 signature".to_string()));
+        // Use up the nonce, so the signed request cannot be relayed again
+        if let Some(nonces) = &self.nonces {
+            nonces.claim(&request.sender, request.nonce)?;
         }
 
         // Relay the transaction
@@ -301,6 +452,7 @@ impl<S: MetaTxStorage> MetaTxService<S> {
             status: MetaTxStatus::Pending,
             created_at: timestamp,
             updated_at: timestamp,
+            fee_bumps: Vec::new(),
         };
 
         // Store the record
@@ -324,6 +476,7 @@ impl<S: MetaTxStorage> MetaTxService<S> {
             status: MetaTxStatus::Submitted,
             created_at: timestamp,
             updated_at: timestamp,
+            fee_bumps: Vec::new(),
         };
 
         // Store the updated record
@@ -332,6 +485,51 @@ impl<S: MetaTxStorage> MetaTxService<S> {
         // Return the response
         Ok(response)
     }
+
+    /// Run a meta transaction against the latest chain state without relaying it
+    pub async fn simulate(&self, request: MetaTxRequest) -> Result<MetaTxSimulation, Error> {
+        debug!("Simulating meta transaction: {:?}", request);
+
+        // Validate the request
+        self.validate_request(&request).await?;
+
+        // An invalid signature fails the submission, the transaction is still run so the
+        // caller learns about every problem at once
+        let signature_valid = self.verify_signature(&request).await.unwrap_or(false);
+
+        // Decode the transaction data
+        let (network, tx, _) = self.decode_tx(&FeeQuoteRequest::from(&request))?;
+        let chain = self.chains.get(request.blockchain_type.chain(), &network)?;
+
+        // Run the transaction
+        let outcome = chain.simulate_tx(&tx).await?;
+        info!(
+            "Simulated meta transaction from {}: success={}, gas={:?}",
+            request.sender, outcome.success, outcome.gas_used
+        );
+
+        Ok(MetaTxSimulation {
+            success: signature_valid && outcome.success,
+            signature_valid,
+            result: outcome.result,
+            gas_cost: outcome.gas_used,
+            revert_reason: outcome.error,
+        })
+    }
+}
+
+/// Network of an Ethereum chain ID, naming its RPC endpoints in the chain client configuration
+fn ethereum_network(chain_id: u64) -> Result<&'static str, Error> {
+    match chain_id {
+        1 => Ok("mainnet"),
+        11155111 => Ok("sepolia"),
+        17000 => Ok("holesky"),
+        1337 => Ok("local"),
+        _ => Err(Error::InvalidParameter(format!(
+            "Unsupported Ethereum chain ID: {}",
+            chain_id
+        ))),
+    }
 }
 
 #[async_trait]
@@ -340,6 +538,14 @@ impl<S: MetaTxStorage> MetaTxServiceTrait for MetaTxService<S> {
         self.submit(request).await
     }
 
+    async fn simulate(&self, request: MetaTxRequest) -> Result<MetaTxSimulation, Error> {
+        self.simulate(request).await
+    }
+
+    async fn quote(&self, request: &FeeQuoteRequest) -> Result<FeeQuote, Error> {
+        self.quote(request).await
+    }
+
     async fn get_status(&self, request_id: &str) -> Result<String, Error> {
         // Get record
         let record = match self.storage.get_record(request_id).await? {
@@ -364,6 +570,9 @@ impl<S: MetaTxStorage> MetaTxServiceTrait for MetaTxService<S> {
     }
 
     async fn get_next_nonce(&self, sender: &str) -> Result<u64, Error> {
+        if let Some(nonces) = &self.nonces {
+            return nonces.next(sender);
+        }
         self.storage.get_nonce(sender).await
     }
 
@@ -371,21 +580,24 @@ impl<S: MetaTxStorage> MetaTxServiceTrait for MetaTxService<S> {
         // Create a gas bank service with default settings
         let storage = self.gas_bank_storage.clone();
         let rpc_client = self.rpc_client.clone();
-        let wallet = self.relayer_wallet.clone();
+        let relayer_signer = self.relayer_signer.clone();
         let network = self.network.clone();
         
-        // Default settings
-        let fee_model = FeeModel::Percentage(1.0); // 1% fee
+        // Charge the fee model of the contract's service
+        let fee_model = self.fee_schedule.model(contract_hash).clone();
         let credit_limit = 1_000_000_000; // 1 GAS
 
-        let gas_bank_service = GasBankService::new(
+        let mut gas_bank_service = GasBankService::new(
             storage,
             rpc_client,
-            wallet,
+            relayer_signer,
             network,
             fee_model.clone(),
             credit_limit,
         );
+        if let Some(chain_state) = &self.chain_state {
+            gas_bank_service = gas_bank_service.with_chain_state(chain_state.clone());
+        }
         
         // Check if there's an account for this contract
         match gas_bank_service
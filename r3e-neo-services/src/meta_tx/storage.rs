@@ -15,6 +15,12 @@ pub trait MetaTxStorage: Send + Sync {
     /// Get meta transaction records by sender
     async fn get_records_by_sender(&self, sender: &str) -> Result<Vec<MetaTxRecord>, Error>;
 
+    /// Get meta transaction records by status
+    async fn get_records_by_status(
+        &self,
+        status: &MetaTxStatus,
+    ) -> Result<Vec<MetaTxRecord>, Error>;
+
     /// Create meta transaction record
     async fn create_record(&self, record: MetaTxRecord) -> Result<(), Error>;
 
@@ -55,6 +61,18 @@ impl MetaTxStorage for InMemoryMetaTxStorage {
             .collect())
     }
 
+    async fn get_records_by_status(
+        &self,
+        status: &MetaTxStatus,
+    ) -> Result<Vec<MetaTxRecord>, Error> {
+        let records = self.records.read().await;
+        Ok(records
+            .iter()
+            .filter(|r| &r.status == status)
+            .cloned()
+            .collect())
+    }
+
     async fn create_record(&self, record: MetaTxRecord) -> Result<(), Error> {
         let mut records = self.records.write().await;
         if records.iter().any(|r| r.request_id == record.request_id) {
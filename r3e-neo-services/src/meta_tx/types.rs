@@ -5,7 +5,7 @@ use crate::types::FeeModel;
 use serde::{Deserialize, Serialize};
 
 /// Blockchain type for meta transactions
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum BlockchainType {
     /// Neo N3 blockchain
     #[serde(rename = "neo")]
@@ -24,6 +24,14 @@ impl BlockchainType {
             BlockchainType::Ethereum => 1337, // Ethereum testnet chain ID
         }
     }
+
+    /// Chain name of the chain clients
+    pub fn chain(&self) -> &'static str {
+        match self {
+            BlockchainType::NeoN3 => "neo",
+            BlockchainType::Ethereum => "ethereum",
+        }
+    }
 }
 
 impl Default for BlockchainType {
@@ -85,6 +93,44 @@ pub struct MetaTxRequest {
     pub fee_model: Option<String>,
 }
 
+/// Request for the fee of relaying a meta transaction, made before signing it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FeeQuoteRequest {
+    /// Transaction data (serialized transaction)
+    pub tx_data: String,
+    /// Sender address
+    pub sender: String,
+    /// Target address (contract or account)
+    pub target_address: String,
+    /// Blockchain type (neo or ethereum)
+    #[serde(default)]
+    pub blockchain_type: BlockchainType,
+    /// Target contract address
+    pub target_contract: Option<String>,
+    /// Chain ID
+    pub chain_id: Option<u64>,
+}
+
+impl FeeQuoteRequest {
+    /// Service the transaction is relayed for, named by the contract it calls
+    pub fn service(&self) -> &str {
+        self.target_contract.as_deref().unwrap_or(&self.target_address)
+    }
+}
+
+impl From<&MetaTxRequest> for FeeQuoteRequest {
+    fn from(request: &MetaTxRequest) -> Self {
+        Self {
+            tx_data: request.tx_data.clone(),
+            sender: request.sender.clone(),
+            target_address: request.target_address.clone(),
+            blockchain_type: request.blockchain_type,
+            target_contract: request.target_contract.clone(),
+            chain_id: request.chain_id,
+        }
+    }
+}
+
 /// Meta transaction response
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MetaTxResponse {
@@ -102,8 +148,23 @@ pub struct MetaTxResponse {
     pub timestamp: u64,
 }
 
-/// Meta transaction status
+/// Outcome of running a meta transaction without relaying it
 #[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MetaTxSimulation {
+    /// Whether the transaction would be relayed and executed successfully
+    pub success: bool,
+    /// Whether the signature of the request is valid
+    pub signature_valid: bool,
+    /// Expected result: the result stack on Neo, the hex encoded return data on Ethereum
+    pub result: serde_json::Value,
+    /// Expected gas cost (system fee in datoshi on Neo, gas units on Ethereum)
+    pub gas_cost: Option<u64>,
+    /// Exception or revert reason of a failing transaction
+    pub revert_reason: Option<String>,
+}
+
+/// Meta transaction status
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum MetaTxStatus {
     /// Pending
     Pending,
@@ -117,6 +178,8 @@ pub enum MetaTxStatus {
     Expired,
     /// Rejected
     Rejected,
+    /// Unconfirmed after every allowed fee bump
+    Stuck,
 }
 
 impl ToString for MetaTxStatus {
@@ -128,6 +191,7 @@ impl ToString for MetaTxStatus {
             MetaTxStatus::Failed => "failed".to_string(),
             MetaTxStatus::Expired => "expired".to_string(),
             MetaTxStatus::Rejected => "rejected".to_string(),
+            MetaTxStatus::Stuck => "stuck".to_string(),
         }
     }
 }
@@ -147,4 +211,18 @@ pub struct MetaTxRecord {
     pub created_at: u64,
     /// Updated timestamp
     pub updated_at: u64,
+    /// Rebroadcasts with a higher fee, oldest first
+    #[serde(default)]
+    pub fee_bumps: Vec<FeeBump>,
+}
+
+/// Rebroadcast of a relayed transaction with a higher fee
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FeeBump {
+    /// Hash of the rebroadcast transaction
+    pub tx_hash: String,
+    /// Fee of the rebroadcast transaction (network fee in datoshi or gas price in wei)
+    pub fee: u64,
+    /// Rebroadcast timestamp
+    pub timestamp: u64,
 }
@@ -0,0 +1,88 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+//! Encryption of dealt shares to their recipient, so the coordinator relaying them
+//! never learns a share: ephemeral secp256r1 ECDH and AES-256-GCM.
+
+use aes_gcm::aead::{Aead, KeyInit, Payload};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use p256::elliptic_curve::ff::Field;
+use p256::elliptic_curve::point::AffineCoordinates;
+use p256::elliptic_curve::sec1::ToEncodedPoint;
+use p256::{ProjectivePoint, Scalar};
+use rand::rngs::OsRng;
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+
+use super::math::point_from_hex;
+use crate::Error;
+
+/// Length of a compressed point
+const POINT_LEN: usize = 33;
+
+/// Length of an AES-GCM nonce
+const NONCE_LEN: usize = 12;
+
+fn cipher(ephemeral: &ProjectivePoint, shared: &ProjectivePoint) -> Aes256Gcm {
+    let mut hasher = Sha256::new();
+    hasher.update(b"r3e-mpc-envelope");
+    hasher.update(ephemeral.to_affine().to_encoded_point(true).as_bytes());
+    hasher.update(shared.to_affine().x());
+    let key = hasher.finalize();
+
+    Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key))
+}
+
+/// Seal `plaintext` to the hex encoded public key of the recipient, binding it to `aad`
+pub fn seal(recipient: &str, aad: &[u8], plaintext: &[u8]) -> Result<String, Error> {
+    let recipient = point_from_hex(recipient)?;
+    if recipient == ProjectivePoint::IDENTITY {
+        return Err(Error::MpcError("Invalid recipient key".to_string()));
+    }
+
+    let ephemeral_secret = Scalar::random(&mut OsRng);
+    let ephemeral = ProjectivePoint::GENERATOR * ephemeral_secret;
+    let shared = recipient * ephemeral_secret;
+
+    let mut nonce = [0u8; NONCE_LEN];
+    OsRng.fill_bytes(&mut nonce);
+    let ciphertext = cipher(&ephemeral, &shared)
+        .encrypt(
+            Nonce::from_slice(&nonce),
+            Payload {
+                msg: plaintext,
+                aad,
+            },
+        )
+        .map_err(|e| Error::MpcError(format!("Failed to seal share: {}", e)))?;
+
+    let mut envelope = Vec::with_capacity(POINT_LEN + NONCE_LEN + ciphertext.len());
+    envelope.extend_from_slice(ephemeral.to_affine().to_encoded_point(true).as_bytes());
+    envelope.extend_from_slice(&nonce);
+    envelope.extend_from_slice(&ciphertext);
+    Ok(hex::encode(envelope))
+}
+
+/// Open an envelope sealed to the public key of `secret`
+pub fn open(secret: &Scalar, aad: &[u8], envelope: &str) -> Result<Vec<u8>, Error> {
+    let envelope = hex::decode(envelope)
+        .map_err(|e| Error::MpcError(format!("Invalid share envelope: {}", e)))?;
+    if envelope.len() < POINT_LEN + NONCE_LEN {
+        return Err(Error::MpcError("Share envelope too short".to_string()));
+    }
+
+    let (ephemeral, rest) = envelope.split_at(POINT_LEN);
+    let (nonce, ciphertext) = rest.split_at(NONCE_LEN);
+    let ephemeral = point_from_hex(&hex::encode(ephemeral))?;
+    let shared = ephemeral * secret;
+
+    cipher(&ephemeral, &shared)
+        .decrypt(
+            Nonce::from_slice(nonce),
+            Payload {
+                msg: ciphertext,
+                aad,
+            },
+        )
+        .map_err(|_| Error::MpcError("Failed to open share envelope".to_string()))
+}
@@ -0,0 +1,185 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+//! Shamir sharing over the secp256r1 scalar field.
+
+use p256::elliptic_curve::ff::{Field, PrimeField};
+use p256::elliptic_curve::sec1::{FromEncodedPoint, ToEncodedPoint};
+use p256::{AffinePoint, EncodedPoint, FieldBytes, ProjectivePoint, Scalar};
+use rand::rngs::OsRng;
+
+use crate::Error;
+
+/// Random polynomial of the given degree, with a zero constant term if `zero` is set
+pub fn random_polynomial(degree: usize, zero: bool) -> Vec<Scalar> {
+    (0..=degree)
+        .map(|i| {
+            if i == 0 && zero {
+                Scalar::ZERO
+            } else {
+                Scalar::random(&mut OsRng)
+            }
+        })
+        .collect()
+}
+
+/// Evaluate a polynomial at `x`
+pub fn evaluate(coefficients: &[Scalar], x: u16) -> Scalar {
+    let x = Scalar::from(x as u64);
+    coefficients
+        .iter()
+        .rev()
+        .fold(Scalar::ZERO, |acc, coefficient| acc * x + coefficient)
+}
+
+/// Feldman commitments to the coefficients of a polynomial
+pub fn commit(coefficients: &[Scalar]) -> Vec<ProjectivePoint> {
+    coefficients
+        .iter()
+        .map(|coefficient| ProjectivePoint::GENERATOR * coefficient)
+        .collect()
+}
+
+/// Evaluate committed coefficients at `x`, the public image of the share at `x`
+pub fn evaluate_commitments(commitments: &[ProjectivePoint], x: u16) -> ProjectivePoint {
+    let x = Scalar::from(x as u64);
+    commitments
+        .iter()
+        .rev()
+        .fold(ProjectivePoint::IDENTITY, |acc, commitment| {
+            acc * x + commitment
+        })
+}
+
+/// Check a share against the dealer's commitments
+pub fn verify_share(commitments: &[ProjectivePoint], x: u16, share: &Scalar) -> bool {
+    evaluate_commitments(commitments, x) == ProjectivePoint::GENERATOR * share
+}
+
+/// Lagrange coefficients interpolating at zero from the shares at `indices`
+pub fn lagrange_coefficients(indices: &[u16]) -> Result<Vec<Scalar>, Error> {
+    indices
+        .iter()
+        .map(|&i| {
+            let xi = Scalar::from(i as u64);
+            let mut numerator = Scalar::ONE;
+            let mut denominator = Scalar::ONE;
+            for &j in indices.iter().filter(|&&j| j != i) {
+                let xj = Scalar::from(j as u64);
+                numerator *= xj;
+                denominator *= xj - xi;
+            }
+
+            Option::<Scalar>::from(denominator.invert())
+                .map(|inverse| numerator * inverse)
+                .ok_or_else(|| Error::MpcError("Duplicate or zero share index".to_string()))
+        })
+        .collect()
+}
+
+/// Hex encoding of a scalar
+pub fn scalar_to_hex(scalar: &Scalar) -> String {
+    hex::encode(scalar.to_repr())
+}
+
+/// Decode a hex encoded scalar
+pub fn scalar_from_hex(value: &str) -> Result<Scalar, Error> {
+    let bytes = hex::decode(value)
+        .map_err(|e| Error::MpcError(format!("Invalid scalar encoding: {}", e)))?;
+    if bytes.len() != 32 {
+        return Err(Error::MpcError(format!(
+            "Invalid scalar length: {}",
+            bytes.len()
+        )));
+    }
+
+    Option::from(Scalar::from_repr(FieldBytes::clone_from_slice(&bytes)))
+        .ok_or_else(|| Error::MpcError("Scalar out of range".to_string()))
+}
+
+/// Compressed hex encoding of a point, `00` for the identity
+pub fn point_to_hex(point: &ProjectivePoint) -> String {
+    hex::encode(point.to_affine().to_encoded_point(true).as_bytes())
+}
+
+/// Decode a compressed hex encoded point
+pub fn point_from_hex(value: &str) -> Result<ProjectivePoint, Error> {
+    let bytes = hex::decode(value)
+        .map_err(|e| Error::MpcError(format!("Invalid point encoding: {}", e)))?;
+    let encoded = EncodedPoint::from_bytes(&bytes)
+        .map_err(|e| Error::MpcError(format!("Invalid point encoding: {}", e)))?;
+
+    Option::<AffinePoint>::from(AffinePoint::from_encoded_point(&encoded))
+        .map(ProjectivePoint::from)
+        .ok_or_else(|| Error::MpcError("Point not on the curve".to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Interpolate the secret at zero from the shares at `indices`
+    fn interpolate(shares: &[(u16, Scalar)]) -> Scalar {
+        let indices: Vec<u16> = shares.iter().map(|(index, _)| *index).collect();
+        lagrange_coefficients(&indices)
+            .unwrap()
+            .iter()
+            .zip(shares)
+            .map(|(lambda, (_, share))| *lambda * share)
+            .sum()
+    }
+
+    #[test]
+    fn test_any_threshold_subset_recovers_the_secret() {
+        let polynomial = random_polynomial(2, false);
+        let shares: Vec<(u16, Scalar)> = (1..=5)
+            .map(|index| (index, evaluate(&polynomial, index)))
+            .collect();
+
+        for subset in [[0, 1, 2], [2, 3, 4], [0, 2, 4], [4, 1, 3]] {
+            let subset: Vec<(u16, Scalar)> = subset.iter().map(|&i| shares[i]).collect();
+            assert_eq!(interpolate(&subset), polynomial[0]);
+        }
+
+        // Fewer shares than the degree plus one interpolate another value
+        assert_ne!(interpolate(&shares[..2]), polynomial[0]);
+    }
+
+    #[test]
+    fn test_verify_share_against_commitments() {
+        let polynomial = random_polynomial(1, false);
+        let commitments = commit(&polynomial);
+        assert_eq!(
+            evaluate_commitments(&commitments, 0),
+            ProjectivePoint::GENERATOR * polynomial[0]
+        );
+
+        for index in 1..=3 {
+            let share = evaluate(&polynomial, index);
+            assert!(verify_share(&commitments, index, &share));
+            assert!(!verify_share(&commitments, index, &(share + Scalar::ONE)));
+            assert!(!verify_share(&commitments, index + 1, &share));
+        }
+
+        let zero = random_polynomial(1, true);
+        assert_eq!(commit(&zero)[0], ProjectivePoint::IDENTITY);
+    }
+
+    #[test]
+    fn test_lagrange_rejects_duplicate_indices() {
+        assert!(lagrange_coefficients(&[1, 2, 2]).is_err());
+        assert_eq!(lagrange_coefficients(&[1]).unwrap(), vec![Scalar::ONE]);
+    }
+
+    #[test]
+    fn test_hex_round_trip() {
+        let scalar = Scalar::random(&mut OsRng);
+        assert_eq!(scalar_from_hex(&scalar_to_hex(&scalar)).unwrap(), scalar);
+        assert!(scalar_from_hex("00").is_err());
+        assert!(scalar_from_hex(&"ff".repeat(32)).is_err());
+
+        let point = ProjectivePoint::GENERATOR * scalar;
+        assert_eq!(point_from_hex(&point_to_hex(&point)).unwrap(), point);
+        assert!(point_from_hex(&format!("02{}", "00".repeat(31))).is_err());
+    }
+}
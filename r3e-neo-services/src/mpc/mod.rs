@@ -0,0 +1,25 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+//! Multi-party computation signing.
+//!
+//! Threshold ECDSA over secp256r1, so a relayer key is never held by a single node: the
+//! key is generated by a Feldman VSS distributed key generation among `n` parties, any
+//! `t` of which may be compromised without exposing it. Signatures are produced by
+//! `2t + 1` parties with the honest-majority protocol of Gennaro, Jarecki, Krawczyk and
+//! Rabin, and shares are rotated by proactive re-sharing, which keeps the public key.
+//! Parties are pluggable through [`MpcParty`]; [`LocalParty`] keeps its shares in a
+//! [`ShareStore`], sealed to its enclave with [`SealedShareStore`].
+
+pub mod envelope;
+pub mod math;
+pub mod party;
+pub mod rocksdb;
+pub mod service;
+pub mod storage;
+pub mod types;
+
+pub use party::{LocalParty, MemoryShareStore, MpcParty, SealedShareStore, ShareStore};
+pub use service::{MpcService, MpcServiceTrait};
+pub use storage::{MemoryMpcStorage, MpcStorage};
+pub use types::*;
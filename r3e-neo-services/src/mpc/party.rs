@@ -0,0 +1,633 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+use async_trait::async_trait;
+use p256::elliptic_curve::ff::Field;
+use p256::{ProjectivePoint, Scalar};
+use r3e_tee::sealing::SealedStorage;
+use rand::rngs::OsRng;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+use super::envelope;
+use super::math::{
+    commit, evaluate, point_from_hex, point_to_hex, random_polynomial, scalar_from_hex,
+    scalar_to_hex, verify_share,
+};
+use super::types::{
+    DealMessage, DealRequest, EncryptedShare, KeyShare, PartialSignRequest, PartialSignature,
+    Participant, PartyInfo, ReceiveOutput, SessionKind, Sharing,
+};
+use crate::Error;
+
+/// How long a party keeps an unused signing nonce
+const NONCE_TTL: Duration = Duration::from_secs(300);
+
+/// Party holding key shares; it only ever reveals public images and masked values
+#[async_trait]
+pub trait MpcParty: Send + Sync {
+    /// Party ID and the key it receives shares under
+    async fn info(&self) -> Result<PartyInfo, Error>;
+
+    /// Deal the sharings of a session to its participants
+    async fn deal(&self, request: &DealRequest) -> Result<DealMessage, Error>;
+
+    /// Verify and combine the sharings dealt to this party by every participant
+    async fn receive(
+        &self,
+        request: &DealRequest,
+        deals: &[DealMessage],
+    ) -> Result<ReceiveOutput, Error>;
+
+    /// Compute this party's share of a signature, consuming the session's nonce
+    async fn sign(&self, request: &PartialSignRequest) -> Result<PartialSignature, Error>;
+
+    /// Drop the shares a rotation to `epoch` replaced
+    async fn finalize(&self, key_id: &str, epoch: u64) -> Result<(), Error>;
+}
+
+/// Store of the key shares of a party
+pub trait ShareStore: Send + Sync {
+    /// Get a share
+    fn get(&self, key_id: &str, epoch: u64) -> Result<Option<KeyShare>, Error>;
+
+    /// Put a share
+    fn put(&self, share: &KeyShare) -> Result<(), Error>;
+
+    /// Delete a share
+    fn delete(&self, key_id: &str, epoch: u64) -> Result<(), Error>;
+}
+
+/// In-memory share store
+#[derive(Default)]
+pub struct MemoryShareStore {
+    shares: RwLock<HashMap<(String, u64), KeyShare>>,
+}
+
+impl MemoryShareStore {
+    /// Create a new in-memory share store
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl ShareStore for MemoryShareStore {
+    fn get(&self, key_id: &str, epoch: u64) -> Result<Option<KeyShare>, Error> {
+        let shares = self
+            .shares
+            .read()
+            .map_err(|e| Error::InternalError(format!("Failed to acquire lock: {}", e)))?;
+        Ok(shares.get(&(key_id.to_string(), epoch)).cloned())
+    }
+
+    fn put(&self, share: &KeyShare) -> Result<(), Error> {
+        let mut shares = self
+            .shares
+            .write()
+            .map_err(|e| Error::InternalError(format!("Failed to acquire lock: {}", e)))?;
+        shares.insert((share.key_id.clone(), share.epoch), share.clone());
+        Ok(())
+    }
+
+    fn delete(&self, key_id: &str, epoch: u64) -> Result<(), Error> {
+        let mut shares = self
+            .shares
+            .write()
+            .map_err(|e| Error::InternalError(format!("Failed to acquire lock: {}", e)))?;
+        shares.remove(&(key_id.to_string(), epoch));
+        Ok(())
+    }
+}
+
+/// Share store sealing shares to the enclave the party runs in
+pub struct SealedShareStore {
+    storage: Arc<SealedStorage>,
+    namespace: String,
+}
+
+impl SealedShareStore {
+    /// Keep the shares of party `party_id` in `storage`
+    pub fn new(storage: Arc<SealedStorage>, party_id: &str) -> Self {
+        Self {
+            storage,
+            namespace: format!("mpc/{}", party_id),
+        }
+    }
+}
+
+impl ShareStore for SealedShareStore {
+    fn get(&self, key_id: &str, epoch: u64) -> Result<Option<KeyShare>, Error> {
+        let share = self
+            .storage
+            .get(&self.namespace, &format!("{}/{}", key_id, epoch))
+            .map_err(|e| Error::Storage(format!("Failed to unseal share: {}", e)))?;
+
+        share
+            .map(|share| serde_json::from_slice(&share))
+            .transpose()
+            .map_err(Error::from)
+    }
+
+    fn put(&self, share: &KeyShare) -> Result<(), Error> {
+        let value = serde_json::to_vec(share)?;
+        self.storage
+            .put(
+                &self.namespace,
+                &format!("{}/{}", share.key_id, share.epoch),
+                &value,
+            )
+            .map_err(|e| Error::Storage(format!("Failed to seal share: {}", e)))
+    }
+
+    fn delete(&self, key_id: &str, epoch: u64) -> Result<(), Error> {
+        self.storage
+            .delete(&self.namespace, &format!("{}/{}", key_id, epoch))
+            .map(|_| ())
+            .map_err(|e| Error::Storage(format!("Failed to delete share: {}", e)))
+    }
+}
+
+/// Nonce shares kept between the nonce and signing rounds
+struct NonceSession {
+    key_id: String,
+    epoch: u64,
+    a: Scalar,
+    c: Scalar,
+    created_at: Instant,
+}
+
+/// Party running in this process, e.g. inside an enclave with a sealed share store
+pub struct LocalParty {
+    id: String,
+    encryption_secret: Scalar,
+    shares: Arc<dyn ShareStore>,
+    sessions: Mutex<HashMap<String, NonceSession>>,
+}
+
+impl LocalParty {
+    /// Create a party keeping its shares in `shares`
+    pub fn new(id: &str, shares: Arc<dyn ShareStore>) -> Self {
+        Self {
+            id: id.to_string(),
+            encryption_secret: Scalar::random(&mut OsRng),
+            shares,
+            sessions: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// This party among the participants of a session
+    fn me<'a>(&self, request: &'a DealRequest) -> Result<&'a Participant, Error> {
+        request
+            .participants
+            .iter()
+            .find(|participant| participant.id == self.id)
+            .ok_or_else(|| {
+                Error::MpcError(format!(
+                    "Party {} does not take part in session {}",
+                    self.id, request.session_id
+                ))
+            })
+    }
+
+    /// Current share of a key
+    fn share(&self, key_id: &str, epoch: u64) -> Result<(KeyShare, Scalar), Error> {
+        let share = self.shares.get(key_id, epoch)?.ok_or_else(|| {
+            Error::NotFound(format!(
+                "Party {} holds no share of key {} at epoch {}",
+                self.id, key_id, epoch
+            ))
+        })?;
+        let secret = scalar_from_hex(&share.secret)?;
+        Ok((share, secret))
+    }
+}
+
+/// Degrees of the sharings of a session kind, and whether their secret is zero
+fn sharing_layout(kind: SessionKind, threshold: u16) -> Vec<(usize, bool)> {
+    let t = threshold as usize;
+    match kind {
+        SessionKind::KeyGen => vec![(t, false)],
+        SessionKind::Refresh => vec![(t, true)],
+        SessionKind::Nonce => vec![(t, false), (t, false), (2 * t, true), (2 * t, true)],
+    }
+}
+
+/// Associated data binding a dealt share to its session, dealer, recipient and sharing
+fn share_aad(request: &DealRequest, from: u16, to: u16, sharing: usize) -> Vec<u8> {
+    format!(
+        "{}:{}:{}:{}:{}:{}",
+        request.session_id, request.key_id, request.epoch, from, to, sharing
+    )
+    .into_bytes()
+}
+
+/// Check the participants of a session
+pub(crate) fn check_participants(request: &DealRequest) -> Result<(), Error> {
+    if request.threshold == 0 {
+        return Err(Error::MpcError("Threshold must be at least 1".to_string()));
+    }
+
+    let required = 2 * request.threshold as usize + 1;
+    if request.participants.len() < required {
+        return Err(Error::MpcError(format!(
+            "Session needs at least {} parties, got {}",
+            required,
+            request.participants.len()
+        )));
+    }
+
+    let mut indices: Vec<u16> = request.participants.iter().map(|p| p.index).collect();
+    indices.sort_unstable();
+    indices.dedup();
+    if indices.len() != request.participants.len() || indices.first() == Some(&0) {
+        return Err(Error::MpcError(
+            "Share indices must be distinct and non-zero".to_string(),
+        ));
+    }
+
+    Ok(())
+}
+
+#[async_trait]
+impl MpcParty for LocalParty {
+    async fn info(&self) -> Result<PartyInfo, Error> {
+        Ok(PartyInfo {
+            id: self.id.clone(),
+            encryption_key: point_to_hex(&(ProjectivePoint::GENERATOR * self.encryption_secret)),
+        })
+    }
+
+    async fn deal(&self, request: &DealRequest) -> Result<DealMessage, Error> {
+        check_participants(request)?;
+        let me = self.me(request)?;
+        if request.kind != SessionKind::KeyGen {
+            // Only holders of the current share take part in rotations and signing
+            self.share(&request.key_id, request.epoch)?;
+        }
+
+        let mut sharings = Vec::new();
+        for (i, (degree, zero)) in sharing_layout(request.kind, request.threshold)
+            .into_iter()
+            .enumerate()
+        {
+            let polynomial = random_polynomial(degree, zero);
+            let mut shares = Vec::with_capacity(request.participants.len());
+            for participant in &request.participants {
+                let share = evaluate(&polynomial, participant.index);
+                shares.push(EncryptedShare {
+                    to: participant.index,
+                    envelope: envelope::seal(
+                        &participant.encryption_key,
+                        &share_aad(request, me.index, participant.index, i),
+                        scalar_to_hex(&share).as_bytes(),
+                    )?,
+                });
+            }
+
+            sharings.push(Sharing {
+                commitments: commit(&polynomial).iter().map(point_to_hex).collect(),
+                shares,
+            });
+        }
+
+        Ok(DealMessage {
+            session_id: request.session_id.clone(),
+            from: me.index,
+            sharings,
+        })
+    }
+
+    async fn receive(
+        &self,
+        request: &DealRequest,
+        deals: &[DealMessage],
+    ) -> Result<ReceiveOutput, Error> {
+        check_participants(request)?;
+        let me = self.me(request)?;
+        let layout = sharing_layout(request.kind, request.threshold);
+
+        // Every participant must have dealt, exactly once
+        let mut dealers: Vec<u16> = deals.iter().map(|deal| deal.from).collect();
+        dealers.sort_unstable();
+        let mut expected: Vec<u16> = request.participants.iter().map(|p| p.index).collect();
+        expected.sort_unstable();
+        if dealers != expected {
+            return Err(Error::MpcError(format!(
+                "Session {} is missing deals",
+                request.session_id
+            )));
+        }
+
+        let mut sums = vec![Scalar::ZERO; layout.len()];
+        for deal in deals {
+            if deal.session_id != request.session_id || deal.sharings.len() != layout.len() {
+                return Err(Error::MpcError(format!(
+                    "Malformed deal from party {}",
+                    deal.from
+                )));
+            }
+
+            for (i, (sharing, (degree, zero))) in deal.sharings.iter().zip(&layout).enumerate() {
+                let commitments = sharing
+                    .commitments
+                    .iter()
+                    .map(|commitment| point_from_hex(commitment))
+                    .collect::<Result<Vec<_>, _>>()?;
+                if commitments.len() != degree + 1
+                    || (*zero && commitments[0] != ProjectivePoint::IDENTITY)
+                {
+                    return Err(Error::MpcError(format!(
+                        "Invalid commitments from party {}",
+                        deal.from
+                    )));
+                }
+
+                let share = sharing
+                    .shares
+                    .iter()
+                    .find(|share| share.to == me.index)
+                    .ok_or_else(|| {
+                        Error::MpcError(format!("Party {} dealt no share to us", deal.from))
+                    })?;
+                let plaintext = envelope::open(
+                    &self.encryption_secret,
+                    &share_aad(request, deal.from, me.index, i),
+                    &share.envelope,
+                )?;
+                let share = scalar_from_hex(&String::from_utf8_lossy(&plaintext))?;
+                if !verify_share(&commitments, me.index, &share) {
+                    return Err(Error::MpcError(format!(
+                        "Share from party {} does not match its commitments",
+                        deal.from
+                    )));
+                }
+
+                sums[i] += share;
+            }
+        }
+
+        match request.kind {
+            SessionKind::KeyGen | SessionKind::Refresh => {
+                let (epoch, secret) = match request.kind {
+                    SessionKind::KeyGen => (request.epoch, sums[0]),
+                    _ => {
+                        let (_, current) = self.share(&request.key_id, request.epoch)?;
+                        (request.epoch + 1, current + sums[0])
+                    }
+                };
+
+                self.shares.put(&KeyShare {
+                    key_id: request.key_id.clone(),
+                    epoch,
+                    index: me.index,
+                    secret: scalar_to_hex(&secret),
+                })?;
+
+                Ok(ReceiveOutput::Share {
+                    index: me.index,
+                    verification_share: point_to_hex(&(ProjectivePoint::GENERATOR * secret)),
+                })
+            }
+            SessionKind::Nonce => {
+                let (k, a, b, c) = (sums[0], sums[1], sums[2], sums[3]);
+
+                let mut sessions = self
+                    .sessions
+                    .lock()
+                    .map_err(|e| Error::InternalError(format!("Failed to acquire lock: {}", e)))?;
+                sessions.retain(|_, session| session.created_at.elapsed() < NONCE_TTL);
+                sessions.insert(
+                    request.session_id.clone(),
+                    NonceSession {
+                        key_id: request.key_id.clone(),
+                        epoch: request.epoch,
+                        a,
+                        c,
+                        created_at: Instant::now(),
+                    },
+                );
+
+                Ok(ReceiveOutput::Nonce {
+                    index: me.index,
+                    nonce_point: point_to_hex(&(ProjectivePoint::GENERATOR * k)),
+                    masked_product: scalar_to_hex(&(k * a + b)),
+                })
+            }
+        }
+    }
+
+    async fn sign(&self, request: &PartialSignRequest) -> Result<PartialSignature, Error> {
+        // A nonce signs at most one message
+        let session = self
+            .sessions
+            .lock()
+            .map_err(|e| Error::InternalError(format!("Failed to acquire lock: {}", e)))?
+            .remove(&request.session_id)
+            .ok_or_else(|| {
+                Error::MpcError(format!("Unknown nonce session: {}", request.session_id))
+            })?;
+        if session.key_id != request.key_id || session.epoch != request.epoch {
+            return Err(Error::MpcError(format!(
+                "Nonce session {} belongs to another key",
+                request.session_id
+            )));
+        }
+
+        let (share, x) = self.share(&request.key_id, request.epoch)?;
+        let digest = scalar_from_hex(&request.digest)?;
+        let r = scalar_from_hex(&request.r)?;
+        let product = Option::<Scalar>::from(scalar_from_hex(&request.product)?.invert())
+            .ok_or_else(|| Error::MpcError("Degenerate nonce".to_string()))?;
+
+        // s_i = (k * a)^-1 * a_i * (z + r * x_i) + c_i, a share of k^-1 * (z + r * x)
+        let s = product * session.a * (digest + r * x) + session.c;
+
+        Ok(PartialSignature {
+            index: share.index,
+            s: scalar_to_hex(&s),
+        })
+    }
+
+    async fn finalize(&self, key_id: &str, epoch: u64) -> Result<(), Error> {
+        if epoch > 0 {
+            self.shares.delete(key_id, epoch - 1)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parties(n: usize) -> Vec<LocalParty> {
+        (1..=n)
+            .map(|i| LocalParty::new(&format!("party-{}", i), Arc::new(MemoryShareStore::new())))
+            .collect()
+    }
+
+    async fn session(
+        parties: &[LocalParty],
+        kind: SessionKind,
+        epoch: u64,
+        threshold: u16,
+    ) -> DealRequest {
+        let mut participants = Vec::new();
+        for (i, party) in parties.iter().enumerate() {
+            let info = party.info().await.unwrap();
+            participants.push(Participant {
+                index: i as u16 + 1,
+                id: info.id,
+                encryption_key: info.encryption_key,
+            });
+        }
+
+        DealRequest {
+            session_id: format!("{:?}-{}", kind, epoch),
+            kind,
+            key_id: "key".to_string(),
+            epoch,
+            threshold,
+            participants,
+        }
+    }
+
+    async fn deal(parties: &[LocalParty], request: &DealRequest) -> Vec<DealMessage> {
+        let mut deals = Vec::new();
+        for party in parties {
+            deals.push(party.deal(request).await.unwrap());
+        }
+        deals
+    }
+
+    /// Generate a key shared by `parties`
+    async fn keygen(parties: &[LocalParty], threshold: u16) {
+        let request = session(parties, SessionKind::KeyGen, 0, threshold).await;
+        let deals = deal(parties, &request).await;
+        for party in parties {
+            party.receive(&request, &deals).await.unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_corrupted_share_fails_feldman_check() {
+        let parties = parties(3);
+        let request = session(&parties, SessionKind::KeyGen, 0, 1).await;
+        let mut deals = deal(&parties, &request).await;
+
+        // Party 2 deals party 1 a share off its committed polynomial, sealed correctly
+        let share = deals[1].sharings[0]
+            .shares
+            .iter_mut()
+            .find(|share| share.to == 1)
+            .unwrap();
+        share.envelope = envelope::seal(
+            &request.participants[0].encryption_key,
+            &share_aad(&request, 2, 1, 0),
+            scalar_to_hex(&Scalar::random(&mut OsRng)).as_bytes(),
+        )
+        .unwrap();
+
+        let err = parties[0].receive(&request, &deals).await.unwrap_err();
+        assert!(err.to_string().contains("does not match its commitments"));
+        assert!(parties[0].shares.get("key", 0).unwrap().is_none());
+
+        // The other recipients' shares are unaffected
+        assert!(parties[2].receive(&request, &deals).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_corrupted_deal_is_rejected() {
+        let parties = parties(3);
+        let request = session(&parties, SessionKind::KeyGen, 0, 1).await;
+        let deals = deal(&parties, &request).await;
+
+        // Commitments that do not match the dealt shares
+        let mut tampered = deals.clone();
+        tampered[0].sharings[0].commitments[1] = point_to_hex(&ProjectivePoint::GENERATOR);
+        for party in &parties[1..] {
+            let err = party.receive(&request, &tampered).await.unwrap_err();
+            assert!(err.to_string().contains("does not match its commitments"));
+        }
+
+        // A share sealed for another dealer does not open
+        let mut replayed = deals.clone();
+        replayed[0].sharings[0].shares = deals[1].sharings[0].shares.clone();
+        assert!(parties[1].receive(&request, &replayed).await.is_err());
+
+        // Every participant must deal
+        assert!(parties[0].receive(&request, &deals[..2]).await.is_err());
+
+        for party in &parties {
+            party.receive(&request, &deals).await.unwrap();
+        }
+
+        // A rotation must deal a sharing of zero
+        let refresh = session(&parties, SessionKind::Refresh, 0, 1).await;
+        let mut deals = deal(&parties, &refresh).await;
+        deals[2].sharings[0].commitments[0] = point_to_hex(&ProjectivePoint::GENERATOR);
+        let err = parties[0].receive(&refresh, &deals).await.unwrap_err();
+        assert!(err.to_string().contains("Invalid commitments"));
+    }
+
+    #[tokio::test]
+    async fn test_sessions_need_enough_parties() {
+        let parties = parties(3);
+
+        // Threshold 1 needs 3 parties, threshold 2 needs 5
+        let request = session(&parties, SessionKind::KeyGen, 0, 2).await;
+        let err = parties[0].deal(&request).await.unwrap_err();
+        assert!(err.to_string().contains("at least 5 parties"));
+
+        let mut request = session(&parties, SessionKind::KeyGen, 0, 1).await;
+        request.participants.pop();
+        assert!(parties[0].deal(&request).await.is_err());
+
+        request.threshold = 0;
+        assert!(parties[0].deal(&request).await.is_err());
+
+        // Only holders of a share deal nonces
+        let request = session(&parties, SessionKind::Nonce, 0, 1).await;
+        assert!(parties[0].deal(&request).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_signing_session_is_used_once() {
+        let parties = parties(3);
+        keygen(&parties, 1).await;
+
+        let request = session(&parties, SessionKind::Nonce, 0, 1).await;
+        let deals = deal(&parties, &request).await;
+        for party in &parties {
+            party.receive(&request, &deals).await.unwrap();
+        }
+
+        let sign = PartialSignRequest {
+            session_id: request.session_id.clone(),
+            key_id: "key".to_string(),
+            epoch: 0,
+            digest: scalar_to_hex(&Scalar::from(7u64)),
+            r: scalar_to_hex(&Scalar::from(11u64)),
+            product: scalar_to_hex(&Scalar::from(13u64)),
+        };
+        let partial = parties[0].sign(&sign).await.unwrap();
+        assert_eq!(partial.index, 1);
+
+        // Signing another message with the same nonce would reveal the key
+        let reused = PartialSignRequest {
+            digest: scalar_to_hex(&Scalar::from(8u64)),
+            ..sign.clone()
+        };
+        let err = parties[0].sign(&reused).await.unwrap_err();
+        assert!(err.to_string().contains("Unknown nonce session"));
+
+        // A nonce of another key is refused, and consumed all the same
+        let other = PartialSignRequest {
+            key_id: "other".to_string(),
+            ..sign.clone()
+        };
+        assert!(parties[1].sign(&other).await.is_err());
+        assert!(parties[1].sign(&sign).await.is_err());
+    }
+}
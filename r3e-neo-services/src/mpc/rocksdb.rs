@@ -0,0 +1,86 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+use async_trait::async_trait;
+use r3e_store::rocksdb::RocksDbConfig;
+use r3e_store::RocksDBStore;
+use std::path::Path;
+use std::sync::Arc;
+
+use super::storage::MpcStorage;
+use super::types::ThresholdKey;
+use crate::Error;
+
+/// RocksDB implementation of MpcStorage
+pub struct RocksDBMpcStorage {
+    db: Arc<RocksDBStore>,
+    keys_cf: String,
+}
+
+impl RocksDBMpcStorage {
+    /// Create a new RocksDB MPC storage
+    pub async fn new<P: AsRef<Path>>(db_path: P) -> Result<Self, Error> {
+        let config = RocksDbConfig {
+            path: db_path.as_ref().to_string_lossy().to_string(),
+            ..Default::default()
+        };
+
+        let db = RocksDBStore::new(config);
+
+        // Open the database
+        db.open()
+            .map_err(|e| Error::Storage(format!("Failed to open RocksDB store: {}", e)))?;
+
+        let keys_cf = "mpc_keys".to_string();
+        db.create_cf_if_missing(&keys_cf).map_err(|e| {
+            Error::Storage(format!("Failed to create column family {}: {}", keys_cf, e))
+        })?;
+
+        Ok(Self {
+            db: Arc::new(db),
+            keys_cf,
+        })
+    }
+}
+
+#[async_trait]
+impl MpcStorage for RocksDBMpcStorage {
+    async fn get_key(&self, key_id: &str) -> Result<Option<ThresholdKey>, Error> {
+        match self.db.get_cf::<_, Vec<u8>>(&self.keys_cf, key_id) {
+            Ok(Some(value)) => serde_json::from_slice(&value)
+                .map(Some)
+                .map_err(|e| Error::Storage(format!("Failed to deserialize key: {}", e))),
+            Ok(None) => Ok(None),
+            Err(e) => Err(Error::Storage(format!("Failed to get key: {}", e))),
+        }
+    }
+
+    async fn put_key(&self, key: ThresholdKey) -> Result<(), Error> {
+        let value = serde_json::to_vec(&key)
+            .map_err(|e| Error::Storage(format!("Failed to serialize key: {}", e)))?;
+
+        self.db
+            .put_cf(&self.keys_cf, &key.id, &value)
+            .map_err(|e| Error::Storage(format!("Failed to store key: {}", e)))
+    }
+
+    async fn list_keys(&self, owner: &str) -> Result<Vec<ThresholdKey>, Error> {
+        // An empty prefix iterates over every key
+        let iter = self
+            .db
+            .prefix_iter_cf::<Vec<u8>>(&self.keys_cf, &[])
+            .map_err(|e| Error::Storage(format!("Failed to scan keys: {}", e)))?;
+
+        let mut keys = Vec::new();
+        for (_, value) in iter {
+            let key = serde_json::from_slice::<ThresholdKey>(&value)
+                .map_err(|e| Error::Storage(format!("Failed to deserialize key: {}", e)))?;
+            if key.owner == owner {
+                keys.push(key);
+            }
+        }
+
+        keys.sort_by_key(|key| key.created_at);
+        Ok(keys)
+    }
+}
@@ -0,0 +1,566 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+use async_trait::async_trait;
+use p256::ecdsa::signature::Verifier;
+use p256::ecdsa::{Signature, VerifyingKey};
+use p256::elliptic_curve::ff::{Field, PrimeField};
+use p256::elliptic_curve::ops::Reduce;
+use p256::elliptic_curve::point::AffineCoordinates;
+use p256::elliptic_curve::sec1::ToEncodedPoint;
+use p256::{ProjectivePoint, Scalar, U256};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+use super::math::{
+    evaluate_commitments, lagrange_coefficients, point_from_hex, point_to_hex, scalar_from_hex,
+    scalar_to_hex,
+};
+use super::party::{check_participants, MpcParty};
+use super::storage::MpcStorage;
+use super::types::{
+    DealMessage, DealRequest, KeyParty, PartialSignRequest, Participant, ReceiveOutput,
+    SessionKind, ThresholdKey,
+};
+use crate::signer::address_from_public_key;
+use crate::Error;
+
+/// MPC service trait
+#[async_trait]
+pub trait MpcServiceTrait: Send + Sync {
+    /// Generate a threshold key shared by all parties, tolerating `threshold` compromised
+    /// parties
+    async fn create_key(&self, owner: &str, threshold: u16) -> Result<ThresholdKey, Error>;
+
+    /// Get a key
+    async fn get_key(&self, key_id: &str) -> Result<ThresholdKey, Error>;
+
+    /// List the keys of an owner
+    async fn list_keys(&self, owner: &str) -> Result<Vec<ThresholdKey>, Error>;
+
+    /// Sign the SHA-256 digest of a message, returning the 64-byte `r || s` signature
+    async fn sign(&self, key_id: &str, message: &[u8]) -> Result<Vec<u8>, Error>;
+
+    /// Re-share a key among its parties; the public key stays the same and the previous
+    /// shares become useless
+    async fn rotate_shares(&self, key_id: &str) -> Result<ThresholdKey, Error>;
+}
+
+/// MPC service, coordinating the parties through the protocol rounds
+///
+/// The coordinator relays encrypted shares and public values only; it never holds a
+/// share or the key.
+pub struct MpcService {
+    storage: Arc<dyn MpcStorage>,
+    parties: HashMap<String, Arc<dyn MpcParty>>,
+    // Signing reads the current shares, rotation replaces them
+    rotation: RwLock<()>,
+}
+
+impl MpcService {
+    /// Create an MPC service coordinating `parties`
+    pub async fn new(
+        storage: Arc<dyn MpcStorage>,
+        parties: Vec<Arc<dyn MpcParty>>,
+    ) -> Result<Self, Error> {
+        let mut by_id = HashMap::new();
+        for party in parties {
+            let info = party.info().await?;
+            if by_id.insert(info.id.clone(), party).is_some() {
+                return Err(Error::ConfigError(format!(
+                    "Duplicate MPC party: {}",
+                    info.id
+                )));
+            }
+        }
+
+        Ok(Self {
+            storage,
+            parties: by_id,
+            rotation: RwLock::new(()),
+        })
+    }
+
+    fn party(&self, id: &str) -> Result<&Arc<dyn MpcParty>, Error> {
+        self.parties
+            .get(id)
+            .ok_or_else(|| Error::MpcError(format!("MPC party not available: {}", id)))
+    }
+
+    /// Participants of a session, with the parties' current encryption keys
+    async fn participants(&self, parties: &[(u16, String)]) -> Result<Vec<Participant>, Error> {
+        let mut participants = Vec::with_capacity(parties.len());
+        for (index, id) in parties {
+            let info = self.party(id)?.info().await?;
+            participants.push(Participant {
+                index: *index,
+                id: id.clone(),
+                encryption_key: info.encryption_key,
+            });
+        }
+        Ok(participants)
+    }
+
+    /// Run the dealing and receiving rounds of a session
+    async fn run_session(
+        &self,
+        request: &DealRequest,
+    ) -> Result<(Vec<DealMessage>, Vec<ReceiveOutput>), Error> {
+        check_participants(request)?;
+
+        let mut deals = Vec::with_capacity(request.participants.len());
+        for participant in &request.participants {
+            deals.push(self.party(&participant.id)?.deal(request).await?);
+        }
+
+        let mut outputs = Vec::with_capacity(request.participants.len());
+        for participant in &request.participants {
+            outputs.push(
+                self.party(&participant.id)?
+                    .receive(request, &deals)
+                    .await?,
+            );
+        }
+
+        Ok((deals, outputs))
+    }
+
+    async fn load_key(&self, key_id: &str) -> Result<ThresholdKey, Error> {
+        self.storage
+            .get_key(key_id)
+            .await?
+            .ok_or_else(|| Error::NotFound(format!("Threshold key not found: {}", key_id)))
+    }
+}
+
+/// Public image of the share at `index` of the sum of the sharings at `sharing`
+fn dealt_image(
+    deals: &[DealMessage],
+    sharing: usize,
+    index: u16,
+) -> Result<ProjectivePoint, Error> {
+    let mut image = ProjectivePoint::IDENTITY;
+    for deal in deals {
+        let commitments = deal
+            .sharings
+            .get(sharing)
+            .ok_or_else(|| Error::MpcError(format!("Malformed deal from party {}", deal.from)))?
+            .commitments
+            .iter()
+            .map(|commitment| point_from_hex(commitment))
+            .collect::<Result<Vec<_>, _>>()?;
+        image += evaluate_commitments(&commitments, index);
+    }
+    Ok(image)
+}
+
+/// Check that the verification shares the parties report match the dealt commitments
+fn check_shares(
+    deals: &[DealMessage],
+    outputs: &[ReceiveOutput],
+    previous: Option<&[KeyParty]>,
+) -> Result<HashMap<u16, ProjectivePoint>, Error> {
+    let mut shares = HashMap::new();
+    for output in outputs {
+        let ReceiveOutput::Share {
+            index,
+            verification_share,
+        } = output
+        else {
+            return Err(Error::MpcError("Unexpected party output".to_string()));
+        };
+
+        let mut expected = dealt_image(deals, 0, *index)?;
+        if let Some(previous) = previous {
+            let party = previous
+                .iter()
+                .find(|party| party.index == *index)
+                .ok_or_else(|| Error::MpcError(format!("Unknown share index: {}", index)))?;
+            expected += point_from_hex(&party.verification_share)?;
+        }
+
+        if point_from_hex(verification_share)? != expected {
+            return Err(Error::MpcError(format!(
+                "Share {} does not match the dealt commitments",
+                index
+            )));
+        }
+        shares.insert(*index, expected);
+    }
+    Ok(shares)
+}
+
+#[async_trait]
+impl MpcServiceTrait for MpcService {
+    async fn create_key(&self, owner: &str, threshold: u16) -> Result<ThresholdKey, Error> {
+        let mut ids: Vec<&String> = self.parties.keys().collect();
+        ids.sort();
+        let parties: Vec<(u16, String)> = ids
+            .into_iter()
+            .enumerate()
+            .map(|(i, id)| (i as u16 + 1, id.clone()))
+            .collect();
+
+        let request = DealRequest {
+            session_id: uuid::Uuid::new_v4().to_string(),
+            kind: SessionKind::KeyGen,
+            key_id: uuid::Uuid::new_v4().to_string(),
+            epoch: 0,
+            threshold,
+            participants: self.participants(&parties).await?,
+        };
+        let (deals, outputs) = self.run_session(&request).await?;
+        let shares = check_shares(&deals, &outputs, None)?;
+
+        // The key is the sum of the dealt secrets, which no party knows on its own
+        let public_key = dealt_image(&deals, 0, 0)?;
+        if public_key == ProjectivePoint::IDENTITY {
+            return Err(Error::MpcError("Degenerate threshold key".to_string()));
+        }
+        let public_key = public_key
+            .to_affine()
+            .to_encoded_point(true)
+            .as_bytes()
+            .to_vec();
+
+        let now = chrono::Utc::now().timestamp() as u64;
+        let key = ThresholdKey {
+            id: request.key_id,
+            owner: owner.to_string(),
+            threshold,
+            parties: parties
+                .into_iter()
+                .map(|(index, party_id)| KeyParty {
+                    index,
+                    party_id,
+                    verification_share: point_to_hex(&shares[&index]),
+                })
+                .collect(),
+            address: address_from_public_key(&public_key)?,
+            public_key: hex::encode(&public_key),
+            epoch: 0,
+            created_at: now,
+            rotated_at: None,
+        };
+        self.storage.put_key(key.clone()).await?;
+
+        log::info!(
+            "MPC key {} created for {}: {}-of-{} signing, address {}",
+            key.id,
+            owner,
+            key.signing_parties(),
+            key.parties.len(),
+            key.address
+        );
+        Ok(key)
+    }
+
+    async fn get_key(&self, key_id: &str) -> Result<ThresholdKey, Error> {
+        self.load_key(key_id).await
+    }
+
+    async fn list_keys(&self, owner: &str) -> Result<Vec<ThresholdKey>, Error> {
+        self.storage.list_keys(owner).await
+    }
+
+    async fn sign(&self, key_id: &str, message: &[u8]) -> Result<Vec<u8>, Error> {
+        let _rotation = self.rotation.read().await;
+        let key = self.load_key(key_id).await?;
+
+        // Any 2t + 1 available parties can sign
+        let signers: Vec<(u16, String)> = key
+            .parties
+            .iter()
+            .filter(|party| self.parties.contains_key(&party.party_id))
+            .take(key.signing_parties())
+            .map(|party| (party.index, party.party_id.clone()))
+            .collect();
+
+        // Nonce round: shared k, a random mask a and zero sharings b and c
+        let request = DealRequest {
+            session_id: uuid::Uuid::new_v4().to_string(),
+            kind: SessionKind::Nonce,
+            key_id: key.id.clone(),
+            epoch: key.epoch,
+            threshold: key.threshold,
+            participants: self.participants(&signers).await?,
+        };
+        let (deals, outputs) = self.run_session(&request).await?;
+
+        let indices: Vec<u16> = signers.iter().map(|(index, _)| *index).collect();
+        let lambdas = lagrange_coefficients(&indices)?;
+
+        let mut nonce = ProjectivePoint::IDENTITY;
+        let mut product = Scalar::ZERO;
+        for output in &outputs {
+            let ReceiveOutput::Nonce {
+                index,
+                nonce_point,
+                masked_product,
+            } = output
+            else {
+                return Err(Error::MpcError("Unexpected party output".to_string()));
+            };
+
+            let nonce_point = point_from_hex(nonce_point)?;
+            if nonce_point != dealt_image(&deals, 0, *index)? {
+                return Err(Error::MpcError(format!(
+                    "Nonce share {} does not match the dealt commitments",
+                    index
+                )));
+            }
+
+            let position = indices
+                .iter()
+                .position(|i| i == index)
+                .ok_or_else(|| Error::MpcError(format!("Unknown share index: {}", index)))?;
+            nonce += nonce_point * lambdas[position];
+            product += scalar_from_hex(masked_product)? * lambdas[position];
+        }
+
+        // R = k * G and r = R.x mod n
+        let r = <Scalar as Reduce<U256>>::reduce_bytes(&nonce.to_affine().x());
+        if nonce == ProjectivePoint::IDENTITY || bool::from(r.is_zero()) {
+            return Err(Error::MpcError("Degenerate signing nonce".to_string()));
+        }
+        if bool::from(product.is_zero()) {
+            return Err(Error::MpcError("Degenerate signing mask".to_string()));
+        }
+        let digest = <Scalar as Reduce<U256>>::reduce_bytes(&Sha256::digest(message));
+
+        let partial_request = PartialSignRequest {
+            session_id: request.session_id.clone(),
+            key_id: key.id.clone(),
+            epoch: key.epoch,
+            digest: scalar_to_hex(&digest),
+            r: scalar_to_hex(&r),
+            product: scalar_to_hex(&product),
+        };
+        let mut s = Scalar::ZERO;
+        for (index, party_id) in &signers {
+            let partial = self.party(party_id)?.sign(&partial_request).await?;
+            if partial.index != *index {
+                return Err(Error::MpcError(format!(
+                    "Party {} answered for share {}",
+                    party_id, partial.index
+                )));
+            }
+            let position = indices.iter().position(|i| i == index).unwrap_or_default();
+            s += scalar_from_hex(&partial.s)? * lambdas[position];
+        }
+
+        let signature = Signature::from_scalars(r.to_repr(), s.to_repr())
+            .map_err(|e| Error::MpcError(format!("Invalid threshold signature: {}", e)))?;
+        let signature = signature.normalize_s().unwrap_or(signature);
+
+        // A misbehaving party yields an invalid signature rather than a wrong one
+        let verifying_key = VerifyingKey::from_sec1_bytes(
+            &hex::decode(&key.public_key)
+                .map_err(|e| Error::MpcError(format!("Invalid public key: {}", e)))?,
+        )
+        .map_err(|e| Error::MpcError(format!("Invalid public key: {}", e)))?;
+        verifying_key
+            .verify(message, &signature)
+            .map_err(|e| Error::InvalidSignature(format!("Threshold signature failed: {}", e)))?;
+
+        Ok(signature.to_bytes().to_vec())
+    }
+
+    async fn rotate_shares(&self, key_id: &str) -> Result<ThresholdKey, Error> {
+        let _rotation = self.rotation.write().await;
+        let mut key = self.load_key(key_id).await?;
+
+        // Every holder must take part, a party left out would keep a stale share
+        let parties: Vec<(u16, String)> = key
+            .parties
+            .iter()
+            .map(|party| (party.index, party.party_id.clone()))
+            .collect();
+        let request = DealRequest {
+            session_id: uuid::Uuid::new_v4().to_string(),
+            kind: SessionKind::Refresh,
+            key_id: key.id.clone(),
+            epoch: key.epoch,
+            threshold: key.threshold,
+            participants: self.participants(&parties).await?,
+        };
+        let (deals, outputs) = self.run_session(&request).await?;
+        let shares = check_shares(&deals, &outputs, Some(&key.parties))?;
+
+        for party in &mut key.parties {
+            party.verification_share = point_to_hex(&shares[&party.index]);
+        }
+        key.epoch += 1;
+        key.rotated_at = Some(chrono::Utc::now().timestamp() as u64);
+        self.storage.put_key(key.clone()).await?;
+
+        // The new epoch is committed, the parties may drop the old shares
+        for (_, party_id) in &parties {
+            if let Err(e) = self.party(party_id)?.finalize(&key.id, key.epoch).await {
+                log::warn!(
+                    "MPC party {} failed to drop old shares of key {}: {}",
+                    party_id,
+                    key.id,
+                    e
+                );
+            }
+        }
+
+        log::info!("MPC key {} rotated to epoch {}", key.id, key.epoch);
+        Ok(key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mpc::party::{LocalParty, MemoryShareStore};
+    use crate::mpc::storage::MemoryMpcStorage;
+
+    fn parties(n: usize) -> Vec<Arc<dyn MpcParty>> {
+        (1..=n)
+            .map(|i| {
+                Arc::new(LocalParty::new(
+                    &format!("party-{}", i),
+                    Arc::new(MemoryShareStore::new()),
+                )) as Arc<dyn MpcParty>
+            })
+            .collect()
+    }
+
+    /// Verify a signature with a plain P-256 verifier against the key's public key
+    fn verify(key: &ThresholdKey, message: &[u8], signature: &[u8]) {
+        let verifying_key =
+            VerifyingKey::from_sec1_bytes(&hex::decode(&key.public_key).unwrap()).unwrap();
+        let signature = Signature::from_slice(signature).unwrap();
+        verifying_key.verify(message, &signature).unwrap();
+        assert!(verifying_key
+            .verify(b"another message", &signature)
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_keygen_sign_verify() {
+        let service = MpcService::new(Arc::new(MemoryMpcStorage::new()), parties(3))
+            .await
+            .unwrap();
+        let key = service.create_key("owner", 1).await.unwrap();
+        assert_eq!(key.parties.len(), 3);
+        assert_eq!(key.signing_parties(), 3);
+        assert_eq!(
+            key.address,
+            address_from_public_key(&hex::decode(&key.public_key).unwrap()).unwrap()
+        );
+
+        for message in [&b"transfer 10 GAS"[..], b""] {
+            let signature = service.sign(&key.id, message).await.unwrap();
+            assert_eq!(signature.len(), 64);
+            verify(&key, message, &signature);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_rotate_keeps_public_key() {
+        let parties = parties(3);
+        let storage = Arc::new(MemoryMpcStorage::new());
+        let service = MpcService::new(storage.clone(), parties.clone())
+            .await
+            .unwrap();
+        let key = service.create_key("owner", 1).await.unwrap();
+
+        let rotated = service.rotate_shares(&key.id).await.unwrap();
+        assert_eq!(rotated.public_key, key.public_key);
+        assert_eq!(rotated.address, key.address);
+        assert_eq!(rotated.epoch, 1);
+        assert!(rotated.rotated_at.is_some());
+        assert_ne!(rotated.parties, key.parties);
+
+        let signature = service.sign(&key.id, b"after rotation").await.unwrap();
+        verify(&rotated, b"after rotation", &signature);
+
+        // The shares of the previous epoch are gone
+        let request = DealRequest {
+            session_id: "stale".to_string(),
+            kind: SessionKind::Nonce,
+            key_id: key.id.clone(),
+            epoch: 0,
+            threshold: 1,
+            participants: service
+                .participants(&[
+                    (1, "party-1".to_string()),
+                    (2, "party-2".to_string()),
+                    (3, "party-3".to_string()),
+                ])
+                .await
+                .unwrap(),
+        };
+        assert!(parties[0].deal(&request).await.is_err());
+
+        let rotated = service.rotate_shares(&key.id).await.unwrap();
+        assert_eq!(rotated.epoch, 2);
+        let signature = service.sign(&key.id, b"second rotation").await.unwrap();
+        verify(&rotated, b"second rotation", &signature);
+    }
+
+    #[tokio::test]
+    async fn test_any_signing_subset_signs() {
+        let parties = parties(5);
+        let storage = Arc::new(MemoryMpcStorage::new());
+        let service = MpcService::new(storage.clone(), parties.clone())
+            .await
+            .unwrap();
+
+        // 1-of-5: any 3 parties sign
+        let key = service.create_key("owner", 1).await.unwrap();
+        for subset in [[0, 1, 2], [2, 3, 4], [0, 2, 4], [1, 3, 4]] {
+            let available = subset.iter().map(|&i| parties[i].clone()).collect();
+            let service = MpcService::new(storage.clone(), available).await.unwrap();
+            let signature = service.sign(&key.id, b"subset").await.unwrap();
+            verify(&key, b"subset", &signature);
+        }
+
+        // 2-of-5: all 5 parties sign
+        let key = service.create_key("owner", 2).await.unwrap();
+        assert_eq!(key.signing_parties(), 5);
+        let signature = service.sign(&key.id, b"all parties").await.unwrap();
+        verify(&key, b"all parties", &signature);
+    }
+
+    #[tokio::test]
+    async fn test_too_few_parties() {
+        let parties = parties(4);
+        let storage = Arc::new(MemoryMpcStorage::new());
+        let service = MpcService::new(storage.clone(), parties.clone())
+            .await
+            .unwrap();
+
+        // Threshold 2 needs 5 parties
+        assert!(service.create_key("owner", 2).await.is_err());
+        assert!(service.create_key("owner", 0).await.is_err());
+
+        // With 2 of the parties left, a 1-of-4 key cannot sign
+        let key = service.create_key("owner", 1).await.unwrap();
+        let service = MpcService::new(storage.clone(), parties[..2].to_vec())
+            .await
+            .unwrap();
+        assert!(service.sign(&key.id, b"message").await.is_err());
+
+        // Nor can it rotate, which needs every holder
+        let service = MpcService::new(storage, parties[..3].to_vec())
+            .await
+            .unwrap();
+        assert!(service.sign(&key.id, b"message").await.is_ok());
+        assert!(service.rotate_shares(&key.id).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_duplicate_party_ids() {
+        let mut parties = parties(3);
+        parties.push(parties[0].clone());
+        assert!(MpcService::new(Arc::new(MemoryMpcStorage::new()), parties)
+            .await
+            .is_err());
+    }
+}
@@ -0,0 +1,60 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+use async_trait::async_trait;
+use std::collections::HashMap;
+use tokio::sync::RwLock;
+
+use super::types::ThresholdKey;
+use crate::Error;
+
+/// Storage of threshold key metadata; shares are kept by the parties
+#[async_trait]
+pub trait MpcStorage: Send + Sync {
+    /// Get a key
+    async fn get_key(&self, key_id: &str) -> Result<Option<ThresholdKey>, Error>;
+
+    /// Create or update a key
+    async fn put_key(&self, key: ThresholdKey) -> Result<(), Error>;
+
+    /// List the keys of an owner
+    async fn list_keys(&self, owner: &str) -> Result<Vec<ThresholdKey>, Error>;
+}
+
+/// In-memory MPC storage
+#[derive(Default)]
+pub struct MemoryMpcStorage {
+    keys: RwLock<HashMap<String, ThresholdKey>>,
+}
+
+impl MemoryMpcStorage {
+    /// Create a new in-memory MPC storage
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl MpcStorage for MemoryMpcStorage {
+    async fn get_key(&self, key_id: &str) -> Result<Option<ThresholdKey>, Error> {
+        Ok(self.keys.read().await.get(key_id).cloned())
+    }
+
+    async fn put_key(&self, key: ThresholdKey) -> Result<(), Error> {
+        self.keys.write().await.insert(key.id.clone(), key);
+        Ok(())
+    }
+
+    async fn list_keys(&self, owner: &str) -> Result<Vec<ThresholdKey>, Error> {
+        let mut keys: Vec<ThresholdKey> = self
+            .keys
+            .read()
+            .await
+            .values()
+            .filter(|key| key.owner == owner)
+            .cloned()
+            .collect();
+        keys.sort_by_key(|key| key.created_at);
+        Ok(keys)
+    }
+}
@@ -0,0 +1,223 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+use serde::{Deserialize, Serialize};
+
+/// Party of a threshold key, as reported by the party itself
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PartyInfo {
+    /// Party ID, unique among the parties of the MPC service
+    pub id: String,
+
+    /// Compressed secp256r1 key the party receives shares under, hex encoded
+    pub encryption_key: String,
+}
+
+/// Party taking part in a protocol session
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Participant {
+    /// Share index of the party, the point its shares are evaluated at
+    pub index: u16,
+
+    /// Party ID
+    pub id: String,
+
+    /// Compressed secp256r1 key the party receives shares under, hex encoded
+    pub encryption_key: String,
+}
+
+/// Party holding a share of a threshold key
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeyParty {
+    /// Share index
+    pub index: u16,
+
+    /// Party ID
+    pub party_id: String,
+
+    /// Public image of the party's share, compressed and hex encoded
+    pub verification_share: String,
+}
+
+/// Threshold signing key
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ThresholdKey {
+    /// Key ID
+    pub id: String,
+
+    /// Owner of the key
+    pub owner: String,
+
+    /// Number of parties that may be compromised without exposing the key; signing needs
+    /// `2 * threshold + 1` parties
+    pub threshold: u16,
+
+    /// Parties holding shares
+    pub parties: Vec<KeyParty>,
+
+    /// Compressed secp256r1 public key, hex encoded
+    pub public_key: String,
+
+    /// Neo N3 address of the key
+    pub address: String,
+
+    /// Share epoch, incremented by every rotation
+    pub epoch: u64,
+
+    /// Created at
+    pub created_at: u64,
+
+    /// Shares last rotated at
+    pub rotated_at: Option<u64>,
+}
+
+impl ThresholdKey {
+    /// Number of parties needed to sign
+    pub fn signing_parties(&self) -> usize {
+        2 * self.threshold as usize + 1
+    }
+}
+
+/// Share of a threshold key held by a party
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KeyShare {
+    /// Key ID
+    pub key_id: String,
+
+    /// Share epoch
+    pub epoch: u64,
+
+    /// Share index
+    pub index: u16,
+
+    /// Secret share, hex encoded scalar
+    pub secret: String,
+}
+
+/// What the sharings dealt in a session are for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SessionKind {
+    /// Distributed key generation: one random sharing of degree `threshold`
+    KeyGen,
+
+    /// Share rotation: one sharing of zero of degree `threshold`
+    Refresh,
+
+    /// Signing nonce: random sharings `k` and `a` of degree `threshold` and sharings of
+    /// zero `b` and `c` of degree `2 * threshold`
+    Nonce,
+}
+
+/// Request to deal sharings for a session
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DealRequest {
+    /// Session ID
+    pub session_id: String,
+
+    /// Session kind
+    pub kind: SessionKind,
+
+    /// Key ID
+    pub key_id: String,
+
+    /// Share epoch the session reads (refresh, nonce) or writes (key generation)
+    pub epoch: u64,
+
+    /// Threshold of the key
+    pub threshold: u16,
+
+    /// Parties taking part in the session
+    pub participants: Vec<Participant>,
+}
+
+/// Share dealt to a party, encrypted to its encryption key
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EncryptedShare {
+    /// Share index of the recipient
+    pub to: u16,
+
+    /// Sealed share, hex encoded
+    pub envelope: String,
+}
+
+/// Feldman sharing of a secret polynomial
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Sharing {
+    /// Commitments to the polynomial coefficients, compressed and hex encoded
+    pub commitments: Vec<String>,
+
+    /// Shares, one per participant
+    pub shares: Vec<EncryptedShare>,
+}
+
+/// Sharings dealt by a party in a session
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DealMessage {
+    /// Session ID
+    pub session_id: String,
+
+    /// Share index of the dealer
+    pub from: u16,
+
+    /// Sharings, in the order of the session kind
+    pub sharings: Vec<Sharing>,
+}
+
+/// Public output of a party after it combined the dealt sharings
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ReceiveOutput {
+    /// Key generation or refresh: the public image of the new share
+    Share {
+        /// Share index
+        index: u16,
+
+        /// Compressed verification share, hex encoded
+        verification_share: String,
+    },
+
+    /// Nonce: the public image of the nonce share and the masked share of `k * a`
+    Nonce {
+        /// Share index
+        index: u16,
+
+        /// Compressed `k_i * G`, hex encoded
+        nonce_point: String,
+
+        /// `k_i * a_i + b_i`, hex encoded scalar
+        masked_product: String,
+    },
+}
+
+/// Request for a party's share of a signature
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PartialSignRequest {
+    /// Nonce session ID
+    pub session_id: String,
+
+    /// Key ID
+    pub key_id: String,
+
+    /// Share epoch
+    pub epoch: u64,
+
+    /// Reduced SHA-256 digest of the message, hex encoded scalar
+    pub digest: String,
+
+    /// `r` of the signature, hex encoded scalar
+    pub r: String,
+
+    /// `k * a`, hex encoded scalar
+    pub product: String,
+}
+
+/// Party's share of a signature
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PartialSignature {
+    /// Share index
+    pub index: u16,
+
+    /// Share of `s`, hex encoded scalar
+    pub s: String,
+}
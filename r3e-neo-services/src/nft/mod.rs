@@ -0,0 +1,8 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+pub mod service;
+pub mod types;
+
+pub use service::{NftService, NftServiceTrait};
+pub use types::*;
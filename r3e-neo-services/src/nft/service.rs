@@ -0,0 +1,426 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+use super::types::{NftToken, NftTransferRequest};
+use crate::abstract_account::service::AbstractAccountServiceTrait;
+use crate::abstract_account::{
+    AccountOperation, AccountOperationRequest, AccountOperationResponse,
+};
+use crate::signer::script_hash_from_address;
+use crate::Error;
+use async_trait::async_trait;
+use base64::Engine;
+use log::{debug, info, warn};
+use r3e_core::chain::{stack_item_bytes, stack_item_integer, ChainClient, ContractCall};
+use serde_json::{json, Map, Value};
+use std::sync::Arc;
+
+/// Maximum number of items read from an iterator
+const MAX_ITERATOR_ITEMS: usize = 1000;
+
+/// Maximum length of a NEP-11 token ID
+const MAX_TOKEN_ID_LEN: usize = 64;
+
+/// NEP-11 service trait
+#[async_trait]
+pub trait NftServiceTrait: Send + Sync {
+    /// Get the owners of a token
+    async fn owners_of(&self, contract: &str, token_id: &str) -> Result<Vec<String>, Error>;
+
+    /// Get the hex encoded IDs of the tokens of an owner
+    async fn tokens_of(&self, contract: &str, owner: &str) -> Result<Vec<String>, Error>;
+
+    /// Get the number of tokens of an owner
+    async fn balance_of(&self, contract: &str, owner: &str) -> Result<u128, Error>;
+
+    /// Get the properties of a token
+    async fn properties(&self, contract: &str, token_id: &str) -> Result<Value, Error>;
+
+    /// Get a token with its owners and properties
+    async fn get_token(&self, contract: &str, token_id: &str) -> Result<NftToken, Error>;
+
+    /// Transfer a token from an abstract account
+    async fn transfer(
+        &self,
+        request: NftTransferRequest,
+    ) -> Result<AccountOperationResponse, Error>;
+}
+
+/// NEP-11 service implementation
+pub struct NftService {
+    /// Neo chain client
+    chain: Arc<dyn ChainClient>,
+    /// Abstract account service sending transfers
+    accounts: Arc<dyn AbstractAccountServiceTrait>,
+}
+
+impl NftService {
+    /// Create a new NEP-11 service
+    pub fn new(
+        chain: Arc<dyn ChainClient>,
+        accounts: Arc<dyn AbstractAccountServiceTrait>,
+    ) -> Self {
+        Self { chain, accounts }
+    }
+
+    /// Invoke a read-only method, returning its result
+    async fn invoke(
+        &self,
+        contract: &str,
+        method: &str,
+        params: Vec<Value>,
+    ) -> Result<Value, Error> {
+        let call = ContractCall {
+            contract: contract.to_string(),
+            method: method.to_string(),
+            params,
+            ..Default::default()
+        };
+        let result = self.chain.call_contract(&call).await?;
+        if !result.success {
+            return Err(Error::ContractError(format!(
+                "{} of {} failed: {}",
+                method,
+                contract,
+                result.error.unwrap_or_default()
+            )));
+        }
+
+        result.result.get(0).cloned().ok_or_else(|| {
+            Error::ContractError(format!("{} of {} returned no result", method, contract))
+        })
+    }
+
+    /// Invoke a read-only method returning an iterator, reading at most `MAX_ITERATOR_ITEMS`
+    async fn iterate(
+        &self,
+        contract: &str,
+        method: &str,
+        params: Vec<Value>,
+    ) -> Result<Vec<Value>, Error> {
+        let result = self
+            .chain
+            .request("invokefunction", json!([contract, method, params]))
+            .await?;
+        if result.get("state").and_then(Value::as_str) != Some("HALT") {
+            return Err(Error::ContractError(format!(
+                "{} of {} failed: {}",
+                method,
+                contract,
+                result
+                    .get("exception")
+                    .and_then(Value::as_str)
+                    .unwrap_or("VM fault")
+            )));
+        }
+
+        // Nodes with sessions disabled expand the iterator in the result
+        let iterator = &result["stack"][0];
+        if let Some(items) = iterator.get("iterator").and_then(Value::as_array) {
+            return Ok(items.clone());
+        }
+
+        let session = result.get("session").and_then(Value::as_str);
+        let id = iterator.get("id").and_then(Value::as_str);
+        let (Some(session), Some(id)) = (session, id) else {
+            return Err(Error::ContractError(format!(
+                "{} of {} did not return an iterator",
+                method, contract
+            )));
+        };
+
+        let items = self
+            .chain
+            .request("traverseiterator", json!([session, id, MAX_ITERATOR_ITEMS]))
+            .await;
+        if let Err(e) = self
+            .chain
+            .request("terminatesession", json!([session]))
+            .await
+        {
+            warn!("Failed to terminate iterator session {}: {}", session, e);
+        }
+
+        Ok(items?.as_array().cloned().unwrap_or_default())
+    }
+
+    /// Token decimals, 0 for non-divisible tokens
+    async fn decimals(&self, contract: &str) -> Result<u32, Error> {
+        let decimals = self.invoke(contract, "decimals", vec![]).await?;
+        stack_item_integer(&decimals)
+            .and_then(|decimals| u32::try_from(decimals).ok())
+            .ok_or_else(|| Error::ContractError(format!("Invalid decimals of {}", contract)))
+    }
+}
+
+#[async_trait]
+impl NftServiceTrait for NftService {
+    async fn owners_of(&self, contract: &str, token_id: &str) -> Result<Vec<String>, Error> {
+        debug!("Getting owners of token {} of {}", token_id, contract);
+
+        let params = vec![token_id_param(token_id)?];
+        // Divisible tokens may have several owners and return an iterator
+        if self.decimals(contract).await? > 0 {
+            let owners = self.iterate(contract, "ownerOf", params).await?;
+            return owners
+                .iter()
+                .map(|owner| stack_hash(contract, owner))
+                .collect();
+        }
+
+        let owner = self.invoke(contract, "ownerOf", params).await?;
+        Ok(vec![stack_hash(contract, &owner)?])
+    }
+
+    async fn tokens_of(&self, contract: &str, owner: &str) -> Result<Vec<String>, Error> {
+        debug!("Getting tokens of {} of {}", owner, contract);
+
+        let tokens = self
+            .iterate(contract, "tokensOf", vec![hash160_param(owner)?])
+            .await?;
+        tokens
+            .iter()
+            .map(|token| {
+                iterator_key(token).map(hex::encode).ok_or_else(|| {
+                    Error::ContractError(format!("Invalid token ID returned by {}", contract))
+                })
+            })
+            .collect()
+    }
+
+    async fn balance_of(&self, contract: &str, owner: &str) -> Result<u128, Error> {
+        let balance = self
+            .invoke(contract, "balanceOf", vec![hash160_param(owner)?])
+            .await?;
+        stack_item_integer(&balance)
+            .and_then(|balance| u128::try_from(balance).ok())
+            .ok_or_else(|| {
+                Error::ContractError(format!("Invalid balance returned by {}", contract))
+            })
+    }
+
+    async fn properties(&self, contract: &str, token_id: &str) -> Result<Value, Error> {
+        let properties = self
+            .invoke(contract, "properties", vec![token_id_param(token_id)?])
+            .await?;
+        Ok(stack_json(&properties))
+    }
+
+    async fn get_token(&self, contract: &str, token_id: &str) -> Result<NftToken, Error> {
+        let owners = self.owners_of(contract, token_id).await?;
+
+        // properties is an optional method of NEP-11
+        let properties = match self.properties(contract, token_id).await {
+            Ok(properties) => Some(properties),
+            Err(Error::ContractError(e)) => {
+                debug!("No properties of token {} of {}: {}", token_id, contract, e);
+                None
+            }
+            Err(e) => return Err(e),
+        };
+
+        Ok(NftToken {
+            contract: contract.to_string(),
+            token_id: normalize_token_id(token_id)?,
+            owners,
+            properties,
+        })
+    }
+
+    async fn transfer(
+        &self,
+        request: NftTransferRequest,
+    ) -> Result<AccountOperationResponse, Error> {
+        info!(
+            "Transferring token {} of {} from {} to {}",
+            request.token_id, request.contract, request.account_address, request.to
+        );
+
+        let account = self
+            .accounts
+            .get_account(&request.account_address)
+            .await?
+            .ok_or_else(|| {
+                Error::NotFound(format!(
+                    "Abstract account not found: {}",
+                    request.account_address
+                ))
+            })?;
+        let from = script_hash(&account.contract_hash)?;
+        let to = script_hash(&request.to)?;
+        let token_id = normalize_token_id(&request.token_id)?;
+        let data = request.data.clone().unwrap_or_default();
+
+        // Check ownership up front, a failed transfer still costs the account its fees
+        let params = if self.decimals(&request.contract).await? == 0 {
+            if request.amount.is_some() {
+                return Err(Error::InvalidParameter(format!(
+                    "Token {} is not divisible, transfers take no amount",
+                    request.contract
+                )));
+            }
+
+            let owners = self.owners_of(&request.contract, &token_id).await?;
+            if !owners.contains(&from) {
+                return Err(Error::InvalidParameter(format!(
+                    "Token {} of {} is not owned by {}",
+                    token_id, request.contract, request.account_address
+                )));
+            }
+
+            vec![to, token_id, data]
+        } else {
+            let amount: u128 = request
+                .amount
+                .as_deref()
+                .ok_or_else(|| {
+                    Error::InvalidParameter(format!(
+                        "Token {} is divisible, transfers need an amount",
+                        request.contract
+                    ))
+                })?
+                .parse()
+                .map_err(|e| Error::InvalidParameter(format!("Invalid amount: {}", e)))?;
+
+            let balance = self
+                .invoke(
+                    &request.contract,
+                    "balanceOf",
+                    vec![hash160_param(&from)?, token_id_param(&token_id)?],
+                )
+                .await?;
+            let balance = stack_item_integer(&balance)
+                .and_then(|balance| u128::try_from(balance).ok())
+                .unwrap_or_default();
+            if balance < amount {
+                return Err(Error::InsufficientFunds(format!(
+                    "Balance of token {} of {} is {}, transferring {}",
+                    token_id, request.contract, balance, amount
+                )));
+            }
+
+            vec![from, to, amount.to_string(), token_id, data]
+        };
+
+        let operation = AccountOperation::Invoke {
+            contract: request.contract.clone(),
+            method: "transfer".to_string(),
+            params,
+        };
+        self.accounts
+            .execute_operation(AccountOperationRequest {
+                account_address: request.account_address,
+                operation,
+                signatures: request.signatures,
+                nonce: request.nonce,
+                deadline: request.deadline,
+                timestamp: chrono::Utc::now().timestamp() as u64,
+            })
+            .await
+    }
+}
+
+/// Script hash of an address or `0x` prefixed script hash, `0x` prefixed big endian
+fn script_hash(account: &str) -> Result<String, Error> {
+    if let Some(hash) = account.strip_prefix("0x") {
+        let bytes = hex::decode(hash).map_err(|e| {
+            Error::InvalidParameter(format!("Invalid script hash {}: {}", account, e))
+        })?;
+        if bytes.len() != 20 {
+            return Err(Error::InvalidParameter(format!(
+                "Invalid script hash: {}",
+                account
+            )));
+        }
+        return Ok(format!("0x{}", hex::encode(bytes)));
+    }
+
+    // Script hashes are serialized little endian, but displayed big endian
+    let mut hash = script_hash_from_address(account)?;
+    hash.reverse();
+    Ok(format!("0x{}", hex::encode(hash)))
+}
+
+fn hash160_param(account: &str) -> Result<Value, Error> {
+    Ok(json!({ "type": "Hash160", "value": script_hash(account)? }))
+}
+
+/// Lowercase hex token ID without prefix
+fn normalize_token_id(token_id: &str) -> Result<String, Error> {
+    let bytes = hex::decode(token_id.trim_start_matches("0x"))
+        .map_err(|e| Error::InvalidParameter(format!("Invalid token ID {}: {}", token_id, e)))?;
+    if bytes.len() > MAX_TOKEN_ID_LEN {
+        return Err(Error::InvalidParameter(format!(
+            "Token ID is longer than {} bytes",
+            MAX_TOKEN_ID_LEN
+        )));
+    }
+    Ok(hex::encode(bytes))
+}
+
+fn token_id_param(token_id: &str) -> Result<Value, Error> {
+    let bytes = hex::decode(normalize_token_id(token_id)?).unwrap_or_default();
+    Ok(json!({
+        "type": "ByteArray",
+        "value": base64::engine::general_purpose::STANDARD.encode(bytes),
+    }))
+}
+
+/// Decode a Hash160 stack item to a `0x` prefixed big endian script hash
+fn stack_hash(contract: &str, item: &Value) -> Result<String, Error> {
+    match iterator_key(item) {
+        Some(mut bytes) if bytes.len() == 20 => {
+            bytes.reverse();
+            Ok(format!("0x{}", hex::encode(bytes)))
+        }
+        _ => Err(Error::ContractError(format!(
+            "Invalid script hash returned by {}",
+            contract
+        ))),
+    }
+}
+
+/// Bytes of an iterator item, which is the key of a `Struct` unless iterating keys only
+fn iterator_key(item: &Value) -> Option<Vec<u8>> {
+    match item.get("type").and_then(Value::as_str) {
+        Some("Struct") => stack_item_bytes(item.get("value")?.get(0)?),
+        _ => stack_item_bytes(item),
+    }
+}
+
+/// Convert a stack item to JSON. Byte strings are decoded as UTF-8 where possible,
+/// otherwise hex encoded, and integers are kept as decimal strings.
+fn stack_json(item: &Value) -> Value {
+    let value = item.get("value");
+    match item.get("type").and_then(Value::as_str) {
+        Some("ByteString") | Some("Buffer") => match stack_item_bytes(item) {
+            Some(bytes) => match String::from_utf8(bytes) {
+                Ok(text) => Value::String(text),
+                Err(e) => Value::String(hex::encode(e.into_bytes())),
+            },
+            None => Value::Null,
+        },
+        Some("Integer") => value.cloned().unwrap_or(Value::Null),
+        Some("Boolean") => value.cloned().unwrap_or(Value::Null),
+        Some("Array") | Some("Struct") => Value::Array(
+            value
+                .and_then(Value::as_array)
+                .into_iter()
+                .flatten()
+                .map(stack_json)
+                .collect(),
+        ),
+        Some("Map") => {
+            let mut map = Map::new();
+            for entry in value.and_then(Value::as_array).into_iter().flatten() {
+                let key = match stack_json(&entry["key"]) {
+                    Value::String(key) => key,
+                    key => key.to_string(),
+                };
+                map.insert(key, stack_json(&entry["value"]));
+            }
+            Value::Object(map)
+        }
+        _ => Value::Null,
+    }
+}
@@ -0,0 +1,44 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+use serde::{Deserialize, Serialize};
+
+use crate::abstract_account::AccountSignature;
+
+/// NEP-11 token
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NftToken {
+    /// Token contract hash, `0x` prefixed big endian
+    pub contract: String,
+    /// Hex encoded token ID
+    pub token_id: String,
+    /// Owner script hashes, a single owner unless the token is divisible
+    pub owners: Vec<String>,
+    /// Token properties, `None` if the contract does not implement `properties`
+    pub properties: Option<serde_json::Value>,
+}
+
+/// NEP-11 transfer from an abstract account
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NftTransferRequest {
+    /// Abstract account address
+    pub account_address: String,
+    /// Token contract hash
+    pub contract: String,
+    /// Recipient, as an address or `0x` prefixed script hash
+    pub to: String,
+    /// Hex encoded token ID
+    pub token_id: String,
+    /// Amount in the token's smallest unit, only for divisible tokens
+    #[serde(default)]
+    pub amount: Option<String>,
+    /// Data passed to the recipient's `onNEP11Payment`
+    #[serde(default)]
+    pub data: Option<String>,
+    /// Signatures
+    pub signatures: Vec<AccountSignature>,
+    /// Nonce
+    pub nonce: u64,
+    /// Deadline
+    pub deadline: u64,
+}
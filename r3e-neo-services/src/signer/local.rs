@@ -0,0 +1,50 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+use super::{RelayerSigner, SignerKind};
+use crate::Error;
+use async_trait::async_trait;
+use p256::ecdsa::signature::Signer;
+use p256::ecdsa::{Signature, SigningKey};
+use p256::elliptic_curve::sec1::ToEncodedPoint;
+
+/// Signer holding the private key in process memory
+pub struct LocalSigner {
+    /// Signing key
+    key: SigningKey,
+}
+
+impl LocalSigner {
+    /// Create a local signer from a hex encoded private key
+    pub fn from_hex(private_key: &str) -> Result<Self, Error> {
+        let private_key = hex::decode(private_key.trim_start_matches("0x"))
+            .map_err(|e| Error::ConfigError(format!("Invalid relayer private key: {}", e)))?;
+
+        Self::from_bytes(&private_key)
+    }
+
+    /// Create a local signer from a raw 32-byte private key
+    pub fn from_bytes(private_key: &[u8]) -> Result<Self, Error> {
+        let key = SigningKey::from_slice(private_key)
+            .map_err(|e| Error::ConfigError(format!("Invalid relayer private key: {}", e)))?;
+
+        Ok(Self { key })
+    }
+}
+
+#[async_trait]
+impl RelayerSigner for LocalSigner {
+    fn kind(&self) -> SignerKind {
+        SignerKind::Local
+    }
+
+    async fn public_key(&self) -> Result<Vec<u8>, Error> {
+        let point = self.key.verifying_key().to_encoded_point(true);
+        Ok(point.as_bytes().to_vec())
+    }
+
+    async fn sign(&self, message: &[u8]) -> Result<Vec<u8>, Error> {
+        let signature: Signature = self.key.sign(message);
+        Ok(signature.to_bytes().to_vec())
+    }
+}
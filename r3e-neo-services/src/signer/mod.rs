@@ -0,0 +1,658 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+pub mod local;
+pub mod mpc;
+#[cfg(feature = "pkcs11")]
+pub mod pkcs11;
+pub mod remote;
+pub mod tee;
+
+use crate::mpc::MpcServiceTrait;
+use crate::Error;
+use async_trait::async_trait;
+use p256::elliptic_curve::sec1::ToEncodedPoint;
+use r3e_tee::key_management::KeyManagementService;
+use ripemd::Ripemd160;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+
+pub use local::LocalSigner;
+pub use mpc::MpcSigner;
+#[cfg(feature = "pkcs11")]
+pub use pkcs11::Pkcs11Signer;
+pub use remote::RemoteSigner;
+pub use tee::TeeSigner;
+
+/// Neo N3 address version byte
+const ADDRESS_VERSION: u8 = 0x35;
+
+/// Interop hash of System.Crypto.CheckSig
+const CHECK_SIG: [u8; 4] = [0x56, 0xe7, 0xb3, 0x27];
+
+/// Signer kind
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SignerKind {
+    /// Private key held in process memory
+    Local,
+    /// Key held by the TEE key management service
+    Tee,
+    /// Remote signing service
+    Remote,
+    /// Hardware security module via PKCS#11
+    Pkcs11,
+    /// Threshold key shared among MPC parties
+    Mpc,
+}
+
+/// Relayer signer, signing Neo N3 messages with a secp256r1 key it may never expose
+#[async_trait]
+pub trait RelayerSigner: Send + Sync {
+    /// Signer kind
+    fn kind(&self) -> SignerKind;
+
+    /// Compressed secp256r1 public key
+    async fn public_key(&self) -> Result<Vec<u8>, Error>;
+
+    /// Sign the SHA-256 digest of a message, returning the 64-byte `r || s` signature
+    async fn sign(&self, message: &[u8]) -> Result<Vec<u8>, Error>;
+
+    /// Neo N3 address of the signing key
+    async fn address(&self) -> Result<String, Error> {
+        address_from_public_key(&self.public_key().await?)
+    }
+}
+
+/// Relayer signer configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SignerConfig {
+    /// Hex encoded private key held in process memory
+    Local {
+        /// Private key
+        private_key: String,
+    },
+    /// Key held by the TEE key management service
+    Tee {
+        /// Signing key ID
+        key_id: String,
+        /// ID of the exportable public key paired with the signing key
+        public_key_id: String,
+    },
+    /// Remote signing service
+    Remote {
+        /// Signing service base URL
+        url: String,
+        /// Key ID at the signing service
+        key_id: String,
+        /// Bearer token authenticating to the signing service
+        #[serde(default)]
+        auth_token: Option<String>,
+        /// Request timeout in milliseconds
+        #[serde(default = "default_remote_timeout_ms")]
+        timeout_ms: u64,
+    },
+    /// Hardware security module via PKCS#11
+    Pkcs11 {
+        /// Path of the PKCS#11 module library
+        module_path: String,
+        /// Label of the token holding the key
+        token_label: String,
+        /// User PIN
+        pin: String,
+        /// Label of the key pair
+        key_label: String,
+    },
+    /// Threshold key shared among MPC parties
+    Mpc {
+        /// Threshold key ID
+        key_id: String,
+    },
+}
+
+fn default_remote_timeout_ms() -> u64 {
+    5_000
+}
+
+impl SignerConfig {
+    /// Signer kind selected by the configuration
+    pub fn kind(&self) -> SignerKind {
+        match self {
+            SignerConfig::Local { .. } => SignerKind::Local,
+            SignerConfig::Tee { .. } => SignerKind::Tee,
+            SignerConfig::Remote { .. } => SignerKind::Remote,
+            SignerConfig::Pkcs11 { .. } => SignerKind::Pkcs11,
+            SignerConfig::Mpc { .. } => SignerKind::Mpc,
+        }
+    }
+
+    /// Create the configured signer; the TEE signer needs the key management service and
+    /// the MPC signer the MPC service
+    pub async fn build(
+        &self,
+        kms: Option<Arc<dyn KeyManagementService>>,
+        mpc: Option<Arc<dyn MpcServiceTrait>>,
+    ) -> Result<Arc<dyn RelayerSigner>, Error> {
+        let signer: Arc<dyn RelayerSigner> = match self {
+            SignerConfig::Local { private_key } => Arc::new(LocalSigner::from_hex(private_key)?),
+            SignerConfig::Tee {
+                key_id,
+                public_key_id,
+            } => {
+                let kms = kms.ok_or_else(|| {
+                    Error::ConfigError("TEE signer requires a key management service".to_string())
+                })?;
+                Arc::new(TeeSigner::new(kms, key_id, public_key_id).await?)
+            }
+            SignerConfig::Remote {
+                url,
+                key_id,
+                auth_token,
+                timeout_ms,
+            } => Arc::new(RemoteSigner::new(url, key_id, auth_token.clone(), *timeout_ms).await?),
+            #[cfg(feature = "pkcs11")]
+            SignerConfig::Pkcs11 {
+                module_path,
+                token_label,
+                pin,
+                key_label,
+            } => Arc::new(Pkcs11Signer::open(
+                module_path,
+                token_label,
+                pin,
+                key_label,
+            )?),
+            #[cfg(not(feature = "pkcs11"))]
+            SignerConfig::Pkcs11 { .. } => {
+                return Err(Error::ConfigError(
+                    "PKCS#11 signer requires the pkcs11 feature".to_string(),
+                ))
+            }
+            SignerConfig::Mpc { key_id } => {
+                let mpc = mpc.ok_or_else(|| {
+                    Error::ConfigError("MPC signer requires an MPC service".to_string())
+                })?;
+                Arc::new(MpcSigner::new(mpc, key_id).await?)
+            }
+        };
+
+        log::info!(
+            "Relayer signer: {:?}, address {}",
+            signer.kind(),
+            signer.address().await?
+        );
+        Ok(signer)
+    }
+}
+
+/// Invocation and verification scripts of a single-signature witness
+#[derive(Debug, Clone)]
+pub struct Witness {
+    /// Invocation script pushing the signature
+    pub invocation: Vec<u8>,
+    /// Verification script checking the signature
+    pub verification: Vec<u8>,
+}
+
+/// Sign a transaction hash on the network with the given magic as a witness
+pub async fn sign_witness(
+    signer: &dyn RelayerSigner,
+    network_magic: u32,
+    tx_hash: &[u8; 32],
+) -> Result<Witness, Error> {
+    let mut message = Vec::with_capacity(36);
+    message.extend_from_slice(&network_magic.to_le_bytes());
+    message.extend_from_slice(tx_hash);
+
+    let signature = signer.sign(&message).await?;
+    let mut invocation = Vec::with_capacity(66);
+    invocation.extend_from_slice(&[0x0c, 0x40]);
+    invocation.extend_from_slice(&signature);
+
+    Ok(Witness {
+        invocation,
+        verification: verification_script(&signer.public_key().await?)?,
+    })
+}
+
+/// Single-signature verification script: PUSHDATA1 <key> SYSCALL CheckSig
+pub fn verification_script(public_key: &[u8]) -> Result<Vec<u8>, Error> {
+    if public_key.len() != 33 {
+        return Err(Error::WalletError(format!(
+            "Invalid compressed public key length: {}",
+            public_key.len()
+        )));
+    }
+
+    let mut script = Vec::with_capacity(40);
+    script.extend_from_slice(&[0x0c, 0x21]);
+    script.extend_from_slice(public_key);
+    script.push(0x41);
+    script.extend_from_slice(&CHECK_SIG);
+    Ok(script)
+}
+
+/// Neo N3 address of a compressed secp256r1 public key
+pub fn address_from_public_key(public_key: &[u8]) -> Result<String, Error> {
+    let mut payload = Vec::with_capacity(21);
+    payload.push(ADDRESS_VERSION);
+    payload.extend_from_slice(&script_hash(public_key)?);
+
+    Ok(bs58::encode(payload).with_check().into_string())
+}
+
+/// Account script hash of a compressed secp256r1 public key, in serialized byte order
+pub fn script_hash(public_key: &[u8]) -> Result<[u8; 20], Error> {
+    let script = verification_script(public_key)?;
+    Ok(Ripemd160::digest(Sha256::digest(&script)).into())
+}
+
+/// Account script hash of a Neo N3 address, in serialized byte order
+pub fn script_hash_from_address(address: &str) -> Result<[u8; 20], Error> {
+    let payload = bs58::decode(address)
+        .with_check(None)
+        .into_vec()
+        .map_err(|e| Error::InvalidParameter(format!("Invalid Neo address {}: {}", address, e)))?;
+    if payload.len() != 21 || payload[0] != ADDRESS_VERSION {
+        return Err(Error::InvalidParameter(format!(
+            "Invalid Neo address: {}",
+            address
+        )));
+    }
+
+    let mut hash = [0u8; 20];
+    hash.copy_from_slice(&payload[1..]);
+    Ok(hash)
+}
+
+/// Decode a 64-byte `r || s` signature, also accepting ASN.1 DER from external signers
+pub(crate) fn normalize_signature(signature: &[u8]) -> Result<Vec<u8>, Error> {
+    if signature.len() == 64 {
+        return Ok(signature.to_vec());
+    }
+
+    p256::ecdsa::Signature::from_der(signature)
+        .map(|signature| signature.to_bytes().to_vec())
+        .map_err(|e| Error::InvalidSignature(format!("Invalid signer signature: {}", e)))
+}
+
+/// Compress a SEC1 encoded secp256r1 public key
+pub(crate) fn compress_public_key(public_key: &[u8]) -> Result<Vec<u8>, Error> {
+    let key = p256::PublicKey::from_sec1_bytes(public_key)
+        .map_err(|e| Error::WalletError(format!("Invalid signer public key: {}", e)))?;
+
+    Ok(key.to_encoded_point(true).as_bytes().to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use p256::ecdsa::signature::{Signer, Verifier};
+    use p256::ecdsa::{Signature, SigningKey, VerifyingKey};
+    use r3e_tee::types::{KeyMetadata, KeyType, KeyUsage};
+    use r3e_tee::TeeError;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    const PRIVATE_KEY: [u8; 32] = [1; 32];
+
+    fn signing_key() -> SigningKey {
+        SigningKey::from_slice(&PRIVATE_KEY).unwrap()
+    }
+
+    fn verify(public_key: &[u8], message: &[u8], signature: &[u8]) {
+        let signature = Signature::from_slice(signature).unwrap();
+        VerifyingKey::from_sec1_bytes(public_key)
+            .unwrap()
+            .verify(message, &signature)
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_local_signer_witness() {
+        let signer = LocalSigner::from_hex(&format!("0x{}", hex::encode(PRIVATE_KEY))).unwrap();
+        assert_eq!(signer.kind(), SignerKind::Local);
+        let public_key = signer.public_key().await.unwrap();
+        assert_eq!(public_key.len(), 33);
+
+        // The witness signs the network magic followed by the transaction hash
+        let witness = sign_witness(&signer, 860_833_102, &[7; 32]).await.unwrap();
+        assert_eq!(witness.invocation[..2], [0x0c, 0x40]);
+        let mut message = 860_833_102u32.to_le_bytes().to_vec();
+        message.extend_from_slice(&[7; 32]);
+        verify(&public_key, &message, &witness.invocation[2..]);
+
+        let mut verification = vec![0x0c, 0x21];
+        verification.extend_from_slice(&public_key);
+        verification.push(0x41);
+        verification.extend_from_slice(&CHECK_SIG);
+        assert_eq!(witness.verification, verification);
+
+        assert!(LocalSigner::from_hex("not hex").is_err());
+        assert!(LocalSigner::from_bytes(&[0; 32]).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_addresses() {
+        let signer = LocalSigner::from_bytes(&PRIVATE_KEY).unwrap();
+        let public_key = signer.public_key().await.unwrap();
+
+        let address = signer.address().await.unwrap();
+        assert!(address.starts_with('N'), "{}", address);
+        assert_eq!(
+            script_hash_from_address(&address).unwrap(),
+            script_hash(&public_key).unwrap()
+        );
+
+        let mut other_version = vec![0x17];
+        other_version.extend_from_slice(&[0; 20]);
+        for invalid in [
+            "NInvalid".to_string(),
+            bs58::encode(other_version).with_check().into_string(),
+        ] {
+            assert!(script_hash_from_address(&invalid).is_err());
+        }
+        assert!(verification_script(&public_key[1..]).is_err());
+    }
+
+    #[test]
+    fn test_signature_formats() {
+        let key = signing_key();
+        let signature: Signature = key.sign(b"message");
+        let raw = signature.to_bytes().to_vec();
+
+        assert_eq!(normalize_signature(&raw).unwrap(), raw);
+        assert_eq!(
+            normalize_signature(signature.to_der().as_bytes()).unwrap(),
+            raw
+        );
+        assert!(normalize_signature(&[1; 10]).is_err());
+
+        let uncompressed = key.verifying_key().to_encoded_point(false);
+        let compressed = key.verifying_key().to_encoded_point(true);
+        assert_eq!(
+            compress_public_key(uncompressed.as_bytes()).unwrap(),
+            compressed.as_bytes()
+        );
+        assert_eq!(
+            compress_public_key(compressed.as_bytes()).unwrap(),
+            compressed.as_bytes()
+        );
+        assert!(compress_public_key(&[4; 65]).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_signer_config() {
+        let config: SignerConfig = serde_json::from_value(serde_json::json!({
+            "type": "local",
+            "private_key": hex::encode(PRIVATE_KEY),
+        }))
+        .unwrap();
+        let signer = config.build(None, None).await.unwrap();
+        assert_eq!(signer.kind(), SignerKind::Local);
+
+        let remote: SignerConfig = serde_json::from_value(serde_json::json!({
+            "type": "remote",
+            "url": "https://signer.example.com",
+            "key_id": "relayer",
+        }))
+        .unwrap();
+        assert!(matches!(
+            remote,
+            SignerConfig::Remote {
+                auth_token: None,
+                timeout_ms: 5_000,
+                ..
+            }
+        ));
+
+        // Signers whose backing service is missing are not built
+        let tee = SignerConfig::Tee {
+            key_id: "key".to_string(),
+            public_key_id: "key.pub".to_string(),
+        };
+        let mpc = SignerConfig::Mpc {
+            key_id: "key".to_string(),
+        };
+        for config in [tee, mpc] {
+            assert!(matches!(
+                config.build(None, None).await,
+                Err(Error::ConfigError(_))
+            ));
+        }
+    }
+
+    /// Signing service on a local port, signing with `PRIVATE_KEY` for bearer `token`
+    async fn signing_service() -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let mut data = Vec::new();
+                let mut buf = [0u8; 4096];
+                let (head, body) = loop {
+                    let n = stream.read(&mut buf).await.unwrap();
+                    data.extend_from_slice(&buf[..n]);
+                    let Some(end) = data.windows(4).position(|w| w == b"\r\n\r\n") else {
+                        continue;
+                    };
+                    let head = String::from_utf8_lossy(&data[..end]).to_lowercase();
+                    let length = head
+                        .lines()
+                        .find_map(|line| line.strip_prefix("content-length:"))
+                        .map_or(0, |length| length.trim().parse().unwrap());
+                    if data.len() >= end + 4 + length {
+                        break (head, data[end + 4..end + 4 + length].to_vec());
+                    }
+                };
+
+                let key = signing_key();
+                let (status, response) = if !head.contains("authorization: bearer token") {
+                    (401, serde_json::json!({}))
+                } else if head.starts_with("get /keys/relayer ") {
+                    let public_key = key.verifying_key().to_encoded_point(false);
+                    (
+                        200,
+                        serde_json::json!({ "public_key": hex::encode(public_key.as_bytes()) }),
+                    )
+                } else if head.starts_with("post /keys/relayer/sign ") {
+                    let request: serde_json::Value = serde_json::from_slice(&body).unwrap();
+                    let message = hex::decode(request["message"].as_str().unwrap()).unwrap();
+                    let signature: Signature = key.sign(&message);
+                    let signature = hex::encode(signature.to_der().as_bytes());
+                    (200, serde_json::json!({ "signature": signature }))
+                } else {
+                    (404, serde_json::json!({}))
+                };
+
+                let body = response.to_string();
+                let response = format!(
+                    "HTTP/1.1 {} Status\r\nContent-Type: application/json\r\n\
+                     Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    status,
+                    body.len(),
+                    body
+                );
+                stream.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+        url
+    }
+
+    #[tokio::test]
+    async fn test_remote_signer() {
+        let url = signing_service().await;
+
+        let signer = RemoteSigner::new(&url, "relayer", Some("token".to_string()), 5_000)
+            .await
+            .unwrap();
+        assert_eq!(signer.kind(), SignerKind::Remote);
+        let public_key = signer.public_key().await.unwrap();
+        assert_eq!(
+            public_key,
+            signing_key()
+                .verifying_key()
+                .to_encoded_point(true)
+                .as_bytes()
+        );
+
+        // DER signatures of the service are returned as r || s
+        let signature = signer.sign(b"message").await.unwrap();
+        assert_eq!(signature.len(), 64);
+        verify(&public_key, b"message", &signature);
+
+        for (key_id, token) in [("relayer", None), ("unknown", Some("token".to_string()))] {
+            assert!(matches!(
+                RemoteSigner::new(&url, key_id, token, 5_000).await,
+                Err(Error::External(_))
+            ));
+        }
+    }
+
+    /// Key management service holding the relayer key as `relayer` and its public key as
+    /// `relayer.pub`
+    struct MockKms {
+        usage: Vec<KeyUsage>,
+    }
+
+    #[async_trait]
+    impl KeyManagementService for MockKms {
+        async fn generate_key(
+            &self,
+            _key_type: KeyType,
+            _usage: Vec<KeyUsage>,
+            _algorithm: &str,
+            _size: u32,
+            _exportable: bool,
+        ) -> Result<KeyMetadata, TeeError> {
+            unimplemented!()
+        }
+
+        async fn import_key(
+            &self,
+            _key_data: &[u8],
+            _key_type: KeyType,
+            _usage: Vec<KeyUsage>,
+            _algorithm: &str,
+            _exportable: bool,
+        ) -> Result<KeyMetadata, TeeError> {
+            unimplemented!()
+        }
+
+        async fn export_key(&self, key_id: &str) -> Result<Vec<u8>, TeeError> {
+            match key_id {
+                "relayer.pub" => Ok(signing_key()
+                    .verifying_key()
+                    .to_encoded_point(false)
+                    .as_bytes()
+                    .to_vec()),
+                _ => Err(TeeError::KeyManagement(format!(
+                    "{} is not exportable",
+                    key_id
+                ))),
+            }
+        }
+
+        async fn delete_key(&self, _key_id: &str) -> Result<bool, TeeError> {
+            unimplemented!()
+        }
+
+        async fn get_key_metadata(&self, key_id: &str) -> Result<KeyMetadata, TeeError> {
+            Ok(KeyMetadata {
+                id: key_id.to_string(),
+                key_type: KeyType::AsymmetricPrivate,
+                usage: self.usage.clone(),
+                algorithm: "ECDSA-P256".to_string(),
+                size: 256,
+                created_at: 0,
+                expires_at: None,
+                exportable: false,
+            })
+        }
+
+        async fn list_keys(&self) -> Result<Vec<KeyMetadata>, TeeError> {
+            unimplemented!()
+        }
+
+        async fn encrypt(
+            &self,
+            _key_id: &str,
+            _data: &[u8],
+            _iv: Option<&[u8]>,
+        ) -> Result<Vec<u8>, TeeError> {
+            unimplemented!()
+        }
+
+        async fn decrypt(
+            &self,
+            _key_id: &str,
+            _data: &[u8],
+            _iv: Option<&[u8]>,
+        ) -> Result<Vec<u8>, TeeError> {
+            unimplemented!()
+        }
+
+        async fn sign(&self, _key_id: &str, data: &[u8]) -> Result<Vec<u8>, TeeError> {
+            let signature: Signature = signing_key().sign(data);
+            Ok(signature.to_der().as_bytes().to_vec())
+        }
+
+        async fn verify(
+            &self,
+            _key_id: &str,
+            _data: &[u8],
+            _signature: &[u8],
+        ) -> Result<bool, TeeError> {
+            unimplemented!()
+        }
+
+        async fn wrap_key(
+            &self,
+            _wrapping_key_id: &str,
+            _key_id: &str,
+        ) -> Result<Vec<u8>, TeeError> {
+            unimplemented!()
+        }
+
+        async fn unwrap_key(
+            &self,
+            _unwrapping_key_id: &str,
+            _wrapped_key: &[u8],
+            _key_type: KeyType,
+            _usage: Vec<KeyUsage>,
+            _algorithm: &str,
+            _exportable: bool,
+        ) -> Result<KeyMetadata, TeeError> {
+            unimplemented!()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_tee_signer() {
+        let kms = Arc::new(MockKms {
+            usage: vec![KeyUsage::Signing],
+        });
+        let config = SignerConfig::Tee {
+            key_id: "relayer".to_string(),
+            public_key_id: "relayer.pub".to_string(),
+        };
+        let signer = config.build(Some(kms.clone()), None).await.unwrap();
+        assert_eq!(signer.kind(), SignerKind::Tee);
+
+        let public_key = signer.public_key().await.unwrap();
+        let signature = signer.sign(b"message").await.unwrap();
+        assert_eq!(signature.len(), 64);
+        verify(&public_key, b"message", &signature);
+
+        assert!(TeeSigner::new(kms, "relayer", "relayer").await.is_err());
+        let encryption_key = Arc::new(MockKms {
+            usage: vec![KeyUsage::Encryption],
+        });
+        assert!(matches!(
+            TeeSigner::new(encryption_key, "relayer", "relayer.pub").await,
+            Err(Error::ConfigError(_))
+        ));
+    }
+}
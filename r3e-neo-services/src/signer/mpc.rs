@@ -0,0 +1,54 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+use super::{RelayerSigner, SignerKind};
+use crate::mpc::MpcServiceTrait;
+use crate::Error;
+use async_trait::async_trait;
+use std::sync::Arc;
+
+/// Signer whose key is shared among the parties of the MPC service
+pub struct MpcSigner {
+    /// MPC service
+    mpc: Arc<dyn MpcServiceTrait>,
+    /// Threshold key ID
+    key_id: String,
+    /// Compressed public key
+    public_key: Vec<u8>,
+}
+
+impl MpcSigner {
+    /// Create an MPC signer for a threshold key
+    pub async fn new(mpc: Arc<dyn MpcServiceTrait>, key_id: &str) -> Result<Self, Error> {
+        let key = mpc
+            .get_key(key_id)
+            .await
+            .map_err(|e| Error::ConfigError(format!("MPC key {}: {}", key_id, e)))?;
+        let public_key = hex::decode(&key.public_key)
+            .map_err(|e| Error::ConfigError(format!("MPC key {}: {}", key_id, e)))?;
+
+        Ok(Self {
+            mpc,
+            key_id: key_id.to_string(),
+            public_key,
+        })
+    }
+}
+
+#[async_trait]
+impl RelayerSigner for MpcSigner {
+    fn kind(&self) -> SignerKind {
+        SignerKind::Mpc
+    }
+
+    async fn public_key(&self) -> Result<Vec<u8>, Error> {
+        Ok(self.public_key.clone())
+    }
+
+    async fn sign(&self, message: &[u8]) -> Result<Vec<u8>, Error> {
+        self.mpc
+            .sign(&self.key_id, message)
+            .await
+            .map_err(|e| Error::WalletError(format!("MPC signing failed: {}", e)))
+    }
+}
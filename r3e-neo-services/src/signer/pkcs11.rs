@@ -0,0 +1,125 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+use super::{compress_public_key, normalize_signature, RelayerSigner, SignerKind};
+use crate::Error;
+use async_trait::async_trait;
+use cryptoki::context::{CInitializeArgs, Pkcs11};
+use cryptoki::mechanism::Mechanism;
+use cryptoki::object::{Attribute, AttributeType, ObjectClass, ObjectHandle};
+use cryptoki::session::{Session, UserType};
+use cryptoki::types::AuthPin;
+use sha2::{Digest, Sha256};
+use std::sync::Mutex;
+
+/// Signer backed by a hardware security module via PKCS#11
+pub struct Pkcs11Signer {
+    /// Logged-in session, PKCS#11 sessions must not be used concurrently
+    session: Mutex<Session>,
+    /// Private key handle
+    key: ObjectHandle,
+    /// Compressed public key
+    public_key: Vec<u8>,
+}
+
+impl Pkcs11Signer {
+    /// Open a session on the labelled token and find the labelled key pair
+    pub fn open(
+        module_path: &str,
+        token_label: &str,
+        pin: &str,
+        key_label: &str,
+    ) -> Result<Self, Error> {
+        let pkcs11 = Pkcs11::new(module_path).map_err(pkcs11_error)?;
+        pkcs11
+            .initialize(CInitializeArgs::OsThreads)
+            .map_err(pkcs11_error)?;
+
+        let slot = pkcs11
+            .get_slots_with_token()
+            .map_err(pkcs11_error)?
+            .into_iter()
+            .find(|slot| {
+                pkcs11
+                    .get_token_info(*slot)
+                    .map(|info| info.label().trim() == token_label)
+                    .unwrap_or(false)
+            })
+            .ok_or_else(|| {
+                Error::ConfigError(format!("PKCS#11 token not found: {}", token_label))
+            })?;
+
+        let session = pkcs11.open_ro_session(slot).map_err(pkcs11_error)?;
+        session
+            .login(UserType::User, Some(&AuthPin::new(pin.to_string())))
+            .map_err(pkcs11_error)?;
+
+        let key = find_object(&session, ObjectClass::PRIVATE_KEY, key_label)?;
+        let public = find_object(&session, ObjectClass::PUBLIC_KEY, key_label)?;
+        let ec_point = session
+            .get_attributes(public, &[AttributeType::EcPoint])
+            .map_err(pkcs11_error)?
+            .into_iter()
+            .find_map(|attribute| match attribute {
+                Attribute::EcPoint(point) => Some(point),
+                _ => None,
+            })
+            .ok_or_else(|| {
+                Error::ConfigError(format!("PKCS#11 key has no EC point: {}", key_label))
+            })?;
+
+        Ok(Self {
+            session: Mutex::new(session),
+            key,
+            public_key: compress_public_key(unwrap_octet_string(&ec_point))?,
+        })
+    }
+}
+
+#[async_trait]
+impl RelayerSigner for Pkcs11Signer {
+    fn kind(&self) -> SignerKind {
+        SignerKind::Pkcs11
+    }
+
+    async fn public_key(&self) -> Result<Vec<u8>, Error> {
+        Ok(self.public_key.clone())
+    }
+
+    async fn sign(&self, message: &[u8]) -> Result<Vec<u8>, Error> {
+        // CKM_ECDSA signs a precomputed digest
+        let digest = Sha256::digest(message);
+        let signature = self
+            .session
+            .lock()
+            .unwrap()
+            .sign(&Mechanism::Ecdsa, self.key, &digest)
+            .map_err(pkcs11_error)?;
+
+        normalize_signature(&signature)
+    }
+}
+
+fn find_object(session: &Session, class: ObjectClass, label: &str) -> Result<ObjectHandle, Error> {
+    session
+        .find_objects(&[
+            Attribute::Class(class),
+            Attribute::Label(label.as_bytes().to_vec()),
+        ])
+        .map_err(pkcs11_error)?
+        .into_iter()
+        .next()
+        .ok_or_else(|| Error::ConfigError(format!("PKCS#11 {} not found: {}", class, label)))
+}
+
+/// CKA_EC_POINT is a DER OCTET STRING wrapping the SEC1 point
+fn unwrap_octet_string(value: &[u8]) -> &[u8] {
+    match value {
+        [0x04, len, point @ ..] if *len as usize == point.len() => point,
+        _ => value,
+    }
+}
+
+fn pkcs11_error(e: cryptoki::error::Error) -> Error {
+    Error::WalletError(format!("PKCS#11 error: {}", e))
+}
@@ -0,0 +1,131 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+use super::{compress_public_key, normalize_signature, RelayerSigner, SignerKind};
+use crate::Error;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// Public key response of the signing service
+#[derive(Debug, Deserialize)]
+struct PublicKeyResponse {
+    /// Hex encoded SEC1 public key
+    public_key: String,
+}
+
+/// Sign request to the signing service
+#[derive(Debug, Serialize)]
+struct SignRequest {
+    /// Hex encoded message, hashed with SHA-256 by the service
+    message: String,
+}
+
+/// Sign response of the signing service
+#[derive(Debug, Deserialize)]
+struct SignResponse {
+    /// Hex encoded signature, `r || s` or ASN.1 DER
+    signature: String,
+}
+
+/// Signer delegating to a remote signing service.
+///
+/// The service exposes `GET {url}/keys/{key_id}` returning the public key and
+/// `POST {url}/keys/{key_id}/sign` signing a message.
+pub struct RemoteSigner {
+    /// HTTP client
+    client: reqwest::Client,
+    /// Key URL
+    key_url: String,
+    /// Bearer token
+    auth_token: Option<String>,
+    /// Compressed public key
+    public_key: Vec<u8>,
+}
+
+impl RemoteSigner {
+    /// Create a remote signer, fetching the public key of the key
+    pub async fn new(
+        url: &str,
+        key_id: &str,
+        auth_token: Option<String>,
+        timeout_ms: u64,
+    ) -> Result<Self, Error> {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_millis(timeout_ms))
+            .build()
+            .map_err(|e| Error::ConfigError(format!("Failed to create HTTP client: {}", e)))?;
+        let key_url = format!("{}/keys/{}", url.trim_end_matches('/'), key_id);
+
+        let mut signer = Self {
+            client,
+            key_url,
+            auth_token,
+            public_key: Vec::new(),
+        };
+
+        let response: PublicKeyResponse =
+            signer.request(signer.client.get(&signer.key_url)).await?;
+        let public_key = hex::decode(&response.public_key)
+            .map_err(|e| Error::WalletError(format!("Invalid signer public key: {}", e)))?;
+        signer.public_key = compress_public_key(&public_key)?;
+
+        Ok(signer)
+    }
+
+    async fn request<T: for<'de> Deserialize<'de>>(
+        &self,
+        request: reqwest::RequestBuilder,
+    ) -> Result<T, Error> {
+        let request = match &self.auth_token {
+            Some(token) => request.bearer_auth(token),
+            None => request,
+        };
+
+        let response = request
+            .send()
+            .await
+            .map_err(|e| Error::Network(format!("Signing service request failed: {}", e)))?;
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            return Err(Error::External(format!(
+                "Signing service returned {}: {}",
+                status, body
+            )));
+        }
+
+        response
+            .json()
+            .await
+            .map_err(|e| Error::Serialization(format!("Invalid signing service response: {}", e)))
+    }
+}
+
+#[async_trait]
+impl RelayerSigner for RemoteSigner {
+    fn kind(&self) -> SignerKind {
+        SignerKind::Remote
+    }
+
+    async fn public_key(&self) -> Result<Vec<u8>, Error> {
+        Ok(self.public_key.clone())
+    }
+
+    async fn sign(&self, message: &[u8]) -> Result<Vec<u8>, Error> {
+        let request = SignRequest {
+            message: hex::encode(message),
+        };
+        let response: SignResponse = self
+            .request(
+                self.client
+                    .post(format!("{}/sign", self.key_url))
+                    .json(&request),
+            )
+            .await?;
+
+        let signature = hex::decode(&response.signature)
+            .map_err(|e| Error::InvalidSignature(format!("Invalid signer signature: {}", e)))?;
+        normalize_signature(&signature)
+    }
+}
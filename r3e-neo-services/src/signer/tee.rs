@@ -0,0 +1,74 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+use super::{compress_public_key, normalize_signature, RelayerSigner, SignerKind};
+use crate::Error;
+use async_trait::async_trait;
+use r3e_tee::key_management::KeyManagementService;
+use r3e_tee::types::KeyUsage;
+use std::sync::Arc;
+
+/// Signer whose key never leaves the TEE key management service
+pub struct TeeSigner {
+    /// Key management service
+    kms: Arc<dyn KeyManagementService>,
+    /// Signing key ID
+    key_id: String,
+    /// Compressed public key
+    public_key: Vec<u8>,
+}
+
+impl TeeSigner {
+    /// Create a TEE signer for a signing key and its exportable public key
+    pub async fn new(
+        kms: Arc<dyn KeyManagementService>,
+        key_id: &str,
+        public_key_id: &str,
+    ) -> Result<Self, Error> {
+        let metadata = kms
+            .get_key_metadata(key_id)
+            .await
+            .map_err(|e| Error::ConfigError(format!("TEE signing key {}: {}", key_id, e)))?;
+        if !metadata.usage.contains(&KeyUsage::Signing) {
+            return Err(Error::ConfigError(format!(
+                "TEE key {} cannot be used for signing",
+                key_id
+            )));
+        }
+        if metadata.exportable {
+            log::warn!("TEE signing key {} is exportable", key_id);
+        }
+
+        let public_key = kms
+            .export_key(public_key_id)
+            .await
+            .map_err(|e| Error::ConfigError(format!("TEE public key {}: {}", public_key_id, e)))?;
+
+        Ok(Self {
+            kms,
+            key_id: key_id.to_string(),
+            public_key: compress_public_key(&public_key)?,
+        })
+    }
+}
+
+#[async_trait]
+impl RelayerSigner for TeeSigner {
+    fn kind(&self) -> SignerKind {
+        SignerKind::Tee
+    }
+
+    async fn public_key(&self) -> Result<Vec<u8>, Error> {
+        Ok(self.public_key.clone())
+    }
+
+    async fn sign(&self, message: &[u8]) -> Result<Vec<u8>, Error> {
+        let signature = self
+            .kms
+            .sign(&self.key_id, message)
+            .await
+            .map_err(|e| Error::WalletError(format!("TEE signing failed: {}", e)))?;
+
+        normalize_signature(&signature)
+    }
+}
@@ -31,7 +31,7 @@ pub struct NeoServicesConfig {
 }
 
 /// Transaction fee model
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum FeeModel {
     /// Fixed fee amount
     Fixed(u64),
@@ -43,6 +43,37 @@ pub enum FeeModel {
     Free,
 }
 
+impl std::fmt::Display for FeeModel {
+    /// Fee model as signed in meta transactions: `fixed:<amount>`, `percentage:<percent>`,
+    /// `dynamic` or `free`
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FeeModel::Fixed(amount) => write!(f, "fixed:{}", amount),
+            FeeModel::Percentage(percentage) => write!(f, "percentage:{}", percentage),
+            FeeModel::Dynamic => write!(f, "dynamic"),
+            FeeModel::Free => write!(f, "free"),
+        }
+    }
+}
+
+impl std::str::FromStr for FeeModel {
+    type Err = crate::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || crate::Error::InvalidParameter(format!("Invalid fee model: {}", s));
+        match s.split_once(':') {
+            Some(("fixed", amount)) => amount.parse().map(FeeModel::Fixed).map_err(|_| invalid()),
+            Some(("percentage", percentage)) => percentage
+                .parse()
+                .map(FeeModel::Percentage)
+                .map_err(|_| invalid()),
+            None if s == "dynamic" => Ok(FeeModel::Dynamic),
+            None if s == "free" => Ok(FeeModel::Free),
+            _ => Err(invalid()),
+        }
+    }
+}
+
 /// Gas bank transaction
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GasBankTransaction {
@@ -0,0 +1,16 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+//! Managed accounts imported from, and exported to, NEP-6 wallets.
+
+pub mod nep6;
+pub mod rocksdb;
+pub mod service;
+pub mod storage;
+pub mod types;
+
+pub use nep6::{Nep6Account, Nep6Wallet, ScryptParams};
+pub use rocksdb::RocksDBManagedAccountStorage;
+pub use service::WalletService;
+pub use storage::{ManagedAccountStorage, MemoryManagedAccountStorage};
+pub use types::*;
@@ -0,0 +1,344 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+//! NEP-6 wallet files and the NEP-2 encrypted keys they hold.
+
+use aes::cipher::{generic_array::GenericArray, BlockDecrypt, BlockEncrypt, KeyInit};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
+use p256::elliptic_curve::sec1::ToEncodedPoint;
+use p256::SecretKey;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::signer;
+use crate::Error;
+
+/// NEP-6 version written to exported wallets
+pub const NEP6_VERSION: &str = "1.0";
+
+/// Prefix of a NEP-2 encrypted key, without EC multiplication
+const NEP2_PREFIX: [u8; 3] = [0x01, 0x42, 0xe0];
+
+/// Largest scrypt cost accepted, as log2(N), and largest block size and parallelism
+const MAX_SCRYPT_LOG_N: u8 = 16;
+const MAX_SCRYPT_R: u32 = 16;
+const MAX_SCRYPT_P: u32 = 16;
+
+/// scrypt parameters of the keys of a wallet
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScryptParams {
+    /// CPU and memory cost, a power of two
+    pub n: u32,
+    /// Block size
+    pub r: u32,
+    /// Parallelism
+    pub p: u32,
+}
+
+impl Default for ScryptParams {
+    /// Parameters NEP-2 specifies
+    fn default() -> Self {
+        Self {
+            n: 16384,
+            r: 8,
+            p: 8,
+        }
+    }
+}
+
+impl ScryptParams {
+    /// Check the parameters, bounded so an uploaded wallet cannot tie up the server
+    pub fn validate(&self) -> Result<(), Error> {
+        self.params().map(|_| ())
+    }
+
+    fn params(&self) -> Result<scrypt::Params, Error> {
+        if !self.n.is_power_of_two() || self.n < 2 {
+            return Err(Error::InvalidParameter(format!(
+                "scrypt n must be a power of two, got {}",
+                self.n
+            )));
+        }
+        let log_n = self.n.trailing_zeros() as u8;
+        if log_n > MAX_SCRYPT_LOG_N || self.r > MAX_SCRYPT_R || self.p > MAX_SCRYPT_P {
+            return Err(Error::InvalidParameter(format!(
+                "scrypt parameters exceed n = {}, r = {}, p = {}",
+                1u32 << MAX_SCRYPT_LOG_N,
+                MAX_SCRYPT_R,
+                MAX_SCRYPT_P
+            )));
+        }
+
+        scrypt::Params::new(log_n, self.r, self.p, 64)
+            .map_err(|e| Error::InvalidParameter(format!("Invalid scrypt parameters: {}", e)))
+    }
+}
+
+/// NEP-6 wallet
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Nep6Wallet {
+    /// Wallet name
+    #[serde(default)]
+    pub name: Option<String>,
+    /// NEP-6 version
+    pub version: String,
+    /// scrypt parameters of the account keys
+    pub scrypt: ScryptParams,
+    /// Accounts
+    pub accounts: Vec<Nep6Account>,
+    /// Wallet specific data
+    #[serde(default)]
+    pub extra: Option<serde_json::Value>,
+}
+
+/// Account of a NEP-6 wallet
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Nep6Account {
+    /// Address
+    pub address: String,
+    /// Label
+    #[serde(default)]
+    pub label: Option<String>,
+    /// Whether the account is the default one of the wallet
+    #[serde(default)]
+    pub is_default: bool,
+    /// Whether the account is locked
+    #[serde(default)]
+    pub lock: bool,
+    /// NEP-2 encrypted private key, not set for watch-only accounts
+    #[serde(default)]
+    pub key: Option<String>,
+    /// Verification contract
+    #[serde(default)]
+    pub contract: Option<Nep6Contract>,
+    /// Account specific data
+    #[serde(default)]
+    pub extra: Option<serde_json::Value>,
+}
+
+/// Verification contract of a NEP-6 account
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Nep6Contract {
+    /// Base64 encoded verification script
+    pub script: Option<String>,
+    /// Parameters of the verification script
+    #[serde(default)]
+    pub parameters: Vec<Nep6Parameter>,
+    /// Whether the contract is deployed
+    #[serde(default)]
+    pub deployed: bool,
+}
+
+/// Parameter of a verification contract
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Nep6Parameter {
+    /// Name
+    pub name: String,
+    /// Parameter type
+    #[serde(rename = "type")]
+    pub param_type: String,
+}
+
+impl Nep6Account {
+    /// Single-signature account of a private key, its key encrypted with `password`
+    pub fn single_sig(
+        private_key: &[u8],
+        password: &str,
+        params: &ScryptParams,
+        label: Option<String>,
+        is_default: bool,
+    ) -> Result<Self, Error> {
+        let public_key = public_key(private_key)?;
+        Ok(Self {
+            address: signer::address_from_public_key(&public_key)?,
+            label,
+            is_default,
+            lock: false,
+            key: Some(nep2_encrypt(private_key, password, params)?),
+            contract: Some(Nep6Contract {
+                script: Some(BASE64.encode(signer::verification_script(&public_key)?)),
+                parameters: vec![Nep6Parameter {
+                    name: "signature".to_string(),
+                    param_type: "Signature".to_string(),
+                }],
+                deployed: false,
+            }),
+            extra: None,
+        })
+    }
+}
+
+/// Compressed public key of a private key
+pub fn public_key(private_key: &[u8]) -> Result<Vec<u8>, Error> {
+    let secret = SecretKey::from_slice(private_key)
+        .map_err(|e| Error::WalletError(format!("Invalid private key: {}", e)))?;
+
+    Ok(secret
+        .public_key()
+        .to_encoded_point(true)
+        .as_bytes()
+        .to_vec())
+}
+
+/// First 4 bytes of the double SHA-256 of an address, the salt of its NEP-2 key
+fn address_hash(address: &str) -> [u8; 4] {
+    let hash = Sha256::digest(Sha256::digest(address.as_bytes()));
+    [hash[0], hash[1], hash[2], hash[3]]
+}
+
+fn derive_key(password: &str, salt: &[u8; 4], params: &ScryptParams) -> Result<[u8; 64], Error> {
+    let mut derived = [0u8; 64];
+    scrypt::scrypt(password.as_bytes(), salt, &params.params()?, &mut derived)
+        .map_err(|e| Error::WalletError(format!("scrypt failed: {}", e)))?;
+    Ok(derived)
+}
+
+/// Encrypt a private key with a password as NEP-2
+pub fn nep2_encrypt(
+    private_key: &[u8],
+    password: &str,
+    params: &ScryptParams,
+) -> Result<String, Error> {
+    if private_key.len() != 32 {
+        return Err(Error::InvalidParameter(format!(
+            "Invalid private key length: {}",
+            private_key.len()
+        )));
+    }
+
+    let address = signer::address_from_public_key(&public_key(private_key)?)?;
+    let salt = address_hash(&address);
+    let derived = derive_key(password, &salt, params)?;
+
+    let mut encrypted = [0u8; 32];
+    for (i, byte) in encrypted.iter_mut().enumerate() {
+        *byte = private_key[i] ^ derived[i];
+    }
+    let cipher = aes::Aes256::new(GenericArray::from_slice(&derived[32..]));
+    for block in encrypted.chunks_mut(16) {
+        cipher.encrypt_block(GenericArray::from_mut_slice(block));
+    }
+
+    let mut payload = Vec::with_capacity(39);
+    payload.extend_from_slice(&NEP2_PREFIX);
+    payload.extend_from_slice(&salt);
+    payload.extend_from_slice(&encrypted);
+    Ok(bs58::encode(payload).with_check().into_string())
+}
+
+/// Decrypt a NEP-2 key, failing on a wrong password
+pub fn nep2_decrypt(key: &str, password: &str, params: &ScryptParams) -> Result<[u8; 32], Error> {
+    let payload = bs58::decode(key)
+        .with_check(None)
+        .into_vec()
+        .map_err(|e| Error::InvalidParameter(format!("Invalid NEP-2 key: {}", e)))?;
+    if payload.len() != 39 || payload[..3] != NEP2_PREFIX {
+        return Err(Error::InvalidParameter(
+            "Invalid NEP-2 key: not a non-EC-multiplied key".to_string(),
+        ));
+    }
+
+    let salt: [u8; 4] = payload[3..7].try_into().expect("4 bytes");
+    let derived = derive_key(password, &salt, params)?;
+
+    let mut private_key = [0u8; 32];
+    private_key.copy_from_slice(&payload[7..]);
+    let cipher = aes::Aes256::new(GenericArray::from_slice(&derived[32..]));
+    for block in private_key.chunks_mut(16) {
+        cipher.decrypt_block(GenericArray::from_mut_slice(block));
+    }
+    for (i, byte) in private_key.iter_mut().enumerate() {
+        *byte ^= derived[i];
+    }
+
+    // The salt is the hash of the key's address, which a wrong password does not reproduce
+    let address = public_key(&private_key)
+        .and_then(|public_key| signer::address_from_public_key(&public_key))
+        .map_err(|_| Error::InvalidParameter("Wrong password for NEP-2 key".to_string()))?;
+    if address_hash(&address) != salt {
+        return Err(Error::InvalidParameter(
+            "Wrong password for NEP-2 key".to_string(),
+        ));
+    }
+
+    Ok(private_key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Cheap parameters, so the tests do not spend their time in scrypt
+    const FAST: ScryptParams = ScryptParams { n: 2, r: 1, p: 1 };
+
+    #[test]
+    fn test_nep2_known_answer() {
+        let private_key =
+            hex::decode("84180ac9d6eb6fba207ea4ef9d2200102d1ebeb4b9c07e2c6a738a42742e27a5")
+                .unwrap();
+        let key = "6PYM7jHL4GmS8Aw2iEFpuaHTCUKjhT4mwVqdoozGU6sUE25BjV4ePXDdLz";
+        let params = ScryptParams::default();
+
+        let address = signer::address_from_public_key(&public_key(&private_key).unwrap()).unwrap();
+        assert_eq!(address, "NM7Aky765FG8NhhwtxjXRx7jEL1cnw7PBP");
+        assert_eq!(nep2_encrypt(&private_key, "neo", &params).unwrap(), key);
+        assert_eq!(
+            nep2_decrypt(key, "neo", &params).unwrap().to_vec(),
+            private_key
+        );
+    }
+
+    #[test]
+    fn test_nep2_round_trip() {
+        let private_key = [1u8; 32];
+        let key = nep2_encrypt(&private_key, "123", &FAST).unwrap();
+        assert_eq!(
+            key,
+            "6PYWucwbucUkCwxzwQJAwzB1gXKdCDZBpB3So55crUHRvLcFkD6sMF5mkM"
+        );
+        assert_eq!(nep2_decrypt(&key, "123", &FAST).unwrap(), private_key);
+
+        let account = Nep6Account::single_sig(&private_key, "123", &FAST, None, true).unwrap();
+        assert_eq!(account.address, "NUz6PKTAM7NbPJzkKJFNay3VckQtcDkgWo");
+        assert_eq!(account.key.as_deref(), Some(key.as_str()));
+    }
+
+    #[test]
+    fn test_nep2_wrong_password() {
+        let key = nep2_encrypt(&[1u8; 32], "123", &FAST).unwrap();
+        for password in ["1234", "", "12"] {
+            assert!(matches!(
+                nep2_decrypt(&key, password, &FAST),
+                Err(Error::InvalidParameter(message)) if message.contains("Wrong password")
+            ));
+        }
+
+        // The scrypt parameters are part of the key derivation
+        let other = ScryptParams { n: 4, r: 1, p: 1 };
+        assert!(nep2_decrypt(&key, "123", &other).is_err());
+    }
+
+    #[test]
+    fn test_nep2_invalid_input() {
+        for private_key in [vec![1u8; 31], vec![1u8; 33], Vec::new()] {
+            assert!(matches!(
+                nep2_encrypt(&private_key, "123", &FAST),
+                Err(Error::InvalidParameter(_))
+            ));
+        }
+        // Out of range scalar
+        assert!(nep2_encrypt(&[0xff; 32], "123", &FAST).is_err());
+
+        let key = nep2_encrypt(&[1u8; 32], "123", &FAST).unwrap();
+        let mut corrupted = key.clone().into_bytes();
+        corrupted[10] = if corrupted[10] == b'a' { b'b' } else { b'a' };
+        let corrupted = String::from_utf8(corrupted).unwrap();
+        assert!(nep2_decrypt(&corrupted, "123", &FAST).is_err());
+        assert!(nep2_decrypt("not a key", "123", &FAST).is_err());
+
+        // A WIF is a checksummed key, but not a NEP-2 one
+        let wif = "L1eV34wPoj9weqhGijdDLtVQzUpWGHszXXpdU9dPuh2nRFFzFa7E";
+        assert!(nep2_decrypt(wif, "123", &FAST).is_err());
+    }
+}
@@ -0,0 +1,105 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+use async_trait::async_trait;
+use r3e_store::rocksdb::RocksDbConfig;
+use r3e_store::RocksDBStore;
+use std::path::Path;
+use std::sync::Arc;
+
+use super::storage::ManagedAccountStorage;
+use super::types::ManagedAccount;
+use crate::Error;
+
+/// RocksDB implementation of ManagedAccountStorage, keyed by `{owner}/{address}`
+pub struct RocksDBManagedAccountStorage {
+    db: Arc<RocksDBStore>,
+    accounts_cf: String,
+}
+
+impl RocksDBManagedAccountStorage {
+    /// Create a new RocksDB managed account storage
+    pub async fn new<P: AsRef<Path>>(db_path: P) -> Result<Self, Error> {
+        let config = RocksDbConfig {
+            path: db_path.as_ref().to_string_lossy().to_string(),
+            ..Default::default()
+        };
+
+        let db = RocksDBStore::new(config);
+
+        // Open the database
+        db.open()
+            .map_err(|e| Error::Storage(format!("Failed to open RocksDB store: {}", e)))?;
+
+        let accounts_cf = "managed_accounts".to_string();
+        db.create_cf_if_missing(&accounts_cf).map_err(|e| {
+            Error::Storage(format!(
+                "Failed to create column family {}: {}",
+                accounts_cf, e
+            ))
+        })?;
+
+        Ok(Self {
+            db: Arc::new(db),
+            accounts_cf,
+        })
+    }
+}
+
+fn account_key(owner: &str, address: &str) -> String {
+    format!("{}/{}", owner, address)
+}
+
+#[async_trait]
+impl ManagedAccountStorage for RocksDBManagedAccountStorage {
+    async fn get_account(
+        &self,
+        owner: &str,
+        address: &str,
+    ) -> Result<Option<ManagedAccount>, Error> {
+        match self
+            .db
+            .get_cf::<_, Vec<u8>>(&self.accounts_cf, account_key(owner, address))
+        {
+            Ok(Some(value)) => serde_json::from_slice(&value)
+                .map(Some)
+                .map_err(|e| Error::Storage(format!("Failed to deserialize account: {}", e))),
+            Ok(None) => Ok(None),
+            Err(e) => Err(Error::Storage(format!("Failed to get account: {}", e))),
+        }
+    }
+
+    async fn put_account(&self, account: ManagedAccount) -> Result<(), Error> {
+        let value = serde_json::to_vec(&account)
+            .map_err(|e| Error::Storage(format!("Failed to serialize account: {}", e)))?;
+
+        self.db
+            .put_cf(
+                &self.accounts_cf,
+                account_key(&account.owner, &account.address),
+                &value,
+            )
+            .map_err(|e| Error::Storage(format!("Failed to store account: {}", e)))
+    }
+
+    async fn list_accounts(&self, owner: &str) -> Result<Vec<ManagedAccount>, Error> {
+        let prefix = format!("{}/", owner);
+        let iter = self
+            .db
+            .prefix_iter_cf::<Vec<u8>>(&self.accounts_cf, prefix.as_bytes())
+            .map_err(|e| Error::Storage(format!("Failed to scan accounts: {}", e)))?;
+
+        let mut accounts = Vec::new();
+        for (_, value) in iter {
+            let account = serde_json::from_slice::<ManagedAccount>(&value)
+                .map_err(|e| Error::Storage(format!("Failed to deserialize account: {}", e)))?;
+            // Owners containing `/` may share the prefix of another owner
+            if account.owner == owner {
+                accounts.push(account);
+            }
+        }
+
+        accounts.sort_by_key(|account| account.imported_at);
+        Ok(accounts)
+    }
+}
@@ -0,0 +1,248 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+use log::info;
+use r3e_secrets::service::SecretService;
+use r3e_secrets::SecretError;
+use std::sync::Arc;
+
+use super::nep6::{self, Nep6Account, Nep6Wallet, NEP6_VERSION};
+use super::storage::ManagedAccountStorage;
+use super::types::{
+    ManagedAccount, SkippedAccount, WalletExportRequest, WalletImportRequest, WalletImportResult,
+};
+use crate::signer;
+use crate::Error;
+
+/// Namespace of the account keys in the secret service, in place of a function ID
+pub const KEY_NAMESPACE: &str = "neo-wallet";
+
+/// Most accounts a wallet may hold to be imported
+pub const MAX_WALLET_ACCOUNTS: usize = 100;
+
+/// Import and export of NEP-6 wallets into managed accounts.
+///
+/// Account keys are decrypted with the wallet password on import and stored in the secret
+/// service encrypted with the master key; the password itself is never stored. Exports
+/// encrypt the keys again with a password of the caller's choosing.
+pub struct WalletService {
+    /// Storage
+    storage: Arc<dyn ManagedAccountStorage>,
+    /// Secret service holding the account keys
+    secret_service: Arc<dyn SecretService>,
+    /// Key encrypting the account keys
+    master_key: [u8; 32],
+}
+
+fn secret_error(e: SecretError) -> Error {
+    match e {
+        SecretError::NotFound(msg) => Error::NotFound(msg),
+        e => Error::Storage(format!("Secret service error: {}", e)),
+    }
+}
+
+fn skipped(address: &str, reason: &str) -> SkippedAccount {
+    SkippedAccount {
+        address: address.to_string(),
+        reason: reason.to_string(),
+    }
+}
+
+impl WalletService {
+    /// Create a new wallet service
+    pub fn new(
+        storage: Arc<dyn ManagedAccountStorage>,
+        secret_service: Arc<dyn SecretService>,
+        master_key: [u8; 32],
+    ) -> Self {
+        Self {
+            storage,
+            secret_service,
+            master_key,
+        }
+    }
+
+    /// Import the accounts of a wallet. Nothing is imported unless the password opens the
+    /// keys of all of them.
+    pub async fn import(&self, request: WalletImportRequest) -> Result<WalletImportResult, Error> {
+        let wallet = request.wallet;
+        wallet.scrypt.validate()?;
+        if wallet.accounts.len() > MAX_WALLET_ACCOUNTS {
+            return Err(Error::InvalidParameter(format!(
+                "Wallet holds {} accounts, at most {} are imported at a time",
+                wallet.accounts.len(),
+                MAX_WALLET_ACCOUNTS
+            )));
+        }
+
+        let mut result = WalletImportResult::default();
+        let mut pending: Vec<(Nep6Account, String)> = Vec::new();
+        for account in wallet.accounts {
+            let Some(key) = account.key.clone() else {
+                result.skipped.push(skipped(
+                    &account.address,
+                    "Watch-only account, the wallet holds no key",
+                ));
+                continue;
+            };
+            let imported = pending.iter().any(|(a, _)| a.address == account.address)
+                || self
+                    .storage
+                    .get_account(&request.owner, &account.address)
+                    .await?
+                    .is_some();
+            if imported {
+                result
+                    .skipped
+                    .push(skipped(&account.address, "Already imported"));
+                continue;
+            }
+            pending.push((account, key));
+        }
+
+        // scrypt is slow by design, keep it off the async workers
+        let password = request.password;
+        let params = wallet.scrypt;
+        let decrypted = tokio::task::spawn_blocking(move || {
+            pending
+                .into_iter()
+                .map(|(account, key)| {
+                    let private_key =
+                        nep6::nep2_decrypt(&key, &password, &params).map_err(|e| match e {
+                            Error::InvalidParameter(msg) => Error::InvalidParameter(format!(
+                                "Account {}: {}",
+                                account.address, msg
+                            )),
+                            e => e,
+                        })?;
+                    Ok((account, private_key))
+                })
+                .collect::<Result<Vec<_>, Error>>()
+        })
+        .await
+        .map_err(|e| Error::InternalError(format!("Wallet decryption failed: {}", e)))??;
+
+        let now = chrono::Utc::now().timestamp() as u64;
+        for (account, private_key) in decrypted {
+            let public_key = nep6::public_key(&private_key)?;
+            // The key of a multi-signature account has an address of its own
+            if signer::address_from_public_key(&public_key)? != account.address {
+                result.skipped.push(skipped(
+                    &account.address,
+                    "Not a single-signature account of its key",
+                ));
+                continue;
+            }
+
+            // Key first, so that an account is never listed without one
+            self.secret_service
+                .store_secret(
+                    &request.owner,
+                    KEY_NAMESPACE,
+                    &account.address,
+                    &private_key,
+                    &self.master_key,
+                )
+                .await
+                .map_err(secret_error)?;
+
+            let managed = ManagedAccount {
+                owner: request.owner.clone(),
+                address: account.address,
+                label: account.label,
+                public_key: hex::encode(&public_key),
+                is_default: account.is_default,
+                imported_at: now,
+            };
+            self.storage.put_account(managed.clone()).await?;
+            result.imported.push(managed);
+        }
+
+        info!(
+            "Imported {} wallet accounts for {}, skipped {}",
+            result.imported.len(),
+            request.owner,
+            result.skipped.len()
+        );
+        Ok(result)
+    }
+
+    /// Export managed accounts as a NEP-6 wallet, their keys encrypted with the password of
+    /// the request
+    pub async fn export(&self, request: WalletExportRequest) -> Result<Nep6Wallet, Error> {
+        if request.password.is_empty() {
+            return Err(Error::InvalidParameter(
+                "A password is required to encrypt the exported keys".to_string(),
+            ));
+        }
+        let params = request.scrypt.unwrap_or_default();
+        params.validate()?;
+
+        let accounts = self.storage.list_accounts(&request.owner).await?;
+        let accounts = match &request.addresses {
+            Some(addresses) => addresses
+                .iter()
+                .map(|address| {
+                    accounts
+                        .iter()
+                        .find(|account| &account.address == address)
+                        .cloned()
+                        .ok_or_else(|| {
+                            Error::NotFound(format!("Managed account not found: {}", address))
+                        })
+                })
+                .collect::<Result<Vec<_>, Error>>()?,
+            None => accounts,
+        };
+
+        let mut keys = Vec::with_capacity(accounts.len());
+        for account in accounts {
+            let private_key = self
+                .secret_service
+                .get_secret(
+                    &request.owner,
+                    KEY_NAMESPACE,
+                    &account.address,
+                    &self.master_key,
+                )
+                .await
+                .map_err(secret_error)?;
+            keys.push((account, private_key));
+        }
+
+        let password = request.password;
+        let exported = tokio::task::spawn_blocking(move || {
+            keys.iter()
+                .map(|(account, private_key)| {
+                    Nep6Account::single_sig(
+                        private_key,
+                        &password,
+                        &params,
+                        account.label.clone(),
+                        account.is_default,
+                    )
+                })
+                .collect::<Result<Vec<_>, Error>>()
+        })
+        .await
+        .map_err(|e| Error::InternalError(format!("Wallet encryption failed: {}", e)))??;
+
+        info!(
+            "Exported {} wallet accounts of {}",
+            exported.len(),
+            request.owner
+        );
+        Ok(Nep6Wallet {
+            name: None,
+            version: NEP6_VERSION.to_string(),
+            scrypt: params,
+            accounts: exported,
+            extra: None,
+        })
+    }
+
+    /// List the managed accounts of an owner, oldest first
+    pub async fn list_accounts(&self, owner: &str) -> Result<Vec<ManagedAccount>, Error> {
+        self.storage.list_accounts(owner).await
+    }
+}
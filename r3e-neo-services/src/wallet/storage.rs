@@ -0,0 +1,76 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+use async_trait::async_trait;
+use std::collections::HashMap;
+use tokio::sync::RwLock;
+
+use super::types::ManagedAccount;
+use crate::Error;
+
+/// Storage of managed accounts; their keys are held by the secret service
+#[async_trait]
+pub trait ManagedAccountStorage: Send + Sync {
+    /// Get an account of an owner
+    async fn get_account(
+        &self,
+        owner: &str,
+        address: &str,
+    ) -> Result<Option<ManagedAccount>, Error>;
+
+    /// Create or update an account
+    async fn put_account(&self, account: ManagedAccount) -> Result<(), Error>;
+
+    /// List the accounts of an owner, oldest first
+    async fn list_accounts(&self, owner: &str) -> Result<Vec<ManagedAccount>, Error>;
+}
+
+/// In-memory managed account storage
+#[derive(Default)]
+pub struct MemoryManagedAccountStorage {
+    accounts: RwLock<HashMap<(String, String), ManagedAccount>>,
+}
+
+impl MemoryManagedAccountStorage {
+    /// Create a new in-memory managed account storage
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl ManagedAccountStorage for MemoryManagedAccountStorage {
+    async fn get_account(
+        &self,
+        owner: &str,
+        address: &str,
+    ) -> Result<Option<ManagedAccount>, Error> {
+        Ok(self
+            .accounts
+            .read()
+            .await
+            .get(&(owner.to_string(), address.to_string()))
+            .cloned())
+    }
+
+    async fn put_account(&self, account: ManagedAccount) -> Result<(), Error> {
+        self.accounts
+            .write()
+            .await
+            .insert((account.owner.clone(), account.address.clone()), account);
+        Ok(())
+    }
+
+    async fn list_accounts(&self, owner: &str) -> Result<Vec<ManagedAccount>, Error> {
+        let mut accounts: Vec<ManagedAccount> = self
+            .accounts
+            .read()
+            .await
+            .values()
+            .filter(|account| account.owner == owner)
+            .cloned()
+            .collect();
+        accounts.sort_by_key(|account| account.imported_at);
+        Ok(accounts)
+    }
+}
@@ -0,0 +1,80 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+use serde::{Deserialize, Serialize};
+
+use super::nep6::{Nep6Wallet, ScryptParams};
+
+/// Account whose key was imported from a wallet and is held by the platform
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ManagedAccount {
+    /// Owner of the account
+    pub owner: String,
+
+    /// Neo N3 address
+    pub address: String,
+
+    /// Label from the wallet
+    #[serde(default)]
+    pub label: Option<String>,
+
+    /// Hex encoded compressed public key
+    pub public_key: String,
+
+    /// Whether the account was the default one of its wallet
+    pub is_default: bool,
+
+    /// Imported at
+    pub imported_at: u64,
+}
+
+/// Request to import the accounts of a NEP-6 wallet
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WalletImportRequest {
+    /// Owner the accounts are imported for
+    pub owner: String,
+
+    /// Wallet file
+    pub wallet: Nep6Wallet,
+
+    /// Password the account keys are encrypted with
+    pub password: String,
+}
+
+/// Account of a wallet that was not imported
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SkippedAccount {
+    /// Address
+    pub address: String,
+
+    /// Reason the account was skipped
+    pub reason: String,
+}
+
+/// Result of a wallet import
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct WalletImportResult {
+    /// Accounts imported
+    pub imported: Vec<ManagedAccount>,
+
+    /// Accounts skipped, as watch-only, multi-signature or already imported
+    pub skipped: Vec<SkippedAccount>,
+}
+
+/// Request to export managed accounts as a NEP-6 wallet
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WalletExportRequest {
+    /// Owner of the accounts
+    pub owner: String,
+
+    /// Accounts to export, all of the owner's if not set
+    #[serde(default)]
+    pub addresses: Option<Vec<String>>,
+
+    /// Password to encrypt the account keys with
+    pub password: String,
+
+    /// scrypt parameters of the account keys, those of NEP-2 if not set
+    #[serde(default)]
+    pub scrypt: Option<ScryptParams>,
+}
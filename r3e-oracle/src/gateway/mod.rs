@@ -3,6 +3,7 @@
 
 use std::sync::Arc;
 use async_trait::async_trait;
+use r3e_core::chain::{stack_item_bytes, stack_item_integer, ChainClient, ChainError, ContractCall};
 use crate::OracleError;
 use crate::types::PriceData;
 use crate::registry::PriceIndexRegistry;
@@ -20,10 +21,19 @@ pub trait BlockchainGatewayService: Send + Sync {
     async fn get_price_data_by_symbol(&self, symbol: &str) -> Result<PriceData, OracleError>;
 }
 
+/// Network of the chains the oracle gateways write to, `mainnet` unless set in `ORACLE_NETWORK`
+pub fn oracle_network_from_env() -> String {
+    std::env::var("ORACLE_NETWORK").unwrap_or_else(|_| "mainnet".to_string())
+}
+
+fn chain_error(e: ChainError) -> OracleError {
+    OracleError::Provider(e.to_string())
+}
+
 /// Neo N3 blockchain gateway service implementation
 pub struct NeoBlockchainGatewayService {
-    /// RPC client
-    rpc_client: Arc<reqwest::Client>,
+    /// Neo chain client
+    chain: Arc<dyn ChainClient>,
     
     /// Oracle wallet
     wallet_address: String,
@@ -38,13 +48,13 @@ pub struct NeoBlockchainGatewayService {
 impl NeoBlockchainGatewayService {
     /// Create a new Neo blockchain gateway service
     pub fn new(
-        rpc_client: Arc<reqwest::Client>,
+        chain: Arc<dyn ChainClient>,
         wallet_address: String,
         gateway_contract_hash: String,
         index_registry: Arc<PriceIndexRegistry>,
     ) -> Self {
         Self {
-            rpc_client,
+            chain,
             wallet_address,
             gateway_contract_hash,
             index_registry,
@@ -64,12 +74,8 @@ impl BlockchainGatewayService for NeoBlockchainGatewayService {
         // Convert price to integer (multiply by 10^8 for precision)
         let price_int = (price_data.price_usd * 100_000_000.0) as u64;
         
-        // Create transaction to call the gateway contract using NeoRust SDK
-        let rpc_client = self.rpc_client.clone();
-        
-        // Create a Neo RPC client for blockchain interaction
-        let url = "http://seed1.neo.org:10332"; // Use appropriate RPC endpoint
-        let neo_client = neo3::prelude::JsonRpcClient::new(url)?;
+        // Get the current height to bound the validity of the transaction
+        let block_count = self.chain.block_number().await.map_err(chain_error)? + 1;
         
         // Load wallet from private key (in production, this would be securely stored)
         let wallet_account = neo3::prelude::Account::from_wif(&std::env::var("NEO_ORACLE_PRIVATE_KEY")
@@ -96,11 +102,15 @@ impl BlockchainGatewayService for NeoBlockchainGatewayService {
         let transaction = neo3::prelude::TransactionBuilder::new()
             .script(script)
             .gas_limit(20_000_000)
-            .valid_until_block(neo_client.get_block_count().await? + 5760)  // Valid for ~1 day
+            .valid_until_block(block_count + 5760)  // Valid for ~1 day
             .sign(&wallet_account)?;
             
-        // Send the transaction
-        let tx_hash = neo_client.send_raw_transaction(&transaction).await?;
+        // Send the transaction; resending the same signed transaction to another endpoint is safe
+        let tx_hash = self
+            .chain
+            .send_raw_tx(&transaction.to_array())
+            .await
+            .map_err(chain_error)?;
         
         // Log the update for debugging
         log::info!(
@@ -115,65 +125,44 @@ impl BlockchainGatewayService for NeoBlockchainGatewayService {
     }
     
     async fn get_price_data(&self, index: u8) -> Result<PriceData, OracleError> {
-        // Call the gateway contract to get price data using NeoRust SDK
-        // Create a Neo RPC client for blockchain interaction
-        let url = "http://seed1.neo.org:10332"; // Use appropriate RPC endpoint
-        let neo_client = neo3::prelude::JsonRpcClient::new(url)?;
-        
-        // Build the script to invoke the gateway contract read method
-        let script = neo3::prelude::ScriptBuilder::new()
-            .contract_call(
-                &self.gateway_contract_hash,
-                "getPriceData",  // Gateway contract method
-                &[neo3::prelude::ContractParameter::Integer(index as i64)],
-            )
-            .to_bytes();
-            
-        // Invoke the script in read-only mode
-        let response = neo_client.invoke_script(&script).await?;
+        // Call the gateway contract read method
+        let call = ContractCall {
+            contract: self.gateway_contract_hash.clone(),
+            method: "getPriceData".to_string(),
+            params: vec![serde_json::json!(index)],
+            ..ContractCall::default()
+        };
+        let response = self.chain.call_contract(&call).await.map_err(chain_error)?;
         
         // Parse the response
-        if response.state != "HALT" {
-            return Err(OracleError::Blockchain(format!("Invocation failed with state: {}", response.state)));
+        if !response.success {
+            return Err(OracleError::Provider(format!(
+                "Invocation failed: {}",
+                response.error.unwrap_or_default()
+            )));
         }
         
-        let stack_item = response.stack.first()
-            .ok_or_else(|| OracleError::Parsing("Empty response stack".to_string()))?;
-            
-        // Parse the price data from the stack item
-        // Assuming the contract returns an array with [symbol, price, timestamp]
-        let array = match stack_item {
-            neo3::prelude::StackItem::Array(arr) => arr,
-            _ => return Err(OracleError::Parsing("Expected array response".to_string())),
-        };
-        
-        if array.len() < 3 {
-            return Err(OracleError::Parsing("Invalid price data response".to_string()));
-        }
+        // The contract returns an array with [symbol, price, timestamp]
+        let array = response.result[0]["value"]
+            .as_array()
+            .filter(|array| array.len() >= 3)
+            .ok_or_else(|| OracleError::Provider("Invalid price data response".to_string()))?;
         
         // Extract symbol
-        let symbol = match &array[0] {
-            neo3::prelude::StackItem::ByteString(bytes) => String::from_utf8(bytes.clone())
-                .map_err(|e| OracleError::Parsing(format!("Invalid symbol: {}", e)))?,
-            _ => return Err(OracleError::Parsing("Expected string for symbol".to_string())),
-        };
+        let symbol = stack_item_bytes(&array[0])
+            .and_then(|bytes| String::from_utf8(bytes).ok())
+            .ok_or_else(|| OracleError::Provider("Expected string for symbol".to_string()))?;
         
         // Extract price (stored as integer with 8 decimal places)
-        let price_int = match &array[1] {
-            neo3::prelude::StackItem::Integer(val) => val.as_i64()
-                .map_err(|e| OracleError::Parsing(format!("Invalid price: {}", e)))?,
-            _ => return Err(OracleError::Parsing("Expected integer for price".to_string())),
-        };
+        let price_int = stack_item_integer(&array[1])
+            .ok_or_else(|| OracleError::Provider("Expected integer for price".to_string()))?;
         
         // Convert price back to float
         let price_usd = (price_int as f64) / 100_000_000.0;
         
         // Extract timestamp
-        let timestamp = match &array[2] {
-            neo3::prelude::StackItem::Integer(val) => val.as_i64()
-                .map_err(|e| OracleError::Parsing(format!("Invalid timestamp: {}", e)))?,
-            _ => return Err(OracleError::Parsing("Expected integer for timestamp".to_string())),
-        };
+        let timestamp = stack_item_integer(&array[2])
+            .ok_or_else(|| OracleError::Provider("Expected integer for timestamp".to_string()))?;
         
         // Get symbol from index registry
         let symbol_from_registry = self.index_registry.get_symbol(index).await?;
@@ -223,8 +212,8 @@ impl BlockchainGatewayService for NeoBlockchainGatewayService {
 
 /// Ethereum blockchain gateway service implementation
 pub struct EthereumBlockchainGatewayService {
-    /// RPC client
-    rpc_client: Arc<reqwest::Client>,
+    /// Ethereum chain client
+    chain: Arc<dyn ChainClient>,
     
     /// Oracle wallet
     wallet_address: String,
@@ -239,23 +228,47 @@ pub struct EthereumBlockchainGatewayService {
 impl EthereumBlockchainGatewayService {
     /// Create a new Ethereum blockchain gateway service
     pub fn new(
-        rpc_client: Arc<reqwest::Client>,
+        chain: Arc<dyn ChainClient>,
         wallet_address: String,
         gateway_contract_address: String,
         index_registry: Arc<PriceIndexRegistry>,
     ) -> Self {
         Self {
-            rpc_client,
+            chain,
             wallet_address,
             gateway_contract_address,
             index_registry,
         }
     }
+    
+    /// Send a JSON-RPC request returning a quantity
+    async fn quantity(
+        &self,
+        method: &str,
+        params: serde_json::Value,
+    ) -> Result<ethers::types::U256, OracleError> {
+        let value = self.chain.request(method, params).await.map_err(chain_error)?;
+        value
+            .as_str()
+            .and_then(|quantity| ethers::types::U256::from_str_radix(quantity.trim_start_matches("0x"), 16).ok())
+            .ok_or_else(|| OracleError::Provider(format!("Invalid {} response: {}", method, value)))
+    }
+}
+
+/// ABI encoded call of a function with its canonical signature
+fn eth_calldata(signature: &str, tokens: &[ethers::abi::Token]) -> Vec<u8> {
+    let mut data = ethers::utils::id(signature).to_vec();
+    data.extend(ethers::abi::encode(tokens));
+    data
 }
 
 #[async_trait]
 impl BlockchainGatewayService for EthereumBlockchainGatewayService {
     async fn update_price_data(&self, price_data: &PriceData) -> Result<String, OracleError> {
+        use ethers::abi::Token;
+        use ethers::signers::{LocalWallet, Signer};
+        use ethers::types::{transaction::eip2718::TypedTransaction, Address, TransactionRequest, U256};
+        
         // Check if price data has an index
         let index = match price_data.index {
             Some(idx) => idx,
@@ -265,59 +278,65 @@ impl BlockchainGatewayService for EthereumBlockchainGatewayService {
         // Convert price to integer (multiply by 10^8 for precision)
         let price_int = (price_data.price_usd * 100_000_000.0) as u64;
         
-        // Import necessary ethers crates
-        use ethers::prelude::*;
-        use ethers::providers::{Http, Provider};
-        use ethers::signers::{LocalWallet, Signer};
-        use ethers::contract::abigen;
-        
-        // Generate contract bindings
-        // Define the ABI for the gateway contract - this is simplified, adjust based on actual contract
-        abigen!(
-            GatewayContract,
-            r#"[
-                function updatePriceData(uint8 index, string symbol, uint256 price, uint256 timestamp) external returns (bool)
-                function getPriceData(uint8 index) external view returns (string symbol, uint256 price, uint256 timestamp)
-            ]"#
-        );
-        
-        // Set up provider and wallet
-        let provider_url = std::env::var("ETH_RPC_URL")
-            .map_err(|_| OracleError::Configuration("ETH_RPC_URL environment variable not set".to_string()))?;
-            
-        let provider = Provider::<Http>::try_from(provider_url)
-            .map_err(|e| OracleError::Blockchain(format!("Failed to create Ethereum provider: {}", e)))?;
-            
-        let chain_id = provider.get_chainid().await
-            .map_err(|e| OracleError::Blockchain(format!("Failed to get chain ID: {}", e)))?
-            .as_u64();
-            
+        // Set up the wallet
         let private_key = std::env::var("ETH_ORACLE_PRIVATE_KEY")
-            .map_err(|_| OracleError::Configuration("ETH_ORACLE_PRIVATE_KEY environment variable not set".to_string()))?;
+            .map_err(|_| OracleError::Validation("ETH_ORACLE_PRIVATE_KEY environment variable not set".to_string()))?;
             
         let wallet = private_key.parse::<LocalWallet>()
-            .map_err(|e| OracleError::Configuration(format!("Invalid private key: {}", e)))?
-            .with_chain_id(chain_id);
-            
-        let client = SignerMiddleware::new(provider, wallet);
+            .map_err(|e| OracleError::Validation(format!("Invalid private key: {}", e)))?;
         
-        // Create contract instance
         let contract_address: Address = self.gateway_contract_address.parse()
-            .map_err(|e| OracleError::Configuration(format!("Invalid contract address: {}", e)))?;
-            
-        let contract = GatewayContract::new(contract_address, Arc::new(client));
-        
-        // Call the updatePriceData function
-        let tx = contract.update_price_data(
-            index, 
-            price_data.symbol.clone(), 
-            U256::from(price_int), 
-            U256::from(price_data.timestamp)
-        ).send().await
-        .map_err(|e| OracleError::Blockchain(format!("Failed to send transaction: {}", e)))?;
+            .map_err(|e| OracleError::Validation(format!("Invalid contract address: {}", e)))?;
+        
+        // Encode the updatePriceData call
+        let data = eth_calldata(
+            "updatePriceData(uint8,string,uint256,uint256)",
+            &[
+                Token::Uint(U256::from(index)),
+                Token::String(price_data.symbol.clone()),
+                Token::Uint(U256::from(price_int)),
+                Token::Uint(U256::from(price_data.timestamp)),
+            ],
+        );
         
-        // Get transaction hash
-        let tx_hash = format!("{:?}", tx.tx_hash());
+        // Fill in the transaction from the chain state
+        let from = format!("{:?}", wallet.address());
+        let chain_id = self.quantity("eth_chainId", serde_json::json!([])).await?;
+        let nonce = self
+            .quantity("eth_getTransactionCount", serde_json::json!([from, "pending"]))
+            .await?;
+        let gas_price = self.quantity("eth_gasPrice", serde_json::json!([])).await?;
+        let gas = self
+            .quantity(
+                "eth_estimateGas",
+                serde_json::json!([{
+                    "from": from,
+                    "to": self.gateway_contract_address,
+                    "data": format!("0x{}", hex::encode(&data)),
+                }]),
+            )
+            .await?;
+        
+        // Sign the transaction locally and broadcast it.
+        // A retry after a lost response resends the same signed transaction, which is harmless.
+        let wallet = wallet.with_chain_id(chain_id.as_u64());
+        let tx: TypedTransaction = TransactionRequest::new()
+            .from(wallet.address())
+            .to(contract_address)
+            .data(data)
+            .nonce(nonce)
+            .gas(gas)
+            .gas_price(gas_price)
+            .chain_id(chain_id.as_u64())
+            .into();
+        let signature = wallet
+            .sign_transaction_sync(&tx)
+            .map_err(|e| OracleError::Signature(format!("Failed to sign transaction: {}", e)))?;
+        let tx_hash = self
+            .chain
+            .send_raw_tx(&tx.rlp_signed(&signature))
+            .await
+            .map_err(chain_error)?;
         
         // Log the update for debugging
         log::info!(
@@ -332,36 +351,41 @@ impl BlockchainGatewayService for EthereumBlockchainGatewayService {
     }
     
     async fn get_price_data(&self, index: u8) -> Result<PriceData, OracleError> {
-        // Import necessary ethers crates
-        use ethers::prelude::*;
-        use ethers::providers::{Http, Provider};
-        use ethers::contract::abigen;
-        
-        // Generate contract bindings
-        abigen!(
-            GatewayContract,
-            r#"[
-                function updatePriceData(uint8 index, string symbol, uint256 price, uint256 timestamp) external returns (bool)
-                function getPriceData(uint8 index) external view returns (string symbol, uint256 price, uint256 timestamp)
-            ]"#
-        );
-        
-        // Set up provider
-        let provider_url = std::env::var("ETH_RPC_URL")
-            .map_err(|_| OracleError::Configuration("ETH_RPC_URL environment variable not set".to_string()))?;
-            
-        let provider = Provider::<Http>::try_from(provider_url)
-            .map_err(|e| OracleError::Blockchain(format!("Failed to create Ethereum provider: {}", e)))?;
-            
-        // Create contract instance
-        let contract_address: Address = self.gateway_contract_address.parse()
-            .map_err(|e| OracleError::Configuration(format!("Invalid contract address: {}", e)))?;
-            
-        let contract = GatewayContract::new(contract_address, Arc::new(provider));
+        use ethers::abi::{ParamType, Token};
+        use ethers::types::U256;
         
         // Call the getPriceData function
-        let (symbol, price, timestamp) = contract.get_price_data(index).call().await
-            .map_err(|e| OracleError::Blockchain(format!("Failed to call contract: {}", e)))?;
+        let call = ContractCall {
+            contract: self.gateway_contract_address.clone(),
+            method: "getPriceData".to_string(),
+            data: Some(eth_calldata("getPriceData(uint8)", &[Token::Uint(U256::from(index))])),
+            ..ContractCall::default()
+        };
+        let response = self.chain.call_contract(&call).await.map_err(chain_error)?;
+        if !response.success {
+            return Err(OracleError::Provider(format!(
+                "Failed to call contract: {}",
+                response.error.unwrap_or_default()
+            )));
+        }
+        
+        // Decode (string symbol, uint256 price, uint256 timestamp)
+        let output = response
+            .result
+            .as_str()
+            .and_then(|output| hex::decode(output.trim_start_matches("0x")).ok())
+            .ok_or_else(|| OracleError::Provider("Invalid contract response".to_string()))?;
+        let tokens = ethers::abi::decode(
+            &[ParamType::String, ParamType::Uint(256), ParamType::Uint(256)],
+            &output,
+        )
+        .map_err(|e| OracleError::Provider(format!("Failed to decode contract response: {}", e)))?;
+        let (symbol, price, timestamp) = match tokens.as_slice() {
+            [Token::String(symbol), Token::Uint(price), Token::Uint(timestamp)] => {
+                (symbol.clone(), *price, *timestamp)
+            }
+            _ => return Err(OracleError::Provider("Unexpected contract response".to_string())),
+        };
             
         // Convert price from uint256 to f64 (divide by 10^8 for precision)
         let price_usd = price.as_u128() as f64 / 100_000_000.0;
@@ -8,6 +8,8 @@ use thiserror::Error;
 pub mod auth;
 pub mod provider;
 pub mod service;
+pub mod signing;
+pub mod simulated;
 pub mod types;
 
 /// Oracle service error types
@@ -28,6 +30,9 @@ pub enum OracleError {
     #[error("validation error: {0}")]
     Validation(String),
 
+    #[error("signature error: {0}")]
+    Signature(String),
+
     #[error("timeout error: {0}")]
     Timeout(String),
 
@@ -96,6 +101,10 @@ pub struct OracleResponse {
 
     /// Error message (if any)
     pub error: Option<String>,
+
+    /// Signature of the oracle key, if responses are signed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<signing::ResponseSignature>,
 }
 
 /// Oracle service trait
@@ -104,6 +113,9 @@ pub trait OracleService: Send + Sync {
     /// Submit a new oracle request
     async fn submit_request(&self, request: OracleRequest) -> Result<String, OracleError>;
 
+    /// Get an oracle request
+    async fn get_request(&self, request_id: &str) -> Result<OracleRequest, OracleError>;
+
     /// Get the status of an oracle request
     async fn get_request_status(
         &self,
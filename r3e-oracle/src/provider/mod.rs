@@ -2,7 +2,10 @@
 // All Rights Reserved
 
 pub mod price;
+pub mod price_cache;
 pub mod random;
+pub mod sports;
+pub mod weather;
 
 use std::collections::HashMap;
 use std::sync::Arc;
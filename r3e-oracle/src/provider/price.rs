@@ -1,46 +1,75 @@
 // Copyright @ 2023 - 2024, R3E Network
 // All Rights Reserved
 
-use std::collections::HashMap;
 use std::sync::Arc;
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use async_trait::async_trait;
+use r3e_core::egress::EgressGuard;
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
-use tokio::sync::RwLock;
 
+use super::price_cache::{CacheLookup, PriceCache, PriceCacheConfig};
 use crate::registry::PriceIndexRegistry;
 use crate::types::{PriceData, PriceRequest, PriceResponse};
 use crate::{OracleError, OracleProvider, OracleRequest, OracleRequestType, OracleResponse};
 
 /// Price feed provider for cryptocurrency price data
+#[derive(Clone)]
 pub struct PriceProvider {
-    /// HTTP client for API requests
-    client: Client,
-
     /// Cache for price data
-    cache: Arc<RwLock<HashMap<String, PriceData>>>,
-
-    /// Cache expiration time in seconds
-    cache_expiration: u64,
+    cache: Arc<PriceCache>,
 
     /// Price index registry
     index_registry: Arc<PriceIndexRegistry>,
+
+    /// Egress guard for API requests
+    egress_guard: EgressGuard,
 }
 
 impl PriceProvider {
-    /// Create a new price provider
+    /// Create a new price provider, prices staying fresh for `cache_expiration` seconds
     pub fn new(cache_expiration: u64, index_registry: Arc<PriceIndexRegistry>) -> Self {
+        let config = PriceCacheConfig {
+            default_sla: cache_expiration,
+            ..PriceCacheConfig::default()
+        };
+
         Self {
-            client: Client::new(),
-            cache: Arc::new(RwLock::new(HashMap::new())),
-            cache_expiration,
+            cache: Arc::new(PriceCache::new(config)),
             index_registry,
+            egress_guard: EgressGuard::new(),
         }
     }
 
+    /// Share a price cache, e.g. to configure per-symbol SLAs or read its metrics
+    pub fn with_cache(mut self, cache: Arc<PriceCache>) -> Self {
+        self.cache = cache;
+        self
+    }
+
+    /// Price cache of the provider
+    pub fn cache(&self) -> Arc<PriceCache> {
+        Arc::clone(&self.cache)
+    }
+
+    /// HTTP client for an API URL, refusing internal destinations and pinning the connection
+    /// to the checked addresses
+    async fn client_for(&self, url: &str) -> Result<Client, OracleError> {
+        let resolved = self
+            .egress_guard
+            .check_url(url)
+            .await
+            .map_err(|e| OracleError::Provider(e.to_string()))?;
+
+        Client::builder()
+            .resolve_to_addrs(&resolved.host, &resolved.addrs)
+            .redirect(reqwest::redirect::Policy::none())
+            .build()
+            .map_err(|e| OracleError::Internal(format!("Failed to create HTTP client: {}", e)))
+    }
+
     /// Get price data from CoinGecko API
     async fn get_price_from_coingecko(&self, symbol: &str) -> Result<PriceData, OracleError> {
         let url = format!(
@@ -48,8 +77,9 @@ impl PriceProvider {
             symbol.to_lowercase()
         );
 
+        let client = self.client_for(&url).await?;
         let response =
-            self.client.get(&url).send().await.map_err(|e| {
+            client.get(&url).send().await.map_err(|e| {
                 OracleError::Provider(format!("CoinGecko API request failed: {}", e))
             })?;
 
@@ -100,8 +130,8 @@ impl PriceProvider {
             binance_symbol
         );
 
-        let response = self
-            .client
+        let client = self.client_for(&url).await?;
+        let response = client
             .get(&url)
             .send()
             .await
@@ -149,29 +179,56 @@ impl PriceProvider {
         })
     }
 
-    /// Get price data from cache or fetch from APIs
+    /// Get price data from cache or fetch from APIs, returning the age of the prices in seconds
     async fn get_price(
         &self,
         symbol: &str,
         sources: &[String],
-    ) -> Result<Vec<PriceData>, OracleError> {
-        let mut prices = Vec::new();
-
-        // Check cache first
-        let cache = self.cache.read().await;
-        if let Some(cached_data) = cache.get(symbol) {
-            let now = SystemTime::now()
-                .duration_since(UNIX_EPOCH)
-                .unwrap_or_default()
-                .as_secs();
-
-            // Use cached data if it's still valid
-            if now - cached_data.timestamp < self.cache_expiration {
-                prices.push(cached_data.clone());
-                return Ok(prices);
+        max_age: Option<u64>,
+    ) -> Result<(Vec<PriceData>, u64), OracleError> {
+        match self.cache.lookup(symbol, sources, max_age) {
+            CacheLookup::Hit {
+                prices,
+                age,
+                refresh,
+            } => {
+                if refresh {
+                    self.spawn_refresh(symbol, sources);
+                }
+                Ok((prices, age))
+            }
+            CacheLookup::Miss => {
+                let prices = self.fetch_prices(symbol, sources).await?;
+                self.cache.store(symbol, sources, prices.clone());
+                Ok((prices, 0))
             }
         }
-        drop(cache);
+    }
+
+    /// Replace stale cached prices without holding up the lookup serving them
+    fn spawn_refresh(&self, symbol: &str, sources: &[String]) {
+        let provider = self.clone();
+        let symbol = symbol.to_string();
+        let sources = sources.to_vec();
+
+        tokio::spawn(async move {
+            match provider.fetch_prices(&symbol, &sources).await {
+                Ok(prices) => provider.cache.store(&symbol, &sources, prices),
+                Err(e) => {
+                    log::warn!("Failed to refresh cached price of {}: {}", symbol, e);
+                    provider.cache.refresh_failed(&symbol, &sources);
+                }
+            }
+        });
+    }
+
+    /// Fetch price data from the APIs
+    async fn fetch_prices(
+        &self,
+        symbol: &str,
+        sources: &[String],
+    ) -> Result<Vec<PriceData>, OracleError> {
+        let mut prices = Vec::new();
 
         // Fetch from APIs
         let mut fetch_sources = Vec::new();
@@ -186,36 +243,22 @@ impl PriceProvider {
 
         for source in fetch_sources {
             match source.as_str() {
-                "coingecko" => {
-                    match self.get_price_from_coingecko(symbol).await {
-                        Ok(price_data) => {
-                            // Update cache
-                            self.cache
-                                .write()
-                                .await
-                                .insert(symbol.to_string(), price_data.clone());
-                            prices.push(price_data);
-                        }
-                        Err(e) => {
-                            log::warn!("Failed to get price from CoinGecko: {}", e);
-                        }
+                "coingecko" => match self.get_price_from_coingecko(symbol).await {
+                    Ok(price_data) => {
+                        prices.push(price_data);
                     }
-                }
-                "binance" => {
-                    match self.get_price_from_binance(symbol).await {
-                        Ok(price_data) => {
-                            // Update cache
-                            self.cache
-                                .write()
-                                .await
-                                .insert(symbol.to_string(), price_data.clone());
-                            prices.push(price_data);
-                        }
-                        Err(e) => {
-                            log::warn!("Failed to get price from Binance: {}", e);
-                        }
+                    Err(e) => {
+                        log::warn!("Failed to get price from CoinGecko: {}", e);
                     }
-                }
+                },
+                "binance" => match self.get_price_from_binance(symbol).await {
+                    Ok(price_data) => {
+                        prices.push(price_data);
+                    }
+                    Err(e) => {
+                        log::warn!("Failed to get price from Binance: {}", e);
+                    }
+                },
                 _ => {
                     log::warn!("Unsupported price source: {}", source);
                 }
@@ -263,8 +306,12 @@ impl OracleProvider for PriceProvider {
             .map_err(|e| OracleError::Validation(format!("Invalid price request data: {}", e)))?;
 
         // Get price data
-        let prices = self
-            .get_price(&price_request.symbol, &price_request.sources)
+        let (prices, age) = self
+            .get_price(
+                &price_request.symbol,
+                &price_request.sources,
+                price_request.max_age,
+            )
             .await?;
 
         // Calculate average price
@@ -277,6 +324,7 @@ impl OracleProvider for PriceProvider {
             currency: price_request.currency,
             price: avg_price,
             sources: prices.iter().map(|p| p.source.clone()).collect(),
+            age,
             timestamp: SystemTime::now()
                 .duration_since(UNIX_EPOCH)
                 .unwrap_or_default()
@@ -295,6 +343,7 @@ impl OracleProvider for PriceProvider {
                 .unwrap_or_default()
                 .as_secs(),
             error: None,
+            signature: None,
         })
     }
 }
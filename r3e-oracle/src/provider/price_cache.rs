@@ -0,0 +1,350 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::RwLock;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use crate::types::PriceData;
+
+/// Freshness SLAs of cached prices
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PriceCacheConfig {
+    /// Seconds a price stays fresh, unless its symbol has its own SLA
+    pub default_sla: u64,
+
+    /// Seconds past its SLA a price is still served while it is refreshed
+    pub stale_window: u64,
+
+    /// Freshness SLAs in seconds by symbol
+    pub symbol_slas: HashMap<String, u64>,
+}
+
+impl Default for PriceCacheConfig {
+    fn default() -> Self {
+        Self {
+            default_sla: 60,
+            stale_window: 300,
+            symbol_slas: HashMap::new(),
+        }
+    }
+}
+
+impl PriceCacheConfig {
+    /// Freshness SLA of a symbol
+    pub fn sla(&self, symbol: &str) -> Duration {
+        let secs = self
+            .symbol_slas
+            .get(&symbol.to_uppercase())
+            .copied()
+            .unwrap_or(self.default_sla);
+        Duration::from_secs(secs)
+    }
+}
+
+/// Price cache counters since the cache was created
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PriceCacheMetrics {
+    /// Lookups served prices within their SLA
+    pub hits: u64,
+
+    /// Lookups served prices past their SLA, refreshed in the background
+    pub stale_hits: u64,
+
+    /// Lookups that had to fetch prices from the sources
+    pub misses: u64,
+
+    /// Background refreshes started
+    pub refreshes: u64,
+
+    /// Background refreshes that failed, the stale prices are kept
+    pub refresh_failures: u64,
+
+    /// Cached symbol and source combinations
+    pub entries: usize,
+}
+
+/// Result of a price cache lookup
+#[derive(Debug, Clone)]
+pub enum CacheLookup {
+    /// Cached prices usable by the caller
+    Hit {
+        /// Cached prices
+        prices: Vec<PriceData>,
+        /// Seconds since the prices were fetched
+        age: u64,
+        /// Whether the caller should refresh the prices in the background
+        refresh: bool,
+    },
+    /// No usable prices, they must be fetched
+    Miss,
+}
+
+struct CacheEntry {
+    symbol: String,
+    prices: Vec<PriceData>,
+    fetched_at: Instant,
+    refreshing: bool,
+}
+
+/// Stale-while-revalidate cache of price lookups.
+///
+/// Prices within the SLA of their symbol are served as is. Past it they are served for
+/// another stale window while a single background refresh replaces them, unless the
+/// caller bounds their age with `max_age`.
+pub struct PriceCache {
+    config: PriceCacheConfig,
+    entries: RwLock<HashMap<String, CacheEntry>>,
+    hits: AtomicU64,
+    stale_hits: AtomicU64,
+    misses: AtomicU64,
+    refreshes: AtomicU64,
+    refresh_failures: AtomicU64,
+}
+
+impl PriceCache {
+    /// Create a new price cache
+    pub fn new(config: PriceCacheConfig) -> Self {
+        Self {
+            config,
+            entries: RwLock::new(HashMap::new()),
+            hits: AtomicU64::new(0),
+            stale_hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            refreshes: AtomicU64::new(0),
+            refresh_failures: AtomicU64::new(0),
+        }
+    }
+
+    /// Cache configuration
+    pub fn config(&self) -> &PriceCacheConfig {
+        &self.config
+    }
+
+    /// Lookups of the same symbol from different sources are cached apart
+    fn key(symbol: &str, sources: &[String]) -> String {
+        let mut sources = sources.to_vec();
+        sources.sort();
+        format!("{}:{}", symbol.to_uppercase(), sources.join(","))
+    }
+
+    /// Look up prices, `max_age` in seconds overriding the SLA of the symbol
+    pub fn lookup(&self, symbol: &str, sources: &[String], max_age: Option<u64>) -> CacheLookup {
+        let sla = self.config.sla(symbol);
+        let mut entries = self.entries.write().unwrap();
+        let entry = match entries.get_mut(&Self::key(symbol, sources)) {
+            Some(entry) => entry,
+            None => {
+                self.misses.fetch_add(1, Ordering::Relaxed);
+                return CacheLookup::Miss;
+            }
+        };
+
+        let age = entry.fetched_at.elapsed();
+        let limit = match max_age {
+            Some(max_age) => Duration::from_secs(max_age),
+            None => sla + Duration::from_secs(self.config.stale_window),
+        };
+        if age > limit {
+            self.misses.fetch_add(1, Ordering::Relaxed);
+            return CacheLookup::Miss;
+        }
+
+        let refresh = if age <= sla {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            false
+        } else {
+            // Only the first stale lookup refreshes, the others keep serving the stale prices
+            self.stale_hits.fetch_add(1, Ordering::Relaxed);
+            !std::mem::replace(&mut entry.refreshing, true)
+        };
+        if refresh {
+            self.refreshes.fetch_add(1, Ordering::Relaxed);
+        }
+
+        CacheLookup::Hit {
+            prices: entry.prices.clone(),
+            age: age.as_secs(),
+            refresh,
+        }
+    }
+
+    /// Store freshly fetched prices, dropping entries too old to be served
+    pub fn store(&self, symbol: &str, sources: &[String], prices: Vec<PriceData>) {
+        let stale_window = Duration::from_secs(self.config.stale_window);
+        let mut entries = self.entries.write().unwrap();
+        entries.retain(|_, entry| {
+            entry.fetched_at.elapsed() <= self.config.sla(&entry.symbol) + stale_window
+        });
+        entries.insert(
+            Self::key(symbol, sources),
+            CacheEntry {
+                symbol: symbol.to_uppercase(),
+                prices,
+                fetched_at: Instant::now(),
+                refreshing: false,
+            },
+        );
+    }
+
+    /// Record a failed background refresh, so the next stale lookup retries it
+    pub fn refresh_failed(&self, symbol: &str, sources: &[String]) {
+        self.refresh_failures.fetch_add(1, Ordering::Relaxed);
+        if let Some(entry) = self
+            .entries
+            .write()
+            .unwrap()
+            .get_mut(&Self::key(symbol, sources))
+        {
+            entry.refreshing = false;
+        }
+    }
+
+    /// Cache hit metrics
+    pub fn metrics(&self) -> PriceCacheMetrics {
+        PriceCacheMetrics {
+            hits: self.hits.load(Ordering::Relaxed),
+            stale_hits: self.stale_hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            refreshes: self.refreshes.load(Ordering::Relaxed),
+            refresh_failures: self.refresh_failures.load(Ordering::Relaxed),
+            entries: self.entries.read().unwrap().len(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn prices(price_usd: f64) -> Vec<PriceData> {
+        vec![PriceData {
+            symbol: "NEO".to_string(),
+            price_usd,
+            source: "binance".to_string(),
+            timestamp: 0,
+            index: Some(0),
+        }]
+    }
+
+    fn cache() -> PriceCache {
+        PriceCache::new(PriceCacheConfig {
+            default_sla: 60,
+            stale_window: 300,
+            symbol_slas: HashMap::from([("GAS".to_string(), 10)]),
+        })
+    }
+
+    /// Pretend the cached prices were fetched `secs` seconds ago
+    fn age(cache: &PriceCache, symbol: &str, sources: &[String], secs: u64) {
+        let mut entries = cache.entries.write().unwrap();
+        let entry = entries.get_mut(&PriceCache::key(symbol, sources)).unwrap();
+        entry.fetched_at = Instant::now() - Duration::from_secs(secs);
+    }
+
+    fn hit(lookup: CacheLookup) -> (f64, u64, bool) {
+        match lookup {
+            CacheLookup::Hit {
+                prices,
+                age,
+                refresh,
+            } => (prices[0].price_usd, age, refresh),
+            CacheLookup::Miss => panic!("expected a cache hit"),
+        }
+    }
+
+    #[test]
+    fn test_config() {
+        let config = cache().config().clone();
+        assert_eq!(config.sla("neo"), Duration::from_secs(60));
+        assert_eq!(config.sla("gas"), Duration::from_secs(10));
+
+        let config: PriceCacheConfig = serde_json::from_str(r#"{ "default_sla": 30 }"#).unwrap();
+        assert_eq!(config.sla("NEO"), Duration::from_secs(30));
+        assert_eq!(config.stale_window, 300);
+    }
+
+    #[test]
+    fn test_lookup() {
+        let cache = cache();
+        let sources = vec!["binance".to_string(), "coingecko".to_string()];
+        assert!(matches!(
+            cache.lookup("NEO", &sources, None),
+            CacheLookup::Miss
+        ));
+
+        cache.store("neo", &sources, prices(10.0));
+        assert_eq!(hit(cache.lookup("NEO", &sources, None)), (10.0, 0, false));
+
+        // Sources are cached apart, whatever their order
+        let reversed = vec!["coingecko".to_string(), "binance".to_string()];
+        assert_eq!(hit(cache.lookup("NEO", &reversed, None)).0, 10.0);
+        assert!(matches!(cache.lookup("NEO", &[], None), CacheLookup::Miss));
+
+        // Callers bound the age of the prices they get
+        age(&cache, "NEO", &sources, 30);
+        assert_eq!(hit(cache.lookup("NEO", &sources, None)), (10.0, 30, false));
+        assert!(matches!(
+            cache.lookup("NEO", &sources, Some(10)),
+            CacheLookup::Miss
+        ));
+        assert_eq!(hit(cache.lookup("NEO", &sources, Some(40))).1, 30);
+
+        // Past the stale window the prices are no longer served
+        age(&cache, "NEO", &sources, 361);
+        assert!(matches!(
+            cache.lookup("NEO", &sources, None),
+            CacheLookup::Miss
+        ));
+
+        // Symbols have their own SLAs
+        cache.store("GAS", &[], prices(4.0));
+        age(&cache, "GAS", &[], 20);
+        assert!(hit(cache.lookup("GAS", &[], None)).2);
+    }
+
+    #[test]
+    fn test_stale_while_revalidate() {
+        let cache = cache();
+        cache.store("NEO", &[], prices(10.0));
+        age(&cache, "NEO", &[], 120);
+
+        // Only the first stale lookup refreshes the prices
+        assert_eq!(hit(cache.lookup("NEO", &[], None)), (10.0, 120, true));
+        assert_eq!(hit(cache.lookup("NEO", &[], None)), (10.0, 120, false));
+
+        // A failed refresh keeps the stale prices and lets the next lookup retry
+        cache.refresh_failed("NEO", &[]);
+        assert!(hit(cache.lookup("NEO", &[], None)).2);
+
+        cache.store("NEO", &[], prices(11.0));
+        assert_eq!(hit(cache.lookup("NEO", &[], None)), (11.0, 0, false));
+
+        let metrics = cache.metrics();
+        assert_eq!(metrics.hits, 1);
+        assert_eq!(metrics.stale_hits, 3);
+        assert_eq!(metrics.misses, 0);
+        assert_eq!(metrics.refreshes, 2);
+        assert_eq!(metrics.refresh_failures, 1);
+        assert_eq!(metrics.entries, 1);
+    }
+
+    #[test]
+    fn test_store_prunes_expired() {
+        let cache = cache();
+        cache.store("NEO", &[], prices(10.0));
+        cache.store("GAS", &[], prices(4.0));
+        age(&cache, "NEO", &[], 200);
+        age(&cache, "GAS", &[], 311);
+
+        // GAS is past its 10 second SLA and stale window, NEO is still served
+        cache.store("BTC", &[], prices(60000.0));
+        assert_eq!(cache.metrics().entries, 2);
+        assert!(matches!(cache.lookup("GAS", &[], None), CacheLookup::Miss));
+        assert!(hit(cache.lookup("NEO", &[], None)).2);
+    }
+}
@@ -226,6 +226,7 @@ impl OracleProvider for RandomProvider {
                 .unwrap_or_default()
                 .as_secs(),
             error: None,
+            signature: None,
         })
     }
 }
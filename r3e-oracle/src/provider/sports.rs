@@ -0,0 +1,497 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+use std::collections::HashMap;
+use std::num::NonZeroU32;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use async_trait::async_trait;
+use chrono::NaiveDateTime;
+use governor::clock::DefaultClock;
+use governor::state::{InMemoryState, NotKeyed};
+use governor::{Quota, RateLimiter};
+use r3e_core::egress::EgressGuard;
+use reqwest::Client;
+use serde_json::Value;
+use tokio::sync::RwLock;
+use url::Url;
+
+use crate::types::{
+    SportsDataType, SportsEvent, SportsReport, SportsRequest, SportsResponse, Standing,
+};
+use crate::{OracleError, OracleProvider, OracleRequest, OracleRequestType, OracleResponse};
+
+/// TheSportsDB API
+const SPORTSDB_URL: &str = "https://www.thesportsdb.com/api/v1/json";
+
+/// Public TheSportsDB API key, for development
+const DEFAULT_API_KEY: &str = "3";
+
+/// Maximum length of a sport or team name
+const MAX_NAME_LENGTH: usize = 100;
+
+/// Sports provider backed by TheSportsDB
+pub struct SportsProvider {
+    /// TheSportsDB API key
+    api_key: String,
+
+    /// Cache for sports reports, with the time they were fetched
+    cache: Arc<RwLock<HashMap<String, (u64, SportsReport)>>>,
+
+    /// Cache expiration time in seconds
+    cache_expiration: u64,
+
+    /// Limits requests to TheSportsDB, cached reports are not limited
+    rate_limiter: RateLimiter<NotKeyed, InMemoryState, DefaultClock>,
+
+    /// Egress guard for API requests
+    egress_guard: EgressGuard,
+}
+
+/// Validate a sports request
+fn validate_request(request: &SportsRequest) -> Result<(), OracleError> {
+    let sport = request.sport.trim();
+    if sport.is_empty() || sport.len() > MAX_NAME_LENGTH {
+        return Err(OracleError::Validation(format!(
+            "Sport must be between 1 and {} characters",
+            MAX_NAME_LENGTH
+        )));
+    }
+    if let Some(team) = &request.team {
+        if team.trim().is_empty() || team.len() > MAX_NAME_LENGTH {
+            return Err(OracleError::Validation(format!(
+                "Team must be between 1 and {} characters",
+                MAX_NAME_LENGTH
+            )));
+        }
+    }
+    // Leagues are TheSportsDB league IDs, such as 4328 for the English Premier League
+    if let Some(league) = &request.league {
+        if league.is_empty() || !league.chars().all(|c| c.is_ascii_digit()) {
+            return Err(OracleError::Validation(format!(
+                "League must be a numeric league ID: {}",
+                league
+            )));
+        }
+    }
+    if let Some(season) = &request.season {
+        let valid = match season.split_once('-') {
+            Some((start, end)) => is_year(start) && is_year(end),
+            None => is_year(season),
+        };
+        if !valid {
+            return Err(OracleError::Validation(format!(
+                "Season must be YYYY or YYYY-YYYY: {}",
+                season
+            )));
+        }
+    }
+
+    match request.data_type {
+        SportsDataType::Scores | SportsDataType::Schedule
+            if request.team.is_none() && request.league.is_none() =>
+        {
+            Err(OracleError::Validation(
+                "A team or league is required for scores and schedules".to_string(),
+            ))
+        }
+        SportsDataType::Standings if request.league.is_none() => Err(OracleError::Validation(
+            "A league is required for standings".to_string(),
+        )),
+        SportsDataType::Stats => Err(OracleError::Validation(
+            "Statistics are not supported by the sports provider".to_string(),
+        )),
+        _ => Ok(()),
+    }
+}
+
+fn is_year(value: &str) -> bool {
+    value.len() == 4 && value.chars().all(|c| c.is_ascii_digit())
+}
+
+/// Key of a request in the cache
+fn cache_key(request: &SportsRequest) -> String {
+    format!(
+        "{:?}:{}:{}:{}:{}",
+        request.data_type,
+        request.sport.trim().to_lowercase(),
+        request.league.as_deref().unwrap_or(""),
+        request
+            .team
+            .as_deref()
+            .map(|team| team.trim().to_lowercase())
+            .unwrap_or_default(),
+        request.season.as_deref().unwrap_or("")
+    )
+}
+
+/// String field of a TheSportsDB object, which may also be given as a number
+fn text(value: &Value, field: &str) -> Option<String> {
+    match value.get(field)? {
+        Value::String(s) if !s.trim().is_empty() => Some(s.trim().to_string()),
+        Value::Number(n) => Some(n.to_string()),
+        _ => None,
+    }
+}
+
+/// Numeric field of a TheSportsDB object, which is usually given as a string
+fn number(value: &Value, field: &str) -> Option<u32> {
+    match value.get(field)? {
+        Value::Number(n) => n.as_u64().and_then(|n| u32::try_from(n).ok()),
+        Value::String(s) => s.trim().parse().ok(),
+        _ => None,
+    }
+}
+
+/// Start time of an event in UTC
+fn start_time(event: &Value) -> Option<String> {
+    let timestamp = text(event, "strTimestamp").or_else(|| {
+        Some(format!(
+            "{}T{}",
+            text(event, "dateEvent")?,
+            text(event, "strTime")?
+        ))
+    })?;
+
+    // Given in UTC, with or without the offset
+    let timestamp = timestamp.trim_end_matches("+00:00").trim_end_matches('Z');
+    NaiveDateTime::parse_from_str(timestamp, "%Y-%m-%dT%H:%M:%S")
+        .ok()
+        .map(|time| time.format("%Y-%m-%dT%H:%M:%SZ").to_string())
+}
+
+/// Normalize a TheSportsDB event
+fn normalize_event(event: &Value) -> Option<SportsEvent> {
+    Some(SportsEvent {
+        id: text(event, "idEvent")?,
+        name: text(event, "strEvent")?,
+        league: text(event, "strLeague"),
+        season: text(event, "strSeason"),
+        home_team: text(event, "strHomeTeam"),
+        away_team: text(event, "strAwayTeam"),
+        home_score: number(event, "intHomeScore"),
+        away_score: number(event, "intAwayScore"),
+        start_time: start_time(event),
+        status: text(event, "strStatus"),
+    })
+}
+
+/// Normalize a TheSportsDB table row
+fn normalize_standing(row: &Value) -> Option<Standing> {
+    Some(Standing {
+        rank: number(row, "intRank")?,
+        team: text(row, "strTeam")?,
+        played: number(row, "intPlayed").unwrap_or_default(),
+        won: number(row, "intWin").unwrap_or_default(),
+        drawn: number(row, "intDraw").unwrap_or_default(),
+        lost: number(row, "intLoss").unwrap_or_default(),
+        goals_for: number(row, "intGoalsFor").unwrap_or_default(),
+        goals_against: number(row, "intGoalsAgainst").unwrap_or_default(),
+        points: number(row, "intPoints").unwrap_or_default(),
+    })
+}
+
+/// Objects of a TheSportsDB list, which is `null` when empty
+fn list<'a>(response: &'a Value, field: &str) -> &'a [Value] {
+    response
+        .get(field)
+        .and_then(Value::as_array)
+        .map(Vec::as_slice)
+        .unwrap_or_default()
+}
+
+impl SportsProvider {
+    /// Create a new sports provider, allowing `rate_limit` requests to TheSportsDB per minute.
+    ///
+    /// Without an API key, the public development key is used.
+    pub fn new(api_key: Option<String>, cache_expiration: u64, rate_limit: u32) -> Self {
+        Self {
+            api_key: api_key.unwrap_or_else(|| DEFAULT_API_KEY.to_string()),
+            cache: Arc::new(RwLock::new(HashMap::new())),
+            cache_expiration,
+            rate_limiter: RateLimiter::direct(Quota::per_minute(
+                NonZeroU32::new(rate_limit).unwrap_or(NonZeroU32::new(30).unwrap()),
+            )),
+            egress_guard: EgressGuard::new(),
+        }
+    }
+
+    /// HTTP client for a TheSportsDB URL, connecting only to the addresses the egress guard checked
+    async fn client_for(&self, url: &str) -> Result<Client, OracleError> {
+        let resolved = self
+            .egress_guard
+            .check_url(url)
+            .await
+            .map_err(|e| OracleError::Provider(e.to_string()))?;
+
+        Client::builder()
+            .resolve_to_addrs(&resolved.host, &resolved.addrs)
+            .redirect(reqwest::redirect::Policy::none())
+            .build()
+            .map_err(|e| OracleError::Internal(format!("Failed to create HTTP client: {}", e)))
+    }
+
+    /// Fetch JSON from a TheSportsDB endpoint
+    async fn fetch(&self, endpoint: &str, params: &[(&str, &str)]) -> Result<Value, OracleError> {
+        if self.rate_limiter.check().is_err() {
+            return Err(OracleError::RateLimit(
+                "Sports provider rate limit exceeded".to_string(),
+            ));
+        }
+
+        let url = Url::parse_with_params(
+            &format!("{}/{}/{}", SPORTSDB_URL, self.api_key, endpoint),
+            params,
+        )
+        .map_err(|e| OracleError::Internal(format!("Invalid sports URL: {}", e)))?;
+        let client = self.client_for(url.as_str()).await?;
+        let response =
+            client.get(url).send().await.map_err(|e| {
+                OracleError::Provider(format!("TheSportsDB API request failed: {}", e))
+            })?;
+
+        if !response.status().is_success() {
+            return Err(OracleError::Provider(format!(
+                "TheSportsDB API returned error status: {}",
+                response.status()
+            )));
+        }
+
+        response.json().await.map_err(|e| {
+            OracleError::Provider(format!("Failed to parse TheSportsDB response: {}", e))
+        })
+    }
+
+    /// Find the ID of a team playing a sport
+    async fn find_team(&self, sport: &str, team: &str) -> Result<String, OracleError> {
+        let response = self.fetch("searchteams.php", &[("t", team.trim())]).await?;
+
+        list(&response, "teams")
+            .iter()
+            .find(|candidate| {
+                text(candidate, "strSport").is_some_and(|s| s.eq_ignore_ascii_case(sport.trim()))
+            })
+            .and_then(|candidate| text(candidate, "idTeam"))
+            .ok_or_else(|| OracleError::Validation(format!("Unknown {} team: {}", sport, team)))
+    }
+
+    /// Fetch the sports report of a request from TheSportsDB
+    async fn fetch_report(&self, request: &SportsRequest) -> Result<SportsReport, OracleError> {
+        let mut report = SportsReport::default();
+
+        match request.data_type {
+            SportsDataType::Scores | SportsDataType::Schedule => {
+                let upcoming = request.data_type == SportsDataType::Schedule;
+                let (endpoint, id, field) = match (&request.team, &request.league) {
+                    (Some(team), _) => {
+                        let id = self.find_team(&request.sport, team).await?;
+                        if upcoming {
+                            ("eventsnext.php", id, "events")
+                        } else {
+                            ("eventslast.php", id, "results")
+                        }
+                    }
+                    (None, Some(league)) if upcoming => {
+                        ("eventsnextleague.php", league.clone(), "events")
+                    }
+                    (None, Some(league)) => ("eventspastleague.php", league.clone(), "events"),
+                    (None, None) => {
+                        return Err(OracleError::Validation(
+                            "A team or league is required for scores and schedules".to_string(),
+                        ))
+                    }
+                };
+
+                let response = self.fetch(endpoint, &[("id", id.as_str())]).await?;
+                report.events = list(&response, field)
+                    .iter()
+                    .filter_map(normalize_event)
+                    .collect();
+            }
+            SportsDataType::Standings => {
+                let league = request.league.as_deref().unwrap_or_default();
+                let mut params = vec![("l", league)];
+                if let Some(season) = &request.season {
+                    params.push(("s", season.as_str()));
+                }
+
+                let response = self.fetch("lookuptable.php", &params).await?;
+                report.standings = list(&response, "table")
+                    .iter()
+                    .filter_map(normalize_standing)
+                    .collect();
+                report.standings.sort_by_key(|standing| standing.rank);
+            }
+            SportsDataType::Stats => {
+                return Err(OracleError::Validation(
+                    "Statistics are not supported by the sports provider".to_string(),
+                ))
+            }
+        }
+
+        Ok(report)
+    }
+
+    /// Get the sports report of a request from cache or TheSportsDB
+    async fn get_report(&self, request: &SportsRequest) -> Result<SportsReport, OracleError> {
+        let key = cache_key(request);
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+
+        // Check cache first
+        if let Some((fetched_at, report)) = self.cache.read().await.get(&key) {
+            if now - fetched_at < self.cache_expiration {
+                return Ok(report.clone());
+            }
+        }
+
+        let report = self.fetch_report(request).await?;
+
+        // Update cache, dropping expired reports
+        let mut cache = self.cache.write().await;
+        cache.retain(|_, (fetched_at, _)| now - *fetched_at < self.cache_expiration);
+        cache.insert(key, (now, report.clone()));
+
+        Ok(report)
+    }
+}
+
+#[async_trait]
+impl OracleProvider for SportsProvider {
+    fn name(&self) -> &str {
+        "sports"
+    }
+
+    fn description(&self) -> &str {
+        "Provides sports scores, schedules and standings from TheSportsDB"
+    }
+
+    fn supported_types(&self) -> Vec<OracleRequestType> {
+        vec![OracleRequestType::Sports]
+    }
+
+    async fn process_request(
+        &self,
+        request: &OracleRequest,
+    ) -> Result<OracleResponse, OracleError> {
+        if request.request_type != OracleRequestType::Sports {
+            return Err(OracleError::Validation(format!(
+                "Unsupported request type: {:?}",
+                request.request_type
+            )));
+        }
+
+        // Parse and validate request data
+        let sports_request: SportsRequest = serde_json::from_str(&request.data)
+            .map_err(|e| OracleError::Validation(format!("Invalid sports request data: {}", e)))?;
+        validate_request(&sports_request)?;
+
+        let report = self.get_report(&sports_request).await?;
+
+        // Create response
+        let sports_response = SportsResponse {
+            sport: sports_request.sport,
+            league: sports_request.league,
+            team: sports_request.team,
+            data: serde_json::to_value(&report).map_err(|e| {
+                OracleError::Internal(format!("Failed to serialize sports report: {}", e))
+            })?,
+            source: "thesportsdb".to_string(),
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs(),
+        };
+
+        let response_data = serde_json::to_string(&sports_response)
+            .map_err(|e| OracleError::Internal(format!("Failed to serialize response: {}", e)))?;
+
+        Ok(OracleResponse {
+            request_id: request.id.clone(),
+            data: response_data,
+            status_code: 200,
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs(),
+            error: None,
+            signature: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn request(data_type: SportsDataType) -> SportsRequest {
+        SportsRequest {
+            sport: "Soccer".to_string(),
+            league: None,
+            team: None,
+            data_type,
+            season: None,
+        }
+    }
+
+    #[test]
+    fn test_validate_request() {
+        let mut scores = request(SportsDataType::Scores);
+        assert!(validate_request(&scores).is_err());
+        scores.team = Some("Arsenal".to_string());
+        assert!(validate_request(&scores).is_ok());
+        scores.league = Some("English Premier League".to_string());
+        assert!(validate_request(&scores).is_err());
+
+        let mut standings = request(SportsDataType::Standings);
+        assert!(validate_request(&standings).is_err());
+        standings.league = Some("4328".to_string());
+        standings.season = Some("2023-2024".to_string());
+        assert!(validate_request(&standings).is_ok());
+        standings.season = Some("23/24".to_string());
+        assert!(validate_request(&standings).is_err());
+
+        assert!(validate_request(&request(SportsDataType::Stats)).is_err());
+    }
+
+    #[test]
+    fn test_normalize() {
+        let event = json!({
+            "idEvent": "1032723",
+            "strEvent": "Arsenal vs Liverpool",
+            "strLeague": "English Premier League",
+            "strSeason": "2023-2024",
+            "strHomeTeam": "Arsenal",
+            "strAwayTeam": "Liverpool",
+            "intHomeScore": "3",
+            "intAwayScore": 1,
+            "strTimestamp": "2024-02-04T16:30:00+00:00",
+            "strStatus": "Match Finished"
+        });
+        let event = normalize_event(&event).unwrap();
+        assert_eq!(event.home_score, Some(3));
+        assert_eq!(event.away_score, Some(1));
+        assert_eq!(event.start_time.as_deref(), Some("2024-02-04T16:30:00Z"));
+
+        let upcoming = json!({
+            "idEvent": "1032724",
+            "strEvent": "Liverpool vs Arsenal",
+            "intHomeScore": null,
+            "dateEvent": "2024-03-10",
+            "strTime": "15:45:00"
+        });
+        let upcoming = normalize_event(&upcoming).unwrap();
+        assert_eq!(upcoming.home_score, None);
+        assert_eq!(upcoming.start_time.as_deref(), Some("2024-03-10T15:45:00Z"));
+
+        let response = json!({ "table": null });
+        assert!(list(&response, "table").is_empty());
+        let row = json!({ "intRank": "1", "strTeam": "Arsenal", "intPoints": "64" });
+        let standing = normalize_standing(&row).unwrap();
+        assert_eq!((standing.rank, standing.points, standing.won), (1, 64, 0));
+    }
+}
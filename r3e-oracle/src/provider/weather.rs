@@ -0,0 +1,578 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+use std::collections::HashMap;
+use std::num::NonZeroU32;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use async_trait::async_trait;
+use chrono::{NaiveDate, Utc};
+use governor::clock::DefaultClock;
+use governor::state::{InMemoryState, NotKeyed};
+use governor::{Quota, RateLimiter};
+use r3e_core::egress::EgressGuard;
+use reqwest::Client;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use tokio::sync::RwLock;
+use url::Url;
+
+use crate::types::{
+    DailyWeather, WeatherDataType, WeatherObservation, WeatherReport, WeatherRequest,
+    WeatherResponse,
+};
+use crate::{OracleError, OracleProvider, OracleRequest, OracleRequestType, OracleResponse};
+
+/// Open-Meteo forecast API
+const FORECAST_URL: &str = "https://api.open-meteo.com/v1/forecast";
+
+/// Open-Meteo historical weather API
+const ARCHIVE_URL: &str = "https://archive-api.open-meteo.com/v1/archive";
+
+/// Open-Meteo geocoding API
+const GEOCODING_URL: &str = "https://geocoding-api.open-meteo.com/v1/search";
+
+/// Maximum length of a location
+const MAX_LOCATION_LENGTH: usize = 100;
+
+/// Maximum number of days to forecast
+const MAX_FORECAST_DAYS: u8 = 16;
+
+/// Default number of days to forecast
+const DEFAULT_FORECAST_DAYS: u8 = 7;
+
+/// Daily variables requested from Open-Meteo
+const DAILY_VARIABLES: &str =
+    "weather_code,temperature_2m_max,temperature_2m_min,precipitation_sum,wind_speed_10m_max";
+
+/// Current variables requested from Open-Meteo
+const CURRENT_VARIABLES: &str =
+    "temperature_2m,relative_humidity_2m,precipitation,weather_code,wind_speed_10m";
+
+/// Weather provider backed by Open-Meteo
+pub struct WeatherProvider {
+    /// Cache for weather reports, with the time they were fetched
+    cache: Arc<RwLock<HashMap<String, (u64, WeatherReport)>>>,
+
+    /// Cache expiration time in seconds
+    cache_expiration: u64,
+
+    /// Limits requests to Open-Meteo, cached reports are not limited
+    rate_limiter: RateLimiter<NotKeyed, InMemoryState, DefaultClock>,
+
+    /// Egress guard for API requests
+    egress_guard: EgressGuard,
+}
+
+/// Location a weather request is for
+#[derive(Debug, Clone, PartialEq)]
+enum Location {
+    /// Latitude and longitude
+    Coordinates(f64, f64),
+
+    /// Place name, geocoded before fetching the weather
+    Name(String),
+}
+
+/// Weather request after validation
+#[derive(Debug, Clone, PartialEq)]
+struct ValidatedRequest {
+    location: Location,
+    data_type: WeatherDataType,
+    days: u8,
+    date: Option<NaiveDate>,
+}
+
+impl ValidatedRequest {
+    /// Key of the request in the cache
+    fn cache_key(&self) -> String {
+        let location = match &self.location {
+            Location::Coordinates(latitude, longitude) => {
+                format!("{:.4},{:.4}", latitude, longitude)
+            }
+            Location::Name(name) => name.to_lowercase(),
+        };
+        match self.data_type {
+            WeatherDataType::Current => format!("current:{}", location),
+            WeatherDataType::Forecast => format!("forecast:{}:{}", location, self.days),
+            WeatherDataType::Historical => format!(
+                "historical:{}:{}",
+                location,
+                self.date.map(|date| date.to_string()).unwrap_or_default()
+            ),
+        }
+    }
+}
+
+/// Validate a weather request
+fn validate_request(request: &WeatherRequest) -> Result<ValidatedRequest, OracleError> {
+    let location = request.location.trim();
+    if location.is_empty() {
+        return Err(OracleError::Validation("Location is required".to_string()));
+    }
+    if location.len() > MAX_LOCATION_LENGTH {
+        return Err(OracleError::Validation(format!(
+            "Location must be at most {} characters",
+            MAX_LOCATION_LENGTH
+        )));
+    }
+    let location = parse_coordinates(location)?
+        .map(|(latitude, longitude)| Location::Coordinates(latitude, longitude))
+        .unwrap_or_else(|| Location::Name(location.to_string()));
+
+    let days = request.days.unwrap_or(DEFAULT_FORECAST_DAYS);
+    if request.data_type == WeatherDataType::Forecast && !(1..=MAX_FORECAST_DAYS).contains(&days) {
+        return Err(OracleError::Validation(format!(
+            "Forecast days must be between 1 and {}",
+            MAX_FORECAST_DAYS
+        )));
+    }
+
+    let date = match request.data_type {
+        WeatherDataType::Historical => {
+            let date = request.date.as_deref().ok_or_else(|| {
+                OracleError::Validation("Date is required for historical weather".to_string())
+            })?;
+            let date = NaiveDate::parse_from_str(date, "%Y-%m-%d").map_err(|_| {
+                OracleError::Validation(format!("Invalid date, expected YYYY-MM-DD: {}", date))
+            })?;
+            if date >= Utc::now().date_naive() {
+                return Err(OracleError::Validation(
+                    "Historical weather is only available for past days".to_string(),
+                ));
+            }
+            Some(date)
+        }
+        _ => None,
+    };
+
+    Ok(ValidatedRequest {
+        location,
+        data_type: request.data_type,
+        days,
+        date,
+    })
+}
+
+/// Parse `latitude,longitude`, returning `None` if the location is not a coordinate pair
+fn parse_coordinates(location: &str) -> Result<Option<(f64, f64)>, OracleError> {
+    let Some((latitude, longitude)) = location.split_once(',') else {
+        return Ok(None);
+    };
+    let (Ok(latitude), Ok(longitude)) = (
+        latitude.trim().parse::<f64>(),
+        longitude.trim().parse::<f64>(),
+    ) else {
+        return Ok(None);
+    };
+
+    if !(-90.0..=90.0).contains(&latitude) || !(-180.0..=180.0).contains(&longitude) {
+        return Err(OracleError::Validation(format!(
+            "Coordinates out of range: {}",
+            location
+        )));
+    }
+
+    Ok(Some((latitude, longitude)))
+}
+
+/// Condition of a WMO weather code
+fn weather_condition(code: Option<u32>) -> &'static str {
+    match code {
+        Some(0) => "clear",
+        Some(1) => "mainly_clear",
+        Some(2) => "partly_cloudy",
+        Some(3) => "overcast",
+        Some(45 | 48) => "fog",
+        Some(51 | 53 | 55) => "drizzle",
+        Some(56 | 57) => "freezing_drizzle",
+        Some(61 | 63 | 65) => "rain",
+        Some(66 | 67) => "freezing_rain",
+        Some(71 | 73 | 75 | 77) => "snow",
+        Some(80..=82) => "rain_showers",
+        Some(85 | 86) => "snow_showers",
+        Some(95) => "thunderstorm",
+        Some(96 | 99) => "thunderstorm_with_hail",
+        _ => "unknown",
+    }
+}
+
+/// Open-Meteo geocoding response
+#[derive(Debug, Deserialize)]
+struct GeocodingResponse {
+    #[serde(default)]
+    results: Vec<GeocodingResult>,
+}
+
+#[derive(Debug, Deserialize)]
+struct GeocodingResult {
+    name: String,
+    latitude: f64,
+    longitude: f64,
+    country: Option<String>,
+}
+
+/// Open-Meteo forecast and archive response
+#[derive(Debug, Deserialize)]
+struct OpenMeteoResponse {
+    latitude: f64,
+    longitude: f64,
+    current: Option<OpenMeteoCurrent>,
+    daily: Option<OpenMeteoDaily>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OpenMeteoCurrent {
+    time: String,
+    temperature_2m: Option<f64>,
+    relative_humidity_2m: Option<f64>,
+    precipitation: Option<f64>,
+    weather_code: Option<u32>,
+    wind_speed_10m: Option<f64>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct OpenMeteoDaily {
+    time: Vec<String>,
+    weather_code: Vec<Option<u32>>,
+    temperature_2m_max: Vec<Option<f64>>,
+    temperature_2m_min: Vec<Option<f64>>,
+    precipitation_sum: Vec<Option<f64>>,
+    wind_speed_10m_max: Vec<Option<f64>>,
+}
+
+/// Normalize an Open-Meteo response into a weather report
+fn normalize_response(location: String, response: OpenMeteoResponse) -> WeatherReport {
+    let current = response.current.map(|current| WeatherObservation {
+        // Requested in UTC, without the offset
+        time: format!("{}Z", current.time),
+        temperature_c: current.temperature_2m,
+        humidity_percent: current.relative_humidity_2m,
+        precipitation_mm: current.precipitation,
+        wind_speed_kmh: current.wind_speed_10m,
+        weather_code: current.weather_code,
+        condition: weather_condition(current.weather_code).to_string(),
+    });
+
+    let daily = response.daily.unwrap_or_default();
+    let value = |values: &[Option<f64>], i: usize| values.get(i).copied().flatten();
+    let days = daily
+        .time
+        .iter()
+        .enumerate()
+        .map(|(i, date)| {
+            let weather_code = daily.weather_code.get(i).copied().flatten();
+            DailyWeather {
+                date: date.clone(),
+                temperature_max_c: value(&daily.temperature_2m_max, i),
+                temperature_min_c: value(&daily.temperature_2m_min, i),
+                precipitation_mm: value(&daily.precipitation_sum, i),
+                wind_speed_max_kmh: value(&daily.wind_speed_10m_max, i),
+                weather_code,
+                condition: weather_condition(weather_code).to_string(),
+            }
+        })
+        .collect();
+
+    WeatherReport {
+        location,
+        latitude: response.latitude,
+        longitude: response.longitude,
+        current,
+        daily: days,
+    }
+}
+
+impl WeatherProvider {
+    /// Create a new weather provider, allowing `rate_limit` requests to Open-Meteo per minute
+    pub fn new(cache_expiration: u64, rate_limit: u32) -> Self {
+        Self {
+            cache: Arc::new(RwLock::new(HashMap::new())),
+            cache_expiration,
+            rate_limiter: RateLimiter::direct(Quota::per_minute(
+                NonZeroU32::new(rate_limit).unwrap_or(NonZeroU32::new(60).unwrap()),
+            )),
+            egress_guard: EgressGuard::new(),
+        }
+    }
+
+    /// HTTP client for an Open-Meteo URL, connecting only to the addresses the egress guard checked
+    async fn client_for(&self, url: &str) -> Result<Client, OracleError> {
+        let resolved = self
+            .egress_guard
+            .check_url(url)
+            .await
+            .map_err(|e| OracleError::Provider(e.to_string()))?;
+
+        Client::builder()
+            .resolve_to_addrs(&resolved.host, &resolved.addrs)
+            .redirect(reqwest::redirect::Policy::none())
+            .build()
+            .map_err(|e| OracleError::Internal(format!("Failed to create HTTP client: {}", e)))
+    }
+
+    /// Fetch JSON from an Open-Meteo API
+    async fn fetch<T: DeserializeOwned>(&self, url: Url) -> Result<T, OracleError> {
+        if self.rate_limiter.check().is_err() {
+            return Err(OracleError::RateLimit(
+                "Weather provider rate limit exceeded".to_string(),
+            ));
+        }
+
+        let client = self.client_for(url.as_str()).await?;
+        let response =
+            client.get(url).send().await.map_err(|e| {
+                OracleError::Provider(format!("Open-Meteo API request failed: {}", e))
+            })?;
+
+        if !response.status().is_success() {
+            return Err(OracleError::Provider(format!(
+                "Open-Meteo API returned error status: {}",
+                response.status()
+            )));
+        }
+
+        response.json().await.map_err(|e| {
+            OracleError::Provider(format!("Failed to parse Open-Meteo response: {}", e))
+        })
+    }
+
+    /// Resolve a location to its name and coordinates
+    async fn resolve_location(
+        &self,
+        location: &Location,
+    ) -> Result<(String, f64, f64), OracleError> {
+        let name = match location {
+            Location::Coordinates(latitude, longitude) => {
+                return Ok((format!("{},{}", latitude, longitude), *latitude, *longitude))
+            }
+            Location::Name(name) => name,
+        };
+
+        let url = Url::parse_with_params(
+            GEOCODING_URL,
+            &[("name", name.as_str()), ("count", "1"), ("format", "json")],
+        )
+        .map_err(|e| OracleError::Internal(format!("Invalid geocoding URL: {}", e)))?;
+        let response: GeocodingResponse = self.fetch(url).await?;
+
+        let place = response
+            .results
+            .into_iter()
+            .next()
+            .ok_or_else(|| OracleError::Validation(format!("Unknown location: {}", name)))?;
+        let name = match place.country {
+            Some(country) => format!("{}, {}", place.name, country),
+            None => place.name,
+        };
+
+        Ok((name, place.latitude, place.longitude))
+    }
+
+    /// Fetch the weather report of a request from Open-Meteo
+    async fn fetch_report(&self, request: &ValidatedRequest) -> Result<WeatherReport, OracleError> {
+        let (name, latitude, longitude) = self.resolve_location(&request.location).await?;
+        let latitude = latitude.to_string();
+        let longitude = longitude.to_string();
+        let mut params = vec![
+            ("latitude", latitude),
+            ("longitude", longitude),
+            ("timezone", "UTC".to_string()),
+        ];
+
+        let base_url = match request.data_type {
+            WeatherDataType::Current => {
+                params.push(("current", CURRENT_VARIABLES.to_string()));
+                FORECAST_URL
+            }
+            WeatherDataType::Forecast => {
+                params.push(("daily", DAILY_VARIABLES.to_string()));
+                params.push(("forecast_days", request.days.to_string()));
+                FORECAST_URL
+            }
+            WeatherDataType::Historical => {
+                let date = request
+                    .date
+                    .map(|date| date.to_string())
+                    .unwrap_or_default();
+                params.push(("daily", DAILY_VARIABLES.to_string()));
+                params.push(("start_date", date.clone()));
+                params.push(("end_date", date));
+                ARCHIVE_URL
+            }
+        };
+
+        let url = Url::parse_with_params(base_url, &params)
+            .map_err(|e| OracleError::Internal(format!("Invalid weather URL: {}", e)))?;
+        let response: OpenMeteoResponse = self.fetch(url).await?;
+
+        Ok(normalize_response(name, response))
+    }
+
+    /// Get the weather report of a request from cache or Open-Meteo
+    async fn get_report(&self, request: &ValidatedRequest) -> Result<WeatherReport, OracleError> {
+        let key = request.cache_key();
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+
+        // Check cache first
+        if let Some((fetched_at, report)) = self.cache.read().await.get(&key) {
+            if now - fetched_at < self.cache_expiration {
+                return Ok(report.clone());
+            }
+        }
+
+        let report = self.fetch_report(request).await?;
+
+        // Update cache, dropping expired reports
+        let mut cache = self.cache.write().await;
+        cache.retain(|_, (fetched_at, _)| now - *fetched_at < self.cache_expiration);
+        cache.insert(key, (now, report.clone()));
+
+        Ok(report)
+    }
+}
+
+#[async_trait]
+impl OracleProvider for WeatherProvider {
+    fn name(&self) -> &str {
+        "weather"
+    }
+
+    fn description(&self) -> &str {
+        "Provides current, forecast and historical weather data from Open-Meteo"
+    }
+
+    fn supported_types(&self) -> Vec<OracleRequestType> {
+        vec![OracleRequestType::Weather]
+    }
+
+    async fn process_request(
+        &self,
+        request: &OracleRequest,
+    ) -> Result<OracleResponse, OracleError> {
+        if request.request_type != OracleRequestType::Weather {
+            return Err(OracleError::Validation(format!(
+                "Unsupported request type: {:?}",
+                request.request_type
+            )));
+        }
+
+        // Parse and validate request data
+        let weather_request: WeatherRequest = serde_json::from_str(&request.data)
+            .map_err(|e| OracleError::Validation(format!("Invalid weather request data: {}", e)))?;
+        let validated = validate_request(&weather_request)?;
+
+        let report = self.get_report(&validated).await?;
+
+        // Create response
+        let weather_response = WeatherResponse {
+            location: weather_request.location,
+            data: serde_json::to_value(&report).map_err(|e| {
+                OracleError::Internal(format!("Failed to serialize weather report: {}", e))
+            })?,
+            source: "open-meteo".to_string(),
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs(),
+        };
+
+        let response_data = serde_json::to_string(&weather_response)
+            .map_err(|e| OracleError::Internal(format!("Failed to serialize response: {}", e)))?;
+
+        Ok(OracleResponse {
+            request_id: request.id.clone(),
+            data: response_data,
+            status_code: 200,
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs(),
+            error: None,
+            signature: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(location: &str, data_type: WeatherDataType) -> WeatherRequest {
+        WeatherRequest {
+            location: location.to_string(),
+            data_type,
+            days: None,
+            date: None,
+        }
+    }
+
+    #[test]
+    fn test_validate_request() {
+        let validated =
+            validate_request(&request(" 52.52, 13.41 ", WeatherDataType::Current)).unwrap();
+        assert_eq!(validated.location, Location::Coordinates(52.52, 13.41));
+        assert_eq!(
+            validate_request(&request("Berlin", WeatherDataType::Forecast))
+                .unwrap()
+                .cache_key(),
+            "forecast:berlin:7"
+        );
+
+        assert!(validate_request(&request("  ", WeatherDataType::Current)).is_err());
+        assert!(validate_request(&request("91,0", WeatherDataType::Current)).is_err());
+
+        let mut forecast = request("Berlin", WeatherDataType::Forecast);
+        forecast.days = Some(17);
+        assert!(validate_request(&forecast).is_err());
+
+        let mut historical = request("Berlin", WeatherDataType::Historical);
+        assert!(validate_request(&historical).is_err());
+        historical.date = Some("2024-13-01".to_string());
+        assert!(validate_request(&historical).is_err());
+        historical.date = Some("2024-01-31".to_string());
+        assert_eq!(
+            validate_request(&historical).unwrap().cache_key(),
+            "historical:berlin:2024-01-31"
+        );
+    }
+
+    #[test]
+    fn test_normalize_response() {
+        let response: OpenMeteoResponse = serde_json::from_value(serde_json::json!({
+            "latitude": 52.52,
+            "longitude": 13.41,
+            "current": {
+                "time": "2024-01-31T12:00",
+                "temperature_2m": 3.5,
+                "relative_humidity_2m": 81.0,
+                "precipitation": 0.0,
+                "weather_code": 3,
+                "wind_speed_10m": 12.2
+            },
+            "daily": {
+                "time": ["2024-01-31", "2024-02-01"],
+                "weather_code": [61, null],
+                "temperature_2m_max": [5.1, 6.0],
+                "temperature_2m_min": [1.2, null],
+                "precipitation_sum": [2.4, 0.0],
+                "wind_speed_10m_max": [20.5, 15.0]
+            }
+        }))
+        .unwrap();
+
+        let report = normalize_response("Berlin, Germany".to_string(), response);
+        let current = report.current.unwrap();
+        assert_eq!(current.time, "2024-01-31T12:00Z");
+        assert_eq!(current.condition, "overcast");
+        assert_eq!(report.daily.len(), 2);
+        assert_eq!(report.daily[0].condition, "rain");
+        assert_eq!(report.daily[1].condition, "unknown");
+        assert_eq!(report.daily[1].temperature_min_c, None);
+    }
+}
@@ -9,8 +9,13 @@ use serde::{Deserialize, Serialize};
 use tokio::sync::{mpsc, RwLock};
 use uuid::Uuid;
 
+use r3e_core::chain::{ChainClients, ChainMetrics};
+use r3e_core::egress::EgressGuard;
+
 use crate::auth::AuthService;
+use crate::provider::price_cache::{PriceCache, PriceCacheMetrics};
 use crate::provider::ProviderRegistry;
+use crate::signing::{sign_response, ResponseSigner};
 use crate::{
     OracleError, OracleProvider, OracleRequest, OracleRequestStatus, OracleRequestType,
     OracleResponse, OracleService,
@@ -35,6 +40,15 @@ pub struct OracleServiceImpl {
 
     /// Request channel receiver
     request_rx: Arc<RwLock<Option<mpsc::Receiver<OracleRequest>>>>,
+
+    /// Chain clients, shared by all gateway calls
+    chains: Arc<ChainClients>,
+
+    /// Key signing responses, unsigned if not set
+    signer: Option<Arc<dyn ResponseSigner>>,
+
+    /// Cache of the price provider, for its metrics
+    price_cache: Option<Arc<PriceCache>>,
 }
 
 impl OracleServiceImpl {
@@ -49,16 +63,58 @@ impl OracleServiceImpl {
             responses: Arc::new(RwLock::new(HashMap::new())),
             request_tx,
             request_rx: Arc::new(RwLock::new(Some(request_rx))),
+            chains: Arc::new(ChainClients::new()),
+            signer: None,
+            price_cache: None,
         }
     }
 
+    /// Sign responses with the oracle key
+    pub fn with_signer(mut self, signer: Arc<dyn ResponseSigner>) -> Self {
+        self.signer = Some(signer);
+        self
+    }
+
+    /// Share chain clients with other services, so endpoint health is tracked across them
+    pub fn with_chain_clients(mut self, chains: Arc<ChainClients>) -> Self {
+        self.chains = chains;
+        self
+    }
+
+    /// Request metrics and endpoint health of the chains the gateways used
+    pub fn chain_metrics(&self) -> Vec<ChainMetrics> {
+        self.chains.metrics()
+    }
+
+    /// Report the metrics of the price provider's cache
+    pub fn with_price_cache(mut self, cache: Arc<PriceCache>) -> Self {
+        self.price_cache = Some(cache);
+        self
+    }
+
+    /// Hit metrics of the price cache, if one was set
+    pub fn price_cache_metrics(&self) -> Option<PriceCacheMetrics> {
+        self.price_cache.as_ref().map(|cache| cache.metrics())
+    }
+
     /// Send callback to the specified URL
     async fn send_callback(
         callback_url: &str,
         response: &OracleResponse,
     ) -> Result<(), OracleError> {
+        // Callback URLs are user supplied, refuse internal destinations and pin
+        // the connection to the checked addresses
+        let resolved = EgressGuard::new()
+            .check_url(callback_url)
+            .await
+            .map_err(|e| OracleError::Validation(format!("Invalid callback URL: {}", e)))?;
+
         // Create a reqwest client
-        let client = reqwest::Client::new();
+        let client = reqwest::Client::builder()
+            .resolve_to_addrs(&resolved.host, &resolved.addrs)
+            .redirect(reqwest::redirect::Policy::none())
+            .build()
+            .map_err(|e| OracleError::Internal(format!("Failed to create HTTP client: {}", e)))?;
 
         // Serialize the response to JSON
         let response_json = serde_json::to_string(response)
@@ -113,9 +169,11 @@ impl OracleServiceImpl {
             ));
         }
 
-        // Create a blockchain gateway service for Neo
-        let client = reqwest::Client::new();
-        let client_arc = Arc::new(client);
+        // Get the Neo chain client
+        let chain = self
+            .chains
+            .get("neo", &crate::gateway::oracle_network_from_env())
+            .map_err(|e| OracleError::Provider(e.to_string()))?;
 
         // Create a price index registry
         let index_registry = Arc::new(crate::registry::PriceIndexRegistry::new());
@@ -125,7 +183,7 @@ impl OracleServiceImpl {
 
         // Create a Neo blockchain gateway service
         let gateway_service = crate::gateway::NeoBlockchainGatewayService::new(
-            client_arc,
+            chain,
             "NeoOracleWallet".to_string(), // This should be configurable
             "0x1234567890abcdef1234567890abcdef12345678".to_string(), // This should be configurable
             index_registry,
@@ -145,6 +203,7 @@ impl OracleServiceImpl {
         let provider_registry = Arc::clone(&self.provider_registry);
         let requests = Arc::clone(&self.requests);
         let responses = Arc::clone(&self.responses);
+        let signer = self.signer.clone();
 
         // Spawn a task to process requests
         tokio::spawn(async move {
@@ -171,15 +230,13 @@ impl OracleServiceImpl {
                     }
                 }
 
-                match result {
-                    Ok(response) => {
-                        responses.write().await.insert(request.id.clone(), response);
-                    }
+                let mut response = match result {
+                    Ok(response) => response,
                     Err(err) => {
                         log::error!("Failed to process request {}: {}", request.id, err);
 
                         // Create an error response
-                        let error_response = OracleResponse {
+                        OracleResponse {
                             request_id: request.id.clone(),
                             data: "".to_string(),
                             status_code: 500,
@@ -188,33 +245,26 @@ impl OracleServiceImpl {
                                 .unwrap_or_default()
                                 .as_secs(),
                             error: Some(err.to_string()),
-                        };
+                            signature: None,
+                        }
+                    }
+                };
 
-                        responses
-                            .write()
-                            .await
-                            .insert(request.id.clone(), error_response);
+                // Sign the response, so callbacks and contracts can check it came from the oracle
+                if let Some(signer) = &signer {
+                    if let Err(err) = sign_response(signer.as_ref(), &mut response).await {
+                        log::error!("Failed to sign response to {}: {}", request.id, err);
                     }
                 }
 
+                responses
+                    .write()
+                    .await
+                    .insert(request.id.clone(), response.clone());
+
                 // Send callback if callback_url is provided
                 if let Some(callback_url) = &request.callback_url {
-                    let response_clone = match result {
-                        Ok(ref response) => response.clone(),
-                        Err(ref err) => {
-                            // Create an error response for the callback
-                            OracleResponse {
-                                request_id: request.id.clone(),
-                                data: "".to_string(),
-                                status_code: 500,
-                                timestamp: SystemTime::now()
-                                    .duration_since(UNIX_EPOCH)
-                                    .unwrap_or_default()
-                                    .as_secs(),
-                                error: Some(err.to_string()),
-                            }
-                        }
-                    };
+                    let response_clone = response.clone();
 
                     // Send the callback asynchronously
                     let callback_url = callback_url.clone();
@@ -232,29 +282,10 @@ impl OracleServiceImpl {
 
                 // If this is a blockchain request, send the response to the blockchain gateway
                 if let OracleRequestType::Blockchain(blockchain_info) = &request.request_type {
-                    let response_clone = match result {
-                        Ok(ref response) => response.clone(),
-                        Err(ref err) => {
-                            // Create an error response for the blockchain
-                            OracleResponse {
-                                request_id: request.id.clone(),
-                                data: "".to_string(),
-                                status_code: 500,
-                                timestamp: SystemTime::now()
-                                    .duration_since(UNIX_EPOCH)
-                                    .unwrap_or_default()
-                                    .as_secs(),
-                                error: Some(err.to_string()),
-                            }
-                        }
-                    };
-
                     // Send the response to the blockchain gateway asynchronously
                     let blockchain_info = blockchain_info.clone();
                     tokio::spawn(async move {
-                        match Self::send_to_blockchain_gateway(&blockchain_info, &response_clone)
-                            .await
-                        {
+                        match Self::send_to_blockchain_gateway(&blockchain_info, &response).await {
                             Ok(_) => {
                                 log::info!(
                                     "Response sent to blockchain gateway for chain {}",
@@ -302,6 +333,7 @@ impl OracleServiceImpl {
             } else {
                 None
             },
+            signature: None,
         }
     }
 
@@ -346,20 +378,20 @@ impl OracleService for OracleServiceImpl {
         Ok(request.id)
     }
 
-    async fn get_request_status(
-        &self,
-        request_id: &str,
-    ) -> Result<OracleRequestStatus, OracleError> {
-        // Get the request
-        let request = self
-            .requests
+    async fn get_request(&self, request_id: &str) -> Result<OracleRequest, OracleError> {
+        self.requests
             .read()
             .await
             .get(request_id)
             .cloned()
-            .ok_or_else(|| OracleError::Validation(format!("Request not found: {}", request_id)))?;
+            .ok_or_else(|| OracleError::Validation(format!("Request not found: {}", request_id)))
+    }
 
-        Ok(request.status)
+    async fn get_request_status(
+        &self,
+        request_id: &str,
+    ) -> Result<OracleRequestStatus, OracleError> {
+        Ok(self.get_request(request_id).await?.status)
     }
 
     async fn get_response(&self, request_id: &str) -> Result<OracleResponse, OracleError> {
@@ -398,7 +430,7 @@ impl OracleService for OracleServiceImpl {
         request.status = OracleRequestStatus::Failed;
 
         // Create an error response
-        let error_response = OracleResponse {
+        let mut error_response = OracleResponse {
             request_id: request_id.to_string(),
             data: "".to_string(),
             status_code: 499, // Client Closed Request
@@ -407,7 +439,13 @@ impl OracleService for OracleServiceImpl {
                 .unwrap_or_default()
                 .as_secs(),
             error: Some("Request canceled".to_string()),
+            signature: None,
         };
+        if let Some(signer) = &self.signer {
+            if let Err(err) = sign_response(signer.as_ref(), &mut error_response).await {
+                log::error!("Failed to sign response to {}: {}", request_id, err);
+            }
+        }
 
         // Store the response
         self.responses
@@ -0,0 +1,383 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+//! Oracle response signing.
+//!
+//! Responses are signed with the platform oracle key, so consumers and contracts can check
+//! a response was produced by the oracle instead of trusting whoever relayed it. The
+//! signature covers a SHA-256 digest of the response:
+//!
+//! ```text
+//! sha256("r3e-oracle:v1|{request_id}|{status_code}|{timestamp}|{error_len}:{error}|{data}")
+//! ```
+//!
+//! where `error_len` is the byte length of the error message, `0` for successful responses.
+//! Neo signatures are secp256r1 signatures of the digest, as checked by
+//! `CryptoLib.verifyWithECDsa` with `secp256r1SHA256`. Ethereum signatures are EIP-191
+//! personal signatures of the digest, as checked by `ecrecover`.
+
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use ethers::signers::{LocalWallet, Signer as _};
+use p256::ecdsa::signature::{Signer as _, Verifier as _};
+use p256::ecdsa::{Signature, SigningKey, VerifyingKey};
+use p256::elliptic_curve::sec1::ToEncodedPoint;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{OracleError, OracleResponse};
+
+/// Domain separator of oracle response digests
+pub const SIGNING_DOMAIN: &str = "r3e-oracle:v1";
+
+/// Signature scheme of a response
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SignatureScheme {
+    /// secp256r1 ECDSA over SHA-256, verifiable by Neo N3 contracts
+    NeoSecp256r1,
+
+    /// secp256k1 EIP-191 personal signature, verifiable by Ethereum contracts
+    EthereumSecp256k1,
+}
+
+/// Signature of an oracle response and the key that made it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ResponseSignature {
+    /// Signature scheme
+    pub scheme: SignatureScheme,
+
+    /// Signer, the compressed public key for Neo and the address for Ethereum (hex)
+    pub signer: String,
+
+    /// Signature, 64 bytes `r || s` for Neo and 65 bytes `r || s || v` for Ethereum (hex)
+    pub signature: String,
+
+    /// Digest of the response that was signed (hex)
+    pub digest: String,
+
+    /// Attestation report of the TEE holding the signing key, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub attestation: Option<String>,
+}
+
+/// Digest of a response that is signed
+pub fn response_digest(response: &OracleResponse) -> [u8; 32] {
+    let error = response.error.as_deref().unwrap_or("");
+    let message = format!(
+        "{}|{}|{}|{}|{}:{}|{}",
+        SIGNING_DOMAIN,
+        response.request_id,
+        response.status_code,
+        response.timestamp,
+        error.len(),
+        error,
+        response.data
+    );
+
+    Sha256::digest(message.as_bytes()).into()
+}
+
+/// Key signing oracle responses
+#[async_trait]
+pub trait ResponseSigner: Send + Sync {
+    /// Signature scheme of the key
+    fn scheme(&self) -> SignatureScheme;
+
+    /// Signer as published to consumers, see [`ResponseSignature::signer`]
+    fn signer(&self) -> String;
+
+    /// Sign the digest of a response
+    async fn sign(&self, digest: &[u8; 32]) -> Result<ResponseSignature, OracleError>;
+}
+
+/// Sign a response, replacing any signature it carried
+pub async fn sign_response(
+    signer: &dyn ResponseSigner,
+    response: &mut OracleResponse,
+) -> Result<(), OracleError> {
+    response.signature = None;
+    let digest = response_digest(response);
+    response.signature = Some(signer.sign(&digest).await?);
+    Ok(())
+}
+
+/// Verify the signature of a response, returning it if the response is authentic.
+///
+/// This only checks the response was signed by the key it names; use
+/// [`verify_response_from`] to check the key is the oracle key.
+pub fn verify_response(response: &OracleResponse) -> Result<&ResponseSignature, OracleError> {
+    let signature = response
+        .signature
+        .as_ref()
+        .ok_or_else(|| OracleError::Signature("Response is not signed".to_string()))?;
+
+    let digest = response_digest(response);
+    if !signature.digest.eq_ignore_ascii_case(&hex::encode(digest)) {
+        return Err(OracleError::Signature(
+            "Response does not match the signed digest".to_string(),
+        ));
+    }
+
+    let signature_bytes = decode_hex(&signature.signature, "signature")?;
+    match signature.scheme {
+        SignatureScheme::NeoSecp256r1 => {
+            let public_key = decode_hex(&signature.signer, "signer")?;
+            let key = VerifyingKey::from_sec1_bytes(&public_key)
+                .map_err(|e| OracleError::Signature(format!("Invalid signer: {}", e)))?;
+            let sig = Signature::from_slice(&signature_bytes)
+                .map_err(|e| OracleError::Signature(format!("Invalid signature: {}", e)))?;
+            key.verify(&digest, &sig)
+                .map_err(|_| OracleError::Signature("Invalid signature".to_string()))?;
+        }
+        SignatureScheme::EthereumSecp256k1 => {
+            let sig = ethers::types::Signature::try_from(signature_bytes.as_slice())
+                .map_err(|e| OracleError::Signature(format!("Invalid signature: {}", e)))?;
+            let address = sig
+                .recover(digest.as_slice())
+                .map_err(|_| OracleError::Signature("Invalid signature".to_string()))?;
+            if !signature
+                .signer
+                .eq_ignore_ascii_case(&format!("{:?}", address))
+            {
+                return Err(OracleError::Signature("Invalid signature".to_string()));
+            }
+        }
+    }
+
+    Ok(signature)
+}
+
+/// Verify a response was signed by the trusted oracle signer
+pub fn verify_response_from(
+    response: &OracleResponse,
+    trusted_signer: &str,
+) -> Result<(), OracleError> {
+    let signature = verify_response(response)?;
+    let trusted = trusted_signer.trim_start_matches("0x");
+    if !signature
+        .signer
+        .trim_start_matches("0x")
+        .eq_ignore_ascii_case(trusted)
+    {
+        return Err(OracleError::Signature(format!(
+            "Response signed by untrusted signer {}",
+            signature.signer
+        )));
+    }
+
+    Ok(())
+}
+
+fn decode_hex(value: &str, what: &str) -> Result<Vec<u8>, OracleError> {
+    hex::decode(value.trim_start_matches("0x"))
+        .map_err(|e| OracleError::Signature(format!("Invalid {} encoding: {}", what, e)))
+}
+
+/// Neo N3 response signer with a local secp256r1 key
+pub struct NeoResponseSigner {
+    /// Signing key
+    key: SigningKey,
+
+    /// Attestation report of the TEE holding the key
+    attestation: Option<String>,
+}
+
+impl NeoResponseSigner {
+    /// Create a signer from a hex encoded private key
+    pub fn from_hex(private_key: &str) -> Result<Self, OracleError> {
+        let bytes = decode_hex(private_key, "private key")?;
+        let key = SigningKey::from_slice(&bytes)
+            .map_err(|e| OracleError::Signature(format!("Invalid private key: {}", e)))?;
+
+        Ok(Self {
+            key,
+            attestation: None,
+        })
+    }
+
+    /// Publish the attestation report of the TEE the key was generated in
+    pub fn with_attestation(mut self, attestation: String) -> Self {
+        self.attestation = Some(attestation);
+        self
+    }
+}
+
+#[async_trait]
+impl ResponseSigner for NeoResponseSigner {
+    fn scheme(&self) -> SignatureScheme {
+        SignatureScheme::NeoSecp256r1
+    }
+
+    fn signer(&self) -> String {
+        hex::encode(self.key.verifying_key().to_encoded_point(true).as_bytes())
+    }
+
+    async fn sign(&self, digest: &[u8; 32]) -> Result<ResponseSignature, OracleError> {
+        let signature: Signature = self.key.sign(digest);
+
+        Ok(ResponseSignature {
+            scheme: self.scheme(),
+            signer: self.signer(),
+            signature: hex::encode(signature.to_bytes()),
+            digest: hex::encode(digest),
+            attestation: self.attestation.clone(),
+        })
+    }
+}
+
+/// Ethereum response signer with a local secp256k1 key
+pub struct EthereumResponseSigner {
+    /// Signing wallet
+    wallet: LocalWallet,
+
+    /// Attestation report of the TEE holding the key
+    attestation: Option<String>,
+}
+
+impl EthereumResponseSigner {
+    /// Create a signer from a hex encoded private key
+    pub fn from_hex(private_key: &str) -> Result<Self, OracleError> {
+        let wallet = private_key
+            .trim_start_matches("0x")
+            .parse::<LocalWallet>()
+            .map_err(|e| OracleError::Signature(format!("Invalid private key: {}", e)))?;
+
+        Ok(Self {
+            wallet,
+            attestation: None,
+        })
+    }
+
+    /// Publish the attestation report of the TEE the key was generated in
+    pub fn with_attestation(mut self, attestation: String) -> Self {
+        self.attestation = Some(attestation);
+        self
+    }
+}
+
+#[async_trait]
+impl ResponseSigner for EthereumResponseSigner {
+    fn scheme(&self) -> SignatureScheme {
+        SignatureScheme::EthereumSecp256k1
+    }
+
+    fn signer(&self) -> String {
+        format!("{:?}", self.wallet.address())
+    }
+
+    async fn sign(&self, digest: &[u8; 32]) -> Result<ResponseSignature, OracleError> {
+        let signature = self
+            .wallet
+            .sign_message(digest)
+            .await
+            .map_err(|e| OracleError::Signature(format!("Failed to sign response: {}", e)))?;
+
+        Ok(ResponseSignature {
+            scheme: self.scheme(),
+            signer: self.signer(),
+            signature: hex::encode(signature.to_vec()),
+            digest: hex::encode(digest),
+            attestation: self.attestation.clone(),
+        })
+    }
+}
+
+/// Response signer configured from the environment.
+///
+/// `ORACLE_SIGNING_KEY` is the hex encoded private key, `ORACLE_SIGNING_SCHEME` selects
+/// `neo` (the default) or `ethereum`, and `ORACLE_SIGNER_ATTESTATION` is the optional
+/// attestation report of the TEE holding the key. Without a key, responses are not signed.
+pub fn response_signer_from_env() -> Result<Option<Arc<dyn ResponseSigner>>, OracleError> {
+    let Ok(private_key) = std::env::var("ORACLE_SIGNING_KEY") else {
+        return Ok(None);
+    };
+    let attestation = std::env::var("ORACLE_SIGNER_ATTESTATION").ok();
+
+    let scheme = std::env::var("ORACLE_SIGNING_SCHEME").unwrap_or_else(|_| "neo".to_string());
+    let signer: Arc<dyn ResponseSigner> = match scheme.as_str() {
+        "neo" => {
+            let signer = NeoResponseSigner::from_hex(&private_key)?;
+            Arc::new(match attestation {
+                Some(attestation) => signer.with_attestation(attestation),
+                None => signer,
+            })
+        }
+        "ethereum" => {
+            let signer = EthereumResponseSigner::from_hex(&private_key)?;
+            Arc::new(match attestation {
+                Some(attestation) => signer.with_attestation(attestation),
+                None => signer,
+            })
+        }
+        other => {
+            return Err(OracleError::Validation(format!(
+                "Unknown oracle signing scheme: {}",
+                other
+            )))
+        }
+    };
+
+    Ok(Some(signer))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PRIVATE_KEY: &str = "4c0883a69102937d6231471b5dbb6204fe5129617082792ae468d01a3f362318";
+
+    fn response() -> OracleResponse {
+        OracleResponse {
+            request_id: "request-1".to_string(),
+            data: r#"{"symbol":"NEO","price":12.5}"#.to_string(),
+            status_code: 200,
+            timestamp: 1_700_000_000,
+            error: None,
+            signature: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_sign_and_verify() {
+        let signers: Vec<Box<dyn ResponseSigner>> = vec![
+            Box::new(NeoResponseSigner::from_hex(PRIVATE_KEY).unwrap()),
+            Box::new(EthereumResponseSigner::from_hex(PRIVATE_KEY).unwrap()),
+        ];
+
+        for signer in signers {
+            let mut response = response();
+            sign_response(signer.as_ref(), &mut response).await.unwrap();
+            verify_response(&response).unwrap();
+            verify_response_from(&response, &signer.signer()).unwrap();
+
+            // Tampered data, a forged status or another signer are all rejected
+            let mut tampered = response.clone();
+            tampered.data = r#"{"symbol":"NEO","price":1250}"#.to_string();
+            assert!(verify_response(&tampered).is_err());
+
+            let mut forged = response.clone();
+            forged.status_code = 500;
+            forged.error = Some("forged".to_string());
+            assert!(verify_response(&forged).is_err());
+
+            assert!(verify_response_from(&response, "0x0000").is_err());
+        }
+    }
+
+    #[test]
+    fn test_response_digest_separates_fields() {
+        // The error is length prefixed, so it can not be moved into the data
+        let mut with_error = response();
+        with_error.error = Some("a|b".to_string());
+        with_error.data = String::new();
+
+        let mut in_data = response();
+        in_data.error = Some("a".to_string());
+        in_data.data = "b".to_string();
+
+        assert_ne!(response_digest(&with_error), response_digest(&in_data));
+        assert!(verify_response(&response()).is_err());
+    }
+}
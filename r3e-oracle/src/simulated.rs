@@ -0,0 +1,213 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+use std::collections::HashMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use async_trait::async_trait;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use tokio::sync::RwLock;
+
+use crate::types::{PriceRequest, PriceResponse, RandomRequest, RandomResponse};
+use crate::{
+    OracleError, OracleRequest, OracleRequestStatus, OracleRequestType, OracleResponse,
+    OracleService,
+};
+
+/// Oracle service answering every request locally, for development without data providers.
+///
+/// Prices come from a fixed table, random values from a local generator (seeded requests are
+/// reproducible), and other request types echo their request data back.
+pub struct SimulatedOracleService {
+    /// Prices in USD by symbol
+    prices: HashMap<String, f64>,
+
+    /// Requests by ID
+    requests: RwLock<HashMap<String, OracleRequest>>,
+
+    /// Responses by request ID
+    responses: RwLock<HashMap<String, OracleResponse>>,
+}
+
+impl SimulatedOracleService {
+    /// Create a new simulated oracle service with default NEO, GAS, BTC and ETH prices
+    pub fn new() -> Self {
+        let prices = [
+            ("NEO", 10.0),
+            ("GAS", 4.0),
+            ("BTC", 60000.0),
+            ("ETH", 3000.0),
+        ]
+        .into_iter()
+        .map(|(symbol, price)| (symbol.to_string(), price))
+        .collect();
+
+        Self {
+            prices,
+            requests: RwLock::new(HashMap::new()),
+            responses: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// Set the simulated price of a symbol
+    pub fn with_price(mut self, symbol: &str, price_usd: f64) -> Self {
+        self.prices.insert(symbol.to_uppercase(), price_usd);
+        self
+    }
+
+    fn respond(&self, request: &OracleRequest) -> Result<String, OracleError> {
+        let timestamp = now();
+        let data = match request.request_type {
+            OracleRequestType::Price => {
+                let price_request: PriceRequest =
+                    serde_json::from_str(&request.data).map_err(|e| {
+                        OracleError::Validation(format!("Invalid price request: {}", e))
+                    })?;
+                let price = self
+                    .prices
+                    .get(&price_request.symbol.to_uppercase())
+                    .copied()
+                    .ok_or_else(|| {
+                        OracleError::Provider(format!(
+                            "No simulated price for {}",
+                            price_request.symbol
+                        ))
+                    })?;
+
+                serde_json::to_value(PriceResponse {
+                    symbol: price_request.symbol,
+                    currency: price_request.currency,
+                    price,
+                    sources: vec!["simulated".to_string()],
+                    age: 0,
+                    timestamp,
+                })
+            }
+            OracleRequestType::Random => {
+                let random_request: RandomRequest =
+                    serde_json::from_str(&request.data).map_err(|e| {
+                        OracleError::Validation(format!("Invalid random request: {}", e))
+                    })?;
+                if random_request.min > random_request.max {
+                    return Err(OracleError::Validation(
+                        "min must not be greater than max".to_string(),
+                    ));
+                }
+
+                let mut rng = match &random_request.seed {
+                    Some(seed) => StdRng::from_seed(Sha256::digest(seed.as_bytes()).into()),
+                    None => StdRng::from_entropy(),
+                };
+                let values = (0..random_request.count)
+                    .map(|_| rng.gen_range(random_request.min..=random_request.max))
+                    .collect();
+
+                serde_json::to_value(RandomResponse {
+                    values,
+                    method: random_request.method,
+                    proof: None,
+                    timestamp,
+                })
+            }
+            _ => {
+                let data: serde_json::Value =
+                    serde_json::from_str(&request.data).unwrap_or(json!(request.data));
+                Ok(json!({ "simulated": true, "request": data }))
+            }
+        }
+        .map_err(|e| OracleError::Internal(format!("Failed to serialize response: {}", e)))?;
+
+        Ok(data.to_string())
+    }
+}
+
+impl Default for SimulatedOracleService {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl OracleService for SimulatedOracleService {
+    async fn submit_request(&self, request: OracleRequest) -> Result<String, OracleError> {
+        let response = match self.respond(&request) {
+            Ok(data) => OracleResponse {
+                request_id: request.id.clone(),
+                data,
+                status_code: 200,
+                timestamp: now(),
+                error: None,
+                signature: None,
+            },
+            Err(e) => OracleResponse {
+                request_id: request.id.clone(),
+                data: String::new(),
+                status_code: 500,
+                timestamp: now(),
+                error: Some(e.to_string()),
+                signature: None,
+            },
+        };
+
+        self.responses
+            .write()
+            .await
+            .insert(request.id.clone(), response);
+        let request_id = request.id.clone();
+        self.requests
+            .write()
+            .await
+            .insert(request_id.clone(), request);
+
+        Ok(request_id)
+    }
+
+    async fn get_request(&self, request_id: &str) -> Result<OracleRequest, OracleError> {
+        self.requests
+            .read()
+            .await
+            .get(request_id)
+            .cloned()
+            .ok_or_else(|| OracleError::Validation(format!("Unknown request: {}", request_id)))
+    }
+
+    async fn get_request_status(
+        &self,
+        request_id: &str,
+    ) -> Result<OracleRequestStatus, OracleError> {
+        let responses = self.responses.read().await;
+        let response = responses
+            .get(request_id)
+            .ok_or_else(|| OracleError::Validation(format!("Unknown request: {}", request_id)))?;
+
+        Ok(if response.error.is_some() {
+            OracleRequestStatus::Failed
+        } else {
+            OracleRequestStatus::Completed
+        })
+    }
+
+    async fn get_response(&self, request_id: &str) -> Result<OracleResponse, OracleError> {
+        self.responses
+            .read()
+            .await
+            .get(request_id)
+            .cloned()
+            .ok_or_else(|| OracleError::Validation(format!("Unknown request: {}", request_id)))
+    }
+
+    async fn cancel_request(&self, _request_id: &str) -> Result<bool, OracleError> {
+        // Requests are answered on submission, there is nothing left to cancel
+        Ok(false)
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
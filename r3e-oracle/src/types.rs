@@ -35,6 +35,10 @@ pub struct PriceRequest {
     /// Preferred sources (optional)
     #[serde(default)]
     pub sources: Vec<String>,
+
+    /// Maximum age in seconds of cached prices, the symbol's freshness SLA if not set
+    #[serde(default)]
+    pub max_age: Option<u64>,
 }
 
 fn default_currency() -> String {
@@ -56,6 +60,10 @@ pub struct PriceResponse {
     /// Price sources used
     pub sources: Vec<String>,
 
+    /// Seconds since the prices were fetched
+    #[serde(default)]
+    pub age: u64,
+
     /// Timestamp
     pub timestamp: u64,
 }
@@ -138,6 +146,14 @@ pub struct WeatherRequest {
     /// Weather data type
     #[serde(default)]
     pub data_type: WeatherDataType,
+
+    /// Number of days to forecast (1 to 16, default 7)
+    #[serde(default)]
+    pub days: Option<u8>,
+
+    /// Day of historical weather data (YYYY-MM-DD)
+    #[serde(default)]
+    pub date: Option<String>,
 }
 
 /// Weather data type
@@ -178,6 +194,75 @@ pub struct WeatherResponse {
     pub timestamp: u64,
 }
 
+/// Weather at a point in time
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WeatherObservation {
+    /// Observation time (ISO 8601, UTC)
+    pub time: String,
+
+    /// Temperature in degrees Celsius
+    pub temperature_c: Option<f64>,
+
+    /// Relative humidity in percent
+    pub humidity_percent: Option<f64>,
+
+    /// Precipitation in millimeters
+    pub precipitation_mm: Option<f64>,
+
+    /// Wind speed in kilometers per hour
+    pub wind_speed_kmh: Option<f64>,
+
+    /// WMO weather code
+    pub weather_code: Option<u32>,
+
+    /// Weather condition, such as `clear` or `rain`
+    pub condition: String,
+}
+
+/// Weather of a day
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DailyWeather {
+    /// Date (YYYY-MM-DD)
+    pub date: String,
+
+    /// Maximum temperature in degrees Celsius
+    pub temperature_max_c: Option<f64>,
+
+    /// Minimum temperature in degrees Celsius
+    pub temperature_min_c: Option<f64>,
+
+    /// Precipitation in millimeters
+    pub precipitation_mm: Option<f64>,
+
+    /// Maximum wind speed in kilometers per hour
+    pub wind_speed_max_kmh: Option<f64>,
+
+    /// WMO weather code
+    pub weather_code: Option<u32>,
+
+    /// Weather condition, such as `clear` or `rain`
+    pub condition: String,
+}
+
+/// Weather report, the data of weather responses whatever the source
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WeatherReport {
+    /// Resolved location name
+    pub location: String,
+
+    /// Latitude
+    pub latitude: f64,
+
+    /// Longitude
+    pub longitude: f64,
+
+    /// Current weather, for current weather requests
+    pub current: Option<WeatherObservation>,
+
+    /// Weather by day, for forecast and historical requests
+    pub daily: Vec<DailyWeather>,
+}
+
 /// Sports request parameters
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SportsRequest {
@@ -193,6 +278,10 @@ pub struct SportsRequest {
     /// Data type
     #[serde(default)]
     pub data_type: SportsDataType,
+
+    /// Season of standings (such as `2023-2024`), the current season if not set
+    #[serde(default)]
+    pub season: Option<String>,
 }
 
 /// Sports data type
@@ -243,6 +332,81 @@ pub struct SportsResponse {
     pub timestamp: u64,
 }
 
+/// Sports event, such as a match
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SportsEvent {
+    /// Event ID at the source
+    pub id: String,
+
+    /// Event name
+    pub name: String,
+
+    /// League
+    pub league: Option<String>,
+
+    /// Season
+    pub season: Option<String>,
+
+    /// Home team
+    pub home_team: Option<String>,
+
+    /// Away team
+    pub away_team: Option<String>,
+
+    /// Home team score, once the event started
+    pub home_score: Option<u32>,
+
+    /// Away team score, once the event started
+    pub away_score: Option<u32>,
+
+    /// Start time (ISO 8601, UTC)
+    pub start_time: Option<String>,
+
+    /// Event status, such as `Match Finished` or `Not Started`
+    pub status: Option<String>,
+}
+
+/// League standing of a team
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Standing {
+    /// Rank
+    pub rank: u32,
+
+    /// Team
+    pub team: String,
+
+    /// Games played
+    pub played: u32,
+
+    /// Games won
+    pub won: u32,
+
+    /// Games drawn
+    pub drawn: u32,
+
+    /// Games lost
+    pub lost: u32,
+
+    /// Goals or points scored
+    pub goals_for: u32,
+
+    /// Goals or points conceded
+    pub goals_against: u32,
+
+    /// League points
+    pub points: u32,
+}
+
+/// Sports report, the data of sports responses whatever the source
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SportsReport {
+    /// Events, for scores and schedule requests
+    pub events: Vec<SportsEvent>,
+
+    /// Standings, for standings requests
+    pub standings: Vec<Standing>,
+}
+
 /// Custom request parameters
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CustomRequest {
@@ -0,0 +1,604 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+//! Two-phase approval of high-value admin operations.
+//!
+//! An operation is proposed with its parameters and gets a canonical message and digest.
+//! The message is exported and signed offline with an approver's P-256 key, and the
+//! operation runs once the signature is submitted. Every step is recorded in the audit log.
+
+use async_trait::async_trait;
+use p256::ecdsa::signature::Verifier;
+use p256::ecdsa::{Signature, VerifyingKey};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use thiserror::Error;
+use tokio::sync::Mutex;
+use uuid::Uuid;
+
+use crate::audit::{AuditEvent, AuditEventType, AuditStore};
+use crate::SecretError;
+
+/// Domain separating operation messages from anything else an approver key signs
+pub const MESSAGE_DOMAIN: &str = "r3e-faas:admin-operation:v1";
+
+/// Default time an operation can be approved in (in seconds)
+pub const DEFAULT_OPERATION_TTL: u64 = 24 * 3600;
+
+/// Approval error
+#[derive(Debug, Error)]
+pub enum ApprovalError {
+    #[error("approval: operation not found: {0}")]
+    NotFound(String),
+
+    #[error("approval: unknown operation kind: {0}")]
+    UnknownKind(String),
+
+    #[error("approval: invalid parameters: {0}")]
+    InvalidParams(String),
+
+    #[error("approval: operation {0} is {1:?}")]
+    InvalidState(String, OperationStatus),
+
+    #[error("approval: operation expired: {0}")]
+    Expired(String),
+
+    #[error("approval: invalid signature: {0}")]
+    InvalidSignature(String),
+
+    #[error("approval: invalid approver key: {0}")]
+    InvalidKey(String),
+
+    #[error("approval: storage error: {0}")]
+    Storage(String),
+
+    #[error("approval: audit error: {0}")]
+    Audit(#[from] SecretError),
+}
+
+/// Status of an operation
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OperationStatus {
+    /// Waiting for an approver's signature
+    Pending,
+
+    /// Approved and running; an operation left here by a crash needs manual review
+    Executing,
+
+    /// Ran successfully
+    Executed,
+
+    /// Ran and failed
+    Failed,
+
+    /// Cancelled before approval
+    Cancelled,
+
+    /// Not approved in time
+    Expired,
+}
+
+/// Admin operation awaiting or after approval
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PendingOperation {
+    /// Operation ID
+    pub id: String,
+
+    /// Operation kind, e.g. `gas_bank.withdraw`
+    pub kind: String,
+
+    /// Operation parameters
+    pub params: Value,
+
+    /// User who proposed the operation
+    pub proposed_by: String,
+
+    /// Creation timestamp (secs since epoch)
+    pub created_at: u64,
+
+    /// Time the operation must be approved by (secs since epoch)
+    pub expires_at: u64,
+
+    /// Hex encoded SHA-256 digest of the signing message
+    pub digest: String,
+
+    /// Status
+    pub status: OperationStatus,
+
+    /// Hex encoded compressed public key of the approver
+    pub approved_by: Option<String>,
+
+    /// Hex encoded approver signature
+    pub signature: Option<String>,
+
+    /// Approval timestamp (secs since epoch)
+    pub approved_at: Option<u64>,
+
+    /// Result of the operation
+    pub result: Option<Value>,
+
+    /// Error of a failed operation
+    pub error: Option<String>,
+}
+
+impl PendingOperation {
+    /// Canonical message approvers sign: compact JSON with sorted keys
+    pub fn signing_message(&self) -> String {
+        canonical_json(&serde_json::json!({
+            "domain": MESSAGE_DOMAIN,
+            "id": self.id,
+            "kind": self.kind,
+            "params": self.params,
+            "proposed_by": self.proposed_by,
+            "expires_at": self.expires_at,
+        }))
+    }
+
+    /// Status as of a time, pending operations past their deadline are expired
+    pub fn status_at(&self, now: u64) -> OperationStatus {
+        match self.status {
+            OperationStatus::Pending if now >= self.expires_at => OperationStatus::Expired,
+            status => status,
+        }
+    }
+}
+
+/// Operation exported for offline signing
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SigningRequest {
+    /// Operation ID
+    pub id: String,
+
+    /// Operation kind
+    pub kind: String,
+
+    /// Message to sign with ECDSA P-256 over SHA-256
+    pub message: String,
+
+    /// Hex encoded SHA-256 digest of the message, to compare on the signing device
+    pub digest: String,
+
+    /// Time the operation must be approved by (secs since epoch)
+    pub expires_at: u64,
+}
+
+/// JSON with object keys sorted and no whitespace, so equal values encode identically
+pub fn canonical_json(value: &Value) -> String {
+    match value {
+        Value::Object(map) => {
+            let mut entries: Vec<_> = map.iter().collect();
+            entries.sort_by(|a, b| a.0.cmp(b.0));
+            let entries: Vec<String> = entries
+                .into_iter()
+                .map(|(key, value)| {
+                    format!("{}:{}", Value::from(key.as_str()), canonical_json(value))
+                })
+                .collect();
+            format!("{{{}}}", entries.join(","))
+        }
+        Value::Array(items) => {
+            let items: Vec<String> = items.iter().map(canonical_json).collect();
+            format!("[{}]", items.join(","))
+        }
+        value => value.to_string(),
+    }
+}
+
+/// Executor of one kind of operation
+#[async_trait]
+pub trait OperationExecutor: Send + Sync {
+    /// Check the parameters of an operation when it is proposed
+    fn validate(&self, params: &Value) -> Result<(), String>;
+
+    /// Run an approved operation, returning its result
+    async fn execute(&self, params: &Value) -> Result<Value, String>;
+}
+
+/// Persistent operations
+#[async_trait]
+pub trait OperationStore: Send + Sync {
+    /// Insert or replace an operation
+    async fn put(&self, operation: &PendingOperation) -> Result<(), ApprovalError>;
+
+    /// Get an operation
+    async fn get(&self, id: &str) -> Result<Option<PendingOperation>, ApprovalError>;
+
+    /// List all operations
+    async fn list(&self) -> Result<Vec<PendingOperation>, ApprovalError>;
+}
+
+/// Memory-based operation store
+#[derive(Default)]
+pub struct MemoryOperationStore {
+    /// Operations by ID
+    operations: Mutex<HashMap<String, PendingOperation>>,
+}
+
+impl MemoryOperationStore {
+    /// Create a new memory-based operation store
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl OperationStore for MemoryOperationStore {
+    async fn put(&self, operation: &PendingOperation) -> Result<(), ApprovalError> {
+        self.operations
+            .lock()
+            .await
+            .insert(operation.id.clone(), operation.clone());
+        Ok(())
+    }
+
+    async fn get(&self, id: &str) -> Result<Option<PendingOperation>, ApprovalError> {
+        Ok(self.operations.lock().await.get(id).cloned())
+    }
+
+    async fn list(&self) -> Result<Vec<PendingOperation>, ApprovalError> {
+        Ok(self.operations.lock().await.values().cloned().collect())
+    }
+}
+
+/// Approval workflow of admin operations
+pub struct ApprovalService {
+    /// Operation store
+    store: Arc<dyn OperationStore>,
+
+    /// Audit log recording every step
+    audit_store: Arc<dyn AuditStore>,
+
+    /// Keys allowed to approve, by their hex encoded compressed form
+    approvers: Vec<(String, VerifyingKey)>,
+
+    /// Executors by operation kind
+    executors: HashMap<String, Arc<dyn OperationExecutor>>,
+
+    /// Time an operation can be approved in (in seconds)
+    ttl: u64,
+
+    /// Serializes status transitions, so an operation runs at most once
+    lock: Mutex<()>,
+}
+
+impl ApprovalService {
+    /// Create a new approval service accepting signatures of the given hex encoded SEC1
+    /// public keys
+    pub fn new(
+        store: Arc<dyn OperationStore>,
+        audit_store: Arc<dyn AuditStore>,
+        approver_keys: &[String],
+    ) -> Result<Self, ApprovalError> {
+        let approvers = approver_keys
+            .iter()
+            .map(|key| {
+                let bytes = hex::decode(key.trim_start_matches("0x"))
+                    .map_err(|e| ApprovalError::InvalidKey(format!("{}: {}", key, e)))?;
+                let key = VerifyingKey::from_sec1_bytes(&bytes)
+                    .map_err(|e| ApprovalError::InvalidKey(format!("{}: {}", key, e)))?;
+                Ok((hex::encode(key.to_encoded_point(true).as_bytes()), key))
+            })
+            .collect::<Result<_, ApprovalError>>()?;
+
+        Ok(Self {
+            store,
+            audit_store,
+            approvers,
+            executors: HashMap::new(),
+            ttl: DEFAULT_OPERATION_TTL,
+            lock: Mutex::new(()),
+        })
+    }
+
+    /// Register the executor of a kind of operation
+    pub fn with_executor(
+        mut self,
+        kind: impl Into<String>,
+        executor: Arc<dyn OperationExecutor>,
+    ) -> Self {
+        self.executors.insert(kind.into(), executor);
+        self
+    }
+
+    /// Set the time an operation can be approved in (in seconds)
+    pub fn with_ttl(mut self, ttl: u64) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// Propose an operation
+    pub async fn propose(
+        &self,
+        kind: &str,
+        params: Value,
+        proposed_by: &str,
+    ) -> Result<PendingOperation, ApprovalError> {
+        let executor = self
+            .executors
+            .get(kind)
+            .ok_or_else(|| ApprovalError::UnknownKind(kind.to_string()))?;
+        executor
+            .validate(&params)
+            .map_err(ApprovalError::InvalidParams)?;
+
+        let now = now();
+        let mut operation = PendingOperation {
+            id: Uuid::new_v4().to_string(),
+            kind: kind.to_string(),
+            params,
+            proposed_by: proposed_by.to_string(),
+            created_at: now,
+            expires_at: now + self.ttl,
+            digest: String::new(),
+            status: OperationStatus::Pending,
+            approved_by: None,
+            signature: None,
+            approved_at: None,
+            result: None,
+            error: None,
+        };
+        operation.digest = hex::encode(Sha256::digest(operation.signing_message()));
+
+        self.audit(
+            AuditEventType::OperationProposed,
+            proposed_by,
+            &operation,
+            canonical_json(&operation.params),
+        )
+        .await?;
+        self.store.put(&operation).await?;
+
+        Ok(operation)
+    }
+
+    /// Get an operation
+    pub async fn get(&self, id: &str) -> Result<PendingOperation, ApprovalError> {
+        let mut operation = self
+            .store
+            .get(id)
+            .await?
+            .ok_or_else(|| ApprovalError::NotFound(id.to_string()))?;
+        operation.status = operation.status_at(now());
+        Ok(operation)
+    }
+
+    /// List operations, newest first
+    pub async fn list(
+        &self,
+        status: Option<OperationStatus>,
+    ) -> Result<Vec<PendingOperation>, ApprovalError> {
+        let now = now();
+        let mut operations: Vec<PendingOperation> = self
+            .store
+            .list()
+            .await?
+            .into_iter()
+            .map(|mut operation| {
+                operation.status = operation.status_at(now);
+                operation
+            })
+            .filter(|operation| status.is_none_or(|status| operation.status == status))
+            .collect();
+        operations.sort_by_key(|operation| std::cmp::Reverse(operation.created_at));
+        Ok(operations)
+    }
+
+    /// Export a pending operation for offline signing
+    pub async fn signing_request(
+        &self,
+        id: &str,
+        exported_by: &str,
+    ) -> Result<SigningRequest, ApprovalError> {
+        let operation = self.get(id).await?;
+        if operation.status != OperationStatus::Pending {
+            return Err(ApprovalError::InvalidState(
+                id.to_string(),
+                operation.status,
+            ));
+        }
+
+        self.audit(
+            AuditEventType::OperationExported,
+            exported_by,
+            &operation,
+            String::new(),
+        )
+        .await?;
+
+        Ok(SigningRequest {
+            id: operation.id.clone(),
+            kind: operation.kind.clone(),
+            message: operation.signing_message(),
+            digest: operation.digest,
+            expires_at: operation.expires_at,
+        })
+    }
+
+    /// Approve a pending operation with an approver's signature of its message, then run it.
+    ///
+    /// The signature is hex encoded, as 64-byte `r || s` or DER. A failed run is recorded
+    /// on the operation and not retried.
+    pub async fn approve(
+        &self,
+        id: &str,
+        signature: &str,
+        submitted_by: &str,
+    ) -> Result<PendingOperation, ApprovalError> {
+        let mut operation = {
+            let _guard = self.lock.lock().await;
+            let mut operation = self.get(id).await?;
+            match operation.status {
+                OperationStatus::Pending => {}
+                OperationStatus::Expired => {
+                    self.reject(submitted_by, &operation, "operation expired")
+                        .await?;
+                    return Err(ApprovalError::Expired(id.to_string()));
+                }
+                status => return Err(ApprovalError::InvalidState(id.to_string(), status)),
+            }
+
+            let approver = match self.verify(&operation, signature) {
+                Ok(approver) => approver,
+                Err(e) => {
+                    self.reject(submitted_by, &operation, &e.to_string())
+                        .await?;
+                    return Err(e);
+                }
+            };
+
+            operation.status = OperationStatus::Executing;
+            operation.approved_by = Some(approver.clone());
+            operation.signature = Some(signature.to_string());
+            operation.approved_at = Some(now());
+            self.audit(
+                AuditEventType::OperationApproved,
+                submitted_by,
+                &operation,
+                format!("approver {}", approver),
+            )
+            .await?;
+            self.store.put(&operation).await?;
+            operation
+        };
+
+        let executor = self
+            .executors
+            .get(&operation.kind)
+            .ok_or_else(|| ApprovalError::UnknownKind(operation.kind.clone()))?;
+        match executor.execute(&operation.params).await {
+            Ok(result) => {
+                operation.status = OperationStatus::Executed;
+                self.audit(
+                    AuditEventType::OperationExecuted,
+                    submitted_by,
+                    &operation,
+                    canonical_json(&result),
+                )
+                .await?;
+                operation.result = Some(result);
+            }
+            Err(error) => {
+                operation.status = OperationStatus::Failed;
+                self.audit(
+                    AuditEventType::OperationFailed,
+                    submitted_by,
+                    &operation,
+                    error.clone(),
+                )
+                .await?;
+                operation.error = Some(error);
+            }
+        }
+        self.store.put(&operation).await?;
+
+        Ok(operation)
+    }
+
+    /// Cancel a pending operation
+    pub async fn cancel(
+        &self,
+        id: &str,
+        cancelled_by: &str,
+    ) -> Result<PendingOperation, ApprovalError> {
+        let _guard = self.lock.lock().await;
+        let mut operation = self.get(id).await?;
+        if operation.status != OperationStatus::Pending {
+            return Err(ApprovalError::InvalidState(
+                id.to_string(),
+                operation.status,
+            ));
+        }
+
+        operation.status = OperationStatus::Cancelled;
+        self.audit(
+            AuditEventType::OperationCancelled,
+            cancelled_by,
+            &operation,
+            String::new(),
+        )
+        .await?;
+        self.store.put(&operation).await?;
+
+        Ok(operation)
+    }
+
+    /// Approver whose key signed the operation's message
+    fn verify(
+        &self,
+        operation: &PendingOperation,
+        signature: &str,
+    ) -> Result<String, ApprovalError> {
+        let bytes = hex::decode(signature.trim_start_matches("0x"))
+            .map_err(|e| ApprovalError::InvalidSignature(e.to_string()))?;
+        let signature = match bytes.len() {
+            64 => Signature::from_slice(&bytes),
+            _ => Signature::from_der(&bytes),
+        }
+        .map_err(|e| ApprovalError::InvalidSignature(e.to_string()))?;
+
+        let message = operation.signing_message();
+        self.approvers
+            .iter()
+            .find(|(_, key)| key.verify(message.as_bytes(), &signature).is_ok())
+            .map(|(approver, _)| approver.clone())
+            .ok_or_else(|| {
+                ApprovalError::InvalidSignature("not signed by an approver key".to_string())
+            })
+    }
+
+    async fn reject(
+        &self,
+        submitted_by: &str,
+        operation: &PendingOperation,
+        reason: &str,
+    ) -> Result<(), ApprovalError> {
+        self.audit(
+            AuditEventType::OperationRejected,
+            submitted_by,
+            operation,
+            reason.to_string(),
+        )
+        .await
+    }
+
+    async fn audit(
+        &self,
+        event_type: AuditEventType,
+        user_id: &str,
+        operation: &PendingOperation,
+        details: String,
+    ) -> Result<(), ApprovalError> {
+        let details = format!(
+            "{} {} digest {}{}{}",
+            operation.kind,
+            operation.id,
+            operation.digest,
+            if details.is_empty() { "" } else { ": " },
+            details
+        );
+        self.audit_store
+            .append(AuditEvent::new(
+                event_type,
+                user_id.to_string(),
+                None,
+                None,
+                details,
+                None,
+                None,
+            ))
+            .await?;
+        Ok(())
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
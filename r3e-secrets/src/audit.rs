@@ -1,10 +1,21 @@
 // Copyright @ 2023 - 2024, R3E Network
 // All Rights Reserved
 
+use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::VecDeque;
+use std::convert::Infallible;
+use std::future::Future;
 use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::sync::Mutex;
 use uuid::Uuid;
 
+use crate::SecretError;
+
+/// Previous hash of the first record in a chain
+pub const GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
 /// Audit event type
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum AuditEventType {
@@ -23,11 +34,79 @@ pub enum AuditEventType {
     /// Secret deleted
     SecretDeleted,
 
+    /// Secret imported from a bulk import bundle
+    SecretImported,
+
+    /// Secret exported in a bulk export bundle
+    SecretExported,
+
     /// Vault key rotated
     VaultKeyRotated,
 
     /// Unauthorized access attempt
     UnauthorizedAccess,
+
+    /// API request handled
+    ApiRequest,
+
+    /// Administrative action performed
+    AdminAction,
+
+    /// Admin operation proposed for approval
+    OperationProposed,
+
+    /// Admin operation exported for offline signing
+    OperationExported,
+
+    /// Admin operation approved by an approver's signature
+    OperationApproved,
+
+    /// Approval of an admin operation rejected
+    OperationRejected,
+
+    /// Approved admin operation ran successfully
+    OperationExecuted,
+
+    /// Approved admin operation failed
+    OperationFailed,
+
+    /// Admin operation cancelled before approval
+    OperationCancelled,
+}
+
+/// Context of the request being handled, attached to every audit event recorded while it runs
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuditContext {
+    /// Request ID
+    pub request_id: Option<String>,
+
+    /// ID of the authenticated caller
+    pub user_id: Option<String>,
+
+    /// Source IP address
+    pub source_ip: Option<String>,
+
+    /// User agent
+    pub user_agent: Option<String>,
+}
+
+tokio::task_local! {
+    static AUDIT_CONTEXT: AuditContext;
+}
+
+impl AuditContext {
+    /// Run a future with this context as the current one
+    pub async fn scope<F: Future>(self, f: F) -> F::Output {
+        AUDIT_CONTEXT.scope(self, f).await
+    }
+
+    /// Context of the current task, if it runs within a scope
+    ///
+    /// Spawned tasks do not inherit the context, capture it before spawning and `scope` the
+    /// spawned future with it.
+    pub fn current() -> Option<AuditContext> {
+        AUDIT_CONTEXT.try_with(Clone::clone).ok()
+    }
 }
 
 /// Audit event
@@ -59,10 +138,17 @@ pub struct AuditEvent {
 
     /// User agent
     pub user_agent: Option<String>,
+
+    /// ID of the request the event was recorded for
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
 }
 
 impl AuditEvent {
     /// Create a new audit event
+    ///
+    /// The request ID is taken from the current [`AuditContext`], as are the source IP and
+    /// user agent when not given.
     pub fn new(
         event_type: AuditEventType,
         user_id: String,
@@ -77,6 +163,7 @@ impl AuditEvent {
             .duration_since(UNIX_EPOCH)
             .unwrap_or_default()
             .as_secs();
+        let context = AuditContext::current().unwrap_or_default();
 
         Self {
             id,
@@ -86,8 +173,9 @@ impl AuditEvent {
             secret_id,
             timestamp,
             details,
-            source_ip,
-            user_agent,
+            source_ip: source_ip.or(context.source_ip),
+            user_agent: user_agent.or(context.user_agent),
+            request_id: context.request_id,
         }
     }
 }
@@ -151,3 +239,284 @@ impl AuditLogger for MemoryAuditLogger {
         }
     }
 }
+
+/// Audit event chained to its predecessor by hash
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditRecord {
+    /// Position in the chain, starting at 0
+    pub sequence: u64,
+
+    /// Audit event
+    pub event: AuditEvent,
+
+    /// Hash of the previous record, `GENESIS_HASH` for the first
+    pub prev_hash: String,
+
+    /// Hex encoded SHA-256 over sequence, previous hash and event
+    pub hash: String,
+}
+
+impl AuditRecord {
+    /// Append an event after the record with the given sequence and hash
+    pub fn chain(sequence: u64, prev_hash: String, event: AuditEvent) -> Self {
+        let mut record = Self {
+            sequence,
+            event,
+            prev_hash,
+            hash: String::new(),
+        };
+        record.hash = record.compute_hash();
+        record
+    }
+
+    /// Recompute the hash of the record from its content
+    pub fn compute_hash(&self) -> String {
+        let content =
+            serde_json::to_vec(&(self.sequence, &self.prev_hash, &self.event)).unwrap_or_default();
+        hex::encode(Sha256::digest(&content))
+    }
+}
+
+/// Audit query filter, unset fields match everything
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AuditFilter {
+    /// User ID
+    pub user_id: Option<String>,
+
+    /// Function ID
+    pub function_id: Option<String>,
+
+    /// Secret ID
+    pub secret_id: Option<String>,
+
+    /// Event type
+    pub event_type: Option<AuditEventType>,
+
+    /// Request ID
+    pub request_id: Option<String>,
+
+    /// Earliest timestamp, inclusive
+    pub from: Option<u64>,
+
+    /// Latest timestamp, inclusive
+    pub to: Option<u64>,
+
+    /// Maximum number of records, newest first
+    pub limit: Option<usize>,
+}
+
+impl AuditFilter {
+    /// Check if an event matches the filter
+    pub fn matches(&self, event: &AuditEvent) -> bool {
+        self.user_id.as_deref().map_or(true, |u| event.user_id == u)
+            && self
+                .function_id
+                .as_deref()
+                .map_or(true, |f| event.function_id.as_deref() == Some(f))
+            && self
+                .secret_id
+                .as_deref()
+                .map_or(true, |s| event.secret_id.as_deref() == Some(s))
+            && self.event_type.map_or(true, |t| event.event_type == t)
+            && self
+                .request_id
+                .as_deref()
+                .map_or(true, |r| event.request_id.as_deref() == Some(r))
+            && self.from.map_or(true, |from| event.timestamp >= from)
+            && self.to.map_or(true, |to| event.timestamp <= to)
+    }
+
+    /// Select the newest matching records from records in chain order
+    pub fn select<I>(&self, records: I) -> Vec<AuditRecord>
+    where
+        I: IntoIterator<Item = AuditRecord>,
+    {
+        match self.try_select(records.into_iter().map(Ok::<_, Infallible>)) {
+            Ok(selected) => selected,
+            Err(never) => match never {},
+        }
+    }
+
+    /// Select the newest matching records from a stream of records in chain order, holding no
+    /// more than `limit` of them at a time and stopping at the first error
+    pub fn try_select<I, E>(&self, records: I) -> Result<Vec<AuditRecord>, E>
+    where
+        I: IntoIterator<Item = Result<AuditRecord, E>>,
+    {
+        let limit = self.limit.unwrap_or(usize::MAX);
+        let mut selected = VecDeque::new();
+        for record in records {
+            let record = record?;
+            if limit == 0 || !self.matches(&record.event) {
+                continue;
+            }
+            if selected.len() == limit {
+                selected.pop_front();
+            }
+            selected.push_back(record);
+        }
+
+        Ok(selected.into_iter().rev().collect())
+    }
+}
+
+/// Result of verifying an audit chain
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditVerification {
+    /// Whether every record links to its predecessor and matches its hash
+    pub valid: bool,
+
+    /// Number of records checked
+    pub records_checked: u64,
+
+    /// Hash of the last valid record
+    pub head_hash: String,
+
+    /// Sequence of the first record failing verification
+    pub first_invalid: Option<u64>,
+
+    /// Reason the record failed verification
+    pub error: Option<String>,
+}
+
+/// Incremental audit chain verifier, fed records in chain order
+pub struct ChainVerifier {
+    /// Next expected sequence
+    next_sequence: u64,
+
+    /// Hash of the last valid record
+    prev_hash: String,
+
+    /// First failure
+    failure: Option<(u64, String)>,
+}
+
+impl ChainVerifier {
+    /// Create a verifier for a chain starting at the genesis hash
+    pub fn new() -> Self {
+        Self {
+            next_sequence: 0,
+            prev_hash: GENESIS_HASH.to_string(),
+            failure: None,
+        }
+    }
+
+    /// Check the next record, returning false once verification has failed
+    pub fn check(&mut self, record: &AuditRecord) -> bool {
+        if self.failure.is_some() {
+            return false;
+        }
+
+        let error = if record.sequence != self.next_sequence {
+            Some(format!(
+                "expected sequence {}, found {}",
+                self.next_sequence, record.sequence
+            ))
+        } else if record.prev_hash != self.prev_hash {
+            Some("previous hash does not match".to_string())
+        } else if record.hash != record.compute_hash() {
+            Some("record hash does not match its content".to_string())
+        } else {
+            None
+        };
+
+        match error {
+            Some(error) => {
+                self.failure = Some((self.next_sequence, error));
+                false
+            }
+            None => {
+                self.next_sequence += 1;
+                self.prev_hash = record.hash.clone();
+                true
+            }
+        }
+    }
+
+    /// Finish verification
+    pub fn finish(self) -> AuditVerification {
+        let (first_invalid, error) = match self.failure {
+            Some((sequence, error)) => (Some(sequence), Some(error)),
+            None => (None, None),
+        };
+
+        AuditVerification {
+            valid: first_invalid.is_none(),
+            records_checked: self.next_sequence + first_invalid.map_or(0, |_| 1),
+            head_hash: self.prev_hash,
+            first_invalid,
+            error,
+        }
+    }
+}
+
+impl Default for ChainVerifier {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Persistent, hash-chained audit log
+#[async_trait]
+pub trait AuditStore: Send + Sync {
+    /// Append an event to the chain
+    async fn append(&self, event: AuditEvent) -> Result<AuditRecord, SecretError>;
+
+    /// Query records, newest first
+    async fn query(&self, filter: &AuditFilter) -> Result<Vec<AuditRecord>, SecretError>;
+
+    /// Verify the whole chain
+    async fn verify(&self) -> Result<AuditVerification, SecretError>;
+}
+
+/// Memory-based audit store
+pub struct MemoryAuditStore {
+    /// Records in chain order
+    records: Mutex<Vec<AuditRecord>>,
+}
+
+impl MemoryAuditStore {
+    /// Create a new memory-based audit store
+    pub fn new() -> Self {
+        Self {
+            records: Mutex::new(Vec::new()),
+        }
+    }
+}
+
+impl Default for MemoryAuditStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl AuditStore for MemoryAuditStore {
+    async fn append(&self, event: AuditEvent) -> Result<AuditRecord, SecretError> {
+        let mut records = self.records.lock().await;
+        let prev_hash = records
+            .last()
+            .map(|record| record.hash.clone())
+            .unwrap_or_else(|| GENESIS_HASH.to_string());
+        let record = AuditRecord::chain(records.len() as u64, prev_hash, event);
+
+        records.push(record.clone());
+        Ok(record)
+    }
+
+    async fn query(&self, filter: &AuditFilter) -> Result<Vec<AuditRecord>, SecretError> {
+        let records = self.records.lock().await;
+        Ok(filter.select(records.iter().cloned()))
+    }
+
+    async fn verify(&self) -> Result<AuditVerification, SecretError> {
+        let records = self.records.lock().await;
+        let mut verifier = ChainVerifier::new();
+        for record in records.iter() {
+            if !verifier.check(record) {
+                break;
+            }
+        }
+        Ok(verifier.finish())
+    }
+}
@@ -0,0 +1,68 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+//! Backup encryption key held in the secret vault.
+
+use async_trait::async_trait;
+use r3e_store::backup::{BackupError, BackupKeyProvider};
+use std::sync::Arc;
+
+use crate::vault::VaultService;
+
+/// Backup key read from the vault, as 32 raw bytes or 64 hex characters.
+///
+/// The key is fetched for every backup and restore, so rotating the secret only affects
+/// backups taken afterwards; keep the old value to restore older backups.
+pub struct VaultBackupKey {
+    vault: Arc<dyn VaultService>,
+    user_id: String,
+    function_id: String,
+    secret_id: String,
+}
+
+impl VaultBackupKey {
+    /// Create a provider of the key stored in a vault secret
+    pub fn new(
+        vault: Arc<dyn VaultService>,
+        user_id: impl Into<String>,
+        function_id: impl Into<String>,
+        secret_id: impl Into<String>,
+    ) -> Self {
+        Self {
+            vault,
+            user_id: user_id.into(),
+            function_id: function_id.into(),
+            secret_id: secret_id.into(),
+        }
+    }
+}
+
+#[async_trait]
+impl BackupKeyProvider for VaultBackupKey {
+    async fn backup_key(&self) -> Result<[u8; 32], BackupError> {
+        let value = self
+            .vault
+            .get_secret(&self.user_id, &self.function_id, &self.secret_id)
+            .await
+            .map_err(|e| BackupError::Key(e.to_string()))?;
+        parse_key(&value)
+    }
+}
+
+fn parse_key(value: &[u8]) -> Result<[u8; 32], BackupError> {
+    let key = match value.len() {
+        32 => value.to_vec(),
+        64 => {
+            hex::decode(value).map_err(|e| BackupError::Key(format!("invalid hex key: {}", e)))?
+        }
+        len => {
+            return Err(BackupError::Key(format!(
+                "expected a 32 byte key, got {} bytes",
+                len
+            )))
+        }
+    };
+    let mut out = [0u8; 32];
+    out.copy_from_slice(&key);
+    Ok(out)
+}
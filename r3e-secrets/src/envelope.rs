@@ -0,0 +1,482 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+//! Envelope encryption of tenant data at rest.
+//!
+//! Tenants enrolled for encryption at rest get a key encryption key (KEK) of their own. Every
+//! value is encrypted with a fresh data key (DEK), and only the DEK wrapped by the tenant's KEK
+//! is stored next to the ciphertext, together with the KEK identifier. Rotating a tenant's KEK
+//! re-wraps the DEKs without touching the ciphertexts.
+//!
+//! KEKs are held by a [`KeyWrapper`]: [`LocalKeyWrapper`] derives them from a master key, other
+//! wrappers keep them in a KMS such as the TEE's, and never hand them out.
+
+use aes_gcm::{
+    aead::{Aead, KeyInit, Payload},
+    Aes256Gcm, Key, Nonce,
+};
+use async_trait::async_trait;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use thiserror::Error;
+use tokio::sync::RwLock;
+use uuid::Uuid;
+
+/// Prefix of envelopes encoded as text
+pub const ENVELOPE_PREFIX: &str = "r3e-envelope:v1:";
+
+/// Provider of KEKs derived from the master key
+pub const LOCAL_PROVIDER: &str = "local";
+
+/// Error type for envelope encryption
+#[derive(Debug, Error)]
+pub enum EnvelopeError {
+    /// Tenant has no KEK
+    #[error("envelope: tenant is not enrolled: {0}")]
+    NotEnrolled(String),
+
+    /// No wrapper is registered for the provider
+    #[error("envelope: unknown key provider: {0}")]
+    UnknownProvider(String),
+
+    /// Envelope cannot be decoded
+    #[error("envelope: malformed envelope: {0}")]
+    Malformed(String),
+
+    /// Encryption or decryption failed
+    #[error("envelope: crypto error: {0}")]
+    Crypto(String),
+
+    /// KEK provider failure
+    #[error("envelope: key provider error: {0}")]
+    Provider(String),
+
+    /// Storage failure
+    #[error("envelope: storage error: {0}")]
+    Storage(String),
+}
+
+/// Holder of KEKs, wrapping and unwrapping data keys with them
+#[async_trait]
+pub trait KeyWrapper: Send + Sync {
+    /// Create a KEK for a tenant, returning its identifier
+    async fn create_kek(&self, tenant: &str) -> Result<String, EnvelopeError>;
+
+    /// Wrap a data key with a KEK
+    async fn wrap(&self, kek_id: &str, dek: &[u8]) -> Result<Vec<u8>, EnvelopeError>;
+
+    /// Unwrap a data key wrapped with a KEK
+    async fn unwrap(&self, kek_id: &str, wrapped: &[u8]) -> Result<Vec<u8>, EnvelopeError>;
+}
+
+/// KEKs derived from a master key and their identifier, so only the master key needs keeping
+pub struct LocalKeyWrapper {
+    /// Master key
+    master_key: [u8; 32],
+}
+
+impl LocalKeyWrapper {
+    /// Create a new local key wrapper
+    pub fn new(master_key: [u8; 32]) -> Self {
+        Self { master_key }
+    }
+
+    fn kek(&self, kek_id: &str) -> Aes256Gcm {
+        let kek = Sha256::new()
+            .chain_update(b"r3e-faas:tenant-kek:v1")
+            .chain_update(self.master_key)
+            .chain_update(kek_id.as_bytes())
+            .finalize();
+        Aes256Gcm::new(&kek)
+    }
+}
+
+#[async_trait]
+impl KeyWrapper for LocalKeyWrapper {
+    async fn create_kek(&self, tenant: &str) -> Result<String, EnvelopeError> {
+        Ok(format!("{}/{}", tenant, Uuid::new_v4()))
+    }
+
+    async fn wrap(&self, kek_id: &str, dek: &[u8]) -> Result<Vec<u8>, EnvelopeError> {
+        // The KEK identifier is authenticated, a DEK cannot be moved to another tenant
+        let (ciphertext, nonce) = seal(&self.kek(kek_id), dek, kek_id.as_bytes())?;
+        Ok([nonce, ciphertext].concat())
+    }
+
+    async fn unwrap(&self, kek_id: &str, wrapped: &[u8]) -> Result<Vec<u8>, EnvelopeError> {
+        if wrapped.len() < 12 {
+            return Err(EnvelopeError::Malformed(
+                "wrapped key is too short".to_string(),
+            ));
+        }
+        let (nonce, ciphertext) = wrapped.split_at(12);
+        open(&self.kek(kek_id), ciphertext, nonce, kek_id.as_bytes())
+    }
+}
+
+/// KEK of a tenant
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TenantKey {
+    /// Tenant
+    pub tenant: String,
+
+    /// Provider holding the KEK
+    pub provider: String,
+
+    /// Identifier of the current KEK
+    pub kek_id: String,
+
+    /// Identifiers of the previous KEKs, still opening data not re-keyed yet
+    #[serde(default)]
+    pub retired_kek_ids: Vec<String>,
+
+    /// Time the current KEK was created (secs since epoch)
+    pub created_at: u64,
+}
+
+/// Store of the tenants' KEK identifiers
+#[async_trait]
+pub trait TenantKeyStore: Send + Sync {
+    /// KEK of a tenant
+    async fn get(&self, tenant: &str) -> Result<Option<TenantKey>, EnvelopeError>;
+
+    /// Store the KEK of a tenant
+    async fn put(&self, key: &TenantKey) -> Result<(), EnvelopeError>;
+}
+
+/// In-memory tenant key store
+#[derive(Default)]
+pub struct MemoryTenantKeyStore {
+    keys: RwLock<HashMap<String, TenantKey>>,
+}
+
+impl MemoryTenantKeyStore {
+    /// Create a new in-memory tenant key store
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl TenantKeyStore for MemoryTenantKeyStore {
+    async fn get(&self, tenant: &str) -> Result<Option<TenantKey>, EnvelopeError> {
+        Ok(self.keys.read().await.get(tenant).cloned())
+    }
+
+    async fn put(&self, key: &TenantKey) -> Result<(), EnvelopeError> {
+        self.keys
+            .write()
+            .await
+            .insert(key.tenant.clone(), key.clone());
+        Ok(())
+    }
+}
+
+/// Data key of an envelope, wrapped by a tenant's KEK
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EnvelopeKey {
+    /// Provider holding the KEK
+    pub provider: String,
+
+    /// KEK identifier
+    pub kek_id: String,
+
+    /// Wrapped data key
+    #[serde(with = "hex_bytes")]
+    pub wrapped_dek: Vec<u8>,
+
+    /// Nonce the data was encrypted with
+    #[serde(with = "hex_bytes")]
+    pub nonce: Vec<u8>,
+}
+
+/// Value encrypted with a data key
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Envelope {
+    /// Wrapped data key
+    pub key: EnvelopeKey,
+
+    /// Encrypted data
+    #[serde(with = "hex_bytes")]
+    pub ciphertext: Vec<u8>,
+}
+
+impl Envelope {
+    /// Encode the envelope as text, for text columns
+    pub fn encode(&self) -> String {
+        format!(
+            "{}{}",
+            ENVELOPE_PREFIX,
+            hex::encode(serde_json::to_vec(self).unwrap_or_default())
+        )
+    }
+
+    /// Decode an envelope encoded as text
+    pub fn decode(text: &str) -> Result<Self, EnvelopeError> {
+        let encoded = text
+            .strip_prefix(ENVELOPE_PREFIX)
+            .ok_or_else(|| EnvelopeError::Malformed("missing envelope prefix".to_string()))?;
+        let data = hex::decode(encoded).map_err(|e| EnvelopeError::Malformed(e.to_string()))?;
+        serde_json::from_slice(&data).map_err(|e| EnvelopeError::Malformed(e.to_string()))
+    }
+
+    /// Whether text is an encoded envelope
+    pub fn is_encoded(text: &str) -> bool {
+        text.starts_with(ENVELOPE_PREFIX)
+    }
+}
+
+/// Envelope encryption with per-tenant KEKs
+pub struct EnvelopeService {
+    /// Tenant KEK identifiers
+    store: Arc<dyn TenantKeyStore>,
+
+    /// Key wrappers by provider
+    wrappers: HashMap<String, Arc<dyn KeyWrapper>>,
+}
+
+impl EnvelopeService {
+    /// Create a new envelope service
+    pub fn new(store: Arc<dyn TenantKeyStore>) -> Self {
+        Self {
+            store,
+            wrappers: HashMap::new(),
+        }
+    }
+
+    /// Hold the KEKs of a provider with a wrapper
+    pub fn with_wrapper(mut self, provider: &str, wrapper: Arc<dyn KeyWrapper>) -> Self {
+        self.wrappers.insert(provider.to_string(), wrapper);
+        self
+    }
+
+    fn wrapper(&self, provider: &str) -> Result<&Arc<dyn KeyWrapper>, EnvelopeError> {
+        self.wrappers
+            .get(provider)
+            .ok_or_else(|| EnvelopeError::UnknownProvider(provider.to_string()))
+    }
+
+    /// KEK of a tenant, if enrolled
+    pub async fn tenant_key(&self, tenant: &str) -> Result<Option<TenantKey>, EnvelopeError> {
+        self.store.get(tenant).await
+    }
+
+    /// Enroll a tenant, creating a KEK with a provider; enrolled tenants are moved to it
+    pub async fn enroll(&self, tenant: &str, provider: &str) -> Result<TenantKey, EnvelopeError> {
+        match self.store.get(tenant).await? {
+            Some(key) if key.provider == provider => Ok(key),
+            current => self.replace_kek(tenant, provider, current).await,
+        }
+    }
+
+    /// Rotate the KEK of a tenant; data is re-keyed with [`EnvelopeService::rekey`]
+    pub async fn rotate(&self, tenant: &str) -> Result<TenantKey, EnvelopeError> {
+        let current = self
+            .store
+            .get(tenant)
+            .await?
+            .ok_or_else(|| EnvelopeError::NotEnrolled(tenant.to_string()))?;
+        let provider = current.provider.clone();
+        self.replace_kek(tenant, &provider, Some(current)).await
+    }
+
+    async fn replace_kek(
+        &self,
+        tenant: &str,
+        provider: &str,
+        current: Option<TenantKey>,
+    ) -> Result<TenantKey, EnvelopeError> {
+        let kek_id = self.wrapper(provider)?.create_kek(tenant).await?;
+        let mut retired_kek_ids = Vec::new();
+        if let Some(current) = current {
+            retired_kek_ids = current.retired_kek_ids;
+            retired_kek_ids.push(current.kek_id);
+        }
+
+        let key = TenantKey {
+            tenant: tenant.to_string(),
+            provider: provider.to_string(),
+            kek_id,
+            retired_kek_ids,
+            created_at: now(),
+        };
+        self.store.put(&key).await?;
+
+        tracing::info!(
+            "envelope: tenant {} now encrypts with KEK {} of {}",
+            tenant,
+            key.kek_id,
+            provider
+        );
+        Ok(key)
+    }
+
+    /// Encrypt data of a tenant with a fresh data key, `None` if the tenant is not enrolled
+    pub async fn seal(&self, tenant: &str, data: &[u8]) -> Result<Option<Envelope>, EnvelopeError> {
+        let Some(tenant_key) = self.store.get(tenant).await? else {
+            return Ok(None);
+        };
+
+        let mut dek = [0u8; 32];
+        rand::thread_rng().fill_bytes(&mut dek);
+        let (ciphertext, nonce) = seal(
+            &Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&dek)),
+            data,
+            b"",
+        )?;
+        let wrapped_dek = self
+            .wrapper(&tenant_key.provider)?
+            .wrap(&tenant_key.kek_id, &dek)
+            .await?;
+
+        Ok(Some(Envelope {
+            key: EnvelopeKey {
+                provider: tenant_key.provider,
+                kek_id: tenant_key.kek_id,
+                wrapped_dek,
+                nonce,
+            },
+            ciphertext,
+        }))
+    }
+
+    /// Decrypt an envelope
+    pub async fn open(&self, envelope: &Envelope) -> Result<Vec<u8>, EnvelopeError> {
+        self.open_with(&envelope.key, &envelope.ciphertext).await
+    }
+
+    /// Decrypt data encrypted with the data key of an envelope
+    pub async fn open_with(
+        &self,
+        key: &EnvelopeKey,
+        ciphertext: &[u8],
+    ) -> Result<Vec<u8>, EnvelopeError> {
+        let dek = self
+            .wrapper(&key.provider)?
+            .unwrap(&key.kek_id, &key.wrapped_dek)
+            .await?;
+        if dek.len() != 32 {
+            return Err(EnvelopeError::Malformed(
+                "data key is not 32 bytes".to_string(),
+            ));
+        }
+        open(
+            &Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&dek)),
+            ciphertext,
+            &key.nonce,
+            b"",
+        )
+    }
+
+    /// Re-wrap a data key with the tenant's current KEK, `None` if it already is
+    pub async fn rekey(
+        &self,
+        tenant: &str,
+        key: &EnvelopeKey,
+    ) -> Result<Option<EnvelopeKey>, EnvelopeError> {
+        let tenant_key = self
+            .store
+            .get(tenant)
+            .await?
+            .ok_or_else(|| EnvelopeError::NotEnrolled(tenant.to_string()))?;
+        if key.provider == tenant_key.provider && key.kek_id == tenant_key.kek_id {
+            return Ok(None);
+        }
+
+        let dek = self
+            .wrapper(&key.provider)?
+            .unwrap(&key.kek_id, &key.wrapped_dek)
+            .await?;
+        let wrapped_dek = self
+            .wrapper(&tenant_key.provider)?
+            .wrap(&tenant_key.kek_id, &dek)
+            .await?;
+
+        Ok(Some(EnvelopeKey {
+            provider: tenant_key.provider,
+            kek_id: tenant_key.kek_id,
+            wrapped_dek,
+            nonce: key.nonce.clone(),
+        }))
+    }
+
+    /// Encrypt text of a tenant, returning it encoded and its KEK identifier, or the text as is
+    /// if the tenant is not enrolled
+    pub async fn seal_text(
+        &self,
+        tenant: &str,
+        text: &str,
+    ) -> Result<(String, Option<String>), EnvelopeError> {
+        match self.seal(tenant, text.as_bytes()).await? {
+            Some(envelope) => {
+                let kek_id = envelope.key.kek_id.clone();
+                Ok((envelope.encode(), Some(kek_id)))
+            }
+            None => Ok((text.to_string(), None)),
+        }
+    }
+
+    /// Decrypt text sealed with [`EnvelopeService::seal_text`], plain text is returned as is
+    pub async fn open_text(&self, text: &str) -> Result<String, EnvelopeError> {
+        if !Envelope::is_encoded(text) {
+            return Ok(text.to_string());
+        }
+        let data = self.open(&Envelope::decode(text)?).await?;
+        String::from_utf8(data).map_err(|e| EnvelopeError::Malformed(e.to_string()))
+    }
+}
+
+fn seal(cipher: &Aes256Gcm, data: &[u8], aad: &[u8]) -> Result<(Vec<u8>, Vec<u8>), EnvelopeError> {
+    let mut nonce = [0u8; 12];
+    rand::thread_rng().fill_bytes(&mut nonce);
+    let ciphertext = cipher
+        .encrypt(Nonce::from_slice(&nonce), Payload { msg: data, aad })
+        .map_err(|e| EnvelopeError::Crypto(e.to_string()))?;
+    Ok((ciphertext, nonce.to_vec()))
+}
+
+fn open(
+    cipher: &Aes256Gcm,
+    ciphertext: &[u8],
+    nonce: &[u8],
+    aad: &[u8],
+) -> Result<Vec<u8>, EnvelopeError> {
+    if nonce.len() != 12 {
+        return Err(EnvelopeError::Malformed(
+            "nonce is not 12 bytes".to_string(),
+        ));
+    }
+    cipher
+        .decrypt(
+            Nonce::from_slice(nonce),
+            Payload {
+                msg: ciphertext,
+                aad,
+            },
+        )
+        .map_err(|e| EnvelopeError::Crypto(e.to_string()))
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+mod hex_bytes {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&hex::encode(bytes))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        hex::decode(text).map_err(serde::de::Error::custom)
+    }
+}
@@ -0,0 +1,837 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+//! Secrets backed by HashiCorp Vault.
+//!
+//! - [`TransitKeyWrapper`] holds tenant KEKs in the transit engine, so data keys are wrapped and
+//!   unwrapped by Vault and the KEKs never leave it
+//! - [`VaultKvStorage`] stores the encrypted secrets in the KV v2 engine
+//! - [`VaultFunctionKeys`] keeps each function's key in the KV v2 engine, wrapped by a KEK of any
+//!   provider, such as the transit engine or AWS KMS
+//!
+//! Values read from Vault are cached. While Vault is unreachable, cached values keep being
+//! served for up to `stale_ttl_secs` after they were read, writes fail. The client token is
+//! renewed in the background before its lease runs out.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use base64::Engine;
+use reqwest::{Client, Method, StatusCode};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+use tokio::sync::Mutex as AsyncMutex;
+use uuid::Uuid;
+
+use crate::envelope::{EnvelopeError, KeyWrapper};
+use crate::storage::SecretStorage;
+use crate::{EncryptedSecret, SecretEncryption, SecretError};
+
+/// Provider of KEKs held by the Vault transit engine
+pub const VAULT_PROVIDER: &str = "vault";
+
+/// Transit key wrapping the function keys stored in Vault
+pub const FUNCTION_KEYS_KEK: &str = "r3e-function-keys";
+
+/// Delay before retrying a failed token renewal
+const RENEW_RETRY: Duration = Duration::from_secs(30);
+
+/// Vault error types
+#[derive(Debug, Error)]
+pub enum VaultError {
+    /// Vault cannot be reached, is sealed or overloaded
+    #[error("vault: unavailable: {0}")]
+    Unavailable(String),
+
+    /// Vault rejected the request
+    #[error("vault: request failed with status {0}: {1}")]
+    Request(u16, String),
+
+    /// Vault answered with an unexpected response
+    #[error("vault: malformed response: {0}")]
+    Malformed(String),
+
+    /// Invalid configuration or argument
+    #[error("vault: invalid {0}")]
+    Invalid(String),
+}
+
+impl From<VaultError> for SecretError {
+    fn from(err: VaultError) -> Self {
+        match err {
+            VaultError::Unavailable(_) => SecretError::Unavailable(err.to_string()),
+            _ => SecretError::Storage(err.to_string()),
+        }
+    }
+}
+
+impl From<VaultError> for EnvelopeError {
+    fn from(err: VaultError) -> Self {
+        EnvelopeError::Provider(err.to_string())
+    }
+}
+
+/// Vault configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VaultConfig {
+    /// Vault address, e.g. `https://vault.example.com:8200`
+    pub address: String,
+
+    /// Client token
+    pub token: String,
+
+    /// Namespace of Vault Enterprise, if any
+    #[serde(default)]
+    pub namespace: Option<String>,
+
+    /// Mount path of the transit engine
+    #[serde(default = "default_transit_mount")]
+    pub transit_mount: String,
+
+    /// Mount path of the KV v2 engine
+    #[serde(default = "default_kv_mount")]
+    pub kv_mount: String,
+
+    /// Path prefix of the platform's entries in the KV v2 engine
+    #[serde(default = "default_kv_prefix")]
+    pub kv_prefix: String,
+
+    /// Request timeout in seconds
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+
+    /// Time values read from Vault are served from the cache, in seconds
+    #[serde(default = "default_cache_ttl_secs")]
+    pub cache_ttl_secs: u64,
+
+    /// Time cached values keep being served while Vault is unreachable, in seconds; 0 fails
+    /// reads as soon as Vault is unreachable
+    #[serde(default = "default_stale_ttl_secs")]
+    pub stale_ttl_secs: u64,
+
+    /// Lease extension requested on each token renewal, in seconds; 0 keeps the token's own
+    #[serde(default)]
+    pub token_increment_secs: u64,
+}
+
+fn default_transit_mount() -> String {
+    "transit".to_string()
+}
+
+fn default_kv_mount() -> String {
+    "secret".to_string()
+}
+
+fn default_kv_prefix() -> String {
+    "r3e-faas".to_string()
+}
+
+fn default_timeout_secs() -> u64 {
+    10
+}
+
+fn default_cache_ttl_secs() -> u64 {
+    60
+}
+
+fn default_stale_ttl_secs() -> u64 {
+    3600
+}
+
+impl VaultConfig {
+    /// Configuration of a Vault at `address`, with the default mounts and cache lifetimes
+    pub fn new(address: &str, token: &str) -> Self {
+        Self {
+            address: address.to_string(),
+            token: token.to_string(),
+            namespace: None,
+            transit_mount: default_transit_mount(),
+            kv_mount: default_kv_mount(),
+            kv_prefix: default_kv_prefix(),
+            timeout_secs: default_timeout_secs(),
+            cache_ttl_secs: default_cache_ttl_secs(),
+            stale_ttl_secs: default_stale_ttl_secs(),
+            token_increment_secs: 0,
+        }
+    }
+
+    fn cache<V: Clone>(&self) -> VaultCache<V> {
+        VaultCache::new(
+            Duration::from_secs(self.cache_ttl_secs),
+            Duration::from_secs(self.stale_ttl_secs),
+        )
+    }
+}
+
+/// Lease of the client token
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TokenLease {
+    /// Time left on the lease
+    pub duration: Duration,
+
+    /// Whether the lease can be renewed again
+    pub renewable: bool,
+}
+
+/// Vault HTTP API client
+pub struct VaultClient {
+    config: VaultConfig,
+    client: Client,
+}
+
+impl VaultClient {
+    /// Create a new Vault client
+    pub fn new(config: VaultConfig) -> Result<Self, VaultError> {
+        reqwest::Url::parse(&config.address)
+            .map_err(|e| VaultError::Invalid(format!("address: {}", e)))?;
+        let client = Client::builder()
+            .timeout(Duration::from_secs(config.timeout_secs))
+            .build()
+            .map_err(|e| VaultError::Invalid(format!("client: {}", e)))?;
+        Ok(Self { config, client })
+    }
+
+    /// Client configuration
+    pub fn config(&self) -> &VaultConfig {
+        &self.config
+    }
+
+    /// Send a request to the API, returning None for paths that do not exist
+    async fn request(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<serde_json::Value>,
+    ) -> Result<Option<serde_json::Value>, VaultError> {
+        let url = format!("{}/v1/{}", self.config.address.trim_end_matches('/'), path);
+        let mut request = self
+            .client
+            .request(method, &url)
+            .header("X-Vault-Token", &self.config.token);
+        if let Some(namespace) = &self.config.namespace {
+            request = request.header("X-Vault-Namespace", namespace);
+        }
+        if let Some(body) = body {
+            request = request.json(&body);
+        }
+
+        let response = request
+            .send()
+            .await
+            .map_err(|e| VaultError::Unavailable(e.to_string()))?;
+        let status = response.status();
+        if status == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        if status == StatusCode::NO_CONTENT {
+            return Ok(Some(serde_json::Value::Null));
+        }
+
+        let text = response
+            .text()
+            .await
+            .map_err(|e| VaultError::Unavailable(e.to_string()))?;
+        // Sealed, standby and rate limited servers may answer the next request
+        if status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS {
+            return Err(VaultError::Unavailable(format!(
+                "status {}: {}",
+                status, text
+            )));
+        }
+        if !status.is_success() {
+            return Err(VaultError::Request(status.as_u16(), text));
+        }
+        serde_json::from_str(&text)
+            .map(Some)
+            .map_err(|e| VaultError::Malformed(e.to_string()))
+    }
+
+    /// Renew the client token
+    pub async fn renew_token(&self) -> Result<TokenLease, VaultError> {
+        let body = match self.config.token_increment_secs {
+            0 => json!({}),
+            increment => json!({ "increment": format!("{}s", increment) }),
+        };
+        let response = self
+            .request(Method::POST, "auth/token/renew-self", Some(body))
+            .await?
+            .ok_or_else(|| VaultError::Malformed("token renewal not found".to_string()))?;
+
+        let auth = &response["auth"];
+        Ok(TokenLease {
+            duration: Duration::from_secs(auth["lease_duration"].as_u64().unwrap_or(0)),
+            renewable: auth["renewable"].as_bool().unwrap_or(false),
+        })
+    }
+
+    /// Keep renewing the client token at half its lease, until it cannot be renewed anymore
+    pub fn spawn_token_renewal(self: Arc<Self>) -> tokio::task::JoinHandle<()> {
+        tokio::spawn(async move {
+            loop {
+                let wait = match self.renew_token().await {
+                    Ok(lease) if lease.renewable && !lease.duration.is_zero() => {
+                        tracing::debug!("vault: renewed token for {:?}", lease.duration);
+                        (lease.duration / 2).max(Duration::from_secs(1))
+                    }
+                    Ok(_) => {
+                        tracing::info!("vault: token has no renewable lease, renewal stopped");
+                        return;
+                    }
+                    Err(err @ VaultError::Request(..)) => {
+                        tracing::warn!("vault: token renewal rejected, renewal stopped: {}", err);
+                        return;
+                    }
+                    Err(err) => {
+                        tracing::warn!("vault: token renewal failed: {}", err);
+                        RENEW_RETRY
+                    }
+                };
+                tokio::time::sleep(wait).await;
+            }
+        })
+    }
+
+    /// Create a transit key
+    pub async fn transit_create_key(&self, name: &str) -> Result<(), VaultError> {
+        let path = format!("{}/keys/{}", self.config.transit_mount, name);
+        self.request(Method::POST, &path, Some(json!({ "type": "aes256-gcm96" })))
+            .await?;
+        Ok(())
+    }
+
+    /// Encrypt data with a transit key, returning the `vault:v<n>:` ciphertext
+    pub async fn transit_encrypt(
+        &self,
+        name: &str,
+        plaintext: &[u8],
+    ) -> Result<String, VaultError> {
+        let path = format!("{}/encrypt/{}", self.config.transit_mount, name);
+        let body =
+            json!({ "plaintext": base64::engine::general_purpose::STANDARD.encode(plaintext) });
+        let response = self
+            .request(Method::POST, &path, Some(body))
+            .await?
+            .ok_or_else(|| VaultError::Request(404, format!("transit key {}", name)))?;
+
+        response["data"]["ciphertext"]
+            .as_str()
+            .map(str::to_string)
+            .ok_or_else(|| VaultError::Malformed("missing ciphertext".to_string()))
+    }
+
+    /// Decrypt a `vault:v<n>:` ciphertext with a transit key
+    pub async fn transit_decrypt(
+        &self,
+        name: &str,
+        ciphertext: &str,
+    ) -> Result<Vec<u8>, VaultError> {
+        let path = format!("{}/decrypt/{}", self.config.transit_mount, name);
+        let response = self
+            .request(
+                Method::POST,
+                &path,
+                Some(json!({ "ciphertext": ciphertext })),
+            )
+            .await?
+            .ok_or_else(|| VaultError::Request(404, format!("transit key {}", name)))?;
+
+        let plaintext = response["data"]["plaintext"]
+            .as_str()
+            .ok_or_else(|| VaultError::Malformed("missing plaintext".to_string()))?;
+        base64::engine::general_purpose::STANDARD
+            .decode(plaintext)
+            .map_err(|e| VaultError::Malformed(e.to_string()))
+    }
+
+    /// Read the latest version of a KV v2 entry
+    pub async fn kv_get(&self, path: &str) -> Result<Option<serde_json::Value>, VaultError> {
+        let path = format!("{}/data/{}", self.config.kv_mount, self.kv_path(path));
+        let Some(response) = self.request(Method::GET, &path, None).await? else {
+            return Ok(None);
+        };
+        Ok(Some(response["data"]["data"].clone()))
+    }
+
+    /// Write a new version of a KV v2 entry
+    pub async fn kv_put(&self, path: &str, data: serde_json::Value) -> Result<(), VaultError> {
+        let path = format!("{}/data/{}", self.config.kv_mount, self.kv_path(path));
+        self.request(Method::POST, &path, Some(json!({ "data": data })))
+            .await?;
+        Ok(())
+    }
+
+    /// Delete a KV v2 entry with all its versions, returning whether it existed
+    pub async fn kv_delete(&self, path: &str) -> Result<bool, VaultError> {
+        let path = format!("{}/metadata/{}", self.config.kv_mount, self.kv_path(path));
+        Ok(self.request(Method::DELETE, &path, None).await?.is_some())
+    }
+
+    /// Names of the KV v2 entries under a path, sub-paths end with `/`
+    pub async fn kv_list(&self, path: &str) -> Result<Vec<String>, VaultError> {
+        let path = format!("{}/metadata/{}", self.config.kv_mount, self.kv_path(path));
+        let method = Method::from_bytes(b"LIST").expect("LIST is a valid method");
+        let Some(response) = self.request(method, &path, None).await? else {
+            return Ok(Vec::new());
+        };
+        serde_json::from_value(response["data"]["keys"].clone())
+            .map_err(|e| VaultError::Malformed(e.to_string()))
+    }
+
+    fn kv_path(&self, path: &str) -> String {
+        format!("{}/{}", self.config.kv_prefix.trim_matches('/'), path)
+    }
+}
+
+/// Segment of a Vault path, which must not contain separators
+fn segment(value: &str) -> Result<&str, VaultError> {
+    if value.is_empty() || value.contains('/') || value == "." || value == ".." {
+        return Err(VaultError::Invalid(format!("path segment {:?}", value)));
+    }
+    Ok(value)
+}
+
+/// Values read from Vault, served from memory for `ttl` and, while Vault is unreachable, for up
+/// to `stale_ttl` after they were read
+struct VaultCache<V> {
+    entries: Mutex<HashMap<String, (V, Instant)>>,
+    ttl: Duration,
+    stale_ttl: Duration,
+}
+
+impl<V: Clone> VaultCache<V> {
+    fn new(ttl: Duration, stale_ttl: Duration) -> Self {
+        Self {
+            entries: Mutex::new(HashMap::new()),
+            ttl,
+            stale_ttl,
+        }
+    }
+
+    fn get(&self, key: &str, max_age: Duration) -> Option<V> {
+        let entries = self.entries.lock().unwrap();
+        entries
+            .get(key)
+            .filter(|(_, read_at)| read_at.elapsed() < max_age)
+            .map(|(value, _)| value.clone())
+    }
+
+    fn fresh(&self, key: &str) -> Option<V> {
+        self.get(key, self.ttl)
+    }
+
+    fn put(&self, key: &str, value: V) {
+        self.entries
+            .lock()
+            .unwrap()
+            .insert(key.to_string(), (value, Instant::now()));
+    }
+
+    fn remove(&self, key: &str) {
+        self.entries.lock().unwrap().remove(key);
+    }
+
+    /// Serve a stale value in place of a read that failed because Vault is unreachable
+    fn on_error(&self, key: &str, err: VaultError) -> Result<V, VaultError> {
+        match err {
+            VaultError::Unavailable(_) => match self.get(key, self.stale_ttl) {
+                Some(value) => {
+                    tracing::warn!("vault: serving cached {} while unreachable: {}", key, err);
+                    Ok(value)
+                }
+                None => Err(err),
+            },
+            err => Err(err),
+        }
+    }
+}
+
+/// Tenant KEKs held by the Vault transit engine, one transit key per KEK
+pub struct TransitKeyWrapper {
+    client: Arc<VaultClient>,
+    unwrapped: VaultCache<Vec<u8>>,
+}
+
+impl TransitKeyWrapper {
+    /// Create a new transit key wrapper
+    pub fn new(client: Arc<VaultClient>) -> Self {
+        let unwrapped = client.config().cache();
+        Self { client, unwrapped }
+    }
+}
+
+#[async_trait]
+impl KeyWrapper for TransitKeyWrapper {
+    async fn create_kek(&self, tenant: &str) -> Result<String, EnvelopeError> {
+        let tenant: String = tenant
+            .chars()
+            .map(|c| match c {
+                'a'..='z' | 'A'..='Z' | '0'..='9' | '-' | '_' => c,
+                _ => '-',
+            })
+            .collect();
+        let kek_id = format!("r3e-{}-{}", tenant, Uuid::new_v4().simple());
+        self.client.transit_create_key(&kek_id).await?;
+        Ok(kek_id)
+    }
+
+    async fn wrap(&self, kek_id: &str, dek: &[u8]) -> Result<Vec<u8>, EnvelopeError> {
+        let ciphertext = self.client.transit_encrypt(segment(kek_id)?, dek).await?;
+        Ok(ciphertext.into_bytes())
+    }
+
+    async fn unwrap(&self, kek_id: &str, wrapped: &[u8]) -> Result<Vec<u8>, EnvelopeError> {
+        let ciphertext = std::str::from_utf8(wrapped)
+            .map_err(|_| EnvelopeError::Malformed("transit ciphertext is not UTF-8".to_string()))?;
+        let key = hex::encode(
+            Sha256::new()
+                .chain_update(kek_id)
+                .chain_update(wrapped)
+                .finalize(),
+        );
+        if let Some(dek) = self.unwrapped.fresh(&key) {
+            return Ok(dek);
+        }
+
+        let dek = match self
+            .client
+            .transit_decrypt(segment(kek_id)?, ciphertext)
+            .await
+        {
+            Ok(dek) => dek,
+            Err(err) => self.unwrapped.on_error(&key, err)?,
+        };
+        self.unwrapped.put(&key, dek.clone());
+        Ok(dek)
+    }
+}
+
+/// Encrypted secrets stored in the Vault KV v2 engine, under `<prefix>/secrets/<user>/<function>`
+pub struct VaultKvStorage {
+    client: Arc<VaultClient>,
+    cache: VaultCache<EncryptedSecret>,
+}
+
+impl VaultKvStorage {
+    /// Create a new Vault KV storage
+    pub fn new(client: Arc<VaultClient>) -> Self {
+        let cache = client.config().cache();
+        Self { client, cache }
+    }
+
+    fn function_path(user_id: &str, function_id: &str) -> Result<String, VaultError> {
+        Ok(format!(
+            "secrets/{}/{}",
+            segment(user_id)?,
+            segment(function_id)?
+        ))
+    }
+
+    fn secret_path(
+        user_id: &str,
+        function_id: &str,
+        secret_id: &str,
+    ) -> Result<String, VaultError> {
+        Ok(format!(
+            "{}/{}",
+            Self::function_path(user_id, function_id)?,
+            segment(secret_id)?
+        ))
+    }
+
+    async fn read(&self, path: &str) -> Result<Option<EncryptedSecret>, VaultError> {
+        let Some(data) = self.client.kv_get(path).await? else {
+            return Ok(None);
+        };
+        serde_json::from_value(data)
+            .map(Some)
+            .map_err(|e| VaultError::Malformed(e.to_string()))
+    }
+}
+
+#[async_trait]
+impl SecretStorage for VaultKvStorage {
+    async fn store_secret(&self, secret: EncryptedSecret) -> Result<(), SecretError> {
+        let path = Self::secret_path(&secret.user_id, &secret.function_id, &secret.id)?;
+        let data = serde_json::to_value(&secret)
+            .map_err(|e| SecretError::Storage(format!("Failed to serialize secret: {}", e)))?;
+        self.client.kv_put(&path, data).await?;
+        self.cache.put(&path, secret);
+        Ok(())
+    }
+
+    async fn get_secret(
+        &self,
+        user_id: &str,
+        function_id: &str,
+        secret_id: &str,
+    ) -> Result<EncryptedSecret, SecretError> {
+        let path = Self::secret_path(user_id, function_id, secret_id)?;
+        if let Some(secret) = self.cache.fresh(&path) {
+            return Ok(secret);
+        }
+
+        let secret = match self.read(&path).await {
+            Ok(Some(secret)) => secret,
+            Ok(None) => {
+                self.cache.remove(&path);
+                return Err(SecretError::NotFound(format!(
+                    "Secret not found: {}",
+                    secret_id
+                )));
+            }
+            Err(err) => self.cache.on_error(&path, err)?,
+        };
+        self.cache.put(&path, secret.clone());
+        Ok(secret)
+    }
+
+    async fn delete_secret(
+        &self,
+        user_id: &str,
+        function_id: &str,
+        secret_id: &str,
+    ) -> Result<(), SecretError> {
+        let path = Self::secret_path(user_id, function_id, secret_id)?;
+        let existed = self.client.kv_delete(&path).await?;
+        self.cache.remove(&path);
+        if !existed {
+            return Err(SecretError::NotFound(format!(
+                "Secret not found: {}",
+                secret_id
+            )));
+        }
+        Ok(())
+    }
+
+    async fn list_function_secrets(
+        &self,
+        user_id: &str,
+        function_id: &str,
+    ) -> Result<Vec<EncryptedSecret>, SecretError> {
+        let mut secrets = Vec::new();
+        for secret_id in self
+            .client
+            .kv_list(&Self::function_path(user_id, function_id)?)
+            .await?
+        {
+            if secret_id.ends_with('/') {
+                continue;
+            }
+            match self.get_secret(user_id, function_id, &secret_id).await {
+                Ok(secret) => secrets.push(secret),
+                // Deleted while listing
+                Err(SecretError::NotFound(_)) => {}
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(secrets)
+    }
+
+    async fn list_secrets(&self) -> Result<Vec<EncryptedSecret>, SecretError> {
+        // Folders are listed with a trailing separator: `<user>/`, then `<function>/`
+        let mut secrets = Vec::new();
+        for user_id in self.client.kv_list("secrets").await? {
+            let Some(user_id) = user_id.strip_suffix('/') else {
+                continue;
+            };
+            for function_id in self.client.kv_list(&format!("secrets/{}", user_id)).await? {
+                if let Some(function_id) = function_id.strip_suffix('/') {
+                    secrets.extend(self.list_function_secrets(user_id, function_id).await?);
+                }
+            }
+        }
+        Ok(secrets)
+    }
+}
+
+/// Wrapped function key as stored in Vault
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct WrappedFunctionKey {
+    /// KEK the function key is wrapped with
+    kek_id: String,
+
+    /// Hex encoded wrapped function key
+    wrapped: String,
+}
+
+/// Function keys stored wrapped in the Vault KV v2 engine, under `<prefix>/function-keys/<user>`.
+///
+/// The keys are wrapped with a single KEK of the given wrapper, such as a transit key or an AWS
+/// KMS key, so reading the KV entries alone does not reveal them.
+pub struct VaultFunctionKeys {
+    client: Arc<VaultClient>,
+    wrapper: Arc<dyn KeyWrapper>,
+    kek_id: String,
+    keys: VaultCache<[u8; 32]>,
+    // Serializes key creation, so concurrent first uses agree on one key
+    create: AsyncMutex<()>,
+}
+
+impl VaultFunctionKeys {
+    /// Create a new function key store wrapping keys with `kek_id` of `wrapper`
+    pub fn new(client: Arc<VaultClient>, wrapper: Arc<dyn KeyWrapper>, kek_id: &str) -> Self {
+        let keys = client.config().cache();
+        Self {
+            client,
+            wrapper,
+            kek_id: kek_id.to_string(),
+            keys,
+            create: AsyncMutex::new(()),
+        }
+    }
+
+    /// Create a new function key store wrapping keys with the transit key
+    /// [`FUNCTION_KEYS_KEK`], created if missing
+    pub async fn with_transit(client: Arc<VaultClient>) -> Result<Self, VaultError> {
+        client.transit_create_key(FUNCTION_KEYS_KEK).await?;
+        let wrapper = Arc::new(TransitKeyWrapper::new(client.clone()));
+        Ok(Self::new(client, wrapper, FUNCTION_KEYS_KEK))
+    }
+
+    /// Key of a function, created on first use
+    pub async fn function_key(
+        &self,
+        user_id: &str,
+        function_id: &str,
+    ) -> Result<[u8; 32], SecretError> {
+        let path = format!(
+            "function-keys/{}/{}",
+            segment(user_id)?,
+            segment(function_id)?
+        );
+        if let Some(key) = self.keys.fresh(&path) {
+            return Ok(key);
+        }
+
+        let key = match self.read(&path).await {
+            Ok(Some(key)) => key,
+            Ok(None) => {
+                let _create = self.create.lock().await;
+                match self.read(&path).await? {
+                    Some(key) => key,
+                    None => self.create(&path).await?,
+                }
+            }
+            Err(SecretError::Unavailable(reason)) => {
+                self.keys.on_error(&path, VaultError::Unavailable(reason))?
+            }
+            Err(err) => return Err(err),
+        };
+        self.keys.put(&path, key);
+        Ok(key)
+    }
+
+    /// Forget the key of a deleted function
+    pub async fn delete_function_key(
+        &self,
+        user_id: &str,
+        function_id: &str,
+    ) -> Result<(), SecretError> {
+        let path = format!(
+            "function-keys/{}/{}",
+            segment(user_id)?,
+            segment(function_id)?
+        );
+        self.client.kv_delete(&path).await?;
+        self.keys.remove(&path);
+        Ok(())
+    }
+
+    async fn read(&self, path: &str) -> Result<Option<[u8; 32]>, SecretError> {
+        let Some(data) = self.client.kv_get(path).await? else {
+            return Ok(None);
+        };
+        let stored: WrappedFunctionKey = serde_json::from_value(data)
+            .map_err(|e| SecretError::Storage(format!("Malformed function key: {}", e)))?;
+        let wrapped = hex::decode(&stored.wrapped)
+            .map_err(|e| SecretError::Storage(format!("Malformed function key: {}", e)))?;
+
+        let key = self.wrapper.unwrap(&stored.kek_id, &wrapped).await?;
+        key.try_into()
+            .map(Some)
+            .map_err(|_| SecretError::Decryption("Invalid function key length".to_string()))
+    }
+
+    async fn create(&self, path: &str) -> Result<[u8; 32], SecretError> {
+        let key = SecretEncryption::generate_function_key();
+        let wrapped = self.wrapper.wrap(&self.kek_id, &key).await?;
+        let stored = WrappedFunctionKey {
+            kek_id: self.kek_id.clone(),
+            wrapped: hex::encode(wrapped),
+        };
+        self.client.kv_put(path, json!(stored)).await?;
+        Ok(key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cache() {
+        let cache = VaultCache::new(Duration::ZERO, Duration::from_secs(60));
+        cache.put("a", 1);
+
+        // Expired entries are only served while Vault is unreachable
+        assert_eq!(cache.fresh("a"), None);
+        let unavailable = || VaultError::Unavailable("connection refused".to_string());
+        assert_eq!(cache.on_error("a", unavailable()).unwrap(), 1);
+        assert!(matches!(
+            cache.on_error("b", unavailable()),
+            Err(VaultError::Unavailable(_))
+        ));
+        let rejected = VaultError::Request(403, "permission denied".to_string());
+        assert!(matches!(
+            cache.on_error("a", rejected),
+            Err(VaultError::Request(403, _))
+        ));
+
+        // Failing closed
+        let cache = VaultCache::new(Duration::ZERO, Duration::ZERO);
+        cache.put("a", 1);
+        assert!(cache.on_error("a", unavailable()).is_err());
+    }
+
+    #[test]
+    fn test_paths() {
+        let client = VaultClient::new(VaultConfig::new("http://127.0.0.1:8200", "token")).unwrap();
+        assert_eq!(client.kv_path("secrets/u/f"), "r3e-faas/secrets/u/f");
+        assert_eq!(
+            VaultKvStorage::secret_path("u", "f", "s").unwrap(),
+            "secrets/u/f/s"
+        );
+        assert!(VaultKvStorage::secret_path("u", "f/..", "s").is_err());
+        assert!(VaultKvStorage::secret_path("u", "", "s").is_err());
+        assert!(VaultClient::new(VaultConfig::new("not a url", "token")).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_outage() {
+        // Nothing listens on the discard port
+        let client =
+            Arc::new(VaultClient::new(VaultConfig::new("http://127.0.0.1:9", "token")).unwrap());
+        let storage = VaultKvStorage::new(client.clone());
+
+        let secret = EncryptedSecret::new(
+            "u".to_string(),
+            "f".to_string(),
+            Some("s".to_string()),
+            vec![1, 2, 3],
+            vec![0; 12],
+        );
+        let result = storage.store_secret(secret.clone()).await;
+        assert!(matches!(result, Err(SecretError::Unavailable(_))));
+
+        // Secrets read before the outage are still served
+        storage.cache.put("secrets/u/f/s", secret);
+        let secret = storage.get_secret("u", "f", "s").await.unwrap();
+        assert_eq!(secret.encrypted_data, vec![1, 2, 3]);
+        let result = storage.get_secret("u", "f", "other").await;
+        assert!(matches!(result, Err(SecretError::Unavailable(_))));
+    }
+}
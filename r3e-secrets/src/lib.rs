@@ -12,10 +12,16 @@ use std::time::{SystemTime, UNIX_EPOCH};
 use thiserror::Error;
 use uuid::Uuid;
 
+pub mod approval;
 pub mod audit;
+pub mod aws_kms;
+pub mod backup;
+pub mod envelope;
+pub mod hashicorp;
 pub mod rocksdb;
 pub mod service;
 pub mod storage;
+pub mod transfer;
 pub mod vault;
 
 /// Error types for secret management
@@ -35,6 +41,15 @@ pub enum SecretError {
 
     #[error("Unauthorized access: {0}")]
     Unauthorized(String),
+
+    #[error("Quota error: {0}")]
+    Quota(#[from] r3e_core::quota::QuotaError),
+
+    #[error("Envelope error: {0}")]
+    Envelope(#[from] envelope::EnvelopeError),
+
+    #[error("Secret backend unavailable: {0}")]
+    Unavailable(String),
 }
 
 /// Encrypted secret data
@@ -55,6 +70,11 @@ pub struct EncryptedSecret {
     /// Nonce used for encryption
     pub nonce: Vec<u8>,
 
+    /// Tenant data key the encrypted data is sealed with again, if the user is enrolled for
+    /// encryption at rest
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub envelope_key: Option<envelope::EnvelopeKey>,
+
     /// Creation timestamp
     pub created_at: u64,
 
@@ -83,6 +103,7 @@ impl EncryptedSecret {
             function_id,
             encrypted_data,
             nonce,
+            envelope_key: None,
             created_at: now,
             updated_at: now,
         }
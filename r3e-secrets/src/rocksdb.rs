@@ -7,6 +7,11 @@ use r3e_store::rocksdb::RocksDbConfig;
 use std::path::Path;
 use std::sync::Arc;
 
+use crate::approval::{ApprovalError, OperationStore, PendingOperation};
+use crate::audit::{
+    AuditEvent, AuditFilter, AuditRecord, AuditStore, AuditVerification, ChainVerifier,
+    GENESIS_HASH,
+};
 use crate::storage::SecretStorage;
 use crate::{EncryptedSecret, SecretError};
 
@@ -145,4 +150,326 @@ impl SecretStorage for RocksDBSecretStorage {
 
         Ok(secrets)
     }
+
+    async fn list_secrets(&self) -> Result<Vec<EncryptedSecret>, SecretError> {
+        // An empty prefix iterates the whole column family
+        let iter: Box<dyn Iterator<Item = (Box<[u8]>, Box<[u8]>)> + Send> = self
+            .db
+            .prefix_iter_cf(&self.secrets_cf, &[])
+            .map_err(|e| SecretError::Storage(format!("Failed to scan secrets: {}", e)))?;
+
+        iter.map(|(_, value)| {
+            serde_json::from_slice::<EncryptedSecret>(&value)
+                .map_err(|e| SecretError::Storage(format!("Failed to deserialize secret: {}", e)))
+        })
+        .collect()
+    }
+}
+
+/// Key of the chain head in the audit head column family
+const AUDIT_HEAD_KEY: &str = "head";
+
+/// RocksDB implementation of AuditStore
+pub struct RocksDBAuditStore {
+    db: Arc<RocksDBStore>,
+    records_cf: String,
+    head_cf: String,
+    /// Next sequence and hash of the last record, appends are serialized on it
+    head: tokio::sync::Mutex<(u64, String)>,
+}
+
+impl RocksDBAuditStore {
+    /// Create a new RocksDB audit store
+    pub async fn new<P: AsRef<Path>>(db_path: P) -> Result<Self, SecretError> {
+        let config = RocksDbConfig {
+            path: db_path.as_ref().to_string_lossy().to_string(),
+            ..Default::default()
+        };
+
+        let db = RocksDBStore::new(config);
+
+        // Open the database
+        db.open()
+            .map_err(|e| SecretError::Storage(format!("Failed to open RocksDB store: {}", e)))?;
+
+        let records_cf = "audit_records".to_string();
+        let head_cf = "audit_head".to_string();
+        for cf in [&records_cf, &head_cf] {
+            db.create_cf_if_missing(cf).map_err(|e| {
+                SecretError::Storage(format!("Failed to create column family: {}", e))
+            })?;
+        }
+
+        let store = Self {
+            db: Arc::new(db),
+            records_cf,
+            head_cf,
+            head: tokio::sync::Mutex::new((0, GENESIS_HASH.to_string())),
+        };
+
+        // The head is written after its record, catch up on records appended before a crash
+        let mut head = match store
+            .db
+            .get_cf::<_, Vec<u8>>(&store.head_cf, AUDIT_HEAD_KEY)
+        {
+            Ok(Some(value)) => serde_json::from_slice::<(u64, String)>(&value).map_err(|e| {
+                SecretError::Storage(format!("Failed to deserialize audit head: {}", e))
+            })?,
+            Ok(None) => (0, GENESIS_HASH.to_string()),
+            Err(e) => {
+                return Err(SecretError::Storage(format!(
+                    "Failed to get audit head: {}",
+                    e
+                )))
+            }
+        };
+        while let Some(record) = store.get_record(head.0)? {
+            head = (record.sequence + 1, record.hash);
+        }
+        *store.head.lock().await = head;
+
+        Ok(store)
+    }
+
+    fn get_record(&self, sequence: u64) -> Result<Option<AuditRecord>, SecretError> {
+        match self
+            .db
+            .get_cf::<_, Vec<u8>>(&self.records_cf, sequence.to_be_bytes())
+        {
+            Ok(Some(value)) => {
+                let record = serde_json::from_slice::<AuditRecord>(&value).map_err(|e| {
+                    SecretError::Storage(format!("Failed to deserialize audit record: {}", e))
+                })?;
+                Ok(Some(record))
+            }
+            Ok(None) => Ok(None),
+            Err(e) => Err(SecretError::Storage(format!(
+                "Failed to get audit record: {}",
+                e
+            ))),
+        }
+    }
+
+    /// Records in chain order, read as they are iterated, keys are big-endian sequences
+    fn records(
+        &self,
+    ) -> Result<impl Iterator<Item = Result<AuditRecord, SecretError>>, SecretError> {
+        let iter: Box<dyn Iterator<Item = (Box<[u8]>, Vec<u8>)> + Send> = self
+            .db
+            .prefix_iter_cf(&self.records_cf, b"")
+            .map_err(|e| SecretError::Storage(format!("Failed to scan audit records: {}", e)))?;
+
+        Ok(iter.map(|(_, value)| {
+            serde_json::from_slice::<AuditRecord>(&value).map_err(|e| {
+                SecretError::Storage(format!("Failed to deserialize audit record: {}", e))
+            })
+        }))
+    }
+}
+
+#[async_trait]
+impl AuditStore for RocksDBAuditStore {
+    async fn append(&self, event: AuditEvent) -> Result<AuditRecord, SecretError> {
+        let mut head = self.head.lock().await;
+        let record = AuditRecord::chain(head.0, head.1.clone(), event);
+
+        let value = serde_json::to_vec(&record).map_err(|e| {
+            SecretError::Storage(format!("Failed to serialize audit record: {}", e))
+        })?;
+        self.db
+            .put_cf(&self.records_cf, record.sequence.to_be_bytes(), &value)
+            .map_err(|e| SecretError::Storage(format!("Failed to store audit record: {}", e)))?;
+
+        *head = (record.sequence + 1, record.hash.clone());
+        let value = serde_json::to_vec(&*head)
+            .map_err(|e| SecretError::Storage(format!("Failed to serialize audit head: {}", e)))?;
+        self.db
+            .put_cf(&self.head_cf, AUDIT_HEAD_KEY, &value)
+            .map_err(|e| SecretError::Storage(format!("Failed to store audit head: {}", e)))?;
+
+        Ok(record)
+    }
+
+    async fn query(&self, filter: &AuditFilter) -> Result<Vec<AuditRecord>, SecretError> {
+        filter.try_select(self.records()?)
+    }
+
+    async fn verify(&self) -> Result<AuditVerification, SecretError> {
+        let mut verifier = ChainVerifier::new();
+        for record in self.records()? {
+            if !verifier.check(&record?) {
+                break;
+            }
+        }
+
+        // Truncating the log leaves a valid but shorter chain
+        let verification = verifier.finish();
+        let head = self.head.lock().await;
+        if verification.valid && verification.head_hash != head.1 {
+            return Ok(AuditVerification {
+                valid: false,
+                first_invalid: Some(verification.records_checked),
+                error: Some("chain ends before the recorded head".to_string()),
+                ..verification
+            });
+        }
+
+        Ok(verification)
+    }
+}
+
+/// RocksDB implementation of OperationStore
+pub struct RocksDBOperationStore {
+    db: Arc<RocksDBStore>,
+    operations_cf: String,
+}
+
+impl RocksDBOperationStore {
+    /// Create a new RocksDB operation store
+    pub async fn new<P: AsRef<Path>>(db_path: P) -> Result<Self, ApprovalError> {
+        let config = RocksDbConfig {
+            path: db_path.as_ref().to_string_lossy().to_string(),
+            ..Default::default()
+        };
+
+        let db = RocksDBStore::new(config);
+
+        // Open the database
+        db.open()
+            .map_err(|e| ApprovalError::Storage(format!("Failed to open RocksDB store: {}", e)))?;
+
+        let operations_cf = "admin_operations".to_string();
+        db.create_cf_if_missing(&operations_cf).map_err(|e| {
+            ApprovalError::Storage(format!("Failed to create column family: {}", e))
+        })?;
+
+        Ok(Self {
+            db: Arc::new(db),
+            operations_cf,
+        })
+    }
+}
+
+#[async_trait]
+impl OperationStore for RocksDBOperationStore {
+    async fn put(&self, operation: &PendingOperation) -> Result<(), ApprovalError> {
+        let value = serde_json::to_vec(operation).map_err(|e| {
+            ApprovalError::Storage(format!("Failed to serialize operation: {}", e))
+        })?;
+        self.db
+            .put_cf(&self.operations_cf, operation.id.as_bytes(), &value)
+            .map_err(|e| ApprovalError::Storage(format!("Failed to store operation: {}", e)))
+    }
+
+    async fn get(&self, id: &str) -> Result<Option<PendingOperation>, ApprovalError> {
+        match self
+            .db
+            .get_cf::<_, Vec<u8>>(&self.operations_cf, id.as_bytes())
+        {
+            Ok(Some(value)) => serde_json::from_slice(&value).map(Some).map_err(|e| {
+                ApprovalError::Storage(format!("Failed to deserialize operation: {}", e))
+            }),
+            Ok(None) => Ok(None),
+            Err(e) => Err(ApprovalError::Storage(format!(
+                "Failed to get operation: {}",
+                e
+            ))),
+        }
+    }
+
+    async fn list(&self) -> Result<Vec<PendingOperation>, ApprovalError> {
+        let iter: Box<dyn Iterator<Item = (Box<[u8]>, Vec<u8>)> + Send> = self
+            .db
+            .prefix_iter_cf(&self.operations_cf, b"")
+            .map_err(|e| ApprovalError::Storage(format!("Failed to scan operations: {}", e)))?;
+
+        iter.map(|(_, value)| {
+            serde_json::from_slice::<PendingOperation>(&value).map_err(|e| {
+                ApprovalError::Storage(format!("Failed to deserialize operation: {}", e))
+            })
+        })
+        .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audit::AuditEventType;
+
+    fn event(details: &str) -> AuditEvent {
+        AuditEvent::new(
+            AuditEventType::SecretAccessed,
+            "user".to_string(),
+            None,
+            Some("secret".to_string()),
+            details.to_string(),
+            None,
+            None,
+        )
+    }
+
+    /// Overwrite the record stored at a sequence
+    fn put_record(store: &RocksDBAuditStore, sequence: u64, record: &AuditRecord) {
+        let value = serde_json::to_vec(record).unwrap();
+        store
+            .db
+            .put_cf(&store.records_cf, sequence.to_be_bytes(), &value)
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_audit_store_verify() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = RocksDBAuditStore::new(dir.path()).await.unwrap();
+        for i in 0..4 {
+            store.append(event(&i.to_string())).await.unwrap();
+        }
+
+        let verification = store.verify().await.unwrap();
+        assert!(verification.valid);
+        assert_eq!(verification.records_checked, 4);
+
+        let filter = AuditFilter {
+            limit: Some(2),
+            ..Default::default()
+        };
+        let records = store.query(&filter).await.unwrap();
+        let sequences: Vec<_> = records.iter().map(|record| record.sequence).collect();
+        assert_eq!(sequences, [3, 2]);
+
+        // A modified record
+        let original = store.get_record(1).unwrap().unwrap();
+        let mut modified = original.clone();
+        modified.event.details = "tampered".to_string();
+        put_record(&store, 1, &modified);
+        let verification = store.verify().await.unwrap();
+        assert!(!verification.valid);
+        assert_eq!(verification.first_invalid, Some(1));
+        put_record(&store, 1, &original);
+        assert!(store.verify().await.unwrap().valid);
+
+        // Records swapped between sequences
+        let first = store.get_record(1).unwrap().unwrap();
+        let second = store.get_record(2).unwrap().unwrap();
+        put_record(&store, 1, &second);
+        put_record(&store, 2, &first);
+        let verification = store.verify().await.unwrap();
+        assert_eq!(verification.first_invalid, Some(1));
+        put_record(&store, 1, &first);
+        put_record(&store, 2, &second);
+
+        // A truncated tail leaves a valid chain short of the recorded head
+        store
+            .db
+            .delete_cf(&store.records_cf, 3u64.to_be_bytes())
+            .unwrap();
+        let verification = store.verify().await.unwrap();
+        assert!(!verification.valid);
+        assert_eq!(verification.first_invalid, Some(3));
+        assert_eq!(
+            verification.error.as_deref(),
+            Some("chain ends before the recorded head")
+        );
+    }
 }
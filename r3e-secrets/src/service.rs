@@ -2,8 +2,10 @@
 // All Rights Reserved
 
 use async_trait::async_trait;
+use r3e_core::quota::{QuotaEnforcer, QuotaResource};
 use std::sync::Arc;
 
+use crate::envelope::EnvelopeService;
 use crate::storage::SecretStorage;
 use crate::{EncryptedSecret, SecretEncryption, SecretError};
 
@@ -48,12 +50,66 @@ pub trait SecretService: Send + Sync {
 /// Secret service implementation
 pub struct SecretServiceImpl {
     storage: Arc<dyn SecretStorage>,
+    quota: Option<Arc<dyn QuotaEnforcer>>,
+    envelope: Option<Arc<EnvelopeService>>,
 }
 
 impl SecretServiceImpl {
     /// Create a new secret service
     pub fn new(storage: Arc<dyn SecretStorage>) -> Self {
-        Self { storage }
+        Self {
+            storage,
+            quota: None,
+            envelope: None,
+        }
+    }
+
+    /// Limit the number of secrets each user may store
+    pub fn with_quota(mut self, quota: Arc<dyn QuotaEnforcer>) -> Self {
+        self.quota = Some(quota);
+        self
+    }
+
+    /// Seal the secrets of users enrolled for encryption at rest with their tenant key
+    pub fn with_envelope(mut self, envelope: Arc<EnvelopeService>) -> Self {
+        self.envelope = Some(envelope);
+        self
+    }
+
+    /// Re-wrap the secrets of a function with its user's current tenant key, sealing the ones
+    /// stored before the user was enrolled; returns the number of secrets rewritten
+    pub async fn rekey_secrets(
+        &self,
+        user_id: &str,
+        function_id: &str,
+    ) -> Result<usize, SecretError> {
+        let Some(envelope) = &self.envelope else {
+            return Ok(0);
+        };
+
+        let mut rekeyed = 0;
+        for mut secret in self
+            .storage
+            .list_function_secrets(user_id, function_id)
+            .await?
+        {
+            match &secret.envelope_key {
+                Some(key) => match envelope.rekey(user_id, key).await? {
+                    Some(key) => secret.envelope_key = Some(key),
+                    None => continue,
+                },
+                None => match envelope.seal(user_id, &secret.encrypted_data).await? {
+                    Some(sealed) => {
+                        secret.encrypted_data = sealed.ciphertext;
+                        secret.envelope_key = Some(sealed.key);
+                    }
+                    None => continue,
+                },
+            }
+            self.storage.store_secret(secret).await?;
+            rekeyed += 1;
+        }
+        Ok(rekeyed)
     }
 }
 
@@ -74,7 +130,7 @@ impl SecretService for SecretServiceImpl {
         let (encrypted_data, nonce) = encryption.encrypt(data)?;
 
         // Create encrypted secret
-        let secret = EncryptedSecret::new(
+        let mut secret = EncryptedSecret::new(
             user_id.to_string(),
             function_id.to_string(),
             Some(secret_id.to_string()),
@@ -82,8 +138,36 @@ impl SecretService for SecretServiceImpl {
             nonce,
         );
 
+        // Seal it again with the user's tenant key, if enrolled
+        if let Some(envelope) = &self.envelope {
+            if let Some(sealed) = envelope.seal(user_id, &secret.encrypted_data).await? {
+                secret.encrypted_data = sealed.ciphertext;
+                secret.envelope_key = Some(sealed.key);
+            }
+        }
+
+        // Reserve quota for new secrets, overwriting one is free
+        let reserved = match &self.quota {
+            Some(quota) => match self
+                .storage
+                .get_secret(user_id, function_id, secret_id)
+                .await
+            {
+                Err(SecretError::NotFound(_)) => {
+                    quota.reserve(user_id, QuotaResource::Secrets, 1)?;
+                    Some(quota)
+                }
+                _ => None,
+            },
+            None => None,
+        };
+
         // Store secret
-        self.storage.store_secret(secret).await
+        let stored = self.storage.store_secret(secret).await;
+        if let (Err(_), Some(quota)) = (&stored, reserved) {
+            quota.release(user_id, QuotaResource::Secrets, 1);
+        }
+        stored
     }
 
     async fn get_secret(
@@ -99,11 +183,23 @@ impl SecretService for SecretServiceImpl {
             .get_secret(user_id, function_id, secret_id)
             .await?;
 
+        // Open the tenant envelope first
+        let encrypted_data = match (&secret.envelope_key, &self.envelope) {
+            (Some(key), Some(envelope)) => envelope.open_with(key, &secret.encrypted_data).await?,
+            (Some(key), None) => {
+                return Err(SecretError::Decryption(format!(
+                    "Secret is sealed with tenant key {} but envelope encryption is not configured",
+                    key.kek_id
+                )))
+            }
+            (None, _) => secret.encrypted_data,
+        };
+
         // Create encryption service
         let encryption = SecretEncryption::new(function_key)?;
 
         // Decrypt data
-        let decrypted_data = encryption.decrypt(&secret.encrypted_data, &secret.nonce)?;
+        let decrypted_data = encryption.decrypt(&encrypted_data, &secret.nonce)?;
 
         Ok(decrypted_data)
     }
@@ -116,7 +212,12 @@ impl SecretService for SecretServiceImpl {
     ) -> Result<(), SecretError> {
         self.storage
             .delete_secret(user_id, function_id, secret_id)
-            .await
+            .await?;
+
+        if let Some(quota) = &self.quota {
+            quota.release(user_id, QuotaResource::Secrets, 1);
+        }
+        Ok(())
     }
 
     async fn list_secret_ids(
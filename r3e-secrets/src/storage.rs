@@ -36,6 +36,9 @@ pub trait SecretStorage: Send + Sync {
         user_id: &str,
         function_id: &str,
     ) -> Result<Vec<EncryptedSecret>, SecretError>;
+
+    /// List the secrets of every function
+    async fn list_secrets(&self) -> Result<Vec<EncryptedSecret>, SecretError>;
 }
 
 /// Memory-based implementation of SecretStorage
@@ -115,4 +118,9 @@ impl SecretStorage for MemorySecretStorage {
             .cloned()
             .collect())
     }
+
+    async fn list_secrets(&self) -> Result<Vec<EncryptedSecret>, SecretError> {
+        let secrets = self.secrets.read().await;
+        Ok(secrets.values().cloned().collect())
+    }
 }
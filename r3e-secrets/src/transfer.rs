@@ -0,0 +1,269 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+//! Hybrid encryption of secret bundles moved in and out of the platform in bulk.
+//!
+//! A bundle is sealed to a P-256 public key: an ephemeral ECDH key agreement derives a one-off
+//! AES-256-GCM key, and only the ephemeral public key travels with the ciphertext. Clients
+//! seal imports to the platform's published [`TransferKey`] and receive exports sealed to a
+//! key of their own, so secret values never cross the API in plaintext.
+
+use aes_gcm::{
+    aead::{Aead, KeyInit, Payload},
+    Aes256Gcm, Nonce,
+};
+use base64::Engine;
+use p256::ecdh::EphemeralSecret;
+use p256::elliptic_curve::sec1::ToEncodedPoint;
+use p256::{PublicKey, SecretKey};
+use rand::rngs::OsRng;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::SecretError;
+
+/// Current sealed bundle format version
+pub const BUNDLE_VERSION: u32 = 1;
+
+/// Algorithm published with the transfer key, for clients to check what they implement
+pub const TRANSFER_ALGORITHM: &str = "ECDH-P256+SHA256+A256GCM";
+
+/// Secret value in a bundle
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BundledSecret {
+    /// Function the secret belongs to
+    pub function_id: String,
+
+    /// Secret ID
+    pub id: String,
+
+    /// Base64 encoded secret value
+    pub value: String,
+}
+
+impl BundledSecret {
+    /// Create a bundled secret
+    pub fn new(function_id: String, id: String, value: &[u8]) -> Self {
+        Self {
+            function_id,
+            id,
+            value: base64::engine::general_purpose::STANDARD.encode(value),
+        }
+    }
+
+    /// Decoded secret value
+    pub fn decode_value(&self) -> Result<Vec<u8>, SecretError> {
+        base64::engine::general_purpose::STANDARD
+            .decode(&self.value)
+            .map_err(|e| {
+                SecretError::Decryption(format!("Invalid value of secret {}: {}", self.id, e))
+            })
+    }
+}
+
+/// Plaintext of a sealed bundle
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SecretBundle {
+    /// Secrets
+    pub secrets: Vec<BundledSecret>,
+}
+
+/// Bundle encrypted to a P-256 public key, binary fields hex encoded
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SealedBundle {
+    /// Bundle format version
+    pub version: u32,
+
+    /// ID of the key the bundle is sealed to
+    pub key_id: String,
+
+    /// SEC1 encoded ephemeral public key
+    pub ephemeral_key: String,
+
+    /// AES-GCM nonce
+    pub nonce: String,
+
+    /// Encrypted JSON of the [`SecretBundle`]
+    pub ciphertext: String,
+}
+
+/// Public half of a transfer key, as published to clients
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TransferPublicKey {
+    /// Key ID, to be copied into sealed bundles
+    pub key_id: String,
+
+    /// Sealing algorithm
+    pub algorithm: String,
+
+    /// Hex encoded SEC1 compressed public key
+    pub public_key: String,
+}
+
+/// Parse a hex encoded SEC1 public key, compressed or not
+pub fn parse_public_key(key: &str) -> Result<PublicKey, SecretError> {
+    let bytes = hex::decode(key.trim().trim_start_matches("0x"))
+        .map_err(|e| SecretError::Encryption(format!("Invalid public key: {}", e)))?;
+    PublicKey::from_sec1_bytes(&bytes)
+        .map_err(|_| SecretError::Encryption("Invalid P-256 public key".to_string()))
+}
+
+/// ID of a public key: the first 8 bytes of the SHA-256 of its compressed encoding
+pub fn key_id(key: &PublicKey) -> String {
+    let digest = Sha256::digest(compressed(key));
+    hex::encode(&digest[..8])
+}
+
+/// SEC1 compressed encoding of a public key
+fn compressed(key: &PublicKey) -> Vec<u8> {
+    key.to_encoded_point(true).as_bytes().to_vec()
+}
+
+/// Bundle key agreed by ECDH, bound to both public keys
+fn bundle_cipher(shared: &[u8], ephemeral: &[u8], recipient: &PublicKey) -> Aes256Gcm {
+    let key = Sha256::new()
+        .chain_update(b"r3e-faas:secret-bundle:v1")
+        .chain_update(shared)
+        .chain_update(ephemeral)
+        .chain_update(compressed(recipient))
+        .finalize();
+    Aes256Gcm::new(&key)
+}
+
+impl SecretBundle {
+    /// Seal the bundle to a public key
+    pub fn seal(&self, recipient: &PublicKey) -> Result<SealedBundle, SecretError> {
+        let plaintext = serde_json::to_vec(self)
+            .map_err(|e| SecretError::Encryption(format!("Failed to encode bundle: {}", e)))?;
+
+        let ephemeral = EphemeralSecret::random(&mut OsRng);
+        let ephemeral_key = compressed(&ephemeral.public_key());
+        let shared = ephemeral.diffie_hellman(recipient);
+        let cipher = bundle_cipher(
+            shared.raw_secret_bytes().as_slice(),
+            &ephemeral_key,
+            recipient,
+        );
+
+        // The key ID is authenticated, a bundle cannot be relabelled for another key
+        let key_id = key_id(recipient);
+        let mut nonce = [0u8; 12];
+        OsRng.fill_bytes(&mut nonce);
+        let ciphertext = cipher
+            .encrypt(
+                Nonce::from_slice(&nonce),
+                Payload {
+                    msg: &plaintext,
+                    aad: key_id.as_bytes(),
+                },
+            )
+            .map_err(|e| SecretError::Encryption(e.to_string()))?;
+
+        Ok(SealedBundle {
+            version: BUNDLE_VERSION,
+            key_id,
+            ephemeral_key: hex::encode(ephemeral_key),
+            nonce: hex::encode(nonce),
+            ciphertext: hex::encode(ciphertext),
+        })
+    }
+}
+
+/// Key pair bundles are sealed to for import into the platform
+pub struct TransferKey {
+    secret: SecretKey,
+}
+
+impl TransferKey {
+    /// Derive the platform transfer key from the secrets master key, so it is stable across
+    /// restarts and replicas without being stored
+    pub fn derive(master_key: &[u8; 32]) -> Self {
+        // A digest is not a valid scalar with negligible probability, try the next counter
+        let mut counter: u8 = 0;
+        loop {
+            let seed = Sha256::new()
+                .chain_update(b"r3e-faas:transfer-key:v1")
+                .chain_update(master_key)
+                .chain_update([counter])
+                .finalize();
+            if let Ok(secret) = SecretKey::from_slice(&seed) {
+                return Self { secret };
+            }
+            counter = counter.wrapping_add(1);
+        }
+    }
+
+    /// Generate a random transfer key, e.g. for a client receiving an export
+    pub fn generate() -> Self {
+        Self {
+            secret: SecretKey::random(&mut OsRng),
+        }
+    }
+
+    /// Public key
+    pub fn public_key(&self) -> PublicKey {
+        self.secret.public_key()
+    }
+
+    /// Public key as published to clients
+    pub fn published(&self) -> TransferPublicKey {
+        let public_key = self.public_key();
+        TransferPublicKey {
+            key_id: key_id(&public_key),
+            algorithm: TRANSFER_ALGORITHM.to_string(),
+            public_key: hex::encode(compressed(&public_key)),
+        }
+    }
+
+    /// Open a bundle sealed to this key
+    pub fn open(&self, sealed: &SealedBundle) -> Result<SecretBundle, SecretError> {
+        if sealed.version == 0 || sealed.version > BUNDLE_VERSION {
+            return Err(SecretError::Decryption(format!(
+                "Unsupported bundle version: {}, expected at most {}",
+                sealed.version, BUNDLE_VERSION
+            )));
+        }
+        let public_key = self.public_key();
+        let own_id = key_id(&public_key);
+        if sealed.key_id != own_id {
+            return Err(SecretError::Decryption(format!(
+                "Bundle is sealed to key {}, not {}",
+                sealed.key_id, own_id
+            )));
+        }
+
+        let decode = |field: &str, value: &str| {
+            hex::decode(value)
+                .map_err(|e| SecretError::Decryption(format!("Invalid {}: {}", field, e)))
+        };
+        let ephemeral_key = decode("ephemeral key", &sealed.ephemeral_key)?;
+        let nonce = decode("nonce", &sealed.nonce)?;
+        let ciphertext = decode("ciphertext", &sealed.ciphertext)?;
+        if nonce.len() != 12 {
+            return Err(SecretError::Decryption("Invalid nonce length".to_string()));
+        }
+
+        let ephemeral = PublicKey::from_sec1_bytes(&ephemeral_key)
+            .map_err(|_| SecretError::Decryption("Invalid ephemeral key".to_string()))?;
+        let shared =
+            p256::ecdh::diffie_hellman(self.secret.to_nonzero_scalar(), ephemeral.as_affine());
+        let cipher = bundle_cipher(
+            shared.raw_secret_bytes().as_slice(),
+            &ephemeral_key,
+            &public_key,
+        );
+        let plaintext = cipher
+            .decrypt(
+                Nonce::from_slice(&nonce),
+                Payload {
+                    msg: &ciphertext,
+                    aad: sealed.key_id.as_bytes(),
+                },
+            )
+            .map_err(|_| SecretError::Decryption("Bundle cannot be opened".to_string()))?;
+
+        serde_json::from_slice(&plaintext)
+            .map_err(|e| SecretError::Decryption(format!("Invalid bundle: {}", e)))
+    }
+}
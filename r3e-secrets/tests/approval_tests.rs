@@ -0,0 +1,221 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+use async_trait::async_trait;
+use p256::ecdsa::signature::Signer;
+use p256::ecdsa::{Signature, SigningKey};
+use r3e_secrets::approval::{
+    canonical_json, ApprovalError, ApprovalService, MemoryOperationStore, OperationExecutor,
+    OperationStatus,
+};
+use r3e_secrets::audit::{AuditEventType, AuditFilter, AuditStore, MemoryAuditStore};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+/// Executor counting its runs, failing for amounts over 100
+#[derive(Default)]
+struct CountingExecutor {
+    runs: AtomicUsize,
+}
+
+#[async_trait]
+impl OperationExecutor for CountingExecutor {
+    fn validate(&self, params: &Value) -> Result<(), String> {
+        params["amount"]
+            .as_u64()
+            .map(|_| ())
+            .ok_or_else(|| "amount is required".to_string())
+    }
+
+    async fn execute(&self, params: &Value) -> Result<Value, String> {
+        self.runs.fetch_add(1, Ordering::SeqCst);
+        match params["amount"].as_u64() {
+            Some(amount) if amount <= 100 => Ok(json!({ "withdrawn": amount })),
+            _ => Err("insufficient balance".to_string()),
+        }
+    }
+}
+
+fn approver() -> SigningKey {
+    SigningKey::from_slice(&[7u8; 32]).unwrap()
+}
+
+fn service(executor: Arc<CountingExecutor>, audit_store: Arc<MemoryAuditStore>) -> ApprovalService {
+    let public_key = hex::encode(approver().verifying_key().to_encoded_point(true).as_bytes());
+    ApprovalService::new(
+        Arc::new(MemoryOperationStore::new()),
+        audit_store,
+        &[public_key],
+    )
+    .unwrap()
+    .with_executor("gas_bank.withdraw", executor)
+}
+
+fn sign(key: &SigningKey, message: &str) -> String {
+    let signature: Signature = key.sign(message.as_bytes());
+    hex::encode(signature.to_bytes())
+}
+
+#[tokio::test]
+async fn test_offline_approval() {
+    let executor = Arc::new(CountingExecutor::default());
+    let audit_store = Arc::new(MemoryAuditStore::new());
+    let service = service(executor.clone(), audit_store.clone());
+
+    let operation = service
+        .propose(
+            "gas_bank.withdraw",
+            json!({ "amount": 50, "address": "NX" }),
+            "alice",
+        )
+        .await
+        .unwrap();
+    assert_eq!(operation.status, OperationStatus::Pending);
+
+    // The exported message hashes to the digest shown on the signing device
+    let request = service
+        .signing_request(&operation.id, "alice")
+        .await
+        .unwrap();
+    assert_eq!(
+        hex::encode(Sha256::digest(request.message.as_bytes())),
+        request.digest
+    );
+
+    // A key that is not an approver is rejected and nothing runs
+    let other = SigningKey::from_slice(&[9u8; 32]).unwrap();
+    let result = service
+        .approve(&operation.id, &sign(&other, &request.message), "bob")
+        .await;
+    assert!(matches!(result, Err(ApprovalError::InvalidSignature(_))));
+    assert_eq!(executor.runs.load(Ordering::SeqCst), 0);
+
+    let executed = service
+        .approve(&operation.id, &sign(&approver(), &request.message), "bob")
+        .await
+        .unwrap();
+    assert_eq!(executed.status, OperationStatus::Executed);
+    assert_eq!(executed.result, Some(json!({ "withdrawn": 50 })));
+
+    // The signature cannot be replayed
+    let result = service
+        .approve(&operation.id, &sign(&approver(), &request.message), "bob")
+        .await;
+    assert!(matches!(
+        result,
+        Err(ApprovalError::InvalidState(_, OperationStatus::Executed))
+    ));
+    assert_eq!(executor.runs.load(Ordering::SeqCst), 1);
+
+    // Every step is audited
+    let events: Vec<AuditEventType> = audit_store
+        .query(&AuditFilter::default())
+        .await
+        .unwrap()
+        .into_iter()
+        .rev()
+        .map(|record| record.event.event_type)
+        .collect();
+    assert_eq!(
+        events,
+        vec![
+            AuditEventType::OperationProposed,
+            AuditEventType::OperationExported,
+            AuditEventType::OperationRejected,
+            AuditEventType::OperationApproved,
+            AuditEventType::OperationExecuted,
+        ]
+    );
+}
+
+#[tokio::test]
+async fn test_failed_and_cancelled_operations() {
+    let executor = Arc::new(CountingExecutor::default());
+    let service = service(executor.clone(), Arc::new(MemoryAuditStore::new()));
+
+    // Parameters are checked when proposed
+    let result = service
+        .propose("gas_bank.withdraw", json!({}), "alice")
+        .await;
+    assert!(matches!(result, Err(ApprovalError::InvalidParams(_))));
+    let result = service.propose("quota.set_plan", json!({}), "alice").await;
+    assert!(matches!(result, Err(ApprovalError::UnknownKind(_))));
+
+    // A failed run is recorded on the operation
+    let operation = service
+        .propose("gas_bank.withdraw", json!({ "amount": 500 }), "alice")
+        .await
+        .unwrap();
+    let failed = service
+        .approve(
+            &operation.id,
+            &sign(&approver(), &operation.signing_message()),
+            "bob",
+        )
+        .await
+        .unwrap();
+    assert_eq!(failed.status, OperationStatus::Failed);
+    assert_eq!(failed.error.as_deref(), Some("insufficient balance"));
+
+    // Cancelled operations cannot be approved
+    let operation = service
+        .propose("gas_bank.withdraw", json!({ "amount": 5 }), "alice")
+        .await
+        .unwrap();
+    service.cancel(&operation.id, "alice").await.unwrap();
+    let result = service
+        .approve(
+            &operation.id,
+            &sign(&approver(), &operation.signing_message()),
+            "bob",
+        )
+        .await;
+    assert!(matches!(
+        result,
+        Err(ApprovalError::InvalidState(_, OperationStatus::Cancelled))
+    ));
+    assert_eq!(executor.runs.load(Ordering::SeqCst), 1);
+
+    let cancelled = service
+        .list(Some(OperationStatus::Cancelled))
+        .await
+        .unwrap();
+    assert_eq!(cancelled.len(), 1);
+    assert_eq!(cancelled[0].id, operation.id);
+}
+
+#[tokio::test]
+async fn test_expired_operation() {
+    let executor = Arc::new(CountingExecutor::default());
+    let service = service(executor.clone(), Arc::new(MemoryAuditStore::new())).with_ttl(0);
+
+    let operation = service
+        .propose("gas_bank.withdraw", json!({ "amount": 5 }), "alice")
+        .await
+        .unwrap();
+    assert_eq!(
+        service.get(&operation.id).await.unwrap().status,
+        OperationStatus::Expired
+    );
+
+    let result = service
+        .approve(
+            &operation.id,
+            &sign(&approver(), &operation.signing_message()),
+            "bob",
+        )
+        .await;
+    assert!(matches!(result, Err(ApprovalError::Expired(_))));
+    assert_eq!(executor.runs.load(Ordering::SeqCst), 0);
+}
+
+#[test]
+fn test_canonical_json() {
+    let value = json!({ "b": [1, { "z": true, "a": null }], "a": "x\"y" });
+    assert_eq!(
+        canonical_json(&value),
+        r#"{"a":"x\"y","b":[1,{"a":null,"z":true}]}"#
+    );
+}
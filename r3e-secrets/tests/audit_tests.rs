@@ -0,0 +1,172 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+use r3e_secrets::audit::{
+    AuditContext, AuditEvent, AuditEventType, AuditFilter, AuditRecord, AuditStore, ChainVerifier,
+    MemoryAuditStore, GENESIS_HASH,
+};
+
+fn admin_event(source_ip: Option<String>) -> AuditEvent {
+    AuditEvent::new(
+        AuditEventType::AdminAction,
+        "admin".to_string(),
+        None,
+        None,
+        "rotated keys".to_string(),
+        source_ip,
+        None,
+    )
+}
+
+#[tokio::test]
+async fn test_audit_context_propagation() {
+    // Outside a scope events carry no request context
+    assert_eq!(AuditContext::current(), None);
+    let event = admin_event(None);
+    assert_eq!(event.request_id, None);
+    assert_eq!(event.source_ip, None);
+
+    let context = AuditContext {
+        request_id: Some("req-1".to_string()),
+        user_id: Some("admin".to_string()),
+        source_ip: Some("10.0.0.1".to_string()),
+        user_agent: Some("r3e-cli/1.0".to_string()),
+    };
+    let (event, explicit) = context
+        .scope(async {
+            // The context survives awaits within the scope
+            tokio::task::yield_now().await;
+            assert_eq!(
+                AuditContext::current().unwrap().user_id.as_deref(),
+                Some("admin")
+            );
+            (admin_event(None), admin_event(Some("10.0.0.2".to_string())))
+        })
+        .await;
+
+    assert_eq!(event.request_id.as_deref(), Some("req-1"));
+    assert_eq!(event.source_ip.as_deref(), Some("10.0.0.1"));
+    assert_eq!(event.user_agent.as_deref(), Some("r3e-cli/1.0"));
+
+    // Explicit values take precedence over the context
+    assert_eq!(explicit.request_id.as_deref(), Some("req-1"));
+    assert_eq!(explicit.source_ip.as_deref(), Some("10.0.0.2"));
+
+    let filter = AuditFilter {
+        request_id: Some("req-1".to_string()),
+        ..Default::default()
+    };
+    assert!(filter.matches(&event));
+    assert!(!filter.matches(&admin_event(None)));
+}
+
+#[test]
+fn test_audit_event_without_request_id_keeps_encoding() {
+    // Events recorded before request IDs existed must hash the same, so the field is omitted
+    let event = admin_event(None);
+    let json = serde_json::to_value(&event).unwrap();
+    assert!(json.get("request_id").is_none());
+
+    let decoded: AuditEvent = serde_json::from_value(json).unwrap();
+    assert_eq!(decoded.request_id, None);
+}
+
+fn chain(len: u64) -> Vec<AuditRecord> {
+    let mut records: Vec<AuditRecord> = Vec::new();
+    for sequence in 0..len {
+        let prev_hash = records
+            .last()
+            .map(|record| record.hash.clone())
+            .unwrap_or_else(|| GENESIS_HASH.to_string());
+        records.push(AuditRecord::chain(sequence, prev_hash, admin_event(None)));
+    }
+    records
+}
+
+fn verify(records: &[AuditRecord]) -> (Option<u64>, Option<String>) {
+    let mut verifier = ChainVerifier::new();
+    for record in records {
+        if !verifier.check(record) {
+            break;
+        }
+    }
+    let verification = verifier.finish();
+    assert_eq!(verification.valid, verification.first_invalid.is_none());
+    (verification.first_invalid, verification.error)
+}
+
+#[test]
+fn test_chain_verifier() {
+    let records = chain(4);
+    let mut verifier = ChainVerifier::new();
+    assert!(records.iter().all(|record| verifier.check(record)));
+    let verification = verifier.finish();
+    assert!(verification.valid);
+    assert_eq!(verification.records_checked, 4);
+    assert_eq!(verification.head_hash, records[3].hash);
+
+    // A modified event no longer matches its hash
+    let mut modified = records.clone();
+    modified[2].event.details = "nothing happened".to_string();
+    let (first_invalid, error) = verify(&modified);
+    assert_eq!(first_invalid, Some(2));
+    assert!(error.unwrap().contains("hash does not match its content"));
+
+    // Rehashing the modified record breaks the link of its successor
+    modified[2].hash = modified[2].compute_hash();
+    let (first_invalid, error) = verify(&modified);
+    assert_eq!(first_invalid, Some(3));
+    assert!(error.unwrap().contains("previous hash"));
+
+    // Records swapped in place are out of sequence
+    let mut reordered = records.clone();
+    reordered.swap(1, 2);
+    let (first_invalid, error) = verify(&reordered);
+    assert_eq!(first_invalid, Some(1));
+    assert_eq!(error.as_deref(), Some("expected sequence 1, found 2"));
+
+    // Renumbering them does not restore the links
+    reordered[1].sequence = 1;
+    reordered[2].sequence = 2;
+    let (first_invalid, _) = verify(&reordered);
+    assert_eq!(first_invalid, Some(1));
+
+    // A removed record leaves a gap
+    let mut gapped = records;
+    gapped.remove(1);
+    assert_eq!(verify(&gapped).0, Some(1));
+}
+
+#[tokio::test]
+async fn test_memory_audit_store() {
+    let store = MemoryAuditStore::new();
+    for i in 0..5 {
+        let event_type = if i % 2 == 0 {
+            AuditEventType::SecretAccessed
+        } else {
+            AuditEventType::AdminAction
+        };
+        let mut event = admin_event(None);
+        event.event_type = event_type;
+        event.details = i.to_string();
+        store.append(event).await.unwrap();
+    }
+    assert!(store.verify().await.unwrap().valid);
+
+    // The newest matches come first, up to the limit
+    let filter = AuditFilter {
+        event_type: Some(AuditEventType::SecretAccessed),
+        limit: Some(2),
+        ..Default::default()
+    };
+    let records = store.query(&filter).await.unwrap();
+    let details: Vec<_> = records.iter().map(|r| r.event.details.as_str()).collect();
+    assert_eq!(details, ["4", "2"]);
+
+    let filter = AuditFilter {
+        limit: Some(0),
+        ..Default::default()
+    };
+    assert!(store.query(&filter).await.unwrap().is_empty());
+    assert_eq!(store.query(&AuditFilter::default()).await.unwrap().len(), 5);
+}
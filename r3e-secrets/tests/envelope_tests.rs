@@ -0,0 +1,83 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+use r3e_secrets::envelope::{
+    Envelope, EnvelopeError, EnvelopeService, LocalKeyWrapper, MemoryTenantKeyStore, LOCAL_PROVIDER,
+};
+use std::sync::Arc;
+
+fn envelope_service(master_key: [u8; 32]) -> EnvelopeService {
+    EnvelopeService::new(Arc::new(MemoryTenantKeyStore::new()))
+        .with_wrapper(LOCAL_PROVIDER, Arc::new(LocalKeyWrapper::new(master_key)))
+}
+
+#[tokio::test]
+async fn test_seal_and_open() {
+    let service = envelope_service([1u8; 32]);
+
+    // Tenants that are not enrolled are left as is
+    assert!(service.seal("alice", b"code").await.unwrap().is_none());
+    let (text, kek_id) = service.seal_text("alice", "code").await.unwrap();
+    assert_eq!(text, "code");
+    assert!(kek_id.is_none());
+
+    let key = service.enroll("alice", LOCAL_PROVIDER).await.unwrap();
+    let (text, kek_id) = service.seal_text("alice", "code").await.unwrap();
+    assert!(Envelope::is_encoded(&text));
+    assert_eq!(kek_id, Some(key.kek_id));
+    assert_eq!(service.open_text(&text).await.unwrap(), "code");
+
+    // Plain text opens as is
+    assert_eq!(service.open_text("code").await.unwrap(), "code");
+
+    // Envelopes do not open with another master key
+    let other = envelope_service([2u8; 32]);
+    let result = other.open_text(&text).await;
+    assert!(matches!(result, Err(EnvelopeError::Crypto(_))));
+}
+
+#[tokio::test]
+async fn test_rotate_and_rekey() {
+    let service = envelope_service([1u8; 32]);
+    let first = service.enroll("alice", LOCAL_PROVIDER).await.unwrap();
+    let envelope = service.seal("alice", b"secret").await.unwrap().unwrap();
+
+    // Data sealed before a rotation still opens, and is re-wrapped with the new KEK
+    let second = service.rotate("alice").await.unwrap();
+    assert_ne!(first.kek_id, second.kek_id);
+    assert_eq!(second.retired_kek_ids, vec![first.kek_id]);
+    assert_eq!(service.open(&envelope).await.unwrap(), b"secret");
+
+    let key = service
+        .rekey("alice", &envelope.key)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(key.kek_id, second.kek_id);
+    let rekeyed = Envelope {
+        key,
+        ciphertext: envelope.ciphertext.clone(),
+    };
+    assert_eq!(service.open(&rekeyed).await.unwrap(), b"secret");
+    assert!(service
+        .rekey("alice", &rekeyed.key)
+        .await
+        .unwrap()
+        .is_none());
+
+    // A data key cannot be claimed by another tenant's KEK
+    let bob = service.enroll("bob", LOCAL_PROVIDER).await.unwrap();
+    let mut stolen = rekeyed.clone();
+    stolen.key.kek_id = bob.kek_id;
+    assert!(service.open(&stolen).await.is_err());
+}
+
+#[tokio::test]
+async fn test_unknown_provider() {
+    let service = envelope_service([1u8; 32]);
+
+    let result = service.enroll("alice", "tee").await;
+    assert!(matches!(result, Err(EnvelopeError::UnknownProvider(_))));
+    let result = service.rotate("alice").await;
+    assert!(matches!(result, Err(EnvelopeError::NotEnrolled(_))));
+}
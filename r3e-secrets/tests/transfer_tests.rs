@@ -0,0 +1,75 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+use r3e_secrets::transfer::{
+    parse_public_key, BundledSecret, SecretBundle, TransferKey, TRANSFER_ALGORITHM,
+};
+use r3e_secrets::SecretError;
+
+fn bundle() -> SecretBundle {
+    SecretBundle {
+        secrets: vec![
+            BundledSecret::new("f1".to_string(), "api_key".to_string(), b"key-1"),
+            BundledSecret::new("f2".to_string(), "token".to_string(), &[0u8, 255, 7]),
+        ],
+    }
+}
+
+#[test]
+fn test_seal_and_open() {
+    let key = TransferKey::derive(&[1u8; 32]);
+    let published = key.published();
+    assert_eq!(published.algorithm, TRANSFER_ALGORITHM);
+
+    // Clients seal to the published key
+    let recipient = parse_public_key(&published.public_key).unwrap();
+    let sealed = bundle().seal(&recipient).unwrap();
+    assert_eq!(sealed.key_id, published.key_id);
+
+    let opened = key.open(&sealed).unwrap();
+    assert_eq!(opened.secrets.len(), 2);
+    assert_eq!(opened.secrets[0].id, "api_key");
+    assert_eq!(opened.secrets[0].decode_value().unwrap(), b"key-1");
+    assert_eq!(opened.secrets[1].decode_value().unwrap(), vec![0u8, 255, 7]);
+}
+
+#[test]
+fn test_derived_key_is_stable() {
+    let a = TransferKey::derive(&[1u8; 32]).published();
+    let b = TransferKey::derive(&[1u8; 32]).published();
+    let c = TransferKey::derive(&[2u8; 32]).published();
+
+    assert_eq!(a.public_key, b.public_key);
+    assert_eq!(a.key_id, b.key_id);
+    assert_ne!(a.key_id, c.key_id);
+}
+
+#[test]
+fn test_open_rejects_other_keys_and_tampering() {
+    let key = TransferKey::generate();
+    let sealed = bundle().seal(&key.public_key()).unwrap();
+
+    // Sealed to another key
+    let other = TransferKey::generate();
+    assert!(matches!(
+        other.open(&sealed),
+        Err(SecretError::Decryption(_))
+    ));
+
+    // Relabelled for another key
+    let mut relabelled = bundle().seal(&key.public_key()).unwrap();
+    relabelled.key_id = other.published().key_id;
+    assert!(other.open(&relabelled).is_err());
+
+    // Tampered ciphertext
+    let mut tampered = sealed.clone();
+    let mut ciphertext = hex::decode(&tampered.ciphertext).unwrap();
+    ciphertext[0] ^= 1;
+    tampered.ciphertext = hex::encode(ciphertext);
+    assert!(key.open(&tampered).is_err());
+
+    // Unknown version
+    let mut future = sealed;
+    future.version = 2;
+    assert!(key.open(&future).is_err());
+}
@@ -2,10 +2,12 @@
 // All Rights Reserved
 
 use r3e_secrets::{
+    backup::VaultBackupKey,
     storage::{MemorySecretStorage, SecretStorage},
     vault::{SecretMetadata, SecretVault, VaultService},
     SecretError,
 };
+use r3e_store::backup::BackupKeyProvider;
 use std::sync::Arc;
 
 #[tokio::test]
@@ -316,3 +318,46 @@ async fn test_list_secrets() {
     assert!(has_api_key);
     assert!(has_db_password);
 }
+
+#[tokio::test]
+async fn test_vault_backup_key() {
+    let storage = Arc::new(MemorySecretStorage::new());
+    let vault = Arc::new(SecretVault::new(
+        storage,
+        SecretVault::generate_master_key(),
+    ));
+
+    // Hex encoded key
+    let secret_id = vault
+        .store_secret(
+            "admin",
+            "backups",
+            "backup_key",
+            "07".repeat(32).as_bytes(),
+            None,
+            vec![],
+            None,
+            None,
+        )
+        .await
+        .unwrap();
+    let provider = VaultBackupKey::new(vault.clone(), "admin", "backups", secret_id);
+    assert_eq!(provider.backup_key().await.unwrap(), [7u8; 32]);
+
+    // Keys must be 32 bytes
+    let secret_id = vault
+        .store_secret(
+            "admin",
+            "backups",
+            "short_key",
+            b"too short",
+            None,
+            vec![],
+            None,
+            None,
+        )
+        .await
+        .unwrap();
+    let provider = VaultBackupKey::new(vault, "admin", "backups", secret_id);
+    assert!(provider.backup_key().await.is_err());
+}
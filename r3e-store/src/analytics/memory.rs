@@ -0,0 +1,67 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+//! In-memory analytics store, for tests and single-node development
+
+use std::collections::BTreeMap;
+use std::sync::RwLock;
+
+use async_trait::async_trait;
+
+use super::{AnalyticsError, AnalyticsStore, Granularity, UsageAggregate};
+
+/// In-memory analytics store
+#[derive(Debug, Default)]
+pub struct MemoryAnalyticsStore {
+    aggregates: RwLock<BTreeMap<(String, &'static str, u64), UsageAggregate>>,
+}
+
+impl MemoryAnalyticsStore {
+    /// Create a new memory analytics store
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl AnalyticsStore for MemoryAnalyticsStore {
+    async fn merge(&self, aggregates: Vec<UsageAggregate>) -> Result<(), AnalyticsError> {
+        let mut stored = self.aggregates.write().unwrap();
+        for aggregate in aggregates {
+            let key = (
+                aggregate.function_id.clone(),
+                aggregate.granularity.as_str(),
+                aggregate.start,
+            );
+            match stored.get_mut(&key) {
+                Some(existing) => existing.merge(&aggregate),
+                None => {
+                    stored.insert(key, aggregate);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    async fn query(
+        &self,
+        function_id: &str,
+        granularity: Granularity,
+        from: u64,
+        to: u64,
+    ) -> Result<Vec<UsageAggregate>, AnalyticsError> {
+        if from >= to {
+            return Ok(Vec::new());
+        }
+
+        let range = (function_id.to_string(), granularity.as_str(), from)
+            ..(function_id.to_string(), granularity.as_str(), to);
+        Ok(self
+            .aggregates
+            .read()
+            .unwrap()
+            .range(range)
+            .map(|(_, aggregate)| aggregate.clone())
+            .collect())
+    }
+}
@@ -0,0 +1,467 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+//! Usage analytics of functions.
+//!
+//! Invocations are recorded into a [`UsageRollup`], which keeps hourly aggregates in memory
+//! until they are flushed into an [`AnalyticsStore`] as hourly and daily aggregates. Latencies
+//! are kept as histograms, so the percentiles of a day are exact rather than averaged from
+//! hourly percentiles.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+pub mod memory;
+pub mod rocksdb;
+
+pub use memory::MemoryAnalyticsStore;
+pub use rocksdb::RocksDbAnalyticsStore;
+
+/// Upper bounds in milliseconds of the latency histogram buckets; the last bucket is
+/// unbounded
+const LATENCY_BOUNDS_MS: [u64; 28] = [
+    1, 2, 3, 5, 7, 10, 15, 20, 30, 50, 75, 100, 150, 200, 300, 500, 750, 1_000, 1_500, 2_000,
+    3_000, 5_000, 7_500, 10_000, 15_000, 20_000, 30_000, 60_000,
+];
+
+/// Error type for analytics operations
+#[derive(Debug, Error)]
+pub enum AnalyticsError {
+    /// Invalid query
+    #[error("analytics: invalid query: {0}")]
+    InvalidQuery(String),
+
+    /// Backend failure
+    #[error("analytics: storage error: {0}")]
+    Storage(String),
+}
+
+/// Width of an aggregation bucket
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Granularity {
+    /// One hour
+    Hour,
+
+    /// One UTC day
+    Day,
+}
+
+impl Granularity {
+    /// Bucket width in seconds
+    pub fn seconds(&self) -> u64 {
+        match self {
+            Granularity::Hour => 3_600,
+            Granularity::Day => 86_400,
+        }
+    }
+
+    /// Start of the bucket holding a timestamp (secs since epoch)
+    pub fn bucket_start(&self, timestamp: u64) -> u64 {
+        timestamp - timestamp % self.seconds()
+    }
+
+    /// Key component of the granularity
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Granularity::Hour => "hour",
+            Granularity::Day => "day",
+        }
+    }
+}
+
+impl std::str::FromStr for Granularity {
+    type Err = AnalyticsError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "hour" => Ok(Granularity::Hour),
+            "day" => Ok(Granularity::Day),
+            _ => Err(AnalyticsError::InvalidQuery(format!(
+                "unknown granularity: {}",
+                s
+            ))),
+        }
+    }
+}
+
+/// Latency histogram with fixed buckets, so histograms of different periods can be merged
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LatencyHistogram {
+    /// Number of latencies per bucket of `LATENCY_BOUNDS_MS`, plus one unbounded bucket;
+    /// trailing empty buckets are left out
+    counts: Vec<u64>,
+
+    /// Highest latency recorded in milliseconds
+    max_ms: u64,
+}
+
+impl LatencyHistogram {
+    /// Record a latency
+    pub fn record(&mut self, latency_ms: u64) {
+        let bucket = LATENCY_BOUNDS_MS.partition_point(|&bound| bound < latency_ms);
+        if self.counts.len() <= bucket {
+            self.counts.resize(bucket + 1, 0);
+        }
+        self.counts[bucket] += 1;
+        self.max_ms = self.max_ms.max(latency_ms);
+    }
+
+    /// Add the latencies of another histogram
+    pub fn merge(&mut self, other: &LatencyHistogram) {
+        if self.counts.len() < other.counts.len() {
+            self.counts.resize(other.counts.len(), 0);
+        }
+        for (count, other) in self.counts.iter_mut().zip(&other.counts) {
+            *count += other;
+        }
+        self.max_ms = self.max_ms.max(other.max_ms);
+    }
+
+    /// Number of latencies recorded
+    pub fn count(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+    /// Latency under which a fraction `q` of the invocations completed, as the upper bound
+    /// of the bucket it falls in; `None` if nothing was recorded
+    pub fn percentile(&self, q: f64) -> Option<u64> {
+        let count = self.count();
+        if count == 0 {
+            return None;
+        }
+
+        let rank = ((q.clamp(0.0, 1.0) * count as f64).ceil() as u64).max(1);
+        let mut seen = 0;
+        for (bucket, bucket_count) in self.counts.iter().enumerate() {
+            seen += bucket_count;
+            if seen >= rank {
+                let bound = LATENCY_BOUNDS_MS.get(bucket).copied().unwrap_or(u64::MAX);
+                return Some(bound.min(self.max_ms));
+            }
+        }
+        Some(self.max_ms)
+    }
+}
+
+/// One invocation of a function
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UsageSample {
+    /// Function ID
+    pub function_id: String,
+
+    /// Invocation timestamp (secs since epoch)
+    pub timestamp: u64,
+
+    /// Execution time in milliseconds
+    pub latency_ms: u64,
+
+    /// Whether the invocation failed
+    pub error: bool,
+
+    /// Cost of the invocation in GAS
+    pub gas_cost: f64,
+}
+
+/// Usage of a function over one bucket
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UsageAggregate {
+    /// Function ID
+    pub function_id: String,
+
+    /// Bucket width
+    pub granularity: Granularity,
+
+    /// Bucket start (secs since epoch)
+    pub start: u64,
+
+    /// Number of invocations
+    pub invocations: u64,
+
+    /// Number of failed invocations
+    pub errors: u64,
+
+    /// Sum of the execution times in milliseconds
+    pub total_latency_ms: u64,
+
+    /// Execution time histogram
+    pub latency: LatencyHistogram,
+
+    /// Cost of the invocations in GAS
+    pub gas_cost: f64,
+}
+
+impl UsageAggregate {
+    /// Empty aggregate of a bucket
+    pub fn new(function_id: impl Into<String>, granularity: Granularity, start: u64) -> Self {
+        Self {
+            function_id: function_id.into(),
+            granularity,
+            start,
+            invocations: 0,
+            errors: 0,
+            total_latency_ms: 0,
+            latency: LatencyHistogram::default(),
+            gas_cost: 0.0,
+        }
+    }
+
+    /// Add an invocation
+    pub fn record(&mut self, sample: &UsageSample) {
+        self.invocations += 1;
+        if sample.error {
+            self.errors += 1;
+        }
+        self.total_latency_ms += sample.latency_ms;
+        self.latency.record(sample.latency_ms);
+        self.gas_cost += sample.gas_cost;
+    }
+
+    /// Add the usage of another aggregate
+    pub fn merge(&mut self, other: &UsageAggregate) {
+        self.invocations += other.invocations;
+        self.errors += other.errors;
+        self.total_latency_ms += other.total_latency_ms;
+        self.latency.merge(&other.latency);
+        self.gas_cost += other.gas_cost;
+    }
+
+    /// Summary of the aggregate for dashboards
+    pub fn summary(&self) -> UsageSummary {
+        UsageSummary {
+            start: self.start,
+            invocations: self.invocations,
+            errors: self.errors,
+            avg_latency_ms: if self.invocations == 0 {
+                None
+            } else {
+                Some(self.total_latency_ms as f64 / self.invocations as f64)
+            },
+            p50_latency_ms: self.latency.percentile(0.5),
+            p95_latency_ms: self.latency.percentile(0.95),
+            gas_cost: self.gas_cost,
+        }
+    }
+}
+
+/// Usage of a function over one bucket, with the latency percentiles worked out
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UsageSummary {
+    /// Bucket start (secs since epoch)
+    pub start: u64,
+
+    /// Number of invocations
+    pub invocations: u64,
+
+    /// Number of failed invocations
+    pub errors: u64,
+
+    /// Average execution time in milliseconds
+    pub avg_latency_ms: Option<f64>,
+
+    /// Median execution time in milliseconds
+    pub p50_latency_ms: Option<u64>,
+
+    /// 95th percentile execution time in milliseconds
+    pub p95_latency_ms: Option<u64>,
+
+    /// Cost of the invocations in GAS
+    pub gas_cost: f64,
+}
+
+/// Persisted usage aggregates
+#[async_trait]
+pub trait AnalyticsStore: Send + Sync {
+    /// Add aggregates to the stored aggregates of the same buckets
+    async fn merge(&self, aggregates: Vec<UsageAggregate>) -> Result<(), AnalyticsError>;
+
+    /// Aggregates of a function whose bucket starts in `[from, to)`, oldest first
+    async fn query(
+        &self,
+        function_id: &str,
+        granularity: Granularity,
+        from: u64,
+        to: u64,
+    ) -> Result<Vec<UsageAggregate>, AnalyticsError>;
+}
+
+/// Hourly aggregates of recent invocations, waiting to be flushed to a store
+#[derive(Debug, Default)]
+pub struct UsageRollup {
+    pending: Mutex<HashMap<(String, u64), UsageAggregate>>,
+}
+
+impl UsageRollup {
+    /// Create a new usage rollup
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record an invocation
+    pub fn record(&self, sample: &UsageSample) {
+        let start = Granularity::Hour.bucket_start(sample.timestamp);
+        self.pending
+            .lock()
+            .unwrap()
+            .entry((sample.function_id.clone(), start))
+            .or_insert_with(|| UsageAggregate::new(&sample.function_id, Granularity::Hour, start))
+            .record(sample);
+    }
+
+    /// Number of hourly aggregates waiting to be flushed
+    pub fn pending(&self) -> usize {
+        self.pending.lock().unwrap().len()
+    }
+
+    /// Write the pending aggregates to the store as hourly and daily aggregates, returning
+    /// how many hourly aggregates were written. They are kept for the next flush if the
+    /// store fails.
+    pub async fn flush(&self, store: &dyn AnalyticsStore) -> Result<usize, AnalyticsError> {
+        let hourly: Vec<UsageAggregate> = std::mem::take(&mut *self.pending.lock().unwrap())
+            .into_values()
+            .collect();
+        if hourly.is_empty() {
+            return Ok(0);
+        }
+
+        let mut daily: HashMap<(String, u64), UsageAggregate> = HashMap::new();
+        for aggregate in &hourly {
+            let start = Granularity::Day.bucket_start(aggregate.start);
+            daily
+                .entry((aggregate.function_id.clone(), start))
+                .or_insert_with(|| {
+                    UsageAggregate::new(&aggregate.function_id, Granularity::Day, start)
+                })
+                .merge(aggregate);
+        }
+
+        let count = hourly.len();
+        let mut aggregates = hourly.clone();
+        aggregates.extend(daily.into_values());
+        if let Err(e) = store.merge(aggregates).await {
+            // Give the aggregates back, merged with what was recorded in the meantime
+            let mut pending = self.pending.lock().unwrap();
+            for aggregate in hourly {
+                pending
+                    .entry((aggregate.function_id.clone(), aggregate.start))
+                    .or_insert_with(|| {
+                        UsageAggregate::new(
+                            &aggregate.function_id,
+                            Granularity::Hour,
+                            aggregate.start,
+                        )
+                    })
+                    .merge(&aggregate);
+            }
+            return Err(e);
+        }
+
+        Ok(count)
+    }
+}
+
+/// Flush the rollup to the store periodically
+pub fn spawn_usage_rollup(
+    rollup: Arc<UsageRollup>,
+    store: Arc<dyn AnalyticsStore>,
+    interval: Duration,
+) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(interval);
+        loop {
+            interval.tick().await;
+            match rollup.flush(store.as_ref()).await {
+                Ok(0) => {}
+                Ok(flushed) => log::debug!("analytics: flushed {} hourly aggregates", flushed),
+                Err(e) => log::error!("analytics: rollup failed: {}", e),
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(timestamp: u64, latency_ms: u64, error: bool) -> UsageSample {
+        UsageSample {
+            function_id: "f1".to_string(),
+            timestamp,
+            latency_ms,
+            error,
+            gas_cost: 0.5,
+        }
+    }
+
+    #[test]
+    fn test_latency_percentiles() {
+        let mut histogram = LatencyHistogram::default();
+        assert_eq!(histogram.percentile(0.5), None);
+
+        for latency_ms in 1..=100 {
+            histogram.record(latency_ms);
+        }
+        assert_eq!(histogram.count(), 100);
+        assert_eq!(histogram.percentile(0.5), Some(50));
+        assert_eq!(histogram.percentile(0.95), Some(100));
+
+        let mut slow = LatencyHistogram::default();
+        slow.record(90_000);
+        histogram.merge(&slow);
+        assert_eq!(histogram.count(), 101);
+        assert_eq!(histogram.percentile(1.0), Some(90_000));
+    }
+
+    #[tokio::test]
+    async fn test_rollup_flush() {
+        let rollup = UsageRollup::new();
+        let store = MemoryAnalyticsStore::new();
+
+        // Two hours of one day and the first hour of the next
+        rollup.record(&sample(3_600, 10, false));
+        rollup.record(&sample(3_700, 30, true));
+        rollup.record(&sample(7_200, 20, false));
+        rollup.record(&sample(86_400, 40, false));
+        assert_eq!(rollup.pending(), 3);
+        assert_eq!(rollup.flush(&store).await.unwrap(), 3);
+        assert_eq!(rollup.pending(), 0);
+
+        // A later flush adds to the stored buckets
+        rollup.record(&sample(3_800, 1_000, false));
+        rollup.flush(&store).await.unwrap();
+
+        let hourly = store
+            .query("f1", Granularity::Hour, 0, 86_400)
+            .await
+            .unwrap();
+        assert_eq!(
+            hourly.iter().map(|a| a.start).collect::<Vec<_>>(),
+            vec![3_600, 7_200]
+        );
+        assert_eq!(hourly[0].invocations, 3);
+        assert_eq!(hourly[0].errors, 1);
+
+        let daily = store
+            .query("f1", Granularity::Day, 0, 2 * 86_400)
+            .await
+            .unwrap();
+        assert_eq!(daily.len(), 2);
+        let summary = daily[0].summary();
+        assert_eq!(summary.invocations, 4);
+        assert_eq!(summary.p50_latency_ms, Some(20));
+        assert_eq!(summary.p95_latency_ms, Some(1_000));
+        assert_eq!(summary.gas_cost, 2.0);
+        assert_eq!(daily[1].invocations, 1);
+
+        assert!(store
+            .query("f2", Granularity::Day, 0, 2 * 86_400)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+}
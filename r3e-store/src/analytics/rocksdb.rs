@@ -0,0 +1,124 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+//! RocksDB analytics store.
+//!
+//! Aggregates are keyed by `{function_id}/{granularity}/{start}`, with the start zero-padded
+//! so that a function's buckets are sorted by time and a range is a single scan.
+
+use std::path::Path;
+use std::sync::Mutex;
+
+use async_trait::async_trait;
+
+use super::{AnalyticsError, AnalyticsStore, Granularity, UsageAggregate};
+use crate::rocksdb::{BatchOperation, RocksDbClient, RocksDbConfig};
+
+/// Column family of the aggregates
+const CF_USAGE: &str = "usage_analytics";
+
+/// Number of aggregates read per scan
+const SCAN_PAGE: usize = 1000;
+
+fn storage_error(e: impl std::fmt::Display) -> AnalyticsError {
+    AnalyticsError::Storage(e.to_string())
+}
+
+fn aggregate_key(function_id: &str, granularity: Granularity, start: u64) -> String {
+    format!("{}/{}/{:020}", function_id, granularity.as_str(), start)
+}
+
+/// RocksDB analytics store
+pub struct RocksDbAnalyticsStore {
+    db: RocksDbClient,
+
+    /// Serializes merges, which read the stored aggregates before writing them
+    merge_lock: Mutex<()>,
+}
+
+impl RocksDbAnalyticsStore {
+    /// Open the analytics store at a path
+    pub fn new<P: AsRef<Path>>(db_path: P) -> Result<Self, AnalyticsError> {
+        let db = RocksDbClient::new(RocksDbConfig {
+            path: db_path.as_ref().to_string_lossy().to_string(),
+            ..Default::default()
+        });
+        db.open().map_err(storage_error)?;
+        db.create_cf_if_missing(CF_USAGE).map_err(storage_error)?;
+
+        Ok(Self {
+            db,
+            merge_lock: Mutex::new(()),
+        })
+    }
+}
+
+#[async_trait]
+impl AnalyticsStore for RocksDbAnalyticsStore {
+    async fn merge(&self, aggregates: Vec<UsageAggregate>) -> Result<(), AnalyticsError> {
+        let _guard = self.merge_lock.lock().unwrap();
+
+        let mut operations = Vec::with_capacity(aggregates.len());
+        for mut aggregate in aggregates {
+            let key = aggregate_key(
+                &aggregate.function_id,
+                aggregate.granularity,
+                aggregate.start,
+            );
+            if let Some(stored) = self
+                .db
+                .get_cf::<_, UsageAggregate>(CF_USAGE, &key)
+                .map_err(storage_error)?
+            {
+                aggregate.merge(&stored);
+            }
+
+            operations.push(BatchOperation::Put {
+                cf_name: CF_USAGE.to_string(),
+                key: key.into_bytes(),
+                value: bincode::serialize(&aggregate).map_err(storage_error)?,
+            });
+        }
+
+        // All buckets of a flush are written at once, so hourly and daily aggregates agree
+        self.db.write_batch(operations).map_err(storage_error)
+    }
+
+    async fn query(
+        &self,
+        function_id: &str,
+        granularity: Granularity,
+        from: u64,
+        to: u64,
+    ) -> Result<Vec<UsageAggregate>, AnalyticsError> {
+        let end = aggregate_key(function_id, granularity, to);
+        let mut start = aggregate_key(function_id, granularity, from).into_bytes();
+        let mut first = true;
+
+        let mut aggregates = Vec::new();
+        loop {
+            let page = self
+                .db
+                .scan_cf::<UsageAggregate>(CF_USAGE, &start, SCAN_PAGE)
+                .map_err(storage_error)?;
+            let full = page.len() == SCAN_PAGE;
+
+            for (key, aggregate) in page {
+                // Pages after the first start at the last key already read
+                if !first && *key == *start {
+                    continue;
+                }
+                if *key >= *end.as_bytes() {
+                    return Ok(aggregates);
+                }
+                start = key.into_vec();
+                aggregates.push(aggregate);
+            }
+
+            if !full {
+                return Ok(aggregates);
+            }
+            first = false;
+        }
+    }
+}
@@ -0,0 +1,111 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+//! Encryption of backup artifacts.
+//!
+//! Artifacts are sealed with AES-256-GCM as `magic || version || nonce || ciphertext`, with
+//! the artifact name as associated data so a file cannot be swapped for another one.
+
+use aes_gcm::aead::{Aead, KeyInit, Payload};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use rand::RngCore;
+
+use super::BackupError;
+
+/// Magic prefix of sealed artifacts
+const MAGIC: &[u8; 4] = b"R3EB";
+
+/// Version of the sealed artifact format
+const VERSION: u8 = 1;
+
+/// AES-GCM nonce size
+const NONCE_SIZE: usize = 12;
+
+const HEADER_SIZE: usize = MAGIC.len() + 1 + NONCE_SIZE;
+
+/// Encrypt an artifact
+pub(crate) fn seal(key: &[u8; 32], aad: &[u8], plaintext: &[u8]) -> Result<Vec<u8>, BackupError> {
+    let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key));
+    let mut nonce = [0u8; NONCE_SIZE];
+    rand::thread_rng().fill_bytes(&mut nonce);
+
+    let ciphertext = cipher
+        .encrypt(
+            Nonce::from_slice(&nonce),
+            Payload {
+                msg: plaintext,
+                aad,
+            },
+        )
+        .map_err(|e| BackupError::Crypto(format!("failed to encrypt: {}", e)))?;
+
+    let mut sealed = Vec::with_capacity(HEADER_SIZE + ciphertext.len());
+    sealed.extend_from_slice(MAGIC);
+    sealed.push(VERSION);
+    sealed.extend_from_slice(&nonce);
+    sealed.extend_from_slice(&ciphertext);
+    Ok(sealed)
+}
+
+/// Decrypt an artifact
+pub(crate) fn open(key: &[u8; 32], aad: &[u8], sealed: &[u8]) -> Result<Vec<u8>, BackupError> {
+    if sealed.len() < HEADER_SIZE || &sealed[..MAGIC.len()] != MAGIC {
+        return Err(BackupError::Crypto("not a backup artifact".to_string()));
+    }
+    if sealed[MAGIC.len()] != VERSION {
+        return Err(BackupError::Crypto(format!(
+            "unsupported artifact version {}",
+            sealed[MAGIC.len()]
+        )));
+    }
+
+    let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key));
+    let nonce = &sealed[MAGIC.len() + 1..HEADER_SIZE];
+    cipher
+        .decrypt(
+            Nonce::from_slice(nonce),
+            Payload {
+                msg: &sealed[HEADER_SIZE..],
+                aad,
+            },
+        )
+        .map_err(|_| {
+            BackupError::Crypto("failed to decrypt: wrong key or tampered artifact".to_string())
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seal_roundtrip() {
+        let key = [7u8; 32];
+        let sealed = seal(&key, b"000012.sst", b"table data").unwrap();
+        assert_eq!(&sealed[..4], MAGIC);
+        assert_eq!(open(&key, b"000012.sst", &sealed).unwrap(), b"table data");
+
+        // Nonces are random
+        assert_ne!(seal(&key, b"000012.sst", b"table data").unwrap(), sealed);
+    }
+
+    #[test]
+    fn test_open_rejects_tampering() {
+        let key = [7u8; 32];
+        let sealed = seal(&key, b"000012.sst", b"table data").unwrap();
+
+        // Wrong key
+        assert!(open(&[8u8; 32], b"000012.sst", &sealed).is_err());
+
+        // Artifact of another file
+        assert!(open(&key, b"000013.sst", &sealed).is_err());
+
+        // Modified ciphertext
+        let mut tampered = sealed.clone();
+        *tampered.last_mut().unwrap() ^= 1;
+        assert!(open(&key, b"000012.sst", &tampered).is_err());
+
+        // Truncated artifact
+        assert!(open(&key, b"000012.sst", &sealed[..10]).is_err());
+    }
+}
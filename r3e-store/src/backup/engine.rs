@@ -0,0 +1,291 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use bytes::Bytes;
+use tokio::sync::Mutex;
+
+use super::crypto::{open, seal};
+use super::{
+    BackupConfig, BackupEntry, BackupError, BackupFile, BackupKeyProvider, BackupManifest,
+};
+use crate::blob::{digest, BlobRef, BlobStore};
+use crate::rocksdb::RocksDbClient;
+
+/// Associated data of encrypted manifests
+const MANIFEST_AAD: &[u8] = b"manifest";
+
+/// Takes encrypted backups of a RocksDB store and restores them
+pub struct BackupEngine {
+    db: Arc<RocksDbClient>,
+    blobs: Arc<dyn BlobStore>,
+    key: Arc<dyn BackupKeyProvider>,
+    config: BackupConfig,
+    /// Backups taken, oldest first; the lock also serializes backups
+    catalog: Mutex<Vec<BackupEntry>>,
+}
+
+impl BackupEngine {
+    /// Create a backup engine, loading the catalog of earlier backups
+    pub fn new(
+        db: Arc<RocksDbClient>,
+        blobs: Arc<dyn BlobStore>,
+        key: Arc<dyn BackupKeyProvider>,
+        config: BackupConfig,
+    ) -> Result<Self, BackupError> {
+        let catalog = match std::fs::read(&config.catalog_path) {
+            Ok(data) => serde_json::from_slice(&data)
+                .map_err(|e| BackupError::Manifest(format!("invalid catalog: {}", e)))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e.into()),
+        };
+
+        Ok(Self {
+            db,
+            blobs,
+            key,
+            config,
+            catalog: Mutex::new(catalog),
+        })
+    }
+
+    /// Backups taken, oldest first
+    pub async fn list_backups(&self) -> Vec<BackupEntry> {
+        self.catalog.lock().await.clone()
+    }
+
+    /// Take a backup, incremental unless a full backup is due
+    pub async fn create_backup(&self) -> Result<BackupEntry, BackupError> {
+        let mut catalog = self.catalog.lock().await;
+        let key = self.key.backup_key().await?;
+
+        let since_full = catalog.iter().rev().take_while(|e| e.incremental).count() as u32;
+        let parent = catalog
+            .last()
+            .filter(|_| since_full + 1 < self.config.full_every);
+        let previous: HashMap<(String, String), BackupFile> = match parent {
+            Some(parent) => read_manifest(self.blobs.as_ref(), &key, &parent.manifest)
+                .await?
+                .files
+                .into_iter()
+                .map(|file| ((file.name.clone(), file.sha256.clone()), file))
+                .collect(),
+            None => HashMap::new(),
+        };
+
+        let now = chrono::Utc::now();
+        let id = now.format("%Y%m%dT%H%M%S%3fZ").to_string();
+        tokio::fs::create_dir_all(&self.config.staging_dir).await?;
+        let checkpoint_dir = self.config.staging_dir.join(&id);
+        let db = self.db.clone();
+        let dir = checkpoint_dir.clone();
+        tokio::task::spawn_blocking(move || db.create_checkpoint(&dir))
+            .await
+            .map_err(|e| BackupError::Io(std::io::Error::other(e)))??;
+
+        let result = self
+            .upload_checkpoint(&key, &checkpoint_dir, &previous)
+            .await;
+        if let Err(e) = tokio::fs::remove_dir_all(&checkpoint_dir).await {
+            log::warn!(
+                "backup: failed to remove checkpoint {}: {}",
+                checkpoint_dir.display(),
+                e
+            );
+        }
+        let (files, uploaded_files, uploaded_bytes) = result?;
+
+        let manifest = BackupManifest {
+            id: id.clone(),
+            created_at: now.timestamp_millis() as u64,
+            parent: parent.map(|parent| parent.id.clone()),
+            files,
+        };
+        let data = serde_json::to_vec(&manifest)
+            .map_err(|e| BackupError::Manifest(format!("failed to encode manifest: {}", e)))?;
+        let manifest_ref = self
+            .blobs
+            .put(Bytes::from(seal(&key, MANIFEST_AAD, &data)?))
+            .await?;
+
+        let entry = BackupEntry {
+            id,
+            created_at: manifest.created_at,
+            manifest: manifest_ref,
+            incremental: manifest.parent.is_some(),
+            files: manifest.files.len(),
+            uploaded_files,
+            uploaded_bytes,
+        };
+        catalog.push(entry.clone());
+        self.save_catalog(&catalog).await?;
+
+        Ok(entry)
+    }
+
+    /// Restore the latest backup into a new database directory
+    pub async fn restore_latest(&self, target: &Path) -> Result<BackupManifest, BackupError> {
+        let manifest = match self.catalog.lock().await.last() {
+            Some(entry) => entry.manifest.clone(),
+            None => return Err(BackupError::NotFound("no backup taken yet".to_string())),
+        };
+        self.restore(&manifest, target).await
+    }
+
+    /// Restore a backup into a new database directory, see [`restore`]
+    pub async fn restore(
+        &self,
+        manifest: &BlobRef,
+        target: &Path,
+    ) -> Result<BackupManifest, BackupError> {
+        restore(self.blobs.as_ref(), self.key.as_ref(), manifest, target).await
+    }
+
+    /// Encrypt and upload the files of a checkpoint, reusing unchanged files of the previous
+    /// backup; returns the files and the number of files and bytes uploaded
+    async fn upload_checkpoint(
+        &self,
+        key: &[u8; 32],
+        dir: &Path,
+        previous: &HashMap<(String, String), BackupFile>,
+    ) -> Result<(Vec<BackupFile>, usize, u64), BackupError> {
+        let mut names = Vec::new();
+        let mut entries = tokio::fs::read_dir(dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let name = entry
+                .file_name()
+                .into_string()
+                .map_err(|name| BackupError::Manifest(format!("non UTF-8 file name {:?}", name)))?;
+            names.push(name);
+        }
+        names.sort();
+
+        let mut files = Vec::with_capacity(names.len());
+        let (mut uploaded_files, mut uploaded_bytes) = (0, 0);
+        for name in names {
+            let data = tokio::fs::read(dir.join(&name)).await?;
+            let sha256 = digest(&data);
+            if let Some(file) = previous.get(&(name.clone(), sha256.clone())) {
+                files.push(file.clone());
+                continue;
+            }
+
+            let blob = self
+                .blobs
+                .put(Bytes::from(seal(key, name.as_bytes(), &data)?))
+                .await?;
+            uploaded_files += 1;
+            uploaded_bytes += data.len() as u64;
+            files.push(BackupFile {
+                name,
+                size: data.len() as u64,
+                sha256,
+                blob,
+            });
+        }
+
+        Ok((files, uploaded_files, uploaded_bytes))
+    }
+
+    /// Write the catalog, replacing the previous one atomically
+    async fn save_catalog(&self, catalog: &[BackupEntry]) -> Result<(), BackupError> {
+        let path = &self.config.catalog_path;
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        let data = serde_json::to_vec_pretty(catalog)
+            .map_err(|e| BackupError::Manifest(format!("failed to encode catalog: {}", e)))?;
+        let tmp = with_suffix(path, ".tmp");
+        tokio::fs::write(&tmp, data).await?;
+        tokio::fs::rename(&tmp, path).await?;
+        Ok(())
+    }
+}
+
+/// Restore a backup into a new database directory.
+///
+/// Only the blob store and the backup key are needed, so a replacement node can restore
+/// from the manifest reference alone. Every file is decrypted and checked against the
+/// manifest, and `target` only appears once all files are in place; open it with a
+/// [`RocksDbClient`] configured with the column families of the backed up database.
+pub async fn restore(
+    blobs: &dyn BlobStore,
+    key: &dyn BackupKeyProvider,
+    manifest: &BlobRef,
+    target: &Path,
+) -> Result<BackupManifest, BackupError> {
+    if tokio::fs::try_exists(target).await? {
+        return Err(BackupError::Io(std::io::Error::new(
+            std::io::ErrorKind::AlreadyExists,
+            format!("restore target {} already exists", target.display()),
+        )));
+    }
+
+    let key = key.backup_key().await?;
+    let manifest = read_manifest(blobs, &key, manifest).await?;
+
+    let staging = with_suffix(target, ".restoring");
+    if tokio::fs::try_exists(&staging).await? {
+        tokio::fs::remove_dir_all(&staging).await?;
+    }
+    tokio::fs::create_dir_all(&staging).await?;
+
+    let result = restore_files(blobs, &key, &manifest, &staging).await;
+    if result.is_err() {
+        let _ = tokio::fs::remove_dir_all(&staging).await;
+    }
+    result?;
+    tokio::fs::rename(&staging, target).await?;
+
+    Ok(manifest)
+}
+
+async fn restore_files(
+    blobs: &dyn BlobStore,
+    key: &[u8; 32],
+    manifest: &BackupManifest,
+    dir: &Path,
+) -> Result<(), BackupError> {
+    for file in &manifest.files {
+        // Names come from the manifest, keep them inside the target directory
+        if file.name.is_empty()
+            || file.name == "."
+            || file.name == ".."
+            || file.name.contains(['/', '\\'])
+        {
+            return Err(BackupError::Manifest(format!(
+                "invalid file name {:?}",
+                file.name
+            )));
+        }
+
+        let data = open(key, file.name.as_bytes(), &blobs.get(&file.blob).await?)?;
+        if data.len() as u64 != file.size || digest(&data) != file.sha256 {
+            return Err(BackupError::Manifest(format!(
+                "restored file {} does not match the manifest",
+                file.name
+            )));
+        }
+        tokio::fs::write(dir.join(&file.name), data).await?;
+    }
+    Ok(())
+}
+
+async fn read_manifest(
+    blobs: &dyn BlobStore,
+    key: &[u8; 32],
+    manifest: &BlobRef,
+) -> Result<BackupManifest, BackupError> {
+    let data = open(key, MANIFEST_AAD, &blobs.get(manifest).await?)?;
+    serde_json::from_slice(&data).map_err(|e| BackupError::Manifest(e.to_string()))
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut path: OsString = path.as_os_str().to_owned();
+    path.push(suffix);
+    PathBuf::from(path)
+}
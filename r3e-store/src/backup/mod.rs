@@ -0,0 +1,210 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+//! Encrypted, incremental backups of the RocksDB store.
+//!
+//! A backup is a RocksDB checkpoint whose files are encrypted with AES-256-GCM and uploaded
+//! to a [`BlobStore`](crate::blob::BlobStore), e.g. S3 or NeoFS. SST files are immutable, so
+//! an incremental backup only uploads the files that changed since the previous one and
+//! refers to the earlier uploads for the others; every `full_every` backups all files are
+//! uploaded again. Each backup is described by an encrypted [`BackupManifest`], and the
+//! reference to it is all [`BackupEngine::restore`] needs to rebuild the database.
+//!
+//! The encryption key comes from a [`BackupKeyProvider`]; `r3e-secrets` provides one backed
+//! by the secret vault. [`spawn_scheduled_backups`] runs backups at a fixed interval.
+
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::blob::{BlobError, BlobRef};
+use crate::rocksdb::DbError;
+
+mod crypto;
+pub mod engine;
+
+pub use engine::{restore, BackupEngine};
+
+/// Error type for backup operations
+#[derive(Debug, Error)]
+pub enum BackupError {
+    /// Database error
+    #[error("backup: database error: {0}")]
+    Db(#[from] DbError),
+
+    /// Blob store error
+    #[error("backup: blob error: {0}")]
+    Blob(#[from] BlobError),
+
+    /// File system error
+    #[error("backup: I/O error: {0}")]
+    Io(#[from] std::io::Error),
+
+    /// Encryption or decryption failure
+    #[error("backup: crypto error: {0}")]
+    Crypto(String),
+
+    /// Backup key unavailable or invalid
+    #[error("backup: key error: {0}")]
+    Key(String),
+
+    /// Manifest is malformed or does not match the restored files
+    #[error("backup: invalid manifest: {0}")]
+    Manifest(String),
+
+    /// No such backup
+    #[error("backup: not found: {0}")]
+    NotFound(String),
+}
+
+/// Source of the backup encryption key
+#[async_trait]
+pub trait BackupKeyProvider: Send + Sync {
+    /// AES-256 key the backup artifacts are encrypted with
+    async fn backup_key(&self) -> Result<[u8; 32], BackupError>;
+}
+
+/// Fixed backup key, for tests and keys loaded at startup
+pub struct StaticBackupKey([u8; 32]);
+
+impl StaticBackupKey {
+    /// Create a provider of a fixed key
+    pub fn new(key: [u8; 32]) -> Self {
+        Self(key)
+    }
+}
+
+#[async_trait]
+impl BackupKeyProvider for StaticBackupKey {
+    async fn backup_key(&self) -> Result<[u8; 32], BackupError> {
+        Ok(self.0)
+    }
+}
+
+/// Backup configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackupConfig {
+    /// Directory checkpoints are staged in, on the database's file system so SST files are
+    /// hard linked instead of copied
+    #[serde(default = "default_staging_dir")]
+    pub staging_dir: PathBuf,
+
+    /// JSON file listing the backups taken
+    #[serde(default = "default_catalog_path")]
+    pub catalog_path: PathBuf,
+
+    /// Take a full backup every this many backups, the others are incremental
+    #[serde(default = "default_full_every")]
+    pub full_every: u32,
+}
+
+fn default_staging_dir() -> PathBuf {
+    PathBuf::from("data/backup/staging")
+}
+
+fn default_catalog_path() -> PathBuf {
+    PathBuf::from("data/backup/catalog.json")
+}
+
+fn default_full_every() -> u32 {
+    24
+}
+
+impl Default for BackupConfig {
+    fn default() -> Self {
+        Self {
+            staging_dir: default_staging_dir(),
+            catalog_path: default_catalog_path(),
+            full_every: default_full_every(),
+        }
+    }
+}
+
+/// File of a backup
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BackupFile {
+    /// File name in the checkpoint directory
+    pub name: String,
+
+    /// Plaintext size in bytes
+    pub size: u64,
+
+    /// Hex encoded SHA-256 digest of the plaintext
+    pub sha256: String,
+
+    /// Encrypted file, possibly uploaded by an earlier backup
+    pub blob: BlobRef,
+}
+
+/// Manifest of a backup, stored encrypted next to its files
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackupManifest {
+    /// Backup ID
+    pub id: String,
+
+    /// Creation time in milliseconds since the epoch
+    pub created_at: u64,
+
+    /// Backup the unchanged files were taken from, `None` for full backups
+    pub parent: Option<String>,
+
+    /// Checkpoint files
+    pub files: Vec<BackupFile>,
+}
+
+/// Catalog entry of a backup
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackupEntry {
+    /// Backup ID
+    pub id: String,
+
+    /// Creation time in milliseconds since the epoch
+    pub created_at: u64,
+
+    /// Encrypted manifest, what a restore starts from
+    pub manifest: BlobRef,
+
+    /// Whether unchanged files were reused from the previous backup
+    pub incremental: bool,
+
+    /// Number of files in the backup
+    pub files: usize,
+
+    /// Number of files uploaded by this backup
+    pub uploaded_files: usize,
+
+    /// Plaintext bytes uploaded by this backup
+    pub uploaded_bytes: u64,
+}
+
+/// Take a backup at a fixed interval
+pub fn spawn_scheduled_backups(
+    engine: Arc<BackupEngine>,
+    interval: Duration,
+) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(interval);
+        loop {
+            interval.tick().await;
+            match engine.create_backup().await {
+                Ok(entry) => log::info!(
+                    "backup: created {} backup {} ({} of {} files, {} bytes uploaded)",
+                    if entry.incremental {
+                        "incremental"
+                    } else {
+                        "full"
+                    },
+                    entry.id,
+                    entry.uploaded_files,
+                    entry.files,
+                    entry.uploaded_bytes
+                ),
+                Err(e) => log::error!("backup: scheduled backup failed: {}", e),
+            }
+        }
+    })
+}
@@ -0,0 +1,122 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+//! In-memory blob store, for tests and single-node development
+
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+use async_trait::async_trait;
+use bytes::Bytes;
+
+use super::{BlobError, BlobRef, BlobStore};
+
+/// In-memory blob store
+#[derive(Debug, Default)]
+pub struct MemoryBlobStore {
+    blobs: RwLock<HashMap<String, Bytes>>,
+    max_blob_size: Option<u64>,
+}
+
+impl MemoryBlobStore {
+    /// Create a new memory blob store
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reject blobs larger than `max_blob_size` bytes
+    pub fn with_max_blob_size(mut self, max_blob_size: u64) -> Self {
+        self.max_blob_size = Some(max_blob_size);
+        self
+    }
+
+    /// Number of distinct blobs stored
+    pub fn len(&self) -> usize {
+        self.blobs.read().unwrap().len()
+    }
+
+    /// Whether no blob is stored
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[async_trait]
+impl BlobStore for MemoryBlobStore {
+    async fn put(&self, data: Bytes) -> Result<BlobRef, BlobError> {
+        let blob = BlobRef::of(&data);
+        if let Some(limit) = self.max_blob_size {
+            if blob.size > limit {
+                return Err(BlobError::TooLarge {
+                    size: blob.size,
+                    limit,
+                });
+            }
+        }
+
+        self.blobs
+            .write()
+            .unwrap()
+            .entry(blob.digest.clone())
+            .or_insert(data);
+        Ok(blob)
+    }
+
+    async fn get(&self, blob: &BlobRef) -> Result<Bytes, BlobError> {
+        let data = self
+            .blobs
+            .read()
+            .unwrap()
+            .get(&blob.digest)
+            .cloned()
+            .ok_or_else(|| BlobError::NotFound(blob.digest.clone()))?;
+        blob.verify(&data)?;
+        Ok(data)
+    }
+
+    async fn exists(&self, blob: &BlobRef) -> Result<bool, BlobError> {
+        Ok(self.blobs.read().unwrap().contains_key(&blob.digest))
+    }
+
+    async fn delete(&self, blob: &BlobRef) -> Result<(), BlobError> {
+        self.blobs.write().unwrap().remove(&blob.digest);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_content_addressing() {
+        let store = MemoryBlobStore::new().with_max_blob_size(16);
+
+        let first = store.put(Bytes::from_static(b"proving key")).await.unwrap();
+        let second = store.put(Bytes::from_static(b"proving key")).await.unwrap();
+        assert_eq!(first, second);
+        assert_eq!(store.len(), 1);
+        assert!(store.exists(&first).await.unwrap());
+        assert_eq!(store.get(&first).await.unwrap(), "proving key");
+
+        let missing = BlobRef::of(b"ciphertext");
+        assert!(!store.exists(&missing).await.unwrap());
+        assert!(matches!(
+            store.get(&missing).await,
+            Err(BlobError::NotFound(_))
+        ));
+
+        store.delete(&first).await.unwrap();
+        store.delete(&first).await.unwrap();
+        assert!(!store.exists(&first).await.unwrap());
+        assert!(store.is_empty());
+
+        assert!(matches!(
+            store.put(Bytes::from(vec![0u8; 17])).await,
+            Err(BlobError::TooLarge {
+                size: 17,
+                limit: 16
+            })
+        ));
+    }
+}
@@ -0,0 +1,133 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+//! Content-addressed storage for large artifacts.
+//!
+//! Function bundles, proving keys and ciphertexts are kept out of the key-value tables: they
+//! are written to a blob store once, and metadata refers to them with a [`BlobRef`].
+
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+
+pub mod memory;
+pub mod neofs;
+pub mod s3;
+
+pub use memory::MemoryBlobStore;
+pub use neofs::{NeoFsBlobStore, NeoFsConfig};
+pub use s3::{S3BlobStore, S3Config};
+
+/// Error type for blob operations
+#[derive(Debug, Error)]
+pub enum BlobError {
+    /// No blob with the digest
+    #[error("blob: no such blob: {0}")]
+    NotFound(String),
+
+    /// Blob is larger than the store accepts
+    #[error("blob: blob of {size} bytes exceeds the limit of {limit} bytes")]
+    TooLarge { size: u64, limit: u64 },
+
+    /// Stored bytes do not hash to the referenced digest
+    #[error("blob: digest mismatch, expected {expected}, got {actual}")]
+    DigestMismatch { expected: String, actual: String },
+
+    /// Backend failure
+    #[error("blob: backend error: {0}")]
+    Backend(String),
+}
+
+/// Reference to a stored blob, what metadata keeps instead of the bytes
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct BlobRef {
+    /// Hex encoded SHA-256 digest of the content
+    pub digest: String,
+
+    /// Content size in bytes
+    pub size: u64,
+
+    /// Backend specific location, e.g. the NeoFS object ID; lookups fall back to the digest
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub location: Option<String>,
+}
+
+impl BlobRef {
+    /// Reference of some content, without a location
+    pub fn of(data: &[u8]) -> Self {
+        Self {
+            digest: digest(data),
+            size: data.len() as u64,
+            location: None,
+        }
+    }
+
+    /// Check that fetched content is the referenced one
+    pub fn verify(&self, data: &[u8]) -> Result<(), BlobError> {
+        let actual = digest(data);
+        if actual != self.digest {
+            return Err(BlobError::DigestMismatch {
+                expected: self.digest.clone(),
+                actual,
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Hex encoded SHA-256 digest
+pub fn digest(data: &[u8]) -> String {
+    hex::encode(Sha256::digest(data))
+}
+
+/// Content-addressed blob store.
+///
+/// Blobs are immutable and deduplicated: putting the same content twice yields the same
+/// digest and stores it once.
+#[async_trait]
+pub trait BlobStore: Send + Sync {
+    /// Store a blob
+    async fn put(&self, data: Bytes) -> Result<BlobRef, BlobError>;
+
+    /// Fetch a blob, verified against its digest
+    async fn get(&self, blob: &BlobRef) -> Result<Bytes, BlobError>;
+
+    /// Whether the blob is stored
+    async fn exists(&self, blob: &BlobRef) -> Result<bool, BlobError>;
+
+    /// Delete a blob, succeeding if it is not stored.
+    ///
+    /// Content is shared by everything referring to the same digest, so callers only delete
+    /// blobs nothing else refers to.
+    async fn delete(&self, blob: &BlobRef) -> Result<(), BlobError>;
+}
+
+/// Backend of a blob store
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(tag = "backend", rename_all = "lowercase")]
+pub enum BlobStoreConfig {
+    /// In-memory, for tests and single-node development
+    #[default]
+    Memory,
+
+    /// S3 or a compatible object store
+    S3(S3Config),
+
+    /// NeoFS through its HTTP gateway
+    NeoFs(NeoFsConfig),
+}
+
+impl BlobStoreConfig {
+    /// Open the configured blob store
+    pub fn open(&self) -> Result<Arc<dyn BlobStore>, BlobError> {
+        Ok(match self {
+            Self::Memory => Arc::new(MemoryBlobStore::new()),
+            Self::S3(config) => Arc::new(S3BlobStore::new(config.clone())?),
+            Self::NeoFs(config) => Arc::new(NeoFsBlobStore::new(config.clone())?),
+        })
+    }
+}
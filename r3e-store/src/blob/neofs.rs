@@ -0,0 +1,198 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+//! NeoFS blob store, talking to a NeoFS HTTP gateway.
+//!
+//! Objects are uploaded with a `Sha256` attribute holding their digest, so a blob can be found
+//! by content even when its object ID was not recorded.
+
+use std::time::Duration;
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use reqwest::{Client, RequestBuilder, StatusCode};
+use serde::{Deserialize, Serialize};
+
+use super::{BlobError, BlobRef, BlobStore};
+
+/// Object attribute holding the content digest
+const DIGEST_ATTRIBUTE: &str = "Sha256";
+
+/// NeoFS blob store configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NeoFsConfig {
+    /// HTTP gateway URL, e.g. `https://http.fs.neo.org`
+    pub gateway_url: String,
+
+    /// Container the blobs are stored in
+    pub container_id: String,
+
+    /// Base64 encoded bearer token, required by containers with restricted access
+    #[serde(default)]
+    pub bearer_token: Option<String>,
+
+    /// Request timeout in seconds
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+
+    /// Maximum blob size in bytes
+    #[serde(default = "default_max_blob_size")]
+    pub max_blob_size: u64,
+}
+
+fn default_timeout_secs() -> u64 {
+    120
+}
+
+fn default_max_blob_size() -> u64 {
+    // Proving keys of larger circuits run into the hundreds of megabytes
+    1024 * 1024 * 1024
+}
+
+/// Upload response of the gateway
+#[derive(Debug, Deserialize)]
+struct UploadResponse {
+    object_id: String,
+}
+
+/// NeoFS blob store
+pub struct NeoFsBlobStore {
+    config: NeoFsConfig,
+    client: Client,
+}
+
+impl NeoFsBlobStore {
+    /// Create a new NeoFS blob store
+    pub fn new(config: NeoFsConfig) -> Result<Self, BlobError> {
+        let client = Client::builder()
+            .timeout(Duration::from_secs(config.timeout_secs))
+            .build()
+            .map_err(|e| BlobError::Backend(format!("failed to create HTTP client: {}", e)))?;
+        Ok(Self { config, client })
+    }
+
+    fn gateway(&self) -> &str {
+        self.config.gateway_url.trim_end_matches('/')
+    }
+
+    /// URL of the object, by ID when known and by digest otherwise
+    fn object_url(&self, blob: &BlobRef) -> String {
+        match &blob.location {
+            Some(object_id) => format!(
+                "{}/get/{}/{}",
+                self.gateway(),
+                self.config.container_id,
+                object_id
+            ),
+            None => format!(
+                "{}/get_by_attribute/{}/{}/{}",
+                self.gateway(),
+                self.config.container_id,
+                DIGEST_ATTRIBUTE,
+                blob.digest
+            ),
+        }
+    }
+
+    fn authorize(&self, request: RequestBuilder) -> RequestBuilder {
+        match &self.config.bearer_token {
+            Some(token) => request.bearer_auth(token),
+            None => request,
+        }
+    }
+}
+
+fn backend_error(e: reqwest::Error) -> BlobError {
+    BlobError::Backend(format!("NeoFS gateway request failed: {}", e))
+}
+
+#[async_trait]
+impl BlobStore for NeoFsBlobStore {
+    async fn put(&self, data: Bytes) -> Result<BlobRef, BlobError> {
+        let mut blob = BlobRef::of(&data);
+        if blob.size > self.config.max_blob_size {
+            return Err(BlobError::TooLarge {
+                size: blob.size,
+                limit: self.config.max_blob_size,
+            });
+        }
+
+        // Content is immutable, an object with the digest already holds it
+        if self.exists(&blob).await? {
+            return Ok(blob);
+        }
+
+        let part = reqwest::multipart::Part::stream(data).file_name(blob.digest.clone());
+        let form = reqwest::multipart::Form::new().part("file", part);
+        let url = format!("{}/upload/{}", self.gateway(), self.config.container_id);
+        let response = self
+            .authorize(self.client.post(url))
+            .header(format!("X-Attribute-{}", DIGEST_ATTRIBUTE), &blob.digest)
+            .multipart(form)
+            .send()
+            .await
+            .map_err(backend_error)?;
+        if !response.status().is_success() {
+            return Err(BlobError::Backend(format!(
+                "NeoFS upload failed with status {}",
+                response.status()
+            )));
+        }
+
+        let upload: UploadResponse = response.json().await.map_err(backend_error)?;
+        log::debug!(
+            "blob: stored {} ({} bytes) as NeoFS object {}",
+            blob.digest,
+            blob.size,
+            upload.object_id
+        );
+        blob.location = Some(upload.object_id);
+        Ok(blob)
+    }
+
+    async fn get(&self, blob: &BlobRef) -> Result<Bytes, BlobError> {
+        let response = self
+            .authorize(self.client.get(self.object_url(blob)))
+            .send()
+            .await
+            .map_err(backend_error)?;
+        match response.status() {
+            status if status.is_success() => {}
+            StatusCode::NOT_FOUND => return Err(BlobError::NotFound(blob.digest.clone())),
+            status => {
+                return Err(BlobError::Backend(format!(
+                    "NeoFS download failed with status {}",
+                    status
+                )))
+            }
+        }
+
+        let data = response.bytes().await.map_err(backend_error)?;
+        blob.verify(&data)?;
+        Ok(data)
+    }
+
+    async fn exists(&self, blob: &BlobRef) -> Result<bool, BlobError> {
+        let response = self
+            .authorize(self.client.head(self.object_url(blob)))
+            .send()
+            .await
+            .map_err(backend_error)?;
+        match response.status() {
+            status if status.is_success() => Ok(true),
+            StatusCode::NOT_FOUND => Ok(false),
+            status => Err(BlobError::Backend(format!(
+                "NeoFS lookup failed with status {}",
+                status
+            ))),
+        }
+    }
+
+    async fn delete(&self, blob: &BlobRef) -> Result<(), BlobError> {
+        // The HTTP gateway only reads and uploads objects, deletes need a NeoFS client
+        Err(BlobError::Backend(format!(
+            "NeoFS gateway cannot delete object {}",
+            blob.location.as_deref().unwrap_or(&blob.digest)
+        )))
+    }
+}
@@ -0,0 +1,387 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+//! Value encoding of tables with transparent compression and chunking.
+//!
+//! Values of tables with [`TableOptions`] start with a header naming the codec, the
+//! uncompressed length and the number of chunks. Values larger than [`MAX_VALUE_SIZE`]
+//! once compressed are split into chunks stored under separate keys, the entry of the
+//! value then only holds the header. Values without a header are returned as stored,
+//! so compression can be enabled on a table that already holds data.
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::types::MAX_VALUE_SIZE;
+
+/// Magic bytes and format version starting every encoded value
+pub const VALUE_MAGIC: [u8; 4] = *b"R3V\x01";
+
+/// Length of the header of an encoded value
+pub const HEADER_LEN: usize = VALUE_MAGIC.len() + 1 + 8 + 4;
+
+/// Default maximum size of a value before compression (256 MB)
+pub const MAX_CHUNKED_VALUE_SIZE: usize = 256 * 1024 * 1024;
+
+/// Suffix of the table holding the chunks of a table
+pub const CHUNK_TABLE_SUFFIX: &str = "__chunks";
+
+/// Error type for value encoding
+#[derive(Debug, Error)]
+pub enum CodecError {
+    /// Value is larger than the table allows
+    #[error("value of {size} bytes exceeds the maximum of {max} bytes")]
+    TooLarge { size: usize, max: usize },
+
+    /// Compression failed
+    #[error("compression failed: {0}")]
+    Compression(String),
+
+    /// Stored value is damaged or incomplete
+    #[error("corrupted value: {0}")]
+    Corrupted(String),
+}
+
+/// Compression of the values of a table
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ValueCompression {
+    /// Values are stored as is
+    #[default]
+    None,
+
+    /// LZ4 block compression, fast with a moderate ratio
+    Lz4,
+
+    /// Zstandard compression at the given level
+    Zstd {
+        /// Compression level (1 to 22)
+        level: i32,
+    },
+}
+
+impl ValueCompression {
+    fn tag(&self) -> u8 {
+        match self {
+            ValueCompression::None => 0,
+            ValueCompression::Lz4 => 1,
+            ValueCompression::Zstd { .. } => 2,
+        }
+    }
+}
+
+/// Encoding options of a table
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TableOptions {
+    /// Compression of the values
+    #[serde(default)]
+    pub compression: ValueCompression,
+
+    /// Values smaller than this are not compressed (default: 256 bytes)
+    #[serde(default = "default_min_compress_size")]
+    pub min_compress_size: usize,
+
+    /// Maximum size of a value before compression (default: 256 MB)
+    #[serde(default = "default_max_value_size")]
+    pub max_value_size: usize,
+}
+
+fn default_min_compress_size() -> usize {
+    256
+}
+
+fn default_max_value_size() -> usize {
+    MAX_CHUNKED_VALUE_SIZE
+}
+
+impl Default for TableOptions {
+    fn default() -> Self {
+        Self {
+            compression: ValueCompression::None,
+            min_compress_size: default_min_compress_size(),
+            max_value_size: default_max_value_size(),
+        }
+    }
+}
+
+impl TableOptions {
+    /// Options compressing values with the given compression
+    pub fn compressed(compression: ValueCompression) -> Self {
+        Self {
+            compression,
+            ..Default::default()
+        }
+    }
+}
+
+/// Value encoded for storage
+#[derive(Debug, Clone)]
+pub struct EncodedValue {
+    /// Stored under the key of the value
+    pub head: Vec<u8>,
+
+    /// Stored under the chunk keys of the value, empty for values stored inline
+    pub chunks: Vec<Vec<u8>>,
+
+    /// Length of the value before encoding
+    pub logical_len: usize,
+}
+
+impl EncodedValue {
+    /// Number of bytes written to storage
+    pub fn stored_len(&self) -> usize {
+        self.head.len() + self.chunks.iter().map(Vec::len).sum::<usize>()
+    }
+}
+
+/// Header of an encoded value
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ValueHeader {
+    /// Codec tag
+    pub codec: u8,
+
+    /// Length of the value before encoding
+    pub logical_len: u64,
+
+    /// Number of chunks, zero for values stored inline
+    pub chunks: u32,
+}
+
+/// Encode a value with the options of its table
+pub fn encode_value(options: &TableOptions, value: &[u8]) -> Result<EncodedValue, CodecError> {
+    if value.len() > options.max_value_size {
+        return Err(CodecError::TooLarge {
+            size: value.len(),
+            max: options.max_value_size,
+        });
+    }
+
+    // Keep the value as is unless compression makes it smaller
+    let mut codec = ValueCompression::None;
+    let mut payload = None;
+    if options.compression != ValueCompression::None && value.len() >= options.min_compress_size {
+        let compressed = compress(options.compression, value)?;
+        if compressed.len() < value.len() {
+            codec = options.compression;
+            payload = Some(compressed);
+        }
+    }
+    let payload = payload.unwrap_or_else(|| value.to_vec());
+
+    let mut head = Vec::with_capacity(HEADER_LEN + payload.len().min(MAX_VALUE_SIZE));
+    head.extend_from_slice(&VALUE_MAGIC);
+    head.push(codec.tag());
+    head.extend_from_slice(&(value.len() as u64).to_le_bytes());
+
+    if HEADER_LEN + payload.len() <= MAX_VALUE_SIZE {
+        head.extend_from_slice(&0u32.to_le_bytes());
+        head.extend_from_slice(&payload);
+        return Ok(EncodedValue {
+            head,
+            chunks: vec![],
+            logical_len: value.len(),
+        });
+    }
+
+    let chunks: Vec<Vec<u8>> = payload
+        .chunks(MAX_VALUE_SIZE)
+        .map(|chunk| chunk.to_vec())
+        .collect();
+    head.extend_from_slice(&(chunks.len() as u32).to_le_bytes());
+
+    Ok(EncodedValue {
+        head,
+        chunks,
+        logical_len: value.len(),
+    })
+}
+
+/// Header of a stored value, `None` for values stored without one
+pub fn parse_header(stored: &[u8]) -> Option<ValueHeader> {
+    if stored.len() < HEADER_LEN || stored[..VALUE_MAGIC.len()] != VALUE_MAGIC {
+        return None;
+    }
+
+    let codec = stored[VALUE_MAGIC.len()];
+    let logical_len = u64::from_le_bytes(stored[5..13].try_into().unwrap());
+    let chunks = u32::from_le_bytes(stored[13..HEADER_LEN].try_into().unwrap());
+
+    Some(ValueHeader {
+        codec,
+        logical_len,
+        chunks,
+    })
+}
+
+/// Decode a stored value, reading its chunks with `read_chunk` if it has any
+pub fn decode_value<F>(stored: &[u8], mut read_chunk: F) -> Result<Vec<u8>, CodecError>
+where
+    F: FnMut(u32) -> Result<Option<Vec<u8>>, CodecError>,
+{
+    let Some(header) = parse_header(stored) else {
+        return Ok(stored.to_vec());
+    };
+
+    let payload = if header.chunks == 0 {
+        stored[HEADER_LEN..].to_vec()
+    } else {
+        let mut payload = Vec::new();
+        for index in 0..header.chunks {
+            let chunk = read_chunk(index)?.ok_or_else(|| {
+                CodecError::Corrupted(format!("missing chunk {} of {}", index, header.chunks))
+            })?;
+            payload.extend_from_slice(&chunk);
+        }
+        payload
+    };
+
+    let value = decompress(header.codec, &payload, header.logical_len as usize)?;
+    if value.len() as u64 != header.logical_len {
+        return Err(CodecError::Corrupted(format!(
+            "expected {} bytes, decoded {}",
+            header.logical_len,
+            value.len()
+        )));
+    }
+
+    Ok(value)
+}
+
+/// Table holding the chunks of `table`
+pub fn chunk_table(table: &str) -> String {
+    format!("{}{}", table, CHUNK_TABLE_SUFFIX)
+}
+
+/// Key of a chunk of the value stored under `key`
+pub fn chunk_key(key: &[u8], index: u32) -> Vec<u8> {
+    let mut chunk_key = Vec::with_capacity(key.len() + 4);
+    chunk_key.extend_from_slice(key);
+    chunk_key.extend_from_slice(&index.to_be_bytes());
+    chunk_key
+}
+
+fn compress(compression: ValueCompression, value: &[u8]) -> Result<Vec<u8>, CodecError> {
+    match compression {
+        ValueCompression::None => Ok(value.to_vec()),
+        ValueCompression::Lz4 => Ok(lz4_flex::block::compress(value)),
+        ValueCompression::Zstd { level } => {
+            zstd::bulk::compress(value, level).map_err(|e| CodecError::Compression(e.to_string()))
+        }
+    }
+}
+
+fn decompress(codec: u8, payload: &[u8], logical_len: usize) -> Result<Vec<u8>, CodecError> {
+    match codec {
+        0 => Ok(payload.to_vec()),
+        1 => lz4_flex::block::decompress(payload, logical_len)
+            .map_err(|e| CodecError::Corrupted(e.to_string())),
+        2 => zstd::bulk::decompress(payload, logical_len)
+            .map_err(|e| CodecError::Corrupted(e.to_string())),
+        codec => Err(CodecError::Corrupted(format!("unknown codec {}", codec))),
+    }
+}
+
+/// Write statistics of a table
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ValueStats {
+    /// Number of values written
+    pub values_written: u64,
+
+    /// Bytes of the written values before encoding
+    pub logical_bytes_written: u64,
+
+    /// Bytes of the written values after encoding
+    pub stored_bytes_written: u64,
+
+    /// Number of values written in chunks
+    pub chunked_values_written: u64,
+}
+
+impl ValueStats {
+    /// Account for a written value
+    pub fn record(&mut self, value: &EncodedValue) {
+        self.values_written += 1;
+        self.logical_bytes_written += value.logical_len as u64;
+        self.stored_bytes_written += value.stored_len() as u64;
+        if !value.chunks.is_empty() {
+            self.chunked_values_written += 1;
+        }
+    }
+
+    /// Stored bytes per logical byte written, `None` before the first write
+    pub fn compression_ratio(&self) -> Option<f64> {
+        if self.logical_bytes_written == 0 {
+            return None;
+        }
+        Some(self.stored_bytes_written as f64 / self.logical_bytes_written as f64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn roundtrip(options: &TableOptions, value: &[u8]) -> EncodedValue {
+        let encoded = encode_value(options, value).unwrap();
+        let chunks: HashMap<u32, Vec<u8>> = encoded
+            .chunks
+            .iter()
+            .cloned()
+            .enumerate()
+            .map(|(i, chunk)| (i as u32, chunk))
+            .collect();
+
+        let decoded = decode_value(&encoded.head, |i| Ok(chunks.get(&i).cloned())).unwrap();
+        assert_eq!(decoded, value);
+        encoded
+    }
+
+    #[test]
+    fn test_compressed_values() {
+        let value = "function bundle ".repeat(1024).into_bytes();
+
+        for compression in [ValueCompression::Lz4, ValueCompression::Zstd { level: 3 }] {
+            let encoded = roundtrip(&TableOptions::compressed(compression), &value);
+            assert!(encoded.chunks.is_empty());
+            assert!(encoded.stored_len() < value.len() / 4);
+        }
+
+        // Small values are kept as is
+        let encoded = roundtrip(&TableOptions::compressed(ValueCompression::Lz4), b"small");
+        assert_eq!(encoded.head.len(), HEADER_LEN + 5);
+    }
+
+    #[test]
+    fn test_chunked_values() {
+        let value: Vec<u8> = (0..MAX_VALUE_SIZE + 1024)
+            .map(|i| (i * 7919 % 251) as u8)
+            .collect();
+
+        let encoded = roundtrip(&TableOptions::default(), &value);
+        assert_eq!(encoded.chunks.len(), 2);
+        assert_eq!(encoded.head.len(), HEADER_LEN);
+
+        let options = TableOptions {
+            max_value_size: MAX_VALUE_SIZE,
+            ..Default::default()
+        };
+        assert!(matches!(
+            encode_value(&options, &value),
+            Err(CodecError::TooLarge { .. })
+        ));
+    }
+
+    #[test]
+    fn test_legacy_and_corrupted_values() {
+        // Values written before the table was encoded are read as stored
+        assert_eq!(decode_value(b"legacy", |_| Ok(None)).unwrap(), b"legacy");
+
+        let value = vec![7u8; MAX_VALUE_SIZE * 2];
+        let encoded = encode_value(&TableOptions::default(), &value).unwrap();
+        assert!(matches!(
+            decode_value(&encoded.head, |_| Ok(None)),
+            Err(CodecError::Corrupted(_))
+        ));
+    }
+}
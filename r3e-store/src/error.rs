@@ -23,6 +23,10 @@ pub enum PutError {
     /// Value is too large
     #[error("kv-put: value is too large")]
     TooLargeValue,
+
+    /// The tenant's storage quota would be exceeded
+    #[error("kv-put: {0}")]
+    QuotaExceeded(String),
 }
 
 /// Error type for get operations
@@ -108,3 +112,51 @@ pub enum MultiDeleteError {
     #[error("kv-multi-delete: invalid table name")]
     InvalidTable,
 }
+
+/// Error type for increment operations
+#[derive(Debug, Error)]
+pub enum IncrementError {
+    /// Invalid table name
+    #[error("kv-increment: invalid table name")]
+    InvalidTable,
+
+    /// Key is too large
+    #[error("kv-increment: key is too large")]
+    TooLargeKey,
+
+    /// The value stored under the key is not a counter
+    #[error("kv-increment: value is not a counter")]
+    NotACounter,
+
+    /// The tenant's storage quota would be exceeded
+    #[error("kv-increment: {0}")]
+    QuotaExceeded(String),
+
+    /// The backing store failed
+    #[error("kv-increment: {0}")]
+    Storage(String),
+}
+
+/// Error type for compare-and-set operations
+#[derive(Debug, Error)]
+pub enum CasError {
+    /// Invalid table name
+    #[error("kv-cas: invalid table name")]
+    InvalidTable,
+
+    /// Key is too large
+    #[error("kv-cas: key is too large")]
+    TooLargeKey,
+
+    /// Value is too large
+    #[error("kv-cas: value is too large")]
+    TooLargeValue,
+
+    /// The tenant's storage quota would be exceeded
+    #[error("kv-cas: {0}")]
+    QuotaExceeded(String),
+
+    /// The backing store failed
+    #[error("kv-cas: {0}")]
+    Storage(String),
+}
@@ -0,0 +1,50 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+//! In-memory event index, for tests and single-node development
+
+use std::collections::BTreeMap;
+use std::sync::RwLock;
+
+use async_trait::async_trait;
+
+use super::{EventError, EventPage, EventQuery, EventStore, IndexedEvent};
+
+/// In-memory event index
+#[derive(Debug, Default)]
+pub struct MemoryEventStore {
+    /// Events by key
+    events: RwLock<BTreeMap<String, IndexedEvent>>,
+}
+
+impl MemoryEventStore {
+    /// Create a new memory event index
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl EventStore for MemoryEventStore {
+    async fn append(&self, events: Vec<IndexedEvent>) -> Result<(), EventError> {
+        let mut indexed = self.events.write().unwrap();
+        for event in events {
+            indexed.insert(event.key(), event);
+        }
+        Ok(())
+    }
+
+    async fn query(&self, query: &EventQuery) -> Result<EventPage, EventError> {
+        let (start, end) = query.bounds()?;
+        let limit = query.limit();
+        let events = self
+            .events
+            .read()
+            .unwrap()
+            .range(start..end)
+            .take(limit + 1)
+            .map(|(_, event)| event.clone())
+            .collect();
+        Ok(EventPage::from_read(events, limit))
+    }
+}
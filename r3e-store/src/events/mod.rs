@@ -0,0 +1,230 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+//! Index of the chain events delivered to triggers.
+//!
+//! Events are kept per contract in time order, so the events of a contract over a time range
+//! can be read back, e.g. to replay them into a fixed version of a function. Indexing the same
+//! event again, after a reconnect or backfill, overwrites it.
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+pub mod memory;
+pub mod rocksdb;
+
+pub use memory::MemoryEventStore;
+pub use rocksdb::RocksDbEventStore;
+
+/// Most events returned by a query
+pub const MAX_EVENT_PAGE: usize = 1000;
+
+/// Error type for event index operations
+#[derive(Debug, Error)]
+pub enum EventError {
+    /// Invalid query or event
+    #[error("events: invalid input: {0}")]
+    Invalid(String),
+
+    /// Backend failure
+    #[error("events: storage error: {0}")]
+    Storage(String),
+}
+
+/// Chain event as delivered to triggers
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IndexedEvent {
+    /// Event source, e.g. the network
+    pub source: String,
+
+    /// Hash of the contract emitting the event
+    pub contract: String,
+
+    /// Event name
+    pub event_name: Option<String>,
+
+    /// Hash of the block containing the event
+    pub block_hash: String,
+
+    /// Hash of the transaction emitting the event
+    pub tx_hash: String,
+
+    /// Index of the event within the transaction
+    pub event_index: u64,
+
+    /// Block time (millis since epoch)
+    pub timestamp_ms: u64,
+
+    /// Event data, as given to trigger callbacks
+    pub data: serde_json::Value,
+}
+
+impl IndexedEvent {
+    /// Key of the event, sorting the events of a contract by time
+    pub fn key(&self) -> String {
+        format!(
+            "{}{}:{}:{}:{}",
+            time_key(&self.contract, self.timestamp_ms),
+            self.source,
+            self.block_hash,
+            self.tx_hash,
+            self.event_index
+        )
+    }
+}
+
+/// Contract hashes are indexed lowercase, without a `0x` prefix
+pub fn normalize_contract(contract: &str) -> String {
+    let contract = contract.trim();
+    contract
+        .strip_prefix("0x")
+        .or_else(|| contract.strip_prefix("0X"))
+        .unwrap_or(contract)
+        .to_ascii_lowercase()
+}
+
+fn time_key(contract: &str, timestamp_ms: u64) -> String {
+    format!("{}/{:020}/", normalize_contract(contract), timestamp_ms)
+}
+
+/// Events of a contract over a time range, oldest first
+#[derive(Debug, Clone, Default)]
+pub struct EventQuery {
+    /// Contract hash
+    pub contract: String,
+
+    /// Range start (millis since epoch, inclusive)
+    pub from_ms: u64,
+
+    /// Range end (millis since epoch, exclusive)
+    pub to_ms: u64,
+
+    /// Resume after the event with this key, the `next` of the previous page
+    pub after: Option<String>,
+
+    /// Maximum number of events returned
+    pub limit: usize,
+}
+
+impl EventQuery {
+    /// Keys in `[start, end)` the query reads
+    fn bounds(&self) -> Result<(String, String), EventError> {
+        if self.contract.trim().is_empty() {
+            return Err(EventError::Invalid("contract is required".to_string()));
+        }
+        if self.from_ms >= self.to_ms {
+            return Err(EventError::Invalid(
+                "range start must be before its end".to_string(),
+            ));
+        }
+
+        let start = time_key(&self.contract, self.from_ms);
+        let end = time_key(&self.contract, self.to_ms);
+        match &self.after {
+            Some(after) if after.as_str() < start.as_str() || after.as_str() >= end.as_str() => {
+                Err(EventError::Invalid(
+                    "cursor is outside of the range".to_string(),
+                ))
+            }
+            // Right after the cursor, a NUL sorting before every other suffix
+            Some(after) => Ok((format!("{}\0", after), end)),
+            None => Ok((start, end)),
+        }
+    }
+
+    fn limit(&self) -> usize {
+        self.limit.clamp(1, MAX_EVENT_PAGE)
+    }
+}
+
+/// Page of events
+#[derive(Debug, Clone, Default)]
+pub struct EventPage {
+    /// Events, oldest first
+    pub events: Vec<IndexedEvent>,
+
+    /// Cursor of the next page, None if this is the last one
+    pub next: Option<String>,
+}
+
+impl EventPage {
+    /// Page of the events read, one more than the limit telling whether more follow
+    fn from_read(mut events: Vec<IndexedEvent>, limit: usize) -> Self {
+        let next = if events.len() > limit {
+            events.truncate(limit);
+            events.last().map(IndexedEvent::key)
+        } else {
+            None
+        };
+        Self { events, next }
+    }
+}
+
+/// Persisted index of chain events
+#[async_trait]
+pub trait EventStore: Send + Sync {
+    /// Index events, replacing the ones indexed before
+    async fn append(&self, events: Vec<IndexedEvent>) -> Result<(), EventError>;
+
+    /// Events of a contract over a time range
+    async fn query(&self, query: &EventQuery) -> Result<EventPage, EventError>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(contract: &str, timestamp_ms: u64, tx_hash: &str) -> IndexedEvent {
+        IndexedEvent {
+            source: "neo".to_string(),
+            contract: normalize_contract(contract),
+            event_name: Some("Transfer".to_string()),
+            block_hash: "0xblock".to_string(),
+            tx_hash: tx_hash.to_string(),
+            event_index: 0,
+            timestamp_ms,
+            data: serde_json::json!({ "tx_hash": tx_hash }),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_event_query() {
+        let store = MemoryEventStore::new();
+        store
+            .append(vec![
+                event("0xABC", 1_000, "0x1"),
+                event("0xabc", 2_000, "0x2"),
+                event("abc", 2_000, "0x3"),
+                event("0xabc", 3_000, "0x4"),
+                event("0xdef", 2_000, "0x5"),
+                // Indexed again after a reconnect
+                event("0xabc", 2_000, "0x2"),
+            ])
+            .await
+            .unwrap();
+
+        let mut query = EventQuery {
+            contract: "0xAbC".to_string(),
+            from_ms: 1_500,
+            to_ms: 3_500,
+            after: None,
+            limit: 2,
+        };
+        let page = store.query(&query).await.unwrap();
+        let hashes: Vec<_> = page.events.iter().map(|e| e.tx_hash.as_str()).collect();
+        assert_eq!(hashes, ["0x2", "0x3"]);
+
+        query.after = page.next;
+        let page = store.query(&query).await.unwrap();
+        assert_eq!(page.events.len(), 1);
+        assert_eq!(page.events[0].tx_hash, "0x4");
+        assert!(page.next.is_none());
+
+        query.after = Some("zzz".to_string());
+        assert!(matches!(
+            store.query(&query).await,
+            Err(EventError::Invalid(_))
+        ));
+    }
+}
@@ -0,0 +1,75 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+//! RocksDB event index.
+//!
+//! Events are keyed by `{contract}/{timestamp_ms}/{event id}`, zero-padded so that the events of
+//! a contract are sorted by time and a time range is a single scan. Event data being arbitrary
+//! JSON, events are stored as JSON bytes.
+
+use std::path::Path;
+
+use async_trait::async_trait;
+
+use super::{EventError, EventPage, EventQuery, EventStore, IndexedEvent};
+use crate::rocksdb::{BatchOperation, RocksDbClient, RocksDbConfig};
+
+/// Column family of the events
+const CF_EVENTS: &str = "indexed_events";
+
+fn storage_error(e: impl std::fmt::Display) -> EventError {
+    EventError::Storage(e.to_string())
+}
+
+/// RocksDB event index
+pub struct RocksDbEventStore {
+    db: RocksDbClient,
+}
+
+impl RocksDbEventStore {
+    /// Open the event index at a path
+    pub fn new<P: AsRef<Path>>(db_path: P) -> Result<Self, EventError> {
+        let db = RocksDbClient::new(RocksDbConfig {
+            path: db_path.as_ref().to_string_lossy().to_string(),
+            ..Default::default()
+        });
+        db.open().map_err(storage_error)?;
+        db.create_cf_if_missing(CF_EVENTS).map_err(storage_error)?;
+        Ok(Self { db })
+    }
+}
+
+#[async_trait]
+impl EventStore for RocksDbEventStore {
+    async fn append(&self, events: Vec<IndexedEvent>) -> Result<(), EventError> {
+        let mut operations = Vec::with_capacity(events.len());
+        for event in events {
+            // Stored with the put_cf encoding of a byte vector, like the values read back below
+            let json = serde_json::to_vec(&event).map_err(storage_error)?;
+            operations.push(BatchOperation::Put {
+                cf_name: CF_EVENTS.to_string(),
+                key: event.key().into_bytes(),
+                value: bincode::serialize(&json).map_err(storage_error)?,
+            });
+        }
+        self.db.write_batch(operations).map_err(storage_error)
+    }
+
+    async fn query(&self, query: &EventQuery) -> Result<EventPage, EventError> {
+        let (start, end) = query.bounds()?;
+        let limit = query.limit();
+
+        let page = self
+            .db
+            .scan_cf::<Vec<u8>>(CF_EVENTS, start.as_bytes(), limit + 1)
+            .map_err(storage_error)?;
+        let mut events = Vec::with_capacity(page.len());
+        for (key, json) in page {
+            if *key >= *end.as_bytes() {
+                break;
+            }
+            events.push(serde_json::from_slice(&json).map_err(storage_error)?);
+        }
+        Ok(EventPage::from_read(events, limit))
+    }
+}
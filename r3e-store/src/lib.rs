@@ -5,9 +5,17 @@
 //!
 //! Storage abstractions for the R3E FaaS platform.
 
+pub mod analytics;
+pub mod backup;
+pub mod blob;
+pub mod codec;
 pub mod config;
 pub mod error;
+pub mod events;
+pub mod lock;
+pub mod logs;
 pub mod repository;
+pub mod schema;
 pub mod storage;
 pub mod types;
 
@@ -19,17 +27,51 @@ pub mod rocksdb;
 pub mod mem_test;
 
 // Re-export important types
+pub use analytics::{
+    spawn_usage_rollup, AnalyticsError, AnalyticsStore, Granularity, LatencyHistogram,
+    MemoryAnalyticsStore, RocksDbAnalyticsStore, UsageAggregate, UsageRollup, UsageSample,
+    UsageSummary,
+};
+pub use backup::{
+    spawn_scheduled_backups, BackupConfig, BackupEngine, BackupEntry, BackupError,
+    BackupKeyProvider, BackupManifest, StaticBackupKey,
+};
+pub use blob::{
+    BlobError, BlobRef, BlobStore, BlobStoreConfig, MemoryBlobStore, NeoFsBlobStore, NeoFsConfig,
+    S3BlobStore, S3Config,
+};
+pub use codec::{TableOptions, ValueCompression, ValueStats};
 pub use error::{
-    DeleteError, GetError, MultiDeleteError, MultiGetError, MultiPutError, PutError, ScanError,
+    CasError, DeleteError, GetError, IncrementError, MultiDeleteError, MultiGetError,
+    MultiPutError, PutError, ScanError,
+};
+pub use events::{
+    normalize_contract, EventError, EventPage, EventQuery, EventStore, IndexedEvent,
+    MemoryEventStore, RocksDbEventStore, MAX_EVENT_PAGE,
+};
+#[cfg(feature = "postgres")]
+pub use lock::PostgresLockService;
+#[cfg(feature = "redis")]
+pub use lock::RedisLockService;
+pub use lock::{check_fence, KvLockService, Lease, LeaseHolder, LockError, LockService};
+pub use logs::{
+    prune_logs, spawn_log_pruning, LogError, LogLevel, LogPage, LogQuery, LogRecord, LogRetention,
+    LogStore, MemoryLogStore, PruneStats, RocksDbLogStore,
+};
+pub use schema::{
+    decode_record, encode_record, migrate_cf, Decoded, Migration, MigrationStats, SchemaError,
+    Versioned,
 };
-pub use storage::{BatchKvStore, KvStore, SortedKvStore};
+pub use storage::{AtomicKvStore, BatchKvStore, KvStore, ScanIter, SortedKvStore};
 pub use storage::memory::MemoryStore;
+pub use storage::quota::QuotaKvStore;
 
 // Add a type alias for RocksDbClient to support backward compatibility
 pub type RocksDBStore = rocksdb::RocksDbClient;
 
 pub use types::{
-    PutInput, ScanInput, ScanOutput, MAX_KEY_SIZE, MAX_TABLE_NAME_SIZE, MAX_VALUE_SIZE,
+    decode_counter, encode_counter, prefix_end, CasOutcome, PutInput, ScanInput, ScanOutput,
+    COUNTER_SIZE, MAX_KEY_SIZE, MAX_TABLE_NAME_SIZE, MAX_VALUE_SIZE,
 };
 
 // Re-export repository types
@@ -0,0 +1,149 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+//! Locks kept in a key-value store with atomic operations.
+//!
+//! The lease of a lock is stored under its name and swapped by compare-and-set, and fencing
+//! tokens are drawn from a counter per lock, so they keep increasing across releases.
+
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+
+use super::{now_ms, validate, Lease, LockError, LockService};
+use crate::rocksdb::{RocksDbClient, RocksDbConfig};
+use crate::storage::AtomicKvStore;
+use crate::types::CasOutcome;
+
+/// Table of the leases
+pub const LOCK_TABLE: &str = "locks";
+
+/// Table of the fencing token counters
+pub const TOKEN_TABLE: &str = "lock_tokens";
+
+/// Swaps attempted before giving up on a contended lock
+const MAX_ATTEMPTS: usize = 16;
+
+fn storage_error(e: impl std::fmt::Display) -> LockError {
+    LockError::Storage(e.to_string())
+}
+
+fn encode(lease: &Lease) -> Result<Vec<u8>, LockError> {
+    serde_json::to_vec(lease).map_err(storage_error)
+}
+
+/// Locks kept in a key-value store, RocksDB for a single node
+pub struct KvLockService {
+    store: Arc<dyn AtomicKvStore + Send + Sync>,
+}
+
+impl KvLockService {
+    pub fn new(store: Arc<dyn AtomicKvStore + Send + Sync>) -> Self {
+        Self { store }
+    }
+
+    /// Open a RocksDB lock store at a path
+    pub fn open<P: AsRef<Path>>(db_path: P) -> Result<Self, LockError> {
+        let db = RocksDbClient::new(RocksDbConfig {
+            path: db_path.as_ref().to_string_lossy().to_string(),
+            ..Default::default()
+        });
+        db.open().map_err(storage_error)?;
+        Ok(Self::new(Arc::new(db)))
+    }
+
+    fn next_token(&self, name: &str) -> Result<u64, LockError> {
+        self.store
+            .increment(TOKEN_TABLE, name.as_bytes(), 1)
+            .map(|token| token as u64)
+            .map_err(storage_error)
+    }
+}
+
+#[async_trait]
+impl LockService for KvLockService {
+    async fn try_acquire(
+        &self,
+        name: &str,
+        owner: &str,
+        ttl: Duration,
+    ) -> Result<Option<Lease>, LockError> {
+        validate(name, ttl)?;
+
+        let mut expected: Option<Vec<u8>> = None;
+        for _ in 0..MAX_ATTEMPTS {
+            let now = now_ms();
+            let current = expected
+                .as_deref()
+                .and_then(|bytes| serde_json::from_slice::<Lease>(bytes).ok())
+                .filter(|lease| !lease.is_expired(now));
+
+            let token = match current {
+                Some(lease) if lease.owner != owner => return Ok(None),
+                Some(lease) => lease.token,
+                None => self.next_token(name)?,
+            };
+            let lease = Lease {
+                name: name.to_string(),
+                owner: owner.to_string(),
+                token,
+                expires_at_ms: now + ttl.as_millis() as u64,
+            };
+
+            let outcome = self
+                .store
+                .compare_and_set(
+                    LOCK_TABLE,
+                    name.as_bytes(),
+                    expected.as_deref(),
+                    Some(&encode(&lease)?),
+                )
+                .map_err(storage_error)?;
+            match outcome {
+                CasOutcome::Set => return Ok(Some(lease)),
+                CasOutcome::Conflict(found) => expected = found,
+            }
+        }
+
+        // Too many nodes are racing for the lock, one of them got it
+        Ok(None)
+    }
+
+    async fn renew(&self, lease: &Lease, ttl: Duration) -> Result<Lease, LockError> {
+        validate(&lease.name, ttl)?;
+
+        // A lease that expired is renewed as long as nobody acquired the lock since, no
+        // greater token having been handed out
+        let renewed = Lease {
+            expires_at_ms: now_ms() + ttl.as_millis() as u64,
+            ..lease.clone()
+        };
+        let outcome = self
+            .store
+            .compare_and_set(
+                LOCK_TABLE,
+                lease.name.as_bytes(),
+                Some(&encode(lease)?),
+                Some(&encode(&renewed)?),
+            )
+            .map_err(storage_error)?;
+        match outcome {
+            CasOutcome::Set => Ok(renewed),
+            CasOutcome::Conflict(_) => Err(LockError::Lost(lease.name.clone())),
+        }
+    }
+
+    async fn release(&self, lease: &Lease) -> Result<(), LockError> {
+        self.store
+            .compare_and_set(
+                LOCK_TABLE,
+                lease.name.as_bytes(),
+                Some(&encode(lease)?),
+                None,
+            )
+            .map_err(storage_error)?;
+        Ok(())
+    }
+}
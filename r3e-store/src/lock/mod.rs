@@ -0,0 +1,253 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+//! Lease-based distributed locks.
+//!
+//! A lock is held through a [`Lease`] that expires unless its holder renews it, so a crashed
+//! node cannot hold a lock forever. Every acquisition is given a fencing token greater than the
+//! tokens of the previous holders: a holder that was paused past its lease writes with a token
+//! lower than the one of the new holder, and [`check_fence`] rejects it.
+//!
+//! [`KvLockService`] keeps the locks in a store with atomic operations, RocksDB on a single
+//! node, while [`PostgresLockService`] and [`RedisLockService`] share them between the nodes of
+//! a cluster. Schedulers run their periodic work only while their [`LeaseHolder`] holds the
+//! lock, so the work runs on a single node at a time.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::storage::AtomicKvStore;
+use crate::types::CasOutcome;
+
+pub mod kv;
+#[cfg(feature = "postgres")]
+pub mod postgres;
+#[cfg(feature = "redis")]
+pub mod redis;
+
+#[cfg(feature = "redis")]
+pub use self::redis::RedisLockService;
+pub use kv::KvLockService;
+#[cfg(feature = "postgres")]
+pub use postgres::PostgresLockService;
+
+/// Table of the highest fencing token accepted per resource
+pub const FENCE_TABLE: &str = "lock_fences";
+
+/// Error type for lock operations
+#[derive(Debug, Error)]
+pub enum LockError {
+    /// Invalid lock name or lease duration
+    #[error("lock: invalid input: {0}")]
+    Invalid(String),
+
+    /// The lease expired and the lock was acquired by another holder, or released
+    #[error("lock: lease on {0} was lost")]
+    Lost(String),
+
+    /// A write carried a fencing token lower than one already accepted
+    #[error("lock: fencing token {token} of {resource} is stale, {current} was accepted")]
+    Fenced {
+        resource: String,
+        token: u64,
+        current: u64,
+    },
+
+    /// Backend failure
+    #[error("lock: storage error: {0}")]
+    Storage(String),
+}
+
+/// Lease on a lock
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Lease {
+    /// Lock name
+    pub name: String,
+
+    /// Holder of the lease
+    pub owner: String,
+
+    /// Fencing token, greater than the tokens of the previous holders of the lock
+    pub token: u64,
+
+    /// Time the lease expires at unless renewed (in milliseconds since the epoch)
+    pub expires_at_ms: u64,
+}
+
+impl Lease {
+    /// Whether the lease has expired at `now_ms`
+    pub fn is_expired(&self, now_ms: u64) -> bool {
+        now_ms >= self.expires_at_ms
+    }
+}
+
+/// Distributed lock backend
+#[async_trait]
+pub trait LockService: Send + Sync {
+    /// Acquire a lock for `ttl`, None if another owner holds it. An owner acquiring a lock it
+    /// already holds extends its lease and keeps its fencing token.
+    async fn try_acquire(
+        &self,
+        name: &str,
+        owner: &str,
+        ttl: Duration,
+    ) -> Result<Option<Lease>, LockError>;
+
+    /// Extend a lease by `ttl` from now, failing with [`LockError::Lost`] if it was lost
+    async fn renew(&self, lease: &Lease, ttl: Duration) -> Result<Lease, LockError>;
+
+    /// Release a lease, doing nothing if it was already lost
+    async fn release(&self, lease: &Lease) -> Result<(), LockError>;
+}
+
+pub(crate) fn now_ms() -> u64 {
+    chrono::Utc::now().timestamp_millis() as u64
+}
+
+pub(crate) fn validate(name: &str, ttl: Duration) -> Result<(), LockError> {
+    if name.is_empty() || name.len() > crate::MAX_KEY_SIZE {
+        return Err(LockError::Invalid(format!("invalid lock name: {:?}", name)));
+    }
+    if ttl.is_zero() {
+        return Err(LockError::Invalid(
+            "lease duration must be positive".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Accept a write to `resource` carrying a fencing token only if no greater token was
+/// accepted before, recording it as the highest one
+pub fn check_fence(store: &dyn AtomicKvStore, resource: &str, token: u64) -> Result<(), LockError> {
+    let mut expected: Option<Vec<u8>> = None;
+    loop {
+        let current = expected
+            .as_deref()
+            .and_then(|bytes| Some(u64::from_le_bytes(bytes.try_into().ok()?)));
+        if let Some(current) = current.filter(|current| *current > token) {
+            return Err(LockError::Fenced {
+                resource: resource.to_string(),
+                token,
+                current,
+            });
+        }
+        if current == Some(token) {
+            return Ok(());
+        }
+
+        let outcome = store
+            .compare_and_set(
+                FENCE_TABLE,
+                resource.as_bytes(),
+                expected.as_deref(),
+                Some(&token.to_le_bytes()),
+            )
+            .map_err(|e| LockError::Storage(e.to_string()))?;
+        match outcome {
+            CasOutcome::Set => return Ok(()),
+            CasOutcome::Conflict(found) => expected = found,
+        }
+    }
+}
+
+/// Lock a scheduler holds while it runs, renewed on every tick
+pub struct LeaseHolder {
+    locks: Arc<dyn LockService>,
+    name: String,
+    owner: String,
+    ttl: Duration,
+    lease: tokio::sync::Mutex<Option<Lease>>,
+}
+
+impl LeaseHolder {
+    /// Hold the lock `name` with leases of `ttl`, which should outlast the interval between
+    /// two ticks of the scheduler
+    pub fn new(locks: Arc<dyn LockService>, name: impl Into<String>, ttl: Duration) -> Self {
+        Self {
+            locks,
+            name: name.into(),
+            owner: default_owner(),
+            ttl,
+            lease: tokio::sync::Mutex::new(None),
+        }
+    }
+
+    /// Hold the lock as `owner`, a random ID of this process by default
+    pub fn with_owner(mut self, owner: impl Into<String>) -> Self {
+        self.owner = owner.into();
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn owner(&self) -> &str {
+        &self.owner
+    }
+
+    /// Renew the lease, or acquire the lock if not held, None if another node holds it
+    pub async fn hold(&self) -> Option<Lease> {
+        let mut held = self.lease.lock().await;
+        if let Some(lease) = held.take() {
+            match self.locks.renew(&lease, self.ttl).await {
+                Ok(renewed) => {
+                    *held = Some(renewed.clone());
+                    return Some(renewed);
+                }
+                Err(LockError::Lost(_)) => {
+                    log::warn!("lock: lost {} (token {})", self.name, lease.token)
+                }
+                Err(e) => {
+                    // Keep the lease while it has not expired, the backend may recover
+                    log::error!("lock: failed to renew {}: {}", self.name, e);
+                    if !lease.is_expired(now_ms()) {
+                        *held = Some(lease.clone());
+                        return Some(lease);
+                    }
+                    return None;
+                }
+            }
+        }
+
+        match self
+            .locks
+            .try_acquire(&self.name, &self.owner, self.ttl)
+            .await
+        {
+            Ok(Some(lease)) => {
+                log::info!("lock: acquired {} (token {})", self.name, lease.token);
+                *held = Some(lease.clone());
+                Some(lease)
+            }
+            Ok(None) => None,
+            Err(e) => {
+                log::error!("lock: failed to acquire {}: {}", self.name, e);
+                None
+            }
+        }
+    }
+
+    /// Release the lock if held
+    pub async fn release(&self) {
+        if let Some(lease) = self.lease.lock().await.take() {
+            if let Err(e) = self.locks.release(&lease).await {
+                log::error!("lock: failed to release {}: {}", self.name, e);
+            }
+        }
+    }
+}
+
+fn default_owner() -> String {
+    let host = std::env::var("HOSTNAME").unwrap_or_else(|_| "node".to_string());
+    format!(
+        "{}-{}-{:08x}",
+        host,
+        std::process::id(),
+        rand::random::<u32>()
+    )
+}
@@ -0,0 +1,122 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+//! Locks kept in Postgres, shared by the nodes of a cluster.
+//!
+//! Leases expire by the clock of the database rather than the ones of the nodes, and fencing
+//! tokens are drawn from a sequence.
+
+use std::time::Duration;
+
+use async_trait::async_trait;
+use sqlx::PgPool;
+
+use super::{validate, Lease, LockError, LockService};
+
+const NOW_MS: &str = "(extract(epoch from clock_timestamp()) * 1000)::bigint";
+
+fn storage_error(e: impl std::fmt::Display) -> LockError {
+    LockError::Storage(e.to_string())
+}
+
+/// Locks kept in Postgres
+pub struct PostgresLockService {
+    db: PgPool,
+}
+
+impl PostgresLockService {
+    /// Keep the locks in a database, creating their table if missing
+    pub async fn new(db: PgPool) -> Result<Self, LockError> {
+        sqlx::query("CREATE SEQUENCE IF NOT EXISTS r3e_lock_tokens")
+            .execute(&db)
+            .await
+            .map_err(storage_error)?;
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS r3e_locks (
+                name TEXT PRIMARY KEY,
+                owner TEXT NOT NULL,
+                token BIGINT NOT NULL,
+                expires_at_ms BIGINT NOT NULL
+            )",
+        )
+        .execute(&db)
+        .await
+        .map_err(storage_error)?;
+        Ok(Self { db })
+    }
+}
+
+#[async_trait]
+impl LockService for PostgresLockService {
+    async fn try_acquire(
+        &self,
+        name: &str,
+        owner: &str,
+        ttl: Duration,
+    ) -> Result<Option<Lease>, LockError> {
+        validate(name, ttl)?;
+
+        // Take over an expired lease with a new token, or extend a live lease of the same owner
+        let row: Option<(i64, i64)> = sqlx::query_as(&format!(
+            "INSERT INTO r3e_locks (name, owner, token, expires_at_ms)
+             VALUES ($1, $2, nextval('r3e_lock_tokens'), {now} + $3)
+             ON CONFLICT (name) DO UPDATE SET
+                 owner = EXCLUDED.owner,
+                 token = CASE WHEN r3e_locks.owner = EXCLUDED.owner
+                              AND r3e_locks.expires_at_ms > {now}
+                         THEN r3e_locks.token ELSE EXCLUDED.token END,
+                 expires_at_ms = EXCLUDED.expires_at_ms
+             WHERE r3e_locks.expires_at_ms <= {now} OR r3e_locks.owner = EXCLUDED.owner
+             RETURNING token, expires_at_ms",
+            now = NOW_MS
+        ))
+        .bind(name)
+        .bind(owner)
+        .bind(ttl.as_millis() as i64)
+        .fetch_optional(&self.db)
+        .await
+        .map_err(storage_error)?;
+
+        Ok(row.map(|(token, expires_at_ms)| Lease {
+            name: name.to_string(),
+            owner: owner.to_string(),
+            token: token as u64,
+            expires_at_ms: expires_at_ms as u64,
+        }))
+    }
+
+    async fn renew(&self, lease: &Lease, ttl: Duration) -> Result<Lease, LockError> {
+        validate(&lease.name, ttl)?;
+
+        let row: Option<(i64,)> = sqlx::query_as(&format!(
+            "UPDATE r3e_locks SET expires_at_ms = {now} + $3
+             WHERE name = $1 AND token = $2
+             RETURNING expires_at_ms",
+            now = NOW_MS
+        ))
+        .bind(&lease.name)
+        .bind(lease.token as i64)
+        .bind(ttl.as_millis() as i64)
+        .fetch_optional(&self.db)
+        .await
+        .map_err(storage_error)?;
+
+        match row {
+            Some((expires_at_ms,)) => Ok(Lease {
+                expires_at_ms: expires_at_ms as u64,
+                ..lease.clone()
+            }),
+            None => Err(LockError::Lost(lease.name.clone())),
+        }
+    }
+
+    async fn release(&self, lease: &Lease) -> Result<(), LockError> {
+        sqlx::query("DELETE FROM r3e_locks WHERE name = $1 AND token = $2")
+            .bind(&lease.name)
+            .bind(lease.token as i64)
+            .execute(&self.db)
+            .await
+            .map_err(storage_error)?;
+        Ok(())
+    }
+}
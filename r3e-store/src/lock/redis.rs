@@ -0,0 +1,141 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+//! Locks kept in Redis, shared by the nodes of a cluster.
+//!
+//! The lease of a lock is a key holding `{token}|{owner}` that Redis expires, and fencing
+//! tokens are drawn from a counter per lock. Scripts check the holder and swap the lease
+//! atomically.
+
+use std::time::Duration;
+
+use async_trait::async_trait;
+use redis::aio::ConnectionManager;
+use redis::Script;
+
+use super::{now_ms, validate, Lease, LockError, LockService};
+
+const ACQUIRE: &str = r"
+local current = redis.call('GET', KEYS[1])
+local token
+if current then
+    local sep = string.find(current, '|', 1, true)
+    if string.sub(current, sep + 1) ~= ARGV[1] then
+        return false
+    end
+    token = tonumber(string.sub(current, 1, sep - 1))
+else
+    token = redis.call('INCR', KEYS[2])
+end
+redis.call('SET', KEYS[1], token .. '|' .. ARGV[1], 'PX', ARGV[2])
+return token
+";
+
+const RENEW: &str = r"
+if redis.call('GET', KEYS[1]) == ARGV[1] then
+    return redis.call('PEXPIRE', KEYS[1], ARGV[2])
+end
+return 0
+";
+
+const RELEASE: &str = r"
+if redis.call('GET', KEYS[1]) == ARGV[1] then
+    return redis.call('DEL', KEYS[1])
+end
+return 0
+";
+
+fn storage_error(e: impl std::fmt::Display) -> LockError {
+    LockError::Storage(e.to_string())
+}
+
+fn lease_key(name: &str) -> String {
+    format!("r3e:lock:{}", name)
+}
+
+fn token_key(name: &str) -> String {
+    format!("r3e:lock-token:{}", name)
+}
+
+fn holder(lease: &Lease) -> String {
+    format!("{}|{}", lease.token, lease.owner)
+}
+
+/// Locks kept in Redis
+pub struct RedisLockService {
+    conn: ConnectionManager,
+}
+
+impl RedisLockService {
+    /// Connect to Redis at a URL, e.g. `redis://127.0.0.1:6379`
+    pub async fn connect(url: &str) -> Result<Self, LockError> {
+        let client = redis::Client::open(url).map_err(storage_error)?;
+        let conn = ConnectionManager::new(client)
+            .await
+            .map_err(storage_error)?;
+        Ok(Self { conn })
+    }
+}
+
+#[async_trait]
+impl LockService for RedisLockService {
+    async fn try_acquire(
+        &self,
+        name: &str,
+        owner: &str,
+        ttl: Duration,
+    ) -> Result<Option<Lease>, LockError> {
+        validate(name, ttl)?;
+        if owner.contains('|') {
+            return Err(LockError::Invalid(format!("invalid owner: {:?}", owner)));
+        }
+
+        let expires_at_ms = now_ms() + ttl.as_millis() as u64;
+        let token: Option<u64> = Script::new(ACQUIRE)
+            .key(lease_key(name))
+            .key(token_key(name))
+            .arg(owner)
+            .arg(ttl.as_millis() as u64)
+            .invoke_async(&mut self.conn.clone())
+            .await
+            .map_err(storage_error)?;
+
+        Ok(token.map(|token| Lease {
+            name: name.to_string(),
+            owner: owner.to_string(),
+            token,
+            expires_at_ms,
+        }))
+    }
+
+    async fn renew(&self, lease: &Lease, ttl: Duration) -> Result<Lease, LockError> {
+        validate(&lease.name, ttl)?;
+
+        let expires_at_ms = now_ms() + ttl.as_millis() as u64;
+        let renewed: i64 = Script::new(RENEW)
+            .key(lease_key(&lease.name))
+            .arg(holder(lease))
+            .arg(ttl.as_millis() as u64)
+            .invoke_async(&mut self.conn.clone())
+            .await
+            .map_err(storage_error)?;
+
+        if renewed == 0 {
+            return Err(LockError::Lost(lease.name.clone()));
+        }
+        Ok(Lease {
+            expires_at_ms,
+            ..lease.clone()
+        })
+    }
+
+    async fn release(&self, lease: &Lease) -> Result<(), LockError> {
+        let _: i64 = Script::new(RELEASE)
+            .key(lease_key(&lease.name))
+            .arg(holder(lease))
+            .invoke_async(&mut self.conn.clone())
+            .await
+            .map_err(storage_error)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,131 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+//! In-memory log store, for tests and single-node development
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::RwLock;
+
+use async_trait::async_trait;
+
+use super::{
+    records_to_prune, LogError, LogPage, LogQuery, LogRecord, LogRetention, LogStore, PruneStats,
+};
+
+#[derive(Debug, Default)]
+struct Inner {
+    /// Records per function, by timestamp and arrival order
+    records: HashMap<String, BTreeMap<(u64, u64), LogRecord>>,
+
+    /// Arrival counter, orders records with the same timestamp
+    seq: u64,
+
+    /// Retention policies per function
+    retentions: HashMap<String, LogRetention>,
+}
+
+/// In-memory log store
+#[derive(Debug, Default)]
+pub struct MemoryLogStore {
+    inner: RwLock<Inner>,
+}
+
+impl MemoryLogStore {
+    /// Create a new memory log store
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl LogStore for MemoryLogStore {
+    async fn append(&self, records: Vec<LogRecord>) -> Result<(), LogError> {
+        let mut inner = self.inner.write().unwrap();
+        for record in records {
+            inner.seq += 1;
+            let key = (record.timestamp_ms, inner.seq);
+            inner
+                .records
+                .entry(record.function_id.clone())
+                .or_default()
+                .insert(key, record);
+        }
+        Ok(())
+    }
+
+    async fn search(&self, function_id: &str, query: &LogQuery) -> Result<LogPage, LogError> {
+        let inner = self.inner.read().unwrap();
+        let Some(records) = inner.records.get(function_id) else {
+            return Ok(LogPage::default());
+        };
+
+        let from = query.from_ms.unwrap_or(0);
+        let to = query.to_ms.unwrap_or(u64::MAX);
+        if from >= to {
+            return Ok(LogPage::default());
+        }
+
+        let matching = records
+            .range((from, 0)..(to, 0))
+            .map(|(_, record)| record)
+            .filter(|record| query.matches(record))
+            .cloned()
+            .collect();
+        Ok(query.page(matching))
+    }
+
+    async fn functions(&self) -> Result<Vec<String>, LogError> {
+        Ok(self.inner.read().unwrap().records.keys().cloned().collect())
+    }
+
+    async fn retention(&self, function_id: &str) -> Result<Option<LogRetention>, LogError> {
+        Ok(self
+            .inner
+            .read()
+            .unwrap()
+            .retentions
+            .get(function_id)
+            .copied())
+    }
+
+    async fn set_retention(
+        &self,
+        function_id: &str,
+        retention: LogRetention,
+    ) -> Result<(), LogError> {
+        self.inner
+            .write()
+            .unwrap()
+            .retentions
+            .insert(function_id.to_string(), retention);
+        Ok(())
+    }
+
+    async fn prune(
+        &self,
+        function_id: &str,
+        retention: &LogRetention,
+        now_ms: u64,
+    ) -> Result<PruneStats, LogError> {
+        let mut inner = self.inner.write().unwrap();
+        let Some(records) = inner.records.get_mut(function_id) else {
+            return Ok(PruneStats::default());
+        };
+
+        let sizes: Vec<(u64, u64)> = records
+            .values()
+            .map(|record| (record.timestamp_ms, record.size()))
+            .collect();
+        let mut stats = PruneStats::default();
+        for _ in 0..records_to_prune(&sizes, retention, now_ms) {
+            if let Some((_, record)) = records.pop_first() {
+                stats.records += 1;
+                stats.bytes += record.size();
+            }
+        }
+        if records.is_empty() {
+            inner.records.remove(function_id);
+        }
+        Ok(stats)
+    }
+}
@@ -0,0 +1,450 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+//! Invocation logs of functions.
+//!
+//! Logs are kept per function in time order. A [`LogRetention`] policy bounds how old and
+//! how large the logs of a function may grow, and [`spawn_log_pruning`] enforces it in the
+//! background.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+pub mod memory;
+pub mod rocksdb;
+
+pub use memory::MemoryLogStore;
+pub use rocksdb::RocksDbLogStore;
+
+/// Fixed per-record overhead counted towards the size of the logs
+const RECORD_OVERHEAD: u64 = 64;
+
+/// Error type for log operations
+#[derive(Debug, Error)]
+pub enum LogError {
+    /// Invalid query or policy
+    #[error("logs: invalid input: {0}")]
+    Invalid(String),
+
+    /// Backend failure
+    #[error("logs: storage error: {0}")]
+    Storage(String),
+}
+
+/// Log level
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogLevel {
+    /// Debug
+    Debug,
+
+    /// Info
+    Info,
+
+    /// Warning
+    Warn,
+
+    /// Error
+    Error,
+}
+
+impl LogLevel {
+    /// Name of the level
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LogLevel::Debug => "debug",
+            LogLevel::Info => "info",
+            LogLevel::Warn => "warn",
+            LogLevel::Error => "error",
+        }
+    }
+}
+
+impl std::fmt::Display for LogLevel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl std::str::FromStr for LogLevel {
+    type Err = LogError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "debug" | "trace" => Ok(LogLevel::Debug),
+            "info" | "log" => Ok(LogLevel::Info),
+            "warn" | "warning" => Ok(LogLevel::Warn),
+            "error" => Ok(LogLevel::Error),
+            _ => Err(LogError::Invalid(format!("unknown log level: {}", s))),
+        }
+    }
+}
+
+/// One log line of an invocation
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LogRecord {
+    /// Function ID
+    pub function_id: String,
+
+    /// Invocation ID
+    pub invocation_id: Option<String>,
+
+    /// Log level
+    pub level: LogLevel,
+
+    /// Log message
+    pub message: String,
+
+    /// Timestamp (millis since epoch)
+    pub timestamp_ms: u64,
+}
+
+impl LogRecord {
+    /// Bytes the record counts towards the size of the function's logs
+    pub fn size(&self) -> u64 {
+        self.message.len() as u64
+            + self.invocation_id.as_ref().map_or(0, |id| id.len() as u64)
+            + RECORD_OVERHEAD
+    }
+}
+
+/// How much of a function's logs is kept
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LogRetention {
+    /// Logs older than this many seconds are pruned
+    pub max_age_secs: Option<u64>,
+
+    /// The oldest logs are pruned once the logs of the function exceed this many bytes
+    pub max_bytes: Option<u64>,
+}
+
+/// Log search query; records are returned newest first
+#[derive(Debug, Clone, Default)]
+pub struct LogQuery {
+    /// Range start (millis since epoch, inclusive)
+    pub from_ms: Option<u64>,
+
+    /// Range end (millis since epoch, exclusive)
+    pub to_ms: Option<u64>,
+
+    /// Lowest level returned
+    pub min_level: Option<LogLevel>,
+
+    /// Substring the message must contain
+    pub contains: Option<String>,
+
+    /// Pattern the message must match
+    pub regex: Option<Regex>,
+
+    /// Number of matching records skipped
+    pub offset: usize,
+
+    /// Maximum number of records returned
+    pub limit: usize,
+}
+
+impl LogQuery {
+    /// Whether a record passes the level and message filters
+    pub fn matches(&self, record: &LogRecord) -> bool {
+        self.min_level.is_none_or(|level| record.level >= level)
+            && self
+                .contains
+                .as_ref()
+                .is_none_or(|contains| record.message.contains(contains.as_str()))
+            && self
+                .regex
+                .as_ref()
+                .is_none_or(|regex| regex.is_match(&record.message))
+    }
+
+    /// Page of the matching records, given oldest first
+    fn page(&self, mut matching: Vec<LogRecord>) -> LogPage {
+        let total = matching.len();
+        matching.reverse();
+        let records: Vec<LogRecord> = matching
+            .into_iter()
+            .skip(self.offset)
+            .take(self.limit)
+            .collect();
+        LogPage {
+            has_more: self.offset + records.len() < total,
+            records,
+            total,
+        }
+    }
+}
+
+/// Page of search results
+#[derive(Debug, Clone, Default)]
+pub struct LogPage {
+    /// Matching records, newest first
+    pub records: Vec<LogRecord>,
+
+    /// Number of matching records
+    pub total: usize,
+
+    /// Whether more records match after this page
+    pub has_more: bool,
+}
+
+/// What a pruning pass removed
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PruneStats {
+    /// Records removed
+    pub records: u64,
+
+    /// Bytes removed
+    pub bytes: u64,
+}
+
+impl PruneStats {
+    fn add(&mut self, other: PruneStats) {
+        self.records += other.records;
+        self.bytes += other.bytes;
+    }
+}
+
+/// Which of a function's records, given oldest first with their size, a policy prunes
+fn records_to_prune(records: &[(u64, u64)], retention: &LogRetention, now_ms: u64) -> usize {
+    let cutoff_ms = retention
+        .max_age_secs
+        .map(|max_age| now_ms.saturating_sub(max_age.saturating_mul(1000)));
+    let mut pruned = records
+        .iter()
+        .take_while(|(timestamp_ms, _)| cutoff_ms.is_some_and(|cutoff| *timestamp_ms < cutoff))
+        .count();
+
+    if let Some(max_bytes) = retention.max_bytes {
+        let mut bytes: u64 = records[pruned..].iter().map(|(_, size)| size).sum();
+        while bytes > max_bytes && pruned < records.len() {
+            bytes -= records[pruned].1;
+            pruned += 1;
+        }
+    }
+    pruned
+}
+
+/// Persisted function logs
+#[async_trait]
+pub trait LogStore: Send + Sync {
+    /// Append records
+    async fn append(&self, records: Vec<LogRecord>) -> Result<(), LogError>;
+
+    /// Search the logs of a function
+    async fn search(&self, function_id: &str, query: &LogQuery) -> Result<LogPage, LogError>;
+
+    /// Functions with logs
+    async fn functions(&self) -> Result<Vec<String>, LogError>;
+
+    /// Retention policy set for a function
+    async fn retention(&self, function_id: &str) -> Result<Option<LogRetention>, LogError>;
+
+    /// Set the retention policy of a function
+    async fn set_retention(
+        &self,
+        function_id: &str,
+        retention: LogRetention,
+    ) -> Result<(), LogError>;
+
+    /// Remove the records of a function the policy does not keep
+    async fn prune(
+        &self,
+        function_id: &str,
+        retention: &LogRetention,
+        now_ms: u64,
+    ) -> Result<PruneStats, LogError>;
+}
+
+/// Prune the logs of every function, with its own policy or the default one
+pub async fn prune_logs(
+    store: &dyn LogStore,
+    default_retention: &LogRetention,
+    now_ms: u64,
+) -> Result<PruneStats, LogError> {
+    let mut stats = PruneStats::default();
+    for function_id in store.functions().await? {
+        let retention = store
+            .retention(&function_id)
+            .await?
+            .unwrap_or(*default_retention);
+        stats.add(store.prune(&function_id, &retention, now_ms).await?);
+    }
+    Ok(stats)
+}
+
+/// Prune the logs periodically
+pub fn spawn_log_pruning(
+    store: Arc<dyn LogStore>,
+    default_retention: LogRetention,
+    interval: Duration,
+) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(interval);
+        loop {
+            interval.tick().await;
+            let now_ms = chrono::Utc::now().timestamp_millis() as u64;
+            match prune_logs(store.as_ref(), &default_retention, now_ms).await {
+                Ok(stats) if stats.records > 0 => log::info!(
+                    "logs: pruned {} records ({} bytes)",
+                    stats.records,
+                    stats.bytes
+                ),
+                Ok(_) => {}
+                Err(e) => log::error!("logs: pruning failed: {}", e),
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(function_id: &str, level: LogLevel, message: &str, timestamp_ms: u64) -> LogRecord {
+        LogRecord {
+            function_id: function_id.to_string(),
+            invocation_id: None,
+            level,
+            message: message.to_string(),
+            timestamp_ms,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_log_search() {
+        let store = MemoryLogStore::new();
+        store
+            .append(vec![
+                record("f1", LogLevel::Info, "fetching price NEO/USD", 1_000),
+                record(
+                    "f1",
+                    LogLevel::Error,
+                    "price feed timeout after 5000ms",
+                    2_000,
+                ),
+                record("f1", LogLevel::Debug, "cache miss NEO/USD", 3_000),
+                record("f1", LogLevel::Warn, "retrying NEO/USD", 3_000),
+                record("f2", LogLevel::Error, "unrelated", 2_500),
+            ])
+            .await
+            .unwrap();
+
+        let page = store
+            .search(
+                "f1",
+                &LogQuery {
+                    limit: 2,
+                    ..LogQuery::default()
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(page.total, 4);
+        assert!(page.has_more);
+        assert_eq!(page.records[0].message, "retrying NEO/USD");
+
+        let page = store
+            .search(
+                "f1",
+                &LogQuery {
+                    min_level: Some(LogLevel::Warn),
+                    contains: Some("NEO".to_string()),
+                    limit: 10,
+                    ..LogQuery::default()
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(page.total, 1);
+        assert_eq!(page.records[0].level, LogLevel::Warn);
+
+        let page = store
+            .search(
+                "f1",
+                &LogQuery {
+                    from_ms: Some(1_500),
+                    to_ms: Some(3_000),
+                    regex: Some(Regex::new(r"timeout after \d+ms").unwrap()),
+                    limit: 10,
+                    ..LogQuery::default()
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(page.total, 1);
+        assert!(!page.has_more);
+    }
+
+    #[tokio::test]
+    async fn test_log_retention() {
+        let store = MemoryLogStore::new();
+        let message = "x".repeat(36);
+        store
+            .append(
+                (0..10)
+                    .map(|i| record("f1", LogLevel::Info, &message, i * 1_000))
+                    .chain((0..10).map(|i| record("f2", LogLevel::Info, &message, i * 1_000)))
+                    .collect(),
+            )
+            .await
+            .unwrap();
+
+        // Records are 100 bytes each
+        store
+            .set_retention(
+                "f1",
+                LogRetention {
+                    max_age_secs: None,
+                    max_bytes: Some(250),
+                },
+            )
+            .await
+            .unwrap();
+        let default_retention = LogRetention {
+            max_age_secs: Some(5),
+            max_bytes: None,
+        };
+
+        let stats = prune_logs(&store, &default_retention, 10_000)
+            .await
+            .unwrap();
+        assert_eq!(stats.records, 8 + 5);
+        assert_eq!(stats.bytes, 1_300);
+
+        let kept = store
+            .search(
+                "f1",
+                &LogQuery {
+                    limit: 100,
+                    ..LogQuery::default()
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            kept.records
+                .iter()
+                .map(|r| r.timestamp_ms)
+                .collect::<Vec<_>>(),
+            vec![9_000, 8_000]
+        );
+
+        let kept = store
+            .search(
+                "f2",
+                &LogQuery {
+                    limit: 100,
+                    ..LogQuery::default()
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(kept.total, 5);
+        assert_eq!(kept.records.last().unwrap().timestamp_ms, 5_000);
+    }
+}
@@ -0,0 +1,212 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+//! RocksDB log store.
+//!
+//! Records are keyed by `{function_id}/{timestamp_ms}/{seq}`, zero-padded so that the logs of
+//! a function are sorted by time and a time range is a single scan.
+
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use async_trait::async_trait;
+
+use super::{
+    records_to_prune, LogError, LogPage, LogQuery, LogRecord, LogRetention, LogStore, PruneStats,
+};
+use crate::rocksdb::{BatchOperation, RocksDbClient, RocksDbConfig};
+
+/// Column family of the records
+const CF_LOGS: &str = "function_logs";
+
+/// Column family of the retention policies
+const CF_RETENTION: &str = "log_retention";
+
+/// Number of records read per scan
+const SCAN_PAGE: usize = 1000;
+
+fn storage_error(e: impl std::fmt::Display) -> LogError {
+    LogError::Storage(e.to_string())
+}
+
+fn record_key(function_id: &str, timestamp_ms: u64, seq: u64) -> String {
+    format!("{}/{:020}/{:020}", function_id, timestamp_ms, seq)
+}
+
+fn time_key(function_id: &str, timestamp_ms: u64) -> String {
+    format!("{}/{:020}/", function_id, timestamp_ms)
+}
+
+/// First key after every key of a function, `0` sorting right after `/`
+fn function_end(function_id: &str) -> String {
+    format!("{}0", function_id)
+}
+
+/// RocksDB log store
+pub struct RocksDbLogStore {
+    db: RocksDbClient,
+
+    /// Orders records with the same timestamp, seeded from the clock so it keeps increasing
+    /// across restarts
+    seq: AtomicU64,
+}
+
+impl RocksDbLogStore {
+    /// Open the log store at a path
+    pub fn new<P: AsRef<Path>>(db_path: P) -> Result<Self, LogError> {
+        let db = RocksDbClient::new(RocksDbConfig {
+            path: db_path.as_ref().to_string_lossy().to_string(),
+            ..Default::default()
+        });
+        db.open().map_err(storage_error)?;
+        db.create_cf_if_missing(CF_LOGS).map_err(storage_error)?;
+        db.create_cf_if_missing(CF_RETENTION)
+            .map_err(storage_error)?;
+
+        let seq = chrono::Utc::now().timestamp_micros() as u64;
+        Ok(Self {
+            db,
+            seq: AtomicU64::new(seq),
+        })
+    }
+
+    /// Visit the records with a key in `[start, end)` in key order
+    fn scan_range(
+        &self,
+        start: &str,
+        end: &str,
+        mut visit: impl FnMut(Box<[u8]>, LogRecord),
+    ) -> Result<(), LogError> {
+        let mut start = start.as_bytes().to_vec();
+        let mut first = true;
+        loop {
+            let page = self
+                .db
+                .scan_cf::<LogRecord>(CF_LOGS, &start, SCAN_PAGE)
+                .map_err(storage_error)?;
+            let full = page.len() == SCAN_PAGE;
+
+            for (key, record) in page {
+                // Pages after the first start at the last key already visited
+                if !first && *key == *start {
+                    continue;
+                }
+                if *key >= *end.as_bytes() {
+                    return Ok(());
+                }
+                start = key.to_vec();
+                visit(key, record);
+            }
+
+            if !full {
+                return Ok(());
+            }
+            first = false;
+        }
+    }
+}
+
+#[async_trait]
+impl LogStore for RocksDbLogStore {
+    async fn append(&self, records: Vec<LogRecord>) -> Result<(), LogError> {
+        let mut operations = Vec::with_capacity(records.len());
+        for record in records {
+            let seq = self.seq.fetch_add(1, Ordering::Relaxed);
+            operations.push(BatchOperation::Put {
+                cf_name: CF_LOGS.to_string(),
+                key: record_key(&record.function_id, record.timestamp_ms, seq).into_bytes(),
+                value: bincode::serialize(&record).map_err(storage_error)?,
+            });
+        }
+        self.db.write_batch(operations).map_err(storage_error)
+    }
+
+    async fn search(&self, function_id: &str, query: &LogQuery) -> Result<LogPage, LogError> {
+        let start = time_key(function_id, query.from_ms.unwrap_or(0));
+        let end = match query.to_ms {
+            Some(to_ms) => time_key(function_id, to_ms),
+            None => function_end(function_id),
+        };
+        if start >= end {
+            return Ok(LogPage::default());
+        }
+
+        let mut matching = Vec::new();
+        self.scan_range(&start, &end, |_, record| {
+            if query.matches(&record) {
+                matching.push(record);
+            }
+        })?;
+        Ok(query.page(matching))
+    }
+
+    async fn functions(&self) -> Result<Vec<String>, LogError> {
+        // Skip from one function to the next instead of reading every record
+        let mut functions = Vec::new();
+        let mut start = Vec::new();
+        loop {
+            let page = self
+                .db
+                .scan_cf::<LogRecord>(CF_LOGS, &start, 1)
+                .map_err(storage_error)?;
+            let Some((_, record)) = page.into_iter().next() else {
+                return Ok(functions);
+            };
+            start = function_end(&record.function_id).into_bytes();
+            functions.push(record.function_id);
+        }
+    }
+
+    async fn retention(&self, function_id: &str) -> Result<Option<LogRetention>, LogError> {
+        self.db
+            .get_cf::<_, LogRetention>(CF_RETENTION, function_id)
+            .map_err(storage_error)
+    }
+
+    async fn set_retention(
+        &self,
+        function_id: &str,
+        retention: LogRetention,
+    ) -> Result<(), LogError> {
+        self.db
+            .put_cf(CF_RETENTION, function_id, &retention)
+            .map_err(storage_error)
+    }
+
+    async fn prune(
+        &self,
+        function_id: &str,
+        retention: &LogRetention,
+        now_ms: u64,
+    ) -> Result<PruneStats, LogError> {
+        let mut keys = Vec::new();
+        let mut sizes = Vec::new();
+        self.scan_range(
+            &format!("{}/", function_id),
+            &function_end(function_id),
+            |key, record| {
+                keys.push(key);
+                sizes.push((record.timestamp_ms, record.size()));
+            },
+        )?;
+
+        let pruned = records_to_prune(&sizes, retention, now_ms);
+        if pruned == 0 {
+            return Ok(PruneStats::default());
+        }
+
+        let operations = keys[..pruned]
+            .iter()
+            .map(|key| BatchOperation::Delete {
+                cf_name: CF_LOGS.to_string(),
+                key: key.to_vec(),
+            })
+            .collect();
+        self.db.write_batch(operations).map_err(storage_error)?;
+
+        Ok(PruneStats {
+            records: pruned as u64,
+            bytes: sizes[..pruned].iter().map(|(_, size)| size).sum(),
+        })
+    }
+}
@@ -96,10 +96,11 @@ impl SortedKvStore for MemKvStore {
             });
         };
 
-        let start_bound = if input.start_key.is_empty() {
-            Bound::Unbounded
-        } else if input.start_exclusive {
+        // An empty start key excluded is the empty key, which a continued scan may stop at
+        let start_bound = if input.start_exclusive {
             Bound::Excluded(input.start_key.to_vec())
+        } else if input.start_key.is_empty() {
+            Bound::Unbounded
         } else {
             Bound::Included(input.start_key.to_vec())
         };
@@ -112,14 +113,42 @@ impl SortedKvStore for MemKvStore {
             Bound::Excluded(input.end_key.to_vec())
         };
 
+        // An end key before the start key is an empty range, which BTreeMap::range panics on
+        if let (
+            Bound::Included(start) | Bound::Excluded(start),
+            Bound::Included(end) | Bound::Excluded(end),
+        ) = (&start_bound, &end_bound)
+        {
+            let empty = match (&start_bound, &end_bound) {
+                (Bound::Included(_), Bound::Included(_)) => start > end,
+                _ => start >= end,
+            };
+            if empty {
+                return Ok(ScanOutput {
+                    kvs: vec![],
+                    has_more: false,
+                });
+            }
+        }
+
+        // Take one more item than asked to know whether there are more
         let max_count = input.max_count();
         let range = table.range((start_bound, end_bound));
-        let values: Vec<(Vec<u8>, Vec<u8>)> = range
-            .take(max_count)
-            .map(|(k, v)| (k.to_vec(), v.to_vec()))
-            .collect();
+        let mut values: Vec<(Vec<u8>, Vec<u8>)> = if input.reverse {
+            range
+                .rev()
+                .take(max_count + 1)
+                .map(|(k, v)| (k.to_vec(), v.to_vec()))
+                .collect()
+        } else {
+            range
+                .take(max_count + 1)
+                .map(|(k, v)| (k.to_vec(), v.to_vec()))
+                .collect()
+        };
 
-        let has_more = values.len() == max_count;
+        let has_more = values.len() > max_count;
+        values.truncate(max_count);
         Ok(ScanOutput {
             kvs: values,
             has_more,
@@ -203,3 +232,59 @@ impl BatchKvStore for MemKvStore {
         Ok(results)
     }
 }
+
+impl AtomicKvStore for MemKvStore {
+    fn increment(&self, table: &str, key: &[u8], delta: i64) -> Result<i64, IncrementError> {
+        if table.len() > MAX_TABLE_NAME_SIZE {
+            return Err(IncrementError::InvalidTable);
+        }
+
+        if key.len() > MAX_KEY_SIZE {
+            return Err(IncrementError::TooLargeKey);
+        }
+
+        let mut tables = self.tables.lock().unwrap();
+        let entry = tables.entry(table.to_string()).or_default();
+        let current = match entry.get(key) {
+            Some(value) => decode_counter(value).ok_or(IncrementError::NotACounter)?,
+            None => 0,
+        };
+
+        let value = current.saturating_add(delta);
+        entry.insert(key.to_vec(), encode_counter(value).to_vec());
+        Ok(value)
+    }
+
+    fn compare_and_set(
+        &self,
+        table: &str,
+        key: &[u8],
+        expected: Option<&[u8]>,
+        new: Option<&[u8]>,
+    ) -> Result<CasOutcome, CasError> {
+        if table.len() > MAX_TABLE_NAME_SIZE {
+            return Err(CasError::InvalidTable);
+        }
+
+        if key.len() > MAX_KEY_SIZE {
+            return Err(CasError::TooLargeKey);
+        }
+
+        if new.is_some_and(|value| value.len() > MAX_VALUE_SIZE) {
+            return Err(CasError::TooLargeValue);
+        }
+
+        let mut tables = self.tables.lock().unwrap();
+        let entry = tables.entry(table.to_string()).or_default();
+        let current = entry.get(key).map(Vec::as_slice);
+        if current != expected {
+            return Ok(CasOutcome::Conflict(current.map(<[u8]>::to_vec)));
+        }
+
+        match new {
+            Some(value) => entry.insert(key.to_vec(), value.to_vec()),
+            None => entry.remove(key),
+        };
+        Ok(CasOutcome::Set)
+    }
+}
@@ -78,6 +78,7 @@ fn test_mem_store() {
             start_exclusive: false,
             end_key: &[],
             end_inclusive: false,
+            reverse: false,
             max_count: 10,
         },
     );
@@ -89,3 +90,233 @@ fn test_mem_store() {
     assert_eq!(scanned.kvs[0].1, "test_value".as_bytes().to_vec());
     assert_eq!(scanned.has_more, false);
 }
+
+fn scan_store() -> MemKvStore {
+    let store = MemKvStore::new();
+    for key in ["a", "b/1", "b/2", "b/3", "c"] {
+        let put = store.put(
+            "scan",
+            PutInput {
+                key: key.as_bytes(),
+                value: key.as_bytes(),
+                if_not_exists: false,
+            },
+        );
+        assert!(put.is_ok());
+    }
+    store
+}
+
+fn keys(kvs: &[(Vec<u8>, Vec<u8>)]) -> Vec<String> {
+    kvs.iter()
+        .map(|(k, _)| String::from_utf8(k.clone()).unwrap())
+        .collect()
+}
+
+#[test]
+fn test_mem_scan_range() {
+    let store = scan_store();
+
+    let scanned = store
+        .scan(
+            "scan",
+            ScanInput {
+                start_key: b"b/1",
+                start_exclusive: true,
+                end_key: b"c",
+                end_inclusive: true,
+                reverse: false,
+                max_count: 10,
+            },
+        )
+        .unwrap();
+    assert_eq!(keys(&scanned.kvs), vec!["b/2", "b/3", "c"]);
+    assert!(!scanned.has_more);
+
+    // A page holding exactly the remaining items has no more
+    let scanned = store
+        .scan(
+            "scan",
+            ScanInput {
+                start_key: b"b",
+                start_exclusive: false,
+                end_key: b"c",
+                end_inclusive: false,
+                reverse: false,
+                max_count: 3,
+            },
+        )
+        .unwrap();
+    assert_eq!(keys(&scanned.kvs), vec!["b/1", "b/2", "b/3"]);
+    assert!(!scanned.has_more);
+
+    // An end key before the start key is an empty range
+    let scanned = store
+        .scan(
+            "scan",
+            ScanInput {
+                start_key: b"c",
+                start_exclusive: false,
+                end_key: b"a",
+                end_inclusive: false,
+                reverse: false,
+                max_count: 10,
+            },
+        )
+        .unwrap();
+    assert!(scanned.kvs.is_empty());
+}
+
+#[test]
+fn test_mem_scan_reverse() {
+    let store = scan_store();
+
+    let scanned = store
+        .scan(
+            "scan",
+            ScanInput {
+                start_key: b"a",
+                start_exclusive: true,
+                end_key: b"c",
+                end_inclusive: false,
+                reverse: true,
+                max_count: 2,
+            },
+        )
+        .unwrap();
+    assert_eq!(keys(&scanned.kvs), vec!["b/3", "b/2"]);
+    assert!(scanned.has_more);
+}
+
+#[test]
+fn test_mem_prefix_iter() {
+    let store = scan_store();
+
+    let forward: Vec<_> = store
+        .prefix_iter("scan", b"b/", false)
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert_eq!(keys(&forward), vec!["b/1", "b/2", "b/3"]);
+
+    let backward: Vec<_> = store
+        .prefix_iter("scan", b"b/", true)
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert_eq!(keys(&backward), vec!["b/3", "b/2", "b/1"]);
+
+    assert_eq!(prefix_end(b"b/"), b"b0".to_vec());
+    assert_eq!(prefix_end(&[0x61, 0xff]), vec![0x62]);
+    assert!(prefix_end(&[0xff, 0xff]).is_empty());
+}
+
+#[test]
+fn test_mem_range_iter_pages() {
+    let store = MemKvStore::new();
+    for i in 0..1000u32 {
+        let key = i.to_be_bytes();
+        let put = store.put(
+            "pages",
+            PutInput {
+                key: &key,
+                value: &[],
+                if_not_exists: false,
+            },
+        );
+        assert!(put.is_ok());
+    }
+
+    // Continues across pages, without repeating the key a page ends at
+    let all: Vec<_> = store
+        .range_iter("pages", &[], &[], false)
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert_eq!(all.len(), 1000);
+    assert!(all.windows(2).all(|w| w[0].0 < w[1].0));
+
+    let end = 600u32.to_be_bytes();
+    let reversed: Vec<_> = store
+        .range_iter("pages", &100u32.to_be_bytes(), &end, true)
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert_eq!(reversed.len(), 500);
+    assert_eq!(reversed[0].0, 599u32.to_be_bytes().to_vec());
+    assert_eq!(reversed[499].0, 100u32.to_be_bytes().to_vec());
+}
+
+#[test]
+fn test_mem_atomic_ops() {
+    let store = MemKvStore::new();
+    let table = "counters";
+
+    assert_eq!(store.increment(table, b"hits", 5).unwrap(), 5);
+    assert_eq!(store.increment(table, b"hits", -2).unwrap(), 3);
+    assert_eq!(store.increment(table, b"hits", i64::MAX).unwrap(), i64::MAX);
+    assert_eq!(
+        decode_counter(&store.get(table, b"hits").unwrap()),
+        Some(i64::MAX)
+    );
+
+    store
+        .put(
+            table,
+            PutInput {
+                key: b"name",
+                value: b"text",
+                if_not_exists: false,
+            },
+        )
+        .unwrap();
+    assert!(matches!(
+        store.increment(table, b"name", 1),
+        Err(IncrementError::NotACounter)
+    ));
+
+    // Compare-and-set only replaces the expected value, reporting the value found otherwise
+    assert!(store
+        .compare_and_set(table, b"lock", None, Some(b"owner1"))
+        .unwrap()
+        .is_set());
+    assert_eq!(
+        store
+            .compare_and_set(table, b"lock", None, Some(b"owner2"))
+            .unwrap(),
+        CasOutcome::Conflict(Some(b"owner1".to_vec()))
+    );
+    assert!(store
+        .compare_and_set(table, b"lock", Some(b"owner1"), None)
+        .unwrap()
+        .is_set());
+    assert_eq!(
+        store
+            .compare_and_set(table, b"lock", Some(b"owner1"), Some(b"owner2"))
+            .unwrap(),
+        CasOutcome::Conflict(None)
+    );
+    assert!(matches!(
+        store.get(table, b"lock"),
+        Err(GetError::NoSuchKey)
+    ));
+}
+
+#[test]
+fn test_mem_concurrent_increments() {
+    let store = std::sync::Arc::new(MemKvStore::new());
+    let handles: Vec<_> = (0..8)
+        .map(|_| {
+            let store = store.clone();
+            std::thread::spawn(move || {
+                (0..100)
+                    .map(|_| store.increment("counters", b"nonce", 1).unwrap())
+                    .collect::<Vec<_>>()
+            })
+        })
+        .collect();
+
+    let mut values: Vec<i64> = handles
+        .into_iter()
+        .flat_map(|handle| handle.join().unwrap())
+        .collect();
+    values.sort();
+    values.dedup();
+    assert_eq!(values, (1..=800).collect::<Vec<_>>());
+}
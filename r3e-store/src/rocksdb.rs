@@ -4,8 +4,8 @@
 use bincode::{deserialize, serialize};
 use log::error;
 use rocksdb::{
-    ColumnFamilyDescriptor, Direction, IteratorMode, Options, ReadOptions,
-    WriteBatch, DB,
+    checkpoint::Checkpoint, ColumnFamilyDescriptor, Direction, IteratorMode, MergeOperands,
+    Options, ReadOptions, WriteBatch, DB,
 };
 use serde::{de::DeserializeOwned, Serialize, Deserialize};
 use std::{
@@ -17,6 +17,14 @@ use std::{
 use async_trait::async_trait;
 use thiserror::Error;
 
+use crate::codec::{
+    chunk_key, chunk_table, decode_value, encode_value, parse_header, CodecError, TableOptions,
+    ValueStats, CHUNK_TABLE_SUFFIX,
+};
+use crate::error::{CasError, IncrementError};
+use crate::storage::AtomicKvStore;
+use crate::types::{decode_counter, encode_counter, CasOutcome};
+
 /// Database result type
 pub type DbResult<T> = std::result::Result<T, DbError>;
 
@@ -100,6 +108,14 @@ pub enum DbError {
     /// UTF-8 error
     #[error("UTF-8 error: {0}")]
     Utf8Error(String),
+
+    /// Value encoding error
+    #[error("Value encoding error: {0}")]
+    Codec(#[from] CodecError),
+
+    /// The value under a key is not a counter
+    #[error("Not a counter: {0}")]
+    NotACounter(String),
     
     /// Other error
     #[error("Other error: {0}")]
@@ -216,6 +232,9 @@ pub struct RocksDbConfig {
     pub block_cache_size: usize,
     /// Bloom filter bits
     pub bloom_filter_bits: i32,
+    /// Value compression and chunking per column family, values of other column
+    /// families are stored as is
+    pub table_options: HashMap<String, TableOptions>,
 }
 
 impl Default for RocksDbConfig {
@@ -235,6 +254,7 @@ impl Default for RocksDbConfig {
             block_size: 4096,
             block_cache_size: 8 * 1024 * 1024,
             bloom_filter_bits: 10,
+            table_options: HashMap::new(),
         }
     }
 }
@@ -281,6 +301,29 @@ fn optimize_db_options(options: &mut Options, config: &RocksDbConfig) {
     
     // Set compression
     options.set_compression_type(config.compression_type.into());
+
+    // Counters of the default column family
+    options.set_merge_operator_associative(COUNTER_MERGE_OPERATOR, merge_counters);
+}
+
+/// Name of the merge operator adding up counters
+const COUNTER_MERGE_OPERATOR: &str = "r3e_counter_add";
+
+/// Add the little-endian i64 deltas of the operands to a counter, saturating at the i64 bounds.
+/// Fails the merge, reported as corruption, if the existing value or an operand is not a counter.
+fn merge_counters(
+    _key: &[u8],
+    existing: Option<&[u8]>,
+    operands: &MergeOperands,
+) -> Option<Vec<u8>> {
+    let mut value = match existing {
+        Some(existing) => decode_counter(existing)?,
+        None => 0,
+    };
+    for operand in operands.iter() {
+        value = value.saturating_add(decode_counter(operand)?);
+    }
+    Some(encode_counter(value).to_vec())
 }
 
 /// Optimize the column family options
@@ -292,6 +335,34 @@ fn optimize_cf_options(options: &mut Options, config: &RocksDbConfig) {
     if config.optimize_point_lookup {
         options.optimize_for_point_lookup(128 * 1024 * 1024); // 128 MB cache
     }
+
+    // Any column family may hold counters
+    options.set_merge_operator_associative(COUNTER_MERGE_OPERATOR, merge_counters);
+}
+
+/// Size statistics of a column family
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TableStats {
+    /// Column family name
+    pub name: String,
+
+    /// Value compression and chunking, if the values are encoded
+    pub options: Option<TableOptions>,
+
+    /// Estimated number of keys
+    pub estimated_keys: u64,
+
+    /// Estimated size of the live data in bytes, including chunks
+    pub live_data_bytes: u64,
+
+    /// Size of the SST files in bytes, including chunks
+    pub sst_files_bytes: u64,
+
+    /// Size of the memtables in bytes, including chunks
+    pub memtable_bytes: u64,
+
+    /// Values written since the database was opened
+    pub writes: ValueStats,
 }
 
 /// RocksDB client wrapper
@@ -307,18 +378,46 @@ pub struct RocksDbClient {
     
     /// Column family options
     cf_options: Arc<Mutex<HashMap<String, ColumnFamilyConfig>>>,
+
+    /// Value compression and chunking per column family
+    table_options: Arc<Mutex<HashMap<String, TableOptions>>>,
+
+    /// Write statistics per column family
+    value_stats: Arc<Mutex<HashMap<String, ValueStats>>>,
+
+    /// Serializes the read-modify-write of increments and compare-and-sets
+    atomic_lock: Arc<Mutex<()>>,
 }
 
 impl RocksDbClient {
     /// Create a new RocksDB client
     pub fn new(config: RocksDbConfig) -> Self {
+        let table_options = config.table_options.clone();
         Self {
             db: Arc::new(Mutex::new(None)),
             config,
             cf_handles: Arc::new(Mutex::new(HashMap::new())),
             cf_options: Arc::new(Mutex::new(HashMap::new())),
+            table_options: Arc::new(Mutex::new(table_options)),
+            value_stats: Arc::new(Mutex::new(HashMap::new())),
+            atomic_lock: Arc::new(Mutex::new(())),
         }
     }
+
+    /// Compress and chunk the values of a column family written from now on.
+    ///
+    /// Values already stored without encoding stay readable.
+    pub fn set_table_options(&self, cf_name: &str, options: TableOptions) {
+        self.table_options
+            .lock()
+            .unwrap()
+            .insert(cf_name.to_string(), options);
+    }
+
+    /// Value compression and chunking of a column family
+    pub fn table_options(&self, cf_name: &str) -> Option<TableOptions> {
+        self.table_options.lock().unwrap().get(cf_name).cloned()
+    }
     
     /// Open the database
     pub fn open(&self) -> DbResult<()> {
@@ -416,12 +515,21 @@ impl RocksDbClient {
         
         // Get the iterator
         let db_iter = db.iterator_cf(&cf_handle, mode);
+        let options = self.table_options(cf_name);
+        let db_ref = &db;
         
         // Map the iterator to deserialize values
         let iter = db_iter
             .filter_map(move |result| {
                 match result {
                     Ok((k, v)) => {
+                        let v = match self.decode_stored(db_ref, cf_name, options.as_ref(), &k, v) {
+                            Ok(v) => v,
+                            Err(e) => {
+                                error!("Failed to decode value: {}", e);
+                                return None;
+                            }
+                        };
                         match deserialize::<V>(&v) {
                             Ok(value) => Some((k, value)),
                             Err(e) => {
@@ -463,6 +571,8 @@ impl RocksDbClient {
         // Create an iterator with the prefix
         let mode = IteratorMode::From(prefix, Direction::Forward);
         let db_iter = db.iterator_cf_opt(&cf_handle, opts, mode);
+        let options = self.table_options(cf_name);
+        let db_ref = &db;
         
         // Filter by prefix and deserialize values
         let iter = db_iter
@@ -475,6 +585,13 @@ impl RocksDbClient {
             .filter_map(move |result| {
                 match result {
                     Ok((k, v)) => {
+                        let v = match self.decode_stored(db_ref, cf_name, options.as_ref(), &k, v) {
+                            Ok(v) => v,
+                            Err(e) => {
+                                error!("Failed to decode value: {}", e);
+                                return None;
+                            }
+                        };
                         match deserialize::<V>(&v) {
                             Ok(value) => Some((k, value)),
                             Err(e) => {
@@ -493,20 +610,49 @@ impl RocksDbClient {
         Ok(Box::new(ThreadSafeIterator::new(iter)))
     }
 
-    /// Get a value from a column family
-    pub fn get_cf<K, V>(&self, cf_name: &str, key: K) -> DbResult<Option<V>>
+    /// Scan at most `limit` entries of a column family in key order, starting at `start`
+    /// (inclusive). Unlike `iter_cf`, only the requested entries are read.
+    pub fn scan_cf<V>(
+        &self,
+        cf_name: &str,
+        start: &[u8],
+        limit: usize,
+    ) -> DbResult<Vec<(Box<[u8]>, V)>>
     where
-        K: AsRef<[u8]>,
         V: DeserializeOwned,
     {
         let db = self.get_db()?;
+
         let cf_handle = match db.cf_handle(cf_name) {
             Some(handle) => handle,
             None => return Err(DbError::ColumnFamilyNotFound(cf_name.to_string())),
         };
-        
-        let result = db.get_cf(&cf_handle, key.as_ref()).map_err(DbError::RocksDb)?;
-        if let Some(value) = result {
+
+        let mode = if start.is_empty() {
+            IteratorMode::Start
+        } else {
+            IteratorMode::From(start, Direction::Forward)
+        };
+
+        let options = self.table_options(cf_name);
+        let mut items = Vec::new();
+        for result in db.iterator_cf(&cf_handle, mode).take(limit) {
+            let (k, v) = result.map_err(DbError::RocksDb)?;
+            let v = self.decode_stored(&db, cf_name, options.as_ref(), &k, v)?;
+            let value = deserialize::<V>(&v).map_err(|e| DbError::Serialization(e.to_string()))?;
+            items.push((k, value));
+        }
+
+        Ok(items)
+    }
+
+    /// Get a value from a column family
+    pub fn get_cf<K, V>(&self, cf_name: &str, key: K) -> DbResult<Option<V>>
+    where
+        K: AsRef<[u8]>,
+        V: DeserializeOwned,
+    {
+        if let Some(value) = self.get_bytes_cf(cf_name, key.as_ref())? {
             let deserialized = deserialize(&value)?;
             Ok(Some(deserialized))
         } else {
@@ -521,15 +667,12 @@ impl RocksDbClient {
         V: Serialize,
     {
         let db = self.get_db()?;
-        let cf_handle = match db.cf_handle(cf_name) {
-            Some(handle) => handle,
-            None => return Err(DbError::ColumnFamilyNotFound(cf_name.to_string())),
-        };
-        
         let bytes = serialize(value)
             .map_err(|e| DbError::Serialization(e.to_string()))?;
         
-        db.put_cf(&cf_handle, key.as_ref(), bytes).map_err(DbError::RocksDb)
+        let mut batch = WriteBatch::default();
+        self.stage_put(&db, &mut batch, cf_name, key.as_ref(), &bytes)?;
+        db.write(batch).map_err(DbError::RocksDb)
     }
 
     /// Delete a key from a column family
@@ -537,13 +680,285 @@ impl RocksDbClient {
     where
         K: AsRef<[u8]>,
     {
+        let db = self.get_db()?;
+        let mut batch = WriteBatch::default();
+        self.stage_delete(&db, &mut batch, cf_name, key.as_ref())?;
+        db.write(batch).map_err(DbError::RocksDb)
+    }
+
+    /// Add `delta` to the counter under a key with a merge, without reading it.
+    ///
+    /// Counters are stored unencoded, so they cannot be kept in encoded column families.
+    pub fn add_cf(&self, cf_name: &str, key: &[u8], delta: i64) -> DbResult<()> {
+        let db = self.get_db()?;
+        let cf_handle = self.counter_cf(&db, cf_name)?;
+        db.merge_cf(&cf_handle, key, encode_counter(delta))
+            .map_err(DbError::RocksDb)
+    }
+
+    /// Add `delta` to the counter under a key and return its new value.
+    ///
+    /// Increments are serialized, so concurrent callers each get a distinct value.
+    pub fn increment_cf(&self, cf_name: &str, key: &[u8], delta: i64) -> DbResult<i64> {
+        let db = self.get_db()?;
+        let cf_handle = self.counter_cf(&db, cf_name)?;
+
+        let _guard = self.atomic_lock.lock().unwrap();
+        if let Some(stored) = db.get_cf(&cf_handle, key).map_err(DbError::RocksDb)? {
+            if decode_counter(&stored).is_none() {
+                return Err(DbError::NotACounter(format!(
+                    "{} in {}",
+                    String::from_utf8_lossy(key),
+                    cf_name
+                )));
+            }
+        }
+
+        db.merge_cf(&cf_handle, key, encode_counter(delta))
+            .map_err(DbError::RocksDb)?;
+        let stored = db.get_cf(&cf_handle, key).map_err(DbError::RocksDb)?;
+        stored
+            .as_deref()
+            .and_then(decode_counter)
+            .ok_or_else(|| DbError::NotACounter(cf_name.to_string()))
+    }
+
+    /// Replace the stored bytes of a key with `new`, deleting it if None, only if they are
+    /// `expected`, None meaning the key is absent
+    pub fn compare_and_set_cf(
+        &self,
+        cf_name: &str,
+        key: &[u8],
+        expected: Option<&[u8]>,
+        new: Option<&[u8]>,
+    ) -> DbResult<CasOutcome> {
+        let db = self.get_db()?;
+
+        let _guard = self.atomic_lock.lock().unwrap();
+        let current = self.get_bytes_cf(cf_name, key)?;
+        if current.as_deref() != expected {
+            return Ok(CasOutcome::Conflict(current));
+        }
+
+        let mut batch = WriteBatch::default();
+        match new {
+            Some(value) => self.stage_put(&db, &mut batch, cf_name, key, value)?,
+            None => self.stage_delete(&db, &mut batch, cf_name, key)?,
+        }
+        db.write(batch).map_err(DbError::RocksDb)?;
+        Ok(CasOutcome::Set)
+    }
+
+    /// Handle of a column family holding counters
+    fn counter_cf<'a>(
+        &self,
+        db: &'a DB,
+        cf_name: &str,
+    ) -> DbResult<Arc<rocksdb::BoundColumnFamily<'a>>> {
+        if self.table_options(cf_name).is_some() {
+            return Err(DbError::Other(format!(
+                "Counters cannot be kept in encoded column family {}",
+                cf_name
+            )));
+        }
+        db.cf_handle(cf_name)
+            .ok_or_else(|| DbError::ColumnFamilyNotFound(cf_name.to_string()))
+    }
+
+    /// Get the stored bytes of a value, decoded if its column family is encoded
+    fn get_bytes_cf(&self, cf_name: &str, key: &[u8]) -> DbResult<Option<Vec<u8>>> {
         let db = self.get_db()?;
         let cf_handle = match db.cf_handle(cf_name) {
             Some(handle) => handle,
             None => return Err(DbError::ColumnFamilyNotFound(cf_name.to_string())),
         };
-        
-        db.delete_cf(&cf_handle, key.as_ref()).map_err(DbError::RocksDb)
+
+        let options = self.table_options(cf_name);
+        match db.get_cf(&cf_handle, key).map_err(DbError::RocksDb)? {
+            Some(stored) => Ok(Some(self.decode_stored(
+                &db,
+                cf_name,
+                options.as_ref(),
+                key,
+                stored,
+            )?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Decode a stored value of an encoded column family, reading its chunks
+    fn decode_stored<S>(
+        &self,
+        db: &DB,
+        cf_name: &str,
+        options: Option<&TableOptions>,
+        key: &[u8],
+        stored: S,
+    ) -> DbResult<Vec<u8>>
+    where
+        S: AsRef<[u8]> + Into<Vec<u8>>,
+    {
+        if options.is_none() {
+            return Ok(stored.into());
+        }
+
+        let chunk_cf = chunk_table(cf_name);
+        let value = decode_value(stored.as_ref(), |index| {
+            let handle = db.cf_handle(&chunk_cf).ok_or_else(|| {
+                CodecError::Corrupted(format!("missing chunk table {}", chunk_cf))
+            })?;
+            db.get_cf(&handle, chunk_key(key, index))
+                .map_err(|e| CodecError::Corrupted(e.to_string()))
+        })?;
+
+        Ok(value)
+    }
+
+    /// Number of chunks of the value stored under `key`
+    fn stored_chunks(&self, db: &DB, cf_name: &str, key: &[u8]) -> DbResult<u32> {
+        let cf_handle = db
+            .cf_handle(cf_name)
+            .ok_or_else(|| DbError::ColumnFamilyNotFound(cf_name.to_string()))?;
+
+        let stored = db.get_cf(&cf_handle, key).map_err(DbError::RocksDb)?;
+        Ok(stored
+            .as_deref()
+            .and_then(parse_header)
+            .map(|header| header.chunks)
+            .unwrap_or(0))
+    }
+
+    /// Add the writes of a value to a batch, encoding it if its column family is encoded
+    fn stage_put(
+        &self,
+        db: &DB,
+        batch: &mut WriteBatch,
+        cf_name: &str,
+        key: &[u8],
+        bytes: &[u8],
+    ) -> DbResult<()> {
+        let cf_handle = db
+            .cf_handle(cf_name)
+            .ok_or_else(|| DbError::ColumnFamilyNotFound(cf_name.to_string()))?;
+
+        let Some(options) = self.table_options(cf_name) else {
+            batch.put_cf(&cf_handle, key, bytes);
+            return Ok(());
+        };
+
+        let encoded = encode_value(&options, bytes)?;
+        let old_chunks = self.stored_chunks(db, cf_name, key)?;
+        let new_chunks = encoded.chunks.len() as u32;
+
+        if new_chunks > 0 || old_chunks > 0 {
+            let chunk_cf = chunk_table(cf_name);
+            self.create_cf_if_missing(&chunk_cf)?;
+            let chunk_handle = db
+                .cf_handle(&chunk_cf)
+                .ok_or_else(|| DbError::ColumnFamilyNotFound(chunk_cf.clone()))?;
+
+            for (index, chunk) in encoded.chunks.iter().enumerate() {
+                batch.put_cf(&chunk_handle, chunk_key(key, index as u32), chunk);
+            }
+            // Drop the chunks an overwritten value had beyond the new ones
+            for index in new_chunks..old_chunks {
+                batch.delete_cf(&chunk_handle, chunk_key(key, index));
+            }
+        }
+        batch.put_cf(&cf_handle, key, &encoded.head);
+
+        self.value_stats
+            .lock()
+            .unwrap()
+            .entry(cf_name.to_string())
+            .or_default()
+            .record(&encoded);
+
+        Ok(())
+    }
+
+    /// Add the deletes of a value and its chunks to a batch
+    fn stage_delete(
+        &self,
+        db: &DB,
+        batch: &mut WriteBatch,
+        cf_name: &str,
+        key: &[u8],
+    ) -> DbResult<()> {
+        let cf_handle = db
+            .cf_handle(cf_name)
+            .ok_or_else(|| DbError::ColumnFamilyNotFound(cf_name.to_string()))?;
+
+        if self.table_options(cf_name).is_some() {
+            let chunks = self.stored_chunks(db, cf_name, key)?;
+            if chunks > 0 {
+                let chunk_cf = chunk_table(cf_name);
+                let chunk_handle = db
+                    .cf_handle(&chunk_cf)
+                    .ok_or_else(|| DbError::ColumnFamilyNotFound(chunk_cf.clone()))?;
+                for index in 0..chunks {
+                    batch.delete_cf(&chunk_handle, chunk_key(key, index));
+                }
+            }
+        }
+        batch.delete_cf(&cf_handle, key);
+
+        Ok(())
+    }
+
+    /// Size statistics of a column family, with the chunks of its values
+    pub fn table_stats(&self, cf_name: &str) -> DbResult<TableStats> {
+        let db = self.get_db()?;
+        let chunk_cf = chunk_table(cf_name);
+
+        let mut stats = TableStats {
+            name: cf_name.to_string(),
+            options: self.table_options(cf_name),
+            estimated_keys: 0,
+            live_data_bytes: 0,
+            sst_files_bytes: 0,
+            memtable_bytes: 0,
+            writes: self
+                .value_stats
+                .lock()
+                .unwrap()
+                .get(cf_name)
+                .cloned()
+                .unwrap_or_default(),
+        };
+
+        for (name, is_chunks) in [(cf_name, false), (chunk_cf.as_str(), true)] {
+            let cf_handle = match db.cf_handle(name) {
+                Some(handle) => handle,
+                None if is_chunks => continue,
+                None => return Err(DbError::ColumnFamilyNotFound(name.to_string())),
+            };
+            let property = |property: &str| -> DbResult<u64> {
+                Ok(db
+                    .property_int_value_cf(&cf_handle, property)
+                    .map_err(DbError::RocksDb)?
+                    .unwrap_or(0))
+            };
+
+            // Chunks are part of their value, not keys of their own
+            if !is_chunks {
+                stats.estimated_keys = property("rocksdb.estimate-num-keys")?;
+            }
+            stats.live_data_bytes += property("rocksdb.estimate-live-data-size")?;
+            stats.sst_files_bytes += property("rocksdb.total-sst-files-size")?;
+            stats.memtable_bytes += property("rocksdb.cur-size-all-mem-tables")?;
+        }
+
+        Ok(stats)
+    }
+
+    /// Size statistics of all column families
+    pub fn stats(&self) -> DbResult<Vec<TableStats>> {
+        self.list_column_families()?
+            .iter()
+            .filter(|name| !name.ends_with(CHUNK_TABLE_SUFFIX))
+            .map(|name| self.table_stats(name))
+            .collect()
     }
 
     /// Check if a key exists in a column family
@@ -676,18 +1091,10 @@ impl RocksDbClient {
         for operation in operations {
             match operation {
                 BatchOperation::Put { cf_name, key, value } => {
-                    if let Some(handle) = db.cf_handle(&cf_name) {
-                        batch.put_cf(&handle, key, value);
-                    } else {
-                        return Err(DbError::ColumnFamilyNotFound(cf_name));
-                    }
+                    self.stage_put(&db, &mut batch, &cf_name, &key, &value)?;
                 }
                 BatchOperation::Delete { cf_name, key } => {
-                    if let Some(handle) = db.cf_handle(&cf_name) {
-                        batch.delete_cf(&handle, key);
-                    } else {
-                        return Err(DbError::ColumnFamilyNotFound(cf_name));
-                    }
+                    self.stage_delete(&db, &mut batch, &cf_name, &key)?;
                 }
             }
         }
@@ -723,30 +1130,15 @@ impl RocksDbClient {
         }
     }
 
-    /// Create a backup
-    pub fn create_backup(&self) -> DbResult<String> {
+    /// Create a checkpoint, a consistent copy of the database in a new directory.
+    ///
+    /// Memtables are flushed first, and SST files are hard linked when the directory is on
+    /// the same file system. See [`crate::backup`] for encrypted off-site backups.
+    pub fn create_checkpoint(&self, path: &Path) -> DbResult<()> {
         let db = self.get_db()?;
         
-        // Generate a backup ID
-        let backup_id = format!("backup_{}", chrono::Utc::now().timestamp());
-        
-        // Get all column family names by listing them
-        let cf_list = self.list_column_families()?;
-        
-        // Process each column family
-        for cf_name in cf_list {
-            if let Some(_handle) = db.cf_handle(&cf_name) {
-                // TODO: implement actual backup logic
-            }
-        }
-        
-        Ok(backup_id)
-    }
-
-    /// Restore from a backup
-    pub fn restore_backup(&self, _backup_id: &str) -> DbResult<()> {
-        // Implementation 
-        Ok(())
+        let checkpoint = Checkpoint::new(db.as_ref()).map_err(DbError::RocksDb)?;
+        checkpoint.create_checkpoint(path).map_err(DbError::RocksDb)
     }
 
     /// Delete all keys with a prefix in a column family
@@ -792,6 +1184,50 @@ impl RocksDbClient {
     }
 }
 
+/// Tables are column families, created on first use
+impl AtomicKvStore for RocksDbClient {
+    fn increment(&self, table: &str, key: &[u8], delta: i64) -> Result<i64, IncrementError> {
+        if table.len() > crate::MAX_TABLE_NAME_SIZE {
+            return Err(IncrementError::InvalidTable);
+        }
+
+        if key.len() > crate::MAX_KEY_SIZE {
+            return Err(IncrementError::TooLargeKey);
+        }
+
+        self.create_cf_if_missing(table)
+            .and_then(|()| self.increment_cf(table, key, delta))
+            .map_err(|e| match e {
+                DbError::NotACounter(_) => IncrementError::NotACounter,
+                e => IncrementError::Storage(e.to_string()),
+            })
+    }
+
+    fn compare_and_set(
+        &self,
+        table: &str,
+        key: &[u8],
+        expected: Option<&[u8]>,
+        new: Option<&[u8]>,
+    ) -> Result<CasOutcome, CasError> {
+        if table.len() > crate::MAX_TABLE_NAME_SIZE {
+            return Err(CasError::InvalidTable);
+        }
+
+        if key.len() > crate::MAX_KEY_SIZE {
+            return Err(CasError::TooLargeKey);
+        }
+
+        if new.is_some_and(|value| value.len() > crate::MAX_VALUE_SIZE) {
+            return Err(CasError::TooLargeValue);
+        }
+
+        self.create_cf_if_missing(table)
+            .and_then(|()| self.compare_and_set_cf(table, key, expected, new))
+            .map_err(|e| CasError::Storage(e.to_string()))
+    }
+}
+
 /// Batch operation type for the write_batch method
 #[derive(Debug, Clone)]
 pub enum BatchOperation {
@@ -835,39 +1271,10 @@ impl AsyncRocksDbClient {
     {
         let db = self.db.clone();
         let cf_name = cf_name.to_string();
-        let key_bytes = key.as_ref().to_vec();
         
-        let result = tokio::task::spawn_blocking(move || {
-            // Get the DB client
-            let rocks_db = match db.get_db() {
-                Ok(db) => db,
-                Err(e) => return Err(e),
-            };
-            
-            // Get the column family handle
-            let cf_handle = match rocks_db.cf_handle(&cf_name) {
-                Some(handle) => handle,
-                None => return Err(DbError::ColumnFamilyNotFound(cf_name)),
-            };
-            
-            // Get the value
-            match rocks_db.get_cf(&cf_handle, &key_bytes) {
-                Ok(Some(bytes)) => {
-                    // Deserialize the value
-                    match deserialize(&bytes) {
-                        Ok(value) => Ok(Some(value)),
-                        Err(e) => Err(DbError::Deserialization(e.to_string())),
-                    }
-                },
-                Ok(None) => Ok(None),
-                Err(e) => Err(DbError::RocksDb(e)),
-            }
-        }).await;
-        
-        match result {
-            Ok(r) => r,
-            Err(e) => Err(DbError::Tokio(e.to_string())),
-        }
+        tokio::task::spawn_blocking(move || db.get_cf::<_, V>(&cf_name, key))
+            .await
+            .map_err(|e| DbError::Tokio(e.to_string()))?
     }
 
     /// Check if a key exists in a column family
@@ -909,26 +1316,10 @@ impl AsyncRocksDbClient {
     {
         let db = self.db.clone();
         let cf_name = cf_name.to_string();
-        let key_bytes = key.as_ref().to_vec();
-        let value_bytes = serialize(&value)
-            .map_err(|e| DbError::Serialization(e.to_string()))?;
         
-        tokio::task::spawn_blocking(move || {
-            // Get the DB client
-            let rocks_db = match db.get_db() {
-                Ok(db) => db,
-                Err(e) => return Err(e),
-            };
-            
-            // Get the column family handle
-            let cf_handle = match rocks_db.cf_handle(&cf_name) {
-                Some(handle) => handle,
-                None => return Err(DbError::ColumnFamilyNotFound(cf_name)),
-            };
-            
-            rocks_db.put_cf(&cf_handle, &key_bytes, &value_bytes)
-                .map_err(DbError::RocksDb)
-        }).await.map_err(|e| DbError::Tokio(e.to_string()))?
+        tokio::task::spawn_blocking(move || db.put_cf(&cf_name, key, &value))
+            .await
+            .map_err(|e| DbError::Tokio(e.to_string()))?
     }
 
     /// Delete a key from a column family
@@ -938,24 +1329,10 @@ impl AsyncRocksDbClient {
     {
         let db = self.db.clone();
         let cf_name = cf_name.to_string();
-        let key_bytes = key.as_ref().to_vec();
         
-        tokio::task::spawn_blocking(move || {
-            // Get the DB client
-            let rocks_db = match db.get_db() {
-                Ok(db) => db,
-                Err(e) => return Err(e),
-            };
-            
-            // Get the column family handle
-            let cf_handle = match rocks_db.cf_handle(&cf_name) {
-                Some(handle) => handle,
-                None => return Err(DbError::ColumnFamilyNotFound(cf_name)),
-            };
-            
-            rocks_db.delete_cf(&cf_handle, &key_bytes)
-                .map_err(DbError::RocksDb)
-        }).await.map_err(|e| DbError::Tokio(e.to_string()))?
+        tokio::task::spawn_blocking(move || db.delete_cf(&cf_name, key))
+            .await
+            .map_err(|e| DbError::Tokio(e.to_string()))?
     }
 
     /// Iterate over a column family
@@ -1039,30 +1416,28 @@ impl AsyncRocksDbClient {
     pub async fn write_batch(&self, ops: Vec<BatchOperation>) -> DbResult<()> {
         let db = self.db.clone();
         
-        tokio::task::spawn_blocking(move || {
-            let mut batch = WriteBatch::default();
-            
-            for op in ops {
-                match op {
-                    BatchOperation::Put { cf_name, key, value } => {
-                        if let Some(handle) = db.get_db()?.cf_handle(&cf_name) {
-                            batch.put_cf(&handle, key, value);
-                        } else {
-                            return Err(DbError::ColumnFamilyNotFound(cf_name));
-                        }
-                    },
-                    BatchOperation::Delete { cf_name, key } => {
-                        if let Some(handle) = db.get_db()?.cf_handle(&cf_name) {
-                            batch.delete_cf(&handle, key);
-                        } else {
-                            return Err(DbError::ColumnFamilyNotFound(cf_name));
-                        }
-                    },
-                }
-            }
-            
-            db.get_db()?.write(batch).map_err(DbError::RocksDb)
-        }).await.map_err(|e| DbError::Tokio(e.to_string()))?
+        tokio::task::spawn_blocking(move || db.write_batch(ops))
+            .await
+            .map_err(|e| DbError::Tokio(e.to_string()))?
+    }
+    
+    /// Size statistics of a column family
+    pub async fn table_stats(&self, cf_name: &str) -> DbResult<TableStats> {
+        let db = self.db.clone();
+        let cf_name = cf_name.to_string();
+        
+        tokio::task::spawn_blocking(move || db.table_stats(&cf_name))
+            .await
+            .map_err(|e| DbError::Tokio(e.to_string()))?
+    }
+    
+    /// Size statistics of all column families
+    pub async fn stats(&self) -> DbResult<Vec<TableStats>> {
+        let db = self.db.clone();
+        
+        tokio::task::spawn_blocking(move || db.stats())
+            .await
+            .map_err(|e| DbError::Tokio(e.to_string()))?
     }
     
     /// Flush a column family
@@ -0,0 +1,340 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+//! Versioned records and their migrations.
+//!
+//! Records of a [`Versioned`] type are stored as JSON in an envelope starting with
+//! [`RECORD_MAGIC`] and the schema version they were written with. Reading a record
+//! written with an older version runs the migrations of its type up to the current
+//! version, and tells the caller to write it back, so records are upgraded lazily.
+//! [`migrate_cf`] upgrades a whole column family at once.
+//!
+//! Records without an envelope were written before versioning and are read as
+//! version 1. Records written by a newer release are read as is when they still
+//! deserialize, which is the case for added optional fields, so nodes of both
+//! releases can run side by side during a rolling upgrade.
+
+use log::warn;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+use thiserror::Error;
+
+use crate::rocksdb::{DbError, RocksDbClient};
+
+/// Magic bytes and envelope format version starting every versioned record
+pub const RECORD_MAGIC: [u8; 4] = *b"R3R\x01";
+
+/// Length of the envelope header
+pub const RECORD_HEADER_LEN: usize = RECORD_MAGIC.len() + 4;
+
+/// Number of records read per batch when migrating a column family
+const MIGRATION_BATCH: usize = 256;
+
+/// Error type for versioned records
+#[derive(Debug, Error)]
+pub enum SchemaError {
+    /// Record cannot be encoded or decoded
+    #[error("schema: invalid {schema} record: {reason}")]
+    Invalid {
+        schema: &'static str,
+        reason: String,
+    },
+
+    /// Record was written by a newer release and no longer deserializes
+    #[error("schema: {schema} record of version {version} is newer than version {supported}")]
+    TooNew {
+        schema: &'static str,
+        version: u32,
+        supported: u32,
+    },
+
+    /// A migration failed
+    #[error("schema: migrating {schema} from version {from} failed: {reason}")]
+    Migration {
+        schema: &'static str,
+        from: u32,
+        reason: String,
+    },
+
+    /// Storage error
+    #[error("schema: storage error: {0}")]
+    Storage(#[from] DbError),
+}
+
+/// Upgrade a record from one version to the next
+pub type Migration = fn(Value) -> Result<Value, String>;
+
+/// A type stored as a versioned record
+pub trait Versioned: Serialize + DeserializeOwned {
+    /// Name of the record type, used in errors
+    const SCHEMA: &'static str;
+
+    /// Version records are written with, starting at 1
+    const VERSION: u32;
+
+    /// Migrations upgrading records to the current version, the migration at index `i`
+    /// upgrading version `i + 1` to `i + 2`. Must hold `VERSION - 1` migrations.
+    fn migrations() -> &'static [Migration] {
+        &[]
+    }
+}
+
+/// A decoded record
+#[derive(Debug, Clone)]
+pub struct Decoded<T> {
+    /// The record at the current version
+    pub value: T,
+
+    /// Version the record was stored with
+    pub version: u32,
+
+    /// Whether the stored record is older than the current version and should be written
+    /// back
+    pub outdated: bool,
+}
+
+/// Encode a record at the current version of its type
+pub fn encode_record<T: Versioned>(value: &T) -> Result<Vec<u8>, SchemaError> {
+    let mut record = Vec::with_capacity(256);
+    record.extend_from_slice(&RECORD_MAGIC);
+    record.extend_from_slice(&T::VERSION.to_le_bytes());
+    serde_json::to_writer(&mut record, value).map_err(|e| SchemaError::Invalid {
+        schema: T::SCHEMA,
+        reason: e.to_string(),
+    })?;
+    Ok(record)
+}
+
+/// Version and JSON payload of a stored record, version 1 for records without an envelope
+pub fn split_record(stored: &[u8]) -> (u32, &[u8]) {
+    if stored.len() < RECORD_HEADER_LEN || stored[..RECORD_MAGIC.len()] != RECORD_MAGIC {
+        return (1, stored);
+    }
+
+    let version = u32::from_le_bytes(
+        stored[RECORD_MAGIC.len()..RECORD_HEADER_LEN]
+            .try_into()
+            .unwrap(),
+    );
+    (version, &stored[RECORD_HEADER_LEN..])
+}
+
+/// Decode a stored record, migrating it to the current version of its type
+pub fn decode_record<T: Versioned>(stored: &[u8]) -> Result<Decoded<T>, SchemaError> {
+    let (version, payload) = split_record(stored);
+    let invalid = |e: serde_json::Error| SchemaError::Invalid {
+        schema: T::SCHEMA,
+        reason: e.to_string(),
+    };
+
+    if version >= T::VERSION {
+        let value = serde_json::from_slice(payload).map_err(|e| {
+            if version > T::VERSION {
+                SchemaError::TooNew {
+                    schema: T::SCHEMA,
+                    version,
+                    supported: T::VERSION,
+                }
+            } else {
+                invalid(e)
+            }
+        })?;
+        return Ok(Decoded {
+            value,
+            version,
+            outdated: false,
+        });
+    }
+
+    let migrations = T::migrations();
+    let mut value: Value = serde_json::from_slice(payload).map_err(invalid)?;
+    for from in version.max(1)..T::VERSION {
+        let migrate = migrations
+            .get(from as usize - 1)
+            .ok_or_else(|| SchemaError::Migration {
+                schema: T::SCHEMA,
+                from,
+                reason: "no migration registered".to_string(),
+            })?;
+        value = migrate(value).map_err(|reason| SchemaError::Migration {
+            schema: T::SCHEMA,
+            from,
+            reason,
+        })?;
+    }
+
+    Ok(Decoded {
+        value: serde_json::from_value(value).map_err(invalid)?,
+        version,
+        outdated: true,
+    })
+}
+
+/// Outcome of migrating a column family
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct MigrationStats {
+    /// Records read
+    pub scanned: u64,
+
+    /// Records upgraded to the current version
+    pub migrated: u64,
+
+    /// Records written by a newer release, left as is
+    pub newer: u64,
+
+    /// Records that could not be decoded or migrated, left as is
+    pub failed: u64,
+}
+
+/// Upgrade the outdated records of a column family holding records of `T`, stored as byte
+/// vectors. Only counts them without writing with `dry_run`.
+pub fn migrate_cf<T: Versioned>(
+    db: &RocksDbClient,
+    cf_name: &str,
+    dry_run: bool,
+) -> Result<MigrationStats, SchemaError> {
+    let mut stats = MigrationStats::default();
+    let mut start = Vec::new();
+
+    loop {
+        let entries: Vec<(Box<[u8]>, Vec<u8>)> = db.scan_cf(cf_name, &start, MIGRATION_BATCH)?;
+        let exhausted = entries.len() < MIGRATION_BATCH;
+
+        for (key, stored) in entries {
+            if !start.is_empty() && *key == *start {
+                continue;
+            }
+            start = key.to_vec();
+            stats.scanned += 1;
+
+            match decode_record::<T>(&stored) {
+                Ok(decoded) if decoded.version > T::VERSION => stats.newer += 1,
+                Ok(decoded) if decoded.outdated => {
+                    if !dry_run {
+                        db.put_cf(cf_name, &*key, &encode_record(&decoded.value)?)?;
+                    }
+                    stats.migrated += 1;
+                }
+                Ok(_) => {}
+                Err(SchemaError::TooNew { .. }) => stats.newer += 1,
+                Err(e) => {
+                    warn!("schema: skipping record of {}: {}", cf_name, e);
+                    stats.failed += 1;
+                }
+            }
+        }
+
+        if exhausted {
+            break;
+        }
+    }
+
+    Ok(stats)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Job {
+        name: String,
+        retries: u32,
+        #[serde(default)]
+        queue: Option<String>,
+    }
+
+    impl Versioned for Job {
+        const SCHEMA: &'static str = "job";
+        const VERSION: u32 = 3;
+
+        fn migrations() -> &'static [Migration] {
+            &[rename_attempts, optional_queue]
+        }
+    }
+
+    // Version 2 renamed `attempts` to `retries`
+    fn rename_attempts(mut value: Value) -> Result<Value, String> {
+        let attempts = value
+            .as_object_mut()
+            .and_then(|o| o.remove("attempts"))
+            .ok_or("missing attempts")?;
+        value["retries"] = attempts;
+        Ok(value)
+    }
+
+    // Version 3 made `queue` optional
+    fn optional_queue(mut value: Value) -> Result<Value, String> {
+        if value["queue"] == "" {
+            value["queue"] = Value::Null;
+        }
+        Ok(value)
+    }
+
+    #[test]
+    fn test_current_records() {
+        let job = Job {
+            name: "sync".to_string(),
+            retries: 2,
+            queue: Some("high".to_string()),
+        };
+        let stored = encode_record(&job).unwrap();
+        assert_eq!(split_record(&stored).0, 3);
+
+        let decoded = decode_record::<Job>(&stored).unwrap();
+        assert_eq!(decoded.value, job);
+        assert!(!decoded.outdated);
+    }
+
+    #[test]
+    fn test_legacy_records_are_migrated() {
+        // Written before versioning
+        let decoded = decode_record::<Job>(br#"{"name":"sync","attempts":4,"queue":""}"#).unwrap();
+        assert_eq!(decoded.version, 1);
+        assert!(decoded.outdated);
+        assert_eq!(
+            decoded.value,
+            Job {
+                name: "sync".to_string(),
+                retries: 4,
+                queue: None,
+            }
+        );
+
+        // Written at version 2
+        let mut stored = RECORD_MAGIC.to_vec();
+        stored.extend_from_slice(&2u32.to_le_bytes());
+        stored.extend_from_slice(br#"{"name":"sync","retries":1,"queue":"low"}"#);
+        let decoded = decode_record::<Job>(&stored).unwrap();
+        assert_eq!(decoded.version, 2);
+        assert_eq!(decoded.value.queue.as_deref(), Some("low"));
+
+        // Migrations reject records they cannot upgrade
+        assert!(matches!(
+            decode_record::<Job>(br#"{"name":"sync"}"#),
+            Err(SchemaError::Migration { from: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn test_newer_records() {
+        // Added fields are ignored by older releases
+        let mut stored = RECORD_MAGIC.to_vec();
+        stored.extend_from_slice(&4u32.to_le_bytes());
+        stored.extend_from_slice(br#"{"name":"sync","retries":1,"priority":9}"#);
+        let decoded = decode_record::<Job>(&stored).unwrap();
+        assert_eq!(decoded.version, 4);
+        assert!(!decoded.outdated);
+
+        // Removed fields are not
+        let mut stored = RECORD_MAGIC.to_vec();
+        stored.extend_from_slice(&4u32.to_le_bytes());
+        stored.extend_from_slice(br#"{"name":"sync"}"#);
+        assert!(matches!(
+            decode_record::<Job>(&stored),
+            Err(SchemaError::TooNew { version: 4, .. })
+        ));
+    }
+}
@@ -4,9 +4,10 @@
 //! Storage trait definitions for the store crate.
 
 use crate::error::{
-    DeleteError, GetError, MultiDeleteError, MultiGetError, MultiPutError, PutError, ScanError,
+    CasError, DeleteError, GetError, IncrementError, MultiDeleteError, MultiGetError,
+    MultiPutError, PutError, ScanError,
 };
-use crate::types::{PutInput, ScanInput, ScanOutput};
+use crate::types::{prefix_end, CasOutcome, PutInput, ScanInput, ScanOutput};
 
 /// Key-value store trait
 pub trait KvStore {
@@ -24,6 +25,23 @@ pub trait KvStore {
 pub trait SortedKvStore: KvStore {
     /// Scan key-value pairs
     fn scan(&self, table: &str, input: ScanInput) -> Result<ScanOutput, ScanError>;
+
+    /// Iterate over the key-value pairs with keys in `[start, end)`, an empty `end` iterating to
+    /// the end of the table, scanning a page at a time
+    fn range_iter(&self, table: &str, start: &[u8], end: &[u8], reverse: bool) -> ScanIter<'_, Self>
+    where
+        Self: Sized,
+    {
+        ScanIter::new(self, table, start, end, reverse)
+    }
+
+    /// Iterate over the key-value pairs with keys starting with `prefix`
+    fn prefix_iter(&self, table: &str, prefix: &[u8], reverse: bool) -> ScanIter<'_, Self>
+    where
+        Self: Sized,
+    {
+        ScanIter::new(self, table, prefix, &prefix_end(prefix), reverse)
+    }
 }
 
 /// Batch key-value store trait
@@ -41,7 +59,28 @@ pub trait BatchKvStore: KvStore {
     ) -> Result<Vec<Option<Vec<u8>>>, MultiDeleteError>;
 }
 
+/// Key-value store with atomic read-modify-write operations, safe to use from concurrent callers
+pub trait AtomicKvStore {
+    /// Add `delta` to the counter under a key, starting from zero if absent, and return its new
+    /// value. Counters are stored as little-endian i64 and saturate at the i64 bounds.
+    fn increment(&self, table: &str, key: &[u8], delta: i64) -> Result<i64, IncrementError>;
+
+    /// Replace the value of a key with `new`, deleting it if None, only if its current value is
+    /// `expected`, None meaning the key is absent
+    fn compare_and_set(
+        &self,
+        table: &str,
+        key: &[u8],
+        expected: Option<&[u8]>,
+        new: Option<&[u8]>,
+    ) -> Result<CasOutcome, CasError>;
+}
+
 pub mod memory;
+pub mod quota;
+pub mod scan;
+
+pub use scan::ScanIter;
 
 // Re-export RocksDBStore
 pub use crate::RocksDBStore;
@@ -0,0 +1,249 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+//! Key-value store enforcing a tenant's storage quota
+
+use std::sync::Arc;
+
+use r3e_core::quota::{QuotaEnforcer, QuotaResource};
+
+use crate::error::{CasError, DeleteError, GetError, IncrementError, PutError};
+use crate::storage::{AtomicKvStore, KvStore};
+use crate::types::{CasOutcome, PutInput, COUNTER_SIZE};
+
+/// Key-value store of a tenant, charging the bytes of keys and values to its storage quota
+pub struct QuotaKvStore<S> {
+    inner: S,
+    tenant: String,
+    quota: Arc<dyn QuotaEnforcer>,
+}
+
+impl<S: KvStore> QuotaKvStore<S> {
+    /// Wrap the store of a tenant
+    pub fn new(inner: S, tenant: impl Into<String>, quota: Arc<dyn QuotaEnforcer>) -> Self {
+        Self {
+            inner,
+            tenant: tenant.into(),
+            quota,
+        }
+    }
+
+    /// Tenant charged for the stored bytes
+    pub fn tenant(&self) -> &str {
+        &self.tenant
+    }
+}
+
+impl<S: KvStore> KvStore for QuotaKvStore<S> {
+    fn put(&self, table: &str, input: PutInput) -> Result<(), PutError> {
+        // Overwriting a value only charges the difference
+        let old = match self.inner.get(table, input.key) {
+            Ok(value) => (input.key.len() + value.len()) as u64,
+            Err(_) => 0,
+        };
+        let new = (input.key.len() + input.value.len()) as u64;
+
+        if new > old {
+            self.quota
+                .reserve(&self.tenant, QuotaResource::StorageBytes, new - old)
+                .map_err(|e| PutError::QuotaExceeded(e.to_string()))?;
+        }
+
+        match self.inner.put(table, input) {
+            Ok(()) => {
+                if old > new {
+                    self.quota
+                        .release(&self.tenant, QuotaResource::StorageBytes, old - new);
+                }
+                Ok(())
+            }
+            Err(e) => {
+                if new > old {
+                    self.quota
+                        .release(&self.tenant, QuotaResource::StorageBytes, new - old);
+                }
+                Err(e)
+            }
+        }
+    }
+
+    fn get(&self, table: &str, key: &[u8]) -> Result<Vec<u8>, GetError> {
+        self.inner.get(table, key)
+    }
+
+    fn delete(&self, table: &str, key: &[u8]) -> Result<Option<Vec<u8>>, DeleteError> {
+        let deleted = self.inner.delete(table, key)?;
+        if let Some(value) = &deleted {
+            let size = (key.len() + value.len()) as u64;
+            self.quota
+                .release(&self.tenant, QuotaResource::StorageBytes, size);
+        }
+        Ok(deleted)
+    }
+}
+
+impl<S: KvStore + AtomicKvStore> AtomicKvStore for QuotaKvStore<S> {
+    fn increment(&self, table: &str, key: &[u8], delta: i64) -> Result<i64, IncrementError> {
+        // Creating a counter charges its key and value
+        let size = if self.inner.get(table, key).is_err() {
+            (key.len() + COUNTER_SIZE) as u64
+        } else {
+            0
+        };
+
+        if size > 0 {
+            self.quota
+                .reserve(&self.tenant, QuotaResource::StorageBytes, size)
+                .map_err(|e| IncrementError::QuotaExceeded(e.to_string()))?;
+        }
+
+        let result = self.inner.increment(table, key, delta);
+        if result.is_err() && size > 0 {
+            self.quota
+                .release(&self.tenant, QuotaResource::StorageBytes, size);
+        }
+        result
+    }
+
+    fn compare_and_set(
+        &self,
+        table: &str,
+        key: &[u8],
+        expected: Option<&[u8]>,
+        new: Option<&[u8]>,
+    ) -> Result<CasOutcome, CasError> {
+        // A successful swap replaced exactly the expected value, so only the difference is charged
+        let size = |value: Option<&[u8]>| value.map_or(0, |value| (key.len() + value.len()) as u64);
+        let (old, new_size) = (size(expected), size(new));
+
+        if new_size > old {
+            self.quota
+                .reserve(&self.tenant, QuotaResource::StorageBytes, new_size - old)
+                .map_err(|e| CasError::QuotaExceeded(e.to_string()))?;
+        }
+
+        let result = self.inner.compare_and_set(table, key, expected, new);
+        let released = match &result {
+            Ok(CasOutcome::Set) => old.saturating_sub(new_size),
+            _ => new_size.saturating_sub(old),
+        };
+        if released > 0 {
+            self.quota
+                .release(&self.tenant, QuotaResource::StorageBytes, released);
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mem::MemKvStore;
+    use r3e_core::quota::QuotaError;
+    use std::sync::Mutex;
+
+    struct FixedQuota {
+        limit: u64,
+        used: Mutex<u64>,
+    }
+
+    impl QuotaEnforcer for FixedQuota {
+        fn reserve(
+            &self,
+            tenant: &str,
+            resource: QuotaResource,
+            amount: u64,
+        ) -> Result<(), QuotaError> {
+            let mut used = self.used.lock().unwrap();
+            if *used + amount > self.limit {
+                return Err(QuotaError::Exceeded {
+                    tenant: tenant.to_string(),
+                    resource,
+                    used: *used,
+                    requested: amount,
+                    limit: self.limit,
+                });
+            }
+            *used += amount;
+            Ok(())
+        }
+
+        fn release(&self, _tenant: &str, _resource: QuotaResource, amount: u64) {
+            let mut used = self.used.lock().unwrap();
+            *used = used.saturating_sub(amount);
+        }
+    }
+
+    fn put(store: &QuotaKvStore<MemKvStore>, key: &str, value: &str) -> Result<(), PutError> {
+        store.put(
+            "kv",
+            PutInput {
+                key: key.as_bytes(),
+                value: value.as_bytes(),
+                if_not_exists: false,
+            },
+        )
+    }
+
+    #[test]
+    fn test_quota_kv_store() {
+        let quota = Arc::new(FixedQuota {
+            limit: 16,
+            used: Mutex::new(0),
+        });
+        let store = QuotaKvStore::new(MemKvStore::new(), "tenant", quota.clone());
+
+        put(&store, "k1", "value1").unwrap();
+        assert_eq!(*quota.used.lock().unwrap(), 8);
+
+        // Overwriting with a shorter value gives bytes back
+        put(&store, "k1", "v").unwrap();
+        assert_eq!(*quota.used.lock().unwrap(), 3);
+
+        assert!(matches!(
+            put(&store, "k2", "a value too long"),
+            Err(PutError::QuotaExceeded(_))
+        ));
+        assert_eq!(*quota.used.lock().unwrap(), 3);
+
+        store.delete("kv", b"k1").unwrap();
+        assert_eq!(*quota.used.lock().unwrap(), 0);
+    }
+
+    #[test]
+    fn test_quota_atomic_ops() {
+        let quota = Arc::new(FixedQuota {
+            limit: 16,
+            used: Mutex::new(0),
+        });
+        let store = QuotaKvStore::new(MemKvStore::new(), "tenant", quota.clone());
+
+        // Only creating a counter is charged
+        assert_eq!(store.increment("kv", b"c", 1).unwrap(), 1);
+        assert_eq!(store.increment("kv", b"c", 1).unwrap(), 2);
+        assert_eq!(*quota.used.lock().unwrap(), 9);
+
+        // A conflicting swap gives back what it reserved
+        let outcome = store
+            .compare_and_set("kv", b"k", Some(b"old"), Some(b"value"))
+            .unwrap();
+        assert_eq!(outcome, CasOutcome::Conflict(None));
+        assert_eq!(*quota.used.lock().unwrap(), 9);
+
+        assert!(store
+            .compare_and_set("kv", b"k", None, Some(b"value"))
+            .unwrap()
+            .is_set());
+        assert_eq!(*quota.used.lock().unwrap(), 15);
+        assert!(matches!(
+            store.compare_and_set("kv", b"k", Some(b"value"), Some(b"longer value")),
+            Err(CasError::QuotaExceeded(_))
+        ));
+
+        assert!(store
+            .compare_and_set("kv", b"k", Some(b"value"), None)
+            .unwrap()
+            .is_set());
+        assert_eq!(*quota.used.lock().unwrap(), 9);
+    }
+}
@@ -0,0 +1,88 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+//! Iterators over the key ranges of sorted key-value stores
+
+use crate::error::ScanError;
+use crate::storage::SortedKvStore;
+use crate::types::ScanInput;
+
+/// Number of items scanned per page
+pub const SCAN_PAGE_SIZE: u32 = 256;
+
+/// Iterator over a key range of a table, scanning a page at a time and continuing after the
+/// last key returned, so a range larger than a page is read in full
+pub struct ScanIter<'s, S: ?Sized> {
+    store: &'s S,
+    table: String,
+    start: Vec<u8>,
+    start_exclusive: bool,
+    end: Vec<u8>,
+    reverse: bool,
+    page: std::vec::IntoIter<(Vec<u8>, Vec<u8>)>,
+    done: bool,
+}
+
+impl<'s, S: SortedKvStore + ?Sized> ScanIter<'s, S> {
+    /// Iterate over the keys in `[start, end)` of `table`, an empty `end` iterating to the end
+    pub fn new(store: &'s S, table: &str, start: &[u8], end: &[u8], reverse: bool) -> Self {
+        Self {
+            store,
+            table: table.to_string(),
+            start: start.to_vec(),
+            start_exclusive: false,
+            end: end.to_vec(),
+            reverse,
+            page: Vec::new().into_iter(),
+            done: false,
+        }
+    }
+
+    fn next_page(&mut self) -> Result<(), ScanError> {
+        let output = self.store.scan(
+            &self.table,
+            ScanInput {
+                start_key: &self.start,
+                start_exclusive: self.start_exclusive,
+                end_key: &self.end,
+                end_inclusive: false,
+                reverse: self.reverse,
+                max_count: SCAN_PAGE_SIZE,
+            },
+        )?;
+
+        // Continue after the last key, which bounds the end of the range when scanning backwards
+        match output.kvs.last() {
+            Some((key, _)) if output.has_more => {
+                if self.reverse {
+                    self.end = key.clone();
+                } else {
+                    self.start = key.clone();
+                    self.start_exclusive = true;
+                }
+            }
+            _ => self.done = true,
+        }
+        self.page = output.kvs.into_iter();
+        Ok(())
+    }
+}
+
+impl<S: SortedKvStore + ?Sized> Iterator for ScanIter<'_, S> {
+    type Item = Result<(Vec<u8>, Vec<u8>), ScanError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(kv) = self.page.next() {
+                return Some(Ok(kv));
+            }
+            if self.done {
+                return None;
+            }
+            if let Err(e) = self.next_page() {
+                self.done = true;
+                return Some(Err(e));
+            }
+        }
+    }
+}
@@ -27,7 +27,7 @@ pub struct PutInput<'k, 'v> {
 /// Input for scan operations
 #[derive(Debug, Clone)]
 pub struct ScanInput<'k, 'v> {
-    /// Start key (empty means from the start of the table)
+    /// Start key (empty means from the start of the table, or after the empty key if excluded)
     pub start_key: &'k [u8],
 
     /// If true, the start key is excluded, otherwise included
@@ -39,6 +39,9 @@ pub struct ScanInput<'k, 'v> {
     /// If true, the end key is included, otherwise excluded
     pub end_inclusive: bool,
 
+    /// If true, items are returned in descending key order, starting from the end key
+    pub reverse: bool,
+
     /// Maximum number of items to return (0 means 100)
     pub max_count: u32,
 }
@@ -54,6 +57,19 @@ impl<'k, 'v> ScanInput<'k, 'v> {
     }
 }
 
+/// End key of a scan over the keys starting with `prefix`: the smallest key greater than all
+/// of them, or empty (the end of the table) if there is none
+pub fn prefix_end(prefix: &[u8]) -> Vec<u8> {
+    let mut end = prefix.to_vec();
+    while let Some(last) = end.pop() {
+        if last < u8::MAX {
+            end.push(last + 1);
+            break;
+        }
+    }
+    end
+}
+
 /// Output for scan operations
 #[derive(Debug, Clone)]
 pub struct ScanOutput {
@@ -63,3 +79,33 @@ pub struct ScanOutput {
     /// True if there are more items to scan
     pub has_more: bool,
 }
+
+/// Size in bytes of a stored counter
+pub const COUNTER_SIZE: usize = 8;
+
+/// Stored bytes of a counter, a little-endian i64
+pub fn encode_counter(value: i64) -> [u8; COUNTER_SIZE] {
+    value.to_le_bytes()
+}
+
+/// Value of a stored counter, None if the bytes are not a counter
+pub fn decode_counter(bytes: &[u8]) -> Option<i64> {
+    bytes.try_into().ok().map(i64::from_le_bytes)
+}
+
+/// Outcome of a compare-and-set
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CasOutcome {
+    /// The value was as expected and has been replaced
+    Set,
+
+    /// The value was not as expected and is unchanged, with the value found (None if absent)
+    Conflict(Option<Vec<u8>>),
+}
+
+impl CasOutcome {
+    /// Whether the value has been replaced
+    pub fn is_set(&self) -> bool {
+        matches!(self, CasOutcome::Set)
+    }
+}
@@ -0,0 +1,89 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+use std::path::Path;
+use std::sync::Arc;
+
+use r3e_store::rocksdb::{RocksDbClient, RocksDbConfig};
+use r3e_store::{AtomicKvStore, CasOutcome, IncrementError};
+
+fn open_db(path: &Path) -> RocksDbClient {
+    let db = RocksDbClient::new(RocksDbConfig {
+        path: path.to_string_lossy().into_owned(),
+        default_cf_names: vec![
+            "default".to_string(),
+            "counters".to_string(),
+            "locks".to_string(),
+        ],
+        ..Default::default()
+    });
+    db.open().unwrap();
+    db
+}
+
+#[test]
+fn test_rocksdb_counters() {
+    let dir = tempfile::tempdir().unwrap();
+    let db = Arc::new(open_db(dir.path()));
+
+    assert_eq!(db.increment("counters", b"hits", 5).unwrap(), 5);
+    db.add_cf("counters", b"hits", 10).unwrap();
+    assert_eq!(db.increment("counters", b"hits", -3).unwrap(), 12);
+
+    // Concurrent increments each get a distinct value
+    let handles: Vec<_> = (0..4)
+        .map(|_| {
+            let db = db.clone();
+            std::thread::spawn(move || {
+                (0..50)
+                    .map(|_| db.increment("counters", b"nonce", 1).unwrap())
+                    .collect::<Vec<_>>()
+            })
+        })
+        .collect();
+    let mut values: Vec<i64> = handles
+        .into_iter()
+        .flat_map(|handle| handle.join().unwrap())
+        .collect();
+    values.sort();
+    values.dedup();
+    assert_eq!(values, (1..=200).collect::<Vec<_>>());
+
+    db.compare_and_set("counters", b"name", None, Some(b"text"))
+        .unwrap();
+    assert!(matches!(
+        db.increment("counters", b"name", 1),
+        Err(IncrementError::NotACounter)
+    ));
+}
+
+#[test]
+fn test_rocksdb_compare_and_set() {
+    let dir = tempfile::tempdir().unwrap();
+    let db = open_db(dir.path());
+
+    assert!(db
+        .compare_and_set("locks", b"job", None, Some(b"worker-1"))
+        .unwrap()
+        .is_set());
+    assert_eq!(
+        db.compare_and_set("locks", b"job", None, Some(b"worker-2"))
+            .unwrap(),
+        CasOutcome::Conflict(Some(b"worker-1".to_vec()))
+    );
+    assert!(db
+        .compare_and_set("locks", b"job", Some(b"worker-1"), None)
+        .unwrap()
+        .is_set());
+    assert_eq!(
+        db.compare_and_set("locks", b"job", Some(b"worker-1"), None)
+            .unwrap(),
+        CasOutcome::Conflict(None)
+    );
+
+    // Counters survive a reopen, the merge operator being registered again
+    db.increment("locks", b"generation", 7).unwrap();
+    drop(db);
+    let db = open_db(dir.path());
+    assert_eq!(db.increment("locks", b"generation", 1).unwrap(), 8);
+}
@@ -0,0 +1,125 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+use std::path::Path;
+use std::sync::Arc;
+
+use r3e_store::backup::{restore, BackupConfig, BackupEngine, StaticBackupKey};
+use r3e_store::blob::{BlobStore, MemoryBlobStore};
+use r3e_store::rocksdb::{RocksDbClient, RocksDbConfig};
+
+const CF: &str = "functions";
+
+fn open_db(path: &Path) -> RocksDbClient {
+    let db = RocksDbClient::new(RocksDbConfig {
+        path: path.to_string_lossy().into_owned(),
+        default_cf_names: vec![CF.to_string()],
+        ..Default::default()
+    });
+    db.open().unwrap();
+    db
+}
+
+fn write_records(db: &RocksDbClient, range: std::ops::Range<u32>) {
+    for i in range {
+        db.put_cf(
+            CF,
+            format!("fn-{:04}", i),
+            &format!("code of function {}", i),
+        )
+        .unwrap();
+    }
+}
+
+#[tokio::test]
+async fn test_incremental_backup_and_restore() {
+    let dir = tempfile::tempdir().unwrap();
+    let db = Arc::new(open_db(&dir.path().join("db")));
+    let blobs: Arc<dyn BlobStore> = Arc::new(MemoryBlobStore::new());
+    let key = Arc::new(StaticBackupKey::new([42u8; 32]));
+    let config = BackupConfig {
+        staging_dir: dir.path().join("staging"),
+        catalog_path: dir.path().join("catalog.json"),
+        full_every: 4,
+    };
+    let engine = BackupEngine::new(db.clone(), blobs.clone(), key.clone(), config.clone()).unwrap();
+
+    // Full backup
+    write_records(&db, 0..100);
+    let full = engine.create_backup().await.unwrap();
+    assert!(!full.incremental);
+    assert_eq!(full.uploaded_files, full.files);
+
+    // Incremental backup, the SST files of the first backup are reused
+    write_records(&db, 100..150);
+    db.delete_cf(CF, "fn-0000").unwrap();
+    let incremental = engine.create_backup().await.unwrap();
+    assert!(incremental.incremental);
+    assert!(incremental.uploaded_files < incremental.files);
+
+    // The catalog survives a restart
+    let engine = BackupEngine::new(db.clone(), blobs.clone(), key.clone(), config).unwrap();
+    assert_eq!(engine.list_backups().await.len(), 2);
+
+    // Restore the incremental backup and open it
+    let target = dir.path().join("restored");
+    let manifest = engine.restore_latest(&target).await.unwrap();
+    assert_eq!(manifest.id, incremental.id);
+    assert_eq!(manifest.parent.as_deref(), Some(full.id.as_str()));
+    let restored = open_db(&target);
+    assert_eq!(restored.get_cf::<_, String>(CF, "fn-0000").unwrap(), None);
+    for i in 1..150 {
+        assert_eq!(
+            restored
+                .get_cf::<_, String>(CF, format!("fn-{:04}", i))
+                .unwrap(),
+            Some(format!("code of function {}", i))
+        );
+    }
+
+    // Restore the full backup from the manifest reference alone
+    let target = dir.path().join("restored-full");
+    restore(blobs.as_ref(), key.as_ref(), &full.manifest, &target)
+        .await
+        .unwrap();
+    let restored = open_db(&target);
+    assert!(restored
+        .get_cf::<_, String>(CF, "fn-0000")
+        .unwrap()
+        .is_some());
+    assert_eq!(restored.get_cf::<_, String>(CF, "fn-0100").unwrap(), None);
+}
+
+#[tokio::test]
+async fn test_restore_requires_key() {
+    let dir = tempfile::tempdir().unwrap();
+    let db = Arc::new(open_db(&dir.path().join("db")));
+    write_records(&db, 0..10);
+    let blobs: Arc<dyn BlobStore> = Arc::new(MemoryBlobStore::new());
+    let engine = BackupEngine::new(
+        db,
+        blobs.clone(),
+        Arc::new(StaticBackupKey::new([42u8; 32])),
+        BackupConfig {
+            staging_dir: dir.path().join("staging"),
+            catalog_path: dir.path().join("catalog.json"),
+            full_every: 4,
+        },
+    )
+    .unwrap();
+    let backup = engine.create_backup().await.unwrap();
+
+    // A wrong key leaves no partial restore behind
+    let target = dir.path().join("restored");
+    let wrong_key = StaticBackupKey::new([7u8; 32]);
+    assert!(
+        restore(blobs.as_ref(), &wrong_key, &backup.manifest, &target)
+            .await
+            .is_err()
+    );
+    assert!(!target.exists());
+
+    // Restores never overwrite an existing directory
+    std::fs::create_dir(&target).unwrap();
+    assert!(engine.restore(&backup.manifest, &target).await.is_err());
+}
@@ -0,0 +1,102 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use r3e_store::rocksdb::{RocksDbClient, RocksDbConfig};
+use r3e_store::{check_fence, KvLockService, LeaseHolder, LockError, LockService};
+
+#[tokio::test]
+async fn test_kv_lock_leases() {
+    let dir = tempfile::tempdir().unwrap();
+    let locks = KvLockService::open(dir.path()).unwrap();
+    let ttl = Duration::from_secs(30);
+
+    let lease = locks
+        .try_acquire("billing", "node-1", ttl)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(lease.token, 1);
+    assert!(locks
+        .try_acquire("billing", "node-2", ttl)
+        .await
+        .unwrap()
+        .is_none());
+
+    // The holder acquiring again keeps its token
+    let lease = locks
+        .try_acquire("billing", "node-1", ttl)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(lease.token, 1);
+    let lease = locks.renew(&lease, ttl).await.unwrap();
+
+    // A released lock is acquired by another owner with a greater token
+    locks.release(&lease).await.unwrap();
+    let next = locks
+        .try_acquire("billing", "node-2", ttl)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(next.token, 2);
+    assert!(matches!(
+        locks.renew(&lease, ttl).await,
+        Err(LockError::Lost(_))
+    ));
+
+    // An expired lease is taken over
+    let short = locks
+        .try_acquire("bridge", "node-1", Duration::from_millis(20))
+        .await
+        .unwrap()
+        .unwrap();
+    tokio::time::sleep(Duration::from_millis(40)).await;
+    let taken = locks
+        .try_acquire("bridge", "node-2", ttl)
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(taken.token > short.token);
+    assert!(matches!(
+        locks.renew(&short, ttl).await,
+        Err(LockError::Lost(_))
+    ));
+}
+
+#[tokio::test]
+async fn test_lease_holder() {
+    let dir = tempfile::tempdir().unwrap();
+    let locks: Arc<dyn LockService> = Arc::new(KvLockService::open(dir.path()).unwrap());
+
+    let first = LeaseHolder::new(locks.clone(), "feeds", Duration::from_secs(30));
+    let second = LeaseHolder::new(locks.clone(), "feeds", Duration::from_secs(30));
+    let lease = first.hold().await.unwrap();
+    assert!(second.hold().await.is_none());
+    assert_eq!(first.hold().await.unwrap().token, lease.token);
+
+    first.release().await;
+    assert!(second.hold().await.unwrap().token > lease.token);
+    assert!(first.hold().await.is_none());
+}
+
+#[test]
+fn test_fencing() {
+    let dir = tempfile::tempdir().unwrap();
+    let db = RocksDbClient::new(RocksDbConfig {
+        path: dir.path().to_string_lossy().into_owned(),
+        ..Default::default()
+    });
+    db.open().unwrap();
+
+    check_fence(&db, "feeds/NEO-USD", 3).unwrap();
+    check_fence(&db, "feeds/NEO-USD", 3).unwrap();
+    check_fence(&db, "feeds/NEO-USD", 5).unwrap();
+    assert!(matches!(
+        check_fence(&db, "feeds/NEO-USD", 4),
+        Err(LockError::Fenced { current: 5, .. })
+    ));
+    check_fence(&db, "feeds/GAS-USD", 1).unwrap();
+}
@@ -0,0 +1,87 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use r3e_store::rocksdb::{RocksDbClient, RocksDbConfig};
+use r3e_store::schema::{split_record, RECORD_MAGIC};
+use r3e_store::{decode_record, encode_record, migrate_cf, Migration, MigrationStats, Versioned};
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct Account {
+    owner: String,
+    balance: u64,
+}
+
+impl Versioned for Account {
+    const SCHEMA: &'static str = "account";
+    const VERSION: u32 = 2;
+
+    fn migrations() -> &'static [Migration] {
+        &[balance_to_integer]
+    }
+}
+
+// Version 2 stores balances as integers instead of strings
+fn balance_to_integer(mut value: Value) -> Result<Value, String> {
+    let balance = value["balance"]
+        .as_str()
+        .and_then(|b| b.parse::<u64>().ok())
+        .ok_or("invalid balance")?;
+    value["balance"] = balance.into();
+    Ok(value)
+}
+
+#[test]
+fn test_migrate_cf() {
+    let dir = tempfile::tempdir().unwrap();
+    let db = RocksDbClient::new(RocksDbConfig {
+        path: dir.path().to_string_lossy().into_owned(),
+        default_cf_names: vec!["default".to_string(), "accounts".to_string()],
+        ..Default::default()
+    });
+    db.open().unwrap();
+
+    // Legacy records, a current one, one from a newer release and a damaged one
+    for i in 0..300u64 {
+        let legacy = format!(r#"{{"owner":"user-{}","balance":"{}"}}"#, i, i * 10);
+        db.put_cf("accounts", format!("legacy-{:03}", i), &legacy.into_bytes())
+            .unwrap();
+    }
+    let current = Account {
+        owner: "current".to_string(),
+        balance: 5,
+    };
+    db.put_cf("accounts", "current", &encode_record(&current).unwrap())
+        .unwrap();
+    let mut newer = RECORD_MAGIC.to_vec();
+    newer.extend_from_slice(&3u32.to_le_bytes());
+    newer.extend_from_slice(br#"{"owner":"newer"}"#);
+    db.put_cf("accounts", "newer", &newer).unwrap();
+    db.put_cf("accounts", "damaged", &b"{".to_vec()).unwrap();
+
+    let dry_run = migrate_cf::<Account>(&db, "accounts", true).unwrap();
+    let expected = MigrationStats {
+        scanned: 303,
+        migrated: 300,
+        newer: 1,
+        failed: 1,
+    };
+    assert_eq!(dry_run, expected);
+
+    assert_eq!(
+        migrate_cf::<Account>(&db, "accounts", false).unwrap(),
+        expected
+    );
+    let stored: Vec<u8> = db.get_cf("accounts", "legacy-042").unwrap().unwrap();
+    assert_eq!(split_record(&stored).0, 2);
+    let decoded = decode_record::<Account>(&stored).unwrap();
+    assert_eq!(decoded.value.balance, 420);
+    assert!(!decoded.outdated);
+
+    // Nothing is left to migrate
+    let again = migrate_cf::<Account>(&db, "accounts", false).unwrap();
+    assert_eq!(again.migrated, 0);
+    assert_eq!(again.scanned, 303);
+}
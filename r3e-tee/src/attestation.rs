@@ -76,6 +76,12 @@ impl AttestationServiceImpl {
             );
         }
 
+        // Nitro reports carry their attestation document, checked without the enclave
+        verifiers.insert(
+            TeePlatform::Nitro,
+            Arc::new(NitroAttestationVerifier::new()) as Arc<dyn AttestationVerifier>,
+        );
+
         // Always register simulated verifier
         verifiers.insert(
             TeePlatform::Simulated,
@@ -250,6 +256,76 @@ impl AttestationVerifier for SimulatedAttestationVerifier {
     }
 }
 
+/// AWS Nitro attestation verifier, checking a report against the attestation document it
+/// carries. The reports are generated in the enclave by the Nitro provider.
+pub struct NitroAttestationVerifier;
+
+impl NitroAttestationVerifier {
+    /// Create a new Nitro attestation verifier
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+#[async_trait::async_trait]
+impl AttestationVerifier for NitroAttestationVerifier {
+    async fn generate_attestation(
+        &self,
+        _options: &AttestationOptions,
+    ) -> Result<AttestationReport, TeeError> {
+        Err(TeeError::Attestation(
+            "Nitro attestations are generated in the enclave".to_string(),
+        ))
+    }
+
+    async fn verify_attestation(
+        &self,
+        attestation: &AttestationReport,
+    ) -> Result<AttestationVerificationResult, TeeError> {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+
+        let mut details = HashMap::new();
+        details.insert(
+            "verifier".to_string(),
+            "NitroAttestationVerifier".to_string(),
+        );
+        details.insert("platform".to_string(), "Nitro".to_string());
+
+        let document = &attestation.platform_data["document"];
+        let pcr = |name: &str| document["pcrs"][name].as_str().unwrap_or_default();
+        let error = if attestation.platform != TeePlatform::Nitro {
+            Some("attestation is not for Nitro platform".to_string())
+        } else if document["module_id"].is_null() {
+            Some("attestation document is missing".to_string())
+        } else if pcr("pcr0") != attestation.code_hash {
+            Some("code hash does not match PCR0 of the attestation document".to_string())
+        } else if pcr("pcr8") != attestation.signer_hash {
+            Some("signer hash does not match PCR8 of the attestation document".to_string())
+        } else if attestation.signature.is_empty() {
+            Some("attestation document is not signed".to_string())
+        } else {
+            None
+        };
+
+        if let Some(module_id) = document["module_id"].as_str() {
+            details.insert("module_id".to_string(), module_id.to_string());
+        }
+        if let Some(error) = &error {
+            details.insert("error".to_string(), error.clone());
+        }
+
+        Ok(AttestationVerificationResult {
+            is_valid: error.is_none(),
+            timestamp: now,
+            details,
+            error,
+        })
+    }
+}
+
 #[cfg(feature = "sgx")]
 pub struct SgxAttestationVerifier;
 
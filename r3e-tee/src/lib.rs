@@ -1,6 +1,7 @@
 // Copyright @ 2023 - 2024, R3E Network
 // All Rights Reserved
 
+use r3e_core::{CodedError, ErrorCode};
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 use thiserror::Error;
@@ -8,7 +9,9 @@ use thiserror::Error;
 pub mod attestation;
 pub mod enclave;
 pub mod key_management;
+pub mod measurements;
 pub mod provider;
+pub mod sealing;
 pub mod service;
 pub mod types;
 
@@ -40,6 +43,21 @@ pub enum TeeError {
     Internal(String),
 }
 
+impl CodedError for TeeError {
+    fn code(&self) -> ErrorCode {
+        match self {
+            TeeError::Execution(_) => ErrorCode::ExecutionFailed,
+            TeeError::Validation(_) => ErrorCode::Validation,
+            TeeError::Initialization(_) | TeeError::Enclave(_) | TeeError::Provider(_) => {
+                ErrorCode::Unavailable
+            }
+            TeeError::Attestation(_) | TeeError::KeyManagement(_) | TeeError::Internal(_) => {
+                ErrorCode::Internal
+            }
+        }
+    }
+}
+
 /// TEE platform type
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum TeePlatform {
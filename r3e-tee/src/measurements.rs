@@ -0,0 +1,306 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+use crate::attestation::{AttestationService, AttestationVerificationResult};
+use crate::{AttestationReport, TeePlatform, TeeSecurityLevel};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+/// Measurements an enclave of a platform must report to be trusted
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExpectedMeasurements {
+    /// TEE platform
+    pub platform: TeePlatform,
+
+    /// Accepted code measurements (MRENCLAVE for SGX, PCR0 for Nitro, launch digest for SEV)
+    pub code_hashes: Vec<String>,
+
+    /// Accepted signer measurements (MRSIGNER for SGX, PCR8 for Nitro), any signer if empty
+    #[serde(default)]
+    pub signer_hashes: Vec<String>,
+
+    /// Accepted product IDs, any product if empty
+    #[serde(default)]
+    pub product_ids: Vec<u16>,
+
+    /// Lowest accepted security version number
+    #[serde(default)]
+    pub min_security_version: u16,
+
+    /// Whether enclaves in debug mode are trusted, for development only
+    #[serde(default)]
+    pub allow_debug: bool,
+}
+
+/// Measurements the enclaves of the platform are expected to report, per TEE platform
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MeasurementPolicy {
+    pub measurements: Vec<ExpectedMeasurements>,
+}
+
+impl MeasurementPolicy {
+    /// Expected measurements of a platform, none if its enclaves are not trusted
+    pub fn expected(&self, platform: TeePlatform) -> Option<&ExpectedMeasurements> {
+        self.measurements.iter().find(|m| m.platform == platform)
+    }
+
+    /// Reasons the claims do not match the expected measurements, empty if they do
+    pub fn check(&self, claims: &AttestationClaims) -> Vec<String> {
+        let Some(expected) = self.expected(claims.platform) else {
+            return vec![format!(
+                "no measurements are published for platform {:?}",
+                claims.platform
+            )];
+        };
+
+        let mut reasons = Vec::new();
+        if !contains_measurement(&expected.code_hashes, &claims.code_hash) {
+            reasons.push(format!("unexpected code measurement {}", claims.code_hash));
+        }
+        if !expected.signer_hashes.is_empty()
+            && !contains_measurement(&expected.signer_hashes, &claims.signer_hash)
+        {
+            reasons.push(format!(
+                "unexpected signer measurement {}",
+                claims.signer_hash
+            ));
+        }
+        if !expected.product_ids.is_empty() && !expected.product_ids.contains(&claims.product_id) {
+            reasons.push(format!("unexpected product ID {}", claims.product_id));
+        }
+        if claims.security_version < expected.min_security_version {
+            reasons.push(format!(
+                "security version {} is below {}",
+                claims.security_version, expected.min_security_version
+            ));
+        }
+        if claims.debug && !expected.allow_debug {
+            reasons.push("enclave runs in debug mode".to_string());
+        }
+        reasons
+    }
+}
+
+/// Measurements are hex, compared regardless of case and `0x` prefix
+fn normalize_measurement(hash: &str) -> String {
+    hash.trim_start_matches("0x").to_ascii_lowercase()
+}
+
+fn contains_measurement(accepted: &[String], hash: &str) -> bool {
+    let hash = normalize_measurement(hash);
+    accepted.iter().any(|a| normalize_measurement(a) == hash)
+}
+
+/// Claims of an attestation report
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AttestationClaims {
+    /// TEE platform
+    pub platform: TeePlatform,
+
+    /// TEE security level
+    pub security_level: TeeSecurityLevel,
+
+    /// Measurement of the enclave code
+    pub code_hash: String,
+
+    /// Measurement of the enclave signer
+    pub signer_hash: String,
+
+    /// Product ID
+    pub product_id: u16,
+
+    /// Security version number
+    pub security_version: u16,
+
+    /// Whether the enclave runs in debug mode
+    pub debug: bool,
+
+    /// Hex nonce the report was generated for, if any
+    pub nonce: Option<String>,
+
+    /// Hex user data bound to the report, if any
+    pub user_data: Option<String>,
+}
+
+impl From<&AttestationReport> for AttestationClaims {
+    fn from(report: &AttestationReport) -> Self {
+        // Nitro reports keep the attestation document, other platforms their own fields
+        let field = |name: &str| {
+            report
+                .platform_data
+                .get(name)
+                .or_else(|| report.platform_data.get("document")?.get(name))
+                .and_then(|value| value.as_str())
+                .map(str::to_string)
+        };
+
+        Self {
+            platform: report.platform,
+            security_level: report.security_level,
+            code_hash: report.code_hash.clone(),
+            signer_hash: report.signer_hash.clone(),
+            product_id: report.product_id,
+            security_version: report.security_version,
+            debug: report.security_level == TeeSecurityLevel::Debug,
+            nonce: field("nonce"),
+            user_data: field("user_data"),
+        }
+    }
+}
+
+/// Trust verdict of an attestation report
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AttestationVerdict {
+    /// Whether the report is genuine and matches the expected measurements
+    pub trusted: bool,
+
+    /// Claims of the report
+    pub claims: AttestationClaims,
+
+    /// Reasons the report is not trusted, empty if it is
+    pub reasons: Vec<String>,
+
+    /// Result of the platform verification of the report, none if no verifier could check it
+    pub verification: Option<AttestationVerificationResult>,
+}
+
+/// Appraises attestation reports: checks them with the verifier of their platform, then their
+/// claims against the measurement policy
+pub struct AttestationAppraiser {
+    service: Arc<dyn AttestationService>,
+    policy: MeasurementPolicy,
+}
+
+impl AttestationAppraiser {
+    /// Create a new attestation appraiser
+    pub fn new(service: Arc<dyn AttestationService>, policy: MeasurementPolicy) -> Self {
+        Self { service, policy }
+    }
+
+    /// Measurement policy reports are appraised against
+    pub fn policy(&self) -> &MeasurementPolicy {
+        &self.policy
+    }
+
+    /// Appraise a report, requiring it to be generated for `nonce` if one is given
+    pub async fn appraise(
+        &self,
+        report: &AttestationReport,
+        nonce: Option<&str>,
+    ) -> AttestationVerdict {
+        let claims = AttestationClaims::from(report);
+        let mut reasons = Vec::new();
+
+        let verification = match self.service.verify_attestation(report).await {
+            Ok(result) => {
+                if !result.is_valid {
+                    reasons.push(format!(
+                        "report failed verification: {}",
+                        result.error.as_deref().unwrap_or("invalid report")
+                    ));
+                }
+                Some(result)
+            }
+            Err(e) => {
+                reasons.push(e.to_string());
+                None
+            }
+        };
+
+        reasons.extend(self.policy.check(&claims));
+
+        if let Some(nonce) = nonce {
+            let matches = claims
+                .nonce
+                .as_deref()
+                .is_some_and(|n| normalize_measurement(n) == normalize_measurement(nonce));
+            if !matches {
+                reasons.push("report was not generated for the nonce".to_string());
+            }
+        }
+
+        AttestationVerdict {
+            trusted: reasons.is_empty(),
+            claims,
+            reasons,
+            verification,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::attestation::AttestationServiceImpl;
+
+    fn report(platform: TeePlatform, security_level: TeeSecurityLevel) -> AttestationReport {
+        AttestationReport {
+            platform,
+            security_level,
+            code_hash: "ABCD".to_string(),
+            signer_hash: "0x1234".to_string(),
+            product_id: 1,
+            security_version: 2,
+            attributes: 0,
+            extended_product_id: Vec::new(),
+            signature: vec![1; 64],
+            platform_data: serde_json::json!({ "nonce": "00ff" }),
+        }
+    }
+
+    fn policy(allow_debug: bool) -> MeasurementPolicy {
+        MeasurementPolicy {
+            measurements: vec![ExpectedMeasurements {
+                platform: TeePlatform::Simulated,
+                code_hashes: vec!["0xabcd".to_string()],
+                signer_hashes: vec!["1234".to_string()],
+                product_ids: Vec::new(),
+                min_security_version: 2,
+                allow_debug,
+            }],
+        }
+    }
+
+    #[test]
+    fn test_measurement_policy() {
+        let claims = AttestationClaims::from(&report(
+            TeePlatform::Simulated,
+            TeeSecurityLevel::Production,
+        ));
+        assert!(policy(false).check(&claims).is_empty());
+
+        let mut outdated = claims.clone();
+        outdated.security_version = 1;
+        outdated.code_hash = "ef01".to_string();
+        assert_eq!(policy(false).check(&outdated).len(), 2);
+
+        let debug =
+            AttestationClaims::from(&report(TeePlatform::Simulated, TeeSecurityLevel::Debug));
+        assert_eq!(
+            policy(false).check(&debug),
+            vec!["enclave runs in debug mode".to_string()]
+        );
+        assert!(policy(true).check(&debug).is_empty());
+
+        let sgx = AttestationClaims::from(&report(TeePlatform::Sgx, TeeSecurityLevel::Production));
+        assert_eq!(policy(false).check(&sgx).len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_appraise() {
+        let appraiser =
+            AttestationAppraiser::new(Arc::new(AttestationServiceImpl::new()), policy(true));
+        let report = report(TeePlatform::Simulated, TeeSecurityLevel::Debug);
+
+        let verdict = appraiser.appraise(&report, Some("00FF")).await;
+        assert!(verdict.trusted, "{:?}", verdict.reasons);
+        assert_eq!(verdict.claims.nonce.as_deref(), Some("00ff"));
+
+        let verdict = appraiser.appraise(&report, Some("0000")).await;
+        assert!(!verdict.trusted);
+        assert_eq!(
+            verdict.reasons,
+            vec!["report was not generated for the nonce".to_string()]
+        );
+    }
+}
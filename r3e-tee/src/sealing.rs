@@ -0,0 +1,686 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+//! Sealed storage for the private state of functions running in TEE mode.
+//!
+//! Values are encrypted with a key derived from the enclave identity, so a sealed value
+//! can only be read back by an enclave with the same measurement (or, with
+//! [`SealPolicy::MrSigner`], the same signer). SGX enclaves use the platform sealing key;
+//! the simulated platform wraps values with AES-256-GCM under a key derived from a
+//! platform secret and the enclave measurement.
+
+use crate::{AttestationReport, TeeError, TeePlatform};
+use aes_gcm::aead::{Aead, KeyInit, Payload};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+
+/// Version of the sealed blob format
+pub const SEALED_BLOB_VERSION: u8 = 1;
+
+/// Maximum size of a sealed value (1 MiB)
+pub const MAX_SEALED_VALUE_SIZE: usize = 1024 * 1024;
+
+/// Which part of the enclave identity the sealing key is bound to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SealPolicy {
+    /// Only an enclave with the same measurement (MRENCLAVE) can unseal
+    #[default]
+    MrEnclave,
+
+    /// Any enclave from the same signer (MRSIGNER) can unseal, so state survives upgrades
+    MrSigner,
+}
+
+/// Identity of the enclave values are sealed to
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EnclaveIdentity {
+    /// Platform
+    pub platform: TeePlatform,
+
+    /// Enclave measurement (MRENCLAVE)
+    pub code_hash: String,
+
+    /// Enclave signer (MRSIGNER)
+    pub signer_hash: String,
+}
+
+impl EnclaveIdentity {
+    /// Identity reported by an attestation
+    pub fn from_report(report: &AttestationReport) -> Self {
+        Self {
+            platform: report.platform,
+            code_hash: report.code_hash.clone(),
+            signer_hash: report.signer_hash.clone(),
+        }
+    }
+
+    /// Identity of a simulated enclave running `code`
+    pub fn simulated(code: &[u8], signer: &str) -> Self {
+        Self {
+            platform: TeePlatform::Simulated,
+            code_hash: hex::encode(Sha256::digest(code)),
+            signer_hash: signer.to_string(),
+        }
+    }
+
+    /// Identity component the sealing key is bound to under `policy`
+    pub fn measurement(&self, policy: SealPolicy) -> &str {
+        match policy {
+            SealPolicy::MrEnclave => &self.code_hash,
+            SealPolicy::MrSigner => &self.signer_hash,
+        }
+    }
+
+    /// Public identifier of the sealing key under `policy`, recorded in sealed blobs
+    pub fn key_id(&self, policy: SealPolicy) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(format!("{:?}", self.platform).as_bytes());
+        hasher.update([0]);
+        hasher.update(format!("{:?}", policy).as_bytes());
+        hasher.update([0]);
+        hasher.update(self.measurement(policy).as_bytes());
+        hex::encode(&hasher.finalize()[..16])
+    }
+}
+
+/// Sealed value
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SealedBlob {
+    /// Format version
+    pub version: u8,
+
+    /// Platform the value was sealed on
+    pub platform: TeePlatform,
+
+    /// Policy the sealing key was derived with
+    pub policy: SealPolicy,
+
+    /// Identifier of the sealing key
+    pub key_id: String,
+
+    /// Nonce (empty for platforms that embed it in the ciphertext)
+    pub nonce: Vec<u8>,
+
+    /// Ciphertext
+    pub ciphertext: Vec<u8>,
+}
+
+/// Seals values to the identity of the current enclave
+pub trait Sealer: Send + Sync {
+    /// Identity values are sealed to
+    fn identity(&self) -> &EnclaveIdentity;
+
+    /// Seal `plaintext`, binding it to `aad`
+    fn seal(
+        &self,
+        policy: SealPolicy,
+        aad: &[u8],
+        plaintext: &[u8],
+    ) -> Result<SealedBlob, TeeError>;
+
+    /// Unseal a blob sealed with the same `aad`
+    fn unseal(&self, aad: &[u8], blob: &SealedBlob) -> Result<Vec<u8>, TeeError>;
+}
+
+/// Check that `blob` was sealed to the identity of this enclave
+fn check_blob(identity: &EnclaveIdentity, blob: &SealedBlob) -> Result<(), TeeError> {
+    if blob.version != SEALED_BLOB_VERSION {
+        return Err(TeeError::Validation(format!(
+            "Unsupported sealed blob version: {}",
+            blob.version
+        )));
+    }
+
+    if blob.platform != identity.platform || blob.key_id != identity.key_id(blob.policy) {
+        return Err(TeeError::KeyManagement(
+            "Sealed blob belongs to a different enclave".to_string(),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Sealer of the simulated platform, wrapping values with AES-256-GCM
+pub struct SimulatedSealer {
+    identity: EnclaveIdentity,
+    platform_secret: Vec<u8>,
+}
+
+impl SimulatedSealer {
+    /// Create a sealer for `identity`, deriving keys from `platform_secret`
+    ///
+    /// The platform secret stands in for the CPU's fused sealing secret, so it must be
+    /// kept stable for sealed values to stay readable.
+    pub fn new(identity: EnclaveIdentity, platform_secret: &[u8]) -> Result<Self, TeeError> {
+        if platform_secret.is_empty() {
+            return Err(TeeError::Initialization(
+                "Platform secret must not be empty".to_string(),
+            ));
+        }
+
+        Ok(Self {
+            identity,
+            platform_secret: platform_secret.to_vec(),
+        })
+    }
+
+    fn derive_key(&self, policy: SealPolicy) -> Result<Aes256Gcm, TeeError> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(&self.platform_secret)
+            .map_err(|e| TeeError::KeyManagement(format!("Failed to derive key: {}", e)))?;
+        mac.update(b"r3e-seal");
+        mac.update(self.identity.key_id(policy).as_bytes());
+        let key = mac.finalize().into_bytes();
+
+        Ok(Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key)))
+    }
+}
+
+impl Sealer for SimulatedSealer {
+    fn identity(&self) -> &EnclaveIdentity {
+        &self.identity
+    }
+
+    fn seal(
+        &self,
+        policy: SealPolicy,
+        aad: &[u8],
+        plaintext: &[u8],
+    ) -> Result<SealedBlob, TeeError> {
+        let cipher = self.derive_key(policy)?;
+
+        let mut nonce = [0u8; 12];
+        rand::thread_rng().fill_bytes(&mut nonce);
+
+        let ciphertext = cipher
+            .encrypt(
+                Nonce::from_slice(&nonce),
+                Payload {
+                    msg: plaintext,
+                    aad,
+                },
+            )
+            .map_err(|e| TeeError::KeyManagement(format!("Failed to seal: {}", e)))?;
+
+        Ok(SealedBlob {
+            version: SEALED_BLOB_VERSION,
+            platform: self.identity.platform,
+            policy,
+            key_id: self.identity.key_id(policy),
+            nonce: nonce.to_vec(),
+            ciphertext,
+        })
+    }
+
+    fn unseal(&self, aad: &[u8], blob: &SealedBlob) -> Result<Vec<u8>, TeeError> {
+        check_blob(&self.identity, blob)?;
+        if blob.nonce.len() != 12 {
+            return Err(TeeError::Validation(
+                "Invalid sealed blob nonce".to_string(),
+            ));
+        }
+
+        let cipher = self.derive_key(blob.policy)?;
+        cipher
+            .decrypt(
+                Nonce::from_slice(&blob.nonce),
+                Payload {
+                    msg: &blob.ciphertext,
+                    aad,
+                },
+            )
+            .map_err(|_| {
+                TeeError::KeyManagement("Failed to unseal: blob was tampered with".to_string())
+            })
+    }
+}
+
+/// Sealer using the SGX sealing key of the current enclave
+#[cfg(feature = "sgx")]
+pub struct SgxSealer {
+    identity: EnclaveIdentity,
+}
+
+#[cfg(feature = "sgx")]
+impl SgxSealer {
+    /// Create a sealer for the enclave with `identity`
+    pub fn new(identity: EnclaveIdentity) -> Self {
+        Self { identity }
+    }
+}
+
+#[cfg(feature = "sgx")]
+impl Sealer for SgxSealer {
+    fn identity(&self) -> &EnclaveIdentity {
+        &self.identity
+    }
+
+    fn seal(
+        &self,
+        policy: SealPolicy,
+        aad: &[u8],
+        plaintext: &[u8],
+    ) -> Result<SealedBlob, TeeError> {
+        let key_policy = match policy {
+            SealPolicy::MrEnclave => sgx_sdk::KeyPolicy::MrEnclave,
+            SealPolicy::MrSigner => sgx_sdk::KeyPolicy::MrSigner,
+        };
+
+        // The sealed data embeds its nonce and key request
+        let ciphertext = sgx_sdk::seal_data(key_policy, aad, plaintext)
+            .map_err(|e| TeeError::KeyManagement(format!("Failed to seal: {}", e)))?;
+
+        Ok(SealedBlob {
+            version: SEALED_BLOB_VERSION,
+            platform: TeePlatform::Sgx,
+            policy,
+            key_id: self.identity.key_id(policy),
+            nonce: Vec::new(),
+            ciphertext,
+        })
+    }
+
+    fn unseal(&self, aad: &[u8], blob: &SealedBlob) -> Result<Vec<u8>, TeeError> {
+        check_blob(&self.identity, blob)?;
+
+        sgx_sdk::unseal_data(aad, &blob.ciphertext)
+            .map_err(|e| TeeError::KeyManagement(format!("Failed to unseal: {}", e)))
+    }
+}
+
+/// Store of sealed blobs; it only ever sees ciphertext
+pub trait SealedBlobStore: Send + Sync {
+    /// Get a blob
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>, TeeError>;
+
+    /// Put a blob
+    fn put(&self, key: &str, blob: &[u8]) -> Result<(), TeeError>;
+
+    /// Delete a blob, returning whether it existed
+    fn delete(&self, key: &str) -> Result<bool, TeeError>;
+}
+
+/// In-memory sealed blob store
+#[derive(Default)]
+pub struct MemorySealedBlobStore {
+    blobs: RwLock<HashMap<String, Vec<u8>>>,
+}
+
+impl MemorySealedBlobStore {
+    /// Create a new in-memory sealed blob store
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl SealedBlobStore for MemorySealedBlobStore {
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>, TeeError> {
+        let blobs = self
+            .blobs
+            .read()
+            .map_err(|e| TeeError::Internal(format!("Failed to acquire lock: {}", e)))?;
+        Ok(blobs.get(key).cloned())
+    }
+
+    fn put(&self, key: &str, blob: &[u8]) -> Result<(), TeeError> {
+        let mut blobs = self
+            .blobs
+            .write()
+            .map_err(|e| TeeError::Internal(format!("Failed to acquire lock: {}", e)))?;
+        blobs.insert(key.to_string(), blob.to_vec());
+        Ok(())
+    }
+
+    fn delete(&self, key: &str) -> Result<bool, TeeError> {
+        let mut blobs = self
+            .blobs
+            .write()
+            .map_err(|e| TeeError::Internal(format!("Failed to acquire lock: {}", e)))?;
+        Ok(blobs.remove(key).is_some())
+    }
+}
+
+/// Sealed blob store keeping one file per blob in a directory
+pub struct FileSealedBlobStore {
+    dir: PathBuf,
+}
+
+impl FileSealedBlobStore {
+    /// Create a store in `dir`, creating the directory if needed
+    pub fn new(dir: impl Into<PathBuf>) -> Result<Self, TeeError> {
+        let dir = dir.into();
+        std::fs::create_dir_all(&dir).map_err(|e| {
+            TeeError::Initialization(format!(
+                "Failed to create sealed storage directory {}: {}",
+                dir.display(),
+                e
+            ))
+        })?;
+
+        Ok(Self { dir })
+    }
+
+    /// File of a key; keys are hashed so they never escape the directory
+    fn path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!(
+            "{}.sealed",
+            hex::encode(Sha256::digest(key.as_bytes()))
+        ))
+    }
+}
+
+impl SealedBlobStore for FileSealedBlobStore {
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>, TeeError> {
+        match std::fs::read(self.path(key)) {
+            Ok(blob) => Ok(Some(blob)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(TeeError::Internal(format!(
+                "Failed to read sealed blob: {}",
+                e
+            ))),
+        }
+    }
+
+    fn put(&self, key: &str, blob: &[u8]) -> Result<(), TeeError> {
+        // Write then rename, so a crash never leaves a torn blob behind
+        let path = self.path(key);
+        let tmp = path.with_extension("tmp");
+        std::fs::write(&tmp, blob)
+            .and_then(|_| std::fs::rename(&tmp, &path))
+            .map_err(|e| TeeError::Internal(format!("Failed to write sealed blob: {}", e)))
+    }
+
+    fn delete(&self, key: &str) -> Result<bool, TeeError> {
+        match std::fs::remove_file(self.path(key)) {
+            Ok(()) => Ok(true),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(TeeError::Internal(format!(
+                "Failed to delete sealed blob: {}",
+                e
+            ))),
+        }
+    }
+}
+
+/// Sealed key-value storage
+pub struct SealedStorage {
+    sealer: Arc<dyn Sealer>,
+    store: Arc<dyn SealedBlobStore>,
+    policy: SealPolicy,
+}
+
+impl SealedStorage {
+    /// Create a sealed storage sealing with `sealer` into `store`
+    pub fn new(sealer: Arc<dyn Sealer>, store: Arc<dyn SealedBlobStore>) -> Self {
+        Self {
+            sealer,
+            store,
+            policy: SealPolicy::default(),
+        }
+    }
+
+    /// Seal new values with `policy`
+    pub fn with_policy(mut self, policy: SealPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Identity values are sealed to
+    pub fn identity(&self) -> &EnclaveIdentity {
+        self.sealer.identity()
+    }
+
+    /// Blob key and associated data of an entry; binding the blob to its entry keeps a
+    /// host from swapping blobs between entries
+    fn entry(namespace: &str, key: &str) -> String {
+        format!("{}\u{0}{}", namespace, key)
+    }
+
+    /// Seal and store a value
+    pub fn put(&self, namespace: &str, key: &str, value: &[u8]) -> Result<(), TeeError> {
+        if value.len() > MAX_SEALED_VALUE_SIZE {
+            return Err(TeeError::Validation(format!(
+                "Sealed value too large: {} > {} bytes",
+                value.len(),
+                MAX_SEALED_VALUE_SIZE
+            )));
+        }
+
+        let entry = Self::entry(namespace, key);
+        let blob = self.sealer.seal(self.policy, entry.as_bytes(), value)?;
+        let blob = serde_json::to_vec(&blob)
+            .map_err(|e| TeeError::Internal(format!("Failed to serialize sealed blob: {}", e)))?;
+
+        self.store.put(&entry, &blob)
+    }
+
+    /// Get and unseal a value
+    pub fn get(&self, namespace: &str, key: &str) -> Result<Option<Vec<u8>>, TeeError> {
+        let entry = Self::entry(namespace, key);
+        let Some(blob) = self.store.get(&entry)? else {
+            return Ok(None);
+        };
+
+        let blob: SealedBlob = serde_json::from_slice(&blob)
+            .map_err(|e| TeeError::Validation(format!("Invalid sealed blob: {}", e)))?;
+
+        self.sealer.unseal(entry.as_bytes(), &blob).map(Some)
+    }
+
+    /// Delete a value, returning whether it existed
+    pub fn delete(&self, namespace: &str, key: &str) -> Result<bool, TeeError> {
+        self.store.delete(&Self::entry(namespace, key))
+    }
+}
+
+/// Sealed storage of a single function, as put into the function's runtime state
+#[derive(Clone)]
+pub struct FunctionSealedStorage {
+    storage: Arc<SealedStorage>,
+    namespace: String,
+}
+
+impl FunctionSealedStorage {
+    /// Scope `storage` to the function `fid` of user `uid`
+    pub fn new(storage: Arc<SealedStorage>, uid: u64, fid: u64) -> Self {
+        Self {
+            storage,
+            namespace: format!("{}/{}", uid, fid),
+        }
+    }
+
+    /// Seal and store a value
+    pub fn put(&self, key: &str, value: &[u8]) -> Result<(), TeeError> {
+        self.storage.put(&self.namespace, key, value)
+    }
+
+    /// Get and unseal a value
+    pub fn get(&self, key: &str) -> Result<Option<Vec<u8>>, TeeError> {
+        self.storage.get(&self.namespace, key)
+    }
+
+    /// Delete a value, returning whether it existed
+    pub fn delete(&self, key: &str) -> Result<bool, TeeError> {
+        self.storage.delete(&self.namespace, key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECRET: &[u8] = b"platform secret";
+
+    fn sealer(code: &[u8], signer: &str) -> Arc<SimulatedSealer> {
+        Arc::new(SimulatedSealer::new(EnclaveIdentity::simulated(code, signer), SECRET).unwrap())
+    }
+
+    /// Sealer of the same enclave, e.g. after the host restarted
+    fn sealer_copy(sealer: &SimulatedSealer) -> SimulatedSealer {
+        SimulatedSealer::new(sealer.identity().clone(), SECRET).unwrap()
+    }
+
+    fn storage(sealer: Arc<SimulatedSealer>, store: Arc<dyn SealedBlobStore>) -> SealedStorage {
+        SealedStorage::new(sealer, store)
+    }
+
+    #[test]
+    fn test_seal_round_trip() {
+        let sealer = sealer(b"code", "signer");
+        let blob = sealer
+            .seal(SealPolicy::MrEnclave, b"aad", b"secret")
+            .unwrap();
+        assert_ne!(blob.ciphertext, b"secret");
+        assert_eq!(blob.key_id, sealer.identity().key_id(SealPolicy::MrEnclave));
+        assert_eq!(sealer.unseal(b"aad", &blob).unwrap(), b"secret");
+
+        // Fresh nonces, sealing twice gives different blobs
+        let again = sealer
+            .seal(SealPolicy::MrEnclave, b"aad", b"secret")
+            .unwrap();
+        assert_ne!(blob.ciphertext, again.ciphertext);
+
+        // The same enclave on a restarted host reads it back
+        assert_eq!(
+            sealer_copy(&sealer).unseal(b"aad", &blob).unwrap(),
+            b"secret"
+        );
+        assert!(SimulatedSealer::new(sealer.identity().clone(), b"").is_err());
+    }
+
+    #[test]
+    fn test_unseal_rejects_tampering() {
+        let sealer = sealer(b"code", "signer");
+        let blob = sealer
+            .seal(SealPolicy::MrEnclave, b"aad", b"secret")
+            .unwrap();
+
+        let mut flipped = blob.clone();
+        flipped.ciphertext[0] ^= 1;
+        assert!(matches!(
+            sealer.unseal(b"aad", &flipped),
+            Err(TeeError::KeyManagement(_))
+        ));
+
+        let mut renonced = blob.clone();
+        renonced.nonce[0] ^= 1;
+        assert!(sealer.unseal(b"aad", &renonced).is_err());
+        renonced.nonce.truncate(8);
+        assert!(matches!(
+            sealer.unseal(b"aad", &renonced),
+            Err(TeeError::Validation(_))
+        ));
+
+        // Bound to its associated data
+        assert!(sealer.unseal(b"other", &blob).is_err());
+
+        let mut versioned = blob;
+        versioned.version = SEALED_BLOB_VERSION + 1;
+        assert!(matches!(
+            sealer.unseal(b"aad", &versioned),
+            Err(TeeError::Validation(_))
+        ));
+    }
+
+    #[test]
+    fn test_seal_policies() {
+        let v1 = sealer(b"code v1", "signer");
+        let v2 = sealer(b"code v2", "signer");
+        let other_signer = sealer(b"code v1", "other");
+
+        // Another measurement cannot unseal, even by relabelling the blob
+        let blob = v1.seal(SealPolicy::MrEnclave, b"aad", b"secret").unwrap();
+        assert!(v2.unseal(b"aad", &blob).is_err());
+        let mut relabelled = blob.clone();
+        relabelled.key_id = v2.identity().key_id(SealPolicy::MrEnclave);
+        assert!(v2.unseal(b"aad", &relabelled).is_err());
+        assert!(other_signer.unseal(b"aad", &blob).is_ok());
+
+        // An upgrade from the same signer can unseal values sealed to the signer
+        let blob = v1.seal(SealPolicy::MrSigner, b"aad", b"secret").unwrap();
+        assert_eq!(v2.unseal(b"aad", &blob).unwrap(), b"secret");
+        assert!(other_signer.unseal(b"aad", &blob).is_err());
+
+        // Another platform secret, i.e. another machine, cannot unseal
+        let elsewhere =
+            SimulatedSealer::new(v1.identity().clone(), b"another platform secret").unwrap();
+        assert!(elsewhere.unseal(b"aad", &blob).is_err());
+    }
+
+    #[test]
+    fn test_sealed_storage() {
+        let store = Arc::new(MemorySealedBlobStore::new());
+        let storage = Arc::new(storage(sealer(b"code", "signer"), store.clone()));
+        let first = FunctionSealedStorage::new(storage.clone(), 1, 1);
+        let second = FunctionSealedStorage::new(storage.clone(), 1, 2);
+
+        first.put("key", b"first").unwrap();
+        assert_eq!(first.get("key").unwrap().as_deref(), Some(&b"first"[..]));
+        assert_eq!(second.get("key").unwrap(), None);
+
+        // The host only sees ciphertext
+        let raw = store
+            .get(&SealedStorage::entry("1/1", "key"))
+            .unwrap()
+            .unwrap();
+        assert!(!raw.windows(5).any(|w| w == b"first"));
+
+        // A blob swapped into another entry does not unseal there
+        second.put("key", b"second").unwrap();
+        store
+            .put(&SealedStorage::entry("1/2", "key"), &raw)
+            .unwrap();
+        assert!(second.get("key").is_err());
+
+        // Another enclave sharing the store cannot read it
+        let other = FunctionSealedStorage::new(
+            Arc::new(storage(sealer(b"other code", "signer"), store.clone())),
+            1,
+            1,
+        );
+        assert!(other.get("key").is_err());
+
+        assert!(first.delete("key").unwrap());
+        assert!(!first.delete("key").unwrap());
+        assert_eq!(first.get("key").unwrap(), None);
+
+        let too_large = vec![0; MAX_SEALED_VALUE_SIZE + 1];
+        assert!(matches!(
+            first.put("key", &too_large),
+            Err(TeeError::Validation(_))
+        ));
+    }
+
+    #[test]
+    fn test_file_sealed_blob_store() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = Arc::new(FileSealedBlobStore::new(dir.path().join("sealed")).unwrap());
+        let sealed = storage(sealer(b"code", "signer"), store.clone());
+
+        sealed.put("1/1", "../../etc/passwd", b"value").unwrap();
+        assert_eq!(
+            sealed.get("1/1", "../../etc/passwd").unwrap().as_deref(),
+            Some(&b"value"[..])
+        );
+        // Keys are hashed into the directory
+        let files: Vec<_> = std::fs::read_dir(dir.path().join("sealed"))
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        assert_eq!(files.len(), 1);
+        assert!(files[0].ends_with(".sealed"));
+
+        // Values survive reopening the store
+        let reopened = FileSealedBlobStore::new(dir.path().join("sealed")).unwrap();
+        let sealed = storage(sealer(b"code", "signer"), Arc::new(reopened));
+        assert!(sealed.get("1/1", "../../etc/passwd").unwrap().is_some());
+        assert!(sealed.delete("1/1", "../../etc/passwd").unwrap());
+        assert_eq!(sealed.get("1/1", "../../etc/passwd").unwrap(), None);
+    }
+}
@@ -0,0 +1,195 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+//! Containers driven through the `docker` CLI.
+
+use std::path::PathBuf;
+use std::process::Stdio;
+
+use log::{debug, warn};
+use tokio::process::Command;
+
+use crate::{HarnessError, Result};
+
+/// Whether a Docker daemon is reachable
+pub async fn docker_available() -> bool {
+    Command::new("docker")
+        .args(["info", "--format", "{{.ServerVersion}}"])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .await
+        .map(|status| status.success())
+        .unwrap_or(false)
+}
+
+/// Run a docker command, returning its standard output
+async fn docker<I, S>(args: I) -> Result<String>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<std::ffi::OsStr>,
+{
+    let output = Command::new("docker")
+        .args(args)
+        .output()
+        .await
+        .map_err(|e| HarnessError::Docker(format!("failed to run docker: {}", e)))?;
+    if !output.status.success() {
+        // Commands run with `exec` may report errors on either stream
+        return Err(HarnessError::Docker(format!(
+            "{}{}",
+            String::from_utf8_lossy(&output.stderr).trim(),
+            String::from_utf8_lossy(&output.stdout).trim()
+        )));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Container to start
+#[derive(Debug, Clone)]
+pub struct ContainerSpec {
+    /// Image, pulled if missing
+    pub image: String,
+    /// Entrypoint replacing the image's
+    pub entrypoint: Option<String>,
+    /// Arguments of the entrypoint
+    pub args: Vec<String>,
+    /// Container ports published on random host ports
+    pub ports: Vec<u16>,
+    /// Host directories mounted, as (host path, container path)
+    pub mounts: Vec<(PathBuf, String)>,
+    /// Environment variables
+    pub env: Vec<(String, String)>,
+}
+
+impl ContainerSpec {
+    pub fn new(image: impl Into<String>) -> Self {
+        Self {
+            image: image.into(),
+            entrypoint: None,
+            args: Vec::new(),
+            ports: Vec::new(),
+            mounts: Vec::new(),
+            env: Vec::new(),
+        }
+    }
+
+    pub fn with_entrypoint(mut self, entrypoint: impl Into<String>) -> Self {
+        self.entrypoint = Some(entrypoint.into());
+        self
+    }
+
+    pub fn with_args<I, S>(mut self, args: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.args = args.into_iter().map(Into::into).collect();
+        self
+    }
+
+    pub fn with_port(mut self, port: u16) -> Self {
+        self.ports.push(port);
+        self
+    }
+
+    pub fn with_mount(mut self, host: impl Into<PathBuf>, container: impl Into<String>) -> Self {
+        self.mounts.push((host.into(), container.into()));
+        self
+    }
+
+    pub fn with_env(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.env.push((name.into(), value.into()));
+        self
+    }
+
+    /// Start the container
+    pub async fn start(&self) -> Result<Container> {
+        let mut args = vec!["run".to_string(), "--detach".to_string()];
+        args.extend(["--label".to_string(), "r3e-test-harness=1".to_string()]);
+        if let Some(entrypoint) = &self.entrypoint {
+            args.extend(["--entrypoint".to_string(), entrypoint.clone()]);
+        }
+        for port in &self.ports {
+            args.extend(["--publish".to_string(), format!("127.0.0.1::{}", port)]);
+        }
+        for (host, container) in &self.mounts {
+            args.extend([
+                "--volume".to_string(),
+                format!("{}:{}", host.display(), container),
+            ]);
+        }
+        for (name, value) in &self.env {
+            args.extend(["--env".to_string(), format!("{}={}", name, value)]);
+        }
+        args.push(self.image.clone());
+        args.extend(self.args.iter().cloned());
+
+        let id = docker(&args).await?.trim().to_string();
+        debug!("Started container {} from {}", id, self.image);
+        Ok(Container { id })
+    }
+}
+
+/// Running container, removed when dropped
+#[derive(Debug)]
+pub struct Container {
+    id: String,
+}
+
+impl Container {
+    /// Container ID
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Host port a container port is published on
+    pub async fn host_port(&self, port: u16) -> Result<u16> {
+        let output = docker(["port", &self.id, &format!("{}/tcp", port)]).await?;
+        // One line per address, e.g. `127.0.0.1:49153`
+        output
+            .lines()
+            .filter_map(|line| line.rsplit(':').next())
+            .find_map(|port| port.trim().parse().ok())
+            .ok_or_else(|| HarnessError::Docker(format!("port {} is not published", port)))
+    }
+
+    /// Run a command in the container, returning its standard output
+    pub async fn exec<I, S>(&self, command: I) -> Result<String>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let mut args = vec!["exec".to_string(), self.id.clone()];
+        args.extend(command.into_iter().map(|arg| arg.as_ref().to_string()));
+        docker(&args).await
+    }
+
+    /// Output of the container so far, to explain failures
+    pub async fn logs(&self) -> Result<String> {
+        let output = Command::new("docker")
+            .args(["logs", &self.id])
+            .output()
+            .await
+            .map_err(|e| HarnessError::Docker(format!("failed to run docker: {}", e)))?;
+        Ok(format!(
+            "{}{}",
+            String::from_utf8_lossy(&output.stdout),
+            String::from_utf8_lossy(&output.stderr)
+        ))
+    }
+}
+
+impl Drop for Container {
+    fn drop(&mut self) {
+        // Drop cannot await, remove the container synchronously
+        let removed = std::process::Command::new("docker")
+            .args(["rm", "--force", "--volumes", &self.id])
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status();
+        if !matches!(removed, Ok(status) if status.success()) {
+            warn!("Failed to remove container {}", self.id);
+        }
+    }
+}
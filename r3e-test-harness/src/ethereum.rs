@@ -0,0 +1,235 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+//! Anvil development node.
+//!
+//! Anvil mines a block per transaction and keeps its dev accounts unlocked, so transactions
+//! are sent with `eth_sendTransaction` and need no signing on the harness side.
+
+use std::time::Duration;
+
+use log::info;
+use serde_json::{json, Value};
+
+use crate::docker::{Container, ContainerSpec};
+use crate::fixtures;
+use crate::rpc::RpcClient;
+use crate::{wait_for, HarnessError, Result};
+
+/// Foundry image the node runs by default
+pub const DEFAULT_FOUNDRY_IMAGE: &str = "ghcr.io/foundry-rs/foundry:stable";
+
+/// Chain ID of the node
+pub const ANVIL_CHAIN_ID: u64 = 31337;
+
+/// First dev account of anvil's default mnemonic
+pub const DEV_ACCOUNT: EthAccount = EthAccount {
+    address: "0xf39fd6e51aad88f6f4ce6ab8827279cfffb92266",
+    private_key: "ac0974bec39a17e36ba4a6b4d238ff944bacb478cbed5efcae784d7bf4f2ff80",
+};
+
+/// RPC port inside the container
+const RPC_PORT: u16 = 8545;
+
+/// Ethereum account with its private key
+#[derive(Debug, Clone, Copy)]
+pub struct EthAccount {
+    /// Address, lowercase hex
+    pub address: &'static str,
+    /// Private key as hex
+    pub private_key: &'static str,
+}
+
+/// Anvil node configuration
+#[derive(Debug, Clone)]
+pub struct AnvilConfig {
+    /// Image to run
+    pub image: String,
+    /// Deploy the Ping fixture contract once the node is up
+    pub deploy_fixtures: bool,
+    /// Time the node has to start answering requests
+    pub startup_timeout: Duration,
+}
+
+impl Default for AnvilConfig {
+    fn default() -> Self {
+        Self {
+            image: DEFAULT_FOUNDRY_IMAGE.to_string(),
+            deploy_fixtures: true,
+            startup_timeout: Duration::from_secs(60),
+        }
+    }
+}
+
+/// Running anvil node
+pub struct AnvilNode {
+    container: Container,
+    rpc: RpcClient,
+    ping_contract: Option<String>,
+}
+
+impl AnvilNode {
+    /// Start a node and deploy the fixture contracts
+    pub async fn start(config: AnvilConfig) -> Result<Self> {
+        let chain_id = ANVIL_CHAIN_ID.to_string();
+        let container = ContainerSpec::new(&config.image)
+            .with_entrypoint("anvil")
+            .with_args(["--host", "0.0.0.0", "--chain-id", &chain_id])
+            .with_port(RPC_PORT)
+            .start()
+            .await?;
+        let port = container.host_port(RPC_PORT).await?;
+        let rpc = RpcClient::new(format!("http://127.0.0.1:{}", port));
+
+        let mut node = Self {
+            container,
+            rpc,
+            ping_contract: None,
+        };
+
+        let ready = wait_for(
+            "anvil",
+            config.startup_timeout,
+            Duration::from_millis(250),
+            || async { node.block_number().await.map(Some) },
+        )
+        .await;
+        if let Err(e) = ready {
+            let logs = node.container.logs().await.unwrap_or_default();
+            return Err(HarnessError::Timeout(format!("{}\n{}", e, logs)));
+        }
+        info!("anvil node is up at {}", node.rpc_url());
+
+        if config.deploy_fixtures {
+            node.ping_contract = Some(node.deploy(&fixtures::evm_ping_initcode()).await?);
+        }
+
+        Ok(node)
+    }
+
+    /// RPC endpoint URL on the host
+    pub fn rpc_url(&self) -> &str {
+        self.rpc.url()
+    }
+
+    /// RPC client of the node
+    pub fn rpc(&self) -> &RpcClient {
+        &self.rpc
+    }
+
+    /// Container the node runs in
+    pub fn container(&self) -> &Container {
+        &self.container
+    }
+
+    /// Address of the deployed Ping contract
+    pub fn ping_contract(&self) -> Option<&str> {
+        self.ping_contract.as_deref()
+    }
+
+    /// Current block number
+    pub async fn block_number(&self) -> Result<u64> {
+        let number = self.rpc.call("eth_blockNumber", json!([])).await?;
+        parse_quantity(&number)
+    }
+
+    /// Mine `blocks` empty blocks
+    pub async fn mine(&self, blocks: u64) -> Result<()> {
+        self.rpc
+            .call("anvil_mine", json!([format!("{:#x}", blocks)]))
+            .await?;
+        Ok(())
+    }
+
+    /// Set the balance of an address in wei
+    pub async fn set_balance(&self, address: &str, wei: u128) -> Result<()> {
+        self.rpc
+            .call("anvil_setBalance", json!([address, format!("{:#x}", wei)]))
+            .await?;
+        Ok(())
+    }
+
+    /// Deploy a contract from the dev account, returning its address
+    pub async fn deploy(&self, initcode: &[u8]) -> Result<String> {
+        let receipt = self
+            .send_transaction(json!({
+                "from": DEV_ACCOUNT.address,
+                "data": format!("0x{}", hex::encode(initcode)),
+            }))
+            .await?;
+        let address = receipt["contractAddress"]
+            .as_str()
+            .ok_or_else(|| HarnessError::Rpc("no contract address in receipt".to_string()))?
+            .to_string();
+        info!("Deployed EVM contract {}", address);
+        Ok(address)
+    }
+
+    /// Call the Ping contract with `value`, returning the receipt of the transaction
+    pub async fn ping(&self, value: u64) -> Result<Value> {
+        let contract = self
+            .ping_contract
+            .as_deref()
+            .ok_or_else(|| HarnessError::Fixture("Ping contract is not deployed".to_string()))?;
+        self.send_transaction(json!({
+            "from": DEV_ACCOUNT.address,
+            "to": contract,
+            "data": format!("0x{:064x}", value),
+        }))
+        .await
+    }
+
+    /// Logs of a contract from `from_block` on
+    pub async fn logs(&self, address: &str, from_block: u64) -> Result<Vec<Value>> {
+        let logs = self
+            .rpc
+            .call(
+                "eth_getLogs",
+                json!([{
+                    "address": address,
+                    "fromBlock": format!("{:#x}", from_block),
+                    "toBlock": "latest",
+                }]),
+            )
+            .await?;
+        Ok(logs.as_array().cloned().unwrap_or_default())
+    }
+
+    /// Send a transaction from an unlocked account, returning its receipt once mined
+    pub async fn send_transaction(&self, tx: Value) -> Result<Value> {
+        let tx_hash = self.rpc.call("eth_sendTransaction", json!([tx])).await?;
+        let tx_hash = tx_hash
+            .as_str()
+            .ok_or_else(|| HarnessError::Rpc("invalid eth_sendTransaction response".to_string()))?;
+
+        let receipt = wait_for(
+            &format!("ethereum transaction {}", tx_hash),
+            Duration::from_secs(30),
+            Duration::from_millis(100),
+            || async {
+                let receipt = self
+                    .rpc
+                    .call("eth_getTransactionReceipt", json!([tx_hash]))
+                    .await?;
+                Ok((!receipt.is_null()).then_some(receipt))
+            },
+        )
+        .await?;
+
+        if receipt["status"].as_str() != Some("0x1") {
+            return Err(HarnessError::Rpc(format!(
+                "transaction {} reverted",
+                tx_hash
+            )));
+        }
+        Ok(receipt)
+    }
+}
+
+/// Value of a hex quantity
+fn parse_quantity(value: &Value) -> Result<u64> {
+    value
+        .as_str()
+        .and_then(|hex| u64::from_str_radix(hex.trim_start_matches("0x"), 16).ok())
+        .ok_or_else(|| HarnessError::Rpc(format!("invalid quantity: {}", value)))
+}
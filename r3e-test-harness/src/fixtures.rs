@@ -0,0 +1,261 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+//! Fixture contracts deployed by the harness.
+//!
+//! Both chains get a `Ping` contract: calling `ping(value)` emits a `Ping(value)` event, which
+//! is what event source tests wait for. The contracts are assembled here rather than compiled,
+//! so the harness needs no compiler toolchain.
+
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use sha3::Keccak256;
+
+/// Name of the Neo Ping contract
+pub const NEO_PING_NAME: &str = "R3EPing";
+
+/// Name of the event emitted by both Ping contracts
+pub const PING_EVENT: &str = "Ping";
+
+/// Signature of the Ping event of the EVM contract
+pub const EVM_PING_EVENT: &str = "Ping(uint256)";
+
+/// Magic of NEF files, "NEF3"
+const NEF_MAGIC: u32 = 0x3346_454e;
+
+/// Compiler recorded in the NEF file
+const NEF_COMPILER: &str = "r3e-test-harness";
+
+/// Interop hash of a syscall: the first four bytes of the SHA-256 of its name
+pub fn interop_hash(name: &str) -> [u8; 4] {
+    let digest = Sha256::digest(name.as_bytes());
+    [digest[0], digest[1], digest[2], digest[3]]
+}
+
+/// Script of the Neo Ping contract, with `ping` at offset 0
+pub fn neo_ping_script() -> Vec<u8> {
+    let mut script = vec![
+        0x57,
+        0x00,
+        0x01, // INITSLOT 0 locals, 1 argument
+        0x78, // LDARG0
+        0x11, // PUSH1
+        0xc0, // PACK
+        0x0c, // PUSHDATA1
+        PING_EVENT.len() as u8,
+    ];
+    script.extend_from_slice(PING_EVENT.as_bytes());
+    script.push(0x41); // SYSCALL
+    script.extend_from_slice(&interop_hash("System.Runtime.Notify"));
+    script.push(0x40); // RET
+    script
+}
+
+/// NEF file of the Neo Ping contract
+pub fn neo_ping_nef() -> Vec<u8> {
+    let mut nef = NEF_MAGIC.to_le_bytes().to_vec();
+    let mut compiler = [0u8; 64];
+    compiler[..NEF_COMPILER.len()].copy_from_slice(NEF_COMPILER.as_bytes());
+    nef.extend_from_slice(&compiler);
+    nef.push(0x00); // empty source
+    nef.push(0x00); // reserved
+    nef.push(0x00); // no method tokens
+    nef.extend_from_slice(&[0x00, 0x00]); // reserved
+    write_var_bytes(&mut nef, &neo_ping_script());
+    let checksum = nef_checksum(&nef);
+    nef.extend_from_slice(&checksum.to_le_bytes());
+    nef
+}
+
+/// Checksum of a NEF file without its checksum field
+pub fn nef_checksum(nef: &[u8]) -> u32 {
+    let digest = Sha256::digest(Sha256::digest(nef));
+    u32::from_le_bytes([digest[0], digest[1], digest[2], digest[3]])
+}
+
+/// Manifest of the Neo Ping contract
+pub fn neo_ping_manifest() -> Value {
+    json!({
+        "name": NEO_PING_NAME,
+        "groups": [],
+        "features": {},
+        "supportedstandards": [],
+        "abi": {
+            "methods": [{
+                "name": "ping",
+                "parameters": [{ "name": "value", "type": "Integer" }],
+                "returntype": "Void",
+                "offset": 0,
+                "safe": false
+            }],
+            "events": [{
+                "name": PING_EVENT,
+                "parameters": [{ "name": "value", "type": "Integer" }]
+            }]
+        },
+        "permissions": [{ "contract": "*", "methods": "*" }],
+        "trusts": [],
+        "extra": null
+    })
+}
+
+/// Hash a contract deployed by `sender` gets, in serialized byte order.
+///
+/// It is the Hash160 of `ABORT PUSHDATA sender PUSHINT checksum PUSHDATA name`, so it is known
+/// before the deployment is accepted.
+pub fn neo_contract_hash(sender: &[u8; 20], nef_checksum: u32, name: &str) -> [u8; 20] {
+    let mut script = vec![0x38]; // ABORT
+    script.extend_from_slice(&[0x0c, 0x14]);
+    script.extend_from_slice(sender);
+    push_int(&mut script, nef_checksum as i64);
+    script.extend_from_slice(&[0x0c, name.len() as u8]);
+    script.extend_from_slice(name.as_bytes());
+    hash160(&script)
+}
+
+/// RIPEMD-160 of the SHA-256 of `data`
+pub fn hash160(data: &[u8]) -> [u8; 20] {
+    ripemd::Ripemd160::digest(Sha256::digest(data)).into()
+}
+
+/// Script hash in the `0x`-prefixed, reversed form RPC methods take
+pub fn script_hash_hex(script_hash: &[u8; 20]) -> String {
+    let mut reversed = *script_hash;
+    reversed.reverse();
+    format!("0x{}", hex::encode(reversed))
+}
+
+/// Creation code of the EVM Ping contract.
+///
+/// The runtime code stores the first calldata word and logs it as `Ping(uint256)`, whatever
+/// function is called.
+pub fn evm_ping_initcode() -> Vec<u8> {
+    let mut runtime = vec![
+        0x60, 0x00, // PUSH1 0
+        0x35, // CALLDATALOAD
+        0x60, 0x00, // PUSH1 0
+        0x52, // MSTORE
+        0x7f, // PUSH32 topic
+    ];
+    runtime.extend_from_slice(&evm_ping_topic());
+    runtime.extend_from_slice(&[
+        0x60, 0x20, // PUSH1 32
+        0x60, 0x00, // PUSH1 0
+        0xa1, // LOG1
+        0x00, // STOP
+    ]);
+
+    // Copy the runtime code after this 11 byte prefix to memory and return it
+    let mut initcode = vec![
+        0x60,
+        runtime.len() as u8, // PUSH1 len
+        0x80,                // DUP1
+        0x60,
+        0x0b, // PUSH1 offset
+        0x60,
+        0x00, // PUSH1 0
+        0x39, // CODECOPY
+        0x60,
+        0x00, // PUSH1 0
+        0xf3, // RETURN
+    ];
+    initcode.extend_from_slice(&runtime);
+    initcode
+}
+
+/// Topic of the Ping event of the EVM contract
+pub fn evm_ping_topic() -> [u8; 32] {
+    Keccak256::digest(EVM_PING_EVENT.as_bytes()).into()
+}
+
+fn write_var_bytes(out: &mut Vec<u8>, data: &[u8]) {
+    match data.len() {
+        len if len < 0xfd => out.push(len as u8),
+        len if len <= 0xffff => {
+            out.push(0xfd);
+            out.extend_from_slice(&(len as u16).to_le_bytes());
+        }
+        len => {
+            out.push(0xfe);
+            out.extend_from_slice(&(len as u32).to_le_bytes());
+        }
+    }
+    out.extend_from_slice(data);
+}
+
+/// Push an integer the way the Neo script builder does: PUSHM1..PUSH16, otherwise the
+/// smallest PUSHINT holding it
+fn push_int(out: &mut Vec<u8>, value: i64) {
+    if (-1..=16).contains(&value) {
+        out.push((0x10 + value) as u8);
+        return;
+    }
+
+    let bytes = value.to_le_bytes();
+    for (opcode, size) in [(0x00u8, 1usize), (0x01, 2), (0x02, 4), (0x03, 8)] {
+        let truncated = &bytes[..size];
+        let sign = if truncated[size - 1] & 0x80 != 0 {
+            0xff
+        } else {
+            0x00
+        };
+        if bytes[size..].iter().all(|b| *b == sign) {
+            out.push(opcode);
+            out.extend_from_slice(truncated);
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_interop_hash() {
+        assert_eq!(
+            interop_hash("System.Runtime.Notify"),
+            [0x95, 0x01, 0x6f, 0x61]
+        );
+        assert_eq!(
+            interop_hash("System.Crypto.CheckSig"),
+            [0x56, 0xe7, 0xb3, 0x27]
+        );
+    }
+
+    #[test]
+    fn test_neo_ping_nef() {
+        let nef = neo_ping_nef();
+        assert_eq!(&nef[..4], b"NEF3");
+        let (body, checksum) = nef.split_at(nef.len() - 4);
+        assert_eq!(
+            nef_checksum(body),
+            u32::from_le_bytes(checksum.try_into().unwrap())
+        );
+        assert!(body.ends_with(&neo_ping_script()));
+    }
+
+    #[test]
+    fn test_push_int() {
+        let pushed = |value| {
+            let mut out = Vec::new();
+            push_int(&mut out, value);
+            out
+        };
+        assert_eq!(pushed(16), vec![0x20]);
+        assert_eq!(pushed(17), vec![0x00, 0x11]);
+        assert_eq!(pushed(128), vec![0x01, 0x80, 0x00]);
+        assert_eq!(
+            pushed(0xffff_ffff),
+            vec![0x03, 0xff, 0xff, 0xff, 0xff, 0x00, 0x00, 0x00, 0x00]
+        );
+    }
+
+    #[test]
+    fn test_evm_ping_initcode() {
+        let initcode = evm_ping_initcode();
+        assert_eq!(initcode[1] as usize, initcode.len() - 11);
+        assert_eq!(&initcode[18..50], &evm_ping_topic());
+        assert!(initcode.ends_with(&[0xa1, 0x00]));
+    }
+}
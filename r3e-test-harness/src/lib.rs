@@ -0,0 +1,94 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+//! Dockerized chain nodes for integration tests.
+//!
+//! The harness starts a single-node neo-go private network and an anvil node in containers,
+//! deploys fixture contracts to them and provides the helpers tests of event sources, the gas
+//! bank and meta transaction relaying need: funded accounts, contract invocations and RPC
+//! access. Containers are removed when their handle is dropped.
+//!
+//! Tests using the harness need a Docker daemon. They are marked `#[ignore]` and run with
+//! `cargo test -p r3e-test-harness -- --ignored`; [`docker_available`] lets other crates skip
+//! them instead.
+
+pub mod docker;
+pub mod ethereum;
+pub mod fixtures;
+pub mod neo;
+pub mod rpc;
+
+pub use docker::{docker_available, Container, ContainerSpec};
+pub use ethereum::{AnvilConfig, AnvilNode, EthAccount};
+pub use neo::{NeoAccount, NeoGoConfig, NeoGoNode};
+
+use std::time::Duration;
+
+use thiserror::Error;
+
+/// Harness error types
+#[derive(Debug, Error)]
+pub enum HarnessError {
+    #[error("harness: docker: {0}")]
+    Docker(String),
+
+    #[error("harness: rpc: {0}")]
+    Rpc(String),
+
+    #[error("harness: timed out: {0}")]
+    Timeout(String),
+
+    #[error("harness: {0}")]
+    Fixture(String),
+
+    #[error("harness: io: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+pub type Result<T> = std::result::Result<T, HarnessError>;
+
+/// Neo N3 and Ethereum nodes started together
+pub struct Harness {
+    pub neo: NeoGoNode,
+    pub ethereum: AnvilNode,
+}
+
+impl Harness {
+    /// Start both nodes with their default configuration
+    pub async fn start() -> Result<Self> {
+        let (neo, ethereum) = tokio::try_join!(
+            NeoGoNode::start(NeoGoConfig::default()),
+            AnvilNode::start(AnvilConfig::default()),
+        )?;
+        Ok(Self { neo, ethereum })
+    }
+}
+
+/// Poll `check` every `interval` until it returns a value, failing after `timeout`
+pub async fn wait_for<T, F, Fut>(
+    what: &str,
+    timeout: Duration,
+    interval: Duration,
+    mut check: F,
+) -> Result<T>
+where
+    F: FnMut() -> Fut,
+    Fut: std::future::Future<Output = Result<Option<T>>>,
+{
+    let deadline = tokio::time::Instant::now() + timeout;
+    let mut last_error = None;
+    loop {
+        match check().await {
+            Ok(Some(value)) => return Ok(value),
+            Ok(None) => {}
+            Err(err) => last_error = Some(err),
+        }
+        if tokio::time::Instant::now() >= deadline {
+            return Err(HarnessError::Timeout(match last_error {
+                Some(err) => format!("{}: {}", what, err),
+                None => what.to_string(),
+            }));
+        }
+        tokio::time::sleep(interval).await;
+    }
+}
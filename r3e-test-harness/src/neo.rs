@@ -0,0 +1,589 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+//! Single-node neo-go private network.
+//!
+//! The node is its own only validator. Its key is generated by the harness and written to a
+//! NEP-6 wallet together with the 1-of-1 multisig account the genesis block gives all NEO and
+//! GAS to. Transactions are built and signed by the `neo-go` CLI inside the container, so the
+//! harness needs no transaction builder of its own.
+
+use std::path::Path;
+use std::time::Duration;
+
+use aes::cipher::{generic_array::GenericArray, BlockEncrypt, KeyInit};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
+use log::info;
+use p256::elliptic_curve::sec1::ToEncodedPoint;
+use p256::SecretKey;
+use r3e_neo_services::signer::{self, LocalSigner};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use tempfile::TempDir;
+
+use crate::docker::{Container, ContainerSpec};
+use crate::fixtures;
+use crate::rpc::RpcClient;
+use crate::{wait_for, HarnessError, Result};
+
+/// neo-go image the node runs by default
+pub const DEFAULT_NEO_GO_IMAGE: &str = "nspccdev/neo-go:0.106.3";
+
+/// Script hash of the native GasToken
+pub const GAS_TOKEN: &str = "0xd2a4cff31913016155e38e474a2c06d08be276cf";
+
+/// Datoshi in one GAS
+pub const GAS_FACTOR: u64 = 100_000_000;
+
+/// RPC port inside the container
+const RPC_PORT: u16 = 30333;
+
+/// Directory the configuration and wallet are mounted at inside the container
+const CONFIG_DIR: &str = "/harness";
+
+/// Network magic of the private network
+const NETWORK_MAGIC: u32 = 56753;
+
+/// Password of the wallet, which never leaves the test
+const WALLET_PASSWORD: &str = "r3e-harness";
+
+/// scrypt parameters of NEP-2 keys, as log2(N), r and p
+const SCRYPT_LOG_N: u8 = 14;
+const SCRYPT_R: u32 = 8;
+const SCRYPT_P: u32 = 8;
+
+/// neo-go node configuration
+#[derive(Debug, Clone)]
+pub struct NeoGoConfig {
+    /// Image to run
+    pub image: String,
+    /// Time between blocks
+    pub time_per_block: Duration,
+    /// GAS the validator account is funded with from the genesis allocation
+    pub validator_gas: u64,
+    /// Deploy the Ping fixture contract once the node is up
+    pub deploy_fixtures: bool,
+    /// Time the node has to produce its first blocks
+    pub startup_timeout: Duration,
+}
+
+impl Default for NeoGoConfig {
+    fn default() -> Self {
+        Self {
+            image: DEFAULT_NEO_GO_IMAGE.to_string(),
+            time_per_block: Duration::from_secs(1),
+            validator_gas: 10_000,
+            deploy_fixtures: true,
+            startup_timeout: Duration::from_secs(60),
+        }
+    }
+}
+
+/// Neo N3 account with its private key
+#[derive(Clone)]
+pub struct NeoAccount {
+    /// Private key
+    pub private_key: [u8; 32],
+    /// Compressed public key
+    pub public_key: Vec<u8>,
+    /// Address
+    pub address: String,
+    /// Script hash, in serialized byte order
+    pub script_hash: [u8; 20],
+}
+
+impl std::fmt::Debug for NeoAccount {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("NeoAccount")
+            .field("address", &self.address)
+            .finish_non_exhaustive()
+    }
+}
+
+impl NeoAccount {
+    /// Generate a random account
+    pub fn generate() -> Self {
+        let secret = SecretKey::random(&mut rand::thread_rng());
+        Self::from_secret(&secret)
+    }
+
+    /// Account of a private key
+    pub fn from_private_key(private_key: &[u8]) -> Result<Self> {
+        let secret = SecretKey::from_slice(private_key)
+            .map_err(|e| HarnessError::Fixture(format!("invalid private key: {}", e)))?;
+        Ok(Self::from_secret(&secret))
+    }
+
+    fn from_secret(secret: &SecretKey) -> Self {
+        let public_key = secret
+            .public_key()
+            .to_encoded_point(true)
+            .as_bytes()
+            .to_vec();
+        // A compressed secp256r1 key is always 33 bytes, which is all these check
+        let address = signer::address_from_public_key(&public_key).expect("compressed key");
+        let script_hash = signer::script_hash(&public_key).expect("compressed key");
+        Self {
+            private_key: secret.to_bytes().into(),
+            public_key,
+            address,
+            script_hash,
+        }
+    }
+
+    /// Private key as hex, the form signer configurations take
+    pub fn private_key_hex(&self) -> String {
+        hex::encode(self.private_key)
+    }
+
+    /// Script hash in the `0x`-prefixed form RPC methods take
+    pub fn script_hash_hex(&self) -> String {
+        fixtures::script_hash_hex(&self.script_hash)
+    }
+
+    /// Relayer signer of the account
+    pub fn signer(&self) -> LocalSigner {
+        LocalSigner::from_bytes(&self.private_key).expect("valid private key")
+    }
+
+    /// Verification script of a 1-of-1 multisig account of this key
+    fn multisig_script(&self) -> Vec<u8> {
+        let mut script = vec![0x11, 0x0c, 0x21]; // PUSH1, PUSHDATA1 33
+        script.extend_from_slice(&self.public_key);
+        script.extend_from_slice(&[0x11, 0x41]); // PUSH1, SYSCALL
+        script.extend_from_slice(&fixtures::interop_hash("System.Crypto.CheckMultisig"));
+        script
+    }
+
+    /// Address of the 1-of-1 multisig account of this key
+    fn multisig_address(&self) -> String {
+        let mut payload = vec![0x35];
+        payload.extend_from_slice(&fixtures::hash160(&self.multisig_script()));
+        bs58::encode(payload).with_check().into_string()
+    }
+
+    /// NEP-2 encryption of the private key
+    fn nep2(&self, password: &str) -> Result<String> {
+        let address_hash = &Sha256::digest(Sha256::digest(self.address.as_bytes()))[..4];
+
+        let params = scrypt::Params::new(SCRYPT_LOG_N, SCRYPT_R, SCRYPT_P, 64)
+            .map_err(|e| HarnessError::Fixture(format!("invalid scrypt parameters: {}", e)))?;
+        let mut derived = [0u8; 64];
+        scrypt::scrypt(password.as_bytes(), address_hash, &params, &mut derived)
+            .map_err(|e| HarnessError::Fixture(format!("scrypt failed: {}", e)))?;
+
+        let mut encrypted = [0u8; 32];
+        for (i, byte) in encrypted.iter_mut().enumerate() {
+            *byte = self.private_key[i] ^ derived[i];
+        }
+        let cipher = aes::Aes256::new(GenericArray::from_slice(&derived[32..]));
+        for block in encrypted.chunks_mut(16) {
+            cipher.encrypt_block(GenericArray::from_mut_slice(block));
+        }
+
+        let mut payload = vec![0x01, 0x42, 0xe0];
+        payload.extend_from_slice(address_hash);
+        payload.extend_from_slice(&encrypted);
+        Ok(bs58::encode(payload).with_check().into_string())
+    }
+}
+
+/// Running neo-go node
+pub struct NeoGoNode {
+    container: Container,
+    rpc: RpcClient,
+    validator: NeoAccount,
+    committee_address: String,
+    ping_contract: Option<String>,
+    /// Mounted configuration, wallet and fixtures
+    dir: TempDir,
+}
+
+impl NeoGoNode {
+    /// Start a node, fund its validator account and deploy the fixture contracts
+    pub async fn start(config: NeoGoConfig) -> Result<Self> {
+        let validator = NeoAccount::generate();
+        let committee_address = validator.multisig_address();
+
+        let dir = tempfile::tempdir()?;
+        write_config(dir.path(), &validator, &config)?;
+
+        let container = ContainerSpec::new(&config.image)
+            .with_entrypoint("/usr/bin/neo-go")
+            .with_args(["node", "--config-path", CONFIG_DIR, "--privnet"])
+            .with_port(RPC_PORT)
+            .with_mount(dir.path(), CONFIG_DIR)
+            .start()
+            .await?;
+        let port = container.host_port(RPC_PORT).await?;
+        let rpc = RpcClient::new(format!("http://127.0.0.1:{}", port));
+
+        let mut node = Self {
+            container,
+            rpc,
+            validator,
+            committee_address,
+            ping_contract: None,
+            dir,
+        };
+
+        if let Err(e) = node.wait_for_height(1, config.startup_timeout).await {
+            let logs = node.container.logs().await.unwrap_or_default();
+            return Err(HarnessError::Timeout(format!("{}\n{}", e, logs)));
+        }
+        info!("neo-go node is up at {}", node.rpc_url());
+
+        let validator_address = node.validator.address.clone();
+        let committee_address = node.committee_address.clone();
+        node.transfer_gas_from(&committee_address, &validator_address, config.validator_gas)
+            .await?;
+
+        if config.deploy_fixtures {
+            node.ping_contract = Some(node.deploy_ping_contract().await?);
+        }
+
+        Ok(node)
+    }
+
+    /// RPC endpoint URL on the host
+    pub fn rpc_url(&self) -> &str {
+        self.rpc.url()
+    }
+
+    /// RPC client of the node
+    pub fn rpc(&self) -> &RpcClient {
+        &self.rpc
+    }
+
+    /// Container the node runs in
+    pub fn container(&self) -> &Container {
+        &self.container
+    }
+
+    /// Network magic of the private network
+    pub fn network_magic(&self) -> u32 {
+        NETWORK_MAGIC
+    }
+
+    /// Validator account, funded with GAS
+    pub fn validator(&self) -> &NeoAccount {
+        &self.validator
+    }
+
+    /// Hash of the deployed Ping contract
+    pub fn ping_contract(&self) -> Option<&str> {
+        self.ping_contract.as_deref()
+    }
+
+    /// Current block height
+    pub async fn height(&self) -> Result<u32> {
+        let count = self.rpc.call("getblockcount", json!([])).await?;
+        count
+            .as_u64()
+            .map(|count| count.saturating_sub(1) as u32)
+            .ok_or_else(|| HarnessError::Rpc("invalid getblockcount response".to_string()))
+    }
+
+    /// Wait until the chain reaches `height`
+    pub async fn wait_for_height(&self, height: u32, timeout: Duration) -> Result<u32> {
+        wait_for(
+            &format!("neo block {}", height),
+            timeout,
+            Duration::from_millis(250),
+            || async { Ok(self.height().await?.checked_sub(height).map(|_| height)) },
+        )
+        .await
+    }
+
+    /// GAS balance of an address in datoshi
+    pub async fn gas_balance(&self, address: &str) -> Result<u64> {
+        let balances = self.rpc.call("getnep17balances", json!([address])).await?;
+        Ok(balances["balance"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .find(|balance| balance["assethash"].as_str() == Some(GAS_TOKEN))
+            .and_then(|balance| balance["amount"].as_str())
+            .and_then(|amount| amount.parse().ok())
+            .unwrap_or(0))
+    }
+
+    /// Generate an account and fund it with `gas` GAS from the validator
+    pub async fn funded_account(&self, gas: u64) -> Result<NeoAccount> {
+        let account = NeoAccount::generate();
+        self.transfer_gas(&account.address, gas).await?;
+        Ok(account)
+    }
+
+    /// Transfer `gas` GAS from the validator account, returning the applied transaction hash
+    pub async fn transfer_gas(&self, to: &str, gas: u64) -> Result<String> {
+        self.transfer_gas_from(&self.validator.address, to, gas)
+            .await
+    }
+
+    async fn transfer_gas_from(&self, from: &str, to: &str, gas: u64) -> Result<String> {
+        let amount = gas.to_string();
+        let output = self
+            .neo_go(&[
+                "wallet", "nep17", "transfer", "--from", from, "--to", to, "--token", "GAS",
+                "--amount", &amount,
+            ])
+            .await?;
+        let tx_hash = tx_hash_in(&output)?;
+        self.wait_for_transaction(&tx_hash).await?;
+        Ok(tx_hash)
+    }
+
+    /// Deploy the Ping contract from the validator account, returning its hash
+    pub async fn deploy_ping_contract(&self) -> Result<String> {
+        let nef = fixtures::neo_ping_nef();
+        let checksum = fixtures::nef_checksum(&nef[..nef.len() - 4]);
+        std::fs::write(self.dir.path().join("ping.nef"), &nef)?;
+        std::fs::write(
+            self.dir.path().join("ping.manifest.json"),
+            fixtures::neo_ping_manifest().to_string(),
+        )?;
+
+        let nef_path = format!("{}/ping.nef", CONFIG_DIR);
+        let manifest_path = format!("{}/ping.manifest.json", CONFIG_DIR);
+        let output = self
+            .neo_go(&[
+                "contract",
+                "deploy",
+                "--address",
+                &self.validator.address,
+                "--in",
+                &nef_path,
+                "--manifest",
+                &manifest_path,
+            ])
+            .await?;
+        self.wait_for_transaction(&tx_hash_in(&output)?).await?;
+
+        let hash = fixtures::script_hash_hex(&fixtures::neo_contract_hash(
+            &self.validator.script_hash,
+            checksum,
+            fixtures::NEO_PING_NAME,
+        ));
+        self.rpc.call("getcontractstate", json!([hash])).await?;
+        info!("Deployed Neo Ping contract {}", hash);
+        Ok(hash)
+    }
+
+    /// Call `ping(value)` on the Ping contract, returning the applied transaction hash
+    pub async fn invoke_ping(&self, value: i64) -> Result<String> {
+        let contract = self
+            .ping_contract
+            .clone()
+            .ok_or_else(|| HarnessError::Fixture("Ping contract is not deployed".to_string()))?;
+        let argument = format!("int:{}", value);
+        let output = self
+            .neo_go(&[
+                "contract",
+                "invokefunction",
+                "--address",
+                &self.validator.address,
+                &contract,
+                "ping",
+                &argument,
+                "--",
+                &self.validator.address,
+            ])
+            .await?;
+        let tx_hash = tx_hash_in(&output)?;
+        self.wait_for_transaction(&tx_hash).await?;
+        Ok(tx_hash)
+    }
+
+    /// Wait until a transaction is in a block, returning its application log
+    pub async fn wait_for_transaction(&self, tx_hash: &str) -> Result<Value> {
+        let log = wait_for(
+            &format!("neo transaction {}", tx_hash),
+            Duration::from_secs(30),
+            Duration::from_millis(250),
+            || async {
+                match self.rpc.call("getapplicationlog", json!([tx_hash])).await {
+                    Ok(log) => Ok(Some(log)),
+                    // Unknown until the transaction is in a block
+                    Err(HarnessError::Rpc(_)) => Ok(None),
+                    Err(e) => Err(e),
+                }
+            },
+        )
+        .await?;
+
+        match log["executions"][0]["vmstate"].as_str() {
+            Some("HALT") => Ok(log),
+            _ => Err(HarnessError::Rpc(format!(
+                "transaction {} faulted: {}",
+                tx_hash,
+                log["executions"][0]["exception"]
+                    .as_str()
+                    .unwrap_or("unknown error")
+            ))),
+        }
+    }
+
+    /// Run a `neo-go` command signing with the node wallet against the node itself
+    async fn neo_go(&self, args: &[&str]) -> Result<String> {
+        let rpc_endpoint = format!("http://127.0.0.1:{}", RPC_PORT);
+        let wallet_config = format!("{}/wallet.yml", CONFIG_DIR);
+        let mut command = vec!["neo-go"];
+        // Subcommand flags go after the subcommand path, before positional arguments
+        let split = args
+            .iter()
+            .position(|arg| arg.starts_with("--"))
+            .unwrap_or(args.len());
+        command.extend_from_slice(&args[..split]);
+        command.extend_from_slice(&[
+            "--rpc-endpoint",
+            &rpc_endpoint,
+            "--wallet-config",
+            &wallet_config,
+            "--force",
+        ]);
+        command.extend_from_slice(&args[split..]);
+        self.container.exec(command).await
+    }
+}
+
+/// Transaction hash printed by a `neo-go` command, `0x`-prefixed
+fn tx_hash_in(output: &str) -> Result<String> {
+    output
+        .split(|c: char| c.is_whitespace() || c == ',' || c == '"')
+        .map(|token| token.trim_start_matches("0x"))
+        .find(|token| token.len() == 64 && token.chars().all(|c| c.is_ascii_hexdigit()))
+        .map(|hash| format!("0x{}", hash.to_lowercase()))
+        .ok_or_else(|| HarnessError::Fixture(format!("no transaction hash in: {}", output)))
+}
+
+/// Write the protocol configuration, wallet and wallet configuration of a node
+fn write_config(dir: &Path, validator: &NeoAccount, config: &NeoGoConfig) -> Result<()> {
+    let key = validator.nep2(WALLET_PASSWORD)?;
+    let account = |address: String, script: Vec<u8>, label: &str| {
+        json!({
+            "address": address,
+            "key": key,
+            "label": label,
+            "contract": {
+                "script": BASE64.encode(script),
+                "parameters": [{ "name": "parameter0", "type": "Signature" }],
+                "deployed": false
+            },
+            "lock": false,
+            "isDefault": false
+        })
+    };
+    let wallet = json!({
+        "version": "1.0",
+        "accounts": [
+            account(
+                validator.address.clone(),
+                signer::verification_script(&validator.public_key)
+                    .expect("compressed key"),
+                "validator",
+            ),
+            account(
+                validator.multisig_address(),
+                validator.multisig_script(),
+                "committee",
+            ),
+        ],
+        "scrypt": { "n": 1u32 << SCRYPT_LOG_N, "r": SCRYPT_R, "p": SCRYPT_P },
+        "extra": { "Tokens": null }
+    });
+    std::fs::write(dir.join("wallet.json"), wallet.to_string())?;
+
+    std::fs::write(
+        dir.join("wallet.yml"),
+        format!(
+            "Path: {dir}/wallet.json\nPassword: {password}\n",
+            dir = CONFIG_DIR,
+            password = WALLET_PASSWORD
+        ),
+    )?;
+
+    std::fs::write(
+        dir.join("protocol.privnet.yml"),
+        format!(
+            r#"ProtocolConfiguration:
+  Magic: {magic}
+  MaxTraceableBlocks: 200000
+  TimePerBlock: {time_per_block}ms
+  MemPoolSize: 50000
+  StandbyCommittee:
+    - {public_key}
+  ValidatorsCount: 1
+  SeedList: []
+  VerifyTransactions: true
+
+ApplicationConfiguration:
+  SkipBlockVerification: false
+  DBConfiguration:
+    Type: "inmemory"
+  P2P:
+    Addresses:
+      - ":20333"
+    MinPeers: 0
+    AttemptConnPeers: 0
+    MaxPeers: 10
+  Relay: true
+  Consensus:
+    Enabled: true
+    UnlockWallet:
+      Path: "{dir}/wallet.json"
+      Password: "{password}"
+  RPC:
+    Enabled: true
+    Addresses:
+      - ":{rpc_port}"
+    MaxGasInvoke: 100
+"#,
+            magic = NETWORK_MAGIC,
+            time_per_block = config.time_per_block.as_millis(),
+            public_key = hex::encode(&validator.public_key),
+            dir = CONFIG_DIR,
+            password = WALLET_PASSWORD,
+            rpc_port = RPC_PORT,
+        ),
+    )?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_nep2() {
+        // NEP-2 test vector
+        let account = NeoAccount::from_private_key(
+            &hex::decode("7d128a6d096f0c14c3a25a2b0c41cf79661bfcb4a8cc95aaaea28bde4d732344")
+                .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(account.address, "NPTmAHDxo6Pkyic8Nvu3kwyXoYJCvcCB6i");
+        assert_eq!(
+            account.nep2("city of zion").unwrap(),
+            "6PYUUUFei9PBBfVkSn8q7hFCnewWFRBKPxcn6Kz6Bmk3FqWyLyuTQE2XFH"
+        );
+    }
+
+    #[test]
+    fn test_multisig_address() {
+        let account = NeoAccount::generate();
+        assert_ne!(account.multisig_address(), account.address);
+        assert!(account.multisig_address().starts_with('N'));
+    }
+
+    #[test]
+    fn test_tx_hash_in() {
+        let hash = "a1b2c3d4e5f60718293a4b5c6d7e8f90a1b2c3d4e5f60718293a4b5c6d7e8f90";
+        assert_eq!(
+            tx_hash_in(&format!("Sent invocation transaction {}\n", hash)).unwrap(),
+            format!("0x{}", hash)
+        );
+        assert!(tx_hash_in("Contract: 1234").is_err());
+    }
+}
@@ -0,0 +1,60 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+//! JSON-RPC client shared by the Neo and Ethereum nodes.
+
+use serde_json::{json, Value};
+
+use crate::{HarnessError, Result};
+
+/// JSON-RPC 2.0 client of a node
+#[derive(Debug, Clone)]
+pub struct RpcClient {
+    client: reqwest::Client,
+    url: String,
+}
+
+impl RpcClient {
+    pub fn new(url: impl Into<String>) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            url: url.into(),
+        }
+    }
+
+    /// Endpoint URL
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Call a method, returning its result
+    pub async fn call(&self, method: &str, params: Value) -> Result<Value> {
+        let request = json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": method,
+            "params": params,
+        });
+
+        let response: Value = self
+            .client
+            .post(&self.url)
+            .json(&request)
+            .send()
+            .await
+            .map_err(|e| HarnessError::Rpc(format!("{} failed: {}", method, e)))?
+            .json()
+            .await
+            .map_err(|e| HarnessError::Rpc(format!("invalid {} response: {}", method, e)))?;
+
+        if let Some(error) = response.get("error") {
+            return Err(HarnessError::Rpc(format!(
+                "{} failed: {}",
+                method,
+                error["message"].as_str().unwrap_or("unknown error")
+            )));
+        }
+
+        Ok(response["result"].clone())
+    }
+}
@@ -0,0 +1,104 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+//! Integration tests against dockerized chain nodes, run with
+//! `cargo test -p r3e-test-harness -- --ignored`.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use r3e_core::rpc_pool::{RpcEndpointPool, RpcPoolConfig};
+use r3e_event::source::event::Event;
+use r3e_event::source::{NeoSubscriptionTaskSource, TaskSource};
+use r3e_neo_services::chain_state::ChainStateCache;
+use r3e_neo_services::meta_tx::manager::ChainTxStatus;
+use r3e_neo_services::meta_tx::{NeoRelayBackend, RelayBackend};
+use r3e_test_harness::fixtures::{evm_ping_topic, PING_EVENT};
+use r3e_test_harness::neo::GAS_FACTOR;
+use r3e_test_harness::{AnvilConfig, AnvilNode, NeoGoConfig, NeoGoNode};
+
+#[tokio::test]
+#[ignore = "requires docker"]
+async fn test_neo_subscription_delivers_ping_transaction() {
+    let node = NeoGoNode::start(NeoGoConfig::default()).await.unwrap();
+    let start_height = node.height().await.unwrap();
+    let tx_hash = node.invoke_ping(42).await.unwrap();
+
+    let pool = RpcEndpointPool::new("neo", [node.rpc_url()], RpcPoolConfig::default());
+    let mut source = NeoSubscriptionTaskSource::new(Arc::new(pool))
+        .with_poll_interval(Duration::from_millis(250))
+        .with_start_height(start_height)
+        .with_transactions(true);
+
+    let delivered = tokio::time::timeout(Duration::from_secs(60), async {
+        loop {
+            let task = source.acquire_task(1, 1).await.unwrap();
+            if let Event::NeoBlock(block) = task.event {
+                if block.txs.iter().any(|tx| tx.hash == tx_hash) {
+                    return;
+                }
+            }
+        }
+    })
+    .await;
+    assert!(delivered.is_ok(), "transaction {} not delivered", tx_hash);
+}
+
+#[tokio::test]
+#[ignore = "requires docker"]
+async fn test_neo_ping_notification() {
+    let node = NeoGoNode::start(NeoGoConfig::default()).await.unwrap();
+    let tx_hash = node.invoke_ping(7).await.unwrap();
+
+    let log = node.wait_for_transaction(&tx_hash).await.unwrap();
+    let notification = &log["executions"][0]["notifications"][0];
+    assert_eq!(notification["eventname"], PING_EVENT);
+    assert_eq!(notification["contract"], node.ping_contract().unwrap());
+    assert_eq!(notification["state"]["value"][0]["value"], "7");
+}
+
+#[tokio::test]
+#[ignore = "requires docker"]
+async fn test_chain_state_and_relay_backend() {
+    let node = NeoGoNode::start(NeoGoConfig::default()).await.unwrap();
+
+    let chain_state = Arc::new(ChainStateCache::new(node.rpc_url()));
+    let state = chain_state.refresh().await.unwrap();
+    assert!(state.height >= node.height().await.unwrap().saturating_sub(1));
+    assert_eq!(state.fee_per_byte, 1000);
+    assert_eq!(state.exec_fee_factor, 30);
+
+    // A funded relayer sees the funding transaction as confirmed
+    let relayer = node.funded_account(100).await.unwrap();
+    assert_eq!(
+        node.gas_balance(&relayer.address).await.unwrap(),
+        100 * GAS_FACTOR
+    );
+    let backend = NeoRelayBackend::new(node.rpc_url(), Arc::new(relayer.signer()))
+        .await
+        .unwrap()
+        .with_chain_state(chain_state);
+    let tx_hash = node.invoke_ping(1).await.unwrap();
+    assert_eq!(
+        backend.tx_status(&tx_hash).await.unwrap(),
+        ChainTxStatus::Confirmed
+    );
+}
+
+#[tokio::test]
+#[ignore = "requires docker"]
+async fn test_anvil_ping_log() {
+    let node = AnvilNode::start(AnvilConfig::default()).await.unwrap();
+    let contract = node.ping_contract().unwrap().to_string();
+    let from_block = node.block_number().await.unwrap();
+
+    node.ping(42).await.unwrap();
+
+    let logs = node.logs(&contract, from_block).await.unwrap();
+    assert_eq!(logs.len(), 1);
+    assert_eq!(
+        logs[0]["topics"][0],
+        format!("0x{}", hex::encode(evm_ping_topic()))
+    );
+    assert_eq!(logs[0]["data"], format!("0x{:064x}", 42));
+}
@@ -0,0 +1,512 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+//! Local admin endpoint of a worker.
+//!
+//! Runners are forked processes, so each runner publishes its status, the invocation it is
+//! running and the heap statistics of its runtimes to a status board directory, which the
+//! admin endpoint of the worker reads and the runners compare their loads on. Killing an
+//! invocation leaves a request on the board that the runner picks up and answers by
+//! terminating the invocation's isolate. With `admin_mtls` configured, the endpoint only
+//! accepts clients presenting a certificate of the internal CA whose SPIFFE ID is allowed.
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use r3e_core::mtls::MtlsConfig;
+use r3e_deno::IsolateHandle;
+use r3e_tee::AttestationReport;
+
+use crate::autoscale::Autoscaler;
+use crate::drain::{DrainState, Drainer};
+use crate::lanes::LaneDepth;
+use crate::sched::CgroupMemory;
+
+/// Interval at which runners check for kill requests
+const KILL_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Heap statistics of a runtime cached by a runner
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RuntimeStats {
+    /// Function the runtime runs
+    pub fid: u64,
+
+    /// Version of the function code
+    pub version: u64,
+
+    pub total_heap_size: usize,
+    pub used_heap_size: usize,
+    pub heap_size_limit: usize,
+
+    /// Whether the runtime is kept warm by the function's keep-warm policy
+    #[serde(default)]
+    pub warm: bool,
+}
+
+/// Invocation running on a runner
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InvocationStatus {
+    /// Invocation ID, used to kill it
+    pub id: String,
+
+    /// Function invoked
+    pub fid: u64,
+
+    /// Start time, in milliseconds since the Unix epoch
+    pub started_at_ms: u64,
+
+    /// Time since the start, in milliseconds, filled in when listed, or the duration of a
+    /// completed invocation
+    #[serde(default)]
+    pub elapsed_ms: u64,
+
+    /// Attestation of the enclave that ran the invocation, for functions run in a TEE
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub attestation: Option<AttestationReport>,
+}
+
+impl InvocationStatus {
+    /// A new invocation of a function, starting now
+    pub fn new(fid: u64) -> Self {
+        Self {
+            id: uuid::Uuid::new_v4().to_string(),
+            fid,
+            started_at_ms: now_ms(),
+            elapsed_ms: 0,
+            attestation: None,
+        }
+    }
+}
+
+/// Status published by a runner
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RunnerStatus {
+    pub uid: u64,
+    pub pid: u32,
+
+    /// Invocation in progress, if any
+    pub invocation: Option<InvocationStatus>,
+
+    /// Last completed invocation, with its attestation if it ran in a TEE
+    #[serde(default)]
+    pub last_invocation: Option<InvocationStatus>,
+
+    /// Runtimes cached by the runner
+    pub runtimes: Vec<RuntimeStats>,
+
+    /// Memory usage of the runner's cgroup, if readable
+    #[serde(default)]
+    pub memory: Option<CgroupMemory>,
+
+    /// Tasks queued in each lane
+    #[serde(default)]
+    pub lanes: Vec<LaneDepth>,
+
+    /// Time of the last update, in milliseconds since the Unix epoch
+    pub updated_at_ms: u64,
+}
+
+/// Running invocation, as listed by the admin endpoint
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RunningInvocation {
+    pub uid: u64,
+    pub pid: u32,
+
+    #[serde(flatten)]
+    pub invocation: InvocationStatus,
+}
+
+/// Directory of runner statuses and kill requests, one file per runner
+#[derive(Debug, Clone)]
+pub struct StatusBoard {
+    dir: PathBuf,
+}
+
+impl StatusBoard {
+    /// Open the board in `dir`, creating the directory if needed
+    pub fn open(dir: impl AsRef<Path>) -> std::io::Result<Self> {
+        std::fs::create_dir_all(dir.as_ref())?;
+        Ok(Self {
+            dir: dir.as_ref().to_path_buf(),
+        })
+    }
+
+    /// Publish the status of a runner
+    pub fn publish(&self, status: &RunnerStatus) -> std::io::Result<()> {
+        let mut status = status.clone();
+        status.updated_at_ms = now_ms();
+        let data = serde_json::to_vec(&status)?;
+
+        // Write then rename, so readers never see a partial status
+        let tmp = self.dir.join(format!("{}.tmp", status.pid));
+        std::fs::write(&tmp, data)?;
+        std::fs::rename(&tmp, self.status_path(status.pid))
+    }
+
+    /// Remove the status and pending kill request of an exited runner
+    pub fn remove(&self, pid: u32) {
+        let _ = std::fs::remove_file(self.status_path(pid));
+        let _ = std::fs::remove_file(self.kill_path(pid));
+    }
+
+    /// Statuses of all runners, by pid. Unreadable statuses are skipped.
+    pub fn runners(&self) -> std::io::Result<Vec<RunnerStatus>> {
+        let mut runners = Vec::new();
+        for entry in std::fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if path.extension().map_or(true, |ext| ext != "json") {
+                continue;
+            }
+
+            // The runner may exit while listing
+            let status = std::fs::read(&path)
+                .ok()
+                .and_then(|data| serde_json::from_slice::<RunnerStatus>(&data).ok());
+            if let Some(status) = status {
+                runners.push(status);
+            }
+        }
+
+        runners.sort_by_key(|status| status.pid);
+        Ok(runners)
+    }
+
+    /// Invocations in progress, longest running first
+    pub fn invocations(&self) -> std::io::Result<Vec<RunningInvocation>> {
+        let now = now_ms();
+        let mut invocations: Vec<RunningInvocation> = self
+            .runners()?
+            .into_iter()
+            .filter_map(|status| {
+                let mut invocation = status.invocation?;
+                invocation.elapsed_ms = now.saturating_sub(invocation.started_at_ms);
+                Some(RunningInvocation {
+                    uid: status.uid,
+                    pid: status.pid,
+                    invocation,
+                })
+            })
+            .collect();
+
+        invocations.sort_by_key(|running| running.invocation.started_at_ms);
+        Ok(invocations)
+    }
+
+    /// Queue depth of each lane, summed over the runners
+    pub fn lanes(&self) -> std::io::Result<Vec<LaneDepth>> {
+        let mut lanes: Vec<LaneDepth> = Vec::new();
+        for status in self.runners()? {
+            for depth in status.lanes {
+                match lanes.iter_mut().find(|lane| lane.lane == depth.lane) {
+                    Some(lane) => {
+                        lane.depth += depth.depth;
+                        lane.served += depth.served;
+                    }
+                    None => lanes.push(depth),
+                }
+            }
+        }
+
+        lanes.sort_by(|a, b| a.lane.cmp(&b.lane));
+        Ok(lanes)
+    }
+
+    /// Ask the runner of an invocation to kill it, None if no runner is running it
+    pub fn request_kill(&self, invocation_id: &str) -> std::io::Result<Option<RunningInvocation>> {
+        let running = self
+            .invocations()?
+            .into_iter()
+            .find(|running| running.invocation.id == invocation_id);
+        if let Some(running) = &running {
+            std::fs::write(self.kill_path(running.pid), invocation_id)?;
+        }
+        Ok(running)
+    }
+
+    /// Take the pending kill request of a runner
+    pub fn take_kill(&self, pid: u32) -> Option<String> {
+        let path = self.kill_path(pid);
+        let invocation_id = std::fs::read_to_string(&path).ok()?;
+        let _ = std::fs::remove_file(&path);
+        Some(invocation_id)
+    }
+
+    fn status_path(&self, pid: u32) -> PathBuf {
+        self.dir.join(format!("{}.json", pid))
+    }
+
+    fn kill_path(&self, pid: u32) -> PathBuf {
+        self.dir.join(format!("{}.kill", pid))
+    }
+}
+
+/// Invocation that can be killed, and whether it was
+struct Armed {
+    id: String,
+    isolate: IsolateHandle,
+    killed: bool,
+}
+
+/// Terminates the invocation in progress on a runner when a kill request for it arrives
+#[derive(Clone, Default)]
+pub struct KillSwitch {
+    armed: Arc<Mutex<Option<Armed>>>,
+}
+
+impl KillSwitch {
+    /// Watch the board for kill requests addressed to this process, until the switch is dropped
+    pub fn watch(&self, board: Arc<StatusBoard>) {
+        let armed: Weak<Mutex<Option<Armed>>> = Arc::downgrade(&self.armed);
+        let pid = std::process::id();
+        std::thread::spawn(move || loop {
+            std::thread::sleep(KILL_POLL_INTERVAL);
+            let Some(armed) = armed.upgrade() else {
+                return;
+            };
+            let Some(invocation_id) = board.take_kill(pid) else {
+                continue;
+            };
+
+            match armed.lock().unwrap().as_mut() {
+                Some(armed) if armed.id == invocation_id => {
+                    log::warn!("admin: killing invocation {}", invocation_id);
+                    armed.killed = true;
+                    armed.isolate.terminate_execution();
+                }
+                _ => log::info!("admin: invocation {} already finished", invocation_id),
+            }
+        });
+    }
+
+    /// Allow killing an invocation until disarmed
+    pub fn arm(&self, invocation_id: &str, isolate: IsolateHandle) {
+        *self.armed.lock().unwrap() = Some(Armed {
+            id: invocation_id.to_string(),
+            isolate,
+            killed: false,
+        });
+    }
+
+    /// Stop allowing the invocation to be killed, returning whether it was
+    pub fn disarm(&self) -> bool {
+        self.armed
+            .lock()
+            .unwrap()
+            .take()
+            .map_or(false, |armed| armed.killed)
+    }
+}
+
+/// Serve the local admin endpoint on a background thread.
+///
+/// - `GET /runners` lists the runners, their invocation in progress, their last completed
+///   invocation with its TEE attestation, and the heap statistics of their runtimes
+/// - `GET /invocations` lists the invocations in progress with their durations
+/// - `GET /lanes` reports the tasks queued in each priority lane over all the runners
+/// - `POST /invocations/{id}/kill` terminates an invocation
+/// - `GET /autoscale` reports the load of the worker and the replicas desired for it
+/// - `GET /drain` reports the drain status
+/// - `POST /drain` starts draining, like SIGTERM
+/// - `GET /healthz` is 200 while running and 503 once draining, so load balancers stop
+///   routing to the worker
+pub fn serve_admin(
+    addr: SocketAddr,
+    drainer: Arc<Drainer>,
+    board: Option<Arc<StatusBoard>>,
+    autoscaler: Option<Arc<Autoscaler>>,
+    mtls: Option<MtlsConfig>,
+    stop: Arc<AtomicBool>,
+) -> std::io::Result<std::thread::JoinHandle<()>> {
+    if !addr.ip().is_loopback() && mtls.is_none() {
+        log::warn!("admin: endpoint {} is not bound to loopback", addr);
+    }
+
+    let tls = match mtls {
+        Some(mtls) => {
+            mtls.provision().map_err(std::io::Error::other)?;
+            let server_config = mtls.server_config().map_err(std::io::Error::other)?;
+            Some((mtls, server_config))
+        }
+        None => None,
+    };
+
+    let listener = TcpListener::bind(addr)?;
+    log::info!("admin: endpoint listening on {}", addr);
+
+    Ok(std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(err) => {
+                    log::warn!("admin: accept failed: {}", err);
+                    continue;
+                }
+            };
+            if let Err(err) = stream.set_read_timeout(Some(Duration::from_secs(5))) {
+                log::warn!("admin: accept failed: {}", err);
+                continue;
+            }
+
+            let result = match &tls {
+                Some((mtls, server_config)) => {
+                    r3e_core::mtls::accept_blocking(server_config.clone(), stream)
+                        .and_then(|(stream, peer)| {
+                            mtls.authorize(peer.as_ref())?;
+                            Ok(stream)
+                        })
+                        .map_err(std::io::Error::other)
+                        .and_then(|stream| {
+                            handle_admin(
+                                stream,
+                                &drainer,
+                                board.as_deref(),
+                                autoscaler.as_deref(),
+                                &stop,
+                            )
+                        })
+                }
+                None => handle_admin(
+                    stream,
+                    &drainer,
+                    board.as_deref(),
+                    autoscaler.as_deref(),
+                    &stop,
+                ),
+            };
+            if let Err(err) = result {
+                log::warn!("admin: request failed: {}", err);
+            }
+        }
+    }))
+}
+
+fn handle_admin(
+    mut stream: impl Read + Write,
+    drainer: &Drainer,
+    board: Option<&StatusBoard>,
+    autoscaler: Option<&Autoscaler>,
+    stop: &AtomicBool,
+) -> std::io::Result<()> {
+    let mut request_line = String::new();
+    BufReader::new(&mut stream).read_line(&mut request_line)?;
+    let mut parts = request_line.split_whitespace();
+    let (method, path) = (parts.next().unwrap_or(""), parts.next().unwrap_or(""));
+    let kill = path
+        .strip_prefix("/invocations/")
+        .and_then(|path| path.strip_suffix("/kill"));
+
+    let (status, body) = match (method, path, kill, board) {
+        ("GET", "/runners" | "/invocations" | "/lanes", _, None) | ("POST", _, Some(_), None) => (
+            "503 Service Unavailable",
+            r#"{"error":"status board unavailable"}"#.to_string(),
+        ),
+        ("GET", "/runners", _, Some(board)) => {
+            ("200 OK", serde_json::to_string(&board.runners()?)?)
+        }
+        ("GET", "/invocations", _, Some(board)) => {
+            ("200 OK", serde_json::to_string(&board.invocations()?)?)
+        }
+        ("GET", "/lanes", _, Some(board)) => ("200 OK", serde_json::to_string(&board.lanes()?)?),
+        ("POST", _, Some(invocation_id), Some(board)) => match board.request_kill(invocation_id)? {
+            Some(running) => ("202 Accepted", serde_json::to_string(&running)?),
+            None => (
+                "404 Not Found",
+                r#"{"error":"invocation not running"}"#.to_string(),
+            ),
+        },
+        ("GET", "/autoscale", _, _) => match autoscaler {
+            Some(autoscaler) => ("200 OK", serde_json::to_string(&autoscaler.recommend()?)?),
+            None => (
+                "503 Service Unavailable",
+                r#"{"error":"autoscaling disabled"}"#.to_string(),
+            ),
+        },
+        ("GET", "/drain", _, _) => ("200 OK", serde_json::to_string(&drainer.status())?),
+        ("POST", "/drain", _, _) => {
+            stop.store(true, Ordering::SeqCst);
+            ("202 Accepted", serde_json::to_string(&drainer.status())?)
+        }
+        ("GET", "/healthz", _, _) => match drainer.state() {
+            DrainState::Running => ("200 OK", r#"{"status":"ok"}"#.to_string()),
+            _ => (
+                "503 Service Unavailable",
+                r#"{"status":"draining"}"#.to_string(),
+            ),
+        },
+        _ => ("404 Not Found", r#"{"error":"not found"}"#.to_string()),
+    };
+
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    )
+}
+
+pub(crate) fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_status_board() {
+        let dir = tempfile::tempdir().unwrap();
+        let board = StatusBoard::open(dir.path()).unwrap();
+
+        let mut busy = RunnerStatus {
+            uid: 1,
+            pid: 100,
+            invocation: Some(InvocationStatus::new(10)),
+            runtimes: vec![RuntimeStats {
+                fid: 10,
+                version: 1,
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        busy.invocation.as_mut().unwrap().started_at_ms -= 1500;
+        let idle = RunnerStatus {
+            uid: 2,
+            pid: 101,
+            lanes: vec![LaneDepth {
+                lane: "batch".to_string(),
+                weight: 1,
+                depth: 2,
+                served: 5,
+            }],
+            ..Default::default()
+        };
+        board.publish(&busy).unwrap();
+        board.publish(&idle).unwrap();
+        assert_eq!(board.runners().unwrap().len(), 2);
+        assert_eq!(board.lanes().unwrap()[0].depth, 2);
+
+        let invocations = board.invocations().unwrap();
+        assert_eq!(invocations.len(), 1);
+        assert_eq!(invocations[0].pid, 100);
+        assert!(invocations[0].invocation.elapsed_ms >= 1500);
+
+        let id = invocations[0].invocation.id.clone();
+        assert!(board.request_kill("unknown").unwrap().is_none());
+        assert!(board.request_kill(&id).unwrap().is_some());
+        assert_eq!(board.take_kill(100), Some(id));
+        assert_eq!(board.take_kill(100), None);
+
+        board.remove(100);
+        assert_eq!(board.runners().unwrap().len(), 1);
+    }
+}
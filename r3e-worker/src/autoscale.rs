@@ -0,0 +1,596 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+//! Scaling advice for the worker pool.
+//!
+//! The advisor reads the status board the runners publish to and rates the load of the worker
+//! as a ratio to its targets, 1.0 being on target: queued tasks per runner, share of busy
+//! runners and latency of the invocations completed recently, whichever is the furthest above
+//! its target. The desired replica count is the current one scaled by that load, as the
+//! Kubernetes HPA does, kept within the configured bounds. Load above target is acted on at
+//! once, while scaling down waits for the load to stay low for the whole scale-down window.
+//!
+//! The load is served by the admin endpoint as `GET /autoscale`, which KEDA's metrics-api
+//! scaler reads with a `Value` metric type and a target of 1. With a backend configured, the
+//! worker also pushes the desired replica count to it, e.g. to a Nomad job.
+
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+use crate::admin::{now_ms, RunnerStatus, StatusBoard};
+
+/// Invocations completed longer ago than this do not count towards the latency
+const LATENCY_WINDOW_MS: u64 = 60_000;
+
+/// Autoscaling configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AutoscaleConfig {
+    /// Compute scaling advice, served by the admin endpoint
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// Fewest replicas advised
+    #[serde(default = "default_min_replicas")]
+    pub min_replicas: u32,
+
+    /// Most replicas advised
+    #[serde(default = "default_max_replicas")]
+    pub max_replicas: u32,
+
+    /// Tasks queued per runner on target
+    #[serde(default = "default_target_queue_per_runner")]
+    pub target_queue_per_runner: f64,
+
+    /// Share of busy runners on target
+    #[serde(default = "default_target_utilization")]
+    pub target_utilization: f64,
+
+    /// Mean invocation latency on target, the latency is ignored without it
+    #[serde(default)]
+    pub target_latency_ms: Option<u64>,
+
+    /// Distance of the load from 1.0 under which the replica count is kept
+    #[serde(default = "default_tolerance")]
+    pub tolerance: f64,
+
+    /// Time the load must stay low before scaling down
+    #[serde(default = "default_scale_down_window_secs")]
+    pub scale_down_window_secs: u64,
+
+    /// Interval of the pushes to the backend
+    #[serde(default = "default_push_interval_secs")]
+    pub push_interval_secs: u64,
+
+    /// Backend the desired replica count is pushed to
+    #[serde(default)]
+    pub backend: Option<ScaleBackendConfig>,
+}
+
+fn default_min_replicas() -> u32 {
+    1
+}
+
+fn default_max_replicas() -> u32 {
+    10
+}
+
+fn default_target_queue_per_runner() -> f64 {
+    1.0
+}
+
+fn default_target_utilization() -> f64 {
+    0.7
+}
+
+fn default_tolerance() -> f64 {
+    0.1
+}
+
+fn default_scale_down_window_secs() -> u64 {
+    300
+}
+
+fn default_push_interval_secs() -> u64 {
+    15
+}
+
+impl Default for AutoscaleConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            min_replicas: default_min_replicas(),
+            max_replicas: default_max_replicas(),
+            target_queue_per_runner: default_target_queue_per_runner(),
+            target_utilization: default_target_utilization(),
+            target_latency_ms: None,
+            tolerance: default_tolerance(),
+            scale_down_window_secs: default_scale_down_window_secs(),
+            push_interval_secs: default_push_interval_secs(),
+            backend: None,
+        }
+    }
+}
+
+impl AutoscaleConfig {
+    /// Load of the signals, and the signal it is due to
+    pub fn load(&self, signals: &ScaleSignals) -> (f64, ScaleReason) {
+        let queue_target = self.target_queue_per_runner * signals.capacity.max(1) as f64;
+        let mut load = (
+            ratio(signals.queue_depth as f64, queue_target),
+            ScaleReason::QueueDepth,
+        );
+
+        let utilization = ratio(signals.utilization, self.target_utilization);
+        if utilization > load.0 {
+            load = (utilization, ScaleReason::Utilization);
+        }
+
+        if let (Some(latency), Some(target)) = (signals.latency_ms, self.target_latency_ms) {
+            let latency = ratio(latency as f64, target as f64);
+            if latency > load.0 {
+                load = (latency, ScaleReason::Latency);
+            }
+        }
+        load
+    }
+
+    /// Replicas desired for a load, given the current ones
+    pub fn desired_replicas(&self, current: u32, load: f64) -> u32 {
+        let desired = if (load - 1.0).abs() <= self.tolerance {
+            current
+        } else {
+            (current.max(1) as f64 * load).ceil() as u32
+        };
+        desired.clamp(self.min_replicas, self.max_replicas.max(self.min_replicas))
+    }
+}
+
+/// Ratio of a signal to its target, 0 for targets that are not positive
+fn ratio(value: f64, target: f64) -> f64 {
+    if target > 0.0 {
+        value / target
+    } else {
+        0.0
+    }
+}
+
+/// Signal the load is due to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScaleReason {
+    QueueDepth,
+    Utilization,
+    Latency,
+}
+
+/// Signals of a worker, read from its status board
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ScaleSignals {
+    /// Runners publishing their status
+    pub runners: u32,
+
+    /// Runners the worker may run
+    pub capacity: u32,
+
+    /// Runners with an invocation in progress
+    pub busy: u32,
+
+    /// Tasks queued over all the runners
+    pub queue_depth: usize,
+
+    /// Share of the runners busy
+    pub utilization: f64,
+
+    /// Mean latency of the invocations completed recently, if any
+    pub latency_ms: Option<u64>,
+}
+
+impl ScaleSignals {
+    /// Signals of the runners' statuses, at `now_ms`
+    pub fn from_runners(runners: &[RunnerStatus], capacity: u32, now_ms: u64) -> Self {
+        let busy = runners
+            .iter()
+            .filter(|status| status.invocation.is_some())
+            .count() as u32;
+        let queue_depth = runners
+            .iter()
+            .flat_map(|status| &status.lanes)
+            .map(|lane| lane.depth)
+            .sum();
+
+        let latencies: Vec<u64> = runners
+            .iter()
+            .filter_map(|status| status.last_invocation.as_ref())
+            .filter(|invocation| {
+                let completed_at = invocation.started_at_ms + invocation.elapsed_ms;
+                now_ms.saturating_sub(completed_at) <= LATENCY_WINDOW_MS
+            })
+            .map(|invocation| invocation.elapsed_ms)
+            .collect();
+        let latency_ms =
+            (!latencies.is_empty()).then(|| latencies.iter().sum::<u64>() / latencies.len() as u64);
+
+        Self {
+            runners: runners.len() as u32,
+            capacity,
+            busy,
+            queue_depth,
+            utilization: busy as f64 / capacity.max(1) as f64,
+            latency_ms,
+        }
+    }
+}
+
+/// Scaling advice served by the admin endpoint and pushed to the backend
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ScaleRecommendation {
+    /// Load over the scale-down window, 1.0 being on target
+    pub load: f64,
+
+    /// Signal the load is due to
+    pub reason: ScaleReason,
+
+    /// Replicas last reported by the backend, if any
+    pub current_replicas: Option<u32>,
+
+    /// Replicas desired, if the current ones are known
+    pub desired_replicas: Option<u32>,
+
+    /// Signals the load is computed from
+    pub signals: ScaleSignals,
+}
+
+/// Backend applying the desired replica count, such as an orchestrator API
+#[async_trait]
+pub trait ScaleBackend: Send + Sync {
+    /// Replicas currently running, `None` if the backend does not know them
+    async fn current_replicas(&self) -> Result<Option<u32>, String> {
+        Ok(None)
+    }
+
+    /// Push the scaling advice
+    async fn push(&self, recommendation: &ScaleRecommendation) -> Result<(), String>;
+}
+
+/// Scale backend configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ScaleBackendConfig {
+    /// Post the advice as JSON to a URL
+    Webhook {
+        url: String,
+        #[serde(default)]
+        token: Option<String>,
+    },
+
+    /// Scale a task group of a Nomad job
+    Nomad {
+        /// Address of the Nomad API, e.g. `http://127.0.0.1:4646`
+        address: String,
+        job: String,
+        group: String,
+        #[serde(default)]
+        token: Option<String>,
+    },
+}
+
+impl ScaleBackendConfig {
+    /// Backend of the configuration
+    pub fn build(&self) -> Arc<dyn ScaleBackend> {
+        match self {
+            ScaleBackendConfig::Webhook { url, token } => Arc::new(WebhookScaleBackend {
+                client: reqwest::Client::new(),
+                url: url.clone(),
+                token: token.clone(),
+            }),
+            ScaleBackendConfig::Nomad {
+                address,
+                job,
+                group,
+                token,
+            } => Arc::new(NomadScaleBackend {
+                client: reqwest::Client::new(),
+                url: format!("{}/v1/job/{}/scale", address.trim_end_matches('/'), job),
+                group: group.clone(),
+                token: token.clone(),
+            }),
+        }
+    }
+}
+
+/// Posts the advice as JSON, for controllers of other orchestrators
+pub struct WebhookScaleBackend {
+    client: reqwest::Client,
+    url: String,
+    token: Option<String>,
+}
+
+#[async_trait]
+impl ScaleBackend for WebhookScaleBackend {
+    async fn push(&self, recommendation: &ScaleRecommendation) -> Result<(), String> {
+        let mut request = self.client.post(&self.url).json(recommendation);
+        if let Some(token) = &self.token {
+            request = request.bearer_auth(token);
+        }
+        let response = request.send().await.map_err(|e| e.to_string())?;
+        if !response.status().is_success() {
+            return Err(format!("webhook responded {}", response.status()));
+        }
+        Ok(())
+    }
+}
+
+/// Scales a task group of a Nomad job with the job scale API
+pub struct NomadScaleBackend {
+    client: reqwest::Client,
+    url: String,
+    group: String,
+    token: Option<String>,
+}
+
+impl NomadScaleBackend {
+    fn request(&self, method: reqwest::Method) -> reqwest::RequestBuilder {
+        let request = self.client.request(method, &self.url);
+        match &self.token {
+            Some(token) => request.header("X-Nomad-Token", token),
+            None => request,
+        }
+    }
+}
+
+#[async_trait]
+impl ScaleBackend for NomadScaleBackend {
+    async fn current_replicas(&self) -> Result<Option<u32>, String> {
+        let response = self
+            .request(reqwest::Method::GET)
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+        if !response.status().is_success() {
+            return Err(format!("nomad responded {}", response.status()));
+        }
+
+        let status: serde_json::Value = response.json().await.map_err(|e| e.to_string())?;
+        Ok(status["TaskGroups"][&self.group]["Desired"]
+            .as_u64()
+            .map(|desired| desired as u32))
+    }
+
+    async fn push(&self, recommendation: &ScaleRecommendation) -> Result<(), String> {
+        let Some(desired) = recommendation.desired_replicas else {
+            return Ok(());
+        };
+        if recommendation.current_replicas == Some(desired) {
+            return Ok(());
+        }
+
+        let body = serde_json::json!({
+            "Count": desired,
+            "Target": { "Group": self.group },
+            "Message": format!("r3e-worker: load {:.2} ({:?})", recommendation.load, recommendation.reason),
+        });
+        let response = self
+            .request(reqwest::Method::POST)
+            .json(&body)
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+        if !response.status().is_success() {
+            return Err(format!("nomad responded {}", response.status()));
+        }
+        Ok(())
+    }
+}
+
+/// Computes the scaling advice of a worker, and pushes it to the backend
+pub struct Autoscaler {
+    config: AutoscaleConfig,
+    board: Arc<StatusBoard>,
+    capacity: u32,
+    backend: Option<Arc<dyn ScaleBackend>>,
+
+    /// Loads computed over the scale-down window
+    history: Mutex<VecDeque<(Instant, f64)>>,
+
+    /// Replicas last reported by the backend
+    current_replicas: Mutex<Option<u32>>,
+}
+
+impl Autoscaler {
+    /// Advise on the load of the runners publishing to `board`, out of `capacity` runners
+    pub fn new(config: AutoscaleConfig, board: Arc<StatusBoard>, capacity: u32) -> Self {
+        Self {
+            config,
+            board,
+            capacity,
+            backend: None,
+            history: Mutex::new(VecDeque::new()),
+            current_replicas: Mutex::new(None),
+        }
+    }
+
+    /// Push the advice to a backend
+    pub fn with_backend(mut self, backend: Option<Arc<dyn ScaleBackend>>) -> Self {
+        self.backend = backend;
+        self
+    }
+
+    /// Current scaling advice
+    pub fn recommend(&self) -> std::io::Result<ScaleRecommendation> {
+        let signals = ScaleSignals::from_runners(&self.board.runners()?, self.capacity, now_ms());
+        let (load, reason) = self.config.load(&signals);
+        let load = self.stabilize(load, Instant::now());
+
+        let current_replicas = *self.current_replicas.lock().unwrap();
+        Ok(ScaleRecommendation {
+            load,
+            reason,
+            current_replicas,
+            desired_replicas: current_replicas
+                .map(|current| self.config.desired_replicas(current, load)),
+            signals,
+        })
+    }
+
+    /// Highest load over the scale-down window, so that scaling up is immediate and scaling
+    /// down waits for the load to stay low
+    fn stabilize(&self, load: f64, now: Instant) -> f64 {
+        let window = Duration::from_secs(self.config.scale_down_window_secs);
+        let mut history = self.history.lock().unwrap();
+        while history
+            .front()
+            .is_some_and(|(at, _)| now.duration_since(*at) > window)
+        {
+            history.pop_front();
+        }
+        history.push_back((now, load));
+        history.iter().map(|(_, load)| *load).fold(0.0, f64::max)
+    }
+
+    /// Push the advice to the backend every push interval, until `stop` is set
+    pub fn spawn_push(
+        self: Arc<Self>,
+        stop: Arc<AtomicBool>,
+    ) -> Option<std::thread::JoinHandle<()>> {
+        let backend = self.backend.clone()?;
+        let interval = Duration::from_secs(self.config.push_interval_secs.max(1));
+        Some(std::thread::spawn(move || {
+            let rt = tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .expect("autoscale: build reactor");
+
+            rt.block_on(async move {
+                while !stop.load(Ordering::Relaxed) {
+                    match backend.current_replicas().await {
+                        Ok(replicas) => *self.current_replicas.lock().unwrap() = replicas,
+                        Err(err) => log::warn!("autoscale: get current replicas failed: {}", err),
+                    }
+
+                    match self.recommend() {
+                        Ok(recommendation) => {
+                            log::debug!(
+                                "autoscale: load {:.2} ({:?}), desired replicas {:?}",
+                                recommendation.load,
+                                recommendation.reason,
+                                recommendation.desired_replicas
+                            );
+                            if let Err(err) = backend.push(&recommendation).await {
+                                log::warn!("autoscale: push failed: {}", err);
+                            }
+                        }
+                        Err(err) => log::warn!("autoscale: read status board failed: {}", err),
+                    }
+
+                    tokio::time::sleep(interval).await;
+                }
+            });
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::admin::InvocationStatus;
+    use crate::lanes::LaneDepth;
+
+    fn runner(busy: bool, queued: usize, last_ms: Option<u64>, now: u64) -> RunnerStatus {
+        RunnerStatus {
+            invocation: busy.then(|| InvocationStatus::new(1)),
+            last_invocation: last_ms.map(|elapsed_ms| {
+                let mut invocation = InvocationStatus::new(1);
+                invocation.started_at_ms = now - elapsed_ms;
+                invocation.elapsed_ms = elapsed_ms;
+                invocation
+            }),
+            lanes: vec![LaneDepth {
+                lane: "interactive".to_string(),
+                weight: 4,
+                depth: queued,
+                served: 0,
+            }],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_signals_and_load() {
+        let now = now_ms();
+        let runners = [
+            runner(true, 3, Some(200), now),
+            runner(true, 1, Some(400), now),
+            runner(false, 0, Some(100), now - LATENCY_WINDOW_MS - 1),
+            runner(false, 0, None, now),
+        ];
+        let signals = ScaleSignals::from_runners(&runners, 4, now);
+        assert_eq!(signals.runners, 4);
+        assert_eq!(signals.busy, 2);
+        assert_eq!(signals.queue_depth, 4);
+        assert_eq!(signals.utilization, 0.5);
+        assert_eq!(signals.latency_ms, Some(300));
+
+        // 4 tasks queued for 4 runners is on target, but runners are busier than targeted
+        let mut config = AutoscaleConfig {
+            target_utilization: 0.25,
+            ..Default::default()
+        };
+        assert_eq!(config.load(&signals), (2.0, ScaleReason::Utilization));
+
+        config.target_latency_ms = Some(100);
+        assert_eq!(config.load(&signals), (3.0, ScaleReason::Latency));
+
+        let idle = ScaleSignals::from_runners(&[], 4, now);
+        assert_eq!(config.load(&idle), (0.0, ScaleReason::QueueDepth));
+    }
+
+    #[test]
+    fn test_desired_replicas() {
+        let config = AutoscaleConfig {
+            min_replicas: 2,
+            max_replicas: 8,
+            ..Default::default()
+        };
+        assert_eq!(config.desired_replicas(4, 1.05), 4);
+        assert_eq!(config.desired_replicas(4, 1.3), 6);
+        assert_eq!(config.desired_replicas(4, 3.0), 8);
+        assert_eq!(config.desired_replicas(4, 0.5), 2);
+        assert_eq!(config.desired_replicas(4, 0.0), 2);
+        assert_eq!(config.desired_replicas(0, 1.5), 2);
+    }
+
+    #[test]
+    fn test_scale_down_window() {
+        let dir = tempfile::tempdir().unwrap();
+        let board = Arc::new(StatusBoard::open(dir.path()).unwrap());
+        let config = AutoscaleConfig {
+            scale_down_window_secs: 60,
+            ..Default::default()
+        };
+        let autoscaler = Autoscaler::new(config, board, 4);
+
+        let start = Instant::now();
+        assert_eq!(autoscaler.stabilize(2.0, start), 2.0);
+        assert_eq!(
+            autoscaler.stabilize(0.5, start + Duration::from_secs(30)),
+            2.0
+        );
+        assert_eq!(
+            autoscaler.stabilize(3.0, start + Duration::from_secs(45)),
+            3.0
+        );
+        assert_eq!(
+            autoscaler.stabilize(0.5, start + Duration::from_secs(106)),
+            0.5
+        );
+
+        let recommendation = autoscaler.recommend().unwrap();
+        assert_eq!(recommendation.signals.runners, 0);
+        assert_eq!(recommendation.desired_replicas, None);
+    }
+}
@@ -1,10 +1,14 @@
 // Copyright @ 2023 - 2024, R3E Network
 // All Rights Reserved
 
+use std::sync::Arc;
 use std::time::Duration;
 
+use r3e_core::rpc_pool::RpcEndpointPool;
 use r3e_event::source::{
-    ethereum::EthereumTaskSource, mock::MockTaskSource, neo::NeoTaskSource, TaskSource,
+    checkpoint::FileSourceCheckpoints, ethereum::EthereumTaskSource, mock::MockTaskSource,
+    neo::NeoTaskSource, neo_subscription::NeoSubscriptionTaskSource,
+    synthetic::SyntheticTaskSource, TaskSource,
 };
 
 use crate::TaskConfig;
@@ -27,11 +31,22 @@ impl TaskSourceBuilder {
             "neo" => {
                 let source = NeoTaskSource::new(sleep, uid);
 
-                // Configure the source with RPC URL if provided
-                let source = if let Some(rpc_url) = &self.config.rpc_url {
-                    source.with_rpc_url(rpc_url)
-                } else {
+                // Configure the source with the RPC endpoints if provided
+                let urls: Vec<String> = self
+                    .config
+                    .rpc_url
+                    .iter()
+                    .chain(self.config.rpc_urls.iter())
+                    .cloned()
+                    .collect();
+                let source = if urls.is_empty() {
                     source
+                } else {
+                    source.with_rpc_pool(Arc::new(RpcEndpointPool::new(
+                        "neo",
+                        urls,
+                        self.config.rpc_pool.clone(),
+                    )))
                 };
 
                 // Configure the source with filter if provided
@@ -41,6 +56,70 @@ impl TaskSourceBuilder {
                     source
                 };
 
+                // Configure the source with contract triggers
+                let source = source
+                    .with_contract_triggers(&self.config.contract_triggers)
+                    .expect("task source: invalid contract triggers");
+
+                // Configure the source with token transfer triggers
+                let source = source
+                    .with_transfer_triggers(&self.config.transfer_triggers)
+                    .expect("task source: invalid transfer triggers");
+
+                Box::new(source)
+            }
+            "neo_subscription" => {
+                // Push new Neo blocks, long-polling and backfilling through the RPC endpoints
+                let urls: Vec<String> = self
+                    .config
+                    .rpc_url
+                    .iter()
+                    .chain(self.config.rpc_urls.iter())
+                    .cloned()
+                    .collect();
+                let rpc_pool = if urls.is_empty() {
+                    RpcEndpointPool::new(
+                        "neo",
+                        [
+                            "https://testnet1.neo.org:443",
+                            "https://testnet2.neo.org:443",
+                        ],
+                        self.config.rpc_pool.clone(),
+                    )
+                } else {
+                    RpcEndpointPool::new("neo", urls, self.config.rpc_pool.clone())
+                };
+
+                let source = NeoSubscriptionTaskSource::new(Arc::new(rpc_pool))
+                    .with_poll_interval(sleep)
+                    .with_transactions(true)
+                    .with_canonical_reemit(self.config.reorg_reemit);
+                let source = match &self.config.ws_url {
+                    Some(ws_url) => source.with_ws_url(ws_url),
+                    None => source,
+                };
+
+                // Resume after the last block and transaction processed by a previous runner
+                let mut source = match &self.config.checkpoint_dir {
+                    Some(dir) => {
+                        let checkpoints = FileSourceCheckpoints::open(dir).unwrap_or_else(|err| {
+                            panic!("task source: open checkpoints {}: {}", dir, err)
+                        });
+                        source.with_checkpoints("neo_subscription", Arc::new(checkpoints))
+                    }
+                    None => source,
+                };
+
+                for (fid, path) in &self.config.functions {
+                    let code = std::fs::read_to_string(path).unwrap_or_else(|err| {
+                        panic!("task source: read function {} from {}: {}", fid, path, err)
+                    });
+                    source = source.with_function(*fid, code);
+                }
+                for (fid, metadata) in &self.config.function_metadata {
+                    source = source.with_function_metadata(*fid, metadata);
+                }
+
                 Box::new(source)
             }
             "ethereum" => {
@@ -63,6 +142,34 @@ impl TaskSourceBuilder {
                     source
                 };
 
+                // Configure the source with contract triggers
+                let source = source
+                    .with_contract_triggers(&self.config.contract_triggers)
+                    .expect("task source: invalid contract triggers");
+
+                Box::new(source)
+            }
+            "synthetic" => {
+                // Deliver synthetic events from a file, waiting for appended ones
+                let events_file = self
+                    .config
+                    .events_file
+                    .as_deref()
+                    .expect("task source: events_file is required for synthetic events");
+                let mut source = SyntheticTaskSource::new(events_file)
+                    .with_follow(true)
+                    .with_poll_interval(sleep);
+
+                for (fid, path) in &self.config.functions {
+                    let code = std::fs::read_to_string(path).unwrap_or_else(|err| {
+                        panic!("task source: read function {} from {}: {}", fid, path, err)
+                    });
+                    source = source.with_function(*fid, code);
+                }
+                for (fid, metadata) in &self.config.function_metadata {
+                    source = source.with_function_metadata(*fid, metadata);
+                }
+
                 Box::new(source)
             }
             "mock" => {
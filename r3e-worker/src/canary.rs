@@ -0,0 +1,190 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+//! Sandbox escape canary.
+//!
+//! JavaScript only reaches outside the runtime through ops, none of which runs programs,
+//! accepts connections or changes the permissions of files, and without a JIT nothing makes
+//! memory executable either. A runner making one of these canary syscalls is running code that
+//! escaped the runtime. The syscall filter traps them instead of running them: the `SIGSYS`
+//! handler fails the syscall with `EPERM` and counts it, and the runner takes the counts after
+//! every invocation, raising a critical alert about the function that ran.
+
+use std::sync::atomic::{AtomicI64, AtomicU32, Ordering};
+
+/// Distinct syscalls counted between two takes, the others are counted together
+const SLOTS: usize = 16;
+
+/// Marks a slot no syscall was counted in
+const EMPTY: i64 = -1;
+
+#[allow(clippy::declare_interior_mutable_const)]
+const EMPTY_SLOT: AtomicI64 = AtomicI64::new(EMPTY);
+#[allow(clippy::declare_interior_mutable_const)]
+const ZERO: AtomicU32 = AtomicU32::new(0);
+
+// Lock-free, as the handler may interrupt a thread taking the counts
+static SYSCALLS: [AtomicI64; SLOTS] = [EMPTY_SLOT; SLOTS];
+static COUNTS: [AtomicU32; SLOTS] = [ZERO; SLOTS];
+static UNCOUNTED: AtomicU32 = AtomicU32::new(0);
+
+/// Canary syscall made since the last take
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CanaryHit {
+    /// Syscall number
+    pub nr: i64,
+    /// Syscall name, if the filter knows of it
+    pub name: Option<&'static str>,
+    /// Times it was made
+    pub count: u32,
+}
+
+impl std::fmt::Display for CanaryHit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.name {
+            Some(name) => write!(f, "{} x{}", name, self.count),
+            None => write!(f, "syscall {} x{}", self.nr, self.count),
+        }
+    }
+}
+
+/// Count a canary syscall, async-signal-safe
+#[cfg_attr(
+    not(all(
+        target_os = "linux",
+        any(target_arch = "x86_64", target_arch = "aarch64")
+    )),
+    allow(dead_code)
+)]
+fn record(nr: i64) {
+    for (syscall, count) in SYSCALLS.iter().zip(&COUNTS) {
+        let claimed = syscall.compare_exchange(EMPTY, nr, Ordering::AcqRel, Ordering::Acquire);
+        if matches!(claimed, Ok(_)) || claimed == Err(nr) {
+            count.fetch_add(1, Ordering::AcqRel);
+            return;
+        }
+    }
+    UNCOUNTED.fetch_add(1, Ordering::AcqRel);
+}
+
+/// Take the canary syscalls made since the last take. A syscall counted while the counts are
+/// taken may be reported with the next take.
+pub fn take_hits() -> Vec<CanaryHit> {
+    let mut hits = Vec::new();
+    for (syscall, count) in SYSCALLS.iter().zip(&COUNTS) {
+        let nr = syscall.load(Ordering::Acquire);
+        if nr == EMPTY {
+            continue;
+        }
+        let count = count.swap(0, Ordering::AcqRel);
+        if count > 0 {
+            hits.push(CanaryHit {
+                nr,
+                name: crate::seccomp::syscall_name(nr),
+                count,
+            });
+        }
+    }
+
+    let uncounted = UNCOUNTED.swap(0, Ordering::AcqRel);
+    if uncounted > 0 {
+        hits.push(CanaryHit {
+            nr: EMPTY,
+            name: Some("other"),
+            count: uncounted,
+        });
+    }
+    hits
+}
+
+/// `siginfo_t` of a `SIGSYS` raised by a seccomp trap, on 64-bit Linux
+#[cfg(all(
+    target_os = "linux",
+    any(target_arch = "x86_64", target_arch = "aarch64")
+))]
+#[repr(C)]
+#[allow(dead_code)]
+struct SigsysInfo {
+    signo: libc::c_int,
+    errno: libc::c_int,
+    code: libc::c_int,
+    call_addr: *mut libc::c_void,
+    syscall: libc::c_int,
+    arch: libc::c_uint,
+}
+
+#[cfg(all(
+    target_os = "linux",
+    any(target_arch = "x86_64", target_arch = "aarch64")
+))]
+extern "C" fn on_sigsys(
+    _signal: libc::c_int,
+    info: *mut libc::siginfo_t,
+    context: *mut libc::c_void,
+) {
+    // Safety: the kernel passes the siginfo and ucontext of the trapped syscall, as the handler
+    // is installed with SA_SIGINFO
+    unsafe {
+        let info = &*(info as *const SigsysInfo);
+        record(info.syscall as i64);
+
+        // The value in the return register is what the trapped syscall returns
+        let context = &mut *(context as *mut libc::ucontext_t);
+        #[cfg(target_arch = "x86_64")]
+        {
+            context.uc_mcontext.gregs[libc::REG_RAX as usize] = -(libc::EPERM as i64);
+        }
+        #[cfg(target_arch = "aarch64")]
+        {
+            context.uc_mcontext.regs[0] = -(libc::EPERM as i64) as u64;
+        }
+    }
+}
+
+/// Install the `SIGSYS` handler counting the canary syscalls the filter traps
+#[cfg(all(
+    target_os = "linux",
+    any(target_arch = "x86_64", target_arch = "aarch64")
+))]
+pub fn install_handler() -> std::io::Result<()> {
+    // Safety: the handler only touches atomics and the trapped thread's context
+    unsafe {
+        let mut action: libc::sigaction = std::mem::zeroed();
+        action.sa_sigaction = on_sigsys as usize;
+        action.sa_flags = libc::SA_SIGINFO;
+        libc::sigemptyset(&mut action.sa_mask);
+        if libc::sigaction(libc::SIGSYS, &action, std::ptr::null_mut()) != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_take_hits() {
+        assert!(take_hits().is_empty());
+
+        record(libc::SYS_execve);
+        record(libc::SYS_execve);
+        record(libc::SYS_bind);
+        let hits = take_hits();
+        assert_eq!(hits.len(), 2);
+        assert_eq!(hits[0].nr, libc::SYS_execve);
+        assert_eq!(hits[0].count, 2);
+        assert_eq!(hits[0].to_string(), "execve x2");
+        assert_eq!(hits[1].to_string(), "bind x1");
+        assert!(take_hits().is_empty());
+
+        // The slots of the syscalls taken are reused for the same syscalls
+        for nr in 0..SLOTS as i64 + 2 {
+            record(1000 + nr);
+        }
+        let hits = take_hits();
+        assert_eq!(hits.len(), SLOTS - 2 + 1);
+        assert_eq!(hits.last().unwrap().count, 4);
+    }
+}
@@ -1,6 +1,7 @@
 // Copyright @ 2023 - 2024, R3E Network
 // All Rights Reserved
 
+use r3e_core::{CodedError, ErrorCode};
 use std::process::{Command, Stdio};
 use std::time::Duration;
 use thiserror::Error;
@@ -28,6 +29,19 @@ pub enum ContainerError {
     Io(#[from] std::io::Error),
 }
 
+impl CodedError for ContainerError {
+    fn code(&self) -> ErrorCode {
+        match self {
+            ContainerError::Execution(_) => ErrorCode::ExecutionFailed,
+            ContainerError::Timeout => ErrorCode::Timeout,
+            ContainerError::Creation(_)
+            | ContainerError::Start(_)
+            | ContainerError::Stop(_)
+            | ContainerError::Io(_) => ErrorCode::Internal,
+        }
+    }
+}
+
 /// Container isolation configuration
 #[derive(Debug, Clone)]
 pub struct ContainerConfig {
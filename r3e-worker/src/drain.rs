@@ -0,0 +1,310 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+//! Graceful draining of a worker.
+//!
+//! On stop the worker drains instead of killing its runners at once: runners stop acquiring
+//! tasks, in-flight invocations get up to `WorkerConfig.graceful` to finish, and only runners
+//! still busy after that are killed. With a checkpoint directory configured, every task is
+//! journaled before it runs and removed once it completes, so the tasks of killed runners
+//! and tasks acquired after the stop are replayed by the next worker instead of being lost.
+
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use r3e_core::mtls::MtlsConfig;
+use r3e_event::source::{event, Task};
+
+/// Drain configuration
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DrainConfig {
+    /// Directory journaling tasks until they complete
+    #[serde(default)]
+    pub checkpoint_dir: Option<PathBuf>,
+
+    /// Address of the local admin endpoint, e.g. `127.0.0.1:9100`
+    #[serde(default)]
+    pub admin_addr: Option<SocketAddr>,
+
+    /// Require client certificates on the admin endpoint
+    #[serde(default)]
+    pub admin_mtls: Option<MtlsConfig>,
+
+    /// Directory runners publish their status to for the admin endpoint,
+    /// a temporary directory by default
+    #[serde(default)]
+    pub status_dir: Option<PathBuf>,
+}
+
+/// Drain state of a worker
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DrainState {
+    /// Accepting tasks
+    Running,
+    /// Waiting for in-flight invocations to finish
+    Draining,
+    /// All runners exited within the graceful period
+    Drained,
+    /// Runners still busy after the graceful period were killed
+    TimedOut,
+}
+
+/// Drain status reported by the admin endpoint
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DrainStatus {
+    /// Drain state
+    pub state: DrainState,
+
+    /// Live runner processes
+    pub runners: u32,
+
+    /// Time since the drain started, in milliseconds
+    pub elapsed_ms: Option<u64>,
+
+    /// Graceful period, in milliseconds
+    pub graceful_ms: u64,
+
+    /// Runners killed at the end of the graceful period
+    pub killed: u32,
+
+    /// Tasks in the checkpoint journal
+    pub checkpointed: usize,
+}
+
+struct DrainInner {
+    state: DrainState,
+    started: Option<Instant>,
+    runners: u32,
+    killed: u32,
+}
+
+/// Drain state shared by the worker and its admin endpoint
+pub struct Drainer {
+    inner: Mutex<DrainInner>,
+    graceful: Duration,
+    checkpoints: Option<CheckpointStore>,
+}
+
+impl Drainer {
+    /// Create a new drainer
+    pub fn new(graceful: Duration, checkpoints: Option<CheckpointStore>) -> Self {
+        Self {
+            inner: Mutex::new(DrainInner {
+                state: DrainState::Running,
+                started: None,
+                runners: 0,
+                killed: 0,
+            }),
+            graceful,
+            checkpoints,
+        }
+    }
+
+    /// Start draining; returns false if the drain had already started
+    pub fn begin(&self) -> bool {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.state != DrainState::Running {
+            return false;
+        }
+
+        inner.state = DrainState::Draining;
+        inner.started = Some(Instant::now());
+        true
+    }
+
+    /// Record the number of live runners
+    pub fn set_runners(&self, runners: u32) {
+        self.inner.lock().unwrap().runners = runners;
+    }
+
+    /// Finish the drain, `killed` runners did not exit within the graceful period
+    pub fn finish(&self, killed: u32) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.state = if killed == 0 {
+            DrainState::Drained
+        } else {
+            DrainState::TimedOut
+        };
+        inner.killed = killed;
+        inner.runners = 0;
+    }
+
+    /// Current drain state
+    pub fn state(&self) -> DrainState {
+        self.inner.lock().unwrap().state
+    }
+
+    /// Checkpoint journal
+    pub fn checkpoints(&self) -> Option<&CheckpointStore> {
+        self.checkpoints.as_ref()
+    }
+
+    /// Current drain status
+    pub fn status(&self) -> DrainStatus {
+        let inner = self.inner.lock().unwrap();
+        DrainStatus {
+            state: inner.state,
+            runners: inner.runners,
+            elapsed_ms: inner.started.map(|at| at.elapsed().as_millis() as u64),
+            graceful_ms: self.graceful.as_millis() as u64,
+            killed: inner.killed,
+            checkpointed: self.checkpoints.as_ref().map_or(0, CheckpointStore::len),
+        }
+    }
+}
+
+/// Journaled task
+#[derive(Serialize, Deserialize)]
+struct CheckpointRecord {
+    uid: u64,
+    fid: u64,
+    event: event::Event,
+}
+
+/// Journal of tasks not yet completed, one JSON file per task.
+///
+/// File names start with the journaling time, so tasks replay in their original order.
+#[derive(Debug, Clone)]
+pub struct CheckpointStore {
+    dir: PathBuf,
+}
+
+impl CheckpointStore {
+    /// Open the journal in `dir`, creating the directory if needed
+    pub fn open(dir: impl AsRef<Path>) -> std::io::Result<Self> {
+        std::fs::create_dir_all(dir.as_ref())?;
+        Ok(Self {
+            dir: dir.as_ref().to_path_buf(),
+        })
+    }
+
+    /// Journal a task, returning its checkpoint ID
+    pub fn save(&self, task: &Task) -> std::io::Result<String> {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos();
+        let id = format!("{:024}-{}", nanos, uuid::Uuid::new_v4().simple());
+
+        let record = CheckpointRecord {
+            uid: task.uid,
+            fid: task.fid,
+            event: task.event.clone(),
+        };
+        let data = serde_json::to_vec(&record)?;
+
+        // Write then rename, so a crash never leaves a partial record behind
+        let tmp = self.dir.join(format!("{}.tmp", id));
+        std::fs::write(&tmp, data)?;
+        std::fs::rename(&tmp, self.path(&id))?;
+        Ok(id)
+    }
+
+    /// Remove a completed task
+    pub fn remove(&self, id: &str) -> std::io::Result<()> {
+        match std::fs::remove_file(self.path(id)) {
+            Err(err) if err.kind() != std::io::ErrorKind::NotFound => Err(err),
+            _ => Ok(()),
+        }
+    }
+
+    /// All journaled tasks, oldest first. Unreadable records are logged and skipped.
+    pub fn load_all(&self) -> std::io::Result<Vec<(String, Task)>> {
+        let mut ids = self.ids()?;
+        ids.sort();
+
+        let mut tasks = Vec::with_capacity(ids.len());
+        for id in ids {
+            let record = std::fs::read(self.path(&id))
+                .map_err(|err| err.to_string())
+                .and_then(|data| {
+                    serde_json::from_slice::<CheckpointRecord>(&data).map_err(|err| err.to_string())
+                });
+            match record {
+                Ok(record) => {
+                    tasks.push((id, Task::new(record.uid, record.fid, record.event)));
+                }
+                Err(err) => log::error!("drain: skip unreadable checkpoint {}: {}", id, err),
+            }
+        }
+
+        Ok(tasks)
+    }
+
+    /// Number of journaled tasks
+    pub fn len(&self) -> usize {
+        self.ids().map_or(0, |ids| ids.len())
+    }
+
+    /// Whether the journal is empty
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn ids(&self) -> std::io::Result<Vec<String>> {
+        let mut ids = Vec::new();
+        for entry in std::fs::read_dir(&self.dir)? {
+            let name = entry?.file_name();
+            if let Some(id) = name.to_str().and_then(|name| name.strip_suffix(".json")) {
+                ids.push(id.to_string());
+            }
+        }
+        Ok(ids)
+    }
+
+    fn path(&self, id: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use r3e_event::source::MockEvent;
+
+    #[test]
+    fn test_checkpoint_store() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = CheckpointStore::open(dir.path()).unwrap();
+        assert!(store.is_empty());
+
+        let first = store
+            .save(&Task::new(1, 10, event::Event::Mock(MockEvent::default())))
+            .unwrap();
+        let second = store.save(&Task::new(1, 11, event::Event::None)).unwrap();
+        assert_eq!(store.len(), 2);
+
+        let tasks = store.load_all().unwrap();
+        assert_eq!(tasks.len(), 2);
+        assert_eq!(tasks[0].0, first);
+        assert_eq!(tasks[0].1.fid, 10);
+        assert_eq!(tasks[1].0, second);
+
+        store.remove(&first).unwrap();
+        store.remove(&first).unwrap();
+        assert_eq!(store.len(), 1);
+    }
+
+    #[test]
+    fn test_drainer() {
+        let drainer = Drainer::new(Duration::from_secs(2), None);
+        assert_eq!(drainer.state(), DrainState::Running);
+
+        assert!(drainer.begin());
+        assert!(!drainer.begin());
+        drainer.set_runners(3);
+        let status = drainer.status();
+        assert_eq!(status.state, DrainState::Draining);
+        assert_eq!(status.runners, 3);
+        assert!(status.elapsed_ms.is_some());
+
+        drainer.finish(1);
+        assert_eq!(drainer.state(), DrainState::TimedOut);
+        assert_eq!(drainer.status().killed, 1);
+    }
+}
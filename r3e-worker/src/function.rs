@@ -9,9 +9,14 @@ use serde::{Deserialize, Serialize};
 use tokio::sync::RwLock;
 use uuid::Uuid;
 
+use r3e_core::{CodedError, ErrorCode};
+use r3e_deno::heap::HeapReport;
+use r3e_deno::source_map::SourceMap;
 use r3e_deno::{ExecError, JsRuntime, RuntimeConfig, SandboxConfig};
+use r3e_tee::{AttestationReport, TeeError};
 
 use crate::sandbox::SandboxManager;
+use crate::tee_executor::{TeeExecutor, TeeOutcome, TeePolicy};
 
 /// Function deployment status
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -47,12 +52,23 @@ pub struct FunctionDeployment {
     /// Function code
     pub code: String,
 
+    /// Function source map, used to map error stack frames back to the original sources
+    pub source_map: Option<String>,
+
+    /// JSON Schema the function output must conform to
+    #[serde(default)]
+    pub output_schema: Option<serde_json::Value>,
+
     /// Function runtime
     pub runtime: String,
 
     /// Function security level
     pub security_level: String,
 
+    /// TEE execution settings, for functions marked `tee: true` in their metadata
+    #[serde(default)]
+    pub tee: Option<TeePolicy>,
+
     /// Function deployment status
     pub status: DeploymentStatus,
 
@@ -83,8 +99,11 @@ impl FunctionDeployment {
             user_id,
             name,
             code,
+            source_map: None,
+            output_schema: None,
             runtime,
             security_level,
+            tee: None,
             status: DeploymentStatus::Deploying,
             error: None,
             created_at: now,
@@ -104,6 +123,15 @@ impl FunctionDeployment {
         self.status = DeploymentStatus::Failed;
         self.updated_at = Utc::now();
     }
+
+    /// Check an output against the output schema of the function, if it declares one
+    pub fn check_output(&self, output: &serde_json::Value) -> Result<(), String> {
+        match &self.output_schema {
+            Some(schema) => r3e_core::schema::validate(schema, output)
+                .map_err(|e| format!("Output does not match the function's output schema: {}", e)),
+            None => Ok(()),
+        }
+    }
 }
 
 /// Function invocation result
@@ -127,9 +155,25 @@ pub struct FunctionInvocationResult {
     /// Invocation error
     pub error: Option<String>,
 
+    /// Machine-readable code of the invocation error
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_code: Option<ErrorCode>,
+
     /// Invocation execution time in milliseconds
     pub execution_time_ms: u64,
 
+    /// Heap diagnostics, if the invocation ran out of memory
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub heap_report: Option<HeapReport>,
+
+    /// Attestation of the enclave that ran the invocation, for functions run in a TEE
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub attestation: Option<AttestationReport>,
+
+    /// Why the invocation of a TEE function ran in the sandbox instead
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tee_fallback: Option<String>,
+
     /// Invocation created at
     pub created_at: DateTime<Utc>,
 }
@@ -144,7 +188,11 @@ impl FunctionInvocationResult {
             input,
             output: None,
             error: None,
+            error_code: None,
             execution_time_ms: 0,
+            heap_report: None,
+            attestation: None,
+            tee_fallback: None,
             created_at: Utc::now(),
         }
     }
@@ -156,8 +204,9 @@ impl FunctionInvocationResult {
     }
 
     /// Set the invocation error
-    pub fn set_error(&mut self, error: String, execution_time_ms: u64) {
+    pub fn set_error(&mut self, code: ErrorCode, error: String, execution_time_ms: u64) {
         self.error = Some(error);
+        self.error_code = Some(code);
         self.execution_time_ms = execution_time_ms;
     }
 }
@@ -169,6 +218,9 @@ pub struct FunctionDeploymentService {
 
     /// Function deployments
     deployments: Arc<RwLock<Vec<FunctionDeployment>>>,
+
+    /// Executor of the functions marked `tee: true`
+    tee_executor: Option<TeeExecutor>,
 }
 
 impl FunctionDeploymentService {
@@ -177,9 +229,16 @@ impl FunctionDeploymentService {
         Self {
             sandbox_manager: SandboxManager::default(),
             deployments: Arc::new(RwLock::new(Vec::new())),
+            tee_executor: None,
         }
     }
 
+    /// Run the functions marked `tee: true` with the given executor
+    pub fn with_tee_executor(mut self, tee_executor: TeeExecutor) -> Self {
+        self.tee_executor = Some(tee_executor);
+        self
+    }
+
     /// Deploy a function
     pub async fn deploy_function(
         &self,
@@ -187,6 +246,9 @@ impl FunctionDeploymentService {
         user_id: String,
         name: String,
         code: String,
+        source_map: Option<String>,
+        output_schema: Option<serde_json::Value>,
+        metadata: Option<serde_json::Value>,
         runtime: String,
         security_level: String,
     ) -> Result<FunctionDeployment, String> {
@@ -199,6 +261,38 @@ impl FunctionDeploymentService {
             runtime.clone(),
             security_level.clone(),
         );
+        deployment.source_map = source_map;
+        deployment.output_schema = output_schema;
+
+        // Read the TEE settings, a function marked `tee: true` must say how to run
+        match metadata.as_ref().map(TeePolicy::from_metadata) {
+            Some(Ok(tee)) => deployment.tee = tee,
+            Some(Err(err)) => {
+                deployment.set_error(format!("Failed to deploy function: {}", err));
+                return Err(format!("Failed to deploy function: {}", err));
+            }
+            None => {}
+        }
+
+        // Reject an output schema invocations could never be checked against
+        if let Some(Err(err)) = deployment
+            .output_schema
+            .as_ref()
+            .map(r3e_core::schema::check_schema)
+        {
+            deployment.set_error(format!("Failed to deploy function: {}", err));
+            return Err(format!("Failed to deploy function: {}", err));
+        }
+
+        // Validate the source map before accepting the deployment
+        let source_map = match deployment.source_map.as_deref().map(SourceMap::parse) {
+            Some(Ok(source_map)) => Some(source_map),
+            Some(Err(err)) => {
+                deployment.set_error(format!("Failed to deploy function: {}", err));
+                return Err(format!("Failed to deploy function: {}", err));
+            }
+            None => None,
+        };
 
         // Get the sandbox configuration for the security level
         let sandbox_config = self
@@ -213,6 +307,7 @@ impl FunctionDeploymentService {
 
         // Create a new runtime
         let mut runtime = JsRuntime::new(runtime_config);
+        runtime.set_source_map(source_map);
 
         // Try to load the function code
         match runtime.load_main_module(code.clone()).await {
@@ -263,6 +358,9 @@ impl FunctionDeploymentService {
     }
 
     /// Invoke a function
+    ///
+    /// Invocations running out of memory return their record, with the error and the heap
+    /// report, rather than failing.
     pub async fn invoke_function(
         &self,
         id: &str,
@@ -286,6 +384,48 @@ impl FunctionDeploymentService {
         let mut result =
             FunctionInvocationResult::new(id.to_string(), user_id.to_string(), input.clone());
 
+        // Functions marked `tee: true` run in the TEE, attested by the enclave
+        if let Some(policy) = &deployment.tee {
+            let start_time = Instant::now();
+            let outcome = match &self.tee_executor {
+                Some(executor) => {
+                    executor
+                        .invoke(&result.id, policy, &deployment.code, input.clone())
+                        .await
+                }
+                None => policy.on_failure(TeeError::Initialization(
+                    "no TEE service configured".to_string(),
+                )),
+            };
+            let execution_time_ms = start_time.elapsed().as_millis() as u64;
+
+            match outcome {
+                Ok(TeeOutcome::Executed(execution)) => {
+                    // Outputs breaking the declared schema are failures
+                    if let Err(err) = deployment.check_output(&execution.result) {
+                        result.set_error(ErrorCode::InvalidOutput, err.clone(), execution_time_ms);
+                        return Err(err);
+                    }
+
+                    result.set_output(execution.result, execution_time_ms);
+                    result.attestation = Some(execution.attestation);
+                    return Ok(result);
+                }
+                Ok(TeeOutcome::Fallback(err)) => {
+                    log::warn!("function: run {} in the sandbox, TEE failed: {}", id, err);
+                    result.tee_fallback = Some(err.to_string());
+                }
+                Err(err) => {
+                    result.set_error(
+                        err.code(),
+                        format!("Failed to run function in TEE: {}", err),
+                        execution_time_ms,
+                    );
+                    return Err(format!("Failed to run function in TEE: {}", err));
+                }
+            }
+        }
+
         // Get the sandbox configuration for the security level
         let sandbox_config = self
             .sandbox_manager
@@ -300,6 +440,14 @@ impl FunctionDeploymentService {
         // Create a new runtime
         let mut runtime = JsRuntime::new(runtime_config);
 
+        // Map error stack frames back to the original sources
+        if let Some(source_map) = deployment.source_map.as_deref() {
+            match SourceMap::parse(source_map) {
+                Ok(source_map) => runtime.set_source_map(Some(source_map)),
+                Err(err) => log::warn!("function: ignore source map of {}: {}", id, err),
+            }
+        }
+
         // Start the execution timer
         let start_time = Instant::now();
 
@@ -317,15 +465,24 @@ impl FunctionDeploymentService {
                                     Ok(_) => {
                                         // Calculate the execution time
                                         let execution_time = start_time.elapsed();
+                                        let output = serde_json::json!({
+                                            "message": "Function executed successfully",
+                                            "execution_time_ms": execution_time.as_millis(),
+                                        });
+
+                                        // Outputs breaking the declared schema are failures
+                                        if let Err(err) = deployment.check_output(&output) {
+                                            result.set_error(
+                                                ErrorCode::InvalidOutput,
+                                                err.clone(),
+                                                execution_time.as_millis() as u64,
+                                            );
+                                            return Err(err);
+                                        }
 
                                         // Set the output
-                                        result.set_output(
-                                            serde_json::json!({
-                                                "message": "Function executed successfully",
-                                                "execution_time_ms": execution_time.as_millis(),
-                                            }),
-                                            execution_time.as_millis() as u64,
-                                        );
+                                        result
+                                            .set_output(output, execution_time.as_millis() as u64);
 
                                         Ok(result)
                                     }
@@ -335,10 +492,18 @@ impl FunctionDeploymentService {
 
                                         // Set the error
                                         result.set_error(
+                                            err.code(),
                                             format!("Failed to run function: {}", err),
                                             execution_time.as_millis() as u64,
                                         );
 
+                                        // Out of memory failures keep the heap diagnostics
+                                        // with the record, for the user to download
+                                        if let ExecError::OutOfMemory(report) = err {
+                                            result.heap_report = Some(*report);
+                                            return Ok(result);
+                                        }
+
                                         Err(format!("Failed to run function: {}", err))
                                     }
                                 }
@@ -349,6 +514,7 @@ impl FunctionDeploymentService {
 
                                 // Set the error
                                 result.set_error(
+                                    ErrorCode::Validation,
                                     format!("Failed to convert input to global value: {}", err),
                                     execution_time.as_millis() as u64,
                                 );
@@ -363,6 +529,7 @@ impl FunctionDeploymentService {
 
                         // Set the error
                         result.set_error(
+                            err.code(),
                             format!("Failed to evaluate module: {}", err),
                             execution_time.as_millis() as u64,
                         );
@@ -377,6 +544,7 @@ impl FunctionDeploymentService {
 
                 // Set the error
                 result.set_error(
+                    err.code(),
                     format!("Failed to load module: {}", err),
                     execution_time.as_millis() as u64,
                 );
@@ -5,6 +5,7 @@ use std::sync::Arc;
 use std::time::{Duration, Instant};
 use tracing::{debug, error, info, warn};
 
+use r3e_core::{CodedError, ErrorCode};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use tokio::sync::RwLock;
@@ -36,6 +37,10 @@ pub struct FunctionExecutionResult {
     /// Error message
     pub error: Option<String>,
 
+    /// Machine-readable error code
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_code: Option<ErrorCode>,
+
     /// Logs
     pub logs: Vec<String>,
 }
@@ -109,12 +114,13 @@ impl FunctionExecutionContext {
             execution_time_ms: self.execution_time_ms(),
             status: "success".to_string(),
             error: None,
+            error_code: None,
             logs: self.logs.read().await.clone(),
         }
     }
 
     /// Create a failed execution result
-    pub async fn failure(&self, error: String) -> FunctionExecutionResult {
+    pub async fn failure(&self, code: ErrorCode, error: String) -> FunctionExecutionResult {
         FunctionExecutionResult {
             function_id: self.function_id,
             execution_id: self.execution_id,
@@ -122,6 +128,7 @@ impl FunctionExecutionContext {
             execution_time_ms: self.execution_time_ms(),
             status: "error".to_string(),
             error: Some(error),
+            error_code: Some(code),
             logs: self.logs.read().await.clone(),
         }
     }
@@ -305,7 +312,10 @@ impl FunctionExecutor {
                                 .await;
 
                             context
-                                .failure(format!("Failed to parse container output: {}", e))
+                                .failure(
+                                    ErrorCode::InvalidOutput,
+                                    format!("Failed to parse container output: {}", e),
+                                )
                                 .await
                         }
                     }
@@ -317,7 +327,7 @@ impl FunctionExecutor {
                         .await;
 
                     context
-                        .failure(format!("Container execution failed: {}", e))
+                        .failure(e.code(), format!("Container execution failed: {}", e))
                         .await
                 }
             }
@@ -382,7 +392,10 @@ impl FunctionExecutor {
                                 .await;
 
                             context
-                                .failure(format!("Failed to parse result: {}", e))
+                                .failure(
+                                    ErrorCode::InvalidOutput,
+                                    format!("Failed to parse result: {}", e),
+                                )
                                 .await
                         }
                     }
@@ -394,7 +407,10 @@ impl FunctionExecutor {
                         .await;
 
                     context
-                        .failure(format!("Function execution failed: {}", e))
+                        .failure(
+                            ErrorCode::ExecutionFailed,
+                            format!("Function execution failed: {}", e),
+                        )
                         .await
                 }
             }
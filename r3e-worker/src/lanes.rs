@@ -0,0 +1,272 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+//! Priority lanes of the tasks queued on a runner.
+//!
+//! Each trigger type is routed to a lane, and the lanes share the runner by weighted fair
+//! scheduling: a lane with weight 4 runs four tasks for every task of a lane with weight 1
+//! while both have tasks queued, and an idle lane gives its share to the others without
+//! saving it up. Runners queue the checkpointed tasks they replay, with the `replay` trigger
+//! type, and prefetch the tasks their source has ready, so batch and replay traffic does not
+//! hold back interactive invocations.
+
+use std::collections::{BTreeMap, HashMap, VecDeque};
+
+use serde::{Deserialize, Serialize};
+
+/// Trigger type of the checkpointed tasks a runner replays
+pub const REPLAY_TRIGGER: &str = "replay";
+
+/// Pass a lane advances by per task, divided by its weight
+const STRIDE: u64 = 1 << 20;
+
+/// Lane configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LaneConfig {
+    /// Weight of each lane
+    #[serde(default = "default_lanes")]
+    pub lanes: BTreeMap<String, u32>,
+
+    /// Lane of each trigger type, e.g. `neo_block` or `replay`
+    #[serde(default = "default_triggers")]
+    pub triggers: HashMap<String, String>,
+
+    /// Lane of the trigger types not listed, and of the ones routed to unknown lanes
+    #[serde(default = "default_lane")]
+    pub default_lane: String,
+
+    /// Tasks acquired from the source a runner queues at most, 1 disables prefetching
+    #[serde(default = "default_prefetch")]
+    pub prefetch: usize,
+}
+
+fn default_lanes() -> BTreeMap<String, u32> {
+    BTreeMap::from([("interactive".to_string(), 4), ("batch".to_string(), 1)])
+}
+
+fn default_triggers() -> HashMap<String, String> {
+    HashMap::from([(REPLAY_TRIGGER.to_string(), "batch".to_string())])
+}
+
+fn default_lane() -> String {
+    "interactive".to_string()
+}
+
+fn default_prefetch() -> usize {
+    16
+}
+
+impl Default for LaneConfig {
+    fn default() -> Self {
+        Self {
+            lanes: default_lanes(),
+            triggers: default_triggers(),
+            default_lane: default_lane(),
+            prefetch: default_prefetch(),
+        }
+    }
+}
+
+/// Queue depth of a lane
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LaneDepth {
+    pub lane: String,
+    pub weight: u32,
+
+    /// Tasks queued
+    pub depth: usize,
+
+    /// Tasks dequeued since the runner started
+    pub served: u64,
+}
+
+struct Lane<T> {
+    name: String,
+    weight: u32,
+    pass: u64,
+    served: u64,
+    queue: VecDeque<T>,
+}
+
+/// Tasks queued in lanes, dequeued by weighted fair scheduling
+pub struct LaneQueue<T> {
+    lanes: Vec<Lane<T>>,
+    triggers: HashMap<String, usize>,
+    default_lane: usize,
+    // Pass of the lane served last, idle lanes restart from it
+    now: u64,
+}
+
+impl<T> LaneQueue<T> {
+    pub fn new(config: &LaneConfig) -> Self {
+        let mut lanes: Vec<Lane<T>> = config
+            .lanes
+            .iter()
+            .map(|(name, weight)| Lane {
+                name: name.clone(),
+                weight: (*weight).max(1),
+                pass: 0,
+                served: 0,
+                queue: VecDeque::new(),
+            })
+            .collect();
+
+        let mut default_lane = lanes
+            .iter()
+            .position(|lane| lane.name == config.default_lane);
+        if default_lane.is_none() {
+            lanes.push(Lane {
+                name: config.default_lane.clone(),
+                weight: 1,
+                pass: 0,
+                served: 0,
+                queue: VecDeque::new(),
+            });
+            default_lane = Some(lanes.len() - 1);
+        }
+
+        let triggers = config
+            .triggers
+            .iter()
+            .filter_map(|(trigger, lane)| {
+                let lane = lanes.iter().position(|candidate| candidate.name == *lane)?;
+                Some((trigger.clone(), lane))
+            })
+            .collect();
+
+        Self {
+            lanes,
+            triggers,
+            default_lane: default_lane.unwrap_or_default(),
+            now: 0,
+        }
+    }
+
+    /// Lane a trigger type is routed to
+    pub fn lane_of(&self, trigger: &str) -> &str {
+        let lane = self.triggers.get(trigger).copied();
+        &self.lanes[lane.unwrap_or(self.default_lane)].name
+    }
+
+    /// Queue a task in the lane of its trigger type
+    pub fn push(&mut self, trigger: &str, item: T) {
+        let now = self.now;
+        let lane = self.triggers.get(trigger).copied();
+        let lane = &mut self.lanes[lane.unwrap_or(self.default_lane)];
+
+        // An idle lane does not get to spend the turns it skipped
+        if lane.queue.is_empty() {
+            lane.pass = lane.pass.max(now);
+        }
+        lane.queue.push_back(item);
+    }
+
+    /// Dequeue the next task, from the lane furthest behind its share
+    pub fn pop(&mut self) -> Option<T> {
+        let lane = self
+            .lanes
+            .iter_mut()
+            .filter(|lane| !lane.queue.is_empty())
+            .min_by_key(|lane| lane.pass)?;
+
+        self.now = lane.pass;
+        lane.pass += STRIDE / lane.weight as u64;
+        lane.served += 1;
+        lane.queue.pop_front()
+    }
+
+    /// Dequeue all the tasks, in lane order
+    pub fn drain(&mut self) -> Vec<T> {
+        self.lanes
+            .iter_mut()
+            .flat_map(|lane| lane.queue.drain(..))
+            .collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.lanes.iter().map(|lane| lane.queue.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lanes.iter().all(|lane| lane.queue.is_empty())
+    }
+
+    /// Queue depth of each lane
+    pub fn depths(&self) -> Vec<LaneDepth> {
+        self.lanes
+            .iter()
+            .map(|lane| LaneDepth {
+                lane: lane.name.clone(),
+                weight: lane.weight,
+                depth: lane.queue.len(),
+                served: lane.served,
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_weighted_fair() {
+        let mut queue = LaneQueue::new(&LaneConfig::default());
+        assert_eq!(queue.lane_of(REPLAY_TRIGGER), "batch");
+        assert_eq!(queue.lane_of("neo_block"), "interactive");
+
+        for i in 0..10 {
+            queue.push(REPLAY_TRIGGER, ("batch", i));
+            queue.push("mock", ("interactive", i));
+        }
+        assert_eq!(queue.len(), 20);
+
+        let first: Vec<_> = (0..10).map(|_| queue.pop().unwrap().0).collect();
+        assert_eq!(first.iter().filter(|lane| **lane == "batch").count(), 2);
+        assert_eq!(
+            first.iter().filter(|lane| **lane == "interactive").count(),
+            8
+        );
+
+        // Tasks of a lane keep their order
+        assert_eq!(queue.pop(), Some(("batch", 2)));
+
+        let depths = queue.depths();
+        assert_eq!(depths[0].lane, "batch");
+        assert_eq!(depths[0].depth, 7);
+        assert_eq!(depths[0].served, 3);
+        assert_eq!(depths[1].depth, 2);
+        assert_eq!(queue.drain().len(), 9);
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn test_idle_lane_does_not_burst() {
+        let mut queue = LaneQueue::new(&LaneConfig::default());
+        for i in 0..20 {
+            queue.push(REPLAY_TRIGGER, ("batch", i));
+        }
+        for _ in 0..10 {
+            queue.pop();
+        }
+
+        // The interactive lane was idle, it gets its share from now on and not a backlog of turns
+        for i in 0..10 {
+            queue.push("mock", ("interactive", i));
+        }
+        let next: Vec<_> = (0..5).map(|_| queue.pop().unwrap().0).collect();
+        assert_eq!(next.iter().filter(|lane| **lane == "batch").count(), 1);
+    }
+
+    #[test]
+    fn test_unknown_lanes() {
+        let config = LaneConfig {
+            lanes: BTreeMap::from([("batch".to_string(), 1)]),
+            triggers: HashMap::from([("neo_block".to_string(), "missing".to_string())]),
+            ..Default::default()
+        };
+        let queue = LaneQueue::<()>::new(&config);
+        assert_eq!(queue.lane_of("neo_block"), "interactive");
+        assert_eq!(queue.depths().len(), 2);
+    }
+}
@@ -1,27 +1,51 @@
 // Copyright @ 2023 - 2024, R3E Network
 // All Rights Reserved
 
+pub mod admin;
 pub mod assign;
+pub mod autoscale;
 pub mod builder;
+pub mod canary;
 pub mod container;
+pub mod drain;
 pub mod function;
 pub mod function_executor;
+pub mod lanes;
 pub mod neo_task_source;
 pub mod pool;
 pub mod runner;
 pub mod sandbox;
 pub mod sandbox_executor;
+pub mod sched;
+pub mod seccomp;
+pub mod tee_executor;
+pub mod warmup;
 pub mod worker;
 
+use std::collections::HashMap;
+use std::path::PathBuf;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use std::time::Duration;
 
 #[allow(unused_imports)]
 use duration_str::deserialize_duration;
+use r3e_built_in_services::balance::AdmissionConfig;
+use r3e_core::rpc_pool::RpcPoolConfig;
+use r3e_deno::ext::chain::ChainNetworks;
+use r3e_deno::ext::services::ServiceApiConfig;
+use r3e_event::source::{ContractNotificationTrigger, TokenTransferTrigger};
 use serde::{Deserialize, Serialize};
 
+use crate::warmup::WarmupPolicy;
+
+pub use admin::{InvocationStatus, RunnerStatus, RuntimeStats, StatusBoard};
+pub use autoscale::{AutoscaleConfig, Autoscaler, ScaleBackend, ScaleBackendConfig};
 pub use container::{ContainerConfig, ContainerError, ContainerManager, NetworkMode};
+pub use drain::{DrainConfig, DrainState, DrainStatus};
+pub use lanes::{LaneConfig, LaneDepth};
+pub use sched::{CgroupMemory, SchedulingConfig};
+pub use seccomp::{SeccompAction, SeccompConfig};
 pub use {assign::*, builder::*, runner::*, sandbox::*, worker::*};
 
 pub const MAX_RUNNERS: u32 = 1024;
@@ -35,7 +59,36 @@ pub struct TaskConfig {
     pub sleep_ms: u64,
     pub source_type: String,
     pub rpc_url: Option<String>,
+    /// Fallback RPC endpoints, tried in order after `rpc_url`
+    #[serde(default)]
+    pub rpc_urls: Vec<String>,
+    #[serde(default)]
+    pub rpc_pool: RpcPoolConfig,
+    /// WebSocket endpoint, for the `neo_subscription` source type
+    #[serde(default)]
+    pub ws_url: Option<String>,
+    /// Re-emit the canonical blocks after a chain reorg, for the `neo_subscription` source type
+    #[serde(default)]
+    pub reorg_reemit: bool,
+    /// Directory of the source checkpoints, for the `neo_subscription` source type. A restarted
+    /// source resumes after the last block and transaction processed instead of at the head.
+    #[serde(default)]
+    pub checkpoint_dir: Option<String>,
     pub filter: Option<serde_json::Value>,
+    #[serde(default)]
+    pub contract_triggers: Vec<ContractNotificationTrigger>,
+    #[serde(default)]
+    pub transfer_triggers: Vec<TokenTransferTrigger>,
+    /// Synthetic events file, for the `synthetic` source type
+    #[serde(default)]
+    pub events_file: Option<String>,
+    /// Function code files by function ID, for the `synthetic` and `neo_subscription` source types
+    #[serde(default)]
+    pub functions: HashMap<u64, String>,
+    /// Function metadata by function ID, e.g. `tee: true`, for the `synthetic` and
+    /// `neo_subscription` source types
+    #[serde(default)]
+    pub function_metadata: HashMap<u64, serde_json::Value>,
 }
 
 impl Default for TaskConfig {
@@ -44,7 +97,17 @@ impl Default for TaskConfig {
             sleep_ms: 1000,
             source_type: "neo".to_string(),
             rpc_url: None,
+            rpc_urls: Vec::new(),
+            rpc_pool: RpcPoolConfig::default(),
+            ws_url: None,
+            reorg_reemit: false,
+            checkpoint_dir: None,
             filter: None,
+            contract_triggers: Vec::new(),
+            transfer_triggers: Vec::new(),
+            events_file: None,
+            functions: HashMap::new(),
+            function_metadata: HashMap::new(),
         }
     }
 }
@@ -59,6 +122,44 @@ pub struct WorkerConfig {
     pub max_runtimes_per_runner: u32,
     pub tasks: TaskConfig,
     pub sandbox: SandboxConfig,
+    #[serde(default)]
+    pub drain: DrainConfig,
+    /// Steering of invocations to the least-loaded runners, and recycling of bloated runtimes
+    #[serde(default)]
+    pub scheduling: SchedulingConfig,
+    /// Priority lanes of the tasks queued on each runner, by trigger type
+    #[serde(default)]
+    pub lanes: LaneConfig,
+    /// Directory of the functions' permission grants, shared with the API.
+    /// Without it only the sandbox-wide permissions apply.
+    #[serde(default)]
+    pub permission_grants_dir: Option<PathBuf>,
+    /// Directory of the admin policies, shared with the API.
+    /// Without it the ops are not checked against any policy.
+    #[serde(default)]
+    pub policy_dir: Option<PathBuf>,
+    /// API the functions invoke platform services through with `r3e.services`.
+    /// Without it service invocations fail.
+    #[serde(default)]
+    pub service_api: Option<ServiceApiConfig>,
+    /// Networks the functions' `r3e.neo` and `r3e.eth` queries are made on, with the RPC
+    /// endpoints read from the environment as for the other chain clients
+    #[serde(default)]
+    pub chain: ChainNetworks,
+    /// GAS balance limits runs alert tenants against, with the balance storage set on the worker
+    #[serde(default)]
+    pub admission: AdmissionConfig,
+    /// Functions loaded as soon as a runner starts when their `keep_warm` policy covers the
+    /// runner's slot, in addition to the ones with a policy in `tasks.function_metadata`
+    #[serde(default)]
+    pub warm_functions: Vec<u64>,
+    /// Syscall filter installed in the runners, with the canary alerting on escapes
+    #[serde(default)]
+    pub seccomp: SeccompConfig,
+    /// Scaling advice from the runners' load, served by the admin endpoint and pushed to the
+    /// scale backend
+    #[serde(default)]
+    pub autoscale: AutoscaleConfig,
 }
 
 impl Default for WorkerConfig {
@@ -70,6 +171,17 @@ impl Default for WorkerConfig {
             max_runtimes_per_runner: 16,
             tasks: TaskConfig::default(),
             sandbox: SandboxConfig::default(),
+            drain: DrainConfig::default(),
+            scheduling: SchedulingConfig::default(),
+            lanes: LaneConfig::default(),
+            permission_grants_dir: None,
+            policy_dir: None,
+            service_api: None,
+            chain: ChainNetworks::default(),
+            admission: AdmissionConfig::default(),
+            warm_functions: Vec::new(),
+            seccomp: SeccompConfig::default(),
+            autoscale: AutoscaleConfig::default(),
         }
     }
 }
@@ -82,6 +194,19 @@ impl WorkerConfig {
         }
         self.max_runners.min(MAX_RUNNERS)
     }
+
+    /// Functions the runners load at start if their keep-warm policy covers the runner's slot
+    pub fn warm_functions(&self) -> Vec<u64> {
+        let mut functions = self.warm_functions.clone();
+        for (fid, metadata) in &self.tasks.function_metadata {
+            if matches!(WarmupPolicy::from_metadata(metadata), Ok(Some(_))) {
+                functions.push(*fid);
+            }
+        }
+        functions.sort_unstable();
+        functions.dedup();
+        functions
+    }
 }
 
 pub trait Stopper {
@@ -1,6 +1,7 @@
 // Copyright @ 2023 - 2024, R3E Network
 // All Rights Reserved
 
+use std::collections::VecDeque;
 use std::hash::Hash;
 use std::num::NonZero;
 use std::sync::Arc;
@@ -10,10 +11,38 @@ use libc::pid_t;
 use lru::LruCache;
 use uuid::Uuid;
 
-use r3e_built_in_services::balance::{BalanceServiceTrait, TransactionType};
-use r3e_deno::{sandbox::SandboxConfig, ExecError, JsRuntime, RuntimeConfig};
-use r3e_event::source::{Task, TaskSource};
+use r3e_built_in_services::alerts::{Alert, AlertManager, AlertSeverity};
+use r3e_built_in_services::balance::{
+    AdmissionDecision, AdmissionError, BalanceAdmission, BalanceServiceTrait, TransactionType,
+};
+use r3e_built_in_services::billing::{BillingError, BillingServiceTrait};
+use r3e_deno::{
+    ext::{
+        budget::OpLimits,
+        chain::{ChainLimits, ChainQueries},
+        services::ServiceInvoker,
+        timers::TimerLimits,
+        InvocationIdentity,
+    },
+    sandbox::{
+        FunctionGrants, FunctionPolicy, NetPolicy, PermissionGrants, PolicyEngine, SandboxConfig,
+    },
+    source_map::SourceMap,
+    ExecError, JsRuntime, RuntimeConfig,
+};
+use r3e_event::source::{Func, Task, TaskError, TaskSource};
+use r3e_neo_services::nft::NftServiceTrait;
+use r3e_oracle::OracleService;
+use r3e_tee::sealing::{FunctionSealedStorage, SealedStorage};
+use r3e_tee::{AttestationReport, TeeError, TeeService};
 
+use crate::admin::{InvocationStatus, KillSwitch, RunnerStatus, RuntimeStats, StatusBoard};
+use crate::canary;
+use crate::drain::CheckpointStore;
+use crate::lanes::{LaneConfig, LaneQueue, REPLAY_TRIGGER};
+use crate::sched::SchedulingConfig;
+use crate::tee_executor::{TeeExecutor, TeeFallback, TeeOutcome, TeePolicy};
+use crate::warmup::{WarmupPolicy, WarmupSchedule};
 use crate::Stopper;
 
 pub struct Runner {
@@ -24,12 +53,62 @@ pub struct Runner {
     sandbox_config: SandboxConfig,
     // Balance service
     balance_service: Option<Arc<dyn BalanceServiceTrait>>,
+    // Balance check alerting tenants running low on GAS
+    admission: Option<Arc<BalanceAdmission>>,
+    // Journal of tasks not yet completed
+    checkpoints: Option<Arc<CheckpointStore>>,
+    // Checkpointed tasks to replay, queued in the lanes once the runner starts
+    replay: VecDeque<(String, Task)>,
+    // Tasks queued by lane, with their checkpoint if replayed
+    lanes: LaneConfig,
+    queue: LaneQueue<(Option<String>, Task)>,
+    // Tasks acquired from the source still queued
+    source_queued: usize,
+    // Oracle service used by the oracle ops
+    oracle_service: Option<Arc<dyn OracleService>>,
+    // TEE service used by the TEE ops and running the functions marked `tee: true`
+    tee_service: Option<Arc<dyn TeeService>>,
+    // NEP-11 service used by the NFT ops
+    nft_service: Option<Arc<dyn NftServiceTrait>>,
+    // Sealed storage backing the functions' private TEE state
+    sealed_storage: Option<Arc<SealedStorage>>,
+    // Per-function net, fs and env grants, enforced by the ops
+    permission_grants: Option<PermissionGrants>,
+    // Admin policies checked before the oracle and services ops are dispatched
+    policy_engine: Option<PolicyEngine>,
+    // Invoker of platform services used by the services op
+    service_invoker: Option<Arc<dyn ServiceInvoker>>,
+    // Chain clients used by the chain query ops
+    chain_queries: Option<Arc<ChainQueries>>,
+    // Billing service suspending tenants with overdue invoices
+    billing_service: Option<Arc<dyn BillingServiceTrait>>,
+    // Board the runner publishes its status to for the admin endpoint
+    status_board: Option<Arc<StatusBoard>>,
+    // Alert manager the canary syscalls made by the functions are reported to
+    alerts: Option<Arc<AlertManager>>,
+    // Deferral to less loaded runners and recycling of bloated runtimes
+    scheduling: SchedulingConfig,
+    // Functions loaded at start when their keep-warm policy covers the runner's slot
+    warm_functions: Vec<u64>,
+    // Warmup pings of the functions the runner keeps warm
+    warmup: WarmupSchedule,
+    status: RunnerStatus,
+    kill_switch: KillSwitch,
 }
 
 struct RunContext {
-    module: usize,
+    // Module loaded in the sandbox, None for functions that only run in a TEE
+    module: Option<usize>,
     version: u64,
     runtime: JsRuntime,
+    tee: Option<TeeFunction>,
+    warmup: Option<WarmupPolicy>,
+}
+
+// Function run in a TEE, with the code sent to the enclave
+struct TeeFunction {
+    policy: TeePolicy,
+    code: String,
 }
 
 impl Runner {
@@ -45,6 +124,12 @@ impl Runner {
             allow_env: false,
             allow_run: false,
             allow_hrtime: false,
+            allow_chain: true,
+            net_policy: NetPolicy::default(),
+            timer_limits: TimerLimits::default(),
+            chain_limits: ChainLimits::default(),
+            op_limits: OpLimits::default(),
+            heap_snapshot_on_oom: true,
         };
 
         Self {
@@ -53,7 +138,31 @@ impl Runner {
             max_runtimes,
             sandbox_config,
             balance_service: None,
-            sandbox_config: None,
+            admission: None,
+            checkpoints: None,
+            replay: VecDeque::new(),
+            lanes: LaneConfig::default(),
+            queue: LaneQueue::new(&LaneConfig::default()),
+            source_queued: 0,
+            oracle_service: None,
+            tee_service: None,
+            nft_service: None,
+            sealed_storage: None,
+            permission_grants: None,
+            policy_engine: None,
+            service_invoker: None,
+            chain_queries: None,
+            billing_service: None,
+            status_board: None,
+            alerts: None,
+            scheduling: SchedulingConfig::default(),
+            warm_functions: Vec::new(),
+            warmup: WarmupSchedule::default(),
+            status: RunnerStatus {
+                uid,
+                ..Default::default()
+            },
+            kill_switch: KillSwitch::default(),
         }
     }
 
@@ -67,6 +176,103 @@ impl Runner {
         self
     }
 
+    pub fn with_checkpoints(mut self, checkpoints: Option<Arc<CheckpointStore>>) -> Self {
+        self.checkpoints = checkpoints;
+        self
+    }
+
+    pub fn with_oracle_service(mut self, oracle_service: Option<Arc<dyn OracleService>>) -> Self {
+        self.oracle_service = oracle_service;
+        self
+    }
+
+    pub fn with_tee_service(mut self, tee_service: Option<Arc<dyn TeeService>>) -> Self {
+        self.tee_service = tee_service;
+        self
+    }
+
+    pub fn with_nft_service(mut self, nft_service: Option<Arc<dyn NftServiceTrait>>) -> Self {
+        self.nft_service = nft_service;
+        self
+    }
+
+    pub fn with_sealed_storage(mut self, sealed_storage: Option<Arc<SealedStorage>>) -> Self {
+        self.sealed_storage = sealed_storage;
+        self
+    }
+
+    pub fn with_permission_grants(mut self, permission_grants: Option<PermissionGrants>) -> Self {
+        self.permission_grants = permission_grants;
+        self
+    }
+
+    pub fn with_policy_engine(mut self, policy_engine: Option<PolicyEngine>) -> Self {
+        self.policy_engine = policy_engine;
+        self
+    }
+
+    pub fn with_service_invoker(
+        mut self,
+        service_invoker: Option<Arc<dyn ServiceInvoker>>,
+    ) -> Self {
+        self.service_invoker = service_invoker;
+        self
+    }
+
+    pub fn with_chain_queries(mut self, chain_queries: Option<Arc<ChainQueries>>) -> Self {
+        self.chain_queries = chain_queries;
+        self
+    }
+
+    pub fn with_admission(mut self, admission: Option<Arc<BalanceAdmission>>) -> Self {
+        self.admission = admission;
+        self
+    }
+
+    pub fn with_billing_service(
+        mut self,
+        billing_service: Option<Arc<dyn BillingServiceTrait>>,
+    ) -> Self {
+        self.billing_service = billing_service;
+        self
+    }
+
+    pub fn with_status_board(mut self, status_board: Option<Arc<StatusBoard>>) -> Self {
+        self.status_board = status_board;
+        self
+    }
+
+    pub fn with_alerts(mut self, alerts: Option<Arc<AlertManager>>) -> Self {
+        self.alerts = alerts;
+        self
+    }
+
+    pub fn with_scheduling(mut self, scheduling: SchedulingConfig) -> Self {
+        self.scheduling = scheduling;
+        self
+    }
+
+    /// Slot of the runner among the worker's runners, and the functions to load at start when
+    /// their keep-warm policy covers it
+    pub fn with_warmup(mut self, slot: u32, warm_functions: Vec<u64>) -> Self {
+        self.warmup = WarmupSchedule::new(slot);
+        self.warm_functions = warm_functions;
+        self
+    }
+
+    /// Lanes the tasks are queued in, dropping the tasks already queued
+    pub fn with_lanes(mut self, lanes: LaneConfig) -> Self {
+        self.queue = LaneQueue::new(&lanes);
+        self.lanes = lanes;
+        self
+    }
+
+    /// Tasks checkpointed by a previous worker, queued in the lane of replays
+    pub fn with_replay(mut self, tasks: Vec<(String, Task)>) -> Self {
+        self.replay.extend(tasks);
+        self
+    }
+
     pub fn run(mut self, stop: impl Stopper) {
         let reactor = tokio::runtime::Builder::new_current_thread()
             .enable_all()
@@ -83,9 +289,24 @@ impl Runner {
 
         let mut fid = 0;
         let mut runtimes = LruCache::<u64, RunContext>::new(max_runtimes);
+
+        // Pid is known only once forked
+        self.status.pid = std::process::id();
+        if let Some(board) = &self.status_board {
+            self.kill_switch.watch(board.clone());
+        }
+        self.publish_status();
+
+        // Functions kept warm are loaded before their first invocation
+        self.preload_warm(&mut runtimes).await;
+
+        for (id, task) in std::mem::take(&mut self.replay) {
+            self.queue.push(REPLAY_TRIGGER, (Some(id), task));
+        }
+
         while !stop.stopped() {
-            let task = match self.tasks.acquire_task(uid, fid).await {
-                Ok(task) => task,
+            let (checkpoint, task) = match self.next_task(fid, &mut runtimes).await {
+                Ok(next) => next,
                 Err(err) => {
                     log::error!("runner: {} acquire task failed: {}", uid, err);
                     break;
@@ -93,22 +314,113 @@ impl Runner {
             };
             log::info!("runner: {} acquire task for {}", uid, task.fid);
 
+            // Tasks acquired from the source are committed to it once journaled or done, so a
+            // restarted source resumes after them without losing or repeating any
+            let mut acquired = checkpoint.is_none();
+
+            // Draining: hand the task and the queued ones back to the journal instead of
+            // starting them
+            if stop.stopped() {
+                self.hand_back(Some((checkpoint, task))).await;
+                break;
+            }
+
+            // Tenants suspended for overdue invoices do not run until they pay
+            if let Some(billing_service) = &self.billing_service {
+                match billing_service.check_execution(&task.uid.to_string()).await {
+                    Ok(()) => {}
+                    Err(err @ BillingError::Suspended(_)) => {
+                        log::warn!("runner: {} skip task for {}: {}", uid, task.fid, err);
+                        self.complete(checkpoint);
+                        self.commit_source(&mut acquired).await;
+                        continue;
+                    }
+                    // An unavailable billing store does not stop paying tenants
+                    Err(err) => log::error!("runner: {} check billing failed: {}", uid, err),
+                }
+            }
+
+            // The API rejects invocations of tenants whose GAS balance is exhausted; tasks that
+            // reach the runner were accepted, so they run and the check only alerts the tenant
+            if let Some(admission) = &self.admission {
+                match admission.admit(&task.uid.to_string()).await {
+                    Ok(AdmissionDecision::Admitted) => {}
+                    Ok(AdmissionDecision::LowBalance { balance }) => log::warn!(
+                        "runner: {} run task for {} with low GAS balance {}",
+                        uid,
+                        task.fid,
+                        balance
+                    ),
+                    Err(err @ AdmissionError::PaymentRequired { .. }) => {
+                        log::warn!("runner: {} run task for {}, but {}", uid, task.fid, err)
+                    }
+                    Err(err) => log::error!("runner: {} check balance failed: {}", uid, err),
+                }
+            }
+
             fid = task.fid;
             let run_cx = match runtimes.get_mut(&fid) {
                 Some(run_cx) => run_cx,
                 None => match self.load_runtime(fid, &mut runtimes).await {
                     Ok(run_cx) => run_cx,
-                    Err(_err) => continue,
+                    Err(_err) => {
+                        self.complete(checkpoint);
+                        self.commit_source(&mut acquired).await;
+                        continue;
+                    }
                 },
             };
 
+            // Journal the task until it completes, so a killed runner does not lose it
+            let checkpoint = checkpoint.or_else(|| self.checkpoint(&task));
+            if checkpoint.is_some() {
+                self.commit_source(&mut acquired).await;
+            }
+
+            let invocation = InvocationStatus::new(fid);
+            self.kill_switch.arm(&invocation.id, run_cx.runtime.isolate_handle());
+            self.status.invocation = Some(invocation);
+            self.publish_status();
+
             let start = Instant::now();
-            if let Err(err) = self.run_task(run_cx, task).await {
-                log::error!("runner: {} run task failed: {}", uid, err);
+            let mut out_of_memory = false;
+            let mut attestation = None;
+            match self.run_task(run_cx, task).await {
+                Ok(attested) => attestation = attested,
+                Err(ExecError::OutOfMemory(report)) => {
+                    log::error!(
+                        "runner: {},{} out of memory, used {} of {} bytes, largest types: {:?}",
+                        uid,
+                        fid,
+                        report.usage.used_heap_size,
+                        report.usage.heap_size_limit,
+                        report.largest_types.iter().take(5).collect::<Vec<_>>()
+                    );
+                    out_of_memory = true;
+                }
+                Err(ExecError::OpBudget(exceeded)) => log::warn!(
+                    "runner: {},{} op budget exhausted by {}: {}",
+                    uid,
+                    fid,
+                    exceeded.op(),
+                    exceeded
+                ),
+                Err(err) => log::error!("runner: {} run task failed: {}", uid, err),
             }
 
             let elapsed = start.elapsed();
             log::info!("runner: {},{} run task cost: {:?}", uid, fid, elapsed);
+            self.report_canary(uid, fid).await;
+
+            self.complete(checkpoint);
+            self.commit_source(&mut acquired).await;
+            let killed = self.kill_switch.disarm();
+            let mut invocation = self.status.invocation.take();
+            if let Some(invocation) = &mut invocation {
+                invocation.attestation = attestation;
+                invocation.elapsed_ms = elapsed.as_millis() as u64;
+            }
+            self.status.last_invocation = invocation;
 
             // Charge for execution if balance service is available
             if let Some(balance_service) = &self.balance_service {
@@ -157,6 +469,22 @@ impl Runner {
                     }
                 }
             }
+
+            // A terminated isolate is not reused, the next task loads a fresh runtime
+            if killed {
+                log::warn!("runner: {},{} invocation killed", uid, fid);
+            }
+            if killed || out_of_memory {
+                runtimes.pop(&fid);
+            }
+            self.update_runtime_stats(&mut runtimes);
+            self.recycle_runtimes(fid, &mut runtimes);
+            self.publish_status();
+        }
+
+        self.hand_back(None).await;
+        if let Some(board) = &self.status_board {
+            board.remove(self.status.pid);
         }
 
         log::info!(
@@ -167,17 +495,357 @@ impl Runner {
         );
     }
 
-    async fn run_task(&self, run_cx: &mut RunContext, task: Task) -> Result<(), ExecError> {
+    /// Report the canary syscalls made during the last invocation, attributed to the function
+    /// that ran
+    async fn report_canary(&self, uid: u64, fid: u64) {
+        let hits = canary::take_hits();
+        if hits.is_empty() {
+            return;
+        }
+
+        let hits = hits
+            .iter()
+            .map(|hit| hit.to_string())
+            .collect::<Vec<_>>()
+            .join(", ");
+        log::error!("runner: {},{} made canary syscalls: {}", uid, fid, hits);
+        if let Some(alerts) = &self.alerts {
+            let alert = Alert::new(
+                format!("sandbox-canary:{}", fid),
+                AlertSeverity::Critical,
+                format!(
+                    "Function {} made unexpected syscalls in runner {} (pid {}): {}",
+                    fid,
+                    uid,
+                    std::process::id(),
+                    hits
+                ),
+            );
+            alerts.raise(alert).await;
+        }
+    }
+
+    /// Hand a task and the queued ones back to the journal when draining, and commit them to
+    /// the source once all are journaled
+    async fn hand_back(&mut self, task: Option<(Option<String>, Task)>) {
+        let mut queued: Vec<_> = task.into_iter().collect();
+        queued.extend(self.queue.drain());
+        self.source_queued = 0;
+
+        let from_source: Vec<Task> = queued
+            .into_iter()
+            .filter_map(|(checkpoint, task)| checkpoint.is_none().then_some(task))
+            .collect();
+        let unjournaled = from_source
+            .iter()
+            .filter(|task| self.checkpoint(task).is_none())
+            .count();
+        let mut acquired = !from_source.is_empty();
+        if unjournaled == 0 {
+            self.commit_source(&mut acquired).await;
+        }
+    }
+
+    /// Next task to run from the lanes, acquiring one from the source if none is queued
+    async fn next_task(
+        &mut self,
+        fid_hint: u64,
+        runtimes: &mut LruCache<u64, RunContext>,
+    ) -> Result<(Option<String>, Task), TaskError> {
+        if self.queue.is_empty() {
+            let task = self.acquire_task(fid_hint, runtimes).await?;
+            self.enqueue(task);
+        }
+
+        // The tasks the source has ready compete with the queued ones for the runner
+        while self.source_queued < self.lanes.prefetch {
+            match self.tasks.try_acquire_task(self.uid, fid_hint).await {
+                Ok(Some(task)) => self.enqueue(task),
+                Ok(None) => break,
+                Err(err) => {
+                    log::warn!("runner: {} prefetch task failed: {}", self.uid, err);
+                    break;
+                }
+            }
+        }
+
+        let (checkpoint, task) = self.queue.pop().expect("runner: queued task");
+        match &checkpoint {
+            Some(id) => log::info!("runner: {} replay checkpointed task {}", self.uid, id),
+            None => self.source_queued -= 1,
+        }
+        self.status.lanes = self.queue.depths();
+        Ok((checkpoint, task))
+    }
+
+    /// Queue a task acquired from the source in the lane of its trigger type
+    fn enqueue(&mut self, task: Task) {
+        self.source_queued += 1;
+        self.queue.push(task.event.kind(), (None, task));
+    }
+
+    /// Acquire the next task, pinging the warm functions while waiting for it
+    async fn acquire_task(
+        &mut self,
+        fid_hint: u64,
+        runtimes: &mut LruCache<u64, RunContext>,
+    ) -> Result<Task, TaskError> {
+        self.reload_warm(runtimes).await;
+        self.defer_to_less_loaded().await;
+
+        let uid = self.uid;
+        let acquire = self.tasks.acquire_task(uid, fid_hint);
+        tokio::pin!(acquire);
+        loop {
+            let Some(next) = self.warmup.next_due() else {
+                return acquire.await;
+            };
+            tokio::select! {
+                task = &mut acquire => return task,
+                _ = tokio::time::sleep_until(tokio::time::Instant::from_std(next)) => {
+                    ping_warm(uid, &mut self.warmup, runtimes).await;
+                }
+            }
+        }
+    }
+
+    /// Load the configured functions whose keep-warm policy covers the runner's slot
+    async fn preload_warm(&mut self, runtimes: &mut LruCache<u64, RunContext>) {
+        for fid in std::mem::take(&mut self.warm_functions) {
+            let fn_code = match self.tasks.acquire_fn(self.uid, fid).await {
+                Ok(fn_code) => fn_code,
+                Err(err) => {
+                    log::error!(
+                        "runner: {} acquire warm fn {} failed: {}",
+                        self.uid,
+                        fid,
+                        err
+                    );
+                    continue;
+                }
+            };
+
+            let covered = warmup_policy(self.uid, fid, &fn_code.metadata)
+                .is_some_and(|policy| policy.covers(self.warmup.slot()));
+            if !covered || runtimes.contains(&fid) {
+                continue;
+            }
+
+            match self.init_fn(fid, fn_code).await {
+                Ok(run_cx) => {
+                    self.warmup
+                        .track(fid, run_cx.warmup.as_ref(), Instant::now());
+                    runtimes.put(fid, run_cx);
+                    log::info!("runner: {} preloaded warm fn {}", self.uid, fid);
+                }
+                Err(err) => log::error!("runner: {} preload fn {} failed: {}", self.uid, fid, err),
+            }
+        }
+    }
+
+    /// Reload the runtimes of warm functions evicted since they were loaded
+    async fn reload_warm(&mut self, runtimes: &mut LruCache<u64, RunContext>) {
+        let evicted: Vec<u64> = self
+            .warmup
+            .functions()
+            .filter(|fid| !runtimes.contains(fid))
+            .collect();
+        for fid in evicted {
+            if self.load_runtime(fid, runtimes).await.is_err() {
+                self.warmup.track(fid, None, Instant::now());
+            }
+        }
+    }
+
+    /// Give the runners less loaded than this one a head start on the next task
+    async fn defer_to_less_loaded(&self) {
+        let Some(board) = &self.status_board else {
+            return;
+        };
+        if !self.scheduling.enabled {
+            return;
+        }
+
+        let runners = match board.runners() {
+            Ok(runners) => runners,
+            Err(err) => {
+                log::warn!("runner: {} read runner loads failed: {}", self.uid, err);
+                return;
+            }
+        };
+
+        let defer = self.scheduling.defer_for(&self.status, &runners);
+        if !defer.is_zero() {
+            log::debug!("runner: {} defer acquiring task by {:?}", self.uid, defer);
+            tokio::time::sleep(defer).await;
+        }
+    }
+
+    /// Recycle the runtimes whose heap is bloated, and drop the idle ones under cgroup memory
+    /// pressure. Recycled warm runtimes are reloaded fresh before the next task.
+    fn recycle_runtimes(&mut self, fid: u64, runtimes: &mut LruCache<u64, RunContext>) {
+        if !self.scheduling.enabled {
+            return;
+        }
+
+        let uid = self.uid;
+        let scheduling = &self.scheduling;
+        self.status
+            .runtimes
+            .retain(|stats| match scheduling.recycle_reason(stats) {
+                Some(reason) => {
+                    log::warn!("runner: {},{} recycle runtime: {}", uid, stats.fid, reason);
+                    runtimes.pop(&stats.fid);
+                    false
+                }
+                None => true,
+            });
+
+        // Only the runtime just used and the warm ones are kept under memory pressure
+        self.status.memory = scheduling.cgroup_memory();
+        if scheduling.under_pressure(self.status.memory.as_ref()) {
+            self.status.runtimes.retain(|stats| {
+                if stats.fid == fid || stats.warm {
+                    return true;
+                }
+                log::warn!(
+                    "runner: {},{} drop idle runtime under memory pressure",
+                    uid,
+                    stats.fid
+                );
+                runtimes.pop(&stats.fid);
+                false
+            });
+        }
+    }
+
+    fn update_runtime_stats(&mut self, runtimes: &mut LruCache<u64, RunContext>) {
+        // Scheduling needs the heap statistics even without a board
+        if self.status_board.is_none() && !self.scheduling.enabled {
+            return;
+        }
+
+        self.status.runtimes = runtimes
+            .iter_mut()
+            .map(|(fid, run_cx)| {
+                let stats = run_cx.runtime.heap_stats();
+                RuntimeStats {
+                    fid: *fid,
+                    version: run_cx.version,
+                    total_heap_size: stats.total_heap_size(),
+                    used_heap_size: stats.used_heap_size(),
+                    heap_size_limit: stats.heap_size_limit(),
+                    warm: self.warmup.is_warm(*fid),
+                }
+            })
+            .collect();
+    }
+
+    fn publish_status(&self) {
+        if let Some(board) = &self.status_board {
+            if let Err(err) = board.publish(&self.status) {
+                log::warn!("runner: {} publish status failed: {}", self.uid, err);
+            }
+        }
+    }
+
+    fn checkpoint(&self, task: &Task) -> Option<String> {
+        let checkpoints = self.checkpoints.as_ref()?;
+        match checkpoints.save(task) {
+            Ok(id) => Some(id),
+            Err(err) => {
+                log::error!("runner: {} checkpoint task failed: {}", self.uid, err);
+                None
+            }
+        }
+    }
+
+    fn complete(&self, checkpoint: Option<String>) {
+        if let (Some(checkpoints), Some(id)) = (&self.checkpoints, checkpoint) {
+            if let Err(err) = checkpoints.remove(&id) {
+                log::error!("runner: {} remove checkpoint {} failed: {}", self.uid, id, err);
+            }
+        }
+    }
+
+    /// Commit a task acquired from the source, once. Sources commit up to the last task
+    /// acquired, so the task is left to the ones acquired after it while they are queued.
+    async fn commit_source(&mut self, acquired: &mut bool) {
+        if self.source_queued > 0 {
+            return;
+        }
+        if std::mem::take(acquired) {
+            if let Err(err) = self.tasks.commit_task().await {
+                log::error!("runner: {} commit task failed: {}", self.uid, err);
+            }
+        }
+    }
+
+    /// Run a task, returning the attestation of the enclave that ran it if it ran in a TEE
+    async fn run_task(
+        &self,
+        run_cx: &mut RunContext,
+        task: Task,
+    ) -> Result<Option<AttestationReport>, ExecError> {
+        if let Some(tee) = &run_cx.tee {
+            match self.run_tee_task(tee, &task).await? {
+                TeeOutcome::Executed(execution) => {
+                    log::info!(
+                        "runner: {},{} ran in {:?} TEE, enclave {}",
+                        self.uid,
+                        task.fid,
+                        execution.attestation.platform,
+                        execution.attestation.code_hash
+                    );
+                    return Ok(Some(execution.attestation));
+                }
+                TeeOutcome::Fallback(err) => log::warn!(
+                    "runner: {},{} TEE failed, running in the sandbox: {}",
+                    self.uid,
+                    task.fid,
+                    err
+                ),
+            }
+        }
+
+        let Some(module) = run_cx.module else {
+            return Err(ExecError::OnExecute(
+                "function only runs in a TEE".to_string(),
+            ));
+        };
         let event = run_cx
             .runtime
             .to_global(&task.event)
             .map_err(|err| ExecError::OnExecute(err.to_string()))?;
 
-        let _ = run_cx
-            .runtime
-            .run_module_default(run_cx.module, &[event])
-            .await?;
-        Ok(())
+        let _ = run_cx.runtime.run_module_default(module, &[event]).await?;
+        Ok(None)
+    }
+
+    async fn run_tee_task(&self, tee: &TeeFunction, task: &Task) -> Result<TeeOutcome, ExecError> {
+        let Some(tee_service) = &self.tee_service else {
+            let err = TeeError::Initialization("no TEE service configured".to_string());
+            return tee
+                .policy
+                .on_failure(err)
+                .map_err(|err| ExecError::OnExecute(format!("tee: {}", err)));
+        };
+
+        let id = self
+            .status
+            .invocation
+            .as_ref()
+            .map(|invocation| invocation.id.clone())
+            .unwrap_or_default();
+        let input = serde_json::to_value(&task.event)
+            .map_err(|err| ExecError::OnExecute(err.to_string()))?;
+        let memory_limit_mb = (self.sandbox_config.max_heap_size / (1024 * 1024)) as u32;
+
+        TeeExecutor::new(tee_service.clone())
+            .with_limits(self.sandbox_config.max_execution_time, memory_limit_mb)
+            .invoke(&id, &tee.policy, &tee.code, input)
+            .await
+            .map_err(|err| ExecError::OnExecute(format!("tee: {}", err)))
     }
 
     async fn load_runtime<'a>(
@@ -193,80 +861,165 @@ impl Runner {
             }
         };
 
+        // Keep the function warm while its policy covers the runner's slot
+        self.warmup
+            .track(fid, run_cx.warmup.as_ref(), Instant::now());
+
         let run_cx = runtimes.get_or_insert_mut(fid, || run_cx);
         Ok(run_cx)
     }
 
     async fn load_fn(&mut self, fid: u64) -> Result<RunContext, ExecError> {
-        // Check if user has enough balance to run the function
-        if let Some(balance_service) = &self.balance_service {
-            let user_id = self.uid.to_string();
-            let balance = match balance_service.get_balance(&user_id).await {
-                Ok(balance) => balance,
-                Err(err) => {
-                    return Err(ExecError::OnLoad(format!(
-                        "Failed to get user balance: {}",
-                        err
-                    )));
-                }
-            };
-
-            // Check if user has enough GAS balance for function execution
-            let required_balance = {
-                // Base requirement for function execution
-                let base_requirement: u64 = 1000;
-
-                // Additional requirement based on function complexity
-                let complexity_requirement = match fn_code.complexity_score {
-                    Some(score) => score * 100,
-                    None => 500, // Default if complexity score not available
-                };
-
-                // Additional requirement based on estimated resource usage
-                let resource_requirement = (self.sandbox_config.max_heap_size / (1024 * 1024)) as u64 * 100 +  // 100 per MB of max heap
-                    self.sandbox_config.max_execution_time.as_secs() * 1000; // 1000 per second of max time
-
-                base_requirement + complexity_requirement + resource_requirement
-            };
+        let fn_code = self
+            .tasks
+            .acquire_fn(self.uid, fid)
+            .await
+            .map_err(|err| ExecError::OnLoad(err.to_string()))?;
 
-            if balance.gas_balance < required_balance {
-                return Err(ExecError::OnLoad(format!(
-                    "Insufficient GAS balance to run function: {} < 1000",
-                    balance.gas_balance
-                )));
-            }
-        }
+        self.init_fn(fid, fn_code).await
+    }
 
-        // Create a new runtime with sandbox configuration
+    async fn init_fn(&mut self, fid: u64, fn_code: Func) -> Result<RunContext, ExecError> {
+        // Create a new runtime with sandbox configuration, narrowed by the function's egress
+        // rules so every invocation of the function fetches under them
+        let mut sandbox_config = self.sandbox_config.clone();
+        sandbox_config.net_policy = self
+            .sandbox_config
+            .net_policy
+            .for_function(&fn_code.metadata)
+            .map_err(ExecError::OnLoad)?;
         let runtime_config = RuntimeConfig {
-            max_heap_size: self.sandbox_config.max_heap_size,
-            sandbox_config: Some(self.sandbox_config.clone()),
+            max_heap_size: sandbox_config.max_heap_size,
+            sandbox_config: Some(sandbox_config),
         };
 
         let mut runtime = JsRuntime::new(runtime_config);
+        runtime.put_state(InvocationIdentity {
+            tenant: self.uid.to_string(),
+            function_id: fid.to_string(),
+        });
+        if let Some(oracle_service) = &self.oracle_service {
+            runtime.put_state(oracle_service.clone());
+        }
+        if let Some(tee_service) = &self.tee_service {
+            runtime.put_state(tee_service.clone());
+        }
+        if let Some(nft_service) = &self.nft_service {
+            runtime.put_state(nft_service.clone());
+        }
+        if let Some(sealed_storage) = &self.sealed_storage {
+            // Each function only sees its own sealed state
+            runtime.put_state(FunctionSealedStorage::new(
+                sealed_storage.clone(),
+                self.uid,
+                fid,
+            ));
+        }
+        if let Some(permission_grants) = &self.permission_grants {
+            runtime.put_state(FunctionGrants::new(
+                permission_grants.clone(),
+                fid.to_string(),
+            ));
+        }
+        if let Some(policy_engine) = &self.policy_engine {
+            runtime.put_state(FunctionPolicy::new(
+                policy_engine.clone(),
+                self.uid.to_string(),
+                fid.to_string(),
+            ));
+        }
 
-        let fn_code = self
-            .tasks
-            .acquire_fn(self.uid, fid)
-            .await
-            .map_err(|err| ExecError::OnLoad(err.to_string()))?;
+        if let Some(service_invoker) = &self.service_invoker {
+            runtime.put_state(service_invoker.clone());
+        }
+        if let Some(chain_queries) = &self.chain_queries {
+            runtime.put_state(chain_queries.clone());
+        }
+
+        if !fn_code.source_map.is_empty() {
+            match SourceMap::parse(&fn_code.source_map) {
+                Ok(source_map) => runtime.set_source_map(Some(source_map)),
+                Err(err) => log::warn!("runner: {} ignore source map of {}: {}", self.uid, fid, err),
+            }
+        }
 
-        log::info!("runner: {} load fn for {} in sandbox", self.uid, fid);
-        let module = runtime.load_main_module(fn_code.code).await?;
+        let tee = TeePolicy::from_metadata_str(&fn_code.metadata)
+            .map_err(ExecError::OnLoad)?
+            .map(|policy| TeeFunction {
+                policy,
+                code: fn_code.code.clone(),
+            });
 
-        let _ = runtime.eval_module(module).await?;
+        // Functions that only run in a TEE are not evaluated outside of it
+        let module = match &tee {
+            Some(tee) if tee.policy.fallback == TeeFallback::Fail => {
+                log::info!("runner: {} load fn for {} in TEE", self.uid, fid);
+                None
+            }
+            _ => {
+                log::info!("runner: {} load fn for {} in sandbox", self.uid, fid);
+                let module = runtime.load_main_module(fn_code.code).await?;
+                let _ = runtime.eval_module(module).await?;
+                Some(module)
+            }
+        };
 
         Ok(RunContext {
             module,
             version: fn_code.version,
             runtime,
+            tee,
+            warmup: warmup_policy(self.uid, fid, &fn_code.metadata),
         })
     }
 }
 
+/// Keep-warm policy of a function, an invalid one only disables keeping it warm
+fn warmup_policy(uid: u64, fid: u64, metadata: &str) -> Option<WarmupPolicy> {
+    WarmupPolicy::from_metadata_str(metadata).unwrap_or_else(|err| {
+        log::warn!("runner: {} ignore keep_warm of {}: {}", uid, fid, err);
+        None
+    })
+}
+
+/// Call the warmup entrypoint of the warm functions whose ping is due
+async fn ping_warm(
+    uid: u64,
+    warmup: &mut WarmupSchedule,
+    runtimes: &mut LruCache<u64, RunContext>,
+) {
+    for (fid, entrypoint) in warmup.take_due(Instant::now()) {
+        // Evicted runtimes are reloaded before the next wait, functions that only run in a TEE
+        // have no runtime to warm
+        let Some(run_cx) = runtimes.get_mut(&fid) else {
+            continue;
+        };
+        let Some(module) = run_cx.module else {
+            continue;
+        };
+
+        let start = Instant::now();
+        match run_cx
+            .runtime
+            .run_module_export(module, &entrypoint, &[])
+            .await
+        {
+            Ok(()) => log::debug!("runner: {},{} warmup cost: {:?}", uid, fid, start.elapsed()),
+            Err(err @ (ExecError::Timeout | ExecError::OutOfMemory(_))) => {
+                // Reloaded fresh before the next wait, as after a failed invocation
+                log::warn!("runner: {},{} warmup failed: {}", uid, fid, err);
+                runtimes.pop(&fid);
+            }
+            Err(err) => log::warn!("runner: {},{} warmup failed: {}", uid, fid, err),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub(crate) struct RunHandle {
     pub(crate) pid: pid_t,
+    // Slot of the runner, keep-warm policies cover the lowest slots
+    pub(crate) slot: u32,
     pub(crate) kill_on_drop: bool,
 }
 
@@ -285,8 +1038,12 @@ impl Hash for RunHandle {
 }
 
 impl RunHandle {
-    pub fn new(pid: pid_t, kill_on_drop: bool) -> Self {
-        Self { pid, kill_on_drop }
+    pub fn new(pid: pid_t, slot: u32, kill_on_drop: bool) -> Self {
+        Self {
+            pid,
+            slot,
+            kill_on_drop,
+        }
     }
 }
 
@@ -0,0 +1,355 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+//! Resource-aware scheduling of invocations across the runners of a worker.
+//!
+//! Runners pull their tasks, so invocations are steered by deferral: before pulling the next
+//! task, a runner compares its load with the loads its siblings publish to the status board,
+//! and waits a step for every runner less loaded than it, so the least-loaded runner pulls
+//! pending tasks first. After each invocation the runner recycles the runtimes whose heap is
+//! close to its limit or mostly fragmented, and when the memory of its cgroup is under
+//! pressure it also drops its idle runtimes.
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::admin::{RunnerStatus, RuntimeStats};
+
+/// Cgroup v1 reports no limit as a huge page-aligned value
+const CGROUP_V1_UNLIMITED: u64 = 1 << 62;
+
+/// Scheduling configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SchedulingConfig {
+    /// Defer pulling tasks on loaded runners, and recycle bloated runtimes
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+
+    /// Wait before pulling a task, per runner less loaded than this one
+    #[serde(default = "default_defer_step_ms")]
+    pub defer_step_ms: u64,
+
+    /// Longest wait before pulling a task
+    #[serde(default = "default_max_defer_ms")]
+    pub max_defer_ms: u64,
+
+    /// Used heap over heap limit above which a runtime is recycled
+    #[serde(default = "default_max_heap_ratio")]
+    pub max_heap_ratio: f64,
+
+    /// Free share of the allocated heap above which a runtime is recycled
+    #[serde(default = "default_max_fragmentation_ratio")]
+    pub max_fragmentation_ratio: f64,
+
+    /// Allocated heap under which a runtime is never recycled for fragmentation
+    #[serde(default = "default_min_fragmented_heap")]
+    pub min_fragmented_heap: usize,
+
+    /// Cgroup memory usage over its limit above which idle runtimes are dropped
+    #[serde(default = "default_max_memory_pressure")]
+    pub max_memory_pressure: f64,
+
+    /// Cgroup directory the memory usage is read from, `/sys/fs/cgroup` by default
+    #[serde(default)]
+    pub cgroup_dir: Option<PathBuf>,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+fn default_defer_step_ms() -> u64 {
+    20
+}
+
+fn default_max_defer_ms() -> u64 {
+    200
+}
+
+fn default_max_heap_ratio() -> f64 {
+    0.8
+}
+
+fn default_max_fragmentation_ratio() -> f64 {
+    0.5
+}
+
+fn default_min_fragmented_heap() -> usize {
+    16 * 1024 * 1024
+}
+
+fn default_max_memory_pressure() -> f64 {
+    0.9
+}
+
+impl Default for SchedulingConfig {
+    fn default() -> Self {
+        Self {
+            enabled: default_enabled(),
+            defer_step_ms: default_defer_step_ms(),
+            max_defer_ms: default_max_defer_ms(),
+            max_heap_ratio: default_max_heap_ratio(),
+            max_fragmentation_ratio: default_max_fragmentation_ratio(),
+            min_fragmented_heap: default_min_fragmented_heap(),
+            max_memory_pressure: default_max_memory_pressure(),
+            cgroup_dir: None,
+        }
+    }
+}
+
+impl SchedulingConfig {
+    /// Wait before a runner pulls its next task, given the statuses of all the runners
+    pub fn defer_for(&self, own: &RunnerStatus, runners: &[RunnerStatus]) -> Duration {
+        if !self.enabled {
+            return Duration::ZERO;
+        }
+
+        let load = RunnerLoad::of(own);
+        let less_loaded = runners
+            .iter()
+            .filter(|status| status.pid != own.pid && RunnerLoad::of(status) < load)
+            .count() as u64;
+        Duration::from_millis(
+            less_loaded
+                .saturating_mul(self.defer_step_ms)
+                .min(self.max_defer_ms),
+        )
+    }
+
+    /// Why a runtime should be recycled, None if it can be reused
+    pub fn recycle_reason(&self, stats: &RuntimeStats) -> Option<RecycleReason> {
+        if !self.enabled {
+            return None;
+        }
+
+        if stats.heap_size_limit > 0 {
+            let ratio = stats.used_heap_size as f64 / stats.heap_size_limit as f64;
+            if ratio > self.max_heap_ratio {
+                return Some(RecycleReason::HeapLimit { ratio });
+            }
+        }
+
+        if stats.total_heap_size >= self.min_fragmented_heap.max(1) {
+            let used = stats.used_heap_size.min(stats.total_heap_size);
+            let ratio = 1.0 - used as f64 / stats.total_heap_size as f64;
+            if ratio > self.max_fragmentation_ratio {
+                return Some(RecycleReason::Fragmented { ratio });
+            }
+        }
+
+        None
+    }
+
+    /// Whether the memory of the cgroup is under pressure
+    pub fn under_pressure(&self, memory: Option<&CgroupMemory>) -> bool {
+        self.enabled
+            && memory
+                .and_then(CgroupMemory::pressure)
+                .is_some_and(|pressure| pressure > self.max_memory_pressure)
+    }
+
+    /// Memory usage of the runner's cgroup, None if unavailable
+    pub fn cgroup_memory(&self) -> Option<CgroupMemory> {
+        match &self.cgroup_dir {
+            Some(dir) => CgroupMemory::read(dir),
+            None => CgroupMemory::read("/sys/fs/cgroup"),
+        }
+    }
+}
+
+/// Why a runtime is recycled
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RecycleReason {
+    /// The used heap is close to the heap limit
+    HeapLimit { ratio: f64 },
+    /// Most of the allocated heap is free
+    Fragmented { ratio: f64 },
+}
+
+impl fmt::Display for RecycleReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::HeapLimit { ratio } => write!(f, "heap {:.0}% of its limit", ratio * 100.0),
+            Self::Fragmented { ratio } => write!(f, "heap {:.0}% fragmented", ratio * 100.0),
+        }
+    }
+}
+
+/// Load of a runner: busy runners are more loaded than idle ones, then by heap in use
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+struct RunnerLoad {
+    busy: bool,
+    used_heap_size: usize,
+}
+
+impl RunnerLoad {
+    fn of(status: &RunnerStatus) -> Self {
+        Self {
+            busy: status.invocation.is_some(),
+            used_heap_size: status
+                .runtimes
+                .iter()
+                .map(|stats| stats.used_heap_size)
+                .sum(),
+        }
+    }
+}
+
+/// Memory usage of a cgroup
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CgroupMemory {
+    /// Memory in use, in bytes
+    pub usage: u64,
+
+    /// Memory limit, in bytes, None if unlimited
+    pub limit: Option<u64>,
+}
+
+impl CgroupMemory {
+    /// Read the memory usage from a cgroup v2 directory, or from the memory controller of a
+    /// cgroup v1 hierarchy
+    pub fn read(dir: impl AsRef<Path>) -> Option<Self> {
+        let dir = dir.as_ref();
+        if let Some(usage) = read_u64(&dir.join("memory.current")) {
+            let limit = std::fs::read_to_string(dir.join("memory.max"))
+                .ok()
+                .and_then(|limit| limit.trim().parse().ok());
+            return Some(Self { usage, limit });
+        }
+
+        let dir = dir.join("memory");
+        let usage = read_u64(&dir.join("memory.usage_in_bytes"))?;
+        let limit = read_u64(&dir.join("memory.limit_in_bytes"))
+            .filter(|limit| *limit < CGROUP_V1_UNLIMITED);
+        Some(Self { usage, limit })
+    }
+
+    /// Memory in use over the limit, None if unlimited
+    pub fn pressure(&self) -> Option<f64> {
+        self.limit
+            .filter(|limit| *limit > 0)
+            .map(|limit| self.usage as f64 / limit as f64)
+    }
+}
+
+fn read_u64(path: &Path) -> Option<u64> {
+    std::fs::read_to_string(path).ok()?.trim().parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::admin::InvocationStatus;
+
+    fn runner(pid: u32, busy: bool, used_heap_size: usize) -> RunnerStatus {
+        RunnerStatus {
+            pid,
+            invocation: busy.then(|| InvocationStatus::new(1)),
+            runtimes: vec![RuntimeStats {
+                fid: 1,
+                used_heap_size,
+                ..Default::default()
+            }],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_defer_for() {
+        let config = SchedulingConfig::default();
+        let runners = vec![
+            runner(1, false, 10),
+            runner(2, false, 20),
+            runner(3, true, 5),
+            runner(4, false, 30),
+        ];
+
+        assert_eq!(config.defer_for(&runners[0], &runners), Duration::ZERO);
+        assert_eq!(
+            config.defer_for(&runners[1], &runners),
+            Duration::from_millis(20)
+        );
+        assert_eq!(
+            config.defer_for(&runners[3], &runners),
+            Duration::from_millis(40)
+        );
+        assert_eq!(
+            config.defer_for(&runners[2], &runners),
+            Duration::from_millis(60)
+        );
+
+        let config = SchedulingConfig {
+            max_defer_ms: 30,
+            ..Default::default()
+        };
+        assert_eq!(
+            config.defer_for(&runners[2], &runners),
+            Duration::from_millis(30)
+        );
+    }
+
+    #[test]
+    fn test_recycle_reason() {
+        let config = SchedulingConfig::default();
+        let mb = 1024 * 1024;
+        let stats = |used, total, limit| RuntimeStats {
+            used_heap_size: used,
+            total_heap_size: total,
+            heap_size_limit: limit,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            config.recycle_reason(&stats(20 * mb, 32 * mb, 128 * mb)),
+            None
+        );
+        assert!(matches!(
+            config.recycle_reason(&stats(110 * mb, 120 * mb, 128 * mb)),
+            Some(RecycleReason::HeapLimit { .. })
+        ));
+        assert!(matches!(
+            config.recycle_reason(&stats(8 * mb, 64 * mb, 128 * mb)),
+            Some(RecycleReason::Fragmented { .. })
+        ));
+
+        // Small heaps are not worth recycling for fragmentation
+        assert_eq!(config.recycle_reason(&stats(mb, 4 * mb, 128 * mb)), None);
+
+        let config = SchedulingConfig {
+            enabled: false,
+            ..Default::default()
+        };
+        assert_eq!(
+            config.recycle_reason(&stats(110 * mb, 120 * mb, 128 * mb)),
+            None
+        );
+    }
+
+    #[test]
+    fn test_cgroup_memory() {
+        let v2 = tempfile::tempdir().unwrap();
+        std::fs::write(v2.path().join("memory.current"), "900\n").unwrap();
+        std::fs::write(v2.path().join("memory.max"), "1000\n").unwrap();
+        let memory = CgroupMemory::read(v2.path()).unwrap();
+        assert_eq!(memory.limit, Some(1000));
+        assert_eq!(memory.pressure(), Some(0.9));
+
+        std::fs::write(v2.path().join("memory.max"), "max\n").unwrap();
+        let memory = CgroupMemory::read(v2.path()).unwrap();
+        assert_eq!(memory.limit, None);
+        assert!(!SchedulingConfig::default().under_pressure(Some(&memory)));
+
+        let v1 = tempfile::tempdir().unwrap();
+        std::fs::create_dir(v1.path().join("memory")).unwrap();
+        std::fs::write(v1.path().join("memory/memory.usage_in_bytes"), "950").unwrap();
+        std::fs::write(v1.path().join("memory/memory.limit_in_bytes"), "1000").unwrap();
+        let memory = CgroupMemory::read(v1.path()).unwrap();
+        assert!(SchedulingConfig::default().under_pressure(Some(&memory)));
+
+        let empty = tempfile::tempdir().unwrap();
+        assert_eq!(CgroupMemory::read(empty.path()), None);
+    }
+}
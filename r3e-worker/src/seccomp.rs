@@ -0,0 +1,732 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+//! Syscall filtering of the runner processes.
+//!
+//! With `seccomp.enabled` a runner installs a seccomp-bpf filter as soon as it is forked, while
+//! it still has a single thread, so every thread it starts later inherits the filter. Syscalls
+//! on the allow-list run as usual, the canary syscalls are trapped and reported by
+//! [`crate::canary`], and any other syscall gets the configured default action. The filter is
+//! only available on Linux on x86_64 and aarch64; elsewhere a runner with it enabled exits at
+//! start instead of running unfiltered.
+
+use serde::{Deserialize, Serialize};
+
+/// Action on a syscall the filter neither allows nor traps
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SeccompAction {
+    /// Fail the syscall with `EPERM`
+    #[default]
+    Errno,
+    /// Kill the runner
+    Kill,
+    /// Allow the syscall and record it in the kernel audit log, to build an allow-list
+    Log,
+}
+
+/// Syscall filter configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SeccompConfig {
+    /// Install the filter in the runners
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// Action on the syscalls neither allowed nor trapped
+    #[serde(default)]
+    pub default_action: SeccompAction,
+
+    /// Syscalls allowed in addition to the built-in allow-list, by name. A canary syscall
+    /// listed here is allowed instead of trapped.
+    #[serde(default)]
+    pub allow: Vec<String>,
+
+    /// Trap the canary syscalls and raise a critical alert when a runner makes one
+    #[serde(default = "default_canary")]
+    pub canary: bool,
+}
+
+fn default_canary() -> bool {
+    true
+}
+
+impl Default for SeccompConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            default_action: SeccompAction::default(),
+            allow: Vec::new(),
+            canary: default_canary(),
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum SeccompError {
+    #[error("Unknown syscall: {0}")]
+    UnknownSyscall(String),
+
+    #[error("Syscall filtering is not supported on this platform")]
+    Unsupported,
+
+    #[error("Failed to install syscall filter: {0}")]
+    Install(#[from] std::io::Error),
+}
+
+/// Install the filter of a config in the calling process, trapping the canary syscalls if it
+/// sets `canary`. `jitless` runners also trap making memory executable.
+#[cfg(all(
+    target_os = "linux",
+    any(target_arch = "x86_64", target_arch = "aarch64")
+))]
+pub fn install(config: &SeccompConfig, jitless: bool) -> Result<(), SeccompError> {
+    let filter = filter::SeccompFilter::new(config, jitless)?;
+    if config.canary {
+        crate::canary::install_handler()?;
+    }
+    filter.install()
+}
+
+/// Install the filter of a config in the calling process, unsupported on this platform
+#[cfg(not(all(
+    target_os = "linux",
+    any(target_arch = "x86_64", target_arch = "aarch64")
+)))]
+pub fn install(_config: &SeccompConfig, _jitless: bool) -> Result<(), SeccompError> {
+    Err(SeccompError::Unsupported)
+}
+
+/// Name of a syscall number, if it is one the filter knows of
+#[cfg(all(
+    target_os = "linux",
+    any(target_arch = "x86_64", target_arch = "aarch64")
+))]
+pub fn syscall_name(nr: i64) -> Option<&'static str> {
+    filter::known()
+        .find(|(_, known)| *known == nr)
+        .map(|(name, _)| name)
+}
+
+/// Name of a syscall number, unknown on this platform
+#[cfg(not(all(
+    target_os = "linux",
+    any(target_arch = "x86_64", target_arch = "aarch64")
+)))]
+pub fn syscall_name(_nr: i64) -> Option<&'static str> {
+    None
+}
+
+#[cfg(all(
+    target_os = "linux",
+    any(target_arch = "x86_64", target_arch = "aarch64")
+))]
+pub use filter::SeccompFilter;
+
+#[cfg(all(
+    target_os = "linux",
+    any(target_arch = "x86_64", target_arch = "aarch64")
+))]
+mod filter {
+    use libc::{c_long, sock_filter, sock_fprog};
+
+    use super::{SeccompAction, SeccompConfig, SeccompError};
+
+    // Classic BPF instructions the filter is made of
+    const BPF_LD_W_ABS: u16 = 0x20;
+    const BPF_JMP_JEQ_K: u16 = 0x15;
+    #[cfg_attr(not(target_arch = "x86_64"), allow(dead_code))]
+    const BPF_JMP_JGE_K: u16 = 0x35;
+    const BPF_JMP_JSET_K: u16 = 0x45;
+    const BPF_RET_K: u16 = 0x06;
+
+    const SECCOMP_RET_KILL_PROCESS: u32 = 0x8000_0000;
+    const SECCOMP_RET_TRAP: u32 = 0x0003_0000;
+    const SECCOMP_RET_ERRNO: u32 = 0x0005_0000;
+    const SECCOMP_RET_LOG: u32 = 0x7ffc_0000;
+    const SECCOMP_RET_ALLOW: u32 = 0x7fff_0000;
+
+    // Offsets in `struct seccomp_data`
+    const NR_OFFSET: u32 = 0;
+    const ARCH_OFFSET: u32 = 4;
+    const ARGS_OFFSET: u32 = 16;
+
+    #[cfg(target_arch = "x86_64")]
+    const AUDIT_ARCH: u32 = 0xc000_003e;
+    #[cfg(target_arch = "aarch64")]
+    const AUDIT_ARCH: u32 = 0xc000_00b7;
+
+    // x32 syscalls share the x86_64 audit arch, with this bit set in their numbers
+    #[cfg(target_arch = "x86_64")]
+    const X32_SYSCALL_BIT: u32 = 0x4000_0000;
+
+    /// Longest filter the kernel loads
+    const BPF_MAXINSNS: usize = 4096;
+
+    macro_rules! syscalls {
+        ($($name:ident),* $(,)?) => {
+            &[$((stringify!($name), sys::$name)),*]
+        };
+    }
+
+    /// Syscall numbers by name, as `libc::SYS_<name>`
+    mod sys {
+        pub use libc::{
+            SYS_accept as accept, SYS_accept4 as accept4, SYS_add_key as add_key, SYS_bind as bind,
+            SYS_bpf as bpf, SYS_brk as brk, SYS_chroot as chroot, SYS_clock_getres as clock_getres,
+            SYS_clock_gettime as clock_gettime, SYS_clock_nanosleep as clock_nanosleep,
+            SYS_clone as clone, SYS_clone3 as clone3, SYS_close as close,
+            SYS_close_range as close_range, SYS_connect as connect,
+            SYS_delete_module as delete_module, SYS_dup as dup, SYS_dup3 as dup3,
+            SYS_epoll_create1 as epoll_create1, SYS_epoll_ctl as epoll_ctl,
+            SYS_epoll_pwait as epoll_pwait, SYS_eventfd2 as eventfd2, SYS_execve as execve,
+            SYS_execveat as execveat, SYS_exit as exit, SYS_exit_group as exit_group,
+            SYS_faccessat as faccessat, SYS_fallocate as fallocate, SYS_fchmodat as fchmodat,
+            SYS_fchownat as fchownat, SYS_fcntl as fcntl, SYS_fdatasync as fdatasync,
+            SYS_finit_module as finit_module, SYS_flock as flock, SYS_fstat as fstat,
+            SYS_fsync as fsync, SYS_ftruncate as ftruncate, SYS_futex as futex,
+            SYS_getcwd as getcwd, SYS_getdents64 as getdents64, SYS_getegid as getegid,
+            SYS_geteuid as geteuid, SYS_getgid as getgid, SYS_getpeername as getpeername,
+            SYS_getpid as getpid, SYS_getppid as getppid, SYS_getpriority as getpriority,
+            SYS_getrandom as getrandom, SYS_getrlimit as getrlimit, SYS_getrusage as getrusage,
+            SYS_getsockname as getsockname, SYS_getsockopt as getsockopt, SYS_gettid as gettid,
+            SYS_gettimeofday as gettimeofday, SYS_getuid as getuid, SYS_init_module as init_module,
+            SYS_inotify_add_watch as inotify_add_watch, SYS_inotify_init1 as inotify_init1,
+            SYS_inotify_rm_watch as inotify_rm_watch, SYS_ioctl as ioctl,
+            SYS_kexec_load as kexec_load, SYS_keyctl as keyctl, SYS_kill as kill,
+            SYS_linkat as linkat, SYS_listen as listen, SYS_lseek as lseek, SYS_madvise as madvise,
+            SYS_membarrier as membarrier, SYS_memfd_create as memfd_create, SYS_mincore as mincore,
+            SYS_mkdirat as mkdirat, SYS_mknodat as mknodat, SYS_mlock as mlock, SYS_mmap as mmap,
+            SYS_mount as mount, SYS_mprotect as mprotect, SYS_mremap as mremap,
+            SYS_munlock as munlock, SYS_munmap as munmap, SYS_nanosleep as nanosleep,
+            SYS_newfstatat as newfstatat, SYS_openat as openat,
+            SYS_perf_event_open as perf_event_open, SYS_pipe2 as pipe2,
+            SYS_pivot_root as pivot_root, SYS_pkey_mprotect as pkey_mprotect, SYS_ppoll as ppoll,
+            SYS_prctl as prctl, SYS_pread64 as pread64, SYS_prlimit64 as prlimit64,
+            SYS_process_vm_readv as process_vm_readv, SYS_process_vm_writev as process_vm_writev,
+            SYS_pselect6 as pselect6, SYS_ptrace as ptrace, SYS_pwrite64 as pwrite64,
+            SYS_read as read, SYS_readlinkat as readlinkat, SYS_readv as readv,
+            SYS_recvfrom as recvfrom, SYS_recvmmsg as recvmmsg, SYS_recvmsg as recvmsg,
+            SYS_renameat as renameat, SYS_renameat2 as renameat2, SYS_request_key as request_key,
+            SYS_restart_syscall as restart_syscall, SYS_rseq as rseq,
+            SYS_rt_sigaction as rt_sigaction, SYS_rt_sigprocmask as rt_sigprocmask,
+            SYS_rt_sigreturn as rt_sigreturn, SYS_rt_sigtimedwait as rt_sigtimedwait,
+            SYS_sched_getaffinity as sched_getaffinity, SYS_sched_getparam as sched_getparam,
+            SYS_sched_getscheduler as sched_getscheduler,
+            SYS_sched_setaffinity as sched_setaffinity, SYS_sched_yield as sched_yield,
+            SYS_sendmmsg as sendmmsg, SYS_sendmsg as sendmsg, SYS_sendto as sendto,
+            SYS_set_robust_list as set_robust_list, SYS_set_tid_address as set_tid_address,
+            SYS_setns as setns, SYS_setpriority as setpriority, SYS_setsockopt as setsockopt,
+            SYS_shutdown as shutdown, SYS_sigaltstack as sigaltstack, SYS_socket as socket,
+            SYS_socketpair as socketpair, SYS_statx as statx, SYS_symlinkat as symlinkat,
+            SYS_sysinfo as sysinfo, SYS_tgkill as tgkill, SYS_timerfd_create as timerfd_create,
+            SYS_timerfd_gettime as timerfd_gettime, SYS_timerfd_settime as timerfd_settime,
+            SYS_umount2 as umount2, SYS_uname as uname, SYS_unlinkat as unlinkat,
+            SYS_unshare as unshare, SYS_userfaultfd as userfaultfd, SYS_wait4 as wait4,
+            SYS_write as write, SYS_writev as writev,
+        };
+
+        #[cfg(target_arch = "x86_64")]
+        pub use libc::{
+            SYS_access as access, SYS_arch_prctl as arch_prctl, SYS_chmod as chmod,
+            SYS_chown as chown, SYS_dup2 as dup2, SYS_epoll_create as epoll_create,
+            SYS_epoll_wait as epoll_wait, SYS_fork as fork, SYS_getdents as getdents,
+            SYS_lchown as lchown, SYS_link as link, SYS_lstat as lstat, SYS_mkdir as mkdir,
+            SYS_mknod as mknod, SYS_open as open, SYS_pipe as pipe, SYS_poll as poll,
+            SYS_readlink as readlink, SYS_rename as rename, SYS_rmdir as rmdir,
+            SYS_select as select, SYS_stat as stat, SYS_symlink as symlink, SYS_unlink as unlink,
+            SYS_vfork as vfork,
+        };
+    }
+
+    type Syscalls = &'static [(&'static str, c_long)];
+
+    /// Syscalls the runtime, V8, tokio and the HTTP clients of the ops make
+    const ALLOWED: Syscalls = syscalls![
+        read,
+        write,
+        readv,
+        writev,
+        pread64,
+        pwrite64,
+        openat,
+        close,
+        close_range,
+        lseek,
+        fstat,
+        newfstatat,
+        statx,
+        faccessat,
+        getdents64,
+        readlinkat,
+        mkdirat,
+        unlinkat,
+        renameat,
+        renameat2,
+        fcntl,
+        flock,
+        fsync,
+        fdatasync,
+        ftruncate,
+        fallocate,
+        ioctl,
+        getcwd,
+        dup,
+        dup3,
+        pipe2,
+        eventfd2,
+        epoll_create1,
+        epoll_ctl,
+        epoll_pwait,
+        ppoll,
+        pselect6,
+        mmap,
+        munmap,
+        mprotect,
+        mremap,
+        madvise,
+        mincore,
+        brk,
+        membarrier,
+        futex,
+        clone,
+        set_robust_list,
+        set_tid_address,
+        rseq,
+        sched_yield,
+        sched_getaffinity,
+        sched_getparam,
+        sched_getscheduler,
+        getpriority,
+        setpriority,
+        nanosleep,
+        clock_nanosleep,
+        clock_gettime,
+        clock_getres,
+        gettimeofday,
+        rt_sigaction,
+        rt_sigprocmask,
+        rt_sigreturn,
+        sigaltstack,
+        restart_syscall,
+        getpid,
+        getppid,
+        gettid,
+        getuid,
+        geteuid,
+        getgid,
+        getegid,
+        kill,
+        tgkill,
+        wait4,
+        prctl,
+        uname,
+        sysinfo,
+        getrlimit,
+        prlimit64,
+        getrusage,
+        getrandom,
+        socket,
+        socketpair,
+        connect,
+        sendto,
+        recvfrom,
+        sendmsg,
+        recvmsg,
+        sendmmsg,
+        recvmmsg,
+        shutdown,
+        getsockname,
+        getpeername,
+        setsockopt,
+        getsockopt,
+        exit,
+        exit_group,
+    ];
+
+    #[cfg(target_arch = "x86_64")]
+    const ALLOWED_ARCH: Syscalls = syscalls![
+        open,
+        stat,
+        lstat,
+        access,
+        getdents,
+        readlink,
+        mkdir,
+        rmdir,
+        unlink,
+        rename,
+        dup2,
+        pipe,
+        poll,
+        select,
+        epoll_create,
+        epoll_wait,
+        arch_prctl,
+    ];
+    #[cfg(target_arch = "aarch64")]
+    const ALLOWED_ARCH: Syscalls = &[];
+
+    /// Syscalls a runner has no use for, made by code that escaped the runtime: running
+    /// programs, inspecting other processes, changing the mounts, namespaces or the kernel,
+    /// accepting connections and changing the ownership and permissions of files
+    const CANARY: Syscalls = syscalls![
+        execve,
+        execveat,
+        ptrace,
+        process_vm_readv,
+        process_vm_writev,
+        mount,
+        umount2,
+        pivot_root,
+        chroot,
+        setns,
+        unshare,
+        init_module,
+        finit_module,
+        delete_module,
+        kexec_load,
+        bpf,
+        perf_event_open,
+        userfaultfd,
+        keyctl,
+        add_key,
+        request_key,
+        bind,
+        listen,
+        accept,
+        accept4,
+        linkat,
+        symlinkat,
+        mknodat,
+        fchmodat,
+        fchownat,
+    ];
+
+    #[cfg(target_arch = "x86_64")]
+    const CANARY_ARCH: Syscalls =
+        syscalls![fork, vfork, link, symlink, mknod, chmod, chown, lchown];
+    #[cfg(target_arch = "aarch64")]
+    const CANARY_ARCH: Syscalls = &[];
+
+    /// Syscalls the filter knows of without allowing them, for `allow`
+    const OPTIONAL: Syscalls = syscalls![
+        clone3,
+        memfd_create,
+        mlock,
+        munlock,
+        timerfd_create,
+        timerfd_settime,
+        timerfd_gettime,
+        inotify_init1,
+        inotify_add_watch,
+        inotify_rm_watch,
+        sched_setaffinity,
+        rt_sigtimedwait,
+    ];
+
+    /// Syscalls the filter knows of by name
+    pub(super) fn known() -> impl Iterator<Item = (&'static str, c_long)> {
+        [ALLOWED, ALLOWED_ARCH, CANARY, CANARY_ARCH, OPTIONAL]
+            .into_iter()
+            .flat_map(|syscalls| syscalls.iter().copied())
+    }
+
+    fn syscall_number(name: &str) -> Result<c_long, SeccompError> {
+        known()
+            .find(|(known, _)| *known == name)
+            .map(|(_, nr)| nr)
+            .ok_or_else(|| SeccompError::UnknownSyscall(name.to_string()))
+    }
+
+    fn load(offset: u32) -> sock_filter {
+        sock_filter {
+            code: BPF_LD_W_ABS,
+            jt: 0,
+            jf: 0,
+            k: offset,
+        }
+    }
+
+    fn jump(code: u16, k: u32, jt: u8, jf: u8) -> sock_filter {
+        sock_filter { code, jt, jf, k }
+    }
+
+    fn ret(action: u32) -> sock_filter {
+        sock_filter {
+            code: BPF_RET_K,
+            jt: 0,
+            jf: 0,
+            k: action,
+        }
+    }
+
+    /// Offset of the low half of a syscall argument
+    fn arg_offset(index: u32) -> u32 {
+        ARGS_OFFSET + index * 8
+    }
+
+    /// Seccomp-bpf program of a syscall filter
+    pub struct SeccompFilter {
+        program: Vec<sock_filter>,
+    }
+
+    impl SeccompFilter {
+        /// Filter of a config; `jitless` runners also trap making memory executable
+        pub fn new(config: &SeccompConfig, jitless: bool) -> Result<Self, SeccompError> {
+            let extra = config
+                .allow
+                .iter()
+                .map(|name| syscall_number(name))
+                .collect::<Result<Vec<_>, _>>()?;
+
+            let mut allowed: Vec<c_long> = ALLOWED
+                .iter()
+                .chain(ALLOWED_ARCH)
+                .map(|(_, nr)| *nr)
+                .chain(extra.iter().copied())
+                .collect();
+            allowed.sort_unstable();
+            allowed.dedup();
+
+            let trapped: Vec<c_long> = if config.canary {
+                CANARY
+                    .iter()
+                    .chain(CANARY_ARCH)
+                    .map(|(_, nr)| *nr)
+                    .filter(|nr| !extra.contains(nr))
+                    .collect()
+            } else {
+                Vec::new()
+            };
+
+            let default_action = match config.default_action {
+                SeccompAction::Errno => SECCOMP_RET_ERRNO | libc::EPERM as u32,
+                SeccompAction::Kill => SECCOMP_RET_KILL_PROCESS,
+                SeccompAction::Log => SECCOMP_RET_LOG,
+            };
+
+            let mut program = vec![
+                load(ARCH_OFFSET),
+                jump(BPF_JMP_JEQ_K, AUDIT_ARCH, 1, 0),
+                ret(SECCOMP_RET_KILL_PROCESS),
+                load(NR_OFFSET),
+            ];
+            #[cfg(target_arch = "x86_64")]
+            program.extend([
+                jump(BPF_JMP_JGE_K, X32_SYSCALL_BIT, 0, 1),
+                ret(SECCOMP_RET_KILL_PROCESS),
+            ]);
+
+            // Without a JIT no code is generated at run time, so memory never has to be made
+            // executable. Other syscalls skip the check with their number still loaded.
+            if config.canary && jitless {
+                for nr in [libc::SYS_mprotect, libc::SYS_pkey_mprotect] {
+                    if extra.contains(&nr) {
+                        continue;
+                    }
+                    program.extend([
+                        jump(BPF_JMP_JEQ_K, nr as u32, 0, 4),
+                        load(arg_offset(2)),
+                        jump(BPF_JMP_JSET_K, libc::PROT_EXEC as u32, 0, 1),
+                        ret(SECCOMP_RET_TRAP),
+                        ret(SECCOMP_RET_ALLOW),
+                    ]);
+                }
+            }
+
+            for nr in trapped {
+                program.extend([jump(BPF_JMP_JEQ_K, nr as u32, 0, 1), ret(SECCOMP_RET_TRAP)]);
+            }
+            for nr in allowed {
+                program.extend([jump(BPF_JMP_JEQ_K, nr as u32, 0, 1), ret(SECCOMP_RET_ALLOW)]);
+            }
+            // glibc falls back to clone when clone3 is not implemented
+            if !extra.contains(&libc::SYS_clone3) {
+                program.extend([
+                    jump(BPF_JMP_JEQ_K, libc::SYS_clone3 as u32, 0, 1),
+                    ret(SECCOMP_RET_ERRNO | libc::ENOSYS as u32),
+                ]);
+            }
+            program.push(ret(default_action));
+
+            debug_assert!(program.len() <= BPF_MAXINSNS);
+            Ok(Self { program })
+        }
+
+        /// Number of instructions of the program
+        pub fn len(&self) -> usize {
+            self.program.len()
+        }
+
+        /// Whether the program has no instructions, which it never has
+        pub fn is_empty(&self) -> bool {
+            self.program.is_empty()
+        }
+
+        /// Install the filter in the calling thread, and the threads it starts from then on.
+        /// The process gives up gaining privileges on `execve` first, as the kernel requires
+        /// of unprivileged processes.
+        pub fn install(&self) -> Result<(), SeccompError> {
+            let prog = sock_fprog {
+                len: self.program.len() as u16,
+                filter: self.program.as_ptr() as *mut sock_filter,
+            };
+
+            // Safety: `prog` points to the program, which outlives the calls; the kernel
+            // copies it
+            unsafe {
+                if libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) != 0 {
+                    return Err(std::io::Error::last_os_error().into());
+                }
+                if libc::prctl(
+                    libc::PR_SET_SECCOMP,
+                    libc::SECCOMP_MODE_FILTER,
+                    &prog as *const sock_fprog,
+                ) != 0
+                {
+                    return Err(std::io::Error::last_os_error().into());
+                }
+            }
+            Ok(())
+        }
+
+        #[cfg(test)]
+        fn program(&self) -> &[sock_filter] {
+            &self.program
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        /// Action the program returns for a syscall with an argument list, by interpreting it
+        fn run(filter: &SeccompFilter, nr: c_long, args: [u64; 6]) -> u32 {
+            let mut acc = 0u32;
+            let mut pc = 0;
+            loop {
+                let insn = filter.program()[pc];
+                pc += 1;
+                match insn.code {
+                    BPF_LD_W_ABS => {
+                        acc = match insn.k {
+                            NR_OFFSET => nr as u32,
+                            ARCH_OFFSET => AUDIT_ARCH,
+                            k => args[((k - ARGS_OFFSET) / 8) as usize] as u32,
+                        }
+                    }
+                    BPF_RET_K => return insn.k,
+                    code => {
+                        let taken = match code {
+                            BPF_JMP_JEQ_K => acc == insn.k,
+                            BPF_JMP_JGE_K => acc >= insn.k,
+                            BPF_JMP_JSET_K => acc & insn.k != 0,
+                            code => panic!("unexpected instruction {:#x}", code),
+                        };
+                        pc += if taken { insn.jt } else { insn.jf } as usize;
+                    }
+                }
+            }
+        }
+
+        fn enabled() -> SeccompConfig {
+            SeccompConfig {
+                enabled: true,
+                ..Default::default()
+            }
+        }
+
+        #[test]
+        fn test_default_config() {
+            let config: SeccompConfig = serde_json::from_str("{}").unwrap();
+            assert!(!config.enabled);
+            assert!(config.canary);
+            assert_eq!(config.default_action, SeccompAction::Errno);
+        }
+
+        #[test]
+        fn test_filter_actions() {
+            let filter = SeccompFilter::new(&enabled(), true).unwrap();
+            assert!(filter.len() <= BPF_MAXINSNS);
+
+            assert_eq!(run(&filter, libc::SYS_read, [0; 6]), SECCOMP_RET_ALLOW);
+            assert_eq!(run(&filter, libc::SYS_connect, [0; 6]), SECCOMP_RET_ALLOW);
+            assert_eq!(run(&filter, libc::SYS_execve, [0; 6]), SECCOMP_RET_TRAP);
+            assert_eq!(run(&filter, libc::SYS_bind, [0; 6]), SECCOMP_RET_TRAP);
+            assert_eq!(
+                run(&filter, libc::SYS_clone3, [0; 6]),
+                SECCOMP_RET_ERRNO | libc::ENOSYS as u32
+            );
+            assert_eq!(
+                run(&filter, libc::SYS_memfd_create, [0; 6]),
+                SECCOMP_RET_ERRNO | libc::EPERM as u32
+            );
+
+            let rw = (libc::PROT_READ | libc::PROT_WRITE) as u64;
+            let rx = (libc::PROT_READ | libc::PROT_EXEC) as u64;
+            assert_eq!(
+                run(&filter, libc::SYS_mprotect, [0, 4096, rw, 0, 0, 0]),
+                SECCOMP_RET_ALLOW
+            );
+            assert_eq!(
+                run(&filter, libc::SYS_mprotect, [0, 4096, rx, 0, 0, 0]),
+                SECCOMP_RET_TRAP
+            );
+            assert_eq!(
+                run(&filter, libc::SYS_pkey_mprotect, [0, 4096, rx, 0, 0, 0]),
+                SECCOMP_RET_TRAP
+            );
+        }
+
+        #[test]
+        fn test_filter_with_jit() {
+            let filter = SeccompFilter::new(&enabled(), false).unwrap();
+            let rx = (libc::PROT_READ | libc::PROT_EXEC) as u64;
+            assert_eq!(
+                run(&filter, libc::SYS_mprotect, [0, 4096, rx, 0, 0, 0]),
+                SECCOMP_RET_ALLOW
+            );
+            assert_eq!(run(&filter, libc::SYS_execve, [0; 6]), SECCOMP_RET_TRAP);
+        }
+
+        #[test]
+        fn test_filter_config() {
+            let config = SeccompConfig {
+                default_action: SeccompAction::Kill,
+                allow: vec!["bind".to_string(), "memfd_create".to_string()],
+                canary: true,
+                ..enabled()
+            };
+            let filter = SeccompFilter::new(&config, true).unwrap();
+            assert_eq!(run(&filter, libc::SYS_bind, [0; 6]), SECCOMP_RET_ALLOW);
+            assert_eq!(
+                run(&filter, libc::SYS_memfd_create, [0; 6]),
+                SECCOMP_RET_ALLOW
+            );
+            assert_eq!(run(&filter, libc::SYS_listen, [0; 6]), SECCOMP_RET_TRAP);
+            assert_eq!(
+                run(&filter, libc::SYS_setuid, [0; 6]),
+                SECCOMP_RET_KILL_PROCESS
+            );
+
+            let config = SeccompConfig {
+                canary: false,
+                ..enabled()
+            };
+            let filter = SeccompFilter::new(&config, true).unwrap();
+            assert_eq!(
+                run(&filter, libc::SYS_execve, [0; 6]),
+                SECCOMP_RET_ERRNO | libc::EPERM as u32
+            );
+
+            let config = SeccompConfig {
+                allow: vec!["no_such_syscall".to_string()],
+                ..enabled()
+            };
+            assert!(matches!(
+                SeccompFilter::new(&config, true),
+                Err(SeccompError::UnknownSyscall(_))
+            ));
+        }
+    }
+}
@@ -0,0 +1,324 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+//! Execution of functions in a TEE.
+//!
+//! Functions whose metadata has `tee: true` run through the TEE service rather than the
+//! sandbox. The enclave that ran an invocation attests to it, and the attestation is checked
+//! before the result is accepted. When the TEE cannot run an invocation, the function's
+//! `tee_fallback` decides whether it fails or runs in the sandbox without attestation.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use r3e_tee::{AttestationReport, TeeError, TeeExecutionRequest, TeePlatform, TeeService};
+
+/// What to do with an invocation the TEE could not run
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TeeFallback {
+    /// Fail the invocation, the function never runs outside a TEE
+    #[default]
+    Fail,
+
+    /// Run the invocation in the sandbox, without attestation
+    Sandbox,
+}
+
+/// TEE execution settings of a function
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TeePolicy {
+    /// Platform to run on, the simulated TEE if not set
+    #[serde(default)]
+    pub platform: Option<TeePlatform>,
+
+    /// What to do with invocations the TEE could not run
+    #[serde(default)]
+    pub fallback: TeeFallback,
+}
+
+/// TEE settings as they appear in function metadata
+#[derive(Debug, Deserialize)]
+struct TeeMetadata {
+    #[serde(default)]
+    tee: bool,
+    #[serde(default)]
+    tee_platform: Option<TeePlatform>,
+    #[serde(default)]
+    tee_fallback: TeeFallback,
+}
+
+impl TeePolicy {
+    /// Policy of a function from its metadata, None unless the metadata has `tee: true`
+    pub fn from_metadata(metadata: &serde_json::Value) -> Result<Option<Self>, String> {
+        if metadata.is_null() {
+            return Ok(None);
+        }
+
+        let metadata = TeeMetadata::deserialize(metadata)
+            .map_err(|err| format!("invalid tee metadata: {}", err))?;
+        Ok(metadata.tee.then_some(Self {
+            platform: metadata.tee_platform,
+            fallback: metadata.tee_fallback,
+        }))
+    }
+
+    /// Policy of a function from its JSON encoded metadata, which may be empty
+    pub fn from_metadata_str(metadata: &str) -> Result<Option<Self>, String> {
+        if metadata.trim().is_empty() {
+            return Ok(None);
+        }
+
+        let metadata = serde_json::from_str(metadata)
+            .map_err(|err| format!("invalid function metadata: {}", err))?;
+        Self::from_metadata(&metadata)
+    }
+
+    /// Outcome of an invocation the TEE could not run
+    pub fn on_failure(&self, err: TeeError) -> Result<TeeOutcome, TeeError> {
+        match self.fallback {
+            TeeFallback::Fail => Err(err),
+            TeeFallback::Sandbox => Ok(TeeOutcome::Fallback(err)),
+        }
+    }
+}
+
+/// Invocation run in a TEE
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TeeExecution {
+    /// Function result
+    pub result: serde_json::Value,
+
+    /// Checked attestation of the enclave that ran the invocation
+    pub attestation: AttestationReport,
+
+    /// Execution time in the enclave, in milliseconds
+    pub execution_time_ms: u64,
+}
+
+/// Outcome of an invocation of a TEE function
+#[derive(Debug)]
+pub enum TeeOutcome {
+    /// The TEE ran the invocation
+    Executed(TeeExecution),
+
+    /// The TEE could not run the invocation and the policy lets it run in the sandbox
+    Fallback(TeeError),
+}
+
+/// Runs invocations of TEE functions through the TEE service
+#[derive(Clone)]
+pub struct TeeExecutor {
+    tee_service: Arc<dyn TeeService>,
+    timeout: Duration,
+    memory_limit_mb: u32,
+}
+
+impl TeeExecutor {
+    pub fn new(tee_service: Arc<dyn TeeService>) -> Self {
+        Self {
+            tee_service,
+            timeout: Duration::from_secs(10),
+            memory_limit_mb: 128,
+        }
+    }
+
+    /// Limit the execution time and memory of invocations, like the sandbox does
+    pub fn with_limits(mut self, timeout: Duration, memory_limit_mb: u32) -> Self {
+        self.timeout = timeout;
+        self.memory_limit_mb = memory_limit_mb;
+        self
+    }
+
+    /// Run an invocation in the TEE, applying the function's fallback policy on failure
+    pub async fn invoke(
+        &self,
+        id: &str,
+        policy: &TeePolicy,
+        code: &str,
+        input: serde_json::Value,
+    ) -> Result<TeeOutcome, TeeError> {
+        match self.execute(id, policy, code, input).await {
+            Ok(execution) => Ok(TeeOutcome::Executed(execution)),
+            Err(err) => policy.on_failure(err),
+        }
+    }
+
+    /// Run an invocation in the TEE, accepting the result only with a valid attestation
+    pub async fn execute(
+        &self,
+        id: &str,
+        policy: &TeePolicy,
+        code: &str,
+        input: serde_json::Value,
+    ) -> Result<TeeExecution, TeeError> {
+        let platform = policy.platform.unwrap_or(TeePlatform::Simulated);
+        if !self.tee_service.supported_platforms().contains(&platform) {
+            return Err(TeeError::Provider(format!(
+                "platform {:?} is not supported",
+                platform
+            )));
+        }
+
+        let request = TeeExecutionRequest {
+            id: id.to_string(),
+            code: code.to_string(),
+            input,
+            platform: Some(platform),
+            security_level: None,
+            require_attestation: true,
+            timeout_ms: self.timeout.as_millis() as u64,
+            memory_limit_mb: self.memory_limit_mb,
+        };
+        let response = tokio::time::timeout(self.timeout, self.tee_service.execute(request))
+            .await
+            .map_err(|_| {
+                TeeError::Execution(format!("timed out after {} ms", self.timeout.as_millis()))
+            })??;
+        if let Some(error) = response.error {
+            return Err(TeeError::Execution(error));
+        }
+
+        let attestation = response.attestation.ok_or_else(|| {
+            TeeError::Attestation(format!("no attestation for invocation {}", id))
+        })?;
+        if attestation.platform != platform {
+            return Err(TeeError::Attestation(format!(
+                "invocation {} attested by {:?} instead of {:?}",
+                id, attestation.platform, platform
+            )));
+        }
+        if !self.tee_service.verify_attestation(&attestation).await? {
+            return Err(TeeError::Attestation(format!(
+                "invalid attestation for invocation {}",
+                id
+            )));
+        }
+
+        Ok(TeeExecution {
+            result: response.result,
+            attestation,
+            execution_time_ms: response.execution_time_ms,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use r3e_tee::{TeeExecutionResponse, TeeSecurityLevel};
+
+    /// Echoes the input, attested as the simulated platform
+    struct EchoTee {
+        valid: bool,
+    }
+
+    #[async_trait::async_trait]
+    impl TeeService for EchoTee {
+        fn supported_platforms(&self) -> Vec<TeePlatform> {
+            vec![TeePlatform::Simulated]
+        }
+
+        async fn execute(
+            &self,
+            request: TeeExecutionRequest,
+        ) -> Result<TeeExecutionResponse, TeeError> {
+            Ok(TeeExecutionResponse {
+                request_id: request.id,
+                result: request.input,
+                attestation: Some(self.generate_attestation(TeePlatform::Simulated).await?),
+                execution_time_ms: 1,
+                memory_usage_mb: 1,
+                error: None,
+            })
+        }
+
+        async fn generate_attestation(
+            &self,
+            platform: TeePlatform,
+        ) -> Result<AttestationReport, TeeError> {
+            Ok(AttestationReport {
+                platform,
+                security_level: TeeSecurityLevel::Debug,
+                code_hash: "code".to_string(),
+                signer_hash: "signer".to_string(),
+                product_id: 0,
+                security_version: 0,
+                attributes: 0,
+                extended_product_id: Vec::new(),
+                signature: Vec::new(),
+                platform_data: serde_json::Value::Null,
+            })
+        }
+
+        async fn verify_attestation(&self, _: &AttestationReport) -> Result<bool, TeeError> {
+            Ok(self.valid)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_invoke() {
+        let executor = TeeExecutor::new(Arc::new(EchoTee { valid: true }));
+        let input = serde_json::json!({"x": 1});
+
+        let outcome = executor
+            .invoke("1", &TeePolicy::default(), "code", input.clone())
+            .await
+            .unwrap();
+        let TeeOutcome::Executed(execution) = outcome else {
+            panic!("expected the TEE to run the invocation");
+        };
+        assert_eq!(execution.result, input);
+        assert_eq!(execution.attestation.code_hash, "code");
+
+        // Unsupported platforms fail, or fall back to the sandbox if the policy allows it
+        let mut policy = TeePolicy {
+            platform: Some(TeePlatform::Nitro),
+            fallback: TeeFallback::Fail,
+        };
+        let result = executor.invoke("2", &policy, "code", input.clone()).await;
+        assert!(matches!(result, Err(TeeError::Provider(_))));
+        policy.fallback = TeeFallback::Sandbox;
+        let outcome = executor.invoke("3", &policy, "code", input.clone()).await;
+        assert!(matches!(
+            outcome,
+            Ok(TeeOutcome::Fallback(TeeError::Provider(_)))
+        ));
+
+        // Results are rejected without a valid attestation
+        let executor = TeeExecutor::new(Arc::new(EchoTee { valid: false }));
+        let result = executor
+            .execute("4", &TeePolicy::default(), "code", input)
+            .await;
+        assert!(matches!(result, Err(TeeError::Attestation(_))));
+    }
+
+    #[test]
+    fn test_policy_from_metadata() {
+        assert_eq!(TeePolicy::from_metadata_str("").unwrap(), None);
+        assert_eq!(
+            TeePolicy::from_metadata_str(r#"{"name": "f"}"#).unwrap(),
+            None
+        );
+        assert_eq!(
+            TeePolicy::from_metadata_str(r#"{"tee": false}"#).unwrap(),
+            None
+        );
+
+        let policy = TeePolicy::from_metadata_str(r#"{"tee": true}"#)
+            .unwrap()
+            .unwrap();
+        assert_eq!(policy, TeePolicy::default());
+        assert_eq!(policy.fallback, TeeFallback::Fail);
+
+        let metadata = r#"{"tee": true, "tee_platform": "Nitro", "tee_fallback": "sandbox"}"#;
+        let policy = TeePolicy::from_metadata_str(metadata).unwrap().unwrap();
+        assert_eq!(policy.platform, Some(TeePlatform::Nitro));
+        assert_eq!(policy.fallback, TeeFallback::Sandbox);
+
+        assert!(TeePolicy::from_metadata_str(r#"{"tee": true, "tee_fallback": "x"}"#).is_err());
+        assert!(TeePolicy::from_metadata_str("not json").is_err());
+    }
+}
@@ -0,0 +1,243 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+//! Keep-warm policies of functions.
+//!
+//! Functions whose metadata has a `keep_warm` policy have their runtime loaded by the first
+//! `min_instances` runner slots as soon as a runner starts, instead of on their first
+//! invocation. While a runner waits for tasks, it calls the warmup entrypoint of each warm
+//! function every interval, and reloads the runtimes evicted in the meantime.
+
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+/// Shortest interval between the warmup pings of a function
+pub const MIN_WARMUP_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Keep-warm settings of a function
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WarmupPolicy {
+    /// Runner slots keeping a runtime of the function loaded
+    pub min_instances: u32,
+
+    /// Interval between the warmup pings
+    pub interval: Duration,
+
+    /// Export called with no arguments by the warmup pings
+    pub entrypoint: String,
+}
+
+/// Keep-warm settings as they appear in function metadata
+#[derive(Debug, Deserialize)]
+struct WarmupMetadata {
+    #[serde(default)]
+    keep_warm: Option<KeepWarm>,
+}
+
+#[derive(Debug, Deserialize)]
+struct KeepWarm {
+    #[serde(default = "default_min_instances")]
+    min_instances: u32,
+    #[serde(default = "default_interval_secs")]
+    interval_secs: u64,
+    #[serde(default = "default_entrypoint")]
+    entrypoint: String,
+}
+
+fn default_min_instances() -> u32 {
+    1
+}
+
+fn default_interval_secs() -> u64 {
+    60
+}
+
+fn default_entrypoint() -> String {
+    "warmup".to_string()
+}
+
+impl WarmupPolicy {
+    /// Policy of a function from its metadata, None unless the metadata has a `keep_warm`
+    /// policy with at least one instance
+    pub fn from_metadata(metadata: &serde_json::Value) -> Result<Option<Self>, String> {
+        if metadata.is_null() {
+            return Ok(None);
+        }
+
+        let metadata = WarmupMetadata::deserialize(metadata)
+            .map_err(|err| format!("invalid keep_warm metadata: {}", err))?;
+        let Some(keep_warm) = metadata.keep_warm else {
+            return Ok(None);
+        };
+        if keep_warm.entrypoint.is_empty() {
+            return Err("invalid keep_warm metadata: empty entrypoint".to_string());
+        }
+
+        Ok((keep_warm.min_instances > 0).then(|| Self {
+            min_instances: keep_warm.min_instances,
+            interval: Duration::from_secs(keep_warm.interval_secs).max(MIN_WARMUP_INTERVAL),
+            entrypoint: keep_warm.entrypoint,
+        }))
+    }
+
+    /// Policy of a function from its JSON encoded metadata, which may be empty
+    pub fn from_metadata_str(metadata: &str) -> Result<Option<Self>, String> {
+        if metadata.trim().is_empty() {
+            return Ok(None);
+        }
+
+        let metadata = serde_json::from_str(metadata)
+            .map_err(|err| format!("invalid function metadata: {}", err))?;
+        Self::from_metadata(&metadata)
+    }
+
+    /// Whether the runner in a slot keeps the function warm
+    pub fn covers(&self, slot: u32) -> bool {
+        slot < self.min_instances
+    }
+}
+
+/// Warmup pings of the functions a runner keeps warm
+#[derive(Debug, Default)]
+pub struct WarmupSchedule {
+    slot: u32,
+    // Entrypoint, interval and next ping by function
+    functions: BTreeMap<u64, (String, Duration, Instant)>,
+}
+
+impl WarmupSchedule {
+    /// Schedule of the runner in a slot
+    pub fn new(slot: u32) -> Self {
+        Self {
+            slot,
+            functions: BTreeMap::new(),
+        }
+    }
+
+    /// Slot of the runner, the lowest not taken by the other runners of the worker
+    pub fn slot(&self) -> u32 {
+        self.slot
+    }
+
+    /// Keep a function warm if its policy covers the runner's slot, or stop keeping it warm.
+    /// Returns whether the function is kept warm.
+    pub fn track(&mut self, fid: u64, policy: Option<&WarmupPolicy>, now: Instant) -> bool {
+        match policy.filter(|policy| policy.covers(self.slot)) {
+            Some(policy) => {
+                let next = match self.functions.get(&fid) {
+                    Some((_, interval, next)) if *interval == policy.interval => *next,
+                    _ => now + policy.interval,
+                };
+                self.functions
+                    .insert(fid, (policy.entrypoint.clone(), policy.interval, next));
+                true
+            }
+            None => {
+                self.functions.remove(&fid);
+                false
+            }
+        }
+    }
+
+    /// Whether a function is kept warm
+    pub fn is_warm(&self, fid: u64) -> bool {
+        self.functions.contains_key(&fid)
+    }
+
+    /// Functions kept warm
+    pub fn functions(&self) -> impl Iterator<Item = u64> + '_ {
+        self.functions.keys().copied()
+    }
+
+    /// Time of the next warmup ping, None if no function is kept warm
+    pub fn next_due(&self) -> Option<Instant> {
+        self.functions.values().map(|(_, _, next)| *next).min()
+    }
+
+    /// Functions whose warmup ping is due, with their entrypoint, rescheduling their next ping
+    pub fn take_due(&mut self, now: Instant) -> Vec<(u64, String)> {
+        self.functions
+            .iter_mut()
+            .filter(|(_, (_, _, next))| *next <= now)
+            .map(|(fid, (entrypoint, interval, next))| {
+                *next = now + *interval;
+                (*fid, entrypoint.clone())
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_policy_from_metadata() {
+        assert_eq!(WarmupPolicy::from_metadata_str("").unwrap(), None);
+        assert_eq!(
+            WarmupPolicy::from_metadata_str(r#"{"tee": true}"#).unwrap(),
+            None
+        );
+        assert_eq!(
+            WarmupPolicy::from_metadata_str(r#"{"keep_warm": {"min_instances": 0}}"#).unwrap(),
+            None
+        );
+
+        let policy = WarmupPolicy::from_metadata_str(r#"{"keep_warm": {}}"#)
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            policy,
+            WarmupPolicy {
+                min_instances: 1,
+                interval: Duration::from_secs(60),
+                entrypoint: "warmup".to_string(),
+            }
+        );
+
+        let metadata =
+            r#"{"keep_warm": {"min_instances": 2, "interval_secs": 0, "entrypoint": "ping"}}"#;
+        let policy = WarmupPolicy::from_metadata_str(metadata).unwrap().unwrap();
+        assert_eq!(policy.interval, MIN_WARMUP_INTERVAL);
+        assert!(policy.covers(1));
+        assert!(!policy.covers(2));
+
+        assert!(WarmupPolicy::from_metadata_str(r#"{"keep_warm": {"entrypoint": ""}}"#).is_err());
+        assert!(WarmupPolicy::from_metadata_str(r#"{"keep_warm": 1}"#).is_err());
+    }
+
+    #[test]
+    fn test_warmup_schedule() {
+        let policy = |min_instances, interval_secs| WarmupPolicy {
+            min_instances,
+            interval: Duration::from_secs(interval_secs),
+            entrypoint: "warmup".to_string(),
+        };
+
+        let start = Instant::now();
+        let mut schedule = WarmupSchedule::new(1);
+        assert!(!schedule.track(1, Some(&policy(1, 10)), start));
+        assert!(schedule.track(2, Some(&policy(2, 10)), start));
+        assert!(schedule.track(3, Some(&policy(2, 30)), start));
+        assert!(!schedule.track(4, None, start));
+        assert_eq!(schedule.functions().collect::<Vec<_>>(), vec![2, 3]);
+        assert_eq!(schedule.next_due(), Some(start + Duration::from_secs(10)));
+
+        // Pings are rescheduled once taken
+        assert!(schedule.take_due(start).is_empty());
+        let now = start + Duration::from_secs(10);
+        assert_eq!(schedule.take_due(now), vec![(2, "warmup".to_string())]);
+        assert_eq!(schedule.next_due(), Some(now + Duration::from_secs(10)));
+
+        // Tracking again keeps the schedule, unless the interval changed
+        assert!(schedule.track(2, Some(&policy(2, 10)), now));
+        assert_eq!(schedule.next_due(), Some(now + Duration::from_secs(10)));
+
+        // A policy no longer covering the slot stops the pings
+        assert!(!schedule.track(2, Some(&policy(1, 10)), now));
+        assert!(!schedule.is_warm(2));
+        assert_eq!(schedule.next_due(), Some(start + Duration::from_secs(30)));
+    }
+}
@@ -9,19 +9,49 @@ use std::time::Duration;
 
 use libc::pid_t;
 use log::{debug, error, info, warn};
-use signal_hook::consts::SIGINT;
+use signal_hook::consts::{SIGINT, SIGTERM};
 use tokio::sync::mpsc;
 
-use r3e_built_in_services::balance::{BalanceService, MemoryBalanceStorage};
+use r3e_built_in_services::alerts::{AlertManager, ApiAlertSink};
+use r3e_built_in_services::balance::{
+    BalanceAdmission, BalanceService, BalanceStorage, MemoryBalanceStorage,
+};
+use r3e_built_in_services::billing::BillingServiceTrait;
 use r3e_built_in_services::gas_bank::GasBankServiceTrait;
+use r3e_core::chain::ChainClients;
+use r3e_deno::ext::chain::ChainQueries;
+use r3e_deno::ext::services::{HttpServiceInvoker, ServiceInvoker};
+use r3e_deno::sandbox::{FileGrantStore, FilePolicyStore, PermissionGrants, PolicyEngine};
 use r3e_event::source::TaskSource;
+use r3e_neo_services::nft::NftServiceTrait;
+use r3e_oracle::OracleService;
+use r3e_tee::sealing::SealedStorage;
+use r3e_tee::TeeService;
 
-use crate::{RunHandle, Runner, Stopper, TaskConfig, TaskSourceBuilder, WorkerConfig};
+use crate::admin::{self, StatusBoard};
+use crate::autoscale::{Autoscaler, ScaleBackend};
+use crate::drain::{CheckpointStore, Drainer};
+use crate::seccomp;
+use crate::{DrainStatus, RunHandle, Runner, Stopper, TaskConfig, TaskSourceBuilder, WorkerConfig};
 
 pub struct Worker {
     config: WorkerConfig,
     stop: Arc<AtomicBool>,
     runners: Arc<Mutex<HashMap<pid_t, RunHandle>>>,
+    drainer: Arc<Drainer>,
+    status_board: Option<Arc<StatusBoard>>,
+    oracle_service: Option<Arc<dyn OracleService>>,
+    tee_service: Option<Arc<dyn TeeService>>,
+    nft_service: Option<Arc<dyn NftServiceTrait>>,
+    sealed_storage: Option<Arc<SealedStorage>>,
+    billing_service: Option<Arc<dyn BillingServiceTrait>>,
+    balance_storage: Option<Arc<dyn BalanceStorage>>,
+    permission_grants: Option<PermissionGrants>,
+    policy_engine: Option<PolicyEngine>,
+    service_invoker: Option<Arc<dyn ServiceInvoker>>,
+    chain_queries: Arc<ChainQueries>,
+    alert_manager: Arc<AlertManager>,
+    scale_backend: Option<Arc<dyn ScaleBackend>>,
 }
 
 impl Worker {
@@ -29,19 +59,192 @@ impl Worker {
         let stop = Arc::new(AtomicBool::new(false));
         let runners = Arc::new(Mutex::new(HashMap::new()));
 
+        let checkpoints = config.drain.checkpoint_dir.as_ref().and_then(|dir| {
+            CheckpointStore::open(dir)
+                .map_err(|err| error!("worker: open checkpoints {:?} failed: {}", dir, err))
+                .ok()
+        });
+        let drainer = Arc::new(Drainer::new(config.graceful, checkpoints));
+
+        let permission_grants = config.permission_grants_dir.as_ref().and_then(|dir| {
+            FileGrantStore::new(dir)
+                .map(|store| PermissionGrants::new(Arc::new(store)))
+                .map_err(|err| error!("worker: open permission grants {:?} failed: {}", dir, err))
+                .ok()
+        });
+
+        let policy_engine = config.policy_dir.as_ref().and_then(|dir| {
+            FilePolicyStore::new(dir)
+                .map(|store| PolicyEngine::new(Arc::new(store)))
+                .map_err(|err| error!("worker: open policies {:?} failed: {}", dir, err))
+                .ok()
+        });
+
+        let service_invoker = config
+            .service_api
+            .as_ref()
+            .map(|api| Arc::new(HttpServiceInvoker::new(api)) as Arc<dyn ServiceInvoker>);
+
+        // Chain clients connect from the environment on the first query of a function
+        let chain_queries = Arc::new(ChainQueries::new(
+            Arc::new(ChainClients::new()),
+            config.chain.clone(),
+        ));
+
+        // Alerts are logged, and forwarded to the API to notify the users' channels
+        let alert_manager = match &config.service_api {
+            Some(api) => AlertManager::default().with_sink(Arc::new(
+                ApiAlertSink::new(&api.base_url, api.token.clone()).with_client(api.client()),
+            )),
+            None => AlertManager::default(),
+        };
+
+        let scale_backend = config
+            .autoscale
+            .backend
+            .as_ref()
+            .map(|backend| backend.build());
+
+        // Runners publish their status for the admin endpoint, and compare their loads on it
+        let status_board = if config.drain.admin_addr.is_some()
+            || config.scheduling.enabled
+            || config.autoscale.enabled
+        {
+            let dir = config.drain.status_dir.clone().unwrap_or_else(|| {
+                std::env::temp_dir().join(format!("r3e-worker-{}", std::process::id()))
+            });
+            StatusBoard::open(&dir)
+                .map(Arc::new)
+                .map_err(|err| error!("worker: open status board {:?} failed: {}", dir, err))
+                .ok()
+        } else {
+            None
+        };
+
         Self {
             config,
             stop,
             runners,
+            drainer,
+            status_board,
+            oracle_service: None,
+            tee_service: None,
+            nft_service: None,
+            sealed_storage: None,
+            billing_service: None,
+            balance_storage: None,
+            permission_grants,
+            policy_engine,
+            service_invoker,
+            chain_queries,
+            alert_manager: Arc::new(alert_manager),
+            scale_backend,
         }
     }
 
+    /// Oracle service the functions' oracle ops use
+    pub fn with_oracle_service(mut self, oracle_service: Arc<dyn OracleService>) -> Self {
+        self.oracle_service = Some(oracle_service);
+        self
+    }
+
+    /// TEE service the functions' TEE ops use
+    pub fn with_tee_service(mut self, tee_service: Arc<dyn TeeService>) -> Self {
+        self.tee_service = Some(tee_service);
+        self
+    }
+
+    /// NEP-11 service the functions' NFT ops use
+    pub fn with_nft_service(mut self, nft_service: Arc<dyn NftServiceTrait>) -> Self {
+        self.nft_service = Some(nft_service);
+        self
+    }
+
+    /// Sealed storage keeping the private state of functions running in TEE mode
+    pub fn with_sealed_storage(mut self, sealed_storage: Arc<SealedStorage>) -> Self {
+        self.sealed_storage = Some(sealed_storage);
+        self
+    }
+
+    /// Billing service deciding whether a tenant's functions may run
+    pub fn with_billing_service(mut self, billing_service: Arc<dyn BillingServiceTrait>) -> Self {
+        self.billing_service = Some(billing_service);
+        self
+    }
+
+    /// Balances of the tenants, shared with the API, that runs are checked against to alert
+    /// tenants running low on GAS
+    pub fn with_balance_storage(mut self, balance_storage: Arc<dyn BalanceStorage>) -> Self {
+        self.balance_storage = Some(balance_storage);
+        self
+    }
+
+    /// Alert manager tenants running low on GAS are reported to
+    pub fn with_alert_manager(mut self, alert_manager: Arc<AlertManager>) -> Self {
+        self.alert_manager = alert_manager;
+        self
+    }
+
+    /// Backend the desired replica count is pushed to, instead of the configured one
+    pub fn with_scale_backend(mut self, scale_backend: Arc<dyn ScaleBackend>) -> Self {
+        self.scale_backend = Some(scale_backend);
+        self
+    }
+
+    /// Start draining, as on SIGINT or SIGTERM
+    pub fn drain(&self) {
+        self.stop.stop();
+    }
+
+    pub fn drain_status(&self) -> DrainStatus {
+        self.drainer.status()
+    }
+
     pub fn run(&self) {
         let (tx, mut rx) = mpsc::channel::<pid_t>(self.config.max_pending as usize);
 
-        // Register signal handler
+        // Register signal handlers, inherited by the forked runners
         let stop = self.stop.clone();
         let _ = signal_hook::flag::register(SIGINT, Arc::clone(&stop));
+        let _ = signal_hook::flag::register(SIGTERM, Arc::clone(&stop));
+
+        let autoscaler = match &self.status_board {
+            Some(board) if self.config.autoscale.enabled => {
+                let autoscaler = Arc::new(
+                    Autoscaler::new(
+                        self.config.autoscale.clone(),
+                        board.clone(),
+                        self.config.max_runners(),
+                    )
+                    .with_backend(self.scale_backend.clone()),
+                );
+                autoscaler.clone().spawn_push(self.stop.clone());
+                Some(autoscaler)
+            }
+            _ => None,
+        };
+
+        if let Some(addr) = self.config.drain.admin_addr {
+            let (drainer, board) = (self.drainer.clone(), self.status_board.clone());
+            let mtls = self.config.drain.admin_mtls.clone();
+            let stop = self.stop.clone();
+            if let Err(err) = admin::serve_admin(addr, drainer, board, autoscaler, mtls, stop) {
+                error!("worker: serve admin endpoint on {} failed: {}", addr, err);
+            }
+        }
+
+        // Tasks checkpointed by a previous worker go to the first runner
+        let checkpoints = self.drainer.checkpoints().cloned().map(Arc::new);
+        let mut replay = match &checkpoints {
+            Some(checkpoints) => checkpoints.load_all().unwrap_or_else(|err| {
+                error!("worker: load checkpoints failed: {}", err);
+                Vec::new()
+            }),
+            None => Vec::new(),
+        };
+        if !replay.is_empty() {
+            info!("worker: replaying {} checkpointed tasks", replay.len());
+        }
 
         // Spawn runner manager
         let runners = self.runners.clone();
@@ -49,6 +252,11 @@ impl Worker {
         let max_runners = self.config.max_runners();
         let max_runtimes = self.config.max_runtimes_per_runner;
         let task_config = self.config.tasks.clone();
+        let warm_functions = self.config.warm_functions();
+        let seccomp_config = self.config.seccomp.clone();
+        let jitless = !self.config.sandbox.enable_jit;
+        let drainer = self.drainer.clone();
+        let reap_tx = tx.clone();
 
         let handle = thread::spawn(move || {
             let rt = tokio::runtime::Builder::new_current_thread()
@@ -62,19 +270,28 @@ impl Worker {
                     if runners.lock().unwrap().len() >= max_runners as usize {
                         // Wait for a runner to exit
                         match rx.recv().await {
-                            Some(pid) => {
-                                debug!("worker: runner {} exited", pid);
-                                runners.lock().unwrap().remove(&pid);
-                            }
+                            Some(pid) => debug!("worker: runner {} exited", pid),
                             None => {
                                 error!("worker: runner channel closed");
                                 break;
                             }
                         }
+
+                        // Do not replace exited runners while draining
+                        if stop2.load(Ordering::Relaxed) {
+                            break;
+                        }
                     }
 
-                    // Spawn a new runner
+                    // Spawn a new runner in the lowest free slot, so replaced runners keep the
+                    // slots covered by keep-warm policies warm
                     uid += 1;
+                    let slot = {
+                        let runners = runners.lock().unwrap();
+                        (0..max_runners)
+                            .find(|slot| runners.values().all(|handle| handle.slot != *slot))
+                            .unwrap_or(0)
+                    };
                     let task_source = TaskSourceBuilder::new(task_config.clone()).build();
 
                     // Create a balance service
@@ -91,13 +308,36 @@ impl Worker {
 
                     let balance_service =
                         Arc::new(BalanceService::new(balance_storage, gas_bank_service));
+                    let admission = self.balance_storage.clone().map(|balances| {
+                        Arc::new(
+                            BalanceAdmission::new(balances, self.config.admission)
+                                .with_alerts(self.alert_manager.clone()),
+                        )
+                    });
 
                     // Get the sandbox configuration
                     let sandbox_config = self.config.sandbox.clone();
 
                     let runner = Runner::new(uid, max_runtimes, task_source)
                         .with_balance_service(balance_service)
-                        .with_sandbox_config(sandbox_config);
+                        .with_admission(admission)
+                        .with_sandbox_config(sandbox_config)
+                        .with_checkpoints(checkpoints.clone())
+                        .with_lanes(self.config.lanes.clone())
+                        .with_replay(std::mem::take(&mut replay))
+                        .with_warmup(slot, warm_functions.clone())
+                        .with_status_board(self.status_board.clone())
+                        .with_alerts(Some(self.alert_manager.clone()))
+                        .with_scheduling(self.config.scheduling.clone())
+                        .with_oracle_service(self.oracle_service.clone())
+                        .with_tee_service(self.tee_service.clone())
+                        .with_nft_service(self.nft_service.clone())
+                        .with_sealed_storage(self.sealed_storage.clone())
+                        .with_billing_service(self.billing_service.clone())
+                        .with_permission_grants(self.permission_grants.clone())
+                        .with_policy_engine(self.policy_engine.clone())
+                        .with_service_invoker(self.service_invoker.clone())
+                        .with_chain_queries(Some(self.chain_queries.clone()));
 
                     let stop = stop2.clone();
                     let tx = tx.clone();
@@ -111,52 +351,100 @@ impl Worker {
                             // Child process
                             drop(tx);
                             drop(runners);
+                            // Before the runner starts its threads, so that all inherit it
+                            if seccomp_config.enabled {
+                                if let Err(err) = seccomp::install(&seccomp_config, jitless) {
+                                    error!("worker: runner {} seccomp failed: {}", uid, err);
+                                    std::process::exit(1);
+                                }
+                            }
                             runner.run(stop);
                             std::process::exit(0);
                         }
                         _ => {
                             // Parent process
-                            info!("worker: spawned runner {} with pid {}", uid, pid);
-                            runners
-                                .lock()
-                                .unwrap()
-                                .insert(pid, RunHandle::new(pid, true));
+                            info!(
+                                "worker: spawned runner {} in slot {} with pid {}",
+                                uid, slot, pid
+                            );
+                            let mut runners = runners.lock().unwrap();
+                            runners.insert(pid, RunHandle::new(pid, slot, true));
+                            drainer.set_runners(runners.len() as u32);
                         }
                     }
                 }
             });
         });
 
-        // Wait for stop signal
+        // Wait for stop signal, reaping runners as they exit
         while !self.stop.load(Ordering::Relaxed) {
+            self.reap(&reap_tx);
             thread::sleep(Duration::from_millis(100));
         }
 
-        info!("worker: stopping");
+        // Drain: runners finish their in-flight task and stop acquiring new ones
+        self.drainer.begin();
+        info!("worker: draining for up to {:?}", self.config.graceful);
+        for pid in self.runners.lock().unwrap().keys() {
+            unsafe {
+                libc::kill(*pid, SIGINT);
+            }
+        }
 
-        // Wait for all runners to exit
-        let graceful = self.config.graceful;
         let start = std::time::Instant::now();
-        while start.elapsed() < graceful {
+        while start.elapsed() < self.config.graceful {
+            self.reap(&reap_tx);
             if self.runners.lock().unwrap().is_empty() {
                 break;
             }
-            thread::sleep(Duration::from_millis(100));
+            thread::sleep(Duration::from_millis(50));
         }
 
-        // Kill remaining runners
-        let mut runners = self.runners.lock().unwrap();
-        for (pid, _) in runners.iter() {
+        // Kill runners still busy, their tasks stay checkpointed for the next worker
+        let busy: Vec<pid_t> = self.runners.lock().unwrap().keys().copied().collect();
+        for pid in busy.iter() {
+            warn!(
+                "worker: runner {} still busy after {:?}, killing",
+                pid, self.config.graceful
+            );
             unsafe {
-                libc::kill(*pid, SIGINT);
+                libc::kill(*pid, libc::SIGKILL);
+                libc::waitpid(*pid, std::ptr::null_mut(), 0);
             }
+            self.forget(*pid, &reap_tx);
         }
-        runners.clear();
+        self.drainer.finish(busy.len() as u32);
 
         // Wait for runner manager to exit
         let _ = handle.join();
 
-        info!("worker: stopped");
+        info!("worker: stopped, {:?}", self.drainer.status());
+    }
+
+    fn reap(&self, reap_tx: &mpsc::Sender<pid_t>) {
+        loop {
+            let pid = unsafe { libc::waitpid(-1, std::ptr::null_mut(), libc::WNOHANG) };
+            if pid <= 0 {
+                break;
+            }
+            self.forget(pid, reap_tx);
+        }
+    }
+
+    fn forget(&self, pid: pid_t, reap_tx: &mpsc::Sender<pid_t>) {
+        let mut runners = self.runners.lock().unwrap();
+        if let Some(mut handle) = runners.remove(&pid) {
+            // Already exited, nothing to kill
+            handle.kill_on_drop = false;
+        }
+        self.drainer.set_runners(runners.len() as u32);
+        drop(runners);
+
+        if let Some(board) = &self.status_board {
+            board.remove(pid as u32);
+        }
+
+        let _ = reap_tx.try_send(pid);
     }
 }
 
@@ -0,0 +1,206 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::routing::get;
+use axum::{Json, Router};
+use serde_json::{json, Value};
+use tokio::sync::Mutex;
+
+use r3e_event::source::synthetic::{parse_synthetic_events, SyntheticEvent};
+use r3e_oracle::simulated::SimulatedOracleService;
+use r3e_tee::sealing::{EnclaveIdentity, MemorySealedBlobStore, SealedStorage, SimulatedSealer};
+use r3e_tee::service::TeeServiceImpl;
+use r3e_worker::{TaskConfig, Worker, WorkerConfig};
+
+#[derive(clap::Args)]
+pub struct DevCmd {
+    #[arg(
+        long = "function",
+        required = true,
+        help = "A function to serve, as FID=PATH to its JavaScript code"
+    )]
+    functions: Vec<String>,
+
+    #[arg(
+        long,
+        help = "A JSON file of synthetic Neo/Ethereum events, an array or one event per line"
+    )]
+    events: Option<String>,
+
+    #[arg(long, default_value = "127.0.0.1:8080", help = "The dev API address")]
+    addr: SocketAddr,
+
+    #[arg(long, default_value = "./data/dev", help = "The dev data directory")]
+    data_dir: String,
+}
+
+struct DevState {
+    /// Function code paths by function ID
+    functions: BTreeMap<u64, String>,
+
+    /// Events fed to the worker
+    feed: PathBuf,
+
+    /// Events fed so far, in memory
+    events: Mutex<Vec<SyntheticEvent>>,
+}
+
+impl DevCmd {
+    pub fn run(&self) -> anyhow::Result<()> {
+        let functions = self.parse_functions()?;
+
+        // Seed the feed the worker follows, the dev API appends to it
+        std::fs::create_dir_all(&self.data_dir)?;
+        let feed = Path::new(&self.data_dir).join("events.jsonl");
+        let events = match &self.events {
+            Some(events) => {
+                let mut content = crate::read_file(events)?;
+                content.push('\n');
+                parse_synthetic_events(&content)?
+            }
+            None => Vec::new(),
+        };
+        let mut content = String::new();
+        for event in &events {
+            content.push_str(&serde_json::to_string(event)?);
+            content.push('\n');
+        }
+        std::fs::write(&feed, content)?;
+
+        // A single runner, so every event is delivered once
+        let config = WorkerConfig {
+            max_runners: 1,
+            tasks: TaskConfig {
+                sleep_ms: 200,
+                source_type: "synthetic".to_string(),
+                events_file: Some(feed.to_string_lossy().into_owned()),
+                functions: functions.clone().into_iter().collect::<HashMap<_, _>>(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        // Sealed state lives in memory for the session, sealed with a fixed dev secret
+        let identity = EnclaveIdentity::simulated(env!("CARGO_PKG_VERSION").as_bytes(), "r3e-dev");
+        let sealer = SimulatedSealer::new(identity, b"r3e-dev-platform-secret")?;
+        let sealed_storage =
+            SealedStorage::new(Arc::new(sealer), Arc::new(MemorySealedBlobStore::new()));
+
+        let worker = Worker::new(config)
+            .with_oracle_service(Arc::new(SimulatedOracleService::new()))
+            .with_tee_service(Arc::new(TeeServiceImpl::new()))
+            .with_sealed_storage(Arc::new(sealed_storage));
+
+        let state = Arc::new(DevState {
+            functions,
+            feed,
+            events: Mutex::new(events),
+        });
+
+        let rt = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(1)
+            .enable_all()
+            .build()?;
+        let listener = rt.block_on(tokio::net::TcpListener::bind(self.addr))?;
+        log::info!("dev: API listening on http://{}", self.addr);
+        rt.spawn(async move {
+            if let Err(err) = axum::serve(listener, router(state)).await {
+                log::error!("dev: API error: {}", err);
+            }
+        });
+
+        // Runs until SIGINT or SIGTERM
+        worker.run();
+
+        rt.shutdown_background();
+        log::warn!("dev exited, pid {}", std::process::id());
+        Ok(())
+    }
+
+    fn parse_functions(&self) -> anyhow::Result<BTreeMap<u64, String>> {
+        let mut functions = BTreeMap::new();
+        for function in &self.functions {
+            let (fid, path) = function
+                .split_once('=')
+                .ok_or_else(|| anyhow::anyhow!("function must be FID=PATH: {}", function))?;
+            let fid: u64 = fid
+                .parse()
+                .map_err(|err| anyhow::anyhow!("invalid function id {}: {}", fid, err))?;
+            if !Path::new(path).is_file() {
+                anyhow::bail!("function {} code not found: {}", fid, path);
+            }
+            functions.insert(fid, path.to_string());
+        }
+        Ok(functions)
+    }
+}
+
+fn router(state: Arc<DevState>) -> Router {
+    Router::new()
+        .route("/health", get(health))
+        .route("/functions", get(list_functions))
+        .route("/events", get(list_events).post(push_event))
+        .with_state(state)
+}
+
+async fn health() -> Json<Value> {
+    Json(json!({ "status": "ok", "mode": "dev" }))
+}
+
+async fn list_functions(State(state): State<Arc<DevState>>) -> Json<Value> {
+    let functions: Vec<Value> = state
+        .functions
+        .iter()
+        .map(|(fid, path)| json!({ "fid": fid, "path": path }))
+        .collect();
+    Json(json!(functions))
+}
+
+async fn list_events(State(state): State<Arc<DevState>>) -> Json<Vec<SyntheticEvent>> {
+    Json(state.events.lock().await.clone())
+}
+
+async fn push_event(
+    State(state): State<Arc<DevState>>,
+    Json(event): Json<SyntheticEvent>,
+) -> (StatusCode, Json<Value>) {
+    if !state.functions.contains_key(&event.fid) {
+        return (
+            StatusCode::NOT_FOUND,
+            Json(json!({ "error": format!("no such function: {}", event.fid) })),
+        );
+    }
+
+    let mut events = state.events.lock().await;
+    let appended = serde_json::to_string(&event)
+        .map_err(|err| err.to_string())
+        .and_then(|line| {
+            let mut feed = OpenOptions::new()
+                .append(true)
+                .open(&state.feed)
+                .map_err(|err| err.to_string())?;
+            feed.write_all(format!("{}\n", line).as_bytes())
+                .map_err(|err| err.to_string())
+        });
+    if let Err(err) = appended {
+        return (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({ "error": format!("append event: {}", err) })),
+        );
+    }
+
+    events.push(event);
+    (
+        StatusCode::ACCEPTED,
+        Json(json!({ "queued": events.len() })),
+    )
+}
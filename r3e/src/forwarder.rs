@@ -0,0 +1,176 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+use std::path::PathBuf;
+
+use clap::Subcommand;
+
+use r3e_neo_services::meta_tx::forwarder::{
+    deploy_forwarder, load_bytecode, parse_address, set_relayers, signer_client,
+};
+use r3e_neo_services::meta_tx::{ForwarderConfig, ForwarderNetwork};
+
+#[derive(clap::Args)]
+pub struct ForwarderCmd {
+    #[arg(
+        long,
+        default_value = "./config/forwarders.json",
+        help = "The forwarders file, read by the endpoints service from FORWARDERS_PATH"
+    )]
+    config: PathBuf,
+
+    #[arg(
+        long,
+        env = "ETH_DEPLOYER_KEY",
+        help = "The hex private key deploying and owning the forwarders"
+    )]
+    private_key: Option<String>,
+
+    #[command(subcommand)]
+    action: ForwarderAction,
+}
+
+#[derive(Subcommand)]
+enum ForwarderAction {
+    #[command(about = "Deploy the forwarder to a network")]
+    Deploy {
+        #[arg(long, help = "The network name, e.g. sepolia")]
+        network: String,
+
+        #[arg(long, help = "The EIP-155 chain ID, required for a new network")]
+        chain_id: Option<u64>,
+
+        #[arg(long, help = "The JSON-RPC URL, required for a new network")]
+        rpc_url: Option<String>,
+
+        #[arg(
+            long,
+            help = "The hex creation bytecode built by `make forwarder`, the embedded one without it"
+        )]
+        bytecode: Option<PathBuf>,
+
+        #[arg(long, help = "A relayer to trust, repeatable")]
+        relayer: Vec<String>,
+    },
+
+    #[command(about = "Trust or distrust relayers of a deployed forwarder")]
+    Relayers {
+        #[arg(long, help = "The network name")]
+        network: String,
+
+        #[arg(long, help = "A relayer to trust, repeatable")]
+        add: Vec<String>,
+
+        #[arg(long, help = "A relayer to distrust, repeatable")]
+        remove: Vec<String>,
+    },
+
+    #[command(about = "Show the forwarder of a network")]
+    Show {
+        #[arg(long, help = "The network name")]
+        network: String,
+    },
+}
+
+impl ForwarderCmd {
+    pub fn run(&self) -> anyhow::Result<()> {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?;
+        rt.block_on(self.do_run())
+    }
+
+    async fn do_run(&self) -> anyhow::Result<()> {
+        let mut config = ForwarderConfig::load(&self.config)?;
+
+        match &self.action {
+            ForwarderAction::Show { network } => {
+                let network = config
+                    .network(network)
+                    .ok_or_else(|| anyhow::anyhow!("no forwarder for network {}", network))?;
+                println!("{}", serde_json::to_string_pretty(network)?);
+                return Ok(());
+            }
+            ForwarderAction::Deploy {
+                network,
+                chain_id,
+                rpc_url,
+                bytecode,
+                relayer,
+            } => {
+                let existing = config.network(network);
+                let chain_id = chain_id
+                    .or(existing.map(|n| n.chain_id))
+                    .ok_or_else(|| anyhow::anyhow!("--chain-id is required for {}", network))?;
+                let rpc_url = rpc_url
+                    .clone()
+                    .or(existing.map(|n| n.rpc_url.clone()))
+                    .ok_or_else(|| anyhow::anyhow!("--rpc-url is required for {}", network))?;
+                let relayers = relayer
+                    .iter()
+                    .map(|r| parse_address(r))
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                let mut target = ForwarderNetwork {
+                    network: network.clone(),
+                    chain_id,
+                    rpc_url,
+                    address: None,
+                    relayers: relayers.iter().map(|r| format!("{:?}", r)).collect(),
+                };
+                let client = signer_client(&target, self.private_key()?)?;
+                let bytecode = load_bytecode(bytecode.as_deref())?;
+                let address = deploy_forwarder(client, bytecode, relayers).await?;
+                log::info!("forwarder: deployed to {} at {:?}", network, address);
+
+                target.address = Some(format!("{:?}", address));
+                config.upsert(target);
+            }
+            ForwarderAction::Relayers {
+                network,
+                add,
+                remove,
+            } => {
+                let mut target = config
+                    .network(network)
+                    .cloned()
+                    .ok_or_else(|| anyhow::anyhow!("no forwarder for network {}", network))?;
+                let address = target
+                    .address
+                    .as_deref()
+                    .ok_or_else(|| anyhow::anyhow!("the forwarder of {} is not deployed", network))
+                    .and_then(|a| Ok(parse_address(a)?))?;
+                let add = add
+                    .iter()
+                    .map(|r| parse_address(r))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let remove = remove
+                    .iter()
+                    .map(|r| parse_address(r))
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                let client = signer_client(&target, self.private_key()?)?;
+                let added = set_relayers(client.clone(), address, &add, true).await?;
+                let removed = set_relayers(client, address, &remove, false).await?;
+                log::info!(
+                    "forwarder: {} trusts {} new and distrusts {} relayers",
+                    network,
+                    added.len(),
+                    removed.len()
+                );
+
+                target.update_relayers(&add, &remove);
+                config.upsert(target);
+            }
+        }
+
+        config.save(&self.config)?;
+        Ok(())
+    }
+
+    fn private_key(&self) -> anyhow::Result<&str> {
+        self.private_key
+            .as_deref()
+            .ok_or_else(|| anyhow::anyhow!("--private-key or ETH_DEPLOYER_KEY is required"))
+    }
+}
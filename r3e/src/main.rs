@@ -6,8 +6,18 @@ use std::io::Read;
 
 use clap::{Parser, Subcommand};
 
+use crate::dev::DevCmd;
+use crate::forwarder::ForwarderCmd;
+use crate::migrate::MigrateCmd;
+use crate::snapshot::SnapshotCmd;
+use crate::tenant_keys::TenantKeysCmd;
 use crate::worker::WorkerCmd;
 
+mod dev;
+mod forwarder;
+mod migrate;
+mod snapshot;
+mod tenant_keys;
 mod worker;
 
 #[derive(Parser)]
@@ -26,10 +36,31 @@ struct Cli {
 enum Commands {
     #[command(about = "Run worker")]
     Worker(WorkerCmd),
+
+    #[command(about = "Export or import platform state snapshots")]
+    Snapshot(SnapshotCmd),
+
+    #[command(about = "Enroll tenants for encryption at rest and rotate their keys")]
+    TenantKeys(TenantKeysCmd),
+
+    #[command(about = "Run a local development emulator without chains or Postgres")]
+    Dev(DevCmd),
+
+    #[command(about = "Deploy and configure the Ethereum meta transaction forwarder")]
+    Forwarder(ForwarderCmd),
+
+    #[command(about = "Upgrade stored records written by older releases")]
+    Migrate(MigrateCmd),
 }
 
 // run worker test mode:
 // r3e-faas --log ./config/log.dev.yaml worker --config ./config/r3e-faas-worker.test.yaml
+//
+// run dev emulator:
+// r3e-faas --log ./config/log.dev.yaml dev --function 1=./examples/dev/hello.js --events ./examples/dev/events.json
+//
+// deploy the forwarder:
+// r3e-faas forwarder deploy --network sepolia --chain-id 11155111 --rpc-url https://rpc.sepolia.org --relayer 0x...
 fn main() -> anyhow::Result<()> {
     let cli = Cli::parse();
 
@@ -40,6 +71,11 @@ fn main() -> anyhow::Result<()> {
 
     match cli.commands {
         Commands::Worker(cmd) => cmd.run()?,
+        Commands::Snapshot(cmd) => cmd.run()?,
+        Commands::TenantKeys(cmd) => cmd.run()?,
+        Commands::Dev(cmd) => cmd.run()?,
+        Commands::Forwarder(cmd) => cmd.run()?,
+        Commands::Migrate(cmd) => cmd.run()?,
     }
 
     Ok(())
@@ -0,0 +1,49 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+use std::collections::BTreeMap;
+
+use r3e_event::registry::rocksdb::RocksDBFunctionStorage;
+use r3e_neo_services::meta_tx::RocksDBMetaTxStorage;
+
+#[derive(clap::Args)]
+pub struct MigrateCmd {
+    #[arg(long, help = "The function registry database path")]
+    functions_db: Option<String>,
+
+    #[arg(long, help = "The meta transaction database path, e.g. ./data/meta_tx")]
+    meta_tx_db: Option<String>,
+
+    #[arg(long, help = "Count the outdated records without upgrading them")]
+    dry_run: bool,
+}
+
+impl MigrateCmd {
+    pub fn run(&self) -> anyhow::Result<()> {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?;
+        rt.block_on(self.do_run())
+    }
+
+    // Services upgrade records lazily as they read them, this upgrades the rest while they are
+    // stopped, a RocksDB database being opened by one process at a time
+    async fn do_run(&self) -> anyhow::Result<()> {
+        if self.functions_db.is_none() && self.meta_tx_db.is_none() {
+            anyhow::bail!("nothing to migrate, pass --functions-db or --meta-tx-db");
+        }
+
+        let mut report = BTreeMap::new();
+        if let Some(path) = &self.functions_db {
+            let storage = RocksDBFunctionStorage::new(path)?;
+            report.insert("functions", storage.migrate(self.dry_run)?);
+        }
+        if let Some(path) = &self.meta_tx_db {
+            let storage = RocksDBMetaTxStorage::new(path).await?;
+            report.insert("meta_tx_records", storage.migrate(self.dry_run)?);
+        }
+
+        println!("{}", serde_json::to_string_pretty(&report)?);
+        Ok(())
+    }
+}
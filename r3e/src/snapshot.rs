@@ -0,0 +1,113 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+use clap::Subcommand;
+use sqlx::PgPool;
+
+use r3e_api::snapshot::{self, ConflictPolicy, Snapshot, Stores};
+
+#[derive(clap::Args)]
+pub struct SnapshotCmd {
+    #[arg(long, env = "DATABASE_URL", help = "The platform database URL")]
+    database_url: String,
+
+    #[arg(
+        long,
+        env = "SECRETS_DB_PATH",
+        default_value = "./data/secrets",
+        help = "The function secrets database path"
+    )]
+    secrets_db_path: String,
+
+    #[arg(
+        long,
+        env = "SECRETS_BACKEND",
+        default_value = "rocksdb",
+        help = "Where function secrets are stored: rocksdb or vault"
+    )]
+    secrets_backend: String,
+
+    #[arg(
+        long,
+        env = "BALANCE_DB_PATH",
+        default_value = "./data/balances",
+        help = "The user balances database path"
+    )]
+    balance_db_path: String,
+
+    #[arg(
+        long,
+        env = "SNAPSHOT_BACKUP_KEY",
+        help = "The hex encoded 32-byte key encrypting secrets in the snapshot"
+    )]
+    backup_key: String,
+
+    #[command(subcommand)]
+    action: SnapshotAction,
+}
+
+#[derive(Subcommand)]
+enum SnapshotAction {
+    #[command(about = "Export the platform state into a snapshot file")]
+    Export {
+        #[arg(long, help = "The snapshot file path")]
+        output: String,
+    },
+
+    #[command(about = "Import a snapshot file into the platform")]
+    Import {
+        #[arg(long, help = "The snapshot file path")]
+        input: String,
+
+        #[arg(
+            long,
+            default_value = "fail",
+            help = "What to do with existing records: fail, skip or overwrite"
+        )]
+        conflict: String,
+    },
+}
+
+impl SnapshotCmd {
+    pub fn run(&self) -> anyhow::Result<()> {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?;
+        rt.block_on(self.do_run())
+    }
+
+    async fn do_run(&self) -> anyhow::Result<()> {
+        let backup_key = snapshot::parse_key(&self.backup_key)?;
+        let vault = match self.secrets_backend.as_str() {
+            "rocksdb" => None,
+            "vault" => Some(r3e_api::config::vault_from_env().ok_or_else(|| {
+                anyhow::anyhow!("SECRETS_BACKEND=vault requires VAULT_ADDR and VAULT_TOKEN")
+            })?),
+            other => anyhow::bail!("Invalid secrets backend: {}", other),
+        };
+        let stores =
+            Stores::open(&self.secrets_db_path, &self.balance_db_path, vault.as_ref()).await?;
+
+        let db = PgPool::connect(&self.database_url).await?;
+        match &self.action {
+            SnapshotAction::Export { output } => {
+                let snapshot = snapshot::export(&db, &stores, &backup_key).await?;
+                std::fs::write(output, serde_json::to_vec_pretty(&snapshot)?)?;
+                log::info!(
+                    "snapshot: exported version {} to {}",
+                    snapshot.version,
+                    output
+                );
+            }
+            SnapshotAction::Import { input, conflict } => {
+                let conflict: ConflictPolicy = conflict.parse()?;
+                let snapshot: Snapshot = serde_json::from_str(&crate::read_file(input)?)?;
+                let report =
+                    snapshot::import(&db, &stores, &snapshot, &backup_key, conflict).await?;
+                println!("{}", serde_json::to_string_pretty(&report)?);
+            }
+        }
+
+        Ok(())
+    }
+}
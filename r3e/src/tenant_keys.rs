@@ -0,0 +1,130 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+use std::sync::Arc;
+
+use clap::Subcommand;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use r3e_api::encryption;
+use r3e_api::snapshot;
+use r3e_secrets::envelope::{EnvelopeService, LOCAL_PROVIDER};
+use r3e_secrets::rocksdb::RocksDBSecretStorage;
+use r3e_secrets::service::SecretServiceImpl;
+
+#[derive(clap::Args)]
+pub struct TenantKeysCmd {
+    #[arg(long, env = "DATABASE_URL", help = "The platform database URL")]
+    database_url: String,
+
+    #[arg(
+        long,
+        env = "SECRETS_MASTER_KEY",
+        help = "The hex encoded master key local tenant KEKs are derived from"
+    )]
+    master_key: String,
+
+    #[arg(
+        long,
+        default_value = "./data/secrets",
+        help = "The secrets database path, re-keyed while the endpoints service is stopped"
+    )]
+    secrets_db: String,
+
+    #[command(subcommand)]
+    action: TenantKeysAction,
+}
+
+#[derive(Subcommand)]
+enum TenantKeysAction {
+    #[command(about = "Show the KEK of a tenant")]
+    Show {
+        #[arg(long, help = "The tenant user ID")]
+        tenant: Uuid,
+    },
+
+    #[command(about = "Enroll a tenant for encryption at rest, sealing its code and secrets")]
+    Enroll {
+        #[arg(long, help = "The tenant user ID")]
+        tenant: Uuid,
+
+        #[arg(long, default_value = LOCAL_PROVIDER, help = "The provider holding the KEK")]
+        provider: String,
+    },
+
+    #[command(about = "Rotate the KEK of a tenant, re-keying its code and secrets")]
+    Rotate {
+        #[arg(long, help = "The tenant user ID")]
+        tenant: Uuid,
+    },
+
+    #[command(about = "Re-key the code and secrets of a tenant with its current KEK")]
+    Rekey {
+        #[arg(long, help = "The tenant user ID")]
+        tenant: Uuid,
+    },
+}
+
+impl TenantKeysCmd {
+    pub fn run(&self) -> anyhow::Result<()> {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?;
+        rt.block_on(self.do_run())
+    }
+
+    async fn do_run(&self) -> anyhow::Result<()> {
+        let master_key = snapshot::parse_key(&self.master_key)?;
+        let db = PgPool::connect(&self.database_url).await?;
+        let envelope = Arc::new(encryption::envelope_service(db.clone(), master_key, None));
+
+        let tenant = match &self.action {
+            TenantKeysAction::Show { tenant } => {
+                let key = envelope.tenant_key(&tenant.to_string()).await?;
+                println!("{}", serde_json::to_string_pretty(&key)?);
+                return Ok(());
+            }
+            TenantKeysAction::Enroll { tenant, provider } => {
+                envelope.enroll(&tenant.to_string(), provider).await?;
+                *tenant
+            }
+            TenantKeysAction::Rotate { tenant } => {
+                envelope.rotate(&tenant.to_string()).await?;
+                *tenant
+            }
+            TenantKeysAction::Rekey { tenant } => *tenant,
+        };
+
+        self.rekey(&db, envelope, tenant).await
+    }
+
+    async fn rekey(
+        &self,
+        db: &PgPool,
+        envelope: Arc<EnvelopeService>,
+        tenant: Uuid,
+    ) -> anyhow::Result<()> {
+        let report = encryption::rekey_function_code(db, &envelope, tenant).await?;
+        println!("{}", serde_json::to_string_pretty(&report)?);
+
+        let secret_service =
+            SecretServiceImpl::new(Arc::new(RocksDBSecretStorage::new(&self.secrets_db).await?))
+                .with_envelope(envelope);
+        let function_ids =
+            sqlx::query_scalar::<_, Uuid>("SELECT id FROM functions WHERE user_id = $1")
+                .bind(tenant)
+                .fetch_all(db)
+                .await?;
+
+        let mut secrets = 0;
+        for function_id in function_ids {
+            secrets += secret_service
+                .rekey_secrets(&tenant.to_string(), &function_id.to_string())
+                .await?;
+        }
+        log::info!("tenant-keys: re-keyed {} secrets of {}", secrets, tenant);
+
+        Ok(())
+    }
+}
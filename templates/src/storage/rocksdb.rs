@@ -93,6 +93,7 @@ impl Storage for RocksDBStorage {
             start_exclusive: false,
             end_key: &[],
             end_inclusive: false,
+            reverse: false,
             max_count: 1000, // Reasonable limit
         };
         
@@ -0,0 +1,362 @@
+// Copyright @ 2023 - 2024, R3E Network
+// All Rights Reserved
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use r3e_core::chain::{
+    Block, BlockRef, CallResult, ChainClient, ChainError, ChainMetrics, ContractCall, EventFilter,
+    EventStream, TxSimulation,
+};
+use r3e_neo_services::{
+    abstract_account::{
+        service::AbstractAccountServiceTrait, AbstractAccount, AccountCreationRequest,
+        AccountOperation, AccountOperationRecord, AccountOperationRequest,
+        AccountOperationResponse, AccountPolicy, OperationStatus, PolicyType,
+    },
+    nft::{NftService, NftServiceTrait, NftTransferRequest},
+    Error,
+};
+use serde_json::{json, Value};
+
+const NFT: &str = "0x1111111111111111111111111111111111111111";
+const OWNER: &str = "0x2222222222222222222222222222222222222222";
+const RECIPIENT: &str = "0x3333333333333333333333333333333333333333";
+
+/// Stack item of a script hash, which is pushed little endian
+fn hash_item(hash: &str) -> Value {
+    let mut bytes = hex::decode(hash.trim_start_matches("0x")).unwrap();
+    bytes.reverse();
+    bytes_item(&bytes)
+}
+
+fn bytes_item(bytes: &[u8]) -> Value {
+    use base64::Engine;
+    json!({
+        "type": "ByteString",
+        "value": base64::engine::general_purpose::STANDARD.encode(bytes),
+    })
+}
+
+fn halt(stack: Value) -> Value {
+    json!({ "state": "HALT", "gasconsumed": "100000", "stack": [stack] })
+}
+
+// Mock Neo node answering invocations by method name
+struct MockNeoChain {
+    invocations: HashMap<&'static str, Value>,
+    requests: Mutex<Vec<(String, Value)>>,
+}
+
+impl MockNeoChain {
+    fn new(invocations: impl IntoIterator<Item = (&'static str, Value)>) -> Self {
+        Self {
+            invocations: invocations.into_iter().collect(),
+            requests: Mutex::new(Vec::new()),
+        }
+    }
+
+    fn invocation(&self, method: &str) -> Result<Value, ChainError> {
+        self.invocations
+            .get(method)
+            .cloned()
+            .ok_or_else(|| ChainError::Unsupported(method.to_string()))
+    }
+}
+
+#[async_trait]
+impl ChainClient for MockNeoChain {
+    fn chain(&self) -> &str {
+        "neo"
+    }
+
+    fn network(&self) -> &str {
+        "testnet"
+    }
+
+    async fn block_number(&self) -> Result<u64, ChainError> {
+        Ok(1)
+    }
+
+    async fn get_block(&self, _block: BlockRef) -> Result<Block, ChainError> {
+        Err(ChainError::Unsupported("getblock".to_string()))
+    }
+
+    async fn call_contract(&self, call: &ContractCall) -> Result<CallResult, ChainError> {
+        self.requests
+            .lock()
+            .unwrap()
+            .push((call.method.clone(), json!(call.params)));
+        let result = self.invocation(&call.method)?;
+        Ok(CallResult {
+            success: result["state"] == "HALT",
+            result: result["stack"].clone(),
+            gas_used: None,
+            error: result["exception"].as_str().map(String::from),
+        })
+    }
+
+    async fn simulate_tx(&self, _tx: &TxSimulation) -> Result<CallResult, ChainError> {
+        Err(ChainError::Unsupported("invokescript".to_string()))
+    }
+
+    async fn send_raw_tx(&self, _tx: &[u8]) -> Result<String, ChainError> {
+        Err(ChainError::Unsupported("sendrawtransaction".to_string()))
+    }
+
+    async fn subscribe_events(&self, _filter: EventFilter) -> Result<EventStream, ChainError> {
+        Err(ChainError::Unsupported("events".to_string()))
+    }
+
+    async fn request(&self, method: &str, params: Value) -> Result<Value, ChainError> {
+        self.requests
+            .lock()
+            .unwrap()
+            .push((method.to_string(), params.clone()));
+        match method {
+            "invokefunction" => self.invocation(params[1].as_str().unwrap_or_default()),
+            "traverseiterator" => self.invocation("traverseiterator"),
+            "terminatesession" => Ok(json!(true)),
+            method => Err(ChainError::Unsupported(method.to_string())),
+        }
+    }
+
+    fn metrics(&self) -> ChainMetrics {
+        ChainMetrics {
+            chain: "neo".to_string(),
+            network: "testnet".to_string(),
+            methods: HashMap::new(),
+            endpoints: Vec::new(),
+        }
+    }
+}
+
+// Mock abstract account service recording operations
+struct MockAccounts {
+    operations: Mutex<Vec<AccountOperationRequest>>,
+}
+
+#[async_trait]
+impl AbstractAccountServiceTrait for MockAccounts {
+    async fn create_account(
+        &self,
+        _request: AccountCreationRequest,
+    ) -> Result<AbstractAccount, Error> {
+        unimplemented!()
+    }
+
+    async fn get_account(&self, address: &str) -> Result<Option<AbstractAccount>, Error> {
+        Ok(Some(AbstractAccount {
+            address: address.to_string(),
+            owner: "neo1abc".to_string(),
+            controllers: Vec::new(),
+            recovery_addresses: Vec::new(),
+            policy: AccountPolicy {
+                policy_type: PolicyType::SingleSig,
+                parameters: HashMap::new(),
+                required_signatures: 1,
+                total_signatures: 1,
+                time_lock: None,
+                custom_script: None,
+            },
+            contract_hash: OWNER.to_string(),
+            created_at: 0,
+            status: "active".to_string(),
+            metadata: HashMap::new(),
+        }))
+    }
+
+    async fn get_accounts_by_owner(&self, _owner: &str) -> Result<Vec<AbstractAccount>, Error> {
+        unimplemented!()
+    }
+
+    async fn execute_operation(
+        &self,
+        request: AccountOperationRequest,
+    ) -> Result<AccountOperationResponse, Error> {
+        self.operations.lock().unwrap().push(request.clone());
+        Ok(AccountOperationResponse {
+            request_id: "op-1".to_string(),
+            account_address: request.account_address,
+            operation: request.operation,
+            tx_hash: None,
+            status: OperationStatus::Submitted.to_string(),
+            error: None,
+            timestamp: 0,
+        })
+    }
+
+    async fn get_operation_status(&self, _request_id: &str) -> Result<OperationStatus, Error> {
+        unimplemented!()
+    }
+
+    async fn get_operation(
+        &self,
+        _request_id: &str,
+    ) -> Result<Option<AccountOperationRecord>, Error> {
+        unimplemented!()
+    }
+
+    async fn get_operations_by_account(
+        &self,
+        _address: &str,
+    ) -> Result<Vec<AccountOperationRecord>, Error> {
+        unimplemented!()
+    }
+
+    async fn get_next_nonce(&self, _address: &str) -> Result<u64, Error> {
+        Ok(0)
+    }
+}
+
+fn nft_service(chain: Arc<MockNeoChain>) -> (NftService, Arc<MockAccounts>) {
+    let accounts = Arc::new(MockAccounts {
+        operations: Mutex::new(Vec::new()),
+    });
+    (NftService::new(chain, accounts.clone()), accounts)
+}
+
+fn transfer_request(token_id: &str, amount: Option<&str>) -> NftTransferRequest {
+    NftTransferRequest {
+        account_address: "neo-account".to_string(),
+        contract: NFT.to_string(),
+        to: RECIPIENT.to_string(),
+        token_id: token_id.to_string(),
+        amount: amount.map(String::from),
+        data: None,
+        signatures: Vec::new(),
+        nonce: 0,
+        deadline: u64::MAX,
+    }
+}
+
+#[tokio::test]
+async fn test_get_token() {
+    let chain = Arc::new(MockNeoChain::new([
+        ("decimals", halt(json!({ "type": "Integer", "value": "0" }))),
+        ("ownerOf", halt(hash_item(OWNER))),
+        (
+            "properties",
+            halt(json!({
+                "type": "Map",
+                "value": [
+                    { "key": bytes_item(b"name"), "value": bytes_item(b"Neo Punk #1") },
+                    { "key": bytes_item(b"rarity"), "value": { "type": "Integer", "value": "7" } },
+                ],
+            })),
+        ),
+    ]));
+    let (service, _) = nft_service(chain.clone());
+
+    let token = service.get_token(NFT, "0x0A0B").await.unwrap();
+    assert_eq!(token.token_id, "0a0b");
+    assert_eq!(token.owners, vec![OWNER.to_string()]);
+    let properties = token.properties.unwrap();
+    assert_eq!(properties["name"], "Neo Punk #1");
+    assert_eq!(properties["rarity"], "7");
+
+    // Token IDs are passed as byte arrays
+    let requests = chain.requests.lock().unwrap();
+    let (method, params) = &requests[1];
+    assert_eq!(method, "ownerOf");
+    assert_eq!(params[0], json!({ "type": "ByteArray", "value": "Cgs=" }));
+}
+
+#[tokio::test]
+async fn test_tokens_of() {
+    // Expanded iterator of a node with sessions disabled
+    let chain = Arc::new(MockNeoChain::new([(
+        "tokensOf",
+        halt(json!({
+            "type": "InteropInterface",
+            "interface": "IIterator",
+            "iterator": [bytes_item(&[1]), bytes_item(&[2, 3])],
+            "truncated": false,
+        })),
+    )]));
+    let (service, _) = nft_service(chain.clone());
+    let tokens = service.tokens_of(NFT, OWNER).await.unwrap();
+    assert_eq!(tokens, vec!["01", "0203"]);
+
+    // Iterator traversed through a session
+    let chain = Arc::new(MockNeoChain::new([
+        (
+            "tokensOf",
+            json!({
+                "state": "HALT",
+                "session": "session-1",
+                "stack": [{ "type": "InteropInterface", "interface": "IIterator", "id": "it-1" }],
+            }),
+        ),
+        ("traverseiterator", json!([bytes_item(&[4])])),
+    ]));
+    let (service, _) = nft_service(chain.clone());
+    let tokens = service.tokens_of(NFT, OWNER).await.unwrap();
+    assert_eq!(tokens, vec!["04"]);
+
+    let requests = chain.requests.lock().unwrap();
+    let methods: Vec<&str> = requests.iter().map(|(method, _)| method.as_str()).collect();
+    assert_eq!(
+        methods,
+        vec!["invokefunction", "traverseiterator", "terminatesession"]
+    );
+}
+
+#[tokio::test]
+async fn test_transfer() {
+    let chain = Arc::new(MockNeoChain::new([
+        ("decimals", halt(json!({ "type": "Integer", "value": "0" }))),
+        ("ownerOf", halt(hash_item(OWNER))),
+    ]));
+    let (service, accounts) = nft_service(chain);
+
+    let response = service
+        .transfer(transfer_request("0a0b", None))
+        .await
+        .unwrap();
+    assert_eq!(response.request_id, "op-1");
+
+    let operations = accounts.operations.lock().unwrap();
+    match &operations[0].operation {
+        AccountOperation::Invoke {
+            contract,
+            method,
+            params,
+        } => {
+            assert_eq!(contract, NFT);
+            assert_eq!(method, "transfer");
+            assert_eq!(
+                params,
+                &vec![RECIPIENT.to_string(), "0a0b".to_string(), String::new()]
+            );
+        }
+        operation => panic!("unexpected operation {:?}", operation),
+    }
+}
+
+#[tokio::test]
+async fn test_transfer_rejected() {
+    // Token owned by another account
+    let chain = Arc::new(MockNeoChain::new([
+        ("decimals", halt(json!({ "type": "Integer", "value": "0" }))),
+        ("ownerOf", halt(hash_item(RECIPIENT))),
+    ]));
+    let (service, accounts) = nft_service(chain);
+    let result = service.transfer(transfer_request("0a0b", None)).await;
+    assert!(matches!(result, Err(Error::InvalidParameter(_))));
+
+    // Divisible token without enough balance
+    let chain = Arc::new(MockNeoChain::new([
+        ("decimals", halt(json!({ "type": "Integer", "value": "8" }))),
+        (
+            "balanceOf",
+            halt(json!({ "type": "Integer", "value": "5" })),
+        ),
+    ]));
+    let (service, _) = nft_service(chain);
+    let result = service.transfer(transfer_request("0a0b", Some("10"))).await;
+    assert!(matches!(result, Err(Error::InsufficientFunds(_))));
+
+    assert!(accounts.operations.lock().unwrap().is_empty());
+}
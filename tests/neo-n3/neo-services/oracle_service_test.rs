@@ -101,6 +101,7 @@ async fn test_oracle_callback() {
         status_code: 200,
         timestamp: 1614556800, // March 1, 2021
         error: None,
+        signature: None,
     };
     
     // In a real test, we would mock the HTTP client and assert that the callback was sent
@@ -144,6 +145,7 @@ async fn test_blockchain_gateway_integration() {
         status_code: 200,
         timestamp: 1614556800, // March 1, 2021
         error: None,
+        signature: None,
     };
     
     // In a real test, we would mock the blockchain gateway and assert that the response was sent